@@ -0,0 +1,1831 @@
+//! Curated API facade for the Flutter client
+//!
+//! Exposes a small set of coarse-grained async functions and plain DTOs that
+//! are suitable for flutter_rust_bridge code generation. Core engine types stay
+//! private to this crate so the generated bindings only change when this
+//! module changes; `API_VERSION` follows semver for that surface.
+
+use anyhow::Result;
+use remote_desktop_core::input_control::{InputEvent, KeyModifiers, KeyboardLayout, MouseButton};
+#[cfg(feature = "host")]
+use remote_desktop_core::{
+    autostart::AUTOSTART_APP_ID, AudioDeviceKind, AutostartConfig, AutostartManager,
+    AutostartMethod, AutostartStatus, ClipboardFileManager, DiagnosticsManager, DisplayInfo,
+    FileTransfer, HostShutdown, IncomingFileOffer, OpenOutcome, OpenRequest, RemoteOpenManager,
+    RemoteUrlManager, ServerStatus, ShutdownOptions, ShutdownReport, UrlOpenOutcome,
+    UrlOpenRequest, WindowInfo,
+};
+use remote_desktop_core::{
+    AccessControlManager, AccessibilitySettings, ActiveSessionDescriptor, ConnectionType,
+    CursorPredictor, CursorUpdate, DeviceAuthorization, EndReason, InputController, ManagerError,
+    Permission, PrivacyOptions, RecordingStatus, ResourceKind, Session, SessionEvent,
+    SessionManager, SessionOptions, SessionPermission, SessionRole, SessionStatus, SignalingClient,
+    Subscription, SubscriptionOptions,
+};
+#[cfg(feature = "updates")]
+use remote_desktop_core::{ReleaseChannel, UpdateChecker, UpdateComponent};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, LazyLock, OnceLock};
+use tokio::sync::RwLock;
+use tokio_util::sync::CancellationToken;
+
+/// Version of the facade surface exposed to Dart
+pub const API_VERSION: &str = "2.0.0";
+
+/// Permission that can be granted to a remote device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiPermission {
+    ViewScreen,
+    InputControl,
+    FileTransfer,
+    Clipboard,
+    AudioCapture,
+    AppSharing,
+    OpenUrl,
+    /// Block the host's local input and blank its screen
+    PrivacyMode,
+    /// Open received files and change display settings on the host
+    SystemControl,
+}
+
+impl ApiPermission {
+    fn to_access(self) -> Permission {
+        match self {
+            ApiPermission::ViewScreen => Permission::ViewScreen,
+            ApiPermission::InputControl => Permission::InputControl,
+            ApiPermission::FileTransfer => Permission::FileTransfer,
+            ApiPermission::Clipboard => Permission::Clipboard,
+            ApiPermission::AudioCapture => Permission::AudioCapture,
+            ApiPermission::AppSharing => Permission::AppSharing,
+            ApiPermission::OpenUrl => Permission::OpenUrl,
+            ApiPermission::PrivacyMode => Permission::PrivacyMode,
+            ApiPermission::SystemControl => Permission::SystemControl,
+        }
+    }
+
+    fn from_access(permission: Permission) -> Vec<ApiPermission> {
+        match permission {
+            Permission::ViewScreen => vec![ApiPermission::ViewScreen],
+            Permission::InputControl => vec![ApiPermission::InputControl],
+            Permission::FileTransfer => vec![ApiPermission::FileTransfer],
+            Permission::Clipboard => vec![ApiPermission::Clipboard],
+            Permission::AudioCapture => vec![ApiPermission::AudioCapture],
+            Permission::AppSharing => vec![ApiPermission::AppSharing],
+            Permission::OpenUrl => vec![ApiPermission::OpenUrl],
+            Permission::PrivacyMode => vec![ApiPermission::PrivacyMode],
+            Permission::SystemControl => vec![ApiPermission::SystemControl],
+            Permission::FullControl => Permission::expand_full_control()
+                .into_iter()
+                .flat_map(ApiPermission::from_access)
+                .collect(),
+        }
+    }
+
+    fn to_session(self) -> Option<SessionPermission> {
+        match self {
+            ApiPermission::ViewScreen => Some(SessionPermission::ScreenView),
+            ApiPermission::InputControl => Some(SessionPermission::InputControl),
+            ApiPermission::FileTransfer => Some(SessionPermission::FileTransfer),
+            ApiPermission::AudioCapture => Some(SessionPermission::AudioCapture),
+            ApiPermission::AppSharing => Some(SessionPermission::AppSharing),
+            ApiPermission::OpenUrl => Some(SessionPermission::OpenUrl),
+            ApiPermission::PrivacyMode => Some(SessionPermission::PrivacyMode),
+            ApiPermission::SystemControl => Some(SessionPermission::SystemControl),
+            ApiPermission::Clipboard => None,
+        }
+    }
+
+    fn from_session(permission: &SessionPermission) -> Option<ApiPermission> {
+        match permission {
+            SessionPermission::ScreenView => Some(ApiPermission::ViewScreen),
+            SessionPermission::InputControl => Some(ApiPermission::InputControl),
+            SessionPermission::FileTransfer => Some(ApiPermission::FileTransfer),
+            SessionPermission::AudioCapture => Some(ApiPermission::AudioCapture),
+            SessionPermission::AppSharing => Some(ApiPermission::AppSharing),
+            SessionPermission::OpenUrl => Some(ApiPermission::OpenUrl),
+            SessionPermission::PrivacyMode => Some(ApiPermission::PrivacyMode),
+            SessionPermission::SystemControl => Some(ApiPermission::SystemControl),
+        }
+    }
+}
+
+/// What went wrong, for errors the UI handles specially
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiErrorKind {
+    NotFound,
+    InvalidState,
+    PermissionDenied,
+    /// Aborted by `cancel_operation`; `id` is the operation id
+    Cancelled,
+    Other,
+}
+
+/// Error returned by an API call, classified by `describe_error`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiErrorDto {
+    pub kind: ApiErrorKind,
+    pub resource: Option<ResourceKind>,
+    pub id: Option<String>,
+    /// Current status of the resource for `InvalidState`, e.g. "Paused"
+    pub state: Option<String>,
+    pub message: String,
+}
+
+/// Temporary access code shown to the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccessCodeDto {
+    pub code: String,
+    pub expires_in_secs: u64,
+    pub permissions: Vec<ApiPermission>,
+}
+
+/// Pending incoming connection request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectionRequestDto {
+    pub request_id: String,
+    pub device_id: String,
+    pub device_name: String,
+    pub permissions: Vec<ApiPermission>,
+    /// Risk score from 0 to 100
+    pub risk_score: u8,
+    /// The host must confirm (or an access code is needed) even with
+    /// unattended access enabled
+    pub needs_verification: bool,
+}
+
+/// Result of authorizing a connection request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorizationDto {
+    pub request_id: String,
+    pub accepted: bool,
+    pub permissions: Vec<ApiPermission>,
+}
+
+/// Known (authorized) remote device
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceDto {
+    pub device_id: String,
+    pub device_name: String,
+    pub permissions: Vec<ApiPermission>,
+    pub active: bool,
+}
+
+/// Remote control session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDto {
+    pub session_id: String,
+    pub remote_device_id: String,
+    pub permissions: Vec<ApiPermission>,
+    pub duration_secs: u64,
+}
+
+/// How a session's media reaches the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiConnectionType {
+    Direct,
+    Relay,
+    Unknown,
+}
+
+/// Who is connected and what they can do, for the host tray or overlay
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDescriptorDto {
+    pub session_id: String,
+    /// This device is the one being controlled
+    pub is_host: bool,
+    pub peer_device_id: String,
+    pub peer_name: Option<String>,
+    /// Peer name and fingerprint were checked against its certificate
+    pub peer_verified: bool,
+    pub peer_fingerprint: Option<String>,
+    pub permissions: Vec<ApiPermission>,
+    pub connection_type: ApiConnectionType,
+    pub send_bitrate_bps: u64,
+    pub receive_bitrate_bps: u64,
+    /// Session start, in milliseconds since the Unix epoch
+    pub started_at_ms: i64,
+    pub elapsed_secs: u64,
+    pub paused: bool,
+    pub recording: bool,
+    /// Privacy mode this session holds on the host
+    pub privacy_mode: Option<PrivacyModeDto>,
+}
+
+/// What privacy mode hides from the person at the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivacyModeDto {
+    pub block_input: bool,
+    pub blank_screen: bool,
+}
+
+impl From<PrivacyModeDto> for PrivacyOptions {
+    fn from(dto: PrivacyModeDto) -> Self {
+        Self {
+            block_input: dto.block_input,
+            blank_screen: dto.blank_screen,
+        }
+    }
+}
+
+impl From<PrivacyOptions> for PrivacyModeDto {
+    fn from(options: PrivacyOptions) -> Self {
+        Self {
+            block_input: options.block_input,
+            blank_screen: options.blank_screen,
+        }
+    }
+}
+
+/// Change to a session descriptor; `descriptor` is `None` once the session
+/// has ended
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionDescriptorUpdateDto {
+    pub session_id: String,
+    pub descriptor: Option<SessionDescriptorDto>,
+}
+
+/// Cursor position for drawing or reconciling
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CursorDto {
+    pub x: i32,
+    pub y: i32,
+    pub visible: bool,
+    /// Locally predicted rather than reported by the host
+    pub predicted: bool,
+}
+
+/// Average connection quality for one hour of one weekday
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct HeatmapCellDto {
+    /// 0 means no data for this hour
+    pub samples: u32,
+    /// 0 (poor) to 100 (excellent)
+    pub quality_score: f32,
+    pub avg_rtt_ms: f32,
+    pub avg_packet_loss_percent: f32,
+}
+
+/// Connection quality with a device by weekday and hour
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QualityHeatmapDto {
+    pub device_id: String,
+    /// `cells[day][hour]`, 7 days starting on Monday by 24 hours
+    pub cells: Vec<Vec<HeatmapCellDto>>,
+}
+
+/// Release channel to check for updates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiReleaseChannel {
+    Stable,
+    Beta,
+}
+
+/// Application to check for updates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiUpdateComponent {
+    Host,
+    Client,
+}
+
+/// Result of an update check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateInfoDto {
+    pub current_version: String,
+    pub update_available: bool,
+    pub latest_version: Option<String>,
+    pub release_notes: Option<String>,
+    pub download_size: Option<u64>,
+}
+
+/// How the host is launched on login
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiAutostartMethod {
+    RegistryRunKey,
+    TaskScheduler,
+    LaunchAgent,
+    XdgAutostart,
+    SystemdUserUnit,
+}
+
+#[cfg(feature = "host")]
+impl ApiAutostartMethod {
+    fn to_core(self) -> AutostartMethod {
+        match self {
+            ApiAutostartMethod::RegistryRunKey => AutostartMethod::RegistryRunKey,
+            ApiAutostartMethod::TaskScheduler => AutostartMethod::TaskScheduler,
+            ApiAutostartMethod::LaunchAgent => AutostartMethod::LaunchAgent,
+            ApiAutostartMethod::XdgAutostart => AutostartMethod::XdgAutostart,
+            ApiAutostartMethod::SystemdUserUnit => AutostartMethod::SystemdUserUnit,
+        }
+    }
+
+    fn from_core(method: AutostartMethod) -> Self {
+        match method {
+            AutostartMethod::RegistryRunKey => ApiAutostartMethod::RegistryRunKey,
+            AutostartMethod::TaskScheduler => ApiAutostartMethod::TaskScheduler,
+            AutostartMethod::LaunchAgent => ApiAutostartMethod::LaunchAgent,
+            AutostartMethod::XdgAutostart => ApiAutostartMethod::XdgAutostart,
+            AutostartMethod::SystemdUserUnit => ApiAutostartMethod::SystemdUserUnit,
+        }
+    }
+}
+
+/// Launch-on-login state for the settings screen
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AutostartStatusDto {
+    pub enabled: bool,
+    pub enabled_methods: Vec<ApiAutostartMethod>,
+    /// Methods the platform supports, preferred first
+    pub available_methods: Vec<ApiAutostartMethod>,
+}
+
+/// Outcome of `shutdown_host`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShutdownReportDto {
+    pub sessions_notified: u32,
+    pub sessions_ended: u32,
+    pub forced: bool,
+}
+
+/// Assistive input modes for a session
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessibilityDto {
+    pub sticky_keys: bool,
+    /// Click after the pointer rests this long; `None` disables dwell clicks
+    pub dwell_click_ms: Option<u32>,
+    pub double_click_ms: u32,
+    pub scroll_speed: f32,
+}
+
+impl From<AccessibilityDto> for AccessibilitySettings {
+    fn from(dto: AccessibilityDto) -> Self {
+        Self {
+            sticky_keys: dto.sticky_keys,
+            dwell_click_ms: dto.dwell_click_ms,
+            double_click_ms: dto.double_click_ms,
+            scroll_speed: dto.scroll_speed,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<AccessibilitySettings> for AccessibilityDto {
+    fn from(settings: AccessibilitySettings) -> Self {
+        Self {
+            sticky_keys: settings.sticky_keys,
+            dwell_click_ms: settings.dwell_click_ms,
+            double_click_ms: settings.double_click_ms,
+            scroll_speed: settings.scroll_speed,
+        }
+    }
+}
+
+/// Controller's request to open a file on this host, awaiting confirmation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenRequestDto {
+    pub request_id: String,
+    pub session_id: String,
+    pub requested_by: String,
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Viewer's link to open in this host's browser, awaiting confirmation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlRequestDto {
+    pub request_id: String,
+    pub session_id: String,
+    pub requested_by: String,
+    pub url: String,
+    /// Destination host, to show prominently in the confirmation
+    pub host: String,
+}
+
+/// A dropped or pasted file or folder being sent to the remote device
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutgoingTransferDto {
+    /// File transfer ID, or the folder transfer ID for folders
+    pub transfer_id: String,
+    pub name: String,
+    /// Total bytes, including everything in a folder
+    pub size: u64,
+    /// For the file icon; `inode/directory` for folders
+    pub mime_type: String,
+    pub is_folder: bool,
+    pub file_count: u32,
+}
+
+/// File a remote device wants to send, awaiting the user's decision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IncomingFileDto {
+    pub transfer_id: String,
+    pub session_id: String,
+    pub peer_device_id: String,
+    pub name: String,
+    pub size: u64,
+    /// For the file icon in the prompt
+    pub mime_type: String,
+}
+
+/// Summary of a network diagnostics run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NetworkDiagnosticsDto {
+    pub internet_connected: bool,
+    /// Variant name, e.g. "Symmetric"
+    pub nat_type: String,
+    pub signaling_reachable: bool,
+    pub signaling_latency_ms: Option<u32>,
+    /// Number of configured servers that answered
+    pub stun_reachable: u32,
+    pub turn_reachable: u32,
+    /// "Good", "Warning", "Critical" or "Unknown"
+    pub overall_status: String,
+    pub recommendations: Vec<String>,
+}
+
+/// Audio capture device for the device picker
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioDeviceDto {
+    pub id: String,
+    pub name: String,
+    /// Captures system output rather than a microphone
+    pub is_loopback: bool,
+    pub is_default: bool,
+}
+
+/// Host monitor for the display picker
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DisplayDto {
+    pub id: String,
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    /// Hz; 0 when unknown
+    pub refresh_rate: u32,
+    pub scale_factor: f64,
+    pub x: i32,
+    pub y: i32,
+    pub is_primary: bool,
+    /// HDR output is on; the picker can offer HDR passthrough
+    pub hdr_enabled: bool,
+    pub max_luminance_nits: Option<f32>,
+}
+
+/// Host window for the window picker
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowDto {
+    pub window_id: u64,
+    pub title: String,
+    pub process_id: Option<u32>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Keyboard layout keys are translated with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiKeyboardLayout {
+    US,
+    UK,
+    DE,
+    FR,
+    JP,
+    CN,
+}
+
+impl From<ApiKeyboardLayout> for KeyboardLayout {
+    fn from(layout: ApiKeyboardLayout) -> Self {
+        match layout {
+            ApiKeyboardLayout::US => KeyboardLayout::US,
+            ApiKeyboardLayout::UK => KeyboardLayout::UK,
+            ApiKeyboardLayout::DE => KeyboardLayout::DE,
+            ApiKeyboardLayout::FR => KeyboardLayout::FR,
+            ApiKeyboardLayout::JP => KeyboardLayout::JP,
+            ApiKeyboardLayout::CN => KeyboardLayout::CN,
+        }
+    }
+}
+
+impl From<KeyboardLayout> for ApiKeyboardLayout {
+    fn from(layout: KeyboardLayout) -> Self {
+        match layout {
+            KeyboardLayout::US => ApiKeyboardLayout::US,
+            KeyboardLayout::UK => ApiKeyboardLayout::UK,
+            KeyboardLayout::DE => ApiKeyboardLayout::DE,
+            KeyboardLayout::FR => ApiKeyboardLayout::FR,
+            KeyboardLayout::JP => ApiKeyboardLayout::JP,
+            KeyboardLayout::CN => ApiKeyboardLayout::CN,
+        }
+    }
+}
+
+/// Keyboard layout in effect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyboardLayoutDto {
+    pub layout: ApiKeyboardLayout,
+    /// Set manually rather than detected or reported by the host
+    pub overridden: bool,
+}
+
+/// Mouse button for input events
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ApiMouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+/// Input event sent from the controller UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum InputDto {
+    MouseMove {
+        x: i32,
+        y: i32,
+    },
+    MouseClick {
+        button: ApiMouseButton,
+        x: i32,
+        y: i32,
+    },
+    MouseWheel {
+        delta_x: i32,
+        delta_y: i32,
+    },
+    Key {
+        key: String,
+        pressed: bool,
+        ctrl: bool,
+        alt: bool,
+        shift: bool,
+        meta: bool,
+        #[serde(default)]
+        altgr: bool,
+    },
+    /// Text typed or pasted on the host, subject to the host's policy
+    TypeText {
+        text: String,
+    },
+    /// Text committed by the local input method, e.g. Chinese or Japanese
+    ImeCommit {
+        text: String,
+    },
+}
+
+impl From<InputDto> for InputEvent {
+    fn from(input: InputDto) -> Self {
+        match input {
+            InputDto::MouseMove { x, y } => InputEvent::MouseMove { x, y },
+            InputDto::MouseClick { button, x, y } => InputEvent::MouseClick {
+                button: match button {
+                    ApiMouseButton::Left => MouseButton::Left,
+                    ApiMouseButton::Right => MouseButton::Right,
+                    ApiMouseButton::Middle => MouseButton::Middle,
+                },
+                x,
+                y,
+            },
+            InputDto::MouseWheel { delta_x, delta_y } => {
+                InputEvent::MouseWheel { delta_x, delta_y }
+            }
+            InputDto::Key {
+                key,
+                pressed,
+                ctrl,
+                alt,
+                shift,
+                meta,
+                altgr,
+            } => {
+                let modifiers = KeyModifiers {
+                    ctrl,
+                    alt,
+                    shift,
+                    meta,
+                    altgr,
+                };
+                if pressed {
+                    InputEvent::KeyDown { key, modifiers }
+                } else {
+                    InputEvent::KeyUp { key, modifiers }
+                }
+            }
+            InputDto::TypeText { text } => InputEvent::TypeText { text },
+            InputDto::ImeCommit { text } => InputEvent::ImeCommit { text },
+        }
+    }
+}
+
+/// Process-wide state behind the facade
+struct ApiState {
+    device_id: String,
+    /// Running app version, compared against the release manifest
+    #[cfg_attr(not(feature = "updates"), allow(dead_code))]
+    version: String,
+    access_control: AccessControlManager,
+    sessions: Arc<SessionManager>,
+    input: InputController,
+    cursor: std::sync::Mutex<CursorPredictor>,
+    signaling: RwLock<Option<Arc<SignalingClient>>>,
+    /// Descriptor changes not yet collected by the UI
+    descriptor_updates: std::sync::Mutex<Subscription<SessionEvent>>,
+    #[cfg(feature = "host")]
+    remote_open: RemoteOpenManager,
+    #[cfg(feature = "host")]
+    remote_url: RemoteUrlManager,
+    #[cfg(feature = "host")]
+    file_transfer: tokio::sync::Mutex<FileTransfer>,
+    #[cfg(feature = "host")]
+    clipboard_files: tokio::sync::Mutex<ClipboardFileManager>,
+}
+
+impl ApiState {
+    fn cursor(&self) -> Result<std::sync::MutexGuard<'_, CursorPredictor>> {
+        self.cursor
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Cursor predictor lock poisoned"))
+    }
+}
+
+static STATE: OnceLock<ApiState> = OnceLock::new();
+
+fn state() -> Result<&'static ApiState> {
+    STATE
+        .get()
+        .ok_or_else(|| anyhow::anyhow!("API not initialized, call init() first"))
+}
+
+/// Classify an error returned by this API so the UI can react to it
+///
+/// A missing session usually means it ended concurrently; an invalid state
+/// carries the current status to refresh from.
+pub fn describe_error(error: &anyhow::Error) -> ApiErrorDto {
+    let message = error.to_string();
+    if let Some(cancelled) = error.downcast_ref::<OperationCancelled>() {
+        return ApiErrorDto {
+            kind: ApiErrorKind::Cancelled,
+            resource: None,
+            id: Some(cancelled.operation_id.clone()),
+            state: None,
+            message,
+        };
+    }
+    match ManagerError::of(error) {
+        Some(ManagerError::NotFound { kind, id }) => ApiErrorDto {
+            kind: ApiErrorKind::NotFound,
+            resource: Some(*kind),
+            id: Some(id.clone()),
+            state: None,
+            message,
+        },
+        Some(ManagerError::InvalidState {
+            kind, id, state, ..
+        }) => ApiErrorDto {
+            kind: ApiErrorKind::InvalidState,
+            resource: Some(*kind),
+            id: Some(id.clone()),
+            state: Some(state.clone()),
+            message,
+        },
+        Some(ManagerError::PermissionDenied { kind, id, .. }) => ApiErrorDto {
+            kind: ApiErrorKind::PermissionDenied,
+            resource: Some(*kind),
+            id: Some(id.clone()),
+            state: None,
+            message,
+        },
+        None => ApiErrorDto {
+            kind: ApiErrorKind::Other,
+            resource: None,
+            id: None,
+            state: None,
+            message,
+        },
+    }
+}
+
+/// Calls that take an `operation_id` can be aborted with `cancel_operation`
+///
+/// Ids are chosen by the caller and must be unique among running calls.
+static OPERATIONS: LazyLock<std::sync::Mutex<HashMap<String, CancellationToken>>> =
+    LazyLock::new(Default::default);
+
+#[derive(Debug)]
+struct OperationCancelled {
+    operation_id: String,
+}
+
+impl std::fmt::Display for OperationCancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Operation {} was cancelled", self.operation_id)
+    }
+}
+
+impl std::error::Error for OperationCancelled {}
+
+/// A running cancellable call, unregistered when dropped
+struct Operation {
+    id: String,
+    token: CancellationToken,
+}
+
+impl Operation {
+    fn start(id: &str) -> Result<Self> {
+        let mut operations = OPERATIONS.lock().unwrap_or_else(|e| e.into_inner());
+        if operations.contains_key(id) {
+            return Err(anyhow::anyhow!("Operation {} is already running", id));
+        }
+        let token = CancellationToken::new();
+        operations.insert(id.to_string(), token.clone());
+        Ok(Self {
+            id: id.to_string(),
+            token,
+        })
+    }
+}
+
+impl Drop for Operation {
+    fn drop(&mut self) {
+        OPERATIONS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
+    }
+}
+
+/// Run `work` until it completes or the operation is cancelled
+///
+/// On cancellation `work` is dropped at its current await point, so callers
+/// only need to clean up what they set up around it.
+async fn cancellable<T>(operation_id: &str, work: impl Future<Output = Result<T>>) -> Result<T> {
+    let operation = Operation::start(operation_id)?;
+    tokio::select! {
+        result = work => result,
+        _ = operation.token.cancelled() => Err(OperationCancelled {
+            operation_id: operation_id.to_string(),
+        }
+        .into()),
+    }
+}
+
+/// Abort a running call started with this operation id
+///
+/// The call returns an error `describe_error` classifies as `Cancelled`.
+/// Returns false if no such call is running, e.g. it already finished.
+pub fn cancel_operation(operation_id: String) -> Result<bool> {
+    let operations = OPERATIONS.lock().unwrap_or_else(|e| e.into_inner());
+    Ok(match operations.get(&operation_id) {
+        Some(token) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    })
+}
+
+/// Get the facade API version
+pub fn api_version() -> String {
+    API_VERSION.to_string()
+}
+
+/// Initialize the engine and register this device, returning its device ID
+///
+/// Calling this more than once returns the already registered device ID.
+pub async fn init(device_name: String, platform: String, version: String) -> Result<String> {
+    if let Some(state) = STATE.get() {
+        return Ok(state.device_id.clone());
+    }
+
+    let access_control = AccessControlManager::new();
+    let device_id = access_control
+        .register_device(device_name, platform, version.clone())
+        .await?;
+
+    let sessions = Arc::new(SessionManager::new(device_id.clone()));
+    let descriptor_updates =
+        sessions.subscribe(SubscriptionOptions::only(&["DescriptorChanged", "Ended"]));
+    let new_state = ApiState {
+        device_id: device_id.clone(),
+        version,
+        access_control,
+        sessions,
+        input: InputController::new(),
+        cursor: std::sync::Mutex::new(CursorPredictor::default()),
+        signaling: RwLock::new(None),
+        descriptor_updates: std::sync::Mutex::new(descriptor_updates),
+        #[cfg(feature = "host")]
+        remote_open: RemoteOpenManager::new(),
+        #[cfg(feature = "host")]
+        remote_url: RemoteUrlManager::new(),
+        #[cfg(feature = "host")]
+        file_transfer: tokio::sync::Mutex::new(FileTransfer::new()),
+        #[cfg(feature = "host")]
+        clipboard_files: tokio::sync::Mutex::new(ClipboardFileManager::new(
+            std::env::temp_dir().join("cec-clipboard"),
+        )),
+    };
+
+    // A concurrent init may have won the race; either way report the stored ID
+    let _ = STATE.set(new_state);
+    Ok(state()?.device_id.clone())
+}
+
+/// Connect to the signaling server
+///
+/// Cancellable; a cancelled attempt leaves any previous connection in place.
+pub async fn connect(server_url: String, operation_id: String) -> Result<()> {
+    let state = state()?;
+    let client = Arc::new(SignalingClient::new(server_url)?);
+    if let Err(e) = cancellable(&operation_id, client.connect()).await {
+        // A failed or cancelled attempt may leave the socket half set up
+        let _ = client.disconnect().await;
+        return Err(e);
+    }
+    *state.signaling.write().await = Some(client);
+    Ok(())
+}
+
+/// Disconnect from the signaling server
+pub async fn disconnect() -> Result<()> {
+    let state = state()?;
+    if let Some(client) = state.signaling.write().await.take() {
+        client.disconnect().await?;
+    }
+    Ok(())
+}
+
+/// Whether the signaling connection is up
+pub async fn is_connected() -> Result<bool> {
+    let state = state()?;
+    let signaling = state.signaling.read().await;
+    Ok(match signaling.as_ref() {
+        Some(client) => client.is_connected().await,
+        None => false,
+    })
+}
+
+/// Generate a temporary access code granting the given permissions
+pub async fn generate_access_code(permissions: Vec<ApiPermission>) -> Result<AccessCodeDto> {
+    let state = state()?;
+    let code = state
+        .access_control
+        .generate_access_code(permissions.iter().map(|p| p.to_access()).collect())
+        .await?;
+
+    Ok(AccessCodeDto {
+        code: code.code.clone(),
+        expires_in_secs: code.remaining_seconds(),
+        permissions,
+    })
+}
+
+/// List incoming connection requests awaiting a decision
+pub async fn list_pending_requests() -> Result<Vec<ConnectionRequestDto>> {
+    let state = state()?;
+    Ok(state
+        .access_control
+        .get_pending_requests()
+        .await
+        .into_iter()
+        .map(|request| ConnectionRequestDto {
+            risk_score: request.risk.as_ref().map(|r| r.score).unwrap_or(0),
+            needs_verification: request
+                .risk
+                .as_ref()
+                .is_some_and(|r| r.requires_additional_verification()),
+            request_id: request.request_id,
+            device_id: request.from_device_id,
+            device_name: request.from_device_name,
+            permissions: to_api_permissions(&request.requested_permissions),
+        })
+        .collect())
+}
+
+/// Accept or reject a connection request
+///
+/// When accepting, `permissions` narrows the grant; `None` grants what was
+/// requested.
+pub async fn authorize(
+    request_id: String,
+    accept: bool,
+    permissions: Option<Vec<ApiPermission>>,
+) -> Result<AuthorizationDto> {
+    let state = state()?;
+    let response = state
+        .access_control
+        .respond_to_request(
+            &request_id,
+            accept,
+            permissions.map(|p| p.iter().map(|p| p.to_access()).collect()),
+            (!accept).then(|| "Rejected by user".to_string()),
+        )
+        .await?;
+
+    Ok(AuthorizationDto {
+        request_id: response.request_id,
+        accepted: response.accepted,
+        permissions: to_api_permissions(&response.granted_permissions),
+    })
+}
+
+/// Revoke a previously authorized device
+pub async fn revoke_device(device_id: String) -> Result<()> {
+    state()?
+        .access_control
+        .revoke_authorization(&device_id)
+        .await
+}
+
+/// List devices authorized to connect to this host
+pub async fn list_devices() -> Result<Vec<DeviceDto>> {
+    let state = state()?;
+    Ok(state
+        .access_control
+        .get_authorized_devices()
+        .await
+        .into_iter()
+        .map(device_to_dto)
+        .collect())
+}
+
+/// Start a session with a remote device
+pub async fn start_session(
+    remote_device_id: String,
+    permissions: Vec<ApiPermission>,
+) -> Result<SessionDto> {
+    let state = state()?;
+    let options = SessionOptions {
+        permissions: permissions.iter().filter_map(|p| p.to_session()).collect(),
+        ..Default::default()
+    };
+
+    let accessibility = state
+        .access_control
+        .get_accessibility_settings(&remote_device_id)
+        .await;
+    let session = state
+        .sessions
+        .create_session(remote_device_id, options)
+        .await?;
+    if let Some(settings) = accessibility {
+        state
+            .input
+            .set_session_accessibility(&session.session_id, settings);
+    }
+    Ok(session_to_dto(&session, &state.device_id))
+}
+
+/// End a session
+pub async fn end_session(session_id: String) -> Result<()> {
+    let state = state()?;
+    state
+        .sessions
+        .end_session(&session_id, EndReason::UserRequested)?;
+    state.input.clear_session_accessibility(&session_id);
+    state.input.set_session_keyboard_layout(&session_id, None);
+    #[cfg(feature = "host")]
+    state.remote_open.cancel_session(&session_id);
+    Ok(())
+}
+
+/// List active sessions
+pub async fn list_sessions() -> Result<Vec<SessionDto>> {
+    let state = state()?;
+    Ok(state
+        .sessions
+        .get_active_sessions()
+        .iter()
+        .map(|session| session_to_dto(session, &state.device_id))
+        .collect())
+}
+
+/// Describe every active session: peer identity, granted permissions,
+/// connection type, data rates and elapsed time
+pub fn list_session_descriptors() -> Result<Vec<SessionDescriptorDto>> {
+    Ok(state()?
+        .sessions
+        .session_descriptors()
+        .iter()
+        .map(descriptor_to_dto)
+        .collect())
+}
+
+/// Session descriptor changes since the last call, oldest first
+///
+/// Poll this to keep the tray or overlay in step with current access.
+pub fn poll_session_descriptor_updates() -> Result<Vec<SessionDescriptorUpdateDto>> {
+    let mut updates = state()?
+        .descriptor_updates
+        .lock()
+        .map_err(|_| anyhow::anyhow!("Descriptor subscription lock poisoned"))?;
+    let mut changes = Vec::new();
+    while let Some(event) = updates.try_recv() {
+        match event {
+            SessionEvent::DescriptorChanged { descriptor } => {
+                changes.push(SessionDescriptorUpdateDto {
+                    session_id: descriptor.session_id.clone(),
+                    descriptor: Some(descriptor_to_dto(&descriptor)),
+                })
+            }
+            SessionEvent::Ended { session_id, .. } => changes.push(SessionDescriptorUpdateDto {
+                session_id,
+                descriptor: None,
+            }),
+            _ => {}
+        }
+    }
+    Ok(changes)
+}
+
+/// Ask the host for a fresh keyframe, e.g. when the viewer sees rendering
+/// artifacts or returns from the background
+///
+/// Returns `false` if the request came too soon after the previous one and
+/// was dropped.
+pub fn request_keyframe(session_id: String) -> Result<bool> {
+    state()?.sessions.request_keyframe(&session_id)
+}
+
+/// Send an input event within a session
+///
+/// Fails unless the session was granted input control.
+pub async fn send_input(session_id: String, input: InputDto) -> Result<()> {
+    let state = state()?;
+    let session = state
+        .sessions
+        .get_session(&session_id)
+        .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, &session_id))?;
+
+    if !session
+        .permissions
+        .contains(&SessionPermission::InputControl)
+    {
+        return Err(ManagerError::permission_denied(
+            ResourceKind::Session,
+            &session_id,
+            "input control not granted",
+        )
+        .into());
+    }
+
+    if let InputDto::MouseMove { x, y } = input {
+        state.cursor()?.record_local_move(x, y);
+    }
+    state.input.process_session_input(&session_id, input.into())
+}
+
+/// Block this host's local input and optionally blank its screen while a
+/// session is active; `None` turns privacy mode off
+///
+/// Fails unless the session was granted privacy mode and this platform can
+/// enforce it. Ending the session or revoking the permission turns it off as
+/// well.
+pub async fn set_privacy_mode(session_id: String, options: Option<PrivacyModeDto>) -> Result<()> {
+    state()?
+        .sessions
+        .set_privacy_mode(&session_id, options.map(Into::into))
+}
+
+/// Apply assistive input modes to a session
+///
+/// With `remember`, the settings are also stored on the remote device's
+/// profile and applied to its future sessions.
+pub async fn set_accessibility(
+    session_id: String,
+    settings: AccessibilityDto,
+    remember: bool,
+) -> Result<()> {
+    let state = state()?;
+    let session = state
+        .sessions
+        .get_session(&session_id)
+        .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, &session_id))?;
+    let settings = AccessibilitySettings::from(settings);
+    if remember {
+        let remote_device_id = session_to_dto(&session, &state.device_id).remote_device_id;
+        state
+            .access_control
+            .set_accessibility_settings(&remote_device_id, Some(settings.clone()))
+            .await?;
+    }
+    state.input.set_session_accessibility(&session_id, settings);
+    Ok(())
+}
+
+/// Get a session's assistive input modes
+pub fn get_accessibility(session_id: String) -> Result<AccessibilityDto> {
+    Ok(state()?
+        .input
+        .session_accessibility(&session_id)
+        .unwrap_or_default()
+        .into())
+}
+
+/// Inject pending dwell clicks; call every ~50 ms while dwell clicking is on
+pub fn poll_dwell_clicks() -> Result<u32> {
+    Ok(state()?.input.poll_dwell_clicks()? as u32)
+}
+
+/// Historical connection quality with a device, for spotting recurring
+/// slow periods
+pub fn get_quality_heatmap(device_id: String) -> Result<QualityHeatmapDto> {
+    let heatmap = state()?.sessions.get_quality_heatmap(&device_id);
+    Ok(QualityHeatmapDto {
+        device_id: heatmap.device_id,
+        cells: heatmap
+            .cells
+            .into_iter()
+            .map(|day| {
+                day.into_iter()
+                    .map(|cell| HeatmapCellDto {
+                        samples: cell.samples,
+                        quality_score: cell.quality_score,
+                        avg_rtt_ms: cell.avg_rtt_ms,
+                        avg_packet_loss_percent: cell.avg_packet_loss_percent,
+                    })
+                    .collect()
+            })
+            .collect(),
+    })
+}
+
+/// Keyboard layout used to translate keys
+pub fn get_keyboard_layout() -> Result<KeyboardLayoutDto> {
+    let input = &state()?.input;
+    Ok(KeyboardLayoutDto {
+        layout: input.keyboard_layout().into(),
+        overridden: input.layout_override().is_some(),
+    })
+}
+
+/// Force a keyboard layout when detection picks the wrong one; `None`
+/// returns to the detected layout
+pub fn set_keyboard_layout_override(layout: Option<ApiKeyboardLayout>) -> Result<()> {
+    state()?.input.set_layout_override(layout.map(Into::into));
+    Ok(())
+}
+
+/// Translate a session's keys from the controller's keyboard layout to
+/// the host layout; `None` forwards key positions unchanged
+pub fn set_session_keyboard_layout(
+    session_id: String,
+    layout: Option<ApiKeyboardLayout>,
+) -> Result<()> {
+    let state = state()?;
+    if state.sessions.get_session(&session_id).is_none() {
+        return Err(ManagerError::not_found(ResourceKind::Session, &session_id).into());
+    }
+    state
+        .input
+        .set_session_keyboard_layout(&session_id, layout.map(Into::into));
+    Ok(())
+}
+
+/// Enable or disable local cursor prediction
+pub fn set_cursor_prediction(enabled: bool) -> Result<()> {
+    state()?.cursor()?.set_enabled(enabled);
+    Ok(())
+}
+
+/// Apply a cursor position reported by the host
+pub fn apply_host_cursor(x: i32, y: i32, visible: bool, timestamp_ms: i64) -> Result<()> {
+    state()?.cursor()?.apply_update(CursorUpdate {
+        x,
+        y,
+        visible,
+        timestamp_ms,
+    });
+    Ok(())
+}
+
+/// Where to draw the remote cursor, if its position is known
+pub fn get_cursor() -> Result<Option<CursorDto>> {
+    Ok(state()?.cursor()?.rendered().map(|cursor| CursorDto {
+        x: cursor.x,
+        y: cursor.y,
+        visible: cursor.visible,
+        predicted: cursor.predicted,
+    }))
+}
+
+/// Check the signed release manifest for a newer version of this app
+///
+/// `public_key` is the hex-encoded release signing key pinned in the app.
+/// Cancellable.
+#[cfg(feature = "updates")]
+pub async fn check_for_update(
+    manifest_url: String,
+    public_key: String,
+    channel: ApiReleaseChannel,
+    component: ApiUpdateComponent,
+    operation_id: String,
+) -> Result<UpdateInfoDto> {
+    let checker = update_checker(&public_key, channel, component)?;
+    let result = cancellable(&operation_id, checker.check(&manifest_url)).await?;
+    Ok(UpdateInfoDto {
+        current_version: result.current_version,
+        update_available: result.update_available,
+        latest_version: result.latest.as_ref().map(|r| r.version.clone()),
+        release_notes: result.latest.as_ref().and_then(|r| r.release_notes.clone()),
+        download_size: result.latest.as_ref().map(|r| r.size),
+    })
+}
+
+/// Download and verify the newest installer into `dest_dir`
+///
+/// The release is re-read from the verified manifest rather than taken from
+/// the caller. Returns the installer path, or `None` if already up to date.
+/// The installer is not run.
+///
+/// Cancellable. The installer is written only after it has been fully
+/// fetched and verified, so a cancelled download leaves no partial file.
+#[cfg(feature = "updates")]
+pub async fn download_update(
+    manifest_url: String,
+    public_key: String,
+    channel: ApiReleaseChannel,
+    component: ApiUpdateComponent,
+    dest_dir: String,
+    operation_id: String,
+) -> Result<Option<String>> {
+    let checker = update_checker(&public_key, channel, component)?;
+    cancellable(&operation_id, async {
+        let result = checker.check(&manifest_url).await?;
+        let Some(release) = result.latest.filter(|_| result.update_available) else {
+            return Ok(None);
+        };
+        let path = checker
+            .download(&release, std::path::Path::new(&dest_dir))
+            .await?;
+        Ok(Some(path.to_string_lossy().into_owned()))
+    })
+    .await
+}
+
+#[cfg(feature = "updates")]
+fn update_checker(
+    public_key: &str,
+    channel: ApiReleaseChannel,
+    component: ApiUpdateComponent,
+) -> Result<UpdateChecker> {
+    UpdateChecker::with_http(
+        public_key,
+        match channel {
+            ApiReleaseChannel::Stable => ReleaseChannel::Stable,
+            ApiReleaseChannel::Beta => ReleaseChannel::Beta,
+        },
+        match component {
+            ApiUpdateComponent::Host => UpdateComponent::Host,
+            ApiUpdateComponent::Client => UpdateComponent::Client,
+        },
+        &state()?.version,
+    )
+}
+
+/// Get whether the host launches on login
+#[cfg(feature = "host")]
+pub fn get_autostart_status() -> Result<AutostartStatusDto> {
+    Ok(autostart_to_dto(autostart_manager()?.status()?))
+}
+
+/// Launch the host on login using `method`, or the platform's preferred
+/// method when `None`
+#[cfg(feature = "host")]
+pub fn enable_autostart(method: Option<ApiAutostartMethod>) -> Result<AutostartStatusDto> {
+    let method = match method {
+        Some(method) => method.to_core(),
+        None => AutostartMethod::available()
+            .first()
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Autostart is not supported on this platform"))?,
+    };
+    Ok(autostart_to_dto(autostart_manager()?.enable(method)?))
+}
+
+/// Stop launching the host on login
+#[cfg(feature = "host")]
+pub fn disable_autostart() -> Result<AutostartStatusDto> {
+    Ok(autostart_to_dto(autostart_manager()?.disable()?))
+}
+
+/// Warn connected viewers, wait out the countdown and end every session
+///
+/// Call before the host app quits. `countdown_secs` defaults to 10 seconds;
+/// `force` notifies the viewers and ends sessions without waiting.
+#[cfg(feature = "host")]
+pub async fn shutdown_host(
+    reason: Option<String>,
+    countdown_secs: Option<u32>,
+    force: bool,
+) -> Result<ShutdownReportDto> {
+    let state = state()?;
+    let mut shutdown = HostShutdown::new(Arc::clone(&state.sessions));
+    if let Some(signaling) = state.signaling.read().await.clone() {
+        shutdown = shutdown.with_signaling(signaling);
+    }
+    let defaults = ShutdownOptions::default();
+    let ending: Vec<String> = state
+        .sessions
+        .get_active_sessions()
+        .into_iter()
+        .map(|session| session.session_id)
+        .collect();
+    let report = shutdown
+        .run(ShutdownOptions {
+            reason: reason.unwrap_or(defaults.reason),
+            countdown: countdown_secs
+                .map(|secs| std::time::Duration::from_secs(secs.into()))
+                .unwrap_or(defaults.countdown),
+            force,
+        })
+        .await;
+    for session_id in &ending {
+        state.input.clear_session_accessibility(session_id);
+        state.input.set_session_keyboard_layout(session_id, None);
+        state.remote_open.cancel_session(session_id);
+    }
+    Ok(shutdown_report_to_dto(&report))
+}
+
+/// Displays this host can share, primary first
+#[cfg(feature = "host")]
+pub async fn list_displays() -> Result<Vec<DisplayDto>> {
+    let displays = tokio::task::spawn_blocking(remote_desktop_core::enumerate_displays).await??;
+    Ok(displays.into_iter().map(display_to_dto).collect())
+}
+
+/// Check reachability of the signaling, STUN and TURN servers
+///
+/// Cancellable; probes still in flight are abandoned.
+#[cfg(feature = "host")]
+pub async fn run_network_diagnostics(
+    signaling_url: String,
+    stun_urls: Vec<String>,
+    turn_urls: Vec<String>,
+    operation_id: String,
+) -> Result<NetworkDiagnosticsDto> {
+    let mut manager = DiagnosticsManager::new();
+    manager.configure(&signaling_url, stun_urls, turn_urls);
+    let diagnostics = cancellable(&operation_id, async {
+        Ok(manager.run_network_diagnostics().await)
+    })
+    .await?;
+    let reachable =
+        |servers: &[ServerStatus]| servers.iter().filter(|s| s.reachable).count() as u32;
+    Ok(NetworkDiagnosticsDto {
+        internet_connected: diagnostics.internet_connected,
+        nat_type: format!("{:?}", diagnostics.nat_type),
+        signaling_reachable: diagnostics.signaling_server.reachable,
+        signaling_latency_ms: diagnostics.signaling_server.latency_ms,
+        stun_reachable: reachable(&diagnostics.stun_servers),
+        turn_reachable: reachable(&diagnostics.turn_servers),
+        overall_status: format!("{:?}", diagnostics.overall_status),
+        recommendations: diagnostics.recommendations,
+    })
+}
+
+/// Run network and system diagnostics as a structured report
+///
+/// Returns a JSON `DiagnosticsReport`: sections of checks with statuses,
+/// metrics, localization keys and remediation codes. Cancellable.
+#[cfg(feature = "host")]
+pub async fn run_diagnostics_report(
+    signaling_url: String,
+    stun_urls: Vec<String>,
+    turn_urls: Vec<String>,
+    operation_id: String,
+) -> Result<String> {
+    let mut manager = DiagnosticsManager::new();
+    manager.configure(&signaling_url, stun_urls, turn_urls);
+    let report = cancellable(&operation_id, async { Ok(manager.run_report().await) }).await?;
+    report.to_json()
+}
+
+/// Microphones and loopback devices the host can capture, default first
+#[cfg(feature = "host")]
+pub async fn list_audio_devices() -> Result<Vec<AudioDeviceDto>> {
+    let devices = tokio::task::spawn_blocking(remote_desktop_core::list_audio_devices).await??;
+    Ok(devices
+        .into_iter()
+        .map(|device| AudioDeviceDto {
+            is_loopback: device.kind == AudioDeviceKind::Loopback,
+            id: device.id,
+            name: device.name,
+            is_default: device.is_default,
+        })
+        .collect())
+}
+
+/// Top-level windows this host can share on its own, topmost first
+#[cfg(feature = "host")]
+pub async fn list_windows() -> Result<Vec<WindowDto>> {
+    let windows = tokio::task::spawn_blocking(remote_desktop_core::enumerate_windows).await??;
+    Ok(windows.into_iter().map(window_to_dto).collect())
+}
+
+/// Ask the host user to open a file on behalf of a session's remote device
+///
+/// Requires the session's system control permission, an allowed file type
+/// and a file the session sent (see `accept_incoming_file`). The file is
+/// opened only after `respond_open_request` approves it.
+#[cfg(feature = "host")]
+pub async fn request_open_file(session_id: String, path: String) -> Result<OpenRequestDto> {
+    let state = state()?;
+    let session = state
+        .sessions
+        .get_session(&session_id)
+        .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, &session_id))?;
+    let requested_by = session_to_dto(&session, &state.device_id).remote_device_id;
+    let request =
+        state
+            .remote_open
+            .request_open(&session, &requested_by, std::path::Path::new(&path))?;
+    Ok(open_request_to_dto(request))
+}
+
+/// Open requests waiting for the host user's confirmation
+#[cfg(feature = "host")]
+pub fn list_open_requests() -> Result<Vec<OpenRequestDto>> {
+    Ok(state()?
+        .remote_open
+        .pending_requests()
+        .into_iter()
+        .map(open_request_to_dto)
+        .collect())
+}
+
+/// Approve or deny an open request; returns whether the file was opened
+#[cfg(feature = "host")]
+pub fn respond_open_request(request_id: String, approve: bool) -> Result<bool> {
+    match state()?.remote_open.respond(&request_id, approve)? {
+        OpenOutcome::Opened { .. } => Ok(true),
+        OpenOutcome::Denied => Ok(false),
+        OpenOutcome::FileChanged { .. } => Err(anyhow::anyhow!(
+            "File changed since it was requested; not opened"
+        )),
+    }
+}
+
+/// Ask the host user to open a link from a session's remote device
+///
+/// Requires the session's open-link permission and an http(s) link, and is
+/// rate limited per device. The link is opened only after
+/// `respond_url_request` approves it.
+#[cfg(feature = "host")]
+pub async fn request_open_url(session_id: String, url: String) -> Result<UrlRequestDto> {
+    let state = state()?;
+    let session = state
+        .sessions
+        .get_session(&session_id)
+        .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, &session_id))?;
+    let requested_by = session_to_dto(&session, &state.device_id).remote_device_id;
+    let request = state
+        .remote_url
+        .request_open(&session, &requested_by, &url)?;
+    Ok(url_request_to_dto(request))
+}
+
+/// Links waiting for the host user's confirmation
+#[cfg(feature = "host")]
+pub fn list_url_requests() -> Result<Vec<UrlRequestDto>> {
+    Ok(state()?
+        .remote_url
+        .pending_requests()
+        .into_iter()
+        .map(url_request_to_dto)
+        .collect())
+}
+
+/// Approve or deny a link; returns whether it was opened
+#[cfg(feature = "host")]
+pub fn respond_url_request(request_id: String, approve: bool) -> Result<bool> {
+    let outcome = state()?.remote_url.respond(&request_id, approve)?;
+    Ok(outcome == UrlOpenOutcome::Opened)
+}
+
+/// Send files and folders dropped onto a session's window
+///
+/// Requires the session's file transfer permission. Folders are sent with
+/// their structure, two files at a time; files count against the session's
+/// transfer limits.
+#[cfg(feature = "host")]
+pub async fn send_dropped_files(
+    session_id: String,
+    paths: Vec<String>,
+) -> Result<Vec<OutgoingTransferDto>> {
+    let state = state()?;
+    let remote_device_id = file_transfer_peer(state, &session_id)?;
+    let mut file_transfer = state.file_transfer.lock().await;
+    let mut transfers = Vec::with_capacity(paths.len());
+    for path in paths.into_iter().map(std::path::PathBuf::from) {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if tokio::fs::metadata(&path).await?.is_dir() {
+            let offer = file_transfer
+                .send_folder(path, remote_device_id.clone(), DROPPED_FOLDER_CHANNELS)
+                .await?;
+            transfers.push(OutgoingTransferDto {
+                transfer_id: offer.transfer_id,
+                name,
+                size: offer.entries.iter().map(|entry| entry.size).sum(),
+                mime_type: "inode/directory".to_string(),
+                is_folder: true,
+                file_count: offer.file_transfer_ids.len() as u32,
+            });
+        } else {
+            let size = tokio::fs::metadata(&path).await?.len();
+            let transfer_id = file_transfer
+                .send_session_file(&session_id, path, remote_device_id.clone())
+                .await?;
+            transfers.push(OutgoingTransferDto {
+                transfer_id,
+                mime_type: remote_desktop_core::guess_mime_type(&name).to_string(),
+                name,
+                size,
+                is_folder: false,
+                file_count: 1,
+            });
+        }
+    }
+    Ok(transfers)
+}
+
+/// Send files pasted into a session's window
+///
+/// `paths` are the files the client read from its clipboard; reading the
+/// system clipboard here is not supported yet, so `None` fails. Folders are
+/// skipped and clipboard limits apply. Requires the session's file transfer
+/// permission.
+#[cfg(feature = "host")]
+pub async fn send_pasted_files(
+    session_id: String,
+    paths: Option<Vec<String>>,
+) -> Result<Vec<OutgoingTransferDto>> {
+    let state = state()?;
+    let remote_device_id = file_transfer_peer(state, &session_id)?;
+    let mut clipboard = state.clipboard_files.lock().await;
+    let paths = match paths {
+        Some(paths) => paths.into_iter().map(std::path::PathBuf::from).collect(),
+        None => clipboard.read_clipboard_files()?,
+    };
+    let offer = clipboard.create_offer(paths).await?;
+    let mut file_transfer = state.file_transfer.lock().await;
+    let transfer_ids = clipboard
+        .send_offer(&offer.offer_id, &mut file_transfer, &remote_device_id)
+        .await?;
+    Ok(offer
+        .files
+        .into_iter()
+        .zip(transfer_ids)
+        .map(|(file, transfer_id)| OutgoingTransferDto {
+            transfer_id,
+            name: file.name,
+            size: file.size,
+            mime_type: file.mime_type,
+            is_folder: false,
+            file_count: 1,
+        })
+        .collect())
+}
+
+/// Files remote devices offered that wait for the user
+#[cfg(feature = "host")]
+pub async fn list_incoming_files() -> Result<Vec<IncomingFileDto>> {
+    Ok(state()?
+        .file_transfer
+        .lock()
+        .await
+        .pending_incoming()
+        .into_iter()
+        .map(incoming_file_to_dto)
+        .collect())
+}
+
+/// Accept an offered file into `target_dir`, returning where it is saved
+///
+/// The session that sent it may then ask to open it (`request_open_file`).
+#[cfg(feature = "host")]
+pub async fn accept_incoming_file(transfer_id: String, target_dir: String) -> Result<String> {
+    let state = state()?;
+    let mut file_transfer = state.file_transfer.lock().await;
+    let session_id = file_transfer
+        .pending_incoming()
+        .into_iter()
+        .find(|offer| offer.transfer_id == transfer_id)
+        .map(|offer| offer.session_id.clone());
+    let save_path = file_transfer
+        .accept_incoming(&transfer_id, Some(target_dir.into()))
+        .await?;
+    if let Some(session_id) = session_id {
+        state.remote_open.record_received(&session_id, &save_path)?;
+    }
+    Ok(save_path.to_string_lossy().into_owned())
+}
+
+#[cfg(feature = "host")]
+pub async fn reject_incoming_file(transfer_id: String) -> Result<()> {
+    state()?
+        .file_transfer
+        .lock()
+        .await
+        .reject_incoming(&transfer_id)
+}
+
+/// Files sent from one dropped folder at the same time
+#[cfg(feature = "host")]
+const DROPPED_FOLDER_CHANNELS: usize = 2;
+
+/// Remote device of a session that may transfer files
+#[cfg(feature = "host")]
+fn file_transfer_peer(state: &ApiState, session_id: &str) -> Result<String> {
+    let session = state
+        .sessions
+        .get_session(session_id)
+        .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, session_id))?;
+    if !session
+        .permissions
+        .contains(&SessionPermission::FileTransfer)
+    {
+        return Err(ManagerError::permission_denied(
+            ResourceKind::Session,
+            session_id,
+            "file transfer not granted",
+        )
+        .into());
+    }
+    Ok(session_to_dto(&session, &state.device_id).remote_device_id)
+}
+
+#[cfg(feature = "host")]
+fn incoming_file_to_dto(offer: &IncomingFileOffer) -> IncomingFileDto {
+    IncomingFileDto {
+        transfer_id: offer.transfer_id.clone(),
+        session_id: offer.session_id.clone(),
+        peer_device_id: offer.peer_id.clone(),
+        name: offer.filename.clone(),
+        size: offer.size,
+        mime_type: offer.mime_type.clone(),
+    }
+}
+
+#[cfg(feature = "host")]
+fn display_to_dto(display: DisplayInfo) -> DisplayDto {
+    DisplayDto {
+        id: display.id,
+        name: display.name,
+        width: display.width,
+        height: display.height,
+        refresh_rate: display.refresh_rate,
+        scale_factor: display.scale_factor,
+        x: display.x,
+        y: display.y,
+        is_primary: display.is_primary,
+        hdr_enabled: display.hdr.supported && display.hdr.enabled,
+        max_luminance_nits: display.hdr.max_luminance_nits,
+    }
+}
+
+#[cfg(feature = "host")]
+fn window_to_dto(window: WindowInfo) -> WindowDto {
+    WindowDto {
+        window_id: window.window_id,
+        title: window.title,
+        process_id: window.process_id,
+        x: window.x,
+        y: window.y,
+        width: window.width,
+        height: window.height,
+    }
+}
+
+#[cfg(feature = "host")]
+fn open_request_to_dto(request: OpenRequest) -> OpenRequestDto {
+    OpenRequestDto {
+        request_id: request.request_id,
+        session_id: request.session_id,
+        requested_by: request.requested_by,
+        path: request.path.to_string_lossy().into_owned(),
+        size: request.size,
+        sha256: request.sha256,
+    }
+}
+
+#[cfg(feature = "host")]
+fn url_request_to_dto(request: UrlOpenRequest) -> UrlRequestDto {
+    UrlRequestDto {
+        request_id: request.request_id,
+        session_id: request.session_id,
+        requested_by: request.requested_by,
+        url: request.url,
+        host: request.host,
+    }
+}
+
+#[cfg(feature = "host")]
+fn autostart_manager() -> Result<AutostartManager> {
+    Ok(AutostartManager::new(AutostartConfig {
+        app_id: AUTOSTART_APP_ID.to_string(),
+        display_name: "CEC Remote Desktop".to_string(),
+        executable: std::env::current_exe()?,
+        args: vec!["--autostart".to_string()],
+    })?)
+}
+
+#[cfg(feature = "host")]
+fn shutdown_report_to_dto(report: &ShutdownReport) -> ShutdownReportDto {
+    ShutdownReportDto {
+        sessions_notified: report.sessions_notified as u32,
+        sessions_ended: report.sessions_ended as u32,
+        forced: report.forced,
+    }
+}
+
+#[cfg(feature = "host")]
+fn autostart_to_dto(status: AutostartStatus) -> AutostartStatusDto {
+    AutostartStatusDto {
+        enabled: status.is_enabled(),
+        enabled_methods: status
+            .enabled_methods
+            .into_iter()
+            .map(ApiAutostartMethod::from_core)
+            .collect(),
+        available_methods: status
+            .available_methods
+            .into_iter()
+            .map(ApiAutostartMethod::from_core)
+            .collect(),
+    }
+}
+
+fn to_api_permissions(permissions: &[Permission]) -> Vec<ApiPermission> {
+    let mut result = Vec::new();
+    for permission in permissions {
+        for api in ApiPermission::from_access(*permission) {
+            if !result.contains(&api) {
+                result.push(api);
+            }
+        }
+    }
+    result
+}
+
+fn device_to_dto(auth: DeviceAuthorization) -> DeviceDto {
+    DeviceDto {
+        permissions: to_api_permissions(&auth.permissions),
+        device_id: auth.device_id,
+        device_name: auth.device_name,
+        active: auth.active,
+    }
+}
+
+fn descriptor_to_dto(descriptor: &ActiveSessionDescriptor) -> SessionDescriptorDto {
+    SessionDescriptorDto {
+        session_id: descriptor.session_id.clone(),
+        is_host: descriptor.local_role == SessionRole::Controlled,
+        peer_device_id: descriptor.peer.device_id.clone(),
+        peer_name: descriptor.peer.name.clone(),
+        peer_verified: descriptor.peer.verified,
+        peer_fingerprint: descriptor.peer.fingerprint.clone(),
+        permissions: descriptor
+            .permissions
+            .iter()
+            .filter_map(ApiPermission::from_session)
+            .collect(),
+        connection_type: match descriptor.connection_type {
+            ConnectionType::Direct => ApiConnectionType::Direct,
+            ConnectionType::Relay => ApiConnectionType::Relay,
+            ConnectionType::Unknown => ApiConnectionType::Unknown,
+        },
+        send_bitrate_bps: descriptor.send_bitrate_bps,
+        receive_bitrate_bps: descriptor.receive_bitrate_bps,
+        started_at_ms: descriptor.started_at.timestamp_millis(),
+        elapsed_secs: descriptor.elapsed_secs,
+        paused: descriptor.status == SessionStatus::Paused,
+        recording: descriptor.recording == RecordingStatus::Recording,
+        privacy_mode: descriptor.privacy_mode.map(Into::into),
+    }
+}
+
+fn session_to_dto(session: &Session, local_device_id: &str) -> SessionDto {
+    let remote_device_id = if session.controller_id == local_device_id {
+        session.controlled_id.clone()
+    } else {
+        session.controller_id.clone()
+    };
+
+    SessionDto {
+        session_id: session.session_id.clone(),
+        remote_device_id,
+        permissions: session
+            .permissions
+            .iter()
+            .filter_map(ApiPermission::from_session)
+            .collect(),
+        duration_secs: session.duration_secs(),
+    }
+}
@@ -1,16 +1,145 @@
 //! Rust bridge for Flutter client
 //!
 //! This crate provides FFI bindings between the Rust core engine
-//! and the Flutter client application.
+//! and the Flutter client application. Only the curated `api` module
+//! is exposed; core engine internals are not part of the bridge surface.
 
-pub use remote_desktop_core::*;
+pub mod api;
 
 #[cfg(test)]
 mod tests {
+    use super::api::*;
+
     #[test]
-    fn test_bridge_exports_core() {
-        // Verify that core types are re-exported
-        // This ensures the bridge properly exposes the core library
-        let _ = std::any::type_name::<super::SignalingClient>();
+    fn test_bridge_exposes_api_version() {
+        assert_eq!(api_version(), API_VERSION);
+    }
+
+    #[tokio::test]
+    async fn test_api_session_flow() {
+        let device_id = init(
+            "Test Device".to_string(),
+            "linux".to_string(),
+            "1.0.0".to_string(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(device_id.len(), 36);
+
+        let code = generate_access_code(vec![ApiPermission::ViewScreen])
+            .await
+            .unwrap();
+        assert_eq!(code.code.len(), 6);
+
+        let view_only = start_session("remote-1".to_string(), vec![ApiPermission::ViewScreen])
+            .await
+            .unwrap();
+        let click = InputDto::MouseMove { x: 10, y: 20 };
+        assert!(send_input(view_only.session_id.clone(), click.clone())
+            .await
+            .is_err());
+
+        let control = start_session(
+            "remote-2".to_string(),
+            vec![ApiPermission::ViewScreen, ApiPermission::InputControl],
+        )
+        .await
+        .unwrap();
+        assert_eq!(control.remote_device_id, "remote-2");
+        send_input(control.session_id.clone(), click).await.unwrap();
+
+        set_cursor_prediction(true).unwrap();
+        let drag = InputDto::MouseMove { x: 40, y: 50 };
+        send_input(control.session_id.clone(), drag).await.unwrap();
+        assert!(get_cursor().unwrap().unwrap().predicted);
+        apply_host_cursor(40, 50, true, 0).unwrap();
+        assert!(!get_cursor().unwrap().unwrap().predicted);
+
+        assert_eq!(list_sessions().await.unwrap().len(), 2);
+        end_session(control.session_id).await.unwrap();
+        assert_eq!(list_sessions().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_connect_cancelled() {
+        init(
+            "Test Device".to_string(),
+            "linux".to_string(),
+            "1.0.0".to_string(),
+        )
+        .await
+        .unwrap();
+        // Accepts TCP connections (via the backlog) but never answers the
+        // WebSocket handshake
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+
+        let connecting = tokio::spawn(connect(url.clone(), "connect-1".to_string()));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(cancel_operation("connect-1".to_string()).unwrap());
+        let error = connecting.await.unwrap().unwrap_err();
+        let described = describe_error(&error);
+        assert_eq!(described.kind, ApiErrorKind::Cancelled);
+        assert_eq!(described.id.as_deref(), Some("connect-1"));
+
+        // Finished operations are unregistered and their ids reusable
+        assert!(!cancel_operation("connect-1".to_string()).unwrap());
+        assert!(!is_connected().await.unwrap());
+    }
+
+    #[cfg(feature = "host")]
+    #[tokio::test]
+    async fn test_send_dropped_and_pasted_files() {
+        init(
+            "Test Device".to_string(),
+            "linux".to_string(),
+            "1.0.0".to_string(),
+        )
+        .await
+        .unwrap();
+        let dir = std::env::temp_dir().join(format!("cec-drop-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("album")).unwrap();
+        std::fs::write(dir.join("album").join("cover.png"), b"png").unwrap();
+        std::fs::write(dir.join("notes.pdf"), b"pdf!").unwrap();
+        let paths = vec![
+            dir.join("notes.pdf").to_string_lossy().into_owned(),
+            dir.join("album").to_string_lossy().into_owned(),
+        ];
+
+        let view_only = start_session("remote-3".to_string(), vec![ApiPermission::ViewScreen])
+            .await
+            .unwrap();
+        let error = send_dropped_files(view_only.session_id, paths.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(describe_error(&error).kind, ApiErrorKind::PermissionDenied);
+
+        let session = start_session(
+            "remote-4".to_string(),
+            vec![ApiPermission::ViewScreen, ApiPermission::FileTransfer],
+        )
+        .await
+        .unwrap();
+        let sent = send_dropped_files(session.session_id.clone(), paths.clone())
+            .await
+            .unwrap();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(
+            (sent[0].mime_type.as_str(), sent[0].size, sent[0].is_folder),
+            ("application/pdf", 4, false)
+        );
+        assert_eq!(
+            (sent[1].name.as_str(), sent[1].file_count, sent[1].is_folder),
+            ("album", 1, true)
+        );
+
+        // Folders cannot be pasted, only files
+        let pasted = send_pasted_files(session.session_id, Some(paths))
+            .await
+            .unwrap();
+        assert_eq!(pasted.len(), 1);
+        assert_eq!(pasted[0].name, "notes.pdf");
+        assert_eq!(pasted[0].mime_type, "application/pdf");
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -7,17 +7,60 @@ fn main() {
     {
         println!("cargo:rustc-link-lib=user32");
         println!("cargo:rustc-link-lib=gdi32");
+        println!("cargo:rustc-link-lib=shcore");
+        println!("cargo:rustc-link-lib=shell32");
+        println!("cargo:rustc-link-lib=advapi32");
+        println!("cargo:rustc-link-lib=dwmapi");
     }
 
     #[cfg(target_os = "macos")]
     {
         println!("cargo:rustc-link-lib=framework=CoreGraphics");
         println!("cargo:rustc-link-lib=framework=CoreFoundation");
+        println!("cargo:rustc-link-lib=framework=ApplicationServices");
+        println!("cargo:rustc-link-lib=framework=Carbon");
+        println!("cargo:rustc-link-lib=framework=Security");
     }
 
+    // Capture backends are only linked when the capture feature is enabled
     #[cfg(target_os = "linux")]
-    {
+    if std::env::var_os("CARGO_FEATURE_CAPTURE").is_some() {
         println!("cargo:rustc-link-lib=X11");
         println!("cargo:rustc-link-lib=Xrandr");
+        println!("cargo:rustc-link-lib=Xi");
+        println!("cargo:rustc-link-lib=Xext");
+    }
+    #[cfg(target_os = "windows")]
+    if std::env::var_os("CARGO_FEATURE_CAPTURE").is_some() {
+        println!("cargo:rustc-link-lib=d3d11");
+        println!("cargo:rustc-link-lib=dxgi");
+    }
+    #[cfg(target_os = "linux")]
+    if std::env::var_os("CARGO_FEATURE_AUDIO").is_some() {
+        println!("cargo:rustc-link-lib=asound");
+    }
+    // NSPasteboard backs reading clipboard files
+    #[cfg(target_os = "macos")]
+    if std::env::var_os("CARGO_FEATURE_FILE_TRANSFER").is_some() {
+        println!("cargo:rustc-link-lib=framework=AppKit");
+        println!("cargo:rustc-link-lib=objc");
+    }
+    #[cfg(target_os = "windows")]
+    if std::env::var_os("CARGO_FEATURE_AUDIO").is_some() {
+        println!("cargo:rustc-link-lib=ole32");
+        println!("cargo:rustc-link-lib=mmdevapi");
+        println!("cargo:rustc-link-lib=ntdll");
+    }
+    #[cfg(target_os = "macos")]
+    if std::env::var_os("CARGO_FEATURE_AUDIO").is_some() {
+        println!("cargo:rustc-link-lib=framework=CoreAudio");
+        println!("cargo:rustc-link-lib=framework=AudioToolbox");
+        println!("cargo:rustc-link-lib=framework=Foundation");
+        println!("cargo:rustc-link-lib=objc");
+    }
+
+    // Tesseract backs region OCR on every platform
+    if std::env::var_os("CARGO_FEATURE_OCR").is_some() {
+        println!("cargo:rustc-link-lib=tesseract");
     }
 }
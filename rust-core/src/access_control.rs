@@ -3,11 +3,19 @@
 //! Implements device ID generation, temporary access codes, and permission management.
 //! Requirements: 5.1, 5.2, 5.4, 5.5, 5.7
 
+use crate::access_risk::{AccessSchedule, RiskAssessment, RiskScorer};
+use crate::access_store::AccessControlStore;
+use crate::clock::{system_clock, SharedClock};
+use crate::errors::{ManagerError, ResourceKind};
+use crate::input_control::AccessibilitySettings;
+use crate::logging::{LogEntry, LogLevel, LogManager};
+use crate::secrets::SecretsStore;
+use crate::timestamp::Timestamp;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 use tokio::sync::RwLock;
 use uuid::Uuid;
 
@@ -27,6 +35,16 @@ pub enum Permission {
     Clipboard,
     /// Capture audio
     AudioCapture,
+    /// Share a single application and its audio
+    AppSharing,
+    /// Open links in the host's browser, after the host confirms
+    OpenUrl,
+    /// Block the host's local keyboard and mouse and blank its screen;
+    /// not part of FullControl as it locks out the person at the host
+    PrivacyMode,
+    /// Open received files and change display settings on the host, after
+    /// the host confirms; not part of FullControl as it runs programs there
+    SystemControl,
     /// Full control (all permissions)
     FullControl,
 }
@@ -40,6 +58,8 @@ impl Permission {
             Permission::FileTransfer,
             Permission::Clipboard,
             Permission::AudioCapture,
+            Permission::AppSharing,
+            Permission::OpenUrl,
         ]
     }
 }
@@ -55,15 +75,46 @@ pub enum AuthorizationType {
     UnattendedAccess,
 }
 
+/// File transfer limits for a device; `None` fields are unlimited
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferLimits {
+    pub max_files_per_session: Option<u32>,
+    pub max_file_size: Option<u64>,
+    pub max_bytes_per_session: Option<u64>,
+    /// Bytes per device per local calendar day
+    pub max_bytes_per_day: Option<u64>,
+}
+
+impl TransferLimits {
+    /// Default limits for an authorization type
+    ///
+    /// Temporary access-code sessions are limited; persistent authorizations
+    /// are not, unless configured.
+    pub fn default_for(auth_type: &AuthorizationType) -> Self {
+        const GIB: u64 = 1024 * 1024 * 1024;
+        match auth_type {
+            AuthorizationType::AccessCode => Self {
+                max_files_per_session: Some(100),
+                max_file_size: Some(GIB),
+                max_bytes_per_session: Some(2 * GIB),
+                max_bytes_per_day: Some(5 * GIB),
+            },
+            AuthorizationType::AccountBinding | AuthorizationType::UnattendedAccess => {
+                Self::default()
+            }
+        }
+    }
+}
+
 /// Access code for temporary authorization
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AccessCode {
     /// The access code string
     pub code: String,
     /// Device ID that generated this code
     pub device_id: String,
     /// When the code was created
-    pub created_at: Instant,
+    pub created_at: Timestamp,
     /// Expiration duration
     pub expires_in: Duration,
     /// Permissions granted by this code
@@ -75,22 +126,27 @@ pub struct AccessCode {
 impl AccessCode {
     /// Check if the access code has expired
     pub fn is_expired(&self) -> bool {
-        self.created_at.elapsed() > self.expires_in
+        self.is_expired_at(&Timestamp::now())
+    }
+
+    /// Check if the access code has expired as of `now`
+    pub fn is_expired_at(&self, now: &Timestamp) -> bool {
+        self.created_at.has_elapsed_at(self.expires_in, now)
     }
 
     /// Check if the access code is valid (not expired and not used)
     pub fn is_valid(&self) -> bool {
-        !self.is_expired() && !self.used
+        self.is_valid_at(&Timestamp::now())
+    }
+
+    /// Check if the access code is valid as of `now`
+    pub fn is_valid_at(&self, now: &Timestamp) -> bool {
+        !self.is_expired_at(now) && !self.used
     }
 
     /// Get remaining time in seconds
     pub fn remaining_seconds(&self) -> u64 {
-        let elapsed = self.created_at.elapsed();
-        if elapsed >= self.expires_in {
-            0
-        } else {
-            (self.expires_in - elapsed).as_secs()
-        }
+        self.created_at.remaining(self.expires_in).as_secs()
     }
 }
 
@@ -111,10 +167,28 @@ pub struct DeviceAuthorization {
     pub expires_at: Option<String>,
     /// Whether this is an active authorization
     pub active: bool,
+    /// File transfer limits overriding the authorization type's defaults
+    #[serde(default)]
+    pub transfer_limits: Option<TransferLimits>,
+    /// Assistive input modes remembered for this device's sessions
+    #[serde(default)]
+    pub accessibility: Option<AccessibilitySettings>,
+    /// Files this device sends are received without asking the user
+    #[serde(default)]
+    pub auto_accept_files: bool,
+}
+
+impl DeviceAuthorization {
+    /// Effective file transfer limits
+    pub fn effective_transfer_limits(&self) -> TransferLimits {
+        self.transfer_limits
+            .clone()
+            .unwrap_or_else(|| TransferLimits::default_for(&self.auth_type))
+    }
 }
 
 /// Connection request from a remote device
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectionRequest {
     /// Request ID
     pub request_id: String,
@@ -127,7 +201,13 @@ pub struct ConnectionRequest {
     /// Access code if provided
     pub access_code: Option<String>,
     /// When the request was made
-    pub requested_at: Instant,
+    pub requested_at: Timestamp,
+    /// Country the request came from, if known
+    #[serde(default)]
+    pub remote_country: Option<String>,
+    /// Risk score computed when the request arrived
+    #[serde(default)]
+    pub risk: Option<RiskAssessment>,
 }
 
 /// Connection request response
@@ -176,6 +256,16 @@ pub struct AccessControlManager {
     pending_requests: Arc<RwLock<HashMap<String, ConnectionRequest>>>,
     /// Device registration info
     device_registration: Arc<RwLock<Option<DeviceRegistration>>>,
+    /// Secure storage for the unattended access password hash
+    secrets: Arc<RwLock<Option<Arc<SecretsStore>>>>,
+    /// Persistent store written through on every change
+    store: Arc<RwLock<Option<Arc<AccessControlStore>>>>,
+    /// Risk signal history for incoming requests
+    risk_scorer: Arc<RwLock<RiskScorer>>,
+    /// Audit log for access decisions
+    audit_log: Arc<RwLock<Option<Arc<LogManager>>>>,
+    /// Time source for code expiry
+    clock: SharedClock,
 }
 
 impl AccessControlManager {
@@ -187,6 +277,84 @@ impl AccessControlManager {
             authorized_devices: Arc::new(RwLock::new(HashMap::new())),
             pending_requests: Arc::new(RwLock::new(HashMap::new())),
             device_registration: Arc::new(RwLock::new(None)),
+            secrets: Arc::new(RwLock::new(None)),
+            store: Arc::new(RwLock::new(None)),
+            risk_scorer: Arc::new(RwLock::new(RiskScorer::new())),
+            audit_log: Arc::new(RwLock::new(None)),
+            clock: system_clock(),
+        }
+    }
+
+    /// Read the time from `clock` instead of the system clocks
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Write access decisions and request risk scores to the given audit log
+    pub async fn set_audit_log(&self, log_manager: Arc<LogManager>) {
+        *self.audit_log.write().await = Some(log_manager);
+    }
+
+    /// Set the hours remote access is expected; requests outside them score
+    /// higher
+    pub async fn set_access_schedule(&self, schedule: Option<AccessSchedule>) {
+        self.risk_scorer.write().await.set_schedule(schedule);
+    }
+
+    /// Attach a secrets store used to persist unattended access credentials
+    pub async fn set_secrets_store(&self, store: Arc<SecretsStore>) {
+        *self.secrets.write().await = Some(store);
+    }
+
+    /// Attach a persistent store and load its contents
+    ///
+    /// Loaded entries replace in-memory ones with the same key. Access codes
+    /// that expired while the process was not running are discarded.
+    pub async fn attach_store(&self, store: Arc<AccessControlStore>) -> Result<()> {
+        let state = store.load()?;
+
+        if let Some(registration) = state.registration {
+            *self.device_id.write().await = Some(registration.device_id.clone());
+            *self.device_registration.write().await = Some(registration);
+        }
+
+        {
+            let mut codes = self.access_codes.write().await;
+            let now = self.clock.now();
+            for code in state.access_codes {
+                if code.is_expired_at(&now) {
+                    store.delete_access_code(&code.code)?;
+                } else {
+                    codes.insert(code.code.clone(), code);
+                }
+            }
+        }
+
+        {
+            let mut authorized = self.authorized_devices.write().await;
+            for auth in state.authorizations {
+                authorized.insert(auth.device_id.clone(), auth);
+            }
+        }
+
+        {
+            let mut requests = self.pending_requests.write().await;
+            for request in state.pending_requests {
+                requests.insert(request.request_id.clone(), request);
+            }
+        }
+
+        *self.store.write().await = Some(store);
+        tracing::info!("Access control state loaded from persistent store");
+        Ok(())
+    }
+
+    /// Run a write-through operation if a store is attached
+    async fn persist(&self, op: impl FnOnce(&AccessControlStore) -> Result<()>) -> Result<()> {
+        match self.store.read().await.as_ref() {
+            Some(store) => op(store),
+            None => Ok(()),
         }
     }
 
@@ -205,7 +373,7 @@ impl AccessControlManager {
         version: String,
     ) -> Result<String> {
         let device_id = Self::generate_device_id();
-        let now = chrono::Utc::now().to_rfc3339();
+        let now = self.clock.utc().to_rfc3339();
 
         let registration = DeviceRegistration {
             device_id: device_id.clone(),
@@ -220,6 +388,8 @@ impl AccessControlManager {
 
         {
             let mut reg = self.device_registration.write().await;
+            self.persist(|store| store.save_registration(&registration))
+                .await?;
             *reg = Some(registration);
         }
 
@@ -245,7 +415,7 @@ impl AccessControlManager {
             .read()
             .await
             .clone()
-            .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
+            .ok_or_else(not_registered)?;
 
         // Generate a 6-digit numeric code for easy sharing
         let code = format!("{:06}", rand_code());
@@ -253,7 +423,7 @@ impl AccessControlManager {
         let access_code = AccessCode {
             code: code.clone(),
             device_id,
-            created_at: Instant::now(),
+            created_at: self.clock.now(),
             expires_in: Duration::from_secs(ACCESS_CODE_EXPIRATION_SECS),
             permissions,
             used: false,
@@ -261,6 +431,8 @@ impl AccessControlManager {
 
         {
             let mut codes = self.access_codes.write().await;
+            self.persist(|store| store.save_access_code(&access_code))
+                .await?;
             codes.insert(code.clone(), access_code.clone());
         }
 
@@ -278,9 +450,10 @@ impl AccessControlManager {
         let codes = self.access_codes.read().await;
 
         if let Some(access_code) = codes.get(code) {
-            if access_code.is_valid() {
+            let now = self.clock.now();
+            if access_code.is_valid_at(&now) {
                 Ok(Some(access_code.clone()))
-            } else if access_code.is_expired() {
+            } else if access_code.is_expired_at(&now) {
                 tracing::warn!("Access code {} has expired", code);
                 Ok(None)
             } else {
@@ -298,8 +471,10 @@ impl AccessControlManager {
         let mut codes = self.access_codes.write().await;
 
         if let Some(access_code) = codes.get_mut(code) {
-            if access_code.is_valid() {
+            if access_code.is_valid_at(&self.clock.now()) {
                 access_code.used = true;
+                self.persist(|store| store.save_access_code(access_code))
+                    .await?;
                 let permissions = access_code.permissions.clone();
                 tracing::info!("Access code {} used successfully", code);
                 Ok(Some(permissions))
@@ -314,11 +489,20 @@ impl AccessControlManager {
     /// Clean up expired access codes
     pub async fn cleanup_expired_codes(&self) {
         let mut codes = self.access_codes.write().await;
-        let before_count = codes.len();
-        codes.retain(|_, code| !code.is_expired());
-        let removed = before_count - codes.len();
-        if removed > 0 {
-            tracing::debug!("Cleaned up {} expired access codes", removed);
+        let now = self.clock.now();
+        let expired: Vec<String> = codes
+            .values()
+            .filter(|code| code.is_expired_at(&now))
+            .map(|code| code.code.clone())
+            .collect();
+        for code in &expired {
+            codes.remove(code);
+            if let Err(e) = self.persist(|store| store.delete_access_code(code)).await {
+                tracing::warn!("Failed to delete expired access code from store: {}", e);
+            }
+        }
+        if !expired.is_empty() {
+            tracing::debug!("Cleaned up {} expired access codes", expired.len());
         }
     }
 
@@ -330,24 +514,62 @@ impl AccessControlManager {
         from_device_name: String,
         requested_permissions: Vec<Permission>,
         access_code: Option<String>,
+    ) -> Result<ConnectionRequest> {
+        self.handle_connection_request_from_country(
+            from_device_id,
+            from_device_name,
+            requested_permissions,
+            access_code,
+            None,
+        )
+        .await
+    }
+
+    /// `handle_connection_request` for a peer whose country is known, which
+    /// feeds the new-country risk signal
+    pub async fn handle_connection_request_from_country(
+        &self,
+        from_device_id: String,
+        from_device_name: String,
+        requested_permissions: Vec<Permission>,
+        access_code: Option<String>,
+        remote_country: Option<String>,
     ) -> Result<ConnectionRequest> {
         let request_id = Uuid::new_v4().to_string();
 
+        let known_device = self
+            .authorized_devices
+            .read()
+            .await
+            .contains_key(&from_device_id);
+        let requested_at = self.clock.now();
+        let risk = self.risk_scorer.write().await.assess(
+            &from_device_id,
+            known_device,
+            remote_country.as_deref(),
+            &requested_at,
+        );
+
         let request = ConnectionRequest {
             request_id: request_id.clone(),
             from_device_id,
             from_device_name,
             requested_permissions,
             access_code,
-            requested_at: Instant::now(),
+            requested_at,
+            remote_country,
+            risk: Some(risk),
         };
 
         {
             let mut requests = self.pending_requests.write().await;
+            self.persist(|store| store.save_request(&request)).await?;
             requests.insert(request_id.clone(), request.clone());
         }
 
         tracing::info!("Connection request received: {}", request_id);
+        self.audit(LogLevel::Info, "Connection request received", &request)
+            .await;
         Ok(request)
     }
 
@@ -359,12 +581,108 @@ impl AccessControlManager {
         accepted: bool,
         granted_permissions: Option<Vec<Permission>>,
         rejection_reason: Option<String>,
+    ) -> Result<ConnectionResponse> {
+        self.complete_request(
+            request_id,
+            accepted,
+            granted_permissions,
+            rejection_reason,
+            AuthorizationType::AccessCode,
+        )
+        .await
+    }
+
+    /// Accept a connection request with the unattended access password
+    ///
+    /// High-risk requests additionally need a valid access code, either the
+    /// one sent with the request or `access_code`. Without it the request
+    /// stays pending for the host to accept explicitly.
+    pub async fn authorize_unattended(
+        &self,
+        request_id: &str,
+        password: &str,
+        access_code: Option<&str>,
+    ) -> Result<ConnectionResponse> {
+        let request = self
+            .pending_requests
+            .read()
+            .await
+            .get(request_id)
+            .cloned()
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::ConnectionRequest, request_id))?;
+
+        if !self.validate_unattended_password(password).await {
+            self.risk_scorer
+                .write()
+                .await
+                .record_failure(&request.from_device_id, self.clock.now());
+            self.audit(LogLevel::Warn, "Unattended access denied", &request)
+                .await;
+            return Err(ManagerError::permission_denied(
+                ResourceKind::ConnectionRequest,
+                request_id,
+                "invalid unattended access password",
+            )
+            .into());
+        }
+
+        if request
+            .risk
+            .as_ref()
+            .is_some_and(|risk| risk.requires_additional_verification())
+        {
+            let code = access_code.or(request.access_code.as_deref());
+            let verified = match code {
+                Some(code) => self.use_access_code(code).await?.is_some(),
+                None => false,
+            };
+            if !verified {
+                if code.is_some() {
+                    self.risk_scorer
+                        .write()
+                        .await
+                        .record_failure(&request.from_device_id, self.clock.now());
+                }
+                self.audit(LogLevel::Warn, "Additional verification required", &request)
+                    .await;
+                return Err(ManagerError::permission_denied(
+                    ResourceKind::ConnectionRequest,
+                    request_id,
+                    "high-risk request needs a valid access code or host confirmation",
+                )
+                .into());
+            }
+        }
+
+        let response = self
+            .complete_request(
+                request_id,
+                true,
+                None,
+                None,
+                AuthorizationType::UnattendedAccess,
+            )
+            .await?;
+        self.audit(LogLevel::Info, "Unattended access granted", &request)
+            .await;
+        Ok(response)
+    }
+
+    async fn complete_request(
+        &self,
+        request_id: &str,
+        accepted: bool,
+        granted_permissions: Option<Vec<Permission>>,
+        rejection_reason: Option<String>,
+        auth_type: AuthorizationType,
     ) -> Result<ConnectionResponse> {
         let mut requests = self.pending_requests.write().await;
 
         let request = requests
             .remove(request_id)
-            .ok_or_else(|| anyhow::anyhow!("Request not found: {}", request_id))?;
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::ConnectionRequest, request_id))?;
+        self.persist(|store| store.delete_request(request_id))
+            .await?;
 
         let response = if accepted {
             let permissions =
@@ -374,17 +692,26 @@ impl AccessControlManager {
             let auth = DeviceAuthorization {
                 device_id: request.from_device_id.clone(),
                 device_name: request.from_device_name.clone(),
-                auth_type: AuthorizationType::AccessCode,
+                auth_type,
                 permissions: permissions.clone(),
-                authorized_at: chrono::Utc::now().to_rfc3339(),
+                authorized_at: self.clock.utc().to_rfc3339(),
                 expires_at: None,
                 active: true,
+                transfer_limits: None,
+                accessibility: None,
+                auto_accept_files: false,
             };
 
             {
                 let mut authorized = self.authorized_devices.write().await;
+                self.persist(|store| store.save_authorization(&auth))
+                    .await?;
                 authorized.insert(request.from_device_id.clone(), auth);
             }
+            self.risk_scorer
+                .write()
+                .await
+                .record_success(&request.from_device_id, request.remote_country.as_deref());
 
             ConnectionResponse {
                 request_id: request_id.to_string(),
@@ -433,13 +760,82 @@ impl AccessControlManager {
 
         if let Some(auth) = authorized.get_mut(device_id) {
             auth.active = false;
+            self.persist(|store| store.save_authorization(auth)).await?;
             tracing::info!("Authorization revoked for device: {}", device_id);
             Ok(())
         } else {
-            Err(anyhow::anyhow!("Device not found: {}", device_id))
+            Err(ManagerError::not_found(ResourceKind::Device, device_id).into())
         }
     }
 
+    /// Override (or with `None`, reset) a device's file transfer limits
+    pub async fn set_transfer_limits(
+        &self,
+        device_id: &str,
+        limits: Option<TransferLimits>,
+    ) -> Result<()> {
+        let mut authorized = self.authorized_devices.write().await;
+        let auth = authorized
+            .get_mut(device_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Device, device_id))?;
+        auth.transfer_limits = limits;
+        self.persist(|store| store.save_authorization(auth)).await
+    }
+
+    /// File transfer limits for a device; unknown devices get access-code limits
+    pub async fn get_transfer_limits(&self, device_id: &str) -> TransferLimits {
+        self.authorized_devices
+            .read()
+            .await
+            .get(device_id)
+            .map(|auth| auth.effective_transfer_limits())
+            .unwrap_or_else(|| TransferLimits::default_for(&AuthorizationType::AccessCode))
+    }
+
+    /// Receive files from a device without a prompt, or ask again
+    pub async fn set_auto_accept_files(&self, device_id: &str, enabled: bool) -> Result<()> {
+        let mut authorized = self.authorized_devices.write().await;
+        let auth = authorized
+            .get_mut(device_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Device, device_id))?;
+        auth.auto_accept_files = enabled;
+        self.persist(|store| store.save_authorization(auth)).await
+    }
+
+    /// Whether files from a device skip the acceptance prompt
+    pub async fn auto_accepts_files(&self, device_id: &str) -> bool {
+        self.authorized_devices
+            .read()
+            .await
+            .get(device_id)
+            .is_some_and(|auth| auth.active && auth.auto_accept_files)
+    }
+
+    /// Remember assistive input modes for a device's future sessions
+    pub async fn set_accessibility_settings(
+        &self,
+        device_id: &str,
+        settings: Option<AccessibilitySettings>,
+    ) -> Result<()> {
+        let mut authorized = self.authorized_devices.write().await;
+        let auth = authorized
+            .get_mut(device_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Device, device_id))?;
+        auth.accessibility = settings;
+        self.persist(|store| store.save_authorization(auth)).await
+    }
+
+    pub async fn get_accessibility_settings(
+        &self,
+        device_id: &str,
+    ) -> Option<AccessibilitySettings> {
+        self.authorized_devices
+            .read()
+            .await
+            .get(device_id)
+            .and_then(|auth| auth.accessibility.clone())
+    }
+
     /// Enable unattended access
     /// Requirement 5.6: Support unattended access mode with pre-authorization
     pub async fn enable_unattended_access(&self, password: &str) -> Result<()> {
@@ -448,12 +844,17 @@ impl AccessControlManager {
         if let Some(registration) = reg.as_mut() {
             // In production, use proper password hashing (bcrypt, argon2, etc.)
             let hash = simple_hash(password);
+            if let Some(store) = self.secrets.read().await.as_ref() {
+                store.set_unattended_password_hash(&hash)?;
+            }
             registration.unattended_access_enabled = true;
             registration.unattended_password_hash = Some(hash);
+            self.persist(|store| store.save_registration(registration))
+                .await?;
             tracing::info!("Unattended access enabled");
             Ok(())
         } else {
-            Err(anyhow::anyhow!("Device not registered"))
+            Err(not_registered().into())
         }
     }
 
@@ -462,12 +863,39 @@ impl AccessControlManager {
         let mut reg = self.device_registration.write().await;
 
         if let Some(registration) = reg.as_mut() {
+            if let Some(store) = self.secrets.read().await.as_ref() {
+                store.clear_unattended_password_hash()?;
+            }
             registration.unattended_access_enabled = false;
             registration.unattended_password_hash = None;
+            self.persist(|store| store.save_registration(registration))
+                .await?;
             tracing::info!("Unattended access disabled");
             Ok(())
         } else {
-            Err(anyhow::anyhow!("Device not registered"))
+            Err(not_registered().into())
+        }
+    }
+
+    /// Restore unattended access from the secrets store
+    ///
+    /// Returns whether a stored password hash was found and applied.
+    pub async fn restore_unattended_access(&self) -> Result<bool> {
+        let hash = match self.secrets.read().await.as_ref() {
+            Some(store) => store.get_unattended_password_hash()?,
+            None => return Ok(false),
+        };
+
+        let mut reg = self.device_registration.write().await;
+        match (reg.as_mut(), hash) {
+            (Some(registration), Some(hash)) => {
+                registration.unattended_access_enabled = true;
+                registration.unattended_password_hash = Some(hash);
+                tracing::info!("Unattended access restored from secrets store");
+                Ok(true)
+            }
+            (None, Some(_)) => Err(not_registered().into()),
+            _ => Ok(false),
         }
     }
 
@@ -501,6 +929,20 @@ impl AccessControlManager {
     pub async fn get_device_registration(&self) -> Option<DeviceRegistration> {
         self.device_registration.read().await.clone()
     }
+
+    /// Write an access decision, with the request's risk score, to the audit log
+    async fn audit(&self, level: LogLevel, message: &str, request: &ConnectionRequest) {
+        if let Some(log_manager) = self.audit_log.read().await.as_ref() {
+            log_manager.log(LogEntry::new(level, "audit", message).with_metadata(
+                serde_json::json!({
+                    "request_id": request.request_id,
+                    "device_id": request.from_device_id,
+                    "remote_country": request.remote_country,
+                    "risk": request.risk,
+                }),
+            ));
+        }
+    }
 }
 
 impl Default for AccessControlManager {
@@ -510,6 +952,11 @@ impl Default for AccessControlManager {
 }
 
 /// Generate a random 6-digit code
+/// This device has no registration yet
+fn not_registered() -> ManagerError {
+    ManagerError::invalid_state(ResourceKind::Device, "local", "Unregistered", "Registered")
+}
+
 fn rand_code() -> u32 {
     use std::time::{SystemTime, UNIX_EPOCH};
     let seed = SystemTime::now()
@@ -549,6 +996,40 @@ mod tests {
         assert!(permissions.contains(&Permission::FileTransfer));
         assert!(permissions.contains(&Permission::Clipboard));
         assert!(permissions.contains(&Permission::AudioCapture));
+        assert!(permissions.contains(&Permission::AppSharing));
+        assert!(permissions.contains(&Permission::OpenUrl));
+        assert!(!permissions.contains(&Permission::PrivacyMode));
+        assert!(!permissions.contains(&Permission::SystemControl));
+    }
+
+    #[tokio::test]
+    async fn test_access_code_expires_on_injected_clock() {
+        let clock = crate::clock::TestClock::shared();
+        let manager = AccessControlManager::new().with_clock(clock.clone());
+        manager
+            .register_device("Host".to_string(), "linux".to_string(), "1.0".to_string())
+            .await
+            .unwrap();
+        let code = manager
+            .generate_access_code(vec![Permission::ViewScreen])
+            .await
+            .unwrap();
+
+        clock.advance(Duration::from_secs(ACCESS_CODE_EXPIRATION_SECS - 1));
+        assert!(manager
+            .validate_access_code(&code.code)
+            .await
+            .unwrap()
+            .is_some());
+
+        clock.advance(Duration::from_secs(2));
+        assert!(manager
+            .validate_access_code(&code.code)
+            .await
+            .unwrap()
+            .is_none());
+        manager.cleanup_expired_codes().await;
+        assert!(manager.access_codes.read().await.is_empty());
     }
 
     #[tokio::test]
@@ -556,8 +1037,8 @@ mod tests {
         let code = AccessCode {
             code: "123456".to_string(),
             device_id: "test-device".to_string(),
-            created_at: Instant::now() - Duration::from_secs(700), // 700 seconds ago
-            expires_in: Duration::from_secs(600),                  // 10 minutes
+            created_at: Timestamp::now() - Duration::from_secs(700), // 700 seconds ago
+            expires_in: Duration::from_secs(600),                    // 10 minutes
             permissions: vec![Permission::ViewScreen],
             used: false,
         };
@@ -571,7 +1052,7 @@ mod tests {
         let code = AccessCode {
             code: "123456".to_string(),
             device_id: "test-device".to_string(),
-            created_at: Instant::now(),
+            created_at: Timestamp::now(),
             expires_in: Duration::from_secs(600),
             permissions: vec![Permission::ViewScreen],
             used: false,
@@ -586,7 +1067,7 @@ mod tests {
         let code = AccessCode {
             code: "123456".to_string(),
             device_id: "test-device".to_string(),
-            created_at: Instant::now(),
+            created_at: Timestamp::now(),
             expires_in: Duration::from_secs(600),
             permissions: vec![Permission::ViewScreen],
             used: true,
@@ -595,4 +1076,200 @@ mod tests {
         assert!(!code.is_expired());
         assert!(!code.is_valid()); // Used codes are not valid
     }
+
+    #[tokio::test]
+    async fn test_typed_errors_for_unknown_targets() {
+        use crate::errors::{ManagerError, ResourceKind};
+
+        let manager = AccessControlManager::new();
+        let err = manager.generate_access_code(vec![]).await.unwrap_err();
+        assert!(matches!(
+            ManagerError::of(&err),
+            Some(ManagerError::InvalidState { state, .. }) if state == "Unregistered"
+        ));
+        let err = manager.revoke_authorization("gone").await.unwrap_err();
+        assert_eq!(
+            ManagerError::of(&err),
+            Some(&ManagerError::not_found(ResourceKind::Device, "gone"))
+        );
+    }
+
+    #[test]
+    fn test_access_code_expiry_after_restore() {
+        let code = AccessCode {
+            code: "123456".to_string(),
+            device_id: "test-device".to_string(),
+            created_at: Timestamp::now() - Duration::from_secs(700),
+            expires_in: Duration::from_secs(600),
+            permissions: vec![Permission::ViewScreen],
+            used: false,
+        };
+
+        let json = serde_json::to_string(&code).unwrap();
+        let restored: AccessCode = serde_json::from_str(&json).unwrap();
+        assert!(!restored.created_at.has_monotonic());
+        assert!(restored.is_expired());
+        assert_eq!(restored.remaining_seconds(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_state_survives_restart() {
+        let store = Arc::new(AccessControlStore::open_in_memory().unwrap());
+
+        let manager = AccessControlManager::new();
+        manager.attach_store(store.clone()).await.unwrap();
+        let device_id = manager
+            .register_device("Host".to_string(), "linux".to_string(), "1.0".to_string())
+            .await
+            .unwrap();
+        let code = manager
+            .generate_access_code(vec![Permission::ViewScreen])
+            .await
+            .unwrap();
+        let request = manager
+            .handle_connection_request(
+                "viewer".to_string(),
+                "Viewer".to_string(),
+                vec![Permission::ViewScreen],
+                None,
+            )
+            .await
+            .unwrap();
+        manager
+            .respond_to_request(&request.request_id, true, None, None)
+            .await
+            .unwrap();
+
+        // A fresh manager on the same store sees everything
+        let restarted = AccessControlManager::new();
+        restarted.attach_store(store).await.unwrap();
+        assert_eq!(restarted.get_device_id().await, Some(device_id));
+        assert!(restarted
+            .validate_access_code(&code.code)
+            .await
+            .unwrap()
+            .is_some());
+        assert!(restarted.is_device_authorized("viewer").await);
+        assert!(restarted.get_pending_requests().await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_high_risk_unattended_request_needs_access_code() {
+        let log_manager = Arc::new(LogManager::default());
+        let manager = AccessControlManager::new();
+        manager.set_audit_log(log_manager.clone()).await;
+        manager
+            .register_device("Host".to_string(), "linux".to_string(), "1.0".to_string())
+            .await
+            .unwrap();
+        manager.enable_unattended_access("secret").await.unwrap();
+
+        // Establish a known device and country
+        let first = manager
+            .handle_connection_request_from_country(
+                "laptop".to_string(),
+                "Laptop".to_string(),
+                vec![Permission::ViewScreen],
+                None,
+                Some("DE".to_string()),
+            )
+            .await
+            .unwrap();
+        manager
+            .respond_to_request(&first.request_id, true, None, None)
+            .await
+            .unwrap();
+
+        let request = manager
+            .handle_connection_request_from_country(
+                "stranger".to_string(),
+                "Stranger".to_string(),
+                vec![Permission::ViewScreen],
+                None,
+                Some("BR".to_string()),
+            )
+            .await
+            .unwrap();
+        let risk = request.risk.clone().unwrap();
+        assert_eq!(risk.level, crate::access_risk::RiskLevel::High);
+
+        assert!(manager
+            .authorize_unattended(&request.request_id, "secret", None)
+            .await
+            .is_err());
+        assert_eq!(manager.get_pending_requests().await.len(), 1);
+
+        let code = manager
+            .generate_access_code(vec![Permission::ViewScreen])
+            .await
+            .unwrap();
+        let response = manager
+            .authorize_unattended(&request.request_id, "secret", Some(&code.code))
+            .await
+            .unwrap();
+        assert!(response.accepted);
+
+        let audit = log_manager.get_logs(None, None);
+        assert_eq!(audit[0].message, "Unattended access granted");
+        assert_eq!(audit[1].message, "Additional verification required");
+        assert_eq!(
+            audit[0].metadata.as_ref().unwrap()["risk"]["score"],
+            risk.score
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_passwords_raise_risk() {
+        let clock = crate::clock::TestClock::shared();
+        let manager = AccessControlManager::new().with_clock(clock.clone());
+        manager
+            .register_device("Host".to_string(), "linux".to_string(), "1.0".to_string())
+            .await
+            .unwrap();
+        manager.enable_unattended_access("secret").await.unwrap();
+
+        let request = manager
+            .handle_connection_request(
+                "viewer".to_string(),
+                "Viewer".to_string(),
+                vec![Permission::ViewScreen],
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            request.risk.unwrap().level,
+            crate::access_risk::RiskLevel::Medium
+        );
+        for _ in 0..2 {
+            assert!(manager
+                .authorize_unattended(&request.request_id, "wrong", None)
+                .await
+                .is_err());
+        }
+
+        let retry = manager
+            .handle_connection_request(
+                "viewer".to_string(),
+                "Viewer".to_string(),
+                vec![Permission::ViewScreen],
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(retry.risk.unwrap().requires_additional_verification());
+
+        // The failures age out on the manager's clock
+        clock.advance(Duration::from_secs(16 * 60));
+        let later = manager
+            .handle_connection_request(
+                "viewer".to_string(),
+                "Viewer".to_string(),
+                vec![Permission::ViewScreen],
+                None,
+            )
+            .await
+            .unwrap();
+        assert!(!later.risk.unwrap().requires_additional_verification());
+    }
 }
@@ -7,8 +7,9 @@
 use crate::access_control::{
     AccessCode, AccessControlManager, Permission, ACCESS_CODE_EXPIRATION_SECS,
 };
+use crate::timestamp::Timestamp;
 use proptest::prelude::*;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 /// Strategy for generating random permissions
 fn permission_strategy() -> impl Strategy<Value = Permission> {
@@ -18,6 +19,10 @@ fn permission_strategy() -> impl Strategy<Value = Permission> {
         Just(Permission::FileTransfer),
         Just(Permission::Clipboard),
         Just(Permission::AudioCapture),
+        Just(Permission::AppSharing),
+        Just(Permission::OpenUrl),
+        Just(Permission::PrivacyMode),
+        Just(Permission::SystemControl),
         Just(Permission::FullControl),
     ]
 }
@@ -44,7 +49,7 @@ proptest! {
         permissions in permissions_list_strategy()
     ) {
         // Create an access code with a specific elapsed time
-        let created_at = Instant::now() - Duration::from_secs(elapsed_secs);
+        let created_at = Timestamp::now() - Duration::from_secs(elapsed_secs);
 
         let code = AccessCode {
             code: "123456".to_string(),
@@ -70,7 +75,7 @@ proptest! {
         elapsed_secs in 0u64..700,
         permissions in permissions_list_strategy()
     ) {
-        let created_at = Instant::now() - Duration::from_secs(elapsed_secs);
+        let created_at = Timestamp::now() - Duration::from_secs(elapsed_secs);
 
         let code = AccessCode {
             code: "123456".to_string(),
@@ -105,7 +110,7 @@ proptest! {
         used in any::<bool>(),
         permissions in permissions_list_strategy()
     ) {
-        let created_at = Instant::now() - Duration::from_secs(elapsed_secs);
+        let created_at = Timestamp::now() - Duration::from_secs(elapsed_secs);
 
         let code = AccessCode {
             code: "123456".to_string(),
@@ -160,7 +165,7 @@ proptest! {
         let code = AccessCode {
             code: "123456".to_string(),
             device_id: "test-device".to_string(),
-            created_at: Instant::now(),
+            created_at: Timestamp::now(),
             expires_in: Duration::from_secs(ACCESS_CODE_EXPIRATION_SECS),
             permissions,
             used: false,
@@ -182,7 +187,7 @@ proptest! {
     ) {
         // Create code that is (600 + extra_secs) seconds old
         let elapsed = ACCESS_CODE_EXPIRATION_SECS + extra_secs;
-        let created_at = Instant::now() - Duration::from_secs(elapsed);
+        let created_at = Timestamp::now() - Duration::from_secs(elapsed);
 
         let code = AccessCode {
             code: "123456".to_string(),
@@ -207,12 +212,14 @@ mod unit_tests {
     #[test]
     fn test_permission_expand_full_control() {
         let permissions = Permission::expand_full_control();
-        assert_eq!(permissions.len(), 5);
+        assert_eq!(permissions.len(), 7);
         assert!(permissions.contains(&Permission::ViewScreen));
         assert!(permissions.contains(&Permission::InputControl));
         assert!(permissions.contains(&Permission::FileTransfer));
         assert!(permissions.contains(&Permission::Clipboard));
         assert!(permissions.contains(&Permission::AudioCapture));
+        assert!(permissions.contains(&Permission::AppSharing));
+        assert!(permissions.contains(&Permission::OpenUrl));
     }
 
     #[test]
@@ -221,7 +228,7 @@ mod unit_tests {
         let code = AccessCode {
             code: "123456".to_string(),
             device_id: "test-device".to_string(),
-            created_at: Instant::now() - Duration::from_secs(600),
+            created_at: Timestamp::now() - Duration::from_secs(600),
             expires_in: Duration::from_secs(600),
             permissions: vec![Permission::ViewScreen],
             used: false,
@@ -237,7 +244,7 @@ mod unit_tests {
         let code = AccessCode {
             code: "123456".to_string(),
             device_id: "test-device".to_string(),
-            created_at: Instant::now() - Duration::from_secs(599),
+            created_at: Timestamp::now() - Duration::from_secs(599),
             expires_in: Duration::from_secs(600),
             permissions: vec![Permission::ViewScreen],
             used: false,
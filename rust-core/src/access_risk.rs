@@ -0,0 +1,274 @@
+//! Access Request Risk Scoring
+//!
+//! Scores each incoming connection request from a few cheap signals: whether
+//! the device has connected before, whether it comes from a country not seen
+//! before, recent failed authentication attempts, and whether the request
+//! falls outside the host's configured access schedule. High-risk requests
+//! need more than the unattended password to get in.
+
+use crate::timestamp::Timestamp;
+use chrono::{DateTime, Datelike, Local, Timelike, Weekday};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+/// Failed attempts older than this no longer count
+const FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+const NEW_DEVICE_WEIGHT: u8 = 30;
+const NEW_COUNTRY_WEIGHT: u8 = 30;
+const FAILURE_WEIGHT: u8 = 15;
+const MAX_FAILURE_WEIGHT: u8 = 45;
+const OUTSIDE_SCHEDULE_WEIGHT: u8 = 25;
+
+/// Scores at or above this are medium risk
+pub const MEDIUM_RISK_SCORE: u8 = 30;
+/// Scores at or above this require additional verification
+pub const HIGH_RISK_SCORE: u8 = 60;
+
+/// Hours during which remote access is expected, in host local time
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccessSchedule {
+    pub days: Vec<Weekday>,
+    /// First allowed hour (0-23)
+    pub start_hour: u32,
+    /// Hour at which access ends (1-24); may be before `start_hour` to span
+    /// midnight
+    pub end_hour: u32,
+}
+
+impl AccessSchedule {
+    /// Whether `time` falls within the schedule
+    ///
+    /// For overnight windows the day is the one the window started on.
+    pub fn allows(&self, time: DateTime<Local>) -> bool {
+        let hour = time.hour();
+        if self.start_hour <= self.end_hour {
+            self.days.contains(&time.weekday()) && hour >= self.start_hour && hour < self.end_hour
+        } else if hour >= self.start_hour {
+            self.days.contains(&time.weekday())
+        } else {
+            hour < self.end_hour && self.days.contains(&time.weekday().pred())
+        }
+    }
+}
+
+impl Default for AccessSchedule {
+    /// Weekdays, 08:00 to 20:00
+    fn default() -> Self {
+        Self {
+            days: vec![
+                Weekday::Mon,
+                Weekday::Tue,
+                Weekday::Wed,
+                Weekday::Thu,
+                Weekday::Fri,
+            ],
+            start_hour: 8,
+            end_hour: 20,
+        }
+    }
+}
+
+/// Signal that contributed to a risk score
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RiskSignal {
+    /// Device never authorized before
+    NewDevice,
+    /// Request from a country not seen for any accepted connection
+    NewCountry(String),
+    /// Failed authentication attempts within the last 15 minutes
+    RepeatedFailures(u32),
+    /// Request outside the access schedule
+    OutsideSchedule,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+}
+
+/// Risk score (0-100) attached to a connection request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RiskAssessment {
+    pub score: u8,
+    pub level: RiskLevel,
+    pub signals: Vec<RiskSignal>,
+}
+
+impl RiskAssessment {
+    /// Whether the unattended password alone is not enough
+    pub fn requires_additional_verification(&self) -> bool {
+        self.level == RiskLevel::High
+    }
+}
+
+/// Tracks the history risk signals are computed from
+#[derive(Debug, Default)]
+pub struct RiskScorer {
+    schedule: Option<AccessSchedule>,
+    known_countries: HashSet<String>,
+    failures: HashMap<String, Vec<Timestamp>>,
+}
+
+impl RiskScorer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the expected access hours; `None` disables the schedule signal
+    pub fn set_schedule(&mut self, schedule: Option<AccessSchedule>) {
+        self.schedule = schedule;
+    }
+
+    pub fn schedule(&self) -> Option<&AccessSchedule> {
+        self.schedule.as_ref()
+    }
+
+    /// Score a request from `device_id` made at `now`
+    pub fn assess(
+        &mut self,
+        device_id: &str,
+        known_device: bool,
+        country_code: Option<&str>,
+        now: &Timestamp,
+    ) -> RiskAssessment {
+        let mut signals = Vec::new();
+        let mut score: u32 = 0;
+
+        if !known_device {
+            signals.push(RiskSignal::NewDevice);
+            score += NEW_DEVICE_WEIGHT as u32;
+        }
+        // The first country ever seen establishes the baseline
+        if let Some(country) = country_code {
+            if !self.known_countries.is_empty() && !self.known_countries.contains(country) {
+                signals.push(RiskSignal::NewCountry(country.to_string()));
+                score += NEW_COUNTRY_WEIGHT as u32;
+            }
+        }
+        let failures = self.recent_failures(device_id, now);
+        if failures > 0 {
+            signals.push(RiskSignal::RepeatedFailures(failures));
+            score += (failures * FAILURE_WEIGHT as u32).min(MAX_FAILURE_WEIGHT as u32);
+        }
+        let local = now.wall_clock().with_timezone(&Local);
+        if self.schedule.as_ref().is_some_and(|s| !s.allows(local)) {
+            signals.push(RiskSignal::OutsideSchedule);
+            score += OUTSIDE_SCHEDULE_WEIGHT as u32;
+        }
+
+        let score = score.min(100) as u8;
+        let level = if score >= HIGH_RISK_SCORE {
+            RiskLevel::High
+        } else if score >= MEDIUM_RISK_SCORE {
+            RiskLevel::Medium
+        } else {
+            RiskLevel::Low
+        };
+        RiskAssessment {
+            score,
+            level,
+            signals,
+        }
+    }
+
+    /// Record a failed authentication attempt made at `at`
+    pub fn record_failure(&mut self, device_id: &str, at: Timestamp) {
+        self.failures
+            .entry(device_id.to_string())
+            .or_default()
+            .push(at);
+    }
+
+    /// Record an accepted connection, clearing the device's failures
+    pub fn record_success(&mut self, device_id: &str, country_code: Option<&str>) {
+        self.failures.remove(device_id);
+        if let Some(country) = country_code {
+            self.known_countries.insert(country.to_string());
+        }
+    }
+
+    fn recent_failures(&mut self, device_id: &str, now: &Timestamp) -> u32 {
+        let Some(attempts) = self.failures.get_mut(device_id) else {
+            return 0;
+        };
+        attempts.retain(|at| at.elapsed_at(now) < FAILURE_WINDOW);
+        attempts.len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn local(day: u32, hour: u32) -> DateTime<Local> {
+        // 2024-01-01 is a Monday
+        Local.with_ymd_and_hms(2024, 1, day, hour, 0, 0).unwrap()
+    }
+
+    fn at(day: u32, hour: u32) -> Timestamp {
+        Timestamp::from_wall_clock(local(day, hour).with_timezone(&chrono::Utc))
+    }
+
+    #[test]
+    fn test_access_schedule() {
+        let office = AccessSchedule::default();
+        assert!(office.allows(local(1, 9)));
+        assert!(!office.allows(local(1, 22)));
+        assert!(!office.allows(local(6, 10)));
+
+        let overnight = AccessSchedule {
+            days: vec![Weekday::Fri],
+            start_hour: 22,
+            end_hour: 6,
+        };
+        assert!(overnight.allows(local(5, 23)));
+        assert!(overnight.allows(local(6, 3)));
+        assert!(!overnight.allows(local(5, 3)));
+    }
+
+    #[test]
+    fn test_signals_combine_into_score() {
+        let mut scorer = RiskScorer::new();
+        scorer.record_success("laptop", Some("DE"));
+
+        let known = scorer.assess("laptop", true, Some("DE"), &at(1, 10));
+        assert_eq!(known.score, 0);
+        assert_eq!(known.level, RiskLevel::Low);
+
+        scorer.set_schedule(Some(AccessSchedule::default()));
+        scorer.record_failure("phone", at(6, 3));
+        scorer.record_failure("phone", at(6, 3));
+        let risky = scorer.assess("phone", false, Some("BR"), &at(6, 3));
+        assert_eq!(
+            risky.signals,
+            vec![
+                RiskSignal::NewDevice,
+                RiskSignal::NewCountry("BR".to_string()),
+                RiskSignal::RepeatedFailures(2),
+                RiskSignal::OutsideSchedule,
+            ]
+        );
+        assert_eq!(risky.score, 100);
+        assert!(risky.requires_additional_verification());
+
+        scorer.record_success("phone", Some("BR"));
+        let after = scorer.assess("phone", true, Some("BR"), &at(1, 10));
+        assert_eq!(after.level, RiskLevel::Low);
+    }
+
+    #[test]
+    fn test_failures_expire_on_request_time() {
+        let mut scorer = RiskScorer::new();
+        scorer.record_failure("phone", at(1, 9));
+        let soon = scorer.assess("phone", true, None, &at(1, 9));
+        assert_eq!(soon.signals, vec![RiskSignal::RepeatedFailures(1)]);
+
+        let later = scorer.assess("phone", true, None, &at(1, 10));
+        assert!(later.signals.is_empty());
+    }
+}
@@ -0,0 +1,404 @@
+//! Persistent Access Control Store
+//!
+//! SQLite-backed storage for the device registration, access codes, device
+//! authorizations and pending connection requests held by
+//! `AccessControlManager`. The schema is versioned through `PRAGMA
+//! user_version` and upgraded by the ordered `MIGRATIONS` list on open.
+//!
+//! A database that cannot be opened, fails `integrity_check`, or cannot be
+//! migrated is moved aside and replaced with an empty one, so a corrupted
+//! file never prevents the host from starting.
+
+use crate::access_control::{
+    AccessCode, ConnectionRequest, DeviceAuthorization, DeviceRegistration,
+};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, ErrorCode, OptionalExtension};
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// File name of the access control database
+pub const ACCESS_STORE_FILE_NAME: &str = "access_control.db";
+
+/// Schema migrations; entry `n` upgrades the schema from version `n` to `n + 1`
+const MIGRATIONS: &[&str] = &[
+    // v1: initial schema
+    "CREATE TABLE device_registration (
+         id INTEGER PRIMARY KEY CHECK (id = 1),
+         data TEXT NOT NULL
+     );
+     CREATE TABLE access_codes (
+         code TEXT PRIMARY KEY,
+         data TEXT NOT NULL
+     );
+     CREATE TABLE authorizations (
+         device_id TEXT PRIMARY KEY,
+         data TEXT NOT NULL
+     );
+     CREATE TABLE pending_requests (
+         request_id TEXT PRIMARY KEY,
+         data TEXT NOT NULL
+     );",
+];
+
+/// Everything loaded from the store at startup
+#[derive(Debug, Default)]
+pub struct AccessControlState {
+    pub registration: Option<DeviceRegistration>,
+    pub access_codes: Vec<AccessCode>,
+    pub authorizations: Vec<DeviceAuthorization>,
+    pub pending_requests: Vec<ConnectionRequest>,
+}
+
+/// SQLite store behind `AccessControlManager`
+pub struct AccessControlStore {
+    conn: Mutex<Connection>,
+}
+
+impl AccessControlStore {
+    /// Open (or create) the store at `path`, recovering from corruption
+    ///
+    /// Only a damaged file or one that is not a database is moved aside and
+    /// replaced; any other failure (locked, newer schema, no permission) is
+    /// returned so the existing data is kept.
+    pub fn open(path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        match Self::open_and_migrate(path) {
+            Ok(conn) => Ok(Self {
+                conn: Mutex::new(conn),
+            }),
+            Err(e) if !is_corruption(&e) => Err(e),
+            Err(e) => {
+                let backup = corrupt_backup_path(path);
+                tracing::warn!(
+                    "Access control store unusable ({:#}), moving it to {}",
+                    e,
+                    backup.display()
+                );
+                std::fs::rename(path, &backup)?;
+                for suffix in ["-wal", "-shm"] {
+                    let _ = std::fs::remove_file(sidecar_path(path, suffix));
+                }
+                Ok(Self {
+                    conn: Mutex::new(Self::open_and_migrate(path)?),
+                })
+            }
+        }
+    }
+
+    /// Open a throwaway in-memory store
+    pub fn open_in_memory() -> Result<Self> {
+        let mut conn = Connection::open_in_memory()?;
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn open_and_migrate(path: &Path) -> Result<Connection> {
+        create_private(path)?;
+        let mut conn = Connection::open(path)?;
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+
+        let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+        if integrity != "ok" {
+            return Err(StoreCorrupt(integrity).into());
+        }
+
+        migrate(&mut conn)?;
+        Ok(conn)
+    }
+
+    /// Current schema version
+    pub fn schema_version(&self) -> Result<usize> {
+        let conn = self.lock()?;
+        schema_version(&conn)
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, Connection>> {
+        self.conn
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Access control store lock poisoned"))
+    }
+
+    fn put(&self, sql: &str, key: &str, value: &impl serde::Serialize) -> Result<()> {
+        let data = serde_json::to_string(value)?;
+        self.lock()?.execute(sql, params![key, data])?;
+        Ok(())
+    }
+
+    fn load_all<T: DeserializeOwned>(&self, table: &str) -> Result<Vec<T>> {
+        let conn = self.lock()?;
+        let mut stmt = conn.prepare(&format!("SELECT data FROM {}", table))?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+
+        let mut items = Vec::new();
+        for data in rows {
+            match serde_json::from_str(&data?) {
+                Ok(item) => items.push(item),
+                Err(e) => tracing::warn!("Skipping unreadable row in {}: {}", table, e),
+            }
+        }
+        Ok(items)
+    }
+
+    /// Load the full persisted state
+    pub fn load(&self) -> Result<AccessControlState> {
+        let registration = self
+            .lock()?
+            .query_row(
+                "SELECT data FROM device_registration WHERE id = 1",
+                [],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .and_then(|data| serde_json::from_str(&data).ok());
+
+        Ok(AccessControlState {
+            registration,
+            access_codes: self.load_all("access_codes")?,
+            authorizations: self.load_all("authorizations")?,
+            pending_requests: self.load_all("pending_requests")?,
+        })
+    }
+
+    /// Save the device registration
+    ///
+    /// The unattended password hash is never written here; it belongs in the
+    /// secrets store.
+    pub fn save_registration(&self, registration: &DeviceRegistration) -> Result<()> {
+        let mut registration = registration.clone();
+        registration.unattended_password_hash = None;
+        let data = serde_json::to_string(&registration)?;
+        self.lock()?.execute(
+            "INSERT OR REPLACE INTO device_registration (id, data) VALUES (1, ?1)",
+            params![data],
+        )?;
+        Ok(())
+    }
+
+    pub fn save_access_code(&self, code: &AccessCode) -> Result<()> {
+        self.put(
+            "INSERT OR REPLACE INTO access_codes (code, data) VALUES (?1, ?2)",
+            &code.code,
+            code,
+        )
+    }
+
+    pub fn delete_access_code(&self, code: &str) -> Result<()> {
+        self.lock()?
+            .execute("DELETE FROM access_codes WHERE code = ?1", params![code])?;
+        Ok(())
+    }
+
+    pub fn save_authorization(&self, authorization: &DeviceAuthorization) -> Result<()> {
+        self.put(
+            "INSERT OR REPLACE INTO authorizations (device_id, data) VALUES (?1, ?2)",
+            &authorization.device_id,
+            authorization,
+        )
+    }
+
+    pub fn save_request(&self, request: &ConnectionRequest) -> Result<()> {
+        self.put(
+            "INSERT OR REPLACE INTO pending_requests (request_id, data) VALUES (?1, ?2)",
+            &request.request_id,
+            request,
+        )
+    }
+
+    pub fn delete_request(&self, request_id: &str) -> Result<()> {
+        self.lock()?.execute(
+            "DELETE FROM pending_requests WHERE request_id = ?1",
+            params![request_id],
+        )?;
+        Ok(())
+    }
+}
+
+fn schema_version(conn: &Connection) -> Result<usize> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+/// Apply pending migrations, each in its own transaction
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let current = schema_version(conn)?;
+    if current > MIGRATIONS.len() {
+        return Err(anyhow::anyhow!(
+            "Access control store schema v{} is newer than supported v{}",
+            current,
+            MIGRATIONS.len()
+        ));
+    }
+
+    for (version, sql) in MIGRATIONS.iter().enumerate().skip(current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)
+            .with_context(|| format!("Migration to schema v{} failed", version + 1))?;
+        tx.pragma_update(None, "user_version", (version + 1) as i64)?;
+        tx.commit()?;
+        tracing::info!("Access control store migrated to schema v{}", version + 1);
+    }
+    Ok(())
+}
+
+/// `PRAGMA integrity_check` reported damage
+#[derive(Debug, thiserror::Error)]
+#[error("Integrity check failed: {0}")]
+struct StoreCorrupt(String);
+
+/// Whether opening failed because the file is damaged or not a database
+fn is_corruption(error: &anyhow::Error) -> bool {
+    if error.downcast_ref::<StoreCorrupt>().is_some() {
+        return true;
+    }
+    matches!(
+        error.downcast_ref::<rusqlite::Error>(),
+        Some(rusqlite::Error::SqliteFailure(e, _))
+            if matches!(e.code, ErrorCode::DatabaseCorrupt | ErrorCode::NotADatabase)
+    )
+}
+
+/// Create the database and its WAL sidecars readable by the owner only,
+/// and tighten files left by older versions
+///
+/// SQLite would otherwise create them with the umask's mode, exposing the
+/// access codes and authorizations to other local users.
+fn create_private(path: &Path) -> Result<()> {
+    for file in [
+        path.to_path_buf(),
+        sidecar_path(path, "-wal"),
+        sidecar_path(path, "-shm"),
+    ] {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let handle = options
+            .open(&file)
+            .with_context(|| format!("Failed to create {}", file.display()))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            handle.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+        }
+        drop(handle);
+    }
+    Ok(())
+}
+
+fn sidecar_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn corrupt_backup_path(path: &Path) -> PathBuf {
+    sidecar_path(
+        path,
+        &format!(".corrupt-{}", chrono::Utc::now().format("%Y%m%d%H%M%S")),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("cec-access-{}", uuid::Uuid::new_v4()))
+            .join(ACCESS_STORE_FILE_NAME)
+    }
+
+    #[test]
+    fn test_migrations_applied_once() {
+        let path = temp_path();
+        let store = AccessControlStore::open(&path).unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len());
+        drop(store);
+
+        let store = AccessControlStore::open(&path).unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_corrupt_database_recovered() {
+        let path = temp_path();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, b"definitely not a sqlite database").unwrap();
+
+        let store = AccessControlStore::open(&path).unwrap();
+        assert_eq!(store.schema_version().unwrap(), MIGRATIONS.len());
+        assert!(store.load().unwrap().registration.is_none());
+
+        let backups = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .contains(".corrupt-")
+            })
+            .count();
+        assert_eq!(backups, 1);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_database_files_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_path();
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(sidecar_path(&path, "-shm"), b"").unwrap();
+        std::fs::set_permissions(
+            sidecar_path(&path, "-shm"),
+            std::fs::Permissions::from_mode(0o644),
+        )
+        .unwrap();
+
+        let store = AccessControlStore::open(&path).unwrap();
+        for file in [
+            path.clone(),
+            sidecar_path(&path, "-wal"),
+            sidecar_path(&path, "-shm"),
+        ] {
+            let mode = std::fs::metadata(&file).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600, "{}", file.display());
+        }
+        drop(store);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_newer_schema_is_not_discarded() {
+        let path = temp_path();
+        drop(AccessControlStore::open(&path).unwrap());
+        let conn = Connection::open(&path).unwrap();
+        conn.pragma_update(None, "user_version", (MIGRATIONS.len() + 1) as i64)
+            .unwrap();
+        drop(conn);
+
+        let err = AccessControlStore::open(&path).err().unwrap();
+        assert!(err.to_string().contains("newer than supported"));
+        let names: Vec<_> = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert!(names.iter().all(|name| !name.contains(".corrupt-")));
+        assert!(path.exists());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,2954 @@
+//! Audio Capture Backends
+//!
+//! Reads system audio or a microphone for `AudioCapturer`:
+//!
+//! - Windows: WASAPI shared-mode capture of microphones, and loopback
+//!   capture of every speaker endpoint.
+//! - macOS: Core Audio input devices, plus a global process tap listed as
+//!   "System audio" on macOS 14.2 and later.
+//! - Linux: ALSA PCMs, including `snd-aloop` loopback devices, and the
+//!   default sink's monitor through PulseAudio or PipeWire's PulseAudio
+//!   server, listed as "System audio".
+//!
+//! Elsewhere listing and opening devices fail with an unsupported-platform
+//! error.
+//!
+//! A single application's audio is captured where the OS can isolate it:
+//! WASAPI process loopback on Windows 10 2004 and later, a Core Audio
+//! process tap on macOS 14.2 and later, and on Linux a PulseAudio (or
+//! PipeWire's PulseAudio server) monitor stream of each sink input the
+//! process plays. The Windows and Linux capture includes the process's
+//! children, as browsers play audio from a helper process.
+//!
+//! The capture thread reads 20 ms frames. While the selected device cannot
+//! be opened it sends silence, so the outgoing audio track keeps its timing,
+//! and retries the device every second.
+
+use crate::screen_capture::{AudioCaptureOptions, AudioFrame};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, RwLock};
+
+/// Id under which the platform default device is metered
+pub const DEFAULT_AUDIO_DEVICE: &str = "default";
+
+/// Length of one captured frame
+pub const AUDIO_FRAME_DURATION: Duration = Duration::from_millis(20);
+
+/// How often a device that failed to open is retried
+const REOPEN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long a read waits past a frame's length for audio to arrive
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+const READ_SLACK: Duration = Duration::from_millis(20);
+
+/// Most captured audio held back before the oldest is dropped
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+const MAX_PENDING: Duration = Duration::from_millis(200);
+
+/// Level reported for digital silence, the floor of 16-bit audio
+pub const SILENCE_DBFS: f32 = -96.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AudioDeviceKind {
+    Microphone,
+    /// Captures what the system plays
+    Loopback,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioDeviceInfo {
+    /// Platform device name, passed back to `AudioCapturer::select_device`
+    pub id: String,
+    pub name: String,
+    pub kind: AudioDeviceKind,
+    pub is_default: bool,
+}
+
+/// Loudness of one frame, in dB relative to full scale
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AudioLevel {
+    pub peak_dbfs: f32,
+    pub rms_dbfs: f32,
+}
+
+impl AudioLevel {
+    pub fn measure(samples: &[i16]) -> Self {
+        let to_dbfs = |amplitude: f64| {
+            if amplitude <= 0.0 {
+                SILENCE_DBFS
+            } else {
+                ((20.0 * (amplitude / 32768.0).log10()) as f32).max(SILENCE_DBFS)
+            }
+        };
+        let peak = samples
+            .iter()
+            .map(|&s| (s as i32).unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        let mean_square = if samples.is_empty() {
+            0.0
+        } else {
+            samples.iter().map(|&s| (s as f64).powi(2)).sum::<f64>() / samples.len() as f64
+        };
+        Self {
+            peak_dbfs: to_dbfs(peak as f64),
+            rms_dbfs: to_dbfs(mean_square.sqrt()),
+        }
+    }
+}
+
+/// An open capture device
+pub trait AudioSource: Send {
+    /// Fill `buffer` with interleaved samples, blocking until it is full
+    fn read(&mut self, buffer: &mut [i16]) -> Result<()>;
+}
+
+/// Device listing and opening; replaceable for tests and custom sources
+pub trait AudioBackend: Send + Sync {
+    fn devices(&self) -> Result<Vec<AudioDeviceInfo>>;
+
+    /// Open a device, or the platform default for `None`
+    fn open(
+        &self,
+        device_id: Option<&str>,
+        sample_rate: u32,
+        channels: u8,
+    ) -> Result<Box<dyn AudioSource>>;
+
+    /// Whether `open_process` can isolate one application's audio
+    fn process_capture_supported(&self) -> bool {
+        false
+    }
+
+    /// Open what one process, and where supported its children, plays
+    fn open_process(
+        &self,
+        process_id: u32,
+        sample_rate: u32,
+        channels: u8,
+    ) -> Result<Box<dyn AudioSource>> {
+        let _ = (sample_rate, channels);
+        Err(anyhow::anyhow!(
+            "Cannot capture the audio of process {} with this audio backend",
+            process_id
+        ))
+    }
+}
+
+/// The OS audio stack
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PlatformAudioBackend;
+
+impl AudioBackend for PlatformAudioBackend {
+    fn devices(&self) -> Result<Vec<AudioDeviceInfo>> {
+        list_audio_devices()
+    }
+
+    fn open(
+        &self,
+        device_id: Option<&str>,
+        sample_rate: u32,
+        channels: u8,
+    ) -> Result<Box<dyn AudioSource>> {
+        #[cfg(target_os = "windows")]
+        {
+            Ok(Box::new(wasapi::WasapiSource::open(
+                device_id,
+                sample_rate,
+                channels,
+            )?))
+        }
+        #[cfg(target_os = "macos")]
+        {
+            Ok(Box::new(coreaudio::QueueSource::open(
+                device_id,
+                sample_rate,
+                channels,
+            )?))
+        }
+        #[cfg(target_os = "linux")]
+        {
+            match device_id.and_then(|id| id.strip_prefix(pulse::DEVICE_PREFIX)) {
+                Some(name) => Ok(Box::new(pulse::PulseSource::open_source(
+                    name,
+                    sample_rate,
+                    channels,
+                )?)),
+                None => Ok(Box::new(alsa::AlsaSource::open(
+                    device_id.unwrap_or(DEFAULT_AUDIO_DEVICE),
+                    sample_rate,
+                    channels,
+                )?)),
+            }
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            let _ = (device_id, sample_rate, channels);
+            Err(unsupported())
+        }
+    }
+
+    fn process_capture_supported(&self) -> bool {
+        process_capture_available()
+    }
+
+    fn open_process(
+        &self,
+        process_id: u32,
+        sample_rate: u32,
+        channels: u8,
+    ) -> Result<Box<dyn AudioSource>> {
+        #[cfg(target_os = "windows")]
+        {
+            Ok(Box::new(wasapi::WasapiSource::open_process(
+                process_id,
+                sample_rate,
+                channels,
+            )?))
+        }
+        #[cfg(target_os = "macos")]
+        {
+            Ok(Box::new(coreaudio::QueueSource::open_process(
+                process_id,
+                sample_rate,
+                channels,
+            )?))
+        }
+        #[cfg(target_os = "linux")]
+        {
+            Ok(Box::new(pulse::PulseSource::open_process(
+                process_id,
+                sample_rate,
+                channels,
+            )?))
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            let _ = (process_id, sample_rate, channels);
+            Err(anyhow::anyhow!(
+                "Per-application audio capture is not supported on {}",
+                std::env::consts::OS
+            ))
+        }
+    }
+}
+
+/// Whether this OS can capture a single application's audio
+pub fn process_capture_available() -> bool {
+    #[cfg(target_os = "windows")]
+    {
+        wasapi::process_loopback_available()
+    }
+    #[cfg(target_os = "macos")]
+    {
+        coreaudio::process_taps_available()
+    }
+    #[cfg(target_os = "linux")]
+    {
+        pulse::available()
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        false
+    }
+}
+
+/// Capture devices of this machine, default first
+pub fn list_audio_devices() -> Result<Vec<AudioDeviceInfo>> {
+    let mut devices = platform_devices()?;
+    devices.sort_by_key(|d| !d.is_default);
+    Ok(devices)
+}
+
+fn platform_devices() -> Result<Vec<AudioDeviceInfo>> {
+    #[cfg(target_os = "windows")]
+    {
+        wasapi::devices()
+    }
+    #[cfg(target_os = "macos")]
+    {
+        coreaudio::devices()
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let mut devices = alsa::devices()?;
+        devices.extend(pulse::monitor_device());
+        Ok(devices)
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        Err(unsupported())
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn unsupported() -> anyhow::Error {
+    anyhow::anyhow!("Audio capture is not supported on {}", std::env::consts::OS)
+}
+
+/// State shared between `AudioCapturer` and its capture thread
+pub(crate) struct AudioThreadContext {
+    pub capturing: Arc<RwLock<bool>>,
+    pub options: Arc<RwLock<AudioCaptureOptions>>,
+    pub device: Arc<RwLock<Option<String>>>,
+    /// Capture this process's audio instead of `device`
+    pub process_id: Option<u32>,
+    pub backend: Arc<dyn AudioBackend>,
+    pub frame_counter: Arc<std::sync::atomic::AtomicU64>,
+    pub levels: Arc<Mutex<HashMap<String, AudioLevel>>>,
+    pub last_error: Arc<Mutex<Option<String>>>,
+    pub sender: mpsc::UnboundedSender<AudioFrame>,
+}
+
+pub(crate) fn spawn_audio_thread(context: AudioThreadContext) -> Result<()> {
+    std::thread::Builder::new()
+        .name("cec-audio-capture".to_string())
+        .spawn(move || audio_loop(context))?;
+    Ok(())
+}
+
+/// What the open source was opened for
+#[derive(Clone, PartialEq)]
+struct Opened {
+    device: Option<String>,
+    process_id: Option<u32>,
+    sample_rate: u32,
+    channels: u8,
+}
+
+fn audio_loop(context: AudioThreadContext) {
+    let mut source: Option<(Opened, Box<dyn AudioSource>)> = None;
+    // What was last opened, or failed, and when
+    let mut last_attempt: Option<(Opened, Instant)> = None;
+
+    while *context.capturing.blocking_read() {
+        let started = Instant::now();
+        let options = context.options.blocking_read().clone();
+        let wanted = Opened {
+            device: context.device.blocking_read().clone(),
+            process_id: context.process_id,
+            sample_rate: options.sample_rate.max(1),
+            channels: options.channels.max(1),
+        };
+        let meter_key = match (wanted.process_id, &wanted.device) {
+            (Some(process_id), _) => format!("process:{}", process_id),
+            (None, Some(device)) => device.clone(),
+            (None, None) => DEFAULT_AUDIO_DEVICE.to_string(),
+        };
+
+        if source.as_ref().is_some_and(|(opened, _)| *opened != wanted) {
+            source = None;
+        }
+        // A different device or format is tried straight away
+        let retry_due = last_attempt
+            .as_ref()
+            .is_none_or(|(tried, at)| *tried != wanted || at.elapsed() >= REOPEN_INTERVAL);
+        if source.is_none() && retry_due {
+            last_attempt = Some((wanted.clone(), Instant::now()));
+            let opened = match wanted.process_id {
+                Some(process_id) => {
+                    context
+                        .backend
+                        .open_process(process_id, wanted.sample_rate, wanted.channels)
+                }
+                None => context.backend.open(
+                    wanted.device.as_deref(),
+                    wanted.sample_rate,
+                    wanted.channels,
+                ),
+            };
+            match opened {
+                Ok(opened) => {
+                    tracing::info!("Opened audio device {}", meter_key);
+                    *lock(&context.last_error) = None;
+                    source = Some((wanted, opened));
+                }
+                Err(e) => {
+                    tracing::warn!("Cannot open audio device {}: {}", meter_key, e);
+                    *lock(&context.last_error) = Some(e.to_string());
+                }
+            }
+        }
+
+        let samples_per_channel =
+            (options.sample_rate.max(1) as u128 * AUDIO_FRAME_DURATION.as_millis() / 1000) as usize;
+        let mut data = vec![0i16; samples_per_channel * options.channels.max(1) as usize];
+        let mut live = false;
+        if let Some((_, device)) = source.as_mut() {
+            match device.read(&mut data) {
+                Ok(()) => {
+                    live = true;
+                    lock(&context.levels).insert(meter_key.clone(), AudioLevel::measure(&data));
+                }
+                Err(e) => {
+                    tracing::warn!("Audio device {} failed: {}", meter_key, e);
+                    *lock(&context.last_error) = Some(e.to_string());
+                    lock(&context.levels).remove(&meter_key);
+                    data.fill(0);
+                    last_attempt = source.take().map(|(opened, _)| (opened, Instant::now()));
+                }
+            }
+        }
+
+        let frame = AudioFrame {
+            id: context
+                .frame_counter
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+                + 1,
+            timestamp: std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_millis() as u64,
+            sample_rate: options.sample_rate,
+            channels: options.channels,
+            data,
+        };
+        if context.sender.send(frame).is_err() {
+            break;
+        }
+        // A live device paces the loop by blocking in `read`
+        if !live {
+            std::thread::sleep(AUDIO_FRAME_DURATION.saturating_sub(started.elapsed()));
+        }
+    }
+}
+
+/// Hand out buffered samples, padding with silence while nothing plays
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+fn take_pending(pending: &mut VecDeque<i16>, buffer: &mut [i16]) {
+    let available = pending.len().min(buffer.len());
+    for (slot, sample) in buffer.iter_mut().zip(pending.drain(..available)) {
+        *slot = sample;
+    }
+    buffer[available..].fill(0);
+}
+
+/// Drop the oldest samples beyond `MAX_PENDING`, so latency cannot build up
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+fn trim_pending(pending: &mut VecDeque<i16>, sample_rate: u32, channels: usize) {
+    let limit = (sample_rate as u128 * MAX_PENDING.as_millis() / 1000) as usize * channels;
+    if pending.len() > limit {
+        let excess = pending.len() - limit;
+        pending.drain(..excess);
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[cfg(target_os = "linux")]
+mod alsa {
+    use super::{AudioDeviceInfo, AudioDeviceKind, AudioSource, DEFAULT_AUDIO_DEVICE};
+    use anyhow::Result;
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+
+    #[repr(C)]
+    struct SndPcm {
+        _private: [u8; 0],
+    }
+
+    const SND_PCM_STREAM_CAPTURE: c_int = 1;
+    const SND_PCM_NONBLOCK: c_int = 1;
+    const SND_PCM_FORMAT_S16_LE: c_int = 2;
+    const SND_PCM_ACCESS_RW_INTERLEAVED: c_int = 3;
+    /// Device buffer; long enough to ride out scheduling hiccups
+    const LATENCY_US: c_uint = 100_000;
+
+    extern "C" {
+        fn snd_pcm_open(
+            pcm: *mut *mut SndPcm,
+            name: *const c_char,
+            stream: c_int,
+            mode: c_int,
+        ) -> c_int;
+        fn snd_pcm_nonblock(pcm: *mut SndPcm, nonblock: c_int) -> c_int;
+        fn snd_pcm_set_params(
+            pcm: *mut SndPcm,
+            format: c_int,
+            access: c_int,
+            channels: c_uint,
+            rate: c_uint,
+            soft_resample: c_int,
+            latency: c_uint,
+        ) -> c_int;
+        fn snd_pcm_readi(pcm: *mut SndPcm, buffer: *mut c_void, frames: c_ulong) -> c_long;
+        fn snd_pcm_recover(pcm: *mut SndPcm, err: c_int, silent: c_int) -> c_int;
+        fn snd_pcm_close(pcm: *mut SndPcm) -> c_int;
+        fn snd_strerror(errnum: c_int) -> *const c_char;
+        fn snd_device_name_hint(
+            card: c_int,
+            iface: *const c_char,
+            hints: *mut *mut *mut c_void,
+        ) -> c_int;
+        fn snd_device_name_get_hint(hint: *const c_void, id: *const c_char) -> *mut c_char;
+        fn snd_device_name_free_hint(hints: *mut *mut c_void) -> c_int;
+        fn free(ptr: *mut c_void);
+    }
+
+    fn error(context: &str, code: c_int) -> anyhow::Error {
+        // SAFETY: snd_strerror returns a static string for any code
+        let message = unsafe { CStr::from_ptr(snd_strerror(code)) };
+        anyhow::anyhow!("{}: {}", context, message.to_string_lossy())
+    }
+
+    pub(super) struct AlsaSource {
+        pcm: *mut SndPcm,
+        channels: usize,
+    }
+
+    // SAFETY: the handle is only used by the thread owning the source
+    unsafe impl Send for AlsaSource {}
+
+    impl AlsaSource {
+        pub(super) fn open(device: &str, sample_rate: u32, channels: u8) -> Result<Self> {
+            let name = CString::new(device)?;
+            let mut pcm = std::ptr::null_mut();
+            // SAFETY: `pcm` is only used after a successful open, and closed
+            // by `Drop` from then on. Opening non-blocking keeps a device busy
+            // with another client from hanging the capture thread.
+            unsafe {
+                let code = snd_pcm_open(
+                    &mut pcm,
+                    name.as_ptr(),
+                    SND_PCM_STREAM_CAPTURE,
+                    SND_PCM_NONBLOCK,
+                );
+                if code < 0 {
+                    return Err(error(&format!("Cannot open {}", device), code));
+                }
+                let source = Self {
+                    pcm,
+                    channels: channels as usize,
+                };
+                let code = snd_pcm_set_params(
+                    pcm,
+                    SND_PCM_FORMAT_S16_LE,
+                    SND_PCM_ACCESS_RW_INTERLEAVED,
+                    channels as c_uint,
+                    sample_rate,
+                    1,
+                    LATENCY_US,
+                );
+                if code < 0 {
+                    return Err(error(
+                        &format!("{} rejected {} Hz x{}", device, sample_rate, channels),
+                        code,
+                    ));
+                }
+                snd_pcm_nonblock(pcm, 0);
+                Ok(source)
+            }
+        }
+    }
+
+    impl AudioSource for AlsaSource {
+        fn read(&mut self, buffer: &mut [i16]) -> Result<()> {
+            let frames = buffer.len() / self.channels.max(1);
+            let mut done = 0;
+            while done < frames {
+                // SAFETY: the buffer holds `frames` interleaved frames; reads
+                // start at the first unfilled one
+                let read = unsafe {
+                    snd_pcm_readi(
+                        self.pcm,
+                        buffer[done * self.channels..].as_mut_ptr() as *mut c_void,
+                        (frames - done) as c_ulong,
+                    )
+                };
+                if read < 0 {
+                    // Overruns (the thread fell behind) and suspends recover
+                    // by restarting the stream
+                    // SAFETY: the handle is open
+                    let code = unsafe { snd_pcm_recover(self.pcm, read as c_int, 1) };
+                    if code < 0 {
+                        return Err(error("Audio read failed", code));
+                    }
+                    continue;
+                }
+                done += read as usize;
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for AlsaSource {
+        fn drop(&mut self) {
+            // SAFETY: opened in `open`
+            unsafe {
+                snd_pcm_close(self.pcm);
+            }
+        }
+    }
+
+    /// Take ownership of a hint string
+    fn hint(entry: *const c_void, id: &CStr) -> Option<String> {
+        // SAFETY: the entry comes from snd_device_name_hint; the returned
+        // string is malloc'ed and freed here
+        unsafe {
+            let value = snd_device_name_get_hint(entry, id.as_ptr());
+            if value.is_null() {
+                return None;
+            }
+            let text = CStr::from_ptr(value).to_string_lossy().into_owned();
+            free(value as *mut c_void);
+            Some(text)
+        }
+    }
+
+    pub(super) fn devices() -> Result<Vec<AudioDeviceInfo>> {
+        let mut hints = std::ptr::null_mut();
+        // SAFETY: the hint array is NULL-terminated and freed below
+        let code = unsafe { snd_device_name_hint(-1, c"pcm".as_ptr(), &mut hints) };
+        if code < 0 {
+            return Err(error("Cannot list audio devices", code));
+        }
+
+        let mut devices = Vec::new();
+        let mut cursor = hints;
+        // SAFETY: walks the array up to its NULL terminator
+        unsafe {
+            while !(*cursor).is_null() {
+                let entry = *cursor as *const c_void;
+                cursor = cursor.add(1);
+                // IOID is absent for devices that do both directions
+                if hint(entry, c"IOID").is_some_and(|io| io != "Input") {
+                    continue;
+                }
+                let Some(id) = hint(entry, c"NAME").filter(|n| n != "null") else {
+                    continue;
+                };
+                let description = hint(entry, c"DESC").unwrap_or_else(|| id.clone());
+                let loopback =
+                    id.contains("CARD=Loopback") || description.to_lowercase().contains("monitor");
+                devices.push(AudioDeviceInfo {
+                    name: description.replace('\n', " "),
+                    kind: if loopback {
+                        AudioDeviceKind::Loopback
+                    } else {
+                        AudioDeviceKind::Microphone
+                    },
+                    is_default: id == DEFAULT_AUDIO_DEVICE,
+                    id,
+                });
+            }
+            snd_device_name_free_hint(hints);
+        }
+        Ok(devices)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod wasapi {
+    use super::{
+        take_pending, trim_pending, AudioDeviceInfo, AudioDeviceKind, AudioSource, READ_SLACK,
+    };
+    use anyhow::Result;
+    use std::collections::VecDeque;
+    use std::ffi::c_void;
+    use std::marker::PhantomData;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Condvar, Mutex};
+    use std::time::{Duration, Instant};
+
+    type Hresult = i32;
+    type Handle = *mut c_void;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, PartialEq, Eq)]
+    struct Guid {
+        data1: u32,
+        data2: u16,
+        data3: u16,
+        data4: [u8; 8],
+    }
+
+    const IID_IUNKNOWN: Guid = Guid {
+        data1: 0x0000_0000,
+        data2: 0x0000,
+        data3: 0x0000,
+        data4: [0xC0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46],
+    };
+    const IID_IAGILE_OBJECT: Guid = Guid {
+        data1: 0x94EA_2B94,
+        data2: 0xE9CC,
+        data3: 0x49E0,
+        data4: [0xC0, 0xFF, 0xEE, 0x64, 0xCA, 0x8F, 0x5B, 0x90],
+    };
+    const IID_IACTIVATE_AUDIO_INTERFACE_COMPLETION_HANDLER: Guid = Guid {
+        data1: 0x41D9_49AB,
+        data2: 0x9862,
+        data3: 0x444A,
+        data4: [0x80, 0xF6, 0xC2, 0x61, 0x33, 0x4D, 0xA5, 0xEB],
+    };
+    const IID_IAUDIO_CLIENT: Guid = Guid {
+        data1: 0x1CB9_AD4C,
+        data2: 0xDBFA,
+        data3: 0x4C32,
+        data4: [0xB1, 0x78, 0xC2, 0xF5, 0x68, 0xA7, 0x03, 0xB2],
+    };
+    const IID_IAUDIO_CAPTURE_CLIENT: Guid = Guid {
+        data1: 0xC8AD_BD64,
+        data2: 0xE71E,
+        data3: 0x48A0,
+        data4: [0xA4, 0xDE, 0x18, 0x5C, 0x39, 0x5C, 0xD3, 0x17],
+    };
+    const CLSID_MM_DEVICE_ENUMERATOR: Guid = Guid {
+        data1: 0xBCDE_0395,
+        data2: 0xE52F,
+        data3: 0x467C,
+        data4: [0x8E, 0x3D, 0xC4, 0x57, 0x92, 0x91, 0x69, 0x2E],
+    };
+    const IID_IMM_DEVICE_ENUMERATOR: Guid = Guid {
+        data1: 0xA956_64D2,
+        data2: 0x9614,
+        data3: 0x4F35,
+        data4: [0xA7, 0x46, 0xDE, 0x8D, 0xB6, 0x36, 0x17, 0xE6],
+    };
+    const PKEY_DEVICE_FRIENDLY_NAME: PropertyKey = PropertyKey {
+        format_id: Guid {
+            data1: 0xA45C_254E,
+            data2: 0xDF1C,
+            data3: 0x4EFD,
+            data4: [0x80, 0x20, 0x67, 0xD1, 0x46, 0xA8, 0x50, 0xE0],
+        },
+        property_id: 14,
+    };
+
+    const S_OK: Hresult = 0;
+    const E_NOINTERFACE: Hresult = 0x8000_4002_u32 as i32;
+    const COINIT_MULTITHREADED: u32 = 0x0;
+    const VT_LPWSTR: u16 = 31;
+    const VT_BLOB: u16 = 65;
+    const CLSCTX_ALL: u32 = 0x17;
+    const STGM_READ: u32 = 0;
+    const E_RENDER: u32 = 0;
+    const E_CAPTURE: u32 = 1;
+    const E_CONSOLE: u32 = 0;
+    const DEVICE_STATE_ACTIVE: u32 = 0x1;
+    const AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK: u32 = 1;
+    const PROCESS_LOOPBACK_MODE_INCLUDE_TARGET_PROCESS_TREE: u32 = 0;
+    const AUDCLNT_SHAREMODE_SHARED: u32 = 0;
+    const AUDCLNT_STREAMFLAGS_LOOPBACK: u32 = 0x0002_0000;
+    const AUDCLNT_STREAMFLAGS_EVENTCALLBACK: u32 = 0x0004_0000;
+    const AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY: u32 = 0x0800_0000;
+    const AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM: u32 = 0x8000_0000;
+    const AUDCLNT_BUFFERFLAGS_SILENT: u32 = 0x2;
+    const WAVE_FORMAT_PCM: u16 = 1;
+    /// Device id prefix of a render endpoint opened for loopback
+    const LOOPBACK_PREFIX: &str = "loopback:";
+    /// Shared-mode buffer, in 100 ns units
+    const BUFFER_DURATION: i64 = 1_000_000;
+    /// How long the audio service may take to hand out a process loopback client
+    const ACTIVATION_TIMEOUT: Duration = Duration::from_secs(5);
+    /// First Windows build with process loopback (Windows 10 2004)
+    const PROCESS_LOOPBACK_MIN_BUILD: u32 = 19041;
+
+    #[repr(C)]
+    struct WaveFormatEx {
+        format_tag: u16,
+        channels: u16,
+        samples_per_sec: u32,
+        avg_bytes_per_sec: u32,
+        block_align: u16,
+        bits_per_sample: u16,
+        size: u16,
+    }
+
+    /// `AUDIOCLIENT_ACTIVATION_PARAMS` for process loopback
+    #[repr(C)]
+    struct ActivationParams {
+        activation_type: u32,
+        target_process_id: u32,
+        process_loopback_mode: u32,
+    }
+
+    /// `PROPVARIANT`; `data` holds a pointer, or a `BLOB`'s size and pointer
+    #[repr(C)]
+    struct PropVariant {
+        vt: u16,
+        reserved: [u16; 3],
+        data: [usize; 2],
+    }
+
+    #[repr(C)]
+    struct PropertyKey {
+        format_id: Guid,
+        property_id: u32,
+    }
+
+    #[repr(C)]
+    struct OsVersionInfoW {
+        size: u32,
+        major: u32,
+        minor: u32,
+        build: u32,
+        platform: u32,
+        service_pack: [u16; 128],
+    }
+
+    #[repr(C)]
+    struct IUnknownVtbl {
+        query_interface:
+            unsafe extern "system" fn(*mut c_void, *const Guid, *mut *mut c_void) -> Hresult,
+        add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+        release: unsafe extern "system" fn(*mut c_void) -> u32,
+    }
+
+    #[repr(C)]
+    struct IAudioClientVtbl {
+        base: IUnknownVtbl,
+        initialize: unsafe extern "system" fn(
+            *mut c_void,
+            u32,
+            u32,
+            i64,
+            i64,
+            *const WaveFormatEx,
+            *const Guid,
+        ) -> Hresult,
+        get_buffer_size: unsafe extern "system" fn(*mut c_void, *mut u32) -> Hresult,
+        get_stream_latency: unsafe extern "system" fn(*mut c_void, *mut i64) -> Hresult,
+        get_current_padding: unsafe extern "system" fn(*mut c_void, *mut u32) -> Hresult,
+        is_format_supported: unsafe extern "system" fn(
+            *mut c_void,
+            u32,
+            *const WaveFormatEx,
+            *mut *mut WaveFormatEx,
+        ) -> Hresult,
+        get_mix_format: unsafe extern "system" fn(*mut c_void, *mut *mut WaveFormatEx) -> Hresult,
+        get_device_period: unsafe extern "system" fn(*mut c_void, *mut i64, *mut i64) -> Hresult,
+        start: unsafe extern "system" fn(*mut c_void) -> Hresult,
+        stop: unsafe extern "system" fn(*mut c_void) -> Hresult,
+        reset: unsafe extern "system" fn(*mut c_void) -> Hresult,
+        set_event_handle: unsafe extern "system" fn(*mut c_void, Handle) -> Hresult,
+        get_service:
+            unsafe extern "system" fn(*mut c_void, *const Guid, *mut *mut c_void) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct IAudioCaptureClientVtbl {
+        base: IUnknownVtbl,
+        get_buffer: unsafe extern "system" fn(
+            *mut c_void,
+            *mut *mut u8,
+            *mut u32,
+            *mut u32,
+            *mut u64,
+            *mut u64,
+        ) -> Hresult,
+        release_buffer: unsafe extern "system" fn(*mut c_void, u32) -> Hresult,
+        get_next_packet_size: unsafe extern "system" fn(*mut c_void, *mut u32) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct IActivateAudioInterfaceAsyncOperationVtbl {
+        base: IUnknownVtbl,
+        get_activate_result:
+            unsafe extern "system" fn(*mut c_void, *mut Hresult, *mut *mut c_void) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct IMMDeviceEnumeratorVtbl {
+        base: IUnknownVtbl,
+        enum_audio_endpoints:
+            unsafe extern "system" fn(*mut c_void, u32, u32, *mut *mut c_void) -> Hresult,
+        get_default_audio_endpoint:
+            unsafe extern "system" fn(*mut c_void, u32, u32, *mut *mut c_void) -> Hresult,
+        get_device: unsafe extern "system" fn(*mut c_void, *const u16, *mut *mut c_void) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct IMMDeviceCollectionVtbl {
+        base: IUnknownVtbl,
+        get_count: unsafe extern "system" fn(*mut c_void, *mut u32) -> Hresult,
+        item: unsafe extern "system" fn(*mut c_void, u32, *mut *mut c_void) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct IMMDeviceVtbl {
+        base: IUnknownVtbl,
+        activate: unsafe extern "system" fn(
+            *mut c_void,
+            *const Guid,
+            u32,
+            *const PropVariant,
+            *mut *mut c_void,
+        ) -> Hresult,
+        open_property_store:
+            unsafe extern "system" fn(*mut c_void, u32, *mut *mut c_void) -> Hresult,
+        get_id: unsafe extern "system" fn(*mut c_void, *mut *mut u16) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct IPropertyStoreVtbl {
+        base: IUnknownVtbl,
+        get_count: unsafe extern "system" fn(*mut c_void, *mut u32) -> Hresult,
+        get_at: unsafe extern "system" fn(*mut c_void, u32, *mut PropertyKey) -> Hresult,
+        get_value:
+            unsafe extern "system" fn(*mut c_void, *const PropertyKey, *mut PropVariant) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct CompletionHandlerVtbl {
+        base: IUnknownVtbl,
+        activate_completed: unsafe extern "system" fn(*mut c_void, *mut c_void) -> Hresult,
+    }
+
+    extern "system" {
+        fn CoInitializeEx(reserved: *mut c_void, coinit: u32) -> Hresult;
+        fn CoCreateInstance(
+            clsid: *const Guid,
+            outer: *mut c_void,
+            context: u32,
+            iid: *const Guid,
+            object: *mut *mut c_void,
+        ) -> Hresult;
+        fn CoTaskMemFree(memory: *mut c_void);
+        fn PropVariantClear(variant: *mut PropVariant) -> Hresult;
+        fn ActivateAudioInterfaceAsync(
+            device_path: *const u16,
+            riid: *const Guid,
+            params: *const PropVariant,
+            handler: *mut c_void,
+            operation: *mut *mut c_void,
+        ) -> Hresult;
+        fn CreateEventW(
+            attributes: *mut c_void,
+            manual_reset: i32,
+            initial_state: i32,
+            name: *const u16,
+        ) -> Handle;
+        fn WaitForSingleObject(handle: Handle, milliseconds: u32) -> u32;
+        fn CloseHandle(handle: Handle) -> i32;
+        fn RtlGetVersion(info: *mut OsVersionInfoW) -> i32;
+    }
+
+    fn check(action: &str, hr: Hresult) -> Result<()> {
+        if hr < 0 {
+            Err(anyhow::anyhow!(
+                "{} failed: HRESULT {:#010x}",
+                action,
+                hr as u32
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Join the multithreaded apartment once per capture thread
+    fn init_com() {
+        thread_local! {
+            // SAFETY: a null reserved pointer is required; a second call on
+            // the same thread only bumps the apartment's reference count
+            static COM: () = unsafe {
+                CoInitializeEx(std::ptr::null_mut(), COINIT_MULTITHREADED);
+            };
+        }
+        COM.with(|_| ());
+    }
+
+    pub(super) fn process_loopback_available() -> bool {
+        let mut info = OsVersionInfoW {
+            size: std::mem::size_of::<OsVersionInfoW>() as u32,
+            major: 0,
+            minor: 0,
+            build: 0,
+            platform: 0,
+            service_pack: [0; 128],
+        };
+        // SAFETY: `size` is set as the call requires
+        let queried = unsafe { RtlGetVersion(&mut info) } == 0;
+        queried && (info.major, info.build) >= (10, PROCESS_LOOPBACK_MIN_BUILD)
+    }
+
+    /// Owned reference to a COM interface whose vtable is `V`
+    struct Com<V> {
+        ptr: *mut c_void,
+        _vtbl: PhantomData<*const V>,
+    }
+
+    impl<V> Com<V> {
+        /// # Safety
+        ///
+        /// `ptr` must be null or an owned reference to an interface laid out
+        /// as `V`.
+        unsafe fn from_raw(ptr: *mut c_void) -> Option<Self> {
+            (!ptr.is_null()).then_some(Self {
+                ptr,
+                _vtbl: PhantomData,
+            })
+        }
+
+        fn vtbl(&self) -> &V {
+            // SAFETY: a COM object starts with its vtable pointer
+            unsafe { &**self.ptr.cast::<*const V>() }
+        }
+    }
+
+    impl<V> Drop for Com<V> {
+        fn drop(&mut self) {
+            // SAFETY: every COM vtable starts with IUnknown's
+            unsafe { ((**self.ptr.cast::<*const IUnknownVtbl>()).release)(self.ptr) };
+        }
+    }
+
+    /// `IActivateAudioInterfaceCompletionHandler` that wakes the activating
+    /// thread
+    #[repr(C)]
+    struct CompletionHandler {
+        vtbl: *const CompletionHandlerVtbl,
+        refs: AtomicU32,
+        completed: Mutex<bool>,
+        signal: Condvar,
+    }
+
+    static COMPLETION_HANDLER_VTBL: CompletionHandlerVtbl = CompletionHandlerVtbl {
+        base: IUnknownVtbl {
+            query_interface: handler_query_interface,
+            add_ref: handler_add_ref,
+            release: handler_release,
+        },
+        activate_completed: handler_activate_completed,
+    };
+
+    unsafe extern "system" fn handler_query_interface(
+        this: *mut c_void,
+        iid: *const Guid,
+        object: *mut *mut c_void,
+    ) -> Hresult {
+        // The agile marker lets the audio service call back from its own
+        // thread without marshalling
+        let iid = *iid;
+        if iid == IID_IUNKNOWN
+            || iid == IID_IAGILE_OBJECT
+            || iid == IID_IACTIVATE_AUDIO_INTERFACE_COMPLETION_HANDLER
+        {
+            handler_add_ref(this);
+            *object = this;
+            S_OK
+        } else {
+            *object = std::ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+
+    unsafe extern "system" fn handler_add_ref(this: *mut c_void) -> u32 {
+        (*this.cast::<CompletionHandler>())
+            .refs
+            .fetch_add(1, Ordering::AcqRel)
+            + 1
+    }
+
+    unsafe extern "system" fn handler_release(this: *mut c_void) -> u32 {
+        let refs = (*this.cast::<CompletionHandler>())
+            .refs
+            .fetch_sub(1, Ordering::AcqRel)
+            - 1;
+        if refs == 0 {
+            drop(Box::from_raw(this.cast::<CompletionHandler>()));
+        }
+        refs
+    }
+
+    unsafe extern "system" fn handler_activate_completed(
+        this: *mut c_void,
+        _operation: *mut c_void,
+    ) -> Hresult {
+        let handler = &*this.cast::<CompletionHandler>();
+        *super::lock(&handler.completed) = true;
+        handler.signal.notify_all();
+        S_OK
+    }
+
+    /// Activate an `IAudioClient` capturing what `process_id` and its
+    /// children play
+    fn activate_process_loopback(process_id: u32) -> Result<Com<IAudioClientVtbl>> {
+        let params = ActivationParams {
+            activation_type: AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK,
+            target_process_id: process_id,
+            process_loopback_mode: PROCESS_LOOPBACK_MODE_INCLUDE_TARGET_PROCESS_TREE,
+        };
+        let variant = PropVariant {
+            vt: VT_BLOB,
+            reserved: [0; 3],
+            data: [
+                std::mem::size_of::<ActivationParams>(),
+                std::ptr::addr_of!(params) as usize,
+            ],
+        };
+        let handler = Box::into_raw(Box::new(CompletionHandler {
+            vtbl: &COMPLETION_HANDLER_VTBL,
+            refs: AtomicU32::new(1),
+            completed: Mutex::new(false),
+            signal: Condvar::new(),
+        }));
+        let path = to_wide("VAD\\Process_Loopback");
+
+        let mut operation = std::ptr::null_mut();
+        // SAFETY: every pointer outlives the call; the handler holds its own
+        // reference for as long as the audio service keeps one
+        let activated = unsafe {
+            ActivateAudioInterfaceAsync(
+                path.as_ptr(),
+                &IID_IAUDIO_CLIENT,
+                &variant,
+                handler.cast(),
+                &mut operation,
+            )
+        };
+        let result = (|| {
+            check("ActivateAudioInterfaceAsync", activated)?;
+            // SAFETY: on success `operation` is an owned
+            // IActivateAudioInterfaceAsyncOperation
+            let operation =
+                unsafe { Com::<IActivateAudioInterfaceAsyncOperationVtbl>::from_raw(operation) }
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("Process loopback activation returned nothing")
+                    })?;
+            // SAFETY: our reference keeps the handler alive
+            let handler = unsafe { &*handler };
+            let (completed, _) = handler
+                .signal
+                .wait_timeout_while(
+                    super::lock(&handler.completed),
+                    ACTIVATION_TIMEOUT,
+                    |completed| !*completed,
+                )
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            if !*completed {
+                return Err(anyhow::anyhow!("Process loopback activation timed out"));
+            }
+            drop(completed);
+
+            let mut outcome = S_OK;
+            let mut client = std::ptr::null_mut();
+            // SAFETY: the operation completed and both out pointers are valid
+            check("GetActivateResult", unsafe {
+                (operation.vtbl().get_activate_result)(operation.ptr, &mut outcome, &mut client)
+            })?;
+            check("Process loopback activation", outcome)?;
+            // SAFETY: the result is an owned IAudioClient, as requested
+            unsafe { Com::from_raw(client) }
+                .ok_or_else(|| anyhow::anyhow!("Process loopback activation returned no client"))
+        })();
+        // SAFETY: drops the reference taken by Box::into_raw
+        unsafe { handler_release(handler.cast()) };
+        result
+    }
+
+    /// A NUL-terminated UTF-16 string
+    ///
+    /// # Safety
+    ///
+    /// `text` must be NUL-terminated.
+    unsafe fn from_wide(text: *const u16) -> String {
+        let length = (0..).take_while(|&i| *text.add(i) != 0).count();
+        String::from_utf16_lossy(std::slice::from_raw_parts(text, length))
+    }
+
+    fn to_wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn enumerator() -> Result<Com<IMMDeviceEnumeratorVtbl>> {
+        init_com();
+        let mut enumerator = std::ptr::null_mut();
+        // SAFETY: the out pointer is valid
+        check("Creating the audio device enumerator", unsafe {
+            CoCreateInstance(
+                &CLSID_MM_DEVICE_ENUMERATOR,
+                std::ptr::null_mut(),
+                CLSCTX_ALL,
+                &IID_IMM_DEVICE_ENUMERATOR,
+                &mut enumerator,
+            )
+        })?;
+        // SAFETY: an owned IMMDeviceEnumerator on success
+        unsafe { Com::from_raw(enumerator) }
+            .ok_or_else(|| anyhow::anyhow!("The audio device enumerator is unavailable"))
+    }
+
+    /// Endpoint ID string of a device
+    fn endpoint_id(device: &Com<IMMDeviceVtbl>) -> Result<String> {
+        let mut id = std::ptr::null_mut();
+        // SAFETY: the string is allocated by the callee, copied and freed here
+        unsafe {
+            check(
+                "IMMDevice::GetId",
+                (device.vtbl().get_id)(device.ptr, &mut id),
+            )?;
+            let text = from_wide(id);
+            CoTaskMemFree(id.cast());
+            Ok(text)
+        }
+    }
+
+    /// Name shown in the Sound control panel
+    fn friendly_name(device: &Com<IMMDeviceVtbl>) -> Option<String> {
+        let mut store = std::ptr::null_mut();
+        // SAFETY: the store is released on drop; the value is cleared after
+        // its string was copied
+        unsafe {
+            if (device.vtbl().open_property_store)(device.ptr, STGM_READ, &mut store) < 0 {
+                return None;
+            }
+            let store = Com::<IPropertyStoreVtbl>::from_raw(store)?;
+            let mut value = PropVariant {
+                vt: 0,
+                reserved: [0; 3],
+                data: [0; 2],
+            };
+            if (store.vtbl().get_value)(store.ptr, &PKEY_DEVICE_FRIENDLY_NAME, &mut value) < 0 {
+                return None;
+            }
+            let name = (value.vt == VT_LPWSTR && value.data[0] != 0)
+                .then(|| from_wide(value.data[0] as *const u16));
+            PropVariantClear(&mut value);
+            name
+        }
+    }
+
+    fn default_endpoint(
+        enumerator: &Com<IMMDeviceEnumeratorVtbl>,
+        flow: u32,
+    ) -> Result<Com<IMMDeviceVtbl>> {
+        let mut device = std::ptr::null_mut();
+        // SAFETY: the out pointer is valid and owned on success
+        unsafe {
+            check(
+                "Finding the default audio device",
+                (enumerator.vtbl().get_default_audio_endpoint)(
+                    enumerator.ptr,
+                    flow,
+                    E_CONSOLE,
+                    &mut device,
+                ),
+            )?;
+            Com::from_raw(device).ok_or_else(|| anyhow::anyhow!("No default audio device"))
+        }
+    }
+
+    /// Microphones, and speakers as loopback devices
+    pub(super) fn devices() -> Result<Vec<AudioDeviceInfo>> {
+        let enumerator = enumerator()?;
+        let mut devices = Vec::new();
+        for (flow, kind) in [
+            (E_CAPTURE, AudioDeviceKind::Microphone),
+            (E_RENDER, AudioDeviceKind::Loopback),
+        ] {
+            // Only the default microphone is what `None` opens
+            let default = (kind == AudioDeviceKind::Microphone)
+                .then(|| default_endpoint(&enumerator, flow).and_then(|d| endpoint_id(&d)))
+                .and_then(Result::ok);
+            let mut collection = std::ptr::null_mut();
+            // SAFETY: the out pointer is valid and owned on success
+            let collection = unsafe {
+                check(
+                    "Listing audio devices",
+                    (enumerator.vtbl().enum_audio_endpoints)(
+                        enumerator.ptr,
+                        flow,
+                        DEVICE_STATE_ACTIVE,
+                        &mut collection,
+                    ),
+                )?;
+                Com::<IMMDeviceCollectionVtbl>::from_raw(collection)
+            };
+            let Some(collection) = collection else {
+                continue;
+            };
+            let mut count = 0u32;
+            // SAFETY: the out pointer is valid
+            check("Listing audio devices", unsafe {
+                (collection.vtbl().get_count)(collection.ptr, &mut count)
+            })?;
+            for index in 0..count {
+                let mut device = std::ptr::null_mut();
+                // SAFETY: `index` is within the collection; the device is
+                // owned on success
+                let device = unsafe {
+                    if (collection.vtbl().item)(collection.ptr, index, &mut device) < 0 {
+                        continue;
+                    }
+                    Com::<IMMDeviceVtbl>::from_raw(device)
+                };
+                let Some(device) = device else {
+                    continue;
+                };
+                let Ok(id) = endpoint_id(&device) else {
+                    continue;
+                };
+                let name = friendly_name(&device).unwrap_or_else(|| id.clone());
+                devices.push(match kind {
+                    AudioDeviceKind::Microphone => AudioDeviceInfo {
+                        is_default: default.as_deref() == Some(id.as_str()),
+                        id,
+                        name,
+                        kind,
+                    },
+                    AudioDeviceKind::Loopback => AudioDeviceInfo {
+                        id: format!("{}{}", LOOPBACK_PREFIX, id),
+                        name: format!("{} (loopback)", name),
+                        kind,
+                        is_default: false,
+                    },
+                });
+            }
+        }
+        Ok(devices)
+    }
+
+    /// Shared-mode WASAPI capture, converted to 16-bit PCM by the audio engine
+    pub(super) struct WasapiSource {
+        client: Com<IAudioClientVtbl>,
+        capture: Com<IAudioCaptureClientVtbl>,
+        event: Handle,
+        sample_rate: u32,
+        channels: usize,
+        /// Captured samples not yet handed out
+        pending: VecDeque<i16>,
+    }
+
+    // SAFETY: the COM objects live in the multithreaded apartment and the
+    // source is only used by the thread owning it
+    unsafe impl Send for WasapiSource {}
+
+    impl WasapiSource {
+        /// Record a microphone, a render endpoint's loopback, or the default
+        /// microphone for `None`
+        pub(super) fn open(
+            device_id: Option<&str>,
+            sample_rate: u32,
+            channels: u8,
+        ) -> Result<Self> {
+            let enumerator = enumerator()?;
+            let render = device_id.and_then(|id| id.strip_prefix(LOOPBACK_PREFIX));
+            let device = match render.or(device_id) {
+                Some(id) => {
+                    let id = to_wide(id);
+                    let mut device = std::ptr::null_mut();
+                    // SAFETY: the ID is NUL-terminated and the device is
+                    // owned on success
+                    unsafe {
+                        check(
+                            "Opening the audio device",
+                            (enumerator.vtbl().get_device)(
+                                enumerator.ptr,
+                                id.as_ptr(),
+                                &mut device,
+                            ),
+                        )?;
+                        Com::<IMMDeviceVtbl>::from_raw(device)
+                    }
+                    .ok_or_else(|| anyhow::anyhow!("Audio device not found"))?
+                }
+                None => default_endpoint(&enumerator, E_CAPTURE)?,
+            };
+            let mut client = std::ptr::null_mut();
+            // SAFETY: the client is owned on success
+            let client = unsafe {
+                check(
+                    "IMMDevice::Activate",
+                    (device.vtbl().activate)(
+                        device.ptr,
+                        &IID_IAUDIO_CLIENT,
+                        CLSCTX_ALL,
+                        std::ptr::null(),
+                        &mut client,
+                    ),
+                )?;
+                Com::from_raw(client)
+            }
+            .ok_or_else(|| anyhow::anyhow!("IMMDevice::Activate returned nothing"))?;
+            let flags = if render.is_some() {
+                AUDCLNT_STREAMFLAGS_LOOPBACK
+            } else {
+                0
+            };
+            Self::start(client, flags, sample_rate, channels)
+        }
+
+        pub(super) fn open_process(
+            process_id: u32,
+            sample_rate: u32,
+            channels: u8,
+        ) -> Result<Self> {
+            init_com();
+            let client = activate_process_loopback(process_id)?;
+            Self::start(client, AUDCLNT_STREAMFLAGS_LOOPBACK, sample_rate, channels)
+        }
+
+        fn start(
+            client: Com<IAudioClientVtbl>,
+            flags: u32,
+            sample_rate: u32,
+            channels: u8,
+        ) -> Result<Self> {
+            let block_align = channels as u16 * 2;
+            let format = WaveFormatEx {
+                format_tag: WAVE_FORMAT_PCM,
+                channels: channels as u16,
+                samples_per_sec: sample_rate,
+                avg_bytes_per_sec: sample_rate * block_align as u32,
+                block_align,
+                bits_per_sample: 16,
+                size: 0,
+            };
+            // SAFETY: the format outlives the call and a null session GUID
+            // picks the default session
+            check("IAudioClient::Initialize", unsafe {
+                (client.vtbl().initialize)(
+                    client.ptr,
+                    AUDCLNT_SHAREMODE_SHARED,
+                    flags
+                        | AUDCLNT_STREAMFLAGS_EVENTCALLBACK
+                        | AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM
+                        | AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY,
+                    BUFFER_DURATION,
+                    0,
+                    &format,
+                    std::ptr::null(),
+                )
+            })?;
+
+            // SAFETY: an unnamed auto-reset event with default security
+            let event = unsafe { CreateEventW(std::ptr::null_mut(), 0, 0, std::ptr::null()) };
+            if event.is_null() {
+                return Err(anyhow::anyhow!(
+                    "CreateEventW failed: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+            let mut capture = std::ptr::null_mut();
+            // SAFETY: the event stays open until the source is dropped and
+            // the out pointer is valid
+            let service = unsafe {
+                check(
+                    "IAudioClient::SetEventHandle",
+                    (client.vtbl().set_event_handle)(client.ptr, event),
+                )
+                .and_then(|()| {
+                    check(
+                        "IAudioClient::GetService",
+                        (client.vtbl().get_service)(
+                            client.ptr,
+                            &IID_IAUDIO_CAPTURE_CLIENT,
+                            &mut capture,
+                        ),
+                    )
+                })
+                .and_then(|()| {
+                    Com::from_raw(capture)
+                        .ok_or_else(|| anyhow::anyhow!("IAudioClient::GetService returned nothing"))
+                })
+            };
+            let capture = match service {
+                Ok(capture) => capture,
+                Err(e) => {
+                    // SAFETY: created above and not shared
+                    unsafe { CloseHandle(event) };
+                    return Err(e);
+                }
+            };
+            let source = Self {
+                client,
+                capture,
+                event,
+                sample_rate,
+                channels: channels as usize,
+                pending: VecDeque::new(),
+            };
+            // SAFETY: the client is initialized
+            check("IAudioClient::Start", unsafe {
+                (source.client.vtbl().start)(source.client.ptr)
+            })?;
+            Ok(source)
+        }
+
+        /// Move every packet the engine has ready into `pending`
+        fn drain(&mut self) -> Result<()> {
+            let capture = self.capture.vtbl();
+            loop {
+                let mut packet = 0u32;
+                // SAFETY: the out pointer is valid
+                check("IAudioCaptureClient::GetNextPacketSize", unsafe {
+                    (capture.get_next_packet_size)(self.capture.ptr, &mut packet)
+                })?;
+                if packet == 0 {
+                    break;
+                }
+                let mut data = std::ptr::null_mut();
+                let mut frames = 0u32;
+                let mut flags = 0u32;
+                // SAFETY: the out pointers are valid; device position and
+                // timestamp are optional
+                check("IAudioCaptureClient::GetBuffer", unsafe {
+                    (capture.get_buffer)(
+                        self.capture.ptr,
+                        &mut data,
+                        &mut frames,
+                        &mut flags,
+                        std::ptr::null_mut(),
+                        std::ptr::null_mut(),
+                    )
+                })?;
+                let count = frames as usize * self.channels;
+                if flags & AUDCLNT_BUFFERFLAGS_SILENT != 0 || data.is_null() {
+                    self.pending.extend(std::iter::repeat_n(0, count));
+                } else {
+                    // SAFETY: the packet holds `frames` frames of the 16-bit
+                    // format requested in `start`, valid until released
+                    let bytes = unsafe { std::slice::from_raw_parts(data, count * 2) };
+                    self.pending.extend(
+                        bytes
+                            .chunks_exact(2)
+                            .map(|pair| i16::from_le_bytes([pair[0], pair[1]])),
+                    );
+                }
+                // SAFETY: releases exactly the packet obtained above
+                check("IAudioCaptureClient::ReleaseBuffer", unsafe {
+                    (capture.release_buffer)(self.capture.ptr, frames)
+                })?;
+            }
+            trim_pending(&mut self.pending, self.sample_rate, self.channels);
+            Ok(())
+        }
+    }
+
+    impl AudioSource for WasapiSource {
+        fn read(&mut self, buffer: &mut [i16]) -> Result<()> {
+            let frames = (buffer.len() / self.channels.max(1)) as u64;
+            let deadline = Instant::now()
+                + Duration::from_micros(frames * 1_000_000 / self.sample_rate.max(1) as u64)
+                + READ_SLACK;
+            loop {
+                self.drain()?;
+                let now = Instant::now();
+                if self.pending.len() >= buffer.len() || now >= deadline {
+                    break;
+                }
+                let wait = (deadline - now).as_millis() as u32 + 1;
+                // SAFETY: the event is open for the source's lifetime
+                unsafe { WaitForSingleObject(self.event, wait) };
+            }
+            // Loopback delivers nothing while nothing plays
+            take_pending(&mut self.pending, buffer);
+            Ok(())
+        }
+    }
+
+    impl Drop for WasapiSource {
+        fn drop(&mut self) {
+            // SAFETY: the client was started and the event is ours
+            unsafe {
+                (self.client.vtbl().stop)(self.client.ptr);
+                CloseHandle(self.event);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod coreaudio {
+    use super::{
+        lock, take_pending, trim_pending, AudioDeviceInfo, AudioDeviceKind, AudioSource, READ_SLACK,
+    };
+    use anyhow::Result;
+    use std::collections::VecDeque;
+    use std::ffi::{c_char, c_void, CStr};
+    use std::sync::{Arc, Condvar, Mutex, PoisonError};
+    use std::time::{Duration, Instant};
+
+    type OsStatus = i32;
+    type AudioObjectId = u32;
+    type AudioQueueRef = *mut c_void;
+    type CfTypeRef = *const c_void;
+    type Id = *mut c_void;
+    type Sel = *const c_void;
+
+    const fn fourcc(code: &[u8; 4]) -> u32 {
+        u32::from_be_bytes(*code)
+    }
+
+    const AUDIO_OBJECT_SYSTEM_OBJECT: AudioObjectId = 1;
+    const AUDIO_OBJECT_UNKNOWN: AudioObjectId = 0;
+    const TRANSLATE_PID_TO_PROCESS_OBJECT: u32 = fourcc(b"id2p");
+    const HARDWARE_DEVICES: u32 = fourcc(b"dev#");
+    const DEFAULT_INPUT_DEVICE: u32 = fourcc(b"dIn ");
+    const DEFAULT_OUTPUT_DEVICE: u32 = fourcc(b"dOut");
+    const DEVICE_UID: u32 = fourcc(b"uid ");
+    const DEVICE_STREAMS: u32 = fourcc(b"stm#");
+    const OBJECT_NAME: u32 = fourcc(b"lnam");
+    const SCOPE_GLOBAL: u32 = fourcc(b"glob");
+    const SCOPE_INPUT: u32 = fourcc(b"inpt");
+    const ELEMENT_MAIN: u32 = 0;
+    const FORMAT_LINEAR_PCM: u32 = fourcc(b"lpcm");
+    const FORMAT_FLAG_SIGNED_INTEGER: u32 = 1 << 2;
+    const FORMAT_FLAG_PACKED: u32 = 1 << 3;
+    const QUEUE_PROPERTY_CURRENT_DEVICE: u32 = fourcc(b"aqcd");
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    /// Buffers cycling through the queue
+    const QUEUE_BUFFERS: usize = 3;
+    /// Device id of the global tap on everything the system plays
+    const SYSTEM_AUDIO_DEVICE: &str = "system-audio";
+
+    #[repr(C)]
+    struct AudioObjectPropertyAddress {
+        selector: u32,
+        scope: u32,
+        element: u32,
+    }
+
+    #[repr(C)]
+    struct AudioStreamBasicDescription {
+        sample_rate: f64,
+        format_id: u32,
+        format_flags: u32,
+        bytes_per_packet: u32,
+        frames_per_packet: u32,
+        bytes_per_frame: u32,
+        channels_per_frame: u32,
+        bits_per_channel: u32,
+        reserved: u32,
+    }
+
+    #[repr(C)]
+    struct AudioQueueBuffer {
+        capacity: u32,
+        audio_data: *mut c_void,
+        data_byte_size: u32,
+        user_data: *mut c_void,
+        packet_description_capacity: u32,
+        packet_descriptions: *mut c_void,
+        packet_description_count: u32,
+    }
+
+    type InputCallback = extern "C" fn(
+        *mut c_void,
+        AudioQueueRef,
+        *mut AudioQueueBuffer,
+        *const c_void,
+        u32,
+        *const c_void,
+    );
+
+    extern "C" {
+        static kCFTypeDictionaryKeyCallBacks: u8;
+        static kCFTypeDictionaryValueCallBacks: u8;
+        static kCFTypeArrayCallBacks: u8;
+        static kCFBooleanTrue: CfTypeRef;
+        fn CFStringCreateWithCString(
+            allocator: CfTypeRef,
+            string: *const c_char,
+            encoding: u32,
+        ) -> CfTypeRef;
+        fn CFDictionaryCreate(
+            allocator: CfTypeRef,
+            keys: *const CfTypeRef,
+            values: *const CfTypeRef,
+            count: isize,
+            key_callbacks: *const u8,
+            value_callbacks: *const u8,
+        ) -> CfTypeRef;
+        fn CFArrayCreate(
+            allocator: CfTypeRef,
+            values: *const CfTypeRef,
+            count: isize,
+            callbacks: *const u8,
+        ) -> CfTypeRef;
+        fn CFUUIDCreate(allocator: CfTypeRef) -> CfTypeRef;
+        fn CFUUIDCreateString(allocator: CfTypeRef, uuid: CfTypeRef) -> CfTypeRef;
+        fn CFStringGetCString(
+            string: CfTypeRef,
+            buffer: *mut c_char,
+            size: isize,
+            encoding: u32,
+        ) -> u8;
+        fn CFRetain(cf: *const c_void) -> *const c_void;
+        fn CFRelease(cf: *const c_void);
+
+        fn AudioObjectGetPropertyDataSize(
+            object: AudioObjectId,
+            address: *const AudioObjectPropertyAddress,
+            qualifier_size: u32,
+            qualifier: *const c_void,
+            data_size: *mut u32,
+        ) -> OsStatus;
+        fn AudioObjectGetPropertyData(
+            object: AudioObjectId,
+            address: *const AudioObjectPropertyAddress,
+            qualifier_size: u32,
+            qualifier: *const c_void,
+            data_size: *mut u32,
+            data: *mut c_void,
+        ) -> OsStatus;
+        fn AudioHardwareCreateProcessTap(description: Id, tap: *mut AudioObjectId) -> OsStatus;
+        fn AudioHardwareDestroyProcessTap(tap: AudioObjectId) -> OsStatus;
+        fn AudioHardwareCreateAggregateDevice(
+            description: CfTypeRef,
+            device: *mut AudioObjectId,
+        ) -> OsStatus;
+        fn AudioHardwareDestroyAggregateDevice(device: AudioObjectId) -> OsStatus;
+
+        fn AudioQueueNewInput(
+            format: *const AudioStreamBasicDescription,
+            callback: InputCallback,
+            user_data: *mut c_void,
+            run_loop: *const c_void,
+            run_loop_mode: *const c_void,
+            flags: u32,
+            queue: *mut AudioQueueRef,
+        ) -> OsStatus;
+        fn AudioQueueSetProperty(
+            queue: AudioQueueRef,
+            property: u32,
+            data: *const c_void,
+            size: u32,
+        ) -> OsStatus;
+        fn AudioQueueAllocateBuffer(
+            queue: AudioQueueRef,
+            size: u32,
+            buffer: *mut *mut AudioQueueBuffer,
+        ) -> OsStatus;
+        fn AudioQueueEnqueueBuffer(
+            queue: AudioQueueRef,
+            buffer: *mut AudioQueueBuffer,
+            packet_count: u32,
+            packets: *const c_void,
+        ) -> OsStatus;
+        fn AudioQueueStart(queue: AudioQueueRef, start_time: *const c_void) -> OsStatus;
+        fn AudioQueueStop(queue: AudioQueueRef, immediate: u8) -> OsStatus;
+        fn AudioQueueDispose(queue: AudioQueueRef, immediate: u8) -> OsStatus;
+
+        fn objc_getClass(name: *const c_char) -> Id;
+        fn sel_registerName(name: *const c_char) -> Sel;
+        fn objc_msgSend();
+        fn objc_autoreleasePoolPush() -> *mut c_void;
+        fn objc_autoreleasePoolPop(pool: *mut c_void);
+    }
+
+    fn check(action: &str, status: OsStatus) -> Result<()> {
+        if status == 0 {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("{} failed: OSStatus {}", action, status))
+        }
+    }
+
+    /// A Core Foundation object released on drop
+    struct Cf(CfTypeRef);
+
+    impl Cf {
+        fn string(value: &CStr) -> Self {
+            // SAFETY: `value` is NUL-terminated UTF-8
+            Self(unsafe {
+                CFStringCreateWithCString(std::ptr::null(), value.as_ptr(), CF_STRING_ENCODING_UTF8)
+            })
+        }
+
+        fn dictionary(entries: &[(&Cf, CfTypeRef)]) -> Self {
+            let keys: Vec<CfTypeRef> = entries.iter().map(|(key, _)| key.0).collect();
+            let values: Vec<CfTypeRef> = entries.iter().map(|(_, value)| *value).collect();
+            // SAFETY: both arrays hold `entries.len()` live CF objects, which
+            // the dictionary retains
+            Self(unsafe {
+                CFDictionaryCreate(
+                    std::ptr::null(),
+                    keys.as_ptr(),
+                    values.as_ptr(),
+                    entries.len() as isize,
+                    &kCFTypeDictionaryKeyCallBacks,
+                    &kCFTypeDictionaryValueCallBacks,
+                )
+            })
+        }
+
+        fn array(values: &[&Cf]) -> Self {
+            let values: Vec<CfTypeRef> = values.iter().map(|value| value.0).collect();
+            // SAFETY: the array retains the live CF objects passed
+            Self(unsafe {
+                CFArrayCreate(
+                    std::ptr::null(),
+                    values.as_ptr(),
+                    values.len() as isize,
+                    &kCFTypeArrayCallBacks,
+                )
+            })
+        }
+    }
+
+    impl Cf {
+        fn to_string_lossy(&self) -> Option<String> {
+            let mut buffer = [0 as c_char; 512];
+            // SAFETY: the string is live and the buffer size is passed
+            let copied = unsafe {
+                !self.0.is_null()
+                    && CFStringGetCString(
+                        self.0,
+                        buffer.as_mut_ptr(),
+                        buffer.len() as isize,
+                        CF_STRING_ENCODING_UTF8,
+                    ) != 0
+            };
+            // SAFETY: CFStringGetCString NUL-terminated the buffer
+            copied.then(|| {
+                unsafe { CStr::from_ptr(buffer.as_ptr()) }
+                    .to_string_lossy()
+                    .into_owned()
+            })
+        }
+    }
+
+    impl Drop for Cf {
+        fn drop(&mut self) {
+            if !self.0.is_null() {
+                // SAFETY: we own one reference
+                unsafe { CFRelease(self.0) };
+            }
+        }
+    }
+
+    /// `objc_msgSend` for a method taking no arguments and returning an object
+    ///
+    /// # Safety
+    ///
+    /// `receiver` must respond to `selector` with that signature.
+    unsafe fn send(receiver: Id, selector: &CStr) -> Id {
+        let send = std::mem::transmute::<unsafe extern "C" fn(), unsafe extern "C" fn(Id, Sel) -> Id>(
+            objc_msgSend,
+        );
+        send(receiver, sel_registerName(selector.as_ptr()))
+    }
+
+    /// `objc_msgSend` for a method taking one object
+    ///
+    /// # Safety
+    ///
+    /// `receiver` must respond to `selector` with that signature.
+    unsafe fn send_object(receiver: Id, selector: &CStr, argument: Id) -> Id {
+        let send = std::mem::transmute::<
+            unsafe extern "C" fn(),
+            unsafe extern "C" fn(Id, Sel, Id) -> Id,
+        >(objc_msgSend);
+        send(receiver, sel_registerName(selector.as_ptr()), argument)
+    }
+
+    /// `objc_msgSend` for a method taking one unsigned int
+    ///
+    /// # Safety
+    ///
+    /// `receiver` must respond to `selector` with that signature.
+    unsafe fn send_u32(receiver: Id, selector: &CStr, argument: u32) -> Id {
+        let send = std::mem::transmute::<
+            unsafe extern "C" fn(),
+            unsafe extern "C" fn(Id, Sel, u32) -> Id,
+        >(objc_msgSend);
+        send(receiver, sel_registerName(selector.as_ptr()), argument)
+    }
+
+    /// `objc_msgSend` for a setter taking a BOOL
+    ///
+    /// # Safety
+    ///
+    /// `receiver` must respond to `selector` with that signature.
+    unsafe fn send_bool(receiver: Id, selector: &CStr, argument: bool) {
+        let send = std::mem::transmute::<unsafe extern "C" fn(), unsafe extern "C" fn(Id, Sel, bool)>(
+            objc_msgSend,
+        );
+        send(receiver, sel_registerName(selector.as_ptr()), argument)
+    }
+
+    fn class(name: &CStr) -> Result<Id> {
+        // SAFETY: `name` is NUL-terminated
+        let class = unsafe { objc_getClass(name.as_ptr()) };
+        if class.is_null() {
+            Err(anyhow::anyhow!(
+                "{} is unavailable; process taps need macOS 14.2 or later",
+                name.to_string_lossy()
+            ))
+        } else {
+            Ok(class)
+        }
+    }
+
+    pub(super) fn process_taps_available() -> bool {
+        class(c"CATapDescription").is_ok()
+    }
+
+    /// Core Audio's object for a process that has audio I/O
+    fn process_object(process_id: u32) -> Result<AudioObjectId> {
+        let address = AudioObjectPropertyAddress {
+            selector: TRANSLATE_PID_TO_PROCESS_OBJECT,
+            scope: SCOPE_GLOBAL,
+            element: ELEMENT_MAIN,
+        };
+        let pid = process_id as i32;
+        let mut object = AUDIO_OBJECT_UNKNOWN;
+        let mut size = std::mem::size_of::<AudioObjectId>() as u32;
+        // SAFETY: the qualifier is a pid_t and `size` matches `object`
+        check("Translating the process to an audio object", unsafe {
+            AudioObjectGetPropertyData(
+                AUDIO_OBJECT_SYSTEM_OBJECT,
+                &address,
+                std::mem::size_of::<i32>() as u32,
+                std::ptr::addr_of!(pid).cast(),
+                &mut size,
+                std::ptr::addr_of_mut!(object).cast(),
+            )
+        })?;
+        if object == AUDIO_OBJECT_UNKNOWN {
+            return Err(anyhow::anyhow!(
+                "Process {} has no audio for Core Audio to tap",
+                process_id
+            ));
+        }
+        Ok(object)
+    }
+
+    /// Read a fixed-size global property of an audio object
+    fn property<T: Copy>(object: AudioObjectId, selector: u32, empty: T) -> Result<T> {
+        let address = AudioObjectPropertyAddress {
+            selector,
+            scope: SCOPE_GLOBAL,
+            element: ELEMENT_MAIN,
+        };
+        let mut value = empty;
+        let mut size = std::mem::size_of::<T>() as u32;
+        // SAFETY: `size` matches the out value
+        check("AudioObjectGetPropertyData", unsafe {
+            AudioObjectGetPropertyData(
+                object,
+                &address,
+                0,
+                std::ptr::null(),
+                &mut size,
+                std::ptr::addr_of_mut!(value).cast(),
+            )
+        })?;
+        Ok(value)
+    }
+
+    /// A CFString property, returned retained
+    fn string_property(object: AudioObjectId, selector: u32) -> Result<Cf> {
+        property(object, selector, std::ptr::null()).map(Cf)
+    }
+
+    /// UID of the system's default input or output device
+    fn default_device_uid(selector: u32) -> Result<Cf> {
+        let device = property(AUDIO_OBJECT_SYSTEM_OBJECT, selector, AUDIO_OBJECT_UNKNOWN)?;
+        if device == AUDIO_OBJECT_UNKNOWN {
+            return Err(anyhow::anyhow!("No default audio device"));
+        }
+        string_property(device, DEVICE_UID)
+    }
+
+    /// Whether a device has input streams
+    fn has_input(device: AudioObjectId) -> bool {
+        let address = AudioObjectPropertyAddress {
+            selector: DEVICE_STREAMS,
+            scope: SCOPE_INPUT,
+            element: ELEMENT_MAIN,
+        };
+        let mut size = 0u32;
+        // SAFETY: only the size is queried
+        let status = unsafe {
+            AudioObjectGetPropertyDataSize(device, &address, 0, std::ptr::null(), &mut size)
+        };
+        status == 0 && size > 0
+    }
+
+    pub(super) fn devices() -> Result<Vec<AudioDeviceInfo>> {
+        let address = AudioObjectPropertyAddress {
+            selector: HARDWARE_DEVICES,
+            scope: SCOPE_GLOBAL,
+            element: ELEMENT_MAIN,
+        };
+        let mut size = 0u32;
+        // SAFETY: only the size is queried
+        check("Listing audio devices", unsafe {
+            AudioObjectGetPropertyDataSize(
+                AUDIO_OBJECT_SYSTEM_OBJECT,
+                &address,
+                0,
+                std::ptr::null(),
+                &mut size,
+            )
+        })?;
+        let mut ids =
+            vec![AUDIO_OBJECT_UNKNOWN; size as usize / std::mem::size_of::<AudioObjectId>()];
+        // SAFETY: `size` matches the buffer
+        check("Listing audio devices", unsafe {
+            AudioObjectGetPropertyData(
+                AUDIO_OBJECT_SYSTEM_OBJECT,
+                &address,
+                0,
+                std::ptr::null(),
+                &mut size,
+                ids.as_mut_ptr().cast(),
+            )
+        })?;
+        ids.truncate(size as usize / std::mem::size_of::<AudioObjectId>());
+
+        let default_input = default_device_uid(DEFAULT_INPUT_DEVICE)
+            .ok()
+            .and_then(|uid| uid.to_string_lossy());
+        let mut devices = Vec::new();
+        for device in ids.into_iter().filter(|&device| has_input(device)) {
+            let Some(id) = string_property(device, DEVICE_UID)
+                .ok()
+                .and_then(|uid| uid.to_string_lossy())
+            else {
+                continue;
+            };
+            // Our own tap aggregates are private and never listed
+            let name = string_property(device, OBJECT_NAME)
+                .ok()
+                .and_then(|name| name.to_string_lossy())
+                .unwrap_or_else(|| id.clone());
+            devices.push(AudioDeviceInfo {
+                is_default: default_input.as_deref() == Some(id.as_str()),
+                id,
+                name,
+                kind: AudioDeviceKind::Microphone,
+            });
+        }
+        if process_taps_available() {
+            devices.push(AudioDeviceInfo {
+                id: SYSTEM_AUDIO_DEVICE.to_string(),
+                name: "System audio".to_string(),
+                kind: AudioDeviceKind::Loopback,
+                is_default: false,
+            });
+        }
+        Ok(devices)
+    }
+
+    /// What a tap records
+    enum TapTarget {
+        /// One process object and its output
+        Process(AudioObjectId),
+        /// Everything the system plays
+        System,
+    }
+
+    /// A process tap and the private aggregate device exposing it as input
+    struct Tap {
+        tap: AudioObjectId,
+        aggregate: AudioObjectId,
+        /// UID of the aggregate device, for the audio queue
+        aggregate_uid: Cf,
+    }
+
+    impl Tap {
+        fn create(target: TapTarget) -> Result<Self> {
+            let description_class = class(c"CATapDescription")?;
+            let number_class = class(c"NSNumber")?;
+            let array_class = class(c"NSArray")?;
+
+            let mut tap = AUDIO_OBJECT_UNKNOWN;
+            // SAFETY: the classes exist and the messages match their
+            // declarations; autoreleased objects live until the pool pops
+            let tap_uid = unsafe {
+                let pool = objc_autoreleasePoolPush();
+                let allocated = send(description_class, c"alloc");
+                let description = match target {
+                    TapTarget::Process(process) => send_object(
+                        allocated,
+                        c"initStereoMixdownOfProcesses:",
+                        send_object(
+                            array_class,
+                            c"arrayWithObject:",
+                            send_u32(number_class, c"numberWithUnsignedInt:", process),
+                        ),
+                    ),
+                    TapTarget::System => send_object(
+                        allocated,
+                        c"initStereoGlobalTapButExcludeProcesses:",
+                        send(array_class, c"array"),
+                    ),
+                };
+                let created = if description.is_null() {
+                    Err(anyhow::anyhow!("Cannot describe the audio tap"))
+                } else {
+                    // Private taps are invisible to other processes
+                    send_bool(description, c"setPrivateTap:", true);
+                    check(
+                        "AudioHardwareCreateProcessTap",
+                        AudioHardwareCreateProcessTap(description, &mut tap),
+                    )
+                    .map(|()| Cf(CFRetain(send(send(description, c"UUID"), c"UUIDString"))))
+                };
+                if !description.is_null() {
+                    send(description, c"release");
+                }
+                objc_autoreleasePoolPop(pool);
+                created?
+            };
+
+            let mut created = Self {
+                tap,
+                aggregate: AUDIO_OBJECT_UNKNOWN,
+                // SAFETY: creates a fresh UUID and its string form
+                aggregate_uid: unsafe {
+                    let uuid = Cf(CFUUIDCreate(std::ptr::null()));
+                    Cf(CFUUIDCreateString(std::ptr::null(), uuid.0))
+                },
+            };
+            let output_uid = default_device_uid(DEFAULT_OUTPUT_DEVICE)?;
+            // Key strings of the kAudioAggregateDevice*Key constants
+            let (uid, name, main, private, auto_start, sub_devices, taps, drift) = (
+                Cf::string(c"uid"),
+                Cf::string(c"name"),
+                Cf::string(c"master"),
+                Cf::string(c"private"),
+                Cf::string(c"tapautostart"),
+                Cf::string(c"subdevices"),
+                Cf::string(c"taps"),
+                Cf::string(c"drift"),
+            );
+            // SAFETY: reads an immutable framework constant
+            let yes = unsafe { kCFBooleanTrue };
+            let device_name = Cf::string(c"CecDesk Audio Tap");
+            let sub_device = Cf::dictionary(&[(&uid, output_uid.0)]);
+            let sub_tap = Cf::dictionary(&[(&uid, tap_uid.0), (&drift, yes)]);
+            let description = Cf::dictionary(&[
+                (&uid, created.aggregate_uid.0),
+                (&name, device_name.0),
+                (&main, output_uid.0),
+                (&private, yes),
+                (&auto_start, yes),
+                (&sub_devices, Cf::array(&[&sub_device]).0),
+                (&taps, Cf::array(&[&sub_tap]).0),
+            ]);
+            // SAFETY: the description is a live CFDictionary
+            check("AudioHardwareCreateAggregateDevice", unsafe {
+                AudioHardwareCreateAggregateDevice(description.0, &mut created.aggregate)
+            })?;
+            Ok(created)
+        }
+    }
+
+    impl Drop for Tap {
+        fn drop(&mut self) {
+            // SAFETY: both objects were created by us and are destroyed once
+            unsafe {
+                if self.aggregate != AUDIO_OBJECT_UNKNOWN {
+                    AudioHardwareDestroyAggregateDevice(self.aggregate);
+                }
+                AudioHardwareDestroyProcessTap(self.tap);
+            }
+        }
+    }
+
+    /// Samples handed from the queue's thread to the reader
+    struct Shared {
+        pending: Mutex<VecDeque<i16>>,
+        ready: Condvar,
+        sample_rate: u32,
+        channels: usize,
+    }
+
+    extern "C" fn input_callback(
+        user_data: *mut c_void,
+        queue: AudioQueueRef,
+        buffer: *mut AudioQueueBuffer,
+        _start_time: *const c_void,
+        _packet_count: u32,
+        _packets: *const c_void,
+    ) {
+        // SAFETY: `user_data` is the `Shared` the source keeps alive until
+        // the queue is disposed, and `buffer` belongs to `queue`
+        unsafe {
+            let shared = &*user_data.cast::<Shared>();
+            let bytes = std::slice::from_raw_parts(
+                (*buffer).audio_data.cast::<u8>(),
+                (*buffer).data_byte_size as usize,
+            );
+            {
+                let mut pending = lock(&shared.pending);
+                pending.extend(
+                    bytes
+                        .chunks_exact(2)
+                        .map(|pair| i16::from_le_bytes([pair[0], pair[1]])),
+                );
+                trim_pending(&mut pending, shared.sample_rate, shared.channels);
+            }
+            shared.ready.notify_all();
+            // Fails only while the queue is being stopped
+            AudioQueueEnqueueBuffer(queue, buffer, 0, std::ptr::null());
+        }
+    }
+
+    /// An audio queue recording an input device or a tap, converted to
+    /// 16-bit PCM
+    pub(super) struct QueueSource {
+        queue: AudioQueueRef,
+        shared: Arc<Shared>,
+        /// Kept until the queue is disposed
+        _tap: Option<Tap>,
+    }
+
+    // SAFETY: audio queues may be used from any thread
+    unsafe impl Send for QueueSource {}
+
+    impl QueueSource {
+        /// Record an input device by UID, the default input for `None`, or
+        /// the system audio tap
+        pub(super) fn open(
+            device_id: Option<&str>,
+            sample_rate: u32,
+            channels: u8,
+        ) -> Result<Self> {
+            match device_id {
+                Some(SYSTEM_AUDIO_DEVICE) => {
+                    let tap = Tap::create(TapTarget::System)?;
+                    Self::start(Cf(std::ptr::null()), Some(tap), sample_rate, channels)
+                }
+                Some(uid) => {
+                    let uid = std::ffi::CString::new(uid)
+                        .map_err(|_| anyhow::anyhow!("Invalid audio device: {}", uid))?;
+                    Self::start(Cf::string(&uid), None, sample_rate, channels)
+                }
+                None => Self::start(
+                    default_device_uid(DEFAULT_INPUT_DEVICE)?,
+                    None,
+                    sample_rate,
+                    channels,
+                ),
+            }
+        }
+
+        /// Record what one process plays
+        pub(super) fn open_process(
+            process_id: u32,
+            sample_rate: u32,
+            channels: u8,
+        ) -> Result<Self> {
+            let tap = Tap::create(TapTarget::Process(process_object(process_id)?))?;
+            Self::start(Cf(std::ptr::null()), Some(tap), sample_rate, channels)
+        }
+
+        /// Start a queue on `device`, or on the tap's aggregate device
+        fn start(device: Cf, tap: Option<Tap>, sample_rate: u32, channels: u8) -> Result<Self> {
+            let shared = Arc::new(Shared {
+                pending: Mutex::new(VecDeque::new()),
+                ready: Condvar::new(),
+                sample_rate,
+                channels: channels as usize,
+            });
+            let bytes_per_frame = channels as u32 * 2;
+            let format = AudioStreamBasicDescription {
+                sample_rate: sample_rate as f64,
+                format_id: FORMAT_LINEAR_PCM,
+                format_flags: FORMAT_FLAG_SIGNED_INTEGER | FORMAT_FLAG_PACKED,
+                bytes_per_packet: bytes_per_frame,
+                frames_per_packet: 1,
+                bytes_per_frame,
+                channels_per_frame: channels as u32,
+                bits_per_channel: 16,
+                reserved: 0,
+            };
+            let mut queue: AudioQueueRef = std::ptr::null_mut();
+            // SAFETY: a null run loop runs the callback on the queue's own
+            // thread; `shared` outlives the queue, which `Drop` disposes
+            check("AudioQueueNewInput", unsafe {
+                AudioQueueNewInput(
+                    &format,
+                    input_callback,
+                    Arc::as_ptr(&shared) as *mut c_void,
+                    std::ptr::null(),
+                    std::ptr::null(),
+                    0,
+                    &mut queue,
+                )
+            })?;
+            let source = Self {
+                queue,
+                shared,
+                _tap: tap,
+            };
+            let device_uid = match &source._tap {
+                Some(tap) => tap.aggregate_uid.0,
+                None => device.0,
+            };
+
+            let buffer_size = (sample_rate as u128 * super::AUDIO_FRAME_DURATION.as_millis() / 1000)
+                as u32
+                * bytes_per_frame;
+            // SAFETY: the queue is live; the device property is a CFString
+            // the queue retains
+            unsafe {
+                check(
+                    "Selecting the audio device",
+                    AudioQueueSetProperty(
+                        source.queue,
+                        QUEUE_PROPERTY_CURRENT_DEVICE,
+                        std::ptr::addr_of!(device_uid).cast(),
+                        std::mem::size_of::<CfTypeRef>() as u32,
+                    ),
+                )?;
+                for _ in 0..QUEUE_BUFFERS {
+                    let mut buffer = std::ptr::null_mut();
+                    check(
+                        "AudioQueueAllocateBuffer",
+                        AudioQueueAllocateBuffer(source.queue, buffer_size, &mut buffer),
+                    )?;
+                    check(
+                        "AudioQueueEnqueueBuffer",
+                        AudioQueueEnqueueBuffer(source.queue, buffer, 0, std::ptr::null()),
+                    )?;
+                }
+                check(
+                    "AudioQueueStart",
+                    AudioQueueStart(source.queue, std::ptr::null()),
+                )?;
+            }
+            Ok(source)
+        }
+    }
+
+    impl AudioSource for QueueSource {
+        fn read(&mut self, buffer: &mut [i16]) -> Result<()> {
+            let frames = (buffer.len() / self.shared.channels.max(1)) as u64;
+            let deadline = Instant::now()
+                + Duration::from_micros(frames * 1_000_000 / self.shared.sample_rate.max(1) as u64)
+                + READ_SLACK;
+            let mut pending = lock(&self.shared.pending);
+            while pending.len() < buffer.len() {
+                let now = Instant::now();
+                if now >= deadline {
+                    break;
+                }
+                pending = self
+                    .shared
+                    .ready
+                    .wait_timeout(pending, deadline - now)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+            }
+            // A tap delivers silence, or nothing, while nothing plays
+            take_pending(&mut pending, buffer);
+            Ok(())
+        }
+    }
+
+    impl Drop for QueueSource {
+        fn drop(&mut self) {
+            // SAFETY: stopping and disposing synchronously guarantees the
+            // callback no longer runs once `shared` is released
+            unsafe {
+                AudioQueueStop(self.queue, 1);
+                AudioQueueDispose(self.queue, 1);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod pulse {
+    use super::{trim_pending, AudioDeviceInfo, AudioDeviceKind, AudioSource, READ_SLACK};
+    use anyhow::Result;
+    use std::collections::VecDeque;
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_char, c_int, c_void};
+    use std::sync::OnceLock;
+    use std::time::{Duration, Instant};
+
+    #[repr(C)]
+    struct Mainloop {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    struct Context {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    struct Stream {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    struct Operation {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    struct Proplist {
+        _private: [u8; 0],
+    }
+
+    const CHANNELS_MAX: usize = 32;
+    const RTLD_NOW: c_int = 2;
+    const SAMPLE_S16LE: c_int = 3;
+    const CONTEXT_NOAUTOSPAWN: c_int = 1;
+    const CONTEXT_READY: c_int = 4;
+    const CONTEXT_FAILED: c_int = 5;
+    const CONTEXT_TERMINATED: c_int = 6;
+    const STREAM_READY: c_int = 2;
+    const STREAM_FAILED: c_int = 3;
+    const STREAM_TERMINATED: c_int = 4;
+    const STREAM_ADJUST_LATENCY: c_int = 0x2000;
+    const OPERATION_RUNNING: c_int = 0;
+    /// How long connecting to the server and its streams may take
+    const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+    /// Device id prefix of a PulseAudio source, as opposed to an ALSA PCM
+    pub(super) const DEVICE_PREFIX: &str = "pulse:";
+    /// Source name PulseAudio resolves to the default sink's monitor
+    const DEFAULT_MONITOR: &str = "@DEFAULT_MONITOR@";
+    /// Parent links followed when matching a stream to a process tree
+    const MAX_PROCESS_DEPTH: usize = 64;
+
+    #[repr(C)]
+    struct SampleSpec {
+        format: c_int,
+        rate: u32,
+        channels: u8,
+    }
+
+    #[repr(C)]
+    struct BufferAttr {
+        maxlength: u32,
+        tlength: u32,
+        prebuf: u32,
+        minreq: u32,
+        fragsize: u32,
+    }
+
+    /// Leading fields of `pa_sink_input_info`
+    #[repr(C)]
+    struct SinkInputInfo {
+        index: u32,
+        name: *const c_char,
+        owner_module: u32,
+        client: u32,
+        sink: u32,
+        sample_spec: SampleSpec,
+        channel_map_channels: u8,
+        channel_map: [c_int; CHANNELS_MAX],
+        volume_channels: u8,
+        volume: [u32; CHANNELS_MAX],
+        buffer_usec: u64,
+        sink_usec: u64,
+        resample_method: *const c_char,
+        driver: *const c_char,
+        mute: c_int,
+        proplist: *mut Proplist,
+    }
+
+    type SinkInputInfoCallback =
+        unsafe extern "C" fn(*mut Context, *const SinkInputInfo, c_int, *mut c_void);
+
+    extern "C" {
+        fn dlopen(filename: *const c_char, flags: c_int) -> *mut c_void;
+        fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+    }
+
+    /// libpulse entry points, resolved at runtime so the library stays
+    /// optional
+    struct Api {
+        mainloop_new: unsafe extern "C" fn() -> *mut Mainloop,
+        mainloop_get_api: unsafe extern "C" fn(*mut Mainloop) -> *mut c_void,
+        mainloop_prepare: unsafe extern "C" fn(*mut Mainloop, c_int) -> c_int,
+        mainloop_poll: unsafe extern "C" fn(*mut Mainloop) -> c_int,
+        mainloop_dispatch: unsafe extern "C" fn(*mut Mainloop) -> c_int,
+        mainloop_free: unsafe extern "C" fn(*mut Mainloop),
+        context_new: unsafe extern "C" fn(*mut c_void, *const c_char) -> *mut Context,
+        context_connect:
+            unsafe extern "C" fn(*mut Context, *const c_char, c_int, *const c_void) -> c_int,
+        context_get_state: unsafe extern "C" fn(*mut Context) -> c_int,
+        context_errno: unsafe extern "C" fn(*mut Context) -> c_int,
+        context_disconnect: unsafe extern "C" fn(*mut Context),
+        context_unref: unsafe extern "C" fn(*mut Context),
+        context_get_sink_input_info_list: unsafe extern "C" fn(
+            *mut Context,
+            SinkInputInfoCallback,
+            *mut c_void,
+        ) -> *mut Operation,
+        operation_get_state: unsafe extern "C" fn(*mut Operation) -> c_int,
+        operation_unref: unsafe extern "C" fn(*mut Operation),
+        stream_new: unsafe extern "C" fn(
+            *mut Context,
+            *const c_char,
+            *const SampleSpec,
+            *const c_void,
+        ) -> *mut Stream,
+        stream_set_monitor_stream: unsafe extern "C" fn(*mut Stream, u32) -> c_int,
+        stream_connect_record:
+            unsafe extern "C" fn(*mut Stream, *const c_char, *const BufferAttr, c_int) -> c_int,
+        stream_get_state: unsafe extern "C" fn(*mut Stream) -> c_int,
+        stream_peek: unsafe extern "C" fn(*mut Stream, *mut *const c_void, *mut usize) -> c_int,
+        stream_drop: unsafe extern "C" fn(*mut Stream) -> c_int,
+        stream_disconnect: unsafe extern "C" fn(*mut Stream) -> c_int,
+        stream_unref: unsafe extern "C" fn(*mut Stream),
+        proplist_gets: unsafe extern "C" fn(*const Proplist, *const c_char) -> *const c_char,
+        strerror: unsafe extern "C" fn(c_int) -> *const c_char,
+    }
+
+    /// Resolve one symbol as a function pointer of type `T`
+    ///
+    /// # Safety
+    ///
+    /// `T` must be the symbol's C signature.
+    unsafe fn symbol<T: Copy>(library: *mut c_void, name: &CStr) -> Option<T> {
+        let address = dlsym(library, name.as_ptr());
+        (!address.is_null()).then(|| std::mem::transmute_copy(&address))
+    }
+
+    fn load() -> Option<Api> {
+        // SAFETY: each symbol is given its signature from the libpulse
+        // headers; the library is never unloaded
+        unsafe {
+            let library = dlopen(c"libpulse.so.0".as_ptr(), RTLD_NOW);
+            if library.is_null() {
+                return None;
+            }
+            Some(Api {
+                mainloop_new: symbol(library, c"pa_mainloop_new")?,
+                mainloop_get_api: symbol(library, c"pa_mainloop_get_api")?,
+                mainloop_prepare: symbol(library, c"pa_mainloop_prepare")?,
+                mainloop_poll: symbol(library, c"pa_mainloop_poll")?,
+                mainloop_dispatch: symbol(library, c"pa_mainloop_dispatch")?,
+                mainloop_free: symbol(library, c"pa_mainloop_free")?,
+                context_new: symbol(library, c"pa_context_new")?,
+                context_connect: symbol(library, c"pa_context_connect")?,
+                context_get_state: symbol(library, c"pa_context_get_state")?,
+                context_errno: symbol(library, c"pa_context_errno")?,
+                context_disconnect: symbol(library, c"pa_context_disconnect")?,
+                context_unref: symbol(library, c"pa_context_unref")?,
+                context_get_sink_input_info_list: symbol(
+                    library,
+                    c"pa_context_get_sink_input_info_list",
+                )?,
+                operation_get_state: symbol(library, c"pa_operation_get_state")?,
+                operation_unref: symbol(library, c"pa_operation_unref")?,
+                stream_new: symbol(library, c"pa_stream_new")?,
+                stream_set_monitor_stream: symbol(library, c"pa_stream_set_monitor_stream")?,
+                stream_connect_record: symbol(library, c"pa_stream_connect_record")?,
+                stream_get_state: symbol(library, c"pa_stream_get_state")?,
+                stream_peek: symbol(library, c"pa_stream_peek")?,
+                stream_drop: symbol(library, c"pa_stream_drop")?,
+                stream_disconnect: symbol(library, c"pa_stream_disconnect")?,
+                stream_unref: symbol(library, c"pa_stream_unref")?,
+                proplist_gets: symbol(library, c"pa_proplist_gets")?,
+                strerror: symbol(library, c"pa_strerror")?,
+            })
+        }
+    }
+
+    fn api() -> Result<&'static Api> {
+        static API: OnceLock<Option<Api>> = OnceLock::new();
+        API.get_or_init(load)
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("The PulseAudio client library is not installed"))
+    }
+
+    /// What the default output plays, if a sound server is running
+    pub(super) fn monitor_device() -> Option<AudioDeviceInfo> {
+        PulseSource::connect(48_000, 2).ok()?;
+        Some(AudioDeviceInfo {
+            id: format!("{}{}", DEVICE_PREFIX, DEFAULT_MONITOR),
+            name: "System audio".to_string(),
+            kind: AudioDeviceKind::Loopback,
+            is_default: false,
+        })
+    }
+
+    pub(super) fn available() -> bool {
+        api().is_ok()
+    }
+
+    /// Whether `process_id` is `ancestor` or one of its descendants
+    fn in_process_tree(process_id: u32, ancestor: u32) -> bool {
+        let mut current = process_id;
+        for _ in 0..MAX_PROCESS_DEPTH {
+            if current == ancestor {
+                return true;
+            }
+            let Ok(status) = std::fs::read_to_string(format!("/proc/{}/status", current)) else {
+                return false;
+            };
+            let parent = status
+                .lines()
+                .find_map(|line| line.strip_prefix("PPid:"))
+                .and_then(|ppid| ppid.trim().parse::<u32>().ok());
+            match parent {
+                Some(parent) if parent != 0 => current = parent,
+                _ => return false,
+            }
+        }
+        false
+    }
+
+    /// Time left until `deadline`, or an error once it passed
+    fn remaining(deadline: Instant) -> Result<Duration> {
+        let now = Instant::now();
+        if now >= deadline {
+            Err(anyhow::anyhow!("PulseAudio server did not respond"))
+        } else {
+            Ok(deadline - now)
+        }
+    }
+
+    /// State for the sink input listing callback
+    struct SinkInputQuery {
+        api: &'static Api,
+        process_id: u32,
+        found: Vec<u32>,
+    }
+
+    unsafe extern "C" fn collect_sink_input(
+        _context: *mut Context,
+        info: *const SinkInputInfo,
+        end_of_list: c_int,
+        user_data: *mut c_void,
+    ) {
+        if end_of_list != 0 || info.is_null() {
+            return;
+        }
+        let query = &mut *user_data.cast::<SinkInputQuery>();
+        let owner = (query.api.proplist_gets)((*info).proplist, c"application.process.id".as_ptr());
+        if owner.is_null() {
+            return;
+        }
+        let owner = CStr::from_ptr(owner)
+            .to_str()
+            .ok()
+            .and_then(|pid| pid.parse().ok());
+        if owner.is_some_and(|owner| in_process_tree(owner, query.process_id)) {
+            query.found.push((*info).index);
+        }
+    }
+
+    /// One record stream and the samples it delivered so far
+    struct Recording {
+        stream: *mut Stream,
+        pending: VecDeque<i16>,
+    }
+
+    /// A private PulseAudio connection recording one or more streams, mixed
+    pub(super) struct PulseSource {
+        api: &'static Api,
+        mainloop: *mut Mainloop,
+        context: *mut Context,
+        recordings: Vec<Recording>,
+        sample_rate: u32,
+        channels: usize,
+    }
+
+    // SAFETY: the connection is only used by the thread owning the source
+    unsafe impl Send for PulseSource {}
+
+    impl PulseSource {
+        /// Record every stream `process_id` and its children play
+        ///
+        /// Streams the process opens later are picked up when the capture
+        /// thread reopens the source, which it does once one of these ends.
+        pub(super) fn open_process(
+            process_id: u32,
+            sample_rate: u32,
+            channels: u8,
+        ) -> Result<Self> {
+            let mut source = Self::connect(sample_rate, channels)?;
+            let sink_inputs = source.sink_inputs_of(process_id)?;
+            if sink_inputs.is_empty() {
+                return Err(anyhow::anyhow!(
+                    "Process {} is not playing audio",
+                    process_id
+                ));
+            }
+            for sink_input in sink_inputs {
+                source.record(Some(sink_input), None)?;
+            }
+            source.wait_for_streams()?;
+            Ok(source)
+        }
+
+        /// Record a source by name, such as a sink's monitor
+        pub(super) fn open_source(name: &str, sample_rate: u32, channels: u8) -> Result<Self> {
+            let name = CString::new(name)?;
+            let mut source = Self::connect(sample_rate, channels)?;
+            source.record(None, Some(&name))?;
+            source.wait_for_streams()?;
+            Ok(source)
+        }
+
+        fn connect(sample_rate: u32, channels: u8) -> Result<Self> {
+            let api = api()?;
+            // SAFETY: the objects are created in order and owned by the
+            // source, whose Drop frees whatever was created
+            unsafe {
+                let mainloop = (api.mainloop_new)();
+                if mainloop.is_null() {
+                    return Err(anyhow::anyhow!("pa_mainloop_new failed"));
+                }
+                let mut source = Self {
+                    api,
+                    mainloop,
+                    context: std::ptr::null_mut(),
+                    recordings: Vec::new(),
+                    sample_rate,
+                    channels: channels as usize,
+                };
+                source.context =
+                    (api.context_new)((api.mainloop_get_api)(mainloop), c"CecDesk".as_ptr());
+                if source.context.is_null() {
+                    return Err(anyhow::anyhow!("pa_context_new failed"));
+                }
+                if (api.context_connect)(
+                    source.context,
+                    std::ptr::null(),
+                    CONTEXT_NOAUTOSPAWN,
+                    std::ptr::null(),
+                ) < 0
+                {
+                    return Err(source.error("Connecting to the PulseAudio server"));
+                }
+                let deadline = Instant::now() + CONNECT_TIMEOUT;
+                loop {
+                    match (api.context_get_state)(source.context) {
+                        CONTEXT_READY => break,
+                        CONTEXT_FAILED | CONTEXT_TERMINATED => {
+                            return Err(source.error("Connecting to the PulseAudio server"))
+                        }
+                        _ => source.iterate(remaining(deadline)?)?,
+                    }
+                }
+                Ok(source)
+            }
+        }
+
+        fn error(&self, action: &str) -> anyhow::Error {
+            // SAFETY: the context is live and pa_strerror returns a static
+            // string for any code
+            let message = unsafe {
+                CStr::from_ptr((self.api.strerror)((self.api.context_errno)(self.context)))
+            };
+            anyhow::anyhow!("{} failed: {}", action, message.to_string_lossy())
+        }
+
+        /// Run one main loop iteration, waiting `timeout` at most for events
+        fn iterate(&mut self, timeout: Duration) -> Result<()> {
+            let timeout = timeout.as_micros().min(c_int::MAX as u128) as c_int;
+            // SAFETY: the main loop is live and only driven from this thread
+            let failed = unsafe {
+                (self.api.mainloop_prepare)(self.mainloop, timeout) < 0
+                    || (self.api.mainloop_poll)(self.mainloop) < 0
+                    || (self.api.mainloop_dispatch)(self.mainloop) < 0
+            };
+            if failed {
+                return Err(anyhow::anyhow!("PulseAudio main loop failed"));
+            }
+            // SAFETY: the context is live
+            match unsafe { (self.api.context_get_state)(self.context) } {
+                CONTEXT_FAILED | CONTEXT_TERMINATED => Err(self.error("PulseAudio connection")),
+                _ => Ok(()),
+            }
+        }
+
+        /// Indexes of the sink inputs played by `process_id` or its children
+        fn sink_inputs_of(&mut self, process_id: u32) -> Result<Vec<u32>> {
+            let mut query = SinkInputQuery {
+                api: self.api,
+                process_id,
+                found: Vec::new(),
+            };
+            // SAFETY: `query` outlives the operation, which completes (or
+            // the connection fails) before this returns
+            let operation = unsafe {
+                (self.api.context_get_sink_input_info_list)(
+                    self.context,
+                    collect_sink_input,
+                    std::ptr::addr_of_mut!(query).cast(),
+                )
+            };
+            if operation.is_null() {
+                return Err(self.error("Listing sink inputs"));
+            }
+            let deadline = Instant::now() + CONNECT_TIMEOUT;
+            let mut result = Ok(());
+            // SAFETY: the operation is live until unreferenced below
+            while unsafe { (self.api.operation_get_state)(operation) } == OPERATION_RUNNING {
+                result = remaining(deadline).and_then(|timeout| self.iterate(timeout));
+                if result.is_err() {
+                    break;
+                }
+            }
+            // SAFETY: drops our reference; a pending callback is cancelled
+            // along with the context on failure
+            unsafe { (self.api.operation_unref)(operation) };
+            result.map(|()| query.found)
+        }
+
+        /// Start recording what one sink input plays
+        /// Record one sink input, or the named source
+        fn record(&mut self, sink_input: Option<u32>, device: Option<&CStr>) -> Result<()> {
+            let spec = SampleSpec {
+                format: SAMPLE_S16LE,
+                rate: self.sample_rate,
+                channels: self.channels as u8,
+            };
+            let fragment = (self.sample_rate as u128 * super::AUDIO_FRAME_DURATION.as_millis()
+                / 1000) as u32
+                * self.channels as u32
+                * 2;
+            let attributes = BufferAttr {
+                maxlength: u32::MAX,
+                tlength: u32::MAX,
+                prebuf: u32::MAX,
+                minreq: u32::MAX,
+                fragsize: fragment,
+            };
+            // SAFETY: the context is ready; a null channel map picks the
+            // default for the channel count
+            unsafe {
+                let stream = (self.api.stream_new)(
+                    self.context,
+                    c"Captured audio".as_ptr(),
+                    &spec,
+                    std::ptr::null(),
+                );
+                if stream.is_null() {
+                    return Err(self.error("pa_stream_new"));
+                }
+                self.recordings.push(Recording {
+                    stream,
+                    pending: VecDeque::new(),
+                });
+                let monitored = match sink_input {
+                    Some(index) => (self.api.stream_set_monitor_stream)(stream, index) >= 0,
+                    None => true,
+                };
+                if !monitored
+                    || (self.api.stream_connect_record)(
+                        stream,
+                        device.map_or(std::ptr::null(), CStr::as_ptr),
+                        &attributes,
+                        STREAM_ADJUST_LATENCY,
+                    ) < 0
+                {
+                    return Err(self.error("Starting the recording"));
+                }
+            }
+            Ok(())
+        }
+
+        fn wait_for_streams(&mut self) -> Result<()> {
+            let deadline = Instant::now() + CONNECT_TIMEOUT;
+            loop {
+                let mut ready = true;
+                for recording in &self.recordings {
+                    // SAFETY: the stream is live
+                    match unsafe { (self.api.stream_get_state)(recording.stream) } {
+                        STREAM_READY => {}
+                        STREAM_FAILED | STREAM_TERMINATED => {
+                            return Err(self.error("Starting the recording"))
+                        }
+                        _ => ready = false,
+                    }
+                }
+                if ready {
+                    return Ok(());
+                }
+                self.iterate(remaining(deadline)?)?;
+            }
+        }
+
+        /// Move what every stream has received into its `pending` samples
+        fn drain(&mut self) -> Result<()> {
+            for recording in &mut self.recordings {
+                // SAFETY: the stream is live
+                match unsafe { (self.api.stream_get_state)(recording.stream) } {
+                    STREAM_FAILED | STREAM_TERMINATED => {
+                        return Err(anyhow::anyhow!("The recorded stream ended"))
+                    }
+                    _ => {}
+                }
+                loop {
+                    let mut data: *const c_void = std::ptr::null();
+                    let mut length = 0usize;
+                    // SAFETY: the out pointers are valid; the fragment stays
+                    // valid until pa_stream_drop
+                    unsafe {
+                        if (self.api.stream_peek)(recording.stream, &mut data, &mut length) < 0 {
+                            return Err(anyhow::anyhow!("pa_stream_peek failed"));
+                        }
+                        if length == 0 {
+                            break;
+                        }
+                        if data.is_null() {
+                            // A hole in the stream
+                            recording.pending.extend(std::iter::repeat_n(0, length / 2));
+                        } else {
+                            let bytes = std::slice::from_raw_parts(data.cast::<u8>(), length);
+                            recording.pending.extend(
+                                bytes
+                                    .chunks_exact(2)
+                                    .map(|pair| i16::from_le_bytes([pair[0], pair[1]])),
+                            );
+                        }
+                        (self.api.stream_drop)(recording.stream);
+                    }
+                }
+                trim_pending(&mut recording.pending, self.sample_rate, self.channels);
+            }
+            Ok(())
+        }
+    }
+
+    impl AudioSource for PulseSource {
+        fn read(&mut self, buffer: &mut [i16]) -> Result<()> {
+            let frames = (buffer.len() / self.channels.max(1)) as u64;
+            let deadline = Instant::now()
+                + Duration::from_micros(frames * 1_000_000 / self.sample_rate.max(1) as u64)
+                + READ_SLACK;
+            loop {
+                self.drain()?;
+                let now = Instant::now();
+                if self
+                    .recordings
+                    .iter()
+                    .all(|recording| recording.pending.len() >= buffer.len())
+                    || now >= deadline
+                {
+                    break;
+                }
+                self.iterate(deadline - now)?;
+            }
+            // Mix the streams; one that delivered nothing adds silence
+            buffer.fill(0);
+            for recording in &mut self.recordings {
+                let available = recording.pending.len().min(buffer.len());
+                for (slot, sample) in buffer.iter_mut().zip(recording.pending.drain(..available)) {
+                    *slot = slot.saturating_add(sample);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for PulseSource {
+        fn drop(&mut self) {
+            // SAFETY: everything was created by this source and is released
+            // once, streams before their context
+            unsafe {
+                for recording in &self.recordings {
+                    (self.api.stream_disconnect)(recording.stream);
+                    (self.api.stream_unref)(recording.stream);
+                }
+                if !self.context.is_null() {
+                    (self.api.context_disconnect)(self.context);
+                    (self.api.context_unref)(self.context);
+                }
+                (self.api.mainloop_free)(self.mainloop);
+            }
+        }
+    }
+}
@@ -0,0 +1,274 @@
+//! Session Audio Control
+//!
+//! Keeps host audio capture and the outgoing WebRTC audio track in step with
+//! the session's `AudioCapture` permission. The permission can be granted or
+//! revoked mid-session; the controller then starts or stops the capturer,
+//! adds or removes the track (which renegotiates the connection), and writes
+//! an audit entry for each change.
+
+use crate::logging::{LogEntry, LogLevel, LogManager};
+use crate::screen_capture::{AudioCaptureOptions, AudioCapturer, AudioFrame};
+use crate::session_manager::{Permission, SessionEvent, SessionManager};
+use crate::webrtc_engine::WebRTCEngine;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use tokio::sync::{mpsc, Mutex};
+
+/// Adds and removes the outgoing audio track on a connection
+pub trait AudioTrackControl: Send + Sync {
+    fn set_audio_track_enabled<'a>(
+        &'a self,
+        connection_id: &'a str,
+        enabled: bool,
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
+impl AudioTrackControl for WebRTCEngine {
+    fn set_audio_track_enabled<'a>(
+        &'a self,
+        connection_id: &'a str,
+        enabled: bool,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(WebRTCEngine::set_audio_track_enabled(
+            self,
+            connection_id,
+            enabled,
+        ))
+    }
+}
+
+/// Audio capture for one session's connection
+pub struct SessionAudioController {
+    session_id: String,
+    connection_id: String,
+    options: AudioCaptureOptions,
+    capturer: Mutex<AudioCapturer>,
+    tracks: Arc<dyn AudioTrackControl>,
+    audit_log: Option<Arc<LogManager>>,
+    frame_sender: mpsc::UnboundedSender<AudioFrame>,
+    frame_receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<AudioFrame>>>>,
+}
+
+impl SessionAudioController {
+    pub fn new(
+        session_id: String,
+        connection_id: String,
+        tracks: Arc<dyn AudioTrackControl>,
+    ) -> Self {
+        let (frame_sender, frame_receiver) = mpsc::unbounded_channel();
+        Self {
+            session_id,
+            connection_id,
+            options: AudioCaptureOptions::default(),
+            capturer: Mutex::new(AudioCapturer::new()),
+            tracks,
+            audit_log: None,
+            frame_sender,
+            frame_receiver: Arc::new(Mutex::new(Some(frame_receiver))),
+        }
+    }
+
+    pub fn with_options(mut self, options: AudioCaptureOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Write audio state changes to the given audit log
+    pub fn set_audit_log(&mut self, log_manager: Arc<LogManager>) {
+        self.audit_log = Some(log_manager);
+    }
+
+    /// Take the audio frame receiver (can only be taken once)
+    ///
+    /// Frames from every capture run are delivered here, so the receiver
+    /// survives revoke/grant cycles.
+    pub async fn take_frame_receiver(&self) -> Option<mpsc::UnboundedReceiver<AudioFrame>> {
+        self.frame_receiver.lock().await.take()
+    }
+
+    pub async fn is_active(&self) -> bool {
+        self.capturer.lock().await.is_capturing().await
+    }
+
+    /// Start or stop audio to match the session's current permissions
+    ///
+    /// Returns whether audio is active afterwards.
+    pub async fn apply_permissions(&self, permissions: &[Permission]) -> Result<bool> {
+        let allowed = permissions.contains(&Permission::AudioCapture);
+        let mut capturer = self.capturer.lock().await;
+        if allowed == capturer.is_capturing().await {
+            return Ok(allowed);
+        }
+
+        if allowed {
+            let mut frames = capturer
+                .start_capture(self.options.clone(), permissions)
+                .await?;
+            if let Err(e) = self
+                .tracks
+                .set_audio_track_enabled(&self.connection_id, true)
+                .await
+            {
+                capturer.stop_capture().await;
+                self.audit("Audio capture failed", Some(&e.to_string()));
+                return Err(e);
+            }
+
+            let sender = self.frame_sender.clone();
+            tokio::spawn(async move {
+                while let Some(frame) = frames.recv().await {
+                    if sender.send(frame).is_err() {
+                        break;
+                    }
+                }
+            });
+            self.audit("Audio capture started", None);
+        } else {
+            // Stop capturing even if the track cannot be removed cleanly
+            capturer.stop_capture().await;
+            let result = self
+                .tracks
+                .set_audio_track_enabled(&self.connection_id, false)
+                .await;
+            self.audit(
+                "Audio capture stopped",
+                result.as_ref().err().map(|e| e.to_string()).as_deref(),
+            );
+            result?;
+        }
+        Ok(allowed)
+    }
+
+    /// React to permission changes and the end of the session
+    pub async fn handle_session_event(
+        &self,
+        event: &SessionEvent,
+        sessions: &SessionManager,
+    ) -> Result<()> {
+        match event {
+            SessionEvent::PermissionChanged {
+                session_id,
+                permission: Permission::AudioCapture,
+                ..
+            } if *session_id == self.session_id => {
+                let permissions = sessions
+                    .get_session(session_id)
+                    .map(|s| s.permissions)
+                    .unwrap_or_default();
+                self.apply_permissions(&permissions).await?;
+            }
+            SessionEvent::Ended { session_id, .. } if *session_id == self.session_id => {
+                self.apply_permissions(&[]).await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn audit(&self, message: &str, error: Option<&str>) {
+        tracing::info!("Session {}: {}", self.session_id, message);
+        if let Some(log_manager) = &self.audit_log {
+            let level = if error.is_some() {
+                LogLevel::Warn
+            } else {
+                LogLevel::Info
+            };
+            log_manager.log(
+                LogEntry::new(level, "audit", message)
+                    .with_session(&self.session_id)
+                    .with_metadata(serde_json::json!({
+                        "connection_id": self.connection_id,
+                        "error": error,
+                    })),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_manager::SessionOptions;
+    use std::sync::Mutex as StdMutex;
+
+    #[derive(Default)]
+    struct RecordingTracks {
+        calls: StdMutex<Vec<bool>>,
+    }
+
+    impl AudioTrackControl for RecordingTracks {
+        fn set_audio_track_enabled<'a>(
+            &'a self,
+            _connection_id: &'a str,
+            enabled: bool,
+        ) -> BoxFuture<'a, Result<()>> {
+            self.calls.lock().unwrap().push(enabled);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_grant_and_revoke_mid_session() {
+        let sessions = SessionManager::new("host".to_string());
+        let session = sessions
+            .create_session("viewer".to_string(), SessionOptions::default())
+            .await
+            .unwrap();
+        assert!(!session.permissions.contains(&Permission::AudioCapture));
+
+        let tracks = Arc::new(RecordingTracks::default());
+        let controller = SessionAudioController::new(
+            session.session_id.clone(),
+            "conn-1".to_string(),
+            tracks.clone(),
+        );
+        assert!(!controller
+            .apply_permissions(&session.permissions)
+            .await
+            .unwrap());
+
+        for granted in [true, false] {
+            sessions
+                .set_session_permission(&session.session_id, Permission::AudioCapture, granted)
+                .unwrap();
+            let event = SessionEvent::PermissionChanged {
+                session_id: session.session_id.clone(),
+                permission: Permission::AudioCapture,
+                granted,
+            };
+            controller
+                .handle_session_event(&event, &sessions)
+                .await
+                .unwrap();
+            assert_eq!(controller.is_active().await, granted);
+        }
+        assert_eq!(*tracks.calls.lock().unwrap(), vec![true, false]);
+    }
+
+    #[tokio::test]
+    async fn test_audio_changes_audited() {
+        let log_manager = Arc::new(LogManager::default());
+        let mut controller = SessionAudioController::new(
+            "s1".to_string(),
+            "conn-1".to_string(),
+            Arc::new(RecordingTracks::default()),
+        );
+        controller.set_audit_log(log_manager.clone());
+
+        controller
+            .apply_permissions(&[Permission::AudioCapture])
+            .await
+            .unwrap();
+        let mut frames = controller.take_frame_receiver().await.unwrap();
+        assert!(frames.recv().await.is_some());
+        controller.apply_permissions(&[]).await.unwrap();
+
+        let messages: Vec<String> = log_manager
+            .get_logs(None, None)
+            .into_iter()
+            .map(|entry| entry.message)
+            .collect();
+        assert_eq!(messages, ["Audio capture stopped", "Audio capture started"]);
+    }
+}
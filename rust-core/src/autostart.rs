@@ -0,0 +1,440 @@
+//! Launch on Login
+//!
+//! Registers the host to start when the user logs in, using whichever
+//! mechanism the platform offers: the registry `Run` key or a Task Scheduler
+//! logon task on Windows, a LaunchAgent on macOS, and an XDG autostart entry
+//! or systemd user unit on Linux. Everything is per-user, so no elevation is
+//! needed.
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Identifier used for entry names, task names and unit names
+pub const AUTOSTART_APP_ID: &str = "cec-remote";
+
+const RUN_KEY: &str = r"HKCU\Software\Microsoft\Windows\CurrentVersion\Run";
+
+/// Mechanism used to launch on login
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AutostartMethod {
+    /// `HKCU\...\CurrentVersion\Run` value (Windows)
+    RegistryRunKey,
+    /// Logon-triggered scheduled task (Windows)
+    TaskScheduler,
+    /// `~/Library/LaunchAgents` plist (macOS)
+    LaunchAgent,
+    /// `~/.config/autostart` desktop entry (Linux desktops)
+    XdgAutostart,
+    /// `~/.config/systemd/user` unit wanted by `default.target` (Linux)
+    SystemdUserUnit,
+}
+
+impl AutostartMethod {
+    /// Methods supported on this platform, preferred first
+    pub fn available() -> Vec<AutostartMethod> {
+        #[cfg(target_os = "windows")]
+        {
+            vec![
+                AutostartMethod::RegistryRunKey,
+                AutostartMethod::TaskScheduler,
+            ]
+        }
+        #[cfg(target_os = "macos")]
+        {
+            vec![AutostartMethod::LaunchAgent]
+        }
+        #[cfg(target_os = "linux")]
+        {
+            vec![
+                AutostartMethod::XdgAutostart,
+                AutostartMethod::SystemdUserUnit,
+            ]
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            vec![]
+        }
+    }
+}
+
+/// Why an autostart operation failed
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AutostartError {
+    #[error("Autostart method {0:?} is not supported on this platform")]
+    Unsupported(AutostartMethod),
+    #[error("Home directory could not be determined")]
+    NoHomeDirectory,
+    #[error("Autostart entry could not be written: {0}")]
+    Io(String),
+    #[error("{program} failed: {message}")]
+    CommandFailed { program: String, message: String },
+}
+
+impl From<std::io::Error> for AutostartError {
+    fn from(err: std::io::Error) -> Self {
+        AutostartError::Io(err.to_string())
+    }
+}
+
+/// Current launch-on-login state
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AutostartStatus {
+    /// Methods currently registered; empty when disabled
+    pub enabled_methods: Vec<AutostartMethod>,
+    pub available_methods: Vec<AutostartMethod>,
+}
+
+impl AutostartStatus {
+    pub fn is_enabled(&self) -> bool {
+        !self.enabled_methods.is_empty()
+    }
+}
+
+/// What to launch on login
+#[derive(Debug, Clone)]
+pub struct AutostartConfig {
+    pub app_id: String,
+    pub display_name: String,
+    pub executable: PathBuf,
+    pub args: Vec<String>,
+}
+
+/// Enables, disables and queries launch on login for the current user
+pub struct AutostartManager {
+    config: AutostartConfig,
+    home_dir: PathBuf,
+}
+
+impl AutostartManager {
+    /// Manage autostart for the current user
+    pub fn new(config: AutostartConfig) -> Result<Self, AutostartError> {
+        let home_dir = std::env::var_os("HOME")
+            .or_else(|| std::env::var_os("USERPROFILE"))
+            .map(PathBuf::from)
+            .ok_or(AutostartError::NoHomeDirectory)?;
+        Ok(Self::with_home_dir(config, home_dir))
+    }
+
+    /// Manage autostart entries under a specific home directory
+    pub fn with_home_dir(config: AutostartConfig, home_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            config,
+            home_dir: home_dir.into(),
+        }
+    }
+
+    /// Register `method`; any other registered method is removed so the app
+    /// is not launched twice
+    pub fn enable(&self, method: AutostartMethod) -> Result<AutostartStatus, AutostartError> {
+        if !AutostartMethod::available().contains(&method) {
+            return Err(AutostartError::Unsupported(method));
+        }
+        for other in AutostartMethod::available() {
+            if other != method && self.is_registered(other)? {
+                self.unregister(other)?;
+            }
+        }
+        self.register(method)?;
+        tracing::info!("Autostart enabled via {:?}", method);
+        self.status()
+    }
+
+    /// Remove every registered method
+    pub fn disable(&self) -> Result<AutostartStatus, AutostartError> {
+        for method in AutostartMethod::available() {
+            if self.is_registered(method)? {
+                self.unregister(method)?;
+            }
+        }
+        tracing::info!("Autostart disabled");
+        self.status()
+    }
+
+    pub fn status(&self) -> Result<AutostartStatus, AutostartError> {
+        let available_methods = AutostartMethod::available();
+        let mut enabled_methods = Vec::new();
+        for method in &available_methods {
+            if self.is_registered(*method)? {
+                enabled_methods.push(*method);
+            }
+        }
+        Ok(AutostartStatus {
+            enabled_methods,
+            available_methods,
+        })
+    }
+
+    fn register(&self, method: AutostartMethod) -> Result<(), AutostartError> {
+        match method {
+            AutostartMethod::RegistryRunKey => {
+                let value_name = self.config.app_id.as_str();
+                let command = self.command_line();
+                run(
+                    "reg",
+                    &["add", RUN_KEY, "/v", value_name, "/d", &command, "/f"],
+                )
+            }
+            AutostartMethod::TaskScheduler => {
+                let command = self.command_line();
+                run(
+                    "schtasks",
+                    &[
+                        "/Create",
+                        "/SC",
+                        "ONLOGON",
+                        "/TN",
+                        &self.config.app_id,
+                        "/TR",
+                        &command,
+                        "/F",
+                    ],
+                )
+            }
+            AutostartMethod::LaunchAgent => write_file(&self.launch_agent_path(), &self.plist()),
+            AutostartMethod::XdgAutostart => {
+                write_file(&self.xdg_entry_path(), &self.desktop_entry())
+            }
+            AutostartMethod::SystemdUserUnit => {
+                let unit_path = self.systemd_unit_path();
+                write_file(&unit_path, &self.systemd_unit())?;
+                // The same link `systemctl --user enable` creates, without
+                // needing a running user manager
+                let wants = self.systemd_wants_path();
+                if let Some(dir) = wants.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                if wants.symlink_metadata().is_ok() {
+                    std::fs::remove_file(&wants)?;
+                }
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&unit_path, &wants)?;
+                Ok(())
+            }
+        }
+    }
+
+    fn unregister(&self, method: AutostartMethod) -> Result<(), AutostartError> {
+        match method {
+            AutostartMethod::RegistryRunKey => {
+                run("reg", &["delete", RUN_KEY, "/v", &self.config.app_id, "/f"])
+            }
+            AutostartMethod::TaskScheduler => {
+                run("schtasks", &["/Delete", "/TN", &self.config.app_id, "/F"])
+            }
+            AutostartMethod::LaunchAgent => remove_file(&self.launch_agent_path()),
+            AutostartMethod::XdgAutostart => remove_file(&self.xdg_entry_path()),
+            AutostartMethod::SystemdUserUnit => {
+                remove_file(&self.systemd_wants_path())?;
+                remove_file(&self.systemd_unit_path())
+            }
+        }
+    }
+
+    fn is_registered(&self, method: AutostartMethod) -> Result<bool, AutostartError> {
+        Ok(match method {
+            AutostartMethod::RegistryRunKey => {
+                succeeds("reg", &["query", RUN_KEY, "/v", &self.config.app_id])
+            }
+            AutostartMethod::TaskScheduler => {
+                succeeds("schtasks", &["/Query", "/TN", &self.config.app_id])
+            }
+            AutostartMethod::LaunchAgent => self.launch_agent_path().exists(),
+            AutostartMethod::XdgAutostart => self.xdg_entry_path().exists(),
+            AutostartMethod::SystemdUserUnit => self.systemd_wants_path().exists(),
+        })
+    }
+
+    fn launch_agent_path(&self) -> PathBuf {
+        self.home_dir
+            .join("Library/LaunchAgents")
+            .join(format!("com.{}.host.plist", self.config.app_id))
+    }
+
+    fn xdg_entry_path(&self) -> PathBuf {
+        self.config_dir()
+            .join("autostart")
+            .join(format!("{}.desktop", self.config.app_id))
+    }
+
+    fn systemd_unit_path(&self) -> PathBuf {
+        self.config_dir()
+            .join("systemd/user")
+            .join(format!("{}.service", self.config.app_id))
+    }
+
+    fn systemd_wants_path(&self) -> PathBuf {
+        self.config_dir()
+            .join("systemd/user/default.target.wants")
+            .join(format!("{}.service", self.config.app_id))
+    }
+
+    fn config_dir(&self) -> PathBuf {
+        self.home_dir.join(".config")
+    }
+
+    /// Executable and arguments, each quoted if it contains whitespace
+    fn command_line(&self) -> String {
+        std::iter::once(self.config.executable.to_string_lossy().into_owned())
+            .chain(self.config.args.iter().cloned())
+            .map(|part| {
+                if part.contains(char::is_whitespace) {
+                    format!("\"{}\"", part.replace('"', "\\\""))
+                } else {
+                    part
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn desktop_entry(&self) -> String {
+        format!(
+            "[Desktop Entry]\nType=Application\nName={}\nExec={}\nX-GNOME-Autostart-enabled=true\nNoDisplay=true\n",
+            self.config.display_name,
+            self.command_line()
+        )
+    }
+
+    fn systemd_unit(&self) -> String {
+        format!(
+            "[Unit]\nDescription={}\nAfter=graphical-session.target\n\n[Service]\nExecStart={}\nRestart=on-failure\n\n[Install]\nWantedBy=default.target\n",
+            self.config.display_name,
+            self.command_line()
+        )
+    }
+
+    fn plist(&self) -> String {
+        let arguments: String =
+            std::iter::once(self.config.executable.to_string_lossy().into_owned())
+                .chain(self.config.args.iter().cloned())
+                .map(|arg| format!("    <string>{}</string>\n", xml_escape(&arg)))
+                .collect();
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+  <key>Label</key>
+  <string>com.{}.host</string>
+  <key>ProgramArguments</key>
+  <array>
+{}  </array>
+  <key>RunAtLoad</key>
+  <true/>
+</dict>
+</plist>
+"#,
+            xml_escape(&self.config.app_id),
+            arguments
+        )
+    }
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), AutostartError> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+fn remove_file(path: &Path) -> Result<(), AutostartError> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+        _ => Ok(()),
+    }
+}
+
+fn run(program: &str, args: &[&str]) -> Result<(), AutostartError> {
+    let output =
+        Command::new(program)
+            .args(args)
+            .output()
+            .map_err(|err| AutostartError::CommandFailed {
+                program: program.to_string(),
+                message: err.to_string(),
+            })?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(AutostartError::CommandFailed {
+            program: program.to_string(),
+            message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        })
+    }
+}
+
+fn succeeds(program: &str, args: &[&str]) -> bool {
+    Command::new(program)
+        .args(args)
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn manager() -> (AutostartManager, PathBuf) {
+        let home = std::env::temp_dir().join(format!("cec-autostart-{}", Uuid::new_v4()));
+        let config = AutostartConfig {
+            app_id: AUTOSTART_APP_ID.to_string(),
+            display_name: "CEC Remote Desktop".to_string(),
+            executable: PathBuf::from("/opt/cec remote/host"),
+            args: vec!["--autostart".to_string()],
+        };
+        (AutostartManager::with_home_dir(config, &home), home)
+    }
+
+    #[test]
+    fn test_command_line_and_plist_quoting() {
+        let (manager, _) = manager();
+        assert_eq!(
+            manager.command_line(),
+            "\"/opt/cec remote/host\" --autostart"
+        );
+        let plist = manager.plist();
+        assert!(plist.contains("<string>com.cec-remote.host</string>"));
+        assert!(plist.contains("<string>/opt/cec remote/host</string>"));
+        assert!(plist.contains("<string>--autostart</string>"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_enable_switch_and_disable() {
+        let (manager, home) = manager();
+        assert!(!manager.status().unwrap().is_enabled());
+        assert_eq!(
+            manager.enable(AutostartMethod::LaunchAgent),
+            Err(AutostartError::Unsupported(AutostartMethod::LaunchAgent))
+        );
+
+        let status = manager.enable(AutostartMethod::XdgAutostart).unwrap();
+        assert_eq!(status.enabled_methods, vec![AutostartMethod::XdgAutostart]);
+        let entry = std::fs::read_to_string(manager.xdg_entry_path()).unwrap();
+        assert!(entry.contains("Exec=\"/opt/cec remote/host\" --autostart"));
+
+        // Switching methods removes the previous one
+        let status = manager.enable(AutostartMethod::SystemdUserUnit).unwrap();
+        assert_eq!(
+            status.enabled_methods,
+            vec![AutostartMethod::SystemdUserUnit]
+        );
+        assert!(manager.systemd_unit_path().exists());
+
+        let status = manager.disable().unwrap();
+        assert!(!status.is_enabled());
+        assert!(!manager.systemd_unit_path().exists());
+        std::fs::remove_dir_all(home).ok();
+    }
+}
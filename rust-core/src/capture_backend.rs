@@ -0,0 +1,1737 @@
+//! Platform Capture Backends
+//!
+//! A `CaptureBackend` grabs the pixels of one capture source as tightly
+//! packed BGRA frames at native resolution; scaling to the configured size
+//! happens at encode time. `BackendFrameSource` adapts a backend to the
+//! capture thread's `FrameSource`, opening it on the capture thread itself
+//! and reopening it after a failed grab (display unplugged, mode change) or
+//! when the `CaptureTarget` changes. Region targets are cropped out of the
+//! display frame; window targets open a backend for that window.
+//!
+//! Backends in this build:
+//!
+//! - Linux: X11 (including XWayland) through MIT-SHM `XShmGetImage`,
+//!   falling back to `XGetImage` when shared memory is unavailable
+//! - Windows: DXGI Desktop Duplication, with its dirty rectangles; GDI
+//!   `BitBlt` from the screen DC where duplication cannot be opened (Remote
+//!   Desktop sessions, rotated displays, basic display drivers)
+//! - macOS: CoreGraphics `CGDisplayCreateImage`
+//!
+//! Two preferred APIs are not implemented yet, and their fallbacks are
+//! deliberate: macOS captures through `CGDisplayCreateImage` rather than
+//! ScreenCaptureKit, and a Wayland session without XWayland cannot be
+//! captured at all, since there is no xdg-desktop-portal ScreenCast/PipeWire
+//! backend; opening a display there fails with an error saying so.
+
+use crate::capture_thread::FrameSource;
+use crate::screen_capture::{
+    CaptureOptions, CaptureSource, CaptureTarget, DirtyRect, DisplayInfo, FrameFormat, VideoFrame,
+};
+use anyhow::Result;
+
+#[cfg(target_os = "linux")]
+pub(crate) use x11::{install_error_handler, take_x_error};
+
+/// Platform API that grabs frames of one source
+pub trait CaptureBackend: Send {
+    /// Short name for logs and diagnostics
+    fn name(&self) -> &'static str;
+
+    /// Grab the current contents of the source; may block for up to a frame
+    fn capture(&mut self) -> Result<VideoFrame>;
+}
+
+/// Opens the backend for a capture source and target
+///
+/// Region targets get a backend for the whole display; the frame source
+/// crops its frames.
+pub type BackendOpener = fn(&CaptureSource, &CaptureTarget) -> Result<Box<dyn CaptureBackend>>;
+
+/// Open the best available backend for `source` on this platform
+pub fn open_platform_backend(
+    source: &CaptureSource,
+    target: &CaptureTarget,
+) -> Result<Box<dyn CaptureBackend>> {
+    if let CaptureTarget::Window(window_id) = *target {
+        return open_window_backend(window_id);
+    }
+    let display_id = match source {
+        CaptureSource::Display { display_id } => display_id,
+        CaptureSource::Application { name, .. } => {
+            #[cfg(target_os = "windows")]
+            {
+                // Windows.Graphics.Capture for the application's windows would go here
+            }
+            #[cfg(target_os = "macos")]
+            {
+                // SCContentFilter with the application's windows would go here
+            }
+            return Err(anyhow::anyhow!(
+                "No capture backend for application {} on this platform",
+                name
+            ));
+        }
+    };
+    let displays = crate::display_enum::enumerate_displays()?;
+    let display = resolve_display(&displays, display_id)
+        .ok_or_else(|| anyhow::anyhow!("Display not found: {}", display_id))?;
+
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(x11::X11Backend::open(display)?))
+    }
+    #[cfg(target_os = "windows")]
+    {
+        match dxgi::DxgiBackend::open(display) {
+            Ok(backend) => Ok(Box::new(backend)),
+            Err(e) => {
+                // Remote Desktop sessions, rotated displays and drivers
+                // without duplication support
+                tracing::info!("Desktop Duplication unavailable, using GDI: {}", e);
+                Ok(Box::new(gdi::GdiBackend::open(display)?))
+            }
+        }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // CGDisplayCreateImage stands in for ScreenCaptureKit on every
+        // macOS version
+        Ok(Box::new(quartz::QuartzBackend::open(display)?))
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        Err(anyhow::anyhow!(
+            "No capture backend for display {} on this platform",
+            display.id
+        ))
+    }
+}
+
+fn open_window_backend(window_id: u64) -> Result<Box<dyn CaptureBackend>> {
+    #[cfg(target_os = "linux")]
+    {
+        Ok(Box::new(x11::X11Backend::open_window(window_id)?))
+    }
+    #[cfg(target_os = "windows")]
+    {
+        // Windows.Graphics.Capture with CreateForWindow would go here
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // SCContentFilter(desktopIndependentWindow:) would go here
+    }
+    #[cfg(not(target_os = "linux"))]
+    Err(anyhow::anyhow!(
+        "No capture backend for window {} on this platform",
+        window_id
+    ))
+}
+
+/// The display with `display_id`, or the primary display for an unknown ID
+///
+/// Older clients ask for `display_0` rather than a platform ID.
+pub fn resolve_display<'a>(
+    displays: &'a [DisplayInfo],
+    display_id: &str,
+) -> Option<&'a DisplayInfo> {
+    displays
+        .iter()
+        .find(|d| d.id == display_id)
+        .or_else(|| displays.iter().find(|d| d.is_primary))
+        .or_else(|| displays.first())
+}
+
+/// Frame source that pulls from a platform capture backend
+pub struct BackendFrameSource {
+    source: CaptureSource,
+    opener: BackendOpener,
+    backend: Option<Box<dyn CaptureBackend>>,
+    /// Target the open backend was opened for
+    target: CaptureTarget,
+}
+
+impl BackendFrameSource {
+    pub fn new(source: CaptureSource) -> Self {
+        Self::with_opener(source, open_platform_backend)
+    }
+
+    pub fn with_opener(source: CaptureSource, opener: BackendOpener) -> Self {
+        Self {
+            source,
+            opener,
+            backend: None,
+            target: CaptureTarget::FullDisplay,
+        }
+    }
+
+    /// Name of the open backend, if any
+    pub fn backend_name(&self) -> Option<&'static str> {
+        self.backend.as_ref().map(|backend| backend.name())
+    }
+}
+
+impl FrameSource for BackendFrameSource {
+    fn next_frame(&mut self, options: &CaptureOptions) -> Result<VideoFrame> {
+        if options.target != self.target {
+            self.backend = None;
+            self.target = options.target;
+        }
+        let backend = match &mut self.backend {
+            Some(backend) => backend,
+            None => {
+                let backend = (self.opener)(&self.source, &self.target)?;
+                tracing::info!(
+                    "Capturing {:?} ({:?}) with {}",
+                    self.source,
+                    self.target,
+                    backend.name()
+                );
+                self.backend.insert(backend)
+            }
+        };
+        let result = backend
+            .capture()
+            .and_then(|frame| crop_to_target(frame, &self.target));
+        if result.is_err() {
+            // Reopen on the next frame; the display or window may have changed
+            self.backend = None;
+        }
+        result
+    }
+}
+
+/// Cut a region target out of a full-display BGRA frame
+///
+/// The region is clipped to the frame; one entirely outside it is an error.
+fn crop_to_target(frame: VideoFrame, target: &CaptureTarget) -> Result<VideoFrame> {
+    let CaptureTarget::Region {
+        x,
+        y,
+        width,
+        height,
+    } = *target
+    else {
+        return Ok(frame);
+    };
+    let width = width.min(frame.width.saturating_sub(x));
+    let height = height.min(frame.height.saturating_sub(y));
+    if width == 0 || height == 0 {
+        return Err(anyhow::anyhow!(
+            "Capture region lies outside the {}x{} display",
+            frame.width,
+            frame.height
+        ));
+    }
+    let stride = frame.width as usize * 4;
+    let offset = y as usize * stride + x as usize * 4;
+    let data = pack_rows(
+        &frame.data[offset.min(frame.data.len())..],
+        stride,
+        width,
+        height,
+    )?;
+    // Damage the backend reported is moved into region coordinates
+    let dirty_rects = frame.dirty_rects.map(|rects| {
+        rects
+            .into_iter()
+            .filter_map(|r| {
+                let left = r.x.max(x);
+                let top = r.y.max(y);
+                let right = (r.x + r.width).min(x + width);
+                let bottom = (r.y + r.height).min(y + height);
+                (left < right && top < bottom).then(|| DirtyRect {
+                    x: left - x,
+                    y: top - y,
+                    width: right - left,
+                    height: bottom - top,
+                })
+            })
+            .collect()
+    });
+    Ok(VideoFrame {
+        width,
+        height,
+        data,
+        dirty_rects,
+        ..frame
+    })
+}
+
+/// BGRA frame stamped with the current time; the capture thread assigns IDs
+fn bgra_frame(width: u32, height: u32, data: Vec<u8>) -> VideoFrame {
+    VideoFrame {
+        id: 0,
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64,
+        width,
+        height,
+        data,
+        format: FrameFormat::BGRA,
+        dirty_rects: None,
+    }
+}
+
+/// Copy `height` rows of `width` 4-byte pixels out of a padded buffer
+fn pack_rows(data: &[u8], stride: usize, width: u32, height: u32) -> Result<Vec<u8>> {
+    let row = width as usize * 4;
+    let height = height as usize;
+    if stride < row || data.len() < stride * height.saturating_sub(1) + row {
+        return Err(anyhow::anyhow!(
+            "Captured image buffer too small for {}x{}",
+            width,
+            height
+        ));
+    }
+    if stride == row {
+        return Ok(data[..row * height].to_vec());
+    }
+    let mut packed = Vec::with_capacity(row * height);
+    for y in 0..height {
+        packed.extend_from_slice(&data[y * stride..y * stride + row]);
+    }
+    Ok(packed)
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use super::{bgra_frame, pack_rows, CaptureBackend};
+    use crate::screen_capture::{DisplayInfo, VideoFrame};
+    use anyhow::Result;
+    use std::os::raw::{c_char, c_int, c_long, c_uint, c_ulong, c_void};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Once;
+
+    type Window = c_ulong;
+
+    #[repr(C)]
+    struct Display {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    struct Visual {
+        _private: [u8; 0],
+    }
+
+    /// Leading fields of Xlib's `XImage`; only ever used through pointers
+    #[repr(C)]
+    struct XImage {
+        width: c_int,
+        height: c_int,
+        xoffset: c_int,
+        format: c_int,
+        data: *mut c_char,
+        byte_order: c_int,
+        bitmap_unit: c_int,
+        bitmap_bit_order: c_int,
+        bitmap_pad: c_int,
+        depth: c_int,
+        bytes_per_line: c_int,
+        bits_per_pixel: c_int,
+    }
+
+    #[repr(C)]
+    struct XShmSegmentInfo {
+        shmseg: c_ulong,
+        shmid: c_int,
+        shmaddr: *mut c_char,
+        read_only: c_int,
+    }
+
+    /// Xlib's `XWindowAttributes`
+    #[repr(C)]
+    struct XWindowAttributes {
+        x: c_int,
+        y: c_int,
+        width: c_int,
+        height: c_int,
+        border_width: c_int,
+        depth: c_int,
+        visual: *mut Visual,
+        root: Window,
+        class: c_int,
+        bit_gravity: c_int,
+        win_gravity: c_int,
+        backing_store: c_int,
+        backing_planes: c_ulong,
+        backing_pixel: c_ulong,
+        save_under: c_int,
+        colormap: c_ulong,
+        map_installed: c_int,
+        map_state: c_int,
+        all_event_masks: c_long,
+        your_event_mask: c_long,
+        do_not_propagate_mask: c_long,
+        override_redirect: c_int,
+        screen: *mut c_void,
+    }
+
+    #[repr(C)]
+    struct XErrorEvent {
+        kind: c_int,
+        display: *mut Display,
+        resourceid: c_ulong,
+        serial: c_ulong,
+        error_code: u8,
+        request_code: u8,
+        minor_code: u8,
+    }
+
+    const Z_PIXMAP: c_int = 2;
+    const ALL_PLANES: c_ulong = !0;
+    const IPC_PRIVATE: c_int = 0;
+    const IPC_CREAT: c_int = 0o1000;
+    const IPC_RMID: c_int = 0;
+
+    extern "C" {
+        fn XOpenDisplay(name: *const c_char) -> *mut Display;
+        fn XCloseDisplay(display: *mut Display) -> c_int;
+        fn XDefaultRootWindow(display: *mut Display) -> Window;
+        fn XDefaultScreen(display: *mut Display) -> c_int;
+        fn XDefaultVisual(display: *mut Display, screen: c_int) -> *mut Visual;
+        fn XDefaultDepth(display: *mut Display, screen: c_int) -> c_int;
+        fn XGetWindowAttributes(
+            display: *mut Display,
+            window: Window,
+            attributes: *mut XWindowAttributes,
+        ) -> c_int;
+        fn XSync(display: *mut Display, discard: c_int) -> c_int;
+        fn XSetErrorHandler(
+            handler: Option<unsafe extern "C" fn(*mut Display, *mut XErrorEvent) -> c_int>,
+        ) -> *mut c_void;
+        fn XGetImage(
+            display: *mut Display,
+            drawable: Window,
+            x: c_int,
+            y: c_int,
+            width: c_uint,
+            height: c_uint,
+            plane_mask: c_ulong,
+            format: c_int,
+        ) -> *mut XImage;
+        fn XDestroyImage(image: *mut XImage) -> c_int;
+
+        fn XShmQueryExtension(display: *mut Display) -> c_int;
+        fn XShmCreateImage(
+            display: *mut Display,
+            visual: *mut Visual,
+            depth: c_uint,
+            format: c_int,
+            data: *mut c_char,
+            info: *mut XShmSegmentInfo,
+            width: c_uint,
+            height: c_uint,
+        ) -> *mut XImage;
+        fn XShmAttach(display: *mut Display, info: *mut XShmSegmentInfo) -> c_int;
+        fn XShmDetach(display: *mut Display, info: *mut XShmSegmentInfo) -> c_int;
+        fn XShmGetImage(
+            display: *mut Display,
+            drawable: Window,
+            image: *mut XImage,
+            x: c_int,
+            y: c_int,
+            plane_mask: c_ulong,
+        ) -> c_int;
+
+        fn shmget(key: c_int, size: usize, flags: c_int) -> c_int;
+        fn shmat(id: c_int, addr: *const c_void, flags: c_int) -> *mut c_void;
+        fn shmdt(addr: *const c_void) -> c_int;
+        fn shmctl(id: c_int, cmd: c_int, buf: *mut c_void) -> c_int;
+    }
+
+    /// Set by the error handler; Xlib's default handler exits the process
+    static X_ERROR: AtomicBool = AtomicBool::new(false);
+    static INSTALL_HANDLER: Once = Once::new();
+
+    unsafe extern "C" fn record_error(_display: *mut Display, event: *mut XErrorEvent) -> c_int {
+        X_ERROR.store(true, Ordering::SeqCst);
+        tracing::debug!(
+            "X error {} on request {}",
+            (*event).error_code,
+            (*event).request_code
+        );
+        0
+    }
+
+    /// Route X errors to the recorder instead of Xlib's exiting default
+    pub(crate) fn install_error_handler() {
+        INSTALL_HANDLER.call_once(|| unsafe {
+            XSetErrorHandler(Some(record_error));
+        });
+    }
+
+    /// Whether an X error arrived since the last call, clearing the flag
+    pub(crate) fn take_x_error() -> bool {
+        X_ERROR.swap(false, Ordering::SeqCst)
+    }
+
+    /// Shared-memory image reused for every frame
+    struct ShmImage {
+        image: *mut XImage,
+        info: Box<XShmSegmentInfo>,
+    }
+
+    pub(super) struct X11Backend {
+        display: *mut Display,
+        /// Root window for displays, the client window for window targets
+        drawable: Window,
+        /// Set for window targets, which are checked for resizes
+        window: bool,
+        visual: *mut Visual,
+        depth: c_int,
+        x: c_int,
+        y: c_int,
+        width: u32,
+        height: u32,
+        shm: Option<ShmImage>,
+    }
+
+    // SAFETY: the connection is opened, used and closed by whichever single
+    // thread owns the backend; nothing else holds the pointers
+    unsafe impl Send for X11Backend {}
+
+    impl X11Backend {
+        pub(super) fn open(target: &DisplayInfo) -> Result<Self> {
+            let display = Self::open_display()?;
+            // SAFETY: the display was just opened and is owned by the backend
+            unsafe {
+                let screen = XDefaultScreen(display);
+                let mut backend = Self {
+                    display,
+                    drawable: XDefaultRootWindow(display),
+                    window: false,
+                    visual: XDefaultVisual(display, screen),
+                    depth: XDefaultDepth(display, screen),
+                    x: target.x,
+                    y: target.y,
+                    width: target.width,
+                    height: target.height,
+                    shm: None,
+                };
+                backend.attach_shm();
+                Ok(backend)
+            }
+        }
+
+        /// Capture the contents of a single top-level window
+        pub(super) fn open_window(window_id: u64) -> Result<Self> {
+            let display = Self::open_display()?;
+            // SAFETY: as in `open`; a bad window ID is trapped by the handler
+            unsafe {
+                let mut backend = Self {
+                    display,
+                    drawable: window_id as Window,
+                    window: true,
+                    visual: std::ptr::null_mut(),
+                    depth: 0,
+                    x: 0,
+                    y: 0,
+                    width: 0,
+                    height: 0,
+                    shm: None,
+                };
+                // Dropping the backend closes the display on every error path
+                let attributes = backend
+                    .window_attributes()
+                    .ok_or_else(|| anyhow::anyhow!("Window {} not found", window_id))?;
+                if attributes.width <= 0 || attributes.height <= 0 {
+                    return Err(anyhow::anyhow!("Window {} has no visible area", window_id));
+                }
+                backend.visual = attributes.visual;
+                backend.depth = attributes.depth;
+                backend.width = attributes.width as u32;
+                backend.height = attributes.height as u32;
+                backend.attach_shm();
+                Ok(backend)
+            }
+        }
+
+        fn open_display() -> Result<*mut Display> {
+            if std::env::var_os("DISPLAY").is_none() {
+                return Err(anyhow::anyhow!(
+                    "No X display available for capture; Wayland sessions are only captured through XWayland, as this build has no PipeWire portal backend"
+                ));
+            }
+            install_error_handler();
+            // SAFETY: a null name selects $DISPLAY; the result is checked
+            let display = unsafe { XOpenDisplay(std::ptr::null()) };
+            if display.is_null() {
+                return Err(anyhow::anyhow!("Cannot open X display"));
+            }
+            Ok(display)
+        }
+
+        unsafe fn attach_shm(&mut self) {
+            self.shm = self.create_shm_image();
+            if self.shm.is_none() {
+                tracing::info!("MIT-SHM unavailable, capturing with XGetImage");
+            }
+        }
+
+        unsafe fn window_attributes(&self) -> Option<XWindowAttributes> {
+            let mut attributes = std::mem::MaybeUninit::<XWindowAttributes>::zeroed();
+            X_ERROR.store(false, Ordering::SeqCst);
+            let ok = XGetWindowAttributes(self.display, self.drawable, attributes.as_mut_ptr());
+            XSync(self.display, 0);
+            if ok == 0 || X_ERROR.load(Ordering::SeqCst) {
+                return None;
+            }
+            Some(attributes.assume_init())
+        }
+
+        unsafe fn create_shm_image(&self) -> Option<ShmImage> {
+            if XShmQueryExtension(self.display) == 0 {
+                return None;
+            }
+            let mut info = Box::new(XShmSegmentInfo {
+                shmseg: 0,
+                shmid: -1,
+                shmaddr: std::ptr::null_mut(),
+                read_only: 0,
+            });
+            let image = XShmCreateImage(
+                self.display,
+                self.visual,
+                self.depth as c_uint,
+                Z_PIXMAP,
+                std::ptr::null_mut(),
+                &mut *info,
+                self.width,
+                self.height,
+            );
+            if image.is_null() {
+                return None;
+            }
+            let size = (*image).bytes_per_line as usize * (*image).height as usize;
+            info.shmid = shmget(IPC_PRIVATE, size, IPC_CREAT | 0o600);
+            if info.shmid < 0 {
+                XDestroyImage(image);
+                return None;
+            }
+            let addr = shmat(info.shmid, std::ptr::null(), 0);
+            if addr as isize == -1 {
+                shmctl(info.shmid, IPC_RMID, std::ptr::null_mut());
+                XDestroyImage(image);
+                return None;
+            }
+            info.shmaddr = addr as *mut c_char;
+            (*image).data = info.shmaddr;
+
+            X_ERROR.store(false, Ordering::SeqCst);
+            let attached = XShmAttach(self.display, &mut *info) != 0;
+            XSync(self.display, 0);
+            // The segment goes away once both sides detach
+            shmctl(info.shmid, IPC_RMID, std::ptr::null_mut());
+            if !attached || X_ERROR.load(Ordering::SeqCst) {
+                // Typical for remote X servers
+                shmdt(addr);
+                XDestroyImage(image);
+                return None;
+            }
+            Some(ShmImage { image, info })
+        }
+
+        unsafe fn copy_image(&self, image: *const XImage) -> Result<Vec<u8>> {
+            let image = &*image;
+            if image.bits_per_pixel != 32 || image.data.is_null() {
+                return Err(anyhow::anyhow!(
+                    "Unsupported X image format: {} bits per pixel",
+                    image.bits_per_pixel
+                ));
+            }
+            let stride = image.bytes_per_line as usize;
+            let data =
+                std::slice::from_raw_parts(image.data as *const u8, stride * image.height as usize);
+            pack_rows(data, stride, self.width, self.height)
+        }
+    }
+
+    impl CaptureBackend for X11Backend {
+        fn name(&self) -> &'static str {
+            if self.shm.is_some() {
+                "x11-shm"
+            } else {
+                "x11"
+            }
+        }
+
+        fn capture(&mut self) -> Result<VideoFrame> {
+            // SAFETY: the display and images stay valid for the backend's
+            // lifetime; X errors are caught by the installed handler
+            let data = unsafe {
+                if self.window {
+                    // A resized window needs a new image; the caller reopens
+                    match self.window_attributes() {
+                        Some(a)
+                            if (a.width as u32, a.height as u32) == (self.width, self.height) => {}
+                        Some(_) => return Err(anyhow::anyhow!("Captured window was resized")),
+                        None => return Err(anyhow::anyhow!("Captured window was closed")),
+                    }
+                }
+                X_ERROR.store(false, Ordering::SeqCst);
+                match &self.shm {
+                    Some(shm) => {
+                        let ok = XShmGetImage(
+                            self.display,
+                            self.drawable,
+                            shm.image,
+                            self.x,
+                            self.y,
+                            ALL_PLANES,
+                        ) != 0;
+                        XSync(self.display, 0);
+                        if !ok || X_ERROR.load(Ordering::SeqCst) {
+                            return Err(anyhow::anyhow!("XShmGetImage failed"));
+                        }
+                        self.copy_image(shm.image)?
+                    }
+                    None => {
+                        let image = XGetImage(
+                            self.display,
+                            self.drawable,
+                            self.x,
+                            self.y,
+                            self.width,
+                            self.height,
+                            ALL_PLANES,
+                            Z_PIXMAP,
+                        );
+                        XSync(self.display, 0);
+                        if image.is_null() || X_ERROR.load(Ordering::SeqCst) {
+                            if !image.is_null() {
+                                XDestroyImage(image);
+                            }
+                            return Err(anyhow::anyhow!("XGetImage failed"));
+                        }
+                        let data = self.copy_image(image);
+                        XDestroyImage(image);
+                        data?
+                    }
+                }
+            };
+            Ok(bgra_frame(self.width, self.height, data))
+        }
+    }
+
+    impl Drop for X11Backend {
+        fn drop(&mut self) {
+            // SAFETY: releases what `open` and `create_shm_image` acquired
+            unsafe {
+                if let Some(mut shm) = self.shm.take() {
+                    XShmDetach(self.display, &mut *shm.info);
+                    XSync(self.display, 0);
+                    shmdt(shm.info.shmaddr as *const c_void);
+                    // The SHM destroy hook frees the struct, not the segment
+                    XDestroyImage(shm.image);
+                }
+                XCloseDisplay(self.display);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod gdi {
+    use super::{bgra_frame, CaptureBackend};
+    use crate::screen_capture::{DisplayInfo, VideoFrame};
+    use anyhow::Result;
+    use std::os::raw::c_void;
+
+    type Hdc = isize;
+    type Hbitmap = isize;
+    type Hgdiobj = isize;
+    type Hwnd = isize;
+    type Bool = i32;
+
+    #[repr(C)]
+    struct BitmapInfoHeader {
+        size: u32,
+        width: i32,
+        height: i32,
+        planes: u16,
+        bit_count: u16,
+        compression: u32,
+        size_image: u32,
+        x_pels_per_meter: i32,
+        y_pels_per_meter: i32,
+        clr_used: u32,
+        clr_important: u32,
+    }
+
+    #[repr(C)]
+    struct BitmapInfo {
+        header: BitmapInfoHeader,
+        colors: [u32; 1],
+    }
+
+    const SRCCOPY: u32 = 0x00CC_0020;
+    /// Include layered windows
+    const CAPTUREBLT: u32 = 0x4000_0000;
+    const BI_RGB: u32 = 0;
+    const DIB_RGB_COLORS: u32 = 0;
+
+    extern "system" {
+        fn GetDC(hwnd: Hwnd) -> Hdc;
+        fn ReleaseDC(hwnd: Hwnd, hdc: Hdc) -> i32;
+        fn CreateCompatibleDC(hdc: Hdc) -> Hdc;
+        fn CreateCompatibleBitmap(hdc: Hdc, width: i32, height: i32) -> Hbitmap;
+        fn SelectObject(hdc: Hdc, object: Hgdiobj) -> Hgdiobj;
+        fn BitBlt(
+            dest: Hdc,
+            x: i32,
+            y: i32,
+            width: i32,
+            height: i32,
+            src: Hdc,
+            src_x: i32,
+            src_y: i32,
+            rop: u32,
+        ) -> Bool;
+        fn GetDIBits(
+            hdc: Hdc,
+            bitmap: Hbitmap,
+            start: u32,
+            lines: u32,
+            bits: *mut c_void,
+            info: *mut BitmapInfo,
+            usage: u32,
+        ) -> i32;
+        fn DeleteObject(object: Hgdiobj) -> Bool;
+        fn DeleteDC(hdc: Hdc) -> Bool;
+    }
+
+    pub(super) struct GdiBackend {
+        screen_dc: Hdc,
+        memory_dc: Hdc,
+        bitmap: Hbitmap,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32,
+    }
+
+    impl GdiBackend {
+        pub(super) fn open(target: &DisplayInfo) -> Result<Self> {
+            // SAFETY: each handle is checked and released on drop
+            unsafe {
+                let screen_dc = GetDC(0);
+                if screen_dc == 0 {
+                    return Err(anyhow::anyhow!("GetDC failed"));
+                }
+                let memory_dc = CreateCompatibleDC(screen_dc);
+                let bitmap =
+                    CreateCompatibleBitmap(screen_dc, target.width as i32, target.height as i32);
+                let backend = Self {
+                    screen_dc,
+                    memory_dc,
+                    bitmap,
+                    x: target.x,
+                    y: target.y,
+                    width: target.width,
+                    height: target.height,
+                };
+                if memory_dc == 0 || bitmap == 0 {
+                    return Err(anyhow::anyhow!("Cannot create capture bitmap"));
+                }
+                SelectObject(memory_dc, bitmap);
+                Ok(backend)
+            }
+        }
+    }
+
+    impl CaptureBackend for GdiBackend {
+        fn name(&self) -> &'static str {
+            "gdi"
+        }
+
+        fn capture(&mut self) -> Result<VideoFrame> {
+            let mut data = vec![0u8; self.width as usize * self.height as usize * 4];
+            let mut info = BitmapInfo {
+                header: BitmapInfoHeader {
+                    size: std::mem::size_of::<BitmapInfoHeader>() as u32,
+                    width: self.width as i32,
+                    // Negative height: top-down rows
+                    height: -(self.height as i32),
+                    planes: 1,
+                    bit_count: 32,
+                    compression: BI_RGB,
+                    size_image: 0,
+                    x_pels_per_meter: 0,
+                    y_pels_per_meter: 0,
+                    clr_used: 0,
+                    clr_important: 0,
+                },
+                colors: [0],
+            };
+            // SAFETY: `data` holds exactly width * height 32-bit pixels
+            unsafe {
+                if BitBlt(
+                    self.memory_dc,
+                    0,
+                    0,
+                    self.width as i32,
+                    self.height as i32,
+                    self.screen_dc,
+                    self.x,
+                    self.y,
+                    SRCCOPY | CAPTUREBLT,
+                ) == 0
+                {
+                    return Err(anyhow::anyhow!("BitBlt failed"));
+                }
+                let lines = GetDIBits(
+                    self.memory_dc,
+                    self.bitmap,
+                    0,
+                    self.height,
+                    data.as_mut_ptr() as *mut c_void,
+                    &mut info,
+                    DIB_RGB_COLORS,
+                );
+                if lines != self.height as i32 {
+                    return Err(anyhow::anyhow!("GetDIBits failed"));
+                }
+            }
+            Ok(bgra_frame(self.width, self.height, data))
+        }
+    }
+
+    impl Drop for GdiBackend {
+        fn drop(&mut self) {
+            // SAFETY: releases the handles acquired in `open`
+            unsafe {
+                if self.bitmap != 0 {
+                    DeleteObject(self.bitmap);
+                }
+                if self.memory_dc != 0 {
+                    DeleteDC(self.memory_dc);
+                }
+                ReleaseDC(0, self.screen_dc);
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod dxgi {
+    use super::{bgra_frame, pack_rows, CaptureBackend};
+    use crate::screen_capture::{DirtyRect, DisplayInfo, VideoFrame};
+    use anyhow::Result;
+    use std::ffi::c_void;
+    use std::marker::PhantomData;
+
+    type Hresult = i32;
+    type Bool = i32;
+
+    #[repr(C)]
+    struct Guid {
+        data1: u32,
+        data2: u16,
+        data3: u16,
+        data4: [u8; 8],
+    }
+
+    const IID_IDXGI_FACTORY1: Guid = Guid {
+        data1: 0x770A_AE78,
+        data2: 0xF26F,
+        data3: 0x4DBA,
+        data4: [0xA8, 0x29, 0x25, 0x3C, 0x83, 0xD1, 0xB3, 0x87],
+    };
+    const IID_IDXGI_OUTPUT1: Guid = Guid {
+        data1: 0x00CD_DEA8,
+        data2: 0x939B,
+        data3: 0x4B83,
+        data4: [0xA3, 0x40, 0xA6, 0x85, 0x22, 0x66, 0x66, 0xCC],
+    };
+    const IID_ID3D11_TEXTURE2D: Guid = Guid {
+        data1: 0x6F15_AAF2,
+        data2: 0xD208,
+        data3: 0x4E89,
+        data4: [0x9A, 0xB4, 0x48, 0x95, 0x35, 0xD3, 0x4F, 0x9C],
+    };
+
+    const D3D_DRIVER_TYPE_UNKNOWN: u32 = 0;
+    const D3D11_SDK_VERSION: u32 = 7;
+    const D3D11_USAGE_STAGING: u32 = 3;
+    const D3D11_CPU_ACCESS_READ: u32 = 0x2_0000;
+    const D3D11_MAP_READ: u32 = 1;
+    const DXGI_FORMAT_B8G8R8A8_UNORM: u32 = 87;
+    const DXGI_MODE_ROTATION_UNSPECIFIED: u32 = 0;
+    const DXGI_MODE_ROTATION_IDENTITY: u32 = 1;
+    const DXGI_ERROR_NOT_FOUND: Hresult = 0x887A_0002_u32 as Hresult;
+    const DXGI_ERROR_WAIT_TIMEOUT: Hresult = 0x887A_0027_u32 as Hresult;
+    /// How long a grab waits for the desktop to change
+    const FRAME_TIMEOUT_MS: u32 = 16;
+    /// How long the first grab waits for an image
+    const FIRST_FRAME_TIMEOUT_MS: u32 = 1000;
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct Rect {
+        left: i32,
+        top: i32,
+        right: i32,
+        bottom: i32,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    /// `DXGI_OUTPUT_DESC`
+    #[repr(C)]
+    struct OutputDesc {
+        device_name: [u16; 32],
+        desktop_coordinates: Rect,
+        attached_to_desktop: Bool,
+        rotation: u32,
+        monitor: *mut c_void,
+    }
+
+    /// `DXGI_OUTDUPL_FRAME_INFO`
+    #[repr(C)]
+    #[derive(Default)]
+    struct FrameInfo {
+        last_present_time: i64,
+        last_mouse_update_time: i64,
+        accumulated_frames: u32,
+        rects_coalesced: Bool,
+        protected_content_masked_out: Bool,
+        pointer_position: Point,
+        pointer_visible: Bool,
+        total_metadata_buffer_size: u32,
+        pointer_shape_buffer_size: u32,
+    }
+
+    /// `DXGI_OUTDUPL_MOVE_RECT`
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    struct MoveRect {
+        source: Point,
+        destination: Rect,
+    }
+
+    /// `D3D11_TEXTURE2D_DESC`
+    #[repr(C)]
+    #[derive(Default)]
+    struct Texture2dDesc {
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+        array_size: u32,
+        format: u32,
+        sample_count: u32,
+        sample_quality: u32,
+        usage: u32,
+        bind_flags: u32,
+        cpu_access_flags: u32,
+        misc_flags: u32,
+    }
+
+    /// `D3D11_MAPPED_SUBRESOURCE`
+    #[repr(C)]
+    struct MappedSubresource {
+        data: *mut c_void,
+        row_pitch: u32,
+        depth_pitch: u32,
+    }
+
+    #[repr(C)]
+    struct IUnknownVtbl {
+        query_interface:
+            unsafe extern "system" fn(*mut c_void, *const Guid, *mut *mut c_void) -> Hresult,
+        add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+        release: unsafe extern "system" fn(*mut c_void) -> u32,
+    }
+
+    #[repr(C)]
+    struct IDXGIFactoryVtbl {
+        base: IUnknownVtbl,
+        _object: [usize; 4],
+        enum_adapters: unsafe extern "system" fn(*mut c_void, u32, *mut *mut c_void) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct IDXGIAdapterVtbl {
+        base: IUnknownVtbl,
+        _object: [usize; 4],
+        enum_outputs: unsafe extern "system" fn(*mut c_void, u32, *mut *mut c_void) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct IDXGIOutput1Vtbl {
+        base: IUnknownVtbl,
+        _object: [usize; 4],
+        get_desc: unsafe extern "system" fn(*mut c_void, *mut OutputDesc) -> Hresult,
+        /// The rest of `IDXGIOutput` and the first methods of `IDXGIOutput1`
+        _output: [usize; 14],
+        duplicate_output:
+            unsafe extern "system" fn(*mut c_void, *mut c_void, *mut *mut c_void) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct IDXGIOutputDuplicationVtbl {
+        base: IUnknownVtbl,
+        _object: [usize; 4],
+        _get_desc: usize,
+        acquire_next_frame: unsafe extern "system" fn(
+            *mut c_void,
+            u32,
+            *mut FrameInfo,
+            *mut *mut c_void,
+        ) -> Hresult,
+        get_frame_dirty_rects:
+            unsafe extern "system" fn(*mut c_void, u32, *mut Rect, *mut u32) -> Hresult,
+        get_frame_move_rects:
+            unsafe extern "system" fn(*mut c_void, u32, *mut MoveRect, *mut u32) -> Hresult,
+        /// GetFramePointerShape, MapDesktopSurface, UnMapDesktopSurface
+        _pointer_and_map: [usize; 3],
+        release_frame: unsafe extern "system" fn(*mut c_void) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct ID3D11DeviceVtbl {
+        base: IUnknownVtbl,
+        /// CreateBuffer, CreateTexture1D
+        _create: [usize; 2],
+        create_texture_2d: unsafe extern "system" fn(
+            *mut c_void,
+            *const Texture2dDesc,
+            *const c_void,
+            *mut *mut c_void,
+        ) -> Hresult,
+    }
+
+    #[repr(C)]
+    struct ID3D11DeviceContextVtbl {
+        base: IUnknownVtbl,
+        _device_child: [usize; 4],
+        /// VSSetConstantBuffers through Draw
+        _before_map: [usize; 7],
+        map: unsafe extern "system" fn(
+            *mut c_void,
+            *mut c_void,
+            u32,
+            u32,
+            u32,
+            *mut MappedSubresource,
+        ) -> Hresult,
+        unmap: unsafe extern "system" fn(*mut c_void, *mut c_void, u32),
+        /// PSSetConstantBuffers through CopySubresourceRegion
+        _before_copy: [usize; 31],
+        copy_resource: unsafe extern "system" fn(*mut c_void, *mut c_void, *mut c_void),
+    }
+
+    #[repr(C)]
+    struct ID3D11Texture2DVtbl {
+        base: IUnknownVtbl,
+        _device_child: [usize; 4],
+        _resource: [usize; 3],
+        get_desc: unsafe extern "system" fn(*mut c_void, *mut Texture2dDesc),
+    }
+
+    extern "system" {
+        fn CreateDXGIFactory1(iid: *const Guid, factory: *mut *mut c_void) -> Hresult;
+        fn D3D11CreateDevice(
+            adapter: *mut c_void,
+            driver_type: u32,
+            software: *mut c_void,
+            flags: u32,
+            feature_levels: *const u32,
+            feature_level_count: u32,
+            sdk_version: u32,
+            device: *mut *mut c_void,
+            feature_level: *mut u32,
+            context: *mut *mut c_void,
+        ) -> Hresult;
+    }
+
+    fn check(action: &str, hr: Hresult) -> Result<()> {
+        if hr < 0 {
+            Err(anyhow::anyhow!(
+                "{} failed: HRESULT {:#010x}",
+                action,
+                hr as u32
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Owned reference to a COM interface whose vtable is `V`
+    struct Com<V> {
+        ptr: *mut c_void,
+        _vtbl: PhantomData<*const V>,
+    }
+
+    impl<V> Com<V> {
+        /// # Safety
+        ///
+        /// `ptr` must be null or an owned reference to an interface laid out
+        /// as `V`.
+        unsafe fn from_raw(ptr: *mut c_void) -> Option<Self> {
+            (!ptr.is_null()).then_some(Self {
+                ptr,
+                _vtbl: PhantomData,
+            })
+        }
+
+        /// Take ownership of the object an API call returned
+        ///
+        /// # Safety
+        ///
+        /// As `from_raw`; `hr` is the result of the call that filled `ptr`.
+        unsafe fn returned(action: &str, hr: Hresult, ptr: *mut c_void) -> Result<Self> {
+            check(action, hr)?;
+            Self::from_raw(ptr).ok_or_else(|| anyhow::anyhow!("{} returned nothing", action))
+        }
+
+        fn vtbl(&self) -> &V {
+            // SAFETY: a COM object starts with its vtable pointer
+            unsafe { &**self.ptr.cast::<*const V>() }
+        }
+
+        /// # Safety
+        ///
+        /// `W` must be the vtable layout of the interface `iid` names.
+        unsafe fn query<W>(&self, iid: &Guid) -> Result<Com<W>> {
+            let unknown = &**self.ptr.cast::<*const IUnknownVtbl>();
+            let mut object = std::ptr::null_mut();
+            let hr = (unknown.query_interface)(self.ptr, iid, &mut object);
+            Com::returned("QueryInterface", hr, object)
+        }
+    }
+
+    impl<V> Drop for Com<V> {
+        fn drop(&mut self) {
+            // SAFETY: every COM vtable starts with IUnknown's
+            unsafe { ((**self.ptr.cast::<*const IUnknownVtbl>()).release)(self.ptr) };
+        }
+    }
+
+    /// The adapter and output showing `target`
+    fn find_output(target: &DisplayInfo) -> Result<(Com<IDXGIAdapterVtbl>, Com<IDXGIOutput1Vtbl>)> {
+        let mut factory = std::ptr::null_mut();
+        // SAFETY: each returned object is owned by a `Com`, and enumeration
+        // stops at DXGI_ERROR_NOT_FOUND
+        unsafe {
+            let hr = CreateDXGIFactory1(&IID_IDXGI_FACTORY1, &mut factory);
+            let factory = Com::<IDXGIFactoryVtbl>::returned("CreateDXGIFactory1", hr, factory)?;
+            for adapter_index in 0.. {
+                let mut adapter = std::ptr::null_mut();
+                let hr = (factory.vtbl().enum_adapters)(factory.ptr, adapter_index, &mut adapter);
+                if hr == DXGI_ERROR_NOT_FOUND {
+                    break;
+                }
+                let adapter = Com::<IDXGIAdapterVtbl>::returned("EnumAdapters", hr, adapter)?;
+                for output_index in 0.. {
+                    let mut output = std::ptr::null_mut();
+                    let hr = (adapter.vtbl().enum_outputs)(adapter.ptr, output_index, &mut output);
+                    if hr == DXGI_ERROR_NOT_FOUND {
+                        break;
+                    }
+                    let output = Com::<IUnknownVtbl>::returned("EnumOutputs", hr, output)?;
+                    let output = output.query::<IDXGIOutput1Vtbl>(&IID_IDXGI_OUTPUT1)?;
+                    let mut desc = std::mem::zeroed::<OutputDesc>();
+                    check(
+                        "IDXGIOutput::GetDesc",
+                        (output.vtbl().get_desc)(output.ptr, &mut desc),
+                    )?;
+                    let name_len = desc.device_name.iter().position(|&c| c == 0).unwrap_or(32);
+                    let name = String::from_utf16_lossy(&desc.device_name[..name_len]);
+                    let origin = (desc.desktop_coordinates.left, desc.desktop_coordinates.top);
+                    if name != target.id && origin != (target.x, target.y) {
+                        continue;
+                    }
+                    // Duplication hands out the unrotated image
+                    if !matches!(
+                        desc.rotation,
+                        DXGI_MODE_ROTATION_UNSPECIFIED | DXGI_MODE_ROTATION_IDENTITY
+                    ) {
+                        return Err(anyhow::anyhow!("Display {} is rotated", target.id));
+                    }
+                    return Ok((adapter, output));
+                }
+            }
+        }
+        Err(anyhow::anyhow!(
+            "No DXGI output shows display {}",
+            target.id
+        ))
+    }
+
+    /// DXGI Desktop Duplication of one display
+    ///
+    /// Frames come from the compositor's own copy of the desktop, and its
+    /// dirty and move rectangles become the frame's `dirty_rects`.
+    pub(super) struct DxgiBackend {
+        context: Com<ID3D11DeviceContextVtbl>,
+        device: Com<ID3D11DeviceVtbl>,
+        duplication: Com<IDXGIOutputDuplicationVtbl>,
+        /// CPU-readable copy of the latest desktop image
+        staging: Option<Com<ID3D11Texture2DVtbl>>,
+        width: u32,
+        height: u32,
+    }
+
+    // SAFETY: the D3D11 device is free-threaded, and the immediate context
+    // and duplication are only used by the thread owning the backend
+    unsafe impl Send for DxgiBackend {}
+
+    impl DxgiBackend {
+        pub(super) fn open(target: &DisplayInfo) -> Result<Self> {
+            let (adapter, output) = find_output(target)?;
+            let mut device = std::ptr::null_mut();
+            let mut context = std::ptr::null_mut();
+            let mut duplication = std::ptr::null_mut();
+            // SAFETY: the device is created on the adapter owning the output;
+            // every returned object is owned by a `Com`
+            unsafe {
+                let hr = D3D11CreateDevice(
+                    adapter.ptr,
+                    D3D_DRIVER_TYPE_UNKNOWN,
+                    std::ptr::null_mut(),
+                    0,
+                    std::ptr::null(),
+                    0,
+                    D3D11_SDK_VERSION,
+                    &mut device,
+                    std::ptr::null_mut(),
+                    &mut context,
+                );
+                let device = Com::<ID3D11DeviceVtbl>::returned("D3D11CreateDevice", hr, device)?;
+                let context = Com::from_raw(context)
+                    .ok_or_else(|| anyhow::anyhow!("D3D11CreateDevice returned no context"))?;
+                // Fails on the secure desktop and for another session's display
+                let hr = (output.vtbl().duplicate_output)(output.ptr, device.ptr, &mut duplication);
+                let duplication = Com::returned("IDXGIOutput1::DuplicateOutput", hr, duplication)?;
+                Ok(Self {
+                    context,
+                    device,
+                    duplication,
+                    staging: None,
+                    width: 0,
+                    height: 0,
+                })
+            }
+        }
+
+        /// Copy the acquired desktop image into the staging texture
+        ///
+        /// Returns what changed since the previous copy.
+        fn copy_frame(
+            &mut self,
+            resource: *mut c_void,
+            info: &FrameInfo,
+        ) -> Result<Option<Vec<DirtyRect>>> {
+            // SAFETY: `resource` is the owned surface AcquireNextFrame returned
+            unsafe {
+                let resource = Com::<IUnknownVtbl>::returned("AcquireNextFrame", 0, resource)?;
+                let texture = resource.query::<ID3D11Texture2DVtbl>(&IID_ID3D11_TEXTURE2D)?;
+                let mut desc = Texture2dDesc::default();
+                (texture.vtbl().get_desc)(texture.ptr, &mut desc);
+                if desc.format != DXGI_FORMAT_B8G8R8A8_UNORM {
+                    return Err(anyhow::anyhow!("Unexpected desktop format {}", desc.format));
+                }
+                let resized = (desc.width, desc.height) != (self.width, self.height);
+                if resized {
+                    let staging_desc = Texture2dDesc {
+                        width: desc.width,
+                        height: desc.height,
+                        mip_levels: 1,
+                        array_size: 1,
+                        format: desc.format,
+                        sample_count: 1,
+                        usage: D3D11_USAGE_STAGING,
+                        cpu_access_flags: D3D11_CPU_ACCESS_READ,
+                        ..Texture2dDesc::default()
+                    };
+                    let mut staging = std::ptr::null_mut();
+                    let hr = (self.device.vtbl().create_texture_2d)(
+                        self.device.ptr,
+                        &staging_desc,
+                        std::ptr::null(),
+                        &mut staging,
+                    );
+                    self.staging = Some(Com::returned("CreateTexture2D", hr, staging)?);
+                    self.width = desc.width;
+                    self.height = desc.height;
+                }
+                let dirty_rects = if resized {
+                    None
+                } else {
+                    self.changed_rects(info)
+                };
+                let staging = self
+                    .staging
+                    .as_ref()
+                    .map_or(std::ptr::null_mut(), |s| s.ptr);
+                (self.context.vtbl().copy_resource)(self.context.ptr, staging, texture.ptr);
+                Ok(dirty_rects)
+            }
+        }
+
+        /// Dirty and moved-to rectangles of the acquired frame; `None` when
+        /// they are unavailable
+        fn changed_rects(&self, info: &FrameInfo) -> Option<Vec<DirtyRect>> {
+            if info.accumulated_frames == 0 {
+                // Only the pointer moved
+                return Some(Vec::new());
+            }
+            let size = info.total_metadata_buffer_size;
+            if size == 0 {
+                return None;
+            }
+            let mut moves = vec![MoveRect::default(); size as usize / size_of::<MoveRect>() + 1];
+            let mut dirty = vec![Rect::default(); size as usize / size_of::<Rect>() + 1];
+            let (mut moves_size, mut dirty_size) = (0u32, 0u32);
+            let duplication = self.duplication.vtbl();
+            // SAFETY: each buffer holds the whole metadata size
+            unsafe {
+                if (duplication.get_frame_move_rects)(
+                    self.duplication.ptr,
+                    (moves.len() * size_of::<MoveRect>()) as u32,
+                    moves.as_mut_ptr(),
+                    &mut moves_size,
+                ) < 0
+                    || (duplication.get_frame_dirty_rects)(
+                        self.duplication.ptr,
+                        (dirty.len() * size_of::<Rect>()) as u32,
+                        dirty.as_mut_ptr(),
+                        &mut dirty_size,
+                    ) < 0
+                {
+                    return None;
+                }
+            }
+            moves.truncate(moves_size as usize / size_of::<MoveRect>());
+            dirty.truncate(dirty_size as usize / size_of::<Rect>());
+            let rects = moves
+                .iter()
+                .map(|m| m.destination)
+                .chain(dirty)
+                .filter_map(|r| {
+                    let left = r.left.clamp(0, self.width as i32);
+                    let top = r.top.clamp(0, self.height as i32);
+                    let right = r.right.clamp(0, self.width as i32);
+                    let bottom = r.bottom.clamp(0, self.height as i32);
+                    (left < right && top < bottom).then(|| DirtyRect {
+                        x: left as u32,
+                        y: top as u32,
+                        width: (right - left) as u32,
+                        height: (bottom - top) as u32,
+                    })
+                })
+                .collect();
+            Some(rects)
+        }
+
+        /// Read the staging texture back as a packed BGRA frame
+        fn read_staging(&self, dirty_rects: Option<Vec<DirtyRect>>) -> Result<VideoFrame> {
+            let staging = self
+                .staging
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("No desktop image captured yet"))?;
+            let mut mapped = MappedSubresource {
+                data: std::ptr::null_mut(),
+                row_pitch: 0,
+                depth_pitch: 0,
+            };
+            let context = self.context.vtbl();
+            // SAFETY: the mapping covers `row_pitch * height` bytes until
+            // Unmap
+            unsafe {
+                check(
+                    "ID3D11DeviceContext::Map",
+                    (context.map)(
+                        self.context.ptr,
+                        staging.ptr,
+                        0,
+                        D3D11_MAP_READ,
+                        0,
+                        &mut mapped,
+                    ),
+                )?;
+                let stride = mapped.row_pitch as usize;
+                let bytes = std::slice::from_raw_parts(
+                    mapped.data.cast::<u8>(),
+                    stride * self.height as usize,
+                );
+                let packed = pack_rows(bytes, stride, self.width, self.height);
+                (context.unmap)(self.context.ptr, staging.ptr, 0);
+                let mut frame = bgra_frame(self.width, self.height, packed?);
+                frame.dirty_rects = dirty_rects;
+                Ok(frame)
+            }
+        }
+    }
+
+    impl CaptureBackend for DxgiBackend {
+        fn name(&self) -> &'static str {
+            "dxgi"
+        }
+
+        fn capture(&mut self) -> Result<VideoFrame> {
+            let timeout = if self.staging.is_some() {
+                FRAME_TIMEOUT_MS
+            } else {
+                FIRST_FRAME_TIMEOUT_MS
+            };
+            let mut info = FrameInfo::default();
+            let mut resource = std::ptr::null_mut();
+            // SAFETY: the out pointers are valid; an acquired frame is
+            // released below
+            let hr = unsafe {
+                (self.duplication.vtbl().acquire_next_frame)(
+                    self.duplication.ptr,
+                    timeout,
+                    &mut info,
+                    &mut resource,
+                )
+            };
+            let dirty_rects = if hr == DXGI_ERROR_WAIT_TIMEOUT && self.staging.is_some() {
+                // Nothing changed; the staging texture still holds the image
+                Some(Vec::new())
+            } else {
+                // DXGI_ERROR_ACCESS_LOST (mode change, desktop switch) fails
+                // the grab, so the frame source reopens the duplication
+                check("IDXGIOutputDuplication::AcquireNextFrame", hr)?;
+                let copied = self.copy_frame(resource, &info);
+                // SAFETY: the frame was acquired above
+                unsafe { (self.duplication.vtbl().release_frame)(self.duplication.ptr) };
+                copied?
+            };
+            self.read_staging(dirty_rects)
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod quartz {
+    use super::{bgra_frame, pack_rows, CaptureBackend};
+    use crate::screen_capture::{DisplayInfo, VideoFrame};
+    use anyhow::Result;
+    use std::os::raw::c_void;
+
+    type CgImageRef = *mut c_void;
+    type CgDataProviderRef = *mut c_void;
+    type CfDataRef = *mut c_void;
+
+    extern "C" {
+        fn CGDisplayCreateImage(display: u32) -> CgImageRef;
+        fn CGImageGetWidth(image: CgImageRef) -> usize;
+        fn CGImageGetHeight(image: CgImageRef) -> usize;
+        fn CGImageGetBytesPerRow(image: CgImageRef) -> usize;
+        fn CGImageGetBitsPerPixel(image: CgImageRef) -> usize;
+        fn CGImageGetDataProvider(image: CgImageRef) -> CgDataProviderRef;
+        fn CGDataProviderCopyData(provider: CgDataProviderRef) -> CfDataRef;
+        fn CGImageRelease(image: CgImageRef);
+        fn CFDataGetBytePtr(data: CfDataRef) -> *const u8;
+        fn CFDataGetLength(data: CfDataRef) -> isize;
+        fn CFRelease(object: *const c_void);
+    }
+
+    pub(super) struct QuartzBackend {
+        display: u32,
+    }
+
+    impl QuartzBackend {
+        pub(super) fn open(target: &DisplayInfo) -> Result<Self> {
+            let display = target
+                .id
+                .parse()
+                .map_err(|_| anyhow::anyhow!("Invalid display ID: {}", target.id))?;
+            Ok(Self { display })
+        }
+    }
+
+    impl CaptureBackend for QuartzBackend {
+        fn name(&self) -> &'static str {
+            "core-graphics"
+        }
+
+        fn capture(&mut self) -> Result<VideoFrame> {
+            // SAFETY: every created or copied object is released before
+            // returning, and the byte range comes from CFData itself
+            unsafe {
+                let image = CGDisplayCreateImage(self.display);
+                if image.is_null() {
+                    // Also what happens without the Screen Recording permission
+                    return Err(anyhow::anyhow!("CGDisplayCreateImage failed"));
+                }
+                let width = CGImageGetWidth(image) as u32;
+                let height = CGImageGetHeight(image) as u32;
+                let stride = CGImageGetBytesPerRow(image);
+                let bits = CGImageGetBitsPerPixel(image);
+                let copied = CGDataProviderCopyData(CGImageGetDataProvider(image));
+                CGImageRelease(image);
+                if copied.is_null() {
+                    return Err(anyhow::anyhow!("Cannot read captured image"));
+                }
+                let data = std::slice::from_raw_parts(
+                    CFDataGetBytePtr(copied),
+                    CFDataGetLength(copied).max(0) as usize,
+                );
+                let packed = if bits == 32 {
+                    pack_rows(data, stride, width, height)
+                } else {
+                    Err(anyhow::anyhow!("Unsupported image format: {} bits", bits))
+                };
+                CFRelease(copied as *const c_void);
+                Ok(bgra_frame(width, height, packed?))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdr::HdrCapabilities;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    static OPENED: AtomicU32 = AtomicU32::new(0);
+
+    /// Fails to open once, then yields one frame per open before failing
+    struct FlakyBackend {
+        grabbed: bool,
+    }
+
+    impl CaptureBackend for FlakyBackend {
+        fn name(&self) -> &'static str {
+            "flaky"
+        }
+
+        fn capture(&mut self) -> Result<VideoFrame> {
+            if std::mem::replace(&mut self.grabbed, true) {
+                return Err(anyhow::anyhow!("display went away"));
+            }
+            Ok(bgra_frame(2, 1, vec![0xFF; 8]))
+        }
+    }
+
+    fn open_flaky(
+        _source: &CaptureSource,
+        _target: &CaptureTarget,
+    ) -> Result<Box<dyn CaptureBackend>> {
+        if OPENED.fetch_add(1, Ordering::SeqCst) == 0 {
+            return Err(anyhow::anyhow!("not ready"));
+        }
+        Ok(Box::new(FlakyBackend { grabbed: false }))
+    }
+
+    #[test]
+    fn test_backend_reopened_after_failure() {
+        let mut source = BackendFrameSource::with_opener(
+            CaptureSource::Display {
+                display_id: "display_0".to_string(),
+            },
+            open_flaky,
+        );
+        let options = CaptureOptions::default();
+
+        assert!(source.next_frame(&options).is_err());
+        let frame = source.next_frame(&options).unwrap();
+        assert_eq!((frame.width, frame.height), (2, 1));
+        assert_eq!(frame.data.len(), 8);
+        assert_eq!(frame.format, FrameFormat::BGRA);
+        assert_eq!(source.backend_name(), Some("flaky"));
+
+        assert!(source.next_frame(&options).is_err());
+        assert_eq!(source.backend_name(), None);
+        assert!(source.next_frame(&options).is_ok());
+        assert_eq!(OPENED.load(Ordering::SeqCst), 3);
+    }
+
+    static REOPENED: AtomicU32 = AtomicU32::new(0);
+
+    /// A 4x3 display whose pixels hold their own index
+    struct IndexedBackend;
+
+    impl CaptureBackend for IndexedBackend {
+        fn name(&self) -> &'static str {
+            "indexed"
+        }
+
+        fn capture(&mut self) -> Result<VideoFrame> {
+            let data = (0..12u8).flat_map(|i| [i; 4]).collect();
+            Ok(bgra_frame(4, 3, data))
+        }
+    }
+
+    fn open_indexed(
+        _source: &CaptureSource,
+        _target: &CaptureTarget,
+    ) -> Result<Box<dyn CaptureBackend>> {
+        REOPENED.fetch_add(1, Ordering::SeqCst);
+        Ok(Box::new(IndexedBackend))
+    }
+
+    #[test]
+    fn test_region_cropped_and_target_change_reopens() {
+        let mut source = BackendFrameSource::with_opener(
+            CaptureSource::Display {
+                display_id: "display_0".to_string(),
+            },
+            open_indexed,
+        );
+        let mut options = CaptureOptions::default();
+        assert_eq!(source.next_frame(&options).unwrap().width, 4);
+
+        // Clipped to the frame: columns 2..4 of rows 1..3
+        options.target = CaptureTarget::Region {
+            x: 2,
+            y: 1,
+            width: 10,
+            height: 10,
+        };
+        let frame = source.next_frame(&options).unwrap();
+        assert_eq!((frame.width, frame.height), (2, 2));
+        let pixels: Vec<u8> = frame.data.chunks(4).map(|p| p[0]).collect();
+        assert_eq!(pixels, [6, 7, 10, 11]);
+        source.next_frame(&options).unwrap();
+        assert_eq!(REOPENED.load(Ordering::SeqCst), 2);
+
+        options.target = CaptureTarget::Region {
+            x: 4,
+            y: 0,
+            width: 1,
+            height: 1,
+        };
+        assert!(source.next_frame(&options).is_err());
+    }
+
+    #[test]
+    fn test_rows_packed_and_display_resolved() {
+        // 2x2 pixels with 4 bytes of row padding
+        let padded: Vec<u8> = (0..24).collect();
+        let packed = pack_rows(&padded, 12, 2, 2).unwrap();
+        assert_eq!(
+            packed,
+            [(0..8).collect::<Vec<u8>>(), (12..20).collect()].concat()
+        );
+        assert!(pack_rows(&padded, 12, 4, 2).is_err());
+
+        let display = |id: &str, is_primary| DisplayInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            width: 1920,
+            height: 1080,
+            is_primary,
+            refresh_rate: 60,
+            x: 0,
+            y: 0,
+            scale_factor: 1.0,
+            hdr: HdrCapabilities::default(),
+        };
+        let displays = [display("HDMI-1", false), display("eDP-1", true)];
+        assert_eq!(resolve_display(&displays, "HDMI-1").unwrap().id, "HDMI-1");
+        assert_eq!(resolve_display(&displays, "display_0").unwrap().id, "eDP-1");
+        assert!(resolve_display(&[], "display_0").is_none());
+    }
+}
@@ -0,0 +1,348 @@
+//! Adaptive Capture Rate
+//!
+//! A static desktop does not need to be captured 30 times a second. The
+//! capture thread scores how much each frame moved and lowers the capture
+//! rate step by step while the screen stays still, down to a floor. Motion
+//! or local input restores the configured rate at once.
+//!
+//! Two hysteresis bands keep the rate from oscillating: a frame has to move
+//! more than `motion_threshold` to count as motion but less than the lower
+//! `still_threshold` to count as still, and the rate only steps down after
+//! a full `settle_ms` of stillness per step.
+//!
+//! Motion is the dirty fraction of the frame when damage tracking reported
+//! rects; otherwise a sparse sample of pixels is compared with the previous
+//! frame's.
+
+use crate::screen_capture::{FrameFormat, VideoFrame};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Every this many bytes one pixel is sampled (prime, to avoid aligning
+/// with row strides)
+const SAMPLE_STRIDE: usize = 4 * 97;
+
+/// Window over which the effective capture rate is measured
+const EFFECTIVE_RATE_WINDOW: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdaptiveRateOptions {
+    pub enabled: bool,
+    /// Lowest capture rate while the screen is still
+    pub floor_fps: u32,
+    /// Changed fraction of the frame above which it counts as motion
+    pub motion_threshold: f64,
+    /// Changed fraction below which it counts as still
+    pub still_threshold: f64,
+    /// Stillness required before each step down
+    pub settle_ms: u64,
+}
+
+impl Default for AdaptiveRateOptions {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            floor_fps: 5,
+            motion_threshold: 0.002,
+            still_threshold: 0.0005,
+            settle_ms: 1000,
+        }
+    }
+}
+
+impl AdaptiveRateOptions {
+    /// Reject thresholds that leave no hysteresis band
+    pub fn validate(&self) -> Result<()> {
+        if self.floor_fps == 0 {
+            return Err(anyhow::anyhow!("Capture rate floor must be at least 1 fps"));
+        }
+        if !(0.0..=1.0).contains(&self.motion_threshold)
+            || !(0.0..=self.motion_threshold).contains(&self.still_threshold)
+        {
+            return Err(anyhow::anyhow!(
+                "Still threshold ({}) must not exceed motion threshold ({}), both within 0..=1",
+                self.still_threshold,
+                self.motion_threshold
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Cheap per-frame motion score in `0.0..=1.0`
+#[derive(Debug, Default)]
+pub struct MotionEstimator {
+    samples: Vec<u8>,
+    size: Option<(u32, u32, FrameFormat)>,
+}
+
+impl MotionEstimator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Changed fraction of `frame` relative to the previous one
+    ///
+    /// Frames without a previous frame to compare with score 1.0.
+    pub fn score(&mut self, frame: &VideoFrame) -> f64 {
+        let current = Some((frame.width, frame.height, frame.format));
+        let comparable = self.size == current;
+        self.size = current;
+
+        if let Some(rects) = &frame.dirty_rects {
+            // Keep the samples fresh for frames that arrive without rects
+            self.resample(frame);
+            let area = frame.width as f64 * frame.height as f64;
+            if area == 0.0 {
+                return 0.0;
+            }
+            let dirty: f64 = rects.iter().map(|r| r.width as f64 * r.height as f64).sum();
+            return (dirty / area).min(1.0);
+        }
+
+        let previous = std::mem::take(&mut self.samples);
+        self.resample(frame);
+        if !comparable || previous.len() != self.samples.len() || self.samples.is_empty() {
+            return 1.0;
+        }
+        let changed = previous
+            .chunks_exact(4)
+            .zip(self.samples.chunks_exact(4))
+            .filter(|(a, b)| a != b)
+            .count();
+        changed as f64 / (self.samples.len() / 4) as f64
+    }
+
+    fn resample(&mut self, frame: &VideoFrame) {
+        self.samples.clear();
+        self.samples.extend(
+            frame
+                .data
+                .chunks_exact(4)
+                .step_by(SAMPLE_STRIDE / 4)
+                .flatten(),
+        );
+    }
+}
+
+/// Capture rate statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CaptureRateStats {
+    /// Rate the capture loop is currently running at
+    pub current_fps: u32,
+    /// Frames actually captured per second over the last two seconds
+    pub effective_fps: f64,
+    /// Immediate returns to the configured rate
+    pub ramp_ups: u64,
+    pub step_downs: u64,
+}
+
+/// Picks the capture rate for the next frame
+#[derive(Debug)]
+pub struct CaptureRateController {
+    options: AdaptiveRateOptions,
+    current_fps: Option<u32>,
+    still_since: Option<Instant>,
+    captures: VecDeque<Instant>,
+    ramp_ups: u64,
+    step_downs: u64,
+}
+
+impl CaptureRateController {
+    pub fn new(options: AdaptiveRateOptions) -> Self {
+        Self {
+            options,
+            current_fps: None,
+            still_since: None,
+            captures: VecDeque::new(),
+            ramp_ups: 0,
+            step_downs: 0,
+        }
+    }
+
+    pub fn set_options(&mut self, options: AdaptiveRateOptions) {
+        self.options = options;
+    }
+
+    /// Rate to capture at, given the configured `target_fps`
+    pub fn current_fps(&self, target_fps: u32) -> u32 {
+        let target = target_fps.max(1);
+        if !self.options.enabled {
+            return target;
+        }
+        self.current_fps
+            .unwrap_or(target)
+            .clamp(self.options.floor_fps.min(target), target)
+    }
+
+    /// Account one captured frame with its motion score
+    ///
+    /// Returns true when the rate changed.
+    pub fn on_frame(&mut self, score: f64, target_fps: u32, now: Instant) -> bool {
+        self.captures.push_back(now);
+        while self
+            .captures
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) > EFFECTIVE_RATE_WINDOW)
+        {
+            self.captures.pop_front();
+        }
+
+        if score > self.options.motion_threshold {
+            return self.ramp_up(target_fps);
+        }
+        if score >= self.options.still_threshold {
+            // Inside the band: neither motion nor stillness
+            self.still_since = None;
+            return false;
+        }
+
+        let still_since = *self.still_since.get_or_insert(now);
+        let settle = Duration::from_millis(self.options.settle_ms);
+        if !self.options.enabled || now.saturating_duration_since(still_since) < settle {
+            return false;
+        }
+        let current = self.current_fps(target_fps);
+        let lowered = (current / 2).max(self.options.floor_fps.min(target_fps.max(1)));
+        // Each further step needs its own settle period
+        self.still_since = Some(now);
+        if lowered == current {
+            return false;
+        }
+        self.current_fps = Some(lowered);
+        self.step_downs += 1;
+        true
+    }
+
+    /// Local input is about to change the screen; returns true when the
+    /// rate changed
+    pub fn on_input(&mut self, target_fps: u32) -> bool {
+        self.ramp_up(target_fps)
+    }
+
+    fn ramp_up(&mut self, target_fps: u32) -> bool {
+        self.still_since = None;
+        let was = self.current_fps(target_fps);
+        self.current_fps = None;
+        if was == self.current_fps(target_fps) {
+            return false;
+        }
+        self.ramp_ups += 1;
+        true
+    }
+
+    pub fn stats(&self, target_fps: u32) -> CaptureRateStats {
+        let effective_fps = match (self.captures.front(), self.captures.back()) {
+            (Some(first), Some(last)) if self.captures.len() > 1 => {
+                let span = last.saturating_duration_since(*first).as_secs_f64();
+                if span > 0.0 {
+                    (self.captures.len() - 1) as f64 / span
+                } else {
+                    0.0
+                }
+            }
+            _ => 0.0,
+        };
+        CaptureRateStats {
+            current_fps: self.current_fps(target_fps),
+            effective_fps,
+            ramp_ups: self.ramp_ups,
+            step_downs: self.step_downs,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen_capture::DirtyRect;
+
+    fn frame(fill: u8, dirty_rects: Option<Vec<DirtyRect>>) -> VideoFrame {
+        VideoFrame {
+            id: 0,
+            timestamp: 0,
+            width: 100,
+            height: 100,
+            data: vec![fill; 100 * 100 * 4],
+            format: FrameFormat::BGRA,
+            dirty_rects,
+        }
+    }
+
+    #[test]
+    fn test_motion_score() {
+        let mut estimator = MotionEstimator::new();
+        assert_eq!(estimator.score(&frame(0, None)), 1.0);
+        assert_eq!(estimator.score(&frame(0, None)), 0.0);
+        assert_eq!(estimator.score(&frame(1, None)), 1.0);
+
+        let rect = DirtyRect {
+            x: 0,
+            y: 0,
+            width: 10,
+            height: 10,
+        };
+        assert_eq!(estimator.score(&frame(1, Some(vec![rect]))), 0.01);
+        assert_eq!(estimator.score(&frame(1, Some(Vec::new()))), 0.0);
+    }
+
+    #[test]
+    fn test_rate_steps_down_when_still_and_ramps_up_on_motion() {
+        let mut controller = CaptureRateController::new(AdaptiveRateOptions::default());
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert_eq!(controller.current_fps(30), 30);
+
+        // Still frames: one step per settle period, down to the floor
+        let mut changes = Vec::new();
+        for ms in (0..=5000).step_by(100) {
+            if controller.on_frame(0.0, 30, at(ms)) {
+                changes.push((ms, controller.current_fps(30)));
+            }
+        }
+        assert_eq!(changes, vec![(1000, 15), (2000, 7), (3000, 5)]);
+
+        // A score inside the hysteresis band neither ramps up nor settles
+        assert!(!controller.on_frame(0.001, 30, at(5100)));
+        assert_eq!(controller.current_fps(30), 5);
+
+        assert!(controller.on_frame(0.05, 30, at(5200)));
+        assert_eq!(controller.current_fps(30), 30);
+
+        assert!(!controller.on_frame(0.0, 30, at(5300)));
+        assert!(controller.on_frame(0.0, 30, at(6300)));
+        assert!(controller.on_input(30));
+        assert!(!controller.on_input(30));
+
+        let stats = controller.stats(30);
+        assert_eq!(stats.current_fps, 30);
+        assert_eq!(stats.ramp_ups, 2);
+        assert_eq!(stats.step_downs, 4);
+        // Twelve frames in the last two seconds
+        assert_eq!(stats.effective_fps, 5.5);
+    }
+
+    #[test]
+    fn test_disabled_keeps_target_and_validation() {
+        let mut controller = CaptureRateController::new(AdaptiveRateOptions {
+            enabled: false,
+            ..Default::default()
+        });
+        let start = Instant::now();
+        for s in 0..5 {
+            controller.on_frame(0.0, 30, start + Duration::from_secs(s));
+        }
+        assert_eq!(controller.current_fps(30), 30);
+
+        assert!(AdaptiveRateOptions {
+            still_threshold: 0.01,
+            ..Default::default()
+        }
+        .validate()
+        .is_err());
+        assert!(AdaptiveRateOptions::default().validate().is_ok());
+    }
+}
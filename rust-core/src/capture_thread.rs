@@ -0,0 +1,535 @@
+//! Capture Threads
+//!
+//! Platform capture and encode calls block, sometimes for a whole frame
+//! interval or longer (DXGI `AcquireNextFrame`, ScreenCaptureKit callbacks,
+//! PipeWire buffers, hardware encoder submits). Running them inside tokio
+//! tasks can starve the runtime, so each capture run gets a dedicated OS
+//! thread that hands frames to async code through a bounded channel. When the
+//! consumer falls behind, new frames are dropped instead of queuing stale
+//! ones.
+//!
+//! A supervisor thread joins the worker; if the worker panics, the panic is
+//! recorded and the worker is restarted with a fresh frame source, up to
+//! `MAX_CAPTURE_RESTARTS` times.
+
+use crate::capture_rate::{CaptureRateController, MotionEstimator};
+use crate::damage::DamageTracker;
+use crate::frame_processing::FramePipeline;
+use crate::hdr;
+use crate::metrics::{Counter, MetricsRegistry};
+use crate::screen_capture::{CaptureOptions, CaptureSource, VideoFrame};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, RwLock};
+
+/// Frames buffered between the capture thread and its consumer
+pub const CAPTURE_CHANNEL_CAPACITY: usize = 4;
+
+/// Restarts allowed before the capture run is declared failed
+pub const MAX_CAPTURE_RESTARTS: u32 = 5;
+
+/// Delay before restarting a crashed worker, multiplied by the restart count
+const RESTART_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Blocking source of captured (and encoded) frames
+pub trait FrameSource: Send {
+    /// Produce the next frame; may block until one is available
+    fn next_frame(&mut self, options: &CaptureOptions) -> Result<VideoFrame>;
+
+    /// Make the next frame a keyframe; sources that do not encode ignore it
+    fn request_keyframe(&mut self) {}
+}
+
+/// Creates a frame source for each (re)started worker
+pub type FrameSourceFactory = Arc<dyn Fn(&CaptureSource) -> Box<dyn FrameSource> + Send + Sync>;
+
+/// Capture thread state reported in diagnostics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CaptureThreadHealth {
+    pub running: bool,
+    pub restarts: u32,
+    /// Restart limit exhausted; capture stopped
+    pub failed: bool,
+    pub last_panic: Option<String>,
+    pub last_error: Option<String>,
+    pub frames_captured: u64,
+    /// Frames dropped because the consumer fell behind
+    pub frames_dropped: u64,
+    /// Frames with no changes since the previous one
+    #[serde(default)]
+    pub frames_unchanged: u64,
+    /// Rate the adaptive controller currently captures at
+    #[serde(default)]
+    pub capture_fps: u32,
+    /// Frames actually captured per second, recently
+    #[serde(default)]
+    pub effective_fps: f64,
+    /// Times motion or input restored the configured rate
+    #[serde(default)]
+    pub rate_ramp_ups: u64,
+}
+
+/// Shared capture thread health
+///
+/// Lifecycle fields sit behind a lock; the per-frame counts are atomics so
+/// the capture loop never takes it.
+#[derive(Debug, Clone, Default)]
+pub struct CaptureHealthHandle {
+    state: Arc<std::sync::RwLock<CaptureThreadHealth>>,
+    frames_captured: Arc<Counter>,
+    frames_dropped: Arc<Counter>,
+    frames_unchanged: Arc<Counter>,
+}
+
+impl CaptureHealthHandle {
+    /// Health whose frame counts are also reported through `registry`
+    pub fn new(registry: &MetricsRegistry) -> Self {
+        Self {
+            state: Arc::default(),
+            frames_captured: registry.counter("capture.frames_captured"),
+            frames_dropped: registry.counter("capture.frames_dropped"),
+            frames_unchanged: registry.counter("capture.frames_unchanged"),
+        }
+    }
+
+    pub fn snapshot(&self) -> CaptureThreadHealth {
+        let mut health = self
+            .state
+            .read()
+            .map(|health| health.clone())
+            .unwrap_or_default();
+        health.frames_captured = self.frames_captured.get();
+        health.frames_dropped = self.frames_dropped.get();
+        health.frames_unchanged = self.frames_unchanged.get();
+        health
+    }
+
+    pub fn update(&self, update: impl FnOnce(&mut CaptureThreadHealth)) {
+        if let Ok(mut health) = self.state.write() {
+            update(&mut health);
+        }
+    }
+
+    fn reset(&self) {
+        self.frames_captured.reset();
+        self.frames_dropped.reset();
+        self.frames_unchanged.reset();
+        self.update(|health| {
+            *health = CaptureThreadHealth {
+                running: true,
+                ..Default::default()
+            }
+        });
+    }
+}
+
+/// Everything a capture worker needs
+#[derive(Clone)]
+pub(crate) struct CaptureThreadContext {
+    pub capturing: Arc<AtomicBool>,
+    pub paused: Arc<AtomicBool>,
+    /// Set by `ScreenCapturer::request_keyframe`, cleared once forwarded
+    pub keyframe_requested: Arc<AtomicBool>,
+    /// Set by `ScreenCapturer::notify_input`, cleared by the capture loop
+    pub input_activity: Arc<AtomicBool>,
+    pub options: Arc<RwLock<CaptureOptions>>,
+    pub frame_counter: Arc<AtomicU64>,
+    pub health: CaptureHealthHandle,
+    pub sender: mpsc::Sender<VideoFrame>,
+    pub capture_source: CaptureSource,
+    pub source: FrameSourceFactory,
+    /// Pre-encode processors, run on every frame before damage tracking
+    pub processors: FramePipeline,
+}
+
+impl CaptureThreadContext {
+    fn update_health(&self, update: impl FnOnce(&mut CaptureThreadHealth)) {
+        self.health.update(update);
+    }
+}
+
+/// Start the supervisor thread, which runs and restarts the capture worker
+pub(crate) fn spawn_capture_supervisor(context: CaptureThreadContext) -> Result<()> {
+    context.health.reset();
+    std::thread::Builder::new()
+        .name("cec-capture-supervisor".to_string())
+        .spawn(move || supervise(context))?;
+    Ok(())
+}
+
+fn supervise(context: CaptureThreadContext) {
+    let mut restarts = 0;
+    loop {
+        let worker_context = context.clone();
+        let worker = std::thread::Builder::new()
+            .name("cec-capture".to_string())
+            .spawn(move || capture_loop(&worker_context));
+
+        let panic = match worker {
+            Ok(handle) => match handle.join() {
+                Ok(()) => break,
+                Err(panic) => panic_message(panic.as_ref()),
+            },
+            Err(e) => {
+                tracing::error!("Failed to spawn capture thread: {}", e);
+                context.update_health(|health| {
+                    health.failed = true;
+                    health.last_error = Some(e.to_string());
+                });
+                break;
+            }
+        };
+
+        restarts += 1;
+        tracing::error!(
+            "Capture thread panicked ({}), restart {}/{}",
+            panic,
+            restarts,
+            MAX_CAPTURE_RESTARTS
+        );
+        context.update_health(|health| {
+            health.restarts = restarts;
+            health.last_panic = Some(panic);
+        });
+        if restarts >= MAX_CAPTURE_RESTARTS {
+            context.update_health(|health| health.failed = true);
+            break;
+        }
+
+        std::thread::sleep(RESTART_BACKOFF * restarts);
+        if !context.capturing.load(Ordering::SeqCst) {
+            break;
+        }
+    }
+    context.update_health(|health| health.running = false);
+}
+
+fn capture_loop(context: &CaptureThreadContext) {
+    let mut source = (context.source)(&context.capture_source);
+    let mut damage = DamageTracker::new();
+    let mut motion = MotionEstimator::new();
+    let mut rate = CaptureRateController::new(Default::default());
+    let mut stats_reported = Instant::now();
+
+    while context.capturing.load(Ordering::SeqCst) {
+        let options = context.options.blocking_read().clone();
+        rate.set_options(options.adaptive_rate);
+        if context.input_activity.swap(false, Ordering::AcqRel) {
+            rate.on_input(options.frame_rate);
+        }
+        let frame_interval =
+            Duration::from_millis(1000 / rate.current_fps(options.frame_rate) as u64);
+        let started = Instant::now();
+
+        // While paused, produce nothing rather than black frames
+        if !context.paused.load(Ordering::SeqCst) {
+            if context.keyframe_requested.swap(false, Ordering::SeqCst) {
+                source.request_keyframe();
+                // A keyframe repaints everything anyway
+                damage.reset();
+            }
+            let captured = source
+                .next_frame(&options)
+                .and_then(|frame| hdr::convert_for_output(frame, &options.hdr))
+                .map(|mut frame| {
+                    frame.id = context.frame_counter.fetch_add(1, Ordering::SeqCst) + 1;
+                    // Before damage tracking, which must describe the frame sent
+                    context.processors.process(frame)
+                });
+            match captured {
+                Ok(Some(mut frame)) => {
+                    if options.damage_tracking {
+                        damage.track(&mut frame);
+                        if frame.dirty_rects.as_ref().is_some_and(Vec::is_empty) {
+                            context.health.frames_unchanged.increment();
+                        }
+                    } else {
+                        frame.dirty_rects = None;
+                    }
+                    rate.on_frame(motion.score(&frame), options.frame_rate, started);
+                    match context.sender.try_send(frame) {
+                        Ok(()) => context.health.frames_captured.increment(),
+                        Err(mpsc::error::TrySendError::Full(_)) => {
+                            context.health.frames_dropped.increment();
+                            // Damage is relative to the last frame the
+                            // consumer saw, which this one now is not
+                            damage.reset();
+                        }
+                        // Consumer is gone; nothing left to capture for
+                        Err(mpsc::error::TrySendError::Closed(_)) => break,
+                    }
+                }
+                // A processor that must not be bypassed failed on this frame
+                Ok(None) => {}
+                Err(e) => {
+                    tracing::warn!("Frame capture failed: {}", e);
+                    context.update_health(|health| health.last_error = Some(e.to_string()));
+                }
+            }
+        }
+
+        if stats_reported.elapsed() >= Duration::from_secs(1) {
+            stats_reported = Instant::now();
+            let stats = rate.stats(options.frame_rate);
+            context.update_health(|health| {
+                health.capture_fps = stats.current_fps;
+                health.effective_fps = stats.effective_fps;
+                health.rate_ramp_ups = stats.ramp_ups;
+            });
+        }
+
+        sleep_until_next_frame(context, started + frame_interval);
+    }
+}
+
+/// Sleep until `deadline`, waking early when input arrives so a lowered
+/// capture rate ramps up without waiting out the long interval
+fn sleep_until_next_frame(context: &CaptureThreadContext, deadline: Instant) {
+    const INPUT_POLL: Duration = Duration::from_millis(10);
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() || context.input_activity.load(Ordering::Acquire) {
+            return;
+        }
+        std::thread::sleep(remaining.min(INPUT_POLL));
+    }
+}
+
+pub(crate) fn panic_message(panic: &(dyn std::any::Any + Send)) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown panic".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen_capture::{FrameFormat, ScreenCapturer};
+
+    /// Produces a solid frame at the requested size
+    struct SolidSource;
+
+    impl FrameSource for SolidSource {
+        fn next_frame(&mut self, options: &CaptureOptions) -> Result<VideoFrame> {
+            Ok(VideoFrame {
+                id: 0,
+                timestamp: 0,
+                width: options.width,
+                height: options.height,
+                data: vec![0x80; options.width as usize * options.height as usize * 4],
+                format: FrameFormat::BGRA,
+                dirty_rects: None,
+            })
+        }
+    }
+
+    /// Panics on its first frame, for the first instance only
+    struct CrashOnceSource {
+        crashed: Arc<AtomicBool>,
+    }
+
+    impl FrameSource for CrashOnceSource {
+        fn next_frame(&mut self, options: &CaptureOptions) -> Result<VideoFrame> {
+            if !self.crashed.swap(true, Ordering::SeqCst) {
+                panic!("capture driver crashed");
+            }
+            SolidSource.next_frame(options)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_crashed_capture_thread_restarts() {
+        let crashed = Arc::new(AtomicBool::new(false));
+        let factory_crashed = crashed.clone();
+        let mut capturer = ScreenCapturer::new().with_frame_source(Arc::new(move |_| {
+            Box::new(CrashOnceSource {
+                crashed: factory_crashed.clone(),
+            })
+        }));
+
+        let mut frames = capturer
+            .start_capture("display_0".to_string(), CaptureOptions::default())
+            .await
+            .unwrap();
+        let frame = tokio::time::timeout(Duration::from_secs(5), frames.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.id, 1);
+        assert!(crashed.load(Ordering::SeqCst));
+
+        let health = capturer.thread_health();
+        assert!(health.running);
+        assert_eq!(health.restarts, 1);
+        assert_eq!(health.last_panic.as_deref(), Some("capture driver crashed"));
+        capturer.stop_capture().await;
+    }
+
+    /// Counts keyframe requests forwarded to it
+    struct KeyframeSource {
+        keyframes: Arc<AtomicU64>,
+    }
+
+    impl FrameSource for KeyframeSource {
+        fn next_frame(&mut self, options: &CaptureOptions) -> Result<VideoFrame> {
+            SolidSource.next_frame(options)
+        }
+
+        fn request_keyframe(&mut self) {
+            self.keyframes.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keyframe_request_reaches_source() {
+        let keyframes = Arc::new(AtomicU64::new(0));
+        let factory_keyframes = keyframes.clone();
+        let mut capturer = ScreenCapturer::new().with_frame_source(Arc::new(move |_| {
+            Box::new(KeyframeSource {
+                keyframes: factory_keyframes.clone(),
+            })
+        }));
+
+        // Requests made before the next frame are merged into one
+        capturer.request_keyframe();
+        capturer.request_keyframe();
+        let mut frames = capturer
+            .start_capture("display_0".to_string(), CaptureOptions::default())
+            .await
+            .unwrap();
+        frames.recv().await.unwrap();
+        frames.recv().await.unwrap();
+        assert_eq!(keyframes.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            capturer
+                .metrics_registry()
+                .snapshot()
+                .get("capture.keyframe_requests"),
+            2
+        );
+        capturer.stop_capture().await;
+    }
+
+    #[tokio::test]
+    async fn test_static_frames_report_no_damage() {
+        let mut capturer =
+            ScreenCapturer::new().with_frame_source(Arc::new(|_| Box::new(SolidSource)));
+        let options = CaptureOptions {
+            width: 64,
+            height: 36,
+            ..Default::default()
+        };
+        let mut frames = capturer
+            .start_capture("display_0".to_string(), options)
+            .await
+            .unwrap();
+        assert_eq!(frames.recv().await.unwrap().dirty_rects, None);
+        assert_eq!(frames.recv().await.unwrap().dirty_rects, Some(Vec::new()));
+        capturer.stop_capture().await;
+        assert!(capturer.thread_health().frames_unchanged >= 1);
+    }
+
+    #[tokio::test]
+    async fn test_frame_processors_applied_before_send() {
+        use crate::frame_processing::ProcessorConfig;
+
+        let mut capturer =
+            ScreenCapturer::new().with_frame_source(Arc::new(|_| Box::new(SolidSource)));
+        let watermark = |frame: &mut VideoFrame| {
+            frame.data[..4].copy_from_slice(&[0xFF; 4]);
+            Ok(())
+        };
+        capturer
+            .frame_processors()
+            .register("watermark", watermark, ProcessorConfig::default())
+            .unwrap();
+        let options = CaptureOptions {
+            width: 64,
+            height: 36,
+            ..Default::default()
+        };
+        let mut frames = capturer
+            .start_capture("display_0".to_string(), options)
+            .await
+            .unwrap();
+        let frame = frames.recv().await.unwrap();
+        assert_eq!(
+            &frame.data[..8],
+            &[0xFF, 0xFF, 0xFF, 0xFF, 0x80, 0x80, 0x80, 0x80]
+        );
+        // The mark is the same every frame, so nothing changed
+        assert_eq!(frames.recv().await.unwrap().dirty_rects, Some(Vec::new()));
+        capturer.stop_capture().await;
+        assert!(capturer.frame_processors().stats()[0].frames_processed >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_still_screen_lowers_capture_rate_until_input() {
+        use crate::capture_rate::AdaptiveRateOptions;
+
+        let mut capturer =
+            ScreenCapturer::new().with_frame_source(Arc::new(|_| Box::new(SolidSource)));
+        let options = CaptureOptions {
+            frame_rate: 60,
+            width: 64,
+            height: 36,
+            adaptive_rate: AdaptiveRateOptions {
+                settle_ms: 50,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let mut frames = capturer
+            .start_capture("display_0".to_string(), options)
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        let health = capturer.thread_health();
+        assert_eq!(health.capture_fps, 5);
+        assert!(health.effective_fps < 30.0, "{}", health.effective_fps);
+
+        // Right after a frame the next one is 200 ms away, unless input
+        // wakes the capture thread
+        while frames.try_recv().is_ok() {}
+        frames.recv().await.unwrap();
+        capturer.notify_input();
+        let started = Instant::now();
+        frames.recv().await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(100));
+        capturer.stop_capture().await;
+    }
+
+    #[tokio::test]
+    async fn test_slow_consumer_drops_frames() {
+        let mut capturer =
+            ScreenCapturer::new().with_frame_source(Arc::new(|_| Box::new(SolidSource)));
+        let options = CaptureOptions {
+            frame_rate: 200,
+            width: 64,
+            height: 36,
+            ..Default::default()
+        };
+        let mut frames = capturer
+            .start_capture("display_0".to_string(), options)
+            .await
+            .unwrap();
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let health = capturer.thread_health();
+        assert!(health.frames_dropped > 0);
+        assert_eq!(health.frames_captured, CAPTURE_CHANNEL_CAPACITY as u64);
+
+        capturer.stop_capture().await;
+        let mut buffered = 0;
+        while frames.recv().await.is_some() {
+            buffered += 1;
+        }
+        assert_eq!(buffered, CAPTURE_CHANNEL_CAPACITY);
+    }
+}
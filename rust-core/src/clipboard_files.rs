@@ -0,0 +1,896 @@
+//! Clipboard File Copy
+//!
+//! When the host clipboard holds file references, the host announces them to
+//! the viewer as a `ClipboardFileOffer`. Pasting on the viewer starts a
+//! `FileTransfer` for each file into a per-offer staging folder and returns
+//! the local staged paths for the client to put on the viewer's clipboard.
+//!
+//! `read_clipboard_files` reads the file references on the host clipboard:
+//! `CF_HDROP` on Windows, file URLs on the macOS general pasteboard, and the
+//! `text/uri-list` target of the X11 `CLIPBOARD` selection (or `wl-paste` on
+//! Wayland) on Linux.
+
+use crate::access_control::Permission;
+use crate::file_transfer::{guess_mime_type, FileTransfer};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Default maximum number of files in one clipboard offer
+pub const DEFAULT_MAX_CLIPBOARD_FILES: usize = 100;
+
+/// Default maximum combined size of one clipboard offer (1GB)
+pub const DEFAULT_MAX_CLIPBOARD_BYTES: u64 = 1024 * 1024 * 1024;
+
+/// File referenced by the host clipboard
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClipboardFileEntry {
+    pub name: String,
+    pub size: u64,
+    /// Guessed from the name, for the receiver's file icon
+    #[serde(default)]
+    pub mime_type: String,
+}
+
+/// Clipboard file list announced by the host
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClipboardFileOffer {
+    pub offer_id: String,
+    pub files: Vec<ClipboardFileEntry>,
+}
+
+impl ClipboardFileOffer {
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|f| f.size).sum()
+    }
+}
+
+/// Limits applied to clipboard file copies on both sides
+#[derive(Debug, Clone)]
+pub struct ClipboardFileLimits {
+    pub max_files: usize,
+    pub max_total_bytes: u64,
+}
+
+impl Default for ClipboardFileLimits {
+    fn default() -> Self {
+        Self {
+            max_files: DEFAULT_MAX_CLIPBOARD_FILES,
+            max_total_bytes: DEFAULT_MAX_CLIPBOARD_BYTES,
+        }
+    }
+}
+
+/// Tracks outgoing offers (host) and stages incoming pastes (viewer)
+pub struct ClipboardFileManager {
+    staging_dir: PathBuf,
+    limits: ClipboardFileLimits,
+    outgoing: HashMap<String, Vec<PathBuf>>,
+}
+
+impl ClipboardFileManager {
+    pub fn new(staging_dir: PathBuf) -> Self {
+        Self::with_limits(staging_dir, ClipboardFileLimits::default())
+    }
+
+    pub fn with_limits(staging_dir: PathBuf, limits: ClipboardFileLimits) -> Self {
+        Self {
+            staging_dir,
+            limits,
+            outgoing: HashMap::new(),
+        }
+    }
+
+    /// Read file references from the local clipboard
+    ///
+    /// Empty when the clipboard holds no files. Fails where the clipboard
+    /// cannot be read, rather than reporting an empty clipboard.
+    pub fn read_clipboard_files(&self) -> Result<Vec<PathBuf>> {
+        #[cfg(target_os = "windows")]
+        {
+            win32::read_files()
+        }
+        #[cfg(target_os = "macos")]
+        {
+            appkit::read_files()
+        }
+        #[cfg(target_os = "linux")]
+        {
+            // XWayland bridges the clipboard, so X11 covers most Wayland
+            // sessions too
+            #[cfg(feature = "capture")]
+            if std::env::var_os("DISPLAY").is_some() {
+                return x11::read_uri_list().map(|list| parse_uri_list(&list));
+            }
+            if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                return wayland::read_uri_list().map(|list| parse_uri_list(&list));
+            }
+            Err(anyhow::anyhow!(
+                "Reading files from the clipboard needs an X11 or Wayland display"
+            ))
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            Err(anyhow::anyhow!(
+                "Reading files from the clipboard is not supported on {}",
+                std::env::consts::OS
+            ))
+        }
+    }
+
+    /// Build an offer for files found on the host clipboard
+    ///
+    /// Directories are skipped; the offer fails if the remaining files exceed
+    /// the configured limits.
+    pub async fn create_offer(&mut self, paths: Vec<PathBuf>) -> Result<ClipboardFileOffer> {
+        let mut files = Vec::new();
+        let mut sources = Vec::new();
+
+        for path in paths {
+            let metadata = tokio::fs::metadata(&path).await?;
+            if !metadata.is_file() {
+                tracing::debug!("Skipping non-file clipboard entry: {}", path.display());
+                continue;
+            }
+            let name = staged_file_name(&path.to_string_lossy());
+            files.push(ClipboardFileEntry {
+                mime_type: guess_mime_type(&name).to_string(),
+                name,
+                size: metadata.len(),
+            });
+            sources.push(path);
+        }
+
+        let offer = ClipboardFileOffer {
+            offer_id: Uuid::new_v4().to_string(),
+            files,
+        };
+        self.check_limits(&offer)?;
+
+        tracing::info!(
+            "Clipboard file offer {} with {} files",
+            offer.offer_id,
+            offer.files.len()
+        );
+        self.outgoing.insert(offer.offer_id.clone(), sources);
+        Ok(offer)
+    }
+
+    /// Start sending the files of an offer after the viewer pasted it
+    ///
+    /// Returns one transfer ID per file, in offer order.
+    pub async fn send_offer(
+        &mut self,
+        offer_id: &str,
+        file_transfer: &mut FileTransfer,
+        target_id: &str,
+    ) -> Result<Vec<String>> {
+        let sources = self
+            .outgoing
+            .remove(offer_id)
+            .ok_or_else(|| anyhow::anyhow!("Clipboard offer not found: {}", offer_id))?;
+
+        let mut transfer_ids = Vec::with_capacity(sources.len());
+        for path in sources {
+            transfer_ids.push(file_transfer.send_file(path, target_id.to_string()).await?);
+        }
+        Ok(transfer_ids)
+    }
+
+    /// Receive a pasted offer into the staging folder
+    ///
+    /// Requires both the clipboard and file transfer permissions. Returns the
+    /// staged local paths, which the client puts on the viewer clipboard.
+    pub async fn paste_offer(
+        &self,
+        offer: &ClipboardFileOffer,
+        transfer_ids: &[String],
+        permissions: &[Permission],
+        file_transfer: &mut FileTransfer,
+    ) -> Result<Vec<PathBuf>> {
+        let allowed = |p: Permission| {
+            permissions.contains(&p) || permissions.contains(&Permission::FullControl)
+        };
+        if !allowed(Permission::Clipboard) || !allowed(Permission::FileTransfer) {
+            return Err(anyhow::anyhow!(
+                "Clipboard file copy requires clipboard and file transfer permissions"
+            ));
+        }
+        self.check_limits(offer)?;
+        if let Some(file) = offer
+            .files
+            .iter()
+            .find(|f| f.size > file_transfer.get_max_file_size())
+        {
+            return Err(anyhow::anyhow!("File too large to copy: {}", file.name));
+        }
+        if transfer_ids.len() != offer.files.len() {
+            return Err(anyhow::anyhow!(
+                "Expected {} transfers for clipboard offer, got {}",
+                offer.files.len(),
+                transfer_ids.len()
+            ));
+        }
+
+        let offer_dir = self.staging_dir.join(staged_file_name(&offer.offer_id));
+        tokio::fs::create_dir_all(&offer_dir).await?;
+
+        let mut used_names = HashSet::new();
+        let mut staged = Vec::with_capacity(offer.files.len());
+        for (file, transfer_id) in offer.files.iter().zip(transfer_ids) {
+            let path = offer_dir.join(unique_name(&staged_file_name(&file.name), &mut used_names));
+            let result = file_transfer
+                .receive_file(transfer_id.clone(), path.clone())
+                .await?;
+            if !result.success {
+                return Err(anyhow::anyhow!(
+                    "Clipboard file transfer failed for {}: {}",
+                    file.name,
+                    result.error_message.unwrap_or_default()
+                ));
+            }
+            staged.push(path);
+        }
+        Ok(staged)
+    }
+
+    fn check_limits(&self, offer: &ClipboardFileOffer) -> Result<()> {
+        if offer.files.len() > self.limits.max_files {
+            return Err(anyhow::anyhow!(
+                "Too many clipboard files: {} (max {})",
+                offer.files.len(),
+                self.limits.max_files
+            ));
+        }
+        if offer.total_size() > self.limits.max_total_bytes {
+            return Err(anyhow::anyhow!(
+                "Clipboard files too large: {} bytes (max {})",
+                offer.total_size(),
+                self.limits.max_total_bytes
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Reduce a remote-supplied name to a plain file name
+fn staged_file_name(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    match base {
+        "" | "." | ".." => "file".to_string(),
+        base => base.to_string(),
+    }
+}
+
+fn unique_name(name: &str, used: &mut HashSet<String>) -> String {
+    let mut candidate = name.to_string();
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let extension = path.extension().and_then(|e| e.to_str());
+
+    let mut n = 1;
+    while !used.insert(candidate.clone()) {
+        candidate = match extension {
+            Some(ext) => format!("{} ({}).{}", stem, n, ext),
+            None => format!("{} ({})", stem, n),
+        };
+        n += 1;
+    }
+    candidate
+}
+
+/// Local paths of the `file://` URIs in a `text/uri-list` (RFC 2483)
+///
+/// Comments and other schemes are skipped.
+#[cfg(target_os = "linux")]
+fn parse_uri_list(list: &str) -> Vec<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+
+    list.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|uri| uri.strip_prefix("file://"))
+        // The authority is empty or names this host
+        .filter_map(|rest| rest.find('/').map(|slash| &rest[slash..]))
+        .map(|path| PathBuf::from(std::ffi::OsStr::from_bytes(&percent_decode(path))))
+        .collect()
+}
+
+/// Undo URI percent-encoding; malformed escapes are kept as they are
+#[cfg(target_os = "linux")]
+fn percent_decode(text: &str) -> Vec<u8> {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+#[cfg(target_os = "windows")]
+mod win32 {
+    use anyhow::Result;
+    use std::ffi::c_void;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    type Hwnd = *mut c_void;
+    type Handle = *mut c_void;
+
+    const CF_HDROP: u32 = 15;
+    /// Another application may hold the clipboard open for a moment
+    const OPEN_ATTEMPTS: u32 = 10;
+    const OPEN_RETRY_DELAY: Duration = Duration::from_millis(20);
+
+    extern "system" {
+        fn OpenClipboard(owner: Hwnd) -> i32;
+        fn CloseClipboard() -> i32;
+        fn IsClipboardFormatAvailable(format: u32) -> i32;
+        fn GetClipboardData(format: u32) -> Handle;
+        fn DragQueryFileW(drop: Handle, index: u32, file: *mut u16, size: u32) -> u32;
+    }
+
+    pub(super) fn read_files() -> Result<Vec<PathBuf>> {
+        // SAFETY: takes a format ID only
+        if unsafe { IsClipboardFormatAvailable(CF_HDROP) } == 0 {
+            return Ok(Vec::new());
+        }
+        let mut attempts = 0;
+        // SAFETY: a null owner associates the clipboard with this task
+        while unsafe { OpenClipboard(std::ptr::null_mut()) } == 0 {
+            attempts += 1;
+            if attempts == OPEN_ATTEMPTS {
+                return Err(anyhow::anyhow!(
+                    "Cannot open the clipboard: {}",
+                    std::io::Error::last_os_error()
+                ));
+            }
+            std::thread::sleep(OPEN_RETRY_DELAY);
+        }
+
+        // SAFETY: the clipboard is open, so the HDROP stays valid until it
+        // is closed below; buffers are sized from DragQueryFileW itself
+        let files = unsafe {
+            let drop = GetClipboardData(CF_HDROP);
+            let mut files = Vec::new();
+            if !drop.is_null() {
+                let count = DragQueryFileW(drop, u32::MAX, std::ptr::null_mut(), 0);
+                for index in 0..count {
+                    let length = DragQueryFileW(drop, index, std::ptr::null_mut(), 0);
+                    let mut name = vec![0u16; length as usize + 1];
+                    let copied = DragQueryFileW(drop, index, name.as_mut_ptr(), length + 1);
+                    name.truncate(copied as usize);
+                    files.push(PathBuf::from(String::from_utf16_lossy(&name)));
+                }
+            }
+            CloseClipboard();
+            files
+        };
+        Ok(files)
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod appkit {
+    use anyhow::Result;
+    use std::ffi::{c_char, c_void, CStr};
+    use std::path::PathBuf;
+
+    type Id = *mut c_void;
+    type Sel = *const c_void;
+
+    extern "C" {
+        static NSPasteboardURLReadingFileURLsOnlyKey: Id;
+        fn objc_getClass(name: *const c_char) -> Id;
+        fn sel_registerName(name: *const c_char) -> Sel;
+        fn objc_msgSend();
+        fn objc_autoreleasePoolPush() -> *mut c_void;
+        fn objc_autoreleasePoolPop(pool: *mut c_void);
+    }
+
+    /// `objc_msgSend` for a method taking no arguments and returning an object
+    ///
+    /// # Safety
+    ///
+    /// `receiver` must respond to `selector` with that signature.
+    unsafe fn send(receiver: Id, selector: &CStr) -> Id {
+        let send = std::mem::transmute::<unsafe extern "C" fn(), unsafe extern "C" fn(Id, Sel) -> Id>(
+            objc_msgSend,
+        );
+        send(receiver, sel_registerName(selector.as_ptr()))
+    }
+
+    /// `objc_msgSend` for a method taking two objects
+    ///
+    /// # Safety
+    ///
+    /// `receiver` must respond to `selector` with that signature.
+    unsafe fn send_objects(receiver: Id, selector: &CStr, first: Id, second: Id) -> Id {
+        let send = std::mem::transmute::<
+            unsafe extern "C" fn(),
+            unsafe extern "C" fn(Id, Sel, Id, Id) -> Id,
+        >(objc_msgSend);
+        send(receiver, sel_registerName(selector.as_ptr()), first, second)
+    }
+
+    /// `objc_msgSend` for a method taking one object
+    ///
+    /// # Safety
+    ///
+    /// `receiver` must respond to `selector` with that signature.
+    unsafe fn send_object(receiver: Id, selector: &CStr, argument: Id) -> Id {
+        let send = std::mem::transmute::<
+            unsafe extern "C" fn(),
+            unsafe extern "C" fn(Id, Sel, Id) -> Id,
+        >(objc_msgSend);
+        send(receiver, sel_registerName(selector.as_ptr()), argument)
+    }
+
+    /// `objc_msgSend` for a method taking one NSUInteger
+    ///
+    /// # Safety
+    ///
+    /// `receiver` must respond to `selector` with that signature.
+    unsafe fn send_index(receiver: Id, selector: &CStr, argument: usize) -> Id {
+        let send = std::mem::transmute::<
+            unsafe extern "C" fn(),
+            unsafe extern "C" fn(Id, Sel, usize) -> Id,
+        >(objc_msgSend);
+        send(receiver, sel_registerName(selector.as_ptr()), argument)
+    }
+
+    /// `objc_msgSend` for a method taking one BOOL
+    ///
+    /// # Safety
+    ///
+    /// `receiver` must respond to `selector` with that signature.
+    unsafe fn send_bool(receiver: Id, selector: &CStr, argument: bool) -> Id {
+        let send = std::mem::transmute::<
+            unsafe extern "C" fn(),
+            unsafe extern "C" fn(Id, Sel, bool) -> Id,
+        >(objc_msgSend);
+        send(receiver, sel_registerName(selector.as_ptr()), argument)
+    }
+
+    /// `objc_msgSend` for a method returning an NSUInteger
+    ///
+    /// # Safety
+    ///
+    /// `receiver` must respond to `selector` with that signature.
+    unsafe fn send_count(receiver: Id, selector: &CStr) -> usize {
+        let send = std::mem::transmute::<
+            unsafe extern "C" fn(),
+            unsafe extern "C" fn(Id, Sel) -> usize,
+        >(objc_msgSend);
+        send(receiver, sel_registerName(selector.as_ptr()))
+    }
+
+    fn class(name: &CStr) -> Result<Id> {
+        // SAFETY: `name` is NUL-terminated
+        let class = unsafe { objc_getClass(name.as_ptr()) };
+        if class.is_null() {
+            Err(anyhow::anyhow!("{} is unavailable", name.to_string_lossy()))
+        } else {
+            Ok(class)
+        }
+    }
+
+    pub(super) fn read_files() -> Result<Vec<PathBuf>> {
+        let pasteboard_class = class(c"NSPasteboard")?;
+        let url_class = class(c"NSURL")?;
+        let array_class = class(c"NSArray")?;
+        let dictionary_class = class(c"NSDictionary")?;
+        let number_class = class(c"NSNumber")?;
+
+        // SAFETY: the classes exist and each message matches its declaration;
+        // every object is autoreleased and read before the pool pops
+        unsafe {
+            let pool = objc_autoreleasePoolPush();
+            let pasteboard = send(pasteboard_class, c"generalPasteboard");
+            let classes = send_object(array_class, c"arrayWithObject:", url_class);
+            let options = send_objects(
+                dictionary_class,
+                c"dictionaryWithObject:forKey:",
+                send_bool(number_class, c"numberWithBool:", true),
+                NSPasteboardURLReadingFileURLsOnlyKey,
+            );
+            let urls = send_objects(
+                pasteboard,
+                c"readObjectsForClasses:options:",
+                classes,
+                options,
+            );
+            let mut files = Vec::new();
+            if !urls.is_null() {
+                for index in 0..send_count(urls, c"count") {
+                    let path = send(send_index(urls, c"objectAtIndex:", index), c"path");
+                    if path.is_null() {
+                        continue;
+                    }
+                    let utf8 = send(path, c"UTF8String") as *const c_char;
+                    if !utf8.is_null() {
+                        files.push(PathBuf::from(
+                            CStr::from_ptr(utf8).to_string_lossy().into_owned(),
+                        ));
+                    }
+                }
+            }
+            objc_autoreleasePoolPop(pool);
+            Ok(files)
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "capture"))]
+mod x11 {
+    use anyhow::Result;
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_int, c_long, c_uchar, c_uint, c_ulong, c_void};
+    use std::time::{Duration, Instant};
+
+    type Window = c_ulong;
+    type Atom = c_ulong;
+    type Time = c_ulong;
+
+    #[repr(C)]
+    struct Display {
+        _private: [u8; 0],
+    }
+
+    /// Xlib's `XSelectionEvent`
+    #[repr(C)]
+    struct XSelectionEvent {
+        kind: c_int,
+        serial: c_ulong,
+        send_event: c_int,
+        display: *mut Display,
+        requestor: Window,
+        selection: Atom,
+        target: Atom,
+        property: Atom,
+        time: Time,
+    }
+
+    /// Xlib's `XEvent` union, 24 longs
+    #[repr(C)]
+    union XEvent {
+        selection: std::mem::ManuallyDrop<XSelectionEvent>,
+        pad: [c_long; 24],
+    }
+
+    const SELECTION_NOTIFY: c_int = 31;
+    const CURRENT_TIME: Time = 0;
+    const ANY_PROPERTY_TYPE: Atom = 0;
+    const NONE: Atom = 0;
+    const SUCCESS: c_int = 0;
+    /// Upper bound on the list length, in 32-bit units
+    const MAX_PROPERTY_LONGS: c_long = 1 << 20;
+    /// How long the selection owner has to answer
+    const CONVERT_TIMEOUT: Duration = Duration::from_secs(2);
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    extern "C" {
+        fn XOpenDisplay(name: *const c_char) -> *mut Display;
+        fn XCloseDisplay(display: *mut Display) -> c_int;
+        fn XDefaultRootWindow(display: *mut Display) -> Window;
+        fn XInternAtom(display: *mut Display, name: *const c_char, only_if_exists: c_int) -> Atom;
+        fn XCreateSimpleWindow(
+            display: *mut Display,
+            parent: Window,
+            x: c_int,
+            y: c_int,
+            width: c_uint,
+            height: c_uint,
+            border_width: c_uint,
+            border: c_ulong,
+            background: c_ulong,
+        ) -> Window;
+        fn XDestroyWindow(display: *mut Display, window: Window) -> c_int;
+        fn XGetSelectionOwner(display: *mut Display, selection: Atom) -> Window;
+        fn XConvertSelection(
+            display: *mut Display,
+            selection: Atom,
+            target: Atom,
+            property: Atom,
+            requestor: Window,
+            time: Time,
+        ) -> c_int;
+        fn XFlush(display: *mut Display) -> c_int;
+        fn XCheckTypedWindowEvent(
+            display: *mut Display,
+            window: Window,
+            event_type: c_int,
+            event: *mut XEvent,
+        ) -> c_int;
+        fn XGetWindowProperty(
+            display: *mut Display,
+            window: Window,
+            property: Atom,
+            offset: c_long,
+            length: c_long,
+            delete: c_int,
+            req_type: Atom,
+            actual_type: *mut Atom,
+            actual_format: *mut c_int,
+            nitems: *mut c_ulong,
+            bytes_after: *mut c_ulong,
+            prop: *mut *mut c_uchar,
+        ) -> c_int;
+        fn XFree(data: *mut c_void) -> c_int;
+    }
+
+    /// An X connection with a hidden window to receive the selection on
+    struct Requestor {
+        display: *mut Display,
+        window: Window,
+    }
+
+    impl Drop for Requestor {
+        fn drop(&mut self) {
+            // SAFETY: both were created by `read_uri_list` and are freed once
+            unsafe {
+                XDestroyWindow(self.display, self.window);
+                XCloseDisplay(self.display);
+            }
+        }
+    }
+
+    /// The `CLIPBOARD` selection as `text/uri-list`, empty if not offered
+    pub(super) fn read_uri_list() -> Result<String> {
+        // SAFETY: a null name opens $DISPLAY
+        let display = unsafe { XOpenDisplay(std::ptr::null()) };
+        if display.is_null() {
+            return Err(anyhow::anyhow!("Cannot open X display"));
+        }
+        // SAFETY: the display is open; the window is a 1x1 unmapped child
+        // of the root, only used as the conversion target
+        let requestor = unsafe {
+            Requestor {
+                display,
+                window: XCreateSimpleWindow(
+                    display,
+                    XDefaultRootWindow(display),
+                    0,
+                    0,
+                    1,
+                    1,
+                    0,
+                    0,
+                    0,
+                ),
+            }
+        };
+        let atom = |name: &CStr| {
+            // SAFETY: the display is open and `name` is NUL-terminated
+            unsafe { XInternAtom(display, name.as_ptr(), 0) }
+        };
+        let clipboard = atom(c"CLIPBOARD");
+        let uri_list = atom(c"text/uri-list");
+        let property = atom(c"CEC_CLIPBOARD_FILES");
+        let incr = atom(c"INCR");
+
+        // SAFETY: the display and window are live for the whole block and
+        // every out pointer is valid
+        unsafe {
+            if XGetSelectionOwner(display, clipboard) == NONE {
+                return Ok(String::new());
+            }
+            XConvertSelection(
+                display,
+                clipboard,
+                uri_list,
+                property,
+                requestor.window,
+                CURRENT_TIME,
+            );
+            XFlush(display);
+
+            let deadline = Instant::now() + CONVERT_TIMEOUT;
+            let mut event: XEvent = std::mem::zeroed();
+            while XCheckTypedWindowEvent(display, requestor.window, SELECTION_NOTIFY, &mut event)
+                == 0
+            {
+                if Instant::now() >= deadline {
+                    return Err(anyhow::anyhow!("The clipboard owner did not answer"));
+                }
+                std::thread::sleep(POLL_INTERVAL);
+            }
+            // The owner refuses targets it does not offer
+            if event.selection.property == NONE {
+                return Ok(String::new());
+            }
+
+            let mut actual_type: Atom = 0;
+            let mut actual_format: c_int = 0;
+            let mut nitems: c_ulong = 0;
+            let mut bytes_after: c_ulong = 0;
+            let mut data: *mut c_uchar = std::ptr::null_mut();
+            let status = XGetWindowProperty(
+                display,
+                requestor.window,
+                property,
+                0,
+                MAX_PROPERTY_LONGS,
+                1,
+                ANY_PROPERTY_TYPE,
+                &mut actual_type,
+                &mut actual_format,
+                &mut nitems,
+                &mut bytes_after,
+                &mut data,
+            );
+            if status != SUCCESS || data.is_null() {
+                return Err(anyhow::anyhow!("Cannot read the clipboard file list"));
+            }
+            let list = (actual_format == 8)
+                .then(|| std::slice::from_raw_parts(data, nitems as usize))
+                .map(|bytes| String::from_utf8_lossy(bytes).into_owned());
+            XFree(data.cast());
+            if actual_type == incr {
+                return Err(anyhow::anyhow!("The clipboard file list is too large"));
+            }
+            Ok(list.unwrap_or_default())
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod wayland {
+    use anyhow::Result;
+    use std::process::Command;
+
+    const URI_LIST: &str = "text/uri-list";
+
+    /// The clipboard as `text/uri-list` through wl-clipboard, empty if not
+    /// offered
+    pub(super) fn read_uri_list() -> Result<String> {
+        let types = Command::new("wl-paste")
+            .arg("--list-types")
+            .output()
+            .map_err(|e| anyhow::anyhow!("Reading the Wayland clipboard needs wl-paste: {}", e))?;
+        // wl-paste fails when the clipboard is empty
+        if !types.status.success()
+            || !String::from_utf8_lossy(&types.stdout)
+                .lines()
+                .any(|offered| offered.trim() == URI_LIST)
+        {
+            return Ok(String::new());
+        }
+        let list = Command::new("wl-paste")
+            .args(["--no-newline", "--type", URI_LIST])
+            .output()?;
+        if !list.status.success() {
+            return Err(anyhow::anyhow!(
+                "wl-paste failed: {}",
+                String::from_utf8_lossy(&list.stderr).trim()
+            ));
+        }
+        Ok(String::from_utf8_lossy(&list.stdout).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cec-clipboard-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_offer_and_paste() {
+        let source_dir = temp_dir();
+        let file = source_dir.join("report.txt");
+        std::fs::write(&file, b"hello").unwrap();
+
+        let mut host = ClipboardFileManager::new(source_dir.clone());
+        let offer = host
+            .create_offer(vec![file, source_dir.clone()])
+            .await
+            .unwrap();
+        assert_eq!(offer.files.len(), 1);
+        assert_eq!(offer.files[0].size, 5);
+
+        let mut host_transfer = FileTransfer::new();
+        let transfer_ids = host
+            .send_offer(&offer.offer_id, &mut host_transfer, "viewer")
+            .await
+            .unwrap();
+        assert_eq!(transfer_ids.len(), 1);
+
+        let viewer = ClipboardFileManager::new(temp_dir());
+        let mut viewer_transfer = FileTransfer::new();
+        assert!(viewer
+            .paste_offer(
+                &offer,
+                &transfer_ids,
+                &[Permission::ViewScreen],
+                &mut viewer_transfer
+            )
+            .await
+            .is_err());
+
+        let staged = viewer
+            .paste_offer(
+                &offer,
+                &transfer_ids,
+                &[Permission::Clipboard, Permission::FileTransfer],
+                &mut viewer_transfer,
+            )
+            .await
+            .unwrap();
+        assert!(staged[0].ends_with("report.txt"));
+    }
+
+    #[tokio::test]
+    async fn test_limits_and_name_sanitizing() {
+        let viewer = ClipboardFileManager::with_limits(
+            temp_dir(),
+            ClipboardFileLimits {
+                max_files: 10,
+                max_total_bytes: 100,
+            },
+        );
+        let offer = ClipboardFileOffer {
+            offer_id: "offer".to_string(),
+            files: vec![ClipboardFileEntry {
+                name: "big.iso".to_string(),
+                size: 1000,
+                mime_type: guess_mime_type("big.iso").to_string(),
+            }],
+        };
+        assert!(viewer
+            .paste_offer(
+                &offer,
+                &["t1".to_string()],
+                &[Permission::FullControl],
+                &mut FileTransfer::new()
+            )
+            .await
+            .is_err());
+
+        assert_eq!(staged_file_name("../../etc/passwd"), "passwd");
+        assert_eq!(staged_file_name("C:\\Users\\a\\..\\"), "file");
+        let mut used = HashSet::new();
+        assert_eq!(unique_name("a.txt", &mut used), "a.txt");
+        assert_eq!(unique_name("a.txt", &mut used), "a (1).txt");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_uri_list_parsing() {
+        let list = "# copied from Files\r\n\
+                    file:///home/ana/Report%20Q3.pdf\r\n\
+                    file://localhost/tmp/caf%C3%A9.txt\r\n\
+                    https://example.com/page\r\n\
+                    file:///tmp/100%.txt\r\n";
+        assert_eq!(
+            parse_uri_list(list),
+            vec![
+                PathBuf::from("/home/ana/Report Q3.pdf"),
+                PathBuf::from("/tmp/café.txt"),
+                PathBuf::from("/tmp/100%.txt"),
+            ]
+        );
+        assert!(parse_uri_list("").is_empty());
+    }
+}
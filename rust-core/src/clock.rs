@@ -0,0 +1,119 @@
+//! Injectable Clock
+//!
+//! Expiry checks (access codes, lockouts, key rotation, permission requests)
+//! read the time through a `Clock` instead of calling `Instant::now()` or
+//! `Utc::now()` themselves. Production code uses `SystemClock`; tests hand
+//! the managers a `TestClock` and move it forward explicitly, so time-based
+//! behaviour needs neither sleeps nor back-dated fixtures.
+
+use crate::timestamp::Timestamp;
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Source of the current time
+pub trait Clock: Send + Sync + fmt::Debug {
+    /// Monotonic time
+    fn instant(&self) -> Instant;
+
+    /// Wall-clock time
+    fn utc(&self) -> DateTime<Utc>;
+
+    fn now(&self) -> Timestamp {
+        Timestamp::from_parts(self.utc(), self.instant())
+    }
+}
+
+/// Clock shared by a manager and its background tasks
+pub type SharedClock = Arc<dyn Clock>;
+
+/// The operating system's clocks
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn utc(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// `SystemClock` as a `SharedClock`
+pub fn system_clock() -> SharedClock {
+    Arc::new(SystemClock)
+}
+
+/// Clock that only moves when told to
+///
+/// Both readings start at the real time of creation and advance together.
+#[derive(Debug)]
+pub struct TestClock {
+    start_instant: Instant,
+    start_utc: DateTime<Utc>,
+    offset: Mutex<Duration>,
+}
+
+impl TestClock {
+    pub fn new() -> Self {
+        Self {
+            start_instant: Instant::now(),
+            start_utc: Utc::now(),
+            offset: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    /// New test clock, already shared
+    pub fn shared() -> Arc<Self> {
+        Arc::new(Self::new())
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.offset.lock().unwrap_or_else(PoisonError::into_inner) += by;
+    }
+
+    /// Time advanced since creation
+    pub fn elapsed(&self) -> Duration {
+        *self.offset.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for TestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for TestClock {
+    fn instant(&self) -> Instant {
+        self.start_instant + self.elapsed()
+    }
+
+    fn utc(&self) -> DateTime<Utc> {
+        self.start_utc + chrono::Duration::from_std(self.elapsed()).unwrap_or(chrono::Duration::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock_moves_only_when_advanced() {
+        let clock = TestClock::shared();
+        let start = clock.now();
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(clock.now().elapsed_at(&clock.now()), Duration::ZERO);
+        assert_eq!(start.elapsed_at(&clock.now()), Duration::ZERO);
+
+        clock.advance(Duration::from_secs(90));
+        let now = clock.now();
+        assert_eq!(start.elapsed_at(&now), Duration::from_secs(90));
+        assert_eq!((now.wall_clock() - start.wall_clock()).num_seconds(), 90);
+        assert!(start.has_elapsed_at(Duration::from_secs(60), &now));
+        assert!(!start.has_elapsed_at(Duration::from_secs(120), &now));
+    }
+}
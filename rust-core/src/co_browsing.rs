@@ -0,0 +1,309 @@
+//! Co-browsing Pointers
+//!
+//! In a session with several viewers, each viewer can point at something on
+//! the shared screen without taking control. Viewers send `PointerUpdate`s on
+//! their own data channel (`pointer_channel_label`); the host's `PointerHub`
+//! rate-limits them, tags them with the viewer's name and color, and fans the
+//! resulting `PointerOverlay`s out to every other participant, and to the host
+//! itself when enabled. Pointer updates never reach the input controller.
+//!
+//! A viewer's pointer disappears when it leaves the session or stops sending
+//! updates for `PointerHubConfig::stale_after`.
+
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Data channel label prefix for viewer pointer updates
+pub const POINTER_CHANNEL_PREFIX: &str = "pointer-";
+
+/// Colors handed out to viewers in join order, as RGB
+pub const POINTER_PALETTE: [u32; 8] = [
+    0xE53935, 0x1E88E5, 0x43A047, 0xFB8C00, 0x8E24AA, 0x00ACC1, 0xD81B60, 0x6D4C41,
+];
+
+/// Label of the data channel carrying a viewer's pointer
+pub fn pointer_channel_label(viewer_id: &str) -> String {
+    format!("{}{}", POINTER_CHANNEL_PREFIX, viewer_id)
+}
+
+/// Pointer position sent by a viewer
+///
+/// Coordinates are normalized to the shared frame (0.0–1.0) so they land in
+/// the same place whatever each participant's zoom or resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PointerUpdate {
+    pub x: f32,
+    pub y: f32,
+    pub visible: bool,
+}
+
+/// Another participant's pointer, as drawn on top of the remote screen
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PointerOverlay {
+    pub viewer_id: String,
+    /// Label shown next to the pointer
+    pub name: String,
+    /// RGB color
+    pub color: u32,
+    pub x: f32,
+    pub y: f32,
+    pub visible: bool,
+}
+
+/// Pointer changes published to participants
+#[derive(Debug, Clone, PartialEq)]
+pub enum PointerEvent {
+    Joined(PointerOverlay),
+    Moved(PointerOverlay),
+    Left { viewer_id: String },
+}
+
+impl EventType for PointerEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            PointerEvent::Joined(_) => "Joined",
+            PointerEvent::Moved(_) => "Moved",
+            PointerEvent::Left { .. } => "Left",
+        }
+    }
+}
+
+/// Host-side pointer hub limits
+#[derive(Debug, Clone)]
+pub struct PointerHubConfig {
+    /// Minimum time between accepted updates from one viewer
+    pub min_interval: Duration,
+    /// Remove a pointer that has not moved for this long
+    pub stale_after: Duration,
+    /// Also draw viewer pointers on the host's own screen
+    pub show_on_host: bool,
+}
+
+impl Default for PointerHubConfig {
+    fn default() -> Self {
+        Self {
+            // 30 updates per second is plenty for pointing
+            min_interval: Duration::from_millis(33),
+            stale_after: Duration::from_secs(30),
+            show_on_host: false,
+        }
+    }
+}
+
+struct ViewerPointer {
+    overlay: PointerOverlay,
+    last_accepted: Option<Instant>,
+    last_seen: Instant,
+}
+
+/// Host side: collects viewer pointers for one session and fans them out
+pub struct PointerHub {
+    config: PointerHubConfig,
+    pointers: HashMap<String, ViewerPointer>,
+    joined: usize,
+    events: EventBus<PointerEvent>,
+}
+
+impl PointerHub {
+    pub fn new(config: PointerHubConfig) -> Self {
+        Self {
+            config,
+            pointers: HashMap::new(),
+            joined: 0,
+            events: EventBus::new(),
+        }
+    }
+
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<PointerEvent> {
+        self.events.subscribe(options)
+    }
+
+    /// Add a viewer's pointer, hidden until its first update
+    ///
+    /// Rejoining keeps the viewer's color.
+    pub fn join(&mut self, viewer_id: &str, name: &str) -> PointerOverlay {
+        let color = match self.pointers.get(viewer_id) {
+            Some(existing) => existing.overlay.color,
+            None => {
+                let color = POINTER_PALETTE[self.joined % POINTER_PALETTE.len()];
+                self.joined += 1;
+                color
+            }
+        };
+        let overlay = PointerOverlay {
+            viewer_id: viewer_id.to_string(),
+            name: name.to_string(),
+            color,
+            x: 0.0,
+            y: 0.0,
+            visible: false,
+        };
+        self.pointers.insert(
+            viewer_id.to_string(),
+            ViewerPointer {
+                overlay: overlay.clone(),
+                last_accepted: None,
+                last_seen: Instant::now(),
+            },
+        );
+        self.events.publish(PointerEvent::Joined(overlay.clone()));
+        overlay
+    }
+
+    /// Remove a viewer's pointer from every participant's view
+    pub fn leave(&mut self, viewer_id: &str) -> bool {
+        if self.pointers.remove(viewer_id).is_none() {
+            return false;
+        }
+        self.events.publish(PointerEvent::Left {
+            viewer_id: viewer_id.to_string(),
+        });
+        true
+    }
+
+    /// Apply an update from a viewer
+    ///
+    /// Returns the overlay to forward, or `None` if the viewer is unknown or
+    /// the update was rate limited. Hiding the pointer is never limited.
+    pub fn update(&mut self, viewer_id: &str, update: PointerUpdate) -> Option<PointerOverlay> {
+        let pointer = self.pointers.get_mut(viewer_id)?;
+        let now = Instant::now();
+        pointer.last_seen = now;
+
+        let visibility_changed = pointer.overlay.visible != update.visible;
+        let throttled = pointer
+            .last_accepted
+            .is_some_and(|at| now.duration_since(at) < self.config.min_interval);
+        if throttled && !visibility_changed {
+            return None;
+        }
+
+        pointer.last_accepted = Some(now);
+        pointer.overlay.x = clamp_unit(update.x);
+        pointer.overlay.y = clamp_unit(update.y);
+        pointer.overlay.visible = update.visible;
+        let overlay = pointer.overlay.clone();
+        self.events.publish(PointerEvent::Moved(overlay.clone()));
+        Some(overlay)
+    }
+
+    /// Pointers to draw for `participant_id`; its own pointer is left out
+    ///
+    /// Pass `None` for the host, which sees nothing unless `show_on_host`.
+    pub fn overlays_for(&self, participant_id: Option<&str>) -> Vec<PointerOverlay> {
+        if participant_id.is_none() && !self.config.show_on_host {
+            return Vec::new();
+        }
+        let mut overlays: Vec<PointerOverlay> = self
+            .pointers
+            .values()
+            .filter(|pointer| pointer.overlay.visible)
+            .filter(|pointer| Some(pointer.overlay.viewer_id.as_str()) != participant_id)
+            .map(|pointer| pointer.overlay.clone())
+            .collect();
+        overlays.sort_by(|a, b| a.viewer_id.cmp(&b.viewer_id));
+        overlays
+    }
+
+    /// Drop pointers of viewers that went quiet, returning their ids
+    pub fn prune_stale(&mut self) -> Vec<String> {
+        let stale_after = self.config.stale_after;
+        let stale: Vec<String> = self
+            .pointers
+            .iter()
+            .filter(|(_, pointer)| pointer.last_seen.elapsed() >= stale_after)
+            .map(|(viewer_id, _)| viewer_id.clone())
+            .collect();
+        for viewer_id in &stale {
+            self.leave(viewer_id);
+        }
+        stale
+    }
+
+    pub fn viewer_count(&self) -> usize {
+        self.pointers.len()
+    }
+}
+
+impl Default for PointerHub {
+    fn default() -> Self {
+        Self::new(PointerHubConfig::default())
+    }
+}
+
+fn clamp_unit(value: f32) -> f32 {
+    if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(0.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: f32, y: f32) -> PointerUpdate {
+        PointerUpdate {
+            x,
+            y,
+            visible: true,
+        }
+    }
+
+    #[test]
+    fn test_pointers_shown_to_other_participants() {
+        let mut hub = PointerHub::default();
+        let mut events = hub.subscribe(SubscriptionOptions::only(&["Left"]));
+        let alice = hub.join("alice", "Alice");
+        let bob = hub.join("bob", "Bob");
+        assert_ne!(alice.color, bob.color);
+        assert_eq!(pointer_channel_label("alice"), "pointer-alice");
+
+        hub.update("alice", at(0.25, 1.5)).unwrap();
+        hub.update("bob", at(0.5, 0.5)).unwrap();
+        assert!(hub.update("mallory", at(0.1, 0.1)).is_none());
+
+        let for_bob = hub.overlays_for(Some("bob"));
+        assert_eq!(for_bob.len(), 1);
+        assert_eq!(for_bob[0].name, "Alice");
+        assert_eq!(for_bob[0].y, 1.0);
+        assert!(hub.overlays_for(None).is_empty());
+
+        assert!(hub.leave("alice"));
+        assert!(hub.overlays_for(Some("bob")).is_empty());
+        assert!(matches!(
+            events.try_recv(),
+            Some(PointerEvent::Left { viewer_id }) if viewer_id == "alice"
+        ));
+    }
+
+    #[test]
+    fn test_rate_limit_and_stale_cleanup() {
+        let mut hub = PointerHub::new(PointerHubConfig {
+            min_interval: Duration::from_secs(60),
+            stale_after: Duration::ZERO,
+            show_on_host: true,
+        });
+        hub.join("alice", "Alice");
+
+        assert!(hub.update("alice", at(0.1, 0.1)).is_some());
+        assert!(hub.update("alice", at(0.2, 0.2)).is_none());
+        // Hiding the pointer goes through regardless of the limit
+        let hidden = hub
+            .update(
+                "alice",
+                PointerUpdate {
+                    visible: false,
+                    ..at(0.2, 0.2)
+                },
+            )
+            .unwrap();
+        assert!(!hidden.visible);
+
+        assert_eq!(hub.prune_stale(), vec!["alice".to_string()]);
+        assert_eq!(hub.viewer_count(), 0);
+    }
+}
@@ -0,0 +1,710 @@
+//! Congestion Control
+//!
+//! Sender-side bandwidth estimation after Google Congestion Control
+//! (draft-ietf-rmcat-gcc-02) driven by transport-wide congestion control
+//! feedback (draft-holmer-rmcat-transport-wide-cc-extensions-01).
+//!
+//! Every outgoing packet carries a transport-wide sequence number and is
+//! remembered with its send time and size. The receiver reports when each
+//! sequence number arrived; the estimator groups packets into bursts,
+//! tracks how the one-way queuing delay trends between groups, and runs an
+//! AIMD controller on the result. A loss-based controller runs alongside it
+//! and the estimate is the lower of the two.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+const RTCP_TRANSPORT_FEEDBACK: u8 = 205;
+const TRANSPORT_CC_FMT: u8 = 15;
+
+/// Receive deltas are counted in 250 µs ticks
+const DELTA_TICK_US: i64 = 250;
+/// The reference time is counted in 64 ms ticks
+const REFERENCE_TICK_US: i64 = 64_000;
+
+/// Packets sent within this span of the first one form one group
+const BURST_SPAN_US: i64 = 5_000;
+/// Sent packets remembered while waiting for feedback
+const SEND_HISTORY_LIMIT: usize = 10_000;
+
+const TRENDLINE_WINDOW: usize = 20;
+const TRENDLINE_SMOOTHING: f64 = 0.9;
+const TRENDLINE_GAIN: f64 = 4.0;
+
+const THRESHOLD_GAIN_UP: f64 = 0.0087;
+const THRESHOLD_GAIN_DOWN: f64 = 0.039;
+const INITIAL_THRESHOLD: f64 = 12.5;
+/// Sustained over-use needed before it is signalled
+const OVERUSE_TIME_MS: f64 = 10.0;
+
+/// Decrease factor applied to the acknowledged bitrate on over-use
+const BETA: f64 = 0.85;
+/// Multiplicative increase per second
+const INCREASE_PER_SECOND: f64 = 1.08;
+/// Span of acknowledged packets averaged into the acked bitrate
+const ACKED_WINDOW: Duration = Duration::from_millis(500);
+
+/// Estimator limits and starting point
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GccConfig {
+    pub min_bitrate: u64,
+    pub max_bitrate: u64,
+    pub start_bitrate: u64,
+}
+
+impl Default for GccConfig {
+    fn default() -> Self {
+        Self {
+            min_bitrate: 100_000,
+            max_bitrate: 20_000_000,
+            start_bitrate: 2_000_000,
+        }
+    }
+}
+
+/// Delay signal of the over-use detector
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BandwidthUsage {
+    Normal,
+    Overusing,
+    Underusing,
+}
+
+/// Receipt status of one packet in a feedback message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketStatus {
+    pub sequence: u16,
+    /// Arrival time in µs on the receiver's clock, `None` if not received
+    pub arrival_us: Option<i64>,
+}
+
+/// One transport-wide CC feedback message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportFeedback {
+    pub sender_ssrc: u32,
+    pub media_ssrc: u32,
+    /// Incremented per message so the sender can spot lost feedback
+    pub feedback_count: u8,
+    pub packets: Vec<PacketStatus>,
+}
+
+impl TransportFeedback {
+    /// Serialize as an RTCP RTPFB message
+    ///
+    /// Arrival times are rounded to the 250 µs resolution of the format.
+    /// Status is always coded in two-bit vector chunks, which every
+    /// receiver accepts.
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let base_sequence = self
+            .packets
+            .first()
+            .ok_or_else(|| anyhow::anyhow!("Transport feedback without packets"))?
+            .sequence;
+        let reference = self
+            .packets
+            .iter()
+            .find_map(|p| p.arrival_us)
+            .unwrap_or(0)
+            .div_euclid(REFERENCE_TICK_US);
+
+        let mut symbols = Vec::with_capacity(self.packets.len());
+        let mut deltas = Vec::new();
+        let mut previous = reference * REFERENCE_TICK_US;
+        for (index, packet) in self.packets.iter().enumerate() {
+            if packet.sequence != base_sequence.wrapping_add(index as u16) {
+                return Err(anyhow::anyhow!("Feedback packets are not consecutive"));
+            }
+            let Some(arrival) = packet.arrival_us else {
+                symbols.push(0u16);
+                continue;
+            };
+            let ticks = (arrival - previous).div_euclid(DELTA_TICK_US);
+            if (0..=255).contains(&ticks) {
+                symbols.push(1);
+                deltas.push(ticks as u8);
+            } else {
+                let ticks = i16::try_from(ticks)
+                    .map_err(|_| anyhow::anyhow!("Receive delta out of range"))?;
+                symbols.push(2);
+                deltas.extend_from_slice(&ticks.to_be_bytes());
+            }
+            previous += ticks * DELTA_TICK_US;
+        }
+
+        let mut out = vec![0x80 | TRANSPORT_CC_FMT, RTCP_TRANSPORT_FEEDBACK, 0, 0];
+        out.extend_from_slice(&self.sender_ssrc.to_be_bytes());
+        out.extend_from_slice(&self.media_ssrc.to_be_bytes());
+        out.extend_from_slice(&base_sequence.to_be_bytes());
+        out.extend_from_slice(&(self.packets.len() as u16).to_be_bytes());
+        out.extend_from_slice(
+            &((reference as u32 & 0x00FF_FFFF) << 8 | u32::from(self.feedback_count)).to_be_bytes(),
+        );
+        for chunk in symbols.chunks(7) {
+            let mut word = 0xC000u16;
+            for (i, symbol) in chunk.iter().enumerate() {
+                word |= symbol << (12 - 2 * i);
+            }
+            out.extend_from_slice(&word.to_be_bytes());
+        }
+        out.extend_from_slice(&deltas);
+        while out.len() % 4 != 0 {
+            out.push(0);
+        }
+        let words = (out.len() / 4 - 1) as u16;
+        out[2..4].copy_from_slice(&words.to_be_bytes());
+        Ok(out)
+    }
+}
+
+/// Transport-wide CC feedback messages in a compound RTCP packet
+pub fn parse_transport_feedback(packet: &[u8]) -> Result<Vec<TransportFeedback>> {
+    let mut messages = Vec::new();
+    let mut rest = packet;
+    while !rest.is_empty() {
+        if rest.len() < 4 || rest[0] >> 6 != 2 {
+            return Err(anyhow::anyhow!("Malformed RTCP packet"));
+        }
+        let length = (usize::from(u16::from_be_bytes([rest[2], rest[3]])) + 1) * 4;
+        if rest.len() < length {
+            return Err(anyhow::anyhow!("Truncated RTCP packet"));
+        }
+        let (body, next) = rest.split_at(length);
+        if body[1] == RTCP_TRANSPORT_FEEDBACK && body[0] & 0x1F == TRANSPORT_CC_FMT {
+            messages.push(parse_message(body)?);
+        }
+        rest = next;
+    }
+    Ok(messages)
+}
+
+fn parse_message(body: &[u8]) -> Result<TransportFeedback> {
+    let truncated = || anyhow::anyhow!("Truncated transport feedback");
+    if body.len() < 20 {
+        return Err(truncated());
+    }
+    let word = |i: usize| u32::from_be_bytes([body[i], body[i + 1], body[i + 2], body[i + 3]]);
+    let base_sequence = u16::from_be_bytes([body[12], body[13]]);
+    let status_count = usize::from(u16::from_be_bytes([body[14], body[15]]));
+    // Sign-extend the 24-bit reference time
+    let reference = i64::from((word(16) as i32) >> 8);
+    let feedback_count = body[19];
+
+    let mut symbols = Vec::with_capacity(status_count);
+    let mut offset = 20;
+    while symbols.len() < status_count {
+        let chunk = body.get(offset..offset + 2).ok_or_else(truncated)?;
+        let chunk = u16::from_be_bytes([chunk[0], chunk[1]]);
+        offset += 2;
+        if chunk & 0x8000 == 0 {
+            // Run length: one symbol repeated
+            let symbol = (chunk >> 13) & 0x3;
+            let run = usize::from(chunk & 0x1FFF);
+            symbols.extend(std::iter::repeat_n(symbol, run));
+        } else if chunk & 0x4000 == 0 {
+            symbols.extend((0..14).rev().map(|bit| (chunk >> bit) & 0x1));
+        } else {
+            symbols.extend((0..7).rev().map(|pair| (chunk >> (2 * pair)) & 0x3));
+        }
+    }
+    symbols.truncate(status_count);
+
+    let mut arrival = reference * REFERENCE_TICK_US;
+    let mut packets = Vec::with_capacity(status_count);
+    for (index, symbol) in symbols.into_iter().enumerate() {
+        let sequence = base_sequence.wrapping_add(index as u16);
+        let ticks = match symbol {
+            0 => {
+                packets.push(PacketStatus {
+                    sequence,
+                    arrival_us: None,
+                });
+                continue;
+            }
+            1 => {
+                let delta = *body.get(offset).ok_or_else(truncated)?;
+                offset += 1;
+                i64::from(delta)
+            }
+            2 => {
+                let delta = body.get(offset..offset + 2).ok_or_else(truncated)?;
+                offset += 2;
+                i64::from(i16::from_be_bytes([delta[0], delta[1]]))
+            }
+            _ => return Err(anyhow::anyhow!("Reserved packet status symbol")),
+        };
+        arrival += ticks * DELTA_TICK_US;
+        packets.push(PacketStatus {
+            sequence,
+            arrival_us: Some(arrival),
+        });
+    }
+
+    Ok(TransportFeedback {
+        sender_ssrc: word(4),
+        media_ssrc: word(8),
+        feedback_count,
+        packets,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SentPacket {
+    send_us: i64,
+    size: usize,
+}
+
+/// Packets sent within one burst
+#[derive(Debug, Clone, Copy)]
+struct PacketGroup {
+    first_send_us: i64,
+    last_send_us: i64,
+    last_arrival_us: i64,
+}
+
+/// Least-squares slope of the accumulated delay variation
+#[derive(Debug, Default)]
+struct Trendline {
+    first_arrival_us: Option<i64>,
+    accumulated_delay_ms: f64,
+    smoothed_delay_ms: f64,
+    /// (arrival ms since the first group, smoothed delay ms)
+    history: VecDeque<(f64, f64)>,
+    samples: usize,
+}
+
+impl Trendline {
+    /// Add one inter-group delay variation; returns the scaled trend
+    fn update(&mut self, delay_variation_ms: f64, arrival_us: i64) -> Option<f64> {
+        let first = *self.first_arrival_us.get_or_insert(arrival_us);
+        self.samples += 1;
+        self.accumulated_delay_ms += delay_variation_ms;
+        self.smoothed_delay_ms = TRENDLINE_SMOOTHING * self.smoothed_delay_ms
+            + (1.0 - TRENDLINE_SMOOTHING) * self.accumulated_delay_ms;
+
+        if self.history.len() == TRENDLINE_WINDOW {
+            self.history.pop_front();
+        }
+        self.history
+            .push_back(((arrival_us - first) as f64 / 1000.0, self.smoothed_delay_ms));
+        if self.history.len() < TRENDLINE_WINDOW {
+            return None;
+        }
+
+        let n = self.history.len() as f64;
+        let mean_x = self.history.iter().map(|(x, _)| x).sum::<f64>() / n;
+        let mean_y = self.history.iter().map(|(_, y)| y).sum::<f64>() / n;
+        let (numerator, denominator) =
+            self.history.iter().fold((0.0, 0.0), |(num, den), (x, y)| {
+                (
+                    num + (x - mean_x) * (y - mean_y),
+                    den + (x - mean_x) * (x - mean_x),
+                )
+            });
+        if denominator == 0.0 {
+            return None;
+        }
+        let slope = numerator / denominator;
+        Some(slope * self.samples.min(60) as f64 * TRENDLINE_GAIN)
+    }
+}
+
+/// Compares the trend against an adaptive threshold
+#[derive(Debug)]
+struct OveruseDetector {
+    threshold: f64,
+    last_update_us: Option<i64>,
+    overuse_time_ms: f64,
+    overuse_count: u32,
+    previous_trend: f64,
+    usage: BandwidthUsage,
+}
+
+impl Default for OveruseDetector {
+    fn default() -> Self {
+        Self {
+            threshold: INITIAL_THRESHOLD,
+            last_update_us: None,
+            overuse_time_ms: 0.0,
+            overuse_count: 0,
+            previous_trend: 0.0,
+            usage: BandwidthUsage::Normal,
+        }
+    }
+}
+
+impl OveruseDetector {
+    fn detect(&mut self, trend: f64, group_interval_ms: f64, arrival_us: i64) -> BandwidthUsage {
+        if trend > self.threshold {
+            self.overuse_time_ms += group_interval_ms;
+            self.overuse_count += 1;
+            if self.overuse_time_ms > OVERUSE_TIME_MS
+                && self.overuse_count > 1
+                && trend >= self.previous_trend
+            {
+                self.overuse_time_ms = 0.0;
+                self.overuse_count = 0;
+                self.usage = BandwidthUsage::Overusing;
+            }
+        } else if trend < -self.threshold {
+            self.overuse_time_ms = 0.0;
+            self.overuse_count = 0;
+            self.usage = BandwidthUsage::Underusing;
+        } else {
+            self.overuse_time_ms = 0.0;
+            self.overuse_count = 0;
+            self.usage = BandwidthUsage::Normal;
+        }
+        self.previous_trend = trend;
+        self.update_threshold(trend, arrival_us);
+        self.usage
+    }
+
+    fn update_threshold(&mut self, trend: f64, arrival_us: i64) {
+        let last = self
+            .last_update_us
+            .replace(arrival_us)
+            .unwrap_or(arrival_us);
+        let magnitude = trend.abs();
+        // Spikes such as a route change should not drag the threshold along
+        if magnitude > self.threshold + 15.0 {
+            return;
+        }
+        let gain = if magnitude < self.threshold {
+            THRESHOLD_GAIN_DOWN
+        } else {
+            THRESHOLD_GAIN_UP
+        };
+        let elapsed_ms = ((arrival_us - last) as f64 / 1000.0).min(100.0);
+        self.threshold += gain * (magnitude - self.threshold) * elapsed_ms;
+        self.threshold = self.threshold.clamp(6.0, 600.0);
+    }
+}
+
+/// Bandwidth estimator for one outgoing transport
+#[derive(Debug)]
+pub struct GccEstimator {
+    config: GccConfig,
+    origin: Option<Instant>,
+    sent: HashMap<u16, SentPacket>,
+    sent_order: VecDeque<u16>,
+    current_group: Option<PacketGroup>,
+    previous_group: Option<PacketGroup>,
+    trendline: Trendline,
+    detector: OveruseDetector,
+    /// (arrival µs, bytes) of recently acknowledged packets
+    acked: VecDeque<(i64, usize)>,
+    delay_based: f64,
+    loss_based: f64,
+    last_update: Option<Instant>,
+    feedback_received: bool,
+}
+
+impl GccEstimator {
+    pub fn new(config: GccConfig) -> Self {
+        let start = config.start_bitrate as f64;
+        Self {
+            config,
+            origin: None,
+            sent: HashMap::new(),
+            sent_order: VecDeque::new(),
+            current_group: None,
+            previous_group: None,
+            trendline: Trendline::default(),
+            detector: OveruseDetector::default(),
+            acked: VecDeque::new(),
+            delay_based: start,
+            loss_based: start,
+            last_update: None,
+            feedback_received: false,
+        }
+    }
+
+    /// Remember a packet stamped with transport-wide sequence `sequence`
+    pub fn on_packet_sent(&mut self, sequence: u16, size: usize, at: Instant) {
+        let origin = *self.origin.get_or_insert(at);
+        let send_us = at.saturating_duration_since(origin).as_micros() as i64;
+        if self
+            .sent
+            .insert(sequence, SentPacket { send_us, size })
+            .is_none()
+        {
+            self.sent_order.push_back(sequence);
+        }
+        while self.sent_order.len() > SEND_HISTORY_LIMIT {
+            if let Some(old) = self.sent_order.pop_front() {
+                self.sent.remove(&old);
+            }
+        }
+    }
+
+    /// Apply one feedback message; returns the new estimate
+    pub fn on_feedback(&mut self, feedback: &TransportFeedback, now: Instant) -> u64 {
+        let mut reported = 0usize;
+        let mut lost = 0usize;
+        for status in &feedback.packets {
+            let Some(sent) = self.sent.remove(&status.sequence) else {
+                continue;
+            };
+            reported += 1;
+            match status.arrival_us {
+                Some(arrival_us) => {
+                    self.on_packet_acked(sent, arrival_us);
+                }
+                None => lost += 1,
+            }
+        }
+        self.feedback_received = true;
+
+        let elapsed = self
+            .last_update
+            .replace(now)
+            .map_or(Duration::ZERO, |last| now.saturating_duration_since(last));
+        self.update_delay_based(elapsed);
+        if reported > 0 {
+            self.update_loss_based(lost as f64 / reported as f64);
+        }
+        self.estimate()
+    }
+
+    fn on_packet_acked(&mut self, sent: SentPacket, arrival_us: i64) {
+        if let Some(&(latest, _)) = self.acked.back() {
+            let window = ACKED_WINDOW.as_micros() as i64;
+            while self
+                .acked
+                .front()
+                .is_some_and(|(at, _)| latest.max(arrival_us) - at > window)
+            {
+                self.acked.pop_front();
+            }
+        }
+        self.acked.push_back((arrival_us, sent.size));
+
+        match &mut self.current_group {
+            Some(group) if sent.send_us - group.first_send_us <= BURST_SPAN_US => {
+                group.last_send_us = group.last_send_us.max(sent.send_us);
+                group.last_arrival_us = group.last_arrival_us.max(arrival_us);
+            }
+            Some(group) if sent.send_us < group.first_send_us => {
+                // Reordered in the network; it belongs to an earlier group
+            }
+            _ => {
+                let finished = self.current_group.replace(PacketGroup {
+                    first_send_us: sent.send_us,
+                    last_send_us: sent.send_us,
+                    last_arrival_us: arrival_us,
+                });
+                if let Some(finished) = finished {
+                    self.on_group_complete(finished);
+                }
+            }
+        }
+    }
+
+    fn on_group_complete(&mut self, group: PacketGroup) {
+        if let Some(previous) = self.previous_group.replace(group) {
+            let send_delta_ms = (group.last_send_us - previous.last_send_us) as f64 / 1000.0;
+            let arrival_delta_ms =
+                (group.last_arrival_us - previous.last_arrival_us) as f64 / 1000.0;
+            if let Some(trend) = self
+                .trendline
+                .update(arrival_delta_ms - send_delta_ms, group.last_arrival_us)
+            {
+                self.detector
+                    .detect(trend, send_delta_ms, group.last_arrival_us);
+            }
+        }
+    }
+
+    fn update_delay_based(&mut self, elapsed: Duration) {
+        let acked = self.acked_bitrate().map(|bps| bps as f64);
+        match self.detector.usage {
+            BandwidthUsage::Overusing => {
+                let base = acked.unwrap_or(self.delay_based);
+                self.delay_based = (BETA * base).min(self.delay_based);
+            }
+            BandwidthUsage::Underusing => {}
+            BandwidthUsage::Normal => {
+                let factor = INCREASE_PER_SECOND.powf(elapsed.as_secs_f64().min(1.0));
+                let mut increased = self.delay_based * factor;
+                // Never run far ahead of what the link has been seen to carry
+                if let Some(acked) = acked {
+                    increased = increased.min(1.5 * acked + 10_000.0).max(self.delay_based);
+                }
+                self.delay_based = increased;
+            }
+        }
+        self.delay_based = self.clamp(self.delay_based);
+    }
+
+    fn update_loss_based(&mut self, loss: f64) {
+        if loss > 0.10 {
+            self.loss_based *= 1.0 - 0.5 * loss;
+        } else if loss < 0.02 {
+            self.loss_based *= 1.05;
+        }
+        self.loss_based = self.clamp(self.loss_based);
+    }
+
+    fn clamp(&self, bitrate: f64) -> f64 {
+        bitrate.clamp(
+            self.config.min_bitrate as f64,
+            self.config.max_bitrate as f64,
+        )
+    }
+
+    /// Estimated available bandwidth in bits per second
+    pub fn estimate(&self) -> u64 {
+        self.delay_based.min(self.loss_based) as u64
+    }
+
+    /// Whether any feedback has arrived yet
+    pub fn has_feedback(&self) -> bool {
+        self.feedback_received
+    }
+
+    pub fn usage(&self) -> BandwidthUsage {
+        self.detector.usage
+    }
+
+    /// Throughput the receiver acknowledged over the recent window
+    pub fn acked_bitrate(&self) -> Option<u64> {
+        let (first, _) = self.acked.front()?;
+        let (last, _) = self.acked.back()?;
+        let span_us = (last - first).max(ACKED_WINDOW.as_micros() as i64 / 5);
+        let bytes: usize = self.acked.iter().map(|(_, size)| size).sum();
+        Some((bytes as i64 * 8 * 1_000_000 / span_us) as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feedback_round_trip_and_run_length() {
+        let feedback = TransportFeedback {
+            sender_ssrc: 1,
+            media_ssrc: 2,
+            feedback_count: 7,
+            packets: vec![
+                PacketStatus {
+                    sequence: 65534,
+                    arrival_us: Some(1_000_250),
+                },
+                PacketStatus {
+                    sequence: 65535,
+                    arrival_us: None,
+                },
+                PacketStatus {
+                    sequence: 0,
+                    arrival_us: Some(1_001_000),
+                },
+                // Out of order: a negative (large) delta
+                PacketStatus {
+                    sequence: 1,
+                    arrival_us: Some(999_000),
+                },
+            ],
+        };
+        let bytes = feedback.encode().unwrap();
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(parse_transport_feedback(&bytes).unwrap(), vec![feedback]);
+
+        // Hand-built: run of three small deltas, then a one-bit vector chunk
+        let mut packet = vec![0x8F, 205, 0, 7, 0, 0, 0, 1, 0, 0, 0, 2];
+        packet.extend_from_slice(&[0, 10, 0, 5, 0, 0, 1, 0]);
+        packet.extend_from_slice(&[0x20, 0x03, 0xA0, 0x00]);
+        packet.extend_from_slice(&[4, 4, 4, 8, 0, 0, 0, 0]);
+        let parsed = parse_transport_feedback(&packet).unwrap();
+        let arrivals: Vec<_> = parsed[0].packets.iter().map(|p| p.arrival_us).collect();
+        assert_eq!(
+            arrivals,
+            vec![
+                Some(64_000 + 1_000),
+                Some(64_000 + 2_000),
+                Some(64_000 + 3_000),
+                Some(64_000 + 5_000),
+                None
+            ]
+        );
+        assert!(parse_transport_feedback(&packet[..24]).is_err());
+    }
+
+    /// Send `count` 1200-byte packets at `rate_bps`, delivered through a
+    /// bottleneck of `capacity_bps`; returns the estimate after each second
+    fn simulate(
+        estimator: &mut GccEstimator,
+        start: Instant,
+        first_sequence: u16,
+        seconds: u64,
+        rate_bps: u64,
+        capacity_bps: u64,
+    ) -> Vec<u64> {
+        let interval_us = 1200 * 8 * 1_000_000 / rate_bps as i64;
+        let service_us = 1200 * 8 * 1_000_000 / capacity_bps as i64;
+        let packets = (seconds * 1_000_000) as i64 / interval_us;
+        let mut queue_free_us = 0i64;
+        let mut pending = Vec::new();
+        let mut estimates = Vec::new();
+        let mut next_feedback_us = 100_000;
+        for i in 0..packets {
+            let send_us = i * interval_us;
+            let sequence = first_sequence.wrapping_add(i as u16);
+            estimator.on_packet_sent(
+                sequence,
+                1200,
+                start + Duration::from_micros(send_us as u64),
+            );
+            queue_free_us = queue_free_us.max(send_us) + service_us;
+            pending.push(PacketStatus {
+                sequence,
+                arrival_us: Some(queue_free_us + 20_000),
+            });
+            if send_us >= next_feedback_us {
+                let feedback = TransportFeedback {
+                    sender_ssrc: 1,
+                    media_ssrc: 2,
+                    feedback_count: 0,
+                    packets: std::mem::take(&mut pending),
+                };
+                let now = start + Duration::from_micros(send_us as u64);
+                let estimate = estimator.on_feedback(&feedback, now);
+                if next_feedback_us % 1_000_000 == 0 {
+                    estimates.push(estimate);
+                }
+                next_feedback_us += 100_000;
+            }
+        }
+        estimates
+    }
+
+    #[test]
+    fn test_estimate_tracks_bottleneck() {
+        let start = Instant::now();
+        let mut estimator = GccEstimator::new(GccConfig {
+            min_bitrate: 100_000,
+            max_bitrate: 10_000_000,
+            start_bitrate: 1_000_000,
+        });
+        assert!(!estimator.has_feedback());
+
+        // Plenty of headroom: the estimate climbs
+        let estimates = simulate(&mut estimator, start, 0, 3, 1_000_000, 5_000_000);
+        assert!(estimates.last().unwrap() > &1_000_000, "{:?}", estimates);
+        assert_eq!(estimator.usage(), BandwidthUsage::Normal);
+
+        // Sending 3 Mbit/s into a 1.5 Mbit/s bottleneck: the queue builds,
+        // over-use is detected and the estimate drops near the capacity
+        let mut estimator = GccEstimator::new(GccConfig {
+            min_bitrate: 100_000,
+            max_bitrate: 10_000_000,
+            start_bitrate: 3_000_000,
+        });
+        simulate(&mut estimator, start, 100, 2, 3_000_000, 1_500_000);
+        let estimate = estimator.estimate();
+        assert!(estimate < 1_800_000, "{}", estimate);
+        assert!(estimate > 500_000, "{}", estimate);
+    }
+}
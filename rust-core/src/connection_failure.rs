@@ -0,0 +1,336 @@
+//! Connection Failure Classification
+//!
+//! A failed connection can stall at many points: the request never reaches
+//! the host, nobody answers the prompt, ICE finds no path, DTLS refuses the
+//! peer. Raw errors from those layers mean little to a user, so this module
+//! folds the stage that failed, the network facts known at the time (NAT
+//! type, server reachability) and explicit denials into one
+//! `FailureCategory` with a stable remediation code the UI can act on.
+
+use crate::session_bootstrap::{BootstrapSnapshot, BootstrapState};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "diagnostics")]
+use crate::diagnostics::{NatType, NetworkDiagnostics};
+
+/// What is known about a failed connection attempt
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FailureContext {
+    /// Bootstrap state the attempt was in when it failed
+    pub stage: Option<BootstrapState>,
+    /// The stage overran its deadline rather than failing outright
+    pub timed_out: bool,
+    /// ICE checks ended in the failed state
+    pub ice_failed: bool,
+    /// DTLS handshake or certificate verification failed
+    pub dtls_failed: bool,
+    /// The host's user or policy rejected the request
+    pub auth_denied: bool,
+    /// Signaling reported the host as not connected
+    pub remote_offline: bool,
+    pub internet_connected: Option<bool>,
+    pub signaling_reachable: Option<bool>,
+    /// Outbound UDP is blocked by a firewall
+    pub udp_blocked: Option<bool>,
+    /// Local NAT maps each destination to a different port
+    pub symmetric_nat: bool,
+    /// At least one TURN server answered
+    pub turn_available: Option<bool>,
+}
+
+impl FailureContext {
+    /// Stage and timeout information from a closed bootstrap
+    pub fn from_bootstrap(snapshot: &BootstrapSnapshot) -> Self {
+        let stage = snapshot.timed_out_in.or(match snapshot.state {
+            BootstrapState::Closing | BootstrapState::Idle => snapshot.previous,
+            state => Some(state),
+        });
+        Self {
+            stage,
+            timed_out: snapshot.timed_out_in.is_some(),
+            ..Default::default()
+        }
+    }
+
+    /// Fill in the network facts from the latest diagnostics run
+    #[cfg(feature = "diagnostics")]
+    pub fn with_network(mut self, diagnostics: &NetworkDiagnostics) -> Self {
+        self.internet_connected = Some(diagnostics.internet_connected);
+        self.signaling_reachable = Some(diagnostics.signaling_server.reachable);
+        self.udp_blocked = match diagnostics.nat_type {
+            NatType::Unknown => None,
+            NatType::Blocked => Some(true),
+            _ => Some(false),
+        };
+        self.symmetric_nat = matches!(
+            diagnostics.nat_type,
+            NatType::Symmetric | NatType::SymmetricUdpFirewall
+        );
+        if !diagnostics.turn_servers.is_empty() {
+            self.turn_available = Some(diagnostics.turn_servers.iter().any(|s| s.reachable));
+        }
+        self
+    }
+}
+
+/// Why a connection could not be established
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FailureCategory {
+    /// This device has no internet access
+    NoNetwork,
+    SignalingUnreachable,
+    /// The host is not connected to signaling or never received the request
+    HostOffline,
+    PermissionDenied,
+    /// Nobody answered the access prompt in time
+    NoResponse,
+    /// UDP is blocked and no relay could be used
+    UdpBlocked,
+    /// Symmetric NAT defeated hole punching and no relay could be used
+    NoRelayPath,
+    /// ICE found no working candidate pair for another reason
+    NetworkPathFailed,
+    /// SDP offer/answer did not complete
+    NegotiationFailed,
+    /// DTLS handshake or certificate check failed
+    EncryptionFailed,
+    Unknown,
+}
+
+impl FailureCategory {
+    /// Stable identifier for UI lookups and logs
+    pub fn code(self) -> &'static str {
+        match self {
+            FailureCategory::NoNetwork => "no_network",
+            FailureCategory::SignalingUnreachable => "signaling_unreachable",
+            FailureCategory::HostOffline => "host_offline",
+            FailureCategory::PermissionDenied => "permission_denied",
+            FailureCategory::NoResponse => "no_response",
+            FailureCategory::UdpBlocked => "udp_blocked",
+            FailureCategory::NoRelayPath => "no_relay_path",
+            FailureCategory::NetworkPathFailed => "network_path_failed",
+            FailureCategory::NegotiationFailed => "negotiation_failed",
+            FailureCategory::EncryptionFailed => "encryption_failed",
+            FailureCategory::Unknown => "unknown",
+        }
+    }
+
+    pub fn remediation(self) -> Remediation {
+        match self {
+            FailureCategory::NoNetwork => Remediation::CheckNetwork,
+            FailureCategory::SignalingUnreachable => Remediation::CheckSignalingServer,
+            FailureCategory::HostOffline => Remediation::BringHostOnline,
+            FailureCategory::PermissionDenied => Remediation::RequestAccess,
+            FailureCategory::NoResponse => Remediation::ContactHostUser,
+            FailureCategory::UdpBlocked | FailureCategory::NoRelayPath => {
+                Remediation::ConfigureTurn
+            }
+            FailureCategory::NetworkPathFailed => Remediation::CheckFirewall,
+            FailureCategory::NegotiationFailed => Remediation::UpdateClient,
+            FailureCategory::EncryptionFailed => Remediation::VerifyDeviceIdentity,
+            FailureCategory::Unknown => Remediation::Retry,
+        }
+    }
+}
+
+impl std::fmt::Display for FailureCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// Action the user (or an administrator) can take to fix the failure
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Remediation {
+    CheckNetwork,
+    CheckSignalingServer,
+    BringHostOnline,
+    RequestAccess,
+    ContactHostUser,
+    ConfigureTurn,
+    CheckFirewall,
+    UpdateClient,
+    VerifyDeviceIdentity,
+    Retry,
+    /// A webhook integration keeps rejecting deliveries
+    CheckWebhookEndpoint,
+    /// CPU, memory or disk is nearly exhausted
+    FreeSystemResources,
+    /// Screen capture stopped after repeated failures
+    RestartCapture,
+}
+
+impl Remediation {
+    /// Stable identifier for UI lookups and logs
+    pub fn code(self) -> &'static str {
+        match self {
+            Remediation::CheckNetwork => "check_network",
+            Remediation::CheckSignalingServer => "check_signaling_server",
+            Remediation::BringHostOnline => "bring_host_online",
+            Remediation::RequestAccess => "request_access",
+            Remediation::ContactHostUser => "contact_host_user",
+            Remediation::ConfigureTurn => "configure_turn",
+            Remediation::CheckFirewall => "check_firewall",
+            Remediation::UpdateClient => "update_client",
+            Remediation::VerifyDeviceIdentity => "verify_device_identity",
+            Remediation::Retry => "retry",
+            Remediation::CheckWebhookEndpoint => "check_webhook_endpoint",
+            Remediation::FreeSystemResources => "free_system_resources",
+            Remediation::RestartCapture => "restart_capture",
+        }
+    }
+}
+
+/// Classified failure, ready to show and log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionFailure {
+    pub category: FailureCategory,
+    pub remediation: Remediation,
+    pub stage: Option<BootstrapState>,
+    /// Short explanation with the suggested fix
+    pub message: String,
+}
+
+impl ConnectionFailure {
+    pub fn new(category: FailureCategory, stage: Option<BootstrapState>) -> Self {
+        Self {
+            category,
+            remediation: category.remediation(),
+            stage,
+            message: message_for(category).to_string(),
+        }
+    }
+}
+
+fn message_for(category: FailureCategory) -> &'static str {
+    match category {
+        FailureCategory::NoNetwork => "No internet connection — check this device's network",
+        FailureCategory::SignalingUnreachable => {
+            "Signaling server unreachable — check the server address or proxy settings"
+        }
+        FailureCategory::HostOffline => "Host offline — make sure the remote device is running",
+        FailureCategory::PermissionDenied => "Permission denied — ask the host for access",
+        FailureCategory::NoResponse => {
+            "No response to the access request — ask someone at the host to accept it"
+        }
+        FailureCategory::UdpBlocked => "UDP blocked — configure TURN",
+        FailureCategory::NoRelayPath => "Symmetric NAT with no relay — configure TURN",
+        FailureCategory::NetworkPathFailed => {
+            "No network path to the host — check firewall rules on both sides"
+        }
+        FailureCategory::NegotiationFailed => {
+            "Session negotiation failed — update both devices to the same version"
+        }
+        FailureCategory::EncryptionFailed => {
+            "Secure channel could not be established — verify the host's identity"
+        }
+        FailureCategory::Unknown => "Connection failed — try again",
+    }
+}
+
+/// Map what is known about a failure to a single category
+///
+/// Explicit signals (no network, denial, host offline) win over inferences
+/// from the stage, and transport failures are refined by the NAT facts.
+pub fn classify(context: &FailureContext) -> ConnectionFailure {
+    let category = if context.internet_connected == Some(false) {
+        FailureCategory::NoNetwork
+    } else if context.auth_denied {
+        FailureCategory::PermissionDenied
+    } else if context.signaling_reachable == Some(false) {
+        FailureCategory::SignalingUnreachable
+    } else if context.remote_offline {
+        FailureCategory::HostOffline
+    } else if context.dtls_failed {
+        FailureCategory::EncryptionFailed
+    } else if context.ice_failed
+        || (context.timed_out && context.stage == Some(BootstrapState::Connecting))
+    {
+        classify_transport(context)
+    } else {
+        match (context.stage, context.timed_out) {
+            (Some(BootstrapState::Requesting), true) => FailureCategory::HostOffline,
+            (Some(BootstrapState::Authorizing), true) => FailureCategory::NoResponse,
+            (Some(BootstrapState::Negotiating), _) => FailureCategory::NegotiationFailed,
+            _ => FailureCategory::Unknown,
+        }
+    };
+    ConnectionFailure::new(category, context.stage)
+}
+
+fn classify_transport(context: &FailureContext) -> FailureCategory {
+    let relay_usable = context.turn_available == Some(true);
+    if context.udp_blocked == Some(true) && !relay_usable {
+        FailureCategory::UdpBlocked
+    } else if context.symmetric_nat && !relay_usable {
+        FailureCategory::NoRelayPath
+    } else {
+        FailureCategory::NetworkPathFailed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_timeouts_map_to_categories() {
+        let timed_out = |stage| FailureContext {
+            stage: Some(stage),
+            timed_out: true,
+            ..Default::default()
+        };
+
+        let failure = classify(&timed_out(BootstrapState::Requesting));
+        assert_eq!(failure.category, FailureCategory::HostOffline);
+        assert_eq!(failure.remediation, Remediation::BringHostOnline);
+
+        let failure = classify(&timed_out(BootstrapState::Authorizing));
+        assert_eq!(failure.category, FailureCategory::NoResponse);
+
+        // Network facts outrank the stage
+        let failure = classify(&FailureContext {
+            signaling_reachable: Some(false),
+            ..timed_out(BootstrapState::Requesting)
+        });
+        assert_eq!(failure.category, FailureCategory::SignalingUnreachable);
+
+        let failure = classify(&FailureContext {
+            auth_denied: true,
+            stage: Some(BootstrapState::Authorizing),
+            ..Default::default()
+        });
+        assert_eq!(failure.remediation.code(), "request_access");
+    }
+
+    #[test]
+    fn test_ice_failure_refined_by_nat() {
+        let ice = FailureContext {
+            stage: Some(BootstrapState::Connecting),
+            ice_failed: true,
+            udp_blocked: Some(true),
+            turn_available: Some(false),
+            ..Default::default()
+        };
+        let failure = classify(&ice);
+        assert_eq!(failure.category, FailureCategory::UdpBlocked);
+        assert_eq!(failure.remediation, Remediation::ConfigureTurn);
+        assert_eq!(failure.message, "UDP blocked — configure TURN");
+
+        // With a working relay the block is not the explanation
+        let failure = classify(&FailureContext {
+            turn_available: Some(true),
+            ..ice.clone()
+        });
+        assert_eq!(failure.category, FailureCategory::NetworkPathFailed);
+
+        let failure = classify(&FailureContext {
+            udp_blocked: Some(false),
+            symmetric_nat: true,
+            turn_available: None,
+            ..ice
+        });
+        assert_eq!(failure.category, FailureCategory::NoRelayPath);
+    }
+}
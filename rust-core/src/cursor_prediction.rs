@@ -0,0 +1,299 @@
+//! Remote Cursor Prediction
+//!
+//! On high-RTT links the cursor baked into the video stream trails the
+//! viewer's mouse by a full round trip. The host therefore reports its cursor
+//! position as separate lightweight updates, and the viewer draws a locally
+//! predicted cursor that follows its own mouse immediately:
+//!
+//! - every local move is kept as a pending prediction;
+//! - an authoritative host update that lands within `max_divergence_px` of a
+//!   pending prediction confirms it and everything before it;
+//! - if the host disagrees for longer than `max_prediction_age`, e.g. because
+//!   an application warped the pointer, the viewer snaps to the host position.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Local predictions kept while waiting for the host to catch up
+const MAX_PENDING_PREDICTIONS: usize = 64;
+
+/// Authoritative cursor position reported by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CursorUpdate {
+    pub x: i32,
+    pub y: i32,
+    pub visible: bool,
+    /// Host time in Unix milliseconds
+    pub timestamp_ms: i64,
+}
+
+/// Host side: turns cursor samples into throttled `CursorUpdate`s
+pub struct CursorReporter {
+    min_interval: Duration,
+    last: Option<(CursorUpdate, Instant)>,
+}
+
+impl CursorReporter {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last: None,
+        }
+    }
+
+    /// Update to send for a new sample, if it changed and the interval passed
+    ///
+    /// Visibility changes are always reported.
+    pub fn report(&mut self, x: i32, y: i32, visible: bool) -> Option<CursorUpdate> {
+        if let Some((last, sent_at)) = &self.last {
+            let unchanged = last.x == x && last.y == y && last.visible == visible;
+            let throttled = last.visible == visible && sent_at.elapsed() < self.min_interval;
+            if unchanged || throttled {
+                return None;
+            }
+        }
+
+        let update = CursorUpdate {
+            x,
+            y,
+            visible,
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+        };
+        self.last = Some((update, Instant::now()));
+        Some(update)
+    }
+}
+
+impl Default for CursorReporter {
+    fn default() -> Self {
+        // Roughly one update per 120Hz display refresh
+        Self::new(Duration::from_millis(8))
+    }
+}
+
+/// Viewer-side prediction limits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CursorPredictionConfig {
+    /// Distance within which a host update confirms a prediction
+    pub max_divergence_px: f64,
+    /// How long a prediction may stay unconfirmed before snapping back
+    pub max_prediction_age: Duration,
+}
+
+impl Default for CursorPredictionConfig {
+    fn default() -> Self {
+        Self {
+            max_divergence_px: 8.0,
+            max_prediction_age: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Where the viewer should draw the cursor
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RenderedCursor {
+    pub x: i32,
+    pub y: i32,
+    pub visible: bool,
+    /// Position is a local prediction not yet confirmed by the host
+    pub predicted: bool,
+}
+
+/// Reconciliation counters
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CursorPredictionStats {
+    pub confirmed: u64,
+    /// Predictions discarded because the host disagreed for too long
+    pub corrections: u64,
+    /// Distance between the newest prediction and the last host update
+    pub last_divergence_px: f64,
+}
+
+struct Prediction {
+    x: i32,
+    y: i32,
+    at: Instant,
+}
+
+/// Viewer side: local echo of the remote cursor
+pub struct CursorPredictor {
+    config: CursorPredictionConfig,
+    enabled: bool,
+    authoritative: Option<CursorUpdate>,
+    pending: VecDeque<Prediction>,
+    stats: CursorPredictionStats,
+}
+
+impl CursorPredictor {
+    /// Create a predictor; prediction is off until enabled
+    pub fn new(config: CursorPredictionConfig) -> Self {
+        Self {
+            config,
+            enabled: false,
+            authoritative: None,
+            pending: VecDeque::new(),
+            stats: CursorPredictionStats::default(),
+        }
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        tracing::info!(
+            "Cursor prediction {}",
+            if enabled { "enabled" } else { "disabled" }
+        );
+        self.enabled = enabled;
+        if !enabled {
+            self.pending.clear();
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Record a mouse move sent to the host
+    pub fn record_local_move(&mut self, x: i32, y: i32) {
+        if !self.enabled {
+            return;
+        }
+        if self.pending.len() >= MAX_PENDING_PREDICTIONS {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(Prediction {
+            x,
+            y,
+            at: Instant::now(),
+        });
+    }
+
+    /// Reconcile with an authoritative host position
+    pub fn apply_update(&mut self, update: CursorUpdate) {
+        self.authoritative = Some(update);
+
+        let distance = |p: &Prediction| {
+            let (dx, dy) = ((p.x - update.x) as f64, (p.y - update.y) as f64);
+            (dx * dx + dy * dy).sqrt()
+        };
+        self.stats.last_divergence_px = self.pending.back().map(distance).unwrap_or(0.0);
+
+        if let Some(index) = self
+            .pending
+            .iter()
+            .rposition(|p| distance(p) <= self.config.max_divergence_px)
+        {
+            self.pending.drain(..=index);
+            self.stats.confirmed += 1;
+        } else if self
+            .pending
+            .front()
+            .is_some_and(|p| p.at.elapsed() > self.config.max_prediction_age)
+        {
+            tracing::debug!(
+                "Cursor prediction diverged by {:.1}px, snapping to host position",
+                self.stats.last_divergence_px
+            );
+            self.pending.clear();
+            self.stats.corrections += 1;
+        }
+    }
+
+    /// Position to draw now
+    pub fn rendered(&self) -> Option<RenderedCursor> {
+        let authoritative = self.authoritative;
+        if self.enabled {
+            if let Some(latest) = self.pending.back() {
+                return Some(RenderedCursor {
+                    x: latest.x,
+                    y: latest.y,
+                    visible: authoritative.map(|u| u.visible).unwrap_or(true),
+                    predicted: true,
+                });
+            }
+        }
+        authoritative.map(|u| RenderedCursor {
+            x: u.x,
+            y: u.y,
+            visible: u.visible,
+            predicted: false,
+        })
+    }
+
+    pub fn stats(&self) -> &CursorPredictionStats {
+        &self.stats
+    }
+}
+
+impl Default for CursorPredictor {
+    fn default() -> Self {
+        Self::new(CursorPredictionConfig::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(x: i32, y: i32) -> CursorUpdate {
+        CursorUpdate {
+            x,
+            y,
+            visible: true,
+            timestamp_ms: 0,
+        }
+    }
+
+    #[test]
+    fn test_prediction_confirmed_by_host() {
+        let mut predictor = CursorPredictor::default();
+        predictor.record_local_move(5, 5);
+        assert!(predictor.rendered().is_none());
+
+        predictor.set_enabled(true);
+        predictor.apply_update(update(0, 0));
+        predictor.record_local_move(10, 10);
+        predictor.record_local_move(20, 20);
+        predictor.record_local_move(30, 30);
+
+        let rendered = predictor.rendered().unwrap();
+        assert!(rendered.predicted);
+        assert_eq!((rendered.x, rendered.y), (30, 30));
+
+        // Host caught up to the second move
+        predictor.apply_update(update(21, 19));
+        assert_eq!(predictor.stats().confirmed, 1);
+        assert_eq!(predictor.rendered().unwrap().x, 30);
+
+        predictor.apply_update(update(30, 30));
+        let rendered = predictor.rendered().unwrap();
+        assert!(!rendered.predicted);
+        assert_eq!((rendered.x, rendered.y), (30, 30));
+
+        let mut reporter = CursorReporter::new(Duration::ZERO);
+        assert!(reporter.report(1, 1, true).is_some());
+        assert!(reporter.report(1, 1, true).is_none());
+        assert!(reporter.report(1, 1, false).is_some());
+    }
+
+    #[test]
+    fn test_divergence_snaps_to_host() {
+        let mut predictor = CursorPredictor::new(CursorPredictionConfig {
+            max_prediction_age: Duration::from_millis(10),
+            ..Default::default()
+        });
+        predictor.set_enabled(true);
+        predictor.record_local_move(100, 100);
+
+        // Host warped the pointer elsewhere; keep predicting until the limit
+        predictor.apply_update(update(500, 500));
+        assert!(predictor.rendered().unwrap().predicted);
+
+        std::thread::sleep(Duration::from_millis(20));
+        predictor.apply_update(update(500, 500));
+        let rendered = predictor.rendered().unwrap();
+        assert!(!rendered.predicted);
+        assert_eq!((rendered.x, rendered.y), (500, 500));
+        assert_eq!(predictor.stats().corrections, 1);
+        assert!(predictor.stats().last_divergence_px > 500.0);
+    }
+}
@@ -0,0 +1,280 @@
+//! Damage Tracking
+//!
+//! Finds the parts of the screen that changed since the previous frame so
+//! the encoder can skip static content; an idle desktop then costs almost
+//! nothing to stream. Sources with platform damage reports (XDamage, DXGI
+//! dirty rects, ScreenCaptureKit's `dirtyRects`) fill
+//! `VideoFrame::dirty_rects` themselves; for the rest the capture thread
+//! diffs consecutive frames here.
+//!
+//! Frames are compared in square tiles. Dirty tiles are merged into runs
+//! along each tile row, and runs with the same horizontal extent in adjacent
+//! rows into taller rectangles. Past `MAX_DIRTY_RECTS` the list collapses to
+//! its bounding box, which is cheaper for the encoder than many fragments.
+
+use crate::screen_capture::{DirtyRect, FrameFormat, VideoFrame};
+
+/// Edge length of the comparison tiles, in pixels
+pub const DAMAGE_TILE_SIZE: u32 = 32;
+
+/// Rectangles reported per frame before falling back to the bounding box
+pub const MAX_DIRTY_RECTS: usize = 64;
+
+/// Diffs consecutive packed RGBA/BGRA frames
+#[derive(Debug, Default)]
+pub struct DamageTracker {
+    previous: Option<PreviousFrame>,
+}
+
+#[derive(Debug)]
+struct PreviousFrame {
+    width: u32,
+    height: u32,
+    format: FrameFormat,
+    data: Vec<u8>,
+}
+
+impl DamageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forget the previous frame; the next one is reported fully dirty
+    ///
+    /// Needed whenever the consumer may not have seen the last frame, e.g.
+    /// after it was dropped, and on keyframe requests.
+    pub fn reset(&mut self) {
+        self.previous = None;
+    }
+
+    /// Fill in `frame.dirty_rects` relative to the previous frame
+    ///
+    /// Rects the source already reported are kept. Frames in planar formats,
+    /// or after a size or format change, are left as fully dirty (`None`).
+    pub fn track(&mut self, frame: &mut VideoFrame) {
+        let packed = matches!(frame.format, FrameFormat::RGBA | FrameFormat::BGRA)
+            && frame.data.len() == frame.width as usize * frame.height as usize * 4;
+        if !packed {
+            self.previous = None;
+            return;
+        }
+
+        if frame.dirty_rects.is_none() {
+            frame.dirty_rects = self
+                .previous
+                .as_ref()
+                .filter(|p| {
+                    (p.width, p.height, p.format) == (frame.width, frame.height, frame.format)
+                })
+                .map(|p| diff(&p.data, &frame.data, frame.width, frame.height));
+        }
+
+        match &mut self.previous {
+            Some(previous) => {
+                previous.width = frame.width;
+                previous.height = frame.height;
+                previous.format = frame.format;
+                previous.data.clone_from(&frame.data);
+            }
+            None => {
+                self.previous = Some(PreviousFrame {
+                    width: frame.width,
+                    height: frame.height,
+                    format: frame.format,
+                    data: frame.data.clone(),
+                })
+            }
+        }
+    }
+}
+
+/// Changed rectangles between two packed 4-byte-per-pixel frames
+fn diff(previous: &[u8], current: &[u8], width: u32, height: u32) -> Vec<DirtyRect> {
+    let stride = width as usize * 4;
+    let tiles_x = width.div_ceil(DAMAGE_TILE_SIZE);
+    let tiles_y = height.div_ceil(DAMAGE_TILE_SIZE);
+
+    let mut done: Vec<DirtyRect> = Vec::new();
+    // Rects still growing downwards, ending at the previous tile row
+    let mut open: Vec<DirtyRect> = Vec::new();
+
+    for ty in 0..tiles_y {
+        let y = ty * DAMAGE_TILE_SIZE;
+        let rows = DAMAGE_TILE_SIZE.min(height - y);
+        let tile_dirty = |tx: u32| {
+            let x = tx * DAMAGE_TILE_SIZE;
+            let bytes = DAMAGE_TILE_SIZE.min(width - x) as usize * 4;
+            (y..y + rows).any(|row| {
+                let start = row as usize * stride + x as usize * 4;
+                previous[start..start + bytes] != current[start..start + bytes]
+            })
+        };
+
+        let mut runs = Vec::new();
+        let mut tx = 0;
+        while tx < tiles_x {
+            if !tile_dirty(tx) {
+                tx += 1;
+                continue;
+            }
+            let first = tx;
+            tx += 1;
+            while tx < tiles_x && tile_dirty(tx) {
+                tx += 1;
+            }
+            let x = first * DAMAGE_TILE_SIZE;
+            runs.push(DirtyRect {
+                x,
+                y,
+                width: (tx * DAMAGE_TILE_SIZE).min(width) - x,
+                height: rows,
+            });
+        }
+
+        let mut still_open = Vec::with_capacity(runs.len());
+        for run in runs {
+            match open
+                .iter()
+                .position(|r| r.x == run.x && r.width == run.width)
+            {
+                Some(index) => {
+                    let mut rect = open.swap_remove(index);
+                    rect.height += run.height;
+                    still_open.push(rect);
+                }
+                None => still_open.push(run),
+            }
+        }
+        done.append(&mut open);
+        open = still_open;
+    }
+    done.append(&mut open);
+
+    if done.len() > MAX_DIRTY_RECTS {
+        return vec![bounding_box(&done)];
+    }
+    done.sort_by_key(|r| (r.y, r.x));
+    done
+}
+
+fn bounding_box(rects: &[DirtyRect]) -> DirtyRect {
+    let left = rects.iter().map(|r| r.x).min().unwrap_or(0);
+    let top = rects.iter().map(|r| r.y).min().unwrap_or(0);
+    let right = rects.iter().map(|r| r.x + r.width).max().unwrap_or(0);
+    let bottom = rects.iter().map(|r| r.y + r.height).max().unwrap_or(0);
+    DirtyRect {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u32, height: u32, data: Vec<u8>) -> VideoFrame {
+        VideoFrame {
+            id: 0,
+            timestamp: 0,
+            width,
+            height,
+            data,
+            format: FrameFormat::BGRA,
+            dirty_rects: None,
+        }
+    }
+
+    fn paint(data: &mut [u8], width: u32, x: u32, y: u32) {
+        let offset = (y * width + x) as usize * 4;
+        data[offset..offset + 4].copy_from_slice(&[0xFF; 4]);
+    }
+
+    #[test]
+    fn test_changed_tiles_reported_and_merged() {
+        // 100x70 leaves partial tiles on the right and bottom edges
+        let (width, height) = (100, 70);
+        let blank = vec![0u8; (width * height * 4) as usize];
+        let mut tracker = DamageTracker::new();
+
+        let mut first = frame(width, height, blank.clone());
+        tracker.track(&mut first);
+        assert_eq!(first.dirty_rects, None);
+
+        let mut unchanged = frame(width, height, blank.clone());
+        tracker.track(&mut unchanged);
+        assert_eq!(unchanged.dirty_rects, Some(Vec::new()));
+
+        let mut data = blank.clone();
+        // Tiles (0,0) and (0,1) stack into one column; (3,2) is a clipped corner
+        paint(&mut data, width, 5, 5);
+        paint(&mut data, width, 20, 40);
+        paint(&mut data, width, 99, 69);
+        let mut changed = frame(width, height, data);
+        tracker.track(&mut changed);
+        assert_eq!(
+            changed.dirty_rects,
+            Some(vec![
+                DirtyRect {
+                    x: 0,
+                    y: 0,
+                    width: 32,
+                    height: 64
+                },
+                DirtyRect {
+                    x: 96,
+                    y: 64,
+                    width: 4,
+                    height: 6
+                },
+            ])
+        );
+
+        // Reverting is a change as well
+        let mut reverted = frame(width, height, blank);
+        tracker.track(&mut reverted);
+        assert_eq!(reverted.dirty_rects.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_reset_resize_and_fragmentation_fall_back() {
+        let (width, height) = (DAMAGE_TILE_SIZE * 20, DAMAGE_TILE_SIZE * 10);
+        let blank = vec![0u8; (width * height * 4) as usize];
+        let mut tracker = DamageTracker::new();
+        tracker.track(&mut frame(width, height, blank.clone()));
+
+        // A checkerboard of dirty tiles exceeds the rect limit
+        let mut data = blank.clone();
+        for ty in 0..10 {
+            for tx in (ty % 2..20).step_by(2) {
+                paint(
+                    &mut data,
+                    width,
+                    tx * DAMAGE_TILE_SIZE,
+                    ty * DAMAGE_TILE_SIZE,
+                );
+            }
+        }
+        let mut checkered = frame(width, height, data);
+        tracker.track(&mut checkered);
+        assert_eq!(
+            checkered.dirty_rects,
+            Some(vec![DirtyRect {
+                x: 0,
+                y: 0,
+                width,
+                height
+            }])
+        );
+
+        tracker.reset();
+        let mut after_reset = frame(width, height, blank.clone());
+        tracker.track(&mut after_reset);
+        assert_eq!(after_reset.dirty_rects, None);
+
+        let mut resized = frame(width / 2, height, blank[..blank.len() / 2].to_vec());
+        tracker.track(&mut resized);
+        assert_eq!(resized.dirty_rects, None);
+    }
+}
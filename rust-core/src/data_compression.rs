@@ -0,0 +1,276 @@
+//! Data Channel Compression
+//!
+//! Clipboard text, chat, remote file listings and system info travel over the
+//! control data channel as JSON, which typically shrinks to a fraction of its
+//! size. Peers advertise the algorithms they can decode in
+//! `DeviceCapabilities::data_compression`; once both sides agree on one, every
+//! message on the channel carries a one-byte header saying whether (and how)
+//! its payload is compressed. Small or incompressible messages are sent as is
+//! under the same header. Without an agreed algorithm no header is added, so
+//! older peers keep working.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+/// Messages smaller than this are not worth compressing
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 256;
+
+/// Largest payload a compressed message may expand to
+pub const MAX_DECOMPRESSED_SIZE: usize = 16 * 1024 * 1024;
+
+const TAG_RAW: u8 = 0;
+const TAG_ZSTD: u8 = 1;
+const TAG_LZ4: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CompressionAlgorithm {
+    /// Better ratio, more CPU
+    Zstd,
+    /// Cheaper, for weak devices
+    Lz4,
+}
+
+impl CompressionAlgorithm {
+    /// Algorithms this build can decode, most preferred first
+    pub fn supported() -> Vec<Self> {
+        vec![Self::Zstd, Self::Lz4]
+    }
+
+    fn tag(self) -> u8 {
+        match self {
+            Self::Zstd => TAG_ZSTD,
+            Self::Lz4 => TAG_LZ4,
+        }
+    }
+
+    fn compress(self, payload: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Zstd => ruzstd::encoding::compress_to_vec(
+                payload,
+                ruzstd::encoding::CompressionLevel::Fastest,
+            ),
+            Self::Lz4 => lz4_flex::compress_prepend_size(payload),
+        }
+    }
+}
+
+/// First of our preferred algorithms the peer supports
+///
+/// `None` if there is none, or the peer predates compression.
+pub fn negotiate_compression(
+    local: &[CompressionAlgorithm],
+    remote: &[CompressionAlgorithm],
+) -> Option<CompressionAlgorithm> {
+    local.iter().find(|a| remote.contains(a)).copied()
+}
+
+/// Compression counters of one channel
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompressionStats {
+    pub messages_compressed: u64,
+    /// Sent as is: below the threshold or incompressible
+    pub messages_uncompressed: u64,
+    /// Size of compressed messages before compression
+    pub original_bytes: u64,
+    /// Size of compressed messages on the wire
+    pub compressed_bytes: u64,
+    pub messages_decompressed: u64,
+}
+
+impl CompressionStats {
+    /// Original over compressed size of the messages that were compressed
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 1.0;
+        }
+        self.original_bytes as f64 / self.compressed_bytes as f64
+    }
+}
+
+/// Frames messages of one data channel
+#[derive(Debug, Clone)]
+pub struct DataChannelCodec {
+    algorithm: Option<CompressionAlgorithm>,
+    threshold: usize,
+    stats: CompressionStats,
+}
+
+impl DataChannelCodec {
+    /// Codec for the negotiated algorithm; `None` passes messages through
+    pub fn new(algorithm: Option<CompressionAlgorithm>) -> Self {
+        Self {
+            algorithm,
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            stats: CompressionStats::default(),
+        }
+    }
+
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn algorithm(&self) -> Option<CompressionAlgorithm> {
+        self.algorithm
+    }
+
+    pub fn stats(&self) -> CompressionStats {
+        self.stats
+    }
+
+    /// Turn an outgoing payload into a channel message
+    pub fn encode(&mut self, payload: Vec<u8>) -> Vec<u8> {
+        let Some(algorithm) = self.algorithm else {
+            return payload;
+        };
+        if payload.len() >= self.threshold {
+            let compressed = algorithm.compress(&payload);
+            if compressed.len() < payload.len() {
+                self.stats.messages_compressed += 1;
+                self.stats.original_bytes += payload.len() as u64;
+                self.stats.compressed_bytes += compressed.len() as u64 + 1;
+                return framed(algorithm.tag(), &compressed);
+            }
+        }
+        self.stats.messages_uncompressed += 1;
+        framed(TAG_RAW, &payload)
+    }
+
+    /// Recover the payload of an incoming channel message
+    pub fn decode(&mut self, message: &[u8]) -> Result<Vec<u8>> {
+        if self.algorithm.is_none() {
+            return Ok(message.to_vec());
+        }
+        let (&tag, body) = message
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("Empty data channel message"))?;
+        let payload = match tag {
+            TAG_RAW => return Ok(body.to_vec()),
+            TAG_ZSTD => {
+                let mut payload = Vec::new();
+                ruzstd::decoding::StreamingDecoder::new(body)
+                    .map_err(|e| anyhow::anyhow!("Invalid zstd message: {}", e))?
+                    .take(MAX_DECOMPRESSED_SIZE as u64 + 1)
+                    .read_to_end(&mut payload)?;
+                if payload.len() > MAX_DECOMPRESSED_SIZE {
+                    return Err(too_large());
+                }
+                payload
+            }
+            TAG_LZ4 => {
+                // The size prefix is checked before anything is allocated
+                let size = body
+                    .get(..4)
+                    .and_then(|prefix| prefix.try_into().ok())
+                    .map(u32::from_le_bytes)
+                    .ok_or_else(|| anyhow::anyhow!("Truncated lz4 message"))?;
+                if size as usize > MAX_DECOMPRESSED_SIZE {
+                    return Err(too_large());
+                }
+                lz4_flex::decompress_size_prepended(body)
+                    .map_err(|e| anyhow::anyhow!("Invalid lz4 message: {}", e))?
+            }
+            other => {
+                return Err(anyhow::anyhow!(
+                    "Unknown data channel compression tag {}",
+                    other
+                ))
+            }
+        };
+        self.stats.messages_decompressed += 1;
+        Ok(payload)
+    }
+}
+
+fn framed(tag: u8, body: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(body.len() + 1);
+    message.push(tag);
+    message.extend_from_slice(body);
+    message
+}
+
+fn too_large() -> anyhow::Error {
+    anyhow::anyhow!(
+        "Data channel message expands beyond {} bytes",
+        MAX_DECOMPRESSED_SIZE
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing() -> Vec<u8> {
+        let entries: Vec<String> = (0..200)
+            .map(|i| {
+                format!(
+                    r#"{{"name":"report-{}.pdf","size":{},"dir":false}}"#,
+                    i,
+                    i * 37
+                )
+            })
+            .collect();
+        format!("[{}]", entries.join(",")).into_bytes()
+    }
+
+    #[test]
+    fn test_round_trip_and_stats() {
+        for algorithm in CompressionAlgorithm::supported() {
+            let mut sender = DataChannelCodec::new(Some(algorithm));
+            let mut receiver = DataChannelCodec::new(Some(algorithm));
+
+            let payload = listing();
+            let message = sender.encode(payload.clone());
+            assert!(message.len() < payload.len() / 3, "{:?}", algorithm);
+            assert_eq!(receiver.decode(&message).unwrap(), payload);
+
+            // Below the threshold: header only
+            let message = sender.encode(b"ok".to_vec());
+            assert_eq!(message, [TAG_RAW, b'o', b'k']);
+            assert_eq!(receiver.decode(&message).unwrap(), b"ok");
+
+            let stats = sender.stats();
+            assert_eq!(
+                (stats.messages_compressed, stats.messages_uncompressed),
+                (1, 1)
+            );
+            assert!(stats.ratio() > 3.0);
+            assert_eq!(receiver.stats().messages_decompressed, 1);
+        }
+
+        // Incompressible payloads go out as is
+        let mut codec = DataChannelCodec::new(Some(CompressionAlgorithm::Lz4)).with_threshold(0);
+        let noise: Vec<u8> = (0..64u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect();
+        assert_eq!(codec.encode(noise.clone())[1..], noise[..]);
+    }
+
+    #[test]
+    fn test_negotiation_and_hostile_input() {
+        let supported = CompressionAlgorithm::supported();
+        assert_eq!(
+            negotiate_compression(&supported, &[CompressionAlgorithm::Lz4]),
+            Some(CompressionAlgorithm::Lz4)
+        );
+        assert_eq!(
+            negotiate_compression(&supported, &supported),
+            Some(CompressionAlgorithm::Zstd)
+        );
+        assert_eq!(negotiate_compression(&supported, &[]), None);
+
+        // Old peer: no framing either way
+        let mut plain = DataChannelCodec::new(None);
+        assert_eq!(plain.encode(listing()), listing());
+        assert_eq!(plain.decode(b"{}").unwrap(), b"{}");
+
+        let mut codec = DataChannelCodec::new(Some(CompressionAlgorithm::Zstd));
+        assert!(codec.decode(&[]).is_err());
+        assert!(codec.decode(&[9, 1, 2]).is_err());
+        assert!(codec.decode(&[TAG_ZSTD, 1, 2, 3]).is_err());
+        // A size prefix claiming 4 GiB is refused up front
+        assert!(codec.decode(&[TAG_LZ4, 0xFF, 0xFF, 0xFF, 0xFF, 0]).is_err());
+        assert!(codec.decode(&[TAG_LZ4, 1]).is_err());
+    }
+}
@@ -0,0 +1,931 @@
+//! Minimal D-Bus Client
+//!
+//! Blocking client for the session and system buses, covering what the host
+//! needs from freedesktop services on Linux: the Secret Service for
+//! credentials and the XDG desktop portals. It speaks the wire protocol over
+//! the bus socket with EXTERNAL authentication, so no libdbus is linked and
+//! a missing bus is an ordinary error.
+//!
+//! Values are marshalled from [`Value`] trees; Unix file descriptors are
+//! not supported.
+
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::time::{Duration, Instant};
+
+/// Well-known name, path and interface of the bus itself
+pub const BUS_NAME: &str = "org.freedesktop.DBus";
+const BUS_PATH: &str = "/org/freedesktop/DBus";
+/// Standard properties interface
+pub const PROPERTIES_INTERFACE: &str = "org.freedesktop.DBus.Properties";
+
+/// How long a method call waits for its reply by default
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(25);
+
+const SYSTEM_BUS_SOCKET: &str = "/var/run/dbus/system_bus_socket";
+/// Messages larger than this are refused, as by the reference daemon
+const MAX_MESSAGE_SIZE: usize = 128 * 1024 * 1024;
+
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+const SIGNAL: u8 = 4;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// A D-Bus value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Byte(u8),
+    Bool(bool),
+    Int16(i16),
+    Uint16(u16),
+    Int32(i32),
+    Uint32(u32),
+    Int64(i64),
+    Uint64(u64),
+    Double(f64),
+    Str(String),
+    ObjectPath(String),
+    Signature(String),
+    Variant(Box<Value>),
+    /// Element signature and elements
+    Array(String, Vec<Value>),
+    Struct(Vec<Value>),
+    DictEntry(Box<Value>, Box<Value>),
+}
+
+impl Value {
+    pub fn str(s: &str) -> Self {
+        Value::Str(s.to_string())
+    }
+
+    pub fn path(p: &str) -> Self {
+        Value::ObjectPath(p.to_string())
+    }
+
+    pub fn variant(value: Value) -> Self {
+        Value::Variant(Box::new(value))
+    }
+
+    /// `ay`
+    pub fn bytes(bytes: &[u8]) -> Self {
+        Value::Array(
+            "y".to_string(),
+            bytes.iter().copied().map(Value::Byte).collect(),
+        )
+    }
+
+    /// `a{ss}`
+    pub fn string_dict<'a>(entries: impl IntoIterator<Item = (&'a str, &'a str)>) -> Self {
+        Value::Array(
+            "{ss}".to_string(),
+            entries
+                .into_iter()
+                .map(|(k, v)| Value::DictEntry(Box::new(Value::str(k)), Box::new(Value::str(v))))
+                .collect(),
+        )
+    }
+
+    /// `a{sv}`
+    pub fn variant_dict(entries: impl IntoIterator<Item = (&'static str, Value)>) -> Self {
+        Value::Array(
+            "{sv}".to_string(),
+            entries
+                .into_iter()
+                .map(|(k, v)| {
+                    Value::DictEntry(Box::new(Value::str(k)), Box::new(Value::variant(v)))
+                })
+                .collect(),
+        )
+    }
+
+    /// Type signature of this value
+    pub fn signature(&self) -> String {
+        match self {
+            Value::Byte(_) => "y".to_string(),
+            Value::Bool(_) => "b".to_string(),
+            Value::Int16(_) => "n".to_string(),
+            Value::Uint16(_) => "q".to_string(),
+            Value::Int32(_) => "i".to_string(),
+            Value::Uint32(_) => "u".to_string(),
+            Value::Int64(_) => "x".to_string(),
+            Value::Uint64(_) => "t".to_string(),
+            Value::Double(_) => "d".to_string(),
+            Value::Str(_) => "s".to_string(),
+            Value::ObjectPath(_) => "o".to_string(),
+            Value::Signature(_) => "g".to_string(),
+            Value::Variant(_) => "v".to_string(),
+            Value::Array(element, _) => format!("a{}", element),
+            Value::Struct(fields) => {
+                format!(
+                    "({})",
+                    fields.iter().map(Value::signature).collect::<String>()
+                )
+            }
+            Value::DictEntry(k, v) => format!("{{{}{}}}", k.signature(), v.signature()),
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) | Value::ObjectPath(s) | Value::Signature(s) => Some(s),
+            Value::Variant(v) => v.as_str(),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            Value::Variant(v) => v.as_bool(),
+            _ => None,
+        }
+    }
+
+    pub fn as_u32(&self) -> Option<u32> {
+        match self {
+            Value::Uint32(n) => Some(*n),
+            Value::Variant(v) => v.as_u32(),
+            _ => None,
+        }
+    }
+
+    /// Elements of an array, or fields of a struct
+    pub fn items(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(_, items) | Value::Struct(items) => Some(items),
+            Value::Variant(v) => v.items(),
+            _ => None,
+        }
+    }
+
+    /// Contents of an `ay`
+    pub fn as_bytes(&self) -> Option<Vec<u8>> {
+        self.items()?
+            .iter()
+            .map(|b| match b {
+                Value::Byte(b) => Some(*b),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Value under a string key of a dictionary, with variants unwrapped
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.items()?.iter().find_map(|entry| match entry {
+            Value::DictEntry(k, v) if k.as_str() == Some(key) => Some(match v.as_ref() {
+                Value::Variant(inner) => inner.as_ref(),
+                v => v,
+            }),
+            _ => None,
+        })
+    }
+}
+
+/// A received message
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub kind: u8,
+    pub serial: u32,
+    pub reply_serial: Option<u32>,
+    pub path: Option<String>,
+    pub interface: Option<String>,
+    pub member: Option<String>,
+    pub error_name: Option<String>,
+    pub sender: Option<String>,
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    pub fn is_signal(&self, interface: &str, member: &str) -> bool {
+        self.kind == SIGNAL
+            && self.interface.as_deref() == Some(interface)
+            && self.member.as_deref() == Some(member)
+    }
+}
+
+/// A connection to a message bus
+pub struct Connection {
+    stream: UnixStream,
+    next_serial: u32,
+    unique_name: String,
+    /// Signals read while waiting for a reply
+    queued: VecDeque<Message>,
+}
+
+impl Connection {
+    /// Connect to the session bus named by `DBUS_SESSION_BUS_ADDRESS`, or the
+    /// conventional `$XDG_RUNTIME_DIR/bus`
+    pub fn session() -> Result<Self> {
+        let address = match std::env::var("DBUS_SESSION_BUS_ADDRESS") {
+            Ok(address) => address,
+            Err(_) => {
+                let runtime = std::env::var("XDG_RUNTIME_DIR")
+                    .context("No session bus address and no XDG_RUNTIME_DIR")?;
+                format!("unix:path={}/bus", runtime)
+            }
+        };
+        Self::open(&address)
+    }
+
+    /// Connect to the system bus
+    pub fn system() -> Result<Self> {
+        let address = std::env::var("DBUS_SYSTEM_BUS_ADDRESS")
+            .unwrap_or_else(|_| format!("unix:path={}", SYSTEM_BUS_SOCKET));
+        Self::open(&address)
+    }
+
+    /// Connect to the first reachable Unix address in a D-Bus address list
+    pub fn open(address: &str) -> Result<Self> {
+        let mut last_error = None;
+        for entry in address.split(';').filter(|a| !a.is_empty()) {
+            match connect_address(entry) {
+                Ok(stream) => return Self::handshake(stream),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No usable D-Bus address: {}", address)))
+    }
+
+    fn handshake(stream: UnixStream) -> Result<Self> {
+        stream.set_read_timeout(Some(DEFAULT_CALL_TIMEOUT))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        // SAFETY: takes no arguments
+        let uid = unsafe { getuid() };
+        writer.write_all(b"\0")?;
+        writer
+            .write_all(format!("AUTH EXTERNAL {}\r\n", hex::encode(uid.to_string())).as_bytes())?;
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        if !line.starts_with("OK ") {
+            return Err(anyhow::anyhow!(
+                "D-Bus authentication refused: {}",
+                line.trim()
+            ));
+        }
+        // The server sends nothing more until BEGIN, so the buffer is empty
+        writer.write_all(b"BEGIN\r\n")?;
+
+        let mut connection = Self {
+            stream,
+            next_serial: 1,
+            unique_name: String::new(),
+            queued: VecDeque::new(),
+        };
+        let reply = connection.call(BUS_NAME, BUS_PATH, BUS_NAME, "Hello", &[])?;
+        connection.unique_name = reply
+            .first()
+            .and_then(Value::as_str)
+            .context("Hello returned no name")?
+            .to_string();
+        Ok(connection)
+    }
+
+    /// Name the bus assigned to this connection
+    pub fn unique_name(&self) -> &str {
+        &self.unique_name
+    }
+
+    /// Call a method and wait for its reply
+    pub fn call(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        args: &[Value],
+    ) -> Result<Vec<Value>> {
+        self.call_with_timeout(
+            destination,
+            path,
+            interface,
+            member,
+            args,
+            DEFAULT_CALL_TIMEOUT,
+        )
+    }
+
+    pub fn call_with_timeout(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        args: &[Value],
+        timeout: Duration,
+    ) -> Result<Vec<Value>> {
+        let serial = self.send(
+            METHOD_CALL,
+            &[
+                (FIELD_PATH, Value::path(path)),
+                (FIELD_INTERFACE, Value::str(interface)),
+                (FIELD_MEMBER, Value::str(member)),
+                (FIELD_DESTINATION, Value::str(destination)),
+            ],
+            args,
+        )?;
+        let deadline = Instant::now() + timeout;
+        loop {
+            let message = self.read_message(deadline)?;
+            if message.reply_serial != Some(serial)
+                || !matches!(message.kind, METHOD_RETURN | ERROR)
+            {
+                if message.kind == SIGNAL {
+                    self.queued.push_back(message);
+                }
+                continue;
+            }
+            return match message.kind {
+                METHOD_RETURN => Ok(message.body),
+                _ => Err(anyhow::anyhow!(
+                    "{}.{} failed: {}{}",
+                    interface,
+                    member,
+                    message.error_name.as_deref().unwrap_or("error"),
+                    message
+                        .body
+                        .first()
+                        .and_then(Value::as_str)
+                        .map(|detail| format!(": {}", detail))
+                        .unwrap_or_default()
+                )),
+            };
+        }
+    }
+
+    /// Read a property through `org.freedesktop.DBus.Properties`
+    pub fn get_property(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        property: &str,
+    ) -> Result<Value> {
+        let mut reply = self.call(
+            destination,
+            path,
+            PROPERTIES_INTERFACE,
+            "Get",
+            &[Value::str(interface), Value::str(property)],
+        )?;
+        match reply.pop() {
+            Some(Value::Variant(value)) => Ok(*value),
+            _ => Err(anyhow::anyhow!("Property {} has no value", property)),
+        }
+    }
+
+    /// Whether a name currently has an owner on the bus
+    pub fn name_has_owner(&mut self, name: &str) -> Result<bool> {
+        self.call(
+            BUS_NAME,
+            BUS_PATH,
+            BUS_NAME,
+            "NameHasOwner",
+            &[Value::str(name)],
+        )?
+        .first()
+        .and_then(Value::as_bool)
+        .context("NameHasOwner returned no answer")
+    }
+
+    /// Subscribe to signals matching a rule, e.g.
+    /// `type='signal',interface='org.freedesktop.portal.Request'`
+    pub fn add_match(&mut self, rule: &str) -> Result<()> {
+        self.call(
+            BUS_NAME,
+            BUS_PATH,
+            BUS_NAME,
+            "AddMatch",
+            &[Value::str(rule)],
+        )
+        .map(|_| ())
+    }
+
+    /// Wait for a signal from `path` matching `interface` and `member`
+    ///
+    /// Other signals received meanwhile are dropped.
+    pub fn wait_for_signal(
+        &mut self,
+        path: &str,
+        interface: &str,
+        member: &str,
+        timeout: Duration,
+    ) -> Result<Message> {
+        let matches =
+            |m: &Message| m.is_signal(interface, member) && m.path.as_deref() == Some(path);
+        if let Some(index) = self.queued.iter().position(matches) {
+            return Ok(self.queued.remove(index).expect("index is in range"));
+        }
+        self.queued.clear();
+        let deadline = Instant::now() + timeout;
+        loop {
+            let message = self.read_message(deadline)?;
+            if matches(&message) {
+                return Ok(message);
+            }
+        }
+    }
+
+    fn send(&mut self, kind: u8, fields: &[(u8, Value)], args: &[Value]) -> Result<u32> {
+        let serial = self.next_serial;
+        self.next_serial = self.next_serial.wrapping_add(1).max(1);
+        let bytes = encode_message(kind, serial, fields, args)?;
+        self.stream.write_all(&bytes)?;
+        Ok(serial)
+    }
+
+    fn read_message(&mut self, deadline: Instant) -> Result<Message> {
+        let remaining = deadline
+            .checked_duration_since(Instant::now())
+            .filter(|d| !d.is_zero())
+            .context("Timed out waiting for D-Bus reply")?;
+        self.stream.set_read_timeout(Some(remaining))?;
+        let mut fixed = [0u8; 16];
+        self.stream
+            .read_exact(&mut fixed)
+            .context("D-Bus connection closed")?;
+        let little = match fixed[0] {
+            b'l' => true,
+            b'B' => false,
+            other => return Err(anyhow::anyhow!("Bad D-Bus endianness marker {}", other)),
+        };
+        let read_u32 = |b: &[u8]| {
+            let b = [b[0], b[1], b[2], b[3]];
+            if little {
+                u32::from_le_bytes(b)
+            } else {
+                u32::from_be_bytes(b)
+            }
+        };
+        let body_len = read_u32(&fixed[4..8]) as usize;
+        let fields_len = read_u32(&fixed[12..16]) as usize;
+        let header_len = align(16 + fields_len, 8);
+        let total = header_len
+            .checked_add(body_len)
+            .filter(|&total| total <= MAX_MESSAGE_SIZE)
+            .context("D-Bus message too large")?;
+        let mut buf = vec![0u8; total];
+        buf[..16].copy_from_slice(&fixed);
+        self.stream.read_exact(&mut buf[16..])?;
+        decode_message(&buf)
+    }
+}
+
+extern "C" {
+    fn getuid() -> u32;
+}
+
+fn connect_address(entry: &str) -> Result<UnixStream> {
+    let (transport, params) = entry.split_once(':').context("Malformed D-Bus address")?;
+    if transport != "unix" {
+        return Err(anyhow::anyhow!("Unsupported D-Bus transport {}", transport));
+    }
+    for param in params.split(',') {
+        match param.split_once('=') {
+            Some(("path", path)) => return Ok(UnixStream::connect(unescape(path))?),
+            Some(("abstract", name)) => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(unescape(name))?;
+                return Ok(UnixStream::connect_addr(&addr)?);
+            }
+            _ => {}
+        }
+    }
+    Err(anyhow::anyhow!("No socket in D-Bus address {}", entry))
+}
+
+/// Undo `%xx` escaping in an address value
+fn unescape(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).unwrap_or("");
+            if let Ok(b) = u8::from_str_radix(hex, 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn align(offset: usize, alignment: usize) -> usize {
+    offset.div_ceil(alignment) * alignment
+}
+
+fn alignment_of(signature: u8) -> usize {
+    match signature {
+        b'y' | b'g' | b'v' => 1,
+        b'n' | b'q' => 2,
+        b'x' | b't' | b'd' | b'(' | b'{' => 8,
+        _ => 4,
+    }
+}
+
+/// Serialize a message, little-endian
+pub(crate) fn encode_message(
+    kind: u8,
+    serial: u32,
+    fields: &[(u8, Value)],
+    args: &[Value],
+) -> Result<Vec<u8>> {
+    let mut body = Encoder::default();
+    for arg in args {
+        body.value(arg)?;
+    }
+    let signature: String = args.iter().map(Value::signature).collect();
+
+    let mut header_fields: Vec<Value> = fields
+        .iter()
+        .map(|(code, value)| Value::Struct(vec![Value::Byte(*code), Value::variant(value.clone())]))
+        .collect();
+    if !signature.is_empty() {
+        header_fields.push(Value::Struct(vec![
+            Value::Byte(FIELD_SIGNATURE),
+            Value::variant(Value::Signature(signature)),
+        ]));
+    }
+
+    let mut header = Encoder::default();
+    header.buf.extend_from_slice(&[b'l', kind, 0, 1]);
+    header
+        .buf
+        .extend_from_slice(&(body.buf.len() as u32).to_le_bytes());
+    header.buf.extend_from_slice(&serial.to_le_bytes());
+    header.value(&Value::Array("(yv)".to_string(), header_fields))?;
+    header.pad(8);
+    header.buf.extend_from_slice(&body.buf);
+    Ok(header.buf)
+}
+
+/// Parse a complete message
+pub(crate) fn decode_message(buf: &[u8]) -> Result<Message> {
+    let little = buf.first() == Some(&b'l');
+    let kind = *buf.get(1).context("Truncated D-Bus message")?;
+    let mut decoder = Decoder {
+        buf,
+        pos: 8,
+        little,
+    };
+    let serial = decoder.u32()?;
+    let fields = decoder.value(b"a(yv)")?;
+
+    let mut message = Message {
+        kind,
+        serial,
+        reply_serial: None,
+        path: None,
+        interface: None,
+        member: None,
+        error_name: None,
+        sender: None,
+        body: Vec::new(),
+    };
+    let mut signature = String::new();
+    for field in fields.items().unwrap_or_default() {
+        let [Value::Byte(code), value] = field.items().unwrap_or_default() else {
+            continue;
+        };
+        let text = value.as_str().map(str::to_string);
+        match *code {
+            FIELD_PATH => message.path = text,
+            FIELD_INTERFACE => message.interface = text,
+            FIELD_MEMBER => message.member = text,
+            FIELD_ERROR_NAME => message.error_name = text,
+            FIELD_REPLY_SERIAL => message.reply_serial = value.as_u32(),
+            FIELD_SENDER => message.sender = text,
+            FIELD_SIGNATURE => signature = text.unwrap_or_default(),
+            _ => {}
+        }
+    }
+
+    decoder.pos = align(decoder.pos, 8);
+    let mut rest = signature.as_bytes();
+    while !rest.is_empty() {
+        let len = single_type_len(rest)?;
+        message.body.push(decoder.value(&rest[..len])?);
+        rest = &rest[len..];
+    }
+    Ok(message)
+}
+
+/// Length of the first complete type in a signature
+fn single_type_len(signature: &[u8]) -> Result<usize> {
+    match signature.first() {
+        Some(b'a') => Ok(1 + single_type_len(&signature[1..])?),
+        Some(&open @ (b'(' | b'{')) => {
+            let close = if open == b'(' { b')' } else { b'}' };
+            let mut len = 1;
+            while signature.get(len) != Some(&close) {
+                if len >= signature.len() {
+                    return Err(anyhow::anyhow!("Unterminated container in signature"));
+                }
+                len += single_type_len(&signature[len..])?;
+            }
+            Ok(len + 1)
+        }
+        Some(_) => Ok(1),
+        None => Err(anyhow::anyhow!("Empty signature")),
+    }
+}
+
+#[derive(Default)]
+struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    fn pad(&mut self, alignment: usize) {
+        let len = align(self.buf.len(), alignment);
+        self.buf.resize(len, 0);
+    }
+
+    fn string(&mut self, s: &str) {
+        self.pad(4);
+        self.buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, s: &str) {
+        self.buf.push(s.len() as u8);
+        self.buf.extend_from_slice(s.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn value(&mut self, value: &Value) -> Result<()> {
+        match value {
+            Value::Byte(b) => self.buf.push(*b),
+            Value::Bool(b) => {
+                self.pad(4);
+                self.buf.extend_from_slice(&(*b as u32).to_le_bytes());
+            }
+            Value::Int16(n) => {
+                self.pad(2);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Uint16(n) => {
+                self.pad(2);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Int32(n) => {
+                self.pad(4);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Uint32(n) => {
+                self.pad(4);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Int64(n) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Uint64(n) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Double(n) => {
+                self.pad(8);
+                self.buf.extend_from_slice(&n.to_le_bytes());
+            }
+            Value::Str(s) | Value::ObjectPath(s) => self.string(s),
+            Value::Signature(s) => self.signature(s),
+            Value::Variant(inner) => {
+                self.signature(&inner.signature());
+                self.value(inner)?;
+            }
+            Value::Array(element, items) => {
+                self.pad(4);
+                let len_at = self.buf.len();
+                self.buf.extend_from_slice(&[0; 4]);
+                self.pad(alignment_of(*element.as_bytes().first().unwrap_or(&b'y')));
+                let start = self.buf.len();
+                for item in items {
+                    if item.signature() != *element {
+                        return Err(anyhow::anyhow!(
+                            "Array of {} holds a {}",
+                            element,
+                            item.signature()
+                        ));
+                    }
+                    self.value(item)?;
+                }
+                let len = (self.buf.len() - start) as u32;
+                self.buf[len_at..len_at + 4].copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                self.pad(8);
+                for field in fields {
+                    self.value(field)?;
+                }
+            }
+            Value::DictEntry(k, v) => {
+                self.pad(8);
+                self.value(k)?;
+                self.value(v)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    little: bool,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, alignment: usize, len: usize) -> Result<&[u8]> {
+        self.pos = align(self.pos, alignment);
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.buf.len())
+            .context("Truncated D-Bus message")?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn fixed<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes: [u8; N] = self.take(N, N)?.try_into().expect("length was checked");
+        if !self.little {
+            bytes.reverse();
+        }
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.fixed()?))
+    }
+
+    fn string(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        let bytes = self.take(1, len + 1)?;
+        Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    }
+
+    fn signature(&mut self) -> Result<String> {
+        let len = self.take(1, 1)?[0] as usize;
+        let bytes = self.take(1, len + 1)?;
+        Ok(String::from_utf8_lossy(&bytes[..len]).into_owned())
+    }
+
+    fn value(&mut self, signature: &[u8]) -> Result<Value> {
+        Ok(match signature.first().context("Empty signature")? {
+            b'y' => Value::Byte(self.take(1, 1)?[0]),
+            b'b' => Value::Bool(self.u32()? != 0),
+            b'n' => Value::Int16(i16::from_le_bytes(self.fixed()?)),
+            b'q' => Value::Uint16(u16::from_le_bytes(self.fixed()?)),
+            b'i' => Value::Int32(i32::from_le_bytes(self.fixed()?)),
+            b'u' => Value::Uint32(self.u32()?),
+            b'h' => Value::Uint32(self.u32()?),
+            b'x' => Value::Int64(i64::from_le_bytes(self.fixed()?)),
+            b't' => Value::Uint64(u64::from_le_bytes(self.fixed()?)),
+            b'd' => Value::Double(f64::from_le_bytes(self.fixed()?)),
+            b's' => Value::Str(self.string()?),
+            b'o' => Value::ObjectPath(self.string()?),
+            b'g' => Value::Signature(self.signature()?),
+            b'v' => {
+                let inner = self.signature()?;
+                if inner.is_empty() || single_type_len(inner.as_bytes())? != inner.len() {
+                    return Err(anyhow::anyhow!("Bad variant signature {}", inner));
+                }
+                Value::variant(self.value(inner.as_bytes())?)
+            }
+            b'a' => {
+                let element = &signature[1..1 + single_type_len(&signature[1..])?];
+                let len = self.u32()? as usize;
+                self.pos = align(self.pos, alignment_of(element[0]));
+                let end = self
+                    .pos
+                    .checked_add(len)
+                    .filter(|&end| end <= self.buf.len())
+                    .context("Truncated D-Bus array")?;
+                let mut items = Vec::new();
+                while self.pos < end {
+                    items.push(self.value(element)?);
+                }
+                Value::Array(String::from_utf8_lossy(element).into_owned(), items)
+            }
+            &open @ (b'(' | b'{') => {
+                self.pos = align(self.pos, 8);
+                let inner_end = single_type_len(signature)? - 1;
+                let mut rest = &signature[1..inner_end];
+                let mut fields = Vec::new();
+                while !rest.is_empty() {
+                    let len = single_type_len(rest)?;
+                    fields.push(self.value(&rest[..len])?);
+                    rest = &rest[len..];
+                }
+                if open == b'{' {
+                    let [key, value]: [Value; 2] = fields
+                        .try_into()
+                        .map_err(|_| anyhow::anyhow!("Dict entry without two fields"))?;
+                    Value::DictEntry(Box::new(key), Box::new(value))
+                } else {
+                    Value::Struct(fields)
+                }
+            }
+            other => return Err(anyhow::anyhow!("Unsupported D-Bus type {}", *other as char)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_roundtrip() {
+        let args = vec![
+            Value::str("plain"),
+            Value::variant(Value::str("")),
+            Value::Struct(vec![
+                Value::path("/org/freedesktop/secrets/session/1"),
+                Value::bytes(&[]),
+                Value::bytes(b"s3cret"),
+                Value::str("text/plain"),
+            ]),
+            Value::variant_dict([
+                ("Label", Value::str("cec-remote")),
+                (
+                    "Attributes",
+                    Value::string_dict([("service", "cec-remote")]),
+                ),
+            ]),
+            Value::Bool(true),
+            Value::Uint64(u64::MAX),
+        ];
+        let bytes = encode_message(
+            SIGNAL,
+            7,
+            &[
+                (FIELD_PATH, Value::path("/a/b")),
+                (FIELD_INTERFACE, Value::str("org.example.Iface")),
+                (FIELD_MEMBER, Value::str("Changed")),
+            ],
+            &args,
+        )
+        .unwrap();
+        assert_eq!(bytes.len() % 8, 0);
+
+        let message = decode_message(&bytes).unwrap();
+        assert!(message.is_signal("org.example.Iface", "Changed"));
+        assert_eq!(message.serial, 7);
+        assert_eq!(message.path.as_deref(), Some("/a/b"));
+        assert_eq!(message.body, args);
+        assert_eq!(
+            message.body[3].get("Attributes").unwrap().get("service"),
+            Some(&Value::str("cec-remote"))
+        );
+        assert_eq!(
+            message.body[2].items().unwrap()[2].as_bytes().unwrap(),
+            b"s3cret"
+        );
+    }
+
+    #[test]
+    fn test_big_endian_reply_decoded() {
+        // Reply to serial 1 with signature "su": "ok", 5
+        let mut msg = vec![b'B', METHOD_RETURN, 0, 1];
+        msg.extend_from_slice(&12u32.to_be_bytes());
+        msg.extend_from_slice(&9u32.to_be_bytes());
+        let mut fields = vec![FIELD_REPLY_SERIAL, 1, b'u', 0];
+        fields.extend_from_slice(&1u32.to_be_bytes());
+        fields.extend_from_slice(&[FIELD_SIGNATURE, 1, b'g', 0, 2, b's', b'u', 0]);
+        msg.extend_from_slice(&(fields.len() as u32).to_be_bytes());
+        msg.extend_from_slice(&fields);
+        msg.extend_from_slice(&2u32.to_be_bytes());
+        msg.extend_from_slice(b"ok\0\0");
+        msg.extend_from_slice(&5u32.to_be_bytes());
+
+        let message = decode_message(&msg).unwrap();
+        assert_eq!(message.reply_serial, Some(1));
+        assert_eq!(message.body, vec![Value::str("ok"), Value::Uint32(5)]);
+    }
+
+    #[test]
+    fn test_address_unescaped() {
+        assert_eq!(unescape("/run/user/1000/bus"), "/run/user/1000/bus");
+        assert_eq!(unescape("/tmp/dbus%2dtest"), "/tmp/dbus-test");
+        assert!(Connection::open("tcp:host=localhost,port=1").is_err());
+    }
+}
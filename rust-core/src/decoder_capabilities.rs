@@ -0,0 +1,79 @@
+//! Viewer Decoder Capabilities
+//!
+//! The viewer advertises what its video decoder can handle in the
+//! `DeviceInfo` it sends during session negotiation. The host clamps its
+//! capture resolution, frame rate and codec to these limits so that e.g. an
+//! old phone is never sent a 4K60 stream it cannot decode.
+
+use serde::{Deserialize, Serialize};
+
+/// Codecs a viewer may be able to decode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecoderCodec {
+    H264,
+    H265,
+    VP8,
+    VP9,
+    AV1,
+}
+
+/// Decoding limits of a viewer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecoderCapabilities {
+    pub max_width: u32,
+    pub max_height: u32,
+    pub max_fps: u32,
+    /// Codecs that can be decoded at all
+    pub codecs: Vec<DecoderCodec>,
+    /// Codecs with hardware decoding; preferred when the host has a choice
+    pub hardware_codecs: Vec<DecoderCodec>,
+    /// Can decode 10-bit video and present it on an HDR display
+    #[serde(default)]
+    pub hdr: bool,
+}
+
+impl DecoderCapabilities {
+    /// Probe the local decoder
+    pub fn detect() -> Self {
+        #[cfg(any(target_os = "android", target_os = "ios"))]
+        {
+            // MediaCodec / VideoToolbox capability queries would go here
+            Self {
+                max_width: 1920,
+                max_height: 1080,
+                max_fps: 30,
+                codecs: vec![DecoderCodec::H264],
+                hardware_codecs: vec![DecoderCodec::H264],
+                hdr: false,
+            }
+        }
+        #[cfg(not(any(target_os = "android", target_os = "ios")))]
+        {
+            // DXVA / VideoToolbox / VAAPI capability queries would go here;
+            // AV1 is always decodable in software (dav1d)
+            Self {
+                max_width: 3840,
+                max_height: 2160,
+                max_fps: 60,
+                codecs: vec![
+                    DecoderCodec::H264,
+                    DecoderCodec::H265,
+                    DecoderCodec::VP9,
+                    DecoderCodec::AV1,
+                ],
+                hardware_codecs: vec![],
+                // IDXGIOutput6 / NSScreen EDR / Display.isHdr checks would
+                // go here
+                hdr: false,
+            }
+        }
+    }
+
+    pub fn supports(&self, codec: DecoderCodec) -> bool {
+        self.codecs.contains(&codec)
+    }
+
+    pub fn is_hardware_decoded(&self, codec: DecoderCodec) -> bool {
+        self.hardware_codecs.contains(&codec)
+    }
+}
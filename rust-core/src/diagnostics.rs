@@ -1,5 +1,12 @@
+#[cfg(feature = "capture")]
+use crate::capture_thread::{CaptureHealthHandle, CaptureThreadHealth};
+use crate::diagnostics_report::DiagnosticsReport;
+use crate::quic_transport::DataTransportType;
+use crate::stun::{self, ChangeRequest, NatProbe, StunConfig};
+use crate::webhooks::{WebhookDiagnostics, WebhookDispatcher};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
 
 /// NAT 类型
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -82,6 +89,12 @@ pub struct NetworkDiagnostics {
     pub signaling_server: ServerStatus,
     pub stun_servers: Vec<ServerStatus>,
     pub turn_servers: Vec<ServerStatus>,
+    /// 当前数据通道所用传输（未建立会话时为空）
+    #[serde(default)]
+    pub data_transport: Option<DataTransportType>,
+    /// Webhook 投递状态（未配置时为空）
+    #[serde(default)]
+    pub webhooks: Option<WebhookDiagnostics>,
     pub overall_status: DiagnosticStatus,
     pub recommendations: Vec<String>,
 }
@@ -101,6 +114,8 @@ impl NetworkDiagnostics {
             signaling_server: ServerStatus::new("Signaling", ""),
             stun_servers: Vec::new(),
             turn_servers: Vec::new(),
+            data_transport: None,
+            webhooks: None,
             overall_status: DiagnosticStatus::Unknown,
             recommendations: Vec::new(),
         }
@@ -153,6 +168,20 @@ impl NetworkDiagnostics {
             }
         }
 
+        if self.data_transport == Some(DataTransportType::Quic) {
+            self.recommendations
+                .push("WebRTC数据通道无法建立，已切换到QUIC传输".to_string());
+        }
+
+        if let Some(webhooks) = &self.webhooks {
+            if webhooks.failed > 0 {
+                self.recommendations.push(format!(
+                    "{} 个Webhook投递失败，请检查集成端点配置",
+                    webhooks.failed
+                ));
+            }
+        }
+
         // 检查延迟
         if let Some(latency) = self.signaling_server.latency_ms {
             if latency > 200 {
@@ -205,6 +234,10 @@ pub struct SystemDiagnostics {
     pub audio_capture_available: bool,
     pub hardware_acceleration_available: bool,
     pub supported_codecs: Vec<String>,
+    /// 采集线程状态（未在采集时为空）
+    #[cfg(feature = "capture")]
+    #[serde(default)]
+    pub capture_thread: Option<CaptureThreadHealth>,
 }
 
 impl SystemDiagnostics {
@@ -220,7 +253,14 @@ impl SystemDiagnostics {
             screen_capture_available: true,
             audio_capture_available: true,
             hardware_acceleration_available: false,
-            supported_codecs: vec!["H.264".to_string(), "VP8".to_string(), "VP9".to_string()],
+            supported_codecs: vec![
+                "H.264".to_string(),
+                "VP8".to_string(),
+                "VP9".to_string(),
+                "AV1".to_string(),
+            ],
+            #[cfg(feature = "capture")]
+            capture_thread: None,
         }
     }
 }
@@ -236,6 +276,11 @@ pub struct DiagnosticsManager {
     signaling_url: String,
     stun_urls: Vec<String>,
     turn_urls: Vec<String>,
+    data_transport: Option<DataTransportType>,
+    webhook_dispatcher: Option<WebhookDispatcher>,
+    stun_config: StunConfig,
+    #[cfg(feature = "capture")]
+    capture_health: Option<CaptureHealthHandle>,
 }
 
 impl DiagnosticsManager {
@@ -244,9 +289,35 @@ impl DiagnosticsManager {
             signaling_url: String::new(),
             stun_urls: Vec::new(),
             turn_urls: Vec::new(),
+            data_transport: None,
+            webhook_dispatcher: None,
+            stun_config: StunConfig::default(),
+            #[cfg(feature = "capture")]
+            capture_health: None,
         }
     }
 
+    /// 设置 STUN 请求的重传参数
+    pub fn set_stun_config(&mut self, config: StunConfig) {
+        self.stun_config = config;
+    }
+
+    /// 设置Webhook分发器，以便在诊断中报告投递状态
+    pub fn set_webhook_dispatcher(&mut self, dispatcher: WebhookDispatcher) {
+        self.webhook_dispatcher = Some(dispatcher);
+    }
+
+    /// 设置采集线程状态来源，以便在系统诊断中报告
+    #[cfg(feature = "capture")]
+    pub fn set_capture_health(&mut self, health: CaptureHealthHandle) {
+        self.capture_health = Some(health);
+    }
+
+    /// 记录当前会话数据通道所用的传输
+    pub fn set_data_transport(&mut self, transport: Option<DataTransportType>) {
+        self.data_transport = transport;
+    }
+
     /// 配置服务器URL
     pub fn configure(
         &mut self,
@@ -262,6 +333,10 @@ impl DiagnosticsManager {
     /// 运行网络诊断
     pub async fn run_network_diagnostics(&self) -> NetworkDiagnostics {
         let mut diagnostics = NetworkDiagnostics::new();
+        diagnostics.data_transport = self.data_transport;
+        if let Some(dispatcher) = &self.webhook_dispatcher {
+            diagnostics.webhooks = Some(dispatcher.get_diagnostics().await);
+        }
 
         // 检查互联网连接
         diagnostics.internet_connected = self.check_internet_connection().await;
@@ -305,6 +380,13 @@ impl DiagnosticsManager {
         diagnostics
     }
 
+    /// 运行网络与系统诊断并生成结构化报告
+    pub async fn run_report(&self) -> DiagnosticsReport {
+        let network = self.run_network_diagnostics().await;
+        let system = self.run_system_diagnostics();
+        DiagnosticsReport::new(Some(&network), Some(&system))
+    }
+
     /// 运行系统诊断
     pub fn run_system_diagnostics(&self) -> SystemDiagnostics {
         let mut diagnostics = SystemDiagnostics::new();
@@ -316,6 +398,15 @@ impl DiagnosticsManager {
         diagnostics.available_memory_mb = 8192;
         diagnostics.disk_usage_percent = 45.0;
 
+        #[cfg(feature = "capture")]
+        if let Some(health) = &self.capture_health {
+            let health = health.snapshot();
+            if health.failed {
+                diagnostics.screen_capture_available = false;
+            }
+            diagnostics.capture_thread = Some(health);
+        }
+
         diagnostics
     }
 
@@ -352,15 +443,148 @@ impl DiagnosticsManager {
     }
 
     /// 通过STUN获取公网IP
-    async fn get_public_ip_via_stun(&self, _stun_url: &str) -> Option<String> {
-        // 模拟 - 实际应该通过STUN协议获取
-        Some("203.0.113.1".to_string())
+    async fn get_public_ip_via_stun(&self, stun_url: &str) -> Option<String> {
+        match stun::binding_request(stun_url, &self.stun_config).await {
+            Ok(address) => Some(address.ip().to_string()),
+            Err(e) => {
+                tracing::debug!("STUN 绑定请求失败 {}: {}", stun_url, e);
+                None
+            }
+        }
     }
 
-    /// 检测NAT类型
-    async fn detect_nat_type(&self) -> NatType {
-        // 模拟 - 实际应该通过STUN协议检测
-        NatType::FullCone
+    /// 通过 STUN 检测 NAT 类型
+    ///
+    /// 用同一个本地端口向已配置的 STUN 服务器发送绑定请求（RFC 5780）：
+    /// 比较不同服务器看到的映射地址判断映射行为，再请求服务器换 IP/端口回复
+    /// 判断过滤行为。只有一个服务器且其不提供备用地址时无法判断映射行为，
+    /// 返回 `Unknown`；服务器不支持 CHANGE-REQUEST 时按最严格的端口受限锥形报告。
+    pub async fn detect_nat_type(&self) -> NatType {
+        match self.probe_nat().await {
+            Ok(nat_type) => nat_type,
+            Err(e) => {
+                tracing::warn!("NAT 类型检测失败: {}", e);
+                NatType::Unknown
+            }
+        }
+    }
+
+    async fn probe_nat(&self) -> anyhow::Result<NatType> {
+        // 每个 IP 只取一个服务器，映射比较需要不同的 IP
+        let mut servers: Vec<SocketAddr> = Vec::new();
+        for url in &self.stun_urls {
+            match stun::resolve_server(url).await {
+                Ok(address) if address.is_ipv4() => {
+                    if !servers.iter().any(|known| known.ip() == address.ip()) {
+                        servers.push(address);
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => tracing::debug!("无法解析 STUN 服务器 {}: {}", url, e),
+            }
+        }
+        if servers.is_empty() {
+            return Ok(NatType::Unknown);
+        }
+
+        let probe = NatProbe::bind(&self.stun_config).await?;
+        let plain = ChangeRequest::default();
+
+        // 测试一：找一个能响应的服务器
+        let mut primary = None;
+        for server in &servers {
+            if let Some(response) = probe.binding(*server, plain).await.ok().flatten() {
+                primary = Some((*server, response));
+                break;
+            }
+        }
+        let Some((server, first)) = primary else {
+            return Ok(NatType::Blocked);
+        };
+
+        let local = SocketAddr::new(outbound_ip(server)?, probe.local_addr()?.port());
+        let translated = first.mapped != local;
+
+        // 测试二：从另一个 IP 看到的映射是否相同
+        let alternate = first
+            .other_address
+            .filter(|other| other.ip() != server.ip())
+            .map(|other| SocketAddr::new(other.ip(), server.port()))
+            .or_else(|| servers.iter().find(|s| s.ip() != server.ip()).copied());
+        let mapping_varies = match alternate {
+            Some(alternate) => probe
+                .binding(alternate, plain)
+                .await
+                .ok()
+                .flatten()
+                .map(|response| response.mapped != first.mapped),
+            None => None,
+        };
+
+        // 测试三：换 IP 和端口、只换端口的回复能否到达
+        let filtering = if first.other_address.is_some() {
+            let any_source = probe
+                .binding(
+                    server,
+                    ChangeRequest {
+                        ip: true,
+                        port: true,
+                    },
+                )
+                .await
+                .ok()
+                .flatten()
+                .is_some();
+            let same_ip = any_source
+                || probe
+                    .binding(
+                        server,
+                        ChangeRequest {
+                            ip: false,
+                            port: true,
+                        },
+                    )
+                    .await
+                    .ok()
+                    .flatten()
+                    .is_some();
+            Some((any_source, same_ip))
+        } else {
+            None
+        };
+
+        Ok(classify_nat(translated, mapping_varies, filtering))
+    }
+}
+
+/// 本机发往 `server` 时使用的本地 IP
+fn outbound_ip(server: SocketAddr) -> anyhow::Result<std::net::IpAddr> {
+    let socket = std::net::UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(server)?;
+    Ok(socket.local_addr()?.ip())
+}
+
+/// 根据测试结果归类 NAT
+///
+/// `filtering` 为（其他 IP 的回复能否到达，同 IP 其他端口的回复能否到达），
+/// 服务器不支持时为 None。
+fn classify_nat(
+    translated: bool,
+    mapping_varies: Option<bool>,
+    filtering: Option<(bool, bool)>,
+) -> NatType {
+    if !translated {
+        return match filtering {
+            Some((false, _)) => NatType::SymmetricUdpFirewall,
+            _ => NatType::OpenInternet,
+        };
+    }
+    match (mapping_varies, filtering) {
+        (None, _) => NatType::Unknown,
+        (Some(true), _) => NatType::Symmetric,
+        (Some(false), Some((true, _))) => NatType::FullCone,
+        (Some(false), Some((false, true))) => NatType::RestrictedCone,
+        (Some(false), _) => NatType::PortRestrictedCone,
     }
 }
 
@@ -397,9 +621,152 @@ mod tests {
         assert_eq!(diagnostics.overall_status, DiagnosticStatus::Good);
     }
 
+    #[cfg(feature = "capture")]
+    #[test]
+    fn test_capture_thread_health_reported() {
+        let mut manager = DiagnosticsManager::new();
+        assert!(manager.run_system_diagnostics().capture_thread.is_none());
+
+        let health = CaptureHealthHandle::default();
+        health.update(|health| health.failed = true);
+        manager.set_capture_health(health);
+
+        let diagnostics = manager.run_system_diagnostics();
+        assert!(diagnostics.capture_thread.unwrap().failed);
+        assert!(!diagnostics.screen_capture_available);
+    }
+
     #[test]
     fn test_nat_type_display() {
         assert_eq!(format!("{}", NatType::FullCone), "完全锥形NAT");
         assert_eq!(format!("{}", NatType::Symmetric), "对称NAT");
     }
+
+    #[test]
+    fn test_nat_classification() {
+        assert_eq!(
+            classify_nat(false, None, Some((true, true))),
+            NatType::OpenInternet
+        );
+        assert_eq!(
+            classify_nat(false, None, Some((false, false))),
+            NatType::SymmetricUdpFirewall
+        );
+        assert_eq!(classify_nat(true, Some(true), None), NatType::Symmetric);
+        assert_eq!(
+            classify_nat(true, Some(false), Some((true, true))),
+            NatType::FullCone
+        );
+        assert_eq!(
+            classify_nat(true, Some(false), Some((false, true))),
+            NatType::RestrictedCone
+        );
+        assert_eq!(
+            classify_nat(true, Some(false), Some((false, false))),
+            NatType::PortRestrictedCone
+        );
+        assert_eq!(
+            classify_nat(true, Some(false), None),
+            NatType::PortRestrictedCone
+        );
+        assert_eq!(classify_nat(true, None, None), NatType::Unknown);
+    }
+
+    /// Minimal STUN server answering Binding requests on `primary`
+    ///
+    /// Reports `mapped(from)` as the client address and, given an
+    /// `alternate` socket, advertises it as OTHER-ADDRESS and answers
+    /// CHANGE-REQUESTs from it.
+    fn serve_stun(
+        primary: tokio::net::UdpSocket,
+        alternate: Option<std::sync::Arc<tokio::net::UdpSocket>>,
+        mapped: fn(SocketAddr) -> SocketAddr,
+    ) {
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1500];
+            while let Ok((len, from)) = primary.recv_from(&mut buf).await {
+                let request = &buf[..len];
+                let change = len >= 28 && request[20..22] == [0, 3] && request[27] != 0;
+                let client = mapped(from);
+                let mut attributes = vec![0x00, 0x20, 0x00, 0x08, 0x00, 0x01];
+                attributes.extend_from_slice(&(client.port() ^ 0x2112).to_be_bytes());
+                let std::net::IpAddr::V4(ip) = client.ip() else {
+                    continue;
+                };
+                attributes.extend_from_slice(&(u32::from(ip) ^ 0x2112_A442).to_be_bytes());
+                if let Some(alternate) = &alternate {
+                    let other = alternate.local_addr().unwrap();
+                    attributes.extend_from_slice(&[0x80, 0x2C, 0x00, 0x08, 0x00, 0x01]);
+                    attributes.extend_from_slice(&other.port().to_be_bytes());
+                    let std::net::IpAddr::V4(other_ip) = other.ip() else {
+                        continue;
+                    };
+                    attributes.extend_from_slice(&other_ip.octets());
+                }
+                let mut response = vec![0x01, 0x01];
+                response.extend_from_slice(&(attributes.len() as u16).to_be_bytes());
+                response.extend_from_slice(&request[4..20]);
+                response.extend_from_slice(&attributes);
+                match (&alternate, change) {
+                    (Some(alternate), true) => alternate.send_to(&response, from).await,
+                    (None, true) => continue,
+                    _ => primary.send_to(&response, from).await,
+                }
+                .unwrap();
+            }
+        });
+    }
+
+    fn fast_manager(stun_urls: Vec<String>) -> DiagnosticsManager {
+        let mut manager = DiagnosticsManager::new();
+        manager.configure("", stun_urls, Vec::new());
+        manager.set_stun_config(StunConfig {
+            initial_rto: std::time::Duration::from_millis(50),
+            max_attempts: 2,
+        });
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_detect_nat_type_against_stun_servers() {
+        use tokio::net::UdpSocket;
+
+        // Not behind NAT, and answers from the alternate address get through
+        let primary = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let alternate = UdpSocket::bind("127.0.0.2:0").await.unwrap();
+        let url = format!("stun:{}", primary.local_addr().unwrap());
+        serve_stun(primary, Some(std::sync::Arc::new(alternate)), |from| from);
+        let manager = fast_manager(vec![url.clone()]);
+        assert_eq!(manager.detect_nat_type().await, NatType::OpenInternet);
+        assert_eq!(
+            manager.get_public_ip_via_stun(&url).await.as_deref(),
+            Some("127.0.0.1")
+        );
+
+        // Each server sees a different public port: symmetric NAT
+        let a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let b = UdpSocket::bind("127.0.0.3:0").await.unwrap();
+        let urls = vec![
+            format!("stun:{}", a.local_addr().unwrap()),
+            format!("stun:{}", b.local_addr().unwrap()),
+        ];
+        serve_stun(a, None, |_| "203.0.113.7:40000".parse().unwrap());
+        serve_stun(b, None, |_| "203.0.113.7:40001".parse().unwrap());
+        assert_eq!(
+            fast_manager(urls).detect_nat_type().await,
+            NatType::Symmetric
+        );
+
+        // Nothing answers
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("stun:{}", silent.local_addr().unwrap());
+        assert_eq!(
+            fast_manager(vec![url]).detect_nat_type().await,
+            NatType::Blocked
+        );
+        assert_eq!(
+            DiagnosticsManager::new().detect_nat_type().await,
+            NatType::Unknown
+        );
+    }
 }
@@ -0,0 +1,630 @@
+//! Diagnostics Report
+//!
+//! `NetworkDiagnostics` and `SystemDiagnostics` hold raw facts and a list of
+//! ready-made Chinese sentences, which a UI can only show verbatim. A
+//! `DiagnosticsReport` turns them into sections of checks, each with a
+//! stable id, a status, the numbers behind it and, when something is wrong,
+//! a `Remediation` code. Text is given as a message key plus arguments for
+//! the UI to localize, with an English fallback. Statuses roll up from
+//! checks to sections to the whole report, the worst one winning.
+
+use crate::connection_failure::Remediation;
+use crate::diagnostics::{NatType, NetworkDiagnostics, ServerStatus, SystemDiagnostics};
+use crate::quic_transport::DataTransportType;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// CPU, memory or disk usage above this is reported as a warning
+const HIGH_USAGE_PERCENT: f32 = 90.0;
+
+/// Signaling latency above this is reported as a warning
+const HIGH_LATENCY_MS: u32 = 200;
+
+/// Outcome of a check, least severe first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum CheckStatus {
+    /// Not run, e.g. no TURN server configured
+    Skipped,
+    Pass,
+    /// Worth knowing, nothing to fix
+    Info,
+    Warning,
+    Fail,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SectionId {
+    Connectivity,
+    Servers,
+    Nat,
+    Transport,
+    System,
+}
+
+impl SectionId {
+    pub fn code(self) -> &'static str {
+        match self {
+            SectionId::Connectivity => "connectivity",
+            SectionId::Servers => "servers",
+            SectionId::Nat => "nat",
+            SectionId::Transport => "transport",
+            SectionId::System => "system",
+        }
+    }
+}
+
+/// Number behind a check
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metric {
+    pub name: String,
+    pub value: f64,
+    /// "ms", "%", "MB" or empty for counts
+    pub unit: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticCheck {
+    /// Stable within a section, e.g. "signaling" or "stun.0"
+    pub id: String,
+    pub status: CheckStatus,
+    /// Localization key of the result, e.g. "diagnostics.nat.symmetric"
+    pub message_key: String,
+    /// Values to substitute into the localized message
+    pub args: BTreeMap<String, String>,
+    /// English text for when the key has no translation
+    pub message: String,
+    pub metrics: Vec<Metric>,
+    pub remediation: Option<Remediation>,
+}
+
+impl DiagnosticCheck {
+    fn new(id: &str, status: CheckStatus, message_key: &str, message: impl Into<String>) -> Self {
+        Self {
+            id: id.to_string(),
+            status,
+            message_key: format!("diagnostics.{}", message_key),
+            args: BTreeMap::new(),
+            message: message.into(),
+            metrics: Vec::new(),
+            remediation: None,
+        }
+    }
+
+    fn arg(mut self, name: &str, value: impl ToString) -> Self {
+        self.args.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    fn metric(mut self, name: &str, value: f64, unit: &str) -> Self {
+        self.metrics.push(Metric {
+            name: name.to_string(),
+            value,
+            unit: unit.to_string(),
+        });
+        self
+    }
+
+    fn remediation(mut self, remediation: Remediation) -> Self {
+        self.remediation = Some(remediation);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticSection {
+    pub id: SectionId,
+    /// Worst status of the section's checks
+    pub status: CheckStatus,
+    pub checks: Vec<DiagnosticCheck>,
+}
+
+impl DiagnosticSection {
+    fn new(id: SectionId, checks: Vec<DiagnosticCheck>) -> Self {
+        Self {
+            id,
+            status: rollup(checks.iter().map(|check| check.status)),
+            checks,
+        }
+    }
+}
+
+/// How many checks ended in each status
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusCounts {
+    pub skipped: usize,
+    pub passed: usize,
+    pub info: usize,
+    pub warnings: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnosticsReport {
+    pub generated_at: DateTime<Utc>,
+    /// Worst status of all sections
+    pub status: CheckStatus,
+    pub counts: StatusCounts,
+    pub sections: Vec<DiagnosticSection>,
+}
+
+impl DiagnosticsReport {
+    /// Build a report from whichever diagnostics were run
+    pub fn new(network: Option<&NetworkDiagnostics>, system: Option<&SystemDiagnostics>) -> Self {
+        let mut sections = Vec::new();
+        if let Some(network) = network {
+            sections.push(DiagnosticSection::new(
+                SectionId::Connectivity,
+                connectivity_checks(network),
+            ));
+            if network.internet_connected {
+                sections.push(DiagnosticSection::new(
+                    SectionId::Servers,
+                    server_checks(network),
+                ));
+                sections.push(DiagnosticSection::new(
+                    SectionId::Nat,
+                    vec![nat_check(network)],
+                ));
+            }
+            sections.push(DiagnosticSection::new(
+                SectionId::Transport,
+                transport_checks(network),
+            ));
+        }
+        if let Some(system) = system {
+            sections.push(DiagnosticSection::new(
+                SectionId::System,
+                system_checks(system),
+            ));
+        }
+
+        let mut counts = StatusCounts::default();
+        for check in sections.iter().flat_map(|section| &section.checks) {
+            match check.status {
+                CheckStatus::Skipped => counts.skipped += 1,
+                CheckStatus::Pass => counts.passed += 1,
+                CheckStatus::Info => counts.info += 1,
+                CheckStatus::Warning => counts.warnings += 1,
+                CheckStatus::Fail => counts.failed += 1,
+            }
+        }
+
+        Self {
+            generated_at: Utc::now(),
+            status: rollup(sections.iter().map(|section| section.status)),
+            counts,
+            sections,
+        }
+    }
+
+    pub fn section(&self, id: SectionId) -> Option<&DiagnosticSection> {
+        self.sections.iter().find(|section| section.id == id)
+    }
+
+    /// Suggested fixes, most severe check first, without repeats
+    pub fn remediations(&self) -> Vec<Remediation> {
+        let mut checks: Vec<&DiagnosticCheck> = self
+            .sections
+            .iter()
+            .flat_map(|section| &section.checks)
+            .collect();
+        checks.sort_by_key(|check| std::cmp::Reverse(check.status));
+        let mut remediations = Vec::new();
+        for remediation in checks.into_iter().filter_map(|check| check.remediation) {
+            if !remediations.contains(&remediation) {
+                remediations.push(remediation);
+            }
+        }
+        remediations
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+fn rollup(statuses: impl Iterator<Item = CheckStatus>) -> CheckStatus {
+    statuses.max().unwrap_or(CheckStatus::Skipped)
+}
+
+fn connectivity_checks(network: &NetworkDiagnostics) -> Vec<DiagnosticCheck> {
+    let mut checks = vec![if network.internet_connected {
+        DiagnosticCheck::new(
+            "internet",
+            CheckStatus::Pass,
+            "internet.connected",
+            "Connected to the internet",
+        )
+    } else {
+        DiagnosticCheck::new(
+            "internet",
+            CheckStatus::Fail,
+            "internet.disconnected",
+            "No internet connection",
+        )
+        .remediation(Remediation::CheckNetwork)
+    }];
+
+    let ip = |id: &str, local: &Option<String>, public: &Option<String>| match local {
+        Some(local) => {
+            let mut check = DiagnosticCheck::new(
+                id,
+                CheckStatus::Pass,
+                &format!("{}.available", id),
+                format!("{} address {}", id.to_uppercase(), local),
+            )
+            .arg("local", local);
+            if let Some(public) = public {
+                check = check.arg("public", public);
+            }
+            check
+        }
+        None => DiagnosticCheck::new(
+            id,
+            CheckStatus::Info,
+            &format!("{}.unavailable", id),
+            format!("No {} address", id.to_uppercase()),
+        ),
+    };
+    if network.internet_connected {
+        checks.push(ip("ipv4", &network.local_ipv4, &network.public_ipv4));
+        checks.push(ip("ipv6", &network.local_ipv6, &network.public_ipv6));
+    }
+    checks
+}
+
+fn server_checks(network: &NetworkDiagnostics) -> Vec<DiagnosticCheck> {
+    let mut checks = vec![server_check(
+        "signaling",
+        &network.signaling_server,
+        CheckStatus::Fail,
+        Remediation::CheckSignalingServer,
+    )];
+    let any_stun = network.stun_servers.iter().any(|s| s.reachable);
+    let any_turn = network.turn_servers.iter().any(|s| s.reachable);
+    // One unreachable server is harmless while another of its kind answers;
+    // none of either kind leaves no way to find a path
+    let severity = |any_of_kind: bool| match (any_of_kind, any_stun || any_turn) {
+        (true, _) => CheckStatus::Info,
+        (false, true) => CheckStatus::Warning,
+        (false, false) => CheckStatus::Fail,
+    };
+
+    for (kind, servers, any, remediation) in [
+        (
+            "stun",
+            &network.stun_servers,
+            any_stun,
+            Remediation::CheckFirewall,
+        ),
+        (
+            "turn",
+            &network.turn_servers,
+            any_turn,
+            Remediation::ConfigureTurn,
+        ),
+    ] {
+        if servers.is_empty() {
+            checks.push(DiagnosticCheck::new(
+                kind,
+                CheckStatus::Skipped,
+                &format!("{}.not_configured", kind),
+                format!("No {} server configured", kind.to_uppercase()),
+            ));
+        }
+        for (i, server) in servers.iter().enumerate() {
+            checks.push(server_check(
+                &format!("{}.{}", kind, i),
+                server,
+                severity(any),
+                remediation,
+            ));
+        }
+    }
+    checks
+}
+
+fn server_check(
+    id: &str,
+    server: &ServerStatus,
+    unreachable: CheckStatus,
+    remediation: Remediation,
+) -> DiagnosticCheck {
+    let kind = id.split('.').next().unwrap_or(id);
+    if !server.reachable {
+        let error = server.error.clone().unwrap_or_default();
+        return DiagnosticCheck::new(
+            id,
+            unreachable,
+            &format!("{}.unreachable", kind),
+            format!("{} unreachable: {}", server.url, error),
+        )
+        .arg("url", &server.url)
+        .arg("error", error)
+        .remediation(remediation);
+    }
+    let latency = server.latency_ms.unwrap_or_default();
+    let slow = kind == "signaling" && latency > HIGH_LATENCY_MS;
+    let check = if slow {
+        DiagnosticCheck::new(
+            id,
+            CheckStatus::Warning,
+            &format!("{}.slow", kind),
+            format!("{} answered slowly ({} ms)", server.url, latency),
+        )
+    } else {
+        DiagnosticCheck::new(
+            id,
+            CheckStatus::Pass,
+            &format!("{}.reachable", kind),
+            format!("{} reachable ({} ms)", server.url, latency),
+        )
+    };
+    check
+        .arg("url", &server.url)
+        .arg("latency_ms", latency)
+        .metric("latency", f64::from(latency), "ms")
+}
+
+fn nat_check(network: &NetworkDiagnostics) -> DiagnosticCheck {
+    let turn_reachable = network.turn_servers.iter().any(|s| s.reachable);
+    let (status, key, message, remediation) = match network.nat_type {
+        NatType::Unknown => (
+            CheckStatus::Skipped,
+            "unknown",
+            "NAT type could not be determined",
+            None,
+        ),
+        NatType::OpenInternet => (CheckStatus::Pass, "open", "Not behind NAT", None),
+        NatType::FullCone | NatType::RestrictedCone | NatType::PortRestrictedCone => (
+            CheckStatus::Pass,
+            "cone",
+            "NAT allows direct connections",
+            None,
+        ),
+        NatType::Symmetric | NatType::SymmetricUdpFirewall if turn_reachable => (
+            CheckStatus::Info,
+            "symmetric_relayed",
+            "Symmetric NAT; connections will use a TURN relay",
+            None,
+        ),
+        NatType::Symmetric | NatType::SymmetricUdpFirewall => (
+            CheckStatus::Warning,
+            "symmetric",
+            "Symmetric NAT and no TURN relay; direct connections may fail",
+            Some(Remediation::ConfigureTurn),
+        ),
+        NatType::Blocked => (
+            CheckStatus::Fail,
+            "udp_blocked",
+            "UDP is blocked by a firewall",
+            Some(Remediation::CheckFirewall),
+        ),
+    };
+    let mut check = DiagnosticCheck::new("nat_type", status, &format!("nat.{}", key), message)
+        .arg("nat_type", format!("{:?}", network.nat_type));
+    check.remediation = remediation;
+    check
+}
+
+fn transport_checks(network: &NetworkDiagnostics) -> Vec<DiagnosticCheck> {
+    let mut checks = Vec::new();
+    match network.data_transport {
+        Some(DataTransportType::DataChannel) => checks.push(DiagnosticCheck::new(
+            "data_transport",
+            CheckStatus::Pass,
+            "transport.data_channel",
+            "Data channel established",
+        )),
+        Some(DataTransportType::Quic) => checks.push(DiagnosticCheck::new(
+            "data_transport",
+            CheckStatus::Info,
+            "transport.quic_fallback",
+            "WebRTC data channel failed; using QUIC instead",
+        )),
+        None => {}
+    }
+    if let Some(webhooks) = &network.webhooks {
+        let check = if webhooks.failed > 0 {
+            DiagnosticCheck::new(
+                "webhooks",
+                CheckStatus::Warning,
+                "webhooks.failing",
+                format!("{} webhook deliveries failed", webhooks.failed),
+            )
+            .arg("failed", webhooks.failed)
+            .arg("error", webhooks.last_error.clone().unwrap_or_default())
+            .remediation(Remediation::CheckWebhookEndpoint)
+        } else {
+            DiagnosticCheck::new(
+                "webhooks",
+                CheckStatus::Pass,
+                "webhooks.healthy",
+                "Webhook deliveries succeeding",
+            )
+        };
+        checks.push(
+            check
+                .metric("delivered", webhooks.delivered as f64, "")
+                .metric("failed", webhooks.failed as f64, "")
+                .metric("pending", webhooks.pending as f64, ""),
+        );
+    }
+    checks
+}
+
+fn system_checks(system: &SystemDiagnostics) -> Vec<DiagnosticCheck> {
+    let usage = |id: &str, percent: f32| {
+        let (status, key) = if percent > HIGH_USAGE_PERCENT {
+            (CheckStatus::Warning, "high")
+        } else {
+            (CheckStatus::Pass, "normal")
+        };
+        let mut check = DiagnosticCheck::new(
+            id,
+            status,
+            &format!("system.{}.{}", id, key),
+            format!("{} usage {:.0}%", id, percent),
+        )
+        .arg("percent", format!("{:.0}", percent))
+        .metric("usage", f64::from(percent), "%");
+        if status == CheckStatus::Warning {
+            check = check.remediation(Remediation::FreeSystemResources);
+        }
+        check
+    };
+    let mut checks = vec![
+        usage("cpu", system.cpu_usage_percent),
+        usage("memory", system.memory_usage_percent).metric(
+            "available",
+            system.available_memory_mb as f64,
+            "MB",
+        ),
+        usage("disk", system.disk_usage_percent),
+    ];
+
+    let capture = if system.screen_capture_available {
+        DiagnosticCheck::new(
+            "screen_capture",
+            CheckStatus::Pass,
+            "system.screen_capture.available",
+            "Screen capture available",
+        )
+    } else {
+        DiagnosticCheck::new(
+            "screen_capture",
+            CheckStatus::Fail,
+            "system.screen_capture.unavailable",
+            "Screen capture unavailable",
+        )
+        .remediation(Remediation::RestartCapture)
+    };
+    #[cfg(feature = "capture")]
+    let capture = match &system.capture_thread {
+        Some(health) => {
+            let capture = capture
+                .metric("restarts", f64::from(health.restarts), "")
+                .metric("frames_captured", health.frames_captured as f64, "")
+                .metric("frames_dropped", health.frames_dropped as f64, "");
+            match &health.last_error {
+                Some(error) => capture.arg("error", error),
+                None => capture,
+            }
+        }
+        None => capture,
+    };
+    checks.push(capture);
+
+    checks.push(if system.audio_capture_available {
+        DiagnosticCheck::new(
+            "audio_capture",
+            CheckStatus::Pass,
+            "system.audio_capture.available",
+            "Audio capture available",
+        )
+    } else {
+        DiagnosticCheck::new(
+            "audio_capture",
+            CheckStatus::Warning,
+            "system.audio_capture.unavailable",
+            "Audio capture unavailable; sessions will be silent",
+        )
+    });
+    checks.push(
+        if system.hardware_acceleration_available {
+            DiagnosticCheck::new(
+                "hardware_acceleration",
+                CheckStatus::Pass,
+                "system.hardware_acceleration.available",
+                "Hardware video encoding available",
+            )
+        } else {
+            DiagnosticCheck::new(
+                "hardware_acceleration",
+                CheckStatus::Info,
+                "system.hardware_acceleration.unavailable",
+                "No hardware video encoding; using the CPU",
+            )
+        }
+        .arg("codecs", system.supported_codecs.join(", ")),
+    );
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn healthy_network() -> NetworkDiagnostics {
+        let mut network = NetworkDiagnostics::new();
+        network.internet_connected = true;
+        network.local_ipv4 = Some("192.168.1.20".to_string());
+        network.signaling_server = ServerStatus::new("Signaling", "wss://sig").success(40);
+        network
+            .stun_servers
+            .push(ServerStatus::new("STUN", "stun:a").success(20));
+        network.nat_type = NatType::PortRestrictedCone;
+        network
+    }
+
+    #[test]
+    fn test_sections_and_rollup() {
+        let mut network = healthy_network();
+        network
+            .stun_servers
+            .push(ServerStatus::new("STUN", "stun:b").failure("timeout"));
+        let report = DiagnosticsReport::new(Some(&network), None);
+
+        let servers = report.section(SectionId::Servers).unwrap();
+        let ids: Vec<&str> = servers.checks.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["signaling", "stun.0", "stun.1", "turn"][..]);
+        // The second STUN server is down but the first answers
+        assert_eq!(servers.checks[2].status, CheckStatus::Info);
+        assert_eq!(servers.checks[2].args["error"], "timeout");
+        assert_eq!(servers.checks[3].status, CheckStatus::Skipped);
+        assert_eq!(servers.checks[0].metrics[0].value, 40.0);
+        assert_eq!(servers.status, CheckStatus::Info);
+        assert_eq!(report.status, CheckStatus::Info);
+        assert_eq!(report.counts.failed, 0);
+        assert!(report.section(SectionId::System).is_none());
+
+        let json = report.to_json().unwrap();
+        let parsed: DiagnosticsReport = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, report);
+        assert!(json.contains("\"message_key\":\"diagnostics.nat.cone\""));
+    }
+
+    #[test]
+    fn test_failures_carry_remediations() {
+        let mut network = healthy_network();
+        network.signaling_server = ServerStatus::new("Signaling", "wss://sig").failure("refused");
+        network.nat_type = NatType::Symmetric;
+        let mut system = SystemDiagnostics::new();
+        system.memory_usage_percent = 97.0;
+        system.screen_capture_available = false;
+
+        let report = DiagnosticsReport::new(Some(&network), Some(&system));
+        assert_eq!(report.status, CheckStatus::Fail);
+        assert_eq!(
+            report.section(SectionId::Nat).unwrap().checks[0].message_key,
+            "diagnostics.nat.symmetric"
+        );
+        assert_eq!(report.counts.failed, 2);
+        // Failures come before warnings
+        assert_eq!(
+            report.remediations(),
+            vec![
+                Remediation::CheckSignalingServer,
+                Remediation::RestartCapture,
+                Remediation::ConfigureTurn,
+                Remediation::FreeSystemResources,
+            ]
+        );
+
+        let offline = DiagnosticsReport::new(Some(&NetworkDiagnostics::new()), None);
+        assert_eq!(offline.sections.len(), 2);
+        assert_eq!(offline.remediations(), vec![Remediation::CheckNetwork]);
+    }
+}
@@ -0,0 +1,397 @@
+//! LAN Device Discovery
+//!
+//! Advertises this device as a `_cecdesk._tcp` DNS-SD service over mDNS and
+//! browses for others, so devices on the same network find each other and
+//! connect directly, without a signaling server.
+//!
+//! Each advertisement carries the device ID, name and platform, and the
+//! certificate fingerprint when known, in its TXT record. The fingerprint
+//! is only a hint: anyone on the LAN can advertise, so a direct connection
+//! still has to authenticate the peer (see `lan_pairing`).
+
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use anyhow::{Context, Result};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+/// DNS-SD service type advertised and browsed
+pub const SERVICE_TYPE: &str = "_cecdesk._tcp.local.";
+
+/// Version of the TXT record layout
+pub const DISCOVERY_TXT_VERSION: &str = "1";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// What this device advertises
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Advertisement {
+    pub device_id: String,
+    pub device_name: String,
+    pub platform: String,
+    /// Port accepting direct connections
+    pub port: u16,
+    /// Addresses to advertise; empty follows the host's interfaces
+    #[serde(default)]
+    pub addresses: Vec<IpAddr>,
+    #[serde(default)]
+    pub cert_fingerprint: Option<String>,
+}
+
+impl Advertisement {
+    fn service_info(&self) -> Result<ServiceInfo> {
+        let instance = instance_name(&self.device_id);
+        let host_name = format!("{}.local.", instance);
+        let mut properties = vec![
+            ("v", DISCOVERY_TXT_VERSION),
+            ("id", self.device_id.as_str()),
+            ("name", self.device_name.as_str()),
+            ("platform", self.platform.as_str()),
+        ];
+        if let Some(fingerprint) = &self.cert_fingerprint {
+            properties.push(("fp", fingerprint.as_str()));
+        }
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            &instance,
+            &host_name,
+            &self.addresses[..],
+            self.port,
+            &properties[..],
+        )
+        .context("Invalid mDNS advertisement")?;
+        Ok(if self.addresses.is_empty() {
+            info.enable_addr_auto()
+        } else {
+            info
+        })
+    }
+}
+
+/// A device found on the local network
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiscoveredDevice {
+    pub device_id: String,
+    pub device_name: String,
+    pub platform: String,
+    /// Resolved addresses, IPv6 first
+    pub addresses: Vec<SocketAddr>,
+    pub cert_fingerprint: Option<String>,
+    /// DNS-SD instance name, used to match removals
+    pub service_name: String,
+}
+
+impl DiscoveredDevice {
+    fn from_service(info: &ServiceInfo) -> Option<Self> {
+        let device_id = info.get_property_val_str("id")?.to_string();
+        if device_id.is_empty() {
+            return None;
+        }
+        let mut addresses: Vec<SocketAddr> = info
+            .get_addresses()
+            .iter()
+            .map(|ip| SocketAddr::new(*ip, info.get_port()))
+            .collect();
+        addresses.sort_by_key(|addr| (addr.is_ipv4(), *addr));
+        Some(Self {
+            device_name: info
+                .get_property_val_str("name")
+                .unwrap_or(&device_id)
+                .to_string(),
+            platform: info
+                .get_property_val_str("platform")
+                .unwrap_or_default()
+                .to_string(),
+            cert_fingerprint: info.get_property_val_str("fp").map(str::to_string),
+            service_name: info.get_fullname().to_string(),
+            addresses,
+            device_id,
+        })
+    }
+
+    /// Open a TCP connection to the first address that answers
+    pub async fn connect(&self) -> Result<TcpStream> {
+        for address in &self.addresses {
+            match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address)).await {
+                Ok(Ok(stream)) => return Ok(stream),
+                Ok(Err(e)) => {
+                    tracing::debug!("{} unreachable at {}: {}", self.device_id, address, e)
+                }
+                Err(_) => tracing::debug!("{} timed out at {}", self.device_id, address),
+            }
+        }
+        Err(anyhow::anyhow!(
+            "Device {} is not reachable at any advertised address",
+            self.device_id
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DiscoveryEvent {
+    /// A device appeared, or its advertisement changed
+    DeviceFound(DiscoveredDevice),
+    DeviceLost {
+        device_id: String,
+    },
+}
+
+impl EventType for DiscoveryEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            DiscoveryEvent::DeviceFound(_) => "DeviceFound",
+            DiscoveryEvent::DeviceLost { .. } => "DeviceLost",
+        }
+    }
+}
+
+/// Devices currently visible, keyed by service name
+#[derive(Default)]
+struct DeviceTable {
+    /// This device's ID, whose own advertisement is ignored
+    local_device_id: Option<String>,
+    devices: HashMap<String, DiscoveredDevice>,
+}
+
+impl DeviceTable {
+    fn apply(&mut self, event: ServiceEvent) -> Option<DiscoveryEvent> {
+        match event {
+            ServiceEvent::ServiceResolved(info) => {
+                let device = DiscoveredDevice::from_service(&info)?;
+                if self.local_device_id.as_deref() == Some(device.device_id.as_str()) {
+                    return None;
+                }
+                let previous = self
+                    .devices
+                    .insert(device.service_name.clone(), device.clone());
+                (previous.as_ref() != Some(&device)).then_some(DiscoveryEvent::DeviceFound(device))
+            }
+            ServiceEvent::ServiceRemoved(_, service_name) => {
+                let device = self.devices.remove(&service_name)?;
+                Some(DiscoveryEvent::DeviceLost {
+                    device_id: device.device_id,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Advertises this device and browses for others on the LAN
+pub struct LanDiscovery {
+    daemon: ServiceDaemon,
+    table: Arc<RwLock<DeviceTable>>,
+    events: EventBus<DiscoveryEvent>,
+    /// Service name of the running advertisement
+    advertised: Mutex<Option<String>>,
+    browser: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LanDiscovery {
+    /// Start the mDNS responder
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            daemon: ServiceDaemon::new().context("Failed to start mDNS daemon")?,
+            table: Arc::new(RwLock::new(DeviceTable::default())),
+            events: EventBus::new(),
+            advertised: Mutex::new(None),
+            browser: Mutex::new(None),
+        })
+    }
+
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<DiscoveryEvent> {
+        self.events.subscribe(options)
+    }
+
+    /// Advertise this device, replacing any previous advertisement
+    pub fn advertise(&self, advertisement: &Advertisement) -> Result<()> {
+        let info = advertisement.service_info()?;
+        let service_name = info.get_fullname().to_string();
+        self.stop_advertising();
+        self.daemon
+            .register(info)
+            .context("Failed to register mDNS service")?;
+        self.table
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .local_device_id = Some(advertisement.device_id.clone());
+        *self
+            .advertised
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(service_name);
+        tracing::info!(
+            "Advertising {} on port {} via mDNS",
+            advertisement.device_id,
+            advertisement.port
+        );
+        Ok(())
+    }
+
+    pub fn stop_advertising(&self) {
+        let service_name = self
+            .advertised
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(service_name) = service_name {
+            if let Err(e) = self.daemon.unregister(&service_name) {
+                tracing::debug!("Failed to unregister {}: {}", service_name, e);
+            }
+        }
+    }
+
+    /// Browse for other devices; found and lost devices are published as
+    /// `DiscoveryEvent`s
+    pub fn start_browsing(&self) -> Result<()> {
+        let mut browser = self.browser.lock().unwrap_or_else(PoisonError::into_inner);
+        if browser.is_some() {
+            return Ok(());
+        }
+        let receiver = self
+            .daemon
+            .browse(SERVICE_TYPE)
+            .context("Failed to browse for mDNS services")?;
+        let table = Arc::clone(&self.table);
+        let events = self.events.clone();
+        *browser = Some(tokio::spawn(async move {
+            while let Ok(event) = receiver.recv_async().await {
+                let change = table
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .apply(event);
+                if let Some(change) = change {
+                    events.publish(change);
+                }
+            }
+        }));
+        Ok(())
+    }
+
+    pub fn stop_browsing(&self) {
+        let browser = self
+            .browser
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        if let Some(browser) = browser {
+            let _ = self.daemon.stop_browse(SERVICE_TYPE);
+            browser.abort();
+        }
+        self.table
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .devices
+            .clear();
+    }
+
+    /// Devices currently visible, by name
+    pub fn devices(&self) -> Vec<DiscoveredDevice> {
+        let mut devices: Vec<_> = self
+            .table
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .devices
+            .values()
+            .cloned()
+            .collect();
+        devices.sort_by(|a, b| a.device_name.cmp(&b.device_name));
+        devices
+    }
+
+    pub fn device(&self, device_id: &str) -> Option<DiscoveredDevice> {
+        self.table
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .devices
+            .values()
+            .find(|device| device.device_id == device_id)
+            .cloned()
+    }
+}
+
+impl Drop for LanDiscovery {
+    fn drop(&mut self) {
+        self.stop_browsing();
+        self.stop_advertising();
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// DNS-SD instance label for a device ID
+fn instance_name(device_id: &str) -> String {
+    let label: String = device_id
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(63)
+        .collect();
+    format!("cecdesk-{}", label.trim_matches('-'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn advertisement(device_id: &str) -> Advertisement {
+        Advertisement {
+            device_id: device_id.to_string(),
+            device_name: "Office PC".to_string(),
+            platform: "windows".to_string(),
+            port: 47800,
+            addresses: vec!["192.168.1.20".parse().unwrap(), "fe80::1".parse().unwrap()],
+            cert_fingerprint: Some("ab:cd".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_advertisement_round_trips_through_txt_record() {
+        let info = advertisement("host-1").service_info().unwrap();
+        assert_eq!(info.get_fullname(), "cecdesk-host-1._cecdesk._tcp.local.");
+
+        let device = DiscoveredDevice::from_service(&info).unwrap();
+        assert_eq!(device.device_id, "host-1");
+        assert_eq!(device.device_name, "Office PC");
+        assert_eq!(device.cert_fingerprint.as_deref(), Some("ab:cd"));
+        assert_eq!(
+            device.addresses,
+            vec![
+                "[fe80::1]:47800".parse().unwrap(),
+                "192.168.1.20:47800".parse().unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_table_ignores_self_and_tracks_removals() {
+        let mut table = DeviceTable {
+            local_device_id: Some("me".to_string()),
+            ..Default::default()
+        };
+        let own = advertisement("me").service_info().unwrap();
+        assert_eq!(table.apply(ServiceEvent::ServiceResolved(own)), None);
+
+        let info = advertisement("host-1").service_info().unwrap();
+        let service_name = info.get_fullname().to_string();
+        assert!(matches!(
+            table.apply(ServiceEvent::ServiceResolved(info.clone())),
+            Some(DiscoveryEvent::DeviceFound(device)) if device.device_id == "host-1"
+        ));
+        // Re-announcements of an unchanged record are not reported again
+        assert_eq!(table.apply(ServiceEvent::ServiceResolved(info)), None);
+
+        assert_eq!(
+            table.apply(ServiceEvent::ServiceRemoved(
+                SERVICE_TYPE.to_string(),
+                service_name
+            )),
+            Some(DiscoveryEvent::DeviceLost {
+                device_id: "host-1".to_string()
+            })
+        );
+        assert!(table.devices.is_empty());
+    }
+}
@@ -0,0 +1,655 @@
+//! Platform Display Enumeration
+//!
+//! Lists the host's active monitors for the display picker: Win32
+//! `EnumDisplayMonitors`, CoreGraphics `CGGetActiveDisplayList`, and RandR
+//! on X11 (including XWayland). Bindings are declared here against the
+//! system libraries `build.rs` links for the capture feature.
+//!
+//! Results are ordered primary first, then left to right and top to bottom.
+//! If the platform marks no display as primary, the one at the desktop
+//! origin (or the first) is treated as primary.
+
+use crate::screen_capture::DisplayInfo;
+use anyhow::Result;
+
+/// List the active displays
+pub fn enumerate_displays() -> Result<Vec<DisplayInfo>> {
+    #[cfg(target_os = "linux")]
+    let displays = x11::enumerate()?;
+    #[cfg(target_os = "windows")]
+    let displays = win32::enumerate()?;
+    #[cfg(target_os = "macos")]
+    let displays = quartz::enumerate()?;
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    let displays: Vec<DisplayInfo> = Vec::new();
+
+    if displays.is_empty() {
+        return Err(anyhow::anyhow!("No active displays found"));
+    }
+    Ok(order_displays(displays))
+}
+
+/// Put the primary display first, then sort by position
+fn order_displays(mut displays: Vec<DisplayInfo>) -> Vec<DisplayInfo> {
+    if !displays.iter().any(|d| d.is_primary) {
+        let index = displays
+            .iter()
+            .position(|d| d.x == 0 && d.y == 0)
+            .unwrap_or(0);
+        if let Some(display) = displays.get_mut(index) {
+            display.is_primary = true;
+        }
+    }
+    displays.sort_by_key(|d| (!d.is_primary, d.x, d.y));
+    displays
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) mod x11 {
+    use crate::hdr::HdrCapabilities;
+    use crate::screen_capture::DisplayInfo;
+    use anyhow::Result;
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_int, c_uint, c_ulong, c_ushort};
+
+    type Xid = c_ulong;
+    type Window = Xid;
+    type RrOutput = Xid;
+    type RrCrtc = Xid;
+    type RrMode = Xid;
+    type Time = c_ulong;
+
+    #[repr(C)]
+    struct Display {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    struct XrrModeInfo {
+        id: RrMode,
+        width: c_uint,
+        height: c_uint,
+        dot_clock: c_ulong,
+        h_sync_start: c_uint,
+        h_sync_end: c_uint,
+        h_total: c_uint,
+        h_skew: c_uint,
+        v_sync_start: c_uint,
+        v_sync_end: c_uint,
+        v_total: c_uint,
+        name: *mut c_char,
+        name_length: c_uint,
+        mode_flags: c_ulong,
+    }
+
+    #[repr(C)]
+    struct XrrScreenResources {
+        timestamp: Time,
+        config_timestamp: Time,
+        ncrtc: c_int,
+        crtcs: *mut RrCrtc,
+        noutput: c_int,
+        outputs: *mut RrOutput,
+        nmode: c_int,
+        modes: *mut XrrModeInfo,
+    }
+
+    #[repr(C)]
+    struct XrrOutputInfo {
+        timestamp: Time,
+        crtc: RrCrtc,
+        name: *mut c_char,
+        name_len: c_int,
+        mm_width: c_ulong,
+        mm_height: c_ulong,
+        connection: c_ushort,
+        subpixel_order: c_ushort,
+        ncrtc: c_int,
+        crtcs: *mut RrCrtc,
+        nclone: c_int,
+        clones: *mut RrOutput,
+        nmode: c_int,
+        npreferred: c_int,
+        modes: *mut RrMode,
+    }
+
+    #[repr(C)]
+    struct XrrCrtcInfo {
+        timestamp: Time,
+        x: c_int,
+        y: c_int,
+        width: c_uint,
+        height: c_uint,
+        mode: RrMode,
+        rotation: c_ushort,
+        noutput: c_int,
+        outputs: *mut RrOutput,
+        rotations: c_ushort,
+        npossible: c_int,
+        possible: *mut RrOutput,
+    }
+
+    const RR_CONNECTED: c_ushort = 0;
+    const RR_INTERLACE: c_ulong = 0x0000_0010;
+    const RR_DOUBLE_SCAN: c_ulong = 0x0000_0020;
+
+    extern "C" {
+        fn XOpenDisplay(name: *const c_char) -> *mut Display;
+        fn XCloseDisplay(display: *mut Display) -> c_int;
+        fn XDefaultRootWindow(display: *mut Display) -> Window;
+        fn XResourceManagerString(display: *mut Display) -> *mut c_char;
+        fn XRRQueryExtension(
+            display: *mut Display,
+            event_base: *mut c_int,
+            error_base: *mut c_int,
+        ) -> c_int;
+        fn XRRGetScreenResourcesCurrent(
+            display: *mut Display,
+            window: Window,
+        ) -> *mut XrrScreenResources;
+        fn XRRFreeScreenResources(resources: *mut XrrScreenResources);
+        fn XRRGetOutputInfo(
+            display: *mut Display,
+            resources: *mut XrrScreenResources,
+            output: RrOutput,
+        ) -> *mut XrrOutputInfo;
+        fn XRRFreeOutputInfo(info: *mut XrrOutputInfo);
+        fn XRRGetCrtcInfo(
+            display: *mut Display,
+            resources: *mut XrrScreenResources,
+            crtc: RrCrtc,
+        ) -> *mut XrrCrtcInfo;
+        fn XRRFreeCrtcInfo(info: *mut XrrCrtcInfo);
+        fn XRRGetOutputPrimary(display: *mut Display, window: Window) -> RrOutput;
+    }
+
+    pub(super) fn enumerate() -> Result<Vec<DisplayInfo>> {
+        if std::env::var_os("DISPLAY").is_none() {
+            if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                // zwlr_output_manager_v1 enumeration would go here for
+                // compositors running without XWayland
+                return Err(anyhow::anyhow!(
+                    "Display enumeration on Wayland requires XWayland"
+                ));
+            }
+            return Err(anyhow::anyhow!("No X display available (DISPLAY not set)"));
+        }
+
+        // SAFETY: the display is checked for null and closed after use; every
+        // RandR structure is freed with its matching function
+        unsafe {
+            let display = XOpenDisplay(std::ptr::null());
+            if display.is_null() {
+                return Err(anyhow::anyhow!("Cannot open X display"));
+            }
+            let result = enumerate_outputs(display);
+            XCloseDisplay(display);
+            result
+        }
+    }
+
+    unsafe fn enumerate_outputs(display: *mut Display) -> Result<Vec<DisplayInfo>> {
+        let (mut event_base, mut error_base) = (0, 0);
+        if XRRQueryExtension(display, &mut event_base, &mut error_base) == 0 {
+            return Err(anyhow::anyhow!("X server does not support RandR"));
+        }
+
+        let root = XDefaultRootWindow(display);
+        let resources = XRRGetScreenResourcesCurrent(display, root);
+        if resources.is_null() {
+            return Err(anyhow::anyhow!("Cannot read RandR screen resources"));
+        }
+        let primary = XRRGetOutputPrimary(display, root);
+        let scale_factor = xft_scale_factor(XResourceManagerString(display)).unwrap_or(1.0);
+        let modes = raw_slice((*resources).modes, (*resources).nmode);
+
+        let mut displays = Vec::new();
+        for &output in raw_slice((*resources).outputs, (*resources).noutput) {
+            let info = XRRGetOutputInfo(display, resources, output);
+            if info.is_null() {
+                continue;
+            }
+            if (*info).connection == RR_CONNECTED && (*info).crtc != 0 {
+                let crtc = XRRGetCrtcInfo(display, resources, (*info).crtc);
+                if !crtc.is_null() {
+                    let name = String::from_utf8_lossy(raw_slice(
+                        (*info).name as *const u8,
+                        (*info).name_len,
+                    ))
+                    .into_owned();
+                    let refresh_rate = modes
+                        .iter()
+                        .find(|mode| mode.id == (*crtc).mode)
+                        .map(|mode| {
+                            refresh_rate(
+                                mode.dot_clock,
+                                mode.h_total,
+                                mode.v_total,
+                                mode.mode_flags,
+                            )
+                        })
+                        .unwrap_or(0);
+                    displays.push(DisplayInfo {
+                        id: name.clone(),
+                        name,
+                        width: (*crtc).width,
+                        height: (*crtc).height,
+                        is_primary: output == primary,
+                        refresh_rate,
+                        x: (*crtc).x,
+                        y: (*crtc).y,
+                        scale_factor,
+                        // X11 has no HDR output path
+                        hdr: HdrCapabilities::default(),
+                    });
+                    XRRFreeCrtcInfo(crtc);
+                }
+            }
+            XRRFreeOutputInfo(info);
+        }
+        XRRFreeScreenResources(resources);
+        Ok(displays)
+    }
+
+    unsafe fn raw_slice<'a, T>(ptr: *const T, len: c_int) -> &'a [T] {
+        if ptr.is_null() || len <= 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(ptr, len as usize)
+        }
+    }
+
+    unsafe fn xft_scale_factor(resources: *const c_char) -> Option<f64> {
+        if resources.is_null() {
+            return None;
+        }
+        parse_xft_dpi(&CStr::from_ptr(resources).to_string_lossy()).map(|dpi| dpi / 96.0)
+    }
+
+    /// `Xft.dpi` from the X resource database, which desktops set for HiDPI
+    pub(super) fn parse_xft_dpi(resources: &str) -> Option<f64> {
+        resources
+            .lines()
+            .find_map(|line| line.strip_prefix("Xft.dpi:"))
+            .and_then(|value| value.trim().parse::<f64>().ok())
+            .filter(|dpi| *dpi > 0.0)
+    }
+
+    /// Vertical refresh in Hz, rounded, from a RandR mode line
+    pub(crate) fn refresh_rate(
+        dot_clock: c_ulong,
+        h_total: c_uint,
+        v_total: c_uint,
+        flags: c_ulong,
+    ) -> u32 {
+        let mut v_total = v_total as f64;
+        if flags & RR_DOUBLE_SCAN != 0 {
+            v_total *= 2.0;
+        }
+        if flags & RR_INTERLACE != 0 {
+            v_total /= 2.0;
+        }
+        if h_total == 0 || v_total == 0.0 {
+            return 0;
+        }
+        (dot_clock as f64 / (h_total as f64 * v_total)).round() as u32
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod win32 {
+    use crate::hdr::HdrCapabilities;
+    use crate::screen_capture::DisplayInfo;
+    use anyhow::Result;
+
+    type Hmonitor = isize;
+    type Hdc = isize;
+    type Bool = i32;
+    type Lparam = isize;
+
+    #[repr(C)]
+    #[derive(Default, Clone, Copy)]
+    struct Rect {
+        left: i32,
+        top: i32,
+        right: i32,
+        bottom: i32,
+    }
+
+    #[repr(C)]
+    struct MonitorInfoExW {
+        cb_size: u32,
+        rc_monitor: Rect,
+        rc_work: Rect,
+        flags: u32,
+        device: [u16; 32],
+    }
+
+    #[repr(C)]
+    struct DisplayDeviceW {
+        cb: u32,
+        device_name: [u16; 32],
+        device_string: [u16; 128],
+        state_flags: u32,
+        device_id: [u16; 128],
+        device_key: [u16; 128],
+    }
+
+    /// DEVMODEW with the display variant of its first union
+    #[repr(C)]
+    struct DevModeW {
+        device_name: [u16; 32],
+        spec_version: u16,
+        driver_version: u16,
+        size: u16,
+        driver_extra: u16,
+        fields: u32,
+        position_x: i32,
+        position_y: i32,
+        display_orientation: u32,
+        display_fixed_output: u32,
+        color: i16,
+        duplex: i16,
+        y_resolution: i16,
+        tt_option: i16,
+        collate: i16,
+        form_name: [u16; 32],
+        log_pixels: u16,
+        bits_per_pel: u32,
+        pels_width: u32,
+        pels_height: u32,
+        display_flags: u32,
+        display_frequency: u32,
+        icm_method: u32,
+        icm_intent: u32,
+        media_type: u32,
+        dither_type: u32,
+        reserved1: u32,
+        reserved2: u32,
+        panning_width: u32,
+        panning_height: u32,
+    }
+
+    type MonitorEnumProc = unsafe extern "system" fn(Hmonitor, Hdc, *mut Rect, Lparam) -> Bool;
+
+    const MONITORINFOF_PRIMARY: u32 = 0x1;
+    const ENUM_CURRENT_SETTINGS: u32 = u32::MAX;
+    const MDT_EFFECTIVE_DPI: i32 = 0;
+
+    extern "system" {
+        fn EnumDisplayMonitors(
+            hdc: Hdc,
+            clip: *const Rect,
+            callback: Option<MonitorEnumProc>,
+            data: Lparam,
+        ) -> Bool;
+        fn GetMonitorInfoW(monitor: Hmonitor, info: *mut MonitorInfoExW) -> Bool;
+        fn EnumDisplaySettingsW(device: *const u16, mode: u32, devmode: *mut DevModeW) -> Bool;
+        fn EnumDisplayDevicesW(
+            device: *const u16,
+            index: u32,
+            display_device: *mut DisplayDeviceW,
+            flags: u32,
+        ) -> Bool;
+        fn GetDpiForMonitor(monitor: Hmonitor, dpi_type: i32, x: *mut u32, y: *mut u32) -> i32;
+    }
+
+    unsafe extern "system" fn collect_monitor(
+        monitor: Hmonitor,
+        _hdc: Hdc,
+        _rect: *mut Rect,
+        data: Lparam,
+    ) -> Bool {
+        (*(data as *mut Vec<Hmonitor>)).push(monitor);
+        1
+    }
+
+    pub(super) fn enumerate() -> Result<Vec<DisplayInfo>> {
+        let mut monitors: Vec<Hmonitor> = Vec::new();
+        // SAFETY: the callback only runs during the call and writes to
+        // `monitors`, which outlives it; the out structures carry their size
+        unsafe {
+            if EnumDisplayMonitors(
+                0,
+                std::ptr::null(),
+                Some(collect_monitor),
+                &mut monitors as *mut Vec<Hmonitor> as Lparam,
+            ) == 0
+            {
+                return Err(anyhow::anyhow!("EnumDisplayMonitors failed"));
+            }
+            Ok(monitors
+                .into_iter()
+                .filter_map(|monitor| describe_monitor(monitor))
+                .collect())
+        }
+    }
+
+    unsafe fn describe_monitor(monitor: Hmonitor) -> Option<DisplayInfo> {
+        let mut info: MonitorInfoExW = std::mem::zeroed();
+        info.cb_size = std::mem::size_of::<MonitorInfoExW>() as u32;
+        if GetMonitorInfoW(monitor, &mut info) == 0 {
+            return None;
+        }
+        let rect = info.rc_monitor;
+
+        let mut mode: DevModeW = std::mem::zeroed();
+        mode.size = std::mem::size_of::<DevModeW>() as u16;
+        let has_mode =
+            EnumDisplaySettingsW(info.device.as_ptr(), ENUM_CURRENT_SETTINGS, &mut mode) != 0;
+        let (width, height, refresh_rate) = if has_mode {
+            // Physical pixels; rc_monitor is scaled for non DPI-aware callers
+            (mode.pels_width, mode.pels_height, mode.display_frequency)
+        } else {
+            (
+                (rect.right - rect.left) as u32,
+                (rect.bottom - rect.top) as u32,
+                0,
+            )
+        };
+        // 0 and 1 mean "hardware default"
+        let refresh_rate = if refresh_rate > 1 { refresh_rate } else { 0 };
+
+        let (mut dpi_x, mut dpi_y) = (0u32, 0u32);
+        let scale_factor = if GetDpiForMonitor(monitor, MDT_EFFECTIVE_DPI, &mut dpi_x, &mut dpi_y)
+            == 0
+            && dpi_x > 0
+        {
+            dpi_x as f64 / 96.0
+        } else {
+            1.0
+        };
+
+        let id = wide_to_string(&info.device);
+        let mut device: DisplayDeviceW = std::mem::zeroed();
+        device.cb = std::mem::size_of::<DisplayDeviceW>() as u32;
+        let name = if EnumDisplayDevicesW(info.device.as_ptr(), 0, &mut device, 0) != 0 {
+            wide_to_string(&device.device_string)
+        } else {
+            id.clone()
+        };
+
+        Some(DisplayInfo {
+            id,
+            name,
+            width,
+            height,
+            is_primary: info.flags & MONITORINFOF_PRIMARY != 0,
+            refresh_rate,
+            x: rect.left,
+            y: rect.top,
+            scale_factor,
+            // IDXGIOutput6::GetDesc1 (ColorSpace == G2084, MaxLuminance)
+            // would go here
+            hdr: HdrCapabilities::default(),
+        })
+    }
+
+    fn wide_to_string(wide: &[u16]) -> String {
+        let len = wide.iter().position(|&c| c == 0).unwrap_or(wide.len());
+        String::from_utf16_lossy(&wide[..len])
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod quartz {
+    use crate::hdr::HdrCapabilities;
+    use crate::screen_capture::DisplayInfo;
+    use anyhow::Result;
+    use std::os::raw::c_void;
+
+    type CgDirectDisplayId = u32;
+    type CgDisplayModeRef = *mut c_void;
+
+    #[repr(C)]
+    struct CgPoint {
+        x: f64,
+        y: f64,
+    }
+
+    #[repr(C)]
+    struct CgSize {
+        width: f64,
+        height: f64,
+    }
+
+    #[repr(C)]
+    struct CgRect {
+        origin: CgPoint,
+        size: CgSize,
+    }
+
+    /// More displays than any Mac can drive
+    const MAX_DISPLAYS: u32 = 32;
+
+    extern "C" {
+        fn CGGetActiveDisplayList(
+            max_displays: u32,
+            displays: *mut CgDirectDisplayId,
+            count: *mut u32,
+        ) -> i32;
+        fn CGMainDisplayID() -> CgDirectDisplayId;
+        fn CGDisplayBounds(display: CgDirectDisplayId) -> CgRect;
+        fn CGDisplayIsBuiltin(display: CgDirectDisplayId) -> u32;
+        fn CGDisplayCopyDisplayMode(display: CgDirectDisplayId) -> CgDisplayModeRef;
+        fn CGDisplayModeGetRefreshRate(mode: CgDisplayModeRef) -> f64;
+        fn CGDisplayModeGetPixelWidth(mode: CgDisplayModeRef) -> usize;
+        fn CGDisplayModeGetPixelHeight(mode: CgDisplayModeRef) -> usize;
+        fn CGDisplayModeRelease(mode: CgDisplayModeRef);
+    }
+
+    pub(super) fn enumerate() -> Result<Vec<DisplayInfo>> {
+        let mut ids = [0 as CgDirectDisplayId; MAX_DISPLAYS as usize];
+        let mut count = 0u32;
+        // SAFETY: `ids` holds MAX_DISPLAYS entries and each copied display
+        // mode is released
+        unsafe {
+            let error = CGGetActiveDisplayList(MAX_DISPLAYS, ids.as_mut_ptr(), &mut count);
+            if error != 0 {
+                return Err(anyhow::anyhow!("CGGetActiveDisplayList failed: {}", error));
+            }
+            let main = CGMainDisplayID();
+            Ok(ids[..count as usize]
+                .iter()
+                .map(|&id| describe_display(id, main))
+                .collect())
+        }
+    }
+
+    unsafe fn describe_display(id: CgDirectDisplayId, main: CgDirectDisplayId) -> DisplayInfo {
+        // Bounds are in points; the mode gives the backing pixel size
+        let bounds = CGDisplayBounds(id);
+        let (mut width, mut height) = (bounds.size.width as u32, bounds.size.height as u32);
+        let mut refresh_rate = 0;
+        let mode = CGDisplayCopyDisplayMode(id);
+        if !mode.is_null() {
+            width = CGDisplayModeGetPixelWidth(mode) as u32;
+            height = CGDisplayModeGetPixelHeight(mode) as u32;
+            // Built-in panels report 0
+            refresh_rate = CGDisplayModeGetRefreshRate(mode).round() as u32;
+            CGDisplayModeRelease(mode);
+        }
+        let scale_factor = if bounds.size.width > 0.0 {
+            width as f64 / bounds.size.width
+        } else {
+            1.0
+        };
+        let name = if CGDisplayIsBuiltin(id) != 0 {
+            "Built-in Display".to_string()
+        } else {
+            format!("Display {}", id)
+        };
+
+        DisplayInfo {
+            id: id.to_string(),
+            name,
+            width,
+            height,
+            is_primary: id == main,
+            refresh_rate,
+            x: bounds.origin.x as i32,
+            y: bounds.origin.y as i32,
+            scale_factor,
+            // NSScreen maximumPotentialExtendedDynamicRangeColorComponentValue
+            // would go here
+            hdr: HdrCapabilities::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdr::HdrCapabilities;
+
+    fn display(id: &str, x: i32, y: i32, is_primary: bool) -> DisplayInfo {
+        DisplayInfo {
+            id: id.to_string(),
+            name: id.to_string(),
+            width: 1920,
+            height: 1080,
+            is_primary,
+            refresh_rate: 60,
+            x,
+            y,
+            scale_factor: 1.0,
+            hdr: HdrCapabilities::default(),
+        }
+    }
+
+    #[test]
+    fn test_primary_first_then_by_position() {
+        let ordered = order_displays(vec![
+            display("right", 1920, 0, false),
+            display("left", -1920, 0, false),
+            display("main", 0, 0, true),
+        ]);
+        let ids: Vec<&str> = ordered.iter().map(|d| d.id.as_str()).collect();
+        assert_eq!(ids, ["main", "left", "right"]);
+
+        // Without a primary flag the display at the origin is primary
+        let ordered = order_displays(vec![
+            display("right", 1920, 0, false),
+            display("origin", 0, 0, false),
+        ]);
+        assert_eq!(ordered[0].id, "origin");
+        assert!(ordered[0].is_primary);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_randr_refresh_and_xft_scale() {
+        // 1920x1080@60 CVT reduced blanking: 138.5 MHz, 2080 x 1111
+        assert_eq!(x11::refresh_rate(138_500_000, 2080, 1111, 0), 60);
+        // 2560x1440@144
+        assert_eq!(x11::refresh_rate(586_586_000, 2720, 1497, 0), 144);
+        assert_eq!(x11::refresh_rate(74_250_000, 2200, 1125, 0x10), 60);
+        assert_eq!(x11::refresh_rate(1, 0, 0, 0), 0);
+
+        assert_eq!(
+            x11::parse_xft_dpi("Xft.antialias:\t1\nXft.dpi:\t192\n"),
+            Some(192.0)
+        );
+        assert_eq!(x11::parse_xft_dpi("Xcursor.size:\t24\n"), None);
+    }
+}
@@ -0,0 +1,1140 @@
+//! Host Display Resolution Control
+//!
+//! Lets the controller temporarily switch a host display to a mode closer to
+//! the viewer's screen (e.g. a laptop controlling a 4K desktop). The original
+//! mode is written to a restore journal before any change, so it can be put
+//! back when the session ends, or on the next start if the process crashed
+//! while a session was active.
+
+use crate::session_manager::{Permission, SessionEvent};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+/// File name of the restore journal
+pub const DISPLAY_JOURNAL_FILE_NAME: &str = "display_restore.json";
+
+/// Display mode (resolution and refresh rate)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DisplayMode {
+    pub width: u32,
+    pub height: u32,
+    pub refresh_rate: u32,
+}
+
+impl DisplayMode {
+    fn pixels(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+}
+
+/// Platform display mode switching
+pub trait DisplayModeBackend: Send + Sync {
+    fn current_mode(&self, display_id: &str) -> Result<DisplayMode>;
+    fn supported_modes(&self, display_id: &str) -> Result<Vec<DisplayMode>>;
+    fn apply_mode(&self, display_id: &str, mode: &DisplayMode) -> Result<()>;
+}
+
+/// Default backend using the OS display APIs
+///
+/// Display IDs are those of `display_enum`: the GDI device name on Windows,
+/// the `CGDirectDisplayID` on macOS and the RandR output name on X11. Modes
+/// are changed for as short as the platform allows: Windows keeps them out
+/// of the registry and macOS reverts them when this process exits, so only
+/// X11 relies on the restore journal after a crash.
+pub struct PlatformDisplayModeBackend;
+
+impl DisplayModeBackend for PlatformDisplayModeBackend {
+    fn current_mode(&self, display_id: &str) -> Result<DisplayMode> {
+        #[cfg(target_os = "windows")]
+        {
+            win32::current_mode(display_id)
+        }
+        #[cfg(target_os = "macos")]
+        {
+            quartz::current_mode(display_id)
+        }
+        #[cfg(target_os = "linux")]
+        {
+            x11::current_mode(display_id)
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            Err(unsupported(display_id))
+        }
+    }
+
+    fn supported_modes(&self, display_id: &str) -> Result<Vec<DisplayMode>> {
+        #[cfg(target_os = "windows")]
+        {
+            win32::supported_modes(display_id).map(distinct)
+        }
+        #[cfg(target_os = "macos")]
+        {
+            quartz::supported_modes(display_id).map(distinct)
+        }
+        #[cfg(target_os = "linux")]
+        {
+            x11::supported_modes(display_id).map(distinct)
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            Err(unsupported(display_id))
+        }
+    }
+
+    fn apply_mode(&self, display_id: &str, mode: &DisplayMode) -> Result<()> {
+        #[cfg(target_os = "windows")]
+        {
+            win32::apply_mode(display_id, mode)
+        }
+        #[cfg(target_os = "macos")]
+        {
+            quartz::apply_mode(display_id, mode)
+        }
+        #[cfg(target_os = "linux")]
+        {
+            x11::apply_mode(display_id, mode)
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            let _ = mode;
+            Err(unsupported(display_id))
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn unsupported(display_id: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Display mode control for {} is not supported on {}",
+        display_id,
+        std::env::consts::OS
+    )
+}
+
+/// Modes in a stable order without the duplicates platforms list for
+/// variants (color depth, scaling) that look the same here
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+fn distinct(mut modes: Vec<DisplayMode>) -> Vec<DisplayMode> {
+    modes.sort_by_key(|m| (m.width, m.height, m.refresh_rate));
+    modes.dedup();
+    modes
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos", target_os = "linux"))]
+fn no_such_mode(display_id: &str, mode: &DisplayMode) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Display {} has no {}x{}@{}Hz mode",
+        display_id,
+        mode.width,
+        mode.height,
+        mode.refresh_rate
+    )
+}
+
+#[cfg(target_os = "windows")]
+mod win32 {
+    use super::DisplayMode;
+    use anyhow::Result;
+    use std::os::raw::c_void;
+
+    type Hwnd = isize;
+    type Bool = i32;
+
+    /// DEVMODEW with the display variant of its first union
+    #[repr(C)]
+    struct DevModeW {
+        device_name: [u16; 32],
+        spec_version: u16,
+        driver_version: u16,
+        size: u16,
+        driver_extra: u16,
+        fields: u32,
+        position_x: i32,
+        position_y: i32,
+        display_orientation: u32,
+        display_fixed_output: u32,
+        color: i16,
+        duplex: i16,
+        y_resolution: i16,
+        tt_option: i16,
+        collate: i16,
+        form_name: [u16; 32],
+        log_pixels: u16,
+        bits_per_pel: u32,
+        pels_width: u32,
+        pels_height: u32,
+        display_flags: u32,
+        display_frequency: u32,
+        icm_method: u32,
+        icm_intent: u32,
+        media_type: u32,
+        dither_type: u32,
+        reserved1: u32,
+        reserved2: u32,
+        panning_width: u32,
+        panning_height: u32,
+    }
+
+    const ENUM_CURRENT_SETTINGS: u32 = u32::MAX;
+    const DM_BITSPERPEL: u32 = 0x0004_0000;
+    const DM_PELSWIDTH: u32 = 0x0008_0000;
+    const DM_PELSHEIGHT: u32 = 0x0010_0000;
+    const DM_DISPLAYFREQUENCY: u32 = 0x0040_0000;
+    /// Not written to the registry, so a reboot or sign-out undoes it
+    const CDS_FULLSCREEN: u32 = 0x0000_0004;
+    const DISP_CHANGE_SUCCESSFUL: i32 = 0;
+
+    extern "system" {
+        fn EnumDisplaySettingsW(device: *const u16, mode: u32, devmode: *mut DevModeW) -> Bool;
+        fn ChangeDisplaySettingsExW(
+            device: *const u16,
+            devmode: *mut DevModeW,
+            hwnd: Hwnd,
+            flags: u32,
+            param: *mut c_void,
+        ) -> i32;
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    /// Settings at `index`, or the current ones for `ENUM_CURRENT_SETTINGS`
+    fn settings(device: &[u16], index: u32) -> Option<DevModeW> {
+        // SAFETY: DEVMODEW is plain data and carries its own size
+        unsafe {
+            let mut mode: DevModeW = std::mem::zeroed();
+            mode.size = std::mem::size_of::<DevModeW>() as u16;
+            (EnumDisplaySettingsW(device.as_ptr(), index, &mut mode) != 0).then_some(mode)
+        }
+    }
+
+    fn to_mode(settings: &DevModeW) -> DisplayMode {
+        DisplayMode {
+            width: settings.pels_width,
+            height: settings.pels_height,
+            // 0 and 1 mean "hardware default"
+            refresh_rate: if settings.display_frequency > 1 {
+                settings.display_frequency
+            } else {
+                0
+            },
+        }
+    }
+
+    fn current_settings(display_id: &str) -> Result<DevModeW> {
+        settings(&wide(display_id), ENUM_CURRENT_SETTINGS)
+            .ok_or_else(|| anyhow::anyhow!("Cannot read the mode of display {}", display_id))
+    }
+
+    pub(super) fn current_mode(display_id: &str) -> Result<DisplayMode> {
+        Ok(to_mode(&current_settings(display_id)?))
+    }
+
+    /// Modes at the current color depth and orientation
+    fn all_settings(display_id: &str) -> Result<Vec<DevModeW>> {
+        let current = current_settings(display_id)?;
+        let device = wide(display_id);
+        Ok((0..)
+            .map_while(|index| settings(&device, index))
+            .filter(|s| {
+                s.bits_per_pel == current.bits_per_pel
+                    && s.display_orientation == current.display_orientation
+            })
+            .collect())
+    }
+
+    pub(super) fn supported_modes(display_id: &str) -> Result<Vec<DisplayMode>> {
+        Ok(all_settings(display_id)?.iter().map(to_mode).collect())
+    }
+
+    pub(super) fn apply_mode(display_id: &str, mode: &DisplayMode) -> Result<()> {
+        let mut settings = all_settings(display_id)?
+            .into_iter()
+            .find(|s| to_mode(s) == *mode)
+            .ok_or_else(|| super::no_such_mode(display_id, mode))?;
+        settings.fields = DM_BITSPERPEL | DM_PELSWIDTH | DM_PELSHEIGHT | DM_DISPLAYFREQUENCY;
+        let device = wide(display_id);
+        // SAFETY: `settings` came from EnumDisplaySettingsW for this device
+        let result = unsafe {
+            ChangeDisplaySettingsExW(
+                device.as_ptr(),
+                &mut settings,
+                0,
+                CDS_FULLSCREEN,
+                std::ptr::null_mut(),
+            )
+        };
+        if result != DISP_CHANGE_SUCCESSFUL {
+            return Err(anyhow::anyhow!(
+                "ChangeDisplaySettingsExW failed for {}: {}",
+                display_id,
+                result
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod quartz {
+    use super::DisplayMode;
+    use anyhow::Result;
+    use std::os::raw::c_void;
+
+    type CgDirectDisplayId = u32;
+    type CgDisplayModeRef = *mut c_void;
+    type CgDisplayConfigRef = *mut c_void;
+    type CfArrayRef = *const c_void;
+
+    /// Undone by Quartz when this process exits
+    const CG_CONFIGURE_FOR_APP_ONLY: u32 = 0;
+
+    extern "C" {
+        fn CGDisplayCopyDisplayMode(display: CgDirectDisplayId) -> CgDisplayModeRef;
+        fn CGDisplayCopyAllDisplayModes(
+            display: CgDirectDisplayId,
+            options: *const c_void,
+        ) -> CfArrayRef;
+        fn CGDisplayModeGetRefreshRate(mode: CgDisplayModeRef) -> f64;
+        fn CGDisplayModeGetWidth(mode: CgDisplayModeRef) -> usize;
+        fn CGDisplayModeGetPixelWidth(mode: CgDisplayModeRef) -> usize;
+        fn CGDisplayModeGetPixelHeight(mode: CgDisplayModeRef) -> usize;
+        fn CGDisplayModeIsUsableForDesktopGUI(mode: CgDisplayModeRef) -> bool;
+        fn CGDisplayModeRelease(mode: CgDisplayModeRef);
+        fn CGBeginDisplayConfiguration(config: *mut CgDisplayConfigRef) -> i32;
+        fn CGConfigureDisplayWithDisplayMode(
+            config: CgDisplayConfigRef,
+            display: CgDirectDisplayId,
+            mode: CgDisplayModeRef,
+            options: *const c_void,
+        ) -> i32;
+        fn CGCompleteDisplayConfiguration(config: CgDisplayConfigRef, option: u32) -> i32;
+        fn CGCancelDisplayConfiguration(config: CgDisplayConfigRef) -> i32;
+        fn CFArrayGetCount(array: CfArrayRef) -> isize;
+        fn CFArrayGetValueAtIndex(array: CfArrayRef, index: isize) -> *const c_void;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    fn display(display_id: &str) -> Result<CgDirectDisplayId> {
+        display_id
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid display ID {}", display_id))
+    }
+
+    /// Pixel size and rounded refresh rate; built-in panels report 0 Hz
+    unsafe fn to_mode(mode: CgDisplayModeRef) -> DisplayMode {
+        DisplayMode {
+            width: CGDisplayModeGetPixelWidth(mode) as u32,
+            height: CGDisplayModeGetPixelHeight(mode) as u32,
+            refresh_rate: CGDisplayModeGetRefreshRate(mode).round() as u32,
+        }
+    }
+
+    /// Backing pixels per point, which tells HiDPI modes from plain ones
+    unsafe fn scale(mode: CgDisplayModeRef) -> usize {
+        CGDisplayModeGetPixelWidth(mode) / CGDisplayModeGetWidth(mode).max(1)
+    }
+
+    pub(super) fn current_mode(display_id: &str) -> Result<DisplayMode> {
+        let id = display(display_id)?;
+        // SAFETY: the copied mode is released after use
+        unsafe {
+            let mode = CGDisplayCopyDisplayMode(id);
+            if mode.is_null() {
+                return Err(anyhow::anyhow!("Cannot read the mode of display {}", id));
+            }
+            let current = to_mode(mode);
+            CGDisplayModeRelease(mode);
+            Ok(current)
+        }
+    }
+
+    /// Desktop modes of a display, passed to `f` while the list is alive
+    unsafe fn with_modes<T>(
+        display_id: &str,
+        f: impl FnOnce(CgDirectDisplayId, &[CgDisplayModeRef]) -> Result<T>,
+    ) -> Result<T> {
+        let id = display(display_id)?;
+        let array = CGDisplayCopyAllDisplayModes(id, std::ptr::null());
+        if array.is_null() {
+            return Err(anyhow::anyhow!("Cannot list the modes of display {}", id));
+        }
+        let modes: Vec<CgDisplayModeRef> = (0..CFArrayGetCount(array))
+            .map(|index| CFArrayGetValueAtIndex(array, index) as CgDisplayModeRef)
+            .filter(|&mode| CGDisplayModeIsUsableForDesktopGUI(mode))
+            .collect();
+        let result = f(id, &modes);
+        CFRelease(array);
+        result
+    }
+
+    pub(super) fn supported_modes(display_id: &str) -> Result<Vec<DisplayMode>> {
+        // SAFETY: the modes are only read while the array holds them
+        unsafe {
+            with_modes(display_id, |_, modes| {
+                Ok(modes.iter().map(|&m| to_mode(m)).collect())
+            })
+        }
+    }
+
+    pub(super) fn apply_mode(display_id: &str, mode: &DisplayMode) -> Result<()> {
+        // SAFETY: the modes are only used while the array holds them and the
+        // configuration is completed or cancelled on every path
+        unsafe {
+            with_modes(display_id, |id, modes| {
+                let current = CGDisplayCopyDisplayMode(id);
+                let current_scale = if current.is_null() {
+                    1
+                } else {
+                    let scale = scale(current);
+                    CGDisplayModeRelease(current);
+                    scale
+                };
+                // The same pixel size can be a HiDPI or a plain mode; keep
+                // the kind the display is in
+                let matching: Vec<CgDisplayModeRef> = modes
+                    .iter()
+                    .copied()
+                    .filter(|&m| to_mode(m) == *mode)
+                    .collect();
+                let target = matching
+                    .iter()
+                    .copied()
+                    .find(|&m| scale(m) == current_scale)
+                    .or_else(|| matching.first().copied())
+                    .ok_or_else(|| super::no_such_mode(display_id, mode))?;
+
+                let mut config: CgDisplayConfigRef = std::ptr::null_mut();
+                let error = CGBeginDisplayConfiguration(&mut config);
+                if error != 0 {
+                    return Err(anyhow::anyhow!(
+                        "CGBeginDisplayConfiguration failed: {}",
+                        error
+                    ));
+                }
+                let error = CGConfigureDisplayWithDisplayMode(config, id, target, std::ptr::null());
+                if error != 0 {
+                    CGCancelDisplayConfiguration(config);
+                    return Err(anyhow::anyhow!(
+                        "CGConfigureDisplayWithDisplayMode failed for {}: {}",
+                        id,
+                        error
+                    ));
+                }
+                let error = CGCompleteDisplayConfiguration(config, CG_CONFIGURE_FOR_APP_ONLY);
+                if error != 0 {
+                    return Err(anyhow::anyhow!(
+                        "CGCompleteDisplayConfiguration failed for {}: {}",
+                        id,
+                        error
+                    ));
+                }
+                Ok(())
+            })
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod x11 {
+    use super::DisplayMode;
+    use crate::capture_backend::{install_error_handler, take_x_error};
+    use crate::display_enum::x11::refresh_rate;
+    use anyhow::Result;
+    use std::os::raw::{c_char, c_int, c_uint, c_ulong, c_ushort};
+
+    type Xid = c_ulong;
+    type Window = Xid;
+    type RrOutput = Xid;
+    type RrCrtc = Xid;
+    type RrMode = Xid;
+    type Time = c_ulong;
+    type Rotation = c_ushort;
+
+    #[repr(C)]
+    struct Display {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    struct XrrModeInfo {
+        id: RrMode,
+        width: c_uint,
+        height: c_uint,
+        dot_clock: c_ulong,
+        h_sync_start: c_uint,
+        h_sync_end: c_uint,
+        h_total: c_uint,
+        h_skew: c_uint,
+        v_sync_start: c_uint,
+        v_sync_end: c_uint,
+        v_total: c_uint,
+        name: *mut c_char,
+        name_length: c_uint,
+        mode_flags: c_ulong,
+    }
+
+    #[repr(C)]
+    struct XrrScreenResources {
+        timestamp: Time,
+        config_timestamp: Time,
+        ncrtc: c_int,
+        crtcs: *mut RrCrtc,
+        noutput: c_int,
+        outputs: *mut RrOutput,
+        nmode: c_int,
+        modes: *mut XrrModeInfo,
+    }
+
+    #[repr(C)]
+    struct XrrOutputInfo {
+        timestamp: Time,
+        crtc: RrCrtc,
+        name: *mut c_char,
+        name_len: c_int,
+        mm_width: c_ulong,
+        mm_height: c_ulong,
+        connection: c_ushort,
+        subpixel_order: c_ushort,
+        ncrtc: c_int,
+        crtcs: *mut RrCrtc,
+        nclone: c_int,
+        clones: *mut RrOutput,
+        nmode: c_int,
+        npreferred: c_int,
+        modes: *mut RrMode,
+    }
+
+    #[repr(C)]
+    struct XrrCrtcInfo {
+        timestamp: Time,
+        x: c_int,
+        y: c_int,
+        width: c_uint,
+        height: c_uint,
+        mode: RrMode,
+        rotation: Rotation,
+        noutput: c_int,
+        outputs: *mut RrOutput,
+        rotations: c_ushort,
+        npossible: c_int,
+        possible: *mut RrOutput,
+    }
+
+    const RR_CONNECTED: c_ushort = 0;
+    const RR_ROTATE_90: Rotation = 2;
+    const RR_ROTATE_270: Rotation = 8;
+    const CURRENT_TIME: Time = 0;
+    const RR_SET_CONFIG_SUCCESS: c_int = 0;
+
+    extern "C" {
+        fn XOpenDisplay(name: *const c_char) -> *mut Display;
+        fn XCloseDisplay(display: *mut Display) -> c_int;
+        fn XDefaultRootWindow(display: *mut Display) -> Window;
+        fn XDefaultScreen(display: *mut Display) -> c_int;
+        fn XDisplayWidth(display: *mut Display, screen: c_int) -> c_int;
+        fn XDisplayHeight(display: *mut Display, screen: c_int) -> c_int;
+        fn XDisplayWidthMM(display: *mut Display, screen: c_int) -> c_int;
+        fn XDisplayHeightMM(display: *mut Display, screen: c_int) -> c_int;
+        fn XGrabServer(display: *mut Display) -> c_int;
+        fn XUngrabServer(display: *mut Display) -> c_int;
+        fn XSync(display: *mut Display, discard: c_int) -> c_int;
+        fn XRRQueryExtension(
+            display: *mut Display,
+            event_base: *mut c_int,
+            error_base: *mut c_int,
+        ) -> c_int;
+        fn XRRGetScreenResourcesCurrent(
+            display: *mut Display,
+            window: Window,
+        ) -> *mut XrrScreenResources;
+        fn XRRFreeScreenResources(resources: *mut XrrScreenResources);
+        fn XRRGetOutputInfo(
+            display: *mut Display,
+            resources: *mut XrrScreenResources,
+            output: RrOutput,
+        ) -> *mut XrrOutputInfo;
+        fn XRRFreeOutputInfo(info: *mut XrrOutputInfo);
+        fn XRRGetCrtcInfo(
+            display: *mut Display,
+            resources: *mut XrrScreenResources,
+            crtc: RrCrtc,
+        ) -> *mut XrrCrtcInfo;
+        fn XRRFreeCrtcInfo(info: *mut XrrCrtcInfo);
+        fn XRRSetCrtcConfig(
+            display: *mut Display,
+            resources: *mut XrrScreenResources,
+            crtc: RrCrtc,
+            timestamp: Time,
+            x: c_int,
+            y: c_int,
+            mode: RrMode,
+            rotation: Rotation,
+            outputs: *mut RrOutput,
+            noutputs: c_int,
+        ) -> c_int;
+        fn XRRSetScreenSize(
+            display: *mut Display,
+            window: Window,
+            width: c_int,
+            height: c_int,
+            mm_width: c_int,
+            mm_height: c_int,
+        );
+    }
+
+    unsafe fn raw_slice<'a, T>(ptr: *const T, len: c_int) -> &'a [T] {
+        if ptr.is_null() || len <= 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(ptr, len as usize)
+        }
+    }
+
+    fn to_mode(info: &XrrModeInfo) -> DisplayMode {
+        DisplayMode {
+            width: info.width,
+            height: info.height,
+            refresh_rate: refresh_rate(info.dot_clock, info.h_total, info.v_total, info.mode_flags),
+        }
+    }
+
+    /// Size a CRTC covers on the screen with a mode, after rotation
+    fn rotated_size(width: c_uint, height: c_uint, rotation: Rotation) -> (c_uint, c_uint) {
+        if rotation & (RR_ROTATE_90 | RR_ROTATE_270) != 0 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// An open connection with the RandR state of one connected output
+    struct Output {
+        display: *mut Display,
+        resources: *mut XrrScreenResources,
+        info: *mut XrrOutputInfo,
+        crtc: *mut XrrCrtcInfo,
+    }
+
+    impl Output {
+        fn open(display_id: &str) -> Result<Self> {
+            if std::env::var_os("DISPLAY").is_none() {
+                return Err(anyhow::anyhow!(
+                    "Display mode control needs an X display (DISPLAY not set)"
+                ));
+            }
+            install_error_handler();
+            // SAFETY: everything opened here is released by `drop`, which
+            // tolerates the fields still being null
+            unsafe {
+                let mut output = Self {
+                    display: XOpenDisplay(std::ptr::null()),
+                    resources: std::ptr::null_mut(),
+                    info: std::ptr::null_mut(),
+                    crtc: std::ptr::null_mut(),
+                };
+                if output.display.is_null() {
+                    return Err(anyhow::anyhow!("Cannot open X display"));
+                }
+                let (mut event_base, mut error_base) = (0, 0);
+                if XRRQueryExtension(output.display, &mut event_base, &mut error_base) == 0 {
+                    return Err(anyhow::anyhow!("X server does not support RandR"));
+                }
+                output.resources = XRRGetScreenResourcesCurrent(
+                    output.display,
+                    XDefaultRootWindow(output.display),
+                );
+                if output.resources.is_null() {
+                    return Err(anyhow::anyhow!("Cannot read RandR screen resources"));
+                }
+                let resources = &*output.resources;
+                for &id in raw_slice(resources.outputs, resources.noutput) {
+                    let info = XRRGetOutputInfo(output.display, output.resources, id);
+                    if info.is_null() {
+                        continue;
+                    }
+                    let name = raw_slice((*info).name as *const u8, (*info).name_len);
+                    if name == display_id.as_bytes()
+                        && (*info).connection == RR_CONNECTED
+                        && (*info).crtc != 0
+                    {
+                        output.info = info;
+                        break;
+                    }
+                    XRRFreeOutputInfo(info);
+                }
+                if output.info.is_null() {
+                    return Err(anyhow::anyhow!("No active output named {}", display_id));
+                }
+                output.crtc = XRRGetCrtcInfo(output.display, output.resources, (*output.info).crtc);
+                if output.crtc.is_null() {
+                    return Err(anyhow::anyhow!("Cannot read the CRTC of {}", display_id));
+                }
+                Ok(output)
+            }
+        }
+
+        fn screen_modes(&self) -> &[XrrModeInfo] {
+            // SAFETY: the resources stay alive as long as `self`
+            unsafe { raw_slice((*self.resources).modes, (*self.resources).nmode) }
+        }
+
+        /// Modes the output supports, with their RandR IDs
+        fn modes(&self) -> Vec<(RrMode, DisplayMode)> {
+            // SAFETY: the output info stays alive as long as `self`
+            let ids = unsafe { raw_slice((*self.info).modes, (*self.info).nmode) };
+            self.screen_modes()
+                .iter()
+                .filter(|info| ids.contains(&info.id))
+                .map(|info| (info.id, to_mode(info)))
+                .collect()
+        }
+
+        fn current(&self) -> Option<DisplayMode> {
+            // SAFETY: the CRTC info stays alive as long as `self`
+            let mode = unsafe { (*self.crtc).mode };
+            self.screen_modes()
+                .iter()
+                .find(|info| info.id == mode)
+                .map(to_mode)
+        }
+
+        /// Screen size that fits every active CRTC once this one shows `mode`
+        unsafe fn screen_size_with(&self, mode: &DisplayMode) -> (c_int, c_int) {
+            let resources = &*self.resources;
+            let (mut width, mut height) = (0, 0);
+            for &id in raw_slice(resources.crtcs, resources.ncrtc) {
+                let (x, y, w, h) = if id == (*self.info).crtc {
+                    let (w, h) = rotated_size(mode.width, mode.height, (*self.crtc).rotation);
+                    ((*self.crtc).x, (*self.crtc).y, w, h)
+                } else {
+                    let crtc = XRRGetCrtcInfo(self.display, self.resources, id);
+                    if crtc.is_null() {
+                        continue;
+                    }
+                    let bounds = if (*crtc).mode != 0 {
+                        ((*crtc).x, (*crtc).y, (*crtc).width, (*crtc).height)
+                    } else {
+                        (0, 0, 0, 0)
+                    };
+                    XRRFreeCrtcInfo(crtc);
+                    bounds
+                };
+                width = width.max(x + w as c_int);
+                height = height.max(y + h as c_int);
+            }
+            (width, height)
+        }
+
+        /// Switch the output's CRTC, resizing the screen around it like
+        /// `xrandr` does: grow before the change, shrink after it
+        fn set_mode(&self, id: RrMode, mode: &DisplayMode) -> Result<()> {
+            // SAFETY: all structures belong to this connection; the server
+            // grab is released on every path
+            unsafe {
+                let display = self.display;
+                let screen = XDefaultScreen(display);
+                let root = XDefaultRootWindow(display);
+                let (old_width, old_height) = (
+                    XDisplayWidth(display, screen),
+                    XDisplayHeight(display, screen),
+                );
+                let (width, height) = self.screen_size_with(mode);
+                // Keep the physical size per pixel so the DPI stays put
+                let mm_width = XDisplayWidthMM(display, screen) * width / old_width.max(1);
+                let mm_height = XDisplayHeightMM(display, screen) * height / old_height.max(1);
+                let grow = width > old_width || height > old_height;
+
+                XGrabServer(display);
+                if grow {
+                    XRRSetScreenSize(
+                        display,
+                        root,
+                        width.max(old_width),
+                        height.max(old_height),
+                        mm_width.max(XDisplayWidthMM(display, screen)),
+                        mm_height.max(XDisplayHeightMM(display, screen)),
+                    );
+                }
+                let crtc = &*self.crtc;
+                let status = XRRSetCrtcConfig(
+                    display,
+                    self.resources,
+                    (*self.info).crtc,
+                    CURRENT_TIME,
+                    crtc.x,
+                    crtc.y,
+                    id,
+                    crtc.rotation,
+                    crtc.outputs,
+                    crtc.noutput,
+                );
+                if status == RR_SET_CONFIG_SUCCESS && (width, height) != (old_width, old_height) {
+                    XRRSetScreenSize(display, root, width, height, mm_width, mm_height);
+                }
+                XUngrabServer(display);
+                XSync(display, 0);
+                if status != RR_SET_CONFIG_SUCCESS || take_x_error() {
+                    return Err(anyhow::anyhow!(
+                        "X server refused {}x{} on the CRTC: status {}",
+                        mode.width,
+                        mode.height,
+                        status
+                    ));
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Output {
+        fn drop(&mut self) {
+            // SAFETY: each structure is freed once with its matching function
+            unsafe {
+                if !self.crtc.is_null() {
+                    XRRFreeCrtcInfo(self.crtc);
+                }
+                if !self.info.is_null() {
+                    XRRFreeOutputInfo(self.info);
+                }
+                if !self.resources.is_null() {
+                    XRRFreeScreenResources(self.resources);
+                }
+                if !self.display.is_null() {
+                    XCloseDisplay(self.display);
+                }
+            }
+        }
+    }
+
+    pub(super) fn current_mode(display_id: &str) -> Result<DisplayMode> {
+        Output::open(display_id)?
+            .current()
+            .ok_or_else(|| anyhow::anyhow!("Output {} shows no known mode", display_id))
+    }
+
+    pub(super) fn supported_modes(display_id: &str) -> Result<Vec<DisplayMode>> {
+        Ok(Output::open(display_id)?
+            .modes()
+            .into_iter()
+            .map(|(_, mode)| mode)
+            .collect())
+    }
+
+    pub(super) fn apply_mode(display_id: &str, mode: &DisplayMode) -> Result<()> {
+        let output = Output::open(display_id)?;
+        let id = output
+            .modes()
+            .into_iter()
+            .find(|(_, m)| m == mode)
+            .map(|(id, _)| id)
+            .ok_or_else(|| super::no_such_mode(display_id, mode))?;
+        output.set_mode(id, mode)
+    }
+}
+
+/// Original mode of a display changed for a session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RestoreEntry {
+    session_id: String,
+    original: DisplayMode,
+}
+
+/// Changes host display modes on behalf of sessions and restores them
+pub struct DisplayModeManager {
+    backend: Arc<dyn DisplayModeBackend>,
+    journal_path: PathBuf,
+    /// display_id -> original mode
+    changed: Arc<Mutex<HashMap<String, RestoreEntry>>>,
+}
+
+impl DisplayModeManager {
+    /// Create a manager keeping its restore journal in `data_dir`
+    pub fn new(data_dir: PathBuf) -> Self {
+        Self::with_backend(data_dir, Arc::new(PlatformDisplayModeBackend))
+    }
+
+    pub fn with_backend(data_dir: PathBuf, backend: Arc<dyn DisplayModeBackend>) -> Self {
+        Self {
+            backend,
+            journal_path: data_dir.join(DISPLAY_JOURNAL_FILE_NAME),
+            changed: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Restore displays left changed by a previous run that did not exit cleanly
+    ///
+    /// Call once at startup. Returns the number of displays restored.
+    pub async fn recover(&self) -> Result<usize> {
+        let content = match std::fs::read_to_string(&self.journal_path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let pending: HashMap<String, RestoreEntry> = match serde_json::from_str(&content) {
+            Ok(pending) => pending,
+            Err(e) => {
+                tracing::warn!("Discarding unreadable display restore journal: {}", e);
+                std::fs::remove_file(&self.journal_path)?;
+                return Ok(0);
+            }
+        };
+
+        let mut restored = 0;
+        for (display_id, entry) in &pending {
+            match self.backend.apply_mode(display_id, &entry.original) {
+                Ok(()) => restored += 1,
+                Err(e) => tracing::error!("Failed to restore display {}: {}", display_id, e),
+            }
+        }
+        std::fs::remove_file(&self.journal_path)?;
+        tracing::info!("Restored {} displays after unclean shutdown", restored);
+        Ok(restored)
+    }
+
+    /// Switch a display to the supported mode that best fits the requested size
+    ///
+    /// Requires `SystemControl`. The display's original mode is journaled the
+    /// first time it is changed and kept across further changes.
+    pub async fn change_resolution(
+        &self,
+        session_id: &str,
+        display_id: &str,
+        width: u32,
+        height: u32,
+        permissions: &[Permission],
+    ) -> Result<DisplayMode> {
+        if !permissions.contains(&Permission::SystemControl) {
+            return Err(anyhow::anyhow!(
+                "Changing display resolution requires SystemControl permission"
+            ));
+        }
+
+        let mut changed = self.changed.lock().await;
+        if let Some(entry) = changed.get(display_id) {
+            if entry.session_id != session_id {
+                return Err(anyhow::anyhow!(
+                    "Display {} resolution is controlled by another session",
+                    display_id
+                ));
+            }
+        }
+
+        let current = self.backend.current_mode(display_id)?;
+        let mode = best_fit(
+            &self.backend.supported_modes(display_id)?,
+            width,
+            height,
+            current.refresh_rate,
+        )
+        .ok_or_else(|| anyhow::anyhow!("No display modes available for {}", display_id))?;
+
+        if !changed.contains_key(display_id) {
+            changed.insert(
+                display_id.to_string(),
+                RestoreEntry {
+                    session_id: session_id.to_string(),
+                    original: current,
+                },
+            );
+            // Journal before touching the display so a crash can be undone
+            if let Err(e) = self.write_journal(&changed) {
+                changed.remove(display_id);
+                return Err(e);
+            }
+        }
+
+        self.backend.apply_mode(display_id, &mode)?;
+        Ok(mode)
+    }
+
+    /// Restore every display changed by a session
+    pub async fn restore_session(&self, session_id: &str) -> Result<()> {
+        let mut changed = self.changed.lock().await;
+        let displays: Vec<String> = changed
+            .iter()
+            .filter(|(_, entry)| entry.session_id == session_id)
+            .map(|(display_id, _)| display_id.clone())
+            .collect();
+
+        let mut first_error = None;
+        for display_id in displays {
+            let entry = &changed[&display_id];
+            match self.backend.apply_mode(&display_id, &entry.original) {
+                Ok(()) => {
+                    changed.remove(&display_id);
+                }
+                Err(e) => {
+                    tracing::error!("Failed to restore display {}: {}", display_id, e);
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+
+        self.write_journal(&changed)?;
+        first_error.map_or(Ok(()), Err)
+    }
+
+    /// Restore displays when the owning session ends
+    pub async fn handle_session_event(&self, event: &SessionEvent) -> Result<()> {
+        if let SessionEvent::Ended { session_id, .. } = event {
+            self.restore_session(session_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Whether a display is currently changed by any session
+    pub async fn is_changed(&self, display_id: &str) -> bool {
+        self.changed.lock().await.contains_key(display_id)
+    }
+
+    fn write_journal(&self, changed: &HashMap<String, RestoreEntry>) -> Result<()> {
+        if changed.is_empty() {
+            return match std::fs::remove_file(&self.journal_path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+        if let Some(parent) = self.journal_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp_path = self.journal_path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string(changed)?)?;
+        std::fs::rename(&tmp_path, &self.journal_path)?;
+        Ok(())
+    }
+}
+
+/// Largest mode that fits within the requested size, preferring the given
+/// refresh rate; falls back to the smallest mode if none fit
+fn best_fit(
+    modes: &[DisplayMode],
+    width: u32,
+    height: u32,
+    refresh_rate: u32,
+) -> Option<DisplayMode> {
+    let rank = |m: &&DisplayMode| (m.pixels(), m.refresh_rate == refresh_rate, m.refresh_rate);
+    modes
+        .iter()
+        .filter(|m| m.width <= width && m.height <= height)
+        .max_by_key(rank)
+        .or_else(|| modes.iter().min_by_key(|m| m.pixels()))
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_manager::EndReason;
+    use std::sync::Mutex as StdMutex;
+
+    struct MockBackend {
+        current: StdMutex<DisplayMode>,
+    }
+
+    impl DisplayModeBackend for MockBackend {
+        fn current_mode(&self, _display_id: &str) -> Result<DisplayMode> {
+            Ok(*self.current.lock().unwrap())
+        }
+
+        fn supported_modes(&self, _display_id: &str) -> Result<Vec<DisplayMode>> {
+            Ok(vec![mode(3840, 2160), mode(2560, 1440), mode(1920, 1080)])
+        }
+
+        fn apply_mode(&self, _display_id: &str, mode: &DisplayMode) -> Result<()> {
+            *self.current.lock().unwrap() = *mode;
+            Ok(())
+        }
+    }
+
+    fn mode(width: u32, height: u32) -> DisplayMode {
+        DisplayMode {
+            width,
+            height,
+            refresh_rate: 60,
+        }
+    }
+
+    fn setup() -> (PathBuf, Arc<MockBackend>) {
+        let dir = std::env::temp_dir().join(format!("cec-display-{}", uuid::Uuid::new_v4()));
+        let backend = Arc::new(MockBackend {
+            current: StdMutex::new(mode(3840, 2160)),
+        });
+        (dir, backend)
+    }
+
+    #[tokio::test]
+    async fn test_change_and_restore_on_session_end() {
+        let (dir, backend) = setup();
+        let manager = DisplayModeManager::with_backend(dir.clone(), backend.clone());
+
+        assert!(manager
+            .change_resolution("s1", "display_0", 1600, 1000, &[Permission::ScreenView])
+            .await
+            .is_err());
+
+        let applied = manager
+            .change_resolution("s1", "display_0", 2000, 1200, &[Permission::SystemControl])
+            .await
+            .unwrap();
+        assert_eq!(applied, mode(1920, 1080));
+        assert!(dir.join(DISPLAY_JOURNAL_FILE_NAME).exists());
+        assert!(manager
+            .change_resolution("s2", "display_0", 1920, 1080, &[Permission::SystemControl])
+            .await
+            .is_err());
+
+        manager
+            .handle_session_event(&SessionEvent::Ended {
+                session_id: "s1".to_string(),
+                reason: EndReason::NetworkError,
+            })
+            .await
+            .unwrap();
+        assert_eq!(*backend.current.lock().unwrap(), mode(3840, 2160));
+        assert!(!dir.join(DISPLAY_JOURNAL_FILE_NAME).exists());
+    }
+
+    #[tokio::test]
+    async fn test_platform_backend_refuses_unknown_display() {
+        let dir = std::env::temp_dir().join(format!("cec-display-{}", uuid::Uuid::new_v4()));
+        let manager = DisplayModeManager::new(dir.clone());
+        assert!(manager
+            .change_resolution(
+                "s1",
+                "no-such-display",
+                1920,
+                1080,
+                &[Permission::SystemControl]
+            )
+            .await
+            .is_err());
+        assert!(!dir.join(DISPLAY_JOURNAL_FILE_NAME).exists());
+    }
+
+    #[tokio::test]
+    async fn test_recover_after_crash() {
+        let (dir, backend) = setup();
+        {
+            let manager = DisplayModeManager::with_backend(dir.clone(), backend.clone());
+            manager
+                .change_resolution("s1", "display_0", 2560, 1440, &[Permission::SystemControl])
+                .await
+                .unwrap();
+            // Dropped without restoring, as if the process crashed
+        }
+        assert_eq!(*backend.current.lock().unwrap(), mode(2560, 1440));
+
+        let manager = DisplayModeManager::with_backend(dir.clone(), backend.clone());
+        assert_eq!(manager.recover().await.unwrap(), 1);
+        assert_eq!(*backend.current.lock().unwrap(), mode(3840, 2160));
+        assert_eq!(manager.recover().await.unwrap(), 0);
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,271 @@
+//! Session Encryption Status
+//!
+//! What both UIs show as "end-to-end encrypted with AES-256-GCM, key
+//! rotated 12 minutes ago": the session key's cipher and age, the DTLS
+//! cipher suite negotiated for the media transport and whether the peer's
+//! DTLS fingerprint matched the one it was paired with.
+//!
+//! Each side builds its own `EncryptionStatus` and sends it to the peer
+//! with `SignalingClient::send_encryption_status` whenever it changes, so
+//! either UI can show both views and flag a disagreement. Changes on either
+//! side are published as `EncryptionStatusEvent`s.
+
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use crate::security::{EncryptionAlgorithm, SessionKey};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+/// Whether the peer's DTLS certificate is the one it was paired with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FingerprintVerification {
+    /// No handshake yet, or no known fingerprint to compare with
+    #[default]
+    Unverified,
+    Verified,
+    /// The handshake presented a different certificate
+    Mismatch,
+}
+
+impl FingerprintVerification {
+    /// Compare the fingerprint seen in the handshake with the expected one
+    ///
+    /// Fingerprints are compared case-insensitively, ignoring `:` separators.
+    pub fn check(presented: &str, expected: Option<&str>) -> Self {
+        let normalize = |f: &str| {
+            f.chars()
+                .filter(|c| *c != ':')
+                .map(|c| c.to_ascii_lowercase())
+                .collect::<String>()
+        };
+        match expected {
+            None => FingerprintVerification::Unverified,
+            Some(expected) if normalize(expected) == normalize(presented) => {
+                FingerprintVerification::Verified
+            }
+            Some(_) => FingerprintVerification::Mismatch,
+        }
+    }
+}
+
+/// One side's view of a session's encryption
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EncryptionStatus {
+    pub session_id: String,
+    /// Cipher of the session key; `None` until a key exists
+    pub algorithm: Option<EncryptionAlgorithm>,
+    pub key_rotated_at: Option<DateTime<Utc>>,
+    pub rotation_count: u32,
+    /// Negotiated DTLS cipher suite, once the handshake completed
+    pub dtls_cipher_suite: Option<String>,
+    pub srtp_profile: String,
+    pub fingerprint: FingerprintVerification,
+}
+
+impl EncryptionStatus {
+    /// Seconds since the session key was created or last rotated
+    pub fn key_age_secs(&self, now: DateTime<Utc>) -> Option<u64> {
+        self.key_rotated_at
+            .map(|at| (now - at).num_seconds().max(0) as u64)
+    }
+
+    /// Keyed, DTLS-protected and talking to the paired peer
+    pub fn is_end_to_end(&self) -> bool {
+        self.algorithm.is_some()
+            && self.dtls_cipher_suite.is_some()
+            && self.fingerprint == FingerprintVerification::Verified
+    }
+
+    /// Whether both sides agree on the ciphers in use
+    pub fn agrees_with(&self, peer: &EncryptionStatus) -> bool {
+        self.algorithm == peer.algorithm && self.dtls_cipher_suite == peer.dtls_cipher_suite
+    }
+}
+
+/// Both views of a session's encryption
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionEncryption {
+    pub local: EncryptionStatus,
+    /// Last status the peer reported, if any
+    pub peer: Option<EncryptionStatus>,
+    pub key_age_secs: Option<u64>,
+    pub end_to_end: bool,
+    /// False while the peer reports different ciphers
+    pub consistent: bool,
+}
+
+/// Encryption status changes
+#[derive(Debug, Clone, PartialEq)]
+pub enum EncryptionStatusEvent {
+    /// This side's status changed; send it to the peer
+    LocalChanged(EncryptionStatus),
+    /// The peer reported a new status
+    PeerChanged {
+        status: EncryptionStatus,
+        consistent: bool,
+    },
+}
+
+impl EventType for EncryptionStatusEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            EncryptionStatusEvent::LocalChanged(_) => "LocalChanged",
+            EncryptionStatusEvent::PeerChanged { .. } => "PeerChanged",
+        }
+    }
+}
+
+/// Transport details learned from the DTLS handshake
+#[derive(Debug, Clone, Default)]
+struct TransportSecurity {
+    cipher_suite: Option<String>,
+    fingerprint: FingerprintVerification,
+}
+
+/// Per-session encryption status, kept by `SecurityManager`
+#[derive(Default)]
+pub struct EncryptionStatusTracker {
+    transport: RwLock<HashMap<String, TransportSecurity>>,
+    local: RwLock<HashMap<String, EncryptionStatus>>,
+    peers: RwLock<HashMap<String, EncryptionStatus>>,
+    events: EventBus<EncryptionStatusEvent>,
+}
+
+impl EncryptionStatusTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<EncryptionStatusEvent> {
+        self.events.subscribe(options)
+    }
+
+    /// Record the outcome of the session's DTLS handshake
+    pub fn set_transport(
+        &self,
+        session_id: &str,
+        cipher_suite: &str,
+        fingerprint: FingerprintVerification,
+    ) {
+        if let Ok(mut transport) = self.transport.write() {
+            transport.insert(
+                session_id.to_string(),
+                TransportSecurity {
+                    cipher_suite: Some(cipher_suite.to_string()),
+                    fingerprint,
+                },
+            );
+        }
+    }
+
+    /// Rebuild the local status from the session key and publish it if it
+    /// changed
+    pub fn refresh(&self, session_id: &str, key: Option<&SessionKey>, srtp_profile: &str) {
+        let transport = self
+            .transport
+            .read()
+            .ok()
+            .and_then(|t| t.get(session_id).cloned())
+            .unwrap_or_default();
+        let status = EncryptionStatus {
+            session_id: session_id.to_string(),
+            algorithm: key.map(|k| k.algorithm),
+            key_rotated_at: key.map(|k| k.last_rotated_at.wall_clock()),
+            rotation_count: key.map(|k| k.rotation_count).unwrap_or(0),
+            dtls_cipher_suite: transport.cipher_suite,
+            srtp_profile: srtp_profile.to_string(),
+            fingerprint: transport.fingerprint,
+        };
+
+        let Ok(mut local) = self.local.write() else {
+            return;
+        };
+        if local.get(session_id) == Some(&status) {
+            return;
+        }
+        local.insert(session_id.to_string(), status.clone());
+        drop(local);
+        self.events
+            .publish(EncryptionStatusEvent::LocalChanged(status));
+    }
+
+    /// Store the status the peer reported for one of our sessions
+    ///
+    /// Returns whether it agrees with the local view. Reports for unknown
+    /// sessions are ignored.
+    pub fn apply_peer(&self, status: EncryptionStatus) -> Option<bool> {
+        let local = self.local.read().ok()?.get(&status.session_id).cloned()?;
+        let consistent = local.agrees_with(&status);
+        if !consistent {
+            tracing::warn!(
+                "Peer reports different encryption for session {}: {:?}/{:?} vs {:?}/{:?}",
+                status.session_id,
+                status.algorithm,
+                status.dtls_cipher_suite,
+                local.algorithm,
+                local.dtls_cipher_suite
+            );
+        }
+        let mut peers = self.peers.write().ok()?;
+        if peers.get(&status.session_id) == Some(&status) {
+            return Some(consistent);
+        }
+        peers.insert(status.session_id.clone(), status.clone());
+        drop(peers);
+        self.events
+            .publish(EncryptionStatusEvent::PeerChanged { status, consistent });
+        Some(consistent)
+    }
+
+    /// Both views of a session, as of `now`
+    pub fn session(&self, session_id: &str, now: DateTime<Utc>) -> Option<SessionEncryption> {
+        let local = self.local.read().ok()?.get(session_id).cloned()?;
+        let peer = self
+            .peers
+            .read()
+            .ok()
+            .and_then(|p| p.get(session_id).cloned());
+        Some(SessionEncryption {
+            key_age_secs: local.key_age_secs(now),
+            end_to_end: local.is_end_to_end(),
+            consistent: peer.as_ref().is_none_or(|p| local.agrees_with(p)),
+            local,
+            peer,
+        })
+    }
+
+    /// Forget an ended session
+    pub fn remove(&self, session_id: &str) {
+        if let Ok(mut transport) = self.transport.write() {
+            transport.remove(session_id);
+        }
+        if let Ok(mut local) = self.local.write() {
+            local.remove(session_id);
+        }
+        if let Ok(mut peers) = self.peers.write() {
+            peers.remove(session_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprint_check() {
+        assert_eq!(
+            FingerprintVerification::check("AB:CD:EF", Some("abcdef")),
+            FingerprintVerification::Verified
+        );
+        assert_eq!(
+            FingerprintVerification::check("AB:CD:EF", Some("AB:CD:00")),
+            FingerprintVerification::Mismatch
+        );
+        assert_eq!(
+            FingerprintVerification::check("AB:CD:EF", None),
+            FingerprintVerification::Unverified
+        );
+    }
+}
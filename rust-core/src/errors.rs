@@ -0,0 +1,118 @@
+//! Typed Manager Errors
+//!
+//! `SessionManager`, `SecurityManager` and `AccessControlManager` return
+//! `anyhow::Result`, but failures a UI has to react to differently are
+//! raised as `ManagerError` inside it: a session that ended a moment ago is
+//! a race to ignore, one in the wrong state needs a refresh, and a denied
+//! permission needs a prompt. Recover the variant with `ManagerError::of`.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What an operation was looking for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ResourceKind {
+    Session,
+    SessionKey,
+    Device,
+    ConnectionRequest,
+    PermissionRequest,
+    Recording,
+}
+
+impl fmt::Display for ResourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ResourceKind::Session => "Session",
+            ResourceKind::SessionKey => "Session key",
+            ResourceKind::Device => "Device",
+            ResourceKind::ConnectionRequest => "Connection request",
+            ResourceKind::PermissionRequest => "Permission request",
+            ResourceKind::Recording => "Recording",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum ManagerError {
+    #[error("{kind} not found: {id}")]
+    NotFound { kind: ResourceKind, id: String },
+    /// `state` is the current status variant name, e.g. `"Paused"`
+    #[error("{kind} {id} is {state}, expected {expected}")]
+    InvalidState {
+        kind: ResourceKind,
+        id: String,
+        state: String,
+        expected: String,
+    },
+    #[error("Permission denied for {kind} {id}: {reason}")]
+    PermissionDenied {
+        kind: ResourceKind,
+        id: String,
+        reason: String,
+    },
+}
+
+impl ManagerError {
+    pub fn not_found(kind: ResourceKind, id: impl Into<String>) -> Self {
+        ManagerError::NotFound {
+            kind,
+            id: id.into(),
+        }
+    }
+
+    pub fn invalid_state(
+        kind: ResourceKind,
+        id: impl Into<String>,
+        state: impl Into<String>,
+        expected: impl Into<String>,
+    ) -> Self {
+        ManagerError::InvalidState {
+            kind,
+            id: id.into(),
+            state: state.into(),
+            expected: expected.into(),
+        }
+    }
+
+    pub fn permission_denied(
+        kind: ResourceKind,
+        id: impl Into<String>,
+        reason: impl Into<String>,
+    ) -> Self {
+        ManagerError::PermissionDenied {
+            kind,
+            id: id.into(),
+            reason: reason.into(),
+        }
+    }
+
+    /// The typed error behind an `anyhow::Error`, if there is one
+    pub fn of(err: &anyhow::Error) -> Option<&ManagerError> {
+        err.downcast_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recovered_from_anyhow() {
+        use crate::session_manager::SessionStatus;
+
+        let err: anyhow::Error = ManagerError::invalid_state(
+            ResourceKind::Session,
+            "s1",
+            format!("{:?}", SessionStatus::Paused),
+            "Active",
+        )
+        .into();
+        assert_eq!(err.to_string(), "Session s1 is Paused, expected Active");
+        assert!(matches!(
+            ManagerError::of(&err),
+            Some(ManagerError::InvalidState { state, .. }) if state == "Paused"
+        ));
+        assert!(ManagerError::of(&anyhow::anyhow!("plain")).is_none());
+    }
+}
@@ -0,0 +1,312 @@
+//! Event Subscriptions
+//!
+//! Fan-out of component events (network, signaling, session) to any number
+//! of independent subscribers. Each subscriber chooses which event types it
+//! wants and gets its own bounded queue, so a slow or abandoned consumer
+//! never blocks the publisher or other subscribers; when its queue is full,
+//! events are dropped according to its `DropPolicy` and counted.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::Notify;
+
+/// Queue length used when none is specified
+pub const DEFAULT_SUBSCRIPTION_CAPACITY: usize = 256;
+
+/// Event that can be filtered by type
+pub trait EventType {
+    /// Variant name, e.g. `"QualityChanged"`
+    fn event_type(&self) -> &'static str;
+}
+
+/// What to do when a subscriber's queue is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DropPolicy {
+    /// Discard the oldest queued event to make room (latest state wins)
+    #[default]
+    DropOldest,
+    /// Discard the incoming event (earliest events are kept)
+    DropNewest,
+}
+
+/// Which events a subscriber receives and how they are queued
+#[derive(Debug, Clone)]
+pub struct SubscriptionOptions {
+    /// Event types to deliver; `None` delivers everything
+    pub event_types: Option<Vec<&'static str>>,
+    pub capacity: usize,
+    pub drop_policy: DropPolicy,
+}
+
+impl SubscriptionOptions {
+    /// Every event, with the default queue
+    pub fn all() -> Self {
+        Self::default()
+    }
+
+    /// Only the given event types
+    pub fn only(event_types: &[&'static str]) -> Self {
+        Self {
+            event_types: Some(event_types.to_vec()),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn with_drop_policy(mut self, drop_policy: DropPolicy) -> Self {
+        self.drop_policy = drop_policy;
+        self
+    }
+}
+
+impl Default for SubscriptionOptions {
+    fn default() -> Self {
+        Self {
+            event_types: None,
+            capacity: DEFAULT_SUBSCRIPTION_CAPACITY,
+            drop_policy: DropPolicy::default(),
+        }
+    }
+}
+
+struct QueueState<E> {
+    events: VecDeque<E>,
+    dropped: u64,
+    closed: bool,
+}
+
+struct SubscriberQueue<E> {
+    options: SubscriptionOptions,
+    state: Mutex<QueueState<E>>,
+    notify: Notify,
+}
+
+impl<E> SubscriberQueue<E> {
+    fn wants(&self, event_type: &str) -> bool {
+        self.options
+            .event_types
+            .as_ref()
+            .is_none_or(|types| types.contains(&event_type))
+    }
+
+    /// Queue an event; returns false if it was dropped
+    fn push(&self, event: E) -> bool {
+        let Ok(mut state) = self.state.lock() else {
+            return false;
+        };
+        let delivered = if state.events.len() < self.options.capacity {
+            state.events.push_back(event);
+            true
+        } else {
+            state.dropped += 1;
+            match self.options.drop_policy {
+                DropPolicy::DropOldest => {
+                    state.events.pop_front();
+                    state.events.push_back(event);
+                    true
+                }
+                DropPolicy::DropNewest => false,
+            }
+        };
+        drop(state);
+        self.notify.notify_one();
+        delivered
+    }
+
+    fn close(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.closed = true;
+        }
+        self.notify.notify_one();
+    }
+}
+
+struct BusInner<E> {
+    subscribers: Mutex<Vec<Weak<SubscriberQueue<E>>>>,
+}
+
+impl<E> Drop for BusInner<E> {
+    fn drop(&mut self) {
+        if let Ok(subscribers) = self.subscribers.lock() {
+            for queue in subscribers.iter().filter_map(Weak::upgrade) {
+                queue.close();
+            }
+        }
+    }
+}
+
+/// Publisher side, cheap to clone into tasks
+pub struct EventBus<E> {
+    inner: Arc<BusInner<E>>,
+}
+
+impl<E> Clone for EventBus<E> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Arc::clone(&self.inner),
+        }
+    }
+}
+
+impl<E: EventType + Clone> EventBus<E> {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(BusInner {
+                subscribers: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Add a subscriber; it sees events published from now on
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<E> {
+        let queue = Arc::new(SubscriberQueue {
+            options,
+            state: Mutex::new(QueueState {
+                events: VecDeque::new(),
+                dropped: 0,
+                closed: false,
+            }),
+            notify: Notify::new(),
+        });
+        if let Ok(mut subscribers) = self.inner.subscribers.lock() {
+            subscribers.push(Arc::downgrade(&queue));
+        }
+        Subscription { queue }
+    }
+
+    /// Deliver an event to every interested subscriber
+    ///
+    /// Returns the number of subscribers that queued it.
+    pub fn publish(&self, event: E) -> usize {
+        let event_type = event.event_type();
+        let queues: Vec<_> = match self.inner.subscribers.lock() {
+            Ok(mut subscribers) => {
+                subscribers.retain(|queue| queue.strong_count() > 0);
+                subscribers.iter().filter_map(Weak::upgrade).collect()
+            }
+            Err(_) => return 0,
+        };
+
+        queues
+            .into_iter()
+            .filter(|queue| queue.wants(event_type))
+            .filter(|queue| queue.push(event.clone()))
+            .count()
+    }
+
+    /// Number of live subscriptions
+    pub fn subscriber_count(&self) -> usize {
+        self.inner
+            .subscribers
+            .lock()
+            .map(|subscribers| subscribers.iter().filter(|q| q.strong_count() > 0).count())
+            .unwrap_or(0)
+    }
+}
+
+impl<E: EventType + Clone> Default for EventBus<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Receiving side of one subscriber; unsubscribes when dropped
+pub struct Subscription<E> {
+    queue: Arc<SubscriberQueue<E>>,
+}
+
+impl<E> Subscription<E> {
+    /// Wait for the next event; `None` once the publisher is gone and the
+    /// queue is drained
+    pub async fn recv(&mut self) -> Option<E> {
+        loop {
+            {
+                let mut state = self.queue.state.lock().ok()?;
+                if let Some(event) = state.events.pop_front() {
+                    return Some(event);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.queue.notify.notified().await;
+        }
+    }
+
+    /// Next queued event, without waiting
+    pub fn try_recv(&mut self) -> Option<E> {
+        self.queue.state.lock().ok()?.events.pop_front()
+    }
+
+    /// Events dropped because this subscriber's queue was full
+    pub fn dropped_count(&self) -> u64 {
+        self.queue.state.lock().map(|s| s.dropped).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq)]
+    enum TestEvent {
+        Tick(u32),
+        Alert,
+    }
+
+    impl EventType for TestEvent {
+        fn event_type(&self) -> &'static str {
+            match self {
+                TestEvent::Tick(_) => "Tick",
+                TestEvent::Alert => "Alert",
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multiple_filtered_subscribers() {
+        let bus = EventBus::new();
+        let mut everything = bus.subscribe(SubscriptionOptions::all());
+        let mut alerts = bus.subscribe(SubscriptionOptions::only(&["Alert"]));
+        let abandoned = bus.subscribe(SubscriptionOptions::all());
+        drop(abandoned);
+
+        assert_eq!(bus.publish(TestEvent::Tick(1)), 1);
+        assert_eq!(bus.publish(TestEvent::Alert), 2);
+        assert_eq!(bus.subscriber_count(), 2);
+
+        assert_eq!(everything.recv().await, Some(TestEvent::Tick(1)));
+        assert_eq!(everything.recv().await, Some(TestEvent::Alert));
+        assert_eq!(alerts.recv().await, Some(TestEvent::Alert));
+        assert!(alerts.try_recv().is_none());
+
+        drop(bus);
+        assert_eq!(everything.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_drop_policies() {
+        let bus = EventBus::new();
+        let mut latest = bus.subscribe(SubscriptionOptions::all().with_capacity(2));
+        let mut earliest = bus.subscribe(
+            SubscriptionOptions::all()
+                .with_capacity(2)
+                .with_drop_policy(DropPolicy::DropNewest),
+        );
+
+        for i in 0..5 {
+            bus.publish(TestEvent::Tick(i));
+        }
+
+        assert_eq!(latest.dropped_count(), 3);
+        assert_eq!(latest.try_recv(), Some(TestEvent::Tick(3)));
+        assert_eq!(latest.try_recv(), Some(TestEvent::Tick(4)));
+        assert_eq!(earliest.dropped_count(), 3);
+        assert_eq!(earliest.try_recv(), Some(TestEvent::Tick(0)));
+        assert_eq!(earliest.try_recv(), Some(TestEvent::Tick(1)));
+    }
+}
@@ -218,6 +218,12 @@ pub unsafe extern "C" fn signaling_client_register_device(
             audio_capture: true,
             file_transfer: true,
             input_control: true,
+            decoder: Some(crate::decoder_capabilities::DecoderCapabilities::detect()),
+            keyboard_layout: Some(
+                crate::input_control::InputController::new().detect_keyboard_layout(),
+            ),
+            app_sharing: cfg!(feature = "capture"),
+            data_compression: crate::data_compression::CompressionAlgorithm::supported(),
         },
     };
 
@@ -236,6 +242,103 @@ pub unsafe extern "C" fn signaling_client_register_device(
     }
 }
 
+// Connection failure classification
+
+/// Classify a failed connection for display
+///
+/// `context_json` is a serialized `FailureContext`; the result is a
+/// serialized `ConnectionFailure` to be released with `free_string`.
+///
+/// # Safety
+/// - `context_json` must be a valid null-terminated C string
+/// - `failure_json_out` must be a valid pointer to a mutable `*mut c_char`
+#[no_mangle]
+pub unsafe extern "C" fn connection_failure_classify(
+    context_json: *const c_char,
+    failure_json_out: *mut *mut c_char,
+) -> c_int {
+    if context_json.is_null() || failure_json_out.is_null() {
+        return FFI_ERROR_INVALID_PARAM;
+    }
+
+    let context: FailureContext = match CStr::from_ptr(context_json)
+        .to_str()
+        .ok()
+        .and_then(|s| serde_json::from_str(s).ok())
+    {
+        Some(context) => context,
+        None => return FFI_ERROR_INVALID_PARAM,
+    };
+
+    let failure = classify_connection_failure(&context);
+    match serde_json::to_string(&failure)
+        .ok()
+        .and_then(|json| CString::new(json).ok())
+    {
+        Some(c_string) => {
+            *failure_json_out = c_string.into_raw();
+            FFI_SUCCESS
+        }
+        None => FFI_ERROR_UNKNOWN,
+    }
+}
+
+// Diagnostics report
+
+/// Build a structured report from diagnostics results
+///
+/// `network_json` is a serialized `NetworkDiagnostics` and `system_json` a
+/// serialized `SystemDiagnostics`; either may be null if that part was not
+/// run. The result is a serialized `DiagnosticsReport` to be released with
+/// `free_string`.
+///
+/// # Safety
+/// - `network_json` and `system_json` must be null or valid null-terminated C strings
+/// - `report_json_out` must be a valid pointer to a mutable `*mut c_char`
+#[cfg(feature = "diagnostics")]
+#[no_mangle]
+pub unsafe extern "C" fn diagnostics_report_build(
+    network_json: *const c_char,
+    system_json: *const c_char,
+    report_json_out: *mut *mut c_char,
+) -> c_int {
+    if report_json_out.is_null() {
+        return FFI_ERROR_INVALID_PARAM;
+    }
+
+    fn parse<T: serde::de::DeserializeOwned>(json: *const c_char) -> Result<Option<T>, ()> {
+        if json.is_null() {
+            return Ok(None);
+        }
+        // SAFETY: non-null pointers are valid C strings per the caller contract
+        unsafe { CStr::from_ptr(json) }
+            .to_str()
+            .ok()
+            .and_then(|s| serde_json::from_str(s).ok())
+            .map(Some)
+            .ok_or(())
+    }
+    let (Ok(network), Ok(system)) = (
+        parse::<NetworkDiagnostics>(network_json),
+        parse::<SystemDiagnostics>(system_json),
+    ) else {
+        return FFI_ERROR_INVALID_PARAM;
+    };
+
+    let report = DiagnosticsReport::new(network.as_ref(), system.as_ref());
+    match report
+        .to_json()
+        .ok()
+        .and_then(|json| CString::new(json).ok())
+    {
+        Some(c_string) => {
+            *report_json_out = c_string.into_raw();
+            FFI_SUCCESS
+        }
+        None => FFI_ERROR_UNKNOWN,
+    }
+}
+
 // Memory management for returned strings
 
 /// # Safety
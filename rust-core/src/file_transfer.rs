@@ -1,9 +1,25 @@
+use crate::access_control::{AccessControlManager, Permission, TransferLimits};
+use crate::metrics::{Counter, MetricsRegistry};
+use crate::quarantine::{FileQuarantine, QuarantineDecision};
+use crate::transfer_state::{
+    TransferChecksums, TransferDirection, TransferManifest, TransferStateStore,
+    PARTIAL_TRANSFER_MAX_AGE,
+};
 use anyhow::Result;
+use chrono::NaiveDate;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 use uuid::Uuid;
 
+/// Persist a manifest after this many new chunks; an interrupted transfer
+/// re-sends at most this many chunks
+const MANIFEST_FLUSH_CHUNKS: u64 = 16;
+
+/// Verification failures tolerated before an incoming transfer is given up
+const MAX_VERIFY_RETRIES: u32 = 3;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransferProgress {
     pub transfer_id: String,
@@ -32,36 +48,488 @@ pub struct TransferResult {
     pub error_message: Option<String>,
     pub final_size: u64,
     pub duration: u64, // seconds
+    /// Where the received file ended up, once released
+    #[serde(default)]
+    pub saved_path: Option<PathBuf>,
+    /// The file matched the sender's SHA-256
+    #[serde(default)]
+    pub verified: bool,
+    /// Chunks that failed verification and were received again
+    #[serde(default)]
+    pub retried_chunks: u64,
+}
+
+/// A completed incoming file did not match the sender's checksums
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TransferIntegrityError {
+    /// The chunks are marked missing again and must be requested from the
+    /// sender before calling `receive_file` once more
+    #[error("Transfer {transfer_id} failed verification, {} chunks must be re-requested", chunks.len())]
+    CorruptChunks {
+        transfer_id: String,
+        chunks: Vec<u64>,
+    },
+}
+
+/// File transfer limit that would be exceeded
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TransferLimitError {
+    #[error("Session file limit of {limit} files reached")]
+    TooManyFiles { limit: u32 },
+    #[error("File size {size} bytes exceeds the limit of {limit} bytes")]
+    FileTooLarge { size: u64, limit: u64 },
+    #[error("Session transfer limit of {limit} bytes exceeded ({used} bytes already used)")]
+    SessionBytesExceeded { limit: u64, used: u64 },
+    #[error("Daily transfer limit of {limit} bytes exceeded ({used} bytes already used today)")]
+    DailyBytesExceeded { limit: u64, used: u64 },
+}
+
+/// Files and bytes transferred in a session
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferCounters {
+    pub files: u32,
+    pub bytes: u64,
+}
+
+/// A file offered by a peer, waiting for the user to accept or reject it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IncomingFileOffer {
+    pub transfer_id: String,
+    pub session_id: String,
+    pub peer_id: String,
+    /// Name the file is saved under, reduced to a safe single component
+    pub filename: String,
+    pub size: u64,
+    /// Guessed from the name, for the file icon in the prompt
+    pub mime_type: String,
+}
+
+/// Outcome of `FileTransfer::offer_incoming`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IncomingFileDecision {
+    /// The peer's files are auto-accepted; receiving into `save_path`
+    Accepted { save_path: PathBuf },
+    /// Waiting for `accept_incoming` or `reject_incoming`
+    PendingApproval,
+}
+
+/// A file or folder inside a folder transfer
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FolderEntry {
+    /// Path below the transferred folder, `/`-separated on every platform
+    pub relative_path: String,
+    pub is_dir: bool,
+    /// Zero for folders
+    pub size: u64,
+    /// Unix permission bits, when the sender has them
+    pub mode: Option<u32>,
+}
+
+/// What the sender announces for a folder, one transfer per file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderOffer {
+    pub transfer_id: String,
+    /// Name of the folder itself
+    pub root_name: String,
+    /// Folders come before their contents
+    pub entries: Vec<FolderEntry>,
+    /// Per-file transfer IDs, in the order of the file entries
+    pub file_transfer_ids: Vec<String>,
+    /// Files streamed at once, each over its own channel
+    pub channels: usize,
+    /// Sender's checksums, in the order of `file_transfer_ids`
+    #[serde(default)]
+    pub checksums: Vec<TransferChecksums>,
+}
+
+/// Progress of a folder transfer: every file plus the totals
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FolderProgress {
+    pub transfer_id: String,
+    pub root_name: String,
+    pub files: Vec<TransferProgress>,
+    pub total_files: usize,
+    pub completed_files: usize,
+    pub total_size: u64,
+    pub transferred_size: u64,
+    pub status: TransferStatus,
+}
+
+struct FolderTransfer {
+    offer: FolderOffer,
+    /// Progress of files no longer in `active_transfers`
+    finished: HashMap<String, TransferProgress>,
+    /// Where an incoming folder is written
+    save_dir: Option<PathBuf>,
+}
+
+struct SessionQuota {
+    device_id: String,
+    limits: TransferLimits,
+    counters: TransferCounters,
 }
 
 pub struct FileTransfer {
     active_transfers: HashMap<String, TransferProgress>,
     max_file_size: u64, // 4GB as per requirement 8.3
+    quarantine: Option<Arc<FileQuarantine>>,
+    session_quotas: HashMap<String, SessionQuota>,
+    /// Bytes per device for the current day
+    daily_usage: HashMap<String, (NaiveDate, u64)>,
+    metrics_registry: Arc<MetricsRegistry>,
+    counters: TransferMetrics,
+    state_store: Option<TransferStateStore>,
+    /// transfer_id -> chunk state of transfers in `active_transfers`
+    manifests: HashMap<String, TransferManifest>,
+    /// Folder transfer_id -> its file transfers
+    folders: HashMap<String, FolderTransfer>,
+    access_control: Option<Arc<AccessControlManager>>,
+    /// Where accepted files go when no folder is chosen
+    download_dir: Option<PathBuf>,
+    /// transfer_id -> offers waiting for the user
+    pending_incoming: HashMap<String, IncomingFileOffer>,
+    /// transfer_id -> empty file holding an accepted file's save path until
+    /// its data arrives
+    reserved_paths: HashMap<String, PathBuf>,
+}
+
+/// Registry counters for transfer activity across all sessions
+struct TransferMetrics {
+    files_sent: Arc<Counter>,
+    bytes_sent: Arc<Counter>,
+    files_received: Arc<Counter>,
+    limit_rejections: Arc<Counter>,
+}
+
+impl TransferMetrics {
+    fn new(registry: &MetricsRegistry) -> Self {
+        Self {
+            files_sent: registry.counter("transfer.files_sent"),
+            bytes_sent: registry.counter("transfer.bytes_sent"),
+            files_received: registry.counter("transfer.files_received"),
+            limit_rejections: registry.counter("transfer.limit_rejections"),
+        }
+    }
 }
 
 impl FileTransfer {
     pub fn new() -> Self {
+        let metrics_registry = Arc::new(MetricsRegistry::new());
         Self {
             active_transfers: HashMap::new(),
             max_file_size: 4 * 1024 * 1024 * 1024, // 4GB
+            quarantine: None,
+            session_quotas: HashMap::new(),
+            daily_usage: HashMap::new(),
+            counters: TransferMetrics::new(&metrics_registry),
+            metrics_registry,
+            state_store: None,
+            manifests: HashMap::new(),
+            folders: HashMap::new(),
+            access_control: None,
+            download_dir: None,
+            pending_incoming: HashMap::new(),
+            reserved_paths: HashMap::new(),
         }
     }
 
-    pub async fn send_file(&mut self, file_path: PathBuf, target_id: String) -> Result<String> {
-        let file_metadata = tokio::fs::metadata(&file_path).await?;
-        let file_size = file_metadata.len();
+    /// Persist transfer manifests so transfers survive an app restart
+    pub fn set_state_store(&mut self, store: TransferStateStore) {
+        self.state_store = Some(store);
+    }
 
-        if file_size > self.max_file_size {
-            return Err(anyhow::anyhow!("File size exceeds maximum limit of 4GB"));
+    /// Report transfer counters into a shared registry
+    pub fn set_metrics_registry(&mut self, registry: Arc<MetricsRegistry>) {
+        self.counters = TransferMetrics::new(&registry);
+        self.metrics_registry = registry;
+    }
+
+    /// Registry holding the transfer counters
+    pub fn metrics_registry(&self) -> Arc<MetricsRegistry> {
+        Arc::clone(&self.metrics_registry)
+    }
+
+    /// Enforce `limits` on transfers in a session with `device_id`
+    pub fn set_session_limits(
+        &mut self,
+        session_id: &str,
+        device_id: &str,
+        limits: TransferLimits,
+    ) {
+        let counters = self
+            .session_quotas
+            .remove(session_id)
+            .map(|quota| quota.counters)
+            .unwrap_or_default();
+        self.session_quotas.insert(
+            session_id.to_string(),
+            SessionQuota {
+                device_id: device_id.to_string(),
+                limits,
+                counters,
+            },
+        );
+    }
+
+    /// Forget a session's limits, returning its final counters
+    pub fn end_session(&mut self, session_id: &str) -> Option<TransferCounters> {
+        self.session_quotas
+            .remove(session_id)
+            .map(|quota| quota.counters)
+    }
+
+    pub fn session_counters(&self, session_id: &str) -> Option<TransferCounters> {
+        self.session_quotas
+            .get(session_id)
+            .map(|quota| quota.counters)
+    }
+
+    /// Check a file of `size` bytes against the session's limits and count it
+    ///
+    /// Called for outgoing files by `send_session_file`, and by the receiving
+    /// side when a remote device offers a file. Nothing is counted if a limit
+    /// would be exceeded.
+    pub fn admit_file(&mut self, session_id: &str, size: u64) -> Result<(), TransferLimitError> {
+        let result = self.check_and_count(session_id, size, true);
+        if result.is_err() {
+            self.counters.limit_rejections.increment();
         }
+        result
+    }
 
-        let transfer_id = Uuid::new_v4().to_string();
+    fn check_and_count(
+        &mut self,
+        session_id: &str,
+        size: u64,
+        count: bool,
+    ) -> Result<(), TransferLimitError> {
+        if size > self.max_file_size {
+            return Err(TransferLimitError::FileTooLarge {
+                size,
+                limit: self.max_file_size,
+            });
+        }
+        let Some(quota) = self.session_quotas.get_mut(session_id) else {
+            return Ok(());
+        };
+        let limits = &quota.limits;
+
+        if let Some(limit) = limits.max_file_size.filter(|limit| size > *limit) {
+            return Err(TransferLimitError::FileTooLarge { size, limit });
+        }
+        if let Some(limit) = limits
+            .max_files_per_session
+            .filter(|limit| quota.counters.files >= *limit)
+        {
+            return Err(TransferLimitError::TooManyFiles { limit });
+        }
+        let used = quota.counters.bytes;
+        if let Some(limit) = limits
+            .max_bytes_per_session
+            .filter(|limit| used.saturating_add(size) > *limit)
+        {
+            return Err(TransferLimitError::SessionBytesExceeded { limit, used });
+        }
+
+        let today = chrono::Local::now().date_naive();
+        let daily = self
+            .daily_usage
+            .entry(quota.device_id.clone())
+            .or_insert((today, 0));
+        if daily.0 != today {
+            *daily = (today, 0);
+        }
+        if let Some(limit) = limits
+            .max_bytes_per_day
+            .filter(|limit| daily.1.saturating_add(size) > *limit)
+        {
+            return Err(TransferLimitError::DailyBytesExceeded {
+                limit,
+                used: daily.1,
+            });
+        }
+
+        if count {
+            daily.1 += size;
+            quota.counters.files += 1;
+            quota.counters.bytes += size;
+        }
+        Ok(())
+    }
+
+    /// Send a file within a session, subject to its transfer limits
+    pub async fn send_session_file(
+        &mut self,
+        session_id: &str,
+        file_path: PathBuf,
+        target_id: String,
+    ) -> Result<String> {
+        let size = tokio::fs::metadata(&file_path).await?.len();
+        self.admit_file(session_id, size)?;
+        self.send_file(file_path, target_id).await
+    }
+
+    /// Require a device's file transfer permission for incoming files and
+    /// honor its auto-accept setting
+    pub fn set_access_control(&mut self, access_control: Arc<AccessControlManager>) {
+        self.access_control = Some(access_control);
+    }
+
+    /// Folder accepted files are saved to unless the user picks another
+    pub fn set_download_dir(&mut self, dir: PathBuf) {
+        self.download_dir = Some(dir);
+    }
+
+    /// Handle a file `peer_id` offers to send within a session
+    ///
+    /// Offers from devices without the file transfer permission, or that
+    /// would exceed the session's limits, are refused outright. Devices set
+    /// to auto-accept start receiving into the download folder; other offers
+    /// wait in `pending_incoming` for the user.
+    pub async fn offer_incoming(
+        &mut self,
+        session_id: &str,
+        peer_id: &str,
+        transfer_id: &str,
+        filename: &str,
+        size: u64,
+    ) -> Result<IncomingFileDecision> {
+        let mut auto_accept = false;
+        if let Some(access_control) = &self.access_control {
+            let permissions = access_control
+                .get_device_permissions(peer_id)
+                .await
+                .unwrap_or_default();
+            if !permissions
+                .iter()
+                .any(|p| matches!(p, Permission::FileTransfer | Permission::FullControl))
+            {
+                return Err(anyhow::anyhow!(
+                    "Device {} is not allowed to send files",
+                    peer_id
+                ));
+            }
+            auto_accept = access_control.auto_accepts_files(peer_id).await;
+        }
+        if let Err(e) = self.check_and_count(session_id, size, false) {
+            self.counters.limit_rejections.increment();
+            return Err(e.into());
+        }
+
+        let filename = sanitize_incoming_name(filename);
+        let offer = IncomingFileOffer {
+            transfer_id: transfer_id.to_string(),
+            session_id: session_id.to_string(),
+            peer_id: peer_id.to_string(),
+            mime_type: guess_mime_type(&filename).to_string(),
+            filename,
+            size,
+        };
+        tracing::info!(
+            "Device {} offers {} ({} bytes) as transfer {}",
+            peer_id,
+            offer.filename,
+            size,
+            transfer_id
+        );
+        self.pending_incoming.insert(transfer_id.to_string(), offer);
+        if auto_accept && self.download_dir.is_some() {
+            let save_path = self.accept_incoming(transfer_id, None).await?;
+            return Ok(IncomingFileDecision::Accepted { save_path });
+        }
+        Ok(IncomingFileDecision::PendingApproval)
+    }
+
+    /// Offers waiting for the user to accept or reject them
+    pub fn pending_incoming(&self) -> Vec<&IncomingFileOffer> {
+        self.pending_incoming.values().collect()
+    }
+
+    /// Accept an offered file into `target_dir`, or the download folder
+    ///
+    /// Returns the path the file will be saved at; an existing file there is
+    /// never overwritten, a numbered name is picked instead. The path is
+    /// reserved with an empty file right away, so offers accepted under the
+    /// same name never share it.
+    pub async fn accept_incoming(
+        &mut self,
+        transfer_id: &str,
+        target_dir: Option<PathBuf>,
+    ) -> Result<PathBuf> {
+        let offer = self
+            .pending_incoming
+            .get(transfer_id)
+            .ok_or_else(|| anyhow::anyhow!("No pending file offer: {}", transfer_id))?;
+        let dir = target_dir
+            .or_else(|| self.download_dir.clone())
+            .ok_or_else(|| anyhow::anyhow!("No target folder for transfer {}", transfer_id))?;
+        let offer = offer.clone();
+        self.admit_file(&offer.session_id, offer.size)?;
+        self.pending_incoming.remove(transfer_id);
+
+        tokio::fs::create_dir_all(&dir).await?;
+        let save_path = reserve_path(&dir, &offer.filename).await?;
+        if let Err(e) = self.start_receive(
+            transfer_id,
+            &offer.peer_id,
+            &offer.filename,
+            offer.size,
+            save_path.clone(),
+        ) {
+            let _ = tokio::fs::remove_file(&save_path).await;
+            return Err(e);
+        }
+        self.reserved_paths
+            .insert(transfer_id.to_string(), save_path.clone());
+        tracing::info!(
+            "Accepted transfer {} into {}",
+            transfer_id,
+            save_path.display()
+        );
+        Ok(save_path)
+    }
+
+    pub fn reject_incoming(&mut self, transfer_id: &str) -> Result<()> {
+        self.pending_incoming
+            .remove(transfer_id)
+            .ok_or_else(|| anyhow::anyhow!("No pending file offer: {}", transfer_id))?;
+        tracing::info!("Rejected transfer: {}", transfer_id);
+        Ok(())
+    }
+
+    /// Stage received files in quarantine instead of writing them directly
+    /// to their save path
+    pub fn set_quarantine(&mut self, quarantine: Arc<FileQuarantine>) {
+        self.quarantine = Some(quarantine);
+    }
+
+    pub async fn send_file(&mut self, file_path: PathBuf, target_id: String) -> Result<String> {
         let filename = file_path
             .file_name()
             .and_then(|name| name.to_str())
             .unwrap_or("unknown")
             .to_string();
+        self.start_send(file_path, filename, target_id).await
+    }
 
+    async fn start_send(
+        &mut self,
+        file_path: PathBuf,
+        filename: String,
+        target_id: String,
+    ) -> Result<String> {
+        let file_metadata = tokio::fs::metadata(&file_path).await?;
+        let file_size = file_metadata.len();
+
+        if file_size > self.max_file_size {
+            return Err(anyhow::anyhow!("File size exceeds maximum limit of 4GB"));
+        }
+
+        let source = file_path.clone();
+        let checksums =
+            tokio::task::spawn_blocking(move || TransferChecksums::compute(&source)).await??;
+
+        let transfer_id = Uuid::new_v4().to_string();
         let progress = TransferProgress {
             transfer_id: transfer_id.clone(),
             filename,
@@ -72,7 +540,21 @@ impl FileTransfer {
             status: TransferStatus::Pending,
         };
 
+        let mut manifest = TransferManifest::new(
+            &transfer_id,
+            TransferDirection::Outgoing,
+            &target_id,
+            &progress.filename,
+            file_size,
+        );
+        manifest.source_path = Some(file_path.clone());
+        manifest.expected_checksums = Some(checksums);
+        self.persist_manifest(&manifest);
+        self.manifests.insert(transfer_id.clone(), manifest);
+
         self.active_transfers.insert(transfer_id.clone(), progress);
+        self.counters.files_sent.increment();
+        self.counters.bytes_sent.add(file_size);
 
         tracing::info!(
             "Starting file transfer: {} to {}",
@@ -82,6 +564,238 @@ impl FileTransfer {
         Ok(transfer_id)
     }
 
+    /// Accept an incoming file offered by `peer_id`
+    ///
+    /// Chunks passed to `record_chunk` are written to a partial file, kept in
+    /// the state store's directory when one is set and next to `save_path`
+    /// otherwise, until `receive_file` completes the transfer.
+    pub fn start_receive(
+        &mut self,
+        transfer_id: &str,
+        peer_id: &str,
+        filename: &str,
+        total_size: u64,
+        save_path: PathBuf,
+    ) -> Result<()> {
+        if total_size > self.max_file_size {
+            return Err(anyhow::anyhow!("File size exceeds maximum limit of 4GB"));
+        }
+        let partial_path = match &self.state_store {
+            Some(store) => store.partial_path(transfer_id)?,
+            // Appended rather than replacing the extension, so `a.txt` and
+            // `a.doc` do not share one
+            None => {
+                let mut name = save_path.file_name().unwrap_or_default().to_os_string();
+                name.push(".");
+                name.push(crate::transfer_state::PARTIAL_FILE_EXTENSION);
+                save_path.with_file_name(name)
+            }
+        };
+        let mut manifest = TransferManifest::new(
+            transfer_id,
+            TransferDirection::Incoming,
+            peer_id,
+            filename,
+            total_size,
+        );
+        manifest.partial_path = Some(partial_path);
+        manifest.save_path = Some(save_path);
+        self.persist_manifest(&manifest);
+        self.manifests.insert(transfer_id.to_string(), manifest);
+
+        self.active_transfers.insert(
+            transfer_id.to_string(),
+            TransferProgress {
+                transfer_id: transfer_id.to_string(),
+                filename: filename.to_string(),
+                total_size,
+                transferred_size: 0,
+                speed: 0,
+                estimated_time: 0,
+                status: TransferStatus::Pending,
+            },
+        );
+        Ok(())
+    }
+
+    /// Record chunk `index` of a transfer as done
+    ///
+    /// For incoming transfers `data` is written to the partial file first.
+    pub async fn record_chunk(&mut self, transfer_id: &str, index: u64, data: &[u8]) -> Result<()> {
+        use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+        let manifest = self
+            .manifests
+            .get_mut(transfer_id)
+            .ok_or_else(|| anyhow::anyhow!("Transfer not found: {}", transfer_id))?;
+        manifest.mark_chunk(index, data)?;
+        if let (TransferDirection::Incoming, Some(partial_path)) =
+            (manifest.direction, &manifest.partial_path)
+        {
+            let offset = index * manifest.chunk_size;
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(partial_path)
+                .await?;
+            file.seek(std::io::SeekFrom::Start(offset)).await?;
+            file.write_all(data).await?;
+            file.flush().await?;
+        }
+
+        let completed = manifest.completed_chunks();
+        if completed % MANIFEST_FLUSH_CHUNKS == 0 || manifest.is_complete() {
+            let manifest = manifest.clone();
+            self.persist_manifest(&manifest);
+        }
+        let transferred = self.manifests[transfer_id].transferred_bytes();
+        if let Some(progress) = self.active_transfers.get_mut(transfer_id) {
+            progress.transferred_size = transferred;
+            progress.status = TransferStatus::InProgress;
+        }
+        Ok(())
+    }
+
+    /// Checksums of an outgoing transfer, to pass on to the receiver
+    pub fn transfer_checksums(&self, transfer_id: &str) -> Option<TransferChecksums> {
+        self.manifests.get(transfer_id)?.expected_checksums.clone()
+    }
+
+    /// Verify an incoming transfer against the sender's checksums once
+    /// `receive_file` completes it
+    pub fn set_transfer_checksums(
+        &mut self,
+        transfer_id: &str,
+        checksums: TransferChecksums,
+    ) -> Result<()> {
+        let manifest = self
+            .manifests
+            .get_mut(transfer_id)
+            .ok_or_else(|| anyhow::anyhow!("Transfer not found: {}", transfer_id))?;
+        if checksums.chunk_hashes.len() as u64 != manifest.chunk_count() {
+            return Err(anyhow::anyhow!(
+                "Transfer {} has {} chunks, checksums list {}",
+                transfer_id,
+                manifest.chunk_count(),
+                checksums.chunk_hashes.len()
+            ));
+        }
+        manifest.expected_checksums = Some(checksums);
+        let manifest = manifest.clone();
+        self.persist_manifest(&manifest);
+        Ok(())
+    }
+
+    /// Transfers a previous run left unfinished, oldest first
+    ///
+    /// Stale ones are expired first, deleting their partial files.
+    pub fn interrupted_transfers(&self) -> Result<Vec<TransferManifest>> {
+        let Some(store) = &self.state_store else {
+            return Ok(Vec::new());
+        };
+        store.expire(PARTIAL_TRANSFER_MAX_AGE)?;
+        Ok(store
+            .load_all()?
+            .into_iter()
+            .filter(|manifest| !self.active_transfers.contains_key(&manifest.transfer_id))
+            .collect())
+    }
+
+    /// Take an interrupted transfer back up, returning the chunks still to
+    /// transfer
+    ///
+    /// Call once the session with the manifest's peer is re-established. The
+    /// transfer comes back paused; chunks already in the partial file are
+    /// checked against their hashes and requested again if they differ. An
+    /// outgoing transfer whose source file changed size is dropped.
+    pub async fn resume_interrupted(&mut self, transfer_id: &str) -> Result<Vec<u64>> {
+        let store = self
+            .state_store
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("No transfer state store configured"))?;
+        let manifest = store
+            .load(transfer_id)?
+            .ok_or_else(|| anyhow::anyhow!("No interrupted transfer: {}", transfer_id))?;
+
+        if let (TransferDirection::Outgoing, Some(source_path)) =
+            (manifest.direction, &manifest.source_path)
+        {
+            let size = tokio::fs::metadata(source_path).await.map(|m| m.len()).ok();
+            if size != Some(manifest.total_size) {
+                store.remove(transfer_id)?;
+                return Err(anyhow::anyhow!(
+                    "Source of transfer {} changed since it was interrupted",
+                    transfer_id
+                ));
+            }
+        }
+        let manifest = tokio::task::spawn_blocking(move || verify_partial_chunks(manifest)).await?;
+
+        let missing = manifest.missing_chunks();
+        self.active_transfers.insert(
+            transfer_id.to_string(),
+            TransferProgress {
+                transfer_id: transfer_id.to_string(),
+                filename: manifest.filename.clone(),
+                total_size: manifest.total_size,
+                transferred_size: manifest.transferred_bytes(),
+                speed: 0,
+                estimated_time: 0,
+                status: TransferStatus::Paused,
+            },
+        );
+        self.persist_manifest(&manifest);
+        self.manifests.insert(transfer_id.to_string(), manifest);
+        tracing::info!(
+            "Restored interrupted transfer {} with {} chunks missing",
+            transfer_id,
+            missing.len()
+        );
+        Ok(missing)
+    }
+
+    fn persist_manifest(&self, manifest: &TransferManifest) {
+        if let Some(store) = &self.state_store {
+            if let Err(e) = store.save(manifest) {
+                tracing::warn!(
+                    "Failed to persist manifest of transfer {}: {}",
+                    manifest.transfer_id,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Drop the reservation of an accepted file's save path, removing the
+    /// empty file unless the received file ended up there
+    fn release_reserved_path(&mut self, transfer_id: &str, saved_path: Option<&Path>) {
+        if let Some(reserved) = self.reserved_paths.remove(transfer_id) {
+            if saved_path != Some(reserved.as_path()) {
+                let _ = std::fs::remove_file(reserved);
+            }
+        }
+    }
+
+    fn discard_manifest(&mut self, transfer_id: &str) {
+        let manifest = self.manifests.remove(transfer_id);
+        if let Some(store) = &self.state_store {
+            if let Err(e) = store.remove(transfer_id) {
+                tracing::warn!("Failed to remove state of transfer {}: {}", transfer_id, e);
+            }
+        }
+        if let Some(partial_path) = manifest.and_then(|manifest| manifest.partial_path) {
+            let _ = std::fs::remove_file(partial_path);
+        }
+    }
+
+    /// Complete an incoming transfer into `save_path`
+    ///
+    /// A transfer with the sender's checksums is verified first. If chunks
+    /// are corrupt this fails with `TransferIntegrityError::CorruptChunks`
+    /// and the transfer stays active so they can be received again; after
+    /// `MAX_VERIFY_RETRIES` such failures it is dropped and an unsuccessful
+    /// result returned instead.
     pub async fn receive_file(
         &mut self,
         transfer_id: String,
@@ -93,22 +807,156 @@ impl FileTransfer {
             save_path.display()
         );
 
-        // Placeholder implementation
-        let result = TransferResult {
+        // With a quarantine, data lands in staging and `save_path` only
+        // supplies the file name; released files go to its release directory
+        let landing_path = match &self.quarantine {
+            Some(quarantine) => quarantine.incoming_path(),
+            None => save_path.clone(),
+        };
+
+        // A transfer received chunk by chunk moves its partial file into place
+        let mut verified = false;
+        let mut retried_chunks = 0;
+        if let Some(manifest) = self.manifests.get(&transfer_id).cloned() {
+            if let (TransferDirection::Incoming, Some(partial_path)) =
+                (manifest.direction, &manifest.partial_path)
+            {
+                if !manifest.is_complete() {
+                    return Err(anyhow::anyhow!(
+                        "Transfer {} is missing {} chunks",
+                        transfer_id,
+                        manifest.missing_chunks().len()
+                    ));
+                }
+                if manifest.total_size > 0 && manifest.expected_checksums.is_some() {
+                    let checked = manifest.clone();
+                    let corrupt =
+                        tokio::task::spawn_blocking(move || corrupt_chunks(&checked)).await??;
+                    if !corrupt.is_empty() {
+                        return self.retry_corrupt_chunks(&transfer_id, corrupt);
+                    }
+                }
+                verified = manifest.expected_checksums.is_some();
+                retried_chunks = manifest.retried_chunks;
+                if manifest.total_size == 0 {
+                    tokio::fs::write(&landing_path, b"").await?;
+                } else {
+                    tokio::fs::rename(partial_path, &landing_path).await?;
+                }
+            }
+        }
+
+        // Received data, if any, is in `landing_path` now
+        let final_size = match tokio::fs::metadata(&landing_path).await {
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        };
+        let mut result = TransferResult {
             transfer_id: transfer_id.clone(),
             success: true,
             error_message: None,
-            final_size: 0,
+            final_size,
             duration: 0,
+            saved_path: None,
+            verified,
+            retried_chunks,
         };
 
+        if let Some(quarantine) = &self.quarantine {
+            if tokio::fs::try_exists(&landing_path).await? {
+                let name = save_path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .unwrap_or("received_file");
+                match quarantine.process(&landing_path, name).await? {
+                    QuarantineDecision::Released { path } => result.saved_path = Some(path),
+                    QuarantineDecision::Rejected { reason } => {
+                        result.success = false;
+                        result.error_message =
+                            Some(format!("File rejected by quarantine: {:?}", reason));
+                    }
+                }
+            }
+        } else if tokio::fs::try_exists(&landing_path).await? {
+            result.saved_path = Some(landing_path);
+        }
+
+        if result.success {
+            self.counters.files_received.increment();
+        }
+        let kept = result.saved_path.clone();
+        self.release_reserved_path(&transfer_id, kept.as_deref());
+        self.finish_folder_file(&transfer_id, &result);
         self.active_transfers.remove(&transfer_id);
+        self.discard_manifest(&transfer_id);
         Ok(result)
     }
 
+    /// Mark corrupt chunks missing again, or give up on the transfer once it
+    /// failed verification too often
+    fn retry_corrupt_chunks(
+        &mut self,
+        transfer_id: &str,
+        chunks: Vec<u64>,
+    ) -> Result<TransferResult> {
+        let manifest = self
+            .manifests
+            .get_mut(transfer_id)
+            .ok_or_else(|| anyhow::anyhow!("Transfer not found: {}", transfer_id))?;
+        manifest.verify_attempts += 1;
+        if manifest.verify_attempts > MAX_VERIFY_RETRIES {
+            let retried_chunks = manifest.retried_chunks;
+            tracing::warn!(
+                "Transfer {} still corrupt after {} retries, giving up",
+                transfer_id,
+                MAX_VERIFY_RETRIES
+            );
+            self.release_reserved_path(transfer_id, None);
+            self.active_transfers.remove(transfer_id);
+            self.discard_manifest(transfer_id);
+            return Ok(TransferResult {
+                transfer_id: transfer_id.to_string(),
+                success: false,
+                error_message: Some(format!(
+                    "File failed verification after {} retries",
+                    MAX_VERIFY_RETRIES
+                )),
+                final_size: 0,
+                duration: 0,
+                saved_path: None,
+                verified: false,
+                retried_chunks,
+            });
+        }
+
+        for index in &chunks {
+            manifest.forget_chunk(*index);
+        }
+        manifest.retried_chunks += chunks.len() as u64;
+        let manifest = manifest.clone();
+        self.persist_manifest(&manifest);
+        if let Some(progress) = self.active_transfers.get_mut(transfer_id) {
+            progress.transferred_size = manifest.transferred_bytes();
+            progress.status = TransferStatus::InProgress;
+        }
+        tracing::warn!(
+            "Transfer {} failed verification, re-requesting {} chunks",
+            transfer_id,
+            chunks.len()
+        );
+        Err(TransferIntegrityError::CorruptChunks {
+            transfer_id: transfer_id.to_string(),
+            chunks,
+        }
+        .into())
+    }
+
     pub fn pause_transfer(&mut self, transfer_id: &str) -> Result<()> {
         if let Some(progress) = self.active_transfers.get_mut(transfer_id) {
             progress.status = TransferStatus::Paused;
+            if let Some(manifest) = self.manifests.get(transfer_id) {
+                self.persist_manifest(manifest);
+            }
             tracing::info!("Paused transfer: {}", transfer_id);
             Ok(())
         } else {
@@ -129,6 +977,8 @@ impl FileTransfer {
     pub fn cancel_transfer(&mut self, transfer_id: &str) -> Result<()> {
         if let Some(mut progress) = self.active_transfers.remove(transfer_id) {
             progress.status = TransferStatus::Cancelled;
+            self.release_reserved_path(transfer_id, None);
+            self.discard_manifest(transfer_id);
             tracing::info!("Cancelled transfer: {}", transfer_id);
             Ok(())
         } else {
@@ -158,13 +1008,1041 @@ impl FileTransfer {
         self.active_transfers.values().collect()
     }
 
+    /// Send a folder and everything below it, file by file without archiving
+    ///
+    /// Every file gets its own transfer, named by its path relative to
+    /// `dir_path`. The returned offer goes to the receiver, which passes it to
+    /// `start_receive_folder`. Up to `channels` files stream at once, see
+    /// `folder_files_to_stream`; 1 sends them one after another.
+    pub async fn send_folder(
+        &mut self,
+        dir_path: PathBuf,
+        target_id: String,
+        channels: usize,
+    ) -> Result<FolderOffer> {
+        let root_name = dir_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("folder")
+            .to_string();
+        let root = dir_path.clone();
+        let entries = tokio::task::spawn_blocking(move || enumerate_folder(&root)).await??;
+        if let Some(entry) = entries.iter().find(|e| e.size > self.max_file_size) {
+            return Err(anyhow::anyhow!(
+                "File {} exceeds maximum limit of 4GB",
+                entry.relative_path
+            ));
+        }
+
+        let mut file_transfer_ids = Vec::new();
+        for entry in entries.iter().filter(|entry| !entry.is_dir) {
+            let source = dir_path.join(&entry.relative_path);
+            match self
+                .start_send(source, entry.relative_path.clone(), target_id.clone())
+                .await
+            {
+                Ok(id) => file_transfer_ids.push(id),
+                Err(e) => {
+                    for id in &file_transfer_ids {
+                        let _ = self.cancel_transfer(id);
+                    }
+                    return Err(e.context(format!("Cannot send {}", entry.relative_path)));
+                }
+            }
+        }
+
+        let checksums = file_transfer_ids
+            .iter()
+            .filter_map(|id| self.transfer_checksums(id))
+            .collect();
+        let offer = FolderOffer {
+            transfer_id: Uuid::new_v4().to_string(),
+            root_name,
+            entries,
+            file_transfer_ids,
+            channels: channels.max(1),
+            checksums,
+        };
+        tracing::info!(
+            "Starting folder transfer {}: {} files from {} to {}",
+            offer.transfer_id,
+            offer.file_transfer_ids.len(),
+            dir_path.display(),
+            target_id
+        );
+        self.folders.insert(
+            offer.transfer_id.clone(),
+            FolderTransfer {
+                offer: offer.clone(),
+                finished: HashMap::new(),
+                save_dir: None,
+            },
+        );
+        Ok(offer)
+    }
+
+    /// Accept a folder offered by `peer_id`, recreating it at `save_dir`
+    ///
+    /// Folders are created up front and every file is started as with
+    /// `start_receive`, to be completed by `receive_file` with the path
+    /// returned for it here. Entries that would escape `save_dir` reject the
+    /// whole offer.
+    pub async fn start_receive_folder(
+        &mut self,
+        peer_id: &str,
+        offer: &FolderOffer,
+        save_dir: PathBuf,
+    ) -> Result<Vec<PathBuf>> {
+        let files: Vec<&FolderEntry> = offer.entries.iter().filter(|entry| !entry.is_dir).collect();
+        if files.len() != offer.file_transfer_ids.len() {
+            return Err(anyhow::anyhow!(
+                "Folder offer {} lists {} files but {} transfers",
+                offer.transfer_id,
+                files.len(),
+                offer.file_transfer_ids.len()
+            ));
+        }
+        let mut paths = Vec::with_capacity(offer.entries.len());
+        for entry in &offer.entries {
+            if entry.size > self.max_file_size {
+                return Err(anyhow::anyhow!(
+                    "File {} exceeds maximum limit of 4GB",
+                    entry.relative_path
+                ));
+            }
+            paths.push(save_dir.join(safe_relative_path(&entry.relative_path)?));
+        }
+
+        tokio::fs::create_dir_all(&save_dir).await?;
+        for (entry, path) in offer.entries.iter().zip(&paths) {
+            let dir = if entry.is_dir {
+                Some(path.as_path())
+            } else {
+                path.parent()
+            };
+            if let Some(dir) = dir {
+                tokio::fs::create_dir_all(dir).await?;
+            }
+        }
+
+        let file_paths: Vec<PathBuf> = offer
+            .entries
+            .iter()
+            .zip(paths)
+            .filter(|(entry, _)| !entry.is_dir)
+            .map(|(_, path)| path)
+            .collect();
+        for ((entry, transfer_id), path) in
+            files.iter().zip(&offer.file_transfer_ids).zip(&file_paths)
+        {
+            self.start_receive(
+                transfer_id,
+                peer_id,
+                &entry.relative_path,
+                entry.size,
+                path.clone(),
+            )?;
+        }
+        if offer.checksums.len() == offer.file_transfer_ids.len() {
+            for (transfer_id, checksums) in offer.file_transfer_ids.iter().zip(&offer.checksums) {
+                self.set_transfer_checksums(transfer_id, checksums.clone())?;
+            }
+        }
+
+        self.folders.insert(
+            offer.transfer_id.clone(),
+            FolderTransfer {
+                offer: offer.clone(),
+                finished: HashMap::new(),
+                save_dir: Some(save_dir),
+            },
+        );
+        Ok(file_paths)
+    }
+
+    /// File transfers of a folder that should be streaming now
+    ///
+    /// The first unfinished files in offer order, at most one per channel;
+    /// call again as files finish to move on to the next ones.
+    pub fn folder_files_to_stream(&self, transfer_id: &str) -> Result<Vec<String>> {
+        let folder = self
+            .folders
+            .get(transfer_id)
+            .ok_or_else(|| anyhow::anyhow!("Folder transfer not found: {}", transfer_id))?;
+        Ok(folder
+            .offer
+            .file_transfer_ids
+            .iter()
+            .filter(|id| {
+                self.active_transfers.contains_key(*id)
+                    && !self.manifests.get(*id).is_some_and(|m| m.is_complete())
+            })
+            .take(folder.offer.channels)
+            .cloned()
+            .collect())
+    }
+
+    /// Per-file and overall progress of a folder transfer
+    pub fn get_folder_progress(&self, transfer_id: &str) -> Option<FolderProgress> {
+        let folder = self.folders.get(transfer_id)?;
+        let files: Vec<TransferProgress> = folder
+            .offer
+            .file_transfer_ids
+            .iter()
+            .filter_map(|id| {
+                if let Some(progress) = folder.finished.get(id) {
+                    return Some(progress.clone());
+                }
+                let mut progress = self.active_transfers.get(id)?.clone();
+                if self.manifests.get(id).is_some_and(|m| m.is_complete()) {
+                    progress.status = TransferStatus::Completed;
+                }
+                Some(progress)
+            })
+            .collect();
+
+        let is = |status: fn(&TransferStatus) -> bool| files.iter().any(|f| status(&f.status));
+        let completed_files = files
+            .iter()
+            .filter(|f| matches!(f.status, TransferStatus::Completed))
+            .count();
+        let status = if is(|s| matches!(s, TransferStatus::Failed)) {
+            TransferStatus::Failed
+        } else if completed_files == files.len() {
+            TransferStatus::Completed
+        } else if is(|s| matches!(s, TransferStatus::Paused)) {
+            TransferStatus::Paused
+        } else if completed_files > 0 || is(|s| matches!(s, TransferStatus::InProgress)) {
+            TransferStatus::InProgress
+        } else {
+            TransferStatus::Pending
+        };
+        Some(FolderProgress {
+            transfer_id: transfer_id.to_string(),
+            root_name: folder.offer.root_name.clone(),
+            total_files: files.len(),
+            completed_files,
+            total_size: files.iter().map(|f| f.total_size).sum(),
+            transferred_size: files.iter().map(|f| f.transferred_size).sum(),
+            files,
+            status,
+        })
+    }
+
+    /// Close a folder transfer once its files are done, returning its final
+    /// progress
+    ///
+    /// On the receiving side the folders get their permissions now, so a
+    /// read-only folder does not block writing its files.
+    pub fn finish_folder(&mut self, transfer_id: &str) -> Result<FolderProgress> {
+        let progress = self
+            .get_folder_progress(transfer_id)
+            .ok_or_else(|| anyhow::anyhow!("Folder transfer not found: {}", transfer_id))?;
+        let folder = self.folders.remove(transfer_id).unwrap();
+        for id in &folder.offer.file_transfer_ids {
+            self.active_transfers.remove(id);
+            self.discard_manifest(id);
+        }
+        if let Some(save_dir) = &folder.save_dir {
+            // Deepest first, as entries list parents before children
+            for entry in folder.offer.entries.iter().rev().filter(|e| e.is_dir) {
+                if let Ok(relative) = safe_relative_path(&entry.relative_path) {
+                    apply_mode(&save_dir.join(relative), entry.mode);
+                }
+            }
+        }
+        tracing::info!(
+            "Finished folder transfer {}: {}/{} files",
+            transfer_id,
+            progress.completed_files,
+            progress.total_files
+        );
+        Ok(progress)
+    }
+
+    /// Cancel every unfinished file of a folder transfer
+    pub fn cancel_folder(&mut self, transfer_id: &str) -> Result<()> {
+        let folder = self
+            .folders
+            .remove(transfer_id)
+            .ok_or_else(|| anyhow::anyhow!("Folder transfer not found: {}", transfer_id))?;
+        for id in &folder.offer.file_transfer_ids {
+            if self.active_transfers.contains_key(id) {
+                self.cancel_transfer(id)?;
+            }
+        }
+        tracing::info!("Cancelled folder transfer: {}", transfer_id);
+        Ok(())
+    }
+
+    /// Keep the outcome of a received file that belongs to a folder
+    fn finish_folder_file(&mut self, transfer_id: &str, result: &TransferResult) {
+        let Some(folder) = self
+            .folders
+            .values_mut()
+            .find(|d| d.offer.file_transfer_ids.iter().any(|id| id == transfer_id))
+        else {
+            return;
+        };
+        let Some(mut progress) = self.active_transfers.get(transfer_id).cloned() else {
+            return;
+        };
+        if result.success {
+            progress.transferred_size = progress.total_size;
+            progress.status = TransferStatus::Completed;
+            let entry = folder
+                .offer
+                .entries
+                .iter()
+                .find(|entry| entry.relative_path == progress.filename);
+            if let (Some(entry), Some(path)) = (entry, &result.saved_path) {
+                apply_mode(path, entry.mode);
+            }
+        } else {
+            progress.status = TransferStatus::Failed;
+        }
+        folder.finished.insert(transfer_id.to_string(), progress);
+    }
+
     pub fn get_max_file_size(&self) -> u64 {
         self.max_file_size
     }
 }
 
+/// Drop chunks of an incoming transfer that no longer match their hashes
+fn verify_partial_chunks(mut manifest: TransferManifest) -> TransferManifest {
+    use sha2::{Digest, Sha256};
+    use std::io::{Read, Seek};
+
+    let Some(partial_path) = manifest.partial_path.clone() else {
+        return manifest;
+    };
+    let mut file = std::fs::File::open(&partial_path).ok();
+    let mut buffer = Vec::new();
+    for index in 0..manifest.chunk_count() {
+        let Some(expected) = manifest.chunk_hash(index).map(str::to_string) else {
+            continue;
+        };
+        let (offset, len) = manifest.chunk_range(index).unwrap_or_default();
+        buffer.resize(len as usize, 0);
+        let intact = file.as_mut().is_some_and(|file| {
+            file.seek(std::io::SeekFrom::Start(offset)).is_ok()
+                && file.read_exact(&mut buffer).is_ok()
+                && hex::encode(Sha256::digest(&buffer)) == expected
+        });
+        if !intact {
+            manifest.forget_chunk(index);
+        }
+    }
+    manifest
+}
+
+/// Chunks of a completed incoming file that differ from the sender's
+///
+/// Empty when the whole file matches. Without per-chunk hashes to narrow a
+/// mismatch down, every chunk is reported.
+fn corrupt_chunks(manifest: &TransferManifest) -> Result<Vec<u64>> {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+
+    let (Some(expected), Some(partial_path)) =
+        (&manifest.expected_checksums, &manifest.partial_path)
+    else {
+        return Ok(Vec::new());
+    };
+    let mut file = std::fs::File::open(partial_path)?;
+    let mut file_hasher = Sha256::new();
+    let mut corrupt = Vec::new();
+    let mut buffer = Vec::new();
+    for index in 0..manifest.chunk_count() {
+        let (_, len) = manifest.chunk_range(index).unwrap_or_default();
+        buffer.resize(len as usize, 0);
+        file.read_exact(&mut buffer)?;
+        file_hasher.update(&buffer);
+        let chunk_hash = expected.chunk_hashes.get(index as usize);
+        if chunk_hash.is_some_and(|hash| *hash != hex::encode(Sha256::digest(&buffer))) {
+            corrupt.push(index);
+        }
+    }
+
+    if hex::encode(file_hasher.finalize()) == expected.file_hash {
+        return Ok(Vec::new());
+    }
+    if corrupt.is_empty() {
+        corrupt = (0..manifest.chunk_count()).collect();
+    }
+    Ok(corrupt)
+}
+
+/// Everything below `root`, folders before their contents, sorted by path
+///
+/// Symbolic links are skipped rather than followed, so a link cannot pull
+/// in files from outside the folder.
+fn enumerate_folder(root: &Path) -> Result<Vec<FolderEntry>> {
+    fn walk(root: &Path, dir: &Path, entries: &mut Vec<FolderEntry>) -> Result<()> {
+        let mut children: Vec<_> = std::fs::read_dir(dir)?.collect::<Result<_, _>>()?;
+        children.sort_by_key(|child| child.file_name());
+        for child in children {
+            let path = child.path();
+            let metadata = std::fs::symlink_metadata(&path)?;
+            if !metadata.is_dir() && !metadata.is_file() {
+                tracing::debug!("Skipping special file: {}", path.display());
+                continue;
+            }
+            let relative_path = path
+                .strip_prefix(root)?
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            entries.push(FolderEntry {
+                relative_path,
+                is_dir: metadata.is_dir(),
+                size: if metadata.is_dir() { 0 } else { metadata.len() },
+                mode: file_mode(&metadata),
+            });
+            if metadata.is_dir() {
+                walk(root, &path, entries)?;
+            }
+        }
+        Ok(())
+    }
+
+    if !std::fs::metadata(root)?.is_dir() {
+        return Err(anyhow::anyhow!("Not a folder: {}", root.display()));
+    }
+    let mut entries = Vec::new();
+    walk(root, root, &mut entries)?;
+    Ok(entries)
+}
+
+/// MIME type for a file name, from its extension
+///
+/// Only used to pick icons and previews, never to decide what is safe;
+/// unknown extensions are `application/octet-stream`.
+pub fn guess_mime_type(name: &str) -> &'static str {
+    let extension = Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "txt" | "log" => "text/plain",
+        "md" => "text/markdown",
+        "csv" => "text/csv",
+        "html" | "htm" => "text/html",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" | "tgz" => "application/gzip",
+        "7z" => "application/x-7z-compressed",
+        "doc" => "application/msword",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xls" => "application/vnd.ms-excel",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "ppt" => "application/vnd.ms-powerpoint",
+        "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "svg" => "image/svg+xml",
+        "bmp" => "image/bmp",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        "webm" => "video/webm",
+        "mkv" => "video/x-matroska",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Reduce a sender-supplied file name to one component that is safe to
+/// create on any platform
+fn sanitize_incoming_name(name: &str) -> String {
+    const RESERVED: [&str; 22] = [
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows drops trailing dots and spaces, which would change the name
+    let cleaned = cleaned.trim_end_matches(['.', ' ']).trim_start();
+    let stem = cleaned.split('.').next().unwrap_or_default();
+    if cleaned.is_empty() {
+        "received_file".to_string()
+    } else if RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        format!("_{}", cleaned)
+    } else {
+        cleaned.to_string()
+    }
+}
+
+/// Create an empty `dir/name`, or `dir/name (n).ext` for the first `n` not
+/// yet taken, and return its path
+///
+/// Creation fails if the file exists, so two callers never get one path.
+async fn reserve_path(dir: &Path, name: &str) -> Result<PathBuf> {
+    let path = Path::new(name);
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(name);
+    let extension = path.extension().and_then(|e| e.to_str());
+    let mut candidate = dir.join(name);
+    let mut n = 1;
+    loop {
+        match tokio::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&candidate)
+            .await
+        {
+            Ok(_) => return Ok(candidate),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!("Failed to create {}", candidate.display())))
+            }
+        }
+        candidate = dir.join(match extension {
+            Some(ext) => format!("{} ({}).{}", stem, n, ext),
+            None => format!("{} ({})", stem, n),
+        });
+        n += 1;
+    }
+}
+
+/// Turn a `/`-separated path from a peer into a local relative path
+fn safe_relative_path(relative_path: &str) -> Result<PathBuf> {
+    let path: PathBuf = relative_path.split('/').collect();
+    let normal = path
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if relative_path.is_empty() || !normal || relative_path.contains('\\') {
+        return Err(anyhow::anyhow!(
+            "Invalid path in folder offer: {}",
+            relative_path
+        ));
+    }
+    Ok(path)
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o7777)
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Give a received file or folder the sender's permissions
+///
+/// Only the rwx bits are taken from the peer, never setuid, setgid or sticky.
+#[cfg(unix)]
+fn apply_mode(path: &Path, mode: Option<u32>) {
+    use std::os::unix::fs::PermissionsExt;
+    let Some(mode) = mode else {
+        return;
+    };
+    let permissions = std::fs::Permissions::from_mode(mode & 0o777);
+    if let Err(e) = std::fs::set_permissions(path, permissions) {
+        tracing::warn!("Failed to set permissions of {}: {}", path.display(), e);
+    }
+}
+
+#[cfg(not(unix))]
+fn apply_mode(_path: &Path, _mode: Option<u32>) {}
+
 impl Default for FileTransfer {
     fn default() -> Self {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits() -> TransferLimits {
+        TransferLimits {
+            max_files_per_session: Some(2),
+            max_file_size: Some(1_000),
+            max_bytes_per_session: Some(1_500),
+            max_bytes_per_day: Some(2_000),
+        }
+    }
+
+    #[test]
+    fn test_session_limits_are_enforced() {
+        let mut transfer = FileTransfer::new();
+        transfer.set_session_limits("s1", "phone", limits());
+
+        assert_eq!(
+            transfer.admit_file("s1", 1_001),
+            Err(TransferLimitError::FileTooLarge {
+                size: 1_001,
+                limit: 1_000
+            })
+        );
+        transfer.admit_file("s1", 800).unwrap();
+        assert_eq!(
+            transfer.admit_file("s1", 800),
+            Err(TransferLimitError::SessionBytesExceeded {
+                limit: 1_500,
+                used: 800
+            })
+        );
+        transfer.admit_file("s1", 100).unwrap();
+        assert_eq!(
+            transfer.admit_file("s1", 1),
+            Err(TransferLimitError::TooManyFiles { limit: 2 })
+        );
+        assert_eq!(
+            transfer.session_counters("s1"),
+            Some(TransferCounters {
+                files: 2,
+                bytes: 900
+            })
+        );
+
+        // Sessions without limits only get the global size cap
+        assert!(transfer.admit_file("other", 10_000).is_ok());
+        assert_eq!(
+            transfer
+                .metrics_registry()
+                .snapshot()
+                .get("transfer.limit_rejections"),
+            3
+        );
+    }
+
+    #[test]
+    fn test_daily_limit_spans_sessions() {
+        let mut transfer = FileTransfer::new();
+        transfer.set_session_limits("s1", "phone", limits());
+        transfer.admit_file("s1", 1_000).unwrap();
+        transfer.admit_file("s1", 400).unwrap();
+        assert_eq!(transfer.end_session("s1").map(|c| c.bytes), Some(1_400));
+
+        transfer.set_session_limits("s2", "phone", limits());
+        let err = anyhow::Error::from(transfer.admit_file("s2", 700).unwrap_err());
+        assert_eq!(
+            err.downcast_ref::<TransferLimitError>(),
+            Some(&TransferLimitError::DailyBytesExceeded {
+                limit: 2_000,
+                used: 1_400
+            })
+        );
+
+        transfer.set_session_limits("s3", "laptop", limits());
+        assert!(transfer.admit_file("s3", 700).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_incoming_transfer_resumes_after_restart() {
+        use crate::transfer_state::TRANSFER_CHUNK_SIZE;
+
+        let dir = std::env::temp_dir().join(format!("cec-resume-{}", Uuid::new_v4()));
+        let save_path = dir.join("report.bin");
+        let content: Vec<u8> = (0..TRANSFER_CHUNK_SIZE + 100)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let (first, second) = content.split_at(TRANSFER_CHUNK_SIZE as usize);
+
+        let mut transfer = FileTransfer::new();
+        transfer.set_state_store(TransferStateStore::new(&dir));
+        transfer
+            .start_receive(
+                "t1",
+                "laptop",
+                "report.bin",
+                content.len() as u64,
+                save_path.clone(),
+            )
+            .unwrap();
+        transfer.record_chunk("t1", 0, first).await.unwrap();
+        transfer.pause_transfer("t1").unwrap();
+        drop(transfer);
+
+        // Restarted app
+        let mut transfer = FileTransfer::new();
+        transfer.set_state_store(TransferStateStore::new(&dir));
+        let interrupted = transfer.interrupted_transfers().unwrap();
+        assert_eq!(interrupted.len(), 1);
+        assert_eq!(interrupted[0].peer_id, "laptop");
+
+        assert_eq!(transfer.resume_interrupted("t1").await.unwrap(), vec![1]);
+        let progress = transfer.get_transfer_progress("t1").unwrap();
+        assert_eq!(progress.transferred_size, TRANSFER_CHUNK_SIZE);
+        assert!(transfer.interrupted_transfers().unwrap().is_empty());
+
+        transfer.record_chunk("t1", 1, second).await.unwrap();
+        let result = transfer
+            .receive_file("t1".to_string(), save_path.clone())
+            .await
+            .unwrap();
+        assert_eq!(result.saved_path, Some(save_path.clone()));
+        assert_eq!(std::fs::read(&save_path).unwrap(), content);
+        assert!(TransferStateStore::new(&dir).load_all().unwrap().is_empty());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_folder_transfer_preserves_tree() {
+        let base = std::env::temp_dir().join(format!("cec-dir-{}", Uuid::new_v4()));
+        let source = base.join("project");
+        std::fs::create_dir_all(source.join("src/bin")).unwrap();
+        std::fs::create_dir_all(source.join("empty")).unwrap();
+        std::fs::write(source.join("README.md"), b"readme").unwrap();
+        std::fs::write(source.join("src/lib.rs"), b"pub fn lib() {}").unwrap();
+        std::fs::write(source.join("src/bin/run.sh"), b"#!/bin/sh\n").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let executable = std::fs::Permissions::from_mode(0o755);
+            std::fs::set_permissions(source.join("src/bin/run.sh"), executable).unwrap();
+        }
+
+        let mut sender = FileTransfer::new();
+        let offer = sender
+            .send_folder(source.clone(), "laptop".to_string(), 2)
+            .await
+            .unwrap();
+        let paths: Vec<&str> = offer
+            .entries
+            .iter()
+            .map(|e| e.relative_path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                "README.md",
+                "empty",
+                "src",
+                "src/bin",
+                "src/bin/run.sh",
+                "src/lib.rs"
+            ]
+        );
+        assert_eq!(offer.root_name, "project");
+        assert_eq!(offer.file_transfer_ids.len(), 3);
+
+        // Two channels: the third file waits for one of the first two
+        let ids = &offer.file_transfer_ids;
+        let streaming = sender.folder_files_to_stream(&offer.transfer_id).unwrap();
+        assert_eq!(streaming, ids[..2]);
+        sender.record_chunk(&ids[0], 0, b"readme").await.unwrap();
+        let streaming = sender.folder_files_to_stream(&offer.transfer_id).unwrap();
+        assert_eq!(streaming, [ids[1].clone(), ids[2].clone()]);
+
+        let progress = sender.get_folder_progress(&offer.transfer_id).unwrap();
+        assert_eq!(progress.total_files, 3);
+        assert_eq!(progress.completed_files, 1);
+        assert_eq!(progress.total_size, 6 + 15 + 10);
+        assert_eq!(progress.transferred_size, 6);
+        assert!(matches!(progress.status, TransferStatus::InProgress));
+
+        // Receiving side rebuilds the tree, including the empty folder
+        let target = base.join("received");
+        let mut receiver = FileTransfer::new();
+        let file_paths = receiver
+            .start_receive_folder("phone", &offer, target.clone())
+            .await
+            .unwrap();
+        assert!(target.join("empty").is_dir());
+        for (id, path) in ids.iter().zip(&file_paths) {
+            let relative = path.strip_prefix(&target).unwrap();
+            let content = std::fs::read(source.join(relative)).unwrap();
+            receiver.record_chunk(id, 0, &content).await.unwrap();
+            let result = receiver
+                .receive_file(id.clone(), path.clone())
+                .await
+                .unwrap();
+            assert!(result.success);
+        }
+        let progress = receiver.finish_folder(&offer.transfer_id).unwrap();
+        assert!(matches!(progress.status, TransferStatus::Completed));
+        assert_eq!(progress.transferred_size, progress.total_size);
+        assert!(receiver.get_active_transfers().is_empty());
+        assert_eq!(
+            std::fs::read(target.join("src/lib.rs")).unwrap(),
+            b"pub fn lib() {}"
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(target.join("src/bin/run.sh"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o755);
+        }
+
+        // Offers must not write outside the chosen folder
+        let mut hostile = offer.clone();
+        hostile.entries[0].relative_path = "../escape.md".to_string();
+        assert!(receiver
+            .start_receive_folder("phone", &hostile, base.join("other"))
+            .await
+            .is_err());
+        std::fs::remove_dir_all(base).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_corrupt_chunks_are_re_requested() {
+        use crate::transfer_state::TRANSFER_CHUNK_SIZE;
+
+        let dir = std::env::temp_dir().join(format!("cec-verify-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("disk.img");
+        let content: Vec<u8> = (0..TRANSFER_CHUNK_SIZE * 2 + 7)
+            .map(|i| (i % 253) as u8)
+            .collect();
+        std::fs::write(&source, &content).unwrap();
+        let chunks: Vec<&[u8]> = content.chunks(TRANSFER_CHUNK_SIZE as usize).collect();
+
+        let mut sender = FileTransfer::new();
+        let id = sender
+            .send_file(source.clone(), "laptop".to_string())
+            .await
+            .unwrap();
+        let checksums = sender.transfer_checksums(&id).unwrap();
+        assert_eq!(checksums.chunk_hashes.len(), 3);
+
+        let receive = |name: &str| {
+            let mut receiver = FileTransfer::new();
+            let save_path = dir.join(name);
+            receiver
+                .start_receive(&id, "phone", name, content.len() as u64, save_path.clone())
+                .unwrap();
+            receiver
+                .set_transfer_checksums(&id, checksums.clone())
+                .unwrap();
+            (receiver, save_path)
+        };
+
+        // Chunk 1 arrives damaged and is requested again
+        let (mut receiver, save_path) = receive("copy.img");
+        let mut damaged = chunks[1].to_vec();
+        damaged[10] ^= 0xff;
+        receiver.record_chunk(&id, 0, chunks[0]).await.unwrap();
+        receiver.record_chunk(&id, 1, &damaged).await.unwrap();
+        receiver.record_chunk(&id, 2, chunks[2]).await.unwrap();
+        let err = receiver
+            .receive_file(id.clone(), save_path.clone())
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<TransferIntegrityError>(),
+            Some(&TransferIntegrityError::CorruptChunks {
+                transfer_id: id.clone(),
+                chunks: vec![1]
+            })
+        );
+        assert_eq!(
+            receiver
+                .get_transfer_progress(&id)
+                .unwrap()
+                .transferred_size,
+            TRANSFER_CHUNK_SIZE + 7
+        );
+
+        receiver.record_chunk(&id, 1, chunks[1]).await.unwrap();
+        let result = receiver
+            .receive_file(id.clone(), save_path.clone())
+            .await
+            .unwrap();
+        assert!(result.success && result.verified);
+        assert_eq!(result.retried_chunks, 1);
+        assert_eq!(result.final_size, content.len() as u64);
+        assert_eq!(std::fs::read(&save_path).unwrap(), content);
+
+        // A chunk that keeps arriving damaged fails the transfer
+        let (mut receiver, save_path) = receive("bad.img");
+        receiver.record_chunk(&id, 0, chunks[0]).await.unwrap();
+        receiver.record_chunk(&id, 2, chunks[2]).await.unwrap();
+        for _ in 0..MAX_VERIFY_RETRIES {
+            receiver.record_chunk(&id, 1, &damaged).await.unwrap();
+            assert!(receiver
+                .receive_file(id.clone(), save_path.clone())
+                .await
+                .is_err());
+        }
+        receiver.record_chunk(&id, 1, &damaged).await.unwrap();
+        let result = receiver
+            .receive_file(id.clone(), save_path.clone())
+            .await
+            .unwrap();
+        assert!(!result.success && !result.verified);
+        assert_eq!(result.retried_chunks, MAX_VERIFY_RETRIES as u64);
+        assert!(receiver.get_transfer_progress(&id).is_none());
+        assert!(!save_path.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_offers_with_one_name_get_separate_save_paths() {
+        let dir = std::env::temp_dir().join(format!("cec-reserve-{}", Uuid::new_v4()));
+        let access_control = Arc::new(AccessControlManager::new());
+        let request = access_control
+            .handle_connection_request(
+                "laptop".to_string(),
+                "laptop".to_string(),
+                vec![Permission::FileTransfer],
+                None,
+            )
+            .await
+            .unwrap();
+        access_control
+            .respond_to_request(&request.request_id, true, None, None)
+            .await
+            .unwrap();
+
+        let mut transfer = FileTransfer::new();
+        transfer.set_access_control(access_control);
+        transfer.set_download_dir(dir.clone());
+        transfer.set_session_limits(
+            "s1",
+            "laptop",
+            TransferLimits {
+                max_files_per_session: Some(3),
+                ..limits()
+            },
+        );
+        for id in ["t1", "t2"] {
+            transfer
+                .offer_incoming("s1", "laptop", id, "report.pdf", 4)
+                .await
+                .unwrap();
+        }
+
+        // Both accepted before either file has any data
+        let first = transfer.accept_incoming("t1", None).await.unwrap();
+        let second = transfer.accept_incoming("t2", None).await.unwrap();
+        assert_eq!(first, dir.join("report.pdf"));
+        assert_eq!(second, dir.join("report (1).pdf"));
+        assert!(first.exists() && second.exists());
+
+        transfer.record_chunk("t1", 0, b"one!").await.unwrap();
+        transfer.record_chunk("t2", 0, b"two!").await.unwrap();
+        for (id, path, data) in [("t1", &first, b"one!"), ("t2", &second, b"two!")] {
+            let result = transfer
+                .receive_file(id.to_string(), path.clone())
+                .await
+                .unwrap();
+            assert!(result.success);
+            assert_eq!(std::fs::read(path).unwrap(), data);
+        }
+
+        // A cancelled transfer gives its name back
+        transfer
+            .offer_incoming("s1", "laptop", "t3", "report.pdf", 4)
+            .await
+            .unwrap();
+        let third = transfer.accept_incoming("t3", None).await.unwrap();
+        assert_eq!(third, dir.join("report (2).pdf"));
+        transfer.cancel_transfer("t3").unwrap();
+        assert!(!third.exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_incoming_offers_need_permission_and_approval() {
+        let dir = std::env::temp_dir().join(format!("cec-accept-{}", Uuid::new_v4()));
+        let downloads = dir.join("downloads");
+        let access_control = Arc::new(AccessControlManager::new());
+        for (device, permission) in [
+            ("laptop", Permission::FileTransfer),
+            ("viewer", Permission::ViewScreen),
+        ] {
+            let request = access_control
+                .handle_connection_request(
+                    device.to_string(),
+                    device.to_string(),
+                    vec![permission],
+                    None,
+                )
+                .await
+                .unwrap();
+            access_control
+                .respond_to_request(&request.request_id, true, None, None)
+                .await
+                .unwrap();
+        }
+
+        let mut transfer = FileTransfer::new();
+        transfer.set_access_control(Arc::clone(&access_control));
+        transfer.set_download_dir(downloads.clone());
+        transfer.set_session_limits("s1", "laptop", limits());
+
+        assert!(transfer
+            .offer_incoming("s2", "viewer", "t0", "notes.txt", 10)
+            .await
+            .is_err());
+        let err = transfer
+            .offer_incoming("s1", "laptop", "t0", "huge.iso", 5_000)
+            .await
+            .unwrap_err();
+        assert!(err.downcast_ref::<TransferLimitError>().is_some());
+
+        // Prompted; the name cannot escape the chosen folder or overwrite
+        assert_eq!(
+            transfer
+                .offer_incoming("s1", "laptop", "t1", "../../etc/passwd", 100)
+                .await
+                .unwrap(),
+            IncomingFileDecision::PendingApproval
+        );
+        assert_eq!(transfer.pending_incoming()[0].filename, "passwd");
+        let chosen = dir.join("chosen");
+        std::fs::create_dir_all(&chosen).unwrap();
+        std::fs::write(chosen.join("passwd"), b"mine").unwrap();
+        let save_path = transfer
+            .accept_incoming("t1", Some(chosen.clone()))
+            .await
+            .unwrap();
+        assert_eq!(save_path, chosen.join("passwd (1)"));
+        assert!(transfer.get_transfer_progress("t1").is_some());
+        assert_eq!(transfer.session_counters("s1").map(|c| c.files), Some(1));
+
+        transfer
+            .offer_incoming("s1", "laptop", "t2", "spam.exe", 100)
+            .await
+            .unwrap();
+        transfer.reject_incoming("t2").unwrap();
+        assert!(transfer.pending_incoming().is_empty());
+        assert_eq!(transfer.session_counters("s1").map(|c| c.files), Some(1));
+
+        access_control
+            .set_auto_accept_files("laptop", true)
+            .await
+            .unwrap();
+        assert_eq!(
+            transfer
+                .offer_incoming("s1", "laptop", "t3", "CON.txt", 100)
+                .await
+                .unwrap(),
+            IncomingFileDecision::Accepted {
+                save_path: downloads.join("_CON.txt")
+            }
+        );
+        assert_eq!(sanitize_incoming_name("a:b?.txt. "), "a_b_.txt");
+        assert_eq!(sanitize_incoming_name(".."), "received_file");
+        assert_eq!(transfer.pending_incoming().len(), 0);
+        assert_eq!(guess_mime_type("Photo.JPG"), "image/jpeg");
+        assert_eq!(guess_mime_type("Makefile"), "application/octet-stream");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
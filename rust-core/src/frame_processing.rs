@@ -0,0 +1,444 @@
+//! Frame Processing Hooks
+//!
+//! Lets embedders transform video frames without forking the pipeline, in
+//! the spirit of WebRTC insertable streams: the host runs a pre-encode
+//! pipeline on every captured frame (redaction, blurring, watermarks), and a
+//! viewer can run a post-decode one before rendering.
+//!
+//! Processors run in registration order within the same `order` value, each
+//! on its own worker thread so a slow or stuck one can be timed out without
+//! stalling capture. Errors, panics and timeouts are contained: the frame
+//! continues unprocessed, or is dropped for processors that must never let
+//! an unprocessed frame through (`FailurePolicy::DropFrame`, e.g. redaction).
+
+use crate::capture_thread::panic_message;
+use crate::screen_capture::VideoFrame;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Time a processor gets per frame unless configured otherwise
+pub const DEFAULT_PROCESSOR_TIMEOUT: Duration = Duration::from_millis(15);
+
+/// Where in the video path a pipeline runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProcessingStage {
+    /// Host, between capture and encoder
+    PreEncode,
+    /// Viewer, between decoder and renderer
+    PostDecode,
+}
+
+/// Transforms frames in place
+///
+/// Implemented for closures, so simple processors need no type of their own.
+pub trait FrameProcessor: Send {
+    fn process(&mut self, frame: &mut VideoFrame) -> Result<()>;
+}
+
+impl<F> FrameProcessor for F
+where
+    F: FnMut(&mut VideoFrame) -> Result<()> + Send,
+{
+    fn process(&mut self, frame: &mut VideoFrame) -> Result<()> {
+        self(frame)
+    }
+}
+
+/// What happens to a frame when its processor fails or times out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FailurePolicy {
+    /// Continue with the frame as it was before this processor; costs a
+    /// copy of each frame
+    #[default]
+    PassThrough,
+    /// Drop the frame; for processors whose output must not be bypassed
+    DropFrame,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessorConfig {
+    /// Lower runs first
+    pub order: i32,
+    pub timeout: Duration,
+    pub on_failure: FailurePolicy,
+}
+
+impl Default for ProcessorConfig {
+    fn default() -> Self {
+        Self {
+            order: 0,
+            timeout: DEFAULT_PROCESSOR_TIMEOUT,
+            on_failure: FailurePolicy::PassThrough,
+        }
+    }
+}
+
+/// Timing and failure counts of one processor
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProcessorStats {
+    pub id: u64,
+    pub name: String,
+    pub order: i32,
+    pub frames_processed: u64,
+    /// Errors and panics
+    pub failures: u64,
+    /// Frames that ran past the timeout, or were skipped while the
+    /// processor was still busy with one that did
+    pub timeouts: u64,
+    /// Frames dropped under `FailurePolicy::DropFrame`
+    pub frames_dropped: u64,
+    pub average_ms: f64,
+    pub max_ms: f64,
+}
+
+/// Ordered set of processors for one stage
+///
+/// Cheap to clone; clones share the processors.
+#[derive(Clone)]
+pub struct FramePipeline {
+    stage: ProcessingStage,
+    slots: Arc<Mutex<Vec<Slot>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl FramePipeline {
+    pub fn new(stage: ProcessingStage) -> Self {
+        Self {
+            stage,
+            slots: Arc::new(Mutex::new(Vec::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    pub fn stage(&self) -> ProcessingStage {
+        self.stage
+    }
+
+    /// Add a processor; returns its id for `unregister`
+    pub fn register(
+        &self,
+        name: impl Into<String>,
+        processor: impl FrameProcessor + 'static,
+        config: ProcessorConfig,
+    ) -> Result<u64> {
+        let name = name.into();
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let worker = Worker::spawn(&name, Box::new(processor))?;
+        let mut slots = self.lock();
+        // Stable, so equal orders keep registration order
+        let index = slots.partition_point(|s| s.config.order <= config.order);
+        slots.insert(
+            index,
+            Slot {
+                config,
+                worker,
+                stats: ProcessorStats {
+                    id,
+                    name: name.clone(),
+                    order: config.order,
+                    ..Default::default()
+                },
+            },
+        );
+        tracing::info!(
+            "Registered {:?} frame processor {} ({}) at order {}",
+            self.stage,
+            name,
+            id,
+            config.order
+        );
+        Ok(id)
+    }
+
+    /// Remove a processor; false if the id is unknown
+    ///
+    /// A processor still busy with a timed-out frame finishes it on its
+    /// worker thread before being dropped.
+    pub fn unregister(&self, id: u64) -> bool {
+        let mut slots = self.lock();
+        let before = slots.len();
+        slots.retain(|s| s.stats.id != id);
+        slots.len() != before
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Run a frame through every processor in order
+    ///
+    /// Returns `None` when a `DropFrame` processor failed on it.
+    pub fn process(&self, mut frame: VideoFrame) -> Option<VideoFrame> {
+        let mut slots = self.lock();
+        for slot in slots.iter_mut() {
+            let original =
+                (slot.config.on_failure == FailurePolicy::PassThrough).then(|| frame.clone());
+            match slot.run(frame) {
+                Some(processed) => frame = processed,
+                None => match original {
+                    Some(original) => frame = original,
+                    None => {
+                        slot.stats.frames_dropped += 1;
+                        return None;
+                    }
+                },
+            }
+        }
+        Some(frame)
+    }
+
+    /// Per-processor statistics, in execution order
+    pub fn stats(&self) -> Vec<ProcessorStats> {
+        self.lock().iter().map(|s| s.stats.clone()).collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Slot>> {
+        self.slots.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl std::fmt::Debug for FramePipeline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FramePipeline")
+            .field("stage", &self.stage)
+            .field("processors", &self.stats())
+            .finish()
+    }
+}
+
+struct Slot {
+    config: ProcessorConfig,
+    worker: Worker,
+    stats: ProcessorStats,
+}
+
+impl Slot {
+    /// The processed frame, or `None` if the processor failed
+    fn run(&mut self, frame: VideoFrame) -> Option<VideoFrame> {
+        if self.worker.busy {
+            match self.worker.results.try_recv() {
+                // The late result is for a frame that has moved on already
+                Ok(_) => self.worker.busy = false,
+                Err(TryRecvError::Empty) => {
+                    self.stats.timeouts += 1;
+                    return None;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    self.fail("worker thread exited");
+                    return None;
+                }
+            }
+        }
+
+        if self.worker.jobs.send(frame).is_err() {
+            self.fail("worker thread exited");
+            return None;
+        }
+        match self.worker.results.recv_timeout(self.config.timeout) {
+            Ok((Ok(frame), elapsed)) => {
+                self.record_timing(elapsed);
+                Some(frame)
+            }
+            Ok((Err(e), _)) => {
+                self.fail(&e.to_string());
+                None
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                self.worker.busy = true;
+                self.stats.timeouts += 1;
+                if self.stats.timeouts == 1 {
+                    tracing::warn!(
+                        "Frame processor {} exceeded {:?}",
+                        self.stats.name,
+                        self.config.timeout
+                    );
+                }
+                None
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                self.fail("worker thread exited");
+                None
+            }
+        }
+    }
+
+    fn record_timing(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_secs_f64() * 1000.0;
+        let n = self.stats.frames_processed as f64;
+        self.stats.average_ms = (self.stats.average_ms * n + ms) / (n + 1.0);
+        self.stats.max_ms = self.stats.max_ms.max(ms);
+        self.stats.frames_processed += 1;
+    }
+
+    fn fail(&mut self, reason: &str) {
+        self.stats.failures += 1;
+        // Once is enough; a broken processor fails on every frame
+        if self.stats.failures == 1 {
+            tracing::warn!("Frame processor {} failed: {}", self.stats.name, reason);
+        } else {
+            tracing::debug!("Frame processor {} failed: {}", self.stats.name, reason);
+        }
+    }
+}
+
+type WorkerResult = (Result<VideoFrame>, Duration);
+
+/// Thread running one processor
+struct Worker {
+    jobs: mpsc::Sender<VideoFrame>,
+    results: mpsc::Receiver<WorkerResult>,
+    /// Still working on a frame that timed out
+    busy: bool,
+}
+
+impl Worker {
+    fn spawn(name: &str, mut processor: Box<dyn FrameProcessor>) -> Result<Self> {
+        let (jobs, pending) = mpsc::channel::<VideoFrame>();
+        let (done, results) = mpsc::channel::<WorkerResult>();
+        std::thread::Builder::new()
+            .name(format!("cec-frame-{}", name))
+            .spawn(move || {
+                // Ends when the slot, and with it `jobs`, is dropped
+                for mut frame in pending {
+                    let started = Instant::now();
+                    let result = match std::panic::catch_unwind(AssertUnwindSafe(|| {
+                        processor.process(&mut frame)
+                    })) {
+                        Ok(Ok(())) => Ok(frame),
+                        Ok(Err(e)) => Err(e),
+                        Err(panic) => Err(anyhow::anyhow!("panicked: {}", panic_message(&*panic))),
+                    };
+                    if done.send((result, started.elapsed())).is_err() {
+                        break;
+                    }
+                }
+            })?;
+        Ok(Self {
+            jobs,
+            results,
+            busy: false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen_capture::FrameFormat;
+
+    fn frame(value: u8) -> VideoFrame {
+        VideoFrame {
+            id: 1,
+            timestamp: 0,
+            width: 2,
+            height: 1,
+            data: vec![value; 8],
+            format: FrameFormat::BGRA,
+            dirty_rects: None,
+        }
+    }
+
+    fn order(order: i32) -> ProcessorConfig {
+        ProcessorConfig {
+            order,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_processors_run_in_order_with_stats() {
+        let pipeline = FramePipeline::new(ProcessingStage::PreEncode);
+        assert_eq!(pipeline.process(frame(1)).unwrap().data, vec![1; 8]);
+
+        let double = |f: &mut VideoFrame| {
+            f.data.iter_mut().for_each(|b| *b *= 2);
+            Ok(())
+        };
+        let add_one = |f: &mut VideoFrame| {
+            f.data.iter_mut().for_each(|b| *b += 1);
+            Ok(())
+        };
+        let add_one = pipeline.register("add", add_one, order(10)).unwrap();
+        pipeline.register("double", double, order(0)).unwrap();
+
+        // (1 * 2) + 1, not (1 + 1) * 2
+        assert_eq!(pipeline.process(frame(1)).unwrap().data, vec![3; 8]);
+        let stats = pipeline.stats();
+        assert_eq!(
+            stats.iter().map(|s| s.name.as_str()).collect::<Vec<_>>(),
+            ["double", "add"]
+        );
+        assert!(stats.iter().all(|s| s.frames_processed == 1));
+        assert!(stats[0].max_ms >= stats[0].average_ms);
+
+        assert!(pipeline.unregister(add_one));
+        assert!(!pipeline.unregister(add_one));
+        assert_eq!(pipeline.process(frame(1)).unwrap().data, vec![2; 8]);
+    }
+
+    #[test]
+    fn test_failures_and_timeouts_isolated() {
+        let pipeline = FramePipeline::new(ProcessingStage::PostDecode);
+        pipeline
+            .register(
+                "panics",
+                |_: &mut VideoFrame| -> Result<()> { panic!("boom") },
+                order(0),
+            )
+            .unwrap();
+        pipeline
+            .register(
+                "slow",
+                |f: &mut VideoFrame| {
+                    std::thread::sleep(Duration::from_millis(200));
+                    f.data.fill(0);
+                    Ok(())
+                },
+                ProcessorConfig {
+                    order: 1,
+                    timeout: Duration::from_millis(20),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let mark = |f: &mut VideoFrame| {
+            f.data[0] = 9;
+            Ok(())
+        };
+        pipeline.register("mark", mark, order(2)).unwrap();
+
+        // The panic and the timeout pass the frame on unchanged; later
+        // processors still run
+        let out = pipeline.process(frame(5)).unwrap();
+        assert_eq!(out.data, [9, 5, 5, 5, 5, 5, 5, 5]);
+        // Still busy, so skipped straight away
+        let started = Instant::now();
+        assert!(pipeline.process(frame(5)).is_some());
+        assert!(started.elapsed() < Duration::from_millis(150));
+
+        let stats = pipeline.stats();
+        assert_eq!(stats[0].failures, 2);
+        assert_eq!(stats[1].timeouts, 2);
+        assert_eq!(stats[2].frames_processed, 2);
+
+        let redact = pipeline
+            .register(
+                "redact",
+                |_: &mut VideoFrame| Err(anyhow::anyhow!("no model")),
+                ProcessorConfig {
+                    order: 3,
+                    on_failure: FailurePolicy::DropFrame,
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert!(pipeline.process(frame(5)).is_none());
+        let stats = pipeline.stats();
+        assert_eq!((stats[3].id, stats[3].frames_dropped), (redact, 1));
+    }
+}
@@ -0,0 +1,218 @@
+//! Offline GeoIP Lookup
+//!
+//! Resolves a remote peer's public IP address to a coarse location using a
+//! local range database, so connection history can be enriched without
+//! contacting any online service.
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use std::path::Path;
+
+/// Coarse geographic location of an IP address
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GeoLocation {
+    /// ISO 3166-1 alpha-2 country code
+    pub country_code: String,
+    /// Human-readable country name
+    pub country_name: String,
+    /// Region or province, if known
+    pub region: Option<String>,
+    /// City, if known
+    pub city: Option<String>,
+}
+
+/// A contiguous IP range mapped to a location
+#[derive(Debug, Clone)]
+struct GeoIpRange {
+    start: u128,
+    end: u128,
+    location: GeoLocation,
+}
+
+/// In-memory offline GeoIP database
+///
+/// The database is loaded from a CSV file with one range per line:
+/// `start_ip,end_ip,country_code,country_name[,region[,city]]`.
+/// Empty lines and lines starting with `#` are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct GeoIpDatabase {
+    ranges: Vec<GeoIpRange>,
+}
+
+impl GeoIpDatabase {
+    /// Create an empty database
+    pub fn new() -> Self {
+        Self { ranges: Vec::new() }
+    }
+
+    /// Parse a database from CSV content
+    pub fn from_csv(content: &str) -> Result<Self> {
+        let mut ranges = Vec::new();
+
+        for (line_no, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(',').map(|f| f.trim()).collect();
+            if fields.len() < 4 {
+                return Err(anyhow::anyhow!(
+                    "Invalid GeoIP record on line {}: expected at least 4 fields",
+                    line_no + 1
+                ));
+            }
+
+            let start: IpAddr = fields[0]
+                .parse()
+                .with_context(|| format!("Invalid start IP on line {}", line_no + 1))?;
+            let end: IpAddr = fields[1]
+                .parse()
+                .with_context(|| format!("Invalid end IP on line {}", line_no + 1))?;
+
+            let (start, end) = (ip_to_u128(start), ip_to_u128(end));
+            if start > end {
+                return Err(anyhow::anyhow!(
+                    "Invalid GeoIP range on line {}: start is after end",
+                    line_no + 1
+                ));
+            }
+
+            let optional = |idx: usize| {
+                fields
+                    .get(idx)
+                    .filter(|f| !f.is_empty())
+                    .map(|f| f.to_string())
+            };
+
+            ranges.push(GeoIpRange {
+                start,
+                end,
+                location: GeoLocation {
+                    country_code: fields[2].to_uppercase(),
+                    country_name: fields[3].to_string(),
+                    region: optional(4),
+                    city: optional(5),
+                },
+            });
+        }
+
+        ranges.sort_by_key(|r| r.start);
+        tracing::info!("Loaded {} GeoIP ranges", ranges.len());
+        Ok(Self { ranges })
+    }
+
+    /// Load a database from a CSV file on disk
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read GeoIP database: {}", path.display()))?;
+        Self::from_csv(&content)
+    }
+
+    /// Look up the location of an IP address
+    ///
+    /// Private, loopback and link-local addresses never resolve.
+    pub fn lookup(&self, ip: IpAddr) -> Option<GeoLocation> {
+        if !is_public_ip(&ip) {
+            return None;
+        }
+
+        let value = ip_to_u128(ip);
+        let idx = self.ranges.partition_point(|r| r.start <= value);
+        if idx == 0 {
+            return None;
+        }
+
+        let range = &self.ranges[idx - 1];
+        if value <= range.end {
+            Some(range.location.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Number of ranges in the database
+    pub fn len(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Whether the database contains no ranges
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+}
+
+/// Check whether an address is publicly routable
+pub fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            !(v4.is_private()
+                || v4.is_loopback()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation())
+        }
+        IpAddr::V6(v6) => {
+            let segments = v6.segments();
+            let unique_local = (segments[0] & 0xfe00) == 0xfc00;
+            let link_local = (segments[0] & 0xffc0) == 0xfe80;
+            !(v6.is_loopback() || v6.is_unspecified() || unique_local || link_local)
+        }
+    }
+}
+
+/// Map an address into a single ordered key space (IPv4 as IPv4-mapped IPv6)
+fn ip_to_u128(ip: IpAddr) -> u128 {
+    match ip {
+        IpAddr::V4(v4) => u128::from(v4.to_ipv6_mapped()),
+        IpAddr::V6(v6) => u128::from(v6),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_DB: &str = "\
+# start,end,country_code,country_name,region,city
+1.0.0.0,1.0.0.255,au,Australia,Queensland,Brisbane
+8.8.8.0,8.8.8.255,US,United States,,
+2001:4860::,2001:4860:ffff:ffff:ffff:ffff:ffff:ffff,US,United States,California,
+";
+
+    #[test]
+    fn test_lookup_ipv4_and_ipv6() {
+        let db = GeoIpDatabase::from_csv(SAMPLE_DB).unwrap();
+        assert_eq!(db.len(), 3);
+
+        let au = db.lookup("1.0.0.1".parse().unwrap()).unwrap();
+        assert_eq!(au.country_code, "AU");
+        assert_eq!(au.city, Some("Brisbane".to_string()));
+
+        let us = db.lookup("8.8.8.8".parse().unwrap()).unwrap();
+        assert_eq!(us.country_code, "US");
+        assert_eq!(us.region, None);
+
+        let v6 = db.lookup("2001:4860::8888".parse().unwrap()).unwrap();
+        assert_eq!(v6.region, Some("California".to_string()));
+
+        assert!(db.lookup("9.9.9.9".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn test_private_addresses_do_not_resolve() {
+        let db = GeoIpDatabase::from_csv("0.0.0.0,255.255.255.255,ZZ,Anywhere").unwrap();
+        assert!(db.lookup("192.168.1.100".parse().unwrap()).is_none());
+        assert!(db.lookup("127.0.0.1".parse().unwrap()).is_none());
+        assert!(db.lookup("203.0.114.1".parse().unwrap()).is_some());
+    }
+
+    #[test]
+    fn test_invalid_records_rejected() {
+        assert!(GeoIpDatabase::from_csv("1.0.0.0,1.0.0.255,AU").is_err());
+        assert!(GeoIpDatabase::from_csv("1.0.0.255,1.0.0.0,AU,Australia").is_err());
+        assert!(GeoIpDatabase::from_csv("not-an-ip,1.0.0.0,AU,Australia").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+//! HDR Capture
+//!
+//! An HDR desktop is composited in a luminance range an 8-bit SDR capture
+//! cannot hold, so it comes out either clipped or washed out. Where the OS
+//! exposes the HDR surface (DXGI `DuplicateOutput1` with
+//! `R16G16B16A16_FLOAT`, ScreenCaptureKit's `captureDynamicRange`), backends
+//! deliver `FrameFormat::RGBA16F` frames and this module tone maps them to
+//! SDR before encoding.
+//!
+//! Passthrough skips tone mapping and is only kept when the viewer reports
+//! HDR decoding and the codec has a 10-bit profile; otherwise
+//! `CaptureOptions::constrained_to` falls back to tone mapping.
+
+use crate::screen_capture::{FrameFormat, VideoFrame};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Luminance of SDR white on an HDR display (ITU-R BT.2408)
+pub const DEFAULT_SDR_WHITE_NITS: f32 = 203.0;
+
+/// Peak luminance assumed when the display does not report one
+pub const DEFAULT_PEAK_NITS: f32 = 1000.0;
+
+/// Luminance of scRGB 1.0
+const SCRGB_NITS: f32 = 80.0;
+
+/// Largest finite half float
+const F16_MAX: f32 = 65504.0;
+
+/// HDR support of a display
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct HdrCapabilities {
+    /// The display can show HDR
+    pub supported: bool,
+    /// HDR output is currently turned on in the OS
+    pub enabled: bool,
+    /// Peak luminance, when the display reports it
+    pub max_luminance_nits: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HdrMode {
+    /// Capture in SDR and leave the conversion to the OS
+    Sdr,
+    /// Capture in HDR where possible and tone map to SDR on the host
+    #[default]
+    ToneMap,
+    /// Send HDR to a viewer that can display it
+    Passthrough,
+}
+
+/// Curve compressing HDR luminance into the SDR range
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ToneMapOperator {
+    /// Exact up to SDR white; anything brighter clips
+    Clip,
+    /// Extended Reinhard reaching SDR white at the display peak
+    #[default]
+    Reinhard,
+    /// Hable's filmic curve; darker midtones, softer highlight roll-off
+    Hable,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HdrOptions {
+    pub mode: HdrMode,
+    pub tone_mapping: ToneMapOperator,
+    /// HDR luminance shown as SDR white
+    pub sdr_white_nits: f32,
+    /// HDR luminance shown as the brightest SDR value
+    pub peak_nits: f32,
+}
+
+impl Default for HdrOptions {
+    fn default() -> Self {
+        Self {
+            mode: HdrMode::ToneMap,
+            tone_mapping: ToneMapOperator::Reinhard,
+            sdr_white_nits: DEFAULT_SDR_WHITE_NITS,
+            peak_nits: DEFAULT_PEAK_NITS,
+        }
+    }
+}
+
+impl HdrOptions {
+    /// Reject luminance settings the curves cannot work with
+    pub fn validate(&self) -> Result<()> {
+        if !(self.sdr_white_nits > 0.0 && self.peak_nits >= self.sdr_white_nits) {
+            return Err(anyhow::anyhow!(
+                "HDR peak ({} nits) must be at least SDR white ({} nits), which must be positive",
+                self.peak_nits,
+                self.sdr_white_nits
+            ));
+        }
+        Ok(())
+    }
+
+    /// Take the peak luminance from the captured display, if it reports one
+    pub fn for_display(mut self, display: &HdrCapabilities) -> Self {
+        if let Some(nits) = display.max_luminance_nits {
+            self.peak_nits = nits.max(self.sdr_white_nits);
+        }
+        self
+    }
+}
+
+/// Prepare a captured frame for the encoder
+///
+/// HDR frames are tone mapped to BGRA unless in passthrough; everything else
+/// is returned unchanged.
+pub fn convert_for_output(frame: VideoFrame, options: &HdrOptions) -> Result<VideoFrame> {
+    if frame.format != FrameFormat::RGBA16F || options.mode == HdrMode::Passthrough {
+        return Ok(frame);
+    }
+    tone_map(frame, options)
+}
+
+/// Map an `RGBA16F` frame to sRGB `BGRA`
+///
+/// The curve is applied to the brightest channel and the others scaled with
+/// it, which keeps hues from shifting towards white as they brighten.
+pub fn tone_map(frame: VideoFrame, options: &HdrOptions) -> Result<VideoFrame> {
+    let pixels = frame.width as usize * frame.height as usize;
+    if frame.format != FrameFormat::RGBA16F || frame.data.len() != pixels * 8 {
+        return Err(anyhow::anyhow!(
+            "Expected a {}x{} RGBA16F frame, got {:?} with {} bytes",
+            frame.width,
+            frame.height,
+            frame.format,
+            frame.data.len()
+        ));
+    }
+
+    let exposure = SCRGB_NITS / options.sdr_white_nits;
+    let white = options.peak_nits / options.sdr_white_nits;
+    let hable_white = hable(white);
+    let curve = |x: f32| match options.tone_mapping {
+        ToneMapOperator::Clip => x.min(1.0),
+        ToneMapOperator::Reinhard => x * (1.0 + x / (white * white)) / (1.0 + x),
+        ToneMapOperator::Hable => hable(x) / hable_white,
+    };
+
+    let mut data = Vec::with_capacity(pixels * 4);
+    for pixel in frame.data.chunks_exact(8) {
+        // NaN and negative (out of gamut) values become black
+        let channel = |i: usize| {
+            let half = u16::from_le_bytes([pixel[i * 2], pixel[i * 2 + 1]]);
+            let value = f16_to_f32(half) * exposure;
+            if value.is_nan() {
+                0.0
+            } else {
+                value.clamp(0.0, F16_MAX)
+            }
+        };
+        let (r, g, b) = (channel(0), channel(1), channel(2));
+        let peak = r.max(g).max(b);
+        let ratio = if peak > 0.0 { curve(peak) / peak } else { 0.0 };
+        data.extend_from_slice(&[
+            encode_srgb(b * ratio),
+            encode_srgb(g * ratio),
+            encode_srgb(r * ratio),
+            0xFF,
+        ]);
+    }
+
+    Ok(VideoFrame {
+        data,
+        format: FrameFormat::BGRA,
+        ..frame
+    })
+}
+
+/// Hable's Uncharted 2 curve, before normalising to the white point
+fn hable(x: f32) -> f32 {
+    const A: f32 = 0.15;
+    const B: f32 = 0.50;
+    const C: f32 = 0.10;
+    const D: f32 = 0.20;
+    const E: f32 = 0.02;
+    const F: f32 = 0.30;
+    (x * (A * x + C * B) + D * E) / (x * (A * x + B) + D * F) - E / F
+}
+
+fn encode_srgb(linear: f32) -> u8 {
+    let v = linear.clamp(0.0, 1.0);
+    let encoded = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+/// IEEE 754 binary16 to f32
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1F);
+    let mantissa = f32::from(bits & 0x3FF);
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1F if mantissa == 0.0 => f32::INFINITY,
+        0x1F => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One RGBA16F pixel per entry, in scRGB
+    fn hdr_frame(pixels: &[[u16; 3]]) -> VideoFrame {
+        let data = pixels
+            .iter()
+            .flat_map(|[r, g, b]| [*r, *g, *b, 0x3C00])
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        VideoFrame {
+            id: 1,
+            timestamp: 0,
+            width: pixels.len() as u32,
+            height: 1,
+            data,
+            format: FrameFormat::RGBA16F,
+            dirty_rects: None,
+        }
+    }
+
+    #[test]
+    fn test_tone_mapping_curves() {
+        assert_eq!(f16_to_f32(0x3C00), 1.0);
+        assert_eq!(f16_to_f32(0xC000), -2.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+
+        // scRGB 2.5375 is 203 nits, SDR white; 0x5A40 is 200.0, 16000 nits
+        let frame = hdr_frame(&[
+            [0, 0, 0],
+            [0x4113, 0x4113, 0x4113],
+            [0x5A40, 0, 0],
+            [0xBC00, 0x7E00, 0x3C00],
+        ]);
+        let options = HdrOptions {
+            tone_mapping: ToneMapOperator::Clip,
+            ..HdrOptions::default()
+        };
+        let clipped = tone_map(frame.clone(), &options).unwrap();
+        assert_eq!(clipped.format, FrameFormat::BGRA);
+        assert_eq!(clipped.id, 1);
+        assert_eq!(&clipped.data[..4], &[0, 0, 0, 0xFF]);
+        assert_eq!(&clipped.data[4..8], &[0xFF, 0xFF, 0xFF, 0xFF]);
+        // Hue survives: a blown-out red stays pure red
+        assert_eq!(&clipped.data[8..12], &[0, 0, 0xFF, 0xFF]);
+        // Negative and NaN channels are dropped, blue at 80 nits remains
+        assert_eq!(&clipped.data[12..14], &[0xA8, 0]);
+
+        let reinhard = tone_map(frame, &HdrOptions::default()).unwrap();
+        // Compresses SDR white below full brightness to leave headroom
+        assert!(reinhard.data[4] < 0xFF && reinhard.data[4] > 0xA0);
+        assert_eq!(&reinhard.data[8..12], &[0, 0, 0xFF, 0xFF]);
+
+        let bad = VideoFrame {
+            width: 2,
+            ..hdr_frame(&[[0, 0, 0]])
+        };
+        assert!(tone_map(bad, &options).is_err());
+    }
+
+    #[test]
+    fn test_passthrough_and_sdr_frames_untouched() {
+        let frame = hdr_frame(&[[0x3C00, 0x3C00, 0x3C00]]);
+        let passthrough = HdrOptions {
+            mode: HdrMode::Passthrough,
+            ..HdrOptions::default()
+        };
+        let kept = convert_for_output(frame.clone(), &passthrough).unwrap();
+        assert_eq!(kept.format, FrameFormat::RGBA16F);
+        assert_eq!(kept.data, frame.data);
+
+        let mapped = convert_for_output(frame, &HdrOptions::default()).unwrap();
+        assert_eq!(mapped.format, FrameFormat::BGRA);
+
+        let options = HdrOptions::default().for_display(&HdrCapabilities {
+            supported: true,
+            enabled: true,
+            max_luminance_nits: Some(600.0),
+        });
+        assert_eq!(options.peak_nits, 600.0);
+        assert!(options.validate().is_ok());
+        assert!(HdrOptions {
+            peak_nits: 100.0,
+            ..options
+        }
+        .validate()
+        .is_err());
+    }
+}
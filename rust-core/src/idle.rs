@@ -0,0 +1,330 @@
+//! Idle Reclamation
+//!
+//! A client left running with no sessions has no use for one-second network
+//! probes, a minute of stats history or a refreshed TURN relay. Once no
+//! session has been active for `idle_after_secs`, `IdleDetector` switches
+//! to `ActivityLevel::Idle` and background work follows the idle
+//! `ActivityProfile`: a longer monitoring interval, a trimmed history and
+//! no relay kept alive for candidate gathering.
+//!
+//! A new session, or an explicit `wake` (e.g. the user opening the app),
+//! restores full activity at once. `NetworkManager::monitor_wakeups` counts
+//! monitoring loop iterations so the saving can be measured.
+
+use crate::clock::{system_clock, SharedClock};
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use crate::network::NetworkManager;
+use crate::session_manager::SessionManager;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+/// Default quiet time before going idle
+pub const DEFAULT_IDLE_AFTER_SECS: u64 = 5 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActivityLevel {
+    Active,
+    Idle,
+}
+
+/// How much background work to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ActivityProfile {
+    /// Delay between network monitoring samples
+    pub monitor_interval_ms: u64,
+    /// Network stats samples kept
+    pub history_len: usize,
+    /// Keep a TURN relay allocated and refreshed for candidate gathering
+    pub gather_candidates: bool,
+}
+
+impl ActivityProfile {
+    pub fn full() -> Self {
+        Self {
+            monitor_interval_ms: 1000,
+            history_len: 60,
+            gather_candidates: true,
+        }
+    }
+
+    pub fn idle() -> Self {
+        Self {
+            monitor_interval_ms: 30_000,
+            history_len: 5,
+            gather_candidates: false,
+        }
+    }
+
+    pub fn monitor_interval(&self) -> Duration {
+        Duration::from_millis(self.monitor_interval_ms.max(1))
+    }
+}
+
+impl Default for ActivityProfile {
+    fn default() -> Self {
+        Self::full()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdlePolicy {
+    /// Time without active sessions before going idle; 0 never goes idle
+    pub idle_after_secs: u64,
+    pub active: ActivityProfile,
+    pub idle: ActivityProfile,
+}
+
+impl Default for IdlePolicy {
+    fn default() -> Self {
+        Self {
+            idle_after_secs: DEFAULT_IDLE_AFTER_SECS,
+            active: ActivityProfile::full(),
+            idle: ActivityProfile::idle(),
+        }
+    }
+}
+
+impl IdlePolicy {
+    pub fn profile(&self, level: ActivityLevel) -> ActivityProfile {
+        match level {
+            ActivityLevel::Active => self.active,
+            ActivityLevel::Idle => self.idle,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdleEvent {
+    LevelChanged(ActivityLevel),
+}
+
+impl EventType for IdleEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            IdleEvent::LevelChanged(_) => "LevelChanged",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct IdleState {
+    level: ActivityLevel,
+    active_sessions: usize,
+    /// Last moment a session was active or `wake` was called
+    last_active: Instant,
+}
+
+/// Decides when the client is idle
+pub struct IdleDetector {
+    policy: IdlePolicy,
+    clock: SharedClock,
+    state: Mutex<IdleState>,
+    events: EventBus<IdleEvent>,
+}
+
+impl Default for IdleDetector {
+    fn default() -> Self {
+        Self::new(IdlePolicy::default())
+    }
+}
+
+impl IdleDetector {
+    pub fn new(policy: IdlePolicy) -> Self {
+        Self::with_clock(policy, system_clock())
+    }
+
+    pub fn with_clock(policy: IdlePolicy, clock: SharedClock) -> Self {
+        let last_active = clock.instant();
+        Self {
+            policy,
+            clock,
+            state: Mutex::new(IdleState {
+                level: ActivityLevel::Active,
+                active_sessions: 0,
+                last_active,
+            }),
+            events: EventBus::new(),
+        }
+    }
+
+    pub fn policy(&self) -> IdlePolicy {
+        self.policy
+    }
+
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<IdleEvent> {
+        self.events.subscribe(options)
+    }
+
+    pub fn level(&self) -> ActivityLevel {
+        self.state().level
+    }
+
+    /// Profile background work should currently follow
+    pub fn profile(&self) -> ActivityProfile {
+        self.policy.profile(self.level())
+    }
+
+    /// Report how many sessions are active; any session wakes the client
+    pub fn set_active_sessions(&self, count: usize) -> Option<ActivityLevel> {
+        let now = self.clock.instant();
+        let mut state = self.state();
+        // The quiet period starts when the last session ends
+        if count > 0 || state.active_sessions > 0 {
+            state.last_active = now;
+        }
+        state.active_sessions = count;
+        let changed = self.update(&mut state, now);
+        drop(state);
+        self.publish(changed)
+    }
+
+    /// Restore full activity on demand, restarting the quiet period
+    pub fn wake(&self) -> Option<ActivityLevel> {
+        let now = self.clock.instant();
+        let mut state = self.state();
+        state.last_active = now;
+        let changed = self.update(&mut state, now);
+        drop(state);
+        self.publish(changed)
+    }
+
+    /// Go idle if the quiet period has elapsed
+    pub fn poll(&self) -> Option<ActivityLevel> {
+        let now = self.clock.instant();
+        let mut state = self.state();
+        let changed = self.update(&mut state, now);
+        drop(state);
+        self.publish(changed)
+    }
+
+    fn update(&self, state: &mut IdleState, now: Instant) -> Option<ActivityLevel> {
+        let quiet = now.saturating_duration_since(state.last_active);
+        let level = if state.active_sessions == 0
+            && self.policy.idle_after_secs > 0
+            && quiet >= Duration::from_secs(self.policy.idle_after_secs)
+        {
+            ActivityLevel::Idle
+        } else {
+            ActivityLevel::Active
+        };
+        if level == state.level {
+            return None;
+        }
+        state.level = level;
+        Some(level)
+    }
+
+    fn publish(&self, changed: Option<ActivityLevel>) -> Option<ActivityLevel> {
+        if let Some(level) = changed {
+            tracing::info!("Background activity now {:?}", level);
+            self.events.publish(IdleEvent::LevelChanged(level));
+        }
+        changed
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, IdleState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Keep `network` following the detector, counting `sessions` every
+/// `check_interval`
+///
+/// Changes from `wake` are applied as soon as they are published, not at
+/// the next check.
+pub fn spawn_idle_supervisor(
+    detector: Arc<IdleDetector>,
+    network: Arc<NetworkManager>,
+    sessions: Arc<SessionManager>,
+    check_interval: Duration,
+) -> JoinHandle<()> {
+    let mut events = detector.subscribe(SubscriptionOptions::default());
+    tokio::spawn(async move {
+        network.set_activity_profile(detector.profile()).await;
+        let mut ticks = tokio::time::interval(check_interval.max(Duration::from_millis(1)));
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = ticks.tick() => {
+                    detector.set_active_sessions(sessions.get_active_sessions().len());
+                    detector.poll();
+                }
+                event = events.recv() => match event {
+                    Some(IdleEvent::LevelChanged(level)) => {
+                        network
+                            .set_activity_profile(detector.policy().profile(level))
+                            .await;
+                    }
+                    None => break,
+                },
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+
+    #[test]
+    fn test_goes_idle_after_quiet_period_and_wakes() {
+        let clock = TestClock::shared();
+        let detector = IdleDetector::with_clock(IdlePolicy::default(), clock.clone());
+        let mut events = detector.subscribe(SubscriptionOptions::default());
+
+        clock.advance(Duration::from_secs(DEFAULT_IDLE_AFTER_SECS - 1));
+        assert_eq!(detector.poll(), None);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(detector.poll(), Some(ActivityLevel::Idle));
+        assert_eq!(detector.profile(), ActivityProfile::idle());
+
+        assert_eq!(detector.wake(), Some(ActivityLevel::Active));
+        clock.advance(Duration::from_secs(DEFAULT_IDLE_AFTER_SECS));
+        assert_eq!(detector.poll(), Some(ActivityLevel::Idle));
+
+        // A session keeps the client active however long it lasts
+        assert_eq!(detector.set_active_sessions(1), Some(ActivityLevel::Active));
+        clock.advance(Duration::from_secs(DEFAULT_IDLE_AFTER_SECS * 2));
+        assert_eq!(detector.poll(), None);
+        // The quiet period starts when it ends
+        assert_eq!(detector.set_active_sessions(0), None);
+        clock.advance(Duration::from_secs(DEFAULT_IDLE_AFTER_SECS - 1));
+        assert_eq!(detector.poll(), None);
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(detector.poll(), Some(ActivityLevel::Idle));
+
+        let mut levels = Vec::new();
+        while let Some(IdleEvent::LevelChanged(level)) = events.try_recv() {
+            levels.push(level);
+        }
+        assert_eq!(
+            levels,
+            vec![
+                ActivityLevel::Idle,
+                ActivityLevel::Active,
+                ActivityLevel::Idle,
+                ActivityLevel::Active,
+                ActivityLevel::Idle,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_zero_never_goes_idle() {
+        let clock = TestClock::shared();
+        let detector = IdleDetector::with_clock(
+            IdlePolicy {
+                idle_after_secs: 0,
+                ..Default::default()
+            },
+            clock.clone(),
+        );
+        clock.advance(Duration::from_secs(24 * 60 * 60));
+        assert_eq!(detector.poll(), None);
+        assert_eq!(detector.level(), ActivityLevel::Active);
+    }
+}
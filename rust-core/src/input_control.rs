@@ -1,19 +1,35 @@
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use crate::input_protocol::{InputAck, InputPacket};
+use crate::input_sequence::{InputReplayGuard, InputVerdict, SequencedInput};
+use crate::keymap::{keys_for_char, KeyAction, KeyTranslator};
+use crate::logging::{LogEntry, LogLevel, LogManager};
+use crate::security::{SecurityManager, SecurityThreat};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Default maximum number of characters accepted by a single type-text request
+pub const MAX_TYPE_TEXT_LENGTH: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MouseButton {
     Left,
     Right,
     Middle,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct KeyModifiers {
     pub ctrl: bool,
     pub alt: bool,
     pub shift: bool,
     pub meta: bool,
+    /// AltGr (right Alt) selecting the third level of a layout
+    #[serde(default)]
+    pub altgr: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,6 +43,13 @@ pub enum InputEvent {
         x: i32,
         y: i32,
     },
+    /// Second click of a double click, injected as such regardless of the
+    /// host's double-click time
+    MouseDoubleClick {
+        button: MouseButton,
+        x: i32,
+        y: i32,
+    },
     MouseWheel {
         delta_x: i32,
         delta_y: i32,
@@ -43,9 +66,90 @@ pub enum InputEvent {
         key: String,
         modifiers: KeyModifiers,
     },
+    /// UTF-8 text typed or pasted on the host
+    TypeText {
+        text: String,
+    },
+    /// Text committed by the controller's input method (e.g. CJK), always
+    /// injected as Unicode so the host's own IME does not compose it again
+    ImeCommit {
+        text: String,
+    },
+}
+
+/// How the host injects text received from a type-text request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TextInjectionMethod {
+    /// Synthetic keystrokes mapped through the host keyboard layout
+    Keystrokes,
+    /// Place text on the clipboard and send the platform paste shortcut
+    ClipboardPaste,
 }
 
+/// Host policy for type-text requests
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TextInjectionPolicy {
+    pub method: TextInjectionMethod,
+    /// Maximum number of characters per request
+    pub max_length: usize,
+}
+
+impl Default for TextInjectionPolicy {
+    fn default() -> Self {
+        Self {
+            method: TextInjectionMethod::Keystrokes,
+            max_length: MAX_TYPE_TEXT_LENGTH,
+        }
+    }
+}
+
+/// Assistive input modes applied to a session's remote input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessibilitySettings {
+    /// Tapping a modifier applies it to the next key; tapping it twice locks
+    /// it until tapped again
+    #[serde(default)]
+    pub sticky_keys: bool,
+    /// Click after the pointer rests this long; `None` disables dwell clicks
+    #[serde(default)]
+    pub dwell_click_ms: Option<u32>,
+    /// Pointer movement in pixels still counted as resting, also used to
+    /// match the two clicks of a double click
+    #[serde(default = "default_dwell_radius")]
+    pub dwell_radius: u32,
+    /// Maximum gap between two clicks injected as a double click
+    #[serde(default = "default_double_click_ms")]
+    pub double_click_ms: u32,
+    /// Multiplier applied to scroll deltas
+    #[serde(default = "default_scroll_speed")]
+    pub scroll_speed: f32,
+}
+
+fn default_dwell_radius() -> u32 {
+    8
+}
+
+fn default_double_click_ms() -> u32 {
+    500
+}
+
+fn default_scroll_speed() -> f32 {
+    1.0
+}
+
+impl Default for AccessibilitySettings {
+    fn default() -> Self {
+        Self {
+            sticky_keys: false,
+            dwell_click_ms: None,
+            dwell_radius: default_dwell_radius(),
+            double_click_ms: default_double_click_ms(),
+            scroll_speed: default_scroll_speed(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum KeyboardLayout {
     US,
     UK,
@@ -55,16 +159,101 @@ pub enum KeyboardLayout {
     CN,
 }
 
+impl KeyboardLayout {
+    /// From an XKB layout name such as `de` or `gb(extd)`
+    pub fn from_xkb(name: &str) -> Option<Self> {
+        let base = name.split(['(', ',']).next()?.trim();
+        match base.to_ascii_lowercase().as_str() {
+            "us" => Some(KeyboardLayout::US),
+            "gb" | "uk" => Some(KeyboardLayout::UK),
+            "de" => Some(KeyboardLayout::DE),
+            "fr" => Some(KeyboardLayout::FR),
+            "jp" => Some(KeyboardLayout::JP),
+            "cn" => Some(KeyboardLayout::CN),
+            _ => None,
+        }
+    }
+
+    /// From the language ID in the low word of a Windows `HKL`
+    pub fn from_windows_langid(langid: u16) -> Option<Self> {
+        match langid {
+            0x0409 => Some(KeyboardLayout::US),
+            0x0809 => Some(KeyboardLayout::UK),
+            0x0407 => Some(KeyboardLayout::DE),
+            0x040c => Some(KeyboardLayout::FR),
+            0x0411 => Some(KeyboardLayout::JP),
+            0x0804 => Some(KeyboardLayout::CN),
+            _ => None,
+        }
+    }
+
+    /// From a macOS input source ID such as `com.apple.keylayout.German`
+    pub fn from_macos_input_source(id: &str) -> Option<Self> {
+        match id.rsplit('.').next()? {
+            "US" | "ABC" => Some(KeyboardLayout::US),
+            "British" => Some(KeyboardLayout::UK),
+            "German" => Some(KeyboardLayout::DE),
+            "French" => Some(KeyboardLayout::FR),
+            "Japanese" | "Kotoeri" => Some(KeyboardLayout::JP),
+            "Pinyin" | "SCIM" => Some(KeyboardLayout::CN),
+            _ => None,
+        }
+    }
+}
+
+/// Keyboard layout used to translate keys changed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum KeyboardLayoutEvent {
+    Changed {
+        previous: KeyboardLayout,
+        current: KeyboardLayout,
+        /// The new layout comes from a manual override
+        overridden: bool,
+    },
+}
+
+impl EventType for KeyboardLayoutEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            KeyboardLayoutEvent::Changed { .. } => "Changed",
+        }
+    }
+}
+
 pub struct InputController {
     max_input_delay: u64, // milliseconds
-    keyboard_layout: KeyboardLayout,
+    /// Layout of the host, detected locally or reported by the remote host
+    keyboard_layout: RwLock<KeyboardLayout>,
+    /// Manual layout for setups where detection picks the wrong one
+    layout_override: RwLock<Option<KeyboardLayout>>,
+    layout_events: EventBus<KeyboardLayoutEvent>,
+    /// Input injection is suspended (e.g. accessibility permission revoked)
+    suspended: AtomicBool,
+    text_policy: TextInjectionPolicy,
+    audit_log: Option<Arc<LogManager>>,
+    /// Assistive translation state per session
+    assistive: Mutex<HashMap<String, AssistiveInput>>,
+    /// Sequence validation per session using sequenced input
+    replay_guards: Mutex<HashMap<String, InputReplayGuard>>,
+    /// Layout translation per session whose controller layout is known
+    key_translators: Mutex<HashMap<String, KeyTranslator>>,
+    security: Option<Arc<SecurityManager>>,
 }
 
 impl InputController {
     pub fn new() -> Self {
         Self {
             max_input_delay: 100, // 100ms as per requirement 7.1
-            keyboard_layout: KeyboardLayout::US,
+            keyboard_layout: RwLock::new(KeyboardLayout::US),
+            layout_override: RwLock::new(None),
+            layout_events: EventBus::new(),
+            suspended: AtomicBool::new(false),
+            text_policy: TextInjectionPolicy::default(),
+            audit_log: None,
+            assistive: Mutex::new(HashMap::new()),
+            replay_guards: Mutex::new(HashMap::new()),
+            key_translators: Mutex::new(HashMap::new()),
+            security: None,
         }
     }
 
@@ -80,6 +269,12 @@ impl InputController {
         Ok(())
     }
 
+    pub fn send_mouse_double_click(&self, button: MouseButton, x: i32, y: i32) -> Result<()> {
+        tracing::debug!("Sending mouse double click: {:?} at ({}, {})", button, x, y);
+        // Platform-specific implementation would go here
+        Ok(())
+    }
+
     pub fn send_mouse_wheel(&self, delta_x: i32, delta_y: i32) -> Result<()> {
         tracing::debug!("Sending mouse wheel: ({}, {})", delta_x, delta_y);
         // Platform-specific implementation would go here
@@ -105,14 +300,346 @@ impl InputController {
     }
 
     pub fn process_remote_input(&self, input_event: InputEvent) -> Result<()> {
+        if self.is_suspended() {
+            return Err(anyhow::anyhow!(
+                "Input injection suspended: accessibility permission unavailable"
+            ));
+        }
+
         match input_event {
             InputEvent::MouseMove { x, y } => self.send_mouse_move(x, y),
             InputEvent::MouseClick { button, x, y } => self.send_mouse_click(button, x, y),
+            InputEvent::MouseDoubleClick { button, x, y } => {
+                self.send_mouse_double_click(button, x, y)
+            }
             InputEvent::MouseWheel { delta_x, delta_y } => self.send_mouse_wheel(delta_x, delta_y),
             InputEvent::KeyDown { key, modifiers } => self.send_key_down(&key, modifiers),
             InputEvent::KeyUp { key, modifiers } => self.send_key_up(&key, modifiers),
             InputEvent::KeyPress { key, modifiers } => self.send_key_press(&key, modifiers),
+            InputEvent::TypeText { text } => self.type_text(&text).map(|_| ()),
+            InputEvent::ImeCommit { text } => {
+                text.chars().try_for_each(|c| self.send_unicode_char(c))
+            }
+        }
+    }
+
+    /// Process input for a session, applying its assistive input modes and
+    /// translating keys from the controller's layout
+    pub fn process_session_input(&self, session_id: &str, input_event: InputEvent) -> Result<()> {
+        let events = match self.assistive()?.get_mut(session_id) {
+            Some(assistive) => assistive.translate(input_event, Instant::now()),
+            None => vec![input_event],
+        };
+        for event in events {
+            let actions = match self.key_translators()?.get_mut(session_id) {
+                Some(translator) => {
+                    let host = self.keyboard_layout();
+                    match &event {
+                        InputEvent::KeyDown { key, modifiers } => {
+                            Some(translator.key_down(key, modifiers, host))
+                        }
+                        InputEvent::KeyUp { key, modifiers } => {
+                            Some(translator.key_up(key, modifiers, host))
+                        }
+                        InputEvent::KeyPress { key, modifiers } => {
+                            Some(translator.key_press(key, modifiers, host))
+                        }
+                        _ => None,
+                    }
+                }
+                None => None,
+            };
+            match actions {
+                Some(actions) => actions
+                    .into_iter()
+                    .try_for_each(|action| self.perform_key_action(action))?,
+                None => self.process_remote_input(event)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn perform_key_action(&self, action: KeyAction) -> Result<()> {
+        if self.is_suspended() {
+            return Err(anyhow::anyhow!(
+                "Input injection suspended: accessibility permission unavailable"
+            ));
+        }
+        match action {
+            KeyAction::Down { key, modifiers } => self.send_key_down(&key, modifiers),
+            KeyAction::Up { key, modifiers } => self.send_key_up(&key, modifiers),
+            KeyAction::Press { key, modifiers } => self.send_key_press(&key, modifiers),
+            KeyAction::Unicode(c) => self.send_unicode_char(c),
+        }
+    }
+
+    /// Translate a session's keys from the controller's layout to the host
+    /// layout; `None` forwards key positions unchanged
+    pub fn set_session_keyboard_layout(&self, session_id: &str, layout: Option<KeyboardLayout>) {
+        tracing::info!(
+            "Controller keyboard layout for session {}: {:?}",
+            session_id,
+            layout
+        );
+        if let Ok(mut translators) = self.key_translators.lock() {
+            match layout {
+                Some(layout) => {
+                    translators.insert(session_id.to_string(), KeyTranslator::new(layout));
+                }
+                None => {
+                    translators.remove(session_id);
+                }
+            }
+        }
+    }
+
+    pub fn session_keyboard_layout(&self, session_id: &str) -> Option<KeyboardLayout> {
+        self.key_translators
+            .lock()
+            .ok()?
+            .get(session_id)
+            .map(KeyTranslator::source_layout)
+    }
+
+    /// Inject clicks for sessions whose pointer has dwelled long enough
+    ///
+    /// Call periodically (e.g. every 50 ms) while dwell clicking is enabled
+    /// for any session. Returns the number of clicks injected.
+    pub fn poll_dwell_clicks(&self) -> Result<usize> {
+        let now = Instant::now();
+        let clicks: Vec<InputEvent> = self
+            .assistive()?
+            .values_mut()
+            .filter_map(|assistive| assistive.poll_dwell(now))
+            .collect();
+        let count = clicks.len();
+        for click in clicks {
+            self.process_remote_input(click)?;
+        }
+        Ok(count)
+    }
+
+    /// Apply assistive input modes to a session, resetting any latched state
+    pub fn set_session_accessibility(&self, session_id: &str, settings: AccessibilitySettings) {
+        tracing::info!(
+            "Set accessibility for session {}: {:?}",
+            session_id,
+            settings
+        );
+        if let Ok(mut assistive) = self.assistive.lock() {
+            assistive.insert(session_id.to_string(), AssistiveInput::new(settings));
+        }
+    }
+
+    pub fn session_accessibility(&self, session_id: &str) -> Option<AccessibilitySettings> {
+        self.assistive
+            .lock()
+            .ok()?
+            .get(session_id)
+            .map(|assistive| assistive.settings.clone())
+    }
+
+    /// Drop a session's assistive state when it ends
+    pub fn clear_session_accessibility(&self, session_id: &str) {
+        if let Ok(mut assistive) = self.assistive.lock() {
+            assistive.remove(session_id);
+        }
+    }
+
+    /// Require sequenced input for a session
+    ///
+    /// Returns the nonce the viewer must stamp its events with.
+    pub fn begin_sequenced_input(&self, session_id: &str) -> Result<u64> {
+        let guard = InputReplayGuard::new(InputReplayGuard::generate_nonce());
+        let nonce = guard.session_nonce();
+        self.replay_guards()?.insert(session_id.to_string(), guard);
+        Ok(nonce)
+    }
+
+    pub fn end_sequenced_input(&self, session_id: &str) {
+        if let Ok(mut guards) = self.replay_guards.lock() {
+            guards.remove(session_id);
+        }
+    }
+
+    /// Validate a sequenced event and inject it if it is fresh
+    ///
+    /// Rejected events are dropped and their verdict returned. Once rejects
+    /// add up to a replay attack, a `ReplayAttack` threat is raised and all
+    /// further input of the session fails.
+    pub fn process_sequenced_input(
+        &self,
+        session_id: &str,
+        input: SequencedInput,
+    ) -> Result<InputVerdict> {
+        let (verdict, attacked) = {
+            let mut guards = self.replay_guards()?;
+            let guard = guards.get_mut(session_id).ok_or_else(|| {
+                anyhow::anyhow!("Sequenced input not started for session: {}", session_id)
+            })?;
+            (guard.check(&input), guard.is_blocked())
+        };
+
+        if verdict == InputVerdict::Blocked {
+            return Err(anyhow::anyhow!(
+                "Input blocked after replay attack on session: {}",
+                session_id
+            ));
+        }
+        if attacked {
+            tracing::error!("Replay attack on input of session {}", session_id);
+            if let Some(security) = &self.security {
+                security.detect_security_threat(SecurityThreat::ReplayAttack)?;
+            }
+            return Err(anyhow::anyhow!(
+                "Replay attack detected on session: {}",
+                session_id
+            ));
+        }
+        if verdict == InputVerdict::Accepted {
+            self.process_session_input(session_id, input.event)?;
+        }
+        Ok(verdict)
+    }
+
+    /// Inject a batch received on the data channel
+    ///
+    /// Each event is validated like `process_sequenced_input`. Returns the
+    /// encoded ack for the newest injected event, or `None` if the batch
+    /// injected nothing.
+    pub fn process_input_packet(&self, session_id: &str, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let InputPacket::Batch(batch) = InputPacket::decode(data)? else {
+            return Err(anyhow::anyhow!("Expected an input batch"));
+        };
+        let mut newest = None;
+        for input in batch.into_sequenced() {
+            let stamp = (input.session_nonce, input.sequence, input.sent_at_ms);
+            if self.process_sequenced_input(session_id, input)? == InputVerdict::Accepted {
+                newest = Some(stamp);
+            }
+        }
+        let Some((session_nonce, sequence, sent_at_ms)) = newest else {
+            return Ok(None);
+        };
+        let ack = InputPacket::Ack(InputAck {
+            session_nonce,
+            sequence,
+            sent_at_ms,
+        });
+        Ok(Some(ack.encode()?))
+    }
+
+    /// Report input replay attacks to the given security manager
+    pub fn set_security_manager(&mut self, security: Arc<SecurityManager>) {
+        self.security = Some(security);
+    }
+
+    fn replay_guards(
+        &self,
+    ) -> Result<std::sync::MutexGuard<'_, HashMap<String, InputReplayGuard>>> {
+        self.replay_guards
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Input sequence state lock poisoned"))
+    }
+
+    fn key_translators(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, KeyTranslator>>> {
+        self.key_translators
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Keyboard translation state lock poisoned"))
+    }
+
+    fn assistive(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, AssistiveInput>>> {
+        self.assistive
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Accessibility state lock poisoned"))
+    }
+
+    /// Inject a UTF-8 string on the host according to the text policy
+    ///
+    /// Returns the injection method used. The text itself is never written to
+    /// the audit log, only its length and the method.
+    pub fn type_text(&self, text: &str) -> Result<TextInjectionMethod> {
+        if self.is_suspended() {
+            return Err(anyhow::anyhow!(
+                "Input injection suspended: accessibility permission unavailable"
+            ));
+        }
+
+        let length = text.chars().count();
+        if length > self.text_policy.max_length {
+            self.audit_text_injection(length, None);
+            return Err(anyhow::anyhow!(
+                "Text too long: {} characters (max {})",
+                length,
+                self.text_policy.max_length
+            ));
+        }
+
+        let method = self.text_policy.method;
+        match method {
+            TextInjectionMethod::Keystrokes => {
+                let layout = self.keyboard_layout();
+                for c in text.chars() {
+                    match keys_for_char(c, layout) {
+                        Some(keys) => {
+                            for (key, modifiers) in keys {
+                                self.send_key_press(&key, modifiers)?;
+                            }
+                        }
+                        None => self.send_unicode_char(c)?,
+                    }
+                }
+            }
+            TextInjectionMethod::ClipboardPaste => {
+                self.set_clipboard_text(text)?;
+                self.send_key_press("V", paste_modifiers())?;
+            }
         }
+
+        self.audit_text_injection(length, Some(method));
+        Ok(method)
+    }
+
+    fn send_unicode_char(&self, c: char) -> Result<()> {
+        tracing::debug!("Sending unicode character: U+{:04X}", c as u32);
+        // Platform-specific Unicode injection would go here
+        Ok(())
+    }
+
+    fn set_clipboard_text(&self, text: &str) -> Result<()> {
+        tracing::debug!("Setting clipboard text ({} bytes)", text.len());
+        // Platform-specific implementation would go here
+        Ok(())
+    }
+
+    fn audit_text_injection(&self, length: usize, method: Option<TextInjectionMethod>) {
+        if let Some(log_manager) = &self.audit_log {
+            let message = if method.is_some() {
+                "Remote text injected"
+            } else {
+                "Remote text injection rejected"
+            };
+            log_manager.log(
+                LogEntry::new(LogLevel::Info, "audit", message).with_metadata(serde_json::json!({
+                    "length": length,
+                    "method": method,
+                })),
+            );
+        }
+    }
+
+    pub fn set_text_injection_policy(&mut self, policy: TextInjectionPolicy) {
+        tracing::info!("Set text injection policy: {:?}", policy);
+        self.text_policy = policy;
+    }
+
+    pub fn get_text_injection_policy(&self) -> &TextInjectionPolicy {
+        &self.text_policy
+    }
+
+    /// Write type-text decisions to the given audit log
+    pub fn set_audit_log(&mut self, log_manager: Arc<LogManager>) {
+        self.audit_log = Some(log_manager);
     }
 
     pub fn set_input_delay(&mut self, max_delay: u64) {
@@ -120,19 +647,103 @@ impl InputController {
         tracing::info!("Set maximum input delay to {} ms", max_delay);
     }
 
-    pub fn set_keyboard_layout(&mut self, layout: KeyboardLayout) {
-        tracing::info!("Set keyboard layout to: {:?}", layout);
-        self.keyboard_layout = layout;
+    /// Set the host layout, e.g. as reported by the remote host
+    ///
+    /// Publishes `KeyboardLayoutEvent::Changed` if the layout in effect
+    /// changed. Returns whether the host layout changed.
+    pub fn set_keyboard_layout(&self, layout: KeyboardLayout) -> bool {
+        let previous_effective = self.keyboard_layout();
+        let changed = match self.keyboard_layout.write() {
+            Ok(mut current) if *current != layout => {
+                *current = layout;
+                true
+            }
+            _ => false,
+        };
+        if changed {
+            tracing::info!("Set keyboard layout to: {:?}", layout);
+            self.publish_layout_change(previous_effective, false);
+        }
+        changed
+    }
+
+    /// Use `layout` regardless of the host layout; `None` returns to the
+    /// detected or reported layout
+    pub fn set_layout_override(&self, layout: Option<KeyboardLayout>) {
+        let previous_effective = self.keyboard_layout();
+        if let Ok(mut current) = self.layout_override.write() {
+            *current = layout;
+        }
+        tracing::info!("Keyboard layout override: {:?}", layout);
+        self.publish_layout_change(previous_effective, layout.is_some());
+    }
+
+    pub fn layout_override(&self) -> Option<KeyboardLayout> {
+        self.layout_override.read().ok().and_then(|l| *l)
     }
 
+    /// Layout keys are translated with: the override if set, else the host
+    /// layout
+    pub fn keyboard_layout(&self) -> KeyboardLayout {
+        self.layout_override().unwrap_or_else(|| {
+            self.keyboard_layout
+                .read()
+                .map(|l| *l)
+                .unwrap_or(KeyboardLayout::US)
+        })
+    }
+
+    /// Probe the layout currently active on this machine
     pub fn detect_keyboard_layout(&self) -> KeyboardLayout {
-        // Placeholder - would detect system keyboard layout
-        self.keyboard_layout.clone()
+        detect_host_layout().unwrap_or_else(|| {
+            self.keyboard_layout
+                .read()
+                .map(|l| *l)
+                .unwrap_or(KeyboardLayout::US)
+        })
+    }
+
+    /// Re-detect the local layout, e.g. on a timer or when the OS reports an
+    /// input language change; returns the new layout if it changed
+    pub fn refresh_keyboard_layout(&self) -> Option<KeyboardLayout> {
+        let layout = detect_host_layout()?;
+        self.set_keyboard_layout(layout).then_some(layout)
+    }
+
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<KeyboardLayoutEvent> {
+        self.layout_events.subscribe(options)
+    }
+
+    fn publish_layout_change(&self, previous: KeyboardLayout, overridden: bool) {
+        let current = self.keyboard_layout();
+        if current != previous {
+            self.layout_events.publish(KeyboardLayoutEvent::Changed {
+                previous,
+                current,
+                overridden,
+            });
+        }
     }
 
     pub fn get_max_input_delay(&self) -> u64 {
         self.max_input_delay
     }
+
+    /// Reject remote input until resumed
+    pub fn suspend(&self) {
+        self.suspended.store(true, Ordering::SeqCst);
+        tracing::info!("Input injection suspended");
+    }
+
+    /// Accept remote input again
+    pub fn resume(&self) {
+        self.suspended.store(false, Ordering::SeqCst);
+        tracing::info!("Input injection resumed");
+    }
+
+    pub fn is_suspended(&self) -> bool {
+        self.suspended.load(Ordering::SeqCst)
+    }
 }
 
 impl Default for InputController {
@@ -140,3 +751,584 @@ impl Default for InputController {
         Self::new()
     }
 }
+
+/// Translation of remote input through a session's assistive modes
+struct AssistiveInput {
+    settings: AccessibilitySettings,
+    /// Applied to the next non-modifier key, then cleared
+    latched: KeyModifiers,
+    /// Applied to every key until tapped again
+    locked: KeyModifiers,
+    /// Where and since when the pointer has been resting; `None` once the
+    /// dwell click fired or after a real click
+    dwell_anchor: Option<(i32, i32, Instant)>,
+    last_click: Option<(MouseButton, i32, i32, Instant)>,
+}
+
+impl AssistiveInput {
+    fn new(settings: AccessibilitySettings) -> Self {
+        Self {
+            settings,
+            latched: KeyModifiers::default(),
+            locked: KeyModifiers::default(),
+            dwell_anchor: None,
+            last_click: None,
+        }
+    }
+
+    fn translate(&mut self, event: InputEvent, now: Instant) -> Vec<InputEvent> {
+        match event {
+            InputEvent::MouseMove { x, y } => {
+                let resting = self
+                    .dwell_anchor
+                    .is_some_and(|(ax, ay, _)| self.within_radius((ax, ay), (x, y)));
+                if !resting {
+                    self.dwell_anchor = Some((x, y, now));
+                }
+                vec![event]
+            }
+            InputEvent::MouseClick { button, x, y } => {
+                self.dwell_anchor = None;
+                vec![self.click(button, x, y, now)]
+            }
+            InputEvent::MouseWheel { delta_x, delta_y } => vec![InputEvent::MouseWheel {
+                delta_x: scale_scroll(delta_x, self.settings.scroll_speed),
+                delta_y: scale_scroll(delta_y, self.settings.scroll_speed),
+            }],
+            InputEvent::KeyDown { key, modifiers } if self.settings.sticky_keys => {
+                if modifier_for_key(&key).is_some() {
+                    return vec![];
+                }
+                let modifiers = self.sticky_modifiers(&modifiers);
+                vec![InputEvent::KeyDown { key, modifiers }]
+            }
+            InputEvent::KeyUp { key, modifiers } if self.settings.sticky_keys => {
+                if self.tap_modifier(&key) {
+                    return vec![];
+                }
+                let modifiers = self.sticky_modifiers(&modifiers);
+                self.latched = KeyModifiers::default();
+                vec![InputEvent::KeyUp { key, modifiers }]
+            }
+            InputEvent::KeyPress { key, modifiers } if self.settings.sticky_keys => {
+                if self.tap_modifier(&key) {
+                    return vec![];
+                }
+                let modifiers = self.sticky_modifiers(&modifiers);
+                self.latched = KeyModifiers::default();
+                vec![InputEvent::KeyPress { key, modifiers }]
+            }
+            event => vec![event],
+        }
+    }
+
+    /// Dwell click due at `now`, if any
+    fn poll_dwell(&mut self, now: Instant) -> Option<InputEvent> {
+        let dwell = Duration::from_millis(self.settings.dwell_click_ms? as u64);
+        let (x, y, since) = self.dwell_anchor?;
+        if now.duration_since(since) < dwell {
+            return None;
+        }
+        self.dwell_anchor = None;
+        Some(self.click(MouseButton::Left, x, y, now))
+    }
+
+    /// A click, upgraded to a double click if it closely follows the last one
+    fn click(&mut self, button: MouseButton, x: i32, y: i32, now: Instant) -> InputEvent {
+        let window = Duration::from_millis(self.settings.double_click_ms as u64);
+        let is_double = self.last_click.is_some_and(|(b, lx, ly, at)| {
+            b == button && now.duration_since(at) <= window && self.within_radius((lx, ly), (x, y))
+        });
+        if is_double {
+            self.last_click = None;
+            InputEvent::MouseDoubleClick { button, x, y }
+        } else {
+            self.last_click = Some((button, x, y, now));
+            InputEvent::MouseClick { button, x, y }
+        }
+    }
+
+    /// Cycle a tapped modifier through latched, locked and released;
+    /// returns false if `key` is not a modifier
+    fn tap_modifier(&mut self, key: &str) -> bool {
+        let Some(modifier) = modifier_for_key(key) else {
+            return false;
+        };
+        let latched = modifier(&mut self.latched);
+        let locked = modifier(&mut self.locked);
+        if *locked {
+            *locked = false;
+        } else if *latched {
+            *latched = false;
+            *locked = true;
+        } else {
+            *latched = true;
+        }
+        true
+    }
+
+    fn sticky_modifiers(&self, pressed: &KeyModifiers) -> KeyModifiers {
+        KeyModifiers {
+            ctrl: pressed.ctrl || self.latched.ctrl || self.locked.ctrl,
+            alt: pressed.alt || self.latched.alt || self.locked.alt,
+            shift: pressed.shift || self.latched.shift || self.locked.shift,
+            meta: pressed.meta || self.latched.meta || self.locked.meta,
+            altgr: pressed.altgr || self.latched.altgr || self.locked.altgr,
+        }
+    }
+
+    fn within_radius(&self, a: (i32, i32), b: (i32, i32)) -> bool {
+        let radius = self.settings.dwell_radius as i64;
+        let (dx, dy) = ((a.0 - b.0) as i64, (a.1 - b.1) as i64);
+        dx * dx + dy * dy <= radius * radius
+    }
+}
+
+/// Field of `KeyModifiers` a modifier key name controls
+fn modifier_for_key(key: &str) -> Option<fn(&mut KeyModifiers) -> &mut bool> {
+    match key.to_ascii_lowercase().as_str() {
+        "shift" => Some(|m| &mut m.shift),
+        "ctrl" | "control" => Some(|m| &mut m.ctrl),
+        "alt" | "option" => Some(|m| &mut m.alt),
+        "meta" | "command" | "cmd" | "super" | "win" => Some(|m| &mut m.meta),
+        "altgr" => Some(|m| &mut m.altgr),
+        _ => None,
+    }
+}
+
+/// Scale a scroll delta, never rounding a non-zero delta to zero
+fn scale_scroll(delta: i32, speed: f32) -> i32 {
+    let scaled = (delta as f32 * speed).round() as i32;
+    if scaled == 0 && delta != 0 && speed > 0.0 {
+        delta.signum()
+    } else {
+        scaled
+    }
+}
+
+/// Active keyboard layout of this machine, if it can be determined
+fn detect_host_layout() -> Option<KeyboardLayout> {
+    #[cfg(target_os = "windows")]
+    {
+        type Hwnd = *mut std::ffi::c_void;
+        type Hkl = *mut std::ffi::c_void;
+
+        extern "system" {
+            fn GetForegroundWindow() -> Hwnd;
+            fn GetWindowThreadProcessId(window: Hwnd, process_id: *mut u32) -> u32;
+            fn GetKeyboardLayout(thread_id: u32) -> Hkl;
+        }
+
+        // Layouts are per thread; the foreground window's is the one the
+        // user types with. Without one, thread 0 gives this thread's layout.
+        // SAFETY: a null window yields thread 0 and a null process ID pointer
+        // is allowed
+        let hkl = unsafe {
+            GetKeyboardLayout(GetWindowThreadProcessId(
+                GetForegroundWindow(),
+                std::ptr::null_mut(),
+            ))
+        };
+        KeyboardLayout::from_windows_langid((hkl as usize & 0xffff) as u16)
+    }
+    #[cfg(target_os = "macos")]
+    {
+        tis::current_input_source_id()
+            .as_deref()
+            .and_then(KeyboardLayout::from_macos_input_source)
+    }
+    #[cfg(target_os = "linux")]
+    {
+        xkb::detect()
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        None
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod tis {
+    use std::ffi::{c_char, c_int, c_void, CStr};
+
+    type CfTypeRef = *const c_void;
+    type CfStringRef = *const c_void;
+
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+
+    extern "C" {
+        static kTISPropertyInputSourceID: CfStringRef;
+        fn TISCopyCurrentKeyboardInputSource() -> CfTypeRef;
+        fn TISGetInputSourceProperty(source: CfTypeRef, key: CfStringRef) -> CfTypeRef;
+        fn CFStringGetCString(
+            string: CfStringRef,
+            buffer: *mut c_char,
+            size: isize,
+            encoding: u32,
+        ) -> u8;
+        fn CFRelease(cf: CfTypeRef);
+        fn pthread_main_np() -> c_int;
+        static _dispatch_main_q: u8;
+        fn dispatch_sync_f(
+            queue: *const u8,
+            context: *mut c_void,
+            work: extern "C" fn(*mut c_void),
+        );
+    }
+
+    /// ID of the selected input source, e.g. `com.apple.keylayout.German`
+    ///
+    /// Text Input Sources may only be used on the main thread, so other
+    /// threads block until the main run loop has read it.
+    pub(super) fn current_input_source_id() -> Option<String> {
+        // SAFETY: takes no arguments
+        if unsafe { pthread_main_np() } != 0 {
+            return read();
+        }
+        extern "C" fn work(context: *mut c_void) {
+            // SAFETY: `context` is the `Option` below, alive until
+            // `dispatch_sync_f` returns
+            unsafe { *context.cast::<Option<String>>() = read() };
+        }
+        let mut id: Option<String> = None;
+        // SAFETY: the main queue outlives the process and the call returns
+        // only after `work` ran
+        unsafe {
+            dispatch_sync_f(
+                std::ptr::addr_of!(_dispatch_main_q),
+                std::ptr::addr_of_mut!(id).cast(),
+                work,
+            )
+        };
+        id
+    }
+
+    fn read() -> Option<String> {
+        // SAFETY: the copied source is released; the property is borrowed
+        // from it and copied out before that
+        unsafe {
+            let source = TISCopyCurrentKeyboardInputSource();
+            if source.is_null() {
+                return None;
+            }
+            let id = TISGetInputSourceProperty(source, kTISPropertyInputSourceID);
+            let mut buffer = [0 as c_char; 256];
+            let copied = !id.is_null()
+                && CFStringGetCString(
+                    id,
+                    buffer.as_mut_ptr(),
+                    buffer.len() as isize,
+                    CF_STRING_ENCODING_UTF8,
+                ) != 0;
+            CFRelease(source);
+            copied.then(|| {
+                CStr::from_ptr(buffer.as_ptr())
+                    .to_string_lossy()
+                    .into_owned()
+            })
+        }
+    }
+}
+
+/// Layout detection without external tools, which Wayland sessions often
+/// lack: the X server's active keymap, then the compositor's libxkbcommon
+/// default, then the system keyboard settings written by `localectl`
+#[cfg(target_os = "linux")]
+mod xkb {
+    use super::KeyboardLayout;
+
+    const KEYBOARD_DEFAULTS: &str = "/etc/default/keyboard";
+    const XORG_KEYBOARD_CONF: &str = "/etc/X11/xorg.conf.d/00-keyboard.conf";
+
+    pub(super) fn detect() -> Option<KeyboardLayout> {
+        #[cfg(feature = "capture")]
+        if let Some(layout) = x11_rules_layout() {
+            return KeyboardLayout::from_xkb(&layout);
+        }
+        if let Ok(layout) = std::env::var("XKB_DEFAULT_LAYOUT") {
+            return KeyboardLayout::from_xkb(&layout);
+        }
+        std::fs::read_to_string(KEYBOARD_DEFAULTS)
+            .ok()
+            .and_then(|content| layout_from_keyboard_defaults(&content))
+            .or_else(|| {
+                std::fs::read_to_string(XORG_KEYBOARD_CONF)
+                    .ok()
+                    .and_then(|content| layout_from_xorg_conf(&content))
+            })
+            .and_then(|layout| KeyboardLayout::from_xkb(&layout))
+    }
+
+    /// `XKBLAYOUT=` of a Debian-style `/etc/default/keyboard`
+    pub(super) fn layout_from_keyboard_defaults(content: &str) -> Option<String> {
+        content
+            .lines()
+            .find_map(|line| line.trim().strip_prefix("XKBLAYOUT="))
+            .map(|value| value.trim().trim_matches('"').to_string())
+            .filter(|layout| !layout.is_empty())
+    }
+
+    /// `Option "XkbLayout"` of an xorg.conf `InputClass` section
+    pub(super) fn layout_from_xorg_conf(content: &str) -> Option<String> {
+        content.lines().find_map(|line| {
+            let mut fields = line.split('"').skip(1).step_by(2);
+            (line.trim().starts_with("Option") && fields.next()? == "XkbLayout")
+                .then(|| fields.next().map(str::to_string))
+                .flatten()
+        })
+    }
+
+    /// Layout field of the root window's `_XKB_RULES_NAMES`, which the X
+    /// server (XWayland included) keeps in sync with the active keymap
+    #[cfg(feature = "capture")]
+    fn x11_rules_layout() -> Option<String> {
+        let names = crate::window_enum::x11::root_string_property(c"_XKB_RULES_NAMES")?;
+        // rules, model, layout, variant, options; NUL separated
+        names
+            .split(|&b| b == 0)
+            .nth(2)
+            .map(|layout| String::from_utf8_lossy(layout).into_owned())
+            .filter(|layout| !layout.is_empty())
+    }
+}
+
+/// Modifiers for the platform paste shortcut
+fn paste_modifiers() -> KeyModifiers {
+    KeyModifiers {
+        ctrl: !cfg!(target_os = "macos"),
+        meta: cfg!(target_os = "macos"),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_detection_names() {
+        assert_eq!(
+            KeyboardLayout::from_xkb(" gb(extd),us"),
+            Some(KeyboardLayout::UK)
+        );
+        assert_eq!(KeyboardLayout::from_xkb("ru"), None);
+        assert_eq!(
+            KeyboardLayout::from_windows_langid(0x0407),
+            Some(KeyboardLayout::DE)
+        );
+        assert_eq!(
+            KeyboardLayout::from_macos_input_source("com.apple.keylayout.French"),
+            Some(KeyboardLayout::FR)
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_system_keyboard_settings_parsed() {
+        assert_eq!(
+            xkb::layout_from_keyboard_defaults("XKBMODEL=\"pc105\"\nXKBLAYOUT=\"de,us\"\n")
+                .as_deref(),
+            Some("de,us")
+        );
+        let conf = "Section \"InputClass\"\n        Identifier \"system-keyboard\"\n        \
+                    Option \"XkbLayout\" \"gb\"\nEndSection\n";
+        assert_eq!(xkb::layout_from_xorg_conf(conf).as_deref(), Some("gb"));
+        assert_eq!(
+            xkb::layout_from_xorg_conf("Option \"XkbModel\" \"pc105\""),
+            None
+        );
+    }
+
+    #[test]
+    fn test_layout_changes_and_override() {
+        let controller = InputController::new();
+        let mut events = controller.subscribe(SubscriptionOptions::all());
+
+        assert!(controller.set_keyboard_layout(KeyboardLayout::DE));
+        assert!(!controller.set_keyboard_layout(KeyboardLayout::DE));
+        assert_eq!(
+            events.try_recv(),
+            Some(KeyboardLayoutEvent::Changed {
+                previous: KeyboardLayout::US,
+                current: KeyboardLayout::DE,
+                overridden: false,
+            })
+        );
+        assert!(events.try_recv().is_none());
+
+        controller.set_layout_override(Some(KeyboardLayout::US));
+        assert_eq!(controller.keyboard_layout(), KeyboardLayout::US);
+        // Host changes are tracked but do not apply while overridden
+        controller.set_keyboard_layout(KeyboardLayout::FR);
+        assert_eq!(controller.keyboard_layout(), KeyboardLayout::US);
+        assert!(matches!(
+            events.try_recv(),
+            Some(KeyboardLayoutEvent::Changed {
+                overridden: true,
+                ..
+            })
+        ));
+        assert!(events.try_recv().is_none());
+
+        controller.set_layout_override(None);
+        assert_eq!(controller.keyboard_layout(), KeyboardLayout::FR);
+    }
+
+    #[test]
+    fn test_sticky_keys_and_scroll_speed() {
+        let mut assistive = AssistiveInput::new(AccessibilitySettings {
+            sticky_keys: true,
+            scroll_speed: 2.5,
+            ..Default::default()
+        });
+        let now = Instant::now();
+        let tap = |a: &mut AssistiveInput, key: &str| {
+            a.translate(
+                InputEvent::KeyPress {
+                    key: key.to_string(),
+                    modifiers: KeyModifiers::default(),
+                },
+                now,
+            )
+        };
+        let modifiers_of = |events: Vec<InputEvent>| match &events[..] {
+            [InputEvent::KeyPress { modifiers, .. }] => modifiers.clone(),
+            other => panic!("unexpected events: {:?}", other),
+        };
+
+        assert!(tap(&mut assistive, "Shift").is_empty());
+        assert!(modifiers_of(tap(&mut assistive, "a")).shift);
+        assert!(!modifiers_of(tap(&mut assistive, "b")).shift);
+
+        // Double tap locks until tapped again
+        tap(&mut assistive, "Ctrl");
+        tap(&mut assistive, "Ctrl");
+        assert!(modifiers_of(tap(&mut assistive, "c")).ctrl);
+        assert!(modifiers_of(tap(&mut assistive, "v")).ctrl);
+        tap(&mut assistive, "Ctrl");
+        assert!(!modifiers_of(tap(&mut assistive, "x")).ctrl);
+
+        match &assistive.translate(
+            InputEvent::MouseWheel {
+                delta_x: 0,
+                delta_y: -3,
+            },
+            now,
+        )[..]
+        {
+            [InputEvent::MouseWheel { delta_x, delta_y }] => {
+                assert_eq!((*delta_x, *delta_y), (0, -8));
+            }
+            other => panic!("unexpected events: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_dwell_click_and_double_click() {
+        let mut assistive = AssistiveInput::new(AccessibilitySettings {
+            dwell_click_ms: Some(600),
+            double_click_ms: 1_000,
+            ..Default::default()
+        });
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+
+        assistive.translate(InputEvent::MouseMove { x: 100, y: 100 }, at(0));
+        assistive.translate(InputEvent::MouseMove { x: 103, y: 102 }, at(300));
+        assert!(assistive.poll_dwell(at(500)).is_none());
+        assert!(matches!(
+            assistive.poll_dwell(at(600)),
+            Some(InputEvent::MouseClick { x: 100, y: 100, .. })
+        ));
+        // Fires once per rest
+        assert!(assistive.poll_dwell(at(2_000)).is_none());
+
+        // A slow second click still counts as a double click
+        assert!(matches!(
+            assistive.translate(
+                InputEvent::MouseClick {
+                    button: MouseButton::Left,
+                    x: 101,
+                    y: 100
+                },
+                at(1_400)
+            )[..],
+            [InputEvent::MouseDoubleClick { .. }]
+        ));
+
+        assistive.translate(InputEvent::MouseMove { x: 300, y: 300 }, at(1_500));
+        assert!(assistive.poll_dwell(at(2_000)).is_none());
+        assert!(assistive.poll_dwell(at(2_100)).is_some());
+    }
+
+    #[test]
+    fn test_type_text_length_cap_and_audit() {
+        let audit_log = Arc::new(LogManager::default());
+        let mut controller = InputController::new();
+        controller.set_audit_log(audit_log.clone());
+        controller.set_text_injection_policy(TextInjectionPolicy {
+            method: TextInjectionMethod::ClipboardPaste,
+            max_length: 8,
+        });
+
+        assert_eq!(
+            controller.type_text("héllo").unwrap(),
+            TextInjectionMethod::ClipboardPaste
+        );
+        assert!(controller.type_text("far too long").is_err());
+
+        let audit = audit_log.get_logs(None, None);
+        assert_eq!(audit.len(), 2);
+        assert!(audit.iter().all(|e| e.category == "audit"));
+        assert!(!audit[0].format().contains("héllo"));
+    }
+
+    #[test]
+    fn test_sequenced_input_rejects_replays() {
+        use crate::input_sequence::InputSequencer;
+
+        let controller = InputController::new();
+        let nonce = controller.begin_sequenced_input("s1").unwrap();
+        let mut sequencer = InputSequencer::new(nonce);
+        let captured = sequencer.wrap(InputEvent::KeyPress {
+            key: "Delete".to_string(),
+            modifiers: KeyModifiers::default(),
+        });
+
+        assert_eq!(
+            controller
+                .process_sequenced_input("s1", captured.clone())
+                .unwrap(),
+            InputVerdict::Accepted
+        );
+        assert_eq!(
+            controller
+                .process_sequenced_input("s1", captured.clone())
+                .unwrap(),
+            InputVerdict::Duplicate
+        );
+        assert!(controller
+            .process_sequenced_input("other", captured.clone())
+            .is_err());
+
+        let mut result = Ok(InputVerdict::Duplicate);
+        for _ in 0..20 {
+            result = controller.process_sequenced_input("s1", captured.clone());
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(result.is_err());
+        let fresh = sequencer.wrap(InputEvent::MouseMove { x: 1, y: 1 });
+        assert!(controller.process_sequenced_input("s1", fresh).is_err());
+
+        // A new session starts with a clean slate and a new nonce
+        controller.end_sequenced_input("s1");
+        let nonce = controller.begin_sequenced_input("s1").unwrap();
+        let event = InputSequencer::new(nonce).wrap(captured.event);
+        assert_eq!(
+            controller.process_sequenced_input("s1", event).unwrap(),
+            InputVerdict::Accepted
+        );
+    }
+}
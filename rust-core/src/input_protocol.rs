@@ -0,0 +1,263 @@
+//! Input Wire Protocol
+//!
+//! Remote input crosses the control channel as `InputPacket`s: a version
+//! byte followed by the packet in bincode with variable-length integers, so
+//! a pointer move takes about a dozen bytes instead of the hundred its JSON
+//! would. The viewer sends the events `InputOptimizer` releases as one
+//! `InputBatch`; the host checks each through the session's
+//! `InputReplayGuard`, injects them in sequence order and answers with an
+//! `InputAck` echoing the send time of the newest injected event.
+//!
+//! `InputSequencer` stamps events from a monotonic clock, so the echoed time
+//! gives the viewer an input round trip that wall clock adjustments on
+//! either side cannot distort.
+
+use crate::input_control::InputEvent;
+use crate::input_sequence::SequencedInput;
+use crate::webrtc_engine::MAX_DATA_CHANNEL_MESSAGE;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+/// First byte of every packet
+pub const INPUT_PROTOCOL_VERSION: u8 = 1;
+
+/// Why an input packet could not be encoded or decoded
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum InputProtocolError {
+    #[error("Empty input packet")]
+    Empty,
+    #[error("Unsupported input protocol version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Malformed input packet: {0}")]
+    Malformed(String),
+    #[error("Input batch mixes events of different sessions")]
+    MixedSessions,
+}
+
+/// One event of a batch; the session nonce is sent once per batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchedInput {
+    pub sequence: u64,
+    pub sent_at_ms: u64,
+    pub event: InputEvent,
+}
+
+impl BatchedInput {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, InputProtocolError> {
+        options()
+            .serialize(self)
+            .map_err(|e| InputProtocolError::Malformed(e.to_string()))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, InputProtocolError> {
+        options()
+            .deserialize(bytes)
+            .map_err(|e| InputProtocolError::Malformed(e.to_string()))
+    }
+}
+
+/// Events of one session sent together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputBatch {
+    pub session_nonce: u64,
+    /// Ascending sequence numbers
+    pub events: Vec<BatchedInput>,
+}
+
+impl InputBatch {
+    /// Batch events, restoring sequence order after prioritisation
+    pub fn new(session_nonce: u64, mut events: Vec<BatchedInput>) -> Self {
+        events.sort_by_key(|input| input.sequence);
+        Self {
+            session_nonce,
+            events,
+        }
+    }
+
+    pub fn from_sequenced(inputs: Vec<SequencedInput>) -> Result<Self, InputProtocolError> {
+        let Some(session_nonce) = inputs.first().map(|input| input.session_nonce) else {
+            return Ok(Self::new(0, Vec::new()));
+        };
+        if inputs
+            .iter()
+            .any(|input| input.session_nonce != session_nonce)
+        {
+            return Err(InputProtocolError::MixedSessions);
+        }
+        let events = inputs
+            .into_iter()
+            .map(|input| BatchedInput {
+                sequence: input.sequence,
+                sent_at_ms: input.sent_at_ms,
+                event: input.event,
+            })
+            .collect();
+        Ok(Self::new(session_nonce, events))
+    }
+
+    pub fn into_sequenced(self) -> impl Iterator<Item = SequencedInput> {
+        let session_nonce = self.session_nonce;
+        self.events.into_iter().map(move |input| SequencedInput {
+            session_nonce,
+            sequence: input.sequence,
+            sent_at_ms: input.sent_at_ms,
+            event: input.event,
+        })
+    }
+}
+
+/// Host reply to a batch it injected events from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputAck {
+    pub session_nonce: u64,
+    /// Newest sequence number injected
+    pub sequence: u64,
+    /// Its `sent_at_ms`, echoed for the viewer's round trip measurement
+    pub sent_at_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum InputPacket {
+    /// Viewer to host
+    Batch(InputBatch),
+    /// Host to viewer
+    Ack(InputAck),
+}
+
+impl InputPacket {
+    pub fn encode(&self) -> Result<Vec<u8>, InputProtocolError> {
+        let mut bytes = vec![INPUT_PROTOCOL_VERSION];
+        options()
+            .serialize_into(&mut bytes, self)
+            .map_err(|e| InputProtocolError::Malformed(e.to_string()))?;
+        if bytes.len() > MAX_DATA_CHANNEL_MESSAGE {
+            return Err(InputProtocolError::Malformed(format!(
+                "{} bytes exceed the data channel message limit",
+                bytes.len()
+            )));
+        }
+        Ok(bytes)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, InputProtocolError> {
+        let (&version, body) = bytes.split_first().ok_or(InputProtocolError::Empty)?;
+        if version != INPUT_PROTOCOL_VERSION {
+            return Err(InputProtocolError::UnsupportedVersion(version));
+        }
+        options()
+            .deserialize(body)
+            .map_err(|e| InputProtocolError::Malformed(e.to_string()))
+    }
+}
+
+/// Varint encoding; the limit stops forged lengths from allocating more
+/// than a data channel message could hold
+fn options() -> impl Options {
+    bincode::DefaultOptions::new().with_limit(MAX_DATA_CHANNEL_MESSAGE as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_control::{InputController, KeyModifiers};
+    use crate::input_sequence::{InputSequencer, InputVerdict};
+    use crate::performance::{InputEventEntry, InputOptimizer};
+    use std::time::Duration;
+
+    #[test]
+    fn test_packets_are_compact_and_versioned() {
+        let mut sequencer = InputSequencer::new(u64::MAX);
+        let batch = InputBatch::from_sequenced(vec![
+            sequencer.wrap(InputEvent::MouseMove { x: 640, y: 360 }),
+            sequencer.wrap(InputEvent::KeyDown {
+                key: "A".to_string(),
+                modifiers: KeyModifiers::default(),
+            }),
+        ])
+        .unwrap();
+        let bytes = InputPacket::Batch(batch).encode().unwrap();
+        assert!(bytes.len() < 48, "{} bytes", bytes.len());
+
+        let InputPacket::Batch(decoded) = InputPacket::decode(&bytes).unwrap() else {
+            panic!("expected a batch");
+        };
+        let inputs: Vec<_> = decoded.into_sequenced().collect();
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(inputs[1].session_nonce, u64::MAX);
+        assert_eq!(inputs[1].sequence, 2);
+        assert!(matches!(inputs[1].event, InputEvent::KeyDown { .. }));
+
+        let mut future = bytes.clone();
+        future[0] = INPUT_PROTOCOL_VERSION + 1;
+        assert_eq!(
+            InputPacket::decode(&future).unwrap_err(),
+            InputProtocolError::UnsupportedVersion(INPUT_PROTOCOL_VERSION + 1)
+        );
+        assert_eq!(
+            InputPacket::decode(&[]).unwrap_err(),
+            InputProtocolError::Empty
+        );
+        assert!(InputPacket::decode(&bytes[..bytes.len() - 1]).is_err());
+
+        let foreign = InputSequencer::new(1).wrap(InputEvent::MouseMove { x: 0, y: 0 });
+        let own = sequencer.wrap(InputEvent::MouseMove { x: 0, y: 0 });
+        assert_eq!(
+            InputBatch::from_sequenced(vec![own, foreign]).unwrap_err(),
+            InputProtocolError::MixedSessions
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batches_are_injected_in_order_and_acknowledged() {
+        let host = InputController::new();
+        let nonce = host.begin_sequenced_input("session-1").unwrap();
+        let mut sequencer = InputSequencer::new(nonce);
+        let optimizer = InputOptimizer::new(100, 0);
+
+        for event in [
+            InputEvent::MouseMove { x: 1, y: 1 },
+            InputEvent::KeyDown {
+                key: "A".to_string(),
+                modifiers: KeyModifiers::default(),
+            },
+            InputEvent::MouseMove { x: 2, y: 2 },
+        ] {
+            let entry = InputEventEntry::sequenced(&sequencer.wrap(event)).unwrap();
+            optimizer.queue_event(entry).await;
+        }
+        let packet = optimizer.next_packet(nonce).await.unwrap().unwrap();
+        assert!(optimizer.next_packet(nonce).await.unwrap().is_none());
+
+        // The key is sent first by priority but sequence order is restored,
+        // and the superseded pointer move was coalesced away
+        let InputPacket::Batch(batch) = InputPacket::decode(&packet).unwrap() else {
+            panic!("expected a batch");
+        };
+        let sequences: Vec<_> = batch.events.iter().map(|input| input.sequence).collect();
+        assert_eq!(sequences, vec![2, 3]);
+
+        let reply = host
+            .process_input_packet("session-1", &packet)
+            .unwrap()
+            .unwrap();
+        let InputPacket::Ack(ack) = InputPacket::decode(&reply).unwrap() else {
+            panic!("expected an ack");
+        };
+        assert_eq!(ack.sequence, 3);
+
+        let round_trip = optimizer.record_ack(&sequencer, &ack).await.unwrap();
+        assert!(round_trip < Duration::from_secs(5));
+        assert!(optimizer.meets_latency_requirement().await);
+
+        // A replayed batch injects nothing and is not acknowledged again
+        assert!(host
+            .process_input_packet("session-1", &packet)
+            .unwrap()
+            .is_none());
+        let replayed = batch.into_sequenced().next().unwrap();
+        assert_eq!(
+            host.process_sequenced_input("session-1", replayed).unwrap(),
+            InputVerdict::Duplicate
+        );
+    }
+}
@@ -0,0 +1,321 @@
+//! Input Sequencing
+//!
+//! Plain `InputEvent`s carry nothing that ties them to a session or a
+//! position in the stream, so a captured burst could be replayed, or arrive
+//! reordered, and repeat destructive keystrokes on the host. The viewer wraps
+//! each event in a `SequencedInput` stamped with a nonce the host issued for
+//! the session, a strictly increasing sequence number and its send time. The
+//! host's `InputReplayGuard` injects an event only if it belongs to the
+//! session, is newer than everything injected so far and took at most
+//! `max_age` longer to arrive than the fastest delivery seen, which absorbs
+//! any clock offset between the peers.
+//!
+//! A few rejects happen on any lossy link. Repeated duplicates, foreign
+//! nonces or expired events within a short window are not, and mark the
+//! session as under replay attack.
+
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::input_control::InputEvent;
+use crate::input_protocol::InputAck;
+
+/// Sequence numbers below the highest seen that are still remembered
+const REPLAY_WINDOW: u64 = 64;
+
+/// Input event as sent on the input channel
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequencedInput {
+    /// Issued by the host when the session starts
+    pub session_nonce: u64,
+    /// Starts at 1 and increases by one per event
+    pub sequence: u64,
+    /// Viewer clock, milliseconds since the Unix epoch; monotonic within a
+    /// sequencer
+    pub sent_at_ms: u64,
+    pub event: InputEvent,
+}
+
+/// Stamps outgoing events on the viewer
+#[derive(Debug)]
+pub struct InputSequencer {
+    session_nonce: u64,
+    next_sequence: u64,
+    /// Wall clock when the sequencer started; later stamps add monotonic
+    /// time so clock adjustments cannot move them backwards
+    started_ms: u64,
+    started: Instant,
+}
+
+impl InputSequencer {
+    pub fn new(session_nonce: u64) -> Self {
+        Self {
+            session_nonce,
+            next_sequence: 1,
+            started_ms: unix_millis(SystemTime::now()),
+            started: Instant::now(),
+        }
+    }
+
+    pub fn session_nonce(&self) -> u64 {
+        self.session_nonce
+    }
+
+    pub fn wrap(&mut self, event: InputEvent) -> SequencedInput {
+        let sequence = self.next_sequence;
+        self.next_sequence += 1;
+        SequencedInput {
+            session_nonce: self.session_nonce,
+            sequence,
+            sent_at_ms: self.now_ms(),
+            event,
+        }
+    }
+
+    /// Time from sending an event to the host acknowledging it
+    ///
+    /// `None` for acks of another session or from the future.
+    pub fn round_trip(&self, ack: &InputAck) -> Option<Duration> {
+        if ack.session_nonce != self.session_nonce {
+            return None;
+        }
+        let elapsed = self.now_ms().checked_sub(ack.sent_at_ms)?;
+        Some(Duration::from_millis(elapsed))
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.started_ms + self.started.elapsed().as_millis() as u64
+    }
+}
+
+/// Outcome of validating one event
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum InputVerdict {
+    Accepted,
+    /// Sequence number already injected
+    Duplicate,
+    /// Arrived after a later event was injected
+    OutOfOrder,
+    /// Took longer than `max_age` beyond the fastest delivery seen
+    Expired,
+    /// Nonce of another (or no longer active) session
+    WrongSession,
+    /// The session was flagged as under attack; input is no longer accepted
+    Blocked,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayGuardConfig {
+    /// Delivery delay tolerated on top of the best one observed
+    pub max_age: Duration,
+    /// Violations within `violation_window` that count as an attack
+    pub violation_threshold: usize,
+    pub violation_window: Duration,
+}
+
+impl Default for ReplayGuardConfig {
+    fn default() -> Self {
+        Self {
+            max_age: Duration::from_secs(5),
+            violation_threshold: 10,
+            violation_window: Duration::from_secs(10),
+        }
+    }
+}
+
+/// Validates the input stream of one session on the host
+#[derive(Debug)]
+pub struct InputReplayGuard {
+    session_nonce: u64,
+    config: ReplayGuardConfig,
+    highest_sequence: u64,
+    /// Bit n set: `highest_sequence - n` was seen
+    seen: u64,
+    /// Smallest receive-minus-send difference, absorbing clock offset
+    best_offset_ms: Option<i64>,
+    violations: VecDeque<Instant>,
+    blocked: bool,
+}
+
+impl InputReplayGuard {
+    pub fn new(session_nonce: u64) -> Self {
+        Self::with_config(session_nonce, ReplayGuardConfig::default())
+    }
+
+    pub fn with_config(session_nonce: u64, config: ReplayGuardConfig) -> Self {
+        Self {
+            session_nonce,
+            config,
+            highest_sequence: 0,
+            seen: 0,
+            best_offset_ms: None,
+            violations: VecDeque::new(),
+            blocked: false,
+        }
+    }
+
+    /// Random nonce for a new session
+    pub fn generate_nonce() -> u64 {
+        rand::random()
+    }
+
+    pub fn session_nonce(&self) -> u64 {
+        self.session_nonce
+    }
+
+    /// The violation threshold was reached
+    pub fn is_blocked(&self) -> bool {
+        self.blocked
+    }
+
+    pub fn check(&mut self, input: &SequencedInput) -> InputVerdict {
+        self.check_at(input, SystemTime::now(), Instant::now())
+    }
+
+    fn check_at(&mut self, input: &SequencedInput, now: SystemTime, tick: Instant) -> InputVerdict {
+        if self.blocked {
+            return InputVerdict::Blocked;
+        }
+        let verdict = self.classify(input, now);
+        match verdict {
+            InputVerdict::Accepted | InputVerdict::Blocked => {}
+            // Reordering alone is what a lossy link does
+            InputVerdict::OutOfOrder => {
+                tracing::debug!("Dropped out-of-order input #{}", input.sequence);
+            }
+            _ => {
+                tracing::warn!("Rejected input #{}: {:?}", input.sequence, verdict);
+                self.record_violation(tick);
+            }
+        }
+        verdict
+    }
+
+    fn classify(&mut self, input: &SequencedInput, now: SystemTime) -> InputVerdict {
+        if input.session_nonce != self.session_nonce {
+            return InputVerdict::WrongSession;
+        }
+        if input.sequence == 0 {
+            return InputVerdict::Duplicate;
+        }
+        if input.sequence <= self.highest_sequence {
+            let behind = self.highest_sequence - input.sequence;
+            let seen = behind >= REPLAY_WINDOW || self.seen & (1 << behind) != 0;
+            return if seen {
+                InputVerdict::Duplicate
+            } else {
+                self.seen |= 1 << behind;
+                InputVerdict::OutOfOrder
+            };
+        }
+
+        let offset = unix_millis(now) as i64 - input.sent_at_ms as i64;
+        let best = *self.best_offset_ms.get_or_insert(offset);
+        if offset - best > self.config.max_age.as_millis() as i64 {
+            return InputVerdict::Expired;
+        }
+        self.best_offset_ms = Some(best.min(offset));
+
+        let advance = input.sequence - self.highest_sequence;
+        self.seen = if advance >= REPLAY_WINDOW {
+            1
+        } else {
+            (self.seen << advance) | 1
+        };
+        self.highest_sequence = input.sequence;
+        InputVerdict::Accepted
+    }
+
+    fn record_violation(&mut self, tick: Instant) {
+        while self
+            .violations
+            .front()
+            .is_some_and(|at| tick.duration_since(*at) > self.config.violation_window)
+        {
+            self.violations.pop_front();
+        }
+        self.violations.push_back(tick);
+        if self.violations.len() >= self.config.violation_threshold {
+            self.blocked = true;
+        }
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(sequencer: &mut InputSequencer) -> SequencedInput {
+        sequencer.wrap(InputEvent::KeyPress {
+            key: "Delete".to_string(),
+            modifiers: Default::default(),
+        })
+    }
+
+    #[test]
+    fn test_duplicates_reordering_and_foreign_sessions() {
+        let mut sequencer = InputSequencer::new(7);
+        let mut guard = InputReplayGuard::new(7);
+
+        let first = key(&mut sequencer);
+        let second = key(&mut sequencer);
+        let third = key(&mut sequencer);
+        assert_eq!(guard.check(&first), InputVerdict::Accepted);
+        assert_eq!(guard.check(&third), InputVerdict::Accepted);
+        assert_eq!(guard.check(&second), InputVerdict::OutOfOrder);
+        assert_eq!(guard.check(&second), InputVerdict::Duplicate);
+        assert_eq!(guard.check(&first), InputVerdict::Duplicate);
+
+        let foreign = InputSequencer::new(8).wrap(first.event.clone());
+        assert_eq!(guard.check(&foreign), InputVerdict::WrongSession);
+        assert!(!guard.is_blocked());
+    }
+
+    #[test]
+    fn test_freshness_tolerates_clock_offset() {
+        let mut guard = InputReplayGuard::new(1);
+        let now = SystemTime::now();
+        let tick = Instant::now();
+        // Viewer clock an hour behind the host
+        let event = |sequence: u64, delay: u64| SequencedInput {
+            session_nonce: 1,
+            sequence,
+            sent_at_ms: unix_millis(now) - 3_600_000 - delay,
+            event: InputEvent::MouseMove { x: 0, y: 0 },
+        };
+        assert_eq!(
+            guard.check_at(&event(1, 20), now, tick),
+            InputVerdict::Accepted
+        );
+        assert_eq!(
+            guard.check_at(&event(2, 900), now, tick),
+            InputVerdict::Accepted
+        );
+        assert_eq!(
+            guard.check_at(&event(3, 30_000), now, tick),
+            InputVerdict::Expired
+        );
+    }
+
+    #[test]
+    fn test_systematic_replay_blocks_session() {
+        let mut sequencer = InputSequencer::new(3);
+        let mut guard = InputReplayGuard::new(3);
+        let captured = key(&mut sequencer);
+        assert_eq!(guard.check(&captured), InputVerdict::Accepted);
+
+        for _ in 0..ReplayGuardConfig::default().violation_threshold {
+            guard.check(&captured);
+        }
+        assert!(guard.is_blocked());
+        assert_eq!(guard.check(&key(&mut sequencer)), InputVerdict::Blocked);
+    }
+}
@@ -0,0 +1,622 @@
+//! Keyboard Layout Translation
+//!
+//! Remote key events name physical keys by their US QWERTY label: "Y" is the
+//! key right of T, whatever it prints. Forwarding positions only works when
+//! both ends use the same layout; a German controller typing z presses "Y",
+//! which a US host turns into y. `KeyTranslator` resolves each key to the
+//! character it produces on the controller's layout, including Shift and
+//! AltGr levels and dead-key composition, and presses the keys producing
+//! that character on the host's layout instead. Characters the host layout
+//! cannot type are injected as Unicode.
+//!
+//! Shortcuts (Ctrl, Alt or Meta held) follow the letter rather than the
+//! position, so Ctrl+Z undoes on every pair of layouts. Keys that produce no
+//! character (arrows, function keys, Enter, modifiers) pass through as is.
+
+use crate::input_control::{KeyModifiers, KeyboardLayout};
+use std::collections::HashMap;
+
+/// What a key produces at one level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeySym {
+    Char(char),
+    /// Accent applied to the next character
+    Dead(char),
+}
+
+/// Symbols at the base, Shift, AltGr and Shift+AltGr levels
+type Levels = [Option<KeySym>; 4];
+
+const N: Option<KeySym> = None;
+
+const fn c(ch: char) -> Option<KeySym> {
+    Some(KeySym::Char(ch))
+}
+
+const fn d(accent: char) -> Option<KeySym> {
+    Some(KeySym::Dead(accent))
+}
+
+const LETTERS: [&str; 26] = [
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S",
+    "T", "U", "V", "W", "X", "Y", "Z",
+];
+
+/// Non-letter keys of each layout, plus letters that differ from QWERTY
+const US_KEYS: &[(&str, Levels)] = &[
+    ("`", [c('`'), c('~'), N, N]),
+    ("1", [c('1'), c('!'), N, N]),
+    ("2", [c('2'), c('@'), N, N]),
+    ("3", [c('3'), c('#'), N, N]),
+    ("4", [c('4'), c('$'), N, N]),
+    ("5", [c('5'), c('%'), N, N]),
+    ("6", [c('6'), c('^'), N, N]),
+    ("7", [c('7'), c('&'), N, N]),
+    ("8", [c('8'), c('*'), N, N]),
+    ("9", [c('9'), c('('), N, N]),
+    ("0", [c('0'), c(')'), N, N]),
+    ("-", [c('-'), c('_'), N, N]),
+    ("=", [c('='), c('+'), N, N]),
+    ("[", [c('['), c('{'), N, N]),
+    ("]", [c(']'), c('}'), N, N]),
+    ("\\", [c('\\'), c('|'), N, N]),
+    (";", [c(';'), c(':'), N, N]),
+    ("'", [c('\''), c('"'), N, N]),
+    (",", [c(','), c('<'), N, N]),
+    (".", [c('.'), c('>'), N, N]),
+    ("/", [c('/'), c('?'), N, N]),
+];
+
+const UK_KEYS: &[(&str, Levels)] = &[
+    ("`", [c('`'), c('¬'), c('¦'), N]),
+    ("1", [c('1'), c('!'), N, N]),
+    ("2", [c('2'), c('"'), N, N]),
+    ("3", [c('3'), c('£'), N, N]),
+    ("4", [c('4'), c('$'), c('€'), N]),
+    ("5", [c('5'), c('%'), N, N]),
+    ("6", [c('6'), c('^'), N, N]),
+    ("7", [c('7'), c('&'), N, N]),
+    ("8", [c('8'), c('*'), N, N]),
+    ("9", [c('9'), c('('), N, N]),
+    ("0", [c('0'), c(')'), N, N]),
+    ("-", [c('-'), c('_'), N, N]),
+    ("=", [c('='), c('+'), N, N]),
+    ("[", [c('['), c('{'), N, N]),
+    ("]", [c(']'), c('}'), N, N]),
+    ("\\", [c('#'), c('~'), N, N]),
+    (";", [c(';'), c(':'), N, N]),
+    ("'", [c('\''), c('@'), N, N]),
+    (",", [c(','), c('<'), N, N]),
+    (".", [c('.'), c('>'), N, N]),
+    ("/", [c('/'), c('?'), N, N]),
+    ("IntlBackslash", [c('\\'), c('|'), N, N]),
+];
+
+const DE_KEYS: &[(&str, Levels)] = &[
+    ("`", [d('^'), c('°'), N, N]),
+    ("1", [c('1'), c('!'), N, N]),
+    ("2", [c('2'), c('"'), c('²'), N]),
+    ("3", [c('3'), c('§'), c('³'), N]),
+    ("4", [c('4'), c('$'), N, N]),
+    ("5", [c('5'), c('%'), N, N]),
+    ("6", [c('6'), c('&'), N, N]),
+    ("7", [c('7'), c('/'), c('{'), N]),
+    ("8", [c('8'), c('('), c('['), N]),
+    ("9", [c('9'), c(')'), c(']'), N]),
+    ("0", [c('0'), c('='), c('}'), N]),
+    ("-", [c('ß'), c('?'), c('\\'), N]),
+    ("=", [d('´'), d('`'), N, N]),
+    ("[", [c('ü'), c('Ü'), N, N]),
+    ("]", [c('+'), c('*'), c('~'), N]),
+    ("\\", [c('#'), c('\''), N, N]),
+    (";", [c('ö'), c('Ö'), N, N]),
+    ("'", [c('ä'), c('Ä'), N, N]),
+    (",", [c(','), c(';'), N, N]),
+    (".", [c('.'), c(':'), N, N]),
+    ("/", [c('-'), c('_'), N, N]),
+    ("IntlBackslash", [c('<'), c('>'), c('|'), N]),
+    ("Q", [c('q'), c('Q'), c('@'), N]),
+    ("E", [c('e'), c('E'), c('€'), N]),
+    ("M", [c('m'), c('M'), c('µ'), N]),
+    ("Y", [c('z'), c('Z'), N, N]),
+    ("Z", [c('y'), c('Y'), N, N]),
+];
+
+const FR_KEYS: &[(&str, Levels)] = &[
+    ("`", [c('²'), N, N, N]),
+    ("1", [c('&'), c('1'), N, N]),
+    ("2", [c('é'), c('2'), d('~'), N]),
+    ("3", [c('"'), c('3'), c('#'), N]),
+    ("4", [c('\''), c('4'), c('{'), N]),
+    ("5", [c('('), c('5'), c('['), N]),
+    ("6", [c('-'), c('6'), c('|'), N]),
+    ("7", [c('è'), c('7'), d('`'), N]),
+    ("8", [c('_'), c('8'), c('\\'), N]),
+    ("9", [c('ç'), c('9'), c('^'), N]),
+    ("0", [c('à'), c('0'), c('@'), N]),
+    ("-", [c(')'), c('°'), c(']'), N]),
+    ("=", [c('='), c('+'), c('}'), N]),
+    ("[", [d('^'), d('¨'), N, N]),
+    ("]", [c('$'), c('£'), c('¤'), N]),
+    ("\\", [c('*'), c('µ'), N, N]),
+    (";", [c('m'), c('M'), N, N]),
+    ("'", [c('ù'), c('%'), N, N]),
+    (",", [c(';'), c('.'), N, N]),
+    (".", [c(':'), c('/'), N, N]),
+    ("/", [c('!'), c('§'), N, N]),
+    ("IntlBackslash", [c('<'), c('>'), N, N]),
+    ("A", [c('q'), c('Q'), N, N]),
+    ("Q", [c('a'), c('A'), N, N]),
+    ("Z", [c('w'), c('W'), N, N]),
+    ("W", [c('z'), c('Z'), N, N]),
+    ("E", [c('e'), c('E'), c('€'), N]),
+    ("M", [c(','), c('?'), N, N]),
+];
+
+/// JIS layout; the key left of 1 switches the IME and prints nothing
+const JP_KEYS: &[(&str, Levels)] = &[
+    ("1", [c('1'), c('!'), N, N]),
+    ("2", [c('2'), c('"'), N, N]),
+    ("3", [c('3'), c('#'), N, N]),
+    ("4", [c('4'), c('$'), N, N]),
+    ("5", [c('5'), c('%'), N, N]),
+    ("6", [c('6'), c('&'), N, N]),
+    ("7", [c('7'), c('\''), N, N]),
+    ("8", [c('8'), c('('), N, N]),
+    ("9", [c('9'), c(')'), N, N]),
+    ("0", [c('0'), N, N, N]),
+    ("-", [c('-'), c('='), N, N]),
+    ("=", [c('^'), c('~'), N, N]),
+    ("IntlYen", [c('¥'), c('|'), N, N]),
+    ("[", [c('@'), c('`'), N, N]),
+    ("]", [c('['), c('{'), N, N]),
+    (";", [c(';'), c('+'), N, N]),
+    ("'", [c(':'), c('*'), N, N]),
+    ("\\", [c(']'), c('}'), N, N]),
+    (",", [c(','), c('<'), N, N]),
+    (".", [c('.'), c('>'), N, N]),
+    ("/", [c('/'), c('?'), N, N]),
+    ("IntlRo", [c('\\'), c('_'), N, N]),
+];
+
+/// Dead-key accents with the characters they combine with and the results
+const COMPOSE: &[(char, &str, &str)] = &[
+    ('^', "aeiouAEIOU", "âêîôûÂÊÎÔÛ"),
+    ('´', "aeiouyAEIOUY", "áéíóúýÁÉÍÓÚÝ"),
+    ('`', "aeiouAEIOU", "àèìòùÀÈÌÒÙ"),
+    ('¨', "aeiouyAEIOU", "äëïöüÿÄËÏÖÜ"),
+    ('~', "anoANO", "ãñõÃÑÕ"),
+];
+
+fn layout_keys(layout: KeyboardLayout) -> &'static [(&'static str, Levels)] {
+    match layout {
+        // Chinese input methods run on the US layout
+        KeyboardLayout::US | KeyboardLayout::CN => US_KEYS,
+        KeyboardLayout::UK => UK_KEYS,
+        KeyboardLayout::DE => DE_KEYS,
+        KeyboardLayout::FR => FR_KEYS,
+        KeyboardLayout::JP => JP_KEYS,
+    }
+}
+
+/// Symbols of `key` on `layout`; `None` for keys that print nothing
+fn key_levels(layout: KeyboardLayout, key: &str) -> Option<Levels> {
+    if key == "Space" {
+        return Some([c(' '), c(' '), N, N]);
+    }
+    let letter = match key.as_bytes() {
+        [b] if b.is_ascii_alphabetic() => Some(b.to_ascii_uppercase() as char),
+        _ => None,
+    };
+    let name = letter.map(String::from);
+    let name = name.as_deref().unwrap_or(key);
+    if let Some((_, levels)) = layout_keys(layout).iter().find(|(k, _)| *k == name) {
+        return Some(*levels);
+    }
+    letter.map(|upper| [c(upper.to_ascii_lowercase()), c(upper), N, N])
+}
+
+/// Every character key of `layout` with its symbols
+fn all_keys(layout: KeyboardLayout) -> impl Iterator<Item = (&'static str, Levels)> {
+    let table = layout_keys(layout);
+    let letters = LETTERS
+        .iter()
+        .filter(move |letter| !table.iter().any(|(k, _)| k == *letter))
+        .filter_map(move |letter| Some((*letter, key_levels(layout, letter)?)));
+    table
+        .iter()
+        .copied()
+        .chain(letters)
+        .chain(std::iter::once(("Space", [c(' '), c(' '), N, N])))
+}
+
+fn level_of(modifiers: &KeyModifiers) -> usize {
+    (modifiers.shift as usize) | ((modifiers.altgr as usize) << 1)
+}
+
+fn level_modifiers(level: usize) -> KeyModifiers {
+    KeyModifiers {
+        shift: level & 1 != 0,
+        altgr: level & 2 != 0,
+        ..Default::default()
+    }
+}
+
+/// Key and modifiers producing `sym` on `layout`, preferring lower levels
+fn find_key(sym: KeySym, layout: KeyboardLayout) -> Option<(String, KeyModifiers)> {
+    (0..4).find_map(|level| {
+        all_keys(layout)
+            .find(|(_, levels)| levels[level] == Some(sym))
+            .map(|(key, _)| (key.to_string(), level_modifiers(level)))
+    })
+}
+
+fn compose(accent: char, base: char) -> Option<char> {
+    if base == ' ' {
+        return Some(accent);
+    }
+    let (_, bases, composed) = COMPOSE.iter().find(|(a, _, _)| *a == accent)?;
+    let index = bases.chars().position(|b| b == base)?;
+    composed.chars().nth(index)
+}
+
+/// Keys typing `c` on `layout`: a single key or a dead key followed by the
+/// base character; `None` if the layout cannot type it
+pub fn keys_for_char(c: char, layout: KeyboardLayout) -> Option<Vec<(String, KeyModifiers)>> {
+    match c {
+        '\n' => return Some(vec![("Enter".to_string(), KeyModifiers::default())]),
+        '\t' => return Some(vec![("Tab".to_string(), KeyModifiers::default())]),
+        _ => {}
+    }
+    if let Some(key) = find_key(KeySym::Char(c), layout) {
+        return Some(vec![key]);
+    }
+    COMPOSE.iter().find_map(|(accent, bases, composed)| {
+        let base = if c == *accent {
+            ' '
+        } else {
+            let index = composed.chars().position(|x| x == c)?;
+            bases.chars().nth(index)?
+        };
+        Some(vec![
+            find_key(KeySym::Dead(*accent), layout)?,
+            find_key(KeySym::Char(base), layout)?,
+        ])
+    })
+}
+
+/// Host-side effect of a translated key event
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyAction {
+    Down {
+        key: String,
+        modifiers: KeyModifiers,
+    },
+    Up {
+        key: String,
+        modifiers: KeyModifiers,
+    },
+    Press {
+        key: String,
+        modifiers: KeyModifiers,
+    },
+    /// Character the host layout cannot type
+    Unicode(char),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyPhase {
+    Down,
+    Up,
+    Press,
+}
+
+/// Translates one controller's key events from its layout to the host's
+#[derive(Debug)]
+pub struct KeyTranslator {
+    source: KeyboardLayout,
+    /// Dead key waiting for the character it applies to
+    pending_dead: Option<char>,
+    /// Controller keys that are down, with the host key held for each;
+    /// `None` if their down was fully typed already
+    held: HashMap<String, Option<(String, KeyModifiers)>>,
+}
+
+impl KeyTranslator {
+    pub fn new(source: KeyboardLayout) -> Self {
+        Self {
+            source,
+            pending_dead: None,
+            held: HashMap::new(),
+        }
+    }
+
+    /// Layout of the controller
+    pub fn source_layout(&self) -> KeyboardLayout {
+        self.source
+    }
+
+    pub fn key_down(
+        &mut self,
+        key: &str,
+        modifiers: &KeyModifiers,
+        host: KeyboardLayout,
+    ) -> Vec<KeyAction> {
+        self.translate(KeyPhase::Down, key, modifiers, host)
+    }
+
+    pub fn key_up(
+        &mut self,
+        key: &str,
+        modifiers: &KeyModifiers,
+        host: KeyboardLayout,
+    ) -> Vec<KeyAction> {
+        self.translate(KeyPhase::Up, key, modifiers, host)
+    }
+
+    pub fn key_press(
+        &mut self,
+        key: &str,
+        modifiers: &KeyModifiers,
+        host: KeyboardLayout,
+    ) -> Vec<KeyAction> {
+        self.translate(KeyPhase::Press, key, modifiers, host)
+    }
+
+    fn translate(
+        &mut self,
+        phase: KeyPhase,
+        key: &str,
+        modifiers: &KeyModifiers,
+        host: KeyboardLayout,
+    ) -> Vec<KeyAction> {
+        if phase == KeyPhase::Up {
+            return match self.held.remove(key) {
+                Some(Some((key, modifiers))) => vec![KeyAction::Up { key, modifiers }],
+                Some(None) => Vec::new(),
+                None => vec![key_action(phase, key.to_string(), modifiers.clone())],
+            };
+        }
+
+        let levels = key_levels(self.source, key);
+        if modifiers.ctrl || modifiers.meta || (modifiers.alt && !modifiers.altgr) {
+            // Shortcuts follow the unshifted character, not the position
+            let host_key = match levels.and_then(|levels| levels[0]) {
+                Some(KeySym::Char(c)) => find_key(KeySym::Char(c), host)
+                    .filter(|(_, host_modifiers)| *host_modifiers == KeyModifiers::default())
+                    .map(|(host_key, _)| host_key),
+                _ => None,
+            };
+            return vec![key_action(
+                phase,
+                host_key.unwrap_or_else(|| key.to_string()),
+                modifiers.clone(),
+            )];
+        }
+
+        let Some(sym) = levels.and_then(|levels| levels[level_of(modifiers)]) else {
+            // Keys printing nothing cancel a pending dead key, modifiers
+            // pressed for the next character do not
+            if !is_modifier_key(key) {
+                self.pending_dead = None;
+            }
+            return vec![key_action(phase, key.to_string(), modifiers.clone())];
+        };
+
+        let chars = match (sym, self.pending_dead.take()) {
+            (KeySym::Dead(accent), None) => {
+                self.pending_dead = Some(accent);
+                Vec::new()
+            }
+            // The same dead key twice types its accent
+            (KeySym::Dead(accent), Some(pending)) if accent == pending => vec![accent],
+            (KeySym::Dead(accent), Some(pending)) => {
+                self.pending_dead = Some(accent);
+                vec![pending]
+            }
+            (KeySym::Char(c), Some(pending)) => match compose(pending, c) {
+                Some(composed) => vec![composed],
+                None => vec![pending, c],
+            },
+            (KeySym::Char(c), None) => vec![c],
+        };
+
+        // A single host key is held as long as the controller key
+        if let ([c], KeyPhase::Down) = (chars.as_slice(), phase) {
+            if let Some((host_key, host_modifiers)) = find_key(KeySym::Char(*c), host) {
+                self.held.insert(
+                    key.to_string(),
+                    Some((host_key.clone(), host_modifiers.clone())),
+                );
+                return vec![KeyAction::Down {
+                    key: host_key,
+                    modifiers: host_modifiers,
+                }];
+            }
+        }
+        if phase == KeyPhase::Down {
+            self.held.insert(key.to_string(), None);
+        }
+        chars.into_iter().flat_map(|c| type_char(c, host)).collect()
+    }
+}
+
+fn key_action(phase: KeyPhase, key: String, modifiers: KeyModifiers) -> KeyAction {
+    match phase {
+        KeyPhase::Down => KeyAction::Down { key, modifiers },
+        KeyPhase::Up => KeyAction::Up { key, modifiers },
+        KeyPhase::Press => KeyAction::Press { key, modifiers },
+    }
+}
+
+/// Actions typing `c` on the host, falling back to Unicode injection
+fn type_char(c: char, host: KeyboardLayout) -> Vec<KeyAction> {
+    match keys_for_char(c, host) {
+        Some(keys) => keys
+            .into_iter()
+            .map(|(key, modifiers)| KeyAction::Press { key, modifiers })
+            .collect(),
+        None => vec![KeyAction::Unicode(c)],
+    }
+}
+
+fn is_modifier_key(key: &str) -> bool {
+    matches!(
+        key.to_ascii_lowercase().as_str(),
+        "shift"
+            | "ctrl"
+            | "control"
+            | "alt"
+            | "option"
+            | "altgr"
+            | "meta"
+            | "command"
+            | "cmd"
+            | "super"
+            | "win"
+            | "capslock"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn press(key: &str, modifiers: KeyModifiers) -> KeyAction {
+        KeyAction::Press {
+            key: key.to_string(),
+            modifiers,
+        }
+    }
+
+    fn shift() -> KeyModifiers {
+        KeyModifiers {
+            shift: true,
+            ..Default::default()
+        }
+    }
+
+    fn altgr() -> KeyModifiers {
+        KeyModifiers {
+            altgr: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_keys_for_char_layouts() {
+        let none = KeyModifiers::default();
+        assert_eq!(
+            keys_for_char('A', KeyboardLayout::US),
+            Some(vec![("A".to_string(), shift())])
+        );
+        assert_eq!(
+            keys_for_char('?', KeyboardLayout::US),
+            Some(vec![("/".to_string(), shift())])
+        );
+        assert_eq!(
+            keys_for_char('z', KeyboardLayout::DE),
+            Some(vec![("Y".to_string(), none.clone())])
+        );
+        assert_eq!(
+            keys_for_char('a', KeyboardLayout::FR),
+            Some(vec![("Q".to_string(), none.clone())])
+        );
+        assert_eq!(
+            keys_for_char('m', KeyboardLayout::FR),
+            Some(vec![(";".to_string(), none.clone())])
+        );
+        assert_eq!(
+            keys_for_char('?', KeyboardLayout::DE),
+            Some(vec![("-".to_string(), shift())])
+        );
+        assert_eq!(
+            keys_for_char('@', KeyboardLayout::DE),
+            Some(vec![("Q".to_string(), altgr())])
+        );
+        // Composed through the host's dead keys
+        assert_eq!(
+            keys_for_char('ê', KeyboardLayout::FR),
+            Some(vec![
+                ("[".to_string(), none.clone()),
+                ("E".to_string(), none.clone())
+            ])
+        );
+        assert_eq!(
+            keys_for_char('^', KeyboardLayout::DE),
+            Some(vec![
+                ("`".to_string(), none.clone()),
+                ("Space".to_string(), none)
+            ])
+        );
+        assert_eq!(keys_for_char('é', KeyboardLayout::US), None);
+        assert_eq!(keys_for_char('¥', KeyboardLayout::US), None);
+    }
+
+    #[test]
+    fn test_translation_between_layouts() {
+        let none = KeyModifiers::default();
+        let mut translator = KeyTranslator::new(KeyboardLayout::DE);
+        assert_eq!(translator.source_layout(), KeyboardLayout::DE);
+
+        // z on QWERTZ is held as z on the US host and released with it
+        assert_eq!(
+            translator.key_down("Y", &none, KeyboardLayout::US),
+            vec![KeyAction::Down {
+                key: "Z".to_string(),
+                modifiers: none.clone(),
+            }]
+        );
+        assert_eq!(
+            translator.key_up("Y", &none, KeyboardLayout::US),
+            vec![KeyAction::Up {
+                key: "Z".to_string(),
+                modifiers: none.clone(),
+            }]
+        );
+
+        // AltGr+Q types @, Shift+2 on the US host
+        assert_eq!(
+            translator.key_press("Q", &altgr(), KeyboardLayout::US),
+            vec![press("2", shift())]
+        );
+
+        // Dead acute then e composes é, absent on US
+        assert!(translator
+            .key_down("=", &none, KeyboardLayout::US)
+            .is_empty());
+        assert!(translator.key_up("=", &none, KeyboardLayout::US).is_empty());
+        assert_eq!(
+            translator.key_down("E", &none, KeyboardLayout::US),
+            vec![KeyAction::Unicode('é')]
+        );
+        assert!(translator.key_up("E", &none, KeyboardLayout::US).is_empty());
+
+        // Dead circumflex followed by a consonant types both
+        translator.key_press("`", &none, KeyboardLayout::US);
+        assert_eq!(
+            translator.key_press("S", &none, KeyboardLayout::US),
+            vec![press("6", shift()), press("S", none.clone())]
+        );
+
+        // Ctrl+Z follows the letter, arrows pass through
+        let ctrl = KeyModifiers {
+            ctrl: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            translator.key_press("Y", &ctrl, KeyboardLayout::US),
+            vec![press("Z", ctrl)]
+        );
+        assert_eq!(
+            translator.key_press("ArrowLeft", &none, KeyboardLayout::US),
+            vec![press("ArrowLeft", none.clone())]
+        );
+
+        // ü from a German controller reaches a French host as dead ¨ + u
+        assert_eq!(
+            translator.key_press("[", &none, KeyboardLayout::FR),
+            vec![press("[", shift()), press("U", none)]
+        );
+    }
+}
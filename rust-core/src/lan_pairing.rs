@@ -0,0 +1,668 @@
+//! LAN-only Pairing
+//!
+//! Pairs a controller with a host on an isolated network without a signaling
+//! server. The host shows a QR code carrying a [`PairingDescriptor`]: its LAN
+//! addresses, certificate fingerprint, an ephemeral X25519 public key and a
+//! one-time secret. The controller scans it, connects straight to one of the
+//! addresses and runs a single round trip:
+//!
+//! ```text
+//! controller → host   PairingHello    (ephemeral key, proof of the QR secret)
+//! host → controller   PairingConfirm  (certificate, signature, proof of the ephemeral key)
+//! ```
+//!
+//! Both proofs are keyed from the X25519 shared secret salted with the QR
+//! secret, so the host learns that the controller saw the QR code, and the
+//! controller learns that it is talking to the device whose certificate
+//! fingerprint was in it. Both sides end up with the same session key.
+
+use crate::security::DeviceCertificate;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use x25519_dalek::{EphemeralSecret, PublicKey, ReusableSecret};
+
+/// Prefix of the QR payload
+pub const PAIRING_URI_PREFIX: &str = "cecpair:";
+pub const PAIRING_PROTOCOL_VERSION: u8 = 1;
+/// How long a displayed QR code stays valid
+pub const DEFAULT_PAIRING_TTL: Duration = Duration::from_secs(300);
+/// Failed hellos tolerated before the pairing is invalidated
+pub const MAX_PAIRING_ATTEMPTS: u32 = 3;
+
+const MAX_MESSAGE_BYTES: u64 = 8 * 1024;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Why pairing failed
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PairingError {
+    #[error("Not a pairing code")]
+    InvalidPayload,
+    #[error("Unsupported pairing protocol version {0}")]
+    UnsupportedVersion(u8),
+    #[error("Pairing code has expired")]
+    Expired,
+    #[error("Pairing code has already been used or was invalidated")]
+    Consumed,
+    #[error("Pairing proof did not verify")]
+    ProofMismatch,
+    #[error("Host certificate does not match the pairing code")]
+    FingerprintMismatch,
+    #[error("Device certificate has no signing key")]
+    MissingSigningKey,
+    #[error("Malformed pairing message: {0}")]
+    Malformed(String),
+    #[error("Could not reach the host at any advertised address")]
+    Unreachable,
+    #[error("Pairing I/O failed: {0}")]
+    Io(String),
+}
+
+impl From<std::io::Error> for PairingError {
+    fn from(err: std::io::Error) -> Self {
+        PairingError::Io(err.to_string())
+    }
+}
+
+/// Contents of the host's pairing QR code
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairingDescriptor {
+    pub version: u8,
+    pub device_id: String,
+    pub device_name: String,
+    /// Addresses the host listens on, in order of preference
+    pub addresses: Vec<SocketAddr>,
+    /// mDNS host name, tried when none of the addresses is reachable
+    #[serde(default)]
+    pub mdns_name: Option<String>,
+    /// Fingerprint of the host's device certificate
+    pub cert_fingerprint: String,
+    /// Hex encoded ephemeral X25519 public key
+    pub ephemeral_public_key: String,
+    /// Hex encoded one-time secret
+    pub pairing_secret: String,
+    /// Unix timestamp (seconds) after which the code is rejected
+    pub expires_at: i64,
+}
+
+impl PairingDescriptor {
+    /// Text to encode in the QR code
+    pub fn to_qr_payload(&self) -> String {
+        let json = serde_json::to_vec(self).expect("descriptor serializes");
+        format!(
+            "{}{}",
+            PAIRING_URI_PREFIX,
+            base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+        )
+    }
+
+    /// Parse a scanned QR code, rejecting expired or unsupported codes
+    pub fn from_qr_payload(payload: &str) -> Result<Self, PairingError> {
+        let encoded = payload
+            .trim()
+            .strip_prefix(PAIRING_URI_PREFIX)
+            .ok_or(PairingError::InvalidPayload)?;
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(encoded)
+            .map_err(|_| PairingError::InvalidPayload)?;
+        let descriptor: PairingDescriptor =
+            serde_json::from_slice(&json).map_err(|_| PairingError::InvalidPayload)?;
+
+        if descriptor.version != PAIRING_PROTOCOL_VERSION {
+            return Err(PairingError::UnsupportedVersion(descriptor.version));
+        }
+        if descriptor.is_expired() {
+            return Err(PairingError::Expired);
+        }
+        Ok(descriptor)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        chrono::Utc::now().timestamp() > self.expires_at
+    }
+}
+
+/// Controller's first message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairingHello {
+    pub device_id: String,
+    pub device_name: String,
+    pub ephemeral_public_key: String,
+    pub proof: String,
+}
+
+/// Host's reply to a valid hello
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairingConfirm {
+    /// Certificate public key, hashed with `verifying_key` into the fingerprint
+    pub public_key: String,
+    pub verifying_key: String,
+    /// Ed25519 signature over the handshake transcript
+    pub signature: String,
+    pub proof: String,
+}
+
+/// Reply sent on the wire: a confirm, or the reason the hello was rejected
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+enum PairingReply {
+    Confirm(PairingConfirm),
+    Rejected { reason: String },
+}
+
+/// Outcome of a completed pairing
+#[derive(Debug, Clone)]
+pub struct PairedPeer {
+    pub device_id: String,
+    pub device_name: String,
+    /// Certificate fingerprint of the host; `None` on the host side
+    pub cert_fingerprint: Option<String>,
+    /// Shared 32-byte key for the direct connection
+    pub session_key: Vec<u8>,
+}
+
+/// Host side of a pairing; one instance per displayed QR code
+pub struct LanPairingHost {
+    descriptor: PairingDescriptor,
+    secret: Mutex<Option<ReusableSecret>>,
+    pairing_secret: [u8; 16],
+    signing_key: SigningKey,
+    public_key: Vec<u8>,
+    verifying_key: Vec<u8>,
+    failed_attempts: Mutex<u32>,
+}
+
+impl LanPairingHost {
+    pub fn new(
+        certificate: &DeviceCertificate,
+        device_name: String,
+        addresses: Vec<SocketAddr>,
+    ) -> Result<Self, PairingError> {
+        Self::with_ttl(certificate, device_name, addresses, DEFAULT_PAIRING_TTL)
+    }
+
+    pub fn with_ttl(
+        certificate: &DeviceCertificate,
+        device_name: String,
+        addresses: Vec<SocketAddr>,
+        ttl: Duration,
+    ) -> Result<Self, PairingError> {
+        let signing_key = certificate
+            .signing_key
+            .as_deref()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .map(|bytes| SigningKey::from_bytes(&bytes))
+            .ok_or(PairingError::MissingSigningKey)?;
+
+        let secret = ReusableSecret::random_from_rng(OsRng);
+        let mut pairing_secret = [0u8; 16];
+        OsRng.fill_bytes(&mut pairing_secret);
+
+        let descriptor = PairingDescriptor {
+            version: PAIRING_PROTOCOL_VERSION,
+            device_id: certificate.device_id.clone(),
+            device_name,
+            addresses,
+            mdns_name: None,
+            cert_fingerprint: certificate.fingerprint.clone(),
+            ephemeral_public_key: hex::encode(PublicKey::from(&secret).as_bytes()),
+            pairing_secret: hex::encode(pairing_secret),
+            expires_at: chrono::Utc::now().timestamp() + ttl.as_secs() as i64,
+        };
+
+        Ok(Self {
+            descriptor,
+            secret: Mutex::new(Some(secret)),
+            pairing_secret,
+            signing_key,
+            public_key: certificate.public_key.clone(),
+            verifying_key: certificate.verifying_key.clone(),
+            failed_attempts: Mutex::new(0),
+        })
+    }
+
+    /// Advertise an mDNS name alongside the addresses
+    pub fn with_mdns_name(mut self, name: String) -> Self {
+        self.descriptor.mdns_name = Some(name);
+        self
+    }
+
+    pub fn descriptor(&self) -> &PairingDescriptor {
+        &self.descriptor
+    }
+
+    pub fn qr_payload(&self) -> String {
+        self.descriptor.to_qr_payload()
+    }
+
+    /// Whether the code can still be used
+    pub fn is_active(&self) -> bool {
+        !self.descriptor.is_expired() && self.secret.lock().unwrap().is_some()
+    }
+
+    /// Check a controller's hello and produce the confirm
+    ///
+    /// The pairing is single use: it is consumed on success, and invalidated
+    /// after [`MAX_PAIRING_ATTEMPTS`] bad proofs.
+    pub fn accept_hello(
+        &self,
+        hello: &PairingHello,
+    ) -> Result<(PairingConfirm, PairedPeer), PairingError> {
+        if self.descriptor.is_expired() {
+            return Err(PairingError::Expired);
+        }
+        let mut slot = self.secret.lock().unwrap();
+        let secret = slot.as_ref().ok_or(PairingError::Consumed)?;
+
+        let controller_public = decode_key(&hello.ephemeral_public_key)?;
+        let shared = secret.diffie_hellman(&PublicKey::from(controller_public));
+        let transcript = transcript_hash(
+            &self.descriptor.ephemeral_public_key,
+            &hello.ephemeral_public_key,
+            &self.descriptor.device_id,
+            &hello.device_id,
+        );
+        let keys = PairingKeys::derive(&self.pairing_secret, shared.as_bytes());
+
+        let proof = decode_hex(&hello.proof)?;
+        if !keys.verify(b"controller", &transcript, &proof) {
+            let mut failed = self.failed_attempts.lock().unwrap();
+            *failed += 1;
+            if *failed >= MAX_PAIRING_ATTEMPTS {
+                *slot = None;
+                tracing::warn!("LAN pairing invalidated after {} bad attempts", *failed);
+            }
+            return Err(PairingError::ProofMismatch);
+        }
+        *slot = None;
+
+        let confirm = PairingConfirm {
+            public_key: hex::encode(&self.public_key),
+            verifying_key: hex::encode(&self.verifying_key),
+            signature: hex::encode(self.signing_key.sign(&transcript).to_bytes()),
+            proof: hex::encode(keys.prove(b"host", &transcript)),
+        };
+        tracing::info!("LAN pairing completed with device {}", hello.device_id);
+
+        Ok((
+            confirm,
+            PairedPeer {
+                device_id: hello.device_id.clone(),
+                device_name: hello.device_name.clone(),
+                cert_fingerprint: None,
+                session_key: keys.session_key,
+            },
+        ))
+    }
+
+    /// Run the host side of the handshake on an accepted connection
+    pub async fn serve<S>(&self, stream: &mut S) -> Result<PairedPeer, PairingError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut reader = BufReader::new(&mut *stream);
+        let hello: PairingHello = read_message(&mut reader).await?;
+        match self.accept_hello(&hello) {
+            Ok((confirm, peer)) => {
+                write_message(stream, &PairingReply::Confirm(confirm)).await?;
+                Ok(peer)
+            }
+            Err(err) => {
+                let reply = PairingReply::Rejected {
+                    reason: err.to_string(),
+                };
+                write_message(stream, &reply).await?;
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Controller side of a pairing, created from a scanned QR code
+pub struct LanPairingController {
+    descriptor: PairingDescriptor,
+    pairing_secret: Vec<u8>,
+    device_id: String,
+    device_name: String,
+}
+
+impl LanPairingController {
+    pub fn from_qr(
+        payload: &str,
+        device_id: String,
+        device_name: String,
+    ) -> Result<Self, PairingError> {
+        let descriptor = PairingDescriptor::from_qr_payload(payload)?;
+        let pairing_secret = decode_hex(&descriptor.pairing_secret)?;
+        Ok(Self {
+            descriptor,
+            pairing_secret,
+            device_id,
+            device_name,
+        })
+    }
+
+    pub fn descriptor(&self) -> &PairingDescriptor {
+        &self.descriptor
+    }
+
+    /// Build the hello; the returned handshake checks the host's confirm
+    pub fn hello(&self) -> Result<(PairingHello, PendingPairing), PairingError> {
+        let host_public = decode_key(&self.descriptor.ephemeral_public_key)?;
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public = hex::encode(PublicKey::from(&secret).as_bytes());
+        let shared = secret.diffie_hellman(&PublicKey::from(host_public));
+
+        let transcript = transcript_hash(
+            &self.descriptor.ephemeral_public_key,
+            &public,
+            &self.descriptor.device_id,
+            &self.device_id,
+        );
+        let keys = PairingKeys::derive(&self.pairing_secret, shared.as_bytes());
+        let hello = PairingHello {
+            device_id: self.device_id.clone(),
+            device_name: self.device_name.clone(),
+            ephemeral_public_key: public,
+            proof: hex::encode(keys.prove(b"controller", &transcript)),
+        };
+
+        Ok((
+            hello,
+            PendingPairing {
+                descriptor: self.descriptor.clone(),
+                transcript,
+                keys,
+            },
+        ))
+    }
+
+    /// Connect to the first reachable address and pair
+    ///
+    /// The stream is returned so the session can continue on it.
+    pub async fn connect(&self) -> Result<(TcpStream, PairedPeer), PairingError> {
+        let mut stream = None;
+        for address in &self.descriptor.addresses {
+            match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address)).await {
+                Ok(Ok(s)) => {
+                    stream = Some(s);
+                    break;
+                }
+                Ok(Err(e)) => tracing::debug!("LAN pairing: {} unreachable: {}", address, e),
+                Err(_) => tracing::debug!("LAN pairing: {} timed out", address),
+            }
+        }
+        if stream.is_none() {
+            if let Some(name) = &self.descriptor.mdns_name {
+                let port = self.descriptor.addresses.first().map_or(0, |a| a.port());
+                if let Ok(Ok(s)) =
+                    tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect((name.as_str(), port)))
+                        .await
+                {
+                    stream = Some(s);
+                }
+            }
+        }
+        let mut stream = stream.ok_or(PairingError::Unreachable)?;
+        let peer = self.pair(&mut stream).await?;
+        Ok((stream, peer))
+    }
+
+    /// Run the controller side of the handshake on an open connection
+    pub async fn pair<S>(&self, stream: &mut S) -> Result<PairedPeer, PairingError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let (hello, pending) = self.hello()?;
+        write_message(stream, &hello).await?;
+        let mut reader = BufReader::new(&mut *stream);
+        match read_message(&mut reader).await? {
+            PairingReply::Confirm(confirm) => pending.finish(&confirm),
+            PairingReply::Rejected { reason } => {
+                tracing::warn!("LAN pairing rejected by host: {}", reason);
+                Err(PairingError::ProofMismatch)
+            }
+        }
+    }
+}
+
+/// Controller state between sending the hello and receiving the confirm
+pub struct PendingPairing {
+    descriptor: PairingDescriptor,
+    transcript: [u8; 32],
+    keys: PairingKeys,
+}
+
+impl PendingPairing {
+    /// Authenticate the host from its confirm
+    pub fn finish(self, confirm: &PairingConfirm) -> Result<PairedPeer, PairingError> {
+        let proof = decode_hex(&confirm.proof)?;
+        if !self.keys.verify(b"host", &self.transcript, &proof) {
+            return Err(PairingError::ProofMismatch);
+        }
+
+        let public_key = decode_hex(&confirm.public_key)?;
+        let verifying_key = decode_hex(&confirm.verifying_key)?;
+        let mut hasher = Sha256::new();
+        hasher.update(&public_key);
+        hasher.update(&verifying_key);
+        let fingerprint = hex::encode(hasher.finalize());
+        if fingerprint != self.descriptor.cert_fingerprint {
+            return Err(PairingError::FingerprintMismatch);
+        }
+
+        let verifying_key = <[u8; 32]>::try_from(verifying_key.as_slice())
+            .ok()
+            .and_then(|bytes| VerifyingKey::from_bytes(&bytes).ok())
+            .ok_or_else(|| PairingError::Malformed("verifying key".to_string()))?;
+        let signature = <[u8; 64]>::try_from(decode_hex(&confirm.signature)?.as_slice())
+            .map(|bytes| Signature::from_bytes(&bytes))
+            .map_err(|_| PairingError::Malformed("signature".to_string()))?;
+        verifying_key
+            .verify(&self.transcript, &signature)
+            .map_err(|_| PairingError::FingerprintMismatch)?;
+
+        Ok(PairedPeer {
+            device_id: self.descriptor.device_id,
+            device_name: self.descriptor.device_name,
+            cert_fingerprint: Some(fingerprint),
+            session_key: self.keys.session_key,
+        })
+    }
+}
+
+struct PairingKeys {
+    confirm_key: hmac::Key,
+    session_key: Vec<u8>,
+}
+
+impl PairingKeys {
+    fn derive(pairing_secret: &[u8], shared_secret: &[u8]) -> Self {
+        let hk = hkdf::Hkdf::<Sha256>::new(Some(pairing_secret), shared_secret);
+        let mut confirm = [0u8; 32];
+        let mut session_key = vec![0u8; 32];
+        hk.expand(b"cec-lan-pair confirm", &mut confirm)
+            .expect("32 bytes is a valid HKDF output length");
+        hk.expand(b"cec-lan-pair session", &mut session_key)
+            .expect("32 bytes is a valid HKDF output length");
+        Self {
+            confirm_key: hmac::Key::new(hmac::HMAC_SHA256, &confirm),
+            session_key,
+        }
+    }
+
+    fn prove(&self, role: &[u8], transcript: &[u8; 32]) -> Vec<u8> {
+        let mut ctx = hmac::Context::with_key(&self.confirm_key);
+        ctx.update(role);
+        ctx.update(transcript);
+        ctx.sign().as_ref().to_vec()
+    }
+
+    fn verify(&self, role: &[u8], transcript: &[u8; 32], proof: &[u8]) -> bool {
+        let mut message = role.to_vec();
+        message.extend_from_slice(transcript);
+        hmac::verify(&self.confirm_key, &message, proof).is_ok()
+    }
+}
+
+fn transcript_hash(
+    host_public: &str,
+    controller_public: &str,
+    host_id: &str,
+    controller_id: &str,
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in [host_public, controller_public, host_id, controller_id] {
+        hasher.update((part.len() as u32).to_be_bytes());
+        hasher.update(part.as_bytes());
+    }
+    hasher.finalize().into()
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, PairingError> {
+    hex::decode(value).map_err(|_| PairingError::Malformed("hex field".to_string()))
+}
+
+fn decode_key(value: &str) -> Result<[u8; 32], PairingError> {
+    <[u8; 32]>::try_from(decode_hex(value)?.as_slice())
+        .map_err(|_| PairingError::Malformed("public key".to_string()))
+}
+
+async fn read_message<R, T>(reader: &mut R) -> Result<T, PairingError>
+where
+    R: AsyncBufReadExt + Unpin,
+    T: serde::de::DeserializeOwned,
+{
+    let mut line = String::new();
+    reader.take(MAX_MESSAGE_BYTES).read_line(&mut line).await?;
+    if !line.ends_with('\n') {
+        return Err(PairingError::Malformed("truncated message".to_string()));
+    }
+    serde_json::from_str(&line).map_err(|e| PairingError::Malformed(e.to_string()))
+}
+
+async fn write_message<W, T>(writer: &mut W, message: &T) -> Result<(), PairingError>
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let mut line =
+        serde_json::to_vec(message).map_err(|e| PairingError::Malformed(e.to_string()))?;
+    line.push(b'\n');
+    writer.write_all(&line).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::SecurityManager;
+
+    async fn new_host() -> LanPairingHost {
+        let mut security = SecurityManager::new();
+        let certificate = security
+            .generate_device_certificate("host-1".to_string())
+            .await
+            .unwrap();
+        LanPairingHost::new(
+            &certificate,
+            "Office PC".to_string(),
+            vec!["192.168.1.20:47000".parse().unwrap()],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_pairing_over_direct_connection() {
+        let host = new_host().await;
+        let controller = LanPairingController::from_qr(
+            &host.qr_payload(),
+            "laptop".to_string(),
+            "Laptop".to_string(),
+        )
+        .unwrap();
+        assert_eq!(controller.descriptor().device_name, "Office PC");
+
+        let (mut host_side, mut controller_side) = tokio::io::duplex(4096);
+        let (host_peer, controller_peer) = tokio::join!(
+            host.serve(&mut host_side),
+            controller.pair(&mut controller_side)
+        );
+        let host_peer = host_peer.unwrap();
+        let controller_peer = controller_peer.unwrap();
+
+        assert_eq!(host_peer.device_id, "laptop");
+        assert_eq!(controller_peer.device_id, "host-1");
+        assert_eq!(
+            controller_peer.cert_fingerprint.as_deref(),
+            Some(host.descriptor().cert_fingerprint.as_str())
+        );
+        assert_eq!(host_peer.session_key, controller_peer.session_key);
+
+        // Single use
+        assert!(!host.is_active());
+        let (hello, _) = controller.hello().unwrap();
+        assert_eq!(
+            host.accept_hello(&hello).unwrap_err(),
+            PairingError::Consumed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pairing_rejects_wrong_secret_and_impostor_host() {
+        let host = new_host().await;
+
+        // Controller that never saw the real secret
+        let mut forged = host.descriptor().clone();
+        forged.pairing_secret = hex::encode([7u8; 16]);
+        let guesser = LanPairingController::from_qr(
+            &forged.to_qr_payload(),
+            "attacker".to_string(),
+            "Attacker".to_string(),
+        )
+        .unwrap();
+        for _ in 0..MAX_PAIRING_ATTEMPTS {
+            let (hello, _) = guesser.hello().unwrap();
+            assert_eq!(
+                host.accept_hello(&hello).unwrap_err(),
+                PairingError::ProofMismatch
+            );
+        }
+        assert!(!host.is_active());
+
+        // Host whose certificate differs from the one in the QR code
+        let real = new_host().await;
+        let impostor = new_host().await;
+        let mut descriptor = real.descriptor().clone();
+        descriptor.cert_fingerprint = impostor.descriptor().cert_fingerprint.clone();
+        let controller = LanPairingController::from_qr(
+            &descriptor.to_qr_payload(),
+            "laptop".to_string(),
+            "Laptop".to_string(),
+        )
+        .unwrap();
+        let (hello, pending) = controller.hello().unwrap();
+        let (confirm, _) = real.accept_hello(&hello).unwrap();
+        assert_eq!(
+            pending.finish(&confirm).unwrap_err(),
+            PairingError::FingerprintMismatch
+        );
+
+        let mut expired = real.descriptor().clone();
+        expired.expires_at = 0;
+        assert_eq!(
+            PairingDescriptor::from_qr_payload(&expired.to_qr_payload()).unwrap_err(),
+            PairingError::Expired
+        );
+    }
+}
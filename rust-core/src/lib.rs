@@ -1,20 +1,113 @@
 pub mod access_control;
+pub mod access_risk;
+pub mod access_store;
+#[cfg(feature = "audio")]
+pub mod audio_backend;
+#[cfg(feature = "audio")]
+pub mod audio_session;
+pub mod autostart;
+#[cfg(feature = "capture")]
+pub mod capture_backend;
+#[cfg(feature = "capture")]
+pub mod capture_rate;
+#[cfg(feature = "capture")]
+pub mod capture_thread;
+#[cfg(feature = "file-transfer")]
+pub mod clipboard_files;
+pub mod clock;
+pub mod co_browsing;
+pub mod congestion;
+pub mod connection_failure;
+pub mod cursor_prediction;
+#[cfg(feature = "capture")]
+pub mod damage;
+pub mod data_compression;
+#[cfg(target_os = "linux")]
+pub mod dbus;
+pub mod decoder_capabilities;
+#[cfg(feature = "diagnostics")]
 pub mod diagnostics;
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics_report;
+#[cfg(feature = "discovery")]
+pub mod discovery;
+#[cfg(feature = "capture")]
+pub mod display_enum;
+#[cfg(feature = "capture")]
+pub mod display_mode;
+pub mod encryption_status;
+pub mod errors;
+pub mod event_bus;
 pub mod ffi;
+#[cfg(feature = "file-transfer")]
 pub mod file_transfer;
+#[cfg(feature = "capture")]
+pub mod frame_processing;
+pub mod geoip;
+#[cfg(feature = "capture")]
+pub mod hdr;
+pub mod idle;
 pub mod input_control;
+pub mod input_protocol;
+pub mod input_sequence;
+pub mod keymap;
+pub mod lan_pairing;
+pub mod link_stats;
+#[cfg(feature = "log-shipping")]
+pub mod log_shipping;
 pub mod logging;
+pub mod memory_budget;
+pub mod metrics;
 pub mod network;
+#[cfg(feature = "ocr")]
+pub mod ocr_assist;
+#[cfg(feature = "signaling-server")]
+pub mod offline_queue;
+#[cfg(feature = "capture")]
+pub mod os_permissions;
+pub mod outbound_queue;
+pub mod path_migration;
 pub mod performance;
+pub mod presence;
+pub mod privacy_mode;
+pub mod quality_heatmap;
+#[cfg(feature = "file-transfer")]
+pub mod quarantine;
+pub mod quic_transport;
+pub mod receive_stats;
+#[cfg(feature = "file-transfer")]
+pub mod remote_open;
+pub mod remote_url;
+#[cfg(feature = "capture")]
 pub mod screen_capture;
+pub mod secrets;
 pub mod security;
+pub mod self_check;
+pub mod session_bootstrap;
 pub mod session_manager;
+pub mod shutdown;
 pub mod signaling;
+#[cfg(feature = "signaling-server")]
+pub mod signaling_server;
+pub mod signaling_transport;
+pub mod stun;
+pub mod timestamp;
+#[cfg(feature = "file-transfer")]
+pub mod transfer_state;
+pub mod updater;
+#[cfg(feature = "audio")]
+pub mod voice_chat;
+pub mod webhooks;
 pub mod webrtc_engine;
+#[cfg(feature = "capture")]
+pub mod window_enum;
 
 #[cfg(test)]
 pub mod webrtc_mock;
 
+#[cfg(test)]
+pub mod signaling_mock;
+
 #[cfg(test)]
 mod signaling_test;
 
@@ -24,7 +117,7 @@ mod access_control_test;
 #[cfg(test)]
 mod network_test;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "capture"))]
 mod screen_capture_test;
 
 #[cfg(test)]
@@ -36,45 +129,213 @@ mod security_test;
 #[cfg(test)]
 mod logging_test;
 
-#[cfg(test)]
+#[cfg(all(test, feature = "capture"))]
 mod integration_test;
 
 pub use access_control::{
     AccessCode, AccessControlManager, AuthorizationType, ConnectionRequest, ConnectionResponse,
-    DeviceAuthorization, DeviceRegistration, Permission, ACCESS_CODE_EXPIRATION_SECS,
+    DeviceAuthorization, DeviceRegistration, Permission, TransferLimits,
+    ACCESS_CODE_EXPIRATION_SECS,
+};
+pub use access_risk::{AccessSchedule, RiskAssessment, RiskLevel, RiskScorer, RiskSignal};
+pub use access_store::AccessControlStore;
+#[cfg(feature = "audio")]
+pub use audio_backend::{
+    list_audio_devices, AudioBackend, AudioDeviceInfo, AudioDeviceKind, AudioLevel, AudioSource,
+};
+#[cfg(feature = "audio")]
+pub use audio_session::{AudioTrackControl, SessionAudioController};
+pub use autostart::{
+    AutostartConfig, AutostartError, AutostartManager, AutostartMethod, AutostartStatus,
+};
+#[cfg(feature = "capture")]
+pub use capture_backend::{BackendFrameSource, CaptureBackend};
+#[cfg(feature = "capture")]
+pub use capture_rate::{AdaptiveRateOptions, CaptureRateStats};
+#[cfg(feature = "capture")]
+pub use capture_thread::{CaptureThreadHealth, FrameSource};
+#[cfg(feature = "file-transfer")]
+pub use clipboard_files::{ClipboardFileManager, ClipboardFileOffer};
+pub use clock::{system_clock, Clock, SharedClock, SystemClock, TestClock};
+pub use co_browsing::{
+    pointer_channel_label, PointerEvent, PointerHub, PointerHubConfig, PointerOverlay,
+    PointerUpdate,
+};
+pub use congestion::{
+    parse_transport_feedback, BandwidthUsage, GccConfig, GccEstimator, PacketStatus,
+    TransportFeedback,
 };
+pub use connection_failure::{
+    classify as classify_connection_failure, ConnectionFailure, FailureCategory, FailureContext,
+    Remediation,
+};
+pub use cursor_prediction::{CursorPredictor, CursorReporter, CursorUpdate, RenderedCursor};
+#[cfg(feature = "capture")]
+pub use damage::DamageTracker;
+pub use data_compression::{
+    negotiate_compression, CompressionAlgorithm, CompressionStats, DataChannelCodec,
+};
+pub use decoder_capabilities::{DecoderCapabilities, DecoderCodec};
+#[cfg(feature = "diagnostics")]
 pub use diagnostics::{
     DiagnosticStatus, DiagnosticsManager, NatType, NetworkDiagnostics, ServerStatus,
     SystemDiagnostics,
 };
-pub use file_transfer::FileTransfer;
-pub use input_control::InputController;
+#[cfg(feature = "diagnostics")]
+pub use diagnostics_report::{
+    CheckStatus, DiagnosticCheck, DiagnosticSection, DiagnosticsReport, Metric, SectionId,
+    StatusCounts,
+};
+#[cfg(feature = "discovery")]
+pub use discovery::{Advertisement, DiscoveredDevice, DiscoveryEvent, LanDiscovery};
+#[cfg(feature = "capture")]
+pub use display_enum::enumerate_displays;
+#[cfg(feature = "capture")]
+pub use display_mode::{DisplayMode, DisplayModeBackend, DisplayModeManager};
+pub use encryption_status::{
+    EncryptionStatus, EncryptionStatusEvent, FingerprintVerification, SessionEncryption,
+};
+pub use errors::{ManagerError, ResourceKind};
+pub use event_bus::{DropPolicy, EventBus, EventType, Subscription, SubscriptionOptions};
+#[cfg(feature = "file-transfer")]
+pub use file_transfer::{
+    guess_mime_type, FileTransfer, FolderEntry, FolderOffer, FolderProgress, IncomingFileDecision,
+    IncomingFileOffer, TransferCounters, TransferIntegrityError, TransferLimitError,
+};
+#[cfg(feature = "capture")]
+pub use frame_processing::{
+    FailurePolicy, FramePipeline, FrameProcessor, ProcessingStage, ProcessorConfig, ProcessorStats,
+};
+pub use geoip::{GeoIpDatabase, GeoLocation};
+#[cfg(feature = "capture")]
+pub use hdr::{HdrCapabilities, HdrMode, HdrOptions, ToneMapOperator};
+pub use idle::{ActivityLevel, ActivityProfile, IdleDetector, IdlePolicy};
+pub use input_control::{
+    AccessibilitySettings, InputController, KeyboardLayout, KeyboardLayoutEvent,
+    TextInjectionMethod, TextInjectionPolicy, MAX_TYPE_TEXT_LENGTH,
+};
+pub use input_protocol::{
+    BatchedInput, InputAck, InputBatch, InputPacket, InputProtocolError, INPUT_PROTOCOL_VERSION,
+};
+pub use input_sequence::{
+    InputReplayGuard, InputSequencer, InputVerdict, ReplayGuardConfig, SequencedInput,
+};
+pub use keymap::{KeyAction, KeyTranslator};
+pub use lan_pairing::{
+    LanPairingController, LanPairingHost, PairedPeer, PairingDescriptor, PairingError,
+};
+pub use link_stats::{LinkMonitor, LinkSample, ReportBlock};
+#[cfg(feature = "log-shipping")]
+pub use log_shipping::{
+    HttpLogTransport, LogBatch, LogShipper, LogShippingConfig, LogShippingStats, LogTransport,
+    ShippedRecord,
+};
 pub use logging::{
     ConnectionEvent, ConnectionEventType, LogConfig, LogEntry, LogLevel, LogManager,
 };
+pub use memory_budget::{
+    MemoryBudget, MemoryBudgetConfig, MemoryBudgetUsage, MemoryPressure, MemorySubsystem,
+    SubsystemUsage,
+};
+pub use metrics::{Counter, Gauge, MetricsRegistry, MetricsSnapshot, MAX_METRICS};
+#[cfg(feature = "ocr")]
+pub use ocr_assist::{OcrAssist, OcrEngine, OcrRegion, OcrRequest, OcrResponse};
+#[cfg(feature = "signaling-server")]
+pub use offline_queue::{OfflineMessageQueue, QueueDrain};
+#[cfg(feature = "capture")]
+pub use os_permissions::{
+    AffectedPipeline, PermissionEvent, PermissionMonitor, SystemPermission, SystemPermissionStatus,
+};
+pub use outbound_queue::{OutboundQueueConfig, SendOutcome};
+pub use path_migration::{spawn_path_migration, PathMigrationConfig};
+pub use performance::QualityPreset;
+pub use presence::{
+    DeviceDirectory, DevicePreferences, DirectoryEntry, PresenceCache, MAX_STATUS_BATCH,
+    PRESENCE_STALE_AFTER,
+};
+#[cfg(feature = "signaling-server")]
+pub use presence::{PresenceSubscriptions, MAX_WATCHED_DEVICES};
+pub use privacy_mode::{PlatformPrivacyBackend, PrivacyBackend, PrivacyMode, PrivacyOptions};
+pub use quality_heatmap::{HeatmapCell, QualityHeatmap, QualityHistory};
+#[cfg(feature = "file-transfer")]
+pub use quarantine::{
+    FileQuarantine, QuarantineConfig, QuarantineDecision, QuarantineEvent, ScannerCommand,
+};
+pub use quic_transport::{select_data_transport, DataPath, DataTransportConfig, DataTransportType};
+#[cfg(feature = "quic")]
+pub use quic_transport::{QuicIdentity, QuicListener, QuicTransport};
+pub use receive_stats::{FreezeEvent, FreezeStats, ReceiveStatsTracker};
+#[cfg(feature = "file-transfer")]
+pub use remote_open::{
+    FileOpener, OpenOutcome, OpenRequest, RemoteOpenEvent, RemoteOpenManager, RemoteOpenPolicy,
+};
+pub use remote_url::{
+    RemoteUrlEvent, RemoteUrlManager, RemoteUrlPolicy, UrlOpenOutcome, UrlOpenRequest, UrlOpener,
+};
+#[cfg(feature = "capture")]
 pub use screen_capture::{
-    AdaptiveBitrateConfig, AudioCaptureOptions, AudioCapturer, AudioFrame, CaptureOptions,
-    DisplayInfo, NetworkConditions, QualityPreset, ScreenCapturer, VideoCodecType, VideoFrame,
+    per_process_audio_supported, AdaptiveBitrateConfig, ApplicationInfo, CaptureOptions,
+    CaptureSource, CaptureTarget, DirtyRect, DisplayInfo, EncoderSelection, NetworkConditions,
+    ScreenCapturer, VideoCodecType, VideoFrame, WindowInfo, AV1_BITRATE_FACTOR,
 };
+#[cfg(feature = "audio")]
+pub use screen_capture::{AudioCaptureOptions, AudioCapturer, AudioFrame};
+pub use secrets::{SecretBackend, SecretsStore, TurnCredential};
 pub use security::{
-    CertificateValidationError, CertificateValidationResult, DeviceCertificate, DtlsSrtpConfig,
-    EncryptedData, EncryptionAlgorithm, FailedAttemptTracker, KeyRotationConfig,
-    ReplayDetectionState, SecurityConfig, SecurityEvent, SecurityEventType, SecurityManager,
-    SecurityThreat, SessionKey, ThreatDetectionConfig, TlsConfig,
+    certificate_fingerprint, verify_message_signature, CertificateValidationError,
+    CertificateValidationResult, DeviceCertificate, DtlsSrtpConfig, EncryptedData,
+    EncryptionAlgorithm, FailedAttemptTracker, IssueSeverity, KeyRotationConfig, MessageSigner,
+    ReplayDetectionState, RotationAnnouncer, RotationMetrics, RotationScheduleConfig,
+    SealedPayload, SecurityConfig, SecurityEvent, SecurityEventType, SecurityIssue,
+    SecurityManager, SecurityPosture, SecurityProfile, SecurityThreat, SessionKey,
+    SignalingKeyPair, ThreatDetectionConfig, TlsConfig,
+};
+pub use self_check::{SelfCheckConfig, SelfCheckFinding, SelfCheckReport};
+pub use session_bootstrap::{
+    BootstrapSnapshot, BootstrapState, BootstrapTimeouts, BootstrapTransition, SessionBootstrap,
 };
+#[cfg(feature = "recording")]
+pub use session_manager::RecordingPolicy;
 pub use session_manager::{
-    ConnectionQuality, ConnectionType, EndReason, Permission as SessionPermission,
-    PermissionRequest, Session, SessionEvent, SessionManager, SessionOptions, SessionRecord,
-    SessionStats, SessionStatus, SessionSummaryStats,
+    ActiveSessionDescriptor, ConnectionAnomaly, ConnectionQuality, ConnectionType, EndReason,
+    PeerIdentity, Permission as SessionPermission, PermissionRequest, RecentConnection,
+    RecordingState, RecordingStatus, Session, SessionEvent, SessionManager, SessionOptions,
+    SessionRecord, SessionRole, SessionStats, SessionStatus, SessionSummaryStats,
 };
+pub use shutdown::{HostShutdown, ShutdownOptions, ShutdownReport};
 pub use signaling::{
-    generate_device_id, DeviceCapabilities, DeviceInfo, DeviceStatus, SignalingClient,
-    SignalingEvent, SignalingMessage, SignalingMetrics,
+    generate_device_id, DeliveryStatus, DeviceCapabilities, DeviceInfo, DeviceStatus,
+    HeartbeatStatus, MessageEnvelope, ReconnectPolicy, RecordingAction, SignalingClient,
+    SignalingEvent, SignalingMessage, SignalingMetrics, SignedMessage, STATUS_QUERY_TIMEOUT,
+};
+#[cfg(feature = "signaling-server")]
+pub use signaling_server::{SignalingServer, SignalingServerConfig};
+pub use signaling_transport::{SignalingTransport, WebSocketTransport};
+pub use stun::{ChangeRequest, NatProbe, ProbeResponse, StunConfig, TurnAllocation};
+pub use timestamp::Timestamp;
+#[cfg(feature = "file-transfer")]
+pub use transfer_state::{
+    TransferChecksums, TransferDirection, TransferManifest, TransferStateStore,
+    PARTIAL_TRANSFER_MAX_AGE,
+};
+pub use updater::{
+    ReleaseChannel, ReleaseInfo, UpdateCheckResult, UpdateChecker, UpdateComponent, UpdateFetcher,
+};
+#[cfg(feature = "audio")]
+pub use voice_chat::{
+    EchoCanceller, EchoCancellerConfig, TalkMode, VoiceChat, VoiceChatOptions, VoiceChatStats,
+    VoiceDirectionStats, VoiceTrackControl, VOICE_TRACK_ID,
 };
+pub use webhooks::{WebhookConfig, WebhookDispatcher, WebhookEventType, WebhookTransport};
 pub use webrtc_engine::{
-    ConnectionStats, IceServer, MediaStream, MediaTrack, RTCConfiguration, RTCPeerConnectionState,
-    WebRTCEngine, WebRTCEvent,
+    ConnectionStats, ConsentConfig, ConsentEvent, DataChannelConfig, DataChannelEvent,
+    DataChannelInfo, DataChannelMessage, DetailedStats, IceServer, InboundRtpStats, MediaStream,
+    MediaTrack, NegotiationRole, OutboundRtpStats, RTCConfiguration, RTCPeerConnectionState,
+    Reliability, RenegotiationProgress, SimulcastLayer, WebRTCEngine, WebRTCEvent,
+    CONTROL_CHANNEL_LABEL, MAX_DATA_CHANNEL_MESSAGE,
 };
+#[cfg(feature = "capture")]
+pub use window_enum::enumerate_windows;
 
 // Re-export common types
 pub use crate::ffi::*;
@@ -0,0 +1,354 @@
+//! Link Statistics
+//!
+//! Measures the figures reported in `NetworkStats` from three sources:
+//! STUN Binding "pings" give round-trip time and, when they go unanswered,
+//! loss on the signalling path; RTCP receiver reports from the peer give
+//! loss and interarrival jitter of the media stream itself, plus RTT via
+//! LSR/DLSR (RFC 3550 §6.4.1); and received byte counts or a timed packet
+//! train give the bandwidth. Receiver reports describe the media path, so
+//! while one is recent its loss and jitter take precedence over the pings.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Pings remembered for the loss estimate
+const PING_WINDOW: usize = 20;
+
+/// Receiver reports older than this no longer describe the stream
+const REPORT_FRESHNESS: Duration = Duration::from_secs(5);
+
+/// Span of received bytes averaged into the throughput
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(2);
+
+const RTCP_SENDER_REPORT: u8 = 200;
+const RTCP_RECEIVER_REPORT: u8 = 201;
+const REPORT_BLOCK_LEN: usize = 24;
+
+/// Seconds between the NTP epoch (1900) and the Unix epoch
+const NTP_UNIX_OFFSET: u64 = 2_208_988_800;
+
+/// Reception report block of an RTCP SR or RR
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportBlock {
+    /// Source the block reports on
+    pub ssrc: u32,
+    /// Lost fraction since the previous report, in 1/256
+    pub fraction_lost: u8,
+    pub cumulative_lost: i32,
+    pub highest_sequence: u32,
+    /// Interarrival jitter in RTP timestamp units
+    pub jitter: u32,
+    /// Middle 32 bits of the NTP time of the last SR received, 0 if none
+    pub last_sr: u32,
+    /// Time since that SR, in 1/65536 s
+    pub delay_since_last_sr: u32,
+}
+
+impl ReportBlock {
+    pub fn loss_percent(&self) -> f32 {
+        f32::from(self.fraction_lost) * 100.0 / 256.0
+    }
+
+    pub fn jitter_duration(&self, clock_rate: u32) -> Duration {
+        if clock_rate == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(f64::from(self.jitter) / f64::from(clock_rate))
+    }
+
+    /// Round trip to the reporter, given when the report arrived
+    ///
+    /// `None` if the reporter has not received a sender report yet.
+    pub fn round_trip(&self, arrival: SystemTime) -> Option<Duration> {
+        if self.last_sr == 0 {
+            return None;
+        }
+        let rtt = ntp_short(arrival)
+            .wrapping_sub(self.last_sr)
+            .wrapping_sub(self.delay_since_last_sr);
+        // A wrapped (negative) result means the clocks disagree
+        (rtt < 0x8000_0000).then(|| Duration::from_secs_f64(f64::from(rtt) / 65536.0))
+    }
+}
+
+/// Report blocks of every SR and RR in a compound RTCP packet
+pub fn parse_report_blocks(packet: &[u8]) -> Result<Vec<ReportBlock>> {
+    let mut blocks = Vec::new();
+    let mut rest = packet;
+    while !rest.is_empty() {
+        if rest.len() < 4 || rest[0] >> 6 != 2 {
+            return Err(anyhow::anyhow!("Malformed RTCP packet"));
+        }
+        let count = usize::from(rest[0] & 0x1F);
+        let length = (usize::from(u16::from_be_bytes([rest[2], rest[3]])) + 1) * 4;
+        if rest.len() < length {
+            return Err(anyhow::anyhow!("Truncated RTCP packet"));
+        }
+        let (body, next) = rest.split_at(length);
+        let blocks_at = match rest[1] {
+            RTCP_SENDER_REPORT => Some(28),
+            RTCP_RECEIVER_REPORT => Some(8),
+            _ => None,
+        };
+        if let Some(offset) = blocks_at {
+            if body.len() < offset + count * REPORT_BLOCK_LEN {
+                return Err(anyhow::anyhow!("RTCP report shorter than its block count"));
+            }
+            for block in body[offset..].chunks_exact(REPORT_BLOCK_LEN).take(count) {
+                let word = |i: usize| {
+                    u32::from_be_bytes([block[i], block[i + 1], block[i + 2], block[i + 3]])
+                };
+                blocks.push(ReportBlock {
+                    ssrc: word(0),
+                    fraction_lost: block[4],
+                    // Sign-extend the 24-bit count
+                    cumulative_lost: (word(4) << 8) as i32 >> 8,
+                    highest_sequence: word(8),
+                    jitter: word(12),
+                    last_sr: word(16),
+                    delay_since_last_sr: word(20),
+                });
+            }
+        }
+        rest = next;
+    }
+    Ok(blocks)
+}
+
+/// Middle 32 bits of the NTP timestamp of `time`
+pub fn ntp_short(time: SystemTime) -> u32 {
+    let since_unix = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_unix.as_secs() + NTP_UNIX_OFFSET;
+    let fraction = (u64::from(since_unix.subsec_nanos()) << 32) / 1_000_000_000;
+    ((seconds as u32 & 0xFFFF) << 16) | (fraction >> 16) as u32
+}
+
+/// Bandwidth from the spread of a back-to-back packet train
+///
+/// `arrivals` are the receive times of equally sized packets sent without
+/// pause; the bottleneck spaces them out to its own rate.
+pub fn train_bandwidth(packet_bytes: usize, arrivals: &[Instant]) -> Option<u64> {
+    let (first, last) = (arrivals.first()?, arrivals.last()?);
+    let spread = last.duration_since(*first).as_secs_f64();
+    if arrivals.len() < 2 || spread <= 0.0 {
+        return None;
+    }
+    let bits = ((arrivals.len() - 1) * packet_bytes * 8) as f64;
+    Some((bits / spread) as u64)
+}
+
+/// Figures for one `NetworkStats` update
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LinkSample {
+    pub rtt_ms: u32,
+    pub jitter_ms: u32,
+    /// Percentage
+    pub packet_loss: f32,
+    pub bandwidth_bps: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ReportState {
+    received_at: Instant,
+    loss_percent: f32,
+    jitter_ms: f64,
+}
+
+/// Accumulates measurements of one link
+#[derive(Debug, Default)]
+pub struct LinkMonitor {
+    /// Smoothed RTT (RFC 6298, gain 1/8)
+    srtt_ms: Option<f64>,
+    last_rtt_ms: Option<f64>,
+    /// Smoothed RTT variation between consecutive samples (RFC 3550 gain 1/16)
+    rtt_jitter_ms: f64,
+    /// Whether each recent ping was answered
+    pings: VecDeque<bool>,
+    report: Option<ReportState>,
+    received: VecDeque<(Instant, usize)>,
+    probe_bps: Option<u64>,
+}
+
+impl LinkMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Outcome of a ping: its round trip, or `None` if it went unanswered
+    pub fn record_ping(&mut self, rtt: Option<Duration>) {
+        if self.pings.len() == PING_WINDOW {
+            self.pings.pop_front();
+        }
+        self.pings.push_back(rtt.is_some());
+        if let Some(rtt) = rtt {
+            self.record_rtt(rtt);
+        }
+    }
+
+    fn record_rtt(&mut self, rtt: Duration) {
+        let sample = rtt.as_secs_f64() * 1000.0;
+        self.srtt_ms = Some(match self.srtt_ms {
+            Some(srtt) => srtt + (sample - srtt) / 8.0,
+            None => sample,
+        });
+        if let Some(last) = self.last_rtt_ms {
+            self.rtt_jitter_ms += ((sample - last).abs() - self.rtt_jitter_ms) / 16.0;
+        }
+        self.last_rtt_ms = Some(sample);
+    }
+
+    /// Feed a report block about a stream we send
+    pub fn record_report(
+        &mut self,
+        block: &ReportBlock,
+        clock_rate: u32,
+        arrival: SystemTime,
+        now: Instant,
+    ) {
+        if let Some(rtt) = block.round_trip(arrival) {
+            self.record_rtt(rtt);
+        }
+        self.report = Some(ReportState {
+            received_at: now,
+            loss_percent: block.loss_percent(),
+            jitter_ms: block.jitter_duration(clock_rate).as_secs_f64() * 1000.0,
+        });
+    }
+
+    /// Count bytes received from the peer
+    pub fn record_received(&mut self, bytes: usize, now: Instant) {
+        self.received.push_back((now, bytes));
+        while self
+            .received
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > THROUGHPUT_WINDOW)
+        {
+            self.received.pop_front();
+        }
+    }
+
+    /// Record the result of a packet-train probe; returns the estimate
+    pub fn record_probe(&mut self, packet_bytes: usize, arrivals: &[Instant]) -> Option<u64> {
+        let estimate = train_bandwidth(packet_bytes, arrivals)?;
+        self.probe_bps = Some(estimate);
+        Some(estimate)
+    }
+
+    pub fn sample(&self, now: Instant) -> LinkSample {
+        let report = self
+            .report
+            .filter(|report| now.duration_since(report.received_at) <= REPORT_FRESHNESS);
+        let ping_loss = if self.pings.is_empty() {
+            0.0
+        } else {
+            let lost = self.pings.iter().filter(|answered| !**answered).count();
+            lost as f32 * 100.0 / self.pings.len() as f32
+        };
+
+        // The probe measures capacity; throughput only what was sent
+        let throughput = self
+            .received
+            .iter()
+            .filter(|(at, _)| now.duration_since(*at) <= THROUGHPUT_WINDOW)
+            .map(|(_, bytes)| *bytes as u64)
+            .sum::<u64>()
+            * 8
+            / THROUGHPUT_WINDOW.as_secs();
+
+        LinkSample {
+            rtt_ms: self.srtt_ms.unwrap_or_default().round() as u32,
+            jitter_ms: report
+                .map_or(self.rtt_jitter_ms, |report| report.jitter_ms)
+                .round() as u32,
+            packet_loss: report.map_or(ping_loss, |report| report.loss_percent),
+            bandwidth_bps: self.probe_bps.unwrap_or(0).max(throughput),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report_block(fraction_lost: u8, jitter: u32, last_sr: u32, dlsr: u32) -> Vec<u8> {
+        let mut block = 0x1234_5678u32.to_be_bytes().to_vec();
+        // 3 packets lost in total
+        block.extend_from_slice(&[fraction_lost, 0, 0, 3]);
+        block.extend_from_slice(&1000u32.to_be_bytes());
+        block.extend_from_slice(&jitter.to_be_bytes());
+        block.extend_from_slice(&last_sr.to_be_bytes());
+        block.extend_from_slice(&dlsr.to_be_bytes());
+        block
+    }
+
+    #[test]
+    fn test_parse_compound_rtcp() {
+        let arrival = SystemTime::now();
+        // LSR 100 ms before arrival, the peer held it for 40 ms: RTT 60 ms
+        let sent = arrival - Duration::from_millis(100);
+        let dlsr = (0.040 * 65536.0) as u32;
+
+        // SR with one block, then an RR with one block, then an SDES
+        let mut packet = vec![0x81, RTCP_SENDER_REPORT, 0, 12];
+        packet.extend_from_slice(&[0; 24]);
+        packet.extend_from_slice(&report_block(64, 900, ntp_short(sent), dlsr));
+        packet.extend_from_slice(&[0x81, RTCP_RECEIVER_REPORT, 0, 7, 0, 0, 0, 1]);
+        packet.extend_from_slice(&report_block(0, 0, 0, 0));
+        packet.extend_from_slice(&[0x81, 202, 0, 1, 0, 0, 0, 1]);
+
+        let blocks = parse_report_blocks(&packet).unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].loss_percent(), 25.0);
+        assert_eq!(blocks[0].cumulative_lost, 3);
+        assert_eq!(blocks[0].jitter_duration(90_000), Duration::from_millis(10));
+        let rtt = blocks[0].round_trip(arrival).unwrap();
+        assert!((rtt.as_secs_f64() - 0.060).abs() < 0.001, "{:?}", rtt);
+        assert_eq!(blocks[1].round_trip(arrival), None);
+
+        assert!(parse_report_blocks(&packet[..40]).is_err());
+        assert!(parse_report_blocks(&[0x40, 201, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_monitor_prefers_fresh_receiver_reports() {
+        let start = Instant::now();
+        let mut monitor = LinkMonitor::new();
+        monitor.record_ping(Some(Duration::from_millis(40)));
+        monitor.record_ping(Some(Duration::from_millis(60)));
+        monitor.record_ping(None);
+        monitor.record_ping(Some(Duration::from_millis(40)));
+
+        let sample = monitor.sample(start);
+        assert_eq!(sample.packet_loss, 25.0);
+        assert!(sample.rtt_ms >= 40 && sample.rtt_ms <= 45);
+        assert!(sample.jitter_ms > 0);
+
+        let block = ReportBlock {
+            ssrc: 1,
+            fraction_lost: 128,
+            cumulative_lost: 10,
+            highest_sequence: 500,
+            jitter: 2700,
+            last_sr: 0,
+            delay_since_last_sr: 0,
+        };
+        monitor.record_report(&block, 90_000, SystemTime::now(), start);
+        let sample = monitor.sample(start);
+        assert_eq!(sample.packet_loss, 50.0);
+        assert_eq!(sample.jitter_ms, 30);
+        // Stale reports give way to the pings again
+        assert_eq!(
+            monitor.sample(start + Duration::from_secs(6)).packet_loss,
+            25.0
+        );
+
+        // 10 x 1200-byte packets spread over 9 ms: 9.6 Mbit/s
+        let arrivals: Vec<Instant> = (0..10).map(|i| start + Duration::from_millis(i)).collect();
+        assert_eq!(monitor.record_probe(1200, &arrivals), Some(9_600_000));
+        monitor.record_received(500_000, start);
+        assert_eq!(monitor.sample(start).bandwidth_bps, 9_600_000);
+        assert_eq!(train_bandwidth(1200, &arrivals[..1]), None);
+    }
+}
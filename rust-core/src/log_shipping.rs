@@ -0,0 +1,694 @@
+//! Remote Log Shipping
+//!
+//! Managed fleets collect client logs centrally instead of asking users to
+//! export a diagnostics bundle. When `LogConfig::shipping` is set, the
+//! `LogManager` hands every `LogEntry` and `ConnectionEvent` at or above the
+//! configured level to a `LogShipper`, which:
+//!
+//! - redacts IP addresses and secret-looking metadata before anything is
+//!   queued;
+//! - groups records into batches of at most `batch_max_records`, serialized
+//!   as JSON and compressed with zstd;
+//! - POSTs batches to an HTTPS collector, oldest first, backing off
+//!   exponentially while the collector is unreachable;
+//! - keeps undelivered batches in memory up to `max_buffer_bytes`, dropping
+//!   the oldest once the cap is reached.
+//!
+//! Delivery counters are reported through a `MetricsRegistry`.
+
+use crate::logging::{ConnectionEvent, LogEntry, LogLevel};
+use crate::metrics::{Counter, MetricsRegistry};
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// Header carrying the shipping device
+pub const DEVICE_HEADER: &str = "X-CEC-Device";
+
+/// Upper bound on the retry delay
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Replacement for redacted values
+const REDACTED: &str = "[redacted]";
+
+/// Metadata keys whose values are never shipped (matched case-insensitively
+/// as substrings)
+const SENSITIVE_KEYS: &[&str] = &[
+    "password",
+    "passwd",
+    "secret",
+    "token",
+    "credential",
+    "access_code",
+    "private_key",
+    "api_key",
+];
+
+/// Where and how logs are shipped; part of `LogConfig`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LogShippingConfig {
+    /// HTTPS collector URL
+    pub endpoint: String,
+    /// Sent as a bearer token when set
+    pub auth_token: Option<String>,
+    /// Records below this level stay local
+    pub min_level: LogLevel,
+    pub include_connection_events: bool,
+    /// Records per batch; a full batch is sealed immediately
+    pub batch_max_records: usize,
+    /// How often the background task seals and sends what is pending
+    pub flush_interval_secs: u64,
+    /// Compressed bytes kept while the collector is unreachable
+    pub max_buffer_bytes: usize,
+    /// Delay before the first retry; doubles on each further retry
+    pub initial_backoff_ms: u64,
+}
+
+impl Default for LogShippingConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: String::new(),
+            auth_token: None,
+            min_level: LogLevel::Info,
+            include_connection_events: true,
+            batch_max_records: 500,
+            flush_interval_secs: 30,
+            max_buffer_bytes: 4 * 1024 * 1024,
+            initial_backoff_ms: 1000,
+        }
+    }
+}
+
+impl LogShippingConfig {
+    pub fn new(endpoint: &str) -> Self {
+        Self {
+            endpoint: endpoint.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Reject collectors that are not HTTPS
+    pub fn validate(&self) -> Result<()> {
+        let url = url::Url::parse(&self.endpoint)
+            .map_err(|e| anyhow::anyhow!("Invalid log collector URL {}: {}", self.endpoint, e))?;
+        if url.scheme() != "https" {
+            return Err(anyhow::anyhow!(
+                "Log collector {} must use https",
+                self.endpoint
+            ));
+        }
+        if self.batch_max_records == 0 {
+            return Err(anyhow::anyhow!("batch_max_records must be at least 1"));
+        }
+        Ok(())
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let delay = self
+            .initial_backoff_ms
+            .saturating_mul(1u64 << retry.min(16));
+        Duration::from_millis(delay).min(MAX_BACKOFF)
+    }
+}
+
+/// One record in a shipped batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ShippedRecord {
+    Log(LogEntry),
+    Connection(ConnectionEvent),
+}
+
+/// JSON document compressed into each request body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogBatch {
+    pub batch_id: String,
+    pub device_id: String,
+    /// RFC 3339 time the batch was sealed
+    pub created_at: String,
+    pub records: Vec<ShippedRecord>,
+}
+
+/// Queue state reported by `LogShipper::stats`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogShippingStats {
+    /// Records not yet sealed into a batch
+    pub pending_records: usize,
+    /// Sealed batches awaiting delivery
+    pub buffered_batches: usize,
+    pub buffered_bytes: usize,
+    /// Consecutive failed attempts for the oldest batch
+    pub retry_attempt: u32,
+}
+
+/// Request headers added to each batch POST
+pub type LogShippingHeaders = Vec<(&'static str, String)>;
+
+/// HTTP client used to post batches; returns the response status code
+pub trait LogTransport: Send + Sync {
+    fn post<'a>(
+        &'a self,
+        url: &'a str,
+        headers: LogShippingHeaders,
+        body: Vec<u8>,
+    ) -> BoxFuture<'a, Result<u16>>;
+}
+
+/// Transport backed by reqwest
+pub struct HttpLogTransport {
+    client: reqwest::Client,
+}
+
+impl HttpLogTransport {
+    pub fn new() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .https_only(true)
+            .build()?;
+        Ok(Self { client })
+    }
+}
+
+impl LogTransport for HttpLogTransport {
+    fn post<'a>(
+        &'a self,
+        url: &'a str,
+        headers: LogShippingHeaders,
+        body: Vec<u8>,
+    ) -> BoxFuture<'a, Result<u16>> {
+        Box::pin(async move {
+            let mut request = self
+                .client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .header("Content-Encoding", "zstd")
+                .body(body);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            Ok(request.send().await?.status().as_u16())
+        })
+    }
+}
+
+/// Replace IP addresses (with or without a port) in free text
+pub fn redact_text(text: &str) -> String {
+    let is_address_char = |c: char| c.is_ascii_hexdigit() || c == '.' || c == ':';
+    let mut redacted = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(is_address_char) {
+        redacted.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest.find(|c| !is_address_char(c)).unwrap_or(rest.len());
+        let token = &rest[..end];
+        // Sentence punctuation directly after an address is not part of it
+        let trimmed = token.trim_end_matches(['.', ':']);
+        if trimmed.parse::<IpAddr>().is_ok() || trimmed.parse::<SocketAddr>().is_ok() {
+            redacted.push_str("[ip]");
+            redacted.push_str(&token[trimmed.len()..]);
+        } else {
+            redacted.push_str(token);
+        }
+        rest = &rest[end..];
+    }
+    redacted.push_str(rest);
+    redacted
+}
+
+/// Mask secret-looking keys and IP addresses in structured metadata
+pub fn redact_value(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                let key = key.to_lowercase();
+                if SENSITIVE_KEYS
+                    .iter()
+                    .any(|sensitive| key.contains(sensitive))
+                {
+                    *value = serde_json::Value::String(REDACTED.to_string());
+                } else {
+                    redact_value(value);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_value),
+        serde_json::Value::String(text) => *text = redact_text(text),
+        _ => {}
+    }
+}
+
+impl ShippedRecord {
+    fn redacted(mut self) -> Self {
+        match &mut self {
+            ShippedRecord::Log(entry) => {
+                entry.message = redact_text(&entry.message);
+                if let Some(metadata) = entry.metadata.as_mut() {
+                    redact_value(metadata);
+                }
+            }
+            ShippedRecord::Connection(event) => {
+                if let Some(error) = event.error_message.as_mut() {
+                    *error = redact_text(error);
+                }
+                if let Some(details) = event.details.as_mut() {
+                    redact_value(details);
+                }
+            }
+        }
+        self
+    }
+}
+
+fn is_retryable(status: u16) -> bool {
+    status == 408 || status == 429 || status >= 500
+}
+
+/// Delivery counters bumped by the shipper
+#[derive(Clone)]
+struct ShippingCounters {
+    records_queued: Arc<Counter>,
+    batches_sent: Arc<Counter>,
+    records_sent: Arc<Counter>,
+    bytes_sent: Arc<Counter>,
+    failed_attempts: Arc<Counter>,
+    batches_dropped: Arc<Counter>,
+    records_dropped: Arc<Counter>,
+}
+
+impl ShippingCounters {
+    fn new(registry: &MetricsRegistry) -> Self {
+        Self {
+            records_queued: registry.counter("log_shipping.records_queued"),
+            batches_sent: registry.counter("log_shipping.batches_sent"),
+            records_sent: registry.counter("log_shipping.records_sent"),
+            bytes_sent: registry.counter("log_shipping.bytes_sent"),
+            failed_attempts: registry.counter("log_shipping.failed_attempts"),
+            batches_dropped: registry.counter("log_shipping.batches_dropped"),
+            records_dropped: registry.counter("log_shipping.records_dropped"),
+        }
+    }
+
+    fn drop_batch(&self, batch: &SealedBatch) {
+        self.batches_dropped.increment();
+        self.records_dropped.add(batch.records as u64);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct SealedBatch {
+    id: String,
+    records: usize,
+    body: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct ShipperQueue {
+    pending: Vec<ShippedRecord>,
+    outbox: VecDeque<SealedBatch>,
+    outbox_bytes: usize,
+    retry: u32,
+    retry_at: Option<Instant>,
+}
+
+/// Batches, compresses and delivers log records to a collector
+pub struct LogShipper {
+    config: LogShippingConfig,
+    device_id: String,
+    transport: Arc<dyn LogTransport>,
+    queue: Mutex<ShipperQueue>,
+    /// Held while a flush is delivering, so batches go out in order
+    delivering: tokio::sync::Mutex<()>,
+    metrics: ShippingCounters,
+    metrics_registry: Arc<MetricsRegistry>,
+}
+
+impl LogShipper {
+    pub fn new(
+        config: LogShippingConfig,
+        device_id: &str,
+        transport: Arc<dyn LogTransport>,
+    ) -> Result<Self> {
+        config.validate()?;
+        let metrics_registry = Arc::new(MetricsRegistry::new());
+        Ok(Self {
+            config,
+            device_id: device_id.to_string(),
+            transport,
+            queue: Mutex::new(ShipperQueue::default()),
+            delivering: tokio::sync::Mutex::new(()),
+            metrics: ShippingCounters::new(&metrics_registry),
+            metrics_registry,
+        })
+    }
+
+    /// Create a shipper that posts over HTTPS
+    pub fn with_http(config: LogShippingConfig, device_id: &str) -> Result<Self> {
+        Self::new(config, device_id, Arc::new(HttpLogTransport::new()?))
+    }
+
+    /// Report counters into a shared registry instead of a private one
+    pub fn with_metrics_registry(mut self, registry: Arc<MetricsRegistry>) -> Self {
+        self.metrics = ShippingCounters::new(&registry);
+        self.metrics_registry = registry;
+        self
+    }
+
+    /// Registry holding this shipper's counters
+    pub fn metrics_registry(&self) -> Arc<MetricsRegistry> {
+        Arc::clone(&self.metrics_registry)
+    }
+
+    pub fn config(&self) -> &LogShippingConfig {
+        &self.config
+    }
+
+    /// Queue a log entry if it meets the shipping level
+    pub fn record_log(&self, entry: &LogEntry) {
+        if entry.level >= self.config.min_level {
+            self.enqueue(ShippedRecord::Log(entry.clone()));
+        }
+    }
+
+    /// Queue a connection event if connection events are shipped
+    pub fn record_connection_event(&self, event: &ConnectionEvent) {
+        let level = if event.success {
+            LogLevel::Info
+        } else {
+            LogLevel::Error
+        };
+        if self.config.include_connection_events && level >= self.config.min_level {
+            self.enqueue(ShippedRecord::Connection(event.clone()));
+        }
+    }
+
+    fn enqueue(&self, record: ShippedRecord) {
+        let record = record.redacted();
+        let Ok(mut queue) = self.queue.lock() else {
+            return;
+        };
+        queue.pending.push(record);
+        self.metrics.records_queued.increment();
+        if queue.pending.len() >= self.config.batch_max_records {
+            self.seal(&mut queue);
+        }
+    }
+
+    /// Compress pending records into a batch, evicting the oldest batches
+    /// beyond the buffer cap
+    fn seal(&self, queue: &mut ShipperQueue) {
+        if queue.pending.is_empty() {
+            return;
+        }
+        let batch = LogBatch {
+            batch_id: Uuid::new_v4().to_string(),
+            device_id: self.device_id.clone(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            records: std::mem::take(&mut queue.pending),
+        };
+        let json = match serde_json::to_vec(&batch) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::error!("Failed to serialize log batch: {}", e);
+                return;
+            }
+        };
+        let body = ruzstd::encoding::compress_to_vec(
+            json.as_slice(),
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        queue.outbox_bytes += body.len();
+        queue.outbox.push_back(SealedBatch {
+            id: batch.batch_id,
+            records: batch.records.len(),
+            body,
+        });
+        while queue.outbox_bytes > self.config.max_buffer_bytes {
+            let Some(evicted) = queue.outbox.pop_front() else {
+                break;
+            };
+            queue.outbox_bytes -= evicted.body.len();
+            queue.retry = 0;
+            queue.retry_at = None;
+            self.metrics.drop_batch(&evicted);
+            tracing::warn!(
+                "Log shipping buffer full; dropped batch of {} records",
+                evicted.records
+            );
+        }
+    }
+
+    /// Seal pending records and deliver buffered batches, oldest first
+    ///
+    /// Stops at the first failure that is worth retrying and leaves the
+    /// batch queued until its backoff has elapsed. Returns the number of
+    /// batches delivered.
+    pub async fn flush(&self) -> usize {
+        let _delivering = self.delivering.lock().await;
+        let mut delivered = 0;
+        loop {
+            let batch = {
+                let Ok(mut queue) = self.queue.lock() else {
+                    return delivered;
+                };
+                self.seal(&mut queue);
+                if queue.retry_at.is_some_and(|at| Instant::now() < at) {
+                    return delivered;
+                }
+                match queue.outbox.front() {
+                    Some(batch) => batch.clone(),
+                    None => return delivered,
+                }
+            };
+
+            let result = self
+                .transport
+                .post(&self.config.endpoint, self.headers(), batch.body.clone())
+                .await;
+            let Ok(mut queue) = self.queue.lock() else {
+                return delivered;
+            };
+            match result {
+                Ok(status) if (200..300).contains(&status) => {
+                    self.metrics.batches_sent.increment();
+                    self.metrics.records_sent.add(batch.records as u64);
+                    self.metrics.bytes_sent.add(batch.body.len() as u64);
+                    delivered += 1;
+                    Self::remove_front(&mut queue, &batch.id);
+                }
+                Ok(status) if !is_retryable(status) => {
+                    tracing::warn!(
+                        "Log collector rejected batch {} with status {}",
+                        batch.id,
+                        status
+                    );
+                    self.metrics.drop_batch(&batch);
+                    Self::remove_front(&mut queue, &batch.id);
+                }
+                failure => {
+                    self.metrics.failed_attempts.increment();
+                    let backoff = self.config.backoff(queue.retry);
+                    match failure {
+                        Ok(status) => tracing::debug!(
+                            "Log collector returned {}; retrying in {:?}",
+                            status,
+                            backoff
+                        ),
+                        Err(e) => tracing::debug!(
+                            "Log collector unreachable ({}); retrying in {:?}",
+                            e,
+                            backoff
+                        ),
+                    }
+                    queue.retry += 1;
+                    queue.retry_at = Some(Instant::now() + backoff);
+                    return delivered;
+                }
+            }
+        }
+    }
+
+    fn remove_front(queue: &mut ShipperQueue, batch_id: &str) {
+        // The batch may have been evicted while the request was in flight
+        if queue
+            .outbox
+            .front()
+            .is_some_and(|front| front.id == batch_id)
+        {
+            if let Some(batch) = queue.outbox.pop_front() {
+                queue.outbox_bytes -= batch.body.len();
+            }
+        }
+        queue.retry = 0;
+        queue.retry_at = None;
+    }
+
+    fn headers(&self) -> LogShippingHeaders {
+        let mut headers = vec![(DEVICE_HEADER, self.device_id.clone())];
+        if let Some(token) = &self.config.auth_token {
+            headers.push(("Authorization", format!("Bearer {}", token)));
+        }
+        headers
+    }
+
+    /// Flush every `flush_interval_secs` until the task is aborted
+    pub fn spawn(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let shipper = Arc::clone(self);
+        let interval = Duration::from_secs(self.config.flush_interval_secs.max(1));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                shipper.flush().await;
+            }
+        })
+    }
+
+    pub fn stats(&self) -> LogShippingStats {
+        self.queue
+            .lock()
+            .map(|queue| LogShippingStats {
+                pending_records: queue.pending.len(),
+                buffered_batches: queue.outbox.len(),
+                buffered_bytes: queue.outbox_bytes,
+                retry_attempt: queue.retry,
+            })
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logging::ConnectionEventType;
+    use std::io::Read;
+
+    /// Records request bodies and answers with scripted status codes
+    #[derive(Default)]
+    struct ScriptedTransport {
+        statuses: Mutex<VecDeque<u16>>,
+        bodies: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl LogTransport for ScriptedTransport {
+        fn post<'a>(
+            &'a self,
+            _url: &'a str,
+            _headers: LogShippingHeaders,
+            body: Vec<u8>,
+        ) -> BoxFuture<'a, Result<u16>> {
+            Box::pin(async move {
+                self.bodies.lock().unwrap().push(body);
+                Ok(self.statuses.lock().unwrap().pop_front().unwrap_or(200))
+            })
+        }
+    }
+
+    fn decode(body: &[u8]) -> LogBatch {
+        let mut json = Vec::new();
+        ruzstd::decoding::StreamingDecoder::new(body)
+            .unwrap()
+            .read_to_end(&mut json)
+            .unwrap();
+        serde_json::from_slice(&json).unwrap()
+    }
+
+    fn config() -> LogShippingConfig {
+        LogShippingConfig {
+            batch_max_records: 2,
+            initial_backoff_ms: 0,
+            ..LogShippingConfig::new("https://logs.example.com/ingest")
+        }
+    }
+
+    #[test]
+    fn test_redaction() {
+        assert_eq!(
+            redact_text("ICE to 203.0.113.7:3478 failed, via fe80::1."),
+            "ICE to [ip] failed, via [ip]."
+        );
+        assert_eq!(
+            redact_text("session abc-123 at 12:30"),
+            "session abc-123 at 12:30"
+        );
+
+        let mut metadata = serde_json::json!({
+            "relay": {"turn_password": "hunter2", "host": "10.0.0.2"},
+            "Access_Code": "123456",
+            "fps": 30,
+        });
+        redact_value(&mut metadata);
+        assert_eq!(metadata["relay"]["turn_password"], REDACTED);
+        assert_eq!(metadata["relay"]["host"], "[ip]");
+        assert_eq!(metadata["Access_Code"], REDACTED);
+        assert_eq!(metadata["fps"], 30);
+
+        assert!(LogShippingConfig::new("http://logs.example.com")
+            .validate()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_batches_compressed_retried_and_capped() {
+        let transport = Arc::new(ScriptedTransport::default());
+        transport.statuses.lock().unwrap().extend([503, 200, 200]);
+        let shipper = LogShipper::new(config(), "device-1", transport.clone()).unwrap();
+
+        shipper.record_log(&LogEntry::new(LogLevel::Debug, "Capture", "below level"));
+        shipper.record_log(&LogEntry::new(
+            LogLevel::Info,
+            "Network",
+            "peer 192.168.1.4",
+        ));
+        shipper.record_connection_event(
+            &ConnectionEvent::new(ConnectionEventType::ConnectionFailed).with_error("timeout"),
+        );
+        shipper.record_log(&LogEntry::new(LogLevel::Warn, "Network", "slow"));
+        let stats = shipper.stats();
+        assert_eq!(stats.pending_records, 1);
+        assert_eq!(stats.buffered_batches, 1);
+
+        // First attempt hits a 503 and stays queued
+        assert_eq!(shipper.flush().await, 0);
+        assert_eq!(shipper.stats().buffered_batches, 2);
+        assert_eq!(shipper.stats().retry_attempt, 1);
+        assert_eq!(shipper.flush().await, 2);
+        assert_eq!(shipper.stats(), LogShippingStats::default());
+
+        let bodies = transport.bodies.lock().unwrap().clone();
+        let batch = decode(&bodies[1]);
+        assert_eq!(batch.device_id, "device-1");
+        assert_eq!(batch.records.len(), 2);
+        assert!(matches!(
+            &batch.records[0],
+            ShippedRecord::Log(entry) if entry.message == "peer [ip]"
+        ));
+        assert!(matches!(&batch.records[1], ShippedRecord::Connection(_)));
+
+        let metrics = shipper.metrics_registry().snapshot();
+        assert_eq!(metrics.get("log_shipping.records_sent"), 3);
+        assert_eq!(metrics.get("log_shipping.failed_attempts"), 1);
+
+        // A buffer smaller than one batch keeps nothing while offline
+        let shipper = LogShipper::new(
+            LogShippingConfig {
+                max_buffer_bytes: 1,
+                ..config()
+            },
+            "device-1",
+            transport,
+        )
+        .unwrap();
+        shipper.record_log(&LogEntry::new(LogLevel::Info, "A", "one"));
+        shipper.record_log(&LogEntry::new(LogLevel::Info, "A", "two"));
+        assert_eq!(shipper.stats().buffered_batches, 0);
+        let metrics = shipper.metrics_registry().snapshot();
+        assert_eq!(metrics.get("log_shipping.records_dropped"), 2);
+    }
+}
@@ -1,3 +1,7 @@
+use crate::connection_failure::ConnectionFailure;
+#[cfg(feature = "log-shipping")]
+use crate::log_shipping::{LogShipper, LogShippingConfig};
+use crate::memory_budget::{MemoryBudget, MemorySubsystem};
 use anyhow::Result;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -64,6 +68,16 @@ impl LogEntry {
         }
     }
 
+    /// 在内存预算中计入的字节数（估算）
+    pub fn footprint(&self) -> u64 {
+        let strings = self.category.len()
+            + self.message.len()
+            + self.session_id.as_ref().map_or(0, String::len)
+            + self.device_id.as_ref().map_or(0, String::len)
+            + self.metadata.as_ref().map_or(0, |m| m.to_string().len());
+        (std::mem::size_of::<Self>() + strings) as u64
+    }
+
     pub fn with_metadata(mut self, metadata: serde_json::Value) -> Self {
         self.metadata = Some(metadata);
         self
@@ -121,6 +135,7 @@ pub enum ConnectionEventType {
     DataChannelOpened,
     DataChannelClosed,
     QualityChanged,
+    VideoFreeze,
 }
 
 impl std::fmt::Display for ConnectionEventType {
@@ -140,6 +155,7 @@ impl std::fmt::Display for ConnectionEventType {
             ConnectionEventType::DataChannelOpened => write!(f, "数据通道打开"),
             ConnectionEventType::DataChannelClosed => write!(f, "数据通道关闭"),
             ConnectionEventType::QualityChanged => write!(f, "质量变化"),
+            ConnectionEventType::VideoFreeze => write!(f, "画面冻结"),
         }
     }
 }
@@ -154,6 +170,9 @@ pub struct ConnectionEvent {
     pub details: Option<serde_json::Value>,
     pub success: bool,
     pub error_message: Option<String>,
+    /// 失败分类及处理建议
+    #[serde(default)]
+    pub failure: Option<ConnectionFailure>,
 }
 
 impl ConnectionEvent {
@@ -166,6 +185,7 @@ impl ConnectionEvent {
             details: None,
             success: true,
             error_message: None,
+            failure: None,
         }
     }
 
@@ -189,6 +209,16 @@ impl ConnectionEvent {
         self.error_message = Some(error.to_string());
         self
     }
+
+    /// 附加失败分类；未设置错误信息时使用分类说明
+    pub fn with_failure(mut self, failure: ConnectionFailure) -> Self {
+        self.success = false;
+        if self.error_message.is_none() {
+            self.error_message = Some(failure.message.clone());
+        }
+        self.failure = Some(failure);
+        self
+    }
 }
 
 /// 日志配置
@@ -201,6 +231,10 @@ pub struct LogConfig {
     pub max_file_size_mb: u64,
     pub rotate_logs: bool,
     pub max_log_files: u32,
+    /// 远程日志上传配置；为 None 时不上传（默认）
+    #[cfg(feature = "log-shipping")]
+    #[serde(default)]
+    pub shipping: Option<LogShippingConfig>,
 }
 
 impl Default for LogConfig {
@@ -213,6 +247,8 @@ impl Default for LogConfig {
             max_file_size_mb: 10,
             rotate_logs: true,
             max_log_files: 5,
+            #[cfg(feature = "log-shipping")]
+            shipping: None,
         }
     }
 }
@@ -223,6 +259,26 @@ pub struct LogManager {
     logs: Arc<RwLock<VecDeque<LogEntry>>>,
     connection_events: Arc<RwLock<VecDeque<ConnectionEvent>>>,
     file_writer: Arc<RwLock<Option<BufWriter<File>>>>,
+    #[cfg(feature = "log-shipping")]
+    shipping: Arc<RwLock<Option<ActiveShipping>>>,
+    /// 内存日志计入的共享内存预算
+    memory_budget: Option<Arc<MemoryBudget>>,
+}
+
+/// 已启用的日志上传器及其后台发送任务
+#[cfg(feature = "log-shipping")]
+struct ActiveShipping {
+    shipper: Arc<LogShipper>,
+    task: Option<tokio::task::JoinHandle<()>>,
+}
+
+#[cfg(feature = "log-shipping")]
+impl Drop for ActiveShipping {
+    fn drop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
 }
 
 impl LogManager {
@@ -246,11 +302,48 @@ impl LogManager {
             logs: Arc::new(RwLock::new(VecDeque::new())),
             connection_events: Arc::new(RwLock::new(VecDeque::new())),
             file_writer: Arc::new(RwLock::new(file_writer)),
+            #[cfg(feature = "log-shipping")]
+            shipping: Arc::new(RwLock::new(None)),
+            memory_budget: None,
         }
     }
 
+    /// 将内存日志计入 `budget`；内存紧张时从最旧的条目开始丢弃
+    pub fn with_memory_budget(mut self, budget: Arc<MemoryBudget>) -> Self {
+        let logs = self.logs.clone();
+        budget.on_pressure(
+            MemorySubsystem::LogHistory,
+            Arc::new(move |wanted| {
+                let Ok(mut logs) = logs.try_write() else {
+                    return 0;
+                };
+                let mut freed = 0;
+                while freed < wanted {
+                    match logs.pop_back() {
+                        Some(entry) => freed += entry.footprint(),
+                        None => break,
+                    }
+                }
+                freed
+            }),
+        );
+        self.memory_budget = Some(budget);
+        self
+    }
+
     /// 记录日志
     pub fn log(&self, entry: LogEntry) {
+        #[cfg(feature = "log-shipping")]
+        if entry.level >= self.get_log_level() {
+            if let Some(shipper) = self.log_shipper() {
+                shipper.record_log(&entry);
+            }
+        }
+        self.write_entry(entry);
+    }
+
+    /// 写入文件、内存与 tracing，不做远程上传
+    fn write_entry(&self, entry: LogEntry) {
         let config = self.config.read().unwrap();
 
         // 检查日志级别
@@ -270,9 +363,13 @@ impl LogManager {
 
         // 添加到内存日志
         if let Ok(mut logs) = self.logs.write() {
-            logs.push_front(entry.clone());
+            if self.reserve_log_memory(&mut logs, entry.footprint()) {
+                logs.push_front(entry.clone());
+            }
             while logs.len() > config.max_entries {
-                logs.pop_back();
+                if let Some(old) = logs.pop_back() {
+                    self.release_log_memory(old.footprint());
+                }
             }
         }
 
@@ -285,6 +382,26 @@ impl LogManager {
         }
     }
 
+    /// 在预算中为新条目预留内存，不足时先丢弃最旧的条目
+    fn reserve_log_memory(&self, logs: &mut VecDeque<LogEntry>, bytes: u64) -> bool {
+        let Some(budget) = &self.memory_budget else {
+            return true;
+        };
+        while !budget.try_reserve(MemorySubsystem::LogHistory, bytes) {
+            match logs.pop_back() {
+                Some(old) => budget.release(MemorySubsystem::LogHistory, old.footprint()),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    fn release_log_memory(&self, bytes: u64) {
+        if let Some(budget) = &self.memory_budget {
+            budget.release(MemorySubsystem::LogHistory, bytes);
+        }
+    }
+
     /// 便捷日志方法
     pub fn debug(&self, category: &str, message: &str) {
         self.log(LogEntry::new(LogLevel::Debug, category, message));
@@ -310,11 +427,18 @@ impl LogManager {
         } else {
             LogLevel::Error
         };
-        let message = if let Some(ref err) = event.error_message {
+        let mut message = if let Some(ref err) = event.error_message {
             format!("{}: {}", event.event_type, err)
         } else {
             format!("{}", event.event_type)
         };
+        if let Some(ref failure) = event.failure {
+            message.push_str(&format!(
+                " [{} → {}]",
+                failure.category,
+                failure.remediation.code()
+            ));
+        }
 
         let mut entry = LogEntry::new(level, "Connection", &message);
         if let Some(ref session_id) = event.session_id {
@@ -326,7 +450,13 @@ impl LogManager {
         if let Some(ref details) = event.details {
             entry = entry.with_metadata(details.clone());
         }
-        self.log(entry);
+        // 连接事件本身会被上传，派生的日志条目不再重复上传
+        self.write_entry(entry);
+
+        #[cfg(feature = "log-shipping")]
+        if let Some(shipper) = self.log_shipper() {
+            shipper.record_connection_event(&event);
+        }
 
         // 添加到连接事件列表
         if let Ok(mut events) = self.connection_events.write() {
@@ -360,7 +490,9 @@ impl LogManager {
     /// 清除日志
     pub fn clear_logs(&self) {
         if let Ok(mut logs) = self.logs.write() {
+            let bytes = logs.iter().map(LogEntry::footprint).sum();
             logs.clear();
+            self.release_log_memory(bytes);
         }
     }
 
@@ -465,6 +597,46 @@ impl LogManager {
             config.log_to_file = false;
         }
     }
+
+    /// 按配置启动远程日志上传，需在 tokio 运行时中调用
+    ///
+    /// 未配置 `shipping` 时返回 None。
+    #[cfg(feature = "log-shipping")]
+    pub fn start_shipping(&self, device_id: &str) -> Result<Option<Arc<LogShipper>>> {
+        let Some(config) = self.config.read().unwrap().shipping.clone() else {
+            return Ok(None);
+        };
+        let shipper = Arc::new(LogShipper::with_http(config, device_id)?);
+        let task = shipper.spawn();
+        if let Ok(mut shipping) = self.shipping.write() {
+            *shipping = Some(ActiveShipping {
+                shipper: Arc::clone(&shipper),
+                task: Some(task),
+            });
+        }
+        Ok(Some(shipper))
+    }
+
+    /// 安装由调用方负责发送的上传器；传入 None 停止上传
+    #[cfg(feature = "log-shipping")]
+    pub fn set_log_shipper(&self, shipper: Option<Arc<LogShipper>>) {
+        if let Ok(mut shipping) = self.shipping.write() {
+            *shipping = shipper.map(|shipper| ActiveShipping {
+                shipper,
+                task: None,
+            });
+        }
+    }
+
+    /// 当前的日志上传器
+    #[cfg(feature = "log-shipping")]
+    pub fn log_shipper(&self) -> Option<Arc<LogShipper>> {
+        self.shipping
+            .read()
+            .ok()?
+            .as_ref()
+            .map(|active| Arc::clone(&active.shipper))
+    }
 }
 
 impl Default for LogManager {
@@ -514,6 +686,48 @@ mod tests {
         assert_eq!(logs.len(), 2); // Only warn and error
     }
 
+    #[cfg(feature = "log-shipping")]
+    #[test]
+    fn test_records_shipped_once() {
+        use crate::log_shipping::{LogShippingHeaders, LogTransport};
+
+        struct NoTransport;
+        impl LogTransport for NoTransport {
+            fn post<'a>(
+                &'a self,
+                _url: &'a str,
+                _headers: LogShippingHeaders,
+                _body: Vec<u8>,
+            ) -> futures::future::BoxFuture<'a, Result<u16>> {
+                Box::pin(async { Err(anyhow::anyhow!("offline")) })
+            }
+        }
+
+        let manager = LogManager::default();
+        assert!(manager.start_shipping("device-1").unwrap().is_none());
+        let shipper = Arc::new(
+            LogShipper::new(
+                LogShippingConfig::new("https://logs.example.com/ingest"),
+                "device-1",
+                Arc::new(NoTransport),
+            )
+            .unwrap(),
+        );
+        manager.set_log_shipper(Some(Arc::clone(&shipper)));
+
+        manager.debug("Test", "below the local level");
+        manager.info("Test", "shipped");
+        manager.log_connection_event(ConnectionEvent::new(
+            ConnectionEventType::ConnectionEstablished,
+        ));
+        assert_eq!(manager.get_logs(None, None).len(), 2);
+        assert_eq!(shipper.stats().pending_records, 2);
+
+        manager.set_log_shipper(None);
+        manager.info("Test", "not shipped");
+        assert_eq!(shipper.stats().pending_records, 2);
+    }
+
     #[test]
     fn test_connection_event_logging() {
         let manager = LogManager::default();
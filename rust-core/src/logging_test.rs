@@ -3,9 +3,11 @@
 //! Feature: cec-remote, Property 13: Connection Event Logging
 //! Validates: Requirements 14.1
 
+use crate::connection_failure::{classify, FailureCategory, FailureContext};
 use crate::logging::{
     ConnectionEvent, ConnectionEventType, LogConfig, LogEntry, LogLevel, LogManager,
 };
+use crate::session_bootstrap::BootstrapState;
 use proptest::prelude::*;
 
 /// Generate arbitrary log levels
@@ -341,6 +343,32 @@ mod tests {
         );
     }
 
+    /// Unit test: Classified failures keep their category and remediation
+    #[test]
+    fn test_classified_failure_logged() {
+        let manager = LogManager::default();
+        let failure = classify(&FailureContext {
+            stage: Some(BootstrapState::Authorizing),
+            auth_denied: true,
+            ..Default::default()
+        });
+
+        manager.log_connection_event(
+            ConnectionEvent::new(ConnectionEventType::ConnectionFailed)
+                .with_session("session-123")
+                .with_failure(failure),
+        );
+
+        let events = manager.get_connection_events(None);
+        assert!(!events[0].success);
+        let logged = events[0].failure.as_ref().unwrap();
+        assert_eq!(logged.category, FailureCategory::PermissionDenied);
+        assert_eq!(events[0].error_message.as_ref(), Some(&logged.message));
+
+        let logs = manager.get_logs(Some(LogLevel::Error), None);
+        assert!(logs[0].message.contains("request_access"));
+    }
+
     /// Unit test: Connection closed event is logged correctly
     #[test]
     fn test_connection_closed_event_logged() {
@@ -0,0 +1,394 @@
+//! Memory Budget
+//!
+//! Buffer pools, frame queues, the in-memory log and replay nonce sets each
+//! cap themselves by count, which says nothing about how many bytes they
+//! hold together. `MemoryBudget` accounts bytes per subsystem against a
+//! quota and against one global ceiling.
+//!
+//! A subsystem reserves bytes before it retains data and releases them when
+//! the data goes. When a reservation would cross the global ceiling, the
+//! budget first asks the other subsystems' pressure handlers to shrink
+//! their caches and only refuses if that does not free enough. Refusal is
+//! not an error: the caller drops or evicts instead of growing.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Share of the global ceiling above which pressure is elevated
+const ELEVATED_PRESSURE: f64 = 0.8;
+
+/// Memory consumer tracked by the budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MemorySubsystem {
+    /// Idle buffers kept by `BufferPool` for reuse
+    BufferPool,
+    /// Frames queued in `FrameBufferManager`
+    FrameBuffers,
+    /// In-memory log history of `LogManager`
+    LogHistory,
+    /// Seen nonces kept for replay detection
+    ReplayNonces,
+}
+
+impl MemorySubsystem {
+    pub const ALL: [MemorySubsystem; 4] = [
+        MemorySubsystem::BufferPool,
+        MemorySubsystem::FrameBuffers,
+        MemorySubsystem::LogHistory,
+        MemorySubsystem::ReplayNonces,
+    ];
+}
+
+/// How close total usage is to the global ceiling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum MemoryPressure {
+    Normal,
+    /// Above 80% of the ceiling
+    Elevated,
+    /// At the ceiling; reservations only succeed after caches shrink
+    Critical,
+}
+
+/// Global ceiling and per-subsystem quotas in bytes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryBudgetConfig {
+    pub global_limit: u64,
+    /// Subsystems without a quota are bounded by the global limit only
+    pub quotas: HashMap<MemorySubsystem, u64>,
+}
+
+impl Default for MemoryBudgetConfig {
+    fn default() -> Self {
+        Self {
+            global_limit: 512 * 1024 * 1024,
+            quotas: HashMap::from([
+                (MemorySubsystem::BufferPool, 64 * 1024 * 1024),
+                (MemorySubsystem::FrameBuffers, 384 * 1024 * 1024),
+                (MemorySubsystem::LogHistory, 16 * 1024 * 1024),
+                (MemorySubsystem::ReplayNonces, 16 * 1024 * 1024),
+            ]),
+        }
+    }
+}
+
+/// Usage of one subsystem
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubsystemUsage {
+    pub subsystem: MemorySubsystem,
+    pub used_bytes: u64,
+    pub quota_bytes: Option<u64>,
+    pub peak_bytes: u64,
+    /// Reservations refused for this subsystem
+    pub rejected: u64,
+}
+
+/// Snapshot of the whole budget
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemoryBudgetUsage {
+    pub used_bytes: u64,
+    pub global_limit: u64,
+    pub peak_bytes: u64,
+    pub pressure: MemoryPressure,
+    /// Bytes pressure handlers have freed so far
+    pub reclaimed_bytes: u64,
+    pub subsystems: Vec<SubsystemUsage>,
+}
+
+/// Shrinks a subsystem's cache by about `bytes`; returns the bytes freed
+///
+/// Handlers run on the thread that needed the memory, possibly while it
+/// holds its own subsystem's lock, so they must only `try_lock` and return
+/// 0 when their state is busy. The budget credits the returned bytes to the
+/// handler's subsystem; handlers must not call `release` themselves.
+pub type PressureHandler = Arc<dyn Fn(u64) -> u64 + Send + Sync>;
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Account {
+    used: u64,
+    peak: u64,
+    rejected: u64,
+}
+
+#[derive(Debug, Default)]
+struct BudgetState {
+    accounts: HashMap<MemorySubsystem, Account>,
+    peak: u64,
+    reclaimed: u64,
+}
+
+impl BudgetState {
+    fn total(&self) -> u64 {
+        self.accounts.values().map(|a| a.used).sum()
+    }
+}
+
+/// Byte accounting shared by every bounded buffer and cache
+pub struct MemoryBudget {
+    config: MemoryBudgetConfig,
+    state: Mutex<BudgetState>,
+    handlers: Mutex<Vec<(MemorySubsystem, PressureHandler)>>,
+}
+
+impl std::fmt::Debug for MemoryBudget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MemoryBudget")
+            .field("config", &self.config)
+            .field("usage", &self.usage())
+            .finish()
+    }
+}
+
+impl MemoryBudget {
+    pub fn new(config: MemoryBudgetConfig) -> Self {
+        Self {
+            config,
+            state: Mutex::new(BudgetState::default()),
+            handlers: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn config(&self) -> &MemoryBudgetConfig {
+        &self.config
+    }
+
+    pub fn quota(&self, subsystem: MemorySubsystem) -> Option<u64> {
+        self.config.quotas.get(&subsystem).copied()
+    }
+
+    /// Call `handler` to shrink `subsystem` when memory runs short elsewhere
+    pub fn on_pressure(&self, subsystem: MemorySubsystem, handler: PressureHandler) {
+        self.handlers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push((subsystem, handler));
+    }
+
+    /// Account `bytes` to `subsystem` if its quota and the ceiling allow it
+    ///
+    /// Going over the ceiling triggers the other subsystems' pressure
+    /// handlers once before giving up. A subsystem over its own quota has
+    /// to make room itself, so no handlers run for that.
+    pub fn try_reserve(&self, subsystem: MemorySubsystem, bytes: u64) -> bool {
+        let shortfall = match self.reserve_within_limits(subsystem, bytes) {
+            Ok(()) => return true,
+            Err(Shortfall::Quota) => {
+                self.record_rejection(subsystem);
+                return false;
+            }
+            Err(Shortfall::Global(missing)) => missing,
+        };
+
+        self.relieve_pressure(shortfall, Some(subsystem));
+        match self.reserve_within_limits(subsystem, bytes) {
+            Ok(()) => true,
+            Err(_) => {
+                self.record_rejection(subsystem);
+                tracing::debug!("Memory budget refused {} bytes for {:?}", bytes, subsystem);
+                false
+            }
+        }
+    }
+
+    /// Return bytes previously reserved by `subsystem`
+    pub fn release(&self, subsystem: MemorySubsystem, bytes: u64) {
+        let mut state = self.lock_state();
+        let account = state.accounts.entry(subsystem).or_default();
+        account.used = account.used.saturating_sub(bytes);
+    }
+
+    /// Ask pressure handlers to free about `bytes`, skipping `except`
+    ///
+    /// Handlers run in registration order until enough is freed. Returns
+    /// the bytes actually freed.
+    pub fn relieve_pressure(&self, bytes: u64, except: Option<MemorySubsystem>) -> u64 {
+        let handlers: Vec<_> = self
+            .handlers
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .filter(|(subsystem, _)| Some(*subsystem) != except)
+            .cloned()
+            .collect();
+
+        let mut freed = 0;
+        for (subsystem, handler) in handlers {
+            if freed >= bytes {
+                break;
+            }
+            let released = handler(bytes - freed);
+            if released > 0 {
+                self.release(subsystem, released);
+                self.lock_state().reclaimed += released;
+                freed += released;
+            }
+        }
+        freed
+    }
+
+    pub fn used(&self, subsystem: MemorySubsystem) -> u64 {
+        self.lock_state()
+            .accounts
+            .get(&subsystem)
+            .map_or(0, |a| a.used)
+    }
+
+    pub fn total_used(&self) -> u64 {
+        self.lock_state().total()
+    }
+
+    pub fn pressure(&self) -> MemoryPressure {
+        self.pressure_at(self.total_used())
+    }
+
+    pub fn usage(&self) -> MemoryBudgetUsage {
+        let state = self.lock_state();
+        let used_bytes = state.total();
+        let subsystems = MemorySubsystem::ALL
+            .iter()
+            .map(|&subsystem| {
+                let account = state.accounts.get(&subsystem).copied().unwrap_or_default();
+                SubsystemUsage {
+                    subsystem,
+                    used_bytes: account.used,
+                    quota_bytes: self.quota(subsystem),
+                    peak_bytes: account.peak,
+                    rejected: account.rejected,
+                }
+            })
+            .collect();
+        MemoryBudgetUsage {
+            used_bytes,
+            global_limit: self.config.global_limit,
+            peak_bytes: state.peak,
+            pressure: self.pressure_at(used_bytes),
+            reclaimed_bytes: state.reclaimed,
+            subsystems,
+        }
+    }
+
+    fn reserve_within_limits(
+        &self,
+        subsystem: MemorySubsystem,
+        bytes: u64,
+    ) -> Result<(), Shortfall> {
+        let mut state = self.lock_state();
+        let total = state.total();
+        let account = state.accounts.entry(subsystem).or_default();
+        if self
+            .quota(subsystem)
+            .is_some_and(|quota| account.used + bytes > quota)
+        {
+            return Err(Shortfall::Quota);
+        }
+        if total + bytes > self.config.global_limit {
+            return Err(Shortfall::Global(total + bytes - self.config.global_limit));
+        }
+        account.used += bytes;
+        account.peak = account.peak.max(account.used);
+        state.peak = state.peak.max(total + bytes);
+        Ok(())
+    }
+
+    fn record_rejection(&self, subsystem: MemorySubsystem) {
+        self.lock_state()
+            .accounts
+            .entry(subsystem)
+            .or_default()
+            .rejected += 1;
+    }
+
+    fn pressure_at(&self, used: u64) -> MemoryPressure {
+        let limit = self.config.global_limit;
+        if used >= limit {
+            MemoryPressure::Critical
+        } else if used as f64 > limit as f64 * ELEVATED_PRESSURE {
+            MemoryPressure::Elevated
+        } else {
+            MemoryPressure::Normal
+        }
+    }
+
+    fn lock_state(&self) -> std::sync::MutexGuard<'_, BudgetState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for MemoryBudget {
+    fn default() -> Self {
+        Self::new(MemoryBudgetConfig::default())
+    }
+}
+
+enum Shortfall {
+    Quota,
+    /// Bytes by which the ceiling would be exceeded
+    Global(u64),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    fn budget(global_limit: u64, quotas: &[(MemorySubsystem, u64)]) -> MemoryBudget {
+        MemoryBudget::new(MemoryBudgetConfig {
+            global_limit,
+            quotas: quotas.iter().copied().collect(),
+        })
+    }
+
+    #[test]
+    fn test_quota_and_ceiling() {
+        let budget = budget(1000, &[(MemorySubsystem::LogHistory, 300)]);
+        assert!(budget.try_reserve(MemorySubsystem::LogHistory, 300));
+        assert!(!budget.try_reserve(MemorySubsystem::LogHistory, 1));
+        assert!(budget.try_reserve(MemorySubsystem::FrameBuffers, 600));
+        assert_eq!(budget.pressure(), MemoryPressure::Elevated);
+        assert!(!budget.try_reserve(MemorySubsystem::FrameBuffers, 200));
+
+        budget.release(MemorySubsystem::LogHistory, 300);
+        assert!(budget.try_reserve(MemorySubsystem::FrameBuffers, 200));
+
+        let usage = budget.usage();
+        assert_eq!(usage.used_bytes, 800);
+        assert_eq!(usage.peak_bytes, 900);
+        let logs = usage.subsystems[MemorySubsystem::ALL
+            .iter()
+            .position(|s| *s == MemorySubsystem::LogHistory)
+            .unwrap()];
+        assert_eq!(logs.used_bytes, 0);
+        assert_eq!(logs.peak_bytes, 300);
+        assert_eq!(logs.rejected, 1);
+    }
+
+    #[test]
+    fn test_pressure_handlers_free_other_subsystems() {
+        let budget = Arc::new(budget(1000, &[]));
+        assert!(budget.try_reserve(MemorySubsystem::ReplayNonces, 500));
+        assert!(budget.try_reserve(MemorySubsystem::FrameBuffers, 400));
+
+        let cache = Arc::new(AtomicU64::new(500));
+        let nonces = cache.clone();
+        budget.on_pressure(
+            MemorySubsystem::ReplayNonces,
+            Arc::new(move |wanted| {
+                let freed = wanted.min(nonces.load(Ordering::Relaxed));
+                nonces.fetch_sub(freed, Ordering::Relaxed);
+                freed
+            }),
+        );
+        // The requesting subsystem's own handler is never asked
+        budget.on_pressure(MemorySubsystem::FrameBuffers, Arc::new(|_| unreachable!()));
+
+        assert!(budget.try_reserve(MemorySubsystem::FrameBuffers, 300));
+        assert_eq!(budget.used(MemorySubsystem::ReplayNonces), 300);
+        assert_eq!(cache.load(Ordering::Relaxed), 300);
+        assert_eq!(budget.total_used(), 1000);
+        assert_eq!(budget.pressure(), MemoryPressure::Critical);
+        assert_eq!(budget.usage().reclaimed_bytes, 200);
+
+        // Nothing left to reclaim beyond the cache itself
+        assert!(!budget.try_reserve(MemorySubsystem::FrameBuffers, 400));
+    }
+}
@@ -0,0 +1,266 @@
+//! Lock-free Metrics Registry
+//!
+//! Counters bumped per message or per frame used to sit behind the same
+//! `RwLock` as the structs that report them, so every increment contended
+//! with readers. Here each counter is a standalone atomic: hot paths hold an
+//! `Arc<Counter>` and never lock. The registry lock is only taken to register
+//! a metric and to take a snapshot, which the existing metrics structs are
+//! then built from.
+//!
+//! The registry is bounded by `MAX_METRICS`; registrations beyond it get a
+//! working but unregistered counter so callers never fail.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::time::{Duration, Instant};
+
+/// Maximum number of metrics one registry tracks
+pub const MAX_METRICS: usize = 256;
+
+/// Monotonic event counter
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn increment(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Start counting again from zero, e.g. for a new capture run
+    pub fn reset(&self) {
+        self.0.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Last-written value, such as a duration
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicU64);
+
+impl Gauge {
+    pub fn set(&self, value: u64) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Metric {
+    Counter(Arc<Counter>),
+    Gauge(Arc<Gauge>),
+}
+
+impl Metric {
+    fn value(&self) -> u64 {
+        match self {
+            Metric::Counter(counter) => counter.get(),
+            Metric::Gauge(gauge) => gauge.get(),
+        }
+    }
+}
+
+/// Point-in-time values of every registered metric
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    pub taken_at: DateTime<Utc>,
+    pub values: BTreeMap<String, u64>,
+    /// Counter increase per second since the previous aggregation
+    pub rates: BTreeMap<String, f64>,
+}
+
+impl MetricsSnapshot {
+    pub fn get(&self, name: &str) -> u64 {
+        self.values.get(name).copied().unwrap_or(0)
+    }
+
+    pub fn rate(&self, name: &str) -> Option<f64> {
+        self.rates.get(name).copied()
+    }
+}
+
+/// Named counters and gauges shared by a subsystem
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    metrics: RwLock<BTreeMap<String, Metric>>,
+    /// Counter values at the last aggregation, for rates
+    previous: Mutex<Option<(Instant, BTreeMap<String, u64>)>>,
+    latest: RwLock<Option<MetricsSnapshot>>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counter registered under `name`, created on first use
+    pub fn counter(&self, name: &str) -> Arc<Counter> {
+        match self.register(name, || Metric::Counter(Arc::default())) {
+            Some(Metric::Counter(counter)) => counter,
+            _ => Arc::default(),
+        }
+    }
+
+    /// Gauge registered under `name`, created on first use
+    pub fn gauge(&self, name: &str) -> Arc<Gauge> {
+        match self.register(name, || Metric::Gauge(Arc::default())) {
+            Some(Metric::Gauge(gauge)) => gauge,
+            _ => Arc::default(),
+        }
+    }
+
+    fn register(&self, name: &str, create: impl FnOnce() -> Metric) -> Option<Metric> {
+        let mut metrics = self.metrics.write().ok()?;
+        if let Some(metric) = metrics.get(name) {
+            return Some(metric.clone());
+        }
+        if metrics.len() >= MAX_METRICS {
+            tracing::warn!("Metrics registry full; {} will not be reported", name);
+            return None;
+        }
+        let metric = create();
+        metrics.insert(name.to_string(), metric.clone());
+        Some(metric)
+    }
+
+    /// Current values, without rates
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let values = self
+            .metrics
+            .read()
+            .map(|metrics| {
+                metrics
+                    .iter()
+                    .map(|(name, metric)| (name.clone(), metric.value()))
+                    .collect()
+            })
+            .unwrap_or_default();
+        MetricsSnapshot {
+            taken_at: Utc::now(),
+            values,
+            rates: BTreeMap::new(),
+        }
+    }
+
+    /// Take a snapshot with rates since the previous aggregation and keep it
+    /// as the latest
+    pub fn aggregate(&self) -> MetricsSnapshot {
+        let mut snapshot = self.snapshot();
+        let now = Instant::now();
+        let counters: BTreeMap<String, u64> = self
+            .metrics
+            .read()
+            .map(|metrics| {
+                metrics
+                    .iter()
+                    .filter(|(_, metric)| matches!(metric, Metric::Counter(_)))
+                    .map(|(name, _)| (name.clone(), snapshot.get(name)))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        if let Ok(mut previous) = self.previous.lock() {
+            if let Some((at, before)) = previous.as_ref() {
+                let secs = now.duration_since(*at).as_secs_f64();
+                if secs > 0.0 {
+                    snapshot.rates = counters
+                        .iter()
+                        .map(|(name, value)| {
+                            let delta =
+                                value.saturating_sub(before.get(name).copied().unwrap_or(0));
+                            (name.clone(), delta as f64 / secs)
+                        })
+                        .collect();
+                }
+            }
+            *previous = Some((now, counters));
+        }
+        if let Ok(mut latest) = self.latest.write() {
+            *latest = Some(snapshot.clone());
+        }
+        snapshot
+    }
+
+    /// Most recent aggregated snapshot
+    pub fn latest(&self) -> Option<MetricsSnapshot> {
+        self.latest.read().ok().and_then(|latest| latest.clone())
+    }
+
+    /// Aggregate every `interval` until the registry is dropped
+    pub fn spawn_aggregation(self: &Arc<Self>, interval: Duration) -> tokio::task::JoinHandle<()> {
+        let registry: Weak<Self> = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match registry.upgrade() {
+                    Some(registry) => {
+                        registry.aggregate();
+                    }
+                    None => break,
+                }
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_counters_and_bound() {
+        let registry = MetricsRegistry::new();
+        let sent = registry.counter("signaling.messages_sent");
+        registry.counter("signaling.messages_sent").add(2);
+        sent.increment();
+        registry.gauge("signaling.last_exchange_ms").set(40);
+
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot.get("signaling.messages_sent"), 3);
+        assert_eq!(snapshot.get("signaling.last_exchange_ms"), 40);
+
+        // A name already used for a gauge does not turn into a counter
+        let detached = registry.counter("signaling.last_exchange_ms");
+        detached.add(5);
+        assert_eq!(registry.snapshot().get("signaling.last_exchange_ms"), 40);
+
+        for i in 0..MAX_METRICS {
+            registry.counter(&format!("extra.{}", i)).increment();
+        }
+        assert_eq!(registry.snapshot().values.len(), MAX_METRICS);
+    }
+
+    #[tokio::test]
+    async fn test_aggregation_reports_rates() {
+        let registry = Arc::new(MetricsRegistry::new());
+        let frames = registry.counter("capture.frames_captured");
+        assert!(registry.aggregate().rates.is_empty());
+
+        frames.add(30);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let snapshot = registry.aggregate();
+        assert_eq!(snapshot.get("capture.frames_captured"), 30);
+        assert!(snapshot.rate("capture.frames_captured").unwrap() > 0.0);
+        assert_eq!(registry.latest(), Some(snapshot));
+
+        let handle = registry.spawn_aggregation(Duration::from_millis(10));
+        drop(registry);
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
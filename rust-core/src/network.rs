@@ -1,9 +1,20 @@
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use crate::idle::ActivityProfile;
+use crate::link_stats::{self, LinkMonitor};
+use crate::secrets::SecretsStore;
+use crate::signaling::SignalingClient;
+use crate::stun::{
+    self, ServerAddress, StunConfig, StunTransport, TurnAllocation, DEFAULT_ALLOCATION_LIFETIME,
+};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::{mpsc, Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{mpsc, oneshot, Mutex, Notify, RwLock};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,8 +27,17 @@ pub struct NetworkStats {
     pub local_address: Option<String>,
     pub remote_address: Option<String>,
     pub protocol: NetworkProtocol,
+    /// Address family that won the last happy-eyeballs race
+    #[serde(default)]
+    pub winning_family: Option<NetworkProtocol>,
 }
 
+/// Delay before starting the second address family (RFC 8305 Connection Attempt Delay)
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// How long before expiry a TURN allocation is refreshed
+const TURN_REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
 impl Default for NetworkStats {
     fn default() -> Self {
         Self {
@@ -29,6 +49,7 @@ impl Default for NetworkStats {
             local_address: None,
             remote_address: None,
             protocol: NetworkProtocol::IPv4,
+            winning_family: None,
         }
     }
 }
@@ -63,6 +84,29 @@ pub struct TurnServer {
     pub priority: u32,
 }
 
+/// Transports ICE may use, for networks that block UDP
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IceTransportConfig {
+    /// Offer ICE-TCP host candidates (RFC 6544) alongside the UDP ones
+    pub tcp_candidates: bool,
+    /// Port to try TURN over TLS on when a server cannot be reached over
+    /// UDP or TCP; 443 gets through most corporate firewalls and proxies
+    pub tls_fallback_port: Option<u16>,
+    /// Gather only relay candidates reached over TCP or TLS
+    pub force_relay_over_tcp: bool,
+}
+
+impl Default for IceTransportConfig {
+    fn default() -> Self {
+        Self {
+            tcp_candidates: true,
+            tls_fallback_port: Some(443),
+            force_relay_over_tcp: false,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum NetworkQuality {
     Excellent, // < 50ms RTT, < 1% loss
@@ -79,6 +123,25 @@ pub enum NetworkEvent {
     ProtocolFallback(NetworkProtocol, NetworkProtocol), // from, to
     StatsUpdated(NetworkStats),
     QualityWarning(String),
+    /// A local candidate was found; published as soon as it is known so it
+    /// can be trickled to the peer
+    CandidateGathered(IceCandidate),
+    /// Gathering finished with this many candidates
+    GatheringComplete(usize),
+}
+
+impl EventType for NetworkEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            NetworkEvent::QualityChanged(_) => "QualityChanged",
+            NetworkEvent::ConnectionTypeChanged(_) => "ConnectionTypeChanged",
+            NetworkEvent::ProtocolFallback(..) => "ProtocolFallback",
+            NetworkEvent::StatsUpdated(_) => "StatsUpdated",
+            NetworkEvent::QualityWarning(_) => "QualityWarning",
+            NetworkEvent::CandidateGathered(_) => "CandidateGathered",
+            NetworkEvent::GatheringComplete(_) => "GatheringComplete",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -116,12 +179,27 @@ pub struct NetworkManager {
     turn_servers: Arc<RwLock<Vec<TurnServer>>>,
     pub current_stats: Arc<RwLock<NetworkStats>>,
     pub stats_history: Arc<Mutex<Vec<NetworkStats>>>,
-    event_sender: mpsc::UnboundedSender<NetworkEvent>,
-    event_receiver: Arc<Mutex<mpsc::UnboundedReceiver<NetworkEvent>>>,
+    events: EventBus<NetworkEvent>,
     ice_candidates: Arc<Mutex<Vec<IceCandidate>>>,
     is_monitoring: Arc<RwLock<bool>>,
     ipv6_available: Arc<RwLock<bool>>,
     ipv4_available: Arc<RwLock<bool>>,
+    stun_config: StunConfig,
+    /// Last address a STUN server saw us at
+    reflexive_address: Arc<RwLock<Option<SocketAddr>>>,
+    /// Relay allocation kept alive by `turn_refresher`
+    turn_allocation: Arc<Mutex<Option<TurnAllocation>>>,
+    turn_refresher: std::sync::Mutex<Option<JoinHandle<()>>>,
+    /// Measurements behind the RTT, jitter, loss and bandwidth figures
+    link: Arc<std::sync::Mutex<LinkMonitor>>,
+    /// STUN server pinged for RTT; the preferred STUN server if unset
+    ping_target: Arc<RwLock<Option<String>>>,
+    ice_transport: Arc<RwLock<IceTransportConfig>>,
+    /// Background work level, lowered while the client is idle
+    activity: Arc<std::sync::RwLock<ActivityProfile>>,
+    /// Cuts the monitoring loop's sleep short when `activity` changes
+    activity_changed: Arc<Notify>,
+    monitor_wakeups: Arc<AtomicU64>,
 }
 
 impl Default for NetworkManager {
@@ -132,8 +210,6 @@ impl Default for NetworkManager {
 
 impl NetworkManager {
     pub fn new() -> Self {
-        let (event_sender, event_receiver) = mpsc::unbounded_channel();
-
         Self {
             id: Uuid::new_v4().to_string(),
             preferred_protocol: Arc::new(RwLock::new(NetworkProtocol::IPv6)),
@@ -154,15 +230,30 @@ impl NetworkManager {
             turn_servers: Arc::new(RwLock::new(Vec::new())),
             current_stats: Arc::new(RwLock::new(NetworkStats::default())),
             stats_history: Arc::new(Mutex::new(Vec::new())),
-            event_sender,
-            event_receiver: Arc::new(Mutex::new(event_receiver)),
+            events: EventBus::new(),
             ice_candidates: Arc::new(Mutex::new(Vec::new())),
             is_monitoring: Arc::new(RwLock::new(false)),
             ipv6_available: Arc::new(RwLock::new(false)),
             ipv4_available: Arc::new(RwLock::new(true)),
+            stun_config: StunConfig::default(),
+            reflexive_address: Arc::new(RwLock::new(None)),
+            turn_allocation: Arc::new(Mutex::new(None)),
+            turn_refresher: std::sync::Mutex::new(None),
+            link: Arc::new(std::sync::Mutex::new(LinkMonitor::new())),
+            ping_target: Arc::new(RwLock::new(None)),
+            ice_transport: Arc::new(RwLock::new(IceTransportConfig::default())),
+            activity: Arc::new(std::sync::RwLock::new(ActivityProfile::full())),
+            activity_changed: Arc::new(Notify::new()),
+            monitor_wakeups: Arc::new(AtomicU64::new(0)),
         }
     }
 
+    /// Use `config` for STUN and TURN transactions
+    pub fn with_stun_config(mut self, config: StunConfig) -> Self {
+        self.stun_config = config;
+        self
+    }
+
     pub async fn initialize(&self) -> Result<()> {
         // Check network availability
         self.check_ipv6_availability().await;
@@ -214,20 +305,46 @@ impl NetworkManager {
         *self.preferred_protocol.read().await
     }
 
+    pub async fn set_ice_transport_config(&self, config: IceTransportConfig) {
+        *self.ice_transport.write().await = config;
+        tracing::info!("ICE transport configuration: {:?}", config);
+    }
+
+    pub async fn ice_transport_config(&self) -> IceTransportConfig {
+        *self.ice_transport.read().await
+    }
+
     pub async fn add_stun_server(&self, server: StunServer) {
         let mut servers = self.stun_servers.write().await;
         servers.push(server);
-        servers.sort_by(|a, b| b.priority.cmp(&a.priority));
+        servers.sort_by_key(|s| std::cmp::Reverse(s.priority));
         tracing::info!("Added STUN server, total: {}", servers.len());
     }
 
     pub async fn add_turn_server(&self, server: TurnServer) {
         let mut servers = self.turn_servers.write().await;
         servers.push(server);
-        servers.sort_by(|a, b| b.priority.cmp(&a.priority));
+        servers.sort_by_key(|s| std::cmp::Reverse(s.priority));
         tracing::info!("Added TURN server, total: {}", servers.len());
     }
 
+    /// Fill in credentials for configured TURN servers from the secrets store
+    pub async fn load_turn_credentials(&self, store: &SecretsStore) -> Result<usize> {
+        let mut servers = self.turn_servers.write().await;
+        let mut loaded = 0;
+
+        for server in servers.iter_mut() {
+            if let Some(credential) = store.get_turn_credential(&server.url)? {
+                server.username = credential.username;
+                server.credential = credential.credential;
+                loaded += 1;
+            }
+        }
+
+        tracing::info!("Loaded credentials for {} TURN servers", loaded);
+        Ok(loaded)
+    }
+
     pub async fn get_stun_servers(&self) -> Vec<StunServer> {
         self.stun_servers.read().await.clone()
     }
@@ -239,32 +356,35 @@ impl NetworkManager {
     // NAT Traversal and Connection Establishment
     pub async fn establish_connection(&self, target: &str) -> Result<ConnectionType> {
         let preferred = *self.preferred_protocol.read().await;
+        let fallback = match preferred {
+            NetworkProtocol::IPv6 => NetworkProtocol::IPv4,
+            NetworkProtocol::IPv4 => NetworkProtocol::IPv6,
+        };
 
-        // Try IPv6 first if preferred and available
-        if preferred == NetworkProtocol::IPv6 && *self.ipv6_available.read().await {
-            match self.try_ipv6_connection(target).await {
-                Ok(conn_type) if conn_type != ConnectionType::Unknown => {
-                    return Ok(conn_type);
-                }
-                _ => {
-                    // Fallback to IPv4
-                    let _ = self.event_sender.send(NetworkEvent::ProtocolFallback(
-                        NetworkProtocol::IPv6,
-                        NetworkProtocol::IPv4,
-                    ));
-                    tracing::info!("IPv6 connection failed, falling back to IPv4");
-                }
+        let mut families = Vec::new();
+        for family in [preferred, fallback] {
+            if self.is_family_available(family).await {
+                families.push(family);
             }
         }
 
-        // Try IPv4
-        if *self.ipv4_available.read().await {
-            match self.try_ipv4_connection(target).await {
-                Ok(conn_type) if conn_type != ConnectionType::Unknown => {
-                    return Ok(conn_type);
-                }
-                _ => {}
+        // Race the available address families (RFC 8305)
+        if let Some((family, conn_type)) =
+            race_address_families(&families, CONNECTION_ATTEMPT_DELAY, |family| {
+                self.try_family_connection(family, target)
+            })
+            .await
+        {
+            if family != preferred {
+                self.events
+                    .publish(NetworkEvent::ProtocolFallback(preferred, family));
+                tracing::info!("{:?} won the connection race over {:?}", family, preferred);
             }
+
+            let mut stats = self.current_stats.write().await;
+            stats.protocol = family;
+            stats.winning_family = Some(family);
+            return Ok(conn_type);
         }
 
         // Try STUN
@@ -279,6 +399,24 @@ impl NetworkManager {
         self.attempt_turn_connection().await
     }
 
+    async fn is_family_available(&self, family: NetworkProtocol) -> bool {
+        match family {
+            NetworkProtocol::IPv6 => *self.ipv6_available.read().await,
+            NetworkProtocol::IPv4 => *self.ipv4_available.read().await,
+        }
+    }
+
+    async fn try_family_connection(
+        &self,
+        family: NetworkProtocol,
+        target: &str,
+    ) -> Result<ConnectionType> {
+        match family {
+            NetworkProtocol::IPv6 => self.try_ipv6_connection(target).await,
+            NetworkProtocol::IPv4 => self.try_ipv4_connection(target).await,
+        }
+    }
+
     async fn try_ipv6_connection(&self, target: &str) -> Result<ConnectionType> {
         tracing::debug!("Attempting IPv6 direct connection to {}", target);
         // Placeholder - would attempt actual IPv6 connection
@@ -315,13 +453,15 @@ impl NetworkManager {
         Ok(ConnectionType::Unknown)
     }
 
-    async fn stun_binding_request(&self, _server: &StunServer) -> Result<SocketAddr> {
-        // Placeholder - would perform actual STUN binding request
-        // Returns the server-reflexive address
-        Ok(SocketAddr::new(
-            IpAddr::V4(Ipv4Addr::new(203, 0, 113, 1)),
-            12345,
-        ))
+    async fn stun_binding_request(&self, server: &StunServer) -> Result<SocketAddr> {
+        let reflexive_addr = stun::binding_request(&server.url, &self.stun_config).await?;
+        *self.reflexive_address.write().await = Some(reflexive_addr);
+        Ok(reflexive_addr)
+    }
+
+    /// Server-reflexive address from the last successful STUN binding
+    pub async fn reflexive_address(&self) -> Option<SocketAddr> {
+        *self.reflexive_address.read().await
     }
 
     pub async fn attempt_turn_connection(&self) -> Result<ConnectionType> {
@@ -352,36 +492,199 @@ impl NetworkManager {
         Err(anyhow::anyhow!("All TURN servers failed"))
     }
 
-    async fn turn_allocate_request(&self, _server: &TurnServer) -> Result<SocketAddr> {
-        // Placeholder - would perform actual TURN allocation
-        Ok(SocketAddr::new(
-            IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1)),
-            54321,
-        ))
+    /// Allocate on `server`, falling back from UDP to TCP and TLS
+    async fn turn_allocate_request(&self, server: &TurnServer) -> Result<SocketAddr> {
+        let config = *self.ice_transport.read().await;
+        let mut last_error = anyhow::anyhow!("No usable transport for {}", server.url);
+        for url in relay_urls(&server.url, &config) {
+            match TurnAllocation::allocate(
+                &url,
+                &server.username,
+                &server.credential,
+                &self.stun_config,
+            )
+            .await
+            {
+                Ok(allocation) => {
+                    let relay_addr = allocation.relayed_address();
+                    self.hold_turn_allocation(allocation).await;
+                    return Ok(relay_addr);
+                }
+                Err(e) => {
+                    tracing::debug!("TURN allocation via {} failed: {}", url, e);
+                    last_error = e;
+                }
+            }
+        }
+        Err(last_error)
+    }
+
+    /// Keep `allocation` refreshed until replaced or released, releasing
+    /// the one held before
+    async fn hold_turn_allocation(&self, allocation: TurnAllocation) {
+        self.stop_turn_refresher();
+        let mut lifetime = allocation.lifetime();
+        let previous = self.turn_allocation.lock().await.replace(allocation);
+        if let Some(previous) = previous {
+            if let Err(e) = previous.release().await {
+                tracing::debug!("Releasing previous TURN allocation failed: {}", e);
+            }
+        }
+
+        let slot = Arc::clone(&self.turn_allocation);
+        let refresher = tokio::spawn(async move {
+            loop {
+                let delay = if lifetime > TURN_REFRESH_MARGIN * 2 {
+                    lifetime - TURN_REFRESH_MARGIN
+                } else {
+                    lifetime / 2
+                };
+                tokio::time::sleep(delay).await;
+
+                let mut slot = slot.lock().await;
+                let Some(allocation) = slot.as_mut() else {
+                    break;
+                };
+                match allocation.refresh(DEFAULT_ALLOCATION_LIFETIME).await {
+                    Ok(granted) => lifetime = granted,
+                    Err(e) => {
+                        tracing::warn!("TURN allocation refresh failed: {}", e);
+                        *slot = None;
+                        break;
+                    }
+                }
+            }
+        });
+        *self
+            .turn_refresher
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(refresher);
+    }
+
+    fn stop_turn_refresher(&self) {
+        if let Some(refresher) = self
+            .turn_refresher
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            refresher.abort();
+        }
+    }
+
+    /// Relayed address of the TURN allocation currently held
+    pub async fn relay_address(&self) -> Option<SocketAddr> {
+        self.turn_allocation
+            .lock()
+            .await
+            .as_ref()
+            .map(TurnAllocation::relayed_address)
+    }
+
+    /// Give the held TURN allocation back to the server
+    pub async fn release_turn_allocation(&self) -> Result<()> {
+        self.stop_turn_refresher();
+        let allocation = self.turn_allocation.lock().await.take();
+        match allocation {
+            Some(allocation) => allocation.release().await,
+            None => Ok(()),
+        }
     }
 
     // ICE Candidate Management
     pub async fn gather_ice_candidates(&self) -> Result<Vec<IceCandidate>> {
-        let mut candidates = Vec::new();
-
-        // Gather host candidates
-        candidates.extend(self.gather_host_candidates().await?);
+        self.gather_candidates_with(|_| {}).await
+    }
 
-        // Gather server-reflexive candidates via STUN
-        candidates.extend(self.gather_srflx_candidates().await?);
+    /// Gather candidates and send each one to `target_id` as soon as it is
+    /// found (trickle ICE), instead of waiting for STUN and TURN to finish
+    ///
+    /// A candidate that fails to send is logged and skipped; the peer can
+    /// still connect through the others.
+    pub async fn trickle_ice_candidates(
+        &self,
+        signaling: &SignalingClient,
+        target_id: &str,
+    ) -> Result<Vec<IceCandidate>> {
+        let (tx, mut rx) = mpsc::unbounded_channel::<IceCandidate>();
+        let gather = self.gather_candidates_with(move |candidate| {
+            let _ = tx.send(candidate.clone());
+        });
+        let forward = async {
+            let mut sent = 0;
+            while let Some(candidate) = rx.recv().await {
+                match signaling
+                    .send_ice_candidate(target_id, &candidate.candidate)
+                    .await
+                {
+                    Ok(()) => sent += 1,
+                    Err(e) => tracing::warn!("Failed to trickle ICE candidate: {}", e),
+                }
+            }
+            sent
+        };
+        let (candidates, sent) = tokio::join!(gather, forward);
+        tracing::debug!("Trickled {} ICE candidates to {}", sent, target_id);
+        candidates
+    }
 
-        // Gather relay candidates via TURN
-        candidates.extend(self.gather_relay_candidates().await?);
+    /// Run the gathering phases, reporting every candidate to `on_candidate`
+    /// and as `CandidateGathered` the moment its phase yields it
+    ///
+    /// Host candidates are known immediately; the STUN and TURN phases run
+    /// concurrently since neither depends on the other.
+    async fn gather_candidates_with(
+        &self,
+        mut on_candidate: impl FnMut(&IceCandidate),
+    ) -> Result<Vec<IceCandidate>> {
+        self.ice_candidates.lock().await.clear();
+        let mut candidates = Vec::new();
+        let mut found = |batch: Vec<IceCandidate>, candidates: &mut Vec<IceCandidate>| {
+            for candidate in batch {
+                on_candidate(&candidate);
+                self.events
+                    .publish(NetworkEvent::CandidateGathered(candidate.clone()));
+                candidates.push(candidate);
+            }
+        };
 
-        // Store candidates
+        // Gather host candidates
+        found(self.gather_host_candidates().await?, &mut candidates);
         *self.ice_candidates.lock().await = candidates.clone();
 
+        // Gather server-reflexive (STUN) and relay (TURN) candidates
+        let srflx = self.gather_srflx_candidates();
+        let relay = self.gather_relay_candidates();
+        tokio::pin!(srflx, relay);
+        let (mut srflx_done, mut relay_done) = (false, false);
+        while !(srflx_done && relay_done) {
+            let batch = tokio::select! {
+                batch = &mut srflx, if !srflx_done => {
+                    srflx_done = true;
+                    batch?
+                }
+                batch = &mut relay, if !relay_done => {
+                    relay_done = true;
+                    batch?
+                }
+            };
+            found(batch, &mut candidates);
+            // Store candidates
+            *self.ice_candidates.lock().await = candidates.clone();
+        }
+
+        self.events
+            .publish(NetworkEvent::GatheringComplete(candidates.len()));
         tracing::info!("Gathered {} ICE candidates", candidates.len());
         Ok(candidates)
     }
 
     async fn gather_host_candidates(&self) -> Result<Vec<IceCandidate>> {
         let mut candidates = Vec::new();
+        let transport = *self.ice_transport.read().await;
+        if transport.force_relay_over_tcp {
+            return Ok(candidates);
+        }
 
         // Get local network interfaces
         // Placeholder - would enumerate actual network interfaces
@@ -413,11 +716,40 @@ impl NetworkManager {
             });
         }
 
+        // ICE-TCP: active candidates connect out, so they carry the discard
+        // port 9 (RFC 6544 section 4.5)
+        if transport.tcp_candidates {
+            let tcp: Vec<IceCandidate> = candidates
+                .iter()
+                .enumerate()
+                .map(|(index, udp)| {
+                    let priority = 1518280447 - index as u32;
+                    IceCandidate {
+                        candidate: format!(
+                            "candidate:{} 1 TCP {} {} 9 typ host tcptype active",
+                            5 + index,
+                            priority,
+                            udp.ip
+                        ),
+                        foundation: (5 + index).to_string(),
+                        priority,
+                        port: 9,
+                        protocol: IceProtocol::Tcp,
+                        ..udp.clone()
+                    }
+                })
+                .collect();
+            candidates.extend(tcp);
+        }
+
         Ok(candidates)
     }
 
     async fn gather_srflx_candidates(&self) -> Result<Vec<IceCandidate>> {
         let mut candidates = Vec::new();
+        if self.ice_transport.read().await.force_relay_over_tcp {
+            return Ok(candidates);
+        }
         let servers = self.stun_servers.read().await;
 
         for server in servers.iter() {
@@ -446,29 +778,36 @@ impl NetworkManager {
 
     async fn gather_relay_candidates(&self) -> Result<Vec<IceCandidate>> {
         let mut candidates = Vec::new();
-        let servers = self.turn_servers.read().await;
 
-        for server in servers.iter() {
-            if let Ok(relay_addr) = self.turn_allocate_request(server).await {
-                candidates.push(IceCandidate {
-                    candidate: format!(
-                        "candidate:4 1 UDP 16777215 {} {} typ relay raddr 192.168.1.100 rport 54321",
-                        relay_addr.ip(),
-                        relay_addr.port()
-                    ),
-                    sdp_mid: Some("0".to_string()),
-                    sdp_mline_index: Some(0),
-                    foundation: "4".to_string(),
-                    priority: 16777215,
-                    ip: relay_addr.ip(),
-                    port: relay_addr.port(),
-                    candidate_type: IceCandidateType::Relay,
-                    protocol: IceProtocol::Udp,
-                });
-                break; // Use first successful TURN server
+        // Reuse the allocation already held, if any
+        let mut relay_addr = self.relay_address().await;
+        if relay_addr.is_none() {
+            for server in self.turn_servers.read().await.iter() {
+                if let Ok(addr) = self.turn_allocate_request(server).await {
+                    relay_addr = Some(addr);
+                    break; // Use first successful TURN server
+                }
             }
         }
 
+        if let Some(relay_addr) = relay_addr {
+            candidates.push(IceCandidate {
+                candidate: format!(
+                    "candidate:4 1 UDP 16777215 {} {} typ relay raddr 192.168.1.100 rport 54321",
+                    relay_addr.ip(),
+                    relay_addr.port()
+                ),
+                sdp_mid: Some("0".to_string()),
+                sdp_mline_index: Some(0),
+                foundation: "4".to_string(),
+                priority: 16777215,
+                ip: relay_addr.ip(),
+                port: relay_addr.port(),
+                candidate_type: IceCandidateType::Relay,
+                protocol: IceProtocol::Udp,
+            });
+        }
+
         Ok(candidates)
     }
 
@@ -487,25 +826,38 @@ impl NetworkManager {
         let is_monitoring = Arc::clone(&self.is_monitoring);
         let current_stats = Arc::clone(&self.current_stats);
         let stats_history = Arc::clone(&self.stats_history);
-        let event_sender = self.event_sender.clone();
+        let events = self.events.clone();
+        let link = Arc::clone(&self.link);
+        let ping_target = Arc::clone(&self.ping_target);
+        let stun_servers = Arc::clone(&self.stun_servers);
+        let stun_config = self.stun_config;
+        let activity = Arc::clone(&self.activity);
+        let activity_changed = Arc::clone(&self.activity_changed);
+        let monitor_wakeups = Arc::clone(&self.monitor_wakeups);
 
         tokio::spawn(async move {
             let mut last_quality = NetworkQuality::Unknown;
 
             while *is_monitoring.read().await {
+                monitor_wakeups.fetch_add(1, Ordering::Relaxed);
+                let profile = *activity.read().unwrap_or_else(PoisonError::into_inner);
                 // Measure network stats
-                let stats = Self::measure_stats_internal().await;
-
-                // Update current stats
-                *current_stats.write().await = stats.clone();
-
-                // Add to history (keep last 60 samples)
+                let target = Self::resolve_ping_target(&ping_target, &stun_servers).await;
+                let sample =
+                    Self::measure_stats_internal(&link, target.as_deref(), &stun_config).await;
+
+                // Update current stats, keeping the connection's addresses and family
+                let stats = {
+                    let mut current = current_stats.write().await;
+                    Self::apply_sample(&mut current, sample);
+                    current.clone()
+                };
+
+                // Add to history, keeping as many samples as the profile allows
                 {
                     let mut history = stats_history.lock().await;
                     history.push(stats.clone());
-                    if history.len() > 60 {
-                        history.remove(0);
-                    }
+                    Self::trim_history(&mut history, profile.history_len);
                 }
 
                 // Calculate quality
@@ -513,10 +865,10 @@ impl NetworkManager {
 
                 // Send events if quality changed
                 if quality != last_quality {
-                    let _ = event_sender.send(NetworkEvent::QualityChanged(quality));
+                    events.publish(NetworkEvent::QualityChanged(quality));
 
                     if quality == NetworkQuality::Poor {
-                        let _ = event_sender.send(NetworkEvent::QualityWarning(format!(
+                        events.publish(NetworkEvent::QualityWarning(format!(
                             "Network quality degraded: RTT={}ms, Loss={:.1}%",
                             stats.rtt, stats.packet_loss
                         )));
@@ -525,10 +877,14 @@ impl NetworkManager {
                     last_quality = quality;
                 }
 
-                let _ = event_sender.send(NetworkEvent::StatsUpdated(stats));
+                events.publish(NetworkEvent::StatsUpdated(stats));
 
-                // Monitor every second
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                // A profile change takes effect at once rather than after
+                // a long idle interval
+                tokio::select! {
+                    _ = tokio::time::sleep(profile.monitor_interval()) => {}
+                    _ = activity_changed.notified() => {}
+                }
             }
         });
 
@@ -541,30 +897,144 @@ impl NetworkManager {
         tracing::info!("Network monitoring stopped");
     }
 
-    async fn measure_stats_internal() -> NetworkStats {
-        // Placeholder - would measure actual network stats
-        // In real implementation, this would:
-        // 1. Send STUN binding requests to measure RTT
-        // 2. Track packet loss from RTP statistics
-        // 3. Calculate jitter from packet arrival times
-        // 4. Estimate bandwidth from throughput measurements
+    /// Follow `profile` for background work
+    ///
+    /// Trims the stats history right away, and releases the TURN relay
+    /// when the profile stops candidate gathering. Gathering on request
+    /// still allocates a relay.
+    pub async fn set_activity_profile(&self, profile: ActivityProfile) {
+        let previous = std::mem::replace(
+            &mut *self
+                .activity
+                .write()
+                .unwrap_or_else(PoisonError::into_inner),
+            profile,
+        );
+        if previous == profile {
+            return;
+        }
+        Self::trim_history(&mut *self.stats_history.lock().await, profile.history_len);
+        if !profile.gather_candidates {
+            if let Err(e) = self.release_turn_allocation().await {
+                tracing::debug!("Releasing idle TURN allocation failed: {}", e);
+            }
+        }
+        self.activity_changed.notify_one();
+    }
 
-        NetworkStats {
-            rtt: 50,
-            packet_loss: 0.5,
-            jitter: 10,
-            bandwidth: 10_000_000, // 10 Mbps
-            connection_type: ConnectionType::Direct,
-            local_address: Some("192.168.1.100:54321".to_string()),
-            remote_address: Some("203.0.113.1:12345".to_string()),
-            protocol: NetworkProtocol::IPv4,
+    pub fn activity_profile(&self) -> ActivityProfile {
+        *self.activity.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Monitoring loop iterations since creation
+    pub fn monitor_wakeups(&self) -> u64 {
+        self.monitor_wakeups.load(Ordering::Relaxed)
+    }
+
+    fn trim_history(history: &mut Vec<NetworkStats>, len: usize) {
+        if history.len() > len {
+            history.drain(..history.len() - len);
         }
     }
 
+    /// Ping `target` once and sample the link
+    ///
+    /// The ping is a single STUN Binding transaction; ICMP echo would need
+    /// raw sockets, which are privileged on most systems.
+    async fn measure_stats_internal(
+        link: &std::sync::Mutex<LinkMonitor>,
+        target: Option<&str>,
+        config: &StunConfig,
+    ) -> link_stats::LinkSample {
+        if let Some(target) = target {
+            // A retransmitted request would blur the round trip
+            let ping_config = StunConfig {
+                initial_rto: config.transaction_timeout(),
+                max_attempts: 1,
+            };
+            let started = Instant::now();
+            let rtt = match stun::binding_request(target, &ping_config).await {
+                Ok(_) => Some(started.elapsed()),
+                Err(e) => {
+                    tracing::debug!("STUN ping to {} failed: {}", target, e);
+                    None
+                }
+            };
+            link.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record_ping(rtt);
+        }
+        link.lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sample(Instant::now())
+    }
+
+    async fn resolve_ping_target(
+        ping_target: &RwLock<Option<String>>,
+        stun_servers: &RwLock<Vec<StunServer>>,
+    ) -> Option<String> {
+        if let Some(target) = ping_target.read().await.clone() {
+            return Some(target);
+        }
+        stun_servers
+            .read()
+            .await
+            .iter()
+            .max_by_key(|server| server.priority)
+            .map(|server| server.url.clone())
+    }
+
+    fn apply_sample(stats: &mut NetworkStats, sample: link_stats::LinkSample) {
+        stats.rtt = sample.rtt_ms;
+        stats.jitter = sample.jitter_ms;
+        stats.packet_loss = sample.packet_loss;
+        stats.bandwidth = sample.bandwidth_bps;
+    }
+
     pub async fn measure_network_stats(&self) -> Result<NetworkStats> {
-        let stats = Self::measure_stats_internal().await;
-        *self.current_stats.write().await = stats.clone();
-        Ok(stats)
+        let target = Self::resolve_ping_target(&self.ping_target, &self.stun_servers).await;
+        let sample =
+            Self::measure_stats_internal(&self.link, target.as_deref(), &self.stun_config).await;
+        let mut current = self.current_stats.write().await;
+        Self::apply_sample(&mut current, sample);
+        Ok(current.clone())
+    }
+
+    /// Ping `url` instead of the preferred STUN server when measuring RTT
+    pub async fn set_ping_target(&self, url: Option<String>) {
+        *self.ping_target.write().await = url;
+    }
+
+    /// Feed an RTCP packet received from the peer
+    ///
+    /// Loss and jitter come from its report blocks, RTT from their LSR and
+    /// DLSR fields. `clock_rate` is the RTP clock of the reported stream,
+    /// e.g. 90000 for video. Returns the number of report blocks used.
+    pub fn record_rtcp(&self, packet: &[u8], clock_rate: u32) -> Result<usize> {
+        let blocks = link_stats::parse_report_blocks(packet)?;
+        let (arrival, now) = (SystemTime::now(), Instant::now());
+        let mut link = self.link.lock().unwrap_or_else(PoisonError::into_inner);
+        for block in &blocks {
+            link.record_report(block, clock_rate, arrival, now);
+        }
+        Ok(blocks.len())
+    }
+
+    /// Count media bytes received from the peer towards the bandwidth
+    pub fn record_received_bytes(&self, bytes: usize) {
+        self.link
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record_received(bytes, Instant::now());
+    }
+
+    /// Bandwidth probe: arrival times of a back-to-back train of
+    /// `packet_bytes`-sized packets; returns the estimate in bits per second
+    pub fn record_probe_train(&self, packet_bytes: usize, arrivals: &[Instant]) -> Option<u64> {
+        self.link
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record_probe(packet_bytes, arrivals)
     }
 
     pub fn calculate_quality(stats: &NetworkStats) -> NetworkQuality {
@@ -592,6 +1062,25 @@ impl NetworkManager {
         self.current_stats.read().await.clone()
     }
 
+    /// Record the kind of path media takes, publishing
+    /// `ConnectionTypeChanged` when it differs from the last one
+    pub async fn set_connection_type(&self, connection_type: ConnectionType) -> bool {
+        let mut stats = self.current_stats.write().await;
+        if stats.connection_type == connection_type {
+            return false;
+        }
+        tracing::info!(
+            "Connection type {:?} -> {:?}",
+            stats.connection_type,
+            connection_type
+        );
+        stats.connection_type = connection_type;
+        drop(stats);
+        self.events
+            .publish(NetworkEvent::ConnectionTypeChanged(connection_type));
+        true
+    }
+
     pub async fn get_stats_history(&self) -> Vec<NetworkStats> {
         self.stats_history.lock().await.clone()
     }
@@ -618,8 +1107,9 @@ impl NetworkManager {
         }
     }
 
-    pub async fn get_event_receiver(&self) -> Arc<Mutex<mpsc::UnboundedReceiver<NetworkEvent>>> {
-        Arc::clone(&self.event_receiver)
+    /// Subscribe to network events
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<NetworkEvent> {
+        self.events.subscribe(options)
     }
 
     pub async fn is_ipv6_available(&self) -> bool {
@@ -631,10 +1121,172 @@ impl NetworkManager {
     }
 }
 
+/// Race connection attempts across address families, happy-eyeballs style
+///
+/// The first family starts immediately; the next one starts after `delay`, or
+/// as soon as the previous attempt fails. The first successful attempt wins
+/// and the remaining attempts are cancelled by dropping them.
+pub(crate) async fn race_address_families<F, Fut>(
+    families: &[NetworkProtocol],
+    delay: Duration,
+    attempt: F,
+) -> Option<(NetworkProtocol, ConnectionType)>
+where
+    F: Fn(NetworkProtocol) -> Fut,
+    Fut: Future<Output = Result<ConnectionType>>,
+{
+    let (first, second) = match families {
+        [] => return None,
+        [only] => {
+            return match attempt(*only).await {
+                Ok(conn_type) if conn_type != ConnectionType::Unknown => Some((*only, conn_type)),
+                _ => None,
+            };
+        }
+        [first, second, ..] => (*first, *second),
+    };
+
+    let (failed_tx, failed_rx) = oneshot::channel::<()>();
+
+    let primary = async {
+        let result = attempt(first).await;
+        if !matches!(result, Ok(t) if t != ConnectionType::Unknown) {
+            let _ = failed_tx.send(());
+        }
+        result
+    };
+    let secondary = async {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = failed_rx => {}
+        }
+        attempt(second).await
+    };
+    tokio::pin!(primary, secondary);
+
+    let (mut primary_done, mut secondary_done) = (false, false);
+    while !(primary_done && secondary_done) {
+        tokio::select! {
+            result = &mut primary, if !primary_done => {
+                primary_done = true;
+                match result {
+                    Ok(conn_type) if conn_type != ConnectionType::Unknown => {
+                        return Some((first, conn_type));
+                    }
+                    _ => tracing::debug!("{:?} connection attempt failed", first),
+                }
+            }
+            result = &mut secondary, if !secondary_done => {
+                secondary_done = true;
+                match result {
+                    Ok(conn_type) if conn_type != ConnectionType::Unknown => {
+                        return Some((second, conn_type));
+                    }
+                    _ => tracing::debug!("{:?} connection attempt failed", second),
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// URLs to try for a TURN server, in order
+///
+/// A UDP server is also tried over TCP on the same port and over TLS on the
+/// fallback port; with `force_relay_over_tcp` the UDP attempt is dropped.
+fn relay_urls(url: &str, config: &IceTransportConfig) -> Vec<String> {
+    let Ok(server) = ServerAddress::parse(url) else {
+        return vec![url.to_string()];
+    };
+    let mut attempts = Vec::new();
+    if server.transport == StunTransport::Udp {
+        if !config.force_relay_over_tcp {
+            attempts.push(server.clone());
+        }
+        attempts.push(ServerAddress {
+            transport: StunTransport::Tcp,
+            ..server.clone()
+        });
+    } else {
+        attempts.push(server.clone());
+    }
+    if let Some(port) = config.tls_fallback_port {
+        let tls = ServerAddress {
+            port,
+            transport: StunTransport::Tls,
+            ..server
+        };
+        if !attempts.contains(&tls) {
+            attempts.push(tls);
+        }
+    }
+    attempts.iter().map(ServerAddress::to_turn_url).collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_happy_eyeballs_stalled_ipv6() {
+        let started = std::time::Instant::now();
+        let winner = race_address_families(
+            &[NetworkProtocol::IPv6, NetworkProtocol::IPv4],
+            CONNECTION_ATTEMPT_DELAY,
+            |family| async move {
+                match family {
+                    // Broken IPv6 hangs until the OS connect timeout
+                    NetworkProtocol::IPv6 => {
+                        tokio::time::sleep(Duration::from_secs(30)).await;
+                        Ok(ConnectionType::Direct)
+                    }
+                    NetworkProtocol::IPv4 => Ok(ConnectionType::Direct),
+                }
+            },
+        )
+        .await;
+
+        assert_eq!(
+            winner,
+            Some((NetworkProtocol::IPv4, ConnectionType::Direct))
+        );
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_happy_eyeballs_prefers_first_family() {
+        let winner = race_address_families(
+            &[NetworkProtocol::IPv6, NetworkProtocol::IPv4],
+            CONNECTION_ATTEMPT_DELAY,
+            |family| async move {
+                match family {
+                    NetworkProtocol::IPv6 => {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        Ok(ConnectionType::Direct)
+                    }
+                    NetworkProtocol::IPv4 => Ok(ConnectionType::Direct),
+                }
+            },
+        )
+        .await;
+        assert_eq!(winner.unwrap().0, NetworkProtocol::IPv6);
+
+        // An immediate IPv6 failure starts IPv4 without waiting for the delay
+        let winner = race_address_families(
+            &[NetworkProtocol::IPv6, NetworkProtocol::IPv4],
+            Duration::from_secs(60),
+            |family| async move {
+                match family {
+                    NetworkProtocol::IPv6 => Err(anyhow::anyhow!("unreachable")),
+                    NetworkProtocol::IPv4 => Ok(ConnectionType::Direct),
+                }
+            },
+        )
+        .await;
+        assert_eq!(winner.unwrap().0, NetworkProtocol::IPv4);
+    }
+
     #[tokio::test]
     async fn test_network_manager_creation() {
         let manager = NetworkManager::new();
@@ -717,4 +1369,161 @@ mod tests {
             .collect();
         assert!(!host_candidates.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_stats_from_pings_and_receiver_reports() {
+        // Never answers, so every ping is lost
+        let silent = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let manager = NetworkManager::new().with_stun_config(StunConfig {
+            initial_rto: Duration::from_millis(50),
+            max_attempts: 1,
+        });
+        manager
+            .set_ping_target(Some(format!("stun:{}", silent.local_addr().unwrap())))
+            .await;
+
+        let stats = manager.measure_network_stats().await.unwrap();
+        assert_eq!(stats.packet_loss, 100.0);
+        assert_eq!(stats.rtt, 0);
+
+        // RR from the peer: a quarter lost, 20 ms of jitter at 90 kHz
+        let mut rr = vec![0x81, 201, 0, 7, 0, 0, 0, 1, 0, 0, 0, 2, 64, 0, 0, 5];
+        rr.extend_from_slice(&100u32.to_be_bytes());
+        rr.extend_from_slice(&1800u32.to_be_bytes());
+        rr.extend_from_slice(&[0; 8]);
+        assert_eq!(manager.record_rtcp(&rr, 90_000).unwrap(), 1);
+        manager.record_received_bytes(250_000);
+
+        let stats = manager.measure_network_stats().await.unwrap();
+        assert_eq!(stats.packet_loss, 25.0);
+        assert_eq!(stats.jitter, 20);
+        assert_eq!(stats.bandwidth, 1_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_candidates_published_as_gathered() {
+        let manager = NetworkManager::new();
+        manager.stun_servers.write().await.clear();
+        let mut events = manager.subscribe(SubscriptionOptions::default());
+
+        let candidates = manager.gather_ice_candidates().await.unwrap();
+
+        let mut published = Vec::new();
+        while let Some(event) = events.try_recv() {
+            match event {
+                NetworkEvent::CandidateGathered(candidate) => published.push(candidate),
+                NetworkEvent::GatheringComplete(count) => {
+                    assert_eq!(count, candidates.len());
+                    break;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(published.len(), candidates.len());
+        assert_eq!(published[0].candidate_type, IceCandidateType::Host);
+        assert_eq!(manager.get_ice_candidates().await.len(), candidates.len());
+    }
+
+    #[tokio::test]
+    async fn test_connection_type_changes_published() {
+        let manager = NetworkManager::new();
+        let mut events = manager.subscribe(SubscriptionOptions::default());
+
+        assert!(manager.set_connection_type(ConnectionType::TurnRelay).await);
+        assert!(!manager.set_connection_type(ConnectionType::TurnRelay).await);
+        assert!(
+            manager
+                .set_connection_type(ConnectionType::StunDirect)
+                .await
+        );
+        assert_eq!(
+            manager.get_current_stats().await.connection_type,
+            ConnectionType::StunDirect
+        );
+
+        let mut changes = Vec::new();
+        while let Some(event) = events.try_recv() {
+            if let NetworkEvent::ConnectionTypeChanged(connection_type) = event {
+                changes.push(connection_type);
+            }
+        }
+        assert_eq!(
+            changes,
+            [ConnectionType::TurnRelay, ConnectionType::StunDirect]
+        );
+    }
+
+    #[test]
+    fn test_relay_urls_fall_back_to_tcp_and_tls() {
+        let config = IceTransportConfig::default();
+        assert_eq!(
+            relay_urls("turn:turn.example.com", &config),
+            vec![
+                "turn:turn.example.com:3478",
+                "turn:turn.example.com:3478?transport=tcp",
+                "turns:turn.example.com:443?transport=tcp",
+            ]
+        );
+        assert_eq!(
+            relay_urls("turns:turn.example.com:443", &config),
+            vec!["turns:turn.example.com:443?transport=tcp"]
+        );
+
+        let forced = IceTransportConfig {
+            force_relay_over_tcp: true,
+            tls_fallback_port: None,
+            ..config
+        };
+        assert_eq!(
+            relay_urls("turn:turn.example.com:3478", &forced),
+            vec!["turn:turn.example.com:3478?transport=tcp"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forced_relay_gathers_no_direct_candidates() {
+        let manager = NetworkManager::new();
+        let host = manager.gather_host_candidates().await.unwrap();
+        assert!(host.iter().any(|c| c.protocol == IceProtocol::Tcp
+            && c.port == 9
+            && c.candidate.ends_with("tcptype active")));
+
+        manager
+            .set_ice_transport_config(IceTransportConfig {
+                force_relay_over_tcp: true,
+                ..IceTransportConfig::default()
+            })
+            .await;
+        // No TURN server is configured, so nothing is left to offer
+        assert!(manager.gather_ice_candidates().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_idle_profile_reduces_monitoring_wakeups() {
+        let manager = NetworkManager::new();
+        manager.stun_servers.write().await.clear();
+        let active = ActivityProfile {
+            monitor_interval_ms: 5,
+            ..ActivityProfile::full()
+        };
+        manager.set_activity_profile(active).await;
+        manager.start_monitoring().await.unwrap();
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let busy = manager.monitor_wakeups();
+        assert!(busy >= 10, "only {} wakeups while active", busy);
+        assert!(manager.get_stats_history().await.len() > 5);
+
+        manager.set_activity_profile(ActivityProfile::idle()).await;
+        assert_eq!(manager.get_stats_history().await.len(), 5);
+        let entered = manager.monitor_wakeups();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(manager.monitor_wakeups() - entered <= 2);
+
+        // Waking cuts the idle interval short
+        manager.set_activity_profile(active).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(manager.monitor_wakeups() - entered >= 5);
+        manager.stop_monitoring().await;
+    }
 }
@@ -26,6 +26,7 @@ prop_compose! {
             local_address: Some("192.168.1.100:54321".to_string()),
             remote_address: Some("203.0.113.1:12345".to_string()),
             protocol: NetworkProtocol::IPv4,
+            winning_family: None,
         }
     }
 }
@@ -65,6 +66,7 @@ mod unit_tests {
             local_address: None,
             remote_address: None,
             protocol: NetworkProtocol::IPv4,
+            winning_family: None,
         };
         assert_eq!(
             NetworkManager::calculate_quality(&at_excellent),
@@ -80,6 +82,7 @@ mod unit_tests {
             local_address: None,
             remote_address: None,
             protocol: NetworkProtocol::IPv4,
+            winning_family: None,
         };
         assert_eq!(
             NetworkManager::calculate_quality(&at_poor),
@@ -0,0 +1,333 @@
+//! Region OCR Assist
+//!
+//! Lets the viewer select a screen region and have the host recognize the
+//! text in it, e.g. to copy an error message out of an application that does
+//! not allow text selection. Recognition runs locally on the host with the
+//! platform's OCR engine; no image leaves the machine.
+//!
+//! Built only with the `ocr` feature.
+
+use crate::access_control::Permission;
+use crate::screen_capture::{FrameFormat, VideoFrame};
+use anyhow::Result;
+use image::{GrayImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Largest region accepted for recognition (about one 4K screen)
+pub const MAX_OCR_REGION_PIXELS: u64 = 3840 * 2160;
+
+/// Screen region in frame pixel coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OcrRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Viewer request to recognize text in a region
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrRequest {
+    pub request_id: String,
+    pub region: OcrRegion,
+}
+
+/// Host reply with the recognized text
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OcrResponse {
+    pub request_id: String,
+    pub text: String,
+    /// Mean confidence in [0, 1], if the engine reports one
+    pub confidence: Option<f32>,
+}
+
+/// Output of an OCR engine
+#[derive(Debug, Clone, Default)]
+pub struct OcrOutput {
+    pub text: String,
+    pub confidence: Option<f32>,
+}
+
+/// Local OCR engine
+pub trait OcrEngine: Send + Sync {
+    fn recognize(&self, image: &GrayImage) -> Result<OcrOutput>;
+}
+
+/// Tesseract language packs used by the platform engine
+pub const OCR_LANGUAGES: &str = "eng";
+
+/// Default engine: Tesseract through its C API, on every platform
+///
+/// `build.rs` links `libtesseract` when the `ocr` feature is enabled; the
+/// language data is looked up in `TESSDATA_PREFIX` or Tesseract's install
+/// prefix. A Tesseract instance is not thread-safe and is cheap next to a
+/// user-triggered request, so each recognition gets its own.
+pub struct PlatformOcrEngine;
+
+impl OcrEngine for PlatformOcrEngine {
+    fn recognize(&self, image: &GrayImage) -> Result<OcrOutput> {
+        tesseract::recognize(image, OCR_LANGUAGES)
+    }
+}
+
+mod tesseract {
+    use super::OcrOutput;
+    use anyhow::Result;
+    use image::GrayImage;
+    use std::ffi::{CStr, CString};
+    use std::os::raw::{c_char, c_int, c_uchar};
+
+    #[repr(C)]
+    struct TessBaseApi {
+        _private: [u8; 0],
+    }
+
+    /// Screen text is rendered for roughly 96 DPI; without a hint Tesseract
+    /// guesses from the image size
+    const SCREEN_PPI: c_int = 96;
+
+    extern "C" {
+        fn TessBaseAPICreate() -> *mut TessBaseApi;
+        fn TessBaseAPIDelete(api: *mut TessBaseApi);
+        fn TessBaseAPIInit3(
+            api: *mut TessBaseApi,
+            datapath: *const c_char,
+            language: *const c_char,
+        ) -> c_int;
+        fn TessBaseAPIEnd(api: *mut TessBaseApi);
+        fn TessBaseAPISetImage(
+            api: *mut TessBaseApi,
+            data: *const c_uchar,
+            width: c_int,
+            height: c_int,
+            bytes_per_pixel: c_int,
+            bytes_per_line: c_int,
+        );
+        fn TessBaseAPISetSourceResolution(api: *mut TessBaseApi, ppi: c_int);
+        fn TessBaseAPIGetUTF8Text(api: *mut TessBaseApi) -> *mut c_char;
+        fn TessBaseAPIMeanTextConf(api: *mut TessBaseApi) -> c_int;
+        fn TessDeleteText(text: *const c_char);
+    }
+
+    /// A Tesseract instance, ended and deleted on drop
+    struct Api(*mut TessBaseApi);
+
+    impl Drop for Api {
+        fn drop(&mut self) {
+            // SAFETY: created by TessBaseAPICreate and dropped once
+            unsafe {
+                TessBaseAPIEnd(self.0);
+                TessBaseAPIDelete(self.0);
+            }
+        }
+    }
+
+    pub(super) fn recognize(image: &GrayImage, languages: &str) -> Result<OcrOutput> {
+        let languages = CString::new(languages)?;
+        let (width, height) = (image.width() as c_int, image.height() as c_int);
+        // SAFETY: the image buffer outlives recognition, which happens inside
+        // TessBaseAPIGetUTF8Text; the returned text is freed by Tesseract
+        unsafe {
+            let api = TessBaseAPICreate();
+            if api.is_null() {
+                return Err(anyhow::anyhow!("Cannot create a Tesseract instance"));
+            }
+            let api = Api(api);
+            if TessBaseAPIInit3(api.0, std::ptr::null(), languages.as_ptr()) != 0 {
+                return Err(anyhow::anyhow!(
+                    "Tesseract has no {:?} language data; install it or set TESSDATA_PREFIX",
+                    languages
+                ));
+            }
+            TessBaseAPISetImage(api.0, image.as_raw().as_ptr(), width, height, 1, width);
+            TessBaseAPISetSourceResolution(api.0, SCREEN_PPI);
+            let text = TessBaseAPIGetUTF8Text(api.0);
+            if text.is_null() {
+                return Err(anyhow::anyhow!("Tesseract failed to recognize the region"));
+            }
+            let recognized = CStr::from_ptr(text)
+                .to_string_lossy()
+                .trim_end()
+                .to_string();
+            TessDeleteText(text);
+            // Tesseract reports -1 or 0 when nothing was recognized
+            let confidence = TessBaseAPIMeanTextConf(api.0);
+            Ok(OcrOutput {
+                text: recognized,
+                confidence: (confidence > 0).then(|| confidence.min(100) as f32 / 100.0),
+            })
+        }
+    }
+}
+
+/// Serves viewer OCR requests against captured frames
+pub struct OcrAssist {
+    engine: Arc<dyn OcrEngine>,
+}
+
+impl OcrAssist {
+    pub fn new() -> Self {
+        Self::with_engine(Arc::new(PlatformOcrEngine))
+    }
+
+    pub fn with_engine(engine: Arc<dyn OcrEngine>) -> Self {
+        Self { engine }
+    }
+
+    /// Recognize text in the requested region of `frame`
+    ///
+    /// Requires the `ViewScreen` permission, since the text reveals the same
+    /// content as the video stream.
+    pub fn handle_request(
+        &self,
+        request: &OcrRequest,
+        frame: &VideoFrame,
+        permissions: &[Permission],
+    ) -> Result<OcrResponse> {
+        if !permissions.contains(&Permission::ViewScreen)
+            && !permissions.contains(&Permission::FullControl)
+        {
+            return Err(anyhow::anyhow!("OCR requires ViewScreen permission"));
+        }
+
+        let region = crop_region(frame, &request.region)?;
+        let output = self.engine.recognize(&region)?;
+
+        tracing::info!(
+            "OCR request {} recognized {} characters",
+            request.request_id,
+            output.text.chars().count()
+        );
+        Ok(OcrResponse {
+            request_id: request.request_id.clone(),
+            text: output.text,
+            confidence: output.confidence,
+        })
+    }
+}
+
+impl Default for OcrAssist {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Cut the region out of a frame as a grayscale image
+fn crop_region(frame: &VideoFrame, region: &OcrRegion) -> Result<GrayImage> {
+    if region.width == 0 || region.height == 0 {
+        return Err(anyhow::anyhow!("OCR region is empty"));
+    }
+    if region.width as u64 * region.height as u64 > MAX_OCR_REGION_PIXELS {
+        return Err(anyhow::anyhow!("OCR region too large"));
+    }
+    let fits =
+        |start: u32, len: u32, max: u32| start.checked_add(len).is_some_and(|end| end <= max);
+    if !fits(region.x, region.width, frame.width) || !fits(region.y, region.height, frame.height) {
+        return Err(anyhow::anyhow!("OCR region outside frame bounds"));
+    }
+
+    let mut data = frame.data.clone();
+    match frame.format {
+        FrameFormat::RGBA => {}
+        FrameFormat::BGRA => data.chunks_exact_mut(4).for_each(|px| px.swap(0, 2)),
+        other => {
+            return Err(anyhow::anyhow!(
+                "Unsupported frame format for OCR: {:?}",
+                other
+            ))
+        }
+    }
+
+    let image = RgbaImage::from_raw(frame.width, frame.height, data)
+        .ok_or_else(|| anyhow::anyhow!("Frame buffer does not match its dimensions"))?;
+    let cropped =
+        image::imageops::crop_imm(&image, region.x, region.y, region.width, region.height)
+            .to_image();
+    Ok(image::DynamicImage::ImageRgba8(cropped).to_luma8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct SizeEngine;
+
+    impl OcrEngine for SizeEngine {
+        fn recognize(&self, image: &GrayImage) -> Result<OcrOutput> {
+            Ok(OcrOutput {
+                text: format!("{}x{}", image.width(), image.height()),
+                confidence: Some(1.0),
+            })
+        }
+    }
+
+    fn frame() -> VideoFrame {
+        VideoFrame {
+            id: 1,
+            timestamp: 0,
+            width: 100,
+            height: 50,
+            data: vec![255; 100 * 50 * 4],
+            format: FrameFormat::BGRA,
+            dirty_rects: None,
+        }
+    }
+
+    fn request(region: OcrRegion) -> OcrRequest {
+        OcrRequest {
+            request_id: "r1".to_string(),
+            region,
+        }
+    }
+
+    #[test]
+    fn test_region_recognized() {
+        let assist = OcrAssist::with_engine(Arc::new(SizeEngine));
+        let region = OcrRegion {
+            x: 10,
+            y: 10,
+            width: 40,
+            height: 20,
+        };
+
+        let response = assist
+            .handle_request(&request(region), &frame(), &[Permission::ViewScreen])
+            .unwrap();
+        assert_eq!(response.request_id, "r1");
+        assert_eq!(response.text, "40x20");
+
+        assert!(assist
+            .handle_request(&request(region), &frame(), &[Permission::InputControl])
+            .is_err());
+    }
+
+    #[test]
+    fn test_region_bounds_checked() {
+        let assist = OcrAssist::with_engine(Arc::new(SizeEngine));
+        for region in [
+            OcrRegion {
+                x: 90,
+                y: 0,
+                width: 20,
+                height: 10,
+            },
+            OcrRegion {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 10,
+            },
+            OcrRegion {
+                x: u32::MAX,
+                y: 0,
+                width: 2,
+                height: 2,
+            },
+        ] {
+            assert!(assist
+                .handle_request(&request(region), &frame(), &[Permission::FullControl])
+                .is_err());
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Store-and-Forward Queue for Offline Devices
+//!
+//! Server-side holding area for signaling envelopes addressed to devices that
+//! are not currently connected. Messages are released when the device comes
+//! online, or expire after their TTL with an `Expired` receipt to the sender.
+
+use crate::signaling::{DeliveryStatus, MessageEnvelope, SignalingMessage};
+use anyhow::Result;
+use std::collections::{HashMap, VecDeque};
+
+/// Maximum queued messages per target device
+pub const MAX_QUEUED_PER_DEVICE: usize = 32;
+
+/// Upper bound on any envelope TTL accepted by the server (1 day)
+pub const MAX_QUEUE_TTL_SECS: u64 = 24 * 60 * 60;
+
+/// Result of draining a device's queue when it comes online
+#[derive(Debug, Default)]
+pub struct QueueDrain {
+    /// Envelopes still within their TTL, oldest first
+    pub deliver: Vec<MessageEnvelope>,
+    /// Envelopes whose TTL elapsed while queued
+    pub expired: Vec<MessageEnvelope>,
+}
+
+/// Per-device queue of undelivered envelopes
+#[derive(Debug)]
+pub struct OfflineMessageQueue {
+    queues: HashMap<String, VecDeque<MessageEnvelope>>,
+    max_per_device: usize,
+    max_ttl_secs: u64,
+}
+
+impl OfflineMessageQueue {
+    pub fn new() -> Self {
+        Self::with_limits(MAX_QUEUED_PER_DEVICE, MAX_QUEUE_TTL_SECS)
+    }
+
+    pub fn with_limits(max_per_device: usize, max_ttl_secs: u64) -> Self {
+        Self {
+            queues: HashMap::new(),
+            max_per_device,
+            max_ttl_secs,
+        }
+    }
+
+    /// Queue an envelope for an offline target
+    ///
+    /// The TTL is clamped to the server maximum. Fails if the envelope has
+    /// already expired or the target's queue is full.
+    pub fn enqueue(&mut self, mut envelope: MessageEnvelope, now_ms: i64) -> Result<()> {
+        envelope.ttl_secs = envelope.ttl_secs.min(self.max_ttl_secs);
+        if envelope.is_expired_at(now_ms) {
+            return Err(anyhow::anyhow!(
+                "Message {} expired before it could be queued",
+                envelope.message_id
+            ));
+        }
+
+        let queue = self.queues.entry(envelope.to.clone()).or_default();
+        if queue.len() >= self.max_per_device {
+            return Err(anyhow::anyhow!(
+                "Offline queue full for device: {}",
+                envelope.to
+            ));
+        }
+
+        tracing::debug!(
+            "Queued message {} for offline device {}",
+            envelope.message_id,
+            envelope.to
+        );
+        queue.push_back(envelope);
+        Ok(())
+    }
+
+    /// Take everything queued for a device that just came online
+    pub fn drain_for(&mut self, device_id: &str, now_ms: i64) -> QueueDrain {
+        let mut drain = QueueDrain::default();
+        if let Some(queue) = self.queues.remove(device_id) {
+            for envelope in queue {
+                if envelope.is_expired_at(now_ms) {
+                    drain.expired.push(envelope);
+                } else {
+                    drain.deliver.push(envelope);
+                }
+            }
+        }
+        drain
+    }
+
+    /// Remove envelopes whose TTL has elapsed
+    pub fn purge_expired(&mut self, now_ms: i64) -> Vec<MessageEnvelope> {
+        let mut expired = Vec::new();
+        for queue in self.queues.values_mut() {
+            let (keep, gone): (VecDeque<_>, VecDeque<_>) =
+                queue.drain(..).partition(|e| !e.is_expired_at(now_ms));
+            *queue = keep;
+            expired.extend(gone);
+        }
+        self.queues.retain(|_, queue| !queue.is_empty());
+        expired
+    }
+
+    /// Number of envelopes queued for a device
+    pub fn pending_for(&self, device_id: &str) -> usize {
+        self.queues.get(device_id).map_or(0, |q| q.len())
+    }
+
+    /// Total number of queued envelopes
+    pub fn len(&self) -> usize {
+        self.queues.values().map(|q| q.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+}
+
+impl Default for OfflineMessageQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Build the receipt sent back to an envelope's sender
+pub fn delivery_receipt(envelope: &MessageEnvelope, status: DeliveryStatus) -> SignalingMessage {
+    SignalingMessage::DeliveryReceipt {
+        message_id: envelope.message_id.clone(),
+        to: envelope.to.clone(),
+        status,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn envelope(to: &str, ttl_secs: u64) -> MessageEnvelope {
+        MessageEnvelope::new(
+            "sender".to_string(),
+            to.to_string(),
+            ttl_secs,
+            SignalingMessage::Heartbeat {
+                device_id: "sender".to_string(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_drain_splits_expired() {
+        let mut queue = OfflineMessageQueue::new();
+        let fresh = envelope("host", 60);
+        let now = fresh.sent_at;
+        queue.enqueue(fresh, now).unwrap();
+        queue.enqueue(envelope("host", 1), now).unwrap();
+        assert_eq!(queue.pending_for("host"), 2);
+
+        let drain = queue.drain_for("host", now + 5_000);
+        assert_eq!(drain.deliver.len(), 1);
+        assert_eq!(drain.expired.len(), 1);
+        assert!(queue.is_empty());
+
+        match delivery_receipt(&drain.expired[0], DeliveryStatus::Expired) {
+            SignalingMessage::DeliveryReceipt { to, status, .. } => {
+                assert_eq!(to, "host");
+                assert_eq!(status, DeliveryStatus::Expired);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_limits_and_purge() {
+        let mut queue = OfflineMessageQueue::with_limits(1, 10);
+        let first = envelope("host", 3600);
+        let now = first.sent_at;
+        queue.enqueue(first, now).unwrap();
+        assert!(queue.enqueue(envelope("host", 60), now).is_err());
+
+        // TTL was clamped to the server maximum of 10s
+        assert!(queue.purge_expired(now + 5_000).is_empty());
+        assert_eq!(queue.purge_expired(now + 11_000).len(), 1);
+        assert_eq!(queue.len(), 0);
+    }
+}
@@ -0,0 +1,655 @@
+//! Runtime OS Permission Monitoring
+//!
+//! macOS and Windows can revoke screen recording or accessibility permission
+//! while a session is running. This module polls the platform permission state,
+//! emits typed events on change, and pauses the affected pipelines so the
+//! controller sees an explicit state instead of black frames or lost input.
+
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use crate::input_control::InputController;
+use crate::screen_capture::ScreenCapturer;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Mutex, RwLock};
+
+/// Default permission polling interval
+pub const PERMISSION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// OS-level permission required by the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SystemPermission {
+    /// Screen recording / screen capture
+    ScreenRecording,
+    /// Accessibility (synthetic input injection)
+    Accessibility,
+}
+
+impl SystemPermission {
+    /// Pipeline that depends on this permission
+    pub fn affected_pipeline(&self) -> AffectedPipeline {
+        match self {
+            SystemPermission::ScreenRecording => AffectedPipeline::ScreenCapture,
+            SystemPermission::Accessibility => AffectedPipeline::InputInjection,
+        }
+    }
+
+    /// Platform-specific hint telling the user how to restore the permission
+    pub fn remediation_hint(&self) -> String {
+        #[cfg(target_os = "macos")]
+        {
+            match self {
+                SystemPermission::ScreenRecording => "Open System Settings > Privacy & Security > Screen Recording, enable CEC Remote, then restart the app".to_string(),
+                SystemPermission::Accessibility => "Open System Settings > Privacy & Security > Accessibility and enable CEC Remote".to_string(),
+            }
+        }
+        #[cfg(target_os = "windows")]
+        {
+            match self {
+                SystemPermission::ScreenRecording => "Allow screen capture in Settings > Privacy & security, and make sure no secure desktop (UAC prompt) is active".to_string(),
+                SystemPermission::Accessibility => "Run CEC Remote with the same or higher privilege level as the focused application".to_string(),
+            }
+        }
+        #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+        {
+            match self {
+                SystemPermission::ScreenRecording => "Log in to an X11 session or enable XWayland; capturing Wayland through the desktop portal is not supported yet".to_string(),
+                SystemPermission::Accessibility => "Give the user write access to /dev/uinput (usually through the input group) or log in to an X11 session".to_string(),
+            }
+        }
+    }
+}
+
+/// Permission state reported by the platform
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SystemPermissionStatus {
+    Granted,
+    Denied,
+    NotDetermined,
+}
+
+/// Pipeline paused when a permission is lost
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AffectedPipeline {
+    ScreenCapture,
+    InputInjection,
+}
+
+/// Permission change event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PermissionEvent {
+    /// A permission was revoked and the pipeline has been paused
+    Revoked {
+        permission: SystemPermission,
+        pipeline: AffectedPipeline,
+        remediation: String,
+    },
+    /// A previously revoked permission is available again
+    Restored {
+        permission: SystemPermission,
+        pipeline: AffectedPipeline,
+    },
+}
+
+impl EventType for PermissionEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            PermissionEvent::Revoked { .. } => "Revoked",
+            PermissionEvent::Restored { .. } => "Restored",
+        }
+    }
+}
+
+/// Source of platform permission state
+pub trait PermissionProbe: Send + Sync {
+    fn check(&self, permission: SystemPermission) -> SystemPermissionStatus;
+}
+
+/// Default probe backed by the platform APIs
+///
+/// - macOS: the TCC Screen Recording and Accessibility grants.
+/// - Windows: both are denied while the secure desktop (UAC prompt, lock
+///   screen) has the input; input is also denied when UIPI blocks it, i.e.
+///   the focused process has a higher integrity level and this process lacks
+///   UIAccess.
+/// - Linux: capture needs a reachable X display (XWayland included); input
+///   needs a writable `/dev/uinput` or an X display with XTEST.
+///
+/// Elsewhere the state is `NotDetermined`, which the monitor neither pauses
+/// nor resumes on.
+pub struct PlatformPermissionProbe;
+
+impl PermissionProbe for PlatformPermissionProbe {
+    fn check(&self, permission: SystemPermission) -> SystemPermissionStatus {
+        #[cfg(target_os = "macos")]
+        {
+            macos::check(permission)
+        }
+        #[cfg(target_os = "windows")]
+        {
+            win32::check(permission)
+        }
+        #[cfg(target_os = "linux")]
+        {
+            linux::check(permission)
+        }
+        #[cfg(not(any(target_os = "macos", target_os = "windows", target_os = "linux")))]
+        {
+            let _ = permission;
+            SystemPermissionStatus::NotDetermined
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod macos {
+    use super::{SystemPermission, SystemPermissionStatus};
+
+    extern "C" {
+        fn CGPreflightScreenCaptureAccess() -> bool;
+        fn AXIsProcessTrusted() -> bool;
+    }
+
+    pub fn check(permission: SystemPermission) -> SystemPermissionStatus {
+        // SAFETY: both take no arguments and only read the process's TCC state
+        let granted = unsafe {
+            match permission {
+                SystemPermission::ScreenRecording => CGPreflightScreenCaptureAccess(),
+                SystemPermission::Accessibility => AXIsProcessTrusted(),
+            }
+        };
+        if granted {
+            SystemPermissionStatus::Granted
+        } else {
+            SystemPermissionStatus::Denied
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod win32 {
+    use super::{SystemPermission, SystemPermissionStatus};
+    use std::ffi::c_void;
+
+    type Handle = *mut c_void;
+    type Hwnd = *mut c_void;
+    type Hdesk = *mut c_void;
+
+    const DESKTOP_READOBJECTS: u32 = 0x0001;
+    const UOI_NAME: i32 = 2;
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const TOKEN_QUERY: u32 = 0x0008;
+    const TOKEN_INTEGRITY_LEVEL: u32 = 25;
+    const TOKEN_UI_ACCESS: u32 = 26;
+    const SECURITY_MANDATORY_HIGH_RID: u32 = 0x3000;
+
+    extern "system" {
+        fn OpenInputDesktop(flags: u32, inherit: i32, access: u32) -> Hdesk;
+        fn CloseDesktop(desktop: Hdesk) -> i32;
+        fn GetUserObjectInformationW(
+            object: Handle,
+            index: i32,
+            info: *mut c_void,
+            length: u32,
+            needed: *mut u32,
+        ) -> i32;
+        fn GetForegroundWindow() -> Hwnd;
+        fn GetWindowThreadProcessId(window: Hwnd, process_id: *mut u32) -> u32;
+        fn GetCurrentProcess() -> Handle;
+        fn OpenProcess(access: u32, inherit: i32, process_id: u32) -> Handle;
+        fn OpenProcessToken(process: Handle, access: u32, token: *mut Handle) -> i32;
+        fn GetTokenInformation(
+            token: Handle,
+            class: u32,
+            info: *mut c_void,
+            length: u32,
+            returned: *mut u32,
+        ) -> i32;
+        fn GetSidSubAuthorityCount(sid: *mut c_void) -> *mut u8;
+        fn GetSidSubAuthority(sid: *mut c_void, index: u32) -> *mut u32;
+        fn CloseHandle(handle: Handle) -> i32;
+    }
+
+    pub fn check(permission: SystemPermission) -> SystemPermissionStatus {
+        // The secure desktop can neither be captured nor sent input by a
+        // process running on the user's desktop
+        if !input_desktop().is_some_and(|name| name.eq_ignore_ascii_case("Default")) {
+            return SystemPermissionStatus::Denied;
+        }
+        match permission {
+            SystemPermission::ScreenRecording => SystemPermissionStatus::Granted,
+            SystemPermission::Accessibility => foreground_accepts_input(),
+        }
+    }
+
+    /// Name of the desktop receiving input; `None` when this process may not
+    /// open it
+    fn input_desktop() -> Option<String> {
+        let mut name = [0u16; 64];
+        let mut needed = 0u32;
+        // SAFETY: the buffer length is passed in bytes; the desktop handle is
+        // closed before returning
+        unsafe {
+            let desktop = OpenInputDesktop(0, 0, DESKTOP_READOBJECTS);
+            if desktop.is_null() {
+                return None;
+            }
+            let read = GetUserObjectInformationW(
+                desktop,
+                UOI_NAME,
+                name.as_mut_ptr().cast(),
+                std::mem::size_of_val(&name) as u32,
+                &mut needed,
+            ) != 0;
+            CloseDesktop(desktop);
+            if !read {
+                return None;
+            }
+        }
+        let length = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        Some(String::from_utf16_lossy(&name[..length]))
+    }
+
+    /// Mandatory integrity level and UIAccess flag of a process's token
+    fn token_rights(process: Handle) -> Option<(u32, bool)> {
+        // TOKEN_MANDATORY_LABEL followed by its SID; usize keeps the
+        // pointer at its start aligned
+        let mut label = [0usize; 16];
+        let mut ui_access = 0u32;
+        let mut returned = 0u32;
+        // SAFETY: the buffers are sized as passed, the label's SID points
+        // into `label`, and the token is closed before returning
+        unsafe {
+            let mut token = std::ptr::null_mut();
+            if OpenProcessToken(process, TOKEN_QUERY, &mut token) == 0 {
+                return None;
+            }
+            let level = if GetTokenInformation(
+                token,
+                TOKEN_INTEGRITY_LEVEL,
+                label.as_mut_ptr().cast(),
+                std::mem::size_of_val(&label) as u32,
+                &mut returned,
+            ) != 0
+            {
+                let sid = label[0] as *mut c_void;
+                (*GetSidSubAuthorityCount(sid))
+                    .checked_sub(1)
+                    .map(|last| *GetSidSubAuthority(sid, last as u32))
+            } else {
+                None
+            };
+            let has_ui_access = GetTokenInformation(
+                token,
+                TOKEN_UI_ACCESS,
+                std::ptr::addr_of_mut!(ui_access).cast(),
+                std::mem::size_of::<u32>() as u32,
+                &mut returned,
+            ) != 0
+                && ui_access != 0;
+            CloseHandle(token);
+            level.map(|level| (level, has_ui_access))
+        }
+    }
+
+    /// Whether UIPI lets this process send input to the focused window
+    fn foreground_accepts_input() -> SystemPermissionStatus {
+        // SAFETY: the pseudo handle needs no closing
+        let Some((own_level, ui_access)) = token_rights(unsafe { GetCurrentProcess() }) else {
+            return SystemPermissionStatus::NotDetermined;
+        };
+        if ui_access {
+            return SystemPermissionStatus::Granted;
+        }
+        let mut process_id = 0u32;
+        // SAFETY: a null foreground window leaves the ID at zero
+        unsafe { GetWindowThreadProcessId(GetForegroundWindow(), &mut process_id) };
+        if process_id == 0 {
+            return SystemPermissionStatus::Granted;
+        }
+        // SAFETY: the handle is checked and closed
+        let target = unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id);
+            if process.is_null() {
+                None
+            } else {
+                let rights = token_rights(process);
+                CloseHandle(process);
+                rights
+            }
+        };
+        let allowed = match target {
+            Some((level, _)) => level <= own_level,
+            // An elevated process's token refuses queries from a
+            // medium-integrity one
+            None => own_level >= SECURITY_MANDATORY_HIGH_RID,
+        };
+        if allowed {
+            SystemPermissionStatus::Granted
+        } else {
+            SystemPermissionStatus::Denied
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{SystemPermission, SystemPermissionStatus};
+    use std::os::raw::{c_char, c_int};
+
+    const UINPUT: &str = "/dev/uinput";
+
+    #[repr(C)]
+    struct Display {
+        _private: [u8; 0],
+    }
+
+    extern "C" {
+        fn XOpenDisplay(name: *const c_char) -> *mut Display;
+        fn XCloseDisplay(display: *mut Display) -> c_int;
+        fn XQueryExtension(
+            display: *mut Display,
+            name: *const c_char,
+            major_opcode: *mut c_int,
+            first_event: *mut c_int,
+            first_error: *mut c_int,
+        ) -> c_int;
+    }
+
+    pub fn check(permission: SystemPermission) -> SystemPermissionStatus {
+        let granted = match permission {
+            SystemPermission::ScreenRecording => x_display_supports(None),
+            SystemPermission::Accessibility => {
+                uinput_writable() || x_display_supports(Some(c"XTEST"))
+            }
+        };
+        if granted {
+            SystemPermissionStatus::Granted
+        } else {
+            SystemPermissionStatus::Denied
+        }
+    }
+
+    /// Whether `/dev/uinput` can create virtual input devices
+    fn uinput_writable() -> bool {
+        std::fs::OpenOptions::new().write(true).open(UINPUT).is_ok()
+    }
+
+    /// Whether the X server can be reached and has `extension`
+    fn x_display_supports(extension: Option<&std::ffi::CStr>) -> bool {
+        if std::env::var_os("DISPLAY").is_none() {
+            return false;
+        }
+        // SAFETY: a null name selects $DISPLAY; the connection is closed
+        // before returning
+        unsafe {
+            let display = XOpenDisplay(std::ptr::null());
+            if display.is_null() {
+                return false;
+            }
+            let (mut opcode, mut event, mut error) = (0, 0, 0);
+            let supported = extension.is_none_or(|name| {
+                XQueryExtension(display, name.as_ptr(), &mut opcode, &mut event, &mut error) != 0
+            });
+            XCloseDisplay(display);
+            supported
+        }
+    }
+}
+
+/// Watches OS permissions and pauses affected pipelines on revocation
+pub struct PermissionMonitor {
+    probe: Arc<dyn PermissionProbe>,
+    last_status: Arc<Mutex<HashMap<SystemPermission, SystemPermissionStatus>>>,
+    events: EventBus<PermissionEvent>,
+    is_monitoring: Arc<RwLock<bool>>,
+}
+
+impl PermissionMonitor {
+    pub fn new() -> Self {
+        Self::with_probe(Arc::new(PlatformPermissionProbe))
+    }
+
+    /// Create a monitor using a specific probe
+    pub fn with_probe(probe: Arc<dyn PermissionProbe>) -> Self {
+        Self {
+            probe,
+            last_status: Arc::new(Mutex::new(HashMap::new())),
+            events: EventBus::new(),
+            is_monitoring: Arc::new(RwLock::new(false)),
+        }
+    }
+
+    /// Subscribe to permission changes
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<PermissionEvent> {
+        self.events.subscribe(options)
+    }
+
+    /// Poll all permissions once and return the changes
+    ///
+    /// The first check only records the baseline; a permission that is already
+    /// missing at startup is reported as revoked.
+    pub async fn check_now(&self) -> Vec<PermissionEvent> {
+        Self::poll(&self.probe, &self.last_status, &self.events).await
+    }
+
+    async fn poll(
+        probe: &Arc<dyn PermissionProbe>,
+        last_status: &Mutex<HashMap<SystemPermission, SystemPermissionStatus>>,
+        bus: &EventBus<PermissionEvent>,
+    ) -> Vec<PermissionEvent> {
+        let mut last_status = last_status.lock().await;
+        let mut events = Vec::new();
+
+        for permission in [
+            SystemPermission::ScreenRecording,
+            SystemPermission::Accessibility,
+        ] {
+            let status = probe.check(permission);
+            // Unknown state says nothing about the pipeline; keep the last
+            // known one so a later answer is compared against it
+            if status == SystemPermissionStatus::NotDetermined {
+                continue;
+            }
+            let previous = last_status.insert(permission, status);
+            let was_granted = previous.is_none_or(|s| s == SystemPermissionStatus::Granted);
+            let is_granted = status == SystemPermissionStatus::Granted;
+
+            let event = match (was_granted, is_granted) {
+                (true, false) => {
+                    tracing::warn!("{:?} permission revoked", permission);
+                    Some(PermissionEvent::Revoked {
+                        permission,
+                        pipeline: permission.affected_pipeline(),
+                        remediation: permission.remediation_hint(),
+                    })
+                }
+                (false, true) => {
+                    tracing::info!("{:?} permission restored", permission);
+                    Some(PermissionEvent::Restored {
+                        permission,
+                        pipeline: permission.affected_pipeline(),
+                    })
+                }
+                _ => None,
+            };
+
+            if let Some(event) = event {
+                bus.publish(event.clone());
+                events.push(event);
+            }
+        }
+
+        events
+    }
+
+    /// Start polling in the background
+    pub async fn start_monitoring(&self, interval: Duration) -> Result<()> {
+        if *self.is_monitoring.read().await {
+            return Ok(());
+        }
+        *self.is_monitoring.write().await = true;
+
+        let probe = Arc::clone(&self.probe);
+        let last_status = Arc::clone(&self.last_status);
+        let events = self.events.clone();
+        let is_monitoring = Arc::clone(&self.is_monitoring);
+
+        tokio::spawn(async move {
+            while *is_monitoring.read().await {
+                Self::poll(&probe, &last_status, &events).await;
+                tokio::time::sleep(interval).await;
+            }
+        });
+
+        tracing::info!("Permission monitoring started");
+        Ok(())
+    }
+
+    pub async fn stop_monitoring(&self) {
+        *self.is_monitoring.write().await = false;
+        tracing::info!("Permission monitoring stopped");
+    }
+}
+
+impl Default for PermissionMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pause or resume the pipeline affected by a permission event
+pub async fn apply_permission_event(
+    event: &PermissionEvent,
+    capturer: &ScreenCapturer,
+    input: &InputController,
+) {
+    match event {
+        PermissionEvent::Revoked { pipeline, .. } => match pipeline {
+            AffectedPipeline::ScreenCapture => capturer.pause_capture().await,
+            AffectedPipeline::InputInjection => input.suspend(),
+        },
+        PermissionEvent::Restored { pipeline, .. } => match pipeline {
+            AffectedPipeline::ScreenCapture => capturer.resume_capture().await,
+            AffectedPipeline::InputInjection => input.resume(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input_control::InputEvent;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    struct MockProbe {
+        screen_granted: AtomicBool,
+    }
+
+    impl PermissionProbe for MockProbe {
+        fn check(&self, permission: SystemPermission) -> SystemPermissionStatus {
+            match permission {
+                SystemPermission::ScreenRecording
+                    if !self.screen_granted.load(Ordering::SeqCst) =>
+                {
+                    SystemPermissionStatus::Denied
+                }
+                _ => SystemPermissionStatus::Granted,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_revocation_pauses_capture() {
+        let probe = Arc::new(MockProbe {
+            screen_granted: AtomicBool::new(true),
+        });
+        let monitor = PermissionMonitor::with_probe(probe.clone());
+        let mut receiver = monitor.subscribe(SubscriptionOptions::all());
+        let capturer = ScreenCapturer::new();
+        let input = InputController::new();
+
+        assert!(monitor.check_now().await.is_empty());
+
+        probe.screen_granted.store(false, Ordering::SeqCst);
+        let events = monitor.check_now().await;
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            PermissionEvent::Revoked {
+                permission,
+                pipeline,
+                remediation,
+            } => {
+                assert_eq!(*permission, SystemPermission::ScreenRecording);
+                assert_eq!(*pipeline, AffectedPipeline::ScreenCapture);
+                assert!(!remediation.is_empty());
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(receiver.recv().await.unwrap(), events[0]);
+
+        apply_permission_event(&events[0], &capturer, &input).await;
+        assert!(capturer.is_paused().await);
+        assert!(!input.is_suspended());
+
+        // No duplicate event while still revoked
+        assert!(monitor.check_now().await.is_empty());
+
+        probe.screen_granted.store(true, Ordering::SeqCst);
+        let events = monitor.check_now().await;
+        assert!(matches!(events[0], PermissionEvent::Restored { .. }));
+        apply_permission_event(&events[0], &capturer, &input).await;
+        assert!(!capturer.is_paused().await);
+    }
+
+    struct ScriptedProbe {
+        screen: std::sync::Mutex<SystemPermissionStatus>,
+    }
+
+    impl PermissionProbe for ScriptedProbe {
+        fn check(&self, permission: SystemPermission) -> SystemPermissionStatus {
+            match permission {
+                SystemPermission::ScreenRecording => *self.screen.lock().unwrap(),
+                SystemPermission::Accessibility => SystemPermissionStatus::NotDetermined,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_undetermined_permissions_do_not_change_pipelines() {
+        let probe = Arc::new(ScriptedProbe {
+            screen: std::sync::Mutex::new(SystemPermissionStatus::NotDetermined),
+        });
+        let monitor = PermissionMonitor::with_probe(probe.clone());
+        assert!(monitor.check_now().await.is_empty());
+
+        *probe.screen.lock().unwrap() = SystemPermissionStatus::Denied;
+        let events = monitor.check_now().await;
+        assert!(matches!(events[..], [PermissionEvent::Revoked { .. }]));
+
+        // Still paused until the platform reports it granted again
+        *probe.screen.lock().unwrap() = SystemPermissionStatus::NotDetermined;
+        assert!(monitor.check_now().await.is_empty());
+        *probe.screen.lock().unwrap() = SystemPermissionStatus::Granted;
+        let events = monitor.check_now().await;
+        assert!(matches!(events[..], [PermissionEvent::Restored { .. }]));
+    }
+
+    #[tokio::test]
+    async fn test_suspended_input_is_rejected() {
+        let input = InputController::new();
+        let capturer = ScreenCapturer::new();
+        let event = PermissionEvent::Revoked {
+            permission: SystemPermission::Accessibility,
+            pipeline: AffectedPipeline::InputInjection,
+            remediation: SystemPermission::Accessibility.remediation_hint(),
+        };
+
+        apply_permission_event(&event, &capturer, &input).await;
+        assert!(input
+            .process_remote_input(InputEvent::MouseMove { x: 1, y: 1 })
+            .is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Outbound Signaling Queue
+//!
+//! Client-side buffer for signaling messages sent while the WebSocket is
+//! down, whether the client is reconnecting or has not connected yet. Queued
+//! messages are flushed, oldest first, as soon as a connection is opened.
+//! The queue is bounded: when full the oldest message is dropped, and
+//! messages older than the TTL are discarded instead of being sent late.
+//!
+//! Each message can carry a delivery callback that is called exactly once
+//! with its `SendOutcome`.
+
+use crate::signaling::SignalingMessage;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Default number of messages held while disconnected
+pub const DEFAULT_OUTBOUND_QUEUE_LEN: usize = 256;
+
+/// Default time a message may wait for the connection
+pub const DEFAULT_OUTBOUND_TTL_SECS: u64 = 30;
+
+/// What happened to an outbound message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SendOutcome {
+    /// Written to the WebSocket
+    Sent,
+    /// Waited longer than the queue TTL for a connection
+    Expired,
+    /// Evicted from a full queue, or queuing is disabled
+    Dropped,
+    /// Discarded by `disconnect`
+    Cancelled,
+    /// Could not be serialized or written, and will not be retried
+    Failed,
+}
+
+/// Called once with the outcome of a message
+pub type DeliveryCallback = Box<dyn FnOnce(SendOutcome) + Send>;
+
+/// Signaling message on its way to the WebSocket writer
+pub struct OutboundMessage {
+    pub message: SignalingMessage,
+    queued_at: Option<Instant>,
+    callback: Option<DeliveryCallback>,
+}
+
+impl OutboundMessage {
+    pub fn new(message: SignalingMessage) -> Self {
+        Self {
+            message,
+            queued_at: None,
+            callback: None,
+        }
+    }
+
+    pub fn with_callback(mut self, callback: impl FnOnce(SendOutcome) + Send + 'static) -> Self {
+        self.callback = Some(Box::new(callback));
+        self
+    }
+
+    /// Report the outcome to the callback, if any
+    pub fn complete(mut self, outcome: SendOutcome) {
+        if let Some(callback) = self.callback.take() {
+            callback(outcome);
+        }
+    }
+}
+
+impl From<SignalingMessage> for OutboundMessage {
+    fn from(message: SignalingMessage) -> Self {
+        Self::new(message)
+    }
+}
+
+impl fmt::Debug for OutboundMessage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutboundMessage")
+            .field("message", &self.message)
+            .field("queued_at", &self.queued_at)
+            .field("callback", &self.callback.is_some())
+            .finish()
+    }
+}
+
+/// Outbound queue limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutboundQueueConfig {
+    /// Messages held at most; 0 disables queuing so sends fail while
+    /// disconnected
+    pub max_messages: usize,
+    pub ttl_secs: u64,
+}
+
+impl Default for OutboundQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_messages: DEFAULT_OUTBOUND_QUEUE_LEN,
+            ttl_secs: DEFAULT_OUTBOUND_TTL_SECS,
+        }
+    }
+}
+
+/// Messages waiting for the connection, oldest first
+///
+/// The queue never calls callbacks itself; messages it gives back are
+/// completed by the caller once its lock is released.
+#[derive(Debug, Default)]
+pub struct OutboundQueue {
+    messages: VecDeque<OutboundMessage>,
+    config: OutboundQueueConfig,
+}
+
+impl OutboundQueue {
+    pub fn new(config: OutboundQueueConfig) -> Self {
+        Self {
+            messages: VecDeque::new(),
+            config,
+        }
+    }
+
+    pub fn config(&self) -> OutboundQueueConfig {
+        self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    /// Queue a message; returns the message dropped to make room, which is
+    /// `message` itself when queuing is disabled
+    pub fn push(&mut self, mut message: OutboundMessage, now: Instant) -> Option<OutboundMessage> {
+        if self.config.max_messages == 0 {
+            return Some(message);
+        }
+        // A message requeued after a failed write keeps its original age
+        message.queued_at.get_or_insert(now);
+        self.messages.push_back(message);
+        if self.messages.len() > self.config.max_messages {
+            return self.messages.pop_front();
+        }
+        None
+    }
+
+    /// Remove messages whose TTL elapsed
+    pub fn expire(&mut self, now: Instant) -> Vec<OutboundMessage> {
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        let mut expired = Vec::new();
+        let mut live = VecDeque::with_capacity(self.messages.len());
+        for message in self.messages.drain(..) {
+            let age = message
+                .queued_at
+                .map(|at| now.saturating_duration_since(at))
+                .unwrap_or_default();
+            if age >= ttl {
+                expired.push(message);
+            } else {
+                live.push_back(message);
+            }
+        }
+        self.messages = live;
+        expired
+    }
+
+    /// Take all messages, oldest first
+    pub fn drain(&mut self) -> Vec<OutboundMessage> {
+        self.messages.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn heartbeat(id: &str) -> SignalingMessage {
+        SignalingMessage::Heartbeat {
+            device_id: id.to_string(),
+        }
+    }
+
+    fn ids(messages: &[OutboundMessage]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|m| match &m.message {
+                SignalingMessage::Heartbeat { device_id } => device_id.clone(),
+                other => panic!("unexpected message: {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_bounded_queue_drops_oldest_and_expires() {
+        let mut queue = OutboundQueue::new(OutboundQueueConfig {
+            max_messages: 2,
+            ttl_secs: 10,
+        });
+        let start = Instant::now();
+        let outcomes = Arc::new(Mutex::new(Vec::new()));
+
+        for (i, id) in ["a", "b", "c"].into_iter().enumerate() {
+            let outcomes = outcomes.clone();
+            let message = OutboundMessage::new(heartbeat(id))
+                .with_callback(move |outcome| outcomes.lock().unwrap().push((id, outcome)));
+            if let Some(dropped) = queue.push(message, start + Duration::from_secs(i as u64 * 4)) {
+                dropped.complete(SendOutcome::Dropped);
+            }
+        }
+        assert_eq!(*outcomes.lock().unwrap(), vec![("a", SendOutcome::Dropped)]);
+
+        // "b" was queued at 4s, "c" at 8s
+        let expired = queue.expire(start + Duration::from_secs(14));
+        assert_eq!(ids(&expired), vec!["b"]);
+        assert_eq!(ids(&queue.drain()), vec!["c"]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_disabled_queue_rejects() {
+        let mut queue = OutboundQueue::new(OutboundQueueConfig {
+            max_messages: 0,
+            ..Default::default()
+        });
+        let rejected = queue.push(OutboundMessage::new(heartbeat("a")), Instant::now());
+        assert_eq!(ids(&[rejected.unwrap()]), vec!["a"]);
+        assert_eq!(queue.len(), 0);
+    }
+}
@@ -0,0 +1,191 @@
+//! Relay to Direct Path Migration
+//!
+//! A session that had to fall back to a TURN relay stays on it even when a
+//! direct path later becomes possible, e.g. after a peer leaves a network
+//! that blocked UDP. `spawn_path_migration` watches the path media takes
+//! and, while it is relayed, periodically probes for a direct one: if a STUN
+//! binding succeeds the NAT can be traversed, so ICE is restarted. ICE
+//! prefers host and server-reflexive pairs over relayed ones, so the
+//! restart moves media to a direct pair whenever connectivity checks pass
+//! on one, without a new peer connection.
+//!
+//! The way back is the consent check: when the direct path dies, consent
+//! expires, ICE restarts and the relay pair takes over again. Every change
+//! of path is published as `NetworkEvent::ConnectionTypeChanged`.
+
+use crate::network::{ConnectionType, NetworkManager};
+use crate::webrtc_engine::{NegotiationRole, WebRTCEngine};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathMigrationConfig {
+    /// How often the selected path is checked
+    pub check_interval: Duration,
+    /// Wait after reaching a relay before the first probe
+    pub probe_interval: Duration,
+    /// Probes that leave media on the relay double the wait, up to this
+    pub max_probe_interval: Duration,
+}
+
+impl Default for PathMigrationConfig {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(2),
+            probe_interval: Duration::from_secs(30),
+            max_probe_interval: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl PathMigrationConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.check_interval.is_zero() || self.probe_interval.is_zero() {
+            return Err(anyhow::anyhow!("Path migration intervals must be positive"));
+        }
+        if self.max_probe_interval < self.probe_interval {
+            return Err(anyhow::anyhow!(
+                "max_probe_interval must not be shorter than probe_interval"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// When to probe for a direct path, backing off while probes fail
+#[derive(Debug)]
+struct ProbeSchedule {
+    config: PathMigrationConfig,
+    interval: Duration,
+    /// Next probe; `None` while media is not relayed
+    next: Option<Instant>,
+}
+
+impl ProbeSchedule {
+    fn new(config: PathMigrationConfig) -> Self {
+        Self {
+            config,
+            interval: config.probe_interval,
+            next: None,
+        }
+    }
+
+    /// Follow the current path; returns whether a probe is due
+    fn update(&mut self, path: ConnectionType, now: Instant) -> bool {
+        if path != ConnectionType::TurnRelay {
+            self.interval = self.config.probe_interval;
+            self.next = None;
+            return false;
+        }
+        match self.next {
+            None => {
+                self.next = Some(now + self.interval);
+                false
+            }
+            Some(at) => now >= at,
+        }
+    }
+
+    /// A probe ran; the next one waits twice as long unless the path changes
+    fn probed(&mut self, now: Instant) {
+        self.interval = (self.interval * 2).min(self.config.max_probe_interval);
+        self.next = Some(now + self.interval);
+    }
+}
+
+/// Keep `connection_id` on the best path available until it closes
+///
+/// Only the impolite peer probes, as only it restarts ICE on consent
+/// expiry; both sides publish path changes on `network`.
+pub fn spawn_path_migration(
+    engine: Arc<WebRTCEngine>,
+    network: Arc<NetworkManager>,
+    connection_id: String,
+    config: PathMigrationConfig,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut schedule = ProbeSchedule::new(config);
+        let mut ticks = tokio::time::interval(config.check_interval.max(Duration::from_millis(1)));
+        ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            // Fails once the connection is closed
+            let Ok(path) = engine.connection_path(&connection_id).await else {
+                break;
+            };
+            if path != ConnectionType::Unknown {
+                network.set_connection_type(path).await;
+            }
+            let now = Instant::now();
+            if !schedule.update(path, now) {
+                continue;
+            }
+            schedule.probed(now);
+            if engine.negotiation_role(&connection_id).await.ok() != Some(NegotiationRole::Impolite)
+            {
+                continue;
+            }
+
+            match network.attempt_stun_connection().await {
+                Ok(ConnectionType::StunDirect) => {
+                    tracing::info!(
+                        "Probing for a direct path on relayed connection {}",
+                        connection_id
+                    );
+                    if let Err(e) = engine.restart_ice(&connection_id).await {
+                        tracing::warn!("Direct path probe on {} failed: {}", connection_id, e);
+                    }
+                }
+                _ => tracing::debug!(
+                    "No STUN mapping, connection {} stays relayed",
+                    connection_id
+                ),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_schedule_backs_off_while_relayed() {
+        let config = PathMigrationConfig {
+            probe_interval: Duration::from_secs(30),
+            max_probe_interval: Duration::from_secs(100),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert!(PathMigrationConfig {
+            max_probe_interval: Duration::from_secs(10),
+            ..config
+        }
+        .validate()
+        .is_err());
+
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut schedule = ProbeSchedule::new(config);
+        assert!(!schedule.update(ConnectionType::Direct, at(0)));
+
+        // Relayed: first probe after probe_interval, then 60s, then capped
+        assert!(!schedule.update(ConnectionType::TurnRelay, at(0)));
+        assert!(!schedule.update(ConnectionType::TurnRelay, at(29)));
+        assert!(schedule.update(ConnectionType::TurnRelay, at(30)));
+        schedule.probed(at(30));
+        assert!(!schedule.update(ConnectionType::TurnRelay, at(89)));
+        assert!(schedule.update(ConnectionType::TurnRelay, at(90)));
+        schedule.probed(at(90));
+        assert!(!schedule.update(ConnectionType::TurnRelay, at(189)));
+        assert!(schedule.update(ConnectionType::TurnRelay, at(190)));
+
+        // Reaching a direct path resets the backoff
+        assert!(!schedule.update(ConnectionType::StunDirect, at(200)));
+        assert!(!schedule.update(ConnectionType::TurnRelay, at(200)));
+        assert!(schedule.update(ConnectionType::TurnRelay, at(230)));
+    }
+}
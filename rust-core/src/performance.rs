@@ -10,10 +10,17 @@
 //!
 //! Validates: Requirements 2.4, 7.1, 15.6, 16.8
 
-use std::collections::VecDeque;
+use crate::congestion::{self, GccConfig, GccEstimator};
+use crate::input_control::InputEvent;
+use crate::input_protocol::{BatchedInput, InputAck, InputBatch, InputPacket};
+use crate::input_sequence::{InputSequencer, SequencedInput};
+use crate::memory_budget::{MemoryBudget, MemoryBudgetUsage, MemorySubsystem};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
-use std::sync::Arc;
-use std::time::Instant;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
 /// Memory usage statistics
@@ -25,6 +32,8 @@ pub struct MemoryStats {
     pub active_buffers: usize,
     pub frame_buffer_count: usize,
     pub frame_buffer_bytes: u64,
+    /// Byte accounting against the shared budget, when one is attached
+    pub budget: Option<MemoryBudgetUsage>,
 }
 
 /// Network transmission statistics
@@ -39,11 +48,265 @@ pub struct TransmissionStats {
     pub bandwidth_utilization: f64,
 }
 
+/// Number of encoded frames kept for statistics
+pub const FRAME_STATS_WINDOW: usize = 120;
+
+/// Type of an encoded video frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodedFrameType {
+    Key,
+    Delta,
+}
+
+/// Statistics reported by the encoder for a single frame
+#[derive(Debug, Clone)]
+pub struct EncodedFrameStats {
+    pub frame_id: u64,
+    pub frame_type: EncodedFrameType,
+    /// Average quantization parameter of the frame
+    pub qp: u8,
+    pub size_bytes: usize,
+    pub encode_duration_us: u64,
+}
+
+/// Aggregated encoder statistics over the recent frame window
+#[derive(Debug, Clone, Default)]
+pub struct EncoderStats {
+    pub frames: usize,
+    pub key_frames: usize,
+    /// Average QP of delta frames (key frames if there are none)
+    pub avg_qp: f64,
+    pub avg_frame_bytes: f64,
+    pub avg_encode_ms: f64,
+    pub max_encode_ms: f64,
+}
+
+impl EncoderStats {
+    fn from_frames<'a>(frames: impl Iterator<Item = &'a EncodedFrameStats>) -> Self {
+        let frames: Vec<_> = frames.collect();
+        if frames.is_empty() {
+            return Self::default();
+        }
+
+        let count = frames.len() as f64;
+        let delta: Vec<_> = frames
+            .iter()
+            .filter(|f| f.frame_type == EncodedFrameType::Delta)
+            .collect();
+        let avg_qp = if delta.is_empty() {
+            frames.iter().map(|f| f.qp as f64).sum::<f64>() / count
+        } else {
+            delta.iter().map(|f| f.qp as f64).sum::<f64>() / delta.len() as f64
+        };
+
+        Self {
+            frames: frames.len(),
+            key_frames: frames.len() - delta.len(),
+            avg_qp,
+            avg_frame_bytes: frames.iter().map(|f| f.size_bytes as f64).sum::<f64>() / count,
+            avg_encode_ms: frames
+                .iter()
+                .map(|f| f.encode_duration_us as f64 / 1000.0)
+                .sum::<f64>()
+                / count,
+            max_encode_ms: frames
+                .iter()
+                .map(|f| f.encode_duration_us as f64 / 1000.0)
+                .fold(0.0, f64::max),
+        }
+    }
+}
+
+/// Encoder latency vs quality trade-off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EncoderPreference {
+    /// Keep encode time minimal, accept coarser quantization
+    LowLatency,
+    #[default]
+    Balanced,
+    /// Spend more bits and encode time for image quality
+    Quality,
+}
+
+impl EncoderPreference {
+    /// Per-frame encode time budget in milliseconds
+    pub fn encode_budget_ms(&self) -> f64 {
+        match self {
+            EncoderPreference::LowLatency => 8.0,
+            EncoderPreference::Balanced => 16.0,
+            EncoderPreference::Quality => 33.0,
+        }
+    }
+
+    /// Acceptable (min, max) average QP range
+    pub fn qp_range(&self) -> (f64, f64) {
+        match self {
+            EncoderPreference::LowLatency => (24.0, 40.0),
+            EncoderPreference::Balanced => (20.0, 34.0),
+            EncoderPreference::Quality => (16.0, 28.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub enum QualityPreset {
+    Low,      // 720p, 15fps, low bitrate
+    Balanced, // 1080p, 30fps, medium bitrate
+    High,     // 1080p, 60fps, high bitrate
+    Ultra,    // Native resolution, 60fps, maximum bitrate
+}
+
+/// Where in the pipeline a frame was dropped, and why
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum FrameDropReason {
+    /// Frame buffer or capture channel was full
+    QueueFull,
+    /// Encoder could not keep up with the capture rate
+    Overload,
+    /// Network congestion control discarded the frame
+    Congestion,
+    /// Pacer held the frame past its send deadline
+    PacedOut,
+    /// Frame arrived too late to be decoded and shown
+    DecodeLate,
+}
+
+impl FrameDropReason {
+    pub const ALL: [FrameDropReason; 5] = [
+        FrameDropReason::QueueFull,
+        FrameDropReason::Overload,
+        FrameDropReason::Congestion,
+        FrameDropReason::PacedOut,
+        FrameDropReason::DecodeLate,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Dropped frame counts by reason
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameDropBreakdown {
+    pub queue_full: u64,
+    pub overload: u64,
+    pub congestion: u64,
+    pub paced_out: u64,
+    pub decode_late: u64,
+}
+
+impl FrameDropBreakdown {
+    fn from_counts(counts: &[u64; 5]) -> Self {
+        Self {
+            queue_full: counts[FrameDropReason::QueueFull.index()],
+            overload: counts[FrameDropReason::Overload.index()],
+            congestion: counts[FrameDropReason::Congestion.index()],
+            paced_out: counts[FrameDropReason::PacedOut.index()],
+            decode_late: counts[FrameDropReason::DecodeLate.index()],
+        }
+    }
+
+    pub fn get(&self, reason: FrameDropReason) -> u64 {
+        match reason {
+            FrameDropReason::QueueFull => self.queue_full,
+            FrameDropReason::Overload => self.overload,
+            FrameDropReason::Congestion => self.congestion,
+            FrameDropReason::PacedOut => self.paced_out,
+            FrameDropReason::DecodeLate => self.decode_late,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        FrameDropReason::ALL.iter().map(|r| self.get(*r)).sum()
+    }
+
+    /// Reason with the most drops, if any frame was dropped
+    pub fn dominant_reason(&self) -> Option<FrameDropReason> {
+        FrameDropReason::ALL
+            .into_iter()
+            .filter(|r| self.get(*r) > 0)
+            .max_by_key(|r| self.get(*r))
+    }
+}
+
+/// Per-session dropped frame counters, shared by every stage that can drop
+///
+/// Recording is synchronous so capture and encoder threads can report drops
+/// without a runtime.
+#[derive(Debug, Default)]
+pub struct FrameDropTracker {
+    sessions: Mutex<HashMap<String, [u64; 5]>>,
+}
+
+impl FrameDropTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, session_id: &str, reason: FrameDropReason) {
+        self.record_many(session_id, reason, 1);
+    }
+
+    pub fn record_many(&self, session_id: &str, reason: FrameDropReason, count: u64) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            let counts = sessions.entry(session_id.to_string()).or_default();
+            counts[reason.index()] += count;
+        }
+    }
+
+    /// Drops for a session; all zero if it never dropped a frame
+    pub fn breakdown(&self, session_id: &str) -> FrameDropBreakdown {
+        self.sessions
+            .lock()
+            .ok()
+            .and_then(|sessions| {
+                sessions
+                    .get(session_id)
+                    .map(FrameDropBreakdown::from_counts)
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn all_sessions(&self) -> HashMap<String, FrameDropBreakdown> {
+        self.sessions
+            .lock()
+            .map(|sessions| {
+                sessions
+                    .iter()
+                    .map(|(id, counts)| (id.clone(), FrameDropBreakdown::from_counts(counts)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Drops summed over all sessions
+    pub fn totals(&self) -> FrameDropBreakdown {
+        let mut totals = [0u64; 5];
+        if let Ok(sessions) = self.sessions.lock() {
+            for counts in sessions.values() {
+                for (total, count) in totals.iter_mut().zip(counts) {
+                    *total += count;
+                }
+            }
+        }
+        FrameDropBreakdown::from_counts(&totals)
+    }
+
+    pub fn remove_session(&self, session_id: &str) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.remove(session_id);
+        }
+    }
+}
+
 /// Performance metrics
 #[derive(Debug, Clone)]
 pub struct PerformanceMetrics {
     pub memory: MemoryStats,
     pub transmission: TransmissionStats,
+    pub encoder: EncoderStats,
+    /// Dropped frames across all sessions
+    pub frame_drops: FrameDropBreakdown,
     pub frame_rate: f64,
     pub input_latency_ms: f64,
     pub cpu_usage_percent: f64,
@@ -55,6 +318,8 @@ impl Default for PerformanceMetrics {
         Self {
             memory: MemoryStats::default(),
             transmission: TransmissionStats::default(),
+            encoder: EncoderStats::default(),
+            frame_drops: FrameDropBreakdown::default(),
             frame_rate: 0.0,
             input_latency_ms: 0.0,
             cpu_usage_percent: 0.0,
@@ -71,6 +336,7 @@ pub struct BufferPool {
     max_buffers: usize,
     allocated_count: AtomicUsize,
     reused_count: AtomicUsize,
+    budget: Option<Arc<MemoryBudget>>,
 }
 
 impl BufferPool {
@@ -81,14 +347,41 @@ impl BufferPool {
             max_buffers,
             allocated_count: AtomicUsize::new(0),
             reused_count: AtomicUsize::new(0),
+            budget: None,
         }
     }
 
+    /// Account idle buffers against `budget`, which may reclaim them
+    pub fn with_memory_budget(mut self, budget: Arc<MemoryBudget>) -> Self {
+        let buffers = self.buffers.clone();
+        budget.on_pressure(
+            MemorySubsystem::BufferPool,
+            Arc::new(move |wanted| {
+                let Ok(mut buffers) = buffers.try_write() else {
+                    return 0;
+                };
+                let mut freed = 0;
+                while freed < wanted {
+                    match buffers.pop_back() {
+                        Some(buffer) => freed += buffer.capacity() as u64,
+                        None => break,
+                    }
+                }
+                freed
+            }),
+        );
+        self.budget = Some(budget);
+        self
+    }
+
     /// Acquire a buffer from the pool or allocate a new one
     pub async fn acquire(&self) -> Vec<u8> {
         let mut buffers = self.buffers.write().await;
 
         if let Some(mut buffer) = buffers.pop_front() {
+            if let Some(budget) = &self.budget {
+                budget.release(MemorySubsystem::BufferPool, buffer.capacity() as u64);
+            }
             buffer.clear();
             self.reused_count.fetch_add(1, Ordering::Relaxed);
             buffer
@@ -103,9 +396,14 @@ impl BufferPool {
         let mut buffers = self.buffers.write().await;
 
         if buffers.len() < self.max_buffers {
-            buffers.push_back(buffer);
+            let fits = self.budget.as_ref().is_none_or(|budget| {
+                budget.try_reserve(MemorySubsystem::BufferPool, buffer.capacity() as u64)
+            });
+            if fits {
+                buffers.push_back(buffer);
+            }
         }
-        // If pool is full, buffer is dropped
+        // If pool is full or over budget, buffer is dropped
     }
 
     /// Get pool statistics
@@ -122,8 +420,10 @@ impl BufferPool {
 pub struct FrameBufferManager {
     buffers: Arc<RwLock<VecDeque<FrameBuffer>>>,
     max_buffers: usize,
-    total_bytes: AtomicU64,
-    dropped_frames: AtomicU64,
+    total_bytes: Arc<AtomicU64>,
+    dropped_frames: Arc<AtomicU64>,
+    drop_tracker: Option<(Arc<FrameDropTracker>, String)>,
+    budget: Option<Arc<MemoryBudget>>,
 }
 
 #[derive(Debug, Clone)]
@@ -149,11 +449,54 @@ impl FrameBufferManager {
         Self {
             buffers: Arc::new(RwLock::new(VecDeque::with_capacity(max_buffers))),
             max_buffers,
-            total_bytes: AtomicU64::new(0),
-            dropped_frames: AtomicU64::new(0),
+            total_bytes: Arc::new(AtomicU64::new(0)),
+            dropped_frames: Arc::new(AtomicU64::new(0)),
+            drop_tracker: None,
+            budget: None,
         }
     }
 
+    /// Attribute overflow drops to `session_id` in `tracker`
+    pub fn with_drop_tracker(mut self, tracker: Arc<FrameDropTracker>, session_id: String) -> Self {
+        self.drop_tracker = Some((tracker, session_id));
+        self
+    }
+
+    /// Account queued frames against `budget`
+    ///
+    /// Under pressure from other subsystems the oldest queued frames are
+    /// dropped, always keeping the newest one. Attach the drop tracker
+    /// first so those drops are attributed too.
+    pub fn with_memory_budget(mut self, budget: Arc<MemoryBudget>) -> Self {
+        let buffers = self.buffers.clone();
+        let total_bytes = self.total_bytes.clone();
+        let dropped_frames = self.dropped_frames.clone();
+        let drop_tracker = self.drop_tracker.clone();
+        budget.on_pressure(
+            MemorySubsystem::FrameBuffers,
+            Arc::new(move |wanted| {
+                let Ok(mut buffers) = buffers.try_write() else {
+                    return 0;
+                };
+                let mut freed = 0;
+                while freed < wanted && buffers.len() > 1 {
+                    if let Some(frame) = buffers.pop_front() {
+                        let size = frame.data.len() as u64;
+                        total_bytes.fetch_sub(size, Ordering::Relaxed);
+                        dropped_frames.fetch_add(1, Ordering::Relaxed);
+                        if let Some((tracker, session_id)) = &drop_tracker {
+                            tracker.record(session_id, FrameDropReason::QueueFull);
+                        }
+                        freed += size;
+                    }
+                }
+                freed
+            }),
+        );
+        self.budget = Some(budget);
+        self
+    }
+
     /// Add a frame to the buffer
     pub async fn push_frame(&self, frame: FrameBuffer) {
         let frame_size = frame.data.len() as u64;
@@ -161,10 +504,19 @@ impl FrameBufferManager {
 
         // Drop oldest frame if buffer is full
         if buffers.len() >= self.max_buffers {
-            if let Some(old_frame) = buffers.pop_front() {
-                self.total_bytes
-                    .fetch_sub(old_frame.data.len() as u64, Ordering::Relaxed);
-                self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+            self.drop_oldest(&mut buffers);
+        }
+
+        // Make room within the budget by dropping older frames first
+        if let Some(budget) = &self.budget {
+            while !budget.try_reserve(MemorySubsystem::FrameBuffers, frame_size) {
+                if !self.drop_oldest(&mut buffers) {
+                    self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+                    if let Some((tracker, session_id)) = &self.drop_tracker {
+                        tracker.record(session_id, FrameDropReason::QueueFull);
+                    }
+                    return;
+                }
             }
         }
 
@@ -172,13 +524,31 @@ impl FrameBufferManager {
         buffers.push_back(frame);
     }
 
+    fn drop_oldest(&self, buffers: &mut VecDeque<FrameBuffer>) -> bool {
+        let Some(old_frame) = buffers.pop_front() else {
+            return false;
+        };
+        self.forget_bytes(old_frame.data.len() as u64);
+        self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+        if let Some((tracker, session_id)) = &self.drop_tracker {
+            tracker.record(session_id, FrameDropReason::QueueFull);
+        }
+        true
+    }
+
+    fn forget_bytes(&self, bytes: u64) {
+        self.total_bytes.fetch_sub(bytes, Ordering::Relaxed);
+        if let Some(budget) = &self.budget {
+            budget.release(MemorySubsystem::FrameBuffers, bytes);
+        }
+    }
+
     /// Get the next frame for display
     pub async fn pop_frame(&self) -> Option<FrameBuffer> {
         let mut buffers = self.buffers.write().await;
 
         if let Some(frame) = buffers.pop_front() {
-            self.total_bytes
-                .fetch_sub(frame.data.len() as u64, Ordering::Relaxed);
+            self.forget_bytes(frame.data.len() as u64);
             Some(frame)
         } else {
             None
@@ -199,7 +569,10 @@ impl FrameBufferManager {
     pub async fn clear(&self) {
         let mut buffers = self.buffers.write().await;
         buffers.clear();
-        self.total_bytes.store(0, Ordering::Relaxed);
+        let bytes = self.total_bytes.swap(0, Ordering::Relaxed);
+        if let Some(budget) = &self.budget {
+            budget.release(MemorySubsystem::FrameBuffers, bytes);
+        }
     }
 }
 
@@ -214,6 +587,9 @@ pub struct TransmissionOptimizer {
     packet_batch_size: AtomicUsize,
     latency_samples: Arc<RwLock<VecDeque<f64>>>,
     bandwidth_samples: Arc<RwLock<VecDeque<u64>>>,
+    frame_stats: Arc<RwLock<VecDeque<EncodedFrameStats>>>,
+    encoder_preference: Arc<RwLock<EncoderPreference>>,
+    bandwidth_estimator: Arc<RwLock<GccEstimator>>,
 }
 
 impl TransmissionOptimizer {
@@ -226,7 +602,40 @@ impl TransmissionOptimizer {
             packet_batch_size: AtomicUsize::new(1),
             latency_samples: Arc::new(RwLock::new(VecDeque::with_capacity(100))),
             bandwidth_samples: Arc::new(RwLock::new(VecDeque::with_capacity(100))),
+            frame_stats: Arc::new(RwLock::new(VecDeque::with_capacity(FRAME_STATS_WINDOW))),
+            encoder_preference: Arc::new(RwLock::new(EncoderPreference::default())),
+            bandwidth_estimator: Arc::new(RwLock::new(GccEstimator::new(GccConfig {
+                min_bitrate,
+                max_bitrate,
+                start_bitrate: target_bitrate,
+            }))),
+        }
+    }
+
+    /// Record a packet sent with transport-wide sequence number `sequence`
+    pub async fn record_packet_sent(&self, sequence: u16, size: usize) {
+        self.bandwidth_estimator
+            .write()
+            .await
+            .on_packet_sent(sequence, size, Instant::now());
+    }
+
+    /// Feed an RTCP packet from the receiver into the bandwidth estimator
+    ///
+    /// Returns the number of transport-wide CC feedback messages applied.
+    pub async fn record_transport_feedback(&self, rtcp: &[u8]) -> Result<usize> {
+        let messages = congestion::parse_transport_feedback(rtcp)?;
+        let mut estimator = self.bandwidth_estimator.write().await;
+        for feedback in &messages {
+            estimator.on_feedback(feedback, Instant::now());
         }
+        Ok(messages.len())
+    }
+
+    /// Available bandwidth estimated from transport feedback, if any arrived
+    pub async fn estimated_bandwidth(&self) -> Option<u64> {
+        let estimator = self.bandwidth_estimator.read().await;
+        estimator.has_feedback().then(|| estimator.estimate())
     }
 
     /// Record a latency sample
@@ -247,8 +656,45 @@ impl TransmissionOptimizer {
         samples.push_back(bandwidth_bps);
     }
 
-    /// Adapt bitrate based on network conditions
+    /// Record statistics for an encoded frame
+    pub async fn record_frame_stats(&self, stats: EncodedFrameStats) {
+        let mut frames = self.frame_stats.write().await;
+        if frames.len() >= FRAME_STATS_WINDOW {
+            frames.pop_front();
+        }
+        frames.push_back(stats);
+    }
+
+    /// Get the statistics of the last `count` encoded frames, oldest first
+    pub async fn recent_frame_stats(&self, count: usize) -> Vec<EncodedFrameStats> {
+        let frames = self.frame_stats.read().await;
+        let skip = frames.len().saturating_sub(count);
+        frames.iter().skip(skip).cloned().collect()
+    }
+
+    /// Get aggregated encoder statistics over the frame window
+    pub async fn get_encoder_stats(&self) -> EncoderStats {
+        EncoderStats::from_frames(self.frame_stats.read().await.iter())
+    }
+
+    /// Set the encoder latency vs quality preference
+    pub async fn set_encoder_preference(&self, preference: EncoderPreference) {
+        *self.encoder_preference.write().await = preference;
+    }
+
+    pub async fn get_encoder_preference(&self) -> EncoderPreference {
+        *self.encoder_preference.read().await
+    }
+
+    /// Adapt bitrate based on network conditions and encoder statistics
+    ///
+    /// Once transport feedback arrives the congestion controller's estimate
+    /// sets the bitrate; the latency heuristics only apply before that.
     pub async fn adapt_bitrate(&self) -> u64 {
+        if let Some(estimate) = self.estimated_bandwidth().await {
+            return self.adapt_to_estimate(estimate).await;
+        }
+
         let latency_samples = self.latency_samples.read().await;
         let bandwidth_samples = self.bandwidth_samples.read().await;
 
@@ -271,11 +717,28 @@ impl TransmissionOptimizer {
             new_bitrate = (new_bitrate as f64 * 0.8) as u64;
         } else if avg_latency > 100.0 {
             new_bitrate = (new_bitrate as f64 * 0.9) as u64;
-        } else if avg_latency < 50.0 {
-            // If latency is low and bandwidth allows, increase bitrate
-            let target = self.target_bitrate.load(Ordering::Relaxed);
-            if new_bitrate < target && avg_bandwidth > new_bitrate {
-                new_bitrate = (new_bitrate as f64 * 1.1) as u64;
+        } else {
+            let encoder = EncoderStats::from_frames(self.frame_stats.read().await.iter());
+            let preference = *self.encoder_preference.read().await;
+            let (min_qp, max_qp) = preference.qp_range();
+
+            if encoder.frames > 0 && encoder.avg_encode_ms > preference.encode_budget_ms() {
+                // Encoder cannot keep up; fewer bits means less work per frame
+                new_bitrate = (new_bitrate as f64 * 0.9) as u64;
+            } else if encoder.frames > 0 && encoder.avg_qp > max_qp {
+                // Picture is visibly degraded; raise bitrate if the link allows
+                if avg_bandwidth > new_bitrate {
+                    new_bitrate = (new_bitrate as f64 * 1.1) as u64;
+                }
+            } else if encoder.frames > 0 && encoder.avg_qp < min_qp {
+                // Quality is saturated; extra bits are wasted
+                new_bitrate = (new_bitrate as f64 * 0.95) as u64;
+            } else if avg_latency < 50.0 {
+                // If latency is low and bandwidth allows, increase bitrate
+                let target = self.target_bitrate.load(Ordering::Relaxed);
+                if new_bitrate < target && avg_bandwidth > new_bitrate {
+                    new_bitrate = (new_bitrate as f64 * 1.1) as u64;
+                }
             }
         }
 
@@ -286,6 +749,24 @@ impl TransmissionOptimizer {
         new_bitrate
     }
 
+    async fn adapt_to_estimate(&self, estimate: u64) -> u64 {
+        let current = self.current_bitrate.load(Ordering::Relaxed);
+        let encoder = EncoderStats::from_frames(self.frame_stats.read().await.iter());
+        let preference = *self.encoder_preference.read().await;
+        let (min_qp, _) = preference.qp_range();
+
+        let mut new_bitrate = estimate;
+        if encoder.frames > 0 && encoder.avg_encode_ms > preference.encode_budget_ms() {
+            new_bitrate = new_bitrate.min((current as f64 * 0.9) as u64);
+        } else if encoder.frames > 0 && encoder.avg_qp < min_qp {
+            new_bitrate = new_bitrate.min((current as f64 * 0.95) as u64);
+        }
+
+        new_bitrate = new_bitrate.clamp(self.min_bitrate, self.max_bitrate);
+        self.current_bitrate.store(new_bitrate, Ordering::Relaxed);
+        new_bitrate
+    }
+
     /// Get current bitrate
     pub fn get_current_bitrate(&self) -> u64 {
         self.current_bitrate.load(Ordering::Relaxed)
@@ -325,6 +806,25 @@ pub struct InputEventEntry {
     pub data: Vec<u8>,
 }
 
+impl InputEventEntry {
+    /// Entry carrying a sequenced event in the input wire format
+    pub fn sequenced(input: &SequencedInput) -> Result<Self> {
+        let event_type = InputEventType::of(&input.event);
+        let data = BatchedInput {
+            sequence: input.sequence,
+            sent_at_ms: input.sent_at_ms,
+            event: input.event.clone(),
+        }
+        .to_bytes()?;
+        Ok(Self {
+            event_type,
+            timestamp: Instant::now(),
+            priority: event_type.default_priority(),
+            data,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum InputEventType {
     MouseMove,
@@ -336,6 +836,21 @@ pub enum InputEventType {
 }
 
 impl InputEventType {
+    pub fn of(event: &InputEvent) -> Self {
+        match event {
+            InputEvent::MouseMove { .. } => InputEventType::MouseMove,
+            InputEvent::MouseClick { .. } | InputEvent::MouseDoubleClick { .. } => {
+                InputEventType::MouseClick
+            }
+            InputEvent::MouseWheel { .. } => InputEventType::MouseScroll,
+            InputEvent::KeyDown { .. } => InputEventType::KeyDown,
+            InputEvent::KeyUp { .. } => InputEventType::KeyUp,
+            InputEvent::KeyPress { .. }
+            | InputEvent::TypeText { .. }
+            | InputEvent::ImeCommit { .. } => InputEventType::KeyPress,
+        }
+    }
+
     /// Get default priority (lower = higher priority)
     pub fn default_priority(&self) -> u8 {
         match self {
@@ -423,6 +938,33 @@ impl InputOptimizer {
         coalesced
     }
 
+    /// Next batch as an input protocol packet, if one is due
+    ///
+    /// Entries must have been created with `InputEventEntry::sequenced`.
+    pub async fn next_packet(&self, session_nonce: u64) -> Result<Option<Vec<u8>>> {
+        let batch = self.get_batch().await;
+        if batch.is_empty() {
+            return Ok(None);
+        }
+        let events = batch
+            .iter()
+            .map(|entry| BatchedInput::from_bytes(&entry.data))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        let packet = InputPacket::Batch(InputBatch::new(session_nonce, events));
+        Ok(Some(packet.encode()?))
+    }
+
+    /// Record the latency measured by a host's ack
+    ///
+    /// Half the round trip is recorded as the input latency. Returns the
+    /// round trip, or `None` if the ack does not belong to `sequencer`.
+    pub async fn record_ack(&self, sequencer: &InputSequencer, ack: &InputAck) -> Option<Duration> {
+        let round_trip = sequencer.round_trip(ack)?;
+        self.record_latency(round_trip.as_secs_f64() * 1000.0 / 2.0)
+            .await;
+        Some(round_trip)
+    }
+
     /// Record input latency
     pub async fn record_latency(&self, latency_ms: f64) {
         let mut samples = self.latency_samples.write().await;
@@ -454,8 +996,10 @@ pub struct PerformanceMonitor {
     frame_buffer: Arc<FrameBufferManager>,
     transmission_optimizer: Arc<TransmissionOptimizer>,
     input_optimizer: Arc<InputOptimizer>,
+    frame_drops: Arc<FrameDropTracker>,
     metrics_history: Arc<RwLock<VecDeque<PerformanceMetrics>>>,
     max_history: usize,
+    memory_budget: Option<Arc<MemoryBudget>>,
 }
 
 impl PerformanceMonitor {
@@ -470,11 +1014,42 @@ impl PerformanceMonitor {
             frame_buffer,
             transmission_optimizer,
             input_optimizer,
+            frame_drops: Arc::new(FrameDropTracker::new()),
             metrics_history: Arc::new(RwLock::new(VecDeque::with_capacity(60))),
             max_history: 60, // Keep 60 seconds of history
+            memory_budget: None,
         }
     }
 
+    /// Report `budget` usage in collected memory stats
+    pub fn with_memory_budget(mut self, budget: Arc<MemoryBudget>) -> Self {
+        self.memory_budget = Some(budget);
+        self
+    }
+
+    /// Tracker to hand to pipeline stages that drop frames
+    pub fn frame_drop_tracker(&self) -> Arc<FrameDropTracker> {
+        self.frame_drops.clone()
+    }
+
+    pub fn record_frame_drop(&self, session_id: &str, reason: FrameDropReason) {
+        self.frame_drops.record(session_id, reason);
+    }
+
+    /// Why a session's frames were dropped
+    pub fn get_frame_drops(&self, session_id: &str) -> FrameDropBreakdown {
+        self.frame_drops.breakdown(session_id)
+    }
+
+    pub fn get_frame_drops_by_session(&self) -> HashMap<String, FrameDropBreakdown> {
+        self.frame_drops.all_sessions()
+    }
+
+    /// Forget a finished session's drop counters
+    pub fn end_session(&self, session_id: &str) {
+        self.frame_drops.remove_session(session_id);
+    }
+
     /// Collect current performance metrics
     pub async fn collect_metrics(&self) -> PerformanceMetrics {
         let (allocated, reused) = self.buffer_pool.stats();
@@ -482,6 +1057,7 @@ impl PerformanceMonitor {
         let avg_latency = self.transmission_optimizer.get_avg_latency().await;
         let input_latency = self.input_optimizer.get_avg_latency().await;
         let current_bitrate = self.transmission_optimizer.get_current_bitrate();
+        let encoder = self.transmission_optimizer.get_encoder_stats().await;
 
         let metrics = PerformanceMetrics {
             memory: MemoryStats {
@@ -491,6 +1067,7 @@ impl PerformanceMonitor {
                 active_buffers: allocated,
                 frame_buffer_count: frame_count,
                 frame_buffer_bytes: frame_bytes,
+                budget: self.memory_budget.as_ref().map(|budget| budget.usage()),
             },
             transmission: TransmissionStats {
                 bytes_sent: 0, // Would need actual tracking
@@ -501,6 +1078,8 @@ impl PerformanceMonitor {
                 avg_latency_ms: avg_latency,
                 bandwidth_utilization: current_bitrate as f64 / 10_000_000.0, // Assume 10Mbps max
             },
+            encoder,
+            frame_drops: self.frame_drops.totals(),
             frame_rate: 30.0, // Would need actual measurement
             input_latency_ms: input_latency,
             cpu_usage_percent: 0.0, // Would need system-level tracking
@@ -616,6 +1195,103 @@ mod tests {
         assert_eq!(dropped, 2); // 2 frames dropped
     }
 
+    #[tokio::test]
+    async fn test_frame_drops_attributed_per_session() {
+        let monitor = PerformanceMonitor::new(
+            Arc::new(BufferPool::new(1024, 4)),
+            Arc::new(FrameBufferManager::new(2)),
+            Arc::new(TransmissionOptimizer::new(500_000, 10_000_000, 2_000_000)),
+            Arc::new(InputOptimizer::new(16, 10)),
+        );
+        let buffer =
+            FrameBufferManager::new(1).with_drop_tracker(monitor.frame_drop_tracker(), "s1".into());
+        for i in 0..3 {
+            buffer
+                .push_frame(FrameBuffer {
+                    id: i,
+                    timestamp: i,
+                    data: vec![0u8; 16],
+                    width: 4,
+                    height: 1,
+                    format: FrameFormat::RGBA,
+                })
+                .await;
+        }
+        monitor.record_frame_drop("s1", FrameDropReason::Congestion);
+        monitor.record_frame_drop("s2", FrameDropReason::DecodeLate);
+
+        let s1 = monitor.get_frame_drops("s1");
+        assert_eq!(s1.queue_full, 2);
+        assert_eq!(s1.congestion, 1);
+        assert_eq!(s1.total(), 3);
+        assert_eq!(s1.dominant_reason(), Some(FrameDropReason::QueueFull));
+        assert_eq!(monitor.get_frame_drops_by_session().len(), 2);
+
+        let metrics = monitor.collect_metrics().await;
+        assert_eq!(metrics.frame_drops.total(), 4);
+        assert_eq!(metrics.frame_drops.decode_late, 1);
+
+        monitor.end_session("s1");
+        assert_eq!(monitor.get_frame_drops("s1"), FrameDropBreakdown::default());
+        assert_eq!(
+            monitor.get_frame_drops("s2").dominant_reason(),
+            Some(FrameDropReason::DecodeLate)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_memory_budget_shared_by_pool_and_frames() {
+        use crate::memory_budget::{MemoryBudgetConfig, MemoryPressure};
+
+        let budget = Arc::new(MemoryBudget::new(MemoryBudgetConfig {
+            global_limit: 8192,
+            quotas: HashMap::from([(MemorySubsystem::FrameBuffers, 6144)]),
+        }));
+        let pool = Arc::new(BufferPool::new(1024, 8).with_memory_budget(budget.clone()));
+        let frames = Arc::new(FrameBufferManager::new(10).with_memory_budget(budget.clone()));
+        let frame = |id| FrameBuffer {
+            id,
+            timestamp: id,
+            data: vec![0u8; 2048],
+            width: 32,
+            height: 16,
+            format: FrameFormat::RGBA,
+        };
+
+        let buffers: Vec<_> = futures::future::join_all((0..4).map(|_| pool.acquire())).await;
+        for buffer in buffers {
+            pool.release(buffer).await;
+        }
+        assert_eq!(budget.used(MemorySubsystem::BufferPool), 4096);
+
+        // Two frames fit beside the idle pool; the third reclaims pool buffers
+        for id in 0..3 {
+            frames.push_frame(frame(id)).await;
+        }
+        assert_eq!(budget.used(MemorySubsystem::FrameBuffers), 6144);
+        assert_eq!(budget.used(MemorySubsystem::BufferPool), 2048);
+        assert_eq!(budget.pressure(), MemoryPressure::Critical);
+
+        // Over its own quota the frame queue drops its oldest frame instead
+        frames.push_frame(frame(3)).await;
+        let (count, bytes, dropped) = frames.stats().await;
+        assert_eq!((count, bytes, dropped), (3, 6144, 1));
+        assert_eq!(frames.pop_frame().await.unwrap().id, 1);
+        assert_eq!(budget.used(MemorySubsystem::FrameBuffers), 4096);
+
+        let monitor = PerformanceMonitor::new(
+            pool,
+            frames,
+            Arc::new(TransmissionOptimizer::new(500_000, 10_000_000, 2_000_000)),
+            Arc::new(InputOptimizer::new(16, 10)),
+        )
+        .with_memory_budget(budget);
+        let usage = monitor.collect_metrics().await.memory.budget.unwrap();
+        assert_eq!(usage.used_bytes, 6144);
+        assert_eq!(usage.reclaimed_bytes, 2048);
+        assert_eq!(usage.peak_bytes, 8192);
+    }
+
     #[tokio::test]
     async fn test_transmission_optimizer() {
         let optimizer = TransmissionOptimizer::new(500_000, 10_000_000, 4_000_000);
@@ -640,6 +1316,94 @@ mod tests {
         assert!(bitrate < 4_000_000);
     }
 
+    fn frame(frame_id: u64, qp: u8, encode_duration_us: u64) -> EncodedFrameStats {
+        EncodedFrameStats {
+            frame_id,
+            frame_type: if frame_id == 0 {
+                EncodedFrameType::Key
+            } else {
+                EncodedFrameType::Delta
+            },
+            qp,
+            size_bytes: 20_000,
+            encode_duration_us,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recent_frame_stats() {
+        let optimizer = TransmissionOptimizer::new(500_000, 10_000_000, 4_000_000);
+        for i in 0..(FRAME_STATS_WINDOW as u64 + 10) {
+            optimizer.record_frame_stats(frame(i, 30, 5_000)).await;
+        }
+
+        let recent = optimizer.recent_frame_stats(3).await;
+        assert_eq!(recent.len(), 3);
+        assert_eq!(recent[2].frame_id, FRAME_STATS_WINDOW as u64 + 9);
+
+        let stats = optimizer.get_encoder_stats().await;
+        assert_eq!(stats.frames, FRAME_STATS_WINDOW);
+        assert_eq!(stats.key_frames, 0);
+        assert!((stats.avg_encode_ms - 5.0).abs() < f64::EPSILON);
+    }
+
+    #[tokio::test]
+    async fn test_frame_stats_drive_bitrate() {
+        // High QP on a healthy link raises bitrate above target
+        let optimizer = TransmissionOptimizer::new(500_000, 10_000_000, 4_000_000);
+        optimizer.record_latency(80.0).await;
+        optimizer.record_bandwidth(8_000_000).await;
+        for i in 0..10 {
+            optimizer.record_frame_stats(frame(i, 45, 5_000)).await;
+        }
+        assert!(optimizer.adapt_bitrate().await > 4_000_000);
+
+        // Encode time over the low-latency budget lowers it
+        let optimizer = TransmissionOptimizer::new(500_000, 10_000_000, 4_000_000);
+        optimizer
+            .set_encoder_preference(EncoderPreference::LowLatency)
+            .await;
+        optimizer.record_latency(30.0).await;
+        optimizer.record_bandwidth(8_000_000).await;
+        for i in 0..10 {
+            optimizer.record_frame_stats(frame(i, 30, 12_000)).await;
+        }
+        assert!(optimizer.adapt_bitrate().await < 4_000_000);
+    }
+
+    #[tokio::test]
+    async fn test_transport_feedback_overrides_latency_thresholds() {
+        let optimizer = TransmissionOptimizer::new(500_000, 10_000_000, 4_000_000);
+        // Latency alone would cut the bitrate
+        optimizer.record_latency(200.0).await;
+        optimizer.record_bandwidth(8_000_000).await;
+        assert_eq!(optimizer.estimated_bandwidth().await, None);
+
+        for sequence in 0..10u16 {
+            optimizer.record_packet_sent(sequence, 1200).await;
+        }
+        let feedback = crate::congestion::TransportFeedback {
+            sender_ssrc: 1,
+            media_ssrc: 2,
+            feedback_count: 0,
+            packets: (0..10u16)
+                .map(|sequence| crate::congestion::PacketStatus {
+                    sequence,
+                    arrival_us: Some(i64::from(sequence) * 1_000),
+                })
+                .collect(),
+        };
+        let applied = optimizer
+            .record_transport_feedback(&feedback.encode().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(applied, 1);
+
+        let estimate = optimizer.estimated_bandwidth().await.unwrap();
+        assert!(estimate >= 4_000_000);
+        assert_eq!(optimizer.adapt_bitrate().await, estimate);
+    }
+
     #[tokio::test]
     async fn test_input_optimizer() {
         let optimizer = InputOptimizer::new(100, 16);
@@ -0,0 +1,491 @@
+//! Device Presence
+//!
+//! The address book shows whether each saved device is online. Asking the
+//! server one device at a time does not scale to a long list, so the client
+//! queries statuses in batches (`SignalingMessage::QueryStatusBatch`) and
+//! subscribes to the devices it displays; the server then pushes a
+//! `PresenceUpdate` whenever one of them comes online or goes away.
+//!
+//! `PresenceCache` remembers the last known status per device. An entry is
+//! stale once it is older than `PRESENCE_STALE_AFTER`, unless the device is
+//! watched over a live subscription, in which case silence means no change.
+//! `DeviceDirectory` is the address-book model fed by `SignalingEvent`s. Each
+//! entry also carries the user's connection preferences for that device,
+//! which `SessionManager` applies to new sessions unless overridden.
+
+use crate::decoder_capabilities::DecoderCodec;
+use crate::performance::QualityPreset;
+use crate::signaling::{DeviceStatus, SignalingEvent};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "signaling-server")]
+use crate::signaling::SignalingMessage;
+
+/// Age after which a polled status is no longer trusted
+pub const PRESENCE_STALE_AFTER: Duration = Duration::from_secs(60);
+
+/// Maximum device IDs in one batch query or subscription message
+pub const MAX_STATUS_BATCH: usize = 200;
+
+#[derive(Debug, Clone)]
+struct CachedStatus {
+    status: DeviceStatus,
+    fetched_at: Instant,
+}
+
+/// Client-side cache of device statuses
+#[derive(Debug)]
+pub struct PresenceCache {
+    entries: HashMap<String, CachedStatus>,
+    watched: HashSet<String>,
+    /// Server is currently pushing updates for `watched`
+    subscription_live: bool,
+    stale_after: Duration,
+}
+
+impl PresenceCache {
+    pub fn new(stale_after: Duration) -> Self {
+        Self {
+            entries: HashMap::new(),
+            watched: HashSet::new(),
+            subscription_live: false,
+            stale_after,
+        }
+    }
+
+    /// Store a status; returns `true` if online state or last-seen changed
+    pub fn update(&mut self, status: DeviceStatus) -> bool {
+        let changed = self.entries.get(&status.device_id).is_none_or(|cached| {
+            cached.status.online != status.online || cached.status.last_seen != status.last_seen
+        });
+        self.entries.insert(
+            status.device_id.clone(),
+            CachedStatus {
+                status,
+                fetched_at: Instant::now(),
+            },
+        );
+        changed
+    }
+
+    /// Last known status, fresh or not
+    pub fn get(&self, device_id: &str) -> Option<&DeviceStatus> {
+        self.entries.get(device_id).map(|cached| &cached.status)
+    }
+
+    /// Last known status if it can still be trusted
+    pub fn fresh(&self, device_id: &str) -> Option<&DeviceStatus> {
+        (!self.is_stale(device_id)).then(|| self.get(device_id))?
+    }
+
+    /// Whether the status is unknown or too old to trust
+    pub fn is_stale(&self, device_id: &str) -> bool {
+        match self.entries.get(device_id) {
+            None => true,
+            Some(_) if self.subscription_live && self.watched.contains(device_id) => false,
+            Some(cached) => cached.fetched_at.elapsed() >= self.stale_after,
+        }
+    }
+
+    /// The subset of `device_ids` that needs a query
+    pub fn stale_ids(&self, device_ids: &[String]) -> Vec<String> {
+        device_ids
+            .iter()
+            .filter(|id| self.is_stale(id))
+            .cloned()
+            .collect()
+    }
+
+    /// Add devices to the watch list, returning the ones not watched before
+    pub fn watch(&mut self, device_ids: &[String]) -> Vec<String> {
+        device_ids
+            .iter()
+            .filter(|id| self.watched.insert((*id).clone()))
+            .cloned()
+            .collect()
+    }
+
+    pub fn unwatch(&mut self, device_ids: &[String]) {
+        for id in device_ids {
+            self.watched.remove(id);
+        }
+    }
+
+    /// Watched devices, sorted
+    pub fn watched(&self) -> Vec<String> {
+        let mut watched: Vec<String> = self.watched.iter().cloned().collect();
+        watched.sort();
+        watched
+    }
+
+    /// Record whether the server subscription is active
+    ///
+    /// Cleared on disconnect so watched entries age like polled ones until
+    /// the subscription is renewed.
+    pub fn set_subscription_live(&mut self, live: bool) {
+        if self.subscription_live && !live {
+            // Pushes stopped now; start the staleness clock from here
+            let now = Instant::now();
+            for id in &self.watched {
+                if let Some(cached) = self.entries.get_mut(id) {
+                    cached.fetched_at = now;
+                }
+            }
+        }
+        self.subscription_live = live;
+    }
+}
+
+impl Default for PresenceCache {
+    fn default() -> Self {
+        Self::new(PRESENCE_STALE_AFTER)
+    }
+}
+
+/// Address-book entry with its presence
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DirectoryEntry {
+    pub device_id: String,
+    pub name: String,
+    /// `None` until the first status arrives, and while signaling is down
+    pub online: Option<bool>,
+    pub last_seen: Option<String>,
+    #[serde(default)]
+    pub preferences: DevicePreferences,
+}
+
+/// Connection defaults for one device
+///
+/// Unset fields fall back to the global defaults. The same record carries
+/// per-session overrides in `SessionOptions`, where set fields win.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DevicePreferences {
+    /// Codecs to try first, most preferred first
+    pub codec_priority: Vec<DecoderCodec>,
+    pub quality_preset: Option<QualityPreset>,
+    /// Never connect directly; always go through TURN
+    pub relay_only: Option<bool>,
+    /// TURN region to use, e.g. "eu-west"
+    pub relay_region: Option<String>,
+    pub audio_enabled: Option<bool>,
+    pub max_bandwidth_kbps: Option<u32>,
+}
+
+impl DevicePreferences {
+    /// These preferences with every field set in `overrides` replaced
+    pub fn overridden_by(&self, overrides: &DevicePreferences) -> DevicePreferences {
+        DevicePreferences {
+            codec_priority: if overrides.codec_priority.is_empty() {
+                self.codec_priority.clone()
+            } else {
+                overrides.codec_priority.clone()
+            },
+            quality_preset: overrides.quality_preset.or(self.quality_preset),
+            relay_only: overrides.relay_only.or(self.relay_only),
+            relay_region: overrides
+                .relay_region
+                .clone()
+                .or_else(|| self.relay_region.clone()),
+            audio_enabled: overrides.audio_enabled.or(self.audio_enabled),
+            max_bandwidth_kbps: overrides.max_bandwidth_kbps.or(self.max_bandwidth_kbps),
+        }
+    }
+
+    pub fn is_relay_only(&self) -> bool {
+        self.relay_only.unwrap_or(false)
+    }
+
+    pub fn is_audio_enabled(&self) -> bool {
+        self.audio_enabled.unwrap_or(true)
+    }
+
+    /// `RTCConfiguration::ice_transport_policy` honouring `relay_only`
+    pub fn ice_transport_policy(&self) -> &'static str {
+        if self.is_relay_only() {
+            "relay"
+        } else {
+            "all"
+        }
+    }
+}
+
+/// Saved devices and their presence, kept current from signaling events
+#[derive(Debug, Default)]
+pub struct DeviceDirectory {
+    entries: BTreeMap<String, DirectoryEntry>,
+}
+
+impl DeviceDirectory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, device_id: &str, name: &str) {
+        self.entries
+            .entry(device_id.to_string())
+            .and_modify(|entry| entry.name = name.to_string())
+            .or_insert_with(|| DirectoryEntry {
+                device_id: device_id.to_string(),
+                name: name.to_string(),
+                online: None,
+                last_seen: None,
+                preferences: DevicePreferences::default(),
+            });
+    }
+
+    /// Replace a device's connection preferences; false if it is not listed
+    pub fn set_preferences(&mut self, device_id: &str, preferences: DevicePreferences) -> bool {
+        match self.entries.get_mut(device_id) {
+            Some(entry) => {
+                entry.preferences = preferences;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn preferences(&self, device_id: &str) -> Option<&DevicePreferences> {
+        self.entries.get(device_id).map(|entry| &entry.preferences)
+    }
+
+    /// Restore a saved entry; its presence starts out unknown
+    pub fn insert(&mut self, entry: DirectoryEntry) {
+        self.entries.insert(
+            entry.device_id.clone(),
+            DirectoryEntry {
+                online: None,
+                ..entry
+            },
+        );
+    }
+
+    pub fn remove(&mut self, device_id: &str) -> bool {
+        self.entries.remove(device_id).is_some()
+    }
+
+    pub fn get(&self, device_id: &str) -> Option<&DirectoryEntry> {
+        self.entries.get(device_id)
+    }
+
+    /// Entries sorted by device ID
+    pub fn entries(&self) -> Vec<DirectoryEntry> {
+        self.entries.values().cloned().collect()
+    }
+
+    /// IDs to query and subscribe to
+    pub fn device_ids(&self) -> Vec<String> {
+        self.entries.keys().cloned().collect()
+    }
+
+    /// Apply a signaling event, returning the entries it changed
+    pub fn apply(&mut self, event: &SignalingEvent) -> Vec<DirectoryEntry> {
+        match event {
+            SignalingEvent::PresenceChanged(status) => {
+                self.apply_status(status).into_iter().collect()
+            }
+            SignalingEvent::Disconnected => self
+                .entries
+                .values_mut()
+                .filter(|entry| entry.online.is_some())
+                .map(|entry| {
+                    entry.online = None;
+                    entry.clone()
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn apply_status(&mut self, status: &DeviceStatus) -> Option<DirectoryEntry> {
+        let entry = self.entries.get_mut(&status.device_id)?;
+        let last_seen = (!status.last_seen.is_empty()).then(|| status.last_seen.clone());
+        if entry.online == Some(status.online) && entry.last_seen == last_seen {
+            return None;
+        }
+        entry.online = Some(status.online);
+        entry.last_seen = last_seen;
+        Some(entry.clone())
+    }
+}
+
+/// Maximum devices one client may watch
+#[cfg(feature = "signaling-server")]
+pub const MAX_WATCHED_DEVICES: usize = 1000;
+
+/// Server side: which connected clients watch which devices
+#[cfg(feature = "signaling-server")]
+#[derive(Debug, Default)]
+pub struct PresenceSubscriptions {
+    /// Watched device -> watchers
+    watchers: HashMap<String, HashSet<String>>,
+    /// Watcher -> watched devices
+    watching: HashMap<String, HashSet<String>>,
+}
+
+#[cfg(feature = "signaling-server")]
+impl PresenceSubscriptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add devices to `watcher`'s list
+    ///
+    /// Fails without changes if the list would exceed `MAX_WATCHED_DEVICES`.
+    pub fn subscribe(&mut self, watcher: &str, device_ids: &[String]) -> anyhow::Result<()> {
+        let watching = self.watching.entry(watcher.to_string()).or_default();
+        let added = device_ids
+            .iter()
+            .filter(|id| !watching.contains(*id))
+            .collect::<HashSet<_>>()
+            .len();
+        if watching.len() + added > MAX_WATCHED_DEVICES {
+            return Err(anyhow::anyhow!(
+                "{} may watch at most {} devices",
+                watcher,
+                MAX_WATCHED_DEVICES
+            ));
+        }
+        for id in device_ids {
+            watching.insert(id.clone());
+            self.watchers
+                .entry(id.clone())
+                .or_default()
+                .insert(watcher.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, watcher: &str, device_ids: &[String]) {
+        for id in device_ids {
+            if let Some(watching) = self.watching.get_mut(watcher) {
+                watching.remove(id);
+            }
+            self.remove_watcher_of(id, watcher);
+        }
+    }
+
+    /// Drop every subscription of a client that disconnected
+    pub fn remove_watcher(&mut self, watcher: &str) {
+        for id in self.watching.remove(watcher).unwrap_or_default() {
+            self.remove_watcher_of(&id, watcher);
+        }
+    }
+
+    fn remove_watcher_of(&mut self, device_id: &str, watcher: &str) {
+        if let Some(watchers) = self.watchers.get_mut(device_id) {
+            watchers.remove(watcher);
+            if watchers.is_empty() {
+                self.watchers.remove(device_id);
+            }
+        }
+    }
+
+    /// `PresenceUpdate` messages to push when `status` changes, per watcher
+    pub fn fan_out(&self, status: &DeviceStatus) -> Vec<(String, SignalingMessage)> {
+        let mut watchers: Vec<&String> = self
+            .watchers
+            .get(&status.device_id)
+            .map(|watchers| watchers.iter().collect())
+            .unwrap_or_default();
+        watchers.sort();
+        watchers
+            .into_iter()
+            .map(|watcher| {
+                (
+                    watcher.clone(),
+                    SignalingMessage::PresenceUpdate(status.clone()),
+                )
+            })
+            .collect()
+    }
+
+    pub fn watcher_count(&self, device_id: &str) -> usize {
+        self.watchers.get(device_id).map_or(0, HashSet::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(device_id: &str, online: bool) -> DeviceStatus {
+        DeviceStatus {
+            device_id: device_id.to_string(),
+            online,
+            last_seen: "2026-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_cache_staleness_and_subscription() {
+        let ids = vec!["a".to_string(), "b".to_string()];
+        let mut cache = PresenceCache::new(Duration::ZERO);
+        assert_eq!(cache.stale_ids(&ids), ids);
+
+        assert!(cache.update(status("a", true)));
+        assert!(!cache.update(status("a", true)));
+        // Polled entries age out immediately with a zero window
+        assert!(cache.is_stale("a"));
+        assert!(cache.fresh("a").is_none());
+        assert!(cache.get("a").unwrap().online);
+
+        assert_eq!(cache.watch(&ids), ids);
+        assert!(cache.watch(&ids).is_empty());
+        cache.set_subscription_live(true);
+        assert!(cache.fresh("a").is_some());
+        // Watched but never reported is still unknown
+        assert_eq!(cache.stale_ids(&ids), vec!["b".to_string()]);
+
+        cache.set_subscription_live(false);
+        assert!(cache.is_stale("a"));
+    }
+
+    #[test]
+    fn test_directory_consumes_presence_events() {
+        let mut directory = DeviceDirectory::new();
+        directory.add("office", "Office PC");
+        directory.add("laptop", "Laptop");
+
+        let changed = directory.apply(&SignalingEvent::PresenceChanged(status("office", true)));
+        assert_eq!(changed.len(), 1);
+        assert_eq!(changed[0].online, Some(true));
+        assert!(directory
+            .apply(&SignalingEvent::PresenceChanged(status("office", true)))
+            .is_empty());
+        assert!(directory
+            .apply(&SignalingEvent::PresenceChanged(status("stranger", true)))
+            .is_empty());
+
+        let changed = directory.apply(&SignalingEvent::Disconnected);
+        assert_eq!(changed.len(), 1);
+        assert_eq!(directory.get("office").unwrap().online, None);
+        assert_eq!(directory.device_ids(), vec!["laptop", "office"]);
+    }
+
+    #[cfg(feature = "signaling-server")]
+    #[test]
+    fn test_server_fans_out_to_watchers() {
+        let mut subscriptions = PresenceSubscriptions::new();
+        let office = vec!["office".to_string()];
+        subscriptions.subscribe("alice", &office).unwrap();
+        subscriptions.subscribe("bob", &office).unwrap();
+
+        let pushes = subscriptions.fan_out(&status("office", false));
+        assert_eq!(pushes.len(), 2);
+        assert_eq!(pushes[0].0, "alice");
+        assert!(matches!(
+            &pushes[1].1,
+            SignalingMessage::PresenceUpdate(s) if s.device_id == "office" && !s.online
+        ));
+
+        subscriptions.remove_watcher("alice");
+        subscriptions.unsubscribe("bob", &office);
+        assert_eq!(subscriptions.watcher_count("office"), 0);
+
+        let too_many: Vec<String> = (0..=MAX_WATCHED_DEVICES).map(|i| i.to_string()).collect();
+        assert!(subscriptions.subscribe("carol", &too_many).is_err());
+    }
+}
@@ -0,0 +1,1241 @@
+//! Host Privacy Mode
+//!
+//! While a remote session works on the host, privacy mode keeps the people in
+//! front of it from interfering or watching: the physical keyboard and mouse
+//! are blocked and the physical screen can be blanked, while injected remote
+//! input and screen capture carry on. Sessions engage it through
+//! `SessionManager::set_privacy_mode`; the host stays private while any
+//! session holds it, with the union of what those sessions asked for.
+//!
+//! Locking out the local user also locks out whoever would notice something
+//! going wrong, so it never outlives its session: ending the session or
+//! revoking `PrivacyMode` releases it, and on Windows Ctrl+Alt+Del always
+//! gets through.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// What privacy mode hides from the host's local user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrivacyOptions {
+    /// Ignore the physical keyboard and mouse
+    pub block_input: bool,
+    /// Show a black physical screen; capture still sees the desktop
+    pub blank_screen: bool,
+}
+
+impl PrivacyOptions {
+    pub fn is_empty(&self) -> bool {
+        !self.block_input && !self.blank_screen
+    }
+
+    fn union(self, other: Self) -> Self {
+        Self {
+            block_input: self.block_input || other.block_input,
+            blank_screen: self.blank_screen || other.blank_screen,
+        }
+    }
+}
+
+/// Platform local input blocking and screen blanking
+pub trait PrivacyBackend: Send + Sync {
+    fn block_local_input(&self, blocked: bool) -> Result<()>;
+    fn blank_screen(&self, blanked: bool) -> Result<()>;
+}
+
+/// Default backend using the OS input and display APIs
+///
+/// Remote input is injected by this process and keeps working: Windows drops
+/// only uninjected events in low-level hooks, macOS drops events from other
+/// processes in an event tap, and X11 grabs the physical devices but not the
+/// XTEST ones. The screen is blanked without touching what capture reads:
+/// Windows covers it with a black window excluded from capture, macOS and
+/// X11 zero the display gamma, which is applied after the framebuffer.
+#[derive(Default)]
+pub struct PlatformPrivacyBackend {
+    input_block: Mutex<Option<InputBlock>>,
+    screen_blank: Mutex<Option<ScreenBlank>>,
+}
+
+impl PrivacyBackend for PlatformPrivacyBackend {
+    fn block_local_input(&self, blocked: bool) -> Result<()> {
+        let mut input_block = self
+            .input_block
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Input block lock poisoned"))?;
+        if !blocked {
+            // Dropping the block releases the input
+            input_block.take();
+        } else if input_block.is_none() {
+            *input_block = Some(InputBlock::engage()?);
+        }
+        Ok(())
+    }
+
+    fn blank_screen(&self, blanked: bool) -> Result<()> {
+        let mut screen_blank = self
+            .screen_blank
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Screen blank lock poisoned"))?;
+        if !blanked {
+            screen_blank.take();
+        } else if screen_blank.is_none() {
+            *screen_blank = Some(ScreenBlank::engage()?);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "windows")]
+use win32::{InputBlock, ScreenBlank};
+
+#[cfg(target_os = "macos")]
+use quartz::{InputBlock, ScreenBlank};
+
+#[cfg(all(target_os = "linux", feature = "capture"))]
+use x11::{InputBlock, ScreenBlank};
+
+#[cfg(not(any(
+    target_os = "windows",
+    target_os = "macos",
+    all(target_os = "linux", feature = "capture")
+)))]
+use unsupported::{InputBlock, ScreenBlank};
+
+#[cfg(target_os = "windows")]
+mod win32 {
+    use anyhow::Result;
+    use std::os::raw::c_void;
+    use std::sync::mpsc;
+    use std::thread::JoinHandle;
+
+    type Hwnd = isize;
+    type Hhook = isize;
+    type Hinstance = isize;
+    type Bool = i32;
+    type HookProc = unsafe extern "system" fn(i32, usize, isize) -> isize;
+    type WndProc = unsafe extern "system" fn(Hwnd, u32, usize, isize) -> isize;
+
+    #[repr(C)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    #[repr(C)]
+    struct Msg {
+        hwnd: Hwnd,
+        message: u32,
+        wparam: usize,
+        lparam: isize,
+        time: u32,
+        pt: Point,
+    }
+
+    #[repr(C)]
+    struct KbdLlHookStruct {
+        vk_code: u32,
+        scan_code: u32,
+        flags: u32,
+        time: u32,
+        extra_info: usize,
+    }
+
+    #[repr(C)]
+    struct MsLlHookStruct {
+        pt: Point,
+        mouse_data: u32,
+        flags: u32,
+        time: u32,
+        extra_info: usize,
+    }
+
+    #[repr(C)]
+    struct WndClassExW {
+        size: u32,
+        style: u32,
+        wnd_proc: WndProc,
+        class_extra: i32,
+        window_extra: i32,
+        instance: Hinstance,
+        icon: isize,
+        cursor: isize,
+        background: isize,
+        menu_name: *const u16,
+        class_name: *const u16,
+        icon_small: isize,
+    }
+
+    const WH_KEYBOARD_LL: i32 = 13;
+    const WH_MOUSE_LL: i32 = 14;
+    const HC_ACTION: i32 = 0;
+    const LLKHF_INJECTED: u32 = 0x10;
+    const LLMHF_INJECTED: u32 = 0x01;
+    const WM_QUIT: u32 = 0x0012;
+    const WM_DISPLAYCHANGE: u32 = 0x007E;
+    const PM_NOREMOVE: u32 = 0;
+    const WS_POPUP: u32 = 0x8000_0000;
+    const WS_EX_TOPMOST: u32 = 0x0000_0008;
+    const WS_EX_TRANSPARENT: u32 = 0x0000_0020;
+    const WS_EX_TOOLWINDOW: u32 = 0x0000_0080;
+    const WS_EX_LAYERED: u32 = 0x0008_0000;
+    const WS_EX_NOACTIVATE: u32 = 0x0800_0000;
+    const LWA_ALPHA: u32 = 0x2;
+    /// Shown on monitors, left out of every capture API including BitBlt
+    const WDA_EXCLUDEFROMCAPTURE: u32 = 0x11;
+    const HWND_TOPMOST: Hwnd = -1;
+    const SWP_NOACTIVATE: u32 = 0x0010;
+    const SWP_SHOWWINDOW: u32 = 0x0040;
+    const BLACK_BRUSH: i32 = 4;
+    const SM_XVIRTUALSCREEN: i32 = 76;
+    const SM_YVIRTUALSCREEN: i32 = 77;
+    const SM_CXVIRTUALSCREEN: i32 = 78;
+    const SM_CYVIRTUALSCREEN: i32 = 79;
+
+    extern "system" {
+        fn GetCurrentThreadId() -> u32;
+        fn GetModuleHandleW(name: *const u16) -> Hinstance;
+        fn GetLastError() -> u32;
+        fn SetWindowsHookExW(id: i32, hook: HookProc, module: Hinstance, thread_id: u32) -> Hhook;
+        fn UnhookWindowsHookEx(hook: Hhook) -> Bool;
+        fn CallNextHookEx(hook: Hhook, code: i32, wparam: usize, lparam: isize) -> isize;
+        fn PeekMessageW(msg: *mut Msg, hwnd: Hwnd, min: u32, max: u32, remove: u32) -> Bool;
+        fn GetMessageW(msg: *mut Msg, hwnd: Hwnd, min: u32, max: u32) -> Bool;
+        fn TranslateMessage(msg: *const Msg) -> Bool;
+        fn DispatchMessageW(msg: *const Msg) -> isize;
+        fn PostThreadMessageW(thread_id: u32, msg: u32, wparam: usize, lparam: isize) -> Bool;
+        fn RegisterClassExW(class: *const WndClassExW) -> u16;
+        fn UnregisterClassW(name: *const u16, instance: Hinstance) -> Bool;
+        fn DefWindowProcW(hwnd: Hwnd, msg: u32, wparam: usize, lparam: isize) -> isize;
+        fn CreateWindowExW(
+            ex_style: u32,
+            class_name: *const u16,
+            title: *const u16,
+            style: u32,
+            x: i32,
+            y: i32,
+            width: i32,
+            height: i32,
+            parent: Hwnd,
+            menu: isize,
+            instance: Hinstance,
+            param: *mut c_void,
+        ) -> Hwnd;
+        fn DestroyWindow(hwnd: Hwnd) -> Bool;
+        fn SetWindowPos(
+            hwnd: Hwnd,
+            insert_after: Hwnd,
+            x: i32,
+            y: i32,
+            width: i32,
+            height: i32,
+            flags: u32,
+        ) -> Bool;
+        fn SetLayeredWindowAttributes(hwnd: Hwnd, key: u32, alpha: u8, flags: u32) -> Bool;
+        fn SetWindowDisplayAffinity(hwnd: Hwnd, affinity: u32) -> Bool;
+        fn GetSystemMetrics(index: i32) -> i32;
+        fn GetStockObject(index: i32) -> isize;
+    }
+
+    /// Thread pumping messages for the hooks or windows it set up
+    ///
+    /// Low-level hooks run on the installing thread's message loop and
+    /// windows belong to their creating thread, so both need one of their own.
+    struct MessageThread {
+        thread_id: u32,
+        handle: Option<JoinHandle<()>>,
+    }
+
+    type Teardown = Box<dyn FnOnce()>;
+
+    impl MessageThread {
+        /// Run `setup` on a new thread and pump its messages until dropped,
+        /// then run the teardown it returned on that same thread
+        fn spawn<F>(name: &str, setup: F) -> Result<Self>
+        where
+            F: FnOnce() -> Result<Teardown> + Send + 'static,
+        {
+            let (ready_tx, ready_rx) = mpsc::channel();
+            let handle = std::thread::Builder::new()
+                .name(name.to_string())
+                .spawn(move || {
+                    // SAFETY: MSG is plain data; peeking creates this
+                    // thread's message queue so the quit message can be
+                    // posted as soon as the ID is known
+                    let mut msg: Msg = unsafe { std::mem::zeroed() };
+                    let thread_id = unsafe {
+                        PeekMessageW(&mut msg, 0, 0, 0, PM_NOREMOVE);
+                        GetCurrentThreadId()
+                    };
+                    let teardown = match setup() {
+                        Ok(teardown) => teardown,
+                        Err(e) => {
+                            let _ = ready_tx.send(Err(e));
+                            return;
+                        }
+                    };
+                    let _ = ready_tx.send(Ok(thread_id));
+                    // SAFETY: `msg` is a valid MSG owned by this thread
+                    unsafe {
+                        while GetMessageW(&mut msg, 0, 0, 0) > 0 {
+                            TranslateMessage(&msg);
+                            DispatchMessageW(&msg);
+                        }
+                    }
+                    teardown();
+                })?;
+            match ready_rx.recv() {
+                Ok(Ok(thread_id)) => Ok(Self {
+                    thread_id,
+                    handle: Some(handle),
+                }),
+                Ok(Err(e)) => {
+                    let _ = handle.join();
+                    Err(e)
+                }
+                Err(_) => {
+                    let _ = handle.join();
+                    Err(anyhow::anyhow!("{} thread exited during setup", name))
+                }
+            }
+        }
+    }
+
+    impl Drop for MessageThread {
+        fn drop(&mut self) {
+            // SAFETY: posting a message has no memory effects on this side
+            unsafe {
+                PostThreadMessageW(self.thread_id, WM_QUIT, 0, 0);
+            }
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    unsafe extern "system" fn keyboard_hook(code: i32, wparam: usize, lparam: isize) -> isize {
+        if code == HC_ACTION && (*(lparam as *const KbdLlHookStruct)).flags & LLKHF_INJECTED == 0 {
+            return 1;
+        }
+        CallNextHookEx(0, code, wparam, lparam)
+    }
+
+    unsafe extern "system" fn mouse_hook(code: i32, wparam: usize, lparam: isize) -> isize {
+        if code == HC_ACTION && (*(lparam as *const MsLlHookStruct)).flags & LLMHF_INJECTED == 0 {
+            return 1;
+        }
+        CallNextHookEx(0, code, wparam, lparam)
+    }
+
+    /// Physical keyboard and mouse input swallowed by low-level hooks
+    ///
+    /// `SendInput` marks its events as injected, so remote input passes.
+    /// The secure attention sequence never reaches hooks: Ctrl+Alt+Del
+    /// always gets through.
+    pub(super) struct InputBlock {
+        _thread: MessageThread,
+    }
+
+    impl InputBlock {
+        pub(super) fn engage() -> Result<Self> {
+            let thread = MessageThread::spawn("privacy-input", || {
+                // SAFETY: the hooks are removed on this thread by the
+                // teardown, before it exits
+                unsafe {
+                    let module = GetModuleHandleW(std::ptr::null());
+                    let keyboard = SetWindowsHookExW(WH_KEYBOARD_LL, keyboard_hook, module, 0);
+                    if keyboard == 0 {
+                        return Err(anyhow::anyhow!(
+                            "Cannot hook the keyboard: error {}",
+                            GetLastError()
+                        ));
+                    }
+                    let mouse = SetWindowsHookExW(WH_MOUSE_LL, mouse_hook, module, 0);
+                    if mouse == 0 {
+                        let error = GetLastError();
+                        UnhookWindowsHookEx(keyboard);
+                        return Err(anyhow::anyhow!("Cannot hook the mouse: error {}", error));
+                    }
+                    Ok(Box::new(move || {
+                        UnhookWindowsHookEx(mouse);
+                        UnhookWindowsHookEx(keyboard);
+                    }) as Teardown)
+                }
+            })?;
+            Ok(Self { _thread: thread })
+        }
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    /// Show the window over every monitor, on top of everything else
+    unsafe fn cover_virtual_screen(hwnd: Hwnd) {
+        SetWindowPos(
+            hwnd,
+            HWND_TOPMOST,
+            GetSystemMetrics(SM_XVIRTUALSCREEN),
+            GetSystemMetrics(SM_YVIRTUALSCREEN),
+            GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            SWP_NOACTIVATE | SWP_SHOWWINDOW,
+        );
+    }
+
+    unsafe extern "system" fn blank_window_proc(
+        hwnd: Hwnd,
+        msg: u32,
+        wparam: usize,
+        lparam: isize,
+    ) -> isize {
+        if msg == WM_DISPLAYCHANGE {
+            // Monitors came, went or changed resolution
+            cover_virtual_screen(hwnd);
+        }
+        DefWindowProcW(hwnd, msg, wparam, lparam)
+    }
+
+    /// Opaque black window over the whole virtual screen
+    ///
+    /// It is layered and click-through, so injected clicks reach the windows
+    /// underneath, and excluded from capture, so the viewer still sees them.
+    pub(super) struct ScreenBlank {
+        _thread: MessageThread,
+    }
+
+    impl ScreenBlank {
+        pub(super) fn engage() -> Result<Self> {
+            let thread = MessageThread::spawn("privacy-screen", || {
+                let class_name = wide("CecDeskPrivacyScreen");
+                // SAFETY: the class name outlives the class, which the
+                // teardown unregisters after destroying its window
+                unsafe {
+                    let instance = GetModuleHandleW(std::ptr::null());
+                    let class = WndClassExW {
+                        size: std::mem::size_of::<WndClassExW>() as u32,
+                        style: 0,
+                        wnd_proc: blank_window_proc,
+                        class_extra: 0,
+                        window_extra: 0,
+                        instance,
+                        icon: 0,
+                        cursor: 0,
+                        background: GetStockObject(BLACK_BRUSH),
+                        menu_name: std::ptr::null(),
+                        class_name: class_name.as_ptr(),
+                        icon_small: 0,
+                    };
+                    if RegisterClassExW(&class) == 0 {
+                        return Err(anyhow::anyhow!(
+                            "Cannot register the privacy screen window class: error {}",
+                            GetLastError()
+                        ));
+                    }
+                    let hwnd = CreateWindowExW(
+                        WS_EX_TOPMOST
+                            | WS_EX_TOOLWINDOW
+                            | WS_EX_NOACTIVATE
+                            | WS_EX_LAYERED
+                            | WS_EX_TRANSPARENT,
+                        class_name.as_ptr(),
+                        std::ptr::null(),
+                        WS_POPUP,
+                        0,
+                        0,
+                        0,
+                        0,
+                        0,
+                        0,
+                        instance,
+                        std::ptr::null_mut(),
+                    );
+                    if hwnd == 0 {
+                        let error = GetLastError();
+                        UnregisterClassW(class_name.as_ptr(), instance);
+                        return Err(anyhow::anyhow!(
+                            "Cannot create the privacy screen window: error {}",
+                            error
+                        ));
+                    }
+                    // Exclusion needs Windows 10 2004; without it the viewer
+                    // would see the black window too
+                    if SetWindowDisplayAffinity(hwnd, WDA_EXCLUDEFROMCAPTURE) == 0 {
+                        let error = GetLastError();
+                        DestroyWindow(hwnd);
+                        UnregisterClassW(class_name.as_ptr(), instance);
+                        return Err(anyhow::anyhow!(
+                            "Cannot exclude the privacy screen from capture: error {}",
+                            error
+                        ));
+                    }
+                    SetLayeredWindowAttributes(hwnd, 0, 255, LWA_ALPHA);
+                    cover_virtual_screen(hwnd);
+                    Ok(Box::new(move || {
+                        DestroyWindow(hwnd);
+                        UnregisterClassW(class_name.as_ptr(), instance);
+                    }) as Teardown)
+                }
+            })?;
+            Ok(Self { _thread: thread })
+        }
+    }
+}
+#[cfg(target_os = "macos")]
+mod quartz {
+    use anyhow::Result;
+    use std::os::raw::c_void;
+    use std::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    type CfTypeRef = *const c_void;
+    type CfStringRef = *const c_void;
+    type CfMachPortRef = *mut c_void;
+    type CfRunLoopRef = *mut c_void;
+    type CfRunLoopSourceRef = *mut c_void;
+    type CgEventRef = *mut c_void;
+    type CgEventTapCallBack =
+        unsafe extern "C" fn(*mut c_void, u32, CgEventRef, *mut c_void) -> CgEventRef;
+
+    const CG_HID_EVENT_TAP: u32 = 0;
+    const CG_HEAD_INSERT_EVENT_TAP: u32 = 0;
+    const CG_EVENT_TAP_OPTION_DEFAULT: u32 = 0;
+    const CG_EVENT_MASK_ALL: u64 = !0;
+    const CG_EVENT_TAP_DISABLED_BY_TIMEOUT: u32 = 0xFFFF_FFFE;
+    const CG_EVENT_TAP_DISABLED_BY_USER_INPUT: u32 = 0xFFFF_FFFF;
+    const CG_EVENT_SOURCE_UNIX_PROCESS_ID: u32 = 41;
+    const MAX_DISPLAYS: u32 = 32;
+    /// How long the tap thread waits on its run loop before checking for stop
+    const RUN_LOOP_SLICE: Duration = Duration::from_millis(250);
+
+    extern "C" {
+        static kCFRunLoopDefaultMode: CfStringRef;
+        fn CGEventTapCreate(
+            tap: u32,
+            place: u32,
+            options: u32,
+            events_of_interest: u64,
+            callback: CgEventTapCallBack,
+            user_info: *mut c_void,
+        ) -> CfMachPortRef;
+        fn CGEventTapEnable(tap: CfMachPortRef, enable: bool);
+        fn CGEventGetIntegerValueField(event: CgEventRef, field: u32) -> i64;
+        fn CFMachPortCreateRunLoopSource(
+            allocator: CfTypeRef,
+            port: CfMachPortRef,
+            order: isize,
+        ) -> CfRunLoopSourceRef;
+        fn CFMachPortInvalidate(port: CfMachPortRef);
+        fn CFRunLoopGetCurrent() -> CfRunLoopRef;
+        fn CFRunLoopAddSource(
+            run_loop: CfRunLoopRef,
+            source: CfRunLoopSourceRef,
+            mode: CfStringRef,
+        );
+        fn CFRunLoopRunInMode(mode: CfStringRef, seconds: f64, return_after_source: u8) -> i32;
+        fn CFRelease(cf: *const c_void);
+        fn CGGetActiveDisplayList(max: u32, displays: *mut u32, count: *mut u32) -> i32;
+        fn CGSetDisplayTransferByTable(
+            display: u32,
+            size: u32,
+            red: *const f32,
+            green: *const f32,
+            blue: *const f32,
+        ) -> i32;
+        fn CGDisplayRestoreColorSyncSettings();
+    }
+
+    /// Drop every event not posted by this process
+    ///
+    /// `user_info` points at the tap itself, which the system disables when
+    /// a callback is slow or the user presses the secure input keys.
+    unsafe extern "C" fn drop_foreign_events(
+        _proxy: *mut c_void,
+        kind: u32,
+        event: CgEventRef,
+        user_info: *mut c_void,
+    ) -> CgEventRef {
+        if kind == CG_EVENT_TAP_DISABLED_BY_TIMEOUT || kind == CG_EVENT_TAP_DISABLED_BY_USER_INPUT {
+            let tap = (*(user_info as *const AtomicPtr<c_void>)).load(Ordering::SeqCst);
+            if !tap.is_null() {
+                CGEventTapEnable(tap, true);
+            }
+            return event;
+        }
+        // Hardware events carry process ID 0
+        if CGEventGetIntegerValueField(event, CG_EVENT_SOURCE_UNIX_PROCESS_ID)
+            == std::process::id() as i64
+        {
+            event
+        } else {
+            std::ptr::null_mut()
+        }
+    }
+
+    /// Physical input swallowed by an event tap at the HID level
+    ///
+    /// Needs the Accessibility permission. Events this process posts carry
+    /// its process ID and pass, so remote input keeps working.
+    pub(super) struct InputBlock {
+        stop: Arc<AtomicBool>,
+        handle: Option<JoinHandle<()>>,
+    }
+
+    impl InputBlock {
+        pub(super) fn engage() -> Result<Self> {
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread_stop = stop.clone();
+            let (ready_tx, ready_rx) = mpsc::channel();
+            let handle = std::thread::Builder::new()
+                .name("privacy-input".to_string())
+                .spawn(move || {
+                    let tap_slot = Box::new(AtomicPtr::new(std::ptr::null_mut()));
+                    // SAFETY: `tap_slot` outlives the tap, which is
+                    // invalidated and released before this thread exits
+                    unsafe {
+                        let tap = CGEventTapCreate(
+                            CG_HID_EVENT_TAP,
+                            CG_HEAD_INSERT_EVENT_TAP,
+                            CG_EVENT_TAP_OPTION_DEFAULT,
+                            CG_EVENT_MASK_ALL,
+                            drop_foreign_events,
+                            &*tap_slot as *const AtomicPtr<c_void> as *mut c_void,
+                        );
+                        if tap.is_null() {
+                            let _ = ready_tx.send(Err(anyhow::anyhow!(
+                                "Blocking local input needs the Accessibility permission"
+                            )));
+                            return;
+                        }
+                        tap_slot.store(tap, Ordering::SeqCst);
+                        let source = CFMachPortCreateRunLoopSource(std::ptr::null(), tap, 0);
+                        CFRunLoopAddSource(CFRunLoopGetCurrent(), source, kCFRunLoopDefaultMode);
+                        CGEventTapEnable(tap, true);
+                        let _ = ready_tx.send(Ok(()));
+                        while !thread_stop.load(Ordering::SeqCst) {
+                            CFRunLoopRunInMode(
+                                kCFRunLoopDefaultMode,
+                                RUN_LOOP_SLICE.as_secs_f64(),
+                                0,
+                            );
+                        }
+                        CGEventTapEnable(tap, false);
+                        CFMachPortInvalidate(tap);
+                        CFRelease(source);
+                        CFRelease(tap);
+                    }
+                })?;
+            match ready_rx.recv() {
+                Ok(Ok(())) => Ok(Self {
+                    stop,
+                    handle: Some(handle),
+                }),
+                Ok(Err(e)) => {
+                    let _ = handle.join();
+                    Err(e)
+                }
+                Err(_) => {
+                    let _ = handle.join();
+                    Err(anyhow::anyhow!("Input block thread exited during setup"))
+                }
+            }
+        }
+    }
+
+    impl Drop for InputBlock {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    /// Every active display's gamma table zeroed
+    ///
+    /// Screenshots are taken before gamma, so capture is unaffected, and
+    /// Quartz restores the user's color settings if this process exits.
+    pub(super) struct ScreenBlank;
+
+    impl ScreenBlank {
+        pub(super) fn engage() -> Result<Self> {
+            let mut displays = [0u32; MAX_DISPLAYS as usize];
+            let mut count = 0u32;
+            let black = [0f32; 2];
+            // SAFETY: the buffers outlive the calls and `count` is bounded by
+            // the array length passed in
+            unsafe {
+                let error = CGGetActiveDisplayList(MAX_DISPLAYS, displays.as_mut_ptr(), &mut count);
+                if error != 0 {
+                    return Err(anyhow::anyhow!("Cannot list displays: CGError {}", error));
+                }
+                for &display in &displays[..count as usize] {
+                    let error = CGSetDisplayTransferByTable(
+                        display,
+                        black.len() as u32,
+                        black.as_ptr(),
+                        black.as_ptr(),
+                        black.as_ptr(),
+                    );
+                    if error != 0 {
+                        CGDisplayRestoreColorSyncSettings();
+                        return Err(anyhow::anyhow!(
+                            "Cannot blank display {}: CGError {}",
+                            display,
+                            error
+                        ));
+                    }
+                }
+            }
+            Ok(Self)
+        }
+    }
+
+    impl Drop for ScreenBlank {
+        fn drop(&mut self) {
+            // SAFETY: takes no arguments
+            unsafe { CGDisplayRestoreColorSyncSettings() };
+        }
+    }
+}
+
+#[cfg(all(target_os = "linux", feature = "capture"))]
+mod x11 {
+    use crate::capture_backend::{install_error_handler, take_x_error};
+    use anyhow::Result;
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_int, c_uchar, c_uint, c_ulong, c_ushort, c_void};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{mpsc, Arc};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    type Xid = c_ulong;
+    type Window = Xid;
+    type RrOutput = Xid;
+    type RrCrtc = Xid;
+    type RrMode = Xid;
+    type Time = c_ulong;
+
+    #[repr(C)]
+    struct Display {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    struct XiEventMask {
+        deviceid: c_int,
+        mask_len: c_int,
+        mask: *mut c_uchar,
+    }
+
+    #[repr(C)]
+    struct XiDeviceInfo {
+        deviceid: c_int,
+        name: *mut c_char,
+        use_: c_int,
+        attachment: c_int,
+        enabled: c_int,
+        num_classes: c_int,
+        classes: *mut *mut c_void,
+    }
+
+    #[repr(C)]
+    struct XrrModeInfo {
+        id: RrMode,
+        width: c_uint,
+        height: c_uint,
+        dot_clock: c_ulong,
+        h_sync_start: c_uint,
+        h_sync_end: c_uint,
+        h_total: c_uint,
+        h_skew: c_uint,
+        v_sync_start: c_uint,
+        v_sync_end: c_uint,
+        v_total: c_uint,
+        name: *mut c_char,
+        name_length: c_uint,
+        mode_flags: c_ulong,
+    }
+
+    #[repr(C)]
+    struct XrrScreenResources {
+        timestamp: Time,
+        config_timestamp: Time,
+        ncrtc: c_int,
+        crtcs: *mut RrCrtc,
+        noutput: c_int,
+        outputs: *mut RrOutput,
+        nmode: c_int,
+        modes: *mut XrrModeInfo,
+    }
+
+    #[repr(C)]
+    struct XrrCrtcGamma {
+        size: c_int,
+        red: *mut c_ushort,
+        green: *mut c_ushort,
+        blue: *mut c_ushort,
+    }
+
+    const SUCCESS: c_int = 0;
+    const XI_ALL_DEVICES: c_int = 0;
+    const XI_SLAVE_POINTER: c_int = 3;
+    const XI_SLAVE_KEYBOARD: c_int = 4;
+    const GRAB_MODE_ASYNC: c_int = 1;
+    const CURRENT_TIME: Time = 0;
+    /// XI_KeyPress, XI_KeyRelease, XI_ButtonPress, XI_ButtonRelease and
+    /// XI_Motion, bits 2 to 6
+    const GRABBED_EVENTS: [c_uchar; 4] = [0x7C, 0, 0, 0];
+    /// How often the grab thread discards the events it was sent
+    const DRAIN_INTERVAL: Duration = Duration::from_millis(100);
+
+    extern "C" {
+        fn XOpenDisplay(name: *const c_char) -> *mut Display;
+        fn XCloseDisplay(display: *mut Display) -> c_int;
+        fn XDefaultRootWindow(display: *mut Display) -> Window;
+        fn XSync(display: *mut Display, discard: c_int) -> c_int;
+        fn XQueryExtension(
+            display: *mut Display,
+            name: *const c_char,
+            major_opcode: *mut c_int,
+            first_event: *mut c_int,
+            first_error: *mut c_int,
+        ) -> c_int;
+        fn XIQueryVersion(display: *mut Display, major: *mut c_int, minor: *mut c_int) -> c_int;
+        fn XIQueryDevice(
+            display: *mut Display,
+            deviceid: c_int,
+            ndevices: *mut c_int,
+        ) -> *mut XiDeviceInfo;
+        fn XIFreeDeviceInfo(info: *mut XiDeviceInfo);
+        fn XIGrabDevice(
+            display: *mut Display,
+            deviceid: c_int,
+            grab_window: Window,
+            time: Time,
+            cursor: c_ulong,
+            grab_mode: c_int,
+            paired_device_mode: c_int,
+            owner_events: c_int,
+            mask: *mut XiEventMask,
+        ) -> c_int;
+        fn XIUngrabDevice(display: *mut Display, deviceid: c_int, time: Time) -> c_int;
+        fn XRRQueryExtension(
+            display: *mut Display,
+            event_base: *mut c_int,
+            error_base: *mut c_int,
+        ) -> c_int;
+        fn XRRGetScreenResourcesCurrent(
+            display: *mut Display,
+            window: Window,
+        ) -> *mut XrrScreenResources;
+        fn XRRFreeScreenResources(resources: *mut XrrScreenResources);
+        fn XRRGetCrtcGammaSize(display: *mut Display, crtc: RrCrtc) -> c_int;
+        fn XRRGetCrtcGamma(display: *mut Display, crtc: RrCrtc) -> *mut XrrCrtcGamma;
+        fn XRRAllocGamma(size: c_int) -> *mut XrrCrtcGamma;
+        fn XRRSetCrtcGamma(display: *mut Display, crtc: RrCrtc, gamma: *mut XrrCrtcGamma);
+        fn XRRFreeGamma(gamma: *mut XrrCrtcGamma);
+    }
+
+    fn open_display() -> Result<*mut Display> {
+        if std::env::var_os("DISPLAY").is_none() {
+            return Err(anyhow::anyhow!(
+                "Privacy mode needs an X display (DISPLAY not set)"
+            ));
+        }
+        install_error_handler();
+        // SAFETY: a null name selects $DISPLAY; the caller closes it
+        let display = unsafe { XOpenDisplay(std::ptr::null()) };
+        if display.is_null() {
+            return Err(anyhow::anyhow!("Cannot open X display"));
+        }
+        Ok(display)
+    }
+
+    unsafe fn raw_slice<'a, T>(ptr: *const T, len: c_int) -> &'a [T] {
+        if ptr.is_null() || len <= 0 {
+            &[]
+        } else {
+            std::slice::from_raw_parts(ptr, len as usize)
+        }
+    }
+
+    /// Grab every physical keyboard and pointer; returns the grabbed IDs
+    ///
+    /// Grabbed slave devices no longer feed their master, while the XTEST
+    /// devices that injected input comes from stay ungrabbed.
+    unsafe fn grab_physical_devices(display: *mut Display) -> Result<Vec<c_int>> {
+        let (mut opcode, mut event, mut error) = (0, 0, 0);
+        if XQueryExtension(
+            display,
+            c"XInputExtension".as_ptr(),
+            &mut opcode,
+            &mut event,
+            &mut error,
+        ) == 0
+        {
+            return Err(anyhow::anyhow!("X server does not support XInput"));
+        }
+        let (mut major, mut minor) = (2, 0);
+        if XIQueryVersion(display, &mut major, &mut minor) != SUCCESS {
+            return Err(anyhow::anyhow!("X server does not support XInput 2"));
+        }
+
+        let mut count = 0;
+        let info = XIQueryDevice(display, XI_ALL_DEVICES, &mut count);
+        if info.is_null() {
+            return Err(anyhow::anyhow!("Cannot list input devices"));
+        }
+        let devices: Vec<(c_int, String)> = raw_slice(info, count)
+            .iter()
+            .filter(|device| matches!(device.use_, XI_SLAVE_POINTER | XI_SLAVE_KEYBOARD))
+            .map(|device| {
+                let name = if device.name.is_null() {
+                    String::new()
+                } else {
+                    CStr::from_ptr(device.name).to_string_lossy().into_owned()
+                };
+                (device.deviceid, name)
+            })
+            .filter(|(_, name)| !name.contains("XTEST"))
+            .collect();
+        XIFreeDeviceInfo(info);
+
+        let root = XDefaultRootWindow(display);
+        let mut bits = GRABBED_EVENTS;
+        let mut grabbed = Vec::new();
+        for (deviceid, name) in devices {
+            let mut mask = XiEventMask {
+                deviceid,
+                mask_len: bits.len() as c_int,
+                mask: bits.as_mut_ptr(),
+            };
+            let status = XIGrabDevice(
+                display,
+                deviceid,
+                root,
+                CURRENT_TIME,
+                0,
+                GRAB_MODE_ASYNC,
+                GRAB_MODE_ASYNC,
+                0,
+                &mut mask,
+            );
+            if status != SUCCESS {
+                ungrab(display, &grabbed);
+                return Err(anyhow::anyhow!(
+                    "Cannot grab input device {}: status {}",
+                    name,
+                    status
+                ));
+            }
+            grabbed.push(deviceid);
+        }
+        XSync(display, 0);
+        if take_x_error() {
+            ungrab(display, &grabbed);
+            return Err(anyhow::anyhow!("X server refused an input grab"));
+        }
+        Ok(grabbed)
+    }
+
+    unsafe fn ungrab(display: *mut Display, devices: &[c_int]) {
+        for &deviceid in devices {
+            XIUngrabDevice(display, deviceid, CURRENT_TIME);
+        }
+        XSync(display, 0);
+        take_x_error();
+    }
+
+    /// Physical keyboards and pointers grabbed by a connection of our own
+    ///
+    /// The server drops the grabs if this process dies, so the local user is
+    /// never locked out past it. Devices plugged in while blocked are not
+    /// grabbed.
+    pub(super) struct InputBlock {
+        stop: Arc<AtomicBool>,
+        handle: Option<JoinHandle<()>>,
+    }
+
+    impl InputBlock {
+        pub(super) fn engage() -> Result<Self> {
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread_stop = stop.clone();
+            let (ready_tx, ready_rx) = mpsc::channel();
+            let handle = std::thread::Builder::new()
+                .name("privacy-input".to_string())
+                .spawn(move || {
+                    let display = match open_display() {
+                        Ok(display) => display,
+                        Err(e) => {
+                            let _ = ready_tx.send(Err(e));
+                            return;
+                        }
+                    };
+                    // SAFETY: the connection is only used on this thread and
+                    // closed before it exits
+                    unsafe {
+                        match grab_physical_devices(display) {
+                            Ok(grabbed) => {
+                                let _ = ready_tx.send(Ok(()));
+                                while !thread_stop.load(Ordering::SeqCst) {
+                                    // Grabbed events are sent to us; throw them away
+                                    XSync(display, 1);
+                                    std::thread::sleep(DRAIN_INTERVAL);
+                                }
+                                ungrab(display, &grabbed);
+                            }
+                            Err(e) => {
+                                let _ = ready_tx.send(Err(e));
+                            }
+                        }
+                        XCloseDisplay(display);
+                    }
+                })?;
+            match ready_rx.recv() {
+                Ok(Ok(())) => Ok(Self {
+                    stop,
+                    handle: Some(handle),
+                }),
+                Ok(Err(e)) => {
+                    let _ = handle.join();
+                    Err(e)
+                }
+                Err(_) => {
+                    let _ = handle.join();
+                    Err(anyhow::anyhow!("Input block thread exited during setup"))
+                }
+            }
+        }
+    }
+
+    impl Drop for InputBlock {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::SeqCst);
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    /// Every CRTC's gamma ramp zeroed, with the originals kept to restore
+    ///
+    /// Capture reads the framebuffer, which gamma does not touch. Unlike a
+    /// grab the ramps outlive the connection, so a crash while blanked
+    /// leaves the screen dark until the session's gamma is next set.
+    pub(super) struct ScreenBlank {
+        display: *mut Display,
+        saved: Vec<(RrCrtc, *mut XrrCrtcGamma)>,
+    }
+
+    // SAFETY: the connection and ramps are only used by whichever thread
+    // holds the blank, under the backend's lock
+    unsafe impl Send for ScreenBlank {}
+
+    impl ScreenBlank {
+        pub(super) fn engage() -> Result<Self> {
+            let mut blank = Self {
+                display: open_display()?,
+                saved: Vec::new(),
+            };
+            // SAFETY: every RandR structure is freed with its matching
+            // function; saved ramps are freed when restored on drop
+            unsafe {
+                let display = blank.display;
+                let (mut event_base, mut error_base) = (0, 0);
+                if XRRQueryExtension(display, &mut event_base, &mut error_base) == 0 {
+                    return Err(anyhow::anyhow!("X server does not support RandR"));
+                }
+                let resources = XRRGetScreenResourcesCurrent(display, XDefaultRootWindow(display));
+                if resources.is_null() {
+                    return Err(anyhow::anyhow!("Cannot read RandR screen resources"));
+                }
+                for &crtc in raw_slice((*resources).crtcs, (*resources).ncrtc) {
+                    let size = XRRGetCrtcGammaSize(display, crtc);
+                    if size <= 0 {
+                        continue;
+                    }
+                    let original = XRRGetCrtcGamma(display, crtc);
+                    let black = XRRAllocGamma(size);
+                    if original.is_null() || black.is_null() {
+                        if !original.is_null() {
+                            XRRFreeGamma(original);
+                        }
+                        if !black.is_null() {
+                            XRRFreeGamma(black);
+                        }
+                        continue;
+                    }
+                    for ramp in [(*black).red, (*black).green, (*black).blue] {
+                        std::ptr::write_bytes(ramp, 0, size as usize);
+                    }
+                    XRRSetCrtcGamma(display, crtc, black);
+                    XRRFreeGamma(black);
+                    blank.saved.push((crtc, original));
+                }
+                XRRFreeScreenResources(resources);
+                XSync(display, 0);
+                if take_x_error() {
+                    return Err(anyhow::anyhow!("X server refused a gamma ramp"));
+                }
+            }
+            if blank.saved.is_empty() {
+                return Err(anyhow::anyhow!("No display accepts a gamma ramp"));
+            }
+            Ok(blank)
+        }
+    }
+
+    impl Drop for ScreenBlank {
+        fn drop(&mut self) {
+            // SAFETY: the saved ramps came from XRRGetCrtcGamma on this
+            // connection and are freed exactly once
+            unsafe {
+                for (crtc, gamma) in self.saved.drain(..) {
+                    XRRSetCrtcGamma(self.display, crtc, gamma);
+                    XRRFreeGamma(gamma);
+                }
+                XSync(self.display, 0);
+                take_x_error();
+                XCloseDisplay(self.display);
+            }
+        }
+    }
+}
+
+#[cfg(not(any(
+    target_os = "windows",
+    target_os = "macos",
+    all(target_os = "linux", feature = "capture")
+)))]
+mod unsupported {
+    use anyhow::Result;
+
+    pub(super) struct InputBlock;
+
+    impl InputBlock {
+        pub(super) fn engage() -> Result<Self> {
+            Err(anyhow::anyhow!(
+                "Blocking local input is not supported on {}",
+                std::env::consts::OS
+            ))
+        }
+    }
+
+    pub(super) struct ScreenBlank;
+
+    impl ScreenBlank {
+        pub(super) fn engage() -> Result<Self> {
+            Err(anyhow::anyhow!(
+                "Blanking the local screen is not supported on {}",
+                std::env::consts::OS
+            ))
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct PrivacyState {
+    sessions: HashMap<String, PrivacyOptions>,
+    /// What the backend currently enforces
+    applied: PrivacyOptions,
+}
+
+/// Tracks which sessions hold privacy mode and drives the backend
+pub struct PrivacyMode {
+    backend: Arc<dyn PrivacyBackend>,
+    state: Mutex<PrivacyState>,
+}
+
+impl PrivacyMode {
+    pub fn new() -> Self {
+        Self::with_backend(Arc::new(PlatformPrivacyBackend::default()))
+    }
+
+    pub fn with_backend(backend: Arc<dyn PrivacyBackend>) -> Self {
+        Self {
+            backend,
+            state: Mutex::new(PrivacyState::default()),
+        }
+    }
+
+    /// Hold privacy mode for a session, replacing its earlier options
+    ///
+    /// Returns what the host enforces now. On failure the session's previous
+    /// options stay in effect.
+    pub fn engage(&self, session_id: &str, options: PrivacyOptions) -> Result<PrivacyOptions> {
+        let mut state = self.state()?;
+        let previous = state.sessions.insert(session_id.to_string(), options);
+        if let Err(e) = self.apply(&mut state) {
+            match previous {
+                Some(previous) => state.sessions.insert(session_id.to_string(), previous),
+                None => state.sessions.remove(session_id),
+            };
+            // Undo whatever part of the change did get applied
+            if let Err(undo) = self.apply(&mut state) {
+                tracing::error!("Failed to restore privacy mode state: {}", undo);
+            }
+            return Err(e);
+        }
+        Ok(state.applied)
+    }
+
+    /// Drop a session's hold; returns what the host still enforces
+    ///
+    /// The hold is dropped even if the backend fails, and calling again
+    /// retries restoring the host.
+    pub fn release(&self, session_id: &str) -> Result<PrivacyOptions> {
+        let mut state = self.state()?;
+        state.sessions.remove(session_id);
+        self.apply(&mut state)?;
+        Ok(state.applied)
+    }
+
+    pub fn session_options(&self, session_id: &str) -> Option<PrivacyOptions> {
+        self.state.lock().ok()?.sessions.get(session_id).copied()
+    }
+
+    /// What the host currently enforces
+    pub fn effective(&self) -> PrivacyOptions {
+        self.state
+            .lock()
+            .map(|state| state.applied)
+            .unwrap_or_default()
+    }
+
+    /// Bring the backend in line with the union of all sessions' options
+    fn apply(&self, state: &mut PrivacyState) -> Result<()> {
+        let target = state
+            .sessions
+            .values()
+            .fold(PrivacyOptions::default(), |all, options| {
+                all.union(*options)
+            });
+        if target.block_input != state.applied.block_input {
+            self.backend.block_local_input(target.block_input)?;
+            state.applied.block_input = target.block_input;
+        }
+        if target.blank_screen != state.applied.blank_screen {
+            self.backend.blank_screen(target.blank_screen)?;
+            state.applied.blank_screen = target.blank_screen;
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> Result<std::sync::MutexGuard<'_, PrivacyState>> {
+        self.state
+            .lock()
+            .map_err(|_| anyhow::anyhow!("Privacy mode state lock poisoned"))
+    }
+}
+
+impl Default for PrivacyMode {
+    fn default() -> Self {
+        Self::new()
+    }
+}
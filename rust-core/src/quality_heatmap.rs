@@ -0,0 +1,160 @@
+//! Connection Quality Heatmap
+//!
+//! Aggregates connection quality samples per peer into day-of-week by
+//! hour-of-day buckets (host local time), so recurring patterns such as "it
+//! is slow every afternoon" show up at a glance. Only running sums are kept,
+//! and only for buckets that have samples, so the history stays small enough
+//! to persist next to the session records.
+
+use crate::session_manager::ConnectionQuality;
+use chrono::{DateTime, Datelike, Local, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+pub const DAYS_PER_WEEK: usize = 7;
+pub const HOURS_PER_DAY: usize = 24;
+
+/// Running sums for one bucket, stored as
+/// `(day, hour, samples, score_sum, rtt_sum_ms, loss_sum_permille)`
+type BucketSums = (u8, u8, u32, u64, u64, u64);
+
+/// Aggregated quality of one hour of one weekday
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct HeatmapCell {
+    /// Samples aggregated; 0 means no data
+    pub samples: u32,
+    /// Average quality score, 0 (poor) to 100 (excellent)
+    pub quality_score: f32,
+    pub avg_rtt_ms: f32,
+    pub avg_packet_loss_percent: f32,
+}
+
+/// Quality matrix for a peer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QualityHeatmap {
+    pub device_id: String,
+    /// `cells[day][hour]`, days starting on Monday
+    pub cells: Vec<Vec<HeatmapCell>>,
+}
+
+impl QualityHeatmap {
+    pub fn cell(&self, weekday: chrono::Weekday, hour: u32) -> HeatmapCell {
+        self.cells[weekday.num_days_from_monday() as usize][hour as usize]
+    }
+}
+
+/// Quality aggregates for all peers
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QualityHistory {
+    peers: HashMap<String, Vec<BucketSums>>,
+}
+
+impl QualityHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sample taken at `time`
+    pub fn record(
+        &mut self,
+        device_id: &str,
+        time: DateTime<Local>,
+        rtt_ms: u32,
+        packet_loss_percent: f32,
+        quality: &ConnectionQuality,
+    ) {
+        let day = time.weekday().num_days_from_monday() as u8;
+        let hour = time.hour() as u8;
+        let buckets = self.peers.entry(device_id.to_string()).or_default();
+        let index = match buckets.iter().position(|b| b.0 == day && b.1 == hour) {
+            Some(index) => index,
+            None => {
+                buckets.push((day, hour, 0, 0, 0, 0));
+                buckets.len() - 1
+            }
+        };
+
+        let bucket = &mut buckets[index];
+        bucket.2 = bucket.2.saturating_add(1);
+        bucket.3 += quality_score(quality) as u64;
+        bucket.4 += rtt_ms as u64;
+        bucket.5 += (packet_loss_percent.clamp(0.0, 100.0) * 10.0).round() as u64;
+    }
+
+    /// Aggregated matrix for a peer; all cells are empty if it has no history
+    pub fn heatmap(&self, device_id: &str) -> QualityHeatmap {
+        let mut cells = vec![vec![HeatmapCell::default(); HOURS_PER_DAY]; DAYS_PER_WEEK];
+        for &(day, hour, samples, score, rtt, loss) in
+            self.peers.get(device_id).into_iter().flatten()
+        {
+            if samples == 0 {
+                continue;
+            }
+            let n = samples as f32;
+            cells[day as usize][hour as usize] = HeatmapCell {
+                samples,
+                quality_score: score as f32 / n,
+                avg_rtt_ms: rtt as f32 / n,
+                avg_packet_loss_percent: loss as f32 / 10.0 / n,
+            };
+        }
+        QualityHeatmap {
+            device_id: device_id.to_string(),
+            cells,
+        }
+    }
+
+    /// Forget a peer's history
+    pub fn remove(&mut self, device_id: &str) {
+        self.peers.remove(device_id);
+    }
+}
+
+fn quality_score(quality: &ConnectionQuality) -> u32 {
+    match quality {
+        ConnectionQuality::Excellent => 100,
+        ConnectionQuality::Good => 75,
+        ConnectionQuality::Fair => 40,
+        ConnectionQuality::Poor => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Weekday};
+
+    #[test]
+    fn test_samples_aggregate_per_hour_and_day() {
+        let mut history = QualityHistory::new();
+        // 2024-01-01 is a Monday
+        let monday_3pm = Local.with_ymd_and_hms(2024, 1, 1, 15, 10, 0).unwrap();
+        let next_monday_3pm = Local.with_ymd_and_hms(2024, 1, 8, 15, 40, 0).unwrap();
+        let tuesday_9am = Local.with_ymd_and_hms(2024, 1, 2, 9, 0, 0).unwrap();
+
+        history.record("peer", monday_3pm, 300, 6.0, &ConnectionQuality::Poor);
+        history.record("peer", next_monday_3pm, 100, 2.0, &ConnectionQuality::Fair);
+        history.record("peer", tuesday_9am, 20, 0.0, &ConnectionQuality::Excellent);
+        history.record("other", tuesday_9am, 500, 9.0, &ConnectionQuality::Poor);
+
+        let heatmap = history.heatmap("peer");
+        let afternoon = heatmap.cell(Weekday::Mon, 15);
+        assert_eq!(afternoon.samples, 2);
+        assert_eq!(afternoon.quality_score, 20.0);
+        assert_eq!(afternoon.avg_rtt_ms, 200.0);
+        assert_eq!(afternoon.avg_packet_loss_percent, 4.0);
+        assert_eq!(heatmap.cell(Weekday::Tue, 9).quality_score, 100.0);
+        assert_eq!(heatmap.cell(Weekday::Wed, 9).samples, 0);
+
+        // Compact form round-trips through JSON
+        let json = serde_json::to_string(&history).unwrap();
+        let restored: QualityHistory = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.heatmap("peer"), heatmap);
+        assert!(history
+            .heatmap("unknown")
+            .cells
+            .iter()
+            .flatten()
+            .all(|c| c.samples == 0));
+    }
+}
@@ -0,0 +1,485 @@
+//! Received File Quarantine
+//!
+//! Files received from a remote device are first staged in a quarantine
+//! directory instead of the user's download folder. Before release a file
+//! must pass the extension deny list, a content-type check (executables are
+//! recognised by their magic bytes, whatever their name) and, if configured,
+//! an external scanner command. Files that fail are deleted. Every decision is
+//! published as a `QuarantineEvent`.
+
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Placeholder in scanner arguments replaced by the staged file path
+pub const SCANNER_FILE_PLACEHOLDER: &str = "{file}";
+
+/// Content types recognised from a file's leading bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DetectedFileType {
+    /// PE executable or DLL
+    WindowsExecutable,
+    ElfExecutable,
+    MachOExecutable,
+    /// Text starting with `#!`
+    Script,
+}
+
+impl DetectedFileType {
+    fn detect(header: &[u8]) -> Option<Self> {
+        match header {
+            [b'M', b'Z', ..] => Some(Self::WindowsExecutable),
+            [0x7f, b'E', b'L', b'F', ..] => Some(Self::ElfExecutable),
+            [0xfe, 0xed, 0xfa, 0xce | 0xcf, ..] | [0xce | 0xcf, 0xfa, 0xed, 0xfe, ..] => {
+                Some(Self::MachOExecutable)
+            }
+            [b'#', b'!', ..] => Some(Self::Script),
+            _ => None,
+        }
+    }
+}
+
+/// External scanner run on each staged file
+///
+/// Exit code 0 means clean; any other exit code rejects the file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScannerCommand {
+    pub program: String,
+    /// Arguments; `{file}` is replaced by the staged path, which is appended
+    /// if no argument contains the placeholder
+    pub args: Vec<String>,
+    pub timeout: Duration,
+}
+
+/// Quarantine settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantineConfig {
+    /// Where received files are staged
+    pub quarantine_dir: PathBuf,
+    /// Where files are moved once they pass all checks
+    pub release_dir: PathBuf,
+    /// Lower-case extensions (without dot) that are always rejected
+    pub denied_extensions: Vec<String>,
+    /// Content types that are always rejected
+    pub denied_types: Vec<DetectedFileType>,
+    pub scanner: Option<ScannerCommand>,
+}
+
+impl QuarantineConfig {
+    /// Default deny lists with the given directories
+    pub fn new(quarantine_dir: PathBuf, release_dir: PathBuf) -> Self {
+        Self {
+            quarantine_dir,
+            release_dir,
+            denied_extensions: [
+                "exe", "msi", "bat", "cmd", "com", "scr", "pif", "ps1", "vbs", "js", "jar", "lnk",
+                "reg", "hta",
+            ]
+            .iter()
+            .map(|ext| ext.to_string())
+            .collect(),
+            denied_types: vec![
+                DetectedFileType::WindowsExecutable,
+                DetectedFileType::ElfExecutable,
+                DetectedFileType::MachOExecutable,
+            ],
+            scanner: None,
+        }
+    }
+}
+
+/// Why a file was not released
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RejectionReason {
+    DeniedExtension(String),
+    DeniedType(DetectedFileType),
+    /// Name refers to an alternate data stream rather than a plain file
+    InvalidName(String),
+    /// Scanner exited with a non-zero code (`None` if killed by a signal)
+    ScannerFlagged {
+        exit_code: Option<i32>,
+        output: String,
+    },
+    /// Scanner could not be run or timed out; the file is not released
+    ScannerFailed(String),
+}
+
+/// A file waiting in quarantine
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuarantinedFile {
+    pub file_id: String,
+    /// Name the file had on the sending device
+    pub original_name: String,
+    pub staged_path: PathBuf,
+}
+
+/// Outcome of reviewing a quarantined file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum QuarantineDecision {
+    Released { path: PathBuf },
+    Rejected { reason: RejectionReason },
+}
+
+/// Quarantine decisions, published to subscribers
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum QuarantineEvent {
+    Quarantined {
+        file_id: String,
+        original_name: String,
+    },
+    Released {
+        file_id: String,
+        original_name: String,
+        path: PathBuf,
+    },
+    Rejected {
+        file_id: String,
+        original_name: String,
+        reason: RejectionReason,
+    },
+}
+
+impl EventType for QuarantineEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            QuarantineEvent::Quarantined { .. } => "Quarantined",
+            QuarantineEvent::Released { .. } => "Released",
+            QuarantineEvent::Rejected { .. } => "Rejected",
+        }
+    }
+}
+
+/// Stages, checks and releases received files
+pub struct FileQuarantine {
+    config: QuarantineConfig,
+    events: EventBus<QuarantineEvent>,
+}
+
+impl FileQuarantine {
+    pub fn new(config: QuarantineConfig) -> Self {
+        Self {
+            config,
+            events: EventBus::new(),
+        }
+    }
+
+    pub fn config(&self) -> &QuarantineConfig {
+        &self.config
+    }
+
+    /// Subscribe to quarantine decisions
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<QuarantineEvent> {
+        self.events.subscribe(options)
+    }
+
+    /// Path a new incoming file should be written to
+    pub fn incoming_path(&self) -> PathBuf {
+        self.config
+            .quarantine_dir
+            .join(format!("{}.quarantine", Uuid::new_v4()))
+    }
+
+    /// Move a received file into quarantine
+    pub async fn stage(&self, received: &Path, original_name: &str) -> Result<QuarantinedFile> {
+        tokio::fs::create_dir_all(&self.config.quarantine_dir).await?;
+        let file_id = Uuid::new_v4().to_string();
+        let staged_path = self
+            .config
+            .quarantine_dir
+            .join(format!("{}.quarantine", file_id));
+        tokio::fs::rename(received, &staged_path)
+            .await
+            .context("Failed to move received file into quarantine")?;
+
+        let file = QuarantinedFile {
+            file_id,
+            original_name: sanitize_file_name(original_name),
+            staged_path,
+        };
+        tracing::info!("Quarantined received file {}", file.original_name);
+        self.events.publish(QuarantineEvent::Quarantined {
+            file_id: file.file_id.clone(),
+            original_name: file.original_name.clone(),
+        });
+        Ok(file)
+    }
+
+    /// Check a quarantined file, then release or delete it
+    pub async fn review(&self, file: QuarantinedFile) -> Result<QuarantineDecision> {
+        let decision = match self.check(&file).await? {
+            Some(reason) => {
+                tracing::warn!(
+                    "Rejected received file {}: {:?}",
+                    file.original_name,
+                    reason
+                );
+                let _ = tokio::fs::remove_file(&file.staged_path).await;
+                self.events.publish(QuarantineEvent::Rejected {
+                    file_id: file.file_id.clone(),
+                    original_name: file.original_name.clone(),
+                    reason: reason.clone(),
+                });
+                QuarantineDecision::Rejected { reason }
+            }
+            None => {
+                let path = self.release(&file).await?;
+                tracing::info!("Released received file to {}", path.display());
+                self.events.publish(QuarantineEvent::Released {
+                    file_id: file.file_id.clone(),
+                    original_name: file.original_name.clone(),
+                    path: path.clone(),
+                });
+                QuarantineDecision::Released { path }
+            }
+        };
+        Ok(decision)
+    }
+
+    /// Stage and review a received file in one step
+    pub async fn process(
+        &self,
+        received: &Path,
+        original_name: &str,
+    ) -> Result<QuarantineDecision> {
+        let file = self.stage(received, original_name).await?;
+        self.review(file).await
+    }
+
+    async fn check(&self, file: &QuarantinedFile) -> Result<Option<RejectionReason>> {
+        // Windows drops trailing dots and spaces when opening a file, so
+        // `run.bat.` runs as `run.bat`; `x.ps1::$DATA` opens a stream of `x.ps1`
+        let name = trim_ignored_suffix(&file.original_name);
+        if name.contains(':') {
+            return Ok(Some(RejectionReason::InvalidName(
+                file.original_name.clone(),
+            )));
+        }
+        if let Some(ext) = Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase())
+        {
+            if self.config.denied_extensions.contains(&ext) {
+                return Ok(Some(RejectionReason::DeniedExtension(ext)));
+            }
+        }
+
+        let mut header = [0u8; 4];
+        let read = std::fs::File::open(&file.staged_path)?.read(&mut header)?;
+        if let Some(detected) = DetectedFileType::detect(&header[..read]) {
+            if self.config.denied_types.contains(&detected) {
+                return Ok(Some(RejectionReason::DeniedType(detected)));
+            }
+        }
+
+        match &self.config.scanner {
+            Some(scanner) => Ok(run_scanner(scanner, &file.staged_path).await),
+            None => Ok(None),
+        }
+    }
+
+    /// Move a file to the release directory without overwriting anything
+    async fn release(&self, file: &QuarantinedFile) -> Result<PathBuf> {
+        tokio::fs::create_dir_all(&self.config.release_dir).await?;
+        let name = Path::new(&file.original_name);
+        let stem = name
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("file")
+            .to_string();
+        let ext = name.extension().and_then(|e| e.to_str());
+
+        let mut path = self.config.release_dir.join(&file.original_name);
+        let mut n = 1;
+        while tokio::fs::try_exists(&path).await? {
+            let candidate = match ext {
+                Some(ext) => format!("{} ({}).{}", stem, n, ext),
+                None => format!("{} ({})", stem, n),
+            };
+            path = self.config.release_dir.join(candidate);
+            n += 1;
+        }
+        tokio::fs::rename(&file.staged_path, &path).await?;
+        Ok(path)
+    }
+}
+
+async fn run_scanner(scanner: &ScannerCommand, path: &Path) -> Option<RejectionReason> {
+    let file = path.to_string_lossy();
+    let mut args: Vec<String> = scanner
+        .args
+        .iter()
+        .map(|arg| arg.replace(SCANNER_FILE_PLACEHOLDER, &file))
+        .collect();
+    if !scanner
+        .args
+        .iter()
+        .any(|arg| arg.contains(SCANNER_FILE_PLACEHOLDER))
+    {
+        args.push(file.into_owned());
+    }
+
+    let output = tokio::process::Command::new(&scanner.program)
+        .args(&args)
+        .kill_on_drop(true)
+        .output();
+    match tokio::time::timeout(scanner.timeout, output).await {
+        Ok(Ok(output)) if output.status.success() => None,
+        Ok(Ok(output)) => Some(RejectionReason::ScannerFlagged {
+            exit_code: output.status.code(),
+            output: String::from_utf8_lossy(&output.stdout).trim().to_string(),
+        }),
+        Ok(Err(e)) => Some(RejectionReason::ScannerFailed(e.to_string())),
+        Err(_) => Some(RejectionReason::ScannerFailed(format!(
+            "Scanner timed out after {:?}",
+            scanner.timeout
+        ))),
+    }
+}
+
+/// Strip the trailing dots and spaces Windows ignores in file names
+fn trim_ignored_suffix(name: &str) -> &str {
+    name.trim_end_matches(['.', ' '])
+}
+
+/// Keep only the final path component of a sender-supplied name
+fn sanitize_file_name(name: &str) -> String {
+    name.rsplit(['/', '\\'])
+        .next()
+        .map(trim_ignored_suffix)
+        .filter(|n| !n.is_empty() && *n != "." && *n != "..")
+        .unwrap_or("received_file")
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config() -> (PathBuf, QuarantineConfig) {
+        let root = std::env::temp_dir().join(format!("cec-quarantine-{}", Uuid::new_v4()));
+        let config = QuarantineConfig::new(root.join("quarantine"), root.join("downloads"));
+        (root, config)
+    }
+
+    async fn receive(quarantine: &FileQuarantine, data: &[u8]) -> PathBuf {
+        let path = quarantine.incoming_path();
+        tokio::fs::create_dir_all(path.parent().unwrap())
+            .await
+            .unwrap();
+        tokio::fs::write(&path, data).await.unwrap();
+        path
+    }
+
+    #[tokio::test]
+    async fn test_deny_lists() {
+        let (root, config) = temp_config();
+        let quarantine = FileQuarantine::new(config);
+        let mut events = quarantine.subscribe(SubscriptionOptions::only(&["Rejected"]));
+
+        let incoming = receive(&quarantine, b"hello").await;
+        let decision = quarantine
+            .process(&incoming, "../../notes.txt")
+            .await
+            .unwrap();
+        let released = root.join("downloads").join("notes.txt");
+        assert_eq!(
+            decision,
+            QuarantineDecision::Released {
+                path: released.clone()
+            }
+        );
+        assert_eq!(std::fs::read(&released).unwrap(), b"hello");
+
+        let incoming = receive(&quarantine, b"hello").await;
+        let decision = quarantine.process(&incoming, "setup.EXE").await.unwrap();
+        assert_eq!(
+            decision,
+            QuarantineDecision::Rejected {
+                reason: RejectionReason::DeniedExtension("exe".to_string())
+            }
+        );
+
+        // Renamed executable is caught by its content
+        let incoming = receive(&quarantine, b"\x7fELF\x02\x01").await;
+        let decision = quarantine.process(&incoming, "photo.jpg").await.unwrap();
+        assert_eq!(
+            decision,
+            QuarantineDecision::Rejected {
+                reason: RejectionReason::DeniedType(DetectedFileType::ElfExecutable)
+            }
+        );
+
+        assert!(matches!(
+            events.try_recv(),
+            Some(QuarantineEvent::Rejected { .. })
+        ));
+        assert!(matches!(
+            events.try_recv(),
+            Some(QuarantineEvent::Rejected { .. })
+        ));
+        assert!(events.try_recv().is_none());
+
+        // Names Windows would resolve to a denied extension or a stream
+        for (name, reason) in [
+            (
+                "run.bat.",
+                RejectionReason::DeniedExtension("bat".to_string()),
+            ),
+            (
+                "run.bat ",
+                RejectionReason::DeniedExtension("bat".to_string()),
+            ),
+            (
+                "x.ps1::$DATA",
+                RejectionReason::InvalidName("x.ps1::$DATA".to_string()),
+            ),
+        ] {
+            let incoming = receive(&quarantine, b"echo hi").await;
+            let decision = quarantine.process(&incoming, name).await.unwrap();
+            assert_eq!(decision, QuarantineDecision::Rejected { reason });
+        }
+
+        // Rejected files are deleted, released ones moved out
+        let staged = std::fs::read_dir(root.join("quarantine")).unwrap().count();
+        assert_eq!(staged, 0);
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_scanner_hook() {
+        let (root, mut config) = temp_config();
+        config.scanner = Some(ScannerCommand {
+            program: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                "grep -q EICAR \"$0\" && echo infected && exit 1 || exit 0".to_string(),
+                SCANNER_FILE_PLACEHOLDER.to_string(),
+            ],
+            timeout: Duration::from_secs(5),
+        });
+        let quarantine = FileQuarantine::new(config);
+
+        let incoming = receive(&quarantine, b"clean report").await;
+        let decision = quarantine.process(&incoming, "report.pdf").await.unwrap();
+        assert!(matches!(decision, QuarantineDecision::Released { .. }));
+
+        let incoming = receive(&quarantine, b"EICAR test").await;
+        let decision = quarantine.process(&incoming, "report.pdf").await.unwrap();
+        assert_eq!(
+            decision,
+            QuarantineDecision::Rejected {
+                reason: RejectionReason::ScannerFlagged {
+                    exit_code: Some(1),
+                    output: "infected".to_string()
+                }
+            }
+        );
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
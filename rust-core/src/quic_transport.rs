@@ -0,0 +1,467 @@
+//! QUIC Fallback Transport for Data Paths
+//!
+//! Some networks mangle SCTP-over-DTLS, which prevents WebRTC data channels
+//! from opening even when media flows. In that case the control and file data
+//! paths can fall back to a QUIC connection (built with the `quic` feature).
+//!
+//! QUIC uses TLS 1.3. Each side generates a self-signed certificate and shares
+//! its SHA-256 fingerprint over signaling. Both sides present their certificate
+//! and accept only the peer's pinned one, which gives the same mutual
+//! fingerprint pinning model as the DTLS fingerprints carried in SDP.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+
+/// Default time allowed for a WebRTC data channel to open before falling back
+pub const DEFAULT_DATA_CHANNEL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Transport carrying the control and file data paths
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataTransportType {
+    /// WebRTC data channel (SCTP over DTLS)
+    DataChannel,
+    /// QUIC fallback connection
+    Quic,
+}
+
+impl std::fmt::Display for DataTransportType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataTransportType::DataChannel => write!(f, "WebRTC DataChannel"),
+            DataTransportType::Quic => write!(f, "QUIC"),
+        }
+    }
+}
+
+/// Logical data path multiplexed over the transport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DataPath {
+    /// Input events and session control messages
+    Control,
+    /// File transfer chunks
+    File,
+}
+
+impl DataPath {
+    #[cfg_attr(not(feature = "quic"), allow(dead_code))]
+    fn tag(self) -> u8 {
+        match self {
+            DataPath::Control => 0,
+            DataPath::File => 1,
+        }
+    }
+
+    #[cfg_attr(not(feature = "quic"), allow(dead_code))]
+    fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(DataPath::Control),
+            1 => Some(DataPath::File),
+            _ => None,
+        }
+    }
+}
+
+/// Data transport selection settings
+#[derive(Debug, Clone)]
+pub struct DataTransportConfig {
+    /// How long to wait for the data channel before giving up on it
+    pub data_channel_timeout: Duration,
+    /// Whether QUIC may be used when the data channel fails
+    pub quic_fallback_enabled: bool,
+}
+
+impl Default for DataTransportConfig {
+    fn default() -> Self {
+        Self {
+            data_channel_timeout: DEFAULT_DATA_CHANNEL_TIMEOUT,
+            quic_fallback_enabled: quic_supported(),
+        }
+    }
+}
+
+/// Whether this build includes the QUIC transport
+pub fn quic_supported() -> bool {
+    cfg!(feature = "quic")
+}
+
+/// Choose the transport for the data paths
+///
+/// The data channel attempt runs first. If it fails or times out and the QUIC
+/// fallback is enabled, the QUIC attempt runs and wins on success.
+pub async fn select_data_transport<D, Q>(
+    config: &DataTransportConfig,
+    data_channel_attempt: D,
+    quic_attempt: Q,
+) -> Result<DataTransportType>
+where
+    D: Future<Output = Result<()>>,
+    Q: Future<Output = Result<()>>,
+{
+    let dc_error =
+        match tokio::time::timeout(config.data_channel_timeout, data_channel_attempt).await {
+            Ok(Ok(())) => return Ok(DataTransportType::DataChannel),
+            Ok(Err(e)) => e.to_string(),
+            Err(_) => "timed out".to_string(),
+        };
+
+    tracing::warn!("Data channel failed to open: {}", dc_error);
+
+    if !config.quic_fallback_enabled || !quic_supported() {
+        return Err(anyhow::anyhow!(
+            "Data channel failed ({}) and QUIC fallback is unavailable",
+            dc_error
+        ));
+    }
+
+    tracing::info!("Falling back to QUIC transport for data paths");
+    quic_attempt.await.map_err(|e| {
+        anyhow::anyhow!(
+            "Data channel failed ({}) and QUIC fallback failed: {}",
+            dc_error,
+            e
+        )
+    })?;
+
+    Ok(DataTransportType::Quic)
+}
+
+#[cfg(feature = "quic")]
+pub use self::quic::{QuicIdentity, QuicListener, QuicTransport};
+
+#[cfg(feature = "quic")]
+mod quic {
+    use super::DataPath;
+    use anyhow::{Context, Result};
+    use sha2::{Digest, Sha256};
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    /// TLS server name used for all QUIC connections
+    const SERVER_NAME: &str = "cec-remote";
+
+    /// Largest single message accepted on a data path
+    const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+    fn fingerprint(der: &[u8]) -> String {
+        hex::encode(Sha256::digest(der))
+    }
+
+    /// Self-signed certificate presented on the QUIC fallback
+    ///
+    /// Generated before signaling so its fingerprint can be sent to the peer
+    /// ahead of the connection.
+    pub struct QuicIdentity {
+        cert_der: Vec<u8>,
+        key_der: Vec<u8>,
+        fingerprint: String,
+    }
+
+    impl QuicIdentity {
+        /// Generate a fresh certificate
+        pub fn generate() -> Result<Self> {
+            let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])
+                .context("Failed to generate QUIC certificate")?;
+            let cert_der = cert.serialize_der()?;
+            Ok(Self {
+                fingerprint: fingerprint(&cert_der),
+                key_der: cert.serialize_private_key_der(),
+                cert_der,
+            })
+        }
+
+        /// Certificate fingerprint to send to the peer over signaling
+        pub fn fingerprint(&self) -> &str {
+            &self.fingerprint
+        }
+
+        fn cert_chain(&self) -> Vec<rustls::Certificate> {
+            vec![rustls::Certificate(self.cert_der.clone())]
+        }
+
+        fn private_key(&self) -> rustls::PrivateKey {
+            rustls::PrivateKey(self.key_der.clone())
+        }
+    }
+
+    /// Accepts only the certificate whose fingerprint was exchanged via signaling
+    struct PinnedCertVerifier {
+        fingerprint: String,
+    }
+
+    impl PinnedCertVerifier {
+        fn check(&self, cert: &rustls::Certificate) -> std::result::Result<(), rustls::Error> {
+            if fingerprint(&cert.0).eq_ignore_ascii_case(&self.fingerprint) {
+                Ok(())
+            } else {
+                Err(rustls::Error::General(
+                    "QUIC certificate fingerprint mismatch".to_string(),
+                ))
+            }
+        }
+    }
+
+    impl rustls::client::ServerCertVerifier for PinnedCertVerifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &rustls::Certificate,
+            _intermediates: &[rustls::Certificate],
+            _server_name: &rustls::ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: SystemTime,
+        ) -> std::result::Result<rustls::client::ServerCertVerified, rustls::Error> {
+            self.check(end_entity)
+                .map(|()| rustls::client::ServerCertVerified::assertion())
+        }
+    }
+
+    // Client authentication is mandatory by default
+    impl rustls::server::ClientCertVerifier for PinnedCertVerifier {
+        fn client_auth_root_subjects(&self) -> &[rustls::DistinguishedName] {
+            &[]
+        }
+
+        fn verify_client_cert(
+            &self,
+            end_entity: &rustls::Certificate,
+            _intermediates: &[rustls::Certificate],
+            _now: SystemTime,
+        ) -> std::result::Result<rustls::server::ClientCertVerified, rustls::Error> {
+            self.check(end_entity)
+                .map(|()| rustls::server::ClientCertVerified::assertion())
+        }
+    }
+
+    /// Listening side of the QUIC fallback
+    pub struct QuicListener {
+        endpoint: quinn::Endpoint,
+    }
+
+    impl QuicListener {
+        /// Bind a QUIC endpoint that only admits the peer with `peer_fingerprint`
+        pub fn bind(
+            addr: SocketAddr,
+            identity: &QuicIdentity,
+            peer_fingerprint: &str,
+        ) -> Result<Self> {
+            let crypto = rustls::ServerConfig::builder()
+                .with_safe_default_cipher_suites()
+                .with_safe_default_kx_groups()
+                .with_protocol_versions(&[&rustls::version::TLS13])?
+                .with_client_cert_verifier(Arc::new(PinnedCertVerifier {
+                    fingerprint: peer_fingerprint.to_string(),
+                }))
+                .with_single_cert(identity.cert_chain(), identity.private_key())?;
+            let server_config = quinn::ServerConfig::with_crypto(Arc::new(crypto));
+            let endpoint = quinn::Endpoint::server(server_config, addr)?;
+
+            tracing::info!("QUIC fallback listening on {}", endpoint.local_addr()?);
+            Ok(Self { endpoint })
+        }
+
+        /// Local address of the endpoint
+        pub fn local_addr(&self) -> Result<SocketAddr> {
+            Ok(self.endpoint.local_addr()?)
+        }
+
+        /// Wait for the peer to connect
+        ///
+        /// Connections that fail the handshake, such as ones presenting
+        /// another certificate, are dropped and waiting continues.
+        pub async fn accept(&self) -> Result<QuicTransport> {
+            loop {
+                let connecting = self
+                    .endpoint
+                    .accept()
+                    .await
+                    .ok_or_else(|| anyhow::anyhow!("QUIC endpoint closed"))?;
+                let remote = connecting.remote_address();
+                match connecting.await {
+                    Ok(connection) => {
+                        tracing::info!("QUIC fallback connection from {}", remote);
+                        return Ok(QuicTransport { connection });
+                    }
+                    Err(e) => tracing::warn!("Rejected QUIC connection from {}: {}", remote, e),
+                }
+            }
+        }
+    }
+
+    /// Established QUIC connection carrying the data paths
+    pub struct QuicTransport {
+        connection: quinn::Connection,
+    }
+
+    impl QuicTransport {
+        /// Connect to a peer, pinning its certificate fingerprint and
+        /// presenting `identity`
+        pub async fn connect(
+            addr: SocketAddr,
+            identity: &QuicIdentity,
+            peer_fingerprint: &str,
+        ) -> Result<Self> {
+            let crypto = rustls::ClientConfig::builder()
+                .with_safe_default_cipher_suites()
+                .with_safe_default_kx_groups()
+                .with_protocol_versions(&[&rustls::version::TLS13])?
+                .with_custom_certificate_verifier(Arc::new(PinnedCertVerifier {
+                    fingerprint: peer_fingerprint.to_string(),
+                }))
+                .with_client_auth_cert(identity.cert_chain(), identity.private_key())?;
+
+            let bind_addr: SocketAddr = if addr.is_ipv6() {
+                "[::]:0".parse()?
+            } else {
+                "0.0.0.0:0".parse()?
+            };
+            let mut endpoint = quinn::Endpoint::client(bind_addr)?;
+            endpoint.set_default_client_config(quinn::ClientConfig::new(Arc::new(crypto)));
+
+            let connection = endpoint.connect(addr, SERVER_NAME)?.await?;
+            tracing::info!("QUIC fallback connected to {}", addr);
+            Ok(Self { connection })
+        }
+
+        /// Send one message on a data path
+        pub async fn send(&self, path: DataPath, data: &[u8]) -> Result<()> {
+            let mut stream = self.connection.open_uni().await?;
+            stream.write_all(&[path.tag()]).await?;
+            stream.write_all(data).await?;
+            stream.finish().await?;
+            Ok(())
+        }
+
+        /// Receive the next message from any data path
+        pub async fn recv(&self) -> Result<(DataPath, Vec<u8>)> {
+            let mut stream = self.connection.accept_uni().await?;
+            let mut message = stream.read_to_end(MAX_MESSAGE_SIZE + 1).await?;
+            if message.is_empty() {
+                return Err(anyhow::anyhow!("Empty QUIC message"));
+            }
+
+            let path = DataPath::from_tag(message[0])
+                .ok_or_else(|| anyhow::anyhow!("Unknown data path tag: {}", message[0]))?;
+            message.remove(0);
+            Ok((path, message))
+        }
+
+        /// Remote peer address
+        pub fn remote_address(&self) -> SocketAddr {
+            self.connection.remote_address()
+        }
+
+        /// Close the connection
+        pub fn close(&self) {
+            self.connection.close(0u32.into(), b"closed");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_data_channel_preferred() {
+        let config = DataTransportConfig::default();
+        let selected = select_data_transport(&config, async { Ok(()) }, async {
+            Err(anyhow::anyhow!("should not run"))
+        })
+        .await
+        .unwrap();
+        assert_eq!(selected, DataTransportType::DataChannel);
+    }
+
+    #[tokio::test]
+    async fn test_fallback_disabled() {
+        let config = DataTransportConfig {
+            data_channel_timeout: Duration::from_millis(10),
+            quic_fallback_enabled: false,
+        };
+        let result = select_data_transport(&config, std::future::pending::<Result<()>>(), async {
+            Ok(())
+        })
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[cfg(feature = "quic")]
+    #[tokio::test]
+    async fn test_quic_fallback_roundtrip() {
+        let (host, viewer) = (
+            QuicIdentity::generate().unwrap(),
+            QuicIdentity::generate().unwrap(),
+        );
+        let listener =
+            QuicListener::bind("127.0.0.1:0".parse().unwrap(), &host, viewer.fingerprint())
+                .unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let transport = listener.accept().await.unwrap();
+            let received = transport.recv().await.unwrap();
+            transport.send(DataPath::Control, b"ack").await.unwrap();
+            // Keep the connection open until the client has read the reply
+            let _ = transport.recv().await;
+            received
+        });
+
+        let config = DataTransportConfig {
+            data_channel_timeout: Duration::from_millis(10),
+            quic_fallback_enabled: true,
+        };
+        let client = tokio::sync::OnceCell::new();
+        let selected = select_data_transport(
+            &config,
+            async { Err(anyhow::anyhow!("SCTP blocked")) },
+            async {
+                client
+                    .set(QuicTransport::connect(addr, &viewer, host.fingerprint()).await?)
+                    .map_err(|_| anyhow::anyhow!("already connected"))
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(selected, DataTransportType::Quic);
+
+        let client = client.get().unwrap();
+        client.send(DataPath::File, b"chunk").await.unwrap();
+        assert_eq!(
+            client.recv().await.unwrap(),
+            (DataPath::Control, b"ack".to_vec())
+        );
+        client.close();
+
+        assert_eq!(server.await.unwrap(), (DataPath::File, b"chunk".to_vec()));
+    }
+
+    #[cfg(feature = "quic")]
+    #[tokio::test]
+    async fn test_quic_rejects_wrong_fingerprint() {
+        let (host, viewer) = (
+            QuicIdentity::generate().unwrap(),
+            QuicIdentity::generate().unwrap(),
+        );
+        let listener =
+            QuicListener::bind("127.0.0.1:0".parse().unwrap(), &host, viewer.fingerprint())
+                .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = tokio::spawn(async move { listener.accept().await.is_ok() });
+
+        // The viewer refuses a host it did not pin
+        let wrong = "00".repeat(32);
+        assert!(QuicTransport::connect(addr, &viewer, &wrong).await.is_err());
+
+        // The host refuses a client certificate it did not pin
+        let intruder = QuicIdentity::generate().unwrap();
+        if let Ok(transport) = QuicTransport::connect(addr, &intruder, host.fingerprint()).await {
+            assert!(transport.recv().await.is_err());
+        }
+        assert!(tokio::time::timeout(Duration::from_millis(500), accepted)
+            .await
+            .is_err());
+    }
+}
@@ -0,0 +1,228 @@
+//! Receive-Side Frame Statistics
+//!
+//! Tracks decode and render progress on the viewer and detects video
+//! freezes: any gap of more than `FREEZE_THRESHOLD` between two rendered
+//! frames. Each freeze is written to the connection event timeline together
+//! with the network events that happened during it, so a freeze report shows
+//! whether it coincided with e.g. a reconnect or ICE change.
+
+use crate::logging::{ConnectionEvent, ConnectionEventType, LogManager};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Render gap treated as a freeze
+pub const FREEZE_THRESHOLD: Duration = Duration::from_millis(500);
+
+/// Freezes kept for the session report
+const MAX_RECENT_FREEZES: usize = 50;
+
+/// Network events this long before a freeze are still considered related
+const CORRELATION_LEAD: chrono::Duration = chrono::Duration::seconds(1);
+
+/// Decode and freeze counters for one session
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FreezeStats {
+    pub frames_decoded: u64,
+    pub frames_rendered: u64,
+    pub decode_errors: u64,
+    pub average_decode_ms: f64,
+    pub freeze_count: u32,
+    pub total_freeze_ms: u64,
+    pub longest_freeze_ms: u64,
+}
+
+/// One detected freeze
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FreezeEvent {
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// Network events on the timeline during (or just before) the freeze
+    pub network_events: Vec<ConnectionEventType>,
+}
+
+/// Viewer-side frame statistics and freeze detector
+pub struct ReceiveStatsTracker {
+    session_id: String,
+    threshold: Duration,
+    last_rendered: Option<(Instant, DateTime<Utc>)>,
+    stats: FreezeStats,
+    recent_freezes: VecDeque<FreezeEvent>,
+    timeline: Option<Arc<LogManager>>,
+}
+
+impl ReceiveStatsTracker {
+    pub fn new(session_id: String) -> Self {
+        Self::with_threshold(session_id, FREEZE_THRESHOLD)
+    }
+
+    pub fn with_threshold(session_id: String, threshold: Duration) -> Self {
+        Self {
+            session_id,
+            threshold,
+            last_rendered: None,
+            stats: FreezeStats::default(),
+            recent_freezes: VecDeque::new(),
+            timeline: None,
+        }
+    }
+
+    /// Correlate freezes with, and record them on, this connection event log
+    pub fn set_timeline(&mut self, log_manager: Arc<LogManager>) {
+        self.timeline = Some(log_manager);
+    }
+
+    /// Record a decoded frame
+    pub fn on_frame_decoded(&mut self, decode_time: Duration) {
+        let decode_ms = decode_time.as_secs_f64() * 1000.0;
+        let n = self.stats.frames_decoded as f64;
+        self.stats.average_decode_ms = (self.stats.average_decode_ms * n + decode_ms) / (n + 1.0);
+        self.stats.frames_decoded += 1;
+    }
+
+    pub fn on_decode_error(&mut self) {
+        self.stats.decode_errors += 1;
+    }
+
+    /// Record a rendered frame; returns the freeze it ended, if any
+    pub fn on_frame_rendered(&mut self) -> Option<FreezeEvent> {
+        let now = (Instant::now(), Utc::now());
+        self.stats.frames_rendered += 1;
+
+        let (last_instant, last_wall) = self.last_rendered.replace(now)?;
+        let gap = now.0.duration_since(last_instant);
+        if gap <= self.threshold {
+            return None;
+        }
+
+        let duration_ms = gap.as_millis() as u64;
+        self.stats.freeze_count += 1;
+        self.stats.total_freeze_ms += duration_ms;
+        self.stats.longest_freeze_ms = self.stats.longest_freeze_ms.max(duration_ms);
+
+        let freeze = FreezeEvent {
+            started_at: last_wall,
+            duration_ms,
+            network_events: self.correlate(last_wall, now.1),
+        };
+        tracing::warn!(
+            "Video freeze of {} ms in session {} (network events: {:?})",
+            duration_ms,
+            self.session_id,
+            freeze.network_events
+        );
+
+        if let Some(timeline) = &self.timeline {
+            timeline.log_connection_event(
+                ConnectionEvent::new(ConnectionEventType::VideoFreeze)
+                    .with_session(&self.session_id)
+                    .with_details(serde_json::json!({
+                        "duration_ms": duration_ms,
+                        "network_events": freeze.network_events,
+                    })),
+            );
+        }
+
+        if self.recent_freezes.len() >= MAX_RECENT_FREEZES {
+            self.recent_freezes.pop_front();
+        }
+        self.recent_freezes.push_back(freeze.clone());
+        Some(freeze)
+    }
+
+    /// Length of the freeze in progress, if no frame has rendered recently
+    pub fn current_freeze(&self) -> Option<Duration> {
+        let (last, _) = self.last_rendered?;
+        let gap = last.elapsed();
+        (gap > self.threshold).then_some(gap)
+    }
+
+    pub fn stats(&self) -> &FreezeStats {
+        &self.stats
+    }
+
+    /// Recent freezes, oldest first
+    pub fn recent_freezes(&self) -> Vec<FreezeEvent> {
+        self.recent_freezes.iter().cloned().collect()
+    }
+
+    fn correlate(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<ConnectionEventType> {
+        let Some(timeline) = &self.timeline else {
+            return Vec::new();
+        };
+        let mut events: Vec<ConnectionEventType> = timeline
+            .get_connection_events(None)
+            .into_iter()
+            .filter(|e| {
+                e.session_id
+                    .as_deref()
+                    .is_none_or(|id| id == self.session_id)
+                    && !matches!(e.event_type, ConnectionEventType::VideoFreeze)
+                    && e.timestamp >= start - CORRELATION_LEAD
+                    && e.timestamp <= end
+            })
+            .map(|e| e.event_type)
+            .collect();
+        // The timeline is newest first
+        events.reverse();
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_stats() {
+        let mut tracker = ReceiveStatsTracker::new("s1".to_string());
+        tracker.on_frame_decoded(Duration::from_millis(4));
+        tracker.on_frame_decoded(Duration::from_millis(8));
+        tracker.on_decode_error();
+        assert!(tracker.on_frame_rendered().is_none());
+        assert!(tracker.on_frame_rendered().is_none());
+
+        let stats = tracker.stats();
+        assert_eq!(stats.frames_decoded, 2);
+        assert_eq!(stats.frames_rendered, 2);
+        assert_eq!(stats.decode_errors, 1);
+        assert!((stats.average_decode_ms - 6.0).abs() < 0.01);
+        assert_eq!(stats.freeze_count, 0);
+        assert!(tracker.current_freeze().is_none());
+    }
+
+    #[test]
+    fn test_freeze_correlated_with_network_events() {
+        let timeline = Arc::new(LogManager::default());
+        let mut tracker =
+            ReceiveStatsTracker::with_threshold("s1".to_string(), Duration::from_millis(30));
+        tracker.set_timeline(timeline.clone());
+
+        tracker.on_frame_rendered();
+        timeline.log_connection_event(
+            ConnectionEvent::new(ConnectionEventType::ReconnectAttempt).with_session("s1"),
+        );
+        timeline.log_connection_event(
+            ConnectionEvent::new(ConnectionEventType::QualityChanged).with_session("other"),
+        );
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(tracker.current_freeze().is_some());
+
+        let freeze = tracker.on_frame_rendered().unwrap();
+        assert!(freeze.duration_ms >= 50);
+        assert!(matches!(
+            freeze.network_events.as_slice(),
+            [ConnectionEventType::ReconnectAttempt]
+        ));
+        assert_eq!(tracker.stats().freeze_count, 1);
+        assert_eq!(tracker.stats().total_freeze_ms, freeze.duration_ms);
+
+        let latest = &timeline.get_connection_events(Some(1))[0];
+        assert!(matches!(
+            latest.event_type,
+            ConnectionEventType::VideoFreeze
+        ));
+    }
+}
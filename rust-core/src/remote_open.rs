@@ -0,0 +1,750 @@
+//! Open Files on the Host
+//!
+//! Lets a controller ask the host to open a file, typically an installer it
+//! just transferred, with the host's default handler (ShellExecute, `open`,
+//! `xdg-open`). Every request needs the session's `SystemControl`
+//! permission, an allow-listed extension and an explicit confirmation by the
+//! person at the host; nothing is ever opened automatically. Only files the
+//! session received over file transfer can be opened, never arbitrary host
+//! paths. The file is hashed when requested. On approval it is copied into a
+//! staging directory only the host user can write, hashing the bytes as they
+//! are copied, and only that copy is opened; the open is refused if the hash
+//! changed. The received file therefore cannot be swapped between the check
+//! and the open. Every step is written to the audit log.
+
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use crate::logging::{LogEntry, LogLevel, LogManager};
+use crate::session_manager::{Permission, Session};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long a request waits for the host's confirmation
+pub const OPEN_CONFIRMATION_TIMEOUT_SECS: i64 = 120;
+
+/// Staged copies older than this are removed when the next file is staged;
+/// younger ones may still be in use by the handler they were opened with
+const STAGED_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Opens a file with the platform's default handler
+pub trait FileOpener: Send + Sync {
+    fn open(&self, path: &Path) -> Result<()>;
+}
+
+/// ShellExecute on Windows, `open` on macOS, `xdg-open` elsewhere
+pub struct SystemFileOpener;
+
+impl FileOpener for SystemFileOpener {
+    fn open(&self, path: &Path) -> Result<()> {
+        #[cfg(target_os = "windows")]
+        {
+            shell_open(path.as_os_str())
+                .with_context(|| format!("Failed to open {}", path.display()))
+        }
+        #[cfg(not(target_os = "windows"))]
+        {
+            #[cfg(target_os = "macos")]
+            let mut command = std::process::Command::new("open");
+            #[cfg(not(target_os = "macos"))]
+            let mut command = std::process::Command::new("xdg-open");
+
+            command
+                .arg(path)
+                .spawn()
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            Ok(())
+        }
+    }
+}
+
+/// Open a file or link with its default handler through ShellExecuteW
+///
+/// `target` is passed as one argument and never reaches `cmd.exe`, whose
+/// parsing would run anything after a `&` in a file name or query string.
+#[cfg(target_os = "windows")]
+pub(crate) fn shell_open(target: &std::ffi::OsStr) -> Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    const SW_SHOWNORMAL: i32 = 1;
+
+    extern "system" {
+        fn ShellExecuteW(
+            hwnd: isize,
+            operation: *const u16,
+            file: *const u16,
+            parameters: *const u16,
+            directory: *const u16,
+            show_cmd: i32,
+        ) -> isize;
+    }
+
+    let file: Vec<u16> = target.encode_wide().collect();
+    if file.contains(&0) {
+        return Err(anyhow::anyhow!("Target contains a NUL character"));
+    }
+    let file: Vec<u16> = file.into_iter().chain(Some(0)).collect();
+    let operation: Vec<u16> = "open".encode_utf16().chain(Some(0)).collect();
+    // SAFETY: both strings are NUL-terminated and outlive the call; the
+    // optional arguments are null
+    let result = unsafe {
+        ShellExecuteW(
+            0,
+            operation.as_ptr(),
+            file.as_ptr(),
+            std::ptr::null(),
+            std::ptr::null(),
+            SW_SHOWNORMAL,
+        )
+    };
+    // Values up to 32 are error codes
+    if result <= 32 {
+        return Err(anyhow::anyhow!("ShellExecuteW failed: {}", result));
+    }
+    Ok(())
+}
+
+/// Which files may be opened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteOpenPolicy {
+    /// Lower-case extensions (without dot) that may be opened
+    pub allowed_extensions: Vec<String>,
+}
+
+impl Default for RemoteOpenPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_extensions: [
+                "msi", "exe", "pkg", "dmg", "deb", "rpm", "pdf", "txt", "png", "jpg",
+            ]
+            .iter()
+            .map(|e| e.to_string())
+            .collect(),
+        }
+    }
+}
+
+/// Request waiting for the host's confirmation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenRequest {
+    pub request_id: String,
+    pub session_id: String,
+    pub requested_by: String,
+    pub path: PathBuf,
+    pub size: u64,
+    /// SHA-256 of the file when it was requested
+    pub sha256: String,
+    pub requested_at: DateTime<Utc>,
+}
+
+impl OpenRequest {
+    pub fn is_expired(&self) -> bool {
+        Utc::now() - self.requested_at > chrono::Duration::seconds(OPEN_CONFIRMATION_TIMEOUT_SECS)
+    }
+}
+
+/// Outcome of the host's response to a request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OpenOutcome {
+    Opened {
+        sha256: String,
+    },
+    Denied,
+    /// Approved, but the file changed since it was requested
+    FileChanged {
+        expected: String,
+        actual: String,
+    },
+}
+
+/// Remote open activity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RemoteOpenEvent {
+    /// The host UI must ask the user to approve or deny
+    ConfirmationRequired(OpenRequest),
+    Completed {
+        request_id: String,
+        outcome: OpenOutcome,
+    },
+}
+
+impl EventType for RemoteOpenEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            RemoteOpenEvent::ConfirmationRequired(_) => "ConfirmationRequired",
+            RemoteOpenEvent::Completed { .. } => "Completed",
+        }
+    }
+}
+
+/// Gatekeeper for opening files on the host
+pub struct RemoteOpenManager {
+    policy: RwLock<RemoteOpenPolicy>,
+    pending: RwLock<HashMap<String, OpenRequest>>,
+    /// session_id -> canonical paths of files it received
+    received: RwLock<HashMap<String, HashSet<PathBuf>>>,
+    opener: Box<dyn FileOpener>,
+    /// Where approved files are copied and opened from
+    staging_dir: PathBuf,
+    audit_log: RwLock<Option<Arc<LogManager>>>,
+    events: EventBus<RemoteOpenEvent>,
+}
+
+impl RemoteOpenManager {
+    pub fn new() -> Self {
+        Self::with_opener(Box::new(SystemFileOpener))
+    }
+
+    pub fn with_opener(opener: Box<dyn FileOpener>) -> Self {
+        Self {
+            policy: RwLock::new(RemoteOpenPolicy::default()),
+            pending: RwLock::new(HashMap::new()),
+            received: RwLock::new(HashMap::new()),
+            opener,
+            staging_dir: std::env::temp_dir().join("cec-remote-open"),
+            audit_log: RwLock::new(None),
+            events: EventBus::new(),
+        }
+    }
+
+    /// Copy approved files into `dir` instead of the temporary directory
+    pub fn with_staging_dir(mut self, dir: PathBuf) -> Self {
+        self.staging_dir = dir;
+        self
+    }
+
+    pub fn set_policy(&self, policy: RemoteOpenPolicy) {
+        tracing::info!("Remote open policy: {:?}", policy.allowed_extensions);
+        if let Ok(mut current) = self.policy.write() {
+            *current = policy;
+        }
+    }
+
+    pub fn set_audit_log(&self, log_manager: Arc<LogManager>) {
+        if let Ok(mut audit_log) = self.audit_log.write() {
+            *audit_log = Some(log_manager);
+        }
+    }
+
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<RemoteOpenEvent> {
+        self.events.subscribe(options)
+    }
+
+    /// Record a file `session_id` received over file transfer, making it
+    /// eligible for `request_open`
+    ///
+    /// The file does not have to exist yet; its folder does. Symlinks and
+    /// `..` in the folder are resolved, so the file is matched by where it
+    /// really is.
+    pub fn record_received(&self, session_id: &str, path: &Path) -> Result<()> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("Not a file path: {}", path.display()))?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let dir = dir
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", dir.display()))?;
+        self.received
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?
+            .entry(session_id.to_string())
+            .or_default()
+            .insert(dir.join(name));
+        Ok(())
+    }
+
+    /// Ask the host to open `path` on behalf of `requested_by`
+    ///
+    /// Fails without asking the host if the session lacks `SystemControl`,
+    /// the extension is not allowed or the file is not one the session
+    /// received (see `record_received`).
+    pub fn request_open(
+        &self,
+        session: &Session,
+        requested_by: &str,
+        path: &Path,
+    ) -> Result<OpenRequest> {
+        let canonical = path.canonicalize().ok();
+        let rejection = if !session.permissions.contains(&Permission::SystemControl) {
+            Some("System control not permitted for session")
+        } else if !self.extension_allowed(canonical.as_deref().unwrap_or(path)) {
+            Some("File type not allowed")
+        } else if !canonical
+            .as_deref()
+            .is_some_and(|canonical| self.was_received(&session.session_id, canonical))
+        {
+            Some("File was not received in this session")
+        } else {
+            None
+        };
+        if let Some(reason) = rejection {
+            self.audit(
+                LogLevel::Warn,
+                "Remote open rejected",
+                serde_json::json!({
+                    "session_id": session.session_id,
+                    "requested_by": requested_by,
+                    "path": path,
+                    "reason": reason,
+                }),
+            );
+            return Err(anyhow::anyhow!("{}: {}", reason, path.display()));
+        }
+        let path = canonical.unwrap_or_else(|| path.to_path_buf());
+
+        let (sha256, size) = hash_file(&path)?;
+        let request = OpenRequest {
+            request_id: Uuid::new_v4().to_string(),
+            session_id: session.session_id.clone(),
+            requested_by: requested_by.to_string(),
+            path,
+            size,
+            sha256,
+            requested_at: Utc::now(),
+        };
+        self.pending
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?
+            .insert(request.request_id.clone(), request.clone());
+
+        self.audit(
+            LogLevel::Info,
+            "Remote open requested",
+            serde_json::json!({
+                "request_id": request.request_id,
+                "session_id": request.session_id,
+                "requested_by": request.requested_by,
+                "path": request.path,
+                "size": request.size,
+                "sha256": request.sha256,
+            }),
+        );
+        self.events
+            .publish(RemoteOpenEvent::ConfirmationRequired(request.clone()));
+        Ok(request)
+    }
+
+    /// Requests still waiting for confirmation
+    pub fn pending_requests(&self) -> Vec<OpenRequest> {
+        self.pending
+            .read()
+            .map(|pending| {
+                pending
+                    .values()
+                    .filter(|r| !r.is_expired())
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Host's answer to a request; the file is opened only if approved and
+    /// unchanged since it was requested
+    ///
+    /// What is opened is a private copy of the file, hashed while copying.
+    pub fn respond(&self, request_id: &str, approve: bool) -> Result<OpenOutcome> {
+        let request = self
+            .pending
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?
+            .remove(request_id)
+            .ok_or_else(|| anyhow::anyhow!("Open request not found: {}", request_id))?;
+        if request.is_expired() {
+            self.audit(
+                LogLevel::Warn,
+                "Remote open expired",
+                serde_json::json!({ "request_id": request_id }),
+            );
+            return Err(anyhow::anyhow!("Open request expired: {}", request_id));
+        }
+
+        let mut staged_path = None;
+        let outcome = if approve {
+            let (staged, actual) = self.stage(&request.path)?;
+            if actual == request.sha256 {
+                self.opener.open(&staged)?;
+                staged_path = Some(staged);
+                OpenOutcome::Opened { sha256: actual }
+            } else {
+                if let Some(dir) = staged.parent() {
+                    let _ = std::fs::remove_dir_all(dir);
+                }
+                OpenOutcome::FileChanged {
+                    expected: request.sha256.clone(),
+                    actual,
+                }
+            }
+        } else {
+            OpenOutcome::Denied
+        };
+
+        let (level, message) = match &outcome {
+            OpenOutcome::Opened { .. } => (LogLevel::Info, "Remote open executed"),
+            OpenOutcome::Denied => (LogLevel::Info, "Remote open denied by host"),
+            OpenOutcome::FileChanged { .. } => {
+                (LogLevel::Warn, "Remote open refused: file changed")
+            }
+        };
+        self.audit(
+            level,
+            message,
+            serde_json::json!({
+                "request_id": request.request_id,
+                "session_id": request.session_id,
+                "requested_by": request.requested_by,
+                "path": request.path,
+                "staged_path": staged_path,
+                "outcome": outcome,
+            }),
+        );
+        self.events.publish(RemoteOpenEvent::Completed {
+            request_id: request.request_id,
+            outcome: outcome.clone(),
+        });
+        Ok(outcome)
+    }
+
+    /// Drop pending requests and received files of an ended session
+    pub fn cancel_session(&self, session_id: &str) {
+        if let Ok(mut pending) = self.pending.write() {
+            pending.retain(|_, r| r.session_id != session_id);
+        }
+        if let Ok(mut received) = self.received.write() {
+            received.remove(session_id);
+        }
+    }
+
+    /// Copy `path` into a fresh private folder under the staging directory,
+    /// keeping its name, and return the copy's path and SHA-256
+    ///
+    /// The copy is read-only, in a folder only this user can write.
+    fn stage(&self, path: &Path) -> Result<(PathBuf, String)> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| anyhow::anyhow!("Not a file path: {}", path.display()))?;
+        create_private_dir(&self.staging_dir)?;
+        prune_staged(&self.staging_dir);
+        let dir = self.staging_dir.join(Uuid::new_v4().to_string());
+        create_private_dir(&dir)?;
+        let staged = dir.join(name);
+
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut copy = options
+            .open(&staged)
+            .with_context(|| format!("Failed to create {}", staged.display()))?;
+        let (sha256, _) = hash_file_into(path, Some(&mut copy))?;
+        copy.sync_all()?;
+        let mut permissions = copy.metadata()?.permissions();
+        permissions.set_readonly(true);
+        copy.set_permissions(permissions)?;
+        Ok((staged, sha256))
+    }
+
+    fn was_received(&self, session_id: &str, canonical: &Path) -> bool {
+        self.received
+            .read()
+            .map(|received| {
+                received
+                    .get(session_id)
+                    .is_some_and(|files| files.contains(canonical))
+            })
+            .unwrap_or(false)
+    }
+
+    fn extension_allowed(&self, path: &Path) -> bool {
+        let Some(extension) = path.extension().and_then(|e| e.to_str()) else {
+            return false;
+        };
+        let extension = extension.to_ascii_lowercase();
+        self.policy
+            .read()
+            .map(|policy| policy.allowed_extensions.contains(&extension))
+            .unwrap_or(false)
+    }
+
+    fn audit(&self, level: LogLevel, message: &str, metadata: serde_json::Value) {
+        if let Some(log_manager) = self.audit_log.read().ok().and_then(|l| l.clone()) {
+            log_manager.log(LogEntry::new(level, "audit", message).with_metadata(metadata));
+        }
+    }
+}
+
+impl Default for RemoteOpenManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// SHA-256 (hex) and size of a file
+fn hash_file(path: &Path) -> Result<(String, u64)> {
+    hash_file_into(path, None)
+}
+
+/// `hash_file`, also writing the hashed bytes to `copy`
+fn hash_file_into(path: &Path, mut copy: Option<&mut std::fs::File>) -> Result<(String, u64)> {
+    let mut file =
+        std::fs::File::open(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    let mut size = 0u64;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        if let Some(copy) = copy.as_mut() {
+            copy.write_all(&buffer[..read])?;
+        }
+        size += read as u64;
+    }
+    Ok((hex::encode(hasher.finalize()), size))
+}
+
+/// Create `dir` accessible to this user only, refusing one that is a
+/// symlink or that another user owns or can write
+fn create_private_dir(dir: &Path) -> Result<()> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder
+        .create(dir)
+        .with_context(|| format!("Failed to create {}", dir.display()))?;
+    let metadata = std::fs::symlink_metadata(dir)?;
+    #[cfg(unix)]
+    let foreign = {
+        use std::os::unix::fs::MetadataExt;
+        extern "C" {
+            fn geteuid() -> u32;
+        }
+        // SAFETY: geteuid has no preconditions
+        metadata.uid() != unsafe { geteuid() } || metadata.mode() & 0o022 != 0
+    };
+    #[cfg(not(unix))]
+    let foreign = false;
+    if !metadata.is_dir() || foreign {
+        return Err(anyhow::anyhow!(
+            "Refusing to stage files in {}, which other users can change",
+            dir.display()
+        ));
+    }
+    Ok(())
+}
+
+/// Remove staged copies past `STAGED_RETENTION`
+fn prune_staged(staging_dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(staging_dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let expired = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > STAGED_RETENTION);
+        if expired {
+            let _ = std::fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingOpener {
+        opened: Arc<Mutex<Vec<PathBuf>>>,
+    }
+
+    impl FileOpener for RecordingOpener {
+        fn open(&self, path: &Path) -> Result<()> {
+            self.opened.lock().unwrap().push(path.to_path_buf());
+            Ok(())
+        }
+    }
+
+    fn setup() -> (RemoteOpenManager, Arc<Mutex<Vec<PathBuf>>>, Arc<LogManager>) {
+        let opener = RecordingOpener::default();
+        let opened = opener.opened.clone();
+        let manager = RemoteOpenManager::with_opener(Box::new(opener)).with_staging_dir(
+            std::env::temp_dir().join(format!("cec-open-staging-{}", Uuid::new_v4())),
+        );
+        let audit_log = Arc::new(LogManager::default());
+        manager.set_audit_log(audit_log.clone());
+        (manager, opened, audit_log)
+    }
+
+    fn session(permissions: Vec<Permission>) -> Session {
+        Session::new("controller".to_string(), "host".to_string(), permissions)
+    }
+
+    #[test]
+    fn test_open_requires_permission_extension_and_confirmation() {
+        let (manager, opened, audit_log) = setup();
+        let dir = std::env::temp_dir().join(format!("cec-open-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let installer = dir.join("setup.msi");
+        let script = dir.join("run.ps1");
+        std::fs::write(&installer, b"installer").unwrap();
+        std::fs::write(&script, b"script").unwrap();
+
+        let view_only = session(vec![Permission::ScreenView]);
+        assert!(manager
+            .request_open(&view_only, "controller", &installer)
+            .is_err());
+        let control = session(vec![Permission::SystemControl]);
+        manager
+            .record_received(&control.session_id, &installer)
+            .unwrap();
+        manager
+            .record_received(&control.session_id, &script)
+            .unwrap();
+        assert!(manager
+            .request_open(&control, "controller", &script)
+            .is_err());
+
+        let mut events = manager.subscribe(SubscriptionOptions::all());
+        let denied = manager
+            .request_open(&control, "controller", &installer)
+            .unwrap();
+        assert!(matches!(
+            events.try_recv(),
+            Some(RemoteOpenEvent::ConfirmationRequired(_))
+        ));
+        assert_eq!(
+            manager.respond(&denied.request_id, false).unwrap(),
+            OpenOutcome::Denied
+        );
+
+        // Each open needs its own confirmation
+        let approved = manager
+            .request_open(&control, "controller", &installer)
+            .unwrap();
+        assert_eq!(manager.pending_requests().len(), 1);
+        let outcome = manager.respond(&approved.request_id, true).unwrap();
+        assert_eq!(
+            outcome,
+            OpenOutcome::Opened {
+                sha256: approved.sha256.clone()
+            }
+        );
+        // A private, read-only copy is opened, never the received file
+        let staged = opened.lock().unwrap().clone();
+        assert_eq!(staged.len(), 1);
+        assert!(staged[0].starts_with(&manager.staging_dir));
+        assert_eq!(staged[0].file_name(), installer.file_name());
+        assert_eq!(std::fs::read(&staged[0]).unwrap(), b"installer");
+        assert!(std::fs::metadata(&staged[0])
+            .unwrap()
+            .permissions()
+            .readonly());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let dir_mode = std::fs::metadata(staged[0].parent().unwrap())
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(dir_mode & 0o777, 0o700);
+        }
+        assert!(manager.respond(&approved.request_id, true).is_err());
+
+        let audit = audit_log.get_logs(None, None);
+        assert_eq!(audit[0].message, "Remote open executed");
+        assert!(audit[0].format().contains(&approved.sha256));
+        std::fs::remove_dir_all(dir).ok();
+        std::fs::remove_dir_all(&manager.staging_dir).ok();
+    }
+
+    #[test]
+    fn test_file_swapped_after_request_is_not_opened() {
+        let (manager, opened, _) = setup();
+        let path = std::env::temp_dir().join(format!("cec-open-{}.pkg", Uuid::new_v4()));
+        std::fs::write(&path, b"original").unwrap();
+
+        let control = session(vec![Permission::SystemControl]);
+        manager.record_received(&control.session_id, &path).unwrap();
+        let request = manager.request_open(&control, "controller", &path).unwrap();
+        std::fs::write(&path, b"tampered").unwrap();
+
+        assert!(matches!(
+            manager.respond(&request.request_id, true).unwrap(),
+            OpenOutcome::FileChanged { .. }
+        ));
+        assert!(opened.lock().unwrap().is_empty());
+        // The refused copy is not left behind
+        assert_eq!(std::fs::read_dir(&manager.staging_dir).unwrap().count(), 0);
+        std::fs::remove_file(path).ok();
+        std::fs::remove_dir_all(&manager.staging_dir).ok();
+    }
+
+    #[test]
+    fn test_only_files_received_in_the_session_can_be_opened() {
+        let (manager, _, _) = setup();
+        let dir = std::env::temp_dir().join(format!("cec-open-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let received = dir.join("setup.exe");
+        let elsewhere = dir.join("other.exe");
+        std::fs::write(&received, b"installer").unwrap();
+        std::fs::write(&elsewhere, b"program").unwrap();
+
+        let control = session(vec![Permission::SystemControl]);
+        let other = session(vec![Permission::SystemControl]);
+        // Recorded before the data arrived
+        let pending = dir.join("later.pdf");
+        manager
+            .record_received(&control.session_id, &pending)
+            .unwrap();
+        manager
+            .record_received(&control.session_id, &dir.join("sub/../setup.exe"))
+            .unwrap();
+
+        assert!(manager
+            .request_open(&control, "controller", &elsewhere)
+            .is_err());
+        assert!(manager
+            .request_open(&other, "controller", &received)
+            .is_err());
+        assert!(manager
+            .request_open(&control, "controller", &pending)
+            .is_err());
+        std::fs::write(&pending, b"document").unwrap();
+        assert!(manager
+            .request_open(&control, "controller", &pending)
+            .is_ok());
+
+        // Matched by where the file really is
+        let request = manager
+            .request_open(&control, "controller", &dir.join("sub/../setup.exe"))
+            .unwrap();
+        assert_eq!(request.path, received.canonicalize().unwrap());
+
+        manager.cancel_session(&control.session_id);
+        assert!(manager
+            .request_open(&control, "controller", &received)
+            .is_err());
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,504 @@
+//! Open Links on the Host
+//!
+//! A viewer can send the host a link, for example documentation it wants
+//! the person at the host to look at, and have it opened in the host's
+//! default browser. Only `http` and `https` links are accepted; links with
+//! embedded credentials are refused because they are a common way to
+//! disguise the real destination. Sending links needs only the session's
+//! `OpenUrl` permission, not input control, but every link still waits for
+//! an explicit confirmation at the host, and each viewer is rate limited so
+//! a busy or hostile viewer cannot flood the host with prompts. Requests,
+//! rejections and the host's answers are written to the audit log.
+
+use crate::clock::{system_clock, SharedClock};
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use crate::logging::{LogEntry, LogLevel, LogManager};
+use crate::session_manager::{Permission, Session};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use url::Url;
+use uuid::Uuid;
+
+/// URL schemes that may be opened; everything else is refused
+pub const ALLOWED_URL_SCHEMES: [&str; 2] = ["http", "https"];
+
+/// How long a link waits for the host's confirmation
+pub const URL_CONFIRMATION_TIMEOUT_SECS: i64 = 120;
+
+/// Opens a link in the platform's default browser
+pub trait UrlOpener: Send + Sync {
+    fn open(&self, url: &Url) -> Result<()>;
+}
+
+/// ShellExecute on Windows, `open` on macOS, `xdg-open` elsewhere
+pub struct SystemUrlOpener;
+
+impl UrlOpener for SystemUrlOpener {
+    fn open(&self, url: &Url) -> Result<()> {
+        #[cfg(target_os = "windows")]
+        {
+            crate::remote_open::shell_open(std::ffi::OsStr::new(url.as_str()))
+                .with_context(|| format!("Failed to open {}", url))
+        }
+        #[cfg(not(target_os = "windows"))]
+        {
+            #[cfg(target_os = "macos")]
+            let mut command = std::process::Command::new("open");
+            #[cfg(not(target_os = "macos"))]
+            let mut command = std::process::Command::new("xdg-open");
+
+            command
+                .arg(url.as_str())
+                .spawn()
+                .with_context(|| format!("Failed to open {}", url))?;
+            Ok(())
+        }
+    }
+}
+
+/// Limits on links sent to the host
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RemoteUrlPolicy {
+    /// Links one viewer may send per `rate_window_secs`
+    pub max_requests: u32,
+    pub rate_window_secs: u64,
+    /// Longest accepted link, in bytes
+    pub max_url_len: usize,
+}
+
+impl Default for RemoteUrlPolicy {
+    fn default() -> Self {
+        Self {
+            max_requests: 5,
+            rate_window_secs: 60,
+            max_url_len: 2048,
+        }
+    }
+}
+
+/// Link waiting for the host's confirmation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UrlOpenRequest {
+    pub request_id: String,
+    pub session_id: String,
+    pub requested_by: String,
+    /// Normalized link, as it will be opened
+    pub url: String,
+    /// Host name, shown prominently in the confirmation
+    pub host: String,
+    pub requested_at: DateTime<Utc>,
+}
+
+/// Outcome of the host's response to a link
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum UrlOpenOutcome {
+    Opened,
+    Denied,
+}
+
+/// Remote link activity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RemoteUrlEvent {
+    /// The host UI must ask the user to approve or deny
+    ConfirmationRequired(UrlOpenRequest),
+    Completed {
+        request_id: String,
+        outcome: UrlOpenOutcome,
+    },
+}
+
+impl EventType for RemoteUrlEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            RemoteUrlEvent::ConfirmationRequired(_) => "ConfirmationRequired",
+            RemoteUrlEvent::Completed { .. } => "Completed",
+        }
+    }
+}
+
+/// Gatekeeper for opening viewer links on the host
+pub struct RemoteUrlManager {
+    policy: RwLock<RemoteUrlPolicy>,
+    pending: RwLock<HashMap<String, UrlOpenRequest>>,
+    /// Accepted requests per viewer inside the current rate window
+    recent: RwLock<HashMap<String, VecDeque<Instant>>>,
+    opener: Box<dyn UrlOpener>,
+    audit_log: RwLock<Option<Arc<LogManager>>>,
+    events: EventBus<RemoteUrlEvent>,
+    clock: SharedClock,
+}
+
+impl RemoteUrlManager {
+    pub fn new() -> Self {
+        Self::with_opener(Box::new(SystemUrlOpener))
+    }
+
+    pub fn with_opener(opener: Box<dyn UrlOpener>) -> Self {
+        Self {
+            policy: RwLock::new(RemoteUrlPolicy::default()),
+            pending: RwLock::new(HashMap::new()),
+            recent: RwLock::new(HashMap::new()),
+            opener,
+            audit_log: RwLock::new(None),
+            events: EventBus::new(),
+            clock: system_clock(),
+        }
+    }
+
+    /// Read the time from `clock` instead of the system clocks
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn set_policy(&self, policy: RemoteUrlPolicy) {
+        tracing::info!(
+            "Remote URL policy: {} per {}s",
+            policy.max_requests,
+            policy.rate_window_secs
+        );
+        if let Ok(mut current) = self.policy.write() {
+            *current = policy;
+        }
+    }
+
+    pub fn set_audit_log(&self, log_manager: Arc<LogManager>) {
+        if let Ok(mut audit_log) = self.audit_log.write() {
+            *audit_log = Some(log_manager);
+        }
+    }
+
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<RemoteUrlEvent> {
+        self.events.subscribe(options)
+    }
+
+    /// Ask the host to open `url` on behalf of `requested_by`
+    ///
+    /// Fails without asking the host if the session lacks `OpenUrl`, the
+    /// link is not an allowed web link, or the viewer is over its rate.
+    pub fn request_open(
+        &self,
+        session: &Session,
+        requested_by: &str,
+        url: &str,
+    ) -> Result<UrlOpenRequest> {
+        let parsed = match self.check(session, requested_by, url) {
+            Ok(parsed) => parsed,
+            Err(reason) => {
+                self.audit(
+                    LogLevel::Warn,
+                    "Remote URL rejected",
+                    serde_json::json!({
+                        "session_id": session.session_id,
+                        "requested_by": requested_by,
+                        "url": url,
+                        "reason": reason,
+                    }),
+                );
+                return Err(anyhow::anyhow!("{}", reason));
+            }
+        };
+
+        let request = UrlOpenRequest {
+            request_id: Uuid::new_v4().to_string(),
+            session_id: session.session_id.clone(),
+            requested_by: requested_by.to_string(),
+            host: parsed.host_str().unwrap_or_default().to_string(),
+            url: parsed.into(),
+            requested_at: self.clock.utc(),
+        };
+        self.pending
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?
+            .insert(request.request_id.clone(), request.clone());
+
+        self.audit(
+            LogLevel::Info,
+            "Remote URL requested",
+            serde_json::json!({
+                "request_id": request.request_id,
+                "session_id": request.session_id,
+                "requested_by": request.requested_by,
+                "url": request.url,
+            }),
+        );
+        self.events
+            .publish(RemoteUrlEvent::ConfirmationRequired(request.clone()));
+        Ok(request)
+    }
+
+    /// Links still waiting for confirmation
+    pub fn pending_requests(&self) -> Vec<UrlOpenRequest> {
+        self.pending
+            .read()
+            .map(|pending| {
+                pending
+                    .values()
+                    .filter(|r| !self.is_expired(r))
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Host's answer to a link; it is opened only if approved
+    pub fn respond(&self, request_id: &str, approve: bool) -> Result<UrlOpenOutcome> {
+        let request = self
+            .pending
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?
+            .remove(request_id)
+            .ok_or_else(|| anyhow::anyhow!("URL request not found: {}", request_id))?;
+        if self.is_expired(&request) {
+            self.audit(
+                LogLevel::Warn,
+                "Remote URL expired",
+                serde_json::json!({ "request_id": request_id }),
+            );
+            return Err(anyhow::anyhow!("URL request expired: {}", request_id));
+        }
+
+        let outcome = if approve {
+            self.opener.open(&Url::parse(&request.url)?)?;
+            UrlOpenOutcome::Opened
+        } else {
+            UrlOpenOutcome::Denied
+        };
+
+        let message = match outcome {
+            UrlOpenOutcome::Opened => "Remote URL opened",
+            UrlOpenOutcome::Denied => "Remote URL denied by host",
+        };
+        self.audit(
+            LogLevel::Info,
+            message,
+            serde_json::json!({
+                "request_id": request.request_id,
+                "session_id": request.session_id,
+                "requested_by": request.requested_by,
+                "url": request.url,
+            }),
+        );
+        self.events.publish(RemoteUrlEvent::Completed {
+            request_id: request.request_id,
+            outcome,
+        });
+        Ok(outcome)
+    }
+
+    /// Drop pending links of an ended session
+    pub fn cancel_session(&self, session_id: &str) {
+        if let Ok(mut pending) = self.pending.write() {
+            pending.retain(|_, r| r.session_id != session_id);
+        }
+    }
+
+    /// Validate a request and count it against the viewer's rate
+    fn check(&self, session: &Session, requested_by: &str, url: &str) -> Result<Url, String> {
+        if !session.permissions.contains(&Permission::OpenUrl) {
+            return Err("Opening links not permitted for session".to_string());
+        }
+        let policy = self
+            .policy
+            .read()
+            .map(|p| p.clone())
+            .map_err(|_| "Failed to acquire lock".to_string())?;
+        if url.len() > policy.max_url_len {
+            return Err(format!("Link longer than {} bytes", policy.max_url_len));
+        }
+        let parsed = Url::parse(url.trim()).map_err(|e| format!("Invalid link: {}", e))?;
+        if !ALLOWED_URL_SCHEMES.contains(&parsed.scheme()) {
+            return Err(format!("Link scheme not allowed: {}", parsed.scheme()));
+        }
+        if parsed.host_str().is_none_or(str::is_empty) {
+            return Err("Link has no host".to_string());
+        }
+        if !parsed.username().is_empty() || parsed.password().is_some() {
+            return Err("Links with embedded credentials are not allowed".to_string());
+        }
+
+        let now = self.clock.instant();
+        let window = Duration::from_secs(policy.rate_window_secs);
+        let mut recent = self
+            .recent
+            .write()
+            .map_err(|_| "Failed to acquire lock".to_string())?;
+        let sent = recent.entry(requested_by.to_string()).or_default();
+        while sent
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) >= window)
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= policy.max_requests as usize {
+            return Err(format!(
+                "Too many links; at most {} per {}s",
+                policy.max_requests, policy.rate_window_secs
+            ));
+        }
+        sent.push_back(now);
+        Ok(parsed)
+    }
+
+    fn is_expired(&self, request: &UrlOpenRequest) -> bool {
+        self.clock.utc() - request.requested_at
+            > chrono::Duration::seconds(URL_CONFIRMATION_TIMEOUT_SECS)
+    }
+
+    fn audit(&self, level: LogLevel, message: &str, metadata: serde_json::Value) {
+        if let Some(log_manager) = self.audit_log.read().ok().and_then(|l| l.clone()) {
+            log_manager.log(LogEntry::new(level, "audit", message).with_metadata(metadata));
+        }
+    }
+}
+
+impl Default for RemoteUrlManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct RecordingOpener {
+        opened: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl UrlOpener for RecordingOpener {
+        fn open(&self, url: &Url) -> Result<()> {
+            self.opened.lock().unwrap().push(url.to_string());
+            Ok(())
+        }
+    }
+
+    type OpenedUrls = Arc<Mutex<Vec<String>>>;
+
+    fn setup() -> (
+        RemoteUrlManager,
+        OpenedUrls,
+        Arc<LogManager>,
+        Arc<TestClock>,
+    ) {
+        let opener = RecordingOpener::default();
+        let opened = opener.opened.clone();
+        let clock = TestClock::shared();
+        let manager = RemoteUrlManager::with_opener(Box::new(opener)).with_clock(clock.clone());
+        let audit_log = Arc::new(LogManager::default());
+        manager.set_audit_log(audit_log.clone());
+        (manager, opened, audit_log, clock)
+    }
+
+    fn session(permissions: Vec<Permission>) -> Session {
+        Session::new("controller".to_string(), "host".to_string(), permissions)
+    }
+
+    #[test]
+    fn test_open_requires_permission_web_scheme_and_confirmation() {
+        let (manager, opened, audit_log, _) = setup();
+
+        // Input control alone does not allow sending links
+        let control = session(vec![Permission::InputControl]);
+        assert!(manager
+            .request_open(&control, "controller", "https://example.com")
+            .is_err());
+
+        let links = session(vec![Permission::OpenUrl]);
+        for rejected in [
+            "file:///etc/passwd",
+            "javascript:alert(1)",
+            "ftp://example.com/",
+            "https://bank.example@evil.example/",
+            "not a url",
+        ] {
+            assert!(
+                manager
+                    .request_open(&links, "controller", rejected)
+                    .is_err(),
+                "{} accepted",
+                rejected
+            );
+        }
+
+        let mut events = manager.subscribe(SubscriptionOptions::all());
+        let denied = manager
+            .request_open(&links, "controller", "https://example.com/docs")
+            .unwrap();
+        assert_eq!(denied.host, "example.com");
+        assert!(matches!(
+            events.try_recv(),
+            Some(RemoteUrlEvent::ConfirmationRequired(_))
+        ));
+        assert_eq!(
+            manager.respond(&denied.request_id, false).unwrap(),
+            UrlOpenOutcome::Denied
+        );
+        assert!(opened.lock().unwrap().is_empty());
+
+        let approved = manager
+            .request_open(&links, "controller", "http://example.com/setup")
+            .unwrap();
+        assert_eq!(manager.pending_requests().len(), 1);
+        assert_eq!(
+            manager.respond(&approved.request_id, true).unwrap(),
+            UrlOpenOutcome::Opened
+        );
+        assert_eq!(
+            *opened.lock().unwrap(),
+            vec!["http://example.com/setup".to_string()]
+        );
+        assert!(manager.respond(&approved.request_id, true).is_err());
+
+        let audit = audit_log.get_logs(None, None);
+        assert_eq!(audit[0].message, "Remote URL opened");
+        assert!(audit
+            .iter()
+            .any(|entry| entry.message == "Remote URL rejected"
+                && entry.format().contains("file:///etc/passwd")));
+    }
+
+    #[test]
+    fn test_rate_limit_per_viewer_and_expiry() {
+        let (manager, _, _, clock) = setup();
+        manager.set_policy(RemoteUrlPolicy {
+            max_requests: 2,
+            rate_window_secs: 10,
+            ..Default::default()
+        });
+        let links = session(vec![Permission::OpenUrl]);
+
+        assert!(manager
+            .request_open(&links, "a", "https://example.com/1")
+            .is_ok());
+        assert!(manager
+            .request_open(&links, "a", "https://example.com/2")
+            .is_ok());
+        assert!(manager
+            .request_open(&links, "a", "https://example.com/3")
+            .is_err());
+        // Other viewers have their own budget
+        assert!(manager
+            .request_open(&links, "b", "https://example.com/1")
+            .is_ok());
+
+        clock.advance(Duration::from_secs(10));
+        let late = manager
+            .request_open(&links, "a", "https://example.com/4")
+            .unwrap();
+
+        clock.advance(Duration::from_secs(
+            URL_CONFIRMATION_TIMEOUT_SECS as u64 + 1,
+        ));
+        assert!(manager.pending_requests().is_empty());
+        assert!(manager.respond(&late.request_id, true).is_err());
+    }
+}
@@ -1,20 +1,176 @@
+#[cfg(feature = "audio")]
+use crate::audio_backend::{
+    spawn_audio_thread, AudioBackend, AudioDeviceInfo, AudioLevel, AudioThreadContext,
+    PlatformAudioBackend, DEFAULT_AUDIO_DEVICE,
+};
+use crate::capture_backend::BackendFrameSource;
+use crate::capture_rate::AdaptiveRateOptions;
+use crate::capture_thread::{
+    spawn_capture_supervisor, CaptureHealthHandle, CaptureThreadContext, CaptureThreadHealth,
+    FrameSourceFactory, CAPTURE_CHANNEL_CAPACITY,
+};
+use crate::decoder_capabilities::{DecoderCapabilities, DecoderCodec};
+use crate::frame_processing::{FramePipeline, ProcessingStage};
+use crate::hdr::{HdrCapabilities, HdrMode, HdrOptions};
+use crate::metrics::{Counter, MetricsRegistry};
+pub use crate::performance::QualityPreset;
+use crate::session_manager::Permission;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+#[cfg(feature = "audio")]
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use tokio::sync::{mpsc, Mutex, RwLock};
+use tokio::sync::{mpsc, RwLock};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DisplayInfo {
     pub id: String,
     pub name: String,
+    /// Size in physical pixels
     pub width: u32,
     pub height: u32,
     pub is_primary: bool,
+    /// Hz; 0 when the platform does not report it
     pub refresh_rate: u32,
+    /// Top-left corner in desktop coordinates
+    #[serde(default)]
+    pub x: i32,
+    #[serde(default)]
+    pub y: i32,
+    /// UI scaling, e.g. 2.0 for a 200% HiDPI display
+    #[serde(default = "default_scale_factor")]
+    pub scale_factor: f64,
+    /// Whether the display can show, and is showing, HDR
+    #[serde(default)]
+    pub hdr: HdrCapabilities,
 }
 
+fn default_scale_factor() -> f64 {
+    1.0
+}
+
+/// What a capture run shares
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaptureSource {
+    /// A whole display
+    Display { display_id: String },
+    /// The windows of one application, paired with only that application's audio
+    Application { process_id: u32, name: String },
+}
+
+impl CaptureSource {
+    /// Session permission needed to start this kind of capture
+    pub fn required_permission(&self) -> Permission {
+        match self {
+            CaptureSource::Display { .. } => Permission::ScreenView,
+            CaptureSource::Application { .. } => Permission::AppSharing,
+        }
+    }
+}
+
+/// Application that can be shared on its own
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApplicationInfo {
+    pub process_id: u32,
+    pub name: String,
+    pub window_titles: Vec<String>,
+    /// Its audio can be captured without the rest of the system's sound
+    pub audio_isolation: bool,
+}
+
+/// Top-level window that can be shared on its own
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WindowInfo {
+    /// Platform handle: X11 window, HWND or CGWindowID
+    pub window_id: u64,
+    pub title: String,
+    /// Owning process, when the platform reports it
+    pub process_id: Option<u32>,
+    /// Top-left corner in desktop coordinates
+    pub x: i32,
+    pub y: i32,
+    /// Size in physical pixels
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Part of the capture source that is shared
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CaptureTarget {
+    #[default]
+    FullDisplay,
+    /// A single top-level window, by `WindowInfo::window_id`
+    Window(u64),
+    /// Rectangle in pixels from the display's top-left corner
+    Region {
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+    },
+}
+
+impl CaptureTarget {
+    /// Reject regions with no area
+    pub fn validate(&self) -> Result<()> {
+        match self {
+            CaptureTarget::Region { width, height, .. } if *width == 0 || *height == 0 => {
+                Err(anyhow::anyhow!("Capture region must not be empty"))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Whether one process's audio can be captured in isolation
+///
+/// Windows 10 2004+ has WASAPI process loopback, macOS 14.2+ Core Audio
+/// process taps, and Linux records the process's PulseAudio (or PipeWire)
+/// streams; see `audio_backend`. Always false without the audio feature.
+pub fn per_process_audio_supported() -> bool {
+    #[cfg(feature = "audio")]
+    {
+        crate::audio_backend::process_capture_available()
+    }
+    #[cfg(not(feature = "audio"))]
+    {
+        false
+    }
+}
+
+/// Group windows by owning process, topmost application first
+///
+/// Windows whose owner is unknown cannot be shared as an application and
+/// are left out.
+fn applications_from_windows(
+    windows: Vec<WindowInfo>,
+    audio_isolation: bool,
+) -> Vec<ApplicationInfo> {
+    let mut applications: Vec<ApplicationInfo> = Vec::new();
+    for window in windows {
+        let Some(process_id) = window.process_id else {
+            continue;
+        };
+        match applications
+            .iter_mut()
+            .find(|application| application.process_id == process_id)
+        {
+            Some(application) => application.window_titles.push(window.title),
+            None => applications.push(ApplicationInfo {
+                process_id,
+                name: crate::window_enum::process_name(process_id)
+                    .unwrap_or_else(|| format!("Process {}", process_id)),
+                window_titles: vec![window.title],
+                audio_isolation,
+            }),
+        }
+    }
+    applications
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct CaptureOptions {
     pub frame_rate: u32,
     pub width: u32,
@@ -23,6 +179,22 @@ pub struct CaptureOptions {
     pub codec: VideoCodecType,
     pub bitrate: u32, // in kbps
     pub quality_preset: QualityPreset,
+    /// Whole display, one window or a region of the display
+    #[serde(default)]
+    pub target: CaptureTarget,
+    /// Report changed areas with each frame so static content can be skipped
+    #[serde(default = "default_damage_tracking")]
+    pub damage_tracking: bool,
+    /// HDR capture, tone mapping and passthrough
+    #[serde(default)]
+    pub hdr: HdrOptions,
+    /// Lower the capture rate while the screen is still
+    #[serde(default)]
+    pub adaptive_rate: AdaptiveRateOptions,
+}
+
+fn default_damage_tracking() -> bool {
+    true
 }
 
 impl Default for CaptureOptions {
@@ -35,7 +207,78 @@ impl Default for CaptureOptions {
             codec: VideoCodecType::H264,
             bitrate: 4000,
             quality_preset: QualityPreset::Balanced,
+            target: CaptureTarget::FullDisplay,
+            damage_tracking: true,
+            hdr: HdrOptions::default(),
+            adaptive_rate: AdaptiveRateOptions::default(),
+        }
+    }
+}
+
+impl CaptureOptions {
+    /// Clamp resolution, frame rate and codec to a viewer's decoder limits
+    ///
+    /// Resolution is scaled down keeping the aspect ratio, and the bitrate
+    /// scaled with the pixel rate. The current codec is kept unless the viewer
+    /// cannot decode it, or can only decode it in software while another host
+    /// codec is hardware-decoded; switching codec rescales the bitrate by
+    /// their `bitrate_factor`s. Fails if there is no common codec.
+    pub fn constrained_to(&self, caps: &DecoderCapabilities) -> Result<CaptureOptions> {
+        let candidates = [
+            self.codec,
+            VideoCodecType::H264,
+            VideoCodecType::H265,
+            VideoCodecType::VP9,
+            VideoCodecType::AV1,
+        ];
+        let pick = |hdr_only: bool| {
+            let usable = |c: &&VideoCodecType| !hdr_only || c.supports_hdr();
+            candidates
+                .iter()
+                .filter(usable)
+                .find(|c| caps.is_hardware_decoded((**c).into()) && caps.supports((**c).into()))
+                .or_else(|| {
+                    candidates
+                        .iter()
+                        .filter(usable)
+                        .find(|c| caps.supports((**c).into()))
+                })
+                .copied()
+        };
+        let wants_hdr = self.hdr.mode == HdrMode::Passthrough && caps.hdr;
+        let codec = wants_hdr
+            .then(|| pick(true))
+            .flatten()
+            .or_else(|| pick(false))
+            .ok_or_else(|| anyhow::anyhow!("Viewer cannot decode any supported video codec"))?;
+
+        let mut options = self.clone();
+        options.codec = codec;
+        if self.hdr.mode == HdrMode::Passthrough && !(caps.hdr && codec.supports_hdr()) {
+            options.hdr.mode = HdrMode::ToneMap;
+        }
+        if codec != self.codec {
+            options.bitrate = ((self.bitrate as f32 * codec.bitrate_factor()
+                / self.codec.bitrate_factor()) as u32)
+                .max(1);
+        }
+        if self.width > 0 && self.height > 0 {
+            let scale = (caps.max_width as f64 / self.width as f64)
+                .min(caps.max_height as f64 / self.height as f64)
+                .min(1.0);
+            // Encoders need even dimensions
+            options.width = ((self.width as f64 * scale) as u32) & !1;
+            options.height = ((self.height as f64 * scale) as u32) & !1;
+        }
+        options.frame_rate = self.frame_rate.min(caps.max_fps.max(1));
+
+        let pixel_rate =
+            |o: &CaptureOptions| o.width as u64 * o.height as u64 * o.frame_rate as u64;
+        if pixel_rate(self) > 0 {
+            options.bitrate =
+                ((options.bitrate as u64 * pixel_rate(&options) / pixel_rate(self)) as u32).max(1);
         }
+        Ok(options)
     }
 }
 
@@ -44,14 +287,106 @@ pub enum VideoCodecType {
     H264,
     H265,
     VP9,
+    AV1,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
-pub enum QualityPreset {
-    Low,      // 720p, 15fps, low bitrate
-    Balanced, // 1080p, 30fps, medium bitrate
-    High,     // 1080p, 60fps, high bitrate
-    Ultra,    // Native resolution, 60fps, maximum bitrate
+/// Fraction of the H.264 bitrate AV1 needs for the same quality on screen
+/// content
+pub const AV1_BITRATE_FACTOR: f32 = 0.65;
+
+impl VideoCodecType {
+    /// Bitrate relative to H.264 for the same quality
+    ///
+    /// Presets and network adaptation are expressed in H.264 terms and
+    /// scaled by this. Only AV1 is tuned so far.
+    pub fn bitrate_factor(self) -> f32 {
+        match self {
+            VideoCodecType::AV1 => AV1_BITRATE_FACTOR,
+            _ => 1.0,
+        }
+    }
+
+    /// Whether the codec has a 10-bit profile for HDR (HEVC Main 10, VP9
+    /// profile 2, AV1 Main)
+    pub fn supports_hdr(self) -> bool {
+        !matches!(self, VideoCodecType::H264)
+    }
+
+    /// Encoder for this codec, in hardware when allowed and available
+    pub fn select_encoder(self, allow_hardware: bool) -> EncoderSelection {
+        if let Some(implementation) = allow_hardware.then(|| hardware_encoder(self)).flatten() {
+            return EncoderSelection {
+                codec: self,
+                hardware: true,
+                implementation,
+            };
+        }
+        EncoderSelection {
+            codec: self,
+            hardware: false,
+            implementation: match self {
+                VideoCodecType::H264 => "openh264",
+                VideoCodecType::H265 => "x265",
+                VideoCodecType::VP9 => "libvpx-vp9",
+                VideoCodecType::AV1 => "svt-av1",
+            },
+        }
+    }
+}
+
+impl From<VideoCodecType> for DecoderCodec {
+    fn from(codec: VideoCodecType) -> Self {
+        match codec {
+            VideoCodecType::H264 => DecoderCodec::H264,
+            VideoCodecType::H265 => DecoderCodec::H265,
+            VideoCodecType::VP9 => DecoderCodec::VP9,
+            VideoCodecType::AV1 => DecoderCodec::AV1,
+        }
+    }
+}
+
+/// Encoder chosen for a codec on this machine
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncoderSelection {
+    pub codec: VideoCodecType,
+    pub hardware: bool,
+    /// e.g. "mediafoundation" or "svt-av1"
+    pub implementation: &'static str,
+}
+
+/// Hardware encoder for `codec`, if the platform has one
+fn hardware_encoder(codec: VideoCodecType) -> Option<&'static str> {
+    #[cfg(target_os = "windows")]
+    {
+        // MFTEnumEx over hardware encoder MFTs would go here; AV1 encoders
+        // (NVENC on RTX 40, AMF on RDNA3, QuickSync on Arc) need that probe
+        match codec {
+            VideoCodecType::H264 | VideoCodecType::H265 => Some("mediafoundation"),
+            VideoCodecType::VP9 | VideoCodecType::AV1 => None,
+        }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        // VideoToolbox has no AV1 or VP9 encoder
+        match codec {
+            VideoCodecType::H264 | VideoCodecType::H265 => Some("videotoolbox"),
+            VideoCodecType::VP9 | VideoCodecType::AV1 => None,
+        }
+    }
+    #[cfg(target_os = "linux")]
+    {
+        // vaQueryConfigEntrypoints for VAProfileAV1Profile0 / VAProfileVP9Profile0
+        // would go here
+        match codec {
+            VideoCodecType::H264 | VideoCodecType::H265 => Some("vaapi"),
+            VideoCodecType::VP9 | VideoCodecType::AV1 => None,
+        }
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    {
+        let _ = codec;
+        None
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -62,6 +397,21 @@ pub struct VideoFrame {
     pub height: u32,
     pub data: Vec<u8>,
     pub format: FrameFormat,
+    /// Areas changed since the previous delivered frame
+    ///
+    /// `None` means anything may have changed (first frame, keyframe,
+    /// resize, or no damage information); an empty list means the frame is
+    /// identical to the previous one.
+    pub dirty_rects: Option<Vec<DirtyRect>>,
+}
+
+/// Changed rectangle of a frame, in pixels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DirtyRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -70,8 +420,11 @@ pub enum FrameFormat {
     BGRA,
     NV12,
     I420,
+    /// scRGB: linear BT.709 half floats, 1.0 = 80 nits; from HDR displays
+    RGBA16F,
 }
 
+#[cfg(feature = "audio")]
 #[derive(Debug, Clone)]
 pub struct AudioFrame {
     pub id: u64,
@@ -81,6 +434,7 @@ pub struct AudioFrame {
     pub data: Vec<i16>,
 }
 
+#[cfg(feature = "audio")]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AudioCaptureOptions {
     pub sample_rate: u32,
@@ -89,6 +443,7 @@ pub struct AudioCaptureOptions {
     pub enable_echo_cancellation: bool,
 }
 
+#[cfg(feature = "audio")]
 impl Default for AudioCaptureOptions {
     fn default() -> Self {
         Self {
@@ -134,181 +489,308 @@ pub struct ScreenCapturer {
     #[allow(dead_code)]
     id: String,
     current_display: Option<String>,
+    current_source: Option<CaptureSource>,
     capture_options: Arc<RwLock<CaptureOptions>>,
     hardware_acceleration_available: bool,
-    is_capturing: Arc<RwLock<bool>>,
-    frame_sender: Option<mpsc::UnboundedSender<VideoFrame>>,
-    frame_counter: Arc<Mutex<u64>>,
+    /// Run flag of the current capture thread
+    is_capturing: Arc<AtomicBool>,
+    /// Capture is suspended (e.g. screen recording permission revoked)
+    is_paused: Arc<AtomicBool>,
+    frame_counter: Arc<AtomicU64>,
     adaptive_config: Arc<RwLock<AdaptiveBitrateConfig>>,
+    frame_source: FrameSourceFactory,
+    thread_health: CaptureHealthHandle,
+    metrics_registry: Arc<MetricsRegistry>,
+    /// Decoder limits advertised by the viewer
+    decoder_limits: Arc<RwLock<Option<DecoderCapabilities>>>,
+    /// A keyframe is due; shared with the capture thread
+    keyframe_requested: Arc<AtomicBool>,
+    keyframe_requests: Arc<Counter>,
+    frame_processors: FramePipeline,
+    /// Local input arrived; shared with the capture thread
+    input_activity: Arc<AtomicBool>,
 }
 
 impl ScreenCapturer {
     pub fn new() -> Self {
+        let metrics_registry = Arc::new(MetricsRegistry::new());
         Self {
             id: Uuid::new_v4().to_string(),
             current_display: None,
+            current_source: None,
             capture_options: Arc::new(RwLock::new(CaptureOptions::default())),
             hardware_acceleration_available: Self::check_hardware_acceleration(),
-            is_capturing: Arc::new(RwLock::new(false)),
-            frame_sender: None,
-            frame_counter: Arc::new(Mutex::new(0)),
+            is_capturing: Arc::new(AtomicBool::new(false)),
+            is_paused: Arc::new(AtomicBool::new(false)),
+            frame_counter: Arc::new(AtomicU64::new(0)),
             adaptive_config: Arc::new(RwLock::new(AdaptiveBitrateConfig::default())),
+            frame_source: Arc::new(|source| Box::new(BackendFrameSource::new(source.clone()))),
+            thread_health: CaptureHealthHandle::new(&metrics_registry),
+            keyframe_requests: metrics_registry.counter("capture.keyframe_requests"),
+            metrics_registry,
+            decoder_limits: Arc::new(RwLock::new(None)),
+            keyframe_requested: Arc::new(AtomicBool::new(false)),
+            frame_processors: FramePipeline::new(ProcessingStage::PreEncode),
+            input_activity: Arc::new(AtomicBool::new(false)),
         }
     }
 
-    pub async fn get_available_displays(&self) -> Result<Vec<DisplayInfo>> {
-        // Platform-specific display enumeration
-        #[cfg(target_os = "windows")]
-        {
-            self.get_windows_displays().await
-        }
-        #[cfg(target_os = "macos")]
-        {
-            self.get_macos_displays().await
-        }
-        #[cfg(target_os = "linux")]
-        {
-            self.get_linux_displays().await
+    /// Keep the stream within what the viewer can decode
+    ///
+    /// Applies to the current options and to every later change, including
+    /// quality presets and network adaptation.
+    pub async fn apply_decoder_capabilities(&self, caps: DecoderCapabilities) -> Result<()> {
+        let mut options = self.capture_options.write().await;
+        let constrained = options.constrained_to(&caps)?;
+        if constrained != *options {
+            tracing::info!(
+                "Constrained capture to viewer decoder: {}x{} {}fps {:?}",
+                constrained.width,
+                constrained.height,
+                constrained.frame_rate,
+                constrained.codec
+            );
         }
-        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
-        {
-            // Fallback for other platforms
-            Ok(vec![DisplayInfo {
-                id: "display_0".to_string(),
-                name: "Primary Display".to_string(),
-                width: 1920,
-                height: 1080,
-                is_primary: true,
-                refresh_rate: 60,
-            }])
+        *options = constrained;
+        *self.decoder_limits.write().await = Some(caps);
+        Ok(())
+    }
+
+    /// Apply the viewer's decoder limits, if any, to `options`
+    async fn constrain(&self, options: &mut CaptureOptions) {
+        if let Some(caps) = self.decoder_limits.read().await.as_ref() {
+            match options.constrained_to(caps) {
+                Ok(constrained) => *options = constrained,
+                Err(e) => tracing::warn!("Cannot constrain capture options: {}", e),
+            }
         }
     }
 
-    #[cfg(target_os = "windows")]
-    async fn get_windows_displays(&self) -> Result<Vec<DisplayInfo>> {
-        // Windows-specific implementation using Win32 API
-        Ok(vec![DisplayInfo {
-            id: "display_0".to_string(),
-            name: "Primary Display".to_string(),
-            width: 1920,
-            height: 1080,
-            is_primary: true,
-            refresh_rate: 60,
-        }])
+    /// Use a custom frame source instead of the platform capture APIs
+    pub fn with_frame_source(mut self, factory: FrameSourceFactory) -> Self {
+        self.frame_source = factory;
+        self
     }
 
-    #[cfg(target_os = "macos")]
-    async fn get_macos_displays(&self) -> Result<Vec<DisplayInfo>> {
-        // macOS-specific implementation using Core Graphics
-        Ok(vec![DisplayInfo {
-            id: "display_0".to_string(),
-            name: "Primary Display".to_string(),
-            width: 1920,
-            height: 1080,
-            is_primary: true,
-            refresh_rate: 60,
-        }])
+    /// Pre-encode processors applied to every captured frame
+    ///
+    /// Registering and removing takes effect on the next frame, including
+    /// during a capture run.
+    pub fn frame_processors(&self) -> &FramePipeline {
+        &self.frame_processors
     }
 
-    #[cfg(target_os = "linux")]
-    async fn get_linux_displays(&self) -> Result<Vec<DisplayInfo>> {
-        // Linux-specific implementation using X11/Wayland
-        Ok(vec![DisplayInfo {
-            id: "display_0".to_string(),
-            name: "Primary Display".to_string(),
-            width: 1920,
-            height: 1080,
-            is_primary: true,
-            refresh_rate: 60,
-        }])
+    /// Active displays, primary first, for the display picker
+    pub async fn get_available_displays(&self) -> Result<Vec<DisplayInfo>> {
+        tokio::task::spawn_blocking(crate::display_enum::enumerate_displays).await?
     }
 
+    /// Start capturing on a dedicated thread
+    ///
+    /// Frames arrive through a bounded channel; if the receiver falls behind,
+    /// new frames are dropped (see `thread_health`).
     pub async fn start_capture(
         &mut self,
         display_id: String,
         options: CaptureOptions,
-    ) -> Result<mpsc::UnboundedReceiver<VideoFrame>> {
-        let (sender, receiver) = mpsc::unbounded_channel();
-
+    ) -> Result<mpsc::Receiver<VideoFrame>> {
         self.current_display = Some(display_id.clone());
+        self.start_source(CaptureSource::Display { display_id }, options)
+            .await
+    }
+
+    /// Visible top-level windows, topmost first, for the window picker
+    pub async fn get_shareable_windows(&self) -> Result<Vec<WindowInfo>> {
+        tokio::task::spawn_blocking(crate::window_enum::enumerate_windows).await?
+    }
+
+    /// Switch between the whole display, one window and a region
+    ///
+    /// Takes effect from the next captured frame of the current run.
+    pub async fn set_capture_target(&self, target: CaptureTarget) -> Result<()> {
+        target.validate()?;
+        self.capture_options.write().await.target = target;
+        tracing::info!("Setting capture target: {:?}", target);
+        Ok(())
+    }
+
+    /// Applications with visible windows that can be shared individually
+    ///
+    /// Fails where windows cannot be listed, so an empty picker never stands
+    /// in for an unsupported one.
+    pub async fn get_shareable_applications(&self) -> Result<Vec<ApplicationInfo>> {
+        if cfg!(not(any(
+            target_os = "windows",
+            target_os = "macos",
+            target_os = "linux"
+        ))) {
+            return Err(anyhow::anyhow!(
+                "Listing shareable applications is not supported on {}",
+                std::env::consts::OS
+            ));
+        }
+        if cfg!(target_os = "linux") && std::env::var_os("DISPLAY").is_none() {
+            return Err(anyhow::anyhow!(
+                "Listing shareable applications needs an X display; on Wayland the ScreenCast portal's picker chooses what is shared"
+            ));
+        }
+        tokio::task::spawn_blocking(|| {
+            let windows = crate::window_enum::enumerate_windows()?;
+            Ok(applications_from_windows(
+                windows,
+                per_process_audio_supported(),
+            ))
+        })
+        .await?
+    }
+
+    /// Share a single application's windows instead of a display
+    ///
+    /// Requires the session's `AppSharing` permission. Pair it with
+    /// `AudioCapturer::start_application_capture` for the application's sound.
+    pub async fn start_application_capture(
+        &mut self,
+        application: &ApplicationInfo,
+        options: CaptureOptions,
+        permissions: &[Permission],
+    ) -> Result<mpsc::Receiver<VideoFrame>> {
+        if !permissions.contains(&Permission::AppSharing) {
+            return Err(anyhow::anyhow!(
+                "Application sharing not permitted for this session"
+            ));
+        }
+        self.current_display = None;
+        self.start_source(
+            CaptureSource::Application {
+                process_id: application.process_id,
+                name: application.name.clone(),
+            },
+            options,
+        )
+        .await
+    }
+
+    /// Source of the current capture run
+    pub fn current_source(&self) -> Option<&CaptureSource> {
+        self.current_source.as_ref()
+    }
+
+    async fn start_source(
+        &mut self,
+        source: CaptureSource,
+        mut options: CaptureOptions,
+    ) -> Result<mpsc::Receiver<VideoFrame>> {
+        options.target.validate()?;
+        options.hdr.validate()?;
+        options.adaptive_rate.validate()?;
+        self.constrain(&mut options).await;
+
+        // A previous run keeps its own flag, so stopping it cannot race the new one
+        self.is_capturing.store(false, Ordering::SeqCst);
+        self.is_capturing = Arc::new(AtomicBool::new(true));
+
+        let (sender, receiver) = mpsc::channel(CAPTURE_CHANNEL_CAPACITY);
         *self.capture_options.write().await = options.clone();
-        self.frame_sender = Some(sender);
-        *self.is_capturing.write().await = true;
 
         tracing::info!(
-            "Starting screen capture for display: {} at {}x{} {}fps",
-            display_id,
+            "Starting screen capture for {:?} at {}x{} {}fps",
+            source,
             options.width,
             options.height,
             options.frame_rate
         );
-
-        // Start capture loop in background
-        self.start_capture_loop().await?;
+        self.current_source = Some(source.clone());
+
+        spawn_capture_supervisor(CaptureThreadContext {
+            capturing: Arc::clone(&self.is_capturing),
+            paused: Arc::clone(&self.is_paused),
+            keyframe_requested: Arc::clone(&self.keyframe_requested),
+            input_activity: Arc::clone(&self.input_activity),
+            options: Arc::clone(&self.capture_options),
+            frame_counter: Arc::clone(&self.frame_counter),
+            health: self.thread_health.clone(),
+            sender,
+            capture_source: source,
+            source: Arc::clone(&self.frame_source),
+            processors: self.frame_processors.clone(),
+        })?;
 
         Ok(receiver)
     }
 
-    async fn start_capture_loop(&self) -> Result<()> {
-        let is_capturing = Arc::clone(&self.is_capturing);
-        let capture_options = Arc::clone(&self.capture_options);
-        let frame_counter = Arc::clone(&self.frame_counter);
-        let frame_sender = self.frame_sender.clone();
-
-        tokio::spawn(async move {
-            while *is_capturing.read().await {
-                let options = capture_options.read().await;
-                let frame_interval =
-                    std::time::Duration::from_millis(1000 / options.frame_rate as u64);
-                drop(options);
-
-                // Capture frame (placeholder - actual implementation would use platform APIs)
-                if let Some(sender) = &frame_sender {
-                    let mut counter = frame_counter.lock().await;
-                    *counter += 1;
-
-                    let frame = VideoFrame {
-                        id: *counter,
-                        timestamp: std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis() as u64,
-                        width: 1920,
-                        height: 1080,
-                        data: vec![], // Placeholder - actual frame data
-                        format: FrameFormat::RGBA,
-                    };
-
-                    let _ = sender.send(frame);
-                }
+    pub async fn stop_capture(&mut self) {
+        self.is_capturing.store(false, Ordering::SeqCst);
 
-                tokio::time::sleep(frame_interval).await;
-            }
-        });
+        if let Some(source) = &self.current_source {
+            tracing::info!("Stopping screen capture for {:?}", source);
+        }
 
-        Ok(())
+        self.current_display = None;
+        self.current_source = None;
     }
 
-    pub async fn stop_capture(&mut self) {
-        *self.is_capturing.write().await = false;
+    /// Health of the capture thread, for diagnostics
+    pub fn thread_health(&self) -> CaptureThreadHealth {
+        self.thread_health.snapshot()
+    }
 
-        if let Some(display_id) = &self.current_display {
-            tracing::info!("Stopping screen capture for display: {}", display_id);
-        }
+    /// Shared handle to the capture thread health
+    pub fn thread_health_handle(&self) -> CaptureHealthHandle {
+        self.thread_health.clone()
+    }
 
-        self.current_display = None;
-        self.frame_sender = None;
+    /// Registry holding the capture frame counters
+    pub fn metrics_registry(&self) -> Arc<MetricsRegistry> {
+        Arc::clone(&self.metrics_registry)
+    }
+
+    /// Have the encoder emit a keyframe with the next captured frame
+    ///
+    /// Called when the viewer reports picture loss. Requests made before the
+    /// capture thread picks up the previous one are merged; rate limiting is
+    /// left to the callers.
+    pub fn request_keyframe(&self) {
+        self.keyframe_requests.increment();
+        self.keyframe_requested.store(true, Ordering::SeqCst);
+    }
+
+    /// Restore the full capture rate ahead of input-driven screen changes
+    ///
+    /// Called for every injected or local input event; cheap enough for
+    /// pointer motion.
+    pub fn notify_input(&self) {
+        self.input_activity.store(true, Ordering::Release);
+    }
+
+    /// Change how the capture rate follows screen motion
+    pub async fn set_adaptive_rate(&self, adaptive_rate: AdaptiveRateOptions) -> Result<()> {
+        adaptive_rate.validate()?;
+        self.capture_options.write().await.adaptive_rate = adaptive_rate;
+        Ok(())
     }
 
     pub async fn set_video_codec(&self, codec: VideoCodecType) {
         let mut options = self.capture_options.write().await;
         options.codec = codec;
-        tracing::info!("Setting video codec: {:?}", codec);
+        self.constrain(&mut options).await;
+        tracing::info!("Setting video codec: {:?}", options.codec);
+    }
+
+    /// Change HDR handling; passthrough falls back to tone mapping if the
+    /// viewer cannot take it
+    pub async fn set_hdr_options(&self, hdr: HdrOptions) -> Result<()> {
+        hdr.validate()?;
+        let mut options = self.capture_options.write().await;
+        options.hdr = hdr;
+        self.constrain(&mut options).await;
+        tracing::info!("Setting HDR mode: {:?}", options.hdr.mode);
+        Ok(())
     }
 
     pub async fn set_frame_rate(&self, fps: u32) {
         let mut options = self.capture_options.write().await;
         options.frame_rate = fps.clamp(15, 60);
+        self.constrain(&mut options).await;
         tracing::info!("Setting frame rate: {} FPS", options.frame_rate);
     }
 
@@ -316,7 +798,16 @@ impl ScreenCapturer {
         let mut options = self.capture_options.write().await;
         options.width = width;
         options.height = height;
-        tracing::info!("Setting resolution: {}x{}", width, height);
+        self.constrain(&mut options).await;
+        tracing::info!("Setting resolution: {}x{}", options.width, options.height);
+    }
+
+    /// Encoder for the current codec and hardware acceleration setting
+    pub async fn encoder_selection(&self) -> EncoderSelection {
+        let options = self.capture_options.read().await;
+        options
+            .codec
+            .select_encoder(options.enable_hardware_acceleration)
     }
 
     pub async fn set_bitrate(&self, bitrate: u32) {
@@ -369,6 +860,7 @@ impl ScreenCapturer {
         self.capture_options.read().await.clone()
     }
 
+    /// Apply a preset; its bitrate is scaled for the current codec
     pub async fn apply_quality_preset(&self, preset: QualityPreset) {
         let mut options = self.capture_options.write().await;
         options.quality_preset = preset;
@@ -398,6 +890,8 @@ impl ScreenCapturer {
                 options.bitrate = 15000;
             }
         }
+        options.bitrate = (options.bitrate as f32 * options.codec.bitrate_factor()) as u32;
+        self.constrain(&mut options).await;
 
         tracing::info!("Applied quality preset: {:?}", preset);
     }
@@ -407,9 +901,15 @@ impl ScreenCapturer {
         let mut options = self.capture_options.write().await;
         let config = self.adaptive_config.read().await;
 
-        // Calculate target bitrate based on available bandwidth
+        // Calculate target bitrate based on available bandwidth. The limits
+        // are H.264 figures; a more efficient codec reaches the same quality
+        // floor and ceiling with less, so it keeps working on thinner links
+        // and leaves headroom on fast ones.
         let target_bitrate = (conditions.available_bandwidth as f32 * 0.8) as u32;
-        let new_bitrate = target_bitrate.clamp(config.min_bitrate, config.max_bitrate);
+        let factor = options.codec.bitrate_factor();
+        let min_bitrate = (config.min_bitrate as f32 * factor) as u32;
+        let max_bitrate = ((config.max_bitrate as f32 * factor) as u32).max(min_bitrate);
+        let new_bitrate = target_bitrate.clamp(min_bitrate, max_bitrate);
 
         // Adjust frame rate based on packet loss and RTT
         let frame_rate_factor = if conditions.packet_loss > 5.0 || conditions.rtt > 150 {
@@ -433,6 +933,7 @@ impl ScreenCapturer {
             options.frame_rate = new_frame_rate;
             tracing::info!("Adaptive frame rate adjustment: {} fps", new_frame_rate);
         }
+        self.constrain(&mut options).await;
     }
 
     pub async fn set_adaptive_config(&self, config: AdaptiveBitrateConfig) {
@@ -440,7 +941,23 @@ impl ScreenCapturer {
     }
 
     pub async fn is_capturing(&self) -> bool {
-        *self.is_capturing.read().await
+        self.is_capturing.load(Ordering::SeqCst)
+    }
+
+    /// Stop emitting frames without tearing down the capture session
+    pub async fn pause_capture(&self) {
+        self.is_paused.store(true, Ordering::SeqCst);
+        tracing::info!("Screen capture paused");
+    }
+
+    /// Resume emitting frames after a pause
+    pub async fn resume_capture(&self) {
+        self.is_paused.store(false, Ordering::SeqCst);
+        tracing::info!("Screen capture resumed");
+    }
+
+    pub async fn is_paused(&self) -> bool {
+        self.is_paused.load(Ordering::SeqCst)
     }
 }
 
@@ -450,15 +967,25 @@ impl Default for ScreenCapturer {
     }
 }
 
+#[cfg(feature = "audio")]
 pub struct AudioCapturer {
     #[allow(dead_code)]
     id: String,
     capture_options: Arc<RwLock<AudioCaptureOptions>>,
+    /// Run flag of the current capture thread
     is_capturing: Arc<RwLock<bool>>,
     frame_sender: Option<mpsc::UnboundedSender<AudioFrame>>,
-    frame_counter: Arc<Mutex<u64>>,
+    frame_counter: Arc<AtomicU64>,
+    /// Process whose audio alone is captured; `None` for the system mix
+    process_id: Option<u32>,
+    backend: Arc<dyn AudioBackend>,
+    /// Device chosen with `select_device`; `None` for the platform default
+    device: Arc<RwLock<Option<String>>>,
+    levels: Arc<std::sync::Mutex<HashMap<String, AudioLevel>>>,
+    last_error: Arc<std::sync::Mutex<Option<String>>>,
 }
 
+#[cfg(feature = "audio")]
 impl AudioCapturer {
     pub fn new() -> Self {
         Self {
@@ -466,75 +993,168 @@ impl AudioCapturer {
             capture_options: Arc::new(RwLock::new(AudioCaptureOptions::default())),
             is_capturing: Arc::new(RwLock::new(false)),
             frame_sender: None,
-            frame_counter: Arc::new(Mutex::new(0)),
+            frame_counter: Arc::new(AtomicU64::new(0)),
+            process_id: None,
+            backend: Arc::new(PlatformAudioBackend),
+            device: Arc::new(RwLock::new(None)),
+            levels: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            last_error: Arc::new(std::sync::Mutex::new(None)),
+        }
+    }
+
+    /// Use a custom audio backend instead of the platform audio APIs
+    pub fn with_backend(mut self, backend: Arc<dyn AudioBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Microphones and loopback devices, default first
+    pub async fn list_audio_devices(&self) -> Result<Vec<AudioDeviceInfo>> {
+        let backend = Arc::clone(&self.backend);
+        tokio::task::spawn_blocking(move || backend.devices()).await?
+    }
+
+    /// Capture from this device, or the platform default for `None`
+    ///
+    /// Takes effect from the next frame of the current run.
+    pub async fn select_device(&self, device_id: Option<String>) -> Result<()> {
+        if let Some(id) = &device_id {
+            if !self.list_audio_devices().await?.iter().any(|d| &d.id == id) {
+                return Err(anyhow::anyhow!("Audio device not found: {}", id));
+            }
         }
+        tracing::info!(
+            "Selecting audio device: {}",
+            device_id.as_deref().unwrap_or(DEFAULT_AUDIO_DEVICE)
+        );
+        *self.device.write().await = device_id;
+        Ok(())
     }
 
+    pub async fn selected_device(&self) -> Option<String> {
+        self.device.read().await.clone()
+    }
+
+    /// Level of the latest frame from each device captured so far
+    ///
+    /// The default device is keyed `DEFAULT_AUDIO_DEVICE` and an
+    /// application's audio `process:<pid>`. A device drops out when it fails.
+    pub fn device_levels(&self) -> HashMap<String, AudioLevel> {
+        self.levels
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Why the device could not be opened or read, while silence is sent
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Start capturing; requires the session's `AudioCapture` permission
     pub async fn start_capture(
         &mut self,
         options: AudioCaptureOptions,
+        permissions: &[Permission],
+    ) -> Result<mpsc::UnboundedReceiver<AudioFrame>> {
+        if !permissions.contains(&Permission::AudioCapture) {
+            return Err(anyhow::anyhow!(
+                "Audio capture not permitted for this session"
+            ));
+        }
+
+        self.process_id = None;
+        self.start(options).await
+    }
+
+    /// Capture only one application's audio, for application sharing
+    ///
+    /// Requires the session's `AppSharing` permission. Fails where the
+    /// backend cannot isolate a process (see `per_process_audio_supported`);
+    /// it never falls back to the system mix. While the process plays
+    /// nothing, or has exited, silence is sent.
+    pub async fn start_application_capture(
+        &mut self,
+        options: AudioCaptureOptions,
+        process_id: u32,
+        permissions: &[Permission],
+    ) -> Result<mpsc::UnboundedReceiver<AudioFrame>> {
+        if !permissions.contains(&Permission::AppSharing) {
+            return Err(anyhow::anyhow!(
+                "Application sharing not permitted for this session"
+            ));
+        }
+        if !self.backend.process_capture_supported() {
+            return Err(anyhow::anyhow!(
+                "Cannot capture the audio of process {}: per-application audio capture is not supported on {}",
+                process_id,
+                std::env::consts::OS
+            ));
+        }
+
+        self.process_id = Some(process_id);
+        self.start(options).await
+    }
+
+    /// Capture the local microphone for voice chat
+    ///
+    /// The local user turns this on, so no session permission applies.
+    pub async fn start_microphone_capture(
+        &mut self,
+        options: AudioCaptureOptions,
+    ) -> Result<mpsc::UnboundedReceiver<AudioFrame>> {
+        self.process_id = None;
+        self.start(options).await
+    }
+
+    /// Process being captured, if limited to one application
+    pub fn captured_process(&self) -> Option<u32> {
+        self.process_id
+    }
+
+    async fn start(
+        &mut self,
+        options: AudioCaptureOptions,
     ) -> Result<mpsc::UnboundedReceiver<AudioFrame>> {
         let (sender, receiver) = mpsc::unbounded_channel();
 
         *self.capture_options.write().await = options.clone();
-        self.frame_sender = Some(sender);
-        *self.is_capturing.write().await = true;
+        self.frame_sender = Some(sender.clone());
+        // A previous run keeps its own flag, so stopping it cannot race the new one
+        *self.is_capturing.write().await = false;
+        self.is_capturing = Arc::new(RwLock::new(true));
 
         tracing::info!(
-            "Starting audio capture at {} Hz, {} channels",
+            "Starting audio capture at {} Hz, {} channels{}",
             options.sample_rate,
-            options.channels
+            options.channels,
+            self.process_id
+                .map(|pid| format!(" for process {}", pid))
+                .unwrap_or_default()
         );
 
-        // Start capture loop in background
-        self.start_capture_loop().await?;
+        spawn_audio_thread(AudioThreadContext {
+            capturing: Arc::clone(&self.is_capturing),
+            options: Arc::clone(&self.capture_options),
+            device: Arc::clone(&self.device),
+            process_id: self.process_id,
+            backend: Arc::clone(&self.backend),
+            frame_counter: Arc::clone(&self.frame_counter),
+            levels: Arc::clone(&self.levels),
+            last_error: Arc::clone(&self.last_error),
+            sender,
+        })?;
 
         Ok(receiver)
     }
 
-    async fn start_capture_loop(&self) -> Result<()> {
-        let is_capturing = Arc::clone(&self.is_capturing);
-        let capture_options = Arc::clone(&self.capture_options);
-        let frame_counter = Arc::clone(&self.frame_counter);
-        let frame_sender = self.frame_sender.clone();
-
-        tokio::spawn(async move {
-            while *is_capturing.read().await {
-                let options = capture_options.read().await;
-                // Audio frames typically at 20ms intervals
-                let frame_interval = std::time::Duration::from_millis(20);
-                let sample_rate = options.sample_rate;
-                let channels = options.channels;
-                drop(options);
-
-                if let Some(sender) = &frame_sender {
-                    let mut counter = frame_counter.lock().await;
-                    *counter += 1;
-
-                    let frame = AudioFrame {
-                        id: *counter,
-                        timestamp: std::time::SystemTime::now()
-                            .duration_since(std::time::UNIX_EPOCH)
-                            .unwrap()
-                            .as_millis() as u64,
-                        sample_rate,
-                        channels,
-                        data: vec![], // Placeholder - actual audio data
-                    };
-
-                    let _ = sender.send(frame);
-                }
-
-                tokio::time::sleep(frame_interval).await;
-            }
-        });
-
-        Ok(())
-    }
-
     pub async fn stop_capture(&mut self) {
         *self.is_capturing.write().await = false;
         self.frame_sender = None;
+        self.process_id = None;
         tracing::info!("Stopping audio capture");
     }
 
@@ -571,6 +1191,7 @@ impl AudioCapturer {
     }
 }
 
+#[cfg(feature = "audio")]
 impl Default for AudioCapturer {
     fn default() -> Self {
         Self::new()
@@ -604,6 +1225,137 @@ mod tests {
         assert_eq!(options.frame_rate, 60);
     }
 
+    fn phone_decoder() -> DecoderCapabilities {
+        DecoderCapabilities {
+            max_width: 1920,
+            max_height: 1080,
+            max_fps: 30,
+            codecs: vec![DecoderCodec::H264, DecoderCodec::VP9],
+            hardware_codecs: vec![DecoderCodec::H264],
+            hdr: false,
+        }
+    }
+
+    #[test]
+    fn test_options_constrained_to_decoder() {
+        let uhd = CaptureOptions {
+            width: 3840,
+            height: 2160,
+            frame_rate: 60,
+            codec: VideoCodecType::VP9,
+            bitrate: 20000,
+            ..Default::default()
+        };
+        let options = uhd.constrained_to(&phone_decoder()).unwrap();
+        assert_eq!((options.width, options.height), (1920, 1080));
+        assert_eq!(options.frame_rate, 30);
+        // VP9 is decodable, but only in software; H.264 is hardware-decoded
+        assert_eq!(options.codec, VideoCodecType::H264);
+        assert_eq!(options.bitrate, 2500);
+
+        let no_common_codec = DecoderCapabilities {
+            codecs: vec![DecoderCodec::VP8],
+            hardware_codecs: vec![],
+            ..phone_decoder()
+        };
+        assert!(uhd.constrained_to(&no_common_codec).is_err());
+    }
+
+    #[test]
+    fn test_hdr_passthrough_negotiated() {
+        let passthrough = CaptureOptions {
+            hdr: HdrOptions {
+                mode: HdrMode::Passthrough,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        // An SDR viewer gets tone-mapped H.264
+        let options = passthrough.constrained_to(&phone_decoder()).unwrap();
+        assert_eq!(options.hdr.mode, HdrMode::ToneMap);
+        assert_eq!(options.codec, VideoCodecType::H264);
+
+        // An HDR viewer gets a 10-bit capable codec even if it is decoded in
+        // software, rather than the hardware-decoded H.264
+        let hdr_viewer = DecoderCapabilities {
+            hdr: true,
+            ..phone_decoder()
+        };
+        let options = passthrough.constrained_to(&hdr_viewer).unwrap();
+        assert_eq!(options.hdr.mode, HdrMode::Passthrough);
+        assert_eq!(options.codec, VideoCodecType::VP9);
+
+        let h264_only = DecoderCapabilities {
+            codecs: vec![DecoderCodec::H264],
+            ..hdr_viewer
+        };
+        let options = passthrough.constrained_to(&h264_only).unwrap();
+        assert_eq!(options.hdr.mode, HdrMode::ToneMap);
+
+        // Tone mapping is the default, and untouched by SDR viewers
+        let options = CaptureOptions::default()
+            .constrained_to(&phone_decoder())
+            .unwrap();
+        assert_eq!(options.hdr, HdrOptions::default());
+    }
+
+    #[tokio::test]
+    async fn test_av1_selection_and_bitrate_tuning() {
+        let av1_viewer = DecoderCapabilities {
+            codecs: vec![DecoderCodec::H264, DecoderCodec::AV1],
+            hardware_codecs: vec![DecoderCodec::AV1],
+            ..phone_decoder()
+        };
+        let options = CaptureOptions {
+            bitrate: 4000,
+            ..Default::default()
+        }
+        .constrained_to(&av1_viewer)
+        .unwrap();
+        assert_eq!(options.codec, VideoCodecType::AV1);
+        assert_eq!(options.bitrate, 2600);
+
+        let encoder = VideoCodecType::AV1.select_encoder(false);
+        assert!(!encoder.hardware);
+        assert_eq!(encoder.implementation, "svt-av1");
+
+        let capturer = ScreenCapturer::new();
+        capturer.set_video_codec(VideoCodecType::AV1).await;
+        capturer.apply_quality_preset(QualityPreset::Balanced).await;
+        assert_eq!(capturer.get_current_options().await.bitrate, 2600);
+
+        // Below the H.264 floor of 500 kbps AV1 still follows the link
+        capturer
+            .adapt_to_network_conditions(NetworkConditions {
+                available_bandwidth: 450,
+                packet_loss: 0.0,
+                rtt: 40,
+            })
+            .await;
+        assert_eq!(capturer.get_current_options().await.bitrate, 360);
+        assert_eq!(
+            capturer.encoder_selection().await.codec,
+            VideoCodecType::AV1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_decoder_limits_survive_later_changes() {
+        let capturer = ScreenCapturer::new();
+        capturer
+            .apply_decoder_capabilities(phone_decoder())
+            .await
+            .unwrap();
+
+        capturer.apply_quality_preset(QualityPreset::High).await;
+        capturer.set_resolution(2560, 1440).await;
+        capturer.set_video_codec(VideoCodecType::H265).await;
+        let options = capturer.get_current_options().await;
+        assert_eq!((options.width, options.height), (1920, 1080));
+        assert_eq!(options.frame_rate, 30);
+        assert_eq!(options.codec, VideoCodecType::H264);
+    }
+
     #[tokio::test]
     async fn test_adaptive_bitrate() {
         let capturer = ScreenCapturer::new();
@@ -628,9 +1380,307 @@ mod tests {
         assert!(options.bitrate <= 2000);
     }
 
+    #[cfg(feature = "audio")]
     #[tokio::test]
     async fn test_audio_capturer_creation() {
         let capturer = AudioCapturer::new();
         assert!(!capturer.is_capturing().await);
     }
+
+    #[cfg(feature = "audio")]
+    #[tokio::test]
+    async fn test_audio_capture_requires_permission() {
+        let mut capturer = AudioCapturer::new();
+        let options = AudioCaptureOptions::default();
+
+        assert!(capturer
+            .start_capture(options.clone(), &[Permission::ScreenView])
+            .await
+            .is_err());
+        assert!(!capturer.is_capturing().await);
+
+        capturer
+            .start_capture(options, &[Permission::AudioCapture])
+            .await
+            .unwrap();
+        assert!(capturer.is_capturing().await);
+        capturer.stop_capture().await;
+    }
+    #[tokio::test]
+    async fn test_capture_target_validated_and_defaulted() {
+        let capturer = ScreenCapturer::new();
+        let empty = CaptureTarget::Region {
+            x: 10,
+            y: 10,
+            width: 0,
+            height: 100,
+        };
+        assert!(capturer.set_capture_target(empty).await.is_err());
+        assert_eq!(
+            capturer.get_current_options().await.target,
+            CaptureTarget::FullDisplay
+        );
+        capturer
+            .set_capture_target(CaptureTarget::Window(0x1a0_0007))
+            .await
+            .unwrap();
+        assert_eq!(
+            capturer.get_current_options().await.target,
+            CaptureTarget::Window(0x1a0_0007)
+        );
+
+        // Options saved before targets existed still load
+        let mut value = serde_json::to_value(CaptureOptions::default()).unwrap();
+        value.as_object_mut().unwrap().remove("target");
+        let options: CaptureOptions = serde_json::from_value(value).unwrap();
+        assert_eq!(options.target, CaptureTarget::FullDisplay);
+    }
+
+    #[tokio::test]
+    async fn test_application_capture_is_separate_source() {
+        let mut capturer = ScreenCapturer::new();
+        let app = ApplicationInfo {
+            process_id: 4242,
+            name: "Support Tool".to_string(),
+            window_titles: vec!["Main".to_string()],
+            audio_isolation: per_process_audio_supported(),
+        };
+
+        // Display permission alone does not cover application sharing
+        assert!(capturer
+            .start_application_capture(&app, CaptureOptions::default(), &[Permission::ScreenView])
+            .await
+            .is_err());
+        assert!(capturer.current_source().is_none());
+
+        let _frames = capturer
+            .start_application_capture(&app, CaptureOptions::default(), &[Permission::AppSharing])
+            .await
+            .unwrap();
+        let source = capturer.current_source().unwrap().clone();
+        assert_eq!(
+            source,
+            CaptureSource::Application {
+                process_id: 4242,
+                name: "Support Tool".to_string()
+            }
+        );
+        assert_eq!(source.required_permission(), Permission::AppSharing);
+        capturer.stop_capture().await;
+        assert!(capturer.current_source().is_none());
+    }
+
+    #[cfg(feature = "audio")]
+    #[tokio::test]
+    async fn test_audio_device_selection_and_metering() {
+        use crate::audio_backend::{AudioDeviceKind, AudioSource};
+
+        /// A microphone producing a constant quarter-scale signal
+        struct FakeBackend;
+
+        struct Constant;
+
+        impl AudioSource for Constant {
+            fn read(&mut self, buffer: &mut [i16]) -> Result<()> {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                buffer.fill(8192);
+                Ok(())
+            }
+        }
+
+        impl AudioBackend for FakeBackend {
+            fn devices(&self) -> Result<Vec<AudioDeviceInfo>> {
+                Ok(vec![AudioDeviceInfo {
+                    id: "mic".to_string(),
+                    name: "Microphone".to_string(),
+                    kind: AudioDeviceKind::Microphone,
+                    is_default: false,
+                }])
+            }
+
+            fn open(
+                &self,
+                device_id: Option<&str>,
+                _sample_rate: u32,
+                _channels: u8,
+            ) -> Result<Box<dyn AudioSource>> {
+                match device_id {
+                    Some("mic") => Ok(Box::new(Constant)),
+                    _ => Err(anyhow::anyhow!("no default device")),
+                }
+            }
+        }
+
+        let mut capturer = AudioCapturer::new().with_backend(Arc::new(FakeBackend));
+        assert!(capturer
+            .select_device(Some("speakers".to_string()))
+            .await
+            .is_err());
+
+        // The default cannot be opened: silence of the right length
+        let mut frames = capturer
+            .start_capture(AudioCaptureOptions::default(), &[Permission::AudioCapture])
+            .await
+            .unwrap();
+        let silent = frames.recv().await.unwrap();
+        assert_eq!(silent.data.len(), 960 * 2);
+        assert!(silent.data.iter().all(|&s| s == 0));
+        assert!(capturer.last_error().is_some());
+        assert!(capturer.device_levels().is_empty());
+
+        capturer
+            .select_device(Some("mic".to_string()))
+            .await
+            .unwrap();
+        let live = loop {
+            let frame = frames.recv().await.unwrap();
+            if frame.data[0] != 0 {
+                break frame;
+            }
+        };
+        assert_eq!(live.data.len(), 960 * 2);
+        let level = capturer.device_levels()["mic"];
+        assert!((level.peak_dbfs + 12.04).abs() < 0.01);
+        assert_eq!(level.peak_dbfs, level.rms_dbfs);
+        assert!(capturer.last_error().is_none());
+        capturer.stop_capture().await;
+    }
+
+    #[cfg(feature = "audio")]
+    #[tokio::test]
+    async fn test_application_audio_never_falls_back_to_system_mix() {
+        use crate::audio_backend::AudioSource;
+
+        /// Plays system sound but cannot isolate a process
+        struct SystemMixOnly;
+
+        impl AudioBackend for SystemMixOnly {
+            fn devices(&self) -> Result<Vec<AudioDeviceInfo>> {
+                Ok(Vec::new())
+            }
+
+            fn open(
+                &self,
+                _device_id: Option<&str>,
+                _sample_rate: u32,
+                _channels: u8,
+            ) -> Result<Box<dyn AudioSource>> {
+                panic!("the system mix must not be opened for an application");
+            }
+        }
+
+        let mut capturer = AudioCapturer::new().with_backend(Arc::new(SystemMixOnly));
+        let options = AudioCaptureOptions::default();
+
+        assert!(capturer
+            .start_application_capture(options.clone(), 4242, &[Permission::AudioCapture])
+            .await
+            .is_err());
+
+        // Without process isolation the system mix is not captured instead
+        assert!(capturer
+            .start_application_capture(options, 4242, &[Permission::AppSharing])
+            .await
+            .is_err());
+        assert!(!capturer.is_capturing().await);
+        assert_eq!(capturer.captured_process(), None);
+    }
+
+    #[cfg(feature = "audio")]
+    #[tokio::test]
+    async fn test_application_audio_reads_only_that_process() {
+        use crate::audio_backend::AudioSource;
+
+        /// Isolates process 4242, whose audio is a constant signal
+        struct Isolating;
+
+        struct Constant;
+
+        impl AudioSource for Constant {
+            fn read(&mut self, buffer: &mut [i16]) -> Result<()> {
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                buffer.fill(4096);
+                Ok(())
+            }
+        }
+
+        impl AudioBackend for Isolating {
+            fn devices(&self) -> Result<Vec<AudioDeviceInfo>> {
+                Ok(Vec::new())
+            }
+
+            fn open(
+                &self,
+                _device_id: Option<&str>,
+                _sample_rate: u32,
+                _channels: u8,
+            ) -> Result<Box<dyn AudioSource>> {
+                panic!("the system mix must not be opened for an application");
+            }
+
+            fn process_capture_supported(&self) -> bool {
+                true
+            }
+
+            fn open_process(
+                &self,
+                process_id: u32,
+                _sample_rate: u32,
+                _channels: u8,
+            ) -> Result<Box<dyn AudioSource>> {
+                assert_eq!(process_id, 4242);
+                Ok(Box::new(Constant))
+            }
+        }
+
+        let mut capturer = AudioCapturer::new().with_backend(Arc::new(Isolating));
+        let mut frames = capturer
+            .start_application_capture(
+                AudioCaptureOptions::default(),
+                4242,
+                &[Permission::AppSharing],
+            )
+            .await
+            .unwrap();
+        let frame = frames.recv().await.unwrap();
+        assert!(frame.data.iter().all(|&s| s == 4096));
+        assert_eq!(capturer.captured_process(), Some(4242));
+        assert!(capturer.device_levels().contains_key("process:4242"));
+        capturer.stop_capture().await;
+        assert_eq!(capturer.captured_process(), None);
+    }
+
+    #[test]
+    fn test_applications_grouped_from_windows() {
+        let window = |window_id: u64, title: &str, process_id: Option<u32>| WindowInfo {
+            window_id,
+            title: title.to_string(),
+            process_id,
+            x: 0,
+            y: 0,
+            width: 800,
+            height: 600,
+        };
+        let this = std::process::id();
+        let applications = applications_from_windows(
+            vec![
+                window(1, "Editor", Some(this)),
+                window(2, "Unowned", None),
+                window(3, "Mail", Some(u32::MAX)),
+                window(4, "Editor - Preferences", Some(this)),
+            ],
+            true,
+        );
+
+        // Topmost application first, its windows in stacking order
+        assert_eq!(applications.len(), 2);
+        assert_eq!(applications[0].process_id, this);
+        assert_eq!(
+            applications[0].window_titles,
+            vec!["Editor".to_string(), "Editor - Preferences".to_string()]
+        );
+        assert!(!applications[0].name.starts_with("Process "));
+        assert_eq!(applications[1].name, format!("Process {}", u32::MAX));
+        assert!(applications.iter().all(|a| a.audio_isolation));
+    }
 }
@@ -0,0 +1,1289 @@
+//! Encrypted Local Settings and Credential Store
+//!
+//! Stores unattended-access password hashes, auth tokens, TURN credentials and
+//! device keys outside of plaintext settings. Secrets live in the OS keychain:
+//! Keychain Services on macOS, Credential Manager on Windows and the Secret
+//! Service (GNOME Keyring, KWallet) on Linux. Without one, they fall back to
+//! an AES-256-GCM encrypted file whose random key sits beside it in an
+//! owner-only file, which protects them from other users but not from this
+//! one.
+//! Requirements: 5.6, 10.3
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use anyhow::{Context, Result};
+use base64::Engine;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Keychain service name used for all entries
+pub const SECRETS_SERVICE_NAME: &str = "cec-remote";
+
+/// File name of the encrypted fallback store
+pub const SECRETS_FILE_NAME: &str = "secrets.enc";
+
+/// File name of the fallback store's key, next to the store
+pub const SECRETS_KEY_FILE_NAME: &str = "secrets.key";
+
+/// Settings keys that must never be kept in plaintext settings files
+pub const SENSITIVE_SETTING_KEYS: &[&str] = &[
+    SETTING_UNATTENDED_PASSWORD_HASH,
+    SETTING_AUTH_TOKEN,
+    SETTING_TURN_USERNAME,
+    SETTING_TURN_CREDENTIAL,
+];
+
+const SETTING_UNATTENDED_PASSWORD_HASH: &str = "unattended_password_hash";
+const SETTING_AUTH_TOKEN: &str = "auth_token";
+const SETTING_TURN_USERNAME: &str = "turn_username";
+const SETTING_TURN_CREDENTIAL: &str = "turn_credential";
+/// Plaintext setting naming the TURN server the credentials belong to
+const SETTING_TURN_URL: &str = "turn_url";
+
+const KEY_UNATTENDED_PASSWORD_HASH: &str = "unattended_password_hash";
+const KEY_DEVICE_SIGNING_KEY: &str = "device_signing_key";
+const KEY_SIGNALING_SECRET_KEY: &str = "signaling_secret_key";
+const KEY_AUTH_TOKEN_PREFIX: &str = "auth_token:";
+const KEY_TURN_CREDENTIAL_PREFIX: &str = "turn_credential:";
+
+/// Storage backend for secrets
+pub trait SecretBackend: Send + Sync {
+    /// Backend name for diagnostics
+    fn name(&self) -> &'static str;
+    /// Read a secret
+    fn get(&self, key: &str) -> Result<Option<String>>;
+    /// Write a secret, replacing any previous value
+    fn set(&self, key: &str, value: &str) -> Result<()>;
+    /// Delete a secret if present
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// OS keychain backend (Keychain Services, Credential Manager, Secret Service)
+///
+/// Entries are generic passwords under the service name, with the secret key
+/// as the account.
+pub struct KeychainBackend {
+    service: String,
+    #[cfg(target_os = "linux")]
+    secret_service: std::sync::Mutex<secret_service::SecretService>,
+}
+
+impl KeychainBackend {
+    /// Open the platform keychain, or `None` if it is not available
+    ///
+    /// On Linux that includes a missing Secret Service and a locked default
+    /// collection, which would need a prompt to use.
+    pub fn open(service: &str) -> Option<Self> {
+        #[cfg(target_os = "windows")]
+        {
+            credman::available(service).then(|| Self {
+                service: service.to_string(),
+            })
+        }
+        #[cfg(target_os = "macos")]
+        {
+            keychain::available().then(|| Self {
+                service: service.to_string(),
+            })
+        }
+        #[cfg(target_os = "linux")]
+        {
+            match secret_service::SecretService::open() {
+                Ok(secret_service) => Some(Self {
+                    service: service.to_string(),
+                    secret_service: std::sync::Mutex::new(secret_service),
+                }),
+                Err(e) => {
+                    tracing::debug!("Secret Service unavailable: {:#}", e);
+                    None
+                }
+            }
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            let _ = service;
+            None
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn secret_service(&self) -> std::sync::MutexGuard<'_, secret_service::SecretService> {
+        self.secret_service
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+impl SecretBackend for KeychainBackend {
+    fn name(&self) -> &'static str {
+        "keychain"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        #[cfg(target_os = "windows")]
+        let value = credman::read(&self.service, key)?;
+        #[cfg(target_os = "macos")]
+        let value = keychain::read(&self.service, key)?;
+        #[cfg(target_os = "linux")]
+        let value = self.secret_service().read(&self.service, key)?;
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        let value: Option<Vec<u8>> = {
+            let _ = (&self.service, key);
+            return Err(keychain_unsupported());
+        };
+
+        value
+            .map(|bytes| String::from_utf8(bytes).context("Keychain entry is not UTF-8"))
+            .transpose()
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        #[cfg(target_os = "windows")]
+        {
+            credman::write(&self.service, key, value.as_bytes())
+        }
+        #[cfg(target_os = "macos")]
+        {
+            keychain::write(&self.service, key, value.as_bytes())
+        }
+        #[cfg(target_os = "linux")]
+        {
+            self.secret_service()
+                .write(&self.service, key, value.as_bytes())
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            let _ = (key, value);
+            Err(keychain_unsupported())
+        }
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        #[cfg(target_os = "windows")]
+        {
+            credman::delete(&self.service, key)
+        }
+        #[cfg(target_os = "macos")]
+        {
+            keychain::delete(&self.service, key)
+        }
+        #[cfg(target_os = "linux")]
+        {
+            self.secret_service().delete(&self.service, key)
+        }
+        #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+        {
+            let _ = key;
+            Err(keychain_unsupported())
+        }
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+fn keychain_unsupported() -> anyhow::Error {
+    anyhow::anyhow!("OS keychain is not supported on {}", std::env::consts::OS)
+}
+
+/// Windows Credential Manager, whose generic credentials are encrypted with
+/// DPAPI for the logged-in user
+#[cfg(target_os = "windows")]
+mod credman {
+    use anyhow::Result;
+    use std::ffi::c_void;
+
+    #[repr(C)]
+    struct FileTime {
+        low: u32,
+        high: u32,
+    }
+
+    #[repr(C)]
+    struct CredentialW {
+        flags: u32,
+        kind: u32,
+        target_name: *mut u16,
+        comment: *mut u16,
+        last_written: FileTime,
+        credential_blob_size: u32,
+        credential_blob: *mut u8,
+        persist: u32,
+        attribute_count: u32,
+        attributes: *mut c_void,
+        target_alias: *mut u16,
+        user_name: *mut u16,
+    }
+
+    const CRED_TYPE_GENERIC: u32 = 1;
+    const CRED_PERSIST_LOCAL_MACHINE: u32 = 2;
+    /// `CRED_MAX_CREDENTIAL_BLOB_SIZE`
+    const MAX_BLOB_SIZE: usize = 5 * 512;
+    const ERROR_NOT_FOUND: u32 = 1168;
+    const ERROR_NO_SUCH_LOGON_SESSION: u32 = 1312;
+
+    extern "system" {
+        fn CredReadW(
+            target: *const u16,
+            kind: u32,
+            flags: u32,
+            credential: *mut *mut CredentialW,
+        ) -> i32;
+        fn CredWriteW(credential: *const CredentialW, flags: u32) -> i32;
+        fn CredDeleteW(target: *const u16, kind: u32, flags: u32) -> i32;
+        fn CredFree(buffer: *const c_void);
+        fn GetLastError() -> u32;
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    fn target(service: &str, key: &str) -> Vec<u16> {
+        wide(&format!("{}/{}", service, key))
+    }
+
+    fn last_error() -> u32 {
+        // SAFETY: no arguments
+        unsafe { GetLastError() }
+    }
+
+    fn os_error(action: &str, code: u32) -> anyhow::Error {
+        anyhow::anyhow!(
+            "Credential Manager {} failed: {}",
+            action,
+            std::io::Error::from_raw_os_error(code as i32)
+        )
+    }
+
+    /// Whether this logon session has a credential store; services running
+    /// without a user profile do not
+    pub(super) fn available(service: &str) -> bool {
+        !matches!(
+            read(service, "probe"),
+            Err(e) if e.to_string().contains(&format!("os error {}", ERROR_NO_SUCH_LOGON_SESSION))
+        )
+    }
+
+    pub(super) fn read(service: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        let target = target(service, key);
+        let mut credential = std::ptr::null_mut();
+        // SAFETY: the target is NUL-terminated and the out pointer is valid
+        if unsafe { CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) } == 0 {
+            return match last_error() {
+                ERROR_NOT_FOUND => Ok(None),
+                code => Err(os_error("read", code)),
+            };
+        }
+        // SAFETY: CredReadW returned a credential whose blob has the given
+        // size; it is freed once copied
+        unsafe {
+            let blob = if (*credential).credential_blob.is_null() {
+                Vec::new()
+            } else {
+                std::slice::from_raw_parts(
+                    (*credential).credential_blob,
+                    (*credential).credential_blob_size as usize,
+                )
+                .to_vec()
+            };
+            CredFree(credential.cast());
+            Ok(Some(blob))
+        }
+    }
+
+    pub(super) fn write(service: &str, key: &str, value: &[u8]) -> Result<()> {
+        if value.len() > MAX_BLOB_SIZE {
+            return Err(anyhow::anyhow!(
+                "Secret of {} bytes exceeds the Credential Manager limit",
+                value.len()
+            ));
+        }
+        let mut target = target(service, key);
+        let mut user = wide(service);
+        let credential = CredentialW {
+            flags: 0,
+            kind: CRED_TYPE_GENERIC,
+            target_name: target.as_mut_ptr(),
+            comment: std::ptr::null_mut(),
+            last_written: FileTime { low: 0, high: 0 },
+            credential_blob_size: value.len() as u32,
+            credential_blob: value.as_ptr().cast_mut(),
+            persist: CRED_PERSIST_LOCAL_MACHINE,
+            attribute_count: 0,
+            attributes: std::ptr::null_mut(),
+            target_alias: std::ptr::null_mut(),
+            user_name: user.as_mut_ptr(),
+        };
+        // SAFETY: every pointer in the credential outlives the call, and the
+        // blob is only read
+        if unsafe { CredWriteW(&credential, 0) } == 0 {
+            return Err(os_error("write", last_error()));
+        }
+        Ok(())
+    }
+
+    pub(super) fn delete(service: &str, key: &str) -> Result<()> {
+        let target = target(service, key);
+        // SAFETY: the target is NUL-terminated
+        if unsafe { CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) } == 0 {
+            return match last_error() {
+                ERROR_NOT_FOUND => Ok(()),
+                code => Err(os_error("delete", code)),
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Keychain Services generic passwords in the user's default keychain
+#[cfg(target_os = "macos")]
+mod keychain {
+    use anyhow::Result;
+    use std::ffi::{c_char, c_void};
+
+    type OsStatus = i32;
+    type ItemRef = *mut c_void;
+
+    const ERR_SEC_SUCCESS: OsStatus = 0;
+    const ERR_SEC_ITEM_NOT_FOUND: OsStatus = -25300;
+
+    extern "C" {
+        fn SecKeychainCopyDefault(keychain: *mut *mut c_void) -> OsStatus;
+        fn SecKeychainFindGenericPassword(
+            keychain_or_array: *const c_void,
+            service_length: u32,
+            service: *const c_char,
+            account_length: u32,
+            account: *const c_char,
+            password_length: *mut u32,
+            password: *mut *mut c_void,
+            item: *mut ItemRef,
+        ) -> OsStatus;
+        fn SecKeychainAddGenericPassword(
+            keychain: *mut c_void,
+            service_length: u32,
+            service: *const c_char,
+            account_length: u32,
+            account: *const c_char,
+            password_length: u32,
+            password: *const c_void,
+            item: *mut ItemRef,
+        ) -> OsStatus;
+        fn SecKeychainItemModifyAttributesAndData(
+            item: ItemRef,
+            attributes: *const c_void,
+            length: u32,
+            data: *const c_void,
+        ) -> OsStatus;
+        fn SecKeychainItemDelete(item: ItemRef) -> OsStatus;
+        fn SecKeychainItemFreeContent(attributes: *mut c_void, data: *mut c_void) -> OsStatus;
+        fn CFRelease(cf: *const c_void);
+    }
+
+    fn check(action: &str, status: OsStatus) -> Result<()> {
+        match status {
+            ERR_SEC_SUCCESS => Ok(()),
+            status => Err(anyhow::anyhow!(
+                "Keychain {} failed with status {}",
+                action,
+                status
+            )),
+        }
+    }
+
+    /// Whether the user has a default keychain; daemons outside a login
+    /// session do not
+    pub(super) fn available() -> bool {
+        let mut keychain = std::ptr::null_mut();
+        // SAFETY: the out pointer is valid; the returned keychain is released
+        unsafe {
+            if SecKeychainCopyDefault(&mut keychain) != ERR_SEC_SUCCESS || keychain.is_null() {
+                return false;
+            }
+            CFRelease(keychain);
+        }
+        true
+    }
+
+    /// Look up an item, returning it (to be released) and, if asked, its data
+    fn find(service: &str, key: &str, with_data: bool) -> Result<Option<(ItemRef, Vec<u8>)>> {
+        let mut length = 0u32;
+        let mut data = std::ptr::null_mut();
+        let mut item = std::ptr::null_mut();
+        // SAFETY: lengths match the buffers; the data is copied and freed
+        unsafe {
+            let status = SecKeychainFindGenericPassword(
+                std::ptr::null(),
+                service.len() as u32,
+                service.as_ptr().cast(),
+                key.len() as u32,
+                key.as_ptr().cast(),
+                if with_data {
+                    &mut length
+                } else {
+                    std::ptr::null_mut()
+                },
+                if with_data {
+                    &mut data
+                } else {
+                    std::ptr::null_mut()
+                },
+                &mut item,
+            );
+            if status == ERR_SEC_ITEM_NOT_FOUND {
+                return Ok(None);
+            }
+            check("lookup", status)?;
+            let mut bytes = Vec::new();
+            if !data.is_null() {
+                bytes = std::slice::from_raw_parts(data.cast::<u8>(), length as usize).to_vec();
+                SecKeychainItemFreeContent(std::ptr::null_mut(), data);
+            }
+            Ok(Some((item, bytes)))
+        }
+    }
+
+    pub(super) fn read(service: &str, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(find(service, key, true)?.map(|(item, bytes)| {
+            // SAFETY: returned retained by the lookup
+            unsafe { CFRelease(item) };
+            bytes
+        }))
+    }
+
+    pub(super) fn write(service: &str, key: &str, value: &[u8]) -> Result<()> {
+        // SAFETY: lengths match the buffers; items are released after use
+        unsafe {
+            if let Some((item, _)) = find(service, key, false)? {
+                let status = SecKeychainItemModifyAttributesAndData(
+                    item,
+                    std::ptr::null(),
+                    value.len() as u32,
+                    value.as_ptr().cast(),
+                );
+                CFRelease(item);
+                return check("update", status);
+            }
+            let mut item = std::ptr::null_mut();
+            check(
+                "add",
+                SecKeychainAddGenericPassword(
+                    std::ptr::null_mut(),
+                    service.len() as u32,
+                    service.as_ptr().cast(),
+                    key.len() as u32,
+                    key.as_ptr().cast(),
+                    value.len() as u32,
+                    value.as_ptr().cast(),
+                    &mut item,
+                ),
+            )?;
+            if !item.is_null() {
+                CFRelease(item);
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn delete(service: &str, key: &str) -> Result<()> {
+        if let Some((item, _)) = find(service, key, false)? {
+            // SAFETY: the item came from the lookup and is released after
+            unsafe {
+                let status = SecKeychainItemDelete(item);
+                CFRelease(item);
+                check("delete", status)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// freedesktop Secret Service over the session bus, using the default
+/// collection with a plain (unencrypted, local socket) transfer session
+#[cfg(target_os = "linux")]
+mod secret_service {
+    use crate::dbus::{Connection, Value};
+    use anyhow::{Context, Result};
+
+    const DESTINATION: &str = "org.freedesktop.secrets";
+    const SERVICE_PATH: &str = "/org/freedesktop/secrets";
+    const SERVICE_INTERFACE: &str = "org.freedesktop.Secret.Service";
+    const COLLECTION_INTERFACE: &str = "org.freedesktop.Secret.Collection";
+    const ITEM_INTERFACE: &str = "org.freedesktop.Secret.Item";
+    const LABEL_PROPERTY: &str = "org.freedesktop.Secret.Item.Label";
+    const ATTRIBUTES_PROPERTY: &str = "org.freedesktop.Secret.Item.Attributes";
+    /// Object path meaning "none", e.g. when no prompt is needed
+    const NO_OBJECT: &str = "/";
+
+    pub(super) struct SecretService {
+        connection: Connection,
+        session: String,
+        collection: String,
+    }
+
+    impl SecretService {
+        pub(super) fn open() -> Result<Self> {
+            let mut connection = Connection::session()?;
+            let reply = connection.call(
+                DESTINATION,
+                SERVICE_PATH,
+                SERVICE_INTERFACE,
+                "OpenSession",
+                &[Value::str("plain"), Value::variant(Value::str(""))],
+            )?;
+            let session = reply
+                .get(1)
+                .and_then(Value::as_str)
+                .context("OpenSession returned no session")?
+                .to_string();
+            let collection = connection
+                .call(
+                    DESTINATION,
+                    SERVICE_PATH,
+                    SERVICE_INTERFACE,
+                    "ReadAlias",
+                    &[Value::str("default")],
+                )?
+                .first()
+                .and_then(Value::as_str)
+                .context("ReadAlias returned no collection")?
+                .to_string();
+            if collection == NO_OBJECT {
+                return Err(anyhow::anyhow!("No default keyring"));
+            }
+            let locked = connection
+                .get_property(DESTINATION, &collection, COLLECTION_INTERFACE, "Locked")?
+                .as_bool()
+                .unwrap_or(true);
+            if locked {
+                return Err(anyhow::anyhow!("Default keyring is locked"));
+            }
+            Ok(Self {
+                connection,
+                session,
+                collection,
+            })
+        }
+
+        /// Unlocked items holding `key`
+        fn search(&mut self, service: &str, key: &str) -> Result<Vec<String>> {
+            let reply = self.connection.call(
+                DESTINATION,
+                SERVICE_PATH,
+                SERVICE_INTERFACE,
+                "SearchItems",
+                &[Value::string_dict([("service", service), ("account", key)])],
+            )?;
+            let paths = |index: usize| -> Vec<String> {
+                reply
+                    .get(index)
+                    .and_then(Value::items)
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|path| path.as_str().map(str::to_string))
+                    .collect()
+            };
+            let (unlocked, locked) = (paths(0), paths(1));
+            if unlocked.is_empty() && !locked.is_empty() {
+                return Err(anyhow::anyhow!("Keyring entry {} is locked", key));
+            }
+            Ok(unlocked)
+        }
+
+        fn check_prompt(reply: &[Value], index: usize) -> Result<()> {
+            match reply.get(index).and_then(Value::as_str) {
+                Some(NO_OBJECT) => Ok(()),
+                _ => Err(anyhow::anyhow!("Keyring needs to be unlocked")),
+            }
+        }
+
+        pub(super) fn read(&mut self, service: &str, key: &str) -> Result<Option<Vec<u8>>> {
+            let Some(item) = self.search(service, key)?.into_iter().next() else {
+                return Ok(None);
+            };
+            let reply = self.connection.call(
+                DESTINATION,
+                &item,
+                ITEM_INTERFACE,
+                "GetSecret",
+                &[Value::path(&self.session)],
+            )?;
+            // (session, parameters, value, content type)
+            reply
+                .first()
+                .and_then(Value::items)
+                .and_then(|secret| secret.get(2))
+                .and_then(Value::as_bytes)
+                .map(Some)
+                .context("GetSecret returned no secret")
+        }
+
+        pub(super) fn write(&mut self, service: &str, key: &str, value: &[u8]) -> Result<()> {
+            let label = format!("{} {}", service, key);
+            let properties = Value::variant_dict([
+                (LABEL_PROPERTY, Value::Str(label)),
+                (
+                    ATTRIBUTES_PROPERTY,
+                    Value::string_dict([("service", service), ("account", key)]),
+                ),
+            ]);
+            let secret = Value::Struct(vec![
+                Value::path(&self.session),
+                Value::bytes(&[]),
+                Value::bytes(value),
+                Value::str("text/plain; charset=utf8"),
+            ]);
+            let reply = self.connection.call(
+                DESTINATION,
+                &self.collection,
+                COLLECTION_INTERFACE,
+                "CreateItem",
+                &[properties, secret, Value::Bool(true)],
+            )?;
+            Self::check_prompt(&reply, 1)
+        }
+
+        pub(super) fn delete(&mut self, service: &str, key: &str) -> Result<()> {
+            for item in self.search(service, key)? {
+                let reply =
+                    self.connection
+                        .call(DESTINATION, &item, ITEM_INTERFACE, "Delete", &[])?;
+                Self::check_prompt(&reply, 0)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// On-disk format of the encrypted fallback store
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedSecretsFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Encrypted-file backend, used when no OS keychain is available
+pub struct EncryptedFileBackend {
+    path: PathBuf,
+    key: [u8; 32],
+    salt: [u8; 16],
+    entries: RwLock<HashMap<String, String>>,
+}
+
+impl EncryptedFileBackend {
+    /// Open (or create) an encrypted store keyed by the random key in
+    /// [`SECRETS_KEY_FILE_NAME`] beside it, creating the key owner-only
+    ///
+    /// A store written by earlier versions, keyed from the guessable machine
+    /// ID, is re-encrypted under a new random key.
+    pub fn open(path: PathBuf) -> Result<Self> {
+        let key_path = path.with_file_name(SECRETS_KEY_FILE_NAME);
+        if key_path.exists() || !path.exists() {
+            return Self::open_with_secret(path, &load_or_create_key(&key_path)?);
+        }
+
+        let legacy = Self::open_with_secret(path.clone(), &legacy_machine_secret())
+            .context("Failed to open secrets file for re-keying")?;
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let store = Self {
+            key: derive_key(&load_or_create_key(&key_path)?, &salt)?,
+            path,
+            salt,
+            entries: legacy.entries,
+        };
+        {
+            let entries = store
+                .entries
+                .read()
+                .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+            store.flush(&entries)?;
+        }
+        tracing::info!("Re-encrypted {} under a random key", store.path.display());
+        Ok(store)
+    }
+
+    /// Open (or create) an encrypted store keyed from the given secret
+    pub fn open_with_secret(path: PathBuf, machine_secret: &[u8]) -> Result<Self> {
+        if path.exists() {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read secrets file: {}", path.display()))?;
+            let file: EncryptedSecretsFile =
+                serde_json::from_str(&content).context("Corrupted secrets file")?;
+
+            let salt: [u8; 16] = hex::decode(&file.salt)?
+                .try_into()
+                .map_err(|_| anyhow::anyhow!("Invalid secrets file salt"))?;
+            let key = derive_key(machine_secret, &salt)?;
+
+            let b64 = base64::engine::general_purpose::STANDARD;
+            let nonce = hex::decode(&file.nonce)?;
+            let ciphertext = b64.decode(&file.ciphertext)?;
+            let cipher = Aes256Gcm::new_from_slice(&key)
+                .map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))?;
+            let plaintext = cipher
+                .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+                .map_err(|_| anyhow::anyhow!("Failed to decrypt secrets file"))?;
+
+            Ok(Self {
+                path,
+                key,
+                salt,
+                entries: RwLock::new(serde_json::from_slice(&plaintext)?),
+            })
+        } else {
+            let mut salt = [0u8; 16];
+            OsRng.fill_bytes(&mut salt);
+            Ok(Self {
+                key: derive_key(machine_secret, &salt)?,
+                path,
+                salt,
+                entries: RwLock::new(HashMap::new()),
+            })
+        }
+    }
+
+    /// Path of the encrypted file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Every stored entry
+    fn entries(&self) -> Result<HashMap<String, String>> {
+        self.entries
+            .read()
+            .map(|entries| entries.clone())
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))
+    }
+
+    fn flush(&self, entries: &HashMap<String, String>) -> Result<()> {
+        let plaintext = serde_json::to_vec(entries)?;
+
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let cipher = Aes256Gcm::new_from_slice(&self.key)
+            .map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))?;
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+
+        let file = EncryptedSecretsFile {
+            version: 1,
+            salt: hex::encode(self.salt),
+            nonce: hex::encode(nonce),
+            ciphertext: base64::engine::general_purpose::STANDARD.encode(ciphertext),
+        };
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // Write to a temporary file first so a crash never leaves a torn store.
+        // It is created fresh so it never inherits a looser mode.
+        let tmp_path = self.path.with_extension("tmp");
+        let _ = std::fs::remove_file(&tmp_path);
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut tmp = options
+            .open(&tmp_path)
+            .with_context(|| format!("Failed to create {}", tmp_path.display()))?;
+        tmp.write_all(serde_json::to_string(&file)?.as_bytes())?;
+        tmp.sync_all()?;
+        drop(tmp);
+        std::fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl SecretBackend for EncryptedFileBackend {
+    fn name(&self) -> &'static str {
+        "encrypted-file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        let entries = self
+            .entries
+            .read()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+        Ok(entries.get(key).cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut entries = self
+            .entries
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+        entries.insert(key.to_string(), value.to_string());
+        self.flush(&entries)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let mut entries = self
+            .entries
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+        if entries.remove(key).is_some() {
+            self.flush(&entries)?;
+        }
+        Ok(())
+    }
+}
+
+/// TURN server credential
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TurnCredential {
+    pub username: String,
+    pub credential: String,
+}
+
+/// Typed secrets store used by access control, security and networking
+pub struct SecretsStore {
+    backend: Arc<dyn SecretBackend>,
+}
+
+impl SecretsStore {
+    /// Open the store for a data directory, preferring the OS keychain
+    ///
+    /// Secrets left in an encrypted file by an earlier run without a
+    /// keychain are moved into the keychain.
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join(SECRETS_FILE_NAME);
+        if let Some(keychain) = KeychainBackend::open(SECRETS_SERVICE_NAME) {
+            tracing::info!("Using OS keychain for secrets");
+            if path.exists() {
+                move_into_keychain(&path, &keychain)?;
+            }
+            return Ok(Self::with_backend(Arc::new(keychain)));
+        }
+
+        tracing::warn!(
+            "No OS keychain available; secrets in {} are protected only by the \
+             permissions of {}",
+            path.display(),
+            path.with_file_name(SECRETS_KEY_FILE_NAME).display()
+        );
+        Ok(Self::with_backend(Arc::new(EncryptedFileBackend::open(
+            path,
+        )?)))
+    }
+
+    /// Create a store on top of a specific backend
+    pub fn with_backend(backend: Arc<dyn SecretBackend>) -> Self {
+        Self { backend }
+    }
+
+    /// Name of the active backend
+    pub fn backend_name(&self) -> &'static str {
+        self.backend.name()
+    }
+
+    /// Read a raw secret
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        self.backend.get(key)
+    }
+
+    /// Write a raw secret
+    pub fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.backend.set(key, value)
+    }
+
+    /// Delete a raw secret
+    pub fn delete(&self, key: &str) -> Result<()> {
+        self.backend.delete(key)
+    }
+
+    /// Store the unattended access password hash
+    pub fn set_unattended_password_hash(&self, hash: &str) -> Result<()> {
+        self.set(KEY_UNATTENDED_PASSWORD_HASH, hash)
+    }
+
+    /// Get the unattended access password hash
+    pub fn get_unattended_password_hash(&self) -> Result<Option<String>> {
+        self.get(KEY_UNATTENDED_PASSWORD_HASH)
+    }
+
+    /// Remove the unattended access password hash
+    pub fn clear_unattended_password_hash(&self) -> Result<()> {
+        self.delete(KEY_UNATTENDED_PASSWORD_HASH)
+    }
+
+    /// Store an auth token for a service
+    pub fn set_auth_token(&self, service: &str, token: &str) -> Result<()> {
+        self.set(&format!("{}{}", KEY_AUTH_TOKEN_PREFIX, service), token)
+    }
+
+    /// Get the auth token for a service
+    pub fn get_auth_token(&self, service: &str) -> Result<Option<String>> {
+        self.get(&format!("{}{}", KEY_AUTH_TOKEN_PREFIX, service))
+    }
+
+    /// Store credentials for a TURN server
+    pub fn set_turn_credential(&self, url: &str, credential: &TurnCredential) -> Result<()> {
+        self.set(
+            &format!("{}{}", KEY_TURN_CREDENTIAL_PREFIX, url),
+            &serde_json::to_string(credential)?,
+        )
+    }
+
+    /// Get credentials for a TURN server
+    pub fn get_turn_credential(&self, url: &str) -> Result<Option<TurnCredential>> {
+        match self.get(&format!("{}{}", KEY_TURN_CREDENTIAL_PREFIX, url))? {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the device signing key
+    pub fn set_device_signing_key(&self, key: &[u8]) -> Result<()> {
+        self.set(KEY_DEVICE_SIGNING_KEY, &hex::encode(key))
+    }
+
+    /// Get the device signing key
+    pub fn get_device_signing_key(&self) -> Result<Option<Vec<u8>>> {
+        match self.get(KEY_DEVICE_SIGNING_KEY)? {
+            Some(encoded) => Ok(Some(hex::decode(encoded)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store the X25519 key signaling payloads are sealed to
+    pub fn set_signaling_secret_key(&self, key: &[u8]) -> Result<()> {
+        self.set(KEY_SIGNALING_SECRET_KEY, &hex::encode(key))
+    }
+
+    /// Get the X25519 key signaling payloads are sealed to
+    pub fn get_signaling_secret_key(&self) -> Result<Option<Vec<u8>>> {
+        match self.get(KEY_SIGNALING_SECRET_KEY)? {
+            Some(encoded) => Ok(Some(hex::decode(encoded)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Move sensitive values out of a plaintext JSON settings file
+    ///
+    /// Each key in `SENSITIVE_SETTING_KEYS` found at the top level of the file is
+    /// written through the matching typed setter and removed from the file: the
+    /// auth token is stored for `auth_service`, and TURN credentials for the
+    /// server named by the file's `turn_url`. TURN credentials without a
+    /// `turn_url` are left in place. Returns the number of migrated values.
+    pub fn migrate_plaintext_settings(
+        &self,
+        settings_path: &Path,
+        auth_service: &str,
+    ) -> Result<usize> {
+        if !settings_path.exists() {
+            return Ok(0);
+        }
+
+        let content = std::fs::read_to_string(settings_path)?;
+        let mut settings: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_str(&content).context("Invalid settings file")?;
+
+        let mut migrated = 0;
+        if let Some(hash) = settings.remove(SETTING_UNATTENDED_PASSWORD_HASH) {
+            self.set_unattended_password_hash(&setting_string(hash))?;
+            migrated += 1;
+        }
+        if let Some(token) = settings.remove(SETTING_AUTH_TOKEN) {
+            self.set_auth_token(auth_service, &setting_string(token))?;
+            migrated += 1;
+        }
+
+        let has_turn_secret = settings.contains_key(SETTING_TURN_USERNAME)
+            || settings.contains_key(SETTING_TURN_CREDENTIAL);
+        match settings.get(SETTING_TURN_URL).and_then(|url| url.as_str()) {
+            Some(url) if has_turn_secret => {
+                let url = url.to_string();
+                let mut take = |key| {
+                    settings.remove(key).map(|value| {
+                        migrated += 1;
+                        setting_string(value)
+                    })
+                };
+                let credential = TurnCredential {
+                    username: take(SETTING_TURN_USERNAME).unwrap_or_default(),
+                    credential: take(SETTING_TURN_CREDENTIAL).unwrap_or_default(),
+                };
+                self.set_turn_credential(&url, &credential)?;
+            }
+            None if has_turn_secret => tracing::warn!(
+                "TURN credentials in {} name no {}, not migrated",
+                settings_path.display(),
+                SETTING_TURN_URL
+            ),
+            _ => {}
+        }
+
+        if migrated > 0 {
+            std::fs::write(settings_path, serde_json::to_string_pretty(&settings)?)?;
+            tracing::info!(
+                "Migrated {} plaintext secrets from {}",
+                migrated,
+                settings_path.display()
+            );
+        }
+
+        Ok(migrated)
+    }
+}
+
+/// A plaintext setting value as the string the store keeps
+fn setting_string(value: serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+/// Derive the file encryption key from the machine secret
+fn derive_key(machine_secret: &[u8], salt: &[u8]) -> Result<[u8; 32]> {
+    let hk = hkdf::Hkdf::<Sha256>::new(Some(salt), machine_secret);
+    let mut key = [0u8; 32];
+    hk.expand(b"cec-remote-secrets", &mut key)
+        .map_err(|_| anyhow::anyhow!("Key derivation failed"))?;
+    Ok(key)
+}
+
+/// Copy every entry of an encrypted secrets file into the keychain, then
+/// delete the file and its key
+fn move_into_keychain(path: &Path, keychain: &KeychainBackend) -> Result<()> {
+    let file = EncryptedFileBackend::open(path.to_path_buf())?;
+    let entries = file.entries()?;
+    for (key, value) in &entries {
+        keychain.set(key, value)?;
+    }
+    std::fs::remove_file(path)?;
+    let key_path = path.with_file_name(SECRETS_KEY_FILE_NAME);
+    if key_path.exists() {
+        std::fs::remove_file(key_path)?;
+    }
+    tracing::info!(
+        "Moved {} secrets from {} into the OS keychain",
+        entries.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Read the fallback store's key, creating a random one readable only by
+/// this user if there is none
+fn load_or_create_key(key_path: &Path) -> Result<Vec<u8>> {
+    match std::fs::read(key_path) {
+        Ok(key) if key.len() == 32 => return Ok(key),
+        Ok(_) => return Err(anyhow::anyhow!("Invalid key file {}", key_path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", key_path.display())),
+    }
+
+    if let Some(parent) = key_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut key = vec![0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(key_path)
+        .with_context(|| format!("Failed to create {}", key_path.display()))?;
+    file.write_all(&key)?;
+    file.sync_all()?;
+    Ok(key)
+}
+
+/// Machine-derived secret that keyed secrets files before random keys;
+/// only used to re-key such files
+fn legacy_machine_secret() -> Vec<u8> {
+    #[cfg(target_os = "linux")]
+    {
+        for path in ["/etc/machine-id", "/var/lib/dbus/machine-id"] {
+            if let Ok(id) = std::fs::read_to_string(path) {
+                let id = id.trim();
+                if !id.is_empty() {
+                    return id.as_bytes().to_vec();
+                }
+            }
+        }
+    }
+
+    let host = std::env::var("COMPUTERNAME")
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_default();
+    let user = std::env::var("USERNAME")
+        .or_else(|_| std::env::var("USER"))
+        .unwrap_or_default();
+    format!("{}:{}:{}", std::env::consts::OS, host, user).into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cec-secrets-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_encrypted_file_roundtrip() {
+        let dir = temp_dir();
+        let path = dir.join(SECRETS_FILE_NAME);
+
+        {
+            let backend = EncryptedFileBackend::open_with_secret(path.clone(), b"machine").unwrap();
+            let store = SecretsStore::with_backend(Arc::new(backend));
+            store.set_unattended_password_hash("hash-value").unwrap();
+            store
+                .set_turn_credential(
+                    "turn:turn.example.com",
+                    &TurnCredential {
+                        username: "user".to_string(),
+                        credential: "s3cret".to_string(),
+                    },
+                )
+                .unwrap();
+        }
+
+        let raw = std::fs::read_to_string(&path).unwrap();
+        assert!(!raw.contains("hash-value"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        assert!(!raw.contains("s3cret"));
+
+        let backend = EncryptedFileBackend::open_with_secret(path.clone(), b"machine").unwrap();
+        let store = SecretsStore::with_backend(Arc::new(backend));
+        assert_eq!(
+            store.get_unattended_password_hash().unwrap(),
+            Some("hash-value".to_string())
+        );
+        assert_eq!(
+            store
+                .get_turn_credential("turn:turn.example.com")
+                .unwrap()
+                .unwrap()
+                .credential,
+            "s3cret"
+        );
+
+        // A different machine secret cannot open the file
+        assert!(EncryptedFileBackend::open_with_secret(path, b"other").is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_file_store_keyed_by_random_key() {
+        let dir = temp_dir();
+        let path = dir.join(SECRETS_FILE_NAME);
+        let key_path = dir.join(SECRETS_KEY_FILE_NAME);
+
+        // A store from before random keys is re-keyed on open
+        EncryptedFileBackend::open_with_secret(path.clone(), &legacy_machine_secret())
+            .unwrap()
+            .set("auth_token:signaling", "tok")
+            .unwrap();
+        let backend = EncryptedFileBackend::open(path.clone()).unwrap();
+        assert_eq!(
+            backend.get("auth_token:signaling").unwrap(),
+            Some("tok".to_string())
+        );
+        assert!(
+            EncryptedFileBackend::open_with_secret(path.clone(), &legacy_machine_secret()).is_err()
+        );
+        assert_eq!(std::fs::read(&key_path).unwrap().len(), 32);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&key_path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let reopened = EncryptedFileBackend::open(path).unwrap();
+        assert_eq!(
+            reopened.get("auth_token:signaling").unwrap(),
+            Some("tok".to_string())
+        );
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_migrate_plaintext_settings() {
+        let dir = temp_dir();
+        let settings_path = dir.join("settings.json");
+        std::fs::write(
+            &settings_path,
+            r#"{"theme":"dark","auth_token":"tok-123","turn_url":"turn:turn.example.com",
+                "turn_username":"alice","turn_credential":"pw"}"#,
+        )
+        .unwrap();
+
+        let backend =
+            EncryptedFileBackend::open_with_secret(dir.join(SECRETS_FILE_NAME), b"machine")
+                .unwrap();
+        let store = SecretsStore::with_backend(Arc::new(backend));
+
+        assert_eq!(
+            store
+                .migrate_plaintext_settings(&settings_path, "signaling")
+                .unwrap(),
+            3
+        );
+        assert_eq!(
+            store.get_auth_token("signaling").unwrap(),
+            Some("tok-123".to_string())
+        );
+        assert_eq!(
+            store.get_turn_credential("turn:turn.example.com").unwrap(),
+            Some(TurnCredential {
+                username: "alice".to_string(),
+                credential: "pw".to_string(),
+            })
+        );
+
+        let remaining = std::fs::read_to_string(&settings_path).unwrap();
+        assert!(remaining.contains("theme") && remaining.contains("turn_url"));
+        assert!(!remaining.contains("tok-123") && !remaining.contains("pw"));
+
+        // Running again is a no-op
+        assert_eq!(
+            store
+                .migrate_plaintext_settings(&settings_path, "signaling")
+                .unwrap(),
+            0
+        );
+
+        // Credentials for an unknown server stay put rather than being lost
+        std::fs::write(&settings_path, r#"{"turn_credential":"pw"}"#).unwrap();
+        assert_eq!(
+            store
+                .migrate_plaintext_settings(&settings_path, "signaling")
+                .unwrap(),
+            0
+        );
+        assert!(std::fs::read_to_string(&settings_path)
+            .unwrap()
+            .contains("pw"));
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -3,20 +3,33 @@
 //! Implements end-to-end encryption for media streams, signaling, and file transfers.
 //! Requirements: 10.1, 10.2, 10.3, 10.4, 10.5, 10.6
 
+use crate::clock::{system_clock, SharedClock};
+use crate::encryption_status::{
+    EncryptionStatus, EncryptionStatusEvent, EncryptionStatusTracker, FingerprintVerification,
+    SessionEncryption,
+};
+use crate::errors::{ManagerError, ResourceKind};
+use crate::event_bus::{Subscription, SubscriptionOptions};
+use crate::memory_budget::{MemoryBudget, MemorySubsystem};
+use crate::secrets::SecretsStore;
+use crate::self_check::{run_self_check, SelfCheckConfig, SelfCheckReport};
+use crate::timestamp::Timestamp;
 use aes_gcm::{
-    aead::{Aead, KeyInit, OsRng},
+    aead::{Aead, KeyInit, OsRng, Payload},
     Aes256Gcm, Nonce,
 };
 use anyhow::{Context, Result};
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
-use rand::RngCore;
+use futures::future::BoxFuture;
+use rand::{Rng, RngCore};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::{HashMap, HashSet};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
-use x25519_dalek::{EphemeralSecret, PublicKey};
+use tokio::task::JoinHandle;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
 
 /// Security configuration for the system
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +46,12 @@ pub struct SecurityConfig {
     pub key_rotation_interval: u64,
     /// Enable security threat detection (Requirement 10.6)
     pub threat_detection_enabled: bool,
+    /// Unattended access is configured on this host
+    #[serde(default)]
+    pub unattended_access: bool,
+    /// Profile the settings came from; `Strict` is enforced at session start
+    #[serde(default)]
+    pub profile: SecurityProfile,
 }
 
 impl Default for SecurityConfig {
@@ -44,10 +63,145 @@ impl Default for SecurityConfig {
             certificate_validation: true,
             key_rotation_interval: 3600, // 1 hour
             threat_detection_enabled: true,
+            unattended_access: false,
+            profile: SecurityProfile::Compatible,
         }
     }
 }
 
+/// Longest key rotation interval the strict profile accepts
+pub const STRICT_MAX_KEY_ROTATION_SECS: u64 = 3600;
+
+/// Named security configuration preset
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SecurityProfile {
+    /// Every protection on; sessions are refused if any requirement is unmet
+    Strict,
+    /// Secure defaults; problems are reported but sessions still start
+    #[default]
+    Compatible,
+    /// Hand-tuned settings, validated like `Compatible`
+    Custom,
+}
+
+impl SecurityConfig {
+    /// Preset settings for a profile; `Custom` starts from the defaults
+    pub fn for_profile(profile: SecurityProfile) -> Self {
+        match profile {
+            SecurityProfile::Strict => Self {
+                key_rotation_interval: 900,
+                profile,
+                ..Self::default()
+            },
+            SecurityProfile::Compatible | SecurityProfile::Custom => Self {
+                profile,
+                ..Self::default()
+            },
+        }
+    }
+
+    /// Flag insecure settings and dangerous combinations
+    pub fn validate(&self) -> Vec<SecurityIssue> {
+        let mut issues = Vec::new();
+        let mut flag = |severity, code: &str, message: &str| {
+            issues.push(SecurityIssue {
+                severity,
+                code: code.to_string(),
+                message: message.to_string(),
+            })
+        };
+
+        if !self.enable_dtls_srtp {
+            flag(
+                IssueSeverity::Critical,
+                "media_unencrypted",
+                "DTLS-SRTP is disabled; screen and audio are sent in the clear",
+            );
+        }
+        if !self.enable_tls_signaling {
+            flag(
+                IssueSeverity::Critical,
+                "signaling_unencrypted",
+                "Signaling is not protected by TLS",
+            );
+        }
+        if !self.enable_file_encryption {
+            flag(
+                IssueSeverity::Warning,
+                "files_unencrypted",
+                "File transfers are not end-to-end encrypted",
+            );
+        }
+        if !self.certificate_validation {
+            if self.unattended_access {
+                flag(
+                    IssueSeverity::Critical,
+                    "unattended_without_cert_validation",
+                    "Unattended access is enabled while device certificates are not validated",
+                );
+            } else {
+                flag(
+                    IssueSeverity::Warning,
+                    "cert_validation_disabled",
+                    "Device certificates are not validated",
+                );
+            }
+        }
+        if !self.threat_detection_enabled {
+            let severity = if self.unattended_access {
+                IssueSeverity::Critical
+            } else {
+                IssueSeverity::Warning
+            };
+            flag(
+                severity,
+                "threat_detection_disabled",
+                "Threat detection is disabled",
+            );
+        }
+        if self.key_rotation_interval == 0 {
+            flag(
+                IssueSeverity::Warning,
+                "key_rotation_disabled",
+                "Session keys are never rotated",
+            );
+        } else if self.profile == SecurityProfile::Strict
+            && self.key_rotation_interval > STRICT_MAX_KEY_ROTATION_SECS
+        {
+            flag(
+                IssueSeverity::Warning,
+                "key_rotation_too_slow",
+                "Session keys rotate less often than the strict profile requires",
+            );
+        }
+        issues
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum IssueSeverity {
+    Warning,
+    Critical,
+}
+
+/// Problem found by `SecurityConfig::validate`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SecurityIssue {
+    pub severity: IssueSeverity,
+    /// Stable identifier, e.g. `unattended_without_cert_validation`
+    pub code: String,
+    pub message: String,
+}
+
+/// Active profile and configuration problems
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SecurityPosture {
+    pub profile: SecurityProfile,
+    pub issues: Vec<SecurityIssue>,
+    /// False when the strict profile's requirements are not met
+    pub sessions_allowed: bool,
+}
+
 /// Device certificate for authentication
 /// Requirement 10.4: Verify device certificates to prevent MITM attacks
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -112,16 +266,26 @@ pub enum EncryptionAlgorithm {
     ChaCha20Poly1305,
 }
 
+impl EncryptionAlgorithm {
+    /// Name as shown to users
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            EncryptionAlgorithm::Aes256Gcm => "AES-256-GCM",
+            EncryptionAlgorithm::ChaCha20Poly1305 => "ChaCha20-Poly1305",
+        }
+    }
+}
+
 /// Session key information
 /// Requirement 10.5: Periodically rotate session keys
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SessionKey {
     pub key: Vec<u8>,
-    pub created_at: Instant,
+    pub created_at: Timestamp,
     pub rotation_count: u32,
     pub algorithm: EncryptionAlgorithm,
     /// Last rotation timestamp
-    pub last_rotated_at: Instant,
+    pub last_rotated_at: Timestamp,
     /// Maximum age before forced rotation (in seconds)
     pub max_age_secs: u64,
     /// Whether automatic rotation is enabled
@@ -152,6 +316,53 @@ impl Default for KeyRotationConfig {
     }
 }
 
+/// Background key rotation schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RotationScheduleConfig {
+    /// Base interval between rotation passes (in milliseconds)
+    pub check_interval_ms: u64,
+    /// Fraction of the interval (0.0-1.0) randomly added or subtracted so
+    /// that many sessions and peers do not rotate in lockstep
+    pub jitter: f64,
+    /// Consecutive failures of one session before an alert is raised
+    pub failure_alert_threshold: u32,
+}
+
+impl Default for RotationScheduleConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_ms: 60_000,
+            jitter: 0.2,
+            failure_alert_threshold: 3,
+        }
+    }
+}
+
+/// Counters for scheduled key rotation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RotationMetrics {
+    /// Rotation passes run
+    pub passes: u64,
+    pub rotations: u64,
+    pub failures: u64,
+    /// Rotations skipped because one was already in progress in-band
+    pub skipped: u64,
+    /// Alerts raised for sessions that keep failing
+    pub alerts: u64,
+    pub last_pass_at: Option<Timestamp>,
+}
+
+/// Delivers a rotated key to the peer over the in-band rotation protocol
+pub trait RotationAnnouncer: Send + Sync {
+    /// The rotation counts as failed, and is retried on the next pass, if
+    /// this returns an error
+    fn announce<'a>(
+        &'a self,
+        session_id: &'a str,
+        key: &'a SessionKey,
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
 /// Threat detection configuration
 /// Requirement 10.6: Detect security threats and terminate connections
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -199,6 +410,37 @@ pub struct ReplayDetectionState {
     pub nonce_expiration_secs: u64,
 }
 
+impl ReplayDetectionState {
+    /// Bytes the seen nonces occupy, as accounted in a memory budget
+    pub fn nonce_bytes(&self) -> u64 {
+        self.seen_nonces.iter().map(|n| nonce_footprint(n)).sum()
+    }
+
+    /// Forget about half of the seen nonces; returns the bytes freed
+    fn evict_half(&mut self) -> u64 {
+        let to_remove: Vec<_> = self
+            .seen_nonces
+            .iter()
+            .take(self.seen_nonces.len().div_ceil(2))
+            .cloned()
+            .collect();
+        to_remove
+            .into_iter()
+            .map(|nonce| {
+                self.seen_nonces.remove(&nonce);
+                nonce_footprint(&nonce)
+            })
+            .sum()
+    }
+}
+
+/// Hash set entry overhead counted on top of the nonce bytes
+const NONCE_ENTRY_OVERHEAD: u64 = 32;
+
+fn nonce_footprint(nonce: &[u8]) -> u64 {
+    nonce.len() as u64 + NONCE_ENTRY_OVERHEAD
+}
+
 impl Default for ReplayDetectionState {
     fn default() -> Self {
         Self {
@@ -283,6 +525,212 @@ pub struct EncryptedData {
     pub key_id: String,
 }
 
+/// Certificate fingerprint: SHA-256 over the key-exchange and verifying keys
+pub fn certificate_fingerprint(public_key: &[u8], verifying_key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(public_key);
+    hasher.update(verifying_key);
+    hex::encode(hasher.finalize())
+}
+
+/// Signs messages with the device certificate's Ed25519 key
+pub struct MessageSigner {
+    signing_key: SigningKey,
+    public_key: Vec<u8>,
+    fingerprint: String,
+}
+
+impl MessageSigner {
+    /// Fails if the certificate does not carry its signing key
+    pub fn from_certificate(certificate: &DeviceCertificate) -> Result<Self> {
+        let signing_key: [u8; 32] = certificate
+            .signing_key
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Certificate has no signing key"))?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid signing key length"))?;
+        Ok(Self {
+            signing_key: SigningKey::from_bytes(&signing_key),
+            public_key: certificate.public_key.clone(),
+            fingerprint: certificate.fingerprint.clone(),
+        })
+    }
+
+    pub fn fingerprint(&self) -> &str {
+        &self.fingerprint
+    }
+
+    /// Certificate key-exchange key, which the fingerprint also covers
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    pub fn verifying_key(&self) -> Vec<u8> {
+        self.signing_key.verifying_key().as_bytes().to_vec()
+    }
+
+    pub fn sign(&self, data: &[u8]) -> Vec<u8> {
+        self.signing_key.sign(data).to_bytes().to_vec()
+    }
+}
+
+impl std::fmt::Debug for MessageSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MessageSigner")
+            .field("fingerprint", &self.fingerprint)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Check a `MessageSigner` signature over `data`, returning the
+/// fingerprint of the certificate that made it
+pub fn verify_message_signature(
+    public_key: &[u8],
+    verifying_key: &[u8],
+    data: &[u8],
+    signature: &[u8],
+) -> Result<String> {
+    let verifying_key_bytes: [u8; 32] = verifying_key
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid verifying key length"))?;
+    let signature_bytes: [u8; 64] = signature
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid signature length"))?;
+    VerifyingKey::from_bytes(&verifying_key_bytes)
+        .context("Invalid verifying key")?
+        .verify(data, &Signature::from_bytes(&signature_bytes))
+        .map_err(|_| anyhow::anyhow!("Signature verification failed"))?;
+    Ok(certificate_fingerprint(public_key, verifying_key))
+}
+
+/// HKDF context for keys sealing signaling payloads
+const SIGNALING_SEAL_INFO: &[u8] = b"cecdesk-signaling-seal-v1";
+
+/// Signaling payload sealed to one device's signaling key
+///
+/// Only the holder of the recipient's secret key can open it, so a
+/// signaling server relaying it learns nothing but its size.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedPayload {
+    /// One-off X25519 public key of the sender
+    pub ephemeral_key: Vec<u8>,
+    pub nonce: Vec<u8>,
+    /// AES-256-GCM ciphertext, tag included
+    pub ciphertext: Vec<u8>,
+}
+
+/// Long-lived X25519 key pair for end-to-end encrypted signaling
+///
+/// Peers seal payloads to the public key with a fresh ephemeral key each
+/// time, so the sender needs no key of its own. The public key must reach
+/// peers over a channel the signaling server cannot tamper with, such as
+/// LAN pairing, or a compromised server could substitute its own.
+pub struct SignalingKeyPair {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl SignalingKeyPair {
+    pub fn generate() -> Self {
+        Self::from_secret(StaticSecret::random_from_rng(OsRng))
+    }
+
+    /// Restore a key pair from `secret_bytes`
+    pub fn from_secret_bytes(secret_bytes: &[u8]) -> Result<Self> {
+        let bytes: [u8; 32] = secret_bytes
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid signaling key length"))?;
+        Ok(Self::from_secret(StaticSecret::from(bytes)))
+    }
+
+    fn from_secret(secret: StaticSecret) -> Self {
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public.to_bytes()
+    }
+
+    /// Seal `plaintext` to `recipient_key`, binding `context` (e.g. sender
+    /// and recipient IDs) so the payload cannot be replayed elsewhere
+    pub fn seal(recipient_key: &[u8], plaintext: &[u8], context: &[u8]) -> Result<SealedPayload> {
+        let recipient = parse_public_key(recipient_key)?;
+        let ephemeral = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_key = PublicKey::from(&ephemeral);
+        let shared = ephemeral.diffie_hellman(&recipient);
+        let cipher = seal_cipher(shared.as_bytes(), &ephemeral_key, &recipient)?;
+
+        let mut nonce = [0u8; 12];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad: context,
+                },
+            )
+            .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+
+        Ok(SealedPayload {
+            ephemeral_key: ephemeral_key.as_bytes().to_vec(),
+            nonce: nonce.to_vec(),
+            ciphertext,
+        })
+    }
+
+    /// Open a payload sealed to this key pair with the same `context`
+    pub fn open(&self, sealed: &SealedPayload, context: &[u8]) -> Result<Vec<u8>> {
+        if sealed.nonce.len() != 12 {
+            return Err(anyhow::anyhow!("Invalid nonce length"));
+        }
+        let ephemeral_key = parse_public_key(&sealed.ephemeral_key)?;
+        let shared = self.secret.diffie_hellman(&ephemeral_key);
+        let cipher = seal_cipher(shared.as_bytes(), &ephemeral_key, &self.public)?;
+        cipher
+            .decrypt(
+                Nonce::from_slice(&sealed.nonce),
+                Payload {
+                    msg: &sealed.ciphertext,
+                    aad: context,
+                },
+            )
+            .map_err(|_| anyhow::anyhow!("Sealed payload failed authentication"))
+    }
+}
+
+impl std::fmt::Debug for SignalingKeyPair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SignalingKeyPair")
+            .field("public", &hex::encode(self.public.as_bytes()))
+            .finish_non_exhaustive()
+    }
+}
+
+fn parse_public_key(bytes: &[u8]) -> Result<PublicKey> {
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid public key length"))?;
+    Ok(PublicKey::from(bytes))
+}
+
+/// AES-256-GCM keyed from an X25519 shared secret and both public keys
+fn seal_cipher(shared: &[u8], ephemeral: &PublicKey, recipient: &PublicKey) -> Result<Aes256Gcm> {
+    let mut salt = [0u8; 64];
+    salt[..32].copy_from_slice(ephemeral.as_bytes());
+    salt[32..].copy_from_slice(recipient.as_bytes());
+    let hk = hkdf::Hkdf::<Sha256>::new(Some(&salt), shared);
+    let mut key = [0u8; 32];
+    hk.expand(SIGNALING_SEAL_INFO, &mut key)
+        .map_err(|_| anyhow::anyhow!("Key derivation failed"))?;
+    Aes256Gcm::new_from_slice(&key).map_err(|e| anyhow::anyhow!("Failed to create cipher: {}", e))
+}
+
 /// Security event for logging and monitoring
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SecurityEvent {
@@ -296,19 +744,24 @@ pub struct SecurityEvent {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum SecurityEventType {
     KeyRotation,
+    KeyRotationFailed,
     CertificateValidation,
     ThreatDetected,
     EncryptionEnabled,
     EncryptionDisabled,
     SessionEstablished,
     SessionTerminated,
+    /// Startup self-check found nothing
+    IntegrityCheckPassed,
+    /// Startup self-check finding; details name the problem
+    IntegrityViolation,
 }
 
 /// Type alias for threat callback functions
 type ThreatCallback = Box<dyn Fn(SecurityThreat) + Send + Sync>;
 
 /// Type alias for old session keys with expiration
-type OldSessionKeys = HashMap<String, Vec<(SessionKey, Instant)>>;
+type OldSessionKeys = HashMap<String, Vec<(SessionKey, Timestamp)>>;
 
 /// Security Manager - handles all encryption and security operations
 pub struct SecurityManager {
@@ -333,6 +786,21 @@ pub struct SecurityManager {
     revoked_certificates: Arc<RwLock<HashSet<String>>>,
     /// Old session keys for grace period (session_id -> old keys with expiration)
     old_session_keys: Arc<RwLock<OldSessionKeys>>,
+    /// Sessions with a rotation under way, scheduled or in-band
+    rotations_in_progress: Arc<RwLock<HashSet<String>>>,
+    /// Consecutive scheduled rotation failures per session
+    rotation_failures: Arc<RwLock<HashMap<String, u32>>>,
+    rotation_metrics: Arc<RwLock<RotationMetrics>>,
+    /// Background rotation task, stopped when the manager is dropped
+    rotation_task: std::sync::Mutex<Option<JoinHandle<()>>>,
+    /// Time source for key ages, nonce expiry and lockouts
+    clock: SharedClock,
+    /// Shared budget the seen nonces are accounted against
+    memory_budget: Option<Arc<MemoryBudget>>,
+    /// Per-session encryption status shown to users
+    encryption_status: Arc<EncryptionStatusTracker>,
+    /// Key peers seal signaling payloads to
+    signaling_keys: Arc<SignalingKeyPair>,
 }
 
 impl SecurityManager {
@@ -356,6 +824,14 @@ impl SecurityManager {
             trusted_certificates: Arc::new(RwLock::new(HashSet::new())),
             revoked_certificates: Arc::new(RwLock::new(HashSet::new())),
             old_session_keys: Arc::new(RwLock::new(HashMap::new())),
+            rotations_in_progress: Arc::new(RwLock::new(HashSet::new())),
+            rotation_failures: Arc::new(RwLock::new(HashMap::new())),
+            rotation_metrics: Arc::new(RwLock::new(RotationMetrics::default())),
+            rotation_task: std::sync::Mutex::new(None),
+            clock: system_clock(),
+            memory_budget: None,
+            encryption_status: Arc::new(EncryptionStatusTracker::new()),
+            signaling_keys: Arc::new(SignalingKeyPair::generate()),
         }
     }
 
@@ -379,6 +855,52 @@ impl SecurityManager {
             trusted_certificates: Arc::new(RwLock::new(HashSet::new())),
             revoked_certificates: Arc::new(RwLock::new(HashSet::new())),
             old_session_keys: Arc::new(RwLock::new(HashMap::new())),
+            rotations_in_progress: Arc::new(RwLock::new(HashSet::new())),
+            rotation_failures: Arc::new(RwLock::new(HashMap::new())),
+            rotation_metrics: Arc::new(RwLock::new(RotationMetrics::default())),
+            rotation_task: std::sync::Mutex::new(None),
+            clock: system_clock(),
+            memory_budget: None,
+            encryption_status: Arc::new(EncryptionStatusTracker::new()),
+            signaling_keys: Arc::new(SignalingKeyPair::generate()),
+        }
+    }
+
+    /// Read the time from `clock` instead of the system clocks
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Account replay nonces against `budget`
+    ///
+    /// Under memory pressure each session forgets half of its nonces, which
+    /// narrows the replay window but keeps recent traffic protected.
+    pub fn with_memory_budget(mut self, budget: Arc<MemoryBudget>) -> Self {
+        let replay_detection = self.replay_detection.clone();
+        budget.on_pressure(
+            MemorySubsystem::ReplayNonces,
+            Arc::new(move |wanted| {
+                let Ok(mut states) = replay_detection.try_write() else {
+                    return 0;
+                };
+                let mut freed = 0;
+                for state in states.values_mut() {
+                    if freed >= wanted {
+                        break;
+                    }
+                    freed += state.evict_half();
+                }
+                freed
+            }),
+        );
+        self.memory_budget = Some(budget);
+        self
+    }
+
+    fn release_nonce_bytes(&self, bytes: u64) {
+        if let Some(budget) = &self.memory_budget {
+            budget.release(MemorySubsystem::ReplayNonces, bytes);
         }
     }
 
@@ -386,6 +908,9 @@ impl SecurityManager {
     pub fn configure(&mut self, config: SecurityConfig) {
         self.config = config;
         tracing::info!("Security configuration updated");
+        for issue in self.config.validate() {
+            tracing::warn!("Security configuration: {}", issue.message);
+        }
         self.log_event(
             SecurityEventType::EncryptionEnabled,
             None,
@@ -399,6 +924,54 @@ impl SecurityManager {
         &self.config
     }
 
+    /// Switch to a profile's preset settings, keeping the unattended flag
+    pub fn apply_profile(&mut self, profile: SecurityProfile) {
+        let config = SecurityConfig {
+            unattended_access: self.config.unattended_access,
+            ..SecurityConfig::for_profile(profile)
+        };
+        self.configure(config);
+    }
+
+    /// Active profile, configuration problems, and whether sessions may start
+    pub fn security_posture(&self) -> SecurityPosture {
+        let issues = self.config.validate();
+        SecurityPosture {
+            profile: self.config.profile,
+            sessions_allowed: self.config.profile != SecurityProfile::Strict || issues.is_empty(),
+            issues,
+        }
+    }
+
+    /// Startup integrity self-check, recorded as security events
+    ///
+    /// Debugger detection only runs under the strict profile.
+    pub async fn run_startup_self_check(&self, config: &SelfCheckConfig) -> SelfCheckReport {
+        let report = run_self_check(config, self.config.profile);
+        let mut events = self.security_events.write().await;
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        if report.passed() {
+            events.push(SecurityEvent {
+                timestamp: timestamp.clone(),
+                event_type: SecurityEventType::IntegrityCheckPassed,
+                session_id: None,
+                device_id: None,
+                details: "Startup self-check passed".to_string(),
+            });
+        }
+        for finding in &report.findings {
+            tracing::warn!("Startup self-check: {}", finding.describe());
+            events.push(SecurityEvent {
+                timestamp: timestamp.clone(),
+                event_type: SecurityEventType::IntegrityViolation,
+                session_id: None,
+                device_id: None,
+                details: format!("{:?}: {}", finding.severity(), finding.describe()),
+            });
+        }
+        report
+    }
+
     /// Get DTLS-SRTP configuration
     pub fn get_dtls_config(&self) -> &DtlsSrtpConfig {
         &self.dtls_config
@@ -426,10 +999,7 @@ impl SecurityManager {
         let verifying_key = signing_key.verifying_key();
 
         // Generate certificate fingerprint
-        let mut hasher = Sha256::new();
-        hasher.update(public.as_bytes());
-        hasher.update(verifying_key.as_bytes());
-        let fingerprint = hex::encode(hasher.finalize());
+        let fingerprint = certificate_fingerprint(public.as_bytes(), verifying_key.as_bytes());
 
         let now = chrono::Utc::now();
         let valid_until = now.checked_add_signed(chrono::Duration::days(365)).unwrap();
@@ -535,10 +1105,8 @@ impl SecurityManager {
         }
 
         // Verify fingerprint
-        let mut hasher = Sha256::new();
-        hasher.update(&certificate.public_key);
-        hasher.update(&certificate.verifying_key);
-        let computed_fingerprint = hex::encode(hasher.finalize());
+        let computed_fingerprint =
+            certificate_fingerprint(&certificate.public_key, &certificate.verifying_key);
 
         if computed_fingerprint != certificate.fingerprint {
             tracing::warn!(
@@ -712,12 +1280,74 @@ impl SecurityManager {
         self.device_certificate.as_ref()
     }
 
+    /// Persist the device signing key to the secrets store
+    pub fn store_signing_key(&self, store: &SecretsStore) -> Result<()> {
+        let key = self
+            .device_certificate
+            .as_ref()
+            .and_then(|cert| cert.signing_key.as_ref())
+            .ok_or_else(|| anyhow::anyhow!("No device signing key"))?;
+        store.set_device_signing_key(key)
+    }
+
+    /// Key pair for end-to-end encrypted signaling payloads
+    pub fn signaling_keys(&self) -> Arc<SignalingKeyPair> {
+        Arc::clone(&self.signaling_keys)
+    }
+
+    /// Signer for the device certificate
+    pub fn message_signer(&self) -> Result<MessageSigner> {
+        let certificate = self
+            .device_certificate
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("No device certificate"))?;
+        MessageSigner::from_certificate(certificate)
+    }
+
+    /// Persist the signaling key so peers that pinned it keep working
+    pub fn store_signaling_key(&self, store: &SecretsStore) -> Result<()> {
+        store.set_signaling_secret_key(&self.signaling_keys.secret_bytes())
+    }
+
+    /// Use the signaling key from the secrets store, if one was stored
+    pub fn load_signaling_key(&mut self, store: &SecretsStore) -> Result<bool> {
+        match store.get_signaling_secret_key()? {
+            Some(secret) => {
+                self.signaling_keys = Arc::new(SignalingKeyPair::from_secret_bytes(&secret)?);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
     /// Generate a session key for encryption
+    ///
+    /// Fails under the strict profile while its requirements are not met.
     pub async fn generate_session_key(&self, session_id: &str) -> Result<SessionKey> {
+        let posture = self.security_posture();
+        if !posture.sessions_allowed {
+            let codes: Vec<_> = posture.issues.iter().map(|i| i.code.as_str()).collect();
+            self.log_event(
+                SecurityEventType::SessionTerminated,
+                Some(session_id.to_string()),
+                None,
+                format!("Session refused by strict profile: {}", codes.join(", ")),
+            );
+            return Err(ManagerError::permission_denied(
+                ResourceKind::Session,
+                session_id,
+                format!(
+                    "strict security profile requirements not met: {}",
+                    codes.join(", ")
+                ),
+            )
+            .into());
+        }
+
         let mut key = vec![0u8; 32]; // 256-bit key
         OsRng.fill_bytes(&mut key);
 
-        let now = Instant::now();
+        let now = self.clock.now();
         let session_key = SessionKey {
             key,
             created_at: now,
@@ -732,12 +1362,21 @@ impl SecurityManager {
             .write()
             .await
             .insert(session_id.to_string(), session_key.clone());
+        self.encryption_status.refresh(
+            session_id,
+            Some(&session_key),
+            &self.dtls_config.srtp_profile,
+        );
 
         // Initialize replay detection for this session
-        self.replay_detection
+        let previous = self
+            .replay_detection
             .write()
             .await
-            .insert(session_id.to_string(), ReplayDetectionState::default());
+            .insert(session_id.to_string(), self.new_replay_state());
+        if let Some(previous) = previous {
+            self.release_nonce_bytes(previous.nonce_bytes());
+        }
 
         self.log_event(
             SecurityEventType::SessionEstablished,
@@ -759,7 +1398,7 @@ impl SecurityManager {
             // Store old key for grace period
             let old_key = existing_key.clone();
             let grace_expiration =
-                Instant::now() + Duration::from_secs(self.key_rotation_config.grace_period_secs);
+                self.clock.now() + Duration::from_secs(self.key_rotation_config.grace_period_secs);
 
             {
                 let mut old_keys = self.old_session_keys.write().await;
@@ -774,7 +1413,7 @@ impl SecurityManager {
             OsRng.fill_bytes(&mut new_key);
 
             existing_key.key = new_key;
-            existing_key.last_rotated_at = Instant::now();
+            existing_key.last_rotated_at = self.clock.now();
             existing_key.rotation_count += 1;
 
             self.log_event(
@@ -793,9 +1432,14 @@ impl SecurityManager {
                 existing_key.rotation_count
             );
 
+            self.encryption_status.refresh(
+                session_id,
+                Some(existing_key),
+                &self.dtls_config.srtp_profile,
+            );
             Ok(existing_key.clone())
         } else {
-            Err(anyhow::anyhow!("Session not found: {}", session_id))
+            Err(ManagerError::not_found(ResourceKind::Session, session_id).into())
         }
     }
 
@@ -805,7 +1449,8 @@ impl SecurityManager {
             if !key.auto_rotate {
                 return false;
             }
-            key.last_rotated_at.elapsed() > Duration::from_secs(key.max_age_secs)
+            key.last_rotated_at
+                .has_elapsed_at(Duration::from_secs(key.max_age_secs), &self.clock.now())
         } else {
             false
         }
@@ -834,16 +1479,174 @@ impl SecurityManager {
     /// Clean up old keys that have exceeded their grace period
     async fn cleanup_expired_old_keys(&self) {
         let mut old_keys = self.old_session_keys.write().await;
-        let now = Instant::now();
+        let now = self.clock.now();
 
         for (_, keys) in old_keys.iter_mut() {
-            keys.retain(|(_, expiration)| *expiration > now);
+            keys.retain(|(_, expiration)| !expiration.is_past_at(&now));
         }
 
         // Remove empty entries
         old_keys.retain(|_, keys| !keys.is_empty());
     }
 
+    /// Claim a session for a key rotation
+    ///
+    /// The in-band rotation protocol calls this before handling a peer's
+    /// rotation so the scheduler does not rotate the same session
+    /// concurrently. Returns false if a rotation is already in progress.
+    pub async fn begin_key_rotation(&self, session_id: &str) -> bool {
+        self.rotations_in_progress
+            .write()
+            .await
+            .insert(session_id.to_string())
+    }
+
+    /// Release a session claimed with `begin_key_rotation`
+    pub async fn end_key_rotation(&self, session_id: &str) {
+        self.rotations_in_progress.write().await.remove(session_id);
+    }
+
+    /// Rotate every expired key, announcing each new key to the peer
+    ///
+    /// Sessions whose last scheduled rotation failed are retried even if
+    /// their key is not expired. Returns the sessions rotated.
+    pub async fn run_rotation_pass(
+        &self,
+        announcer: Option<&dyn RotationAnnouncer>,
+        failure_alert_threshold: u32,
+    ) -> Vec<String> {
+        let session_ids: Vec<String> = self.session_keys.read().await.keys().cloned().collect();
+        let mut rotated = Vec::new();
+
+        for session_id in session_ids {
+            let retry = self
+                .rotation_failures
+                .read()
+                .await
+                .contains_key(&session_id);
+            if !retry && !self.needs_key_rotation(&session_id).await {
+                continue;
+            }
+            if !self.begin_key_rotation(&session_id).await {
+                self.rotation_metrics.write().await.skipped += 1;
+                continue;
+            }
+
+            let result = match self.rotate_session_key(&session_id).await {
+                Ok(key) => match announcer {
+                    Some(announcer) => announcer.announce(&session_id, &key).await,
+                    None => Ok(()),
+                },
+                Err(e) => Err(e),
+            };
+            self.end_key_rotation(&session_id).await;
+
+            match result {
+                Ok(()) => {
+                    self.rotation_failures.write().await.remove(&session_id);
+                    self.rotation_metrics.write().await.rotations += 1;
+                    rotated.push(session_id);
+                }
+                Err(e) => {
+                    self.record_rotation_failure(&session_id, failure_alert_threshold, e)
+                        .await
+                }
+            }
+        }
+
+        self.cleanup_expired_old_keys().await;
+        let mut metrics = self.rotation_metrics.write().await;
+        metrics.passes += 1;
+        metrics.last_pass_at = Some(self.clock.now());
+        rotated
+    }
+
+    async fn record_rotation_failure(
+        &self,
+        session_id: &str,
+        failure_alert_threshold: u32,
+        error: anyhow::Error,
+    ) {
+        let failures = {
+            let mut all = self.rotation_failures.write().await;
+            let count = all.entry(session_id.to_string()).or_insert(0);
+            *count += 1;
+            *count
+        };
+        tracing::warn!(
+            "Key rotation failed for session {} ({} in a row): {}",
+            session_id,
+            failures,
+            error
+        );
+
+        let mut metrics = self.rotation_metrics.write().await;
+        metrics.failures += 1;
+        if failures == failure_alert_threshold {
+            metrics.alerts += 1;
+            tracing::error!(
+                "Session {} failed key rotation {} times in a row",
+                session_id,
+                failures
+            );
+            self.log_event(
+                SecurityEventType::KeyRotationFailed,
+                Some(session_id.to_string()),
+                None,
+                format!("Key rotation failed {} times in a row: {}", failures, error),
+            );
+        }
+    }
+
+    /// Start rotating keys in the background
+    ///
+    /// Replaces any running schedule. The task stops with `stop_key_rotation`
+    /// or when the manager is dropped.
+    pub fn start_key_rotation(
+        self: &Arc<Self>,
+        config: RotationScheduleConfig,
+        announcer: Option<Arc<dyn RotationAnnouncer>>,
+    ) {
+        let manager: Weak<Self> = Arc::downgrade(self);
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(jittered_interval(&config)).await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                manager
+                    .run_rotation_pass(announcer.as_deref(), config.failure_alert_threshold)
+                    .await;
+            }
+        });
+
+        if let Ok(mut slot) = self.rotation_task.lock() {
+            if let Some(previous) = slot.replace(task) {
+                previous.abort();
+            }
+        }
+        tracing::info!("Key rotation scheduler started");
+    }
+
+    /// Stop the background rotation task
+    pub fn stop_key_rotation(&self) {
+        if let Some(task) = self.rotation_task.lock().ok().and_then(|mut t| t.take()) {
+            task.abort();
+            tracing::info!("Key rotation scheduler stopped");
+        }
+    }
+
+    pub fn is_key_rotation_running(&self) -> bool {
+        self.rotation_task
+            .lock()
+            .map(|task| task.as_ref().is_some_and(|t| !t.is_finished()))
+            .unwrap_or(false)
+    }
+
+    pub async fn rotation_metrics(&self) -> RotationMetrics {
+        self.rotation_metrics.read().await.clone()
+    }
+
     /// Configure key rotation settings
     pub fn configure_key_rotation(&mut self, config: KeyRotationConfig) {
         self.key_rotation_config = config;
@@ -855,6 +1658,60 @@ impl SecurityManager {
         &self.key_rotation_config
     }
 
+    /// Record the outcome of a session's DTLS handshake
+    ///
+    /// `presented_fingerprint` is the peer certificate's fingerprint from the
+    /// handshake, `expected_fingerprint` the one known from pairing or the
+    /// peer's device certificate. A mismatch is logged as a threat.
+    pub async fn record_dtls_handshake(
+        &self,
+        session_id: &str,
+        cipher_suite: &str,
+        presented_fingerprint: &str,
+        expected_fingerprint: Option<&str>,
+    ) -> FingerprintVerification {
+        let verification =
+            FingerprintVerification::check(presented_fingerprint, expected_fingerprint);
+        if verification == FingerprintVerification::Mismatch {
+            tracing::error!("DTLS fingerprint mismatch in session {}", session_id);
+            self.log_event(
+                SecurityEventType::ThreatDetected,
+                Some(session_id.to_string()),
+                None,
+                format!(
+                    "DTLS fingerprint mismatch: presented {}",
+                    presented_fingerprint
+                ),
+            );
+        }
+
+        self.encryption_status
+            .set_transport(session_id, cipher_suite, verification);
+        let key = self.session_keys.read().await.get(session_id).cloned();
+        self.encryption_status
+            .refresh(session_id, key.as_ref(), &self.dtls_config.srtp_profile);
+        verification
+    }
+
+    /// Encryption status of a session as seen by both peers
+    pub fn session_encryption(&self, session_id: &str) -> Option<SessionEncryption> {
+        self.encryption_status.session(session_id, self.clock.utc())
+    }
+
+    /// Store the encryption status the peer reported; returns whether it
+    /// agrees with ours, or `None` for an unknown session
+    pub fn apply_peer_encryption_status(&self, status: EncryptionStatus) -> Option<bool> {
+        self.encryption_status.apply_peer(status)
+    }
+
+    /// Subscribe to encryption status changes of either side
+    pub fn subscribe_encryption_status(
+        &self,
+        options: SubscriptionOptions,
+    ) -> Subscription<EncryptionStatusEvent> {
+        self.encryption_status.subscribe(options)
+    }
+
     /// Get session key for a session
     pub async fn get_session_key(&self, session_id: &str) -> Option<SessionKey> {
         self.session_keys.read().await.get(session_id).cloned()
@@ -863,8 +1720,12 @@ impl SecurityManager {
     /// Remove session key when session ends
     pub async fn remove_session_key(&self, session_id: &str) {
         self.session_keys.write().await.remove(session_id);
-        self.replay_detection.write().await.remove(session_id);
+        if let Some(state) = self.replay_detection.write().await.remove(session_id) {
+            self.release_nonce_bytes(state.nonce_bytes());
+        }
         self.old_session_keys.write().await.remove(session_id);
+        self.rotation_failures.write().await.remove(session_id);
+        self.encryption_status.remove(session_id);
 
         self.log_event(
             SecurityEventType::SessionTerminated,
@@ -897,7 +1758,7 @@ impl SecurityManager {
             .read()
             .await
             .get(session_id)
-            .ok_or_else(|| anyhow::anyhow!("Session key not found for: {}", session_id))?
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::SessionKey, session_id))?
             .key
             .clone();
 
@@ -919,7 +1780,7 @@ impl SecurityManager {
             .read()
             .await
             .get(session_id)
-            .ok_or_else(|| anyhow::anyhow!("Session key not found for: {}", session_id))?
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::SessionKey, session_id))?
             .key
             .clone();
 
@@ -944,7 +1805,7 @@ impl SecurityManager {
             .read()
             .await
             .get(session_id)
-            .ok_or_else(|| anyhow::anyhow!("Session key not found for: {}", session_id))?
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::SessionKey, session_id))?
             .key
             .clone();
 
@@ -966,7 +1827,7 @@ impl SecurityManager {
             .read()
             .await
             .get(session_id)
-            .ok_or_else(|| anyhow::anyhow!("Session key not found for: {}", session_id))?
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::SessionKey, session_id))?
             .key
             .clone();
 
@@ -995,7 +1856,7 @@ impl SecurityManager {
             .read()
             .await
             .get(session_id)
-            .ok_or_else(|| anyhow::anyhow!("Session key not found for: {}", session_id))?
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::SessionKey, session_id))?
             .key
             .clone();
 
@@ -1017,7 +1878,7 @@ impl SecurityManager {
             .read()
             .await
             .get(session_id)
-            .ok_or_else(|| anyhow::anyhow!("Session key not found for: {}", session_id))?
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::SessionKey, session_id))?
             .key
             .clone();
 
@@ -1132,12 +1993,16 @@ impl SecurityManager {
         let mut replay_states = self.replay_detection.write().await;
         let state = replay_states
             .entry(session_id.to_string())
-            .or_insert_with(ReplayDetectionState::default);
+            .or_insert_with(|| self.new_replay_state());
 
         // Clean up old nonces if needed
-        if state.oldest_nonce_time.elapsed() > Duration::from_secs(state.nonce_expiration_secs) {
+        let now = self.clock.instant();
+        if now.saturating_duration_since(state.oldest_nonce_time)
+            > Duration::from_secs(state.nonce_expiration_secs)
+        {
+            self.release_nonce_bytes(state.nonce_bytes());
             state.seen_nonces.clear();
-            state.oldest_nonce_time = Instant::now();
+            state.oldest_nonce_time = now;
         }
 
         // Check if nonce was already seen
@@ -1150,14 +2015,22 @@ impl SecurityManager {
         // Add nonce to seen set
         if state.seen_nonces.len() >= state.max_nonces {
             // Remove oldest entries (simple approach: clear half)
-            let to_remove: Vec<_> = state
-                .seen_nonces
-                .iter()
-                .take(state.max_nonces / 2)
-                .cloned()
-                .collect();
-            for nonce in to_remove {
-                state.seen_nonces.remove(&nonce);
+            let freed = state.evict_half();
+            self.release_nonce_bytes(freed);
+        }
+
+        if let Some(budget) = &self.memory_budget {
+            let footprint = nonce_footprint(nonce);
+            if !budget.try_reserve(MemorySubsystem::ReplayNonces, footprint) {
+                let freed = state.evict_half();
+                budget.release(MemorySubsystem::ReplayNonces, freed);
+                if !budget.try_reserve(MemorySubsystem::ReplayNonces, footprint) {
+                    tracing::warn!(
+                        "Replay nonce for session {} not recorded: memory budget exhausted",
+                        session_id
+                    );
+                    return Ok(false);
+                }
             }
         }
 
@@ -1189,7 +2062,7 @@ impl SecurityManager {
         }
 
         let mut tracker = self.failed_attempts.write().await;
-        let now = Instant::now();
+        let now = self.clock.instant();
 
         // Check if already locked out
         if let Some(unlock_time) = tracker.lockouts.get(identifier) {
@@ -1243,12 +2116,19 @@ impl SecurityManager {
     pub async fn is_locked_out(&self, identifier: &str) -> bool {
         let tracker = self.failed_attempts.read().await;
         if let Some(unlock_time) = tracker.lockouts.get(identifier) {
-            Instant::now() < *unlock_time
+            self.clock.instant() < *unlock_time
         } else {
             false
         }
     }
 
+    fn new_replay_state(&self) -> ReplayDetectionState {
+        ReplayDetectionState {
+            oldest_nonce_time: self.clock.instant(),
+            ..ReplayDetectionState::default()
+        }
+    }
+
     /// Clear failed attempts for an identifier (e.g., after successful auth)
     pub async fn clear_failed_attempts(&self, identifier: &str) {
         let mut tracker = self.failed_attempts.write().await;
@@ -1409,3 +2289,20 @@ impl Default for SecurityManager {
         Self::new()
     }
 }
+
+impl Drop for SecurityManager {
+    fn drop(&mut self) {
+        self.stop_key_rotation();
+    }
+}
+
+/// Rotation interval with random jitter applied
+fn jittered_interval(config: &RotationScheduleConfig) -> Duration {
+    let jitter = config.jitter.clamp(0.0, 1.0);
+    let factor = if jitter > 0.0 {
+        1.0 + rand::thread_rng().gen_range(-jitter..=jitter)
+    } else {
+        1.0
+    };
+    Duration::from_millis((config.check_interval_ms as f64 * factor).max(1.0) as u64)
+}
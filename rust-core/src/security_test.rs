@@ -96,6 +96,115 @@ mod tests {
         assert!(rotated.contains(&session_id.to_string()));
     }
 
+    struct FlakyAnnouncer {
+        fail: std::sync::atomic::AtomicBool,
+    }
+
+    impl RotationAnnouncer for FlakyAnnouncer {
+        fn announce<'a>(
+            &'a self,
+            _session_id: &'a str,
+            _key: &'a SessionKey,
+        ) -> futures::future::BoxFuture<'a, anyhow::Result<()>> {
+            let fail = self.fail.load(std::sync::atomic::Ordering::SeqCst);
+            Box::pin(async move {
+                if fail {
+                    Err(anyhow::anyhow!("peer did not acknowledge"))
+                } else {
+                    Ok(())
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_pass_retries_and_alerts() {
+        let mut manager = SecurityManager::new();
+        manager.generate_session_key("fresh").await.unwrap();
+        manager.configure_key_rotation(KeyRotationConfig {
+            rotation_interval_secs: 0,
+            ..KeyRotationConfig::default()
+        });
+        manager.generate_session_key("expired").await.unwrap();
+
+        let announcer = FlakyAnnouncer {
+            fail: std::sync::atomic::AtomicBool::new(true),
+        };
+        for _ in 0..3 {
+            assert!(manager
+                .run_rotation_pass(Some(&announcer), 3)
+                .await
+                .is_empty());
+        }
+        let metrics = manager.rotation_metrics().await;
+        assert_eq!(
+            (metrics.passes, metrics.failures, metrics.alerts),
+            (3, 3, 1)
+        );
+
+        // An in-band rotation in progress is left alone
+        assert!(manager.begin_key_rotation("expired").await);
+        assert!(manager
+            .run_rotation_pass(Some(&announcer), 3)
+            .await
+            .is_empty());
+        assert_eq!(manager.rotation_metrics().await.skipped, 1);
+        manager.end_key_rotation("expired").await;
+
+        announcer
+            .fail
+            .store(false, std::sync::atomic::Ordering::SeqCst);
+        let rotated = manager.run_rotation_pass(Some(&announcer), 3).await;
+        assert_eq!(rotated, vec!["expired".to_string()]);
+        assert_eq!(manager.rotation_metrics().await.rotations, 1);
+
+        tokio::task::yield_now().await;
+        let events = manager.get_security_events().await;
+        assert!(events
+            .iter()
+            .any(|e| matches!(e.event_type, SecurityEventType::KeyRotationFailed)));
+    }
+
+    #[tokio::test]
+    async fn test_rotation_scheduler_lifecycle() {
+        let mut manager = SecurityManager::new();
+        manager.configure_key_rotation(KeyRotationConfig {
+            rotation_interval_secs: 0,
+            ..KeyRotationConfig::default()
+        });
+        let manager = std::sync::Arc::new(manager);
+        manager.generate_session_key("session").await.unwrap();
+
+        manager.start_key_rotation(
+            RotationScheduleConfig {
+                check_interval_ms: 10,
+                jitter: 0.5,
+                failure_alert_threshold: 3,
+            },
+            None,
+        );
+        assert!(manager.is_key_rotation_running());
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(manager.rotation_metrics().await.rotations >= 2);
+
+        manager.stop_key_rotation();
+        assert!(!manager.is_key_rotation_running());
+        let rotations = manager
+            .get_session_key("session")
+            .await
+            .unwrap()
+            .rotation_count;
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert_eq!(
+            manager
+                .get_session_key("session")
+                .await
+                .unwrap()
+                .rotation_count,
+            rotations
+        );
+    }
+
     #[tokio::test]
     async fn test_media_stream_encryption_decryption() {
         let manager = SecurityManager::new();
@@ -171,6 +280,34 @@ mod tests {
         assert_eq!(decrypted, original_data.to_vec());
     }
 
+    #[test]
+    fn test_sealed_signaling_payload() {
+        let manager = SecurityManager::new();
+        let keys = manager.signaling_keys();
+        let sdp = b"v=0 o=- 1 2 IN IP4 192.168.1.20";
+
+        let sealed = SignalingKeyPair::seal(&keys.public_key(), sdp, b"viewer\nhost").unwrap();
+        assert!(!sealed
+            .ciphertext
+            .windows(sdp.len())
+            .any(|window| window == sdp));
+        assert_eq!(keys.open(&sealed, b"viewer\nhost").unwrap(), sdp.to_vec());
+
+        // Bound to its route and to the recipient's key
+        assert!(keys.open(&sealed, b"mallory\nhost").is_err());
+        assert!(SignalingKeyPair::generate()
+            .open(&sealed, b"viewer\nhost")
+            .is_err());
+
+        // The restored key pair opens payloads sealed to the original
+        let restored = SignalingKeyPair::from_secret_bytes(&keys.secret_bytes()).unwrap();
+        assert_eq!(restored.public_key(), keys.public_key());
+        assert_eq!(
+            restored.open(&sealed, b"viewer\nhost").unwrap(),
+            sdp.to_vec()
+        );
+    }
+
     #[tokio::test]
     async fn test_device_certificate_generation() {
         let mut manager = SecurityManager::new();
@@ -191,6 +328,35 @@ mod tests {
         assert!(stored_cert.is_some());
     }
 
+    #[tokio::test]
+    async fn test_message_signer_uses_certificate_identity() {
+        let mut manager = SecurityManager::new();
+        assert!(manager.message_signer().is_err());
+        let cert = manager
+            .generate_device_certificate("device-sign".to_string())
+            .await
+            .unwrap();
+        let signer = manager.message_signer().unwrap();
+        assert_eq!(signer.fingerprint(), cert.fingerprint);
+
+        let signature = signer.sign(b"offer");
+        let fingerprint = verify_message_signature(
+            signer.public_key(),
+            &signer.verifying_key(),
+            b"offer",
+            &signature,
+        )
+        .unwrap();
+        assert_eq!(fingerprint, cert.fingerprint);
+        assert!(verify_message_signature(
+            signer.public_key(),
+            &signer.verifying_key(),
+            b"answer",
+            &signature,
+        )
+        .is_err());
+    }
+
     #[tokio::test]
     async fn test_device_certificate_validation() {
         let mut manager = SecurityManager::new();
@@ -365,6 +531,31 @@ mod tests {
         assert!(!manager.is_locked_out(identifier).await);
     }
 
+    #[tokio::test]
+    async fn test_lockout_and_key_age_follow_injected_clock() {
+        let clock = crate::clock::TestClock::shared();
+        let mut manager = SecurityManager::new().with_clock(clock.clone());
+        manager.configure_threat_detection(ThreatDetectionConfig {
+            max_failed_attempts: 2,
+            lockout_duration_secs: 60,
+            ..ThreatDetectionConfig::default()
+        });
+        manager.generate_session_key("session").await.unwrap();
+
+        manager.track_failed_attempt("peer").await.unwrap();
+        assert!(manager.track_failed_attempt("peer").await.unwrap());
+        clock.advance(std::time::Duration::from_secs(59));
+        assert!(manager.is_locked_out("peer").await);
+        clock.advance(std::time::Duration::from_secs(2));
+        assert!(!manager.is_locked_out("peer").await);
+
+        assert!(!manager.needs_key_rotation("session").await);
+        clock.advance(std::time::Duration::from_secs(
+            KeyRotationConfig::default().rotation_interval_secs,
+        ));
+        assert!(manager.needs_key_rotation("session").await);
+    }
+
     #[tokio::test]
     async fn test_comprehensive_security_check() {
         let manager = SecurityManager::new();
@@ -404,6 +595,56 @@ mod tests {
         assert!(!manager.verify_integrity(modified_data, &hash));
     }
 
+    #[test]
+    fn test_security_profile_validation() {
+        assert!(SecurityConfig::for_profile(SecurityProfile::Strict)
+            .validate()
+            .is_empty());
+        assert!(SecurityConfig::default().validate().is_empty());
+
+        let config = SecurityConfig {
+            certificate_validation: false,
+            unattended_access: true,
+            profile: SecurityProfile::Custom,
+            ..SecurityConfig::default()
+        };
+        let issues = config.validate();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].code, "unattended_without_cert_validation");
+        assert_eq!(issues[0].severity, IssueSeverity::Critical);
+
+        // Same setting without unattended access is only a warning
+        let config = SecurityConfig {
+            unattended_access: false,
+            ..config
+        };
+        assert_eq!(config.validate()[0].severity, IssueSeverity::Warning);
+    }
+
+    #[tokio::test]
+    async fn test_strict_profile_refuses_sessions() {
+        let mut manager = SecurityManager::new();
+        manager.apply_profile(SecurityProfile::Strict);
+        assert!(manager.security_posture().sessions_allowed);
+        manager.generate_session_key("ok").await.unwrap();
+
+        let mut config = manager.get_security_config().clone();
+        config.enable_file_encryption = false;
+        manager.configure(config.clone());
+        let posture = manager.security_posture();
+        assert_eq!(posture.profile, SecurityProfile::Strict);
+        assert!(!posture.sessions_allowed);
+        assert!(manager.generate_session_key("refused").await.is_err());
+
+        // Compatible reports the same problem but lets sessions start
+        config.profile = SecurityProfile::Compatible;
+        manager.configure(config);
+        let posture = manager.security_posture();
+        assert!(posture.sessions_allowed);
+        assert_eq!(posture.issues[0].code, "files_unencrypted");
+        manager.generate_session_key("allowed").await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_encryption_disabled() {
         let config = SecurityConfig {
@@ -413,6 +654,8 @@ mod tests {
             certificate_validation: true,
             key_rotation_interval: 3600,
             threat_detection_enabled: true,
+            unattended_access: false,
+            profile: SecurityProfile::Custom,
         };
 
         let manager = SecurityManager::with_config(config);
@@ -530,6 +773,116 @@ mod tests {
         assert_ne!(key2.key, key3.key);
         assert_ne!(key1.key, key3.key);
     }
+
+    #[tokio::test]
+    async fn test_encryption_status_tracks_keys_handshake_and_peer() {
+        use crate::encryption_status::{EncryptionStatusEvent, FingerprintVerification};
+        use crate::event_bus::SubscriptionOptions;
+
+        let clock = crate::clock::TestClock::shared();
+        let manager = SecurityManager::new().with_clock(clock.clone());
+        let mut events = manager.subscribe_encryption_status(SubscriptionOptions::all());
+        assert!(manager.session_encryption("session").is_none());
+
+        manager.generate_session_key("session").await.unwrap();
+        let status = manager.session_encryption("session").unwrap();
+        assert_eq!(status.local.algorithm, Some(EncryptionAlgorithm::Aes256Gcm));
+        assert!(!status.end_to_end);
+        assert!(matches!(
+            events.try_recv(),
+            Some(EncryptionStatusEvent::LocalChanged(_))
+        ));
+
+        let verification = manager
+            .record_dtls_handshake(
+                "session",
+                "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256",
+                "AB:CD",
+                Some("ab:cd"),
+            )
+            .await;
+        assert_eq!(verification, FingerprintVerification::Verified);
+        assert!(manager.session_encryption("session").unwrap().end_to_end);
+
+        clock.advance(std::time::Duration::from_secs(600));
+        manager.rotate_session_key("session").await.unwrap();
+        let local = match (events.try_recv(), events.try_recv()) {
+            (
+                Some(EncryptionStatusEvent::LocalChanged(_)),
+                Some(EncryptionStatusEvent::LocalChanged(local)),
+            ) => local,
+            other => panic!("unexpected events: {:?}", other),
+        };
+        assert_eq!(local.rotation_count, 1);
+        clock.advance(std::time::Duration::from_secs(120));
+        assert_eq!(
+            manager.session_encryption("session").unwrap().key_age_secs,
+            Some(120)
+        );
+
+        // The peer disagreeing on the cipher suite is flagged
+        let mut peer = local.clone();
+        peer.dtls_cipher_suite = Some("TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA".to_string());
+        assert_eq!(manager.apply_peer_encryption_status(peer), Some(false));
+        assert!(matches!(
+            events.try_recv(),
+            Some(EncryptionStatusEvent::PeerChanged {
+                consistent: false,
+                ..
+            })
+        ));
+        assert!(!manager.session_encryption("session").unwrap().consistent);
+        assert_eq!(manager.apply_peer_encryption_status(local), Some(true));
+
+        manager.remove_session_key("session").await;
+        assert!(manager.session_encryption("session").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_startup_self_check_records_events() {
+        use crate::self_check::SelfCheckConfig;
+
+        // Debugger detection only runs under the strict profile
+        let strict =
+            crate::self_check::run_self_check(&SelfCheckConfig::default(), SecurityProfile::Strict);
+        assert_eq!(
+            strict.debugger_checked,
+            cfg!(any(
+                target_os = "linux",
+                target_os = "windows",
+                target_os = "macos"
+            ))
+        );
+
+        let manager = SecurityManager::new();
+        let report = manager
+            .run_startup_self_check(&SelfCheckConfig::default())
+            .await;
+        assert!(!report.debugger_checked);
+        assert!(report.passed());
+
+        let config = std::env::temp_dir().join(format!("cec-unreadable-{}", uuid::Uuid::new_v4()));
+        let report = manager
+            .run_startup_self_check(&SelfCheckConfig {
+                config_files: vec![config],
+                config_verifying_key: Some(
+                    ed25519_dalek::SigningKey::from_bytes(&[3u8; 32])
+                        .verifying_key()
+                        .to_bytes(),
+                ),
+                key_files: Vec::new(),
+            })
+            .await;
+        assert!(report.has_critical());
+
+        let events = manager.get_security_events().await;
+        assert!(events
+            .iter()
+            .any(|e| matches!(e.event_type, SecurityEventType::IntegrityCheckPassed)));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e.event_type, SecurityEventType::IntegrityViolation)));
+    }
 }
 
 // Property-Based Tests using proptest
@@ -0,0 +1,387 @@
+//! Startup Integrity Self-Check
+//!
+//! Run once when the engine starts, before any session is accepted:
+//!
+//! - configuration files provisioned with a detached Ed25519 signature
+//!   (`<file>.sig`, hex) must still match it;
+//! - files holding private keys or secrets must not be readable by other
+//!   users;
+//! - under the strict security profile, an attached debugger or tracer is
+//!   reported, on platforms where it can be detected.
+//!
+//! Findings are returned as a report and recorded by `SecurityManager` as
+//! security events; the check itself never aborts startup.
+
+use crate::security::{IssueSeverity, SecurityProfile};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Extension of detached configuration signatures
+pub const SIGNATURE_EXTENSION: &str = "sig";
+
+/// What the self-check looks at
+#[derive(Debug, Clone, Default)]
+pub struct SelfCheckConfig {
+    /// Configuration files expected to carry a signature
+    pub config_files: Vec<PathBuf>,
+    /// Key the configuration was signed with; `None` when not provisioned,
+    /// which skips signature checks
+    pub config_verifying_key: Option<[u8; 32]>,
+    /// Private keys and secret stores
+    pub key_files: Vec<PathBuf>,
+}
+
+/// Problem found by the self-check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SelfCheckFinding {
+    ConfigUnreadable {
+        path: PathBuf,
+        error: String,
+    },
+    ConfigSignatureMissing {
+        path: PathBuf,
+    },
+    ConfigSignatureInvalid {
+        path: PathBuf,
+    },
+    /// Key file readable by users other than its owner
+    KeyFileExposed {
+        path: PathBuf,
+        mode: u32,
+        world_readable: bool,
+    },
+    DebuggerAttached {
+        detail: String,
+    },
+}
+
+impl SelfCheckFinding {
+    pub fn severity(&self) -> IssueSeverity {
+        match self {
+            SelfCheckFinding::KeyFileExposed {
+                world_readable: false,
+                ..
+            } => IssueSeverity::Warning,
+            _ => IssueSeverity::Critical,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            SelfCheckFinding::ConfigUnreadable { path, error } => {
+                format!("Cannot read configuration {}: {}", path.display(), error)
+            }
+            SelfCheckFinding::ConfigSignatureMissing { path } => {
+                format!("Configuration {} has no signature", path.display())
+            }
+            SelfCheckFinding::ConfigSignatureInvalid { path } => {
+                format!(
+                    "Configuration {} does not match its signature",
+                    path.display()
+                )
+            }
+            SelfCheckFinding::KeyFileExposed {
+                path,
+                mode,
+                world_readable,
+            } => format!(
+                "Key file {} is {} (mode {:o})",
+                path.display(),
+                if *world_readable {
+                    "world-readable"
+                } else {
+                    "group-readable"
+                },
+                mode
+            ),
+            SelfCheckFinding::DebuggerAttached { detail } => {
+                format!("Debugger attached: {}", detail)
+            }
+        }
+    }
+}
+
+/// Outcome of a self-check run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelfCheckReport {
+    pub checked_at: chrono::DateTime<chrono::Utc>,
+    pub findings: Vec<SelfCheckFinding>,
+    /// Debugger detection ran (strict profile on a supported platform)
+    pub debugger_checked: bool,
+}
+
+impl SelfCheckReport {
+    pub fn passed(&self) -> bool {
+        self.findings.is_empty()
+    }
+
+    pub fn has_critical(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| finding.severity() == IssueSeverity::Critical)
+    }
+}
+
+/// Run every check that applies to `profile`
+pub fn run_self_check(config: &SelfCheckConfig, profile: SecurityProfile) -> SelfCheckReport {
+    let mut findings = Vec::new();
+
+    if let Some(key) = &config.config_verifying_key {
+        match VerifyingKey::from_bytes(key) {
+            Ok(key) => findings.extend(
+                config
+                    .config_files
+                    .iter()
+                    .filter_map(|path| verify_config_signature(path, &key)),
+            ),
+            Err(e) => tracing::error!("Invalid configuration verifying key: {}", e),
+        }
+    }
+
+    findings.extend(
+        config
+            .key_files
+            .iter()
+            .filter_map(|path| check_key_file_permissions(path)),
+    );
+
+    let debugger_checked = profile == SecurityProfile::Strict && debugger_detection_supported();
+    if debugger_checked {
+        if let Some(detail) = detect_debugger() {
+            findings.push(SelfCheckFinding::DebuggerAttached { detail });
+        }
+    }
+
+    SelfCheckReport {
+        checked_at: chrono::Utc::now(),
+        findings,
+        debugger_checked,
+    }
+}
+
+/// Path of the detached signature for `path`
+pub fn signature_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    PathBuf::from(name)
+}
+
+fn verify_config_signature(path: &Path, key: &VerifyingKey) -> Option<SelfCheckFinding> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(e) => {
+            return Some(SelfCheckFinding::ConfigUnreadable {
+                path: path.to_path_buf(),
+                error: e.to_string(),
+            })
+        }
+    };
+    let Ok(encoded) = std::fs::read_to_string(signature_path(path)) else {
+        return Some(SelfCheckFinding::ConfigSignatureMissing {
+            path: path.to_path_buf(),
+        });
+    };
+    let valid = hex::decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .is_some_and(|signature| key.verify(&content, &signature).is_ok());
+    (!valid).then(|| SelfCheckFinding::ConfigSignatureInvalid {
+        path: path.to_path_buf(),
+    })
+}
+
+/// Flag a key file that users other than its owner can read
+///
+/// Missing files are not a finding; nothing has been provisioned yet.
+pub fn check_key_file_permissions(path: &Path) -> Option<SelfCheckFinding> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path).ok()?.permissions().mode() & 0o777;
+        if mode & 0o044 != 0 {
+            return Some(SelfCheckFinding::KeyFileExposed {
+                path: path.to_path_buf(),
+                mode,
+                world_readable: mode & 0o004 != 0,
+            });
+        }
+    }
+    #[cfg(windows)]
+    {
+        // The file ACL would be checked for Everyone / Users read access here
+        let _ = path;
+    }
+    None
+}
+
+fn debugger_detection_supported() -> bool {
+    cfg!(any(
+        target_os = "linux",
+        target_os = "windows",
+        target_os = "macos"
+    ))
+}
+
+/// Describe an attached debugger or tracer, if any
+pub fn detect_debugger() -> Option<String> {
+    #[cfg(target_os = "linux")]
+    {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let tracer = status
+            .lines()
+            .find_map(|line| line.strip_prefix("TracerPid:"))?
+            .trim()
+            .parse::<u32>()
+            .ok()?;
+        if tracer != 0 {
+            return Some(format!("traced by pid {}", tracer));
+        }
+    }
+    #[cfg(target_os = "windows")]
+    {
+        type Bool = i32;
+        type Handle = *mut std::ffi::c_void;
+
+        extern "system" {
+            fn IsDebuggerPresent() -> Bool;
+            fn GetCurrentProcess() -> Handle;
+            fn CheckRemoteDebuggerPresent(process: Handle, present: *mut Bool) -> Bool;
+        }
+
+        // SAFETY: no arguments, or the pseudo-handle of this process and a
+        // valid out pointer
+        unsafe {
+            if IsDebuggerPresent() != 0 {
+                return Some("debugger attached".to_string());
+            }
+            let mut present: Bool = 0;
+            if CheckRemoteDebuggerPresent(GetCurrentProcess(), &mut present) != 0 && present != 0 {
+                return Some("remote debugger attached".to_string());
+            }
+        }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        use std::ffi::{c_int, c_uint, c_void};
+
+        const CTL_KERN: c_int = 1;
+        const KERN_PROC: c_int = 14;
+        const KERN_PROC_PID: c_int = 1;
+        const P_TRACED: i32 = 0x0000_0800;
+        /// `sizeof(struct kinfo_proc)` on 64-bit macOS
+        const KINFO_PROC_SIZE: usize = 648;
+        /// Offset of `kp_proc.p_flag`, after the `p_un` union and two pointers
+        const P_FLAG_OFFSET: usize = 32;
+
+        extern "C" {
+            fn getpid() -> c_int;
+            fn sysctl(
+                name: *mut c_int,
+                namelen: c_uint,
+                oldp: *mut c_void,
+                oldlenp: *mut usize,
+                newp: *mut c_void,
+                newlen: usize,
+            ) -> c_int;
+        }
+
+        // u64 storage keeps the pointer-aligned struct aligned
+        let mut info = [0u64; KINFO_PROC_SIZE / 8];
+        let mut size = KINFO_PROC_SIZE;
+        // SAFETY: the buffer is `size` bytes long and the MIB has 4 entries
+        let ok = unsafe {
+            let mut mib = [CTL_KERN, KERN_PROC, KERN_PROC_PID, getpid()];
+            sysctl(
+                mib.as_mut_ptr(),
+                mib.len() as c_uint,
+                info.as_mut_ptr().cast(),
+                &mut size,
+                std::ptr::null_mut(),
+                0,
+            ) == 0
+        };
+        if ok && size >= P_FLAG_OFFSET + 4 {
+            // SAFETY: the offset lies within the buffer
+            let p_flag = unsafe {
+                info.as_ptr()
+                    .cast::<u8>()
+                    .add(P_FLAG_OFFSET)
+                    .cast::<i32>()
+                    .read_unaligned()
+            };
+            if p_flag & P_TRACED != 0 {
+                return Some("process is being traced".to_string());
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("cec-self-check-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_config_signature_verified() {
+        let dir = temp_dir();
+        let signing_key = SigningKey::from_bytes(&[7u8; 32]);
+        let signed = dir.join("policy.json");
+        let tampered = dir.join("server.json");
+        let unsigned = dir.join("extra.json");
+        for path in [&signed, &tampered, &unsigned] {
+            std::fs::write(path, b"{\"unattended_access\":false}").unwrap();
+        }
+        for path in [&signed, &tampered] {
+            let signature = signing_key.sign(&std::fs::read(path).unwrap());
+            std::fs::write(signature_path(path), hex::encode(signature.to_bytes())).unwrap();
+        }
+        std::fs::write(&tampered, b"{\"unattended_access\":true}").unwrap();
+
+        let report = run_self_check(
+            &SelfCheckConfig {
+                config_files: vec![signed, tampered.clone(), unsigned.clone()],
+                config_verifying_key: Some(signing_key.verifying_key().to_bytes()),
+                key_files: Vec::new(),
+            },
+            SecurityProfile::Compatible,
+        );
+        assert!(!report.debugger_checked);
+        assert_eq!(
+            report.findings,
+            vec![
+                SelfCheckFinding::ConfigSignatureInvalid { path: tampered },
+                SelfCheckFinding::ConfigSignatureMissing { path: unsigned },
+            ]
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_world_readable_key_reported() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = temp_dir();
+        let key = dir.join("device.key");
+        std::fs::write(&key, [1u8; 32]).unwrap();
+
+        std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o600)).unwrap();
+        assert!(check_key_file_permissions(&key).is_none());
+
+        std::fs::set_permissions(&key, std::fs::Permissions::from_mode(0o644)).unwrap();
+        let finding = check_key_file_permissions(&key).unwrap();
+        assert_eq!(finding.severity(), IssueSeverity::Critical);
+        assert!(finding.describe().contains("world-readable"));
+
+        assert!(check_key_file_permissions(&dir.join("missing.key")).is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
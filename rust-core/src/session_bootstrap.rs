@@ -0,0 +1,344 @@
+//! Session Bootstrap State Machine
+//!
+//! Establishing a session touches signaling (request/response), access
+//! control (user decision), and the WebRTC engine (SDP and ICE). This module
+//! gives that sequence one explicit owner:
+//!
+//! ```text
+//! Idle → Requesting → Authorizing → Negotiating → Connecting → Active → Closing → Idle
+//! ```
+//!
+//! The controller starts in `Requesting`; the controlled side enters
+//! `Authorizing` directly when a request arrives. Any in-progress state may
+//! move to `Closing` on failure, cancellation or timeout. Illegal transitions
+//! are rejected, and every state except `Idle` and `Active` has a deadline.
+
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use crate::timestamp::Timestamp;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+
+/// Bootstrap state of a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum BootstrapState {
+    Idle,
+    /// Connection request sent, waiting for the remote side to receive it
+    Requesting,
+    /// Waiting for the controlled side's user or policy decision
+    Authorizing,
+    /// Exchanging SDP offer/answer
+    Negotiating,
+    /// ICE connectivity checks and DTLS handshake
+    Connecting,
+    Active,
+    /// Tearing down transports
+    Closing,
+}
+
+impl BootstrapState {
+    /// Whether `to` is a legal next state
+    pub fn can_transition_to(self, to: BootstrapState) -> bool {
+        use BootstrapState::*;
+        matches!(
+            (self, to),
+            (Idle, Requesting)
+                | (Idle, Authorizing)
+                | (Requesting, Authorizing)
+                | (Authorizing, Negotiating)
+                | (Negotiating, Connecting)
+                | (Connecting, Active)
+                | (
+                    Requesting | Authorizing | Negotiating | Connecting | Active,
+                    Closing
+                )
+                | (Closing, Idle)
+        )
+    }
+}
+
+impl std::fmt::Display for BootstrapState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Maximum time allowed in each transient state
+#[derive(Debug, Clone)]
+pub struct BootstrapTimeouts {
+    pub requesting: Duration,
+    pub authorizing: Duration,
+    pub negotiating: Duration,
+    pub connecting: Duration,
+    pub closing: Duration,
+}
+
+impl BootstrapTimeouts {
+    pub fn for_state(&self, state: BootstrapState) -> Option<Duration> {
+        match state {
+            BootstrapState::Requesting => Some(self.requesting),
+            BootstrapState::Authorizing => Some(self.authorizing),
+            BootstrapState::Negotiating => Some(self.negotiating),
+            BootstrapState::Connecting => Some(self.connecting),
+            BootstrapState::Closing => Some(self.closing),
+            BootstrapState::Idle | BootstrapState::Active => None,
+        }
+    }
+}
+
+impl Default for BootstrapTimeouts {
+    fn default() -> Self {
+        Self {
+            requesting: Duration::from_secs(15),
+            // Leaves the host user time to read the prompt
+            authorizing: Duration::from_secs(60),
+            negotiating: Duration::from_secs(15),
+            connecting: Duration::from_secs(30),
+            closing: Duration::from_secs(5),
+        }
+    }
+}
+
+/// Current bootstrap status, as shown by the UI
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootstrapSnapshot {
+    pub session_id: String,
+    pub state: BootstrapState,
+    pub previous: Option<BootstrapState>,
+    pub entered_at: Timestamp,
+    /// Why the session is closing, if it is
+    pub close_reason: Option<String>,
+    /// State whose deadline expired, when the close was a timeout
+    #[serde(default)]
+    pub timed_out_in: Option<BootstrapState>,
+}
+
+/// State change notification
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BootstrapTransition {
+    pub session_id: String,
+    pub from: BootstrapState,
+    pub to: BootstrapState,
+    pub reason: Option<String>,
+}
+
+impl EventType for BootstrapTransition {
+    /// Name of the state entered, so subscribers can filter on e.g. `"Active"`
+    fn event_type(&self) -> &'static str {
+        match self.to {
+            BootstrapState::Idle => "Idle",
+            BootstrapState::Requesting => "Requesting",
+            BootstrapState::Authorizing => "Authorizing",
+            BootstrapState::Negotiating => "Negotiating",
+            BootstrapState::Connecting => "Connecting",
+            BootstrapState::Active => "Active",
+            BootstrapState::Closing => "Closing",
+        }
+    }
+}
+
+/// Session bootstrap state machine; the single source of truth for the
+/// connection phase of one session
+pub struct SessionBootstrap {
+    snapshot: Arc<RwLock<BootstrapSnapshot>>,
+    timeouts: BootstrapTimeouts,
+    events: EventBus<BootstrapTransition>,
+}
+
+impl SessionBootstrap {
+    pub fn new(session_id: String) -> Self {
+        Self::with_timeouts(session_id, BootstrapTimeouts::default())
+    }
+
+    pub fn with_timeouts(session_id: String, timeouts: BootstrapTimeouts) -> Self {
+        Self {
+            snapshot: Arc::new(RwLock::new(BootstrapSnapshot {
+                session_id,
+                state: BootstrapState::Idle,
+                previous: None,
+                entered_at: Timestamp::now(),
+                close_reason: None,
+                timed_out_in: None,
+            })),
+            timeouts,
+            events: EventBus::new(),
+        }
+    }
+
+    /// Subscribe to state transitions
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<BootstrapTransition> {
+        self.events.subscribe(options)
+    }
+
+    pub async fn state(&self) -> BootstrapState {
+        self.snapshot.read().await.state
+    }
+
+    pub async fn snapshot(&self) -> BootstrapSnapshot {
+        self.snapshot.read().await.clone()
+    }
+
+    /// Move to `to`, rejecting transitions the protocol does not allow
+    pub async fn transition(&self, to: BootstrapState) -> Result<()> {
+        self.transition_with_reason(to, None).await
+    }
+
+    async fn transition_with_reason(
+        &self,
+        to: BootstrapState,
+        reason: Option<String>,
+    ) -> Result<()> {
+        let mut snapshot = self.snapshot.write().await;
+        let from = snapshot.state;
+        if !from.can_transition_to(to) {
+            return Err(anyhow::anyhow!(
+                "Invalid bootstrap transition for session {}: {} -> {}",
+                snapshot.session_id,
+                from,
+                to
+            ));
+        }
+
+        snapshot.previous = Some(from);
+        snapshot.state = to;
+        snapshot.entered_at = Timestamp::now();
+        match to {
+            BootstrapState::Closing => snapshot.close_reason = reason.clone(),
+            BootstrapState::Idle => {}
+            _ => {
+                snapshot.close_reason = None;
+                snapshot.timed_out_in = None;
+            }
+        }
+
+        tracing::debug!(
+            "Session {} bootstrap: {} -> {}",
+            snapshot.session_id,
+            from,
+            to
+        );
+        self.events.publish(BootstrapTransition {
+            session_id: snapshot.session_id.clone(),
+            from,
+            to,
+            reason,
+        });
+        Ok(())
+    }
+
+    /// Abort the bootstrap (or end an active session) with a reason
+    pub async fn close(&self, reason: &str) -> Result<()> {
+        self.transition_with_reason(BootstrapState::Closing, Some(reason.to_string()))
+            .await
+    }
+
+    /// Enforce the current state's deadline
+    ///
+    /// A transient state that overran its timeout moves to `Closing`; a
+    /// `Closing` state that overran moves to `Idle`. Returns the state that
+    /// timed out, if any.
+    pub async fn check_timeout(&self) -> Result<Option<BootstrapState>> {
+        let (state, entered_at) = {
+            let snapshot = self.snapshot.read().await;
+            (snapshot.state, snapshot.entered_at)
+        };
+        let Some(limit) = self.timeouts.for_state(state) else {
+            return Ok(None);
+        };
+        if !entered_at.has_elapsed(limit) {
+            return Ok(None);
+        }
+
+        tracing::warn!("Session bootstrap timed out in state {}", state);
+        if state == BootstrapState::Closing {
+            self.transition(BootstrapState::Idle).await?;
+        } else {
+            self.transition_with_reason(
+                BootstrapState::Closing,
+                Some(format!("Timed out in {}", state)),
+            )
+            .await?;
+            self.snapshot.write().await.timed_out_in = Some(state);
+        }
+        Ok(Some(state))
+    }
+
+    /// Time left before the current state's deadline
+    pub async fn time_remaining(&self) -> Option<Duration> {
+        let snapshot = self.snapshot.read().await;
+        self.timeouts
+            .for_state(snapshot.state)
+            .map(|limit| snapshot.entered_at.remaining(limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_full_lifecycle_and_invalid_transition() {
+        let bootstrap = SessionBootstrap::new("s1".to_string());
+        let mut events = bootstrap.subscribe(SubscriptionOptions::all());
+
+        assert!(bootstrap.transition(BootstrapState::Active).await.is_err());
+
+        for state in [
+            BootstrapState::Requesting,
+            BootstrapState::Authorizing,
+            BootstrapState::Negotiating,
+            BootstrapState::Connecting,
+            BootstrapState::Active,
+        ] {
+            bootstrap.transition(state).await.unwrap();
+        }
+        assert_eq!(bootstrap.state().await, BootstrapState::Active);
+        assert!(bootstrap.time_remaining().await.is_none());
+
+        bootstrap.close("User ended session").await.unwrap();
+        let snapshot = bootstrap.snapshot().await;
+        assert_eq!(snapshot.previous, Some(BootstrapState::Active));
+        assert_eq!(snapshot.close_reason.as_deref(), Some("User ended session"));
+        bootstrap.transition(BootstrapState::Idle).await.unwrap();
+
+        let first = events.recv().await.unwrap();
+        assert_eq!(first.from, BootstrapState::Idle);
+        assert_eq!(first.to, BootstrapState::Requesting);
+    }
+
+    #[tokio::test]
+    async fn test_state_timeout_closes() {
+        let timeouts = BootstrapTimeouts {
+            negotiating: Duration::from_millis(10),
+            closing: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let bootstrap = SessionBootstrap::with_timeouts("s1".to_string(), timeouts);
+        bootstrap
+            .transition(BootstrapState::Authorizing)
+            .await
+            .unwrap();
+        bootstrap
+            .transition(BootstrapState::Negotiating)
+            .await
+            .unwrap();
+        assert_eq!(bootstrap.check_timeout().await.unwrap(), None);
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(
+            bootstrap.check_timeout().await.unwrap(),
+            Some(BootstrapState::Negotiating)
+        );
+        assert_eq!(bootstrap.state().await, BootstrapState::Closing);
+        assert_eq!(
+            bootstrap.snapshot().await.timed_out_in,
+            Some(BootstrapState::Negotiating)
+        );
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        bootstrap.check_timeout().await.unwrap();
+        assert_eq!(bootstrap.state().await, BootstrapState::Idle);
+    }
+}
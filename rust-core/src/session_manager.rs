@@ -1,10 +1,24 @@
+use crate::clock::{system_clock, SharedClock};
+use crate::errors::{ManagerError, ResourceKind};
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use crate::geoip::{GeoIpDatabase, GeoLocation};
+use crate::logging::{LogEntry, LogLevel, LogManager};
+use crate::presence::{DeviceDirectory, DevicePreferences};
+use crate::privacy_mode::{PrivacyMode, PrivacyOptions};
+use crate::quality_heatmap::{QualityHeatmap, QualityHistory};
+use crate::receive_stats::FreezeStats;
 use anyhow::Result;
 use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
+/// 同一会话两次关键帧请求的最小间隔（毫秒）
+pub const KEYFRAME_REQUEST_MIN_INTERVAL_MS: u64 = 1000;
+
 /// 会话状态枚举
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum SessionStatus {
@@ -23,6 +37,12 @@ pub enum Permission {
     FileTransfer,
     AudioCapture,
     SystemControl,
+    /// Share a single application and its audio
+    AppSharing,
+    /// Open links in the host's browser, without input control
+    OpenUrl,
+    /// 屏蔽被控端本地键鼠并可黑屏（隐私模式）
+    PrivacyMode,
 }
 
 /// 连接质量等级
@@ -64,6 +84,30 @@ pub struct SessionStats {
     pub frames_received: u64,
     pub connection_quality: ConnectionQuality,
     pub connection_type: ConnectionType,
+    /// 接收端画面冻结次数（渲染间隔超过 500ms）
+    #[serde(default)]
+    pub freeze_count: u32,
+    /// 冻结总时长
+    #[serde(default)]
+    pub total_freeze_ms: u64,
+    /// 本次会话已传输的文件数
+    #[serde(default)]
+    pub files_transferred: u32,
+    /// 本次会话已传输的文件字节数
+    #[serde(default)]
+    pub file_bytes_transferred: u64,
+    /// 最近一次统计周期的发送码率（bit/s）
+    #[serde(default)]
+    pub send_bitrate_bps: u64,
+    /// 最近一次统计周期的接收码率（bit/s）
+    #[serde(default)]
+    pub receive_bitrate_bps: u64,
+    /// 已发出的关键帧请求数
+    #[serde(default)]
+    pub keyframe_requests: u32,
+    /// 因请求过于频繁而被忽略的关键帧请求数
+    #[serde(default)]
+    pub keyframe_requests_throttled: u32,
 }
 
 impl Default for SessionStats {
@@ -81,6 +125,14 @@ impl Default for SessionStats {
             frames_received: 0,
             connection_quality: ConnectionQuality::Good,
             connection_type: ConnectionType::Direct,
+            freeze_count: 0,
+            total_freeze_ms: 0,
+            files_transferred: 0,
+            file_bytes_transferred: 0,
+            send_bitrate_bps: 0,
+            receive_bitrate_bps: 0,
+            keyframe_requests: 0,
+            keyframe_requests_throttled: 0,
         }
     }
 }
@@ -93,6 +145,62 @@ pub enum ConnectionType {
     Unknown,
 }
 
+/// 对端身份
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PeerIdentity {
+    pub device_id: String,
+    /// 对端设备名称
+    pub name: Option<String>,
+    /// 名称与证书指纹是否已通过校验（证书或配对记录）
+    pub verified: bool,
+    /// 对端证书指纹（hex）
+    pub fingerprint: Option<String>,
+}
+
+impl PeerIdentity {
+    /// 仅知道设备 ID 的未验证身份
+    pub fn unverified(device_id: &str) -> Self {
+        Self {
+            device_id: device_id.to_string(),
+            name: None,
+            verified: false,
+            fingerprint: None,
+        }
+    }
+}
+
+/// 本端在会话中的角色
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SessionRole {
+    /// 控制端
+    Controller,
+    /// 被控端
+    Controlled,
+}
+
+/// 供被控端托盘/悬浮窗展示的当前会话概况
+///
+/// 每当身份、权限、连接方式、码率或状态变化时通过
+/// `SessionEvent::DescriptorChanged` 推送；会话结束时推送 `Ended`。
+/// `elapsed_secs` 是推送时刻的值，界面可根据 `started_at` 自行计时。
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ActiveSessionDescriptor {
+    pub session_id: String,
+    pub local_role: SessionRole,
+    pub peer: PeerIdentity,
+    pub status: SessionStatus,
+    pub permissions: Vec<Permission>,
+    pub connection_type: ConnectionType,
+    pub send_bitrate_bps: u64,
+    pub receive_bitrate_bps: u64,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_secs: u64,
+    pub recording: RecordingStatus,
+    /// 本会话开启的隐私模式
+    #[serde(default)]
+    pub privacy_mode: Option<PrivacyOptions>,
+}
+
 /// 会话信息
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Session {
@@ -105,6 +213,65 @@ pub struct Session {
     pub permissions: Vec<Permission>,
     pub stats: SessionStats,
     pub metadata: HashMap<String, String>,
+    /// 远端观测到的公网 IP
+    #[serde(default)]
+    pub remote_ip: Option<String>,
+    /// 屏幕录制状态
+    #[serde(default)]
+    pub recording: RecordingState,
+    /// 对端身份，连接建立并校验后填入
+    #[serde(default)]
+    pub peer_identity: Option<PeerIdentity>,
+    /// 生效的连接偏好：设备默认值叠加本次会话的覆盖项
+    #[serde(default)]
+    pub preferences: DevicePreferences,
+    /// 本会话开启的隐私模式
+    #[serde(default)]
+    pub privacy_mode: Option<PrivacyOptions>,
+    /// 上一次统计更新的时间，用于计算码率
+    #[serde(skip)]
+    stats_sampled_at: Option<DateTime<Utc>>,
+    /// 上一次发出关键帧请求的时间，用于限流
+    #[serde(skip)]
+    keyframe_requested_at: Option<DateTime<Utc>>,
+}
+
+/// 录制状态
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub enum RecordingStatus {
+    #[default]
+    Idle,
+    /// 等待被控端用户确认
+    AwaitingConsent,
+    Recording,
+    /// 被控端拒绝录制
+    Refused,
+}
+
+/// 会话的录制状态及发起信息
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct RecordingState {
+    pub status: RecordingStatus,
+    pub requested_by: Option<String>,
+    pub purpose: Option<String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// 录制策略
+#[cfg(feature = "recording")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingPolicy {
+    /// 开始录制前是否必须经被控端用户确认
+    pub require_host_consent: bool,
+}
+
+#[cfg(feature = "recording")]
+impl Default for RecordingPolicy {
+    fn default() -> Self {
+        Self {
+            require_host_consent: true,
+        }
+    }
 }
 
 impl Session {
@@ -120,6 +287,39 @@ impl Session {
             permissions,
             stats: SessionStats::default(),
             metadata: HashMap::new(),
+            remote_ip: None,
+            recording: RecordingState::default(),
+            peer_identity: None,
+            preferences: DevicePreferences::default(),
+            privacy_mode: None,
+            stats_sampled_at: None,
+            keyframe_requested_at: None,
+        }
+    }
+
+    /// 从 `local_device_id` 的视角生成会话概况
+    pub fn descriptor(&self, local_device_id: &str) -> ActiveSessionDescriptor {
+        let (local_role, peer_id) = if self.controller_id == local_device_id {
+            (SessionRole::Controller, &self.controlled_id)
+        } else {
+            (SessionRole::Controlled, &self.controller_id)
+        };
+        ActiveSessionDescriptor {
+            session_id: self.session_id.clone(),
+            local_role,
+            peer: self
+                .peer_identity
+                .clone()
+                .unwrap_or_else(|| PeerIdentity::unverified(peer_id)),
+            status: self.status.clone(),
+            permissions: self.permissions.clone(),
+            connection_type: self.stats.connection_type.clone(),
+            send_bitrate_bps: self.stats.send_bitrate_bps,
+            receive_bitrate_bps: self.stats.receive_bitrate_bps,
+            started_at: self.start_time,
+            elapsed_secs: self.duration_secs(),
+            recording: self.recording.status,
+            privacy_mode: self.privacy_mode,
         }
     }
 
@@ -141,6 +341,17 @@ impl Session {
         self.stats.bytes_sent += bytes_delta.0;
         self.stats.bytes_received += bytes_delta.1;
 
+        let now = Utc::now();
+        if let Some(elapsed_ms) = self
+            .stats_sampled_at
+            .map(|at| (now - at).num_milliseconds())
+            .filter(|ms| *ms > 0)
+        {
+            self.stats.send_bitrate_bps = bytes_delta.0 * 8 * 1000 / elapsed_ms as u64;
+            self.stats.receive_bitrate_bps = bytes_delta.1 * 8 * 1000 / elapsed_ms as u64;
+        }
+        self.stats_sampled_at = Some(now);
+
         // 更新延迟统计
         if latency > 0 {
             if self.stats.average_latency_ms == 0 {
@@ -167,6 +378,9 @@ pub struct SessionOptions {
     pub auto_accept: bool,
     pub session_timeout_secs: u64,
     pub require_encryption: bool,
+    /// 本次会话的连接偏好，已设置的字段覆盖设备目录中的默认值
+    #[serde(default)]
+    pub preferences: DevicePreferences,
 }
 
 impl Default for SessionOptions {
@@ -176,6 +390,7 @@ impl Default for SessionOptions {
             auto_accept: false,
             session_timeout_secs: 3600, // 1 hour
             require_encryption: true,
+            preferences: DevicePreferences::default(),
         }
     }
 }
@@ -191,6 +406,21 @@ pub struct SessionRecord {
     pub duration_secs: u64,
     pub end_reason: EndReason,
     pub final_stats: SessionStats,
+    /// 远端观测到的公网 IP
+    #[serde(default)]
+    pub remote_ip: Option<String>,
+    /// 根据离线 GeoIP 数据库解析的大致位置
+    #[serde(default)]
+    pub geo_location: Option<GeoLocation>,
+}
+
+/// 持久化的会话历史文件
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SessionHistoryFile {
+    records: Vec<SessionRecord>,
+    /// 按对端聚合的连接质量
+    #[serde(default)]
+    quality: QualityHistory,
 }
 
 /// 会话结束原因
@@ -202,6 +432,8 @@ pub enum EndReason {
     NetworkError,
     AuthenticationFailed,
     PermissionDenied,
+    /// 被控端退出
+    HostShutdown,
     SystemError(String),
 }
 
@@ -214,6 +446,7 @@ impl std::fmt::Display for EndReason {
             EndReason::NetworkError => write!(f, "网络错误"),
             EndReason::AuthenticationFailed => write!(f, "认证失败"),
             EndReason::PermissionDenied => write!(f, "权限被拒绝"),
+            EndReason::HostShutdown => write!(f, "被控端关闭"),
             EndReason::SystemError(msg) => write!(f, "系统错误: {}", msg),
         }
     }
@@ -233,7 +466,16 @@ pub struct PermissionRequest {
 
 impl PermissionRequest {
     pub fn new(from_device_id: String, to_device_id: String, permissions: Vec<Permission>) -> Self {
-        let now = Utc::now();
+        Self::new_at(from_device_id, to_device_id, permissions, Utc::now())
+    }
+
+    /// 以指定时间作为创建时间
+    pub fn new_at(
+        from_device_id: String,
+        to_device_id: String,
+        permissions: Vec<Permission>,
+        now: DateTime<Utc>,
+    ) -> Self {
         Self {
             request_id: Uuid::new_v4().to_string(),
             from_device_id,
@@ -246,8 +488,37 @@ impl PermissionRequest {
     }
 
     pub fn is_expired(&self) -> bool {
-        Utc::now() > self.expires_at
+        self.is_expired_at(Utc::now())
     }
+
+    /// 在指定时间是否已过期
+    pub fn is_expired_at(&self, now: DateTime<Utc>) -> bool {
+        now > self.expires_at
+    }
+}
+
+/// 连接异常标记
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum ConnectionAnomaly {
+    /// 首次出现的远端设备
+    NewDevice,
+    /// 首次出现的来源国家/地区
+    NewCountry,
+}
+
+/// “最近连接”界面展示的连接记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentConnection {
+    pub session_id: String,
+    pub remote_device_id: String,
+    pub remote_ip: Option<String>,
+    pub connection_type: ConnectionType,
+    pub geo_location: Option<GeoLocation>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub duration_secs: u64,
+    pub end_reason: EndReason,
+    pub anomalies: Vec<ConnectionAnomaly>,
 }
 
 /// 会话事件类型
@@ -285,10 +556,64 @@ pub enum SessionEvent {
     PermissionDenied {
         request_id: String,
     },
+    RecordingRequested {
+        session_id: String,
+        requested_by: String,
+        purpose: Option<String>,
+    },
+    RecordingStarted {
+        session_id: String,
+    },
+    RecordingStopped {
+        session_id: String,
+    },
+    RecordingRefused {
+        session_id: String,
+    },
+    /// 会话中途授予或撤销了某项权限
+    PermissionChanged {
+        session_id: String,
+        permission: Permission,
+        granted: bool,
+    },
+    /// 会话概况发生变化
+    DescriptorChanged {
+        descriptor: ActiveSessionDescriptor,
+    },
+    /// 需要对端编码器尽快输出关键帧
+    KeyframeRequested {
+        session_id: String,
+    },
+    /// 会话开启、调整或关闭了隐私模式；`options` 为 None 表示已关闭
+    PrivacyModeChanged {
+        session_id: String,
+        options: Option<PrivacyOptions>,
+    },
 }
 
-/// 会话事件监听器
-pub type SessionEventCallback = Box<dyn Fn(SessionEvent) + Send + Sync>;
+impl EventType for SessionEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            SessionEvent::Created { .. } => "Created",
+            SessionEvent::Started { .. } => "Started",
+            SessionEvent::Paused { .. } => "Paused",
+            SessionEvent::Resumed { .. } => "Resumed",
+            SessionEvent::Ended { .. } => "Ended",
+            SessionEvent::StatsUpdated { .. } => "StatsUpdated",
+            SessionEvent::PermissionRequested { .. } => "PermissionRequested",
+            SessionEvent::PermissionGranted { .. } => "PermissionGranted",
+            SessionEvent::PermissionDenied { .. } => "PermissionDenied",
+            SessionEvent::RecordingRequested { .. } => "RecordingRequested",
+            SessionEvent::RecordingStarted { .. } => "RecordingStarted",
+            SessionEvent::RecordingStopped { .. } => "RecordingStopped",
+            SessionEvent::RecordingRefused { .. } => "RecordingRefused",
+            SessionEvent::PermissionChanged { .. } => "PermissionChanged",
+            SessionEvent::DescriptorChanged { .. } => "DescriptorChanged",
+            SessionEvent::KeyframeRequested { .. } => "KeyframeRequested",
+            SessionEvent::PrivacyModeChanged { .. } => "PrivacyModeChanged",
+        }
+    }
+}
 
 /// 会话管理器
 pub struct SessionManager {
@@ -296,8 +621,18 @@ pub struct SessionManager {
     active_sessions: Arc<RwLock<HashMap<String, Session>>>,
     session_history: Arc<RwLock<Vec<SessionRecord>>>,
     pending_requests: Arc<RwLock<HashMap<String, PermissionRequest>>>,
-    event_callbacks: Arc<RwLock<Vec<SessionEventCallback>>>,
+    events: EventBus<SessionEvent>,
     history_retention_days: u32,
+    geoip: Arc<RwLock<Option<GeoIpDatabase>>>,
+    #[cfg(feature = "recording")]
+    recording_policy: RecordingPolicy,
+    audit_log: Arc<RwLock<Option<Arc<LogManager>>>>,
+    quality_history: Arc<RwLock<QualityHistory>>,
+    device_directory: Arc<RwLock<DeviceDirectory>>,
+    /// 隐私模式（屏蔽本地输入、黑屏）
+    privacy: Arc<PrivacyMode>,
+    /// 过期判断使用的时钟
+    clock: SharedClock,
 }
 
 impl SessionManager {
@@ -308,31 +643,142 @@ impl SessionManager {
             active_sessions: Arc::new(RwLock::new(HashMap::new())),
             session_history: Arc::new(RwLock::new(Vec::new())),
             pending_requests: Arc::new(RwLock::new(HashMap::new())),
-            event_callbacks: Arc::new(RwLock::new(Vec::new())),
+            events: EventBus::new(),
             history_retention_days: 30,
+            geoip: Arc::new(RwLock::new(None)),
+            #[cfg(feature = "recording")]
+            recording_policy: RecordingPolicy::default(),
+            audit_log: Arc::new(RwLock::new(None)),
+            quality_history: Arc::new(RwLock::new(QualityHistory::new())),
+            device_directory: Arc::new(RwLock::new(DeviceDirectory::new())),
+            privacy: Arc::new(PrivacyMode::new()),
+            clock: system_clock(),
         }
     }
 
+    /// 使用指定时钟代替系统时钟
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// 使用指定的隐私模式实现（例如测试用的后端）
+    pub fn with_privacy_mode(mut self, privacy: Arc<PrivacyMode>) -> Self {
+        self.privacy = privacy;
+        self
+    }
+
+    /// 本机的隐私模式状态
+    pub fn privacy_mode(&self) -> Arc<PrivacyMode> {
+        self.privacy.clone()
+    }
+
+    /// 设备目录，其中的连接偏好会在创建会话时自动应用
+    pub fn device_directory(&self) -> Arc<RwLock<DeviceDirectory>> {
+        self.device_directory.clone()
+    }
+
     /// 设置历史记录保留天数
     pub fn set_history_retention_days(&mut self, days: u32) {
         self.history_retention_days = days;
     }
 
-    /// 注册事件回调
-    pub fn on_event(&self, callback: SessionEventCallback) {
-        if let Ok(mut callbacks) = self.event_callbacks.write() {
-            callbacks.push(callback);
+    /// 设置离线 GeoIP 数据库，用于连接历史的位置解析
+    pub fn set_geoip_database(&self, database: GeoIpDatabase) {
+        if let Ok(mut geoip) = self.geoip.write() {
+            *geoip = Some(database);
         }
     }
 
-    /// 触发事件
-    fn emit_event(&self, event: SessionEvent) {
-        if let Ok(callbacks) = self.event_callbacks.read() {
-            for callback in callbacks.iter() {
-                callback(event.clone());
+    /// 设置录制策略
+    #[cfg(feature = "recording")]
+    pub fn set_recording_policy(&mut self, policy: RecordingPolicy) {
+        self.recording_policy = policy;
+    }
+
+    /// 设置审计日志，录制相关的决定都会写入
+    pub fn set_audit_log(&self, log_manager: Arc<LogManager>) {
+        if let Ok(mut audit_log) = self.audit_log.write() {
+            *audit_log = Some(log_manager);
+        }
+    }
+
+    /// 写入审计日志
+    fn audit(&self, session_id: &str, message: &str, metadata: serde_json::Value) {
+        if let Ok(audit_log) = self.audit_log.read() {
+            if let Some(log_manager) = audit_log.as_ref() {
+                log_manager.log(
+                    LogEntry::new(LogLevel::Info, "audit", message)
+                        .with_session(session_id)
+                        .with_metadata(metadata),
+                );
             }
         }
+    }
+
+    /// 订阅会话事件，可按事件类型过滤
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<SessionEvent> {
+        self.events.subscribe(options)
+    }
+
+    /// 触发事件
+    fn emit_event(&self, event: SessionEvent) {
         tracing::info!("Session event: {:?}", event);
+        self.events.publish(event);
+    }
+
+    /// 推送会话的最新概况；尚未开始的会话在开始时才推送
+    fn emit_descriptor(&self, session_id: &str) {
+        let descriptor = self
+            .describe_session(session_id)
+            .filter(|descriptor| descriptor.status != SessionStatus::Pending);
+        if let Some(descriptor) = descriptor {
+            self.emit_event(SessionEvent::DescriptorChanged { descriptor });
+        }
+    }
+
+    /// 获取会话概况
+    pub fn describe_session(&self, session_id: &str) -> Option<ActiveSessionDescriptor> {
+        let sessions = self.active_sessions.read().ok()?;
+        sessions
+            .get(session_id)
+            .map(|session| session.descriptor(&self.local_device_id))
+    }
+
+    /// 获取所有活动会话的概况，按开始时间排序
+    pub fn session_descriptors(&self) -> Vec<ActiveSessionDescriptor> {
+        let mut descriptors: Vec<ActiveSessionDescriptor> = self
+            .active_sessions
+            .read()
+            .map(|sessions| {
+                sessions
+                    .values()
+                    .map(|session| session.descriptor(&self.local_device_id))
+                    .collect()
+            })
+            .unwrap_or_default();
+        descriptors.sort_by_key(|descriptor| descriptor.started_at);
+        descriptors
+    }
+
+    /// 记录已校验的对端身份
+    pub fn set_peer_identity(&self, session_id: &str, identity: PeerIdentity) -> Result<()> {
+        let mut sessions = self
+            .active_sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, session_id))?;
+        if session.peer_identity.as_ref() == Some(&identity) {
+            return Ok(());
+        }
+        session.peer_identity = Some(identity);
+        drop(sessions);
+
+        self.emit_descriptor(session_id);
+        Ok(())
     }
 
     /// 创建新会话
@@ -341,11 +787,21 @@ impl SessionManager {
         remote_id: String,
         options: SessionOptions,
     ) -> Result<Session> {
-        let session = Session::new(
-            self.local_device_id.clone(),
-            remote_id.clone(),
-            options.permissions,
-        );
+        let preferences = self
+            .device_directory
+            .read()
+            .ok()
+            .and_then(|directory| directory.preferences(&remote_id).cloned())
+            .unwrap_or_default()
+            .overridden_by(&options.preferences);
+        let mut permissions = options.permissions;
+        if !preferences.is_audio_enabled() {
+            permissions.retain(|p| *p != Permission::AudioCapture);
+        }
+
+        let mut session =
+            Session::new(self.local_device_id.clone(), remote_id.clone(), permissions);
+        session.preferences = preferences;
 
         let session_id = session.session_id.clone();
 
@@ -379,12 +835,126 @@ impl SessionManager {
             self.emit_event(SessionEvent::Started {
                 session_id: session_id.clone(),
             });
+            self.emit_descriptor(&session_id);
 
             tracing::info!("Joined session: {}", session_id);
             Ok(session_clone)
         } else {
-            Err(anyhow::anyhow!("Session not found: {}", session_id))
+            Err(ManagerError::not_found(ResourceKind::Session, session_id).into())
+        }
+    }
+
+    /// 会话中途授予或撤销权限，返回权限是否发生变化
+    pub fn set_session_permission(
+        &self,
+        session_id: &str,
+        permission: Permission,
+        granted: bool,
+    ) -> Result<bool> {
+        let mut sessions = self
+            .active_sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, session_id))?;
+
+        let has_permission = session.permissions.contains(&permission);
+        if has_permission == granted {
+            return Ok(false);
+        }
+        if granted {
+            session.permissions.push(permission.clone());
+        } else {
+            session.permissions.retain(|p| *p != permission);
+        }
+        drop(sessions);
+
+        self.audit(
+            session_id,
+            if granted {
+                "Session permission granted"
+            } else {
+                "Session permission revoked"
+            },
+            serde_json::json!({ "permission": permission }),
+        );
+        let revokes_privacy = permission == Permission::PrivacyMode && !granted;
+        self.emit_event(SessionEvent::PermissionChanged {
+            session_id: session_id.to_string(),
+            permission,
+            granted,
+        });
+        if revokes_privacy {
+            // 撤销权限时立即归还本地键鼠和屏幕
+            self.set_privacy_mode(session_id, None)?;
+        }
+        self.emit_descriptor(session_id);
+        Ok(true)
+    }
+
+    /// 开启、调整或关闭会话的隐私模式（`None` 或空选项为关闭）
+    ///
+    /// 开启需要会话处于进行中并拥有 `PrivacyMode` 权限；关闭总是允许。
+    /// 会话结束或权限被撤销时自动关闭。
+    pub fn set_privacy_mode(
+        &self,
+        session_id: &str,
+        options: Option<PrivacyOptions>,
+    ) -> Result<()> {
+        let options = options.filter(|options| !options.is_empty());
+        let mut sessions = self
+            .active_sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, session_id))?;
+        if session.privacy_mode == options {
+            return Ok(());
+        }
+        if let Some(options) = options {
+            if session.status != SessionStatus::Active {
+                return Err(ManagerError::invalid_state(
+                    ResourceKind::Session,
+                    session_id,
+                    format!("{:?}", session.status),
+                    "Active",
+                )
+                .into());
+            }
+            if !session.permissions.contains(&Permission::PrivacyMode) {
+                return Err(ManagerError::permission_denied(
+                    ResourceKind::Session,
+                    session_id,
+                    "privacy mode not granted",
+                )
+                .into());
+            }
+            self.privacy.engage(session_id, options)?;
+        } else {
+            self.privacy.release(session_id)?;
         }
+        session.privacy_mode = options;
+        drop(sessions);
+
+        self.audit(
+            session_id,
+            if options.is_some() {
+                "Privacy mode enabled"
+            } else {
+                "Privacy mode disabled"
+            },
+            serde_json::json!({ "options": options }),
+        );
+        self.emit_event(SessionEvent::PrivacyModeChanged {
+            session_id: session_id.to_string(),
+            options,
+        });
+        self.emit_descriptor(session_id);
+        Ok(())
     }
 
     /// 暂停会话
@@ -395,16 +965,30 @@ impl SessionManager {
             .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
 
         if let Some(session) = sessions.get_mut(session_id) {
+            if !matches!(
+                session.status,
+                SessionStatus::Active | SessionStatus::Paused
+            ) {
+                return Err(ManagerError::invalid_state(
+                    ResourceKind::Session,
+                    session_id,
+                    format!("{:?}", session.status),
+                    "Active",
+                )
+                .into());
+            }
             session.status = SessionStatus::Paused;
+            drop(sessions);
 
             self.emit_event(SessionEvent::Paused {
                 session_id: session_id.to_string(),
             });
+            self.emit_descriptor(session_id);
 
             tracing::info!("Paused session: {}", session_id);
             Ok(())
         } else {
-            Err(anyhow::anyhow!("Session not found: {}", session_id))
+            Err(ManagerError::not_found(ResourceKind::Session, session_id).into())
         }
     }
 
@@ -416,19 +1000,75 @@ impl SessionManager {
             .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
 
         if let Some(session) = sessions.get_mut(session_id) {
+            if !matches!(
+                session.status,
+                SessionStatus::Active | SessionStatus::Paused
+            ) {
+                return Err(ManagerError::invalid_state(
+                    ResourceKind::Session,
+                    session_id,
+                    format!("{:?}", session.status),
+                    "Paused",
+                )
+                .into());
+            }
             session.status = SessionStatus::Active;
+            drop(sessions);
 
             self.emit_event(SessionEvent::Resumed {
                 session_id: session_id.to_string(),
             });
+            self.emit_descriptor(session_id);
 
             tracing::info!("Resumed session: {}", session_id);
             Ok(())
         } else {
-            Err(anyhow::anyhow!("Session not found: {}", session_id))
+            Err(ManagerError::not_found(ResourceKind::Session, session_id).into())
         }
     }
 
+    /// 请求对端发送关键帧，用于画面出现花屏或从后台恢复后刷新
+    ///
+    /// 仅对进行中的会话有效。两次请求间隔不足
+    /// `KEYFRAME_REQUEST_MIN_INTERVAL_MS` 时忽略并计数，返回 false。
+    pub fn request_keyframe(&self, session_id: &str) -> Result<bool> {
+        let mut sessions = self
+            .active_sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, session_id))?;
+        if session.status != SessionStatus::Active {
+            return Err(ManagerError::invalid_state(
+                ResourceKind::Session,
+                session_id,
+                format!("{:?}", session.status),
+                "Active",
+            )
+            .into());
+        }
+
+        let now = self.clock.utc();
+        let min_interval = Duration::milliseconds(KEYFRAME_REQUEST_MIN_INTERVAL_MS as i64);
+        if session
+            .keyframe_requested_at
+            .is_some_and(|at| now - at < min_interval)
+        {
+            session.stats.keyframe_requests_throttled += 1;
+            return Ok(false);
+        }
+        session.keyframe_requested_at = Some(now);
+        session.stats.keyframe_requests += 1;
+        drop(sessions);
+
+        self.emit_event(SessionEvent::KeyframeRequested {
+            session_id: session_id.to_string(),
+        });
+        Ok(true)
+    }
+
     /// 结束会话
     pub fn end_session(&self, session_id: &str, reason: EndReason) -> Result<SessionRecord> {
         let mut sessions = self
@@ -438,7 +1078,7 @@ impl SessionManager {
 
         if let Some(mut session) = sessions.remove(session_id) {
             session.status = SessionStatus::Ended;
-            session.end_time = Some(Utc::now());
+            session.end_time = Some(self.clock.utc());
             session.stats.duration_secs = session.duration_secs();
 
             let record = SessionRecord {
@@ -450,10 +1090,18 @@ impl SessionManager {
                 duration_secs: session.stats.duration_secs,
                 end_reason: reason.clone(),
                 final_stats: session.stats.clone(),
+                geo_location: self.resolve_location(session.remote_ip.as_deref()),
+                remote_ip: session.remote_ip.clone(),
             };
 
             drop(sessions);
 
+            if session.privacy_mode.is_some() {
+                if let Err(e) = self.privacy.release(session_id) {
+                    tracing::error!("Failed to release privacy mode of {}: {}", session_id, e);
+                }
+            }
+
             // 添加到历史记录
             if let Ok(mut history) = self.session_history.write() {
                 history.push(record.clone());
@@ -469,13 +1117,48 @@ impl SessionManager {
             tracing::info!("Ended session: {}", session_id);
             Ok(record)
         } else {
-            Err(anyhow::anyhow!("Session not found: {}", session_id))
+            Err(ManagerError::not_found(ResourceKind::Session, session_id).into())
+        }
+    }
+
+    /// 记录远端的公网地址与连接类型
+    pub fn record_remote_endpoint(
+        &self,
+        session_id: &str,
+        remote_ip: IpAddr,
+        connection_type: ConnectionType,
+    ) -> Result<()> {
+        let mut sessions = self
+            .active_sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+
+        if let Some(session) = sessions.get_mut(session_id) {
+            session.remote_ip = Some(remote_ip.to_string());
+            let type_changed = session.stats.connection_type != connection_type;
+            session.stats.connection_type = connection_type;
+            drop(sessions);
+
+            tracing::debug!("Session {} remote endpoint: {}", session_id, remote_ip);
+            if type_changed {
+                self.emit_descriptor(session_id);
+            }
+            Ok(())
+        } else {
+            Err(ManagerError::not_found(ResourceKind::Session, session_id).into())
         }
     }
 
+    /// 解析远端 IP 的大致位置
+    fn resolve_location(&self, remote_ip: Option<&str>) -> Option<GeoLocation> {
+        let ip: IpAddr = remote_ip?.parse().ok()?;
+        let geoip = self.geoip.read().ok()?;
+        geoip.as_ref()?.lookup(ip)
+    }
+
     /// 清理过期的历史记录
     fn cleanup_old_records(&self, history: &mut Vec<SessionRecord>) {
-        let cutoff = Utc::now() - Duration::days(self.history_retention_days as i64);
+        let cutoff = self.clock.utc() - Duration::days(self.history_retention_days as i64);
         history.retain(|record| record.end_time > cutoff);
     }
 
@@ -496,20 +1179,68 @@ impl SessionManager {
         if let Some(session) = sessions.get_mut(session_id) {
             session.update_stats(latency, packet_loss, jitter, bytes_delta);
             let stats = session.stats.clone();
+            let peer_id = if session.controller_id == self.local_device_id {
+                session.controlled_id.clone()
+            } else {
+                session.controller_id.clone()
+            };
 
             drop(sessions);
 
+            if latency > 0 {
+                if let Ok(mut quality) = self.quality_history.write() {
+                    quality.record(
+                        &peer_id,
+                        chrono::Local::now(),
+                        latency,
+                        packet_loss,
+                        &stats.connection_quality,
+                    );
+                }
+            }
+
             self.emit_event(SessionEvent::StatsUpdated {
                 session_id: session_id.to_string(),
                 stats: stats.clone(),
             });
+            self.emit_descriptor(session_id);
 
             Ok(stats)
         } else {
-            Err(anyhow::anyhow!("Session not found: {}", session_id))
+            Err(ManagerError::not_found(ResourceKind::Session, session_id).into())
         }
     }
 
+    /// 更新接收端冻结统计
+    pub fn update_freeze_stats(&self, session_id: &str, freeze: &FreezeStats) -> Result<()> {
+        let mut sessions = self
+            .active_sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, session_id))?;
+        session.stats.freeze_count = freeze.freeze_count;
+        session.stats.total_freeze_ms = freeze.total_freeze_ms;
+        Ok(())
+    }
+
+    /// 更新文件传输计数
+    pub fn update_transfer_stats(&self, session_id: &str, files: u32, bytes: u64) -> Result<()> {
+        let mut sessions = self
+            .active_sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, session_id))?;
+        session.stats.files_transferred = files;
+        session.stats.file_bytes_transferred = bytes;
+        Ok(())
+    }
+
     /// 获取活动会话列表
     pub fn get_active_sessions(&self) -> Vec<Session> {
         self.active_sessions
@@ -529,7 +1260,7 @@ impl SessionManager {
     /// 获取会话历史记录
     pub fn get_session_history(&self, days: Option<u32>) -> Vec<SessionRecord> {
         let days = days.unwrap_or(self.history_retention_days);
-        let cutoff = Utc::now() - Duration::days(days as i64);
+        let cutoff = self.clock.utc() - Duration::days(days as i64);
 
         self.session_history
             .read()
@@ -543,6 +1274,275 @@ impl SessionManager {
             .unwrap_or_default()
     }
 
+    /// 获取与某设备的连接质量热力图（星期 × 小时）
+    pub fn get_quality_heatmap(&self, device_id: &str) -> QualityHeatmap {
+        self.quality_history
+            .read()
+            .map(|quality| quality.heatmap(device_id))
+            .unwrap_or_else(|_| QualityHistory::new().heatmap(device_id))
+    }
+
+    /// 将会话历史与质量聚合保存到文件
+    pub fn save_history(&self, path: &Path) -> Result<()> {
+        let file = SessionHistoryFile {
+            records: self.get_session_history(None),
+            quality: self
+                .quality_history
+                .read()
+                .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?
+                .clone(),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&file)?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// 从文件加载会话历史与质量聚合，替换当前内容
+    pub fn load_history(&self, path: &Path) -> Result<()> {
+        let mut file: SessionHistoryFile = serde_json::from_slice(&std::fs::read(path)?)?;
+        self.cleanup_old_records(&mut file.records);
+        *self
+            .session_history
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))? = file.records;
+        *self
+            .quality_history
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))? = file.quality;
+        Ok(())
+    }
+
+    /// 获取最近连接列表（最新在前），并标记新设备/新国家等异常
+    pub fn get_recent_connections(&self, limit: Option<usize>) -> Vec<RecentConnection> {
+        let mut history = self.get_session_history(None);
+        history.sort_by_key(|record| record.start_time);
+
+        let mut seen_devices = HashSet::new();
+        let mut seen_countries = HashSet::new();
+        let mut connections = Vec::with_capacity(history.len());
+
+        for record in history {
+            let remote_device_id = if record.controller_id == self.local_device_id {
+                record.controlled_id.clone()
+            } else {
+                record.controller_id.clone()
+            };
+
+            let mut anomalies = Vec::new();
+            if seen_devices.insert(remote_device_id.clone()) {
+                anomalies.push(ConnectionAnomaly::NewDevice);
+            }
+            if let Some(geo) = &record.geo_location {
+                let first_country = seen_countries.is_empty();
+                if seen_countries.insert(geo.country_code.clone()) && !first_country {
+                    anomalies.push(ConnectionAnomaly::NewCountry);
+                }
+            }
+
+            connections.push(RecentConnection {
+                session_id: record.session_id,
+                remote_device_id,
+                remote_ip: record.remote_ip,
+                connection_type: record.final_stats.connection_type,
+                geo_location: record.geo_location,
+                start_time: record.start_time,
+                end_time: record.end_time,
+                duration_secs: record.duration_secs,
+                end_reason: record.end_reason,
+                anomalies,
+            });
+        }
+
+        connections.reverse();
+        connections.truncate(limit.unwrap_or(connections.len()));
+        connections
+    }
+
+    /// 控制端请求开始录制
+    ///
+    /// 策略要求确认时进入等待确认状态，否则直接开始录制。
+    #[cfg(feature = "recording")]
+    pub fn request_recording(
+        &self,
+        session_id: &str,
+        requested_by: &str,
+        purpose: Option<String>,
+    ) -> Result<RecordingStatus> {
+        let mut sessions = self
+            .active_sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, session_id))?;
+
+        if matches!(
+            session.recording.status,
+            RecordingStatus::Recording | RecordingStatus::AwaitingConsent
+        ) {
+            return Err(ManagerError::invalid_state(
+                ResourceKind::Recording,
+                session_id,
+                format!("{:?}", session.recording.status),
+                "Idle",
+            )
+            .into());
+        }
+
+        let status = if self.recording_policy.require_host_consent {
+            RecordingStatus::AwaitingConsent
+        } else {
+            RecordingStatus::Recording
+        };
+
+        session.recording = RecordingState {
+            status,
+            requested_by: Some(requested_by.to_string()),
+            purpose: purpose.clone(),
+            updated_at: Some(Utc::now()),
+        };
+        drop(sessions);
+
+        self.audit(
+            session_id,
+            "Recording requested",
+            serde_json::json!({
+                "requested_by": requested_by,
+                "purpose": purpose,
+                "consent_required": self.recording_policy.require_host_consent,
+            }),
+        );
+
+        if status == RecordingStatus::Recording {
+            self.emit_event(SessionEvent::RecordingStarted {
+                session_id: session_id.to_string(),
+            });
+        } else {
+            self.emit_event(SessionEvent::RecordingRequested {
+                session_id: session_id.to_string(),
+                requested_by: requested_by.to_string(),
+                purpose,
+            });
+        }
+        self.emit_descriptor(session_id);
+
+        Ok(status)
+    }
+
+    /// 被控端用户同意或拒绝录制
+    #[cfg(feature = "recording")]
+    pub fn respond_to_recording(&self, session_id: &str, accept: bool) -> Result<RecordingStatus> {
+        let mut sessions = self
+            .active_sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, session_id))?;
+
+        if session.recording.status != RecordingStatus::AwaitingConsent {
+            return Err(ManagerError::invalid_state(
+                ResourceKind::Recording,
+                session_id,
+                format!("{:?}", session.recording.status),
+                "AwaitingConsent",
+            )
+            .into());
+        }
+
+        let status = if accept {
+            RecordingStatus::Recording
+        } else {
+            RecordingStatus::Refused
+        };
+        session.recording.status = status;
+        session.recording.updated_at = Some(Utc::now());
+        let requested_by = session.recording.requested_by.clone();
+        drop(sessions);
+
+        self.audit(
+            session_id,
+            if accept {
+                "Recording consent granted"
+            } else {
+                "Recording consent refused"
+            },
+            serde_json::json!({ "requested_by": requested_by }),
+        );
+
+        if accept {
+            self.emit_event(SessionEvent::RecordingStarted {
+                session_id: session_id.to_string(),
+            });
+        } else {
+            self.emit_event(SessionEvent::RecordingRefused {
+                session_id: session_id.to_string(),
+            });
+        }
+        self.emit_descriptor(session_id);
+
+        Ok(status)
+    }
+
+    /// 停止录制
+    #[cfg(feature = "recording")]
+    pub fn stop_recording(&self, session_id: &str) -> Result<()> {
+        let mut sessions = self
+            .active_sessions
+            .write()
+            .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
+
+        let session = sessions
+            .get_mut(session_id)
+            .ok_or_else(|| ManagerError::not_found(ResourceKind::Session, session_id))?;
+
+        if session.recording.status != RecordingStatus::Recording {
+            return Err(ManagerError::invalid_state(
+                ResourceKind::Recording,
+                session_id,
+                format!("{:?}", session.recording.status),
+                "Recording",
+            )
+            .into());
+        }
+
+        session.recording.status = RecordingStatus::Idle;
+        session.recording.updated_at = Some(Utc::now());
+        let requested_by = session.recording.requested_by.clone();
+        drop(sessions);
+
+        self.audit(
+            session_id,
+            "Recording stopped",
+            serde_json::json!({ "requested_by": requested_by }),
+        );
+        self.emit_event(SessionEvent::RecordingStopped {
+            session_id: session_id.to_string(),
+        });
+        self.emit_descriptor(session_id);
+        Ok(())
+    }
+
+    /// 当前是否允许录制（已开始且未被拒绝）
+    #[cfg(feature = "recording")]
+    pub fn is_recording_allowed(&self, session_id: &str) -> bool {
+        self.active_sessions
+            .read()
+            .ok()
+            .and_then(|sessions| {
+                sessions
+                    .get(session_id)
+                    .map(|s| s.recording.status == RecordingStatus::Recording)
+            })
+            .unwrap_or(false)
+    }
+
     /// 获取会话统计
     pub fn get_session_stats(&self, session_id: &str) -> Option<SessionStats> {
         self.active_sessions
@@ -557,8 +1557,12 @@ impl SessionManager {
         remote_id: String,
         permissions: Vec<Permission>,
     ) -> Result<String> {
-        let request =
-            PermissionRequest::new(self.local_device_id.clone(), remote_id, permissions.clone());
+        let request = PermissionRequest::new_at(
+            self.local_device_id.clone(),
+            remote_id,
+            permissions.clone(),
+            self.clock.utc(),
+        );
 
         let request_id = request.request_id.clone();
 
@@ -583,11 +1587,14 @@ impl SessionManager {
             .map_err(|_| anyhow::anyhow!("Failed to acquire lock"))?;
 
         if let Some(request) = requests.remove(request_id) {
-            if request.is_expired() {
-                return Err(anyhow::anyhow!(
-                    "Permission request expired: {}",
-                    request_id
-                ));
+            if request.is_expired_at(self.clock.utc()) {
+                return Err(ManagerError::invalid_state(
+                    ResourceKind::PermissionRequest,
+                    request_id,
+                    "Expired",
+                    "pending",
+                )
+                .into());
             }
 
             if grant {
@@ -604,21 +1611,19 @@ impl SessionManager {
 
             Ok(())
         } else {
-            Err(anyhow::anyhow!(
-                "Permission request not found: {}",
-                request_id
-            ))
+            Err(ManagerError::not_found(ResourceKind::PermissionRequest, request_id).into())
         }
     }
 
     /// 获取待处理的权限请求
     pub fn get_pending_requests(&self) -> Vec<PermissionRequest> {
+        let now = self.clock.utc();
         self.pending_requests
             .read()
             .map(|requests| {
                 requests
                     .values()
-                    .filter(|r| !r.is_expired())
+                    .filter(|r| !r.is_expired_at(now))
                     .cloned()
                     .collect()
             })
@@ -628,7 +1633,8 @@ impl SessionManager {
     /// 清理过期的权限请求
     pub fn cleanup_expired_requests(&self) {
         if let Ok(mut requests) = self.pending_requests.write() {
-            requests.retain(|_, request| !request.is_expired());
+            let now = self.clock.utc();
+            requests.retain(|_, request| !request.is_expired_at(now));
         }
     }
 
@@ -673,3 +1679,562 @@ impl Default for SessionManager {
         Self::new("default_device".to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_GEOIP_DB: &str = "\
+1.0.0.0,1.0.0.255,AU,Australia
+8.8.8.0,8.8.8.255,US,United States
+";
+
+    async fn run_session(manager: &SessionManager, remote_id: &str, ip: &str) -> SessionRecord {
+        let session = manager
+            .create_session(remote_id.to_string(), SessionOptions::default())
+            .await
+            .unwrap();
+        manager
+            .record_remote_endpoint(
+                &session.session_id,
+                ip.parse().unwrap(),
+                ConnectionType::Relay,
+            )
+            .unwrap();
+        manager
+            .end_session(&session.session_id, EndReason::UserRequested)
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_permission_request_expiry_uses_clock() {
+        let clock = crate::clock::TestClock::shared();
+        let manager = SessionManager::new("local".to_string()).with_clock(clock.clone());
+        let request_id = manager
+            .request_permission("remote".to_string(), vec![Permission::ScreenView])
+            .await
+            .unwrap();
+        assert_eq!(manager.get_pending_requests().len(), 1);
+
+        clock.advance(std::time::Duration::from_secs(5 * 60 + 1));
+        assert!(manager.get_pending_requests().is_empty());
+        assert!(manager.grant_permission(&request_id, true).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_session_record_includes_remote_endpoint() {
+        let manager = SessionManager::new("local".to_string());
+        manager.set_geoip_database(GeoIpDatabase::from_csv(TEST_GEOIP_DB).unwrap());
+
+        let record = run_session(&manager, "remote-1", "8.8.8.8").await;
+
+        assert_eq!(record.remote_ip, Some("8.8.8.8".to_string()));
+        assert_eq!(record.final_stats.connection_type, ConnectionType::Relay);
+        assert_eq!(record.geo_location.unwrap().country_code, "US");
+    }
+
+    #[tokio::test]
+    async fn test_recent_connections_anomaly_flags() {
+        let manager = SessionManager::new("local".to_string());
+        manager.set_geoip_database(GeoIpDatabase::from_csv(TEST_GEOIP_DB).unwrap());
+
+        run_session(&manager, "remote-1", "8.8.8.8").await;
+        run_session(&manager, "remote-1", "8.8.8.9").await;
+        run_session(&manager, "remote-1", "1.0.0.1").await;
+        run_session(&manager, "remote-2", "8.8.8.8").await;
+
+        let recent = manager.get_recent_connections(None);
+        assert_eq!(recent.len(), 4);
+
+        // Newest first
+        assert_eq!(recent[0].remote_device_id, "remote-2");
+        assert_eq!(recent[0].anomalies, vec![ConnectionAnomaly::NewDevice]);
+        assert_eq!(recent[1].anomalies, vec![ConnectionAnomaly::NewCountry]);
+        assert!(recent[2].anomalies.is_empty());
+        assert_eq!(recent[3].anomalies, vec![ConnectionAnomaly::NewDevice]);
+
+        assert_eq!(manager.get_recent_connections(Some(2)).len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_quality_heatmap_persists_with_history() {
+        let manager = SessionManager::new("local".to_string());
+        let session = manager
+            .create_session("remote-1".to_string(), SessionOptions::default())
+            .await
+            .unwrap();
+        manager
+            .update_session_stats(&session.session_id, 250, 6.0, 40, (0, 0))
+            .unwrap();
+        manager
+            .update_session_stats(&session.session_id, 30, 0.0, 5, (0, 0))
+            .unwrap();
+        manager
+            .end_session(&session.session_id, EndReason::UserRequested)
+            .unwrap();
+
+        let heatmap = manager.get_quality_heatmap("remote-1");
+        let samples: u32 = heatmap.cells.iter().flatten().map(|c| c.samples).sum();
+        assert_eq!(samples, 2);
+        assert!(manager
+            .get_quality_heatmap("local")
+            .cells
+            .iter()
+            .flatten()
+            .all(|c| c.samples == 0));
+
+        let path = std::env::temp_dir().join(format!("cec-history-{}.json", Uuid::new_v4()));
+        manager.save_history(&path).unwrap();
+        let restored = SessionManager::new("local".to_string());
+        restored.load_history(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(restored.get_session_history(None).len(), 1);
+        assert_eq!(
+            restored.get_quality_heatmap("remote-1"),
+            manager.get_quality_heatmap("remote-1")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filtered_event_subscriptions() {
+        let manager = SessionManager::new("local".to_string());
+        let mut all = manager.subscribe(SubscriptionOptions::all());
+        let mut ended = manager.subscribe(SubscriptionOptions::only(&["Ended"]));
+
+        let record = run_session(&manager, "remote-1", "8.8.8.8").await;
+
+        assert!(matches!(
+            all.recv().await,
+            Some(SessionEvent::Created { .. })
+        ));
+        assert!(matches!(all.recv().await, Some(SessionEvent::Ended { .. })));
+        match ended.recv().await {
+            Some(SessionEvent::Ended { session_id, .. }) => {
+                assert_eq!(session_id, record.session_id)
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(ended.try_recv().is_none());
+    }
+
+    #[cfg(feature = "recording")]
+    #[tokio::test]
+    async fn test_recording_requires_host_consent() {
+        let manager = SessionManager::new("local".to_string());
+        let audit_log = Arc::new(LogManager::default());
+        manager.set_audit_log(audit_log.clone());
+
+        let session = manager
+            .create_session("remote-1".to_string(), SessionOptions::default())
+            .await
+            .unwrap();
+        let id = &session.session_id;
+
+        let status = manager
+            .request_recording(id, "viewer", Some("support ticket".to_string()))
+            .unwrap();
+        assert_eq!(status, RecordingStatus::AwaitingConsent);
+        assert!(!manager.is_recording_allowed(id));
+
+        // Refusal blocks recording until a new request is made
+        manager.respond_to_recording(id, false).unwrap();
+        assert!(!manager.is_recording_allowed(id));
+        assert!(manager.stop_recording(id).is_err());
+
+        manager.request_recording(id, "viewer", None).unwrap();
+        manager.respond_to_recording(id, true).unwrap();
+        assert!(manager.is_recording_allowed(id));
+
+        manager.stop_recording(id).unwrap();
+        assert!(!manager.is_recording_allowed(id));
+
+        let audit: Vec<_> = audit_log
+            .get_logs(None, None)
+            .into_iter()
+            .filter(|e| e.category == "audit")
+            .collect();
+        assert_eq!(audit.len(), 5);
+    }
+
+    #[cfg(feature = "recording")]
+    #[tokio::test]
+    async fn test_recording_without_consent_policy() {
+        let mut manager = SessionManager::new("local".to_string());
+        manager.set_recording_policy(RecordingPolicy {
+            require_host_consent: false,
+        });
+
+        let session = manager
+            .create_session("remote-1".to_string(), SessionOptions::default())
+            .await
+            .unwrap();
+
+        let status = manager
+            .request_recording(&session.session_id, "viewer", None)
+            .unwrap();
+        assert_eq!(status, RecordingStatus::Recording);
+        assert!(manager
+            .respond_to_recording(&session.session_id, true)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_descriptor_tracks_host_side_session() {
+        let manager = SessionManager::new("host".to_string());
+        let mut updates = manager.subscribe(SubscriptionOptions::only(&["DescriptorChanged"]));
+        let session = manager
+            .create_session("viewer".to_string(), SessionOptions::default())
+            .await
+            .unwrap();
+        // Host side of the session: the viewer is the controller
+        manager
+            .active_sessions
+            .write()
+            .unwrap()
+            .get_mut(&session.session_id)
+            .unwrap()
+            .controller_id = "viewer".to_string();
+        let session_id = session.session_id.clone();
+
+        manager.join_session(session_id.clone()).await.unwrap();
+        let descriptor = match updates.recv().await {
+            Some(SessionEvent::DescriptorChanged { descriptor }) => descriptor,
+            other => panic!("unexpected event: {:?}", other),
+        };
+        assert_eq!(descriptor.local_role, SessionRole::Controlled);
+        assert_eq!(descriptor.peer, PeerIdentity::unverified("viewer"));
+        assert_eq!(descriptor.status, SessionStatus::Active);
+
+        let identity = PeerIdentity {
+            device_id: "viewer".to_string(),
+            name: Some("Alice's laptop".to_string()),
+            verified: true,
+            fingerprint: Some("ab12".to_string()),
+        };
+        manager
+            .set_peer_identity(&session_id, identity.clone())
+            .unwrap();
+        manager
+            .set_session_permission(&session_id, Permission::FileTransfer, true)
+            .unwrap();
+        manager
+            .record_remote_endpoint(
+                &session_id,
+                "8.8.8.8".parse().unwrap(),
+                ConnectionType::Relay,
+            )
+            .unwrap();
+
+        let mut latest = None;
+        while let Some(SessionEvent::DescriptorChanged { descriptor }) = updates.try_recv() {
+            latest = Some(descriptor);
+        }
+        let latest = latest.unwrap();
+        assert_eq!(latest.peer, identity);
+        assert!(latest.permissions.contains(&Permission::FileTransfer));
+        assert_eq!(latest.connection_type, ConnectionType::Relay);
+        assert_eq!(manager.session_descriptors(), vec![latest]);
+
+        // Re-recording the same identity is not a change
+        manager.set_peer_identity(&session_id, identity).unwrap();
+        assert!(updates.try_recv().is_none());
+    }
+
+    #[test]
+    fn test_stats_update_computes_bitrates() {
+        let mut session = Session::new("a".to_string(), "b".to_string(), Vec::new());
+        session.update_stats(20, 0.0, 2, (0, 0));
+        session.stats_sampled_at = Some(Utc::now() - Duration::seconds(2));
+        session.update_stats(20, 0.0, 2, (250_000, 500_000));
+        // 2s window, with a little slack for the time the test takes
+        assert!((990_000..=1_000_000).contains(&session.stats.send_bitrate_bps));
+        assert!((1_980_000..=2_000_000).contains(&session.stats.receive_bitrate_bps));
+        assert_eq!(session.stats.bytes_received, 500_000);
+    }
+
+    #[tokio::test]
+    async fn test_keyframe_requests_rate_limited() {
+        let manager = SessionManager::new("viewer".to_string());
+        let mut requests = manager.subscribe(SubscriptionOptions::only(&["KeyframeRequested"]));
+        let session = manager
+            .create_session("host".to_string(), SessionOptions::default())
+            .await
+            .unwrap();
+        let session_id = session.session_id.clone();
+        assert!(manager.request_keyframe(&session_id).is_err());
+
+        manager.join_session(session_id.clone()).await.unwrap();
+        assert!(manager.request_keyframe(&session_id).unwrap());
+        assert!(!manager.request_keyframe(&session_id).unwrap());
+        assert!(matches!(
+            requests.try_recv(),
+            Some(SessionEvent::KeyframeRequested { session_id: id }) if id == session_id
+        ));
+        assert!(requests.try_recv().is_none());
+
+        let stats = manager.get_session(&session_id).unwrap().stats;
+        assert_eq!(stats.keyframe_requests, 1);
+        assert_eq!(stats.keyframe_requests_throttled, 1);
+        assert!(manager.request_keyframe("missing").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_typed_errors_carry_current_state() {
+        let manager = SessionManager::new("viewer".to_string());
+        let session_id = manager
+            .create_session("host".to_string(), SessionOptions::default())
+            .await
+            .unwrap()
+            .session_id;
+
+        // Pending sessions cannot be paused yet
+        let err = manager.pause_session(&session_id).unwrap_err();
+        assert_eq!(
+            ManagerError::of(&err),
+            Some(&ManagerError::InvalidState {
+                kind: ResourceKind::Session,
+                id: session_id.clone(),
+                state: "Pending".to_string(),
+                expected: "Active".to_string(),
+            })
+        );
+
+        manager.join_session(session_id.clone()).await.unwrap();
+        manager.pause_session(&session_id).unwrap();
+        let err = manager.request_keyframe(&session_id).unwrap_err();
+        assert!(matches!(
+            ManagerError::of(&err),
+            Some(ManagerError::InvalidState { state, .. }) if state == "Paused"
+        ));
+        manager.resume_session(&session_id).unwrap();
+
+        manager
+            .end_session(&session_id, EndReason::UserRequested)
+            .unwrap();
+        let err = manager.resume_session(&session_id).unwrap_err();
+        assert_eq!(
+            ManagerError::of(&err),
+            Some(&ManagerError::not_found(ResourceKind::Session, &session_id))
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("Session not found: {}", session_id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_device_preferences_applied_with_session_overrides() {
+        use crate::decoder_capabilities::DecoderCodec;
+        use crate::performance::QualityPreset;
+
+        let manager = SessionManager::new("local".to_string());
+        {
+            let directory = manager.device_directory();
+            let mut directory = directory.write().unwrap();
+            directory.add("office", "Office PC");
+            assert!(directory.set_preferences(
+                "office",
+                DevicePreferences {
+                    codec_priority: vec![DecoderCodec::AV1, DecoderCodec::H264],
+                    quality_preset: Some(QualityPreset::High),
+                    relay_only: Some(true),
+                    audio_enabled: Some(false),
+                    max_bandwidth_kbps: Some(8000),
+                    ..Default::default()
+                },
+            ));
+            assert!(!directory.set_preferences("stranger", DevicePreferences::default()));
+        }
+
+        let options = SessionOptions {
+            permissions: vec![Permission::ScreenView, Permission::AudioCapture],
+            preferences: DevicePreferences {
+                quality_preset: Some(QualityPreset::Low),
+                relay_region: Some("eu-west".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let session = manager
+            .create_session("office".to_string(), options)
+            .await
+            .unwrap();
+        let applied = &session.preferences;
+        assert_eq!(applied.quality_preset, Some(QualityPreset::Low));
+        assert_eq!(applied.relay_region.as_deref(), Some("eu-west"));
+        assert_eq!(applied.codec_priority[0], DecoderCodec::AV1);
+        assert_eq!(applied.max_bandwidth_kbps, Some(8000));
+        assert_eq!(applied.ice_transport_policy(), "relay");
+        // Audio is off for this device
+        assert_eq!(session.permissions, vec![Permission::ScreenView]);
+
+        let other = manager
+            .create_session("laptop".to_string(), SessionOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(other.preferences, DevicePreferences::default());
+        assert!(other.preferences.is_audio_enabled());
+    }
+
+    #[tokio::test]
+    async fn test_privacy_mode_needs_permission_and_ends_with_session() {
+        use crate::privacy_mode::PrivacyBackend;
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct RecordingBackend {
+            calls: Mutex<Vec<(&'static str, bool)>>,
+        }
+
+        impl PrivacyBackend for RecordingBackend {
+            fn block_local_input(&self, blocked: bool) -> Result<()> {
+                self.calls.lock().unwrap().push(("input", blocked));
+                Ok(())
+            }
+
+            fn blank_screen(&self, blanked: bool) -> Result<()> {
+                self.calls.lock().unwrap().push(("screen", blanked));
+                Ok(())
+            }
+        }
+
+        let backend = Arc::new(RecordingBackend::default());
+        let manager = SessionManager::new("host".to_string())
+            .with_privacy_mode(Arc::new(PrivacyMode::with_backend(backend.clone())));
+        let mut events = manager.subscribe(SubscriptionOptions::only(&["PrivacyModeChanged"]));
+        let options = SessionOptions {
+            permissions: vec![Permission::ScreenView],
+            ..Default::default()
+        };
+        let first = manager
+            .create_session("viewer".to_string(), options.clone())
+            .await
+            .unwrap()
+            .session_id;
+        manager.join_session(first.clone()).await.unwrap();
+
+        let block = PrivacyOptions {
+            block_input: true,
+            ..Default::default()
+        };
+        let err = manager.set_privacy_mode(&first, Some(block)).unwrap_err();
+        assert!(matches!(
+            ManagerError::of(&err),
+            Some(ManagerError::PermissionDenied { .. })
+        ));
+        assert!(backend.calls.lock().unwrap().is_empty());
+
+        manager
+            .set_session_permission(&first, Permission::PrivacyMode, true)
+            .unwrap();
+        manager.set_privacy_mode(&first, Some(block)).unwrap();
+        assert_eq!(
+            manager.describe_session(&first).unwrap().privacy_mode,
+            Some(block)
+        );
+        assert!(matches!(
+            events.try_recv(),
+            Some(SessionEvent::PrivacyModeChanged {
+                options: Some(_),
+                ..
+            })
+        ));
+
+        // A second session blanking the screen adds to the first
+        let second = manager
+            .create_session("viewer".to_string(), options)
+            .await
+            .unwrap()
+            .session_id;
+        manager.join_session(second.clone()).await.unwrap();
+        manager
+            .set_session_permission(&second, Permission::PrivacyMode, true)
+            .unwrap();
+        let blank = PrivacyOptions {
+            blank_screen: true,
+            ..Default::default()
+        };
+        manager.set_privacy_mode(&second, Some(blank)).unwrap();
+        assert_eq!(
+            manager.privacy_mode().effective(),
+            PrivacyOptions {
+                block_input: true,
+                blank_screen: true,
+            }
+        );
+
+        // Ending the first session unblocks input, revoking the permission
+        // of the second unblanks the screen
+        manager
+            .end_session(&first, EndReason::UserRequested)
+            .unwrap();
+        assert_eq!(manager.privacy_mode().effective(), blank);
+        manager
+            .set_session_permission(&second, Permission::PrivacyMode, false)
+            .unwrap();
+        assert!(manager.privacy_mode().effective().is_empty());
+        assert_eq!(
+            manager.describe_session(&second).unwrap().privacy_mode,
+            None
+        );
+        assert_eq!(
+            *backend.calls.lock().unwrap(),
+            vec![
+                ("input", true),
+                ("screen", true),
+                ("input", false),
+                ("screen", false)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_privacy_mode_fails_closed_when_backend_fails() {
+        use crate::privacy_mode::PrivacyBackend;
+
+        struct RefusingBackend;
+
+        impl PrivacyBackend for RefusingBackend {
+            fn block_local_input(&self, blocked: bool) -> Result<()> {
+                if blocked {
+                    return Err(anyhow::anyhow!("Cannot grab input device"));
+                }
+                Ok(())
+            }
+
+            fn blank_screen(&self, _blanked: bool) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        let manager = SessionManager::new("host".to_string()).with_privacy_mode(Arc::new(
+            PrivacyMode::with_backend(Arc::new(RefusingBackend)),
+        ));
+        let options = SessionOptions {
+            permissions: vec![Permission::ScreenView, Permission::PrivacyMode],
+            ..Default::default()
+        };
+        let session_id = manager
+            .create_session("viewer".to_string(), options)
+            .await
+            .unwrap()
+            .session_id;
+        manager.join_session(session_id.clone()).await.unwrap();
+
+        let block = PrivacyOptions {
+            block_input: true,
+            ..Default::default()
+        };
+        let err = manager
+            .set_privacy_mode(&session_id, Some(block))
+            .unwrap_err();
+        assert!(err.to_string().contains("Cannot grab"), "{}", err);
+        assert!(manager.privacy_mode().effective().is_empty());
+        assert_eq!(
+            manager.describe_session(&session_id).unwrap().privacy_mode,
+            None
+        );
+        manager.set_privacy_mode(&session_id, None).unwrap();
+    }
+}
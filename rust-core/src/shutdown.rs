@@ -0,0 +1,354 @@
+//! Host Soft Shutdown
+//!
+//! Quitting the host while viewers are connected used to drop their
+//! connections without a word. `HostShutdown` first tells every viewer why
+//! the host is going away and how long they have, then waits out the
+//! countdown so running file transfers get a chance to finish. Whatever is
+//! still moving afterwards is paused, which persists its manifest for
+//! `resume_interrupted` on the next start. Sessions are then ended with
+//! `EndReason::HostShutdown`, the session history is written and the logs
+//! are flushed. A forced shutdown skips the countdown and the log upload.
+
+use crate::logging::LogManager;
+use crate::session_manager::{EndReason, SessionManager, SessionRole};
+use crate::signaling::SignalingClient;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "file-transfer")]
+use crate::file_transfer::{FileTransfer, TransferStatus};
+
+/// Time viewers get between the notice and the disconnect
+pub const DEFAULT_SHUTDOWN_COUNTDOWN: Duration = Duration::from_secs(10);
+
+/// How often the countdown checks whether there is anything left to wait for
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+const LOG_CATEGORY: &str = "shutdown";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ShutdownOptions {
+    /// Shown to the viewers
+    pub reason: String,
+    pub countdown: Duration,
+    /// Notify and exit immediately, without waiting or uploading logs
+    pub force: bool,
+}
+
+impl Default for ShutdownOptions {
+    fn default() -> Self {
+        Self {
+            reason: "Host is shutting down".to_string(),
+            countdown: DEFAULT_SHUTDOWN_COUNTDOWN,
+            force: false,
+        }
+    }
+}
+
+/// What a shutdown got done
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ShutdownReport {
+    pub sessions_notified: usize,
+    pub sessions_ended: usize,
+    /// Transfers that finished during the countdown
+    pub transfers_completed: usize,
+    /// Transfers paused with their progress persisted
+    pub transfers_checkpointed: usize,
+    pub history_saved: bool,
+    /// Log batches uploaded by the log shipper
+    pub log_batches_flushed: usize,
+    pub forced: bool,
+}
+
+/// Tears the host down without leaving viewers in the dark
+pub struct HostShutdown {
+    sessions: Arc<SessionManager>,
+    signaling: Option<Arc<SignalingClient>>,
+    #[cfg(feature = "file-transfer")]
+    transfers: Option<Arc<tokio::sync::Mutex<FileTransfer>>>,
+    logs: Option<Arc<LogManager>>,
+    history_path: Option<PathBuf>,
+}
+
+impl HostShutdown {
+    pub fn new(sessions: Arc<SessionManager>) -> Self {
+        Self {
+            sessions,
+            signaling: None,
+            #[cfg(feature = "file-transfer")]
+            transfers: None,
+            logs: None,
+            history_path: None,
+        }
+    }
+
+    /// Client used to notify the viewers
+    pub fn with_signaling(mut self, signaling: Arc<SignalingClient>) -> Self {
+        self.signaling = Some(signaling);
+        self
+    }
+
+    #[cfg(feature = "file-transfer")]
+    pub fn with_transfers(mut self, transfers: Arc<tokio::sync::Mutex<FileTransfer>>) -> Self {
+        self.transfers = Some(transfers);
+        self
+    }
+
+    pub fn with_log_manager(mut self, logs: Arc<LogManager>) -> Self {
+        self.logs = Some(logs);
+        self
+    }
+
+    /// Where to write the session history before exiting
+    pub fn with_history_path(mut self, path: PathBuf) -> Self {
+        self.history_path = Some(path);
+        self
+    }
+
+    /// Notify, wait, checkpoint and tear down
+    ///
+    /// Individual failures are logged and do not stop the shutdown.
+    pub async fn run(&self, options: ShutdownOptions) -> ShutdownReport {
+        let mut report = ShutdownReport {
+            forced: options.force,
+            ..ShutdownReport::default()
+        };
+        let countdown = if options.force {
+            Duration::ZERO
+        } else {
+            options.countdown
+        };
+        self.log_warn(&format!(
+            "Shutting down in {}s: {}",
+            countdown.as_secs(),
+            options.reason
+        ));
+
+        report.sessions_notified = self.notify_viewers(&options.reason, countdown).await;
+
+        #[cfg(feature = "file-transfer")]
+        let running = self.running_transfers().await;
+
+        let deadline = tokio::time::Instant::now() + countdown;
+        while tokio::time::Instant::now() < deadline && self.has_pending_work().await {
+            tokio::time::sleep(POLL_INTERVAL).await;
+        }
+
+        #[cfg(feature = "file-transfer")]
+        {
+            let (completed, checkpointed) = self.checkpoint_transfers(&running).await;
+            report.transfers_completed = completed;
+            report.transfers_checkpointed = checkpointed;
+        }
+
+        for session in self.sessions.get_active_sessions() {
+            match self
+                .sessions
+                .end_session(&session.session_id, EndReason::HostShutdown)
+            {
+                Ok(_) => report.sessions_ended += 1,
+                Err(e) => self.log_warn(&format!(
+                    "Failed to end session {}: {}",
+                    session.session_id, e
+                )),
+            }
+        }
+
+        if let Some(path) = &self.history_path {
+            match self.sessions.save_history(path) {
+                Ok(()) => report.history_saved = true,
+                Err(e) => self.log_warn(&format!("Failed to save session history: {}", e)),
+            }
+        }
+
+        if let Some(logs) = &self.logs {
+            logs.info(
+                LOG_CATEGORY,
+                &format!(
+                    "Shutdown complete: {} sessions ended, {} transfers checkpointed",
+                    report.sessions_ended, report.transfers_checkpointed
+                ),
+            );
+            #[cfg(feature = "log-shipping")]
+            if !options.force {
+                if let Some(shipper) = logs.log_shipper() {
+                    report.log_batches_flushed = shipper.flush().await;
+                }
+            }
+        }
+
+        report
+    }
+
+    /// Send the notice to the viewer of every session this device hosts
+    async fn notify_viewers(&self, reason: &str, countdown: Duration) -> usize {
+        let Some(signaling) = &self.signaling else {
+            return 0;
+        };
+        let countdown_secs = countdown.as_secs().min(u64::from(u32::MAX)) as u32;
+        let mut notified = 0;
+        for session in self.sessions.session_descriptors() {
+            if session.local_role != SessionRole::Controlled {
+                continue;
+            }
+            match signaling
+                .send_host_shutdown(
+                    &session.peer.device_id,
+                    &session.session_id,
+                    reason,
+                    countdown_secs,
+                )
+                .await
+            {
+                Ok(()) => notified += 1,
+                Err(e) => self.log_warn(&format!(
+                    "Failed to notify {} of shutdown: {}",
+                    session.peer.device_id, e
+                )),
+            }
+        }
+        notified
+    }
+
+    /// Sessions are still open or transfers still moving
+    async fn has_pending_work(&self) -> bool {
+        #[cfg(feature = "file-transfer")]
+        if !self.running_transfers().await.is_empty() {
+            return true;
+        }
+        !self.sessions.get_active_sessions().is_empty()
+    }
+
+    #[cfg(feature = "file-transfer")]
+    async fn running_transfers(&self) -> Vec<String> {
+        let Some(transfers) = &self.transfers else {
+            return Vec::new();
+        };
+        transfers
+            .lock()
+            .await
+            .get_active_transfers()
+            .into_iter()
+            .filter(|progress| is_running(&progress.status))
+            .map(|progress| progress.transfer_id.clone())
+            .collect()
+    }
+
+    /// Pause what is still running; returns (completed, checkpointed)
+    #[cfg(feature = "file-transfer")]
+    async fn checkpoint_transfers(&self, running: &[String]) -> (usize, usize) {
+        let Some(transfers) = &self.transfers else {
+            return (0, 0);
+        };
+        let mut transfers = transfers.lock().await;
+        let (mut completed, mut checkpointed) = (0, 0);
+        for transfer_id in running {
+            let status = transfers
+                .get_transfer_progress(transfer_id)
+                .map(|progress| progress.status.clone());
+            match status {
+                Some(TransferStatus::Completed) => completed += 1,
+                Some(status) if is_running(&status) => {
+                    match transfers.pause_transfer(transfer_id) {
+                        Ok(()) => checkpointed += 1,
+                        Err(e) => self.log_warn(&format!(
+                            "Failed to checkpoint transfer {}: {}",
+                            transfer_id, e
+                        )),
+                    }
+                }
+                _ => {}
+            }
+        }
+        (completed, checkpointed)
+    }
+
+    fn log_warn(&self, message: &str) {
+        match &self.logs {
+            Some(logs) => logs.warn(LOG_CATEGORY, message),
+            None => tracing::warn!("{}", message),
+        }
+    }
+}
+
+#[cfg(feature = "file-transfer")]
+fn is_running(status: &TransferStatus) -> bool {
+    matches!(status, TransferStatus::Pending | TransferStatus::InProgress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session_manager::SessionOptions;
+
+    #[tokio::test]
+    async fn test_forced_shutdown_ends_sessions_and_saves_history() {
+        let sessions = Arc::new(SessionManager::new("host".to_string()));
+        sessions
+            .create_session("viewer".to_string(), SessionOptions::default())
+            .await
+            .unwrap();
+        let history = std::env::temp_dir().join(format!(
+            "cecdesk-shutdown-history-{}.json",
+            std::process::id()
+        ));
+
+        let started = std::time::Instant::now();
+        let report = HostShutdown::new(Arc::clone(&sessions))
+            .with_history_path(history.clone())
+            .run(ShutdownOptions {
+                force: true,
+                ..ShutdownOptions::default()
+            })
+            .await;
+
+        assert!(started.elapsed() < DEFAULT_SHUTDOWN_COUNTDOWN);
+        assert!(report.forced);
+        assert_eq!(report.sessions_ended, 1);
+        assert!(report.history_saved);
+        assert!(sessions.get_active_sessions().is_empty());
+        let records = sessions.get_session_history(None);
+        assert_eq!(records[0].end_reason, EndReason::HostShutdown);
+        let _ = std::fs::remove_file(history);
+    }
+
+    #[cfg(feature = "file-transfer")]
+    #[tokio::test]
+    async fn test_countdown_checkpoints_unfinished_transfers() {
+        let sessions = Arc::new(SessionManager::new("host".to_string()));
+        let mut transfer = FileTransfer::new();
+        transfer
+            .start_receive(
+                "t1",
+                "viewer",
+                "report.pdf",
+                1024,
+                std::env::temp_dir().join("cecdesk-shutdown-report.pdf"),
+            )
+            .unwrap();
+        let transfers = Arc::new(tokio::sync::Mutex::new(transfer));
+
+        let report = HostShutdown::new(sessions)
+            .with_transfers(Arc::clone(&transfers))
+            .run(ShutdownOptions {
+                countdown: Duration::from_millis(250),
+                ..ShutdownOptions::default()
+            })
+            .await;
+
+        assert!(!report.forced);
+        assert_eq!(report.transfers_checkpointed, 1);
+        assert_eq!(report.transfers_completed, 0);
+        assert!(matches!(
+            transfers
+                .lock()
+                .await
+                .get_transfer_progress("t1")
+                .unwrap()
+                .status,
+            TransferStatus::Paused
+        ));
+    }
+}
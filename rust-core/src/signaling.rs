@@ -3,14 +3,27 @@
 //! Implements device registration, discovery, and WebRTC signaling exchange.
 //! Requirements: 4.1, 4.2, 4.3
 
+use crate::data_compression::CompressionAlgorithm;
+use crate::decoder_capabilities::DecoderCapabilities;
+use crate::encryption_status::EncryptionStatus;
+use crate::event_bus::{EventBus, EventType, Subscription, SubscriptionOptions};
+use crate::input_control::KeyboardLayout;
+use crate::metrics::{Counter, Gauge, MetricsRegistry};
+use crate::outbound_queue::{OutboundMessage, OutboundQueue, OutboundQueueConfig, SendOutcome};
+use crate::presence::{PresenceCache, MAX_STATUS_BATCH};
+use crate::security::{verify_message_signature, MessageSigner, SealedPayload, SignalingKeyPair};
+use crate::signaling_transport::{FrameStream, SignalingTransport, WebSocketTransport};
 use anyhow::{Context, Result};
 use futures_util::{SinkExt, StreamExt};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
-use tokio::sync::{mpsc, Mutex, RwLock};
-use tokio_tungstenite::{connect_async, tungstenite::Message};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
 use uuid::Uuid;
 
 /// Device information for registration
@@ -30,6 +43,18 @@ pub struct DeviceCapabilities {
     pub audio_capture: bool,
     pub file_transfer: bool,
     pub input_control: bool,
+    /// Video decoding limits, advertised by viewers
+    #[serde(default)]
+    pub decoder: Option<DecoderCapabilities>,
+    /// Active keyboard layout, advertised by hosts
+    #[serde(default)]
+    pub keyboard_layout: Option<KeyboardLayout>,
+    /// Host can share a single application with only that application's audio
+    #[serde(default)]
+    pub app_sharing: bool,
+    /// Data channel compression the device can decode, most preferred first
+    #[serde(default)]
+    pub data_compression: Vec<CompressionAlgorithm>,
 }
 
 /// Device online status
@@ -40,6 +65,343 @@ pub struct DeviceStatus {
     pub last_seen: String,
 }
 
+/// How long a status query waits for the server's reply
+pub const STATUS_QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a correlated request waits for the server's reply
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default time a store-and-forward message stays queued for an offline device
+pub const DEFAULT_MESSAGE_TTL_SECS: u64 = 300;
+
+/// How long a signed peer message may be acted on after it was signed,
+/// unless it was queued for an offline device with a longer TTL
+pub const SIGNED_MESSAGE_LIFETIME: Duration = Duration::from_secs(120);
+
+/// Unacknowledged heartbeats after which the connection is reported unhealthy
+pub const MAX_MISSED_HEARTBEATS: u32 = 3;
+
+/// Liveness of the signaling connection as seen by the heartbeat task
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeartbeatStatus {
+    /// Heartbeats sent since the last ack
+    pub missed: u32,
+    /// Round trip of the last acknowledged heartbeat
+    pub last_rtt_ms: Option<u64>,
+    pub healthy: bool,
+}
+
+#[derive(Debug, Default)]
+struct HeartbeatState {
+    outstanding: u32,
+    last_sent: Option<Instant>,
+    last_rtt: Option<Duration>,
+    unhealthy_reported: bool,
+}
+
+/// Tracks heartbeats in flight; shared by the heartbeat task and the reader
+#[derive(Debug)]
+struct HeartbeatMonitor {
+    max_missed: u32,
+    state: std::sync::Mutex<HeartbeatState>,
+}
+
+impl HeartbeatMonitor {
+    fn new(max_missed: u32) -> Self {
+        Self {
+            max_missed: max_missed.max(1),
+            state: std::sync::Mutex::new(HeartbeatState::default()),
+        }
+    }
+
+    /// Account a heartbeat about to be sent
+    ///
+    /// Returns the missed count the first time it reaches the limit, so the
+    /// caller reports the connection unhealthy once per outage.
+    fn on_send(&self, now: Instant) -> Option<u32> {
+        let mut state = self.state.lock().unwrap();
+        let missed = state.outstanding;
+        state.outstanding += 1;
+        state.last_sent = Some(now);
+        if missed >= self.max_missed && !state.unhealthy_reported {
+            state.unhealthy_reported = true;
+            return Some(missed);
+        }
+        None
+    }
+
+    fn on_ack(&self, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(sent) = state.last_sent {
+            state.last_rtt = Some(now.saturating_duration_since(sent));
+        }
+        state.outstanding = 0;
+        state.unhealthy_reported = false;
+    }
+
+    /// Forget heartbeats of a previous connection
+    fn reset(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.outstanding = 0;
+        state.last_sent = None;
+        state.unhealthy_reported = false;
+    }
+
+    fn status(&self) -> HeartbeatStatus {
+        let state = self.state.lock().unwrap();
+        // The heartbeat just sent is still in flight, not missed
+        let missed = state.outstanding.saturating_sub(1);
+        HeartbeatStatus {
+            missed,
+            last_rtt_ms: state.last_rtt.map(|rtt| rtt.as_millis() as u64),
+            healthy: missed < self.max_missed,
+        }
+    }
+}
+
+/// Requests waiting for the `Response` with their ID; shared by the
+/// client and the reader
+#[derive(Debug, Default)]
+struct PendingRequests {
+    waiting: std::sync::Mutex<HashMap<String, oneshot::Sender<SignalingMessage>>>,
+}
+
+impl PendingRequests {
+    /// Allocate a request ID and the receiver its reply will arrive on
+    fn register(&self) -> (String, oneshot::Receiver<SignalingMessage>) {
+        let request_id = Uuid::new_v4().to_string();
+        let (tx, rx) = oneshot::channel();
+        self.waiting.lock().unwrap().insert(request_id.clone(), tx);
+        (request_id, rx)
+    }
+
+    /// Hand `reply` to the request waiting for it; false if none is, e.g.
+    /// because it timed out
+    fn complete(&self, request_id: &str, reply: SignalingMessage) -> bool {
+        let waiter = self.waiting.lock().unwrap().remove(request_id);
+        waiter.is_some_and(|tx| tx.send(reply).is_ok())
+    }
+
+    fn forget(&self, request_id: &str) {
+        self.waiting.lock().unwrap().remove(request_id);
+    }
+
+    /// Fail every waiting request
+    fn cancel_all(&self) {
+        // Dropping the senders wakes the receivers with an error
+        self.waiting.lock().unwrap().clear();
+    }
+
+    fn len(&self) -> usize {
+        self.waiting.lock().unwrap().len()
+    }
+}
+
+/// How the client reconnects after the WebSocket drops
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectPolicy {
+    pub enabled: bool,
+    /// Delay before the first attempt
+    pub initial_delay_ms: u64,
+    /// Upper bound of the backoff
+    pub max_delay_ms: u64,
+    pub multiplier: f64,
+    /// Random spread of each delay, as a fraction of it (0.2 = ±20%)
+    pub jitter: f64,
+    /// Attempts before giving up; `None` retries forever
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            initial_delay_ms: 500,
+            max_delay_ms: 30_000,
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Backoff before the given attempt (1-based), without jitter
+    pub fn base_delay(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(32) as i32;
+        let delay = self.initial_delay_ms as f64 * self.multiplier.max(1.0).powi(exponent);
+        Duration::from_millis(delay.min(self.max_delay_ms as f64) as u64)
+    }
+
+    /// Backoff before the given attempt with jitter applied
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let base = self.base_delay(attempt).as_secs_f64();
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            rand::thread_rng().gen_range(1.0 - jitter..=1.0 + jitter)
+        } else {
+            1.0
+        };
+        Duration::from_secs_f64(base * factor)
+    }
+}
+
+/// Delivery state of a store-and-forward message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeliveryStatus {
+    /// Target was offline; message is held by the server until it expires
+    Queued,
+    /// Message was handed to the target device
+    Delivered,
+    /// TTL elapsed before the target came online
+    Expired,
+}
+
+/// Store-and-forward envelope for messages to possibly offline devices
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageEnvelope {
+    pub message_id: String,
+    pub from: String,
+    pub to: String,
+    /// Sender timestamp, Unix milliseconds
+    pub sent_at: i64,
+    pub ttl_secs: u64,
+    pub message: Box<SignalingMessage>,
+}
+
+impl MessageEnvelope {
+    pub fn new(from: String, to: String, ttl_secs: u64, message: SignalingMessage) -> Self {
+        Self {
+            message_id: Uuid::new_v4().to_string(),
+            from,
+            to,
+            sent_at: chrono::Utc::now().timestamp_millis(),
+            ttl_secs,
+            message: Box::new(message),
+        }
+    }
+
+    /// Unix milliseconds after which the message must not be acted on
+    pub fn expires_at(&self) -> i64 {
+        self.sent_at
+            .saturating_add((self.ttl_secs as i64).saturating_mul(1000))
+    }
+
+    /// Whether the envelope's TTL has elapsed at `now_ms`
+    pub fn is_expired_at(&self, now_ms: i64) -> bool {
+        now_ms > self.expires_at()
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(chrono::Utc::now().timestamp_millis())
+    }
+}
+
+/// Screen recording state change announced to the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RecordingAction {
+    Start,
+    Stop,
+}
+
+/// Nonces of accepted signed messages, kept until the messages expire
+#[derive(Debug, Default)]
+struct SeenNonces {
+    /// Expiry by (sender, nonce)
+    expiry: HashMap<(String, String), i64>,
+}
+
+impl SeenNonces {
+    /// Record a nonce, returning false if it was already accepted
+    fn insert(&mut self, from: &str, nonce: &str, expires_at: i64, now_ms: i64) -> bool {
+        self.expiry.retain(|_, expires| *expires >= now_ms);
+        self.expiry
+            .insert((from.to_string(), nonce.to_string()), expires_at)
+            .is_none()
+    }
+}
+
+/// Peer message signed with the sender's device certificate key
+///
+/// The signature covers the recipient, a nonce, the expiry and the
+/// serialized message, so it cannot be altered by the server, redirected to
+/// another device, or replayed once accepted or expired.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedMessage {
+    pub from: String,
+    pub to: String,
+    /// Unique per message; recipients accept each nonce once
+    pub nonce: String,
+    /// Unix milliseconds after which the message is refused
+    pub expires_at: i64,
+    /// The signed message, serialized
+    pub payload: String,
+    /// Sender certificate's key-exchange key, covered by its fingerprint
+    pub public_key: Vec<u8>,
+    pub verifying_key: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl SignedMessage {
+    fn sign(
+        signer: &MessageSigner,
+        from: &str,
+        to: &str,
+        message: &SignalingMessage,
+        lifetime: Duration,
+    ) -> Result<Self> {
+        let payload = serde_json::to_string(message)?;
+        let nonce = Uuid::new_v4().to_string();
+        let expires_at = chrono::Utc::now()
+            .timestamp_millis()
+            .saturating_add(lifetime.as_millis().min(i64::MAX as u128) as i64);
+        Ok(Self {
+            from: from.to_string(),
+            to: to.to_string(),
+            signature: signer.sign(&signing_input(to, &nonce, expires_at, &payload)),
+            nonce,
+            expires_at,
+            payload,
+            public_key: signer.public_key().to_vec(),
+            verifying_key: signer.verifying_key(),
+        })
+    }
+
+    /// Check the signature and unwrap the message, returning it with the
+    /// signer's certificate fingerprint
+    ///
+    /// `recipient` is this device's ID; messages signed for another device
+    /// or expired at `now_ms` are refused. Nonces are checked by the caller.
+    fn verify(&self, recipient: Option<&str>, now_ms: i64) -> Result<(SignalingMessage, String)> {
+        if recipient != Some(self.to.as_str()) {
+            return Err(anyhow::anyhow!(
+                "Signed message is addressed to {}",
+                self.to
+            ));
+        }
+        let fingerprint = verify_message_signature(
+            &self.public_key,
+            &self.verifying_key,
+            &signing_input(&self.to, &self.nonce, self.expires_at, &self.payload),
+            &self.signature,
+        )?;
+        if now_ms > self.expires_at {
+            return Err(anyhow::anyhow!("Signed message expired"));
+        }
+        let message: SignalingMessage =
+            serde_json::from_str(&self.payload).context("Invalid signed message")?;
+        if matches!(
+            message,
+            SignalingMessage::Signed(_) | SignalingMessage::Envelope(_)
+        ) || message_sender(&message) != Some(self.from.as_str())
+        {
+            return Err(anyhow::anyhow!("Signed message does not match its sender"));
+        }
+        Ok((message, fingerprint))
+    }
+}
+
 /// Signaling message types for WebSocket communication
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "payload")]
@@ -52,6 +414,16 @@ pub enum SignalingMessage {
     QueryStatus { device_id: String },
     /// Device status response
     StatusResponse(DeviceStatus),
+    /// Query the status of several devices at once
+    QueryStatusBatch { device_ids: Vec<String> },
+    /// Statuses for a batch query, in request order
+    StatusBatchResponse { statuses: Vec<DeviceStatus> },
+    /// Ask the server to push status changes for these devices
+    SubscribePresence { device_ids: Vec<String> },
+    /// Stop presence pushes for these devices
+    UnsubscribePresence { device_ids: Vec<String> },
+    /// Server push: a watched device came online or went away
+    PresenceUpdate(DeviceStatus),
     /// SDP Offer for WebRTC connection
     Offer {
         from: String,
@@ -81,6 +453,69 @@ pub enum SignalingMessage {
         to: String,
         accepted: bool,
     },
+    /// Viewer started or stopped recording the host screen
+    RecordingState {
+        from: String,
+        to: String,
+        session_id: String,
+        action: RecordingAction,
+        requested_by: String,
+        purpose: Option<String>,
+    },
+    /// Host consent decision for a recording request
+    RecordingConsent {
+        from: String,
+        to: String,
+        session_id: String,
+        accepted: bool,
+    },
+    /// Host keyboard layout changed during a session
+    KeyboardLayout {
+        from: String,
+        to: String,
+        session_id: String,
+        layout: KeyboardLayout,
+    },
+    /// Host is quitting; the session ends when the countdown runs out
+    HostShutdown {
+        from: String,
+        to: String,
+        session_id: String,
+        reason: String,
+        countdown_secs: u32,
+    },
+    /// Sender's view of a session's encryption, sent whenever it changes
+    EncryptionStatus {
+        from: String,
+        to: String,
+        status: EncryptionStatus,
+    },
+    /// Offer, answer or ICE candidate sealed to the recipient's signaling key
+    Sealed {
+        from: String,
+        to: String,
+        payload: SealedPayload,
+    },
+    /// Peer message signed by its sender
+    Signed(SignedMessage),
+    /// Message routed with store-and-forward semantics
+    Envelope(MessageEnvelope),
+    /// Server receipt for an envelope sent by this device
+    DeliveryReceipt {
+        message_id: String,
+        to: String,
+        status: DeliveryStatus,
+    },
+    /// Message the server answers with a `Response` carrying the same ID
+    Request {
+        request_id: String,
+        message: Box<SignalingMessage>,
+    },
+    /// Server reply to a `Request`
+    Response {
+        request_id: String,
+        message: Box<SignalingMessage>,
+    },
     /// Heartbeat to keep connection alive
     Heartbeat { device_id: String },
     /// Heartbeat acknowledgment
@@ -96,6 +531,10 @@ pub enum SignalingEvent {
     Connected,
     /// Disconnected from signaling server
     Disconnected,
+    /// Connection lost; waiting before reconnect attempt `attempt` (1-based)
+    Reconnecting { attempt: u32 },
+    /// `missed` heartbeats in a row went unacknowledged
+    ConnectionUnhealthy { missed: u32 },
     /// SDP Offer received from remote device
     OfferReceived { from: String, sdp: String },
     /// SDP Answer received from remote device
@@ -109,10 +548,87 @@ pub enum SignalingEvent {
     },
     /// Connection response received
     ConnectionResponse { from: String, accepted: bool },
+    /// Remote viewer changed its recording state
+    RecordingStateChanged {
+        from: String,
+        session_id: String,
+        action: RecordingAction,
+        requested_by: String,
+        purpose: Option<String>,
+    },
+    /// Host answered a recording request
+    RecordingConsentReceived {
+        from: String,
+        session_id: String,
+        accepted: bool,
+    },
+    /// Remote host switched keyboard layout
+    KeyboardLayoutChanged {
+        from: String,
+        session_id: String,
+        layout: KeyboardLayout,
+    },
+    /// Remote host announced that it is shutting down
+    HostShuttingDown {
+        from: String,
+        session_id: String,
+        reason: String,
+        countdown_secs: u32,
+    },
+    /// Peer reported its view of a session's encryption
+    EncryptionStatusReceived {
+        from: String,
+        status: EncryptionStatus,
+    },
+    /// Server reported the delivery state of a queued message
+    DeliveryReceipt {
+        message_id: String,
+        to: String,
+        status: DeliveryStatus,
+    },
+    /// A late-delivered message was dropped because its TTL had elapsed
+    StaleMessageRejected { message_id: String, from: String },
+    /// A session description was dropped because it was sent in the clear
+    /// or could not be opened with this device's signaling key, or an
+    /// envelope was dropped because its message names another sender
+    PayloadRejected { from: String, reason: String },
+    /// A peer message was dropped because it was unsigned, its signature
+    /// did not verify or it was signed by a certificate other than the
+    /// one pinned for its sender
+    SignatureRejected { from: String, reason: String },
+    /// A device's online status changed or was first reported
+    PresenceChanged(DeviceStatus),
     /// Error occurred
     Error { code: u32, message: String },
 }
 
+impl EventType for SignalingEvent {
+    fn event_type(&self) -> &'static str {
+        match self {
+            SignalingEvent::Connected => "Connected",
+            SignalingEvent::Disconnected => "Disconnected",
+            SignalingEvent::Reconnecting { .. } => "Reconnecting",
+            SignalingEvent::ConnectionUnhealthy { .. } => "ConnectionUnhealthy",
+            SignalingEvent::OfferReceived { .. } => "OfferReceived",
+            SignalingEvent::AnswerReceived { .. } => "AnswerReceived",
+            SignalingEvent::IceCandidateReceived { .. } => "IceCandidateReceived",
+            SignalingEvent::ConnectionRequest { .. } => "ConnectionRequest",
+            SignalingEvent::ConnectionResponse { .. } => "ConnectionResponse",
+            SignalingEvent::RecordingStateChanged { .. } => "RecordingStateChanged",
+            SignalingEvent::RecordingConsentReceived { .. } => "RecordingConsentReceived",
+            SignalingEvent::KeyboardLayoutChanged { .. } => "KeyboardLayoutChanged",
+            SignalingEvent::HostShuttingDown { .. } => "HostShuttingDown",
+            SignalingEvent::EncryptionStatusReceived { .. } => "EncryptionStatusReceived",
+            SignalingEvent::DeliveryReceipt { .. } => "DeliveryReceipt",
+            SignalingEvent::StaleMessageRejected { .. } => "StaleMessageRejected",
+            SignalingEvent::PayloadRejected { .. } => "PayloadRejected",
+            SignalingEvent::SignatureRejected { .. } => "SignatureRejected",
+            SignalingEvent::PresenceChanged(_) => "PresenceChanged",
+            SignalingEvent::Error { .. } => "Error",
+        }
+    }
+}
+
 /// Signaling exchange metrics for performance monitoring
 #[derive(Debug, Clone, Default)]
 pub struct SignalingMetrics {
@@ -130,6 +646,53 @@ pub struct SignalingMetrics {
     pub failed_exchanges: u64,
 }
 
+/// Lock-free counters behind `SignalingMetrics`, bumped from the I/O tasks
+#[derive(Clone)]
+struct SignalingCounters {
+    messages_sent: Arc<Counter>,
+    messages_received: Arc<Counter>,
+    successful_exchanges: Arc<Counter>,
+    failed_exchanges: Arc<Counter>,
+    /// Sum of exchange durations, for the average
+    exchange_ms_total: Arc<Counter>,
+    last_exchange_ms: Arc<Gauge>,
+}
+
+impl SignalingCounters {
+    fn new(registry: &MetricsRegistry) -> Self {
+        Self {
+            messages_sent: registry.counter("signaling.messages_sent"),
+            messages_received: registry.counter("signaling.messages_received"),
+            successful_exchanges: registry.counter("signaling.successful_exchanges"),
+            failed_exchanges: registry.counter("signaling.failed_exchanges"),
+            exchange_ms_total: registry.counter("signaling.exchange_ms_total"),
+            last_exchange_ms: registry.gauge("signaling.last_exchange_ms"),
+        }
+    }
+
+    fn record_exchange(&self, duration_ms: u64) {
+        self.last_exchange_ms.set(duration_ms);
+        self.exchange_ms_total.add(duration_ms);
+        self.successful_exchanges.increment();
+    }
+
+    fn snapshot(&self) -> SignalingMetrics {
+        let successful = self.successful_exchanges.get();
+        SignalingMetrics {
+            messages_sent: self.messages_sent.get(),
+            messages_received: self.messages_received.get(),
+            avg_rtt_ms: if successful == 0 {
+                0.0
+            } else {
+                self.exchange_ms_total.get() as f64 / successful as f64
+            },
+            last_exchange_duration_ms: self.last_exchange_ms.get(),
+            successful_exchanges: successful,
+            failed_exchanges: self.failed_exchanges.get(),
+        }
+    }
+}
+
 /// Internal state for tracking signaling exchanges
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -147,150 +710,697 @@ pub struct SignalingClient {
     server_url: String,
     /// Connection state
     connected: Arc<RwLock<bool>>,
-    /// Event sender for notifying listeners
-    event_sender: mpsc::UnboundedSender<SignalingEvent>,
-    /// Event receiver for consuming events
-    event_receiver: Arc<Mutex<mpsc::UnboundedReceiver<SignalingEvent>>>,
+    /// Event subscribers
+    events: EventBus<SignalingEvent>,
     /// Message sender for WebSocket
-    ws_sender: Arc<Mutex<Option<mpsc::UnboundedSender<SignalingMessage>>>>,
+    ws_sender: Arc<Mutex<Option<OutboundSender>>>,
     /// Registered devices cache
     registered_devices: Arc<RwLock<HashMap<String, DeviceInfo>>>,
+    /// Last known device statuses and the presence watch list
+    presence: Arc<RwLock<PresenceCache>>,
+    metrics_registry: Arc<MetricsRegistry>,
     /// Signaling metrics
-    metrics: Arc<RwLock<SignalingMetrics>>,
+    metrics: SignalingCounters,
     /// Pending signaling exchanges for timing
     pending_exchanges: Arc<RwLock<HashMap<String, SignalingExchange>>>,
+    reconnect_policy: ReconnectPolicy,
+    /// Last registration, repeated after every reconnect
+    registration: Arc<RwLock<Option<DeviceInfo>>>,
+    /// Messages sent while disconnected, flushed once connected
+    queue: Arc<Mutex<OutboundQueue>>,
+    reconnecting: Arc<AtomicBool>,
+    /// Bumped by `connect` and `disconnect` so tasks of an older
+    /// connection stop instead of reconnecting
+    generation: Arc<AtomicU64>,
+    heartbeat: Arc<HeartbeatMonitor>,
+    /// Background task started by `start_heartbeat`
+    heartbeat_task: std::sync::Mutex<Option<JoinHandle<()>>>,
+    requests: Arc<PendingRequests>,
+    request_timeout: Duration,
+    transport: Arc<dyn SignalingTransport>,
+    /// Set by `with_payload_encryption`
+    payload_keys: Option<Arc<SignalingKeyPair>>,
+    /// Signaling public keys of peers, by device ID
+    peer_keys: std::sync::RwLock<HashMap<String, [u8; 32]>>,
+    /// Set by `with_message_signing`
+    signer: Option<Arc<MessageSigner>>,
+    /// Pinned certificate fingerprints of peers, by device ID
+    peer_fingerprints: Arc<std::sync::RwLock<HashMap<String, String>>>,
+    /// Nonces of signed messages already accepted
+    seen_nonces: Arc<std::sync::Mutex<SeenNonces>>,
 }
 
-impl SignalingClient {
-    /// Create a new signaling client
-    pub fn new(server_url: String) -> Result<Self> {
-        let (event_sender, event_receiver) = mpsc::unbounded_channel();
-
-        Ok(Self {
-            device_id: Arc::new(RwLock::new(None)),
-            server_url,
-            connected: Arc::new(RwLock::new(false)),
-            event_sender,
-            event_receiver: Arc::new(Mutex::new(event_receiver)),
-            ws_sender: Arc::new(Mutex::new(None)),
-            registered_devices: Arc::new(RwLock::new(HashMap::new())),
-            metrics: Arc::new(RwLock::new(SignalingMetrics::default())),
-            pending_exchanges: Arc::new(RwLock::new(HashMap::new())),
-        })
-    }
-
-    /// Connect to the signaling server via WebSocket
-    /// Requirement 4.1: WebSocket protocol for real-time bidirectional communication
-    pub async fn connect(&self) -> Result<()> {
-        tracing::info!("Connecting to signaling server: {}", self.server_url);
+type OutboundSender = mpsc::UnboundedSender<OutboundMessage>;
 
-        let url = url::Url::parse(&self.server_url).context("Invalid signaling server URL")?;
+/// Clones of the client state shared with the connection tasks
+#[derive(Clone)]
+struct ConnectionContext {
+    server_url: String,
+    generation: u64,
+    current_generation: Arc<AtomicU64>,
+    connected: Arc<RwLock<bool>>,
+    events: EventBus<SignalingEvent>,
+    ws_sender: Arc<Mutex<Option<OutboundSender>>>,
+    device_id: Arc<RwLock<Option<String>>>,
+    registered_devices: Arc<RwLock<HashMap<String, DeviceInfo>>>,
+    presence: Arc<RwLock<PresenceCache>>,
+    metrics: SignalingCounters,
+    pending_exchanges: Arc<RwLock<HashMap<String, SignalingExchange>>>,
+    reconnect_policy: ReconnectPolicy,
+    registration: Arc<RwLock<Option<DeviceInfo>>>,
+    queue: Arc<Mutex<OutboundQueue>>,
+    reconnecting: Arc<AtomicBool>,
+    heartbeat: Arc<HeartbeatMonitor>,
+    requests: Arc<PendingRequests>,
+    transport: Arc<dyn SignalingTransport>,
+    payload_keys: Option<Arc<SignalingKeyPair>>,
+    /// Refuse unsigned peer messages
+    require_signatures: bool,
+    peer_fingerprints: Arc<std::sync::RwLock<HashMap<String, String>>>,
+    seen_nonces: Arc<std::sync::Mutex<SeenNonces>>,
+}
 
-        let (ws_stream, _) = connect_async(url)
-            .await
-            .context("Failed to connect to signaling server")?;
+impl ConnectionContext {
+    fn is_current(&self) -> bool {
+        self.current_generation.load(Ordering::SeqCst) == self.generation
+    }
 
-        let (mut write, mut read) = ws_stream.split();
+    /// Open a transport connection, start the writer and resume the session
+    ///
+    /// Resuming repeats the registration, renews presence subscriptions and
+    /// flushes messages queued while disconnected, in that order.
+    async fn open(&self) -> Result<FrameStream> {
+        let (mut write, read) = self.transport.connect(&self.server_url).await?;
 
         // Create channel for sending messages
-        let (tx, mut rx) = mpsc::unbounded_channel::<SignalingMessage>();
+        let (tx, mut rx) = mpsc::unbounded_channel::<OutboundMessage>();
 
-        // Store sender for later use
-        {
-            let mut ws_sender = self.ws_sender.lock().await;
-            *ws_sender = Some(tx);
+        let mut outgoing = Vec::new();
+        if let Some(info) = self.registration.read().await.clone() {
+            outgoing.push(SignalingMessage::Register(info).into());
         }
-
-        // Mark as connected
-        {
-            let mut connected = self.connected.write().await;
-            *connected = true;
+        let watched = self.presence.read().await.watched();
+        for chunk in watched.chunks(MAX_STATUS_BATCH) {
+            outgoing.push(
+                SignalingMessage::SubscribePresence {
+                    device_ids: chunk.to_vec(),
+                }
+                .into(),
+            );
+        }
+        if !watched.is_empty() {
+            self.presence.write().await.set_subscription_live(true);
         }
 
-        // Notify listeners
-        let _ = self.event_sender.send(SignalingEvent::Connected);
-
-        // Clone references for async tasks
-        let event_sender = self.event_sender.clone();
-        let connected = self.connected.clone();
-        let device_id = self.device_id.clone();
-        let registered_devices = self.registered_devices.clone();
-        let metrics = self.metrics.clone();
-        let pending_exchanges = self.pending_exchanges.clone();
+        self.heartbeat.reset();
+        // Held while flushing so no send slips in between the queue and the
+        // new sender
+        let mut ws_sender = self.ws_sender.lock().await;
+        let expired = {
+            let mut queue = self.queue.lock().await;
+            let expired = queue.expire(Instant::now());
+            outgoing.extend(queue.drain());
+            expired
+        };
+        complete_all(expired, SendOutcome::Expired);
+        for msg in outgoing {
+            // The receiver is alive until the writer task below exits
+            let _ = tx.send(msg);
+        }
+        *ws_sender = Some(tx);
+        drop(ws_sender);
+        *self.connected.write().await = true;
+        self.reconnecting.store(false, Ordering::SeqCst);
 
         // Spawn task to handle outgoing messages
+        let metrics = self.metrics.clone();
+        let queue = self.queue.clone();
+        let reconnect = self.reconnect_policy.enabled;
         tokio::spawn(async move {
             while let Some(msg) = rx.recv().await {
-                let json = match serde_json::to_string(&msg) {
+                let json = match serde_json::to_string(&msg.message) {
                     Ok(j) => j,
                     Err(e) => {
                         tracing::error!("Failed to serialize message: {}", e);
+                        msg.complete(SendOutcome::Failed);
                         continue;
                     }
                 };
 
-                if let Err(e) = write.send(Message::Text(json)).await {
+                if let Err(e) = write.send(json).await {
                     tracing::error!("Failed to send message: {}", e);
+                    rx.close();
+                    let mut unsent = vec![msg];
+                    while let Ok(msg) = rx.try_recv() {
+                        unsent.push(msg);
+                    }
+                    if !reconnect {
+                        complete_all(unsent, SendOutcome::Failed);
+                        break;
+                    }
+                    // Keep what was not sent for the next connection
+                    let mut dropped = Vec::new();
+                    {
+                        let mut queue = queue.lock().await;
+                        let now = Instant::now();
+                        dropped.extend(unsent.into_iter().filter_map(|m| queue.push(m, now)));
+                    }
+                    complete_all(dropped, SendOutcome::Dropped);
                     break;
                 }
 
-                // Update metrics
-                let mut m = metrics.write().await;
-                m.messages_sent += 1;
+                metrics.messages_sent.increment();
+                msg.complete(SendOutcome::Sent);
             }
         });
 
-        // Clone metrics for read task
-        let metrics = self.metrics.clone();
+        Ok(read)
+    }
 
-        // Spawn task to handle incoming messages
-        tokio::spawn(async move {
-            while let Some(msg_result) = read.next().await {
-                match msg_result {
-                    Ok(Message::Text(text)) => {
-                        // Update metrics
-                        {
-                            let mut m = metrics.write().await;
-                            m.messages_received += 1;
-                        }
+    /// Read until the connection drops, then reconnect per the policy
+    async fn supervise(self, mut read: FrameStream) {
+        loop {
+            self.read_messages(&mut read).await;
+            if !self.is_current() {
+                return;
+            }
 
-                        match serde_json::from_str::<SignalingMessage>(&text) {
-                            Ok(msg) => {
-                                Self::handle_message(
-                                    msg,
-                                    &event_sender,
-                                    &device_id,
-                                    &registered_devices,
-                                    &metrics,
-                                    &pending_exchanges,
-                                )
-                                .await;
-                            }
-                            Err(e) => {
-                                tracing::error!("Failed to parse message: {}", e);
-                            }
-                        }
-                    }
-                    Ok(Message::Close(_)) => {
-                        tracing::info!("WebSocket connection closed");
-                        break;
+            // Mark as disconnected
+            *self.ws_sender.lock().await = None;
+            *self.connected.write().await = false;
+            self.presence.write().await.set_subscription_live(false);
+            self.reconnecting
+                .store(self.reconnect_policy.enabled, Ordering::SeqCst);
+            self.events.publish(SignalingEvent::Disconnected);
+
+            match self.reconnect().await {
+                Some(reader) => read = reader,
+                None => {
+                    // Queued messages wait for a manual `connect` or expire
+                    self.reconnecting.store(false, Ordering::SeqCst);
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn reconnect(&self) -> Option<FrameStream> {
+        if !self.reconnect_policy.enabled {
+            return None;
+        }
+        let mut attempt = 0u32;
+        loop {
+            attempt += 1;
+            if self
+                .reconnect_policy
+                .max_attempts
+                .is_some_and(|max| attempt > max)
+            {
+                tracing::warn!("Giving up reconnecting after {} attempts", attempt - 1);
+                return None;
+            }
+            self.events
+                .publish(SignalingEvent::Reconnecting { attempt });
+            tokio::time::sleep(self.reconnect_policy.delay(attempt)).await;
+            if !self.is_current() {
+                return None;
+            }
+            match self.open().await {
+                Ok(read) => {
+                    tracing::info!("Reconnected to signaling server after {} attempts", attempt);
+                    self.events.publish(SignalingEvent::Connected);
+                    return Some(read);
+                }
+                Err(e) => tracing::warn!("Reconnect attempt {} failed: {:#}", attempt, e),
+            }
+        }
+    }
+
+    /// Unwrap signed peer messages addressed to `local_id` whose signature
+    /// verifies and whose certificate is the one pinned for the sender
+    ///
+    /// Expired and already accepted signed messages are refused. While
+    /// signing is on, unsigned peer messages and senders without a pinned
+    /// fingerprint are refused too. Errors carry the sender and the reason.
+    fn verify_signature(
+        &self,
+        msg: SignalingMessage,
+        local_id: Option<&str>,
+    ) -> std::result::Result<SignalingMessage, (String, String)> {
+        match msg {
+            SignalingMessage::Signed(signed) => {
+                let now_ms = chrono::Utc::now().timestamp_millis();
+                let (message, fingerprint) = signed
+                    .verify(local_id, now_ms)
+                    .map_err(|e| (signed.from.clone(), format!("{:#}", e)))?;
+                let pinned = self
+                    .peer_fingerprints
+                    .read()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .get(&signed.from)
+                    .cloned();
+                match pinned {
+                    Some(pinned) if pinned == fingerprint => {}
+                    Some(_) => {
+                        return Err((signed.from, "certificate fingerprint mismatch".to_string()))
                     }
-                    Err(e) => {
-                        tracing::error!("WebSocket error: {}", e);
-                        break;
+                    None if self.require_signatures => {
+                        return Err((signed.from, "no pinned certificate fingerprint".to_string()))
                     }
-                    _ => {}
+                    None => {}
+                }
+                // Recorded only once accepted, so forgeries cannot burn nonces
+                let fresh = self
+                    .seen_nonces
+                    .lock()
+                    .unwrap_or_else(std::sync::PoisonError::into_inner)
+                    .insert(&signed.from, &signed.nonce, signed.expires_at, now_ms);
+                if !fresh {
+                    return Err((signed.from, "signed message replayed".to_string()));
                 }
+                Ok(message)
             }
+            SignalingMessage::Envelope(mut envelope) => {
+                envelope.message = Box::new(self.verify_signature(*envelope.message, local_id)?);
+                Ok(SignalingMessage::Envelope(envelope))
+            }
+            msg => match message_sender(&msg) {
+                Some(from) if self.require_signatures => {
+                    Err((from.to_string(), "message is not signed".to_string()))
+                }
+                _ => Ok(msg),
+            },
+        }
+    }
 
-            // Mark as disconnected
-            {
-                let mut c = connected.write().await;
-                *c = false;
+    /// Unwrap sealed session descriptions, refusing plaintext ones while
+    /// payload encryption is on
+    ///
+    /// Errors carry the sender and the reason.
+    fn open_payload(
+        &self,
+        msg: SignalingMessage,
+    ) -> std::result::Result<SignalingMessage, (String, String)> {
+        match msg {
+            SignalingMessage::Sealed { from, to, payload } => {
+                let Some(keys) = &self.payload_keys else {
+                    return Err((from, "payload encryption is not enabled".to_string()));
+                };
+                open_sealed(keys, &from, &to, &payload).map_err(|e| (from, format!("{:#}", e)))
+            }
+            SignalingMessage::Envelope(mut envelope) => {
+                envelope.message = Box::new(self.open_payload(*envelope.message)?);
+                Ok(SignalingMessage::Envelope(envelope))
             }
+            msg => match session_description_route(&msg) {
+                Some((from, _)) if self.payload_keys.is_some() => Err((
+                    from.to_string(),
+                    "session description sent unencrypted".to_string(),
+                )),
+                _ => Ok(msg),
+            },
+        }
+    }
 
-            let _ = event_sender.send(SignalingEvent::Disconnected);
-        });
+    async fn read_messages(&self, read: &mut FrameStream) {
+        while let Some(msg_result) = read.next().await {
+            if !self.is_current() {
+                return;
+            }
+            match msg_result {
+                Ok(text) => {
+                    self.metrics.messages_received.increment();
+
+                    match serde_json::from_str::<SignalingMessage>(&text) {
+                        Ok(msg) => {
+                            // Replies are also handled like unsolicited
+                            // messages, so caches stay current
+                            let msg = match msg {
+                                SignalingMessage::Response {
+                                    request_id,
+                                    message,
+                                } => {
+                                    if !self.requests.complete(&request_id, (*message).clone()) {
+                                        tracing::debug!(
+                                            "Reply to unknown or expired request {}",
+                                            request_id
+                                        );
+                                    }
+                                    *message
+                                }
+                                msg => msg,
+                            };
+                            let local_id = self.device_id.read().await.clone();
+                            let msg = match self.verify_signature(msg, local_id.as_deref()) {
+                                Ok(msg) => msg,
+                                Err((from, reason)) => {
+                                    tracing::warn!("Rejected signature from {}: {}", from, reason);
+                                    self.events.publish(SignalingEvent::SignatureRejected {
+                                        from,
+                                        reason,
+                                    });
+                                    continue;
+                                }
+                            };
+                            let msg = match self.open_payload(msg) {
+                                Ok(msg) => msg,
+                                Err((from, reason)) => {
+                                    tracing::warn!("Rejected payload from {}: {}", from, reason);
+                                    self.events
+                                        .publish(SignalingEvent::PayloadRejected { from, reason });
+                                    continue;
+                                }
+                            };
+                            if matches!(msg, SignalingMessage::HeartbeatAck) {
+                                self.heartbeat.on_ack(Instant::now());
+                            }
+                            SignalingClient::handle_message(
+                                msg,
+                                &self.events,
+                                &self.device_id,
+                                &self.registered_devices,
+                                &self.presence,
+                                &self.metrics,
+                                &self.pending_exchanges,
+                            )
+                            .await;
+                        }
+                        Err(e) => {
+                            tracing::error!("Failed to parse message: {}", e);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Signaling connection failed: {:#}", e);
+                    return;
+                }
+            }
+        }
+        tracing::info!("Signaling connection closed");
+    }
+}
+
+/// Sender and recipient of an offer, answer or ICE candidate
+fn session_description_route(msg: &SignalingMessage) -> Option<(&str, &str)> {
+    match msg {
+        SignalingMessage::Offer { from, to, .. }
+        | SignalingMessage::Answer { from, to, .. }
+        | SignalingMessage::IceCandidate { from, to, .. } => Some((from, to)),
+        _ => None,
+    }
+}
+
+/// Device a peer message claims to come from
+fn message_sender(msg: &SignalingMessage) -> Option<&str> {
+    match msg {
+        SignalingMessage::Offer { from, .. }
+        | SignalingMessage::Answer { from, .. }
+        | SignalingMessage::IceCandidate { from, .. }
+        | SignalingMessage::ConnectionRequest { from, .. }
+        | SignalingMessage::ConnectionResponse { from, .. }
+        | SignalingMessage::RecordingState { from, .. }
+        | SignalingMessage::RecordingConsent { from, .. }
+        | SignalingMessage::KeyboardLayout { from, .. }
+        | SignalingMessage::HostShutdown { from, .. }
+        | SignalingMessage::EncryptionStatus { from, .. }
+        | SignalingMessage::Sealed { from, .. } => Some(from),
+        SignalingMessage::Signed(signed) => Some(&signed.from),
+        SignalingMessage::Envelope(envelope) => Some(&envelope.from),
+        _ => None,
+    }
+}
+
+/// Bytes a `SignedMessage` signature covers
+fn signing_input(to: &str, nonce: &str, expires_at: i64, payload: &str) -> Vec<u8> {
+    format!("{}\n{}\n{}\n{}", to, nonce, expires_at, payload).into_bytes()
+}
+
+/// Associated data binding a sealed payload to its route
+fn seal_context(from: &str, to: &str) -> Vec<u8> {
+    format!("{}\n{}", from, to).into_bytes()
+}
+
+fn open_sealed(
+    keys: &SignalingKeyPair,
+    from: &str,
+    to: &str,
+    payload: &SealedPayload,
+) -> Result<SignalingMessage> {
+    let plaintext = keys.open(payload, &seal_context(from, to))?;
+    let msg: SignalingMessage =
+        serde_json::from_slice(&plaintext).context("Invalid sealed message")?;
+    if session_description_route(&msg) != Some((from, to)) {
+        return Err(anyhow::anyhow!("Sealed message does not match its route"));
+    }
+    Ok(msg)
+}
+
+fn complete_all(messages: Vec<OutboundMessage>, outcome: SendOutcome) {
+    for message in messages {
+        message.complete(outcome);
+    }
+}
+
+impl SignalingClient {
+    /// Create a new signaling client
+    pub fn new(server_url: String) -> Result<Self> {
+        let metrics_registry = Arc::new(MetricsRegistry::new());
+        Ok(Self {
+            device_id: Arc::new(RwLock::new(None)),
+            server_url,
+            connected: Arc::new(RwLock::new(false)),
+            events: EventBus::new(),
+            ws_sender: Arc::new(Mutex::new(None)),
+            registered_devices: Arc::new(RwLock::new(HashMap::new())),
+            presence: Arc::new(RwLock::new(PresenceCache::default())),
+            metrics: SignalingCounters::new(&metrics_registry),
+            metrics_registry,
+            pending_exchanges: Arc::new(RwLock::new(HashMap::new())),
+            reconnect_policy: ReconnectPolicy::default(),
+            registration: Arc::new(RwLock::new(None)),
+            queue: Arc::new(Mutex::new(OutboundQueue::new(
+                OutboundQueueConfig::default(),
+            ))),
+            reconnecting: Arc::new(AtomicBool::new(false)),
+            generation: Arc::new(AtomicU64::new(0)),
+            heartbeat: Arc::new(HeartbeatMonitor::new(MAX_MISSED_HEARTBEATS)),
+            heartbeat_task: std::sync::Mutex::new(None),
+            requests: Arc::new(PendingRequests::default()),
+            request_timeout: REQUEST_TIMEOUT,
+            transport: Arc::new(WebSocketTransport),
+            payload_keys: None,
+            peer_keys: std::sync::RwLock::new(HashMap::new()),
+            signer: None,
+            peer_fingerprints: Arc::new(std::sync::RwLock::new(HashMap::new())),
+            seen_nonces: Arc::new(std::sync::Mutex::new(SeenNonces::default())),
+        })
+    }
+
+    /// Carry signaling over `transport` instead of a WebSocket
+    pub fn with_transport(mut self, transport: Arc<dyn SignalingTransport>) -> Self {
+        self.transport = transport;
+        self
+    }
+
+    /// Wait `timeout` instead of `REQUEST_TIMEOUT` for replies to requests
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = timeout;
+        self
+    }
+
+    /// Report the connection unhealthy after `max_missed` unacknowledged
+    /// heartbeats instead of `MAX_MISSED_HEARTBEATS`
+    pub fn with_max_missed_heartbeats(mut self, max_missed: u32) -> Self {
+        self.heartbeat = Arc::new(HeartbeatMonitor::new(max_missed));
+        self
+    }
+
+    /// Reconnect with `policy` when the connection drops
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// Hold messages sent while disconnected within `config`'s limits
+    pub fn with_outbound_queue(mut self, config: OutboundQueueConfig) -> Self {
+        self.queue = Arc::new(Mutex::new(OutboundQueue::new(config)));
+        self
+    }
+
+    /// Report counters into a shared registry instead of a private one
+    pub fn with_metrics_registry(mut self, registry: Arc<MetricsRegistry>) -> Self {
+        self.metrics = SignalingCounters::new(&registry);
+        self.metrics_registry = registry;
+        self
+    }
+
+    /// Seal offers, answers and ICE candidates to each peer's signaling key,
+    /// and accept them from peers only sealed to `keys`
+    ///
+    /// The signaling server then only sees who talks to whom. Sending to a
+    /// peer fails until its key is known through `set_peer_signaling_key`.
+    pub fn with_payload_encryption(mut self, keys: Arc<SignalingKeyPair>) -> Self {
+        self.payload_keys = Some(keys);
+        self
+    }
+
+    pub fn payload_encryption_enabled(&self) -> bool {
+        self.payload_keys.is_some()
+    }
+
+    /// Remember `device_id`'s signaling public key
+    ///
+    /// The key must come from a channel the server cannot tamper with,
+    /// such as LAN pairing, not from the server itself.
+    pub fn set_peer_signaling_key(&self, device_id: &str, public_key: &[u8]) -> Result<()> {
+        let key: [u8; 32] = public_key
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid signaling key length"))?;
+        self.peer_keys
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(device_id.to_string(), key);
+        Ok(())
+    }
+
+    pub fn remove_peer_signaling_key(&self, device_id: &str) {
+        self.peer_keys
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(device_id);
+    }
+
+    /// Seal a session description when payload encryption is on
+    fn seal_payload(&self, msg: SignalingMessage) -> Result<SignalingMessage> {
+        let Some((from, to)) = session_description_route(&msg) else {
+            return Ok(msg);
+        };
+        if self.payload_keys.is_none() {
+            return Ok(msg);
+        }
+        let peer_key = self
+            .peer_keys
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(to)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("No signaling key for device {}", to))?;
+        let payload = SignalingKeyPair::seal(
+            &peer_key,
+            &serde_json::to_vec(&msg)?,
+            &seal_context(from, to),
+        )?;
+        Ok(SignalingMessage::Sealed {
+            from: from.to_string(),
+            to: to.to_string(),
+            payload,
+        })
+    }
+
+    /// Sign offers, answers, ICE candidates and connection requests and
+    /// responses with `signer`, and accept them only signed by the
+    /// certificate pinned for their sender
+    pub fn with_message_signing(mut self, signer: Arc<MessageSigner>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    pub fn message_signing_enabled(&self) -> bool {
+        self.signer.is_some()
+    }
+
+    /// Pin the certificate fingerprint `device_id` must sign with
+    ///
+    /// As with signaling keys, the fingerprint must come from a channel the
+    /// server cannot tamper with.
+    pub fn set_peer_fingerprint(&self, device_id: &str, fingerprint: &str) {
+        self.peer_fingerprints
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .insert(device_id.to_string(), fingerprint.to_string());
+    }
+
+    pub fn remove_peer_fingerprint(&self, device_id: &str) {
+        self.peer_fingerprints
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .remove(device_id);
+    }
+
+    /// Seal and sign a message for `target_id` as configured
+    fn protect(&self, target_id: &str, msg: SignalingMessage) -> Result<SignalingMessage> {
+        self.protect_for(target_id, msg, SIGNED_MESSAGE_LIFETIME)
+    }
+
+    /// `protect`, with the signature valid for `lifetime`
+    fn protect_for(
+        &self,
+        target_id: &str,
+        msg: SignalingMessage,
+        lifetime: Duration,
+    ) -> Result<SignalingMessage> {
+        let msg = self.seal_payload(msg)?;
+        let Some(signer) = &self.signer else {
+            return Ok(msg);
+        };
+        match message_sender(&msg) {
+            Some(from) => Ok(SignalingMessage::Signed(SignedMessage::sign(
+                signer, from, target_id, &msg, lifetime,
+            )?)),
+            None => Ok(msg),
+        }
+    }
+
+    /// Registry holding this client's counters
+    pub fn metrics_registry(&self) -> Arc<MetricsRegistry> {
+        Arc::clone(&self.metrics_registry)
+    }
+
+    /// Connect to the signaling server over the client's transport, a
+    /// WebSocket unless `with_transport` chose another
+    /// Requirement 4.1: WebSocket protocol for real-time bidirectional communication
+    ///
+    /// If the connection later drops, the client reconnects according to its
+    /// `ReconnectPolicy`, announcing each attempt with
+    /// `SignalingEvent::Reconnecting`.
+    pub async fn connect(&self) -> Result<()> {
+        tracing::info!(
+            "Connecting to signaling server: {} ({})",
+            self.server_url,
+            self.transport.name()
+        );
+
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let context = ConnectionContext {
+            server_url: self.server_url.clone(),
+            generation,
+            current_generation: self.generation.clone(),
+            connected: self.connected.clone(),
+            events: self.events.clone(),
+            ws_sender: self.ws_sender.clone(),
+            device_id: self.device_id.clone(),
+            registered_devices: self.registered_devices.clone(),
+            presence: self.presence.clone(),
+            metrics: self.metrics.clone(),
+            pending_exchanges: self.pending_exchanges.clone(),
+            reconnect_policy: self.reconnect_policy,
+            registration: self.registration.clone(),
+            queue: self.queue.clone(),
+            reconnecting: self.reconnecting.clone(),
+            heartbeat: self.heartbeat.clone(),
+            requests: self.requests.clone(),
+            transport: self.transport.clone(),
+            payload_keys: self.payload_keys.clone(),
+            require_signatures: self.signer.is_some(),
+            peer_fingerprints: self.peer_fingerprints.clone(),
+            seen_nonces: self.seen_nonces.clone(),
+        };
+        let read = context.open().await?;
+
+        // Notify listeners
+        self.events.publish(SignalingEvent::Connected);
+
+        tokio::spawn(context.supervise(read));
 
         tracing::info!("Connected to signaling server");
         Ok(())
@@ -299,10 +1409,11 @@ impl SignalingClient {
     /// Handle incoming signaling message
     async fn handle_message(
         msg: SignalingMessage,
-        event_sender: &mpsc::UnboundedSender<SignalingEvent>,
+        events: &EventBus<SignalingEvent>,
         device_id: &Arc<RwLock<Option<String>>>,
         registered_devices: &Arc<RwLock<HashMap<String, DeviceInfo>>>,
-        metrics: &Arc<RwLock<SignalingMetrics>>,
+        presence: &Arc<RwLock<PresenceCache>>,
+        metrics: &SignalingCounters,
         pending_exchanges: &Arc<RwLock<HashMap<String, SignalingExchange>>>,
     ) {
         match msg {
@@ -319,8 +1430,19 @@ impl SignalingClient {
                 }
             }
 
-            SignalingMessage::StatusResponse(status) => {
+            SignalingMessage::StatusResponse(status) | SignalingMessage::PresenceUpdate(status) => {
                 tracing::debug!("Received device status: {:?}", status);
+                presence.write().await.update(status.clone());
+                events.publish(SignalingEvent::PresenceChanged(status));
+            }
+
+            SignalingMessage::StatusBatchResponse { statuses } => {
+                tracing::debug!("Received {} device statuses", statuses.len());
+                let mut cache = presence.write().await;
+                for status in statuses {
+                    cache.update(status.clone());
+                    events.publish(SignalingEvent::PresenceChanged(status));
+                }
             }
 
             SignalingMessage::Offer { from, sdp, .. } => {
@@ -338,7 +1460,7 @@ impl SignalingClient {
                     );
                 }
 
-                let _ = event_sender.send(SignalingEvent::OfferReceived { from, sdp });
+                events.publish(SignalingEvent::OfferReceived { from, sdp });
             }
 
             SignalingMessage::Answer { from, sdp, .. } => {
@@ -348,22 +1470,17 @@ impl SignalingClient {
                     let mut pending = pending_exchanges.write().await;
                     if let Some(exchange) = pending.remove(&exchange_key) {
                         let duration = exchange.start_time.elapsed().as_millis() as u64;
-                        let mut m = metrics.write().await;
-                        m.last_exchange_duration_ms = duration;
-                        m.successful_exchanges += 1;
-                        // Update average RTT
-                        let total = m.successful_exchanges as f64;
-                        m.avg_rtt_ms = (m.avg_rtt_ms * (total - 1.0) + duration as f64) / total;
+                        metrics.record_exchange(duration);
                     }
                 }
 
-                let _ = event_sender.send(SignalingEvent::AnswerReceived { from, sdp });
+                events.publish(SignalingEvent::AnswerReceived { from, sdp });
             }
 
             SignalingMessage::IceCandidate {
                 from, candidate, ..
             } => {
-                let _ = event_sender.send(SignalingEvent::IceCandidateReceived { from, candidate });
+                events.publish(SignalingEvent::IceCandidateReceived { from, candidate });
             }
 
             SignalingMessage::ConnectionRequest { from, device_info } => {
@@ -373,11 +1490,131 @@ impl SignalingClient {
                     devices.insert(from.clone(), device_info.clone());
                 }
 
-                let _ = event_sender.send(SignalingEvent::ConnectionRequest { from, device_info });
+                events.publish(SignalingEvent::ConnectionRequest { from, device_info });
             }
 
             SignalingMessage::ConnectionResponse { from, accepted, .. } => {
-                let _ = event_sender.send(SignalingEvent::ConnectionResponse { from, accepted });
+                events.publish(SignalingEvent::ConnectionResponse { from, accepted });
+            }
+
+            SignalingMessage::RecordingState {
+                from,
+                session_id,
+                action,
+                requested_by,
+                purpose,
+                ..
+            } => {
+                events.publish(SignalingEvent::RecordingStateChanged {
+                    from,
+                    session_id,
+                    action,
+                    requested_by,
+                    purpose,
+                });
+            }
+
+            SignalingMessage::RecordingConsent {
+                from,
+                session_id,
+                accepted,
+                ..
+            } => {
+                events.publish(SignalingEvent::RecordingConsentReceived {
+                    from,
+                    session_id,
+                    accepted,
+                });
+            }
+
+            SignalingMessage::KeyboardLayout {
+                from,
+                session_id,
+                layout,
+                ..
+            } => {
+                events.publish(SignalingEvent::KeyboardLayoutChanged {
+                    from,
+                    session_id,
+                    layout,
+                });
+            }
+
+            SignalingMessage::HostShutdown {
+                from,
+                session_id,
+                reason,
+                countdown_secs,
+                ..
+            } => {
+                tracing::warn!(
+                    "Host {} shutting down in {}s: {}",
+                    from,
+                    countdown_secs,
+                    reason
+                );
+                events.publish(SignalingEvent::HostShuttingDown {
+                    from,
+                    session_id,
+                    reason,
+                    countdown_secs,
+                });
+            }
+
+            SignalingMessage::EncryptionStatus { from, status, .. } => {
+                events.publish(SignalingEvent::EncryptionStatusReceived { from, status });
+            }
+
+            SignalingMessage::Envelope(envelope) => {
+                if envelope.is_expired() {
+                    tracing::warn!(
+                        "Rejecting stale message {} from {}",
+                        envelope.message_id,
+                        envelope.from
+                    );
+                    events.publish(SignalingEvent::StaleMessageRejected {
+                        message_id: envelope.message_id,
+                        from: envelope.from,
+                    });
+                    return;
+                }
+                // The server only checks the envelope's sender
+                if message_sender(&envelope.message) != Some(envelope.from.as_str()) {
+                    tracing::warn!(
+                        "Rejecting message {} from {} sent in another device's name",
+                        envelope.message_id,
+                        envelope.from
+                    );
+                    events.publish(SignalingEvent::PayloadRejected {
+                        from: envelope.from,
+                        reason: "Envelope sender does not match its message".to_string(),
+                    });
+                    return;
+                }
+
+                Box::pin(Self::handle_message(
+                    *envelope.message,
+                    events,
+                    device_id,
+                    registered_devices,
+                    presence,
+                    metrics,
+                    pending_exchanges,
+                ))
+                .await;
+            }
+
+            SignalingMessage::DeliveryReceipt {
+                message_id,
+                to,
+                status,
+            } => {
+                tracing::debug!("Message {} to {}: {:?}", message_id, to, status);
+                events.publish(SignalingEvent::DeliveryReceipt {
+                    message_id,
+                    to,
+                    status,
+                });
             }
 
             SignalingMessage::HeartbeatAck => {
@@ -386,7 +1623,7 @@ impl SignalingClient {
 
             SignalingMessage::Error { code, message } => {
                 tracing::error!("Signaling error {}: {}", code, message);
-                let _ = event_sender.send(SignalingEvent::Error { code, message });
+                events.publish(SignalingEvent::Error { code, message });
             }
 
             _ => {
@@ -399,6 +1636,13 @@ impl SignalingClient {
     pub async fn disconnect(&self) -> Result<()> {
         tracing::info!("Disconnecting from signaling server");
 
+        // Stop the connection tasks from reconnecting
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        self.reconnecting.store(false, Ordering::SeqCst);
+        let cancelled = self.queue.lock().await.drain();
+        complete_all(cancelled, SendOutcome::Cancelled);
+        self.requests.cancel_all();
+
         // Clear WebSocket sender to close connection
         {
             let mut ws_sender = self.ws_sender.lock().await;
@@ -406,9 +1650,10 @@ impl SignalingClient {
         }
 
         // Mark as disconnected
-        {
-            let mut connected = self.connected.write().await;
-            *connected = false;
+        let was_connected = std::mem::replace(&mut *self.connected.write().await, false);
+        if was_connected {
+            self.presence.write().await.set_subscription_live(false);
+            self.events.publish(SignalingEvent::Disconnected);
         }
 
         Ok(())
@@ -416,17 +1661,36 @@ impl SignalingClient {
 
     /// Register device with the signaling server
     /// Requirement 4.2: Register device and assign unique Device_ID
+    ///
+    /// Resolves with the device ID from the server's `RegisterResponse`.
     pub async fn register_device(&self, device_info: DeviceInfo) -> Result<String> {
         if !*self.connected.read().await {
             return Err(anyhow::anyhow!("Not connected to signaling server"));
         }
 
-        let msg = SignalingMessage::Register(device_info.clone());
-        self.send_message(msg).await?;
-
-        // For now, generate a local device ID if server doesn't respond
-        // In production, this would wait for RegisterResponse
-        let device_id = device_info.device_id.clone();
+        let reply = self
+            .request(SignalingMessage::Register(device_info.clone()))
+            .await
+            .context("Device registration failed")?;
+        let device_id = match reply {
+            SignalingMessage::RegisterResponse {
+                device_id,
+                success: true,
+            } => device_id,
+            SignalingMessage::RegisterResponse { success: false, .. } => {
+                return Err(anyhow::anyhow!(
+                    "Server rejected registration of {}",
+                    device_info.device_id
+                ));
+            }
+            other => {
+                return Err(anyhow::anyhow!(
+                    "Unexpected reply to registration: {:?}",
+                    other
+                ));
+            }
+        };
+        *self.registration.write().await = Some(device_info.clone());
 
         // Cache locally
         {
@@ -444,136 +1708,440 @@ impl SignalingClient {
         Ok(device_id)
     }
 
+    /// Send `message` and wait for the server's correlated reply
+    ///
+    /// Fails if no reply arrives within the request timeout, if the server
+    /// answers with `SignalingMessage::Error`, or on `disconnect`. A request
+    /// sent while disconnected is queued like any other message.
+    pub async fn request(&self, message: SignalingMessage) -> Result<SignalingMessage> {
+        self.request_with_timeout(message, self.request_timeout)
+            .await
+    }
+
+    /// `request` with its own timeout
+    pub async fn request_with_timeout(
+        &self,
+        message: SignalingMessage,
+        timeout: Duration,
+    ) -> Result<SignalingMessage> {
+        let (request_id, reply) = self.requests.register();
+        if let Err(e) = self
+            .send_message(SignalingMessage::Request {
+                request_id: request_id.clone(),
+                message: Box::new(message),
+            })
+            .await
+        {
+            self.requests.forget(&request_id);
+            return Err(e);
+        }
+
+        match tokio::time::timeout(timeout, reply).await {
+            Ok(Ok(SignalingMessage::Error { code, message })) => {
+                Err(anyhow::anyhow!("Server error {}: {}", code, message))
+            }
+            Ok(Ok(reply)) => Ok(reply),
+            Ok(Err(_)) => Err(anyhow::anyhow!(
+                "Disconnected before request {} was answered",
+                request_id
+            )),
+            Err(_) => {
+                self.requests.forget(&request_id);
+                Err(anyhow::anyhow!(
+                    "No reply to request {} within {:?}",
+                    request_id,
+                    timeout
+                ))
+            }
+        }
+    }
+
+    /// Requests still waiting for a reply
+    pub fn pending_requests(&self) -> usize {
+        self.requests.len()
+    }
+
     /// Query device status
+    ///
+    /// Answers from the presence cache while the entry is fresh; otherwise
+    /// asks the server and waits up to `STATUS_QUERY_TIMEOUT` for the reply.
     pub async fn query_device_status(&self, device_id: &str) -> Result<DeviceStatus> {
+        if let Some(status) = self.presence.read().await.fresh(device_id) {
+            return Ok(status.clone());
+        }
+
+        let ids = [device_id.to_string()];
+        let mut statuses = self.query_device_statuses(&ids).await?;
+        statuses
+            .pop()
+            .ok_or_else(|| anyhow::anyhow!("No status received for device {}", device_id))
+    }
+
+    /// Query the status of several devices, e.g. for the address book
+    ///
+    /// Only stale entries are requested, in batches of `MAX_STATUS_BATCH`.
+    /// Returns the known statuses in request order once every reply arrived
+    /// or the timeout elapsed; devices the server never reported are left out.
+    pub async fn query_device_statuses(&self, device_ids: &[String]) -> Result<Vec<DeviceStatus>> {
         if !*self.connected.read().await {
             return Err(anyhow::anyhow!("Not connected to signaling server"));
         }
 
-        let msg = SignalingMessage::QueryStatus {
-            device_id: device_id.to_string(),
+        let stale = self.presence.read().await.stale_ids(device_ids);
+        if !stale.is_empty() {
+            // Replies update the presence cache as they are read
+            let replies = futures::future::join_all(stale.chunks(MAX_STATUS_BATCH).map(|chunk| {
+                self.request_with_timeout(
+                    SignalingMessage::QueryStatusBatch {
+                        device_ids: chunk.to_vec(),
+                    },
+                    STATUS_QUERY_TIMEOUT,
+                )
+            }))
+            .await;
+            for reply in replies {
+                if let Err(e) = reply {
+                    tracing::warn!("Status query failed: {:#}", e);
+                }
+            }
+        }
+
+        let cache = self.presence.read().await;
+        Ok(device_ids
+            .iter()
+            .filter_map(|id| cache.get(id).cloned())
+            .collect())
+    }
+
+    /// Watch devices for presence changes pushed by the server
+    ///
+    /// The watch list survives reconnects and is renewed on `connect`.
+    pub async fn subscribe_presence(&self, device_ids: &[String]) -> Result<()> {
+        let added = self.presence.write().await.watch(device_ids);
+        if added.is_empty() || !*self.connected.read().await {
+            return Ok(());
+        }
+        self.send_presence_subscription(&added).await
+    }
+
+    /// Stop watching devices
+    pub async fn unsubscribe_presence(&self, device_ids: &[String]) -> Result<()> {
+        self.presence.write().await.unwatch(device_ids);
+        if !*self.connected.read().await {
+            return Ok(());
+        }
+        for chunk in device_ids.chunks(MAX_STATUS_BATCH) {
+            self.send_message(SignalingMessage::UnsubscribePresence {
+                device_ids: chunk.to_vec(),
+            })
+            .await?;
+        }
+        Ok(())
+    }
+
+    async fn send_presence_subscription(&self, device_ids: &[String]) -> Result<()> {
+        for chunk in device_ids.chunks(MAX_STATUS_BATCH) {
+            self.send_message(SignalingMessage::SubscribePresence {
+                device_ids: chunk.to_vec(),
+            })
+            .await?;
+        }
+        self.presence.write().await.set_subscription_live(true);
+        Ok(())
+    }
+
+    /// Last known status of a device, with whether it is stale
+    pub async fn cached_device_status(&self, device_id: &str) -> Option<(DeviceStatus, bool)> {
+        let cache = self.presence.read().await;
+        cache
+            .get(device_id)
+            .map(|status| (status.clone(), cache.is_stale(device_id)))
+    }
+
+    /// Send SDP offer to target device
+    /// Requirement 4.3: Forward SDP offer/answer
+    pub async fn send_offer(&self, target_id: &str, offer_sdp: &str) -> Result<()> {
+        let device_id = self
+            .get_device_id()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
+
+        let msg = self.protect(
+            target_id,
+            SignalingMessage::Offer {
+                from: device_id,
+                to: target_id.to_string(),
+                sdp: offer_sdp.to_string(),
+            },
+        )?;
+
+        // Track exchange start time
+        let exchange_key = format!("offer_{}", target_id);
+        {
+            let mut pending = self.pending_exchanges.write().await;
+            pending.insert(
+                exchange_key,
+                SignalingExchange {
+                    start_time: Instant::now(),
+                    exchange_type: "offer".to_string(),
+                    target_device: target_id.to_string(),
+                },
+            );
+        }
+
+        self.send_message(msg).await?;
+        tracing::info!("Sent offer to device: {}", target_id);
+        Ok(())
+    }
+
+    /// Send SDP answer to target device
+    /// Requirement 4.3: Forward SDP offer/answer
+    pub async fn send_answer(&self, target_id: &str, answer_sdp: &str) -> Result<()> {
+        let device_id = self
+            .get_device_id()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
+
+        let msg = SignalingMessage::Answer {
+            from: device_id,
+            to: target_id.to_string(),
+            sdp: answer_sdp.to_string(),
+        };
+
+        self.send_message(self.protect(target_id, msg)?).await?;
+        tracing::info!("Sent answer to device: {}", target_id);
+        Ok(())
+    }
+
+    /// Send ICE candidate to target device
+    /// Requirement 4.3: Forward ICE candidates
+    pub async fn send_ice_candidate(&self, target_id: &str, candidate: &str) -> Result<()> {
+        let device_id = self
+            .get_device_id()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
+
+        let msg = SignalingMessage::IceCandidate {
+            from: device_id,
+            to: target_id.to_string(),
+            candidate: candidate.to_string(),
+        };
+
+        self.send_message(self.protect(target_id, msg)?).await?;
+        tracing::debug!("Sent ICE candidate to device: {}", target_id);
+        Ok(())
+    }
+
+    /// Send connection request to target device
+    pub async fn send_connection_request(
+        &self,
+        target_id: &str,
+        device_info: DeviceInfo,
+    ) -> Result<()> {
+        let device_id = self
+            .get_device_id()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
+
+        let msg = SignalingMessage::ConnectionRequest {
+            from: device_id,
+            device_info,
+        };
+
+        self.send_message(self.protect(target_id, msg)?).await?;
+        tracing::info!("Sent connection request to device: {}", target_id);
+        Ok(())
+    }
+
+    /// Send a connection request that the server holds if the target is offline
+    ///
+    /// Returns the message ID used in delivery receipts.
+    pub async fn send_connection_request_queued(
+        &self,
+        target_id: &str,
+        device_info: DeviceInfo,
+        ttl_secs: u64,
+    ) -> Result<String> {
+        let device_id = self
+            .get_device_id()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
+
+        let envelope = MessageEnvelope::new(
+            device_id.clone(),
+            target_id.to_string(),
+            ttl_secs,
+            self.protect_for(
+                target_id,
+                SignalingMessage::ConnectionRequest {
+                    from: device_id,
+                    device_info,
+                },
+                SIGNED_MESSAGE_LIFETIME.max(Duration::from_secs(ttl_secs)),
+            )?,
+        );
+        let message_id = envelope.message_id.clone();
+
+        self.send_message(SignalingMessage::Envelope(envelope))
+            .await?;
+        tracing::info!(
+            "Sent queued connection request {} to device: {} (ttl {}s)",
+            message_id,
+            target_id,
+            ttl_secs
+        );
+        Ok(message_id)
+    }
+
+    /// Respond to connection request
+    pub async fn respond_to_connection(&self, target_id: &str, accepted: bool) -> Result<()> {
+        let device_id = self
+            .get_device_id()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
+
+        let msg = SignalingMessage::ConnectionResponse {
+            from: device_id,
+            to: target_id.to_string(),
+            accepted,
         };
-        self.send_message(msg).await?;
 
-        // Return cached status or default
-        Ok(DeviceStatus {
-            device_id: device_id.to_string(),
-            online: true,
-            last_seen: chrono::Utc::now().to_rfc3339(),
-        })
+        self.send_message(self.protect(target_id, msg)?).await?;
+        tracing::info!(
+            "Sent connection response to device: {} (accepted: {})",
+            target_id,
+            accepted
+        );
+        Ok(())
     }
 
-    /// Send SDP offer to target device
-    /// Requirement 4.3: Forward SDP offer/answer
-    pub async fn send_offer(&self, target_id: &str, offer_sdp: &str) -> Result<()> {
+    /// Notify the host that recording started or stopped
+    #[cfg(feature = "recording")]
+    pub async fn send_recording_state(
+        &self,
+        target_id: &str,
+        session_id: &str,
+        action: RecordingAction,
+        requested_by: &str,
+        purpose: Option<String>,
+    ) -> Result<()> {
         let device_id = self
             .get_device_id()
             .await
             .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
 
-        // Track exchange start time
-        let exchange_key = format!("offer_{}", target_id);
-        {
-            let mut pending = self.pending_exchanges.write().await;
-            pending.insert(
-                exchange_key,
-                SignalingExchange {
-                    start_time: Instant::now(),
-                    exchange_type: "offer".to_string(),
-                    target_device: target_id.to_string(),
-                },
-            );
-        }
-
-        let msg = SignalingMessage::Offer {
+        let msg = SignalingMessage::RecordingState {
             from: device_id,
             to: target_id.to_string(),
-            sdp: offer_sdp.to_string(),
+            session_id: session_id.to_string(),
+            action,
+            requested_by: requested_by.to_string(),
+            purpose,
         };
 
-        self.send_message(msg).await?;
-        tracing::info!("Sent offer to device: {}", target_id);
+        self.send_message(self.protect(target_id, msg)?).await?;
+        tracing::info!("Sent recording state {:?} to device: {}", action, target_id);
         Ok(())
     }
 
-    /// Send SDP answer to target device
-    /// Requirement 4.3: Forward SDP offer/answer
-    pub async fn send_answer(&self, target_id: &str, answer_sdp: &str) -> Result<()> {
+    /// Answer a recording request from the viewer
+    #[cfg(feature = "recording")]
+    pub async fn send_recording_consent(
+        &self,
+        target_id: &str,
+        session_id: &str,
+        accepted: bool,
+    ) -> Result<()> {
         let device_id = self
             .get_device_id()
             .await
             .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
 
-        let msg = SignalingMessage::Answer {
+        let msg = SignalingMessage::RecordingConsent {
             from: device_id,
             to: target_id.to_string(),
-            sdp: answer_sdp.to_string(),
+            session_id: session_id.to_string(),
+            accepted,
         };
 
-        self.send_message(msg).await?;
-        tracing::info!("Sent answer to device: {}", target_id);
+        self.send_message(self.protect(target_id, msg)?).await?;
+        tracing::info!(
+            "Sent recording consent to device: {} (accepted: {})",
+            target_id,
+            accepted
+        );
         Ok(())
     }
 
-    /// Send ICE candidate to target device
-    /// Requirement 4.3: Forward ICE candidates
-    pub async fn send_ice_candidate(&self, target_id: &str, candidate: &str) -> Result<()> {
+    /// Tell the controller that the host keyboard layout changed
+    pub async fn send_keyboard_layout(
+        &self,
+        target_id: &str,
+        session_id: &str,
+        layout: KeyboardLayout,
+    ) -> Result<()> {
         let device_id = self
             .get_device_id()
             .await
             .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
 
-        let msg = SignalingMessage::IceCandidate {
+        let msg = SignalingMessage::KeyboardLayout {
             from: device_id,
             to: target_id.to_string(),
-            candidate: candidate.to_string(),
+            session_id: session_id.to_string(),
+            layout,
         };
 
-        self.send_message(msg).await?;
-        tracing::debug!("Sent ICE candidate to device: {}", target_id);
+        self.send_message(self.protect(target_id, msg)?).await?;
+        tracing::info!("Sent keyboard layout {:?} to device: {}", layout, target_id);
         Ok(())
     }
 
-    /// Send connection request to target device
-    pub async fn send_connection_request(
+    /// Tell the peer how this side encrypts a session
+    pub async fn send_encryption_status(
         &self,
         target_id: &str,
-        device_info: DeviceInfo,
+        status: EncryptionStatus,
     ) -> Result<()> {
         let device_id = self
             .get_device_id()
             .await
             .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
 
-        let msg = SignalingMessage::ConnectionRequest {
+        let msg = SignalingMessage::EncryptionStatus {
             from: device_id,
-            device_info,
+            to: target_id.to_string(),
+            status,
         };
 
-        self.send_message(msg).await?;
-        tracing::info!("Sent connection request to device: {}", target_id);
+        self.send_message(self.protect(target_id, msg)?).await?;
+        tracing::debug!("Sent encryption status to device: {}", target_id);
         Ok(())
     }
 
-    /// Respond to connection request
-    pub async fn respond_to_connection(&self, target_id: &str, accepted: bool) -> Result<()> {
+    /// Warn a connected viewer that this host is about to quit
+    pub async fn send_host_shutdown(
+        &self,
+        target_id: &str,
+        session_id: &str,
+        reason: &str,
+        countdown_secs: u32,
+    ) -> Result<()> {
         let device_id = self
             .get_device_id()
             .await
             .ok_or_else(|| anyhow::anyhow!("Device not registered"))?;
 
-        let msg = SignalingMessage::ConnectionResponse {
+        let msg = SignalingMessage::HostShutdown {
             from: device_id,
             to: target_id.to_string(),
-            accepted,
+            session_id: session_id.to_string(),
+            reason: reason.to_string(),
+            countdown_secs,
         };
 
-        self.send_message(msg).await?;
-        tracing::info!(
-            "Sent connection response to device: {} (accepted: {})",
-            target_id,
-            accepted
-        );
+        self.send_message(self.protect(target_id, msg)?).await?;
+        tracing::info!("Sent shutdown notice to device: {}", target_id);
         Ok(())
     }
 
@@ -586,23 +2154,138 @@ impl SignalingClient {
 
         let msg = SignalingMessage::Heartbeat { device_id };
         self.send_message(msg).await?;
+        if let Some(missed) = self.heartbeat.on_send(Instant::now()) {
+            tracing::warn!("{} heartbeats unacknowledged", missed);
+            self.events
+                .publish(SignalingEvent::ConnectionUnhealthy { missed });
+        }
+        Ok(())
+    }
+
+    /// Send a heartbeat every `interval` in the background
+    ///
+    /// Ticks are skipped while disconnected or unregistered. Once
+    /// `MAX_MISSED_HEARTBEATS` (or the configured limit) heartbeats in a row
+    /// go unacknowledged, `SignalingEvent::ConnectionUnhealthy` is published;
+    /// an ack re-arms it. Calling this again replaces the running task.
+    pub fn start_heartbeat(&self, interval: Duration) -> Result<()> {
+        if interval.is_zero() {
+            return Err(anyhow::anyhow!("Heartbeat interval must be non-zero"));
+        }
+
+        let connected = self.connected.clone();
+        let device_id = self.device_id.clone();
+        let ws_sender = self.ws_sender.clone();
+        let events = self.events.clone();
+        let heartbeat = self.heartbeat.clone();
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if !*connected.read().await {
+                    continue;
+                }
+                let Some(id) = device_id.read().await.clone() else {
+                    continue;
+                };
+                let ws_sender = ws_sender.lock().await;
+                let Some(sender) = ws_sender.as_ref() else {
+                    continue;
+                };
+                if let Some(missed) = heartbeat.on_send(Instant::now()) {
+                    tracing::warn!("{} heartbeats unacknowledged", missed);
+                    events.publish(SignalingEvent::ConnectionUnhealthy { missed });
+                }
+                let _ = sender.send(SignalingMessage::Heartbeat { device_id: id }.into());
+            }
+        });
+
+        if let Some(previous) = self.heartbeat_task.lock().unwrap().replace(task) {
+            previous.abort();
+        }
         Ok(())
     }
 
+    /// Stop the task started by `start_heartbeat`
+    pub fn stop_heartbeat(&self) {
+        if let Some(task) = self.heartbeat_task.lock().unwrap().take() {
+            task.abort();
+        }
+    }
+
+    /// Missed acks and last round trip of the heartbeats
+    pub fn heartbeat_status(&self) -> HeartbeatStatus {
+        self.heartbeat.status()
+    }
+
+    /// Send `msg` and report its fate to `on_delivery`
+    ///
+    /// The callback is called exactly once: with `SendOutcome::Sent` once
+    /// the message is written to the WebSocket, or with the reason it was
+    /// not. While disconnected the message waits in the outbound queue; an
+    /// expired message is reported when the queue is next used.
+    pub async fn send_with_callback(
+        &self,
+        msg: SignalingMessage,
+        on_delivery: impl FnOnce(SendOutcome) + Send + 'static,
+    ) -> Result<()> {
+        self.send_outbound(OutboundMessage::new(msg).with_callback(on_delivery))
+            .await
+    }
+
+    /// Messages waiting for the connection
+    pub async fn queued_messages(&self) -> usize {
+        self.queue.lock().await.len()
+    }
+
     /// Internal method to send a signaling message
+    ///
+    /// While disconnected the message is queued and flushed once a
+    /// connection is open.
     async fn send_message(&self, msg: SignalingMessage) -> Result<()> {
+        self.send_outbound(msg.into()).await
+    }
+
+    async fn send_outbound(&self, msg: OutboundMessage) -> Result<()> {
         let ws_sender = self.ws_sender.lock().await;
+        let msg = match ws_sender.as_ref() {
+            Some(sender) => match sender.send(msg) {
+                Ok(()) => return Ok(()),
+                // The writer stopped; the connection is going down
+                Err(mpsc::error::SendError(msg)) => msg,
+            },
+            None => msg,
+        };
 
-        if let Some(sender) = ws_sender.as_ref() {
-            sender
-                .send(msg)
-                .map_err(|_| anyhow::anyhow!("Failed to send message"))?;
-            Ok(())
-        } else {
-            Err(anyhow::anyhow!("WebSocket not connected"))
+        let (expired, dropped, disabled) = {
+            let mut queue = self.queue.lock().await;
+            let now = Instant::now();
+            let disabled = queue.config().max_messages == 0;
+            (queue.expire(now), queue.push(msg, now), disabled)
+        };
+        drop(ws_sender);
+        complete_all(expired, SendOutcome::Expired);
+
+        match dropped {
+            Some(rejected) if disabled => {
+                rejected.complete(SendOutcome::Dropped);
+                Err(anyhow::anyhow!("WebSocket not connected"))
+            }
+            Some(oldest) => {
+                tracing::warn!("Outbound queue full; dropped oldest message");
+                oldest.complete(SendOutcome::Dropped);
+                Ok(())
+            }
+            None => Ok(()),
         }
     }
 
+    /// Whether the connection dropped and is being re-established
+    pub fn is_reconnecting(&self) -> bool {
+        self.reconnecting.load(Ordering::SeqCst)
+    }
+
     /// Get the device ID
     pub async fn get_device_id(&self) -> Option<String> {
         self.device_id.read().await.clone()
@@ -615,15 +2298,16 @@ impl SignalingClient {
 
     /// Get signaling metrics
     pub async fn get_metrics(&self) -> SignalingMetrics {
-        self.metrics.read().await.clone()
+        self.metrics.snapshot()
     }
 
-    /// Get event receiver for consuming signaling events
-    pub async fn take_event_receiver(&self) -> mpsc::UnboundedReceiver<SignalingEvent> {
-        let mut receiver = self.event_receiver.lock().await;
-        let (_new_sender, new_receiver) = mpsc::unbounded_channel();
-        // Note: In production, we'd need a better way to handle multiple consumers
-        std::mem::replace(&mut *receiver, new_receiver)
+    /// Subscribe to signaling events
+    ///
+    /// Any number of consumers (the FFI layer, the session manager,
+    /// diagnostics) may subscribe at once; each gets its own queue, and
+    /// subscribing or dropping a subscription never affects the others.
+    pub fn subscribe(&self, options: SubscriptionOptions) -> Subscription<SignalingEvent> {
+        self.events.subscribe(options)
     }
 
     /// Get cached device info
@@ -632,6 +2316,12 @@ impl SignalingClient {
     }
 }
 
+impl Drop for SignalingClient {
+    fn drop(&mut self) {
+        self.stop_heartbeat();
+    }
+}
+
 /// Generate a unique device ID
 /// Requirement 5.1: Generate unique Device_ID for each device
 pub fn generate_device_id() -> String {
@@ -641,6 +2331,854 @@ pub fn generate_device_id() -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::security::SecurityManager;
+    use crate::signaling_mock::MockTransport;
+    use tokio_tungstenite::tungstenite::Message;
+
+    #[tokio::test]
+    async fn test_stale_envelope_rejected() {
+        let events = EventBus::new();
+        let mut subscription = events.subscribe(SubscriptionOptions::all());
+        let device_id = Arc::new(RwLock::new(None));
+        let registered_devices = Arc::new(RwLock::new(HashMap::new()));
+        let presence = Arc::new(RwLock::new(PresenceCache::default()));
+        let metrics = SignalingCounters::new(&MetricsRegistry::new());
+        let pending_exchanges = Arc::new(RwLock::new(HashMap::new()));
+
+        let request = SignalingMessage::ConnectionRequest {
+            from: "controller".to_string(),
+            device_info: DeviceInfo {
+                device_id: "controller".to_string(),
+                device_name: "Controller".to_string(),
+                platform: "linux".to_string(),
+                version: "1.0.0".to_string(),
+                capabilities: DeviceCapabilities {
+                    screen_capture: false,
+                    audio_capture: false,
+                    file_transfer: true,
+                    input_control: true,
+                    decoder: None,
+                    keyboard_layout: None,
+                    app_sharing: false,
+                    data_compression: Vec::new(),
+                },
+            },
+        };
+
+        let fresh = MessageEnvelope::new(
+            "controller".to_string(),
+            "host".to_string(),
+            DEFAULT_MESSAGE_TTL_SECS,
+            request.clone(),
+        );
+        let mut stale = fresh.clone();
+        stale.sent_at -= (DEFAULT_MESSAGE_TTL_SECS as i64 + 1) * 1000;
+
+        for envelope in [fresh, stale] {
+            SignalingClient::handle_message(
+                SignalingMessage::Envelope(envelope),
+                &events,
+                &device_id,
+                &registered_devices,
+                &presence,
+                &metrics,
+                &pending_exchanges,
+            )
+            .await;
+        }
+
+        assert!(matches!(
+            subscription.recv().await,
+            Some(SignalingEvent::ConnectionRequest { .. })
+        ));
+        assert!(matches!(
+            subscription.recv().await,
+            Some(SignalingEvent::StaleMessageRejected { .. })
+        ));
+
+        // An envelope cannot carry a message in another device's name
+        let spoofed = MessageEnvelope::new(
+            "intruder".to_string(),
+            "host".to_string(),
+            DEFAULT_MESSAGE_TTL_SECS,
+            request,
+        );
+        SignalingClient::handle_message(
+            SignalingMessage::Envelope(spoofed),
+            &events,
+            &device_id,
+            &registered_devices,
+            &presence,
+            &metrics,
+            &pending_exchanges,
+        )
+        .await;
+        assert!(matches!(
+            subscription.recv().await,
+            Some(SignalingEvent::PayloadRejected { from, .. }) if from == "intruder"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_batch_status_updates_presence_cache() {
+        let events = EventBus::new();
+        let mut subscription = events.subscribe(SubscriptionOptions::only(&["PresenceChanged"]));
+        let presence = Arc::new(RwLock::new(PresenceCache::default()));
+        let status = |device_id: &str, online| DeviceStatus {
+            device_id: device_id.to_string(),
+            online,
+            last_seen: chrono::Utc::now().to_rfc3339(),
+        };
+        let batch = SignalingMessage::StatusBatchResponse {
+            statuses: vec![status("office", true), status("laptop", false)],
+        };
+        let json = serde_json::to_string(&batch).unwrap();
+
+        for message in [
+            serde_json::from_str(&json).unwrap(),
+            SignalingMessage::PresenceUpdate(status("laptop", true)),
+        ] {
+            SignalingClient::handle_message(
+                message,
+                &events,
+                &Arc::new(RwLock::new(None)),
+                &Arc::new(RwLock::new(HashMap::new())),
+                &presence,
+                &SignalingCounters::new(&MetricsRegistry::new()),
+                &Arc::new(RwLock::new(HashMap::new())),
+            )
+            .await;
+        }
+
+        let mut reported = Vec::new();
+        while let Some(SignalingEvent::PresenceChanged(status)) = subscription.try_recv() {
+            reported.push((status.device_id, status.online));
+        }
+        assert_eq!(
+            reported,
+            vec![
+                ("office".to_string(), true),
+                ("laptop".to_string(), false),
+                ("laptop".to_string(), true),
+            ]
+        );
+        let cache = presence.read().await;
+        assert!(cache.fresh("laptop").unwrap().online);
+        assert!(cache.stale_ids(&["office".to_string(), "nas".to_string()]) == ["nas"]);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_snapshot_from_shared_registry() {
+        let registry = Arc::new(MetricsRegistry::new());
+        let client = SignalingClient::new("ws://localhost:8080".to_string())
+            .unwrap()
+            .with_metrics_registry(registry.clone());
+        client.metrics.messages_sent.add(3);
+        client.metrics.record_exchange(100);
+        client.metrics.record_exchange(50);
+
+        let metrics = client.get_metrics().await;
+        assert_eq!(metrics.messages_sent, 3);
+        assert_eq!(metrics.successful_exchanges, 2);
+        assert_eq!(metrics.last_exchange_duration_ms, 50);
+        assert_eq!(metrics.avg_rtt_ms, 75.0);
+        assert_eq!(registry.snapshot().get("signaling.messages_sent"), 3);
+    }
+
+    #[tokio::test]
+    async fn test_keyboard_layout_change_event() {
+        let events = EventBus::new();
+        let mut subscription =
+            events.subscribe(SubscriptionOptions::only(&["KeyboardLayoutChanged"]));
+        let message = SignalingMessage::KeyboardLayout {
+            from: "host".to_string(),
+            to: "controller".to_string(),
+            session_id: "session-1".to_string(),
+            layout: KeyboardLayout::FR,
+        };
+        let json = serde_json::to_string(&message).unwrap();
+
+        SignalingClient::handle_message(
+            serde_json::from_str(&json).unwrap(),
+            &events,
+            &Arc::new(RwLock::new(None)),
+            &Arc::new(RwLock::new(HashMap::new())),
+            &Arc::new(RwLock::new(PresenceCache::default())),
+            &SignalingCounters::new(&MetricsRegistry::new()),
+            &Arc::new(RwLock::new(HashMap::new())),
+        )
+        .await;
+
+        match subscription.try_recv() {
+            Some(SignalingEvent::KeyboardLayoutChanged {
+                from,
+                session_id,
+                layout,
+            }) => {
+                assert_eq!((from.as_str(), session_id.as_str()), ("host", "session-1"));
+                assert_eq!(layout, KeyboardLayout::FR);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconnect_resumes_registration_and_replays_backlog() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (received_tx, mut received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // The first connection drops right after registration
+            for expected in [1, 2] {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+                let mut messages = Vec::new();
+                while messages.len() < expected {
+                    if let Some(Ok(Message::Text(text))) = ws.next().await {
+                        let msg = serde_json::from_str::<SignalingMessage>(&text).unwrap();
+                        if let SignalingMessage::Request { request_id, .. } = &msg {
+                            let reply = SignalingMessage::Response {
+                                request_id: request_id.clone(),
+                                message: Box::new(SignalingMessage::RegisterResponse {
+                                    device_id: "host".to_string(),
+                                    success: true,
+                                }),
+                            };
+                            let json = serde_json::to_string(&reply).unwrap();
+                            ws.send(Message::Text(json)).await.unwrap();
+                        }
+                        messages.push(msg);
+                    }
+                }
+                received_tx.send(messages).unwrap();
+                if expected == 2 {
+                    // Keep the second connection open
+                    std::future::pending::<()>().await;
+                }
+            }
+        });
+
+        let client = SignalingClient::new(url)
+            .unwrap()
+            .with_reconnect_policy(ReconnectPolicy {
+                initial_delay_ms: 50,
+                jitter: 0.0,
+                ..Default::default()
+            });
+        let mut events = client.subscribe(SubscriptionOptions::all());
+        client.connect().await.unwrap();
+        assert!(matches!(
+            events.recv().await,
+            Some(SignalingEvent::Connected)
+        ));
+        client
+            .register_device(DeviceInfo {
+                device_id: "host".to_string(),
+                device_name: "Host".to_string(),
+                platform: "linux".to_string(),
+                version: "1.0.0".to_string(),
+                capabilities: DeviceCapabilities {
+                    screen_capture: true,
+                    audio_capture: false,
+                    file_transfer: false,
+                    input_control: true,
+                    decoder: None,
+                    keyboard_layout: None,
+                    app_sharing: false,
+                    data_compression: Vec::new(),
+                },
+            })
+            .await
+            .unwrap();
+        assert!(matches!(
+            &received.recv().await.unwrap()[..],
+            [SignalingMessage::Request { message, .. }]
+                if matches!(**message, SignalingMessage::Register(_))
+        ));
+
+        assert!(matches!(
+            events.recv().await,
+            Some(SignalingEvent::Disconnected)
+        ));
+        assert!(matches!(
+            events.recv().await,
+            Some(SignalingEvent::Reconnecting { attempt: 1 })
+        ));
+        assert!(client.is_reconnecting());
+        // Sent while down; replayed after the registration
+        client.send_offer("viewer", "sdp").await.unwrap();
+
+        assert!(matches!(
+            events.recv().await,
+            Some(SignalingEvent::Connected)
+        ));
+        let resumed = received.recv().await.unwrap();
+        assert!(matches!(
+            &resumed[..],
+            [SignalingMessage::Register(info), SignalingMessage::Offer { sdp, .. }]
+                if info.device_id == "host" && sdp == "sdp"
+        ));
+        assert!(client.is_connected().await);
+        assert!(!client.is_reconnecting());
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_requests_resolve_with_correlated_replies() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let (mut first, mut held) = (true, None);
+            while let Some(Ok(Message::Text(text))) = ws.next().await {
+                let SignalingMessage::Request {
+                    request_id,
+                    message,
+                } = serde_json::from_str::<SignalingMessage>(&text).unwrap()
+                else {
+                    continue;
+                };
+                let reply = match *message {
+                    SignalingMessage::Register(_) => SignalingMessage::RegisterResponse {
+                        device_id: "assigned-id".to_string(),
+                        success: true,
+                    },
+                    SignalingMessage::QueryStatus { device_id } if device_id == "slow" => {
+                        // Never answered
+                        continue;
+                    }
+                    SignalingMessage::QueryStatus { device_id } => {
+                        SignalingMessage::StatusResponse(DeviceStatus {
+                            device_id,
+                            online: true,
+                            last_seen: chrono::Utc::now().to_rfc3339(),
+                        })
+                    }
+                    _ => SignalingMessage::Error {
+                        code: 400,
+                        message: "unsupported".to_string(),
+                    },
+                };
+                let response = SignalingMessage::Response {
+                    request_id,
+                    message: Box::new(reply),
+                };
+                // The registration is answered only after the next request
+                if first {
+                    first = false;
+                    held = Some(response);
+                    continue;
+                }
+                for reply in std::iter::once(response).chain(held.take()) {
+                    let json = serde_json::to_string(&reply).unwrap();
+                    ws.send(Message::Text(json)).await.unwrap();
+                }
+            }
+        });
+
+        let client = Arc::new(
+            SignalingClient::new(url)
+                .unwrap()
+                .with_request_timeout(Duration::from_millis(200)),
+        );
+        client.connect().await.unwrap();
+        let info = DeviceInfo {
+            device_id: "local-id".to_string(),
+            device_name: "Host".to_string(),
+            platform: "linux".to_string(),
+            version: "1.0.0".to_string(),
+            capabilities: DeviceCapabilities {
+                screen_capture: true,
+                audio_capture: false,
+                file_transfer: false,
+                input_control: true,
+                decoder: None,
+                keyboard_layout: None,
+                app_sharing: false,
+                data_compression: Vec::new(),
+            },
+        };
+        let register = {
+            let client = client.clone();
+            tokio::spawn(async move { client.register_device(info).await })
+        };
+        // Let the registration go out first, so its reply is held back
+        while client.pending_requests() == 0 {
+            tokio::task::yield_now().await;
+        }
+        let status = client
+            .request(SignalingMessage::QueryStatus {
+                device_id: "office".to_string(),
+            })
+            .await
+            .unwrap();
+        assert!(matches!(
+            status,
+            SignalingMessage::StatusResponse(DeviceStatus { ref device_id, .. })
+                if device_id == "office"
+        ));
+        assert_eq!(register.await.unwrap().unwrap(), "assigned-id");
+        assert_eq!(client.get_device_id().await.as_deref(), Some("assigned-id"));
+
+        let err = client
+            .request(SignalingMessage::HeartbeatAck)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("unsupported"));
+
+        let err = client
+            .request(SignalingMessage::QueryStatus {
+                device_id: "slow".to_string(),
+            })
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("No reply"));
+        assert_eq!(client.pending_requests(), 0);
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_mock_transport_reconnects_and_resumes() {
+        let (transport, mut peers) = MockTransport::new();
+        let client = SignalingClient::new("mock://signaling".to_string())
+            .unwrap()
+            .with_transport(transport.clone())
+            .with_reconnect_policy(ReconnectPolicy {
+                initial_delay_ms: 10,
+                max_delay_ms: 10,
+                jitter: 0.0,
+                ..Default::default()
+            });
+        let mut events = client.subscribe(SubscriptionOptions::all());
+
+        transport.set_unreachable(true);
+        assert!(client.connect().await.is_err());
+        transport.set_unreachable(false);
+        client.connect().await.unwrap();
+        let mut peer = peers.recv().await.unwrap();
+        assert_eq!(peer.url, "mock://signaling");
+        assert!(matches!(
+            events.recv().await,
+            Some(SignalingEvent::Connected)
+        ));
+
+        client
+            .subscribe_presence(&["office".to_string()])
+            .await
+            .unwrap();
+        assert!(matches!(
+            peer.recv().await,
+            Some(SignalingMessage::SubscribePresence { device_ids }) if device_ids == ["office"]
+        ));
+        peer.send(&SignalingMessage::PresenceUpdate(DeviceStatus {
+            device_id: "office".to_string(),
+            online: true,
+            last_seen: chrono::Utc::now().to_rfc3339(),
+        }));
+        assert!(matches!(
+            events.recv().await,
+            Some(SignalingEvent::PresenceChanged(status)) if status.online
+        ));
+
+        // The server end goes away; the first reconnect attempt fails
+        transport.set_unreachable(true);
+        drop(peer);
+        assert!(matches!(
+            events.recv().await,
+            Some(SignalingEvent::Disconnected)
+        ));
+        assert!(matches!(
+            events.recv().await,
+            Some(SignalingEvent::Reconnecting { attempt: 1 })
+        ));
+        while transport.connect_count() < 3 {
+            tokio::task::yield_now().await;
+        }
+        transport.set_unreachable(false);
+
+        let mut peer = peers.recv().await.unwrap();
+        assert!(matches!(
+            peer.recv().await,
+            Some(SignalingMessage::SubscribePresence { device_ids }) if device_ids == ["office"]
+        ));
+        assert!(client.is_connected().await);
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_subscribers_each_receive_events() {
+        let (transport, mut peers) = MockTransport::new();
+        let client = SignalingClient::new("mock://signaling".to_string())
+            .unwrap()
+            .with_transport(transport);
+        let mut bridge = client.subscribe(SubscriptionOptions::all());
+        let mut sessions = client.subscribe(SubscriptionOptions::only(&["OfferReceived"]));
+        let diagnostics = client.subscribe(SubscriptionOptions::all());
+        client.connect().await.unwrap();
+        let peer = peers.recv().await.unwrap();
+        assert!(matches!(
+            bridge.recv().await,
+            Some(SignalingEvent::Connected)
+        ));
+
+        // A consumer going away, or a new one arriving, leaves the rest alone
+        drop(diagnostics);
+        let mut late = client.subscribe(SubscriptionOptions::only(&["OfferReceived"]));
+        peer.send(&SignalingMessage::Offer {
+            from: "viewer".to_string(),
+            to: "host".to_string(),
+            sdp: "v=0".to_string(),
+        });
+        for subscription in [&mut bridge, &mut sessions, &mut late] {
+            assert!(matches!(
+                subscription.recv().await,
+                Some(SignalingEvent::OfferReceived { from, .. }) if from == "viewer"
+            ));
+        }
+
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_payload_encryption_hides_session_descriptions() {
+        let viewer_keys = Arc::new(SignalingKeyPair::generate());
+        let host_keys = Arc::new(SignalingKeyPair::generate());
+        let (viewer_transport, mut viewer_peers) = MockTransport::new();
+        let viewer = SignalingClient::new("mock://signaling".to_string())
+            .unwrap()
+            .with_transport(viewer_transport)
+            .with_payload_encryption(viewer_keys);
+        let (host_transport, mut host_peers) = MockTransport::new();
+        let host = SignalingClient::new("mock://signaling".to_string())
+            .unwrap()
+            .with_transport(host_transport)
+            .with_payload_encryption(host_keys.clone());
+        let mut host_events = host.subscribe(SubscriptionOptions::only(&[
+            "OfferReceived",
+            "PayloadRejected",
+        ]));
+        *viewer.device_id.write().await = Some("viewer".to_string());
+        viewer.connect().await.unwrap();
+        host.connect().await.unwrap();
+        let mut viewer_peer = viewer_peers.recv().await.unwrap();
+        let host_peer = host_peers.recv().await.unwrap();
+
+        // Without the host's key the viewer refuses to send in the clear
+        assert!(viewer.send_offer("host", "v=0 secret").await.is_err());
+        viewer
+            .set_peer_signaling_key("host", &host_keys.public_key())
+            .unwrap();
+        viewer.send_offer("host", "v=0 secret").await.unwrap();
+
+        // The server only sees the route
+        let sealed = viewer_peer.recv().await.unwrap();
+        assert!(matches!(
+            &sealed,
+            SignalingMessage::Sealed { from, to, .. } if from == "viewer" && to == "host"
+        ));
+        assert!(!serde_json::to_string(&sealed).unwrap().contains("secret"));
+
+        host_peer.send(&sealed);
+        assert!(matches!(
+            host_events.recv().await,
+            Some(SignalingEvent::OfferReceived { from, sdp }) if from == "viewer" && sdp == "v=0 secret"
+        ));
+
+        // Plaintext descriptions are refused
+        host_peer.send(&SignalingMessage::Offer {
+            from: "server".to_string(),
+            to: "host".to_string(),
+            sdp: "v=0 forged".to_string(),
+        });
+        assert!(matches!(
+            host_events.recv().await,
+            Some(SignalingEvent::PayloadRejected { from, .. }) if from == "server"
+        ));
+
+        // A sealed payload replayed on another route does not open
+        if let SignalingMessage::Sealed { payload, .. } = sealed {
+            host_peer.send(&SignalingMessage::Sealed {
+                from: "mallory".to_string(),
+                to: "host".to_string(),
+                payload,
+            });
+        }
+        assert!(matches!(
+            host_events.recv().await,
+            Some(SignalingEvent::PayloadRejected { from, .. }) if from == "mallory"
+        ));
+
+        viewer.disconnect().await.unwrap();
+        host.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_signed_messages_verify_sender_certificate() {
+        let mut viewer_security = SecurityManager::new();
+        let viewer_cert = viewer_security
+            .generate_device_certificate("viewer".to_string())
+            .await
+            .unwrap();
+        let mut host_security = SecurityManager::new();
+        host_security
+            .generate_device_certificate("host".to_string())
+            .await
+            .unwrap();
+
+        let (viewer_transport, mut viewer_peers) = MockTransport::new();
+        let viewer = SignalingClient::new("mock://signaling".to_string())
+            .unwrap()
+            .with_transport(viewer_transport)
+            .with_message_signing(Arc::new(viewer_security.message_signer().unwrap()));
+        let (host_transport, mut host_peers) = MockTransport::new();
+        let host = SignalingClient::new("mock://signaling".to_string())
+            .unwrap()
+            .with_transport(host_transport)
+            .with_message_signing(Arc::new(host_security.message_signer().unwrap()));
+        let mut host_events = host.subscribe(SubscriptionOptions::only(&[
+            "OfferReceived",
+            "SignatureRejected",
+        ]));
+        *viewer.device_id.write().await = Some("viewer".to_string());
+        *host.device_id.write().await = Some("host".to_string());
+        viewer.connect().await.unwrap();
+        host.connect().await.unwrap();
+        let mut viewer_peer = viewer_peers.recv().await.unwrap();
+        let host_peer = host_peers.recv().await.unwrap();
+
+        viewer.send_offer("host", "v=0 signed").await.unwrap();
+        let signed = viewer_peer.recv().await.unwrap();
+        let SignalingMessage::Signed(signed) = signed else {
+            panic!("offer was not signed: {:?}", signed);
+        };
+        assert_eq!(
+            (signed.from.as_str(), signed.to.as_str()),
+            ("viewer", "host")
+        );
+
+        // Unknown signers are refused until their fingerprint is pinned
+        host_peer.send(&SignalingMessage::Signed(signed.clone()));
+        assert!(matches!(
+            host_events.recv().await,
+            Some(SignalingEvent::SignatureRejected { from, .. }) if from == "viewer"
+        ));
+        host.set_peer_fingerprint("viewer", &viewer_cert.fingerprint);
+        host_peer.send(&SignalingMessage::Signed(signed.clone()));
+        assert!(matches!(
+            host_events.recv().await,
+            Some(SignalingEvent::OfferReceived { from, sdp }) if from == "viewer" && sdp == "v=0 signed"
+        ));
+
+        // A spoofed offer, unsigned or with a tampered payload, is refused
+        host_peer.send(&SignalingMessage::Offer {
+            from: "viewer".to_string(),
+            to: "host".to_string(),
+            sdp: "v=0 spoofed".to_string(),
+        });
+        let mut tampered = signed.clone();
+        tampered.payload = tampered.payload.replace("signed", "spoofed");
+        host_peer.send(&SignalingMessage::Signed(tampered));
+        // So is a valid signature by a certificate other than the pinned one
+        let mut other_security = SecurityManager::new();
+        other_security
+            .generate_device_certificate("viewer".to_string())
+            .await
+            .unwrap();
+        let impostor = other_security.message_signer().unwrap();
+        let offer = SignalingMessage::Offer {
+            from: "viewer".to_string(),
+            to: "host".to_string(),
+            sdp: "v=0 impostor".to_string(),
+        };
+        host_peer.send(&SignalingMessage::Signed(
+            SignedMessage::sign(&impostor, "viewer", "host", &offer, SIGNED_MESSAGE_LIFETIME)
+                .unwrap(),
+        ));
+        // An accepted message is not accepted twice, nor once it expired
+        host_peer.send(&SignalingMessage::Signed(signed.clone()));
+        let viewer_signer = viewer_security.message_signer().unwrap();
+        let expired =
+            SignedMessage::sign(&viewer_signer, "viewer", "host", &offer, Duration::ZERO).unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        host_peer.send(&SignalingMessage::Signed(expired));
+        // Session notices must be signed like session descriptions
+        host_peer.send(&SignalingMessage::HostShutdown {
+            from: "viewer".to_string(),
+            to: "host".to_string(),
+            session_id: "session".to_string(),
+            reason: "spoofed".to_string(),
+            countdown_secs: 0,
+        });
+        for expected in [
+            "message is not signed",
+            "Signature verification failed",
+            "certificate fingerprint mismatch",
+            "signed message replayed",
+            "Signed message expired",
+            "message is not signed",
+        ] {
+            assert!(matches!(
+                host_events.recv().await,
+                Some(SignalingEvent::SignatureRejected { reason, .. }) if reason.contains(expected)
+            ));
+        }
+        viewer
+            .send_host_shutdown("host", "session", "update", 10)
+            .await
+            .unwrap();
+        assert!(matches!(
+            viewer_peer.recv().await,
+            Some(SignalingMessage::Signed(signed)) if signed.payload.contains("HostShutdown")
+        ));
+
+        // The offer signed for the host is refused when replayed to a third
+        // device that trusts the viewer as well
+        let (third_transport, mut third_peers) = MockTransport::new();
+        let third = SignalingClient::new("mock://signaling".to_string())
+            .unwrap()
+            .with_transport(third_transport)
+            .with_message_signing(Arc::new(host_security.message_signer().unwrap()));
+        let mut third_events = third.subscribe(SubscriptionOptions::only(&[
+            "OfferReceived",
+            "SignatureRejected",
+        ]));
+        *third.device_id.write().await = Some("third".to_string());
+        third.set_peer_fingerprint("viewer", &viewer_cert.fingerprint);
+        third.connect().await.unwrap();
+        let third_peer = third_peers.recv().await.unwrap();
+        third_peer.send(&SignalingMessage::Signed(signed));
+        assert!(matches!(
+            third_events.recv().await,
+            Some(SignalingEvent::SignatureRejected { reason, .. }) if reason.contains("addressed to host")
+        ));
+
+        viewer.disconnect().await.unwrap();
+        host.disconnect().await.unwrap();
+        third.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_messages_sent_before_connect_are_flushed() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (received_tx, mut received) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            while let Some(Ok(Message::Text(text))) = ws.next().await {
+                let msg = serde_json::from_str::<SignalingMessage>(&text).unwrap();
+                received_tx.send(msg).unwrap();
+            }
+        });
+
+        let client = SignalingClient::new(url).unwrap();
+        let (outcome_tx, mut outcomes) = mpsc::unbounded_channel();
+        for sdp in ["cancelled", "queued"] {
+            let outcome_tx = outcome_tx.clone();
+            client
+                .send_with_callback(
+                    SignalingMessage::Offer {
+                        from: "host".to_string(),
+                        to: "viewer".to_string(),
+                        sdp: sdp.to_string(),
+                    },
+                    move |outcome| outcome_tx.send((sdp, outcome)).unwrap(),
+                )
+                .await
+                .unwrap();
+            if sdp == "cancelled" {
+                assert_eq!(client.queued_messages().await, 1);
+                client.disconnect().await.unwrap();
+            }
+        }
+        assert_eq!(
+            outcomes.recv().await,
+            Some(("cancelled", SendOutcome::Cancelled))
+        );
+
+        client.connect().await.unwrap();
+        assert!(matches!(
+            received.recv().await,
+            Some(SignalingMessage::Offer { sdp, .. }) if sdp == "queued"
+        ));
+        assert_eq!(outcomes.recv().await, Some(("queued", SendOutcome::Sent)));
+        assert_eq!(client.queued_messages().await, 0);
+        client.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_queue_disabled_fails_while_disconnected() {
+        let client = SignalingClient::new("ws://127.0.0.1:1".to_string())
+            .unwrap()
+            .with_outbound_queue(OutboundQueueConfig {
+                max_messages: 0,
+                ..Default::default()
+            });
+        *client.device_id.write().await = Some("host".to_string());
+        let err = client.send_offer("viewer", "sdp").await.unwrap_err();
+        assert!(err.to_string().contains("not connected"));
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_reports_unhealthy_after_missed_acks() {
+        let client = SignalingClient::new("ws://127.0.0.1:1".to_string())
+            .unwrap()
+            .with_max_missed_heartbeats(2);
+        let mut subscription =
+            client.subscribe(SubscriptionOptions::only(&["ConnectionUnhealthy"]));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        *client.ws_sender.lock().await = Some(tx);
+        *client.connected.write().await = true;
+        *client.device_id.write().await = Some("host".to_string());
+
+        assert!(client.start_heartbeat(Duration::ZERO).is_err());
+        client.start_heartbeat(Duration::from_millis(10)).unwrap();
+
+        // Nobody acks: the third heartbeat finds two outstanding
+        match subscription.recv().await {
+            Some(SignalingEvent::ConnectionUnhealthy { missed }) => assert_eq!(missed, 2),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert!(!client.heartbeat_status().healthy);
+        assert!(matches!(
+            rx.recv().await.map(|m| m.message),
+            Some(SignalingMessage::Heartbeat { device_id }) if device_id == "host"
+        ));
+
+        client.stop_heartbeat();
+        assert!(client.heartbeat_task.lock().unwrap().is_none());
+
+        // An ack clears the outage and re-arms the report
+        client.heartbeat.on_ack(Instant::now());
+        let status = client.heartbeat_status();
+        assert!(status.healthy);
+        assert!(status.last_rtt_ms.is_some());
+    }
+
+    #[test]
+    fn test_reconnect_backoff_is_capped() {
+        let policy = ReconnectPolicy {
+            initial_delay_ms: 100,
+            max_delay_ms: 1000,
+            jitter: 0.0,
+            ..Default::default()
+        };
+        let delays: Vec<_> = (1..=6).map(|attempt| policy.delay(attempt)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1000, 1000].map(Duration::from_millis)
+        );
+
+        let jittered = ReconnectPolicy {
+            jitter: 0.5,
+            ..policy
+        };
+        for _ in 0..20 {
+            let delay = jittered.delay(3);
+            assert!(delay >= Duration::from_millis(200) && delay <= Duration::from_millis(600));
+        }
+    }
 
     #[test]
     fn test_generate_device_id_uniqueness() {
@@ -682,6 +3220,10 @@ mod tests {
                 audio_capture: true,
                 file_transfer: true,
                 input_control: true,
+                decoder: None,
+                keyboard_layout: Some(KeyboardLayout::DE),
+                app_sharing: true,
+                data_compression: CompressionAlgorithm::supported(),
             },
         };
 
@@ -691,5 +3233,9 @@ mod tests {
         assert_eq!(parsed.device_id, "test_id");
         assert_eq!(parsed.device_name, "Test Device");
         assert!(parsed.capabilities.screen_capture);
+        assert_eq!(
+            parsed.capabilities.keyboard_layout,
+            Some(KeyboardLayout::DE)
+        );
     }
 }
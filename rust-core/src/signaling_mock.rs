@@ -0,0 +1,96 @@
+//! Mock signaling transport for testing
+//!
+//! Connections are in-memory channels: each successful `connect` hands the
+//! test a `MockPeer` standing in for the server end, so client behaviour can
+//! be exercised without opening sockets.
+
+use crate::signaling::SignalingMessage;
+use crate::signaling_transport::{FrameSink, FrameStream, SignalingTransport};
+use anyhow::Result;
+use futures::channel::mpsc;
+use futures::future::BoxFuture;
+use futures_util::{SinkExt, StreamExt};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Server end of a mock connection
+///
+/// Dropping it closes the connection.
+pub struct MockPeer {
+    pub url: String,
+    sent: mpsc::UnboundedReceiver<String>,
+    incoming: mpsc::UnboundedSender<Result<String>>,
+}
+
+impl MockPeer {
+    /// Next message the client sent; `None` once it closed the connection
+    pub async fn recv(&mut self) -> Option<SignalingMessage> {
+        let frame = self.sent.next().await?;
+        Some(serde_json::from_str(&frame).expect("client sent invalid JSON"))
+    }
+
+    /// Deliver `message` to the client
+    pub fn send(&self, message: &SignalingMessage) {
+        let frame = serde_json::to_string(message).expect("message serializes");
+        let _ = self.incoming.unbounded_send(Ok(frame));
+    }
+}
+
+/// Transport whose connections end in `MockPeer`s
+pub struct MockTransport {
+    peers: tokio::sync::mpsc::UnboundedSender<MockPeer>,
+    unreachable: AtomicBool,
+    connects: AtomicUsize,
+}
+
+impl MockTransport {
+    /// New transport and the receiver of the peers of its connections
+    pub fn new() -> (Arc<Self>, tokio::sync::mpsc::UnboundedReceiver<MockPeer>) {
+        let (peers, rx) = tokio::sync::mpsc::unbounded_channel();
+        let transport = Arc::new(Self {
+            peers,
+            unreachable: AtomicBool::new(false),
+            connects: AtomicUsize::new(0),
+        });
+        (transport, rx)
+    }
+
+    /// Make connection attempts fail until set back
+    pub fn set_unreachable(&self, unreachable: bool) {
+        self.unreachable.store(unreachable, Ordering::SeqCst);
+    }
+
+    /// Connection attempts so far, failed ones included
+    pub fn connect_count(&self) -> usize {
+        self.connects.load(Ordering::SeqCst)
+    }
+}
+
+impl SignalingTransport for MockTransport {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn connect<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<(FrameSink, FrameStream)>> {
+        Box::pin(async move {
+            self.connects.fetch_add(1, Ordering::SeqCst);
+            if self.unreachable.load(Ordering::SeqCst) {
+                return Err(anyhow::anyhow!("Mock signaling server unreachable"));
+            }
+            let (sent_tx, sent) = mpsc::unbounded();
+            let (incoming, incoming_rx) = mpsc::unbounded();
+            self.peers
+                .send(MockPeer {
+                    url: url.to_string(),
+                    sent,
+                    incoming,
+                })
+                .map_err(|_| anyhow::anyhow!("Mock peer receiver dropped"))?;
+            let sink = sent_tx.sink_map_err(anyhow::Error::from);
+            Ok((
+                Box::pin(sink) as FrameSink,
+                Box::pin(incoming_rx) as FrameStream,
+            ))
+        })
+    }
+}
@@ -0,0 +1,852 @@
+//! Built-in Signaling Server
+//!
+//! The server half of `SignalingMessage` routing, for self-hosted
+//! deployments: a registry of connected devices, presence queries and
+//! pushes, forwarding of offers, answers, candidates and other addressed
+//! messages, store-and-forward envelopes for offline devices (see
+//! `offline_queue`) and heartbeat expiry.
+//!
+//! `serve` accepts WebSocket clients on a TCP listener; `handle_connection`
+//! runs one connection over any `signaling_transport` frame pair. A device
+//! can only send messages as itself: addressed messages whose `from` is not
+//! the sender's registered ID are rejected. A bare `ConnectionRequest`
+//! names no target and is rejected too; it has to travel in an `Envelope`.
+//! An ID that is registered by a live connection cannot be taken over by
+//! another one; a device whose connection broke without the server noticing
+//! registers again once that connection closes or misses its heartbeats.
+//!
+//! Devices whose last message is older than `heartbeat_timeout_secs` are
+//! dropped and reported offline to their watchers.
+
+use crate::clock::{system_clock, SharedClock};
+use crate::offline_queue::{delivery_receipt, OfflineMessageQueue};
+use crate::signaling::{
+    generate_device_id, DeliveryStatus, DeviceInfo, DeviceStatus, MessageEnvelope, SignalingMessage,
+};
+use crate::signaling_transport::{websocket_frames, FrameSink, FrameStream};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures_util::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, Notify};
+
+/// Message was malformed or cannot be routed
+pub const ERROR_BAD_REQUEST: u32 = 400;
+/// Sender has not registered yet
+pub const ERROR_NOT_REGISTERED: u32 = 401;
+/// `from` is not the sender's registered device ID
+pub const ERROR_FORBIDDEN: u32 = 403;
+/// Target device is not connected
+pub const ERROR_OFFLINE: u32 = 404;
+/// Offline queue rejected an envelope
+pub const ERROR_QUEUE_FULL: u32 = 503;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignalingServerConfig {
+    /// Silence after which a device is dropped
+    pub heartbeat_timeout_secs: u64,
+    /// How often `serve` looks for expired devices and envelopes
+    pub sweep_interval_secs: u64,
+}
+
+impl Default for SignalingServerConfig {
+    fn default() -> Self {
+        Self {
+            heartbeat_timeout_secs: 90,
+            sweep_interval_secs: 15,
+        }
+    }
+}
+
+/// A registered device's connection
+struct Peer {
+    connection_id: u64,
+    sender: mpsc::UnboundedSender<SignalingMessage>,
+    closed: Arc<Notify>,
+    info: DeviceInfo,
+    last_seen: Instant,
+}
+
+#[derive(Default)]
+struct ServerState {
+    peers: HashMap<String, Peer>,
+    /// When devices that are no longer connected were last seen
+    last_seen: HashMap<String, DateTime<Utc>>,
+    /// Watched device -> devices watching it
+    watchers: HashMap<String, HashSet<String>>,
+    offline: OfflineMessageQueue,
+}
+
+/// One connection, registered or not
+struct Connection {
+    id: u64,
+    sender: mpsc::UnboundedSender<SignalingMessage>,
+    closed: Arc<Notify>,
+    device_id: Option<String>,
+}
+
+pub struct SignalingServer {
+    config: SignalingServerConfig,
+    clock: SharedClock,
+    state: Mutex<ServerState>,
+    next_connection_id: AtomicU64,
+}
+
+impl Default for SignalingServer {
+    fn default() -> Self {
+        Self::new(SignalingServerConfig::default())
+    }
+}
+
+impl SignalingServer {
+    pub fn new(config: SignalingServerConfig) -> Self {
+        Self {
+            config,
+            clock: system_clock(),
+            state: Mutex::new(ServerState::default()),
+            next_connection_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: SharedClock) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn with_offline_queue(self, queue: OfflineMessageQueue) -> Self {
+        self.state().offline = queue;
+        self
+    }
+
+    /// Accept WebSocket clients on `listener` until it fails
+    ///
+    /// Also expires silent devices every `sweep_interval_secs`.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> Result<()> {
+        tracing::info!("Signaling server listening on {}", listener.local_addr()?);
+        let sweep = async {
+            let mut ticks =
+                tokio::time::interval(Duration::from_secs(self.config.sweep_interval_secs.max(1)));
+            loop {
+                ticks.tick().await;
+                self.sweep();
+            }
+        };
+        let accept = async {
+            loop {
+                let (stream, addr) = listener.accept().await?;
+                let server = Arc::clone(&self);
+                tokio::spawn(async move {
+                    match tokio_tungstenite::accept_async(stream).await {
+                        Ok(ws) => {
+                            let (sink, stream) = websocket_frames(ws);
+                            server.handle_connection(sink, stream).await;
+                        }
+                        Err(e) => {
+                            tracing::debug!("WebSocket handshake with {} failed: {}", addr, e)
+                        }
+                    }
+                });
+            }
+        };
+        tokio::select! {
+            result = accept => result,
+            _ = sweep => Ok(()),
+        }
+    }
+
+    /// Serve one client connection until it closes or is dropped
+    pub async fn handle_connection(self: Arc<Self>, mut sink: FrameSink, mut stream: FrameStream) {
+        let (sender, mut outgoing) = mpsc::unbounded_channel::<SignalingMessage>();
+        let mut connection = Connection {
+            id: self.next_connection_id.fetch_add(1, Ordering::Relaxed),
+            sender,
+            closed: Arc::new(Notify::new()),
+            device_id: None,
+        };
+
+        let writer = tokio::spawn(async move {
+            while let Some(message) = outgoing.recv().await {
+                let frame = match serde_json::to_string(&message) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        tracing::error!("Failed to serialize message: {}", e);
+                        continue;
+                    }
+                };
+                if sink.send(frame).await.is_err() {
+                    break;
+                }
+            }
+            let _ = sink.close().await;
+        });
+
+        let closed = Arc::clone(&connection.closed);
+        loop {
+            let frame = tokio::select! {
+                frame = stream.next() => frame,
+                _ = closed.notified() => break,
+            };
+            let text = match frame {
+                Some(Ok(text)) => text,
+                Some(Err(e)) => {
+                    tracing::debug!("Signaling connection {} failed: {:#}", connection.id, e);
+                    break;
+                }
+                None => break,
+            };
+            let reply = match serde_json::from_str::<SignalingMessage>(&text) {
+                Ok(message) => self.dispatch(&mut connection, message),
+                Err(e) => Some(error(ERROR_BAD_REQUEST, format!("Invalid message: {}", e))),
+            };
+            if let Some(reply) = reply {
+                let _ = connection.sender.send(reply);
+            }
+        }
+
+        self.disconnect(&connection);
+        drop(connection);
+        let _ = writer.await;
+    }
+
+    /// Drop devices that have been silent too long and envelopes whose TTL
+    /// elapsed; returns the dropped device IDs
+    pub fn sweep(&self) -> Vec<String> {
+        let now = self.clock.instant();
+        let timeout = Duration::from_secs(self.config.heartbeat_timeout_secs);
+        let mut state = self.state();
+        let expired: Vec<String> = state
+            .peers
+            .iter()
+            .filter(|(_, peer)| now.saturating_duration_since(peer.last_seen) >= timeout)
+            .map(|(id, _)| id.clone())
+            .collect();
+        for device_id in &expired {
+            tracing::info!("Device {} missed its heartbeats; dropping it", device_id);
+            if let Some(peer) = state.peers.remove(device_id) {
+                peer.closed.notify_one();
+            }
+            self.went_offline(&mut state, device_id);
+        }
+
+        let stale = state.offline.purge_expired(self.now_ms());
+        for envelope in stale {
+            self.send_receipt(&state, &envelope, DeliveryStatus::Expired);
+        }
+        expired
+    }
+
+    /// IDs of the devices currently connected
+    pub fn connected_devices(&self) -> Vec<String> {
+        let mut ids: Vec<String> = self.state().peers.keys().cloned().collect();
+        ids.sort();
+        ids
+    }
+
+    /// Registration of a connected device
+    pub fn device_info(&self, device_id: &str) -> Option<DeviceInfo> {
+        self.state()
+            .peers
+            .get(device_id)
+            .map(|peer| peer.info.clone())
+    }
+
+    pub fn device_status(&self, device_id: &str) -> DeviceStatus {
+        self.status(&self.state(), device_id)
+    }
+
+    /// Envelopes held for offline devices
+    pub fn queued_messages(&self) -> usize {
+        self.state().offline.len()
+    }
+
+    /// Handle one message; returns the reply to the sender, if any
+    fn dispatch(
+        &self,
+        connection: &mut Connection,
+        message: SignalingMessage,
+    ) -> Option<SignalingMessage> {
+        if let Some(device_id) = &connection.device_id {
+            let now = self.clock.instant();
+            if let Some(peer) = self.state().peers.get_mut(device_id) {
+                if peer.connection_id == connection.id {
+                    peer.last_seen = now;
+                }
+            }
+        }
+
+        match message {
+            SignalingMessage::Request {
+                request_id,
+                message,
+            } => {
+                let reply = self.dispatch(connection, *message).unwrap_or_else(|| {
+                    error(ERROR_BAD_REQUEST, "Message has no reply".to_string())
+                });
+                Some(SignalingMessage::Response {
+                    request_id,
+                    message: Box::new(reply),
+                })
+            }
+            SignalingMessage::Register(info) => Some(self.register(connection, info)),
+            SignalingMessage::Heartbeat { .. } => Some(SignalingMessage::HeartbeatAck),
+            SignalingMessage::QueryStatus { device_id } => Some(SignalingMessage::StatusResponse(
+                self.device_status(&device_id),
+            )),
+            SignalingMessage::QueryStatusBatch { device_ids } => {
+                let state = self.state();
+                let statuses = device_ids
+                    .iter()
+                    .map(|id| self.status(&state, id))
+                    .collect();
+                Some(SignalingMessage::StatusBatchResponse { statuses })
+            }
+            SignalingMessage::SubscribePresence { device_ids } => {
+                let watcher = match registered(connection) {
+                    Ok(id) => id,
+                    Err(rejection) => return Some(rejection.into()),
+                };
+                let mut state = self.state();
+                for id in &device_ids {
+                    state
+                        .watchers
+                        .entry(id.clone())
+                        .or_default()
+                        .insert(watcher.clone());
+                }
+                None
+            }
+            SignalingMessage::UnsubscribePresence { device_ids } => {
+                let watcher = match registered(connection) {
+                    Ok(id) => id,
+                    Err(rejection) => return Some(rejection.into()),
+                };
+                let mut state = self.state();
+                for id in &device_ids {
+                    if let Some(watchers) = state.watchers.get_mut(id) {
+                        watchers.remove(&watcher);
+                    }
+                }
+                state.watchers.retain(|_, watchers| !watchers.is_empty());
+                None
+            }
+            SignalingMessage::Envelope(envelope) => self.route_envelope(connection, envelope),
+            SignalingMessage::ConnectionRequest { .. } => Some(error(
+                ERROR_BAD_REQUEST,
+                "ConnectionRequest names no target; send it in an Envelope".to_string(),
+            )),
+            message => match addressing(&message) {
+                Some((from, to)) => {
+                    let (from, to) = (from.to_string(), to.to_string());
+                    self.forward(connection, &from, &to, message)
+                }
+                None => Some(error(
+                    ERROR_BAD_REQUEST,
+                    "Message cannot be sent to the server".to_string(),
+                )),
+            },
+        }
+    }
+
+    fn register(&self, connection: &mut Connection, mut info: DeviceInfo) -> SignalingMessage {
+        if info.device_id.is_empty() {
+            info.device_id = generate_device_id();
+        }
+        let device_id = info.device_id.clone();
+        let mut state = self.state();
+
+        // Whoever holds a live ID keeps it, along with its queued messages
+        if state
+            .peers
+            .get(&device_id)
+            .is_some_and(|peer| peer.connection_id != connection.id && !peer.sender.is_closed())
+        {
+            tracing::warn!(
+                "Connection {} tried to take over registered device {}",
+                connection.id,
+                device_id
+            );
+            return SignalingMessage::RegisterResponse {
+                device_id,
+                success: false,
+            };
+        }
+
+        // A device registering under a new ID leaves its old one behind
+        if let Some(previous) = connection.device_id.take() {
+            if previous != device_id
+                && state
+                    .peers
+                    .get(&previous)
+                    .is_some_and(|peer| peer.connection_id == connection.id)
+            {
+                state.peers.remove(&previous);
+                self.went_offline(&mut state, &previous);
+            }
+        }
+
+        let replaced = state.peers.insert(
+            device_id.clone(),
+            Peer {
+                connection_id: connection.id,
+                sender: connection.sender.clone(),
+                closed: Arc::clone(&connection.closed),
+                info,
+                last_seen: self.clock.instant(),
+            },
+        );
+        connection.device_id = Some(device_id.clone());
+        match replaced {
+            // A reconnect after the old connection closed
+            Some(old) if old.connection_id != connection.id => old.closed.notify_one(),
+            Some(_) => {}
+            None => {
+                tracing::info!("Device {} registered", device_id);
+                state.last_seen.remove(&device_id);
+                self.notify_watchers(&state, &device_id);
+            }
+        }
+
+        // Release what was held while the device was offline
+        let drain = state.offline.drain_for(&device_id, self.now_ms());
+        for envelope in drain.deliver {
+            let _ = connection
+                .sender
+                .send(SignalingMessage::Envelope(envelope.clone()));
+            self.send_receipt(&state, &envelope, DeliveryStatus::Delivered);
+        }
+        for envelope in drain.expired {
+            self.send_receipt(&state, &envelope, DeliveryStatus::Expired);
+        }
+
+        SignalingMessage::RegisterResponse {
+            device_id,
+            success: true,
+        }
+    }
+
+    fn forward(
+        &self,
+        connection: &Connection,
+        from: &str,
+        to: &str,
+        message: SignalingMessage,
+    ) -> Option<SignalingMessage> {
+        if let Err(rejection) = check_sender(connection, from) {
+            return Some(rejection.into());
+        }
+        match self.state().peers.get(to) {
+            Some(peer) if peer.sender.send(message).is_ok() => None,
+            _ => Some(error(ERROR_OFFLINE, format!("Device {} is offline", to))),
+        }
+    }
+
+    fn route_envelope(
+        &self,
+        connection: &Connection,
+        envelope: MessageEnvelope,
+    ) -> Option<SignalingMessage> {
+        if let Err(rejection) = check_sender(connection, &envelope.from) {
+            return Some(rejection.into());
+        }
+        let mut state = self.state();
+        if let Some(peer) = state.peers.get(&envelope.to) {
+            let receipt = delivery_receipt(&envelope, DeliveryStatus::Delivered);
+            if peer
+                .sender
+                .send(SignalingMessage::Envelope(envelope.clone()))
+                .is_ok()
+            {
+                return Some(receipt);
+            }
+        }
+        let receipt = delivery_receipt(&envelope, DeliveryStatus::Queued);
+        match state.offline.enqueue(envelope, self.now_ms()) {
+            Ok(()) => Some(receipt),
+            Err(e) => Some(error(ERROR_QUEUE_FULL, e.to_string())),
+        }
+    }
+
+    /// Forget a closed connection's device, unless it already reconnected
+    fn disconnect(&self, connection: &Connection) {
+        let Some(device_id) = &connection.device_id else {
+            return;
+        };
+        let mut state = self.state();
+        if state
+            .peers
+            .get(device_id)
+            .is_some_and(|peer| peer.connection_id == connection.id)
+        {
+            state.peers.remove(device_id);
+            tracing::info!("Device {} disconnected", device_id);
+            self.went_offline(&mut state, device_id);
+        }
+    }
+
+    fn went_offline(&self, state: &mut ServerState, device_id: &str) {
+        state
+            .last_seen
+            .insert(device_id.to_string(), self.clock.utc());
+        // Its subscriptions are renewed when it reconnects
+        for watchers in state.watchers.values_mut() {
+            watchers.remove(device_id);
+        }
+        state.watchers.retain(|_, watchers| !watchers.is_empty());
+        self.notify_watchers(state, device_id);
+    }
+
+    fn notify_watchers(&self, state: &ServerState, device_id: &str) {
+        let Some(watchers) = state.watchers.get(device_id) else {
+            return;
+        };
+        let status = self.status(state, device_id);
+        for watcher in watchers {
+            if let Some(peer) = state.peers.get(watcher) {
+                let _ = peer
+                    .sender
+                    .send(SignalingMessage::PresenceUpdate(status.clone()));
+            }
+        }
+    }
+
+    fn send_receipt(
+        &self,
+        state: &ServerState,
+        envelope: &MessageEnvelope,
+        status: DeliveryStatus,
+    ) {
+        if let Some(sender) = state.peers.get(&envelope.from) {
+            let _ = sender.sender.send(delivery_receipt(envelope, status));
+        }
+    }
+
+    fn status(&self, state: &ServerState, device_id: &str) -> DeviceStatus {
+        let online = state.peers.contains_key(device_id);
+        let last_seen = if online {
+            Some(self.clock.utc())
+        } else {
+            state.last_seen.get(device_id).copied()
+        };
+        DeviceStatus {
+            device_id: device_id.to_string(),
+            online,
+            last_seen: last_seen.map(|at| at.to_rfc3339()).unwrap_or_default(),
+        }
+    }
+
+    fn now_ms(&self) -> i64 {
+        self.clock.utc().timestamp_millis()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, ServerState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn error(code: u32, message: String) -> SignalingMessage {
+    SignalingMessage::Error { code, message }
+}
+
+/// Error reply for a message the server refuses
+struct Rejection(u32, String);
+
+impl From<Rejection> for SignalingMessage {
+    fn from(Rejection(code, message): Rejection) -> Self {
+        error(code, message)
+    }
+}
+
+fn registered(connection: &Connection) -> Result<String, Rejection> {
+    connection.device_id.clone().ok_or_else(|| {
+        Rejection(
+            ERROR_NOT_REGISTERED,
+            "Register before sending messages".to_string(),
+        )
+    })
+}
+
+fn check_sender(connection: &Connection, from: &str) -> Result<(), Rejection> {
+    let device_id = registered(connection)?;
+    if device_id != from {
+        return Err(Rejection(
+            ERROR_FORBIDDEN,
+            format!("Registered as {}, not {}", device_id, from),
+        ));
+    }
+    Ok(())
+}
+
+/// Sender and target of a message routed between devices
+fn addressing(message: &SignalingMessage) -> Option<(&str, &str)> {
+    match message {
+        SignalingMessage::Offer { from, to, .. }
+        | SignalingMessage::Answer { from, to, .. }
+        | SignalingMessage::IceCandidate { from, to, .. }
+        | SignalingMessage::ConnectionResponse { from, to, .. }
+        | SignalingMessage::RecordingState { from, to, .. }
+        | SignalingMessage::RecordingConsent { from, to, .. }
+        | SignalingMessage::KeyboardLayout { from, to, .. }
+        | SignalingMessage::HostShutdown { from, to, .. }
+        | SignalingMessage::EncryptionStatus { from, to, .. }
+        | SignalingMessage::Sealed { from, to, .. } => Some((from, to)),
+        SignalingMessage::Signed(signed) => Some((&signed.from, &signed.to)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TestClock;
+    use crate::event_bus::SubscriptionOptions;
+    use crate::signaling::{DeviceCapabilities, SignalingClient, SignalingEvent};
+
+    fn device(id: &str) -> DeviceInfo {
+        DeviceInfo {
+            device_id: id.to_string(),
+            device_name: id.to_string(),
+            platform: "linux".to_string(),
+            version: "1.0.0".to_string(),
+            capabilities: DeviceCapabilities {
+                screen_capture: true,
+                audio_capture: false,
+                file_transfer: false,
+                input_control: true,
+                decoder: None,
+                keyboard_layout: None,
+                app_sharing: false,
+                data_compression: Vec::new(),
+            },
+        }
+    }
+
+    async fn start(server: Arc<SignalingServer>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(server.serve(listener));
+        url
+    }
+
+    async fn client(url: &str, id: &str) -> SignalingClient {
+        let client = SignalingClient::new(url.to_string()).unwrap();
+        client.connect().await.unwrap();
+        assert_eq!(client.register_device(device(id)).await.unwrap(), id);
+        client
+    }
+
+    #[tokio::test]
+    async fn test_routes_offers_and_reports_presence() {
+        let clock = TestClock::shared();
+        let server = Arc::new(SignalingServer::default().with_clock(clock.clone()));
+        let url = start(server.clone()).await;
+
+        let host = client(&url, "host").await;
+        let viewer = client(&url, "viewer").await;
+        let mut host_events = host.subscribe(SubscriptionOptions::all());
+        let mut viewer_events = viewer.subscribe(SubscriptionOptions::only(&[
+            "PresenceChanged",
+            "AnswerReceived",
+        ]));
+        assert_eq!(server.connected_devices(), ["host", "viewer"]);
+        assert_eq!(server.device_info("host").unwrap().platform, "linux");
+
+        viewer.send_offer("host", "offer-sdp").await.unwrap();
+        match host_events.recv().await {
+            Some(SignalingEvent::OfferReceived { from, sdp }) => {
+                assert_eq!((from.as_str(), sdp.as_str()), ("viewer", "offer-sdp"))
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        host.send_answer("viewer", "answer-sdp").await.unwrap();
+        assert!(matches!(
+            viewer_events.recv().await,
+            Some(SignalingEvent::AnswerReceived { from, .. }) if from == "host"
+        ));
+
+        let status = viewer.query_device_status("host").await.unwrap();
+        assert!(status.online);
+        viewer
+            .subscribe_presence(&["host".to_string()])
+            .await
+            .unwrap();
+
+        // The host falls silent; the viewer keeps heartbeating
+        clock.advance(Duration::from_secs(60));
+        viewer.send_heartbeat().await.unwrap();
+        while viewer.heartbeat_status().last_rtt_ms.is_none() {
+            tokio::task::yield_now().await;
+        }
+        clock.advance(Duration::from_secs(30));
+        assert_eq!(server.sweep(), ["host"]);
+        // Skip the status pushed by the query above
+        loop {
+            match viewer_events.recv().await {
+                Some(SignalingEvent::PresenceChanged(status)) if !status.online => {
+                    assert_eq!(status.device_id, "host");
+                    break;
+                }
+                Some(SignalingEvent::PresenceChanged(_)) => {}
+                other => panic!("unexpected event: {:?}", other),
+            }
+        }
+        assert!(!server.device_status("host").online);
+        viewer.disconnect().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_envelopes_wait_for_offline_devices() {
+        let server = Arc::new(SignalingServer::default());
+        let url = start(server.clone()).await;
+
+        let viewer = client(&url, "viewer").await;
+        let mut receipts = viewer.subscribe(SubscriptionOptions::only(&["DeliveryReceipt"]));
+        let message_id = viewer
+            .send_connection_request_queued("host", device("viewer"), 60)
+            .await
+            .unwrap();
+        assert!(matches!(
+            receipts.recv().await,
+            Some(SignalingEvent::DeliveryReceipt {
+                status: DeliveryStatus::Queued,
+                ..
+            })
+        ));
+        assert_eq!(server.queued_messages(), 1);
+
+        let host = SignalingClient::new(url.clone()).unwrap();
+        let mut requests = host.subscribe(SubscriptionOptions::only(&["ConnectionRequest"]));
+        host.connect().await.unwrap();
+        host.register_device(device("host")).await.unwrap();
+        assert!(matches!(
+            requests.recv().await,
+            Some(SignalingEvent::ConnectionRequest { from, .. }) if from == "viewer"
+        ));
+        match receipts.recv().await {
+            Some(SignalingEvent::DeliveryReceipt {
+                message_id: id,
+                status,
+                ..
+            }) => {
+                assert_eq!(id, message_id);
+                assert_eq!(status, DeliveryStatus::Delivered);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        assert_eq!(server.queued_messages(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_spoofed_and_unregistered_senders() {
+        let server = Arc::new(SignalingServer::default());
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let mut connection = Connection {
+            id: 1,
+            sender: tx,
+            closed: Arc::new(Notify::new()),
+            device_id: None,
+        };
+        let offer = SignalingMessage::Offer {
+            from: "host".to_string(),
+            to: "viewer".to_string(),
+            sdp: String::new(),
+        };
+        assert!(matches!(
+            server.dispatch(&mut connection, offer.clone()),
+            Some(SignalingMessage::Error {
+                code: ERROR_NOT_REGISTERED,
+                ..
+            })
+        ));
+
+        server.dispatch(
+            &mut connection,
+            SignalingMessage::Register(device("mallory")),
+        );
+        assert!(matches!(
+            server.dispatch(&mut connection, offer),
+            Some(SignalingMessage::Error {
+                code: ERROR_FORBIDDEN,
+                ..
+            })
+        ));
+        let offer = SignalingMessage::Offer {
+            from: "mallory".to_string(),
+            to: "viewer".to_string(),
+            sdp: String::new(),
+        };
+        assert!(matches!(
+            server.dispatch(&mut connection, offer),
+            Some(SignalingMessage::Error {
+                code: ERROR_OFFLINE,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_live_device_id_cannot_be_taken_over() {
+        let server = SignalingServer::default();
+        let connect = |id| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let connection = Connection {
+                id,
+                sender: tx,
+                closed: Arc::new(Notify::new()),
+                device_id: None,
+            };
+            (connection, rx)
+        };
+        let (mut viewer, _viewer_rx) = connect(1);
+        server.dispatch(&mut viewer, SignalingMessage::Register(device("viewer")));
+        let envelope = MessageEnvelope::new(
+            "viewer".to_string(),
+            "host".to_string(),
+            60,
+            SignalingMessage::ConnectionRequest {
+                from: "viewer".to_string(),
+                device_info: device("viewer"),
+            },
+        );
+        server.dispatch(&mut viewer, SignalingMessage::Envelope(envelope));
+        assert_eq!(server.queued_messages(), 1);
+
+        // Only the first live registration gets the ID and its queue
+        let (mut host, mut host_rx) = connect(2);
+        let (mut mallory, mut mallory_rx) = connect(3);
+        server.dispatch(&mut host, SignalingMessage::Register(device("host")));
+        assert!(matches!(
+            host_rx.try_recv(),
+            Ok(SignalingMessage::Envelope(_))
+        ));
+        assert!(matches!(
+            server.dispatch(&mut mallory, SignalingMessage::Register(device("host"))),
+            Some(SignalingMessage::RegisterResponse { success: false, .. })
+        ));
+        assert!(mallory.device_id.is_none());
+        assert!(mallory_rx.try_recv().is_err());
+
+        let offer = SignalingMessage::Offer {
+            from: "viewer".to_string(),
+            to: "host".to_string(),
+            sdp: String::new(),
+        };
+        server.dispatch(&mut viewer, offer);
+        assert!(matches!(
+            host_rx.try_recv(),
+            Ok(SignalingMessage::Offer { .. })
+        ));
+
+        // Once the old connection is gone the device can register again
+        drop(host_rx);
+        assert!(matches!(
+            server.dispatch(&mut mallory, SignalingMessage::Register(device("host"))),
+            Some(SignalingMessage::RegisterResponse { success: true, .. })
+        ));
+    }
+}
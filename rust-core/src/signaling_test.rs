@@ -44,6 +44,10 @@ fn capabilities_strategy() -> impl Strategy<Value = DeviceCapabilities> {
             audio_capture: audio,
             file_transfer: file,
             input_control: input,
+            decoder: None,
+            keyboard_layout: None,
+            app_sharing: false,
+            data_compression: Vec::new(),
         },
     )
 }
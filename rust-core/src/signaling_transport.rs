@@ -0,0 +1,81 @@
+//! Signaling Transports
+//!
+//! `SignalingClient` speaks JSON-encoded `SignalingMessage`s over any
+//! bidirectional, ordered stream of text frames. A `SignalingTransport`
+//! opens one such stream to the server URL; the client handles everything
+//! above it (registration, queueing, heartbeats, reconnects).
+//!
+//! `WebSocketTransport` is the default. Deployments that route signaling
+//! over an MQTT broker or a gRPC bidirectional stream implement the trait
+//! and hand it to `SignalingClient::with_transport`.
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use futures_util::{future, Sink, SinkExt, Stream, StreamExt};
+use std::pin::Pin;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::{connect_async, tungstenite, tungstenite::Message, WebSocketStream};
+
+/// Outgoing half of a transport connection, one frame per message
+pub type FrameSink = Pin<Box<dyn Sink<String, Error = anyhow::Error> + Send>>;
+
+/// Incoming half of a transport connection; ends when the connection closes
+pub type FrameStream = Pin<Box<dyn Stream<Item = Result<String>> + Send>>;
+
+/// Opens connections that carry signaling frames
+pub trait SignalingTransport: Send + Sync {
+    /// Short name for logs
+    fn name(&self) -> &'static str;
+
+    /// Connect to `url`
+    fn connect<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<(FrameSink, FrameStream)>>;
+}
+
+/// Text frames over a WebSocket (`ws://` or `wss://`)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WebSocketTransport;
+
+impl SignalingTransport for WebSocketTransport {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    fn connect<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<(FrameSink, FrameStream)>> {
+        Box::pin(async move {
+            let url = url::Url::parse(url).context("Invalid signaling server URL")?;
+            let (ws_stream, _) = connect_async(url)
+                .await
+                .context("Failed to connect to signaling server")?;
+            Ok(websocket_frames(ws_stream))
+        })
+    }
+}
+
+/// Split an open WebSocket into text frame halves
+///
+/// Shared by the client transport and the built-in server.
+pub fn websocket_frames<S>(ws_stream: WebSocketStream<S>) -> (FrameSink, FrameStream)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (write, read) = ws_stream.split();
+    let sink = write
+        .with(|frame: String| future::ok::<_, tungstenite::Error>(Message::Text(frame)))
+        .sink_map_err(anyhow::Error::from);
+    let stream = read
+        .take_while(|msg| {
+            let open = !matches!(msg, Ok(Message::Close(_)));
+            if !open {
+                tracing::info!("WebSocket connection closed");
+            }
+            future::ready(open)
+        })
+        .filter_map(|msg| {
+            future::ready(match msg {
+                Ok(Message::Text(text)) => Some(Ok(text)),
+                Ok(_) => None,
+                Err(e) => Some(Err(anyhow::Error::from(e).context("WebSocket error"))),
+            })
+        });
+    (Box::pin(sink), Box::pin(stream))
+}
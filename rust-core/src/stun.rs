@@ -0,0 +1,1067 @@
+//! STUN and TURN Client
+//!
+//! Just enough of RFC 5389 (STUN) and RFC 5766 (TURN) for `NetworkManager`
+//! to learn its server-reflexive address and to hold a relay allocation:
+//! Binding, and Allocate / Refresh / CreatePermission with long-term
+//! credentials. Requests go over UDP, retransmitted with a doubling timeout,
+//! or over TCP or TLS (`stuns:` / `turns:`), where STUN messages are
+//! self-delimiting and sent once. `NatProbe` sends the Binding requests of
+//! NAT behaviour discovery (RFC 5780) for diagnostics.
+
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use md5::{Digest, Md5};
+use sha1::Sha1;
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio_native_tls::{native_tls, TlsConnector, TlsStream};
+
+/// Default port of `stun:` and `turn:` URLs
+pub const DEFAULT_STUN_PORT: u16 = 3478;
+
+/// Default port of `stuns:` and `turns:` URLs
+pub const DEFAULT_STUNS_PORT: u16 = 5349;
+
+/// Lifetime requested for TURN allocations (RFC 5766 default)
+pub const DEFAULT_ALLOCATION_LIFETIME: Duration = Duration::from_secs(600);
+
+const MAGIC_COOKIE: u32 = 0x2112_A442;
+const HEADER_LEN: usize = 20;
+const FINGERPRINT_XOR: u32 = 0x5354_554E;
+
+const METHOD_BINDING: u16 = 0x001;
+const METHOD_ALLOCATE: u16 = 0x003;
+const METHOD_REFRESH: u16 = 0x004;
+const METHOD_CREATE_PERMISSION: u16 = 0x008;
+
+const CLASS_REQUEST: u16 = 0x000;
+const CLASS_SUCCESS: u16 = 0x100;
+const CLASS_ERROR: u16 = 0x110;
+
+const ATTR_MAPPED_ADDRESS: u16 = 0x0001;
+const ATTR_CHANGE_REQUEST: u16 = 0x0003;
+const ATTR_CHANGED_ADDRESS: u16 = 0x0005;
+const ATTR_USERNAME: u16 = 0x0006;
+const ATTR_MESSAGE_INTEGRITY: u16 = 0x0008;
+const ATTR_ERROR_CODE: u16 = 0x0009;
+const ATTR_LIFETIME: u16 = 0x000D;
+const ATTR_XOR_PEER_ADDRESS: u16 = 0x0012;
+const ATTR_REALM: u16 = 0x0014;
+const ATTR_NONCE: u16 = 0x0015;
+const ATTR_XOR_RELAYED_ADDRESS: u16 = 0x0016;
+const ATTR_REQUESTED_TRANSPORT: u16 = 0x0019;
+const ATTR_XOR_MAPPED_ADDRESS: u16 = 0x0020;
+const ATTR_FINGERPRINT: u16 = 0x8028;
+const ATTR_OTHER_ADDRESS: u16 = 0x802C;
+
+const CHANGE_IP: u32 = 0x04;
+const CHANGE_PORT: u32 = 0x02;
+
+/// IANA protocol number for UDP, the only relay transport TURN defines
+const TRANSPORT_UDP: u8 = 17;
+
+/// Retransmission schedule of STUN transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StunConfig {
+    /// Wait for the first UDP response; doubled for each retransmission
+    pub initial_rto: Duration,
+    /// Requests sent over UDP before giving up
+    pub max_attempts: u32,
+}
+
+impl Default for StunConfig {
+    fn default() -> Self {
+        // RFC 5389 uses 7 attempts (39.5 s); a connection attempt cannot
+        // wait that long, so give up after 3.5 s
+        Self {
+            initial_rto: Duration::from_millis(500),
+            max_attempts: 3,
+        }
+    }
+}
+
+impl StunConfig {
+    /// Time a transaction may take in total
+    pub fn transaction_timeout(&self) -> Duration {
+        (0..self.max_attempts)
+            .map(|i| self.initial_rto * (1 << i))
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StunTransport {
+    Udp,
+    Tcp,
+    /// TCP wrapped in TLS, the server certificate checked against the host
+    Tls,
+}
+
+/// Parsed `stun:` or `turn:` URL (RFC 7064 / RFC 7065)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerAddress {
+    pub host: String,
+    pub port: u16,
+    pub transport: StunTransport,
+}
+
+impl ServerAddress {
+    pub fn parse(url: &str) -> Result<Self> {
+        let (scheme, rest) = url
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Not a STUN/TURN URL: {}", url))?;
+        let secure = match scheme {
+            "stun" | "turn" => false,
+            "stuns" | "turns" => true,
+            _ => return Err(anyhow::anyhow!("Not a STUN/TURN URL: {}", url)),
+        };
+
+        let (authority, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let transport = match (query.strip_prefix("transport="), secure) {
+            (None, false) if query.is_empty() => StunTransport::Udp,
+            (None, true) if query.is_empty() => StunTransport::Tls,
+            (Some("udp"), false) => StunTransport::Udp,
+            (Some("tcp"), false) => StunTransport::Tcp,
+            (Some("tcp"), true) => StunTransport::Tls,
+            // DTLS is not supported
+            _ => return Err(anyhow::anyhow!("Unsupported URL parameters: {}", url)),
+        };
+
+        // IPv6 literals are bracketed so their colons are not taken for a port
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(bracketed) => {
+                let (host, after) = bracketed
+                    .split_once(']')
+                    .ok_or_else(|| anyhow::anyhow!("Unclosed IPv6 literal: {}", url))?;
+                (host, after.strip_prefix(':'))
+            }
+            None => match authority.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        if host.is_empty() {
+            return Err(anyhow::anyhow!("Missing host: {}", url));
+        }
+        let port = match port {
+            Some(port) => port
+                .parse()
+                .with_context(|| format!("Invalid port in {}", url))?,
+            None if secure => DEFAULT_STUNS_PORT,
+            None => DEFAULT_STUN_PORT,
+        };
+
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            transport,
+        })
+    }
+
+    /// `turn:` or `turns:` URL of this address
+    pub fn to_turn_url(&self) -> String {
+        let host = if self.host.contains(':') {
+            format!("[{}]", self.host)
+        } else {
+            self.host.clone()
+        };
+        match self.transport {
+            StunTransport::Udp => format!("turn:{}:{}", host, self.port),
+            StunTransport::Tcp => format!("turn:{}:{}?transport=tcp", host, self.port),
+            StunTransport::Tls => format!("turns:{}:{}?transport=tcp", host, self.port),
+        }
+    }
+}
+
+/// Ask a STUN server for our server-reflexive address
+pub async fn binding_request(url: &str, config: &StunConfig) -> Result<SocketAddr> {
+    let server = ServerAddress::parse(url)?;
+    let mut channel = Channel::connect(&server).await?;
+    let request = Message::request(METHOD_BINDING, Vec::new());
+    let raw = channel.transact(&request.encode(None), config).await?;
+    let response = Message::decode(&raw)?;
+    if let Some((code, reason)) = response.error_code() {
+        return Err(anyhow::anyhow!("Binding rejected: {} {}", code, reason));
+    }
+    response.expect_success()?;
+    response.mapped_address()
+}
+
+/// Resolve the server address a `stun:` URL points at
+pub async fn resolve_server(url: &str) -> Result<SocketAddr> {
+    let server = ServerAddress::parse(url)?;
+    resolve(&server).await
+}
+
+/// Alternate source a NAT probe asks the server to answer from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChangeRequest {
+    pub ip: bool,
+    pub port: bool,
+}
+
+/// Answer to a NAT probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProbeResponse {
+    /// Our address as the server saw it
+    pub mapped: SocketAddr,
+    /// Where the answer came from
+    pub source: SocketAddr,
+    /// Second address of servers that honour CHANGE-REQUEST
+    pub other_address: Option<SocketAddr>,
+}
+
+/// Binding requests to several servers from one local UDP port
+///
+/// Behaviour discovery compares what a single mapping looks like from
+/// different servers, and whether answers from unexpected sources get
+/// through, so unlike `binding_request` the socket is not connected.
+pub struct NatProbe {
+    socket: UdpSocket,
+    config: StunConfig,
+}
+
+impl NatProbe {
+    /// Bind an IPv4 probe socket
+    pub async fn bind(config: &StunConfig) -> Result<Self> {
+        Ok(Self {
+            socket: UdpSocket::bind("0.0.0.0:0").await?,
+            config: *config,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Send a Binding request to `server`
+    ///
+    /// `None` if nothing came back, which several of the tests expect.
+    pub async fn binding(
+        &self,
+        server: SocketAddr,
+        change: ChangeRequest,
+    ) -> Result<Option<ProbeResponse>> {
+        let mut attributes = Vec::new();
+        if change.ip || change.port {
+            let mut flags = 0;
+            if change.ip {
+                flags |= CHANGE_IP;
+            }
+            if change.port {
+                flags |= CHANGE_PORT;
+            }
+            attributes.push((ATTR_CHANGE_REQUEST, flags.to_be_bytes().to_vec()));
+        }
+        let request = Message::request(METHOD_BINDING, attributes);
+        let raw = request.encode(None);
+
+        let mut buf = vec![0u8; 2048];
+        for attempt in 0..self.config.max_attempts {
+            self.socket.send_to(&raw, server).await?;
+            let deadline = tokio::time::Instant::now() + self.config.initial_rto * (1 << attempt);
+            while let Ok(received) =
+                tokio::time::timeout_at(deadline, self.socket.recv_from(&mut buf)).await
+            {
+                let (len, source) = received?;
+                if len < HEADER_LEN || buf[8..HEADER_LEN] != request.transaction_id {
+                    continue;
+                }
+                let response = Message::decode(&buf[..len])?;
+                if let Some((code, reason)) = response.error_code() {
+                    return Err(anyhow::anyhow!("Binding rejected: {} {}", code, reason));
+                }
+                response.expect_success()?;
+                let other_address = response
+                    .address(ATTR_OTHER_ADDRESS)
+                    .or_else(|| response.address(ATTR_CHANGED_ADDRESS))
+                    .transpose()?;
+                return Ok(Some(ProbeResponse {
+                    mapped: response.mapped_address()?,
+                    source,
+                    other_address,
+                }));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// A relay address held on a TURN server
+///
+/// Expires after `lifetime` unless refreshed. Dropping it without `release`
+/// leaves the server to time it out.
+pub struct TurnAllocation {
+    channel: Channel,
+    username: String,
+    realm: String,
+    nonce: String,
+    /// Long-term credential key, MD5(username:realm:password)
+    key: Vec<u8>,
+    password: String,
+    relayed: SocketAddr,
+    mapped: Option<SocketAddr>,
+    lifetime: Duration,
+    config: StunConfig,
+}
+
+impl std::fmt::Debug for TurnAllocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TurnAllocation")
+            .field("username", &self.username)
+            .field("realm", &self.realm)
+            .field("relayed", &self.relayed)
+            .field("mapped", &self.mapped)
+            .field("lifetime", &self.lifetime)
+            .finish()
+    }
+}
+
+impl TurnAllocation {
+    /// Allocate a UDP relay address
+    pub async fn allocate(
+        url: &str,
+        username: &str,
+        password: &str,
+        config: &StunConfig,
+    ) -> Result<Self> {
+        let server = ServerAddress::parse(url)?;
+        let channel = Channel::connect(&server).await?;
+        let mut allocation = Self {
+            channel,
+            username: username.to_string(),
+            realm: String::new(),
+            nonce: String::new(),
+            key: Vec::new(),
+            password: password.to_string(),
+            relayed: SocketAddr::from(([0, 0, 0, 0], 0)),
+            mapped: None,
+            lifetime: Duration::ZERO,
+            config: *config,
+        };
+
+        let response = allocation
+            .exchange(METHOD_ALLOCATE, |_| {
+                vec![
+                    (ATTR_REQUESTED_TRANSPORT, vec![TRANSPORT_UDP, 0, 0, 0]),
+                    (ATTR_LIFETIME, lifetime_value(DEFAULT_ALLOCATION_LIFETIME)),
+                ]
+            })
+            .await?;
+        allocation.relayed = response
+            .address(ATTR_XOR_RELAYED_ADDRESS)
+            .ok_or_else(|| anyhow::anyhow!("Allocate response without relayed address"))??;
+        allocation.mapped = response.mapped_address().ok();
+        allocation.lifetime = response.lifetime().unwrap_or(DEFAULT_ALLOCATION_LIFETIME);
+        Ok(allocation)
+    }
+
+    pub fn relayed_address(&self) -> SocketAddr {
+        self.relayed
+    }
+
+    /// Our address as seen by the TURN server
+    pub fn mapped_address(&self) -> Option<SocketAddr> {
+        self.mapped
+    }
+
+    /// Lifetime granted by the last Allocate or Refresh
+    pub fn lifetime(&self) -> Duration {
+        self.lifetime
+    }
+
+    /// Extend the allocation; returns the lifetime the server granted
+    pub async fn refresh(&mut self, lifetime: Duration) -> Result<Duration> {
+        let response = self
+            .exchange(METHOD_REFRESH, |_| {
+                vec![(ATTR_LIFETIME, lifetime_value(lifetime))]
+            })
+            .await?;
+        self.lifetime = response.lifetime().unwrap_or(lifetime);
+        Ok(self.lifetime)
+    }
+
+    /// Let `peers` send to the relayed address
+    ///
+    /// Permissions last five minutes and are renewed by calling this again.
+    pub async fn create_permission(&mut self, peers: &[IpAddr]) -> Result<()> {
+        self.exchange(METHOD_CREATE_PERMISSION, |transaction_id| {
+            peers
+                .iter()
+                .map(|ip| {
+                    (
+                        ATTR_XOR_PEER_ADDRESS,
+                        encode_xor_address(SocketAddr::new(*ip, 0), transaction_id),
+                    )
+                })
+                .collect()
+        })
+        .await
+        .map(|_| ())
+    }
+
+    /// Give the relayed address back to the server
+    pub async fn release(mut self) -> Result<()> {
+        self.refresh(Duration::ZERO).await.map(|_| ())
+    }
+
+    /// Send an authenticated request, answering credential and nonce
+    /// challenges
+    ///
+    /// `attributes` builds the request attributes for a transaction ID, as
+    /// XOR-PEER-ADDRESS depends on it.
+    async fn exchange(
+        &mut self,
+        method: u16,
+        attributes: impl Fn(&[u8; 12]) -> Vec<(u16, Vec<u8>)>,
+    ) -> Result<Message> {
+        // An unauthenticated first request, then possibly a stale nonce
+        for _ in 0..3 {
+            let transaction_id = new_transaction_id();
+            let mut request = Message {
+                method,
+                class: CLASS_REQUEST,
+                transaction_id,
+                attributes: attributes(&transaction_id),
+            };
+            let authenticated = !self.realm.is_empty();
+            if authenticated {
+                request.attributes.extend([
+                    (ATTR_USERNAME, self.username.as_bytes().to_vec()),
+                    (ATTR_REALM, self.realm.as_bytes().to_vec()),
+                    (ATTR_NONCE, self.nonce.as_bytes().to_vec()),
+                ]);
+            }
+            let key = authenticated.then_some(self.key.as_slice());
+            let raw = self
+                .channel
+                .transact(&request.encode(key), &self.config)
+                .await?;
+            let response = Message::decode(&raw)?;
+
+            match response.error_code() {
+                None => {
+                    response.expect_success()?;
+                    if key.is_some_and(|key| !verify_integrity(&raw, key)) {
+                        return Err(anyhow::anyhow!("TURN response failed the integrity check"));
+                    }
+                    return Ok(response);
+                }
+                Some((401, _)) if !authenticated => {
+                    self.realm = response.text(ATTR_REALM).unwrap_or_default();
+                    self.nonce = response.text(ATTR_NONCE).unwrap_or_default();
+                    if self.realm.is_empty() {
+                        return Err(anyhow::anyhow!("401 challenge without realm"));
+                    }
+                    self.key = long_term_key(&self.username, &self.realm, &self.password);
+                }
+                Some((438, _)) => {
+                    self.nonce = response
+                        .text(ATTR_NONCE)
+                        .ok_or_else(|| anyhow::anyhow!("438 Stale Nonce without a nonce"))?;
+                }
+                Some((code, reason)) => {
+                    return Err(anyhow::anyhow!(
+                        "TURN request rejected: {} {}",
+                        code,
+                        reason
+                    ));
+                }
+            }
+        }
+        Err(anyhow::anyhow!("TURN server kept rejecting credentials"))
+    }
+}
+
+/// Socket to one STUN/TURN server
+enum Channel {
+    Udp(UdpSocket),
+    Tcp(TcpStream),
+    Tls(Box<TlsStream<TcpStream>>),
+}
+
+impl Channel {
+    async fn connect(server: &ServerAddress) -> Result<Self> {
+        let address = resolve(server).await?;
+        match server.transport {
+            StunTransport::Udp => {
+                let bind: SocketAddr = if address.is_ipv6() {
+                    "[::]:0".parse()?
+                } else {
+                    "0.0.0.0:0".parse()?
+                };
+                let socket = UdpSocket::bind(bind).await?;
+                socket.connect(address).await?;
+                Ok(Self::Udp(socket))
+            }
+            StunTransport::Tcp => Ok(Self::Tcp(TcpStream::connect(address).await?)),
+            StunTransport::Tls => {
+                let stream = TcpStream::connect(address).await?;
+                let connector = TlsConnector::from(native_tls::TlsConnector::new()?);
+                let stream = connector
+                    .connect(&server.host, stream)
+                    .await
+                    .with_context(|| format!("TLS handshake with {} failed", server.host))?;
+                Ok(Self::Tls(Box::new(stream)))
+            }
+        }
+    }
+
+    /// Send a request and wait for the response with its transaction ID
+    async fn transact(&mut self, request: &[u8], config: &StunConfig) -> Result<Vec<u8>> {
+        let transaction_id = &request[8..HEADER_LEN];
+        match self {
+            Self::Udp(socket) => {
+                let mut buf = vec![0u8; 2048];
+                for attempt in 0..config.max_attempts {
+                    socket.send(request).await?;
+                    let deadline =
+                        tokio::time::Instant::now() + config.initial_rto * (1 << attempt);
+                    // Stray datagrams (late responses to earlier attempts of
+                    // another transaction) are skipped
+                    while let Ok(received) =
+                        tokio::time::timeout_at(deadline, socket.recv(&mut buf)).await
+                    {
+                        let len = received?;
+                        if len >= HEADER_LEN && &buf[8..HEADER_LEN] == transaction_id {
+                            return Ok(buf[..len].to_vec());
+                        }
+                    }
+                }
+                Err(anyhow::anyhow!(
+                    "No STUN response after {} attempts",
+                    config.max_attempts
+                ))
+            }
+            Self::Tcp(stream) => transact_stream(stream, request, config).await,
+            Self::Tls(stream) => transact_stream(stream.as_mut(), request, config).await,
+        }
+    }
+}
+
+/// One transaction on a stream transport, where each message goes out once
+async fn transact_stream<S>(stream: &mut S, request: &[u8], config: &StunConfig) -> Result<Vec<u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let transaction_id = &request[8..HEADER_LEN];
+    stream.write_all(request).await?;
+    tokio::time::timeout(config.transaction_timeout(), async {
+        loop {
+            let mut message = vec![0u8; HEADER_LEN];
+            stream.read_exact(&mut message).await?;
+            let len = u16::from_be_bytes([message[2], message[3]]) as usize;
+            message.resize(HEADER_LEN + len, 0);
+            stream.read_exact(&mut message[HEADER_LEN..]).await?;
+            if &message[8..HEADER_LEN] == transaction_id {
+                return Ok(message);
+            }
+        }
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("No STUN response over a stream transport"))?
+}
+
+async fn resolve(server: &ServerAddress) -> Result<SocketAddr> {
+    tokio::net::lookup_host((server.host.as_str(), server.port))
+        .await
+        .with_context(|| format!("Cannot resolve {}", server.host))?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No address for {}", server.host))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Message {
+    method: u16,
+    class: u16,
+    transaction_id: [u8; 12],
+    attributes: Vec<(u16, Vec<u8>)>,
+}
+
+impl Message {
+    fn request(method: u16, attributes: Vec<(u16, Vec<u8>)>) -> Self {
+        Self {
+            method,
+            class: CLASS_REQUEST,
+            transaction_id: new_transaction_id(),
+            attributes,
+        }
+    }
+
+    /// Serialise, adding MESSAGE-INTEGRITY when a key is given, and
+    /// FINGERPRINT
+    fn encode(&self, integrity_key: Option<&[u8]>) -> Vec<u8> {
+        let mut out = Vec::with_capacity(128);
+        out.extend_from_slice(&message_type(self.method, self.class).to_be_bytes());
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(&MAGIC_COOKIE.to_be_bytes());
+        out.extend_from_slice(&self.transaction_id);
+        for (kind, value) in &self.attributes {
+            push_attribute(&mut out, *kind, value);
+        }
+        // Each trailer is computed with the length already covering it
+        if let Some(key) = integrity_key {
+            set_length(&mut out, 24);
+            let mac = hmac_sha1(key, &out);
+            push_attribute(&mut out, ATTR_MESSAGE_INTEGRITY, &mac);
+        }
+        set_length(&mut out, 8);
+        let crc = crc32fast::hash(&out) ^ FINGERPRINT_XOR;
+        push_attribute(&mut out, ATTR_FINGERPRINT, &crc.to_be_bytes());
+        out
+    }
+
+    fn decode(raw: &[u8]) -> Result<Self> {
+        if raw.len() < HEADER_LEN || raw[0] & 0xC0 != 0 {
+            return Err(anyhow::anyhow!("Not a STUN message"));
+        }
+        if raw[4..8] != MAGIC_COOKIE.to_be_bytes() {
+            return Err(anyhow::anyhow!("STUN message without magic cookie"));
+        }
+        let length = u16::from_be_bytes([raw[2], raw[3]]) as usize;
+        if !length.is_multiple_of(4) || raw.len() != HEADER_LEN + length {
+            return Err(anyhow::anyhow!("Malformed STUN message length"));
+        }
+
+        let message_type = u16::from_be_bytes([raw[0], raw[1]]);
+        let mut transaction_id = [0u8; 12];
+        transaction_id.copy_from_slice(&raw[8..HEADER_LEN]);
+        let mut attributes = Vec::new();
+        let mut rest = &raw[HEADER_LEN..];
+        while !rest.is_empty() {
+            if rest.len() < 4 {
+                return Err(anyhow::anyhow!("Truncated STUN attribute"));
+            }
+            let kind = u16::from_be_bytes([rest[0], rest[1]]);
+            let len = u16::from_be_bytes([rest[2], rest[3]]) as usize;
+            let padded = (len + 3) & !3;
+            if rest.len() < 4 + padded {
+                return Err(anyhow::anyhow!("Truncated STUN attribute"));
+            }
+            attributes.push((kind, rest[4..4 + len].to_vec()));
+            rest = &rest[4 + padded..];
+        }
+
+        Ok(Self {
+            method: (message_type & 0x000F)
+                | ((message_type >> 1) & 0x0070)
+                | ((message_type >> 2) & 0x0F80),
+            class: message_type & 0x0110,
+            transaction_id,
+            attributes,
+        })
+    }
+
+    /// Anything but a success or error response to our request is bogus
+    fn expect_success(&self) -> Result<()> {
+        if self.class != CLASS_SUCCESS {
+            return Err(anyhow::anyhow!(
+                "Unexpected STUN message class {:#05x}",
+                self.class
+            ));
+        }
+        Ok(())
+    }
+
+    fn attribute(&self, kind: u16) -> Option<&[u8]> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| *k == kind)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    fn text(&self, kind: u16) -> Option<String> {
+        self.attribute(kind)
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+    }
+
+    fn address(&self, kind: u16) -> Option<Result<SocketAddr>> {
+        let xor = !matches!(
+            kind,
+            ATTR_MAPPED_ADDRESS | ATTR_CHANGED_ADDRESS | ATTR_OTHER_ADDRESS
+        );
+        self.attribute(kind)
+            .map(|value| decode_address(value, xor.then_some(&self.transaction_id)))
+    }
+
+    /// XOR-MAPPED-ADDRESS, or MAPPED-ADDRESS from RFC 3489 servers
+    fn mapped_address(&self) -> Result<SocketAddr> {
+        self.address(ATTR_XOR_MAPPED_ADDRESS)
+            .or_else(|| self.address(ATTR_MAPPED_ADDRESS))
+            .ok_or_else(|| anyhow::anyhow!("STUN response without mapped address"))?
+    }
+
+    fn lifetime(&self) -> Option<Duration> {
+        let value: [u8; 4] = self.attribute(ATTR_LIFETIME)?.try_into().ok()?;
+        Some(Duration::from_secs(u32::from_be_bytes(value).into()))
+    }
+
+    /// Code and reason of an error response
+    fn error_code(&self) -> Option<(u16, String)> {
+        if self.class != CLASS_ERROR {
+            return None;
+        }
+        Some(match self.attribute(ATTR_ERROR_CODE) {
+            Some(value) if value.len() >= 4 => (
+                u16::from(value[2] & 0x07) * 100 + u16::from(value[3]),
+                String::from_utf8_lossy(&value[4..]).into_owned(),
+            ),
+            _ => (500, "error response without ERROR-CODE".to_string()),
+        })
+    }
+}
+
+fn message_type(method: u16, class: u16) -> u16 {
+    (method & 0x000F) | ((method & 0x0070) << 1) | ((method & 0x0F80) << 2) | class
+}
+
+fn new_transaction_id() -> [u8; 12] {
+    rand::random()
+}
+
+fn push_attribute(out: &mut Vec<u8>, kind: u16, value: &[u8]) {
+    out.extend_from_slice(&kind.to_be_bytes());
+    out.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    out.extend_from_slice(value);
+    out.resize((out.len() + 3) & !3, 0);
+}
+
+/// Set the header length to the attributes so far plus `trailer` bytes
+fn set_length(out: &mut [u8], trailer: usize) {
+    let length = (out.len() - HEADER_LEN + trailer) as u16;
+    out[2..4].copy_from_slice(&length.to_be_bytes());
+}
+
+fn hmac_sha1(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn long_term_key(username: &str, realm: &str, password: &str) -> Vec<u8> {
+    Md5::digest(format!("{}:{}:{}", username, realm, password)).to_vec()
+}
+
+/// Check MESSAGE-INTEGRITY of a received message; false if it has none
+fn verify_integrity(raw: &[u8], key: &[u8]) -> bool {
+    let mut offset = HEADER_LEN;
+    while offset + 4 <= raw.len() {
+        let kind = u16::from_be_bytes([raw[offset], raw[offset + 1]]);
+        let len = u16::from_be_bytes([raw[offset + 2], raw[offset + 3]]) as usize;
+        if kind == ATTR_MESSAGE_INTEGRITY {
+            let Some(expected) = raw.get(offset + 4..offset + 4 + 20) else {
+                return false;
+            };
+            let mut covered = raw[..offset].to_vec();
+            set_length(&mut covered, 24);
+            let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any length");
+            mac.update(&covered);
+            return mac.verify_slice(expected).is_ok();
+        }
+        offset += 4 + ((len + 3) & !3);
+    }
+    false
+}
+
+fn lifetime_value(lifetime: Duration) -> Vec<u8> {
+    (lifetime.as_secs().min(u32::MAX.into()) as u32)
+        .to_be_bytes()
+        .to_vec()
+}
+
+/// XOR mask for an address: the magic cookie, then the transaction ID
+fn xor_mask(transaction_id: &[u8; 12]) -> [u8; 16] {
+    let mut mask = [0u8; 16];
+    mask[..4].copy_from_slice(&MAGIC_COOKIE.to_be_bytes());
+    mask[4..].copy_from_slice(transaction_id);
+    mask
+}
+
+fn encode_xor_address(addr: SocketAddr, transaction_id: &[u8; 12]) -> Vec<u8> {
+    let mask = xor_mask(transaction_id);
+    let port = addr.port() ^ (MAGIC_COOKIE >> 16) as u16;
+    let (family, ip): (u8, Vec<u8>) = match addr.ip() {
+        IpAddr::V4(ip) => (0x01, ip.octets().to_vec()),
+        IpAddr::V6(ip) => (0x02, ip.octets().to_vec()),
+    };
+    let mut value = vec![0, family];
+    value.extend_from_slice(&port.to_be_bytes());
+    value.extend(ip.iter().zip(mask).map(|(b, m)| b ^ m));
+    value
+}
+
+fn decode_address(value: &[u8], xor_with: Option<&[u8; 12]>) -> Result<SocketAddr> {
+    let ip_len = match value.get(1) {
+        Some(0x01) => 4,
+        Some(0x02) => 16,
+        _ => return Err(anyhow::anyhow!("Unknown STUN address family")),
+    };
+    if value.len() != 4 + ip_len {
+        return Err(anyhow::anyhow!("Malformed STUN address"));
+    }
+    let mask = xor_with.map(xor_mask).unwrap_or([0; 16]);
+    let port = u16::from_be_bytes([value[2], value[3]]) ^ u16::from_be_bytes([mask[0], mask[1]]);
+    let mut ip = [0u8; 16];
+    for (i, b) in value[4..].iter().enumerate() {
+        ip[i] = b ^ mask[i];
+    }
+    let ip = if ip_len == 4 {
+        IpAddr::from([ip[0], ip[1], ip[2], ip[3]])
+    } else {
+        IpAddr::from(ip)
+    };
+    Ok(SocketAddr::new(ip, port))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ATTR_SOFTWARE: u16 = 0x8022;
+
+    /// Build a response to `request` the way a server would
+    fn respond(
+        request: &Message,
+        class: u16,
+        attributes: Vec<(u16, Vec<u8>)>,
+        key: Option<&[u8]>,
+    ) -> Vec<u8> {
+        Message {
+            method: request.method,
+            class,
+            transaction_id: request.transaction_id,
+            attributes,
+        }
+        .encode(key)
+    }
+
+    fn error(code: u16, reason: &str) -> Vec<u8> {
+        let mut value = vec![0, 0, (code / 100) as u8, (code % 100) as u8];
+        value.extend_from_slice(reason.as_bytes());
+        value
+    }
+
+    #[test]
+    fn test_rfc5769_response_vector() {
+        let raw: [u8; 80] = [
+            0x01, 0x01, 0x00, 0x3c, 0x21, 0x12, 0xa4, 0x42, 0xb7, 0xe7, 0xa7, 0x01, 0xbc, 0x34,
+            0xd6, 0x86, 0xfa, 0x87, 0xdf, 0xae, 0x80, 0x22, 0x00, 0x0b, 0x74, 0x65, 0x73, 0x74,
+            0x20, 0x76, 0x65, 0x63, 0x74, 0x6f, 0x72, 0x20, 0x00, 0x20, 0x00, 0x08, 0x00, 0x01,
+            0xa1, 0x47, 0xe1, 0x12, 0xa6, 0x43, 0x00, 0x08, 0x00, 0x14, 0x2b, 0x91, 0xf5, 0x99,
+            0xfd, 0x9e, 0x90, 0xc3, 0x8c, 0x74, 0x89, 0xf9, 0x2a, 0xf9, 0xba, 0x53, 0xf0, 0x6b,
+            0xe7, 0xd7, 0x80, 0x28, 0x00, 0x04, 0xc0, 0x7d, 0x4c, 0x96,
+        ];
+        let message = Message::decode(&raw).unwrap();
+        assert_eq!(
+            (message.method, message.class),
+            (METHOD_BINDING, CLASS_SUCCESS)
+        );
+        assert_eq!(message.text(ATTR_SOFTWARE).unwrap(), "test vector");
+        assert_eq!(
+            message.mapped_address().unwrap(),
+            "192.0.2.1:32853".parse().unwrap()
+        );
+        assert!(verify_integrity(&raw, b"VOkJxbRl1RmTxUk/WvJxBt"));
+        assert!(!verify_integrity(&raw, b"wrong"));
+
+        // Our own encoding reproduces the integrity and fingerprint
+        let reencoded = Message {
+            attributes: message.attributes[..2].to_vec(),
+            ..message
+        }
+        .encode(Some(b"VOkJxbRl1RmTxUk/WvJxBt"));
+        // SOFTWARE padding differs: the vector pads with spaces
+        assert_eq!(reencoded.len(), raw.len());
+        assert!(verify_integrity(&reencoded, b"VOkJxbRl1RmTxUk/WvJxBt"));
+
+        assert_eq!(
+            ServerAddress::parse("turn:[2001:db8::1]?transport=tcp").unwrap(),
+            ServerAddress {
+                host: "2001:db8::1".to_string(),
+                port: DEFAULT_STUN_PORT,
+                transport: StunTransport::Tcp,
+            }
+        );
+        assert_eq!(
+            ServerAddress::parse("stun:example.org:19302").unwrap().port,
+            19302
+        );
+        let tls = ServerAddress::parse("turns:example.org").unwrap();
+        assert_eq!(
+            (tls.port, tls.transport),
+            (DEFAULT_STUNS_PORT, StunTransport::Tls)
+        );
+        assert_eq!(tls.to_turn_url(), "turns:example.org:5349?transport=tcp");
+        assert_eq!(ServerAddress::parse(&tls.to_turn_url()).unwrap(), tls);
+        assert!(ServerAddress::parse("turns:example.org?transport=udp").is_err());
+        assert!(ServerAddress::parse("http://example.org").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_binding_over_udp_and_tcp() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("stun:{}", server.local_addr().unwrap());
+        let serve = tokio::spawn(async move {
+            let mut buf = [0u8; 1500];
+            // Lose the first request to exercise retransmission
+            server.recv_from(&mut buf).await.unwrap();
+            let (len, from) = server.recv_from(&mut buf).await.unwrap();
+            let request = Message::decode(&buf[..len]).unwrap();
+            let mapped = encode_xor_address(from, &request.transaction_id);
+            let response = respond(
+                &request,
+                CLASS_SUCCESS,
+                vec![(ATTR_XOR_MAPPED_ADDRESS, mapped)],
+                None,
+            );
+            server.send_to(&response, from).await.unwrap();
+        });
+        let config = StunConfig {
+            initial_rto: Duration::from_millis(50),
+            max_attempts: 3,
+        };
+        let mapped = binding_request(&url, &config).await.unwrap();
+        assert_eq!(mapped.ip(), IpAddr::from([127, 0, 0, 1]));
+        serve.await.unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("stun:{}?transport=tcp", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut stream, from) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; HEADER_LEN];
+            stream.read_exact(&mut buf).await.unwrap();
+            let len = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+            buf.resize(HEADER_LEN + len, 0);
+            stream.read_exact(&mut buf[HEADER_LEN..]).await.unwrap();
+            let request = Message::decode(&buf).unwrap();
+            // A pre-RFC 5389 server: plain MAPPED-ADDRESS
+            let mut mapped = vec![0, 0x01];
+            mapped.extend_from_slice(&from.port().to_be_bytes());
+            mapped.extend_from_slice(&[127, 0, 0, 1]);
+            let response = respond(
+                &request,
+                CLASS_SUCCESS,
+                vec![(ATTR_MAPPED_ADDRESS, mapped)],
+                None,
+            );
+            stream.write_all(&response).await.unwrap();
+        });
+        let mapped = binding_request(&url, &config).await.unwrap();
+        assert_eq!(mapped.ip(), IpAddr::from([127, 0, 0, 1]));
+
+        // Nobody answers
+        let silent = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("stun:{}", silent.local_addr().unwrap());
+        assert!(binding_request(&url, &config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_turn_allocation_lifecycle() {
+        let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("turn:{}", server.local_addr().unwrap());
+        let key = long_term_key("alice", "example.org", "secret");
+        let serve = tokio::spawn(async move {
+            let mut buf = [0u8; 1500];
+            let mut methods = Vec::new();
+            let mut nonce_used = false;
+            loop {
+                let (len, from) = server.recv_from(&mut buf).await.unwrap();
+                let raw = &buf[..len];
+                let request = Message::decode(raw).unwrap();
+                methods.push(request.method);
+                let challenge = |code: u16, reason: &str, nonce: &str| {
+                    respond(
+                        &request,
+                        CLASS_ERROR,
+                        vec![
+                            (ATTR_ERROR_CODE, error(code, reason)),
+                            (ATTR_REALM, b"example.org".to_vec()),
+                            (ATTR_NONCE, nonce.as_bytes().to_vec()),
+                        ],
+                        None,
+                    )
+                };
+                let response =
+                    if request.attribute(ATTR_USERNAME).is_none() || !verify_integrity(raw, &key) {
+                        challenge(401, "Unauthorized", "n1")
+                    } else if request.method == METHOD_REFRESH && !nonce_used {
+                        // The nonce expires between allocate and refresh
+                        nonce_used = true;
+                        challenge(438, "Stale Nonce", "n2")
+                    } else {
+                        let attributes = match request.method {
+                            METHOD_ALLOCATE => vec![
+                                (
+                                    ATTR_XOR_RELAYED_ADDRESS,
+                                    encode_xor_address(
+                                        "198.51.100.7:49152".parse().unwrap(),
+                                        &request.transaction_id,
+                                    ),
+                                ),
+                                (
+                                    ATTR_XOR_MAPPED_ADDRESS,
+                                    encode_xor_address(from, &request.transaction_id),
+                                ),
+                                (ATTR_LIFETIME, lifetime_value(DEFAULT_ALLOCATION_LIFETIME)),
+                            ],
+                            METHOD_REFRESH => vec![(
+                                ATTR_LIFETIME,
+                                request.attribute(ATTR_LIFETIME).unwrap().to_vec(),
+                            )],
+                            _ => Vec::new(),
+                        };
+                        respond(&request, CLASS_SUCCESS, attributes, Some(&key))
+                    };
+                server.send_to(&response, from).await.unwrap();
+                if request.method == METHOD_REFRESH
+                    && request.attribute(ATTR_LIFETIME) == Some(&[0; 4])
+                {
+                    return methods;
+                }
+            }
+        });
+
+        let config = StunConfig::default();
+        let err = TurnAllocation::allocate(&url, "alice", "wrong", &config)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("401"), "{}", err);
+
+        let mut allocation = TurnAllocation::allocate(&url, "alice", "secret", &config)
+            .await
+            .unwrap();
+        assert_eq!(
+            allocation.relayed_address(),
+            "198.51.100.7:49152".parse().unwrap()
+        );
+        assert!(allocation.mapped_address().unwrap().ip().is_loopback());
+        assert_eq!(allocation.lifetime(), DEFAULT_ALLOCATION_LIFETIME);
+
+        assert_eq!(
+            allocation.refresh(Duration::from_secs(300)).await.unwrap(),
+            Duration::from_secs(300)
+        );
+        allocation
+            .create_permission(&[IpAddr::from([203, 0, 113, 9])])
+            .await
+            .unwrap();
+        allocation.release().await.unwrap();
+
+        let methods = serve.await.unwrap();
+        assert_eq!(
+            methods,
+            [
+                METHOD_ALLOCATE,
+                METHOD_ALLOCATE,
+                METHOD_ALLOCATE,
+                METHOD_ALLOCATE,
+                METHOD_REFRESH,
+                METHOD_REFRESH,
+                METHOD_CREATE_PERMISSION,
+                METHOD_REFRESH
+            ]
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Dual Monotonic / Wall-Clock Timestamps
+//!
+//! `Instant` is immune to clock changes but cannot be serialized, while
+//! wall-clock time survives restarts but can jump. `Timestamp` carries both:
+//! within the process that created it, elapsed-time checks use the monotonic
+//! clock; after a round-trip through serde only the wall-clock value remains
+//! and checks fall back to it.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::ops::{Add, Sub};
+use std::time::{Duration, Instant};
+
+/// How far in the future a restored wall-clock timestamp may be before it is
+/// treated as untrustworthy (the system clock was moved backwards)
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(300);
+
+/// Point in time with a monotonic and a wall-clock component
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Timestamp {
+    /// Wall-clock time, persisted as RFC 3339
+    wall_clock: DateTime<Utc>,
+    /// Monotonic time; only meaningful inside the creating process
+    #[serde(skip)]
+    monotonic: Option<Instant>,
+}
+
+impl Timestamp {
+    /// Capture the current time
+    pub fn now() -> Self {
+        Self {
+            wall_clock: Utc::now(),
+            monotonic: Some(Instant::now()),
+        }
+    }
+
+    /// Build a timestamp from readings of both clocks
+    pub fn from_parts(wall_clock: DateTime<Utc>, monotonic: Instant) -> Self {
+        Self {
+            wall_clock,
+            monotonic: Some(monotonic),
+        }
+    }
+
+    /// Build a timestamp from a wall-clock value only
+    pub fn from_wall_clock(wall_clock: DateTime<Utc>) -> Self {
+        Self {
+            wall_clock,
+            monotonic: None,
+        }
+    }
+
+    /// Wall-clock component
+    pub fn wall_clock(&self) -> DateTime<Utc> {
+        self.wall_clock
+    }
+
+    /// Whether the monotonic component is available (same process)
+    pub fn has_monotonic(&self) -> bool {
+        self.monotonic.is_some()
+    }
+
+    /// Signed wall-clock time from this timestamp to `now`, in milliseconds
+    fn wall_elapsed_ms(&self, now: &Timestamp) -> i64 {
+        (now.wall_clock - self.wall_clock).num_milliseconds()
+    }
+
+    /// Time elapsed since this timestamp (zero if it lies in the future)
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed_at(&Timestamp::now())
+    }
+
+    /// Time from this timestamp to `now` (zero if `now` is earlier)
+    ///
+    /// Monotonic when both carry a monotonic component, wall-clock otherwise.
+    pub fn elapsed_at(&self, now: &Timestamp) -> Duration {
+        match (self.monotonic, now.monotonic) {
+            (Some(then), Some(now)) => now.saturating_duration_since(then),
+            _ => Duration::from_millis(self.wall_elapsed_ms(now).max(0) as u64),
+        }
+    }
+
+    /// Whether at least `duration` has passed since this timestamp
+    ///
+    /// A restored timestamp that lies further in the future than
+    /// `MAX_CLOCK_SKEW` means the clock moved backwards; it is treated as
+    /// elapsed so that expiring credentials fail closed.
+    pub fn has_elapsed(&self, duration: Duration) -> bool {
+        self.has_elapsed_at(duration, &Timestamp::now())
+    }
+
+    /// `has_elapsed` as seen at `now`
+    pub fn has_elapsed_at(&self, duration: Duration, now: &Timestamp) -> bool {
+        if self.monotonic.is_none()
+            && self.wall_elapsed_ms(now) < -(MAX_CLOCK_SKEW.as_millis() as i64)
+        {
+            return true;
+        }
+        self.elapsed_at(now) > duration
+    }
+
+    /// Time left until `duration` has elapsed since this timestamp
+    pub fn remaining(&self, duration: Duration) -> Duration {
+        self.remaining_at(duration, &Timestamp::now())
+    }
+
+    /// `remaining` as seen at `now`
+    pub fn remaining_at(&self, duration: Duration, now: &Timestamp) -> Duration {
+        if self.has_elapsed_at(duration, now) {
+            Duration::ZERO
+        } else {
+            duration.saturating_sub(self.elapsed_at(now))
+        }
+    }
+
+    /// Whether this timestamp is now or in the past
+    pub fn is_past(&self) -> bool {
+        self.is_past_at(&Timestamp::now())
+    }
+
+    /// Whether this timestamp is at or before `now`
+    pub fn is_past_at(&self, now: &Timestamp) -> bool {
+        match (self.monotonic, now.monotonic) {
+            (Some(then), Some(now)) => now >= then,
+            _ => self.wall_elapsed_ms(now) >= 0,
+        }
+    }
+}
+
+impl Add<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn add(self, rhs: Duration) -> Timestamp {
+        Timestamp {
+            wall_clock: self.wall_clock
+                + chrono::Duration::from_std(rhs).unwrap_or(chrono::Duration::MAX),
+            monotonic: self.monotonic.and_then(|m| m.checked_add(rhs)),
+        }
+    }
+}
+
+impl Sub<Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn sub(self, rhs: Duration) -> Timestamp {
+        Timestamp {
+            wall_clock: self.wall_clock
+                - chrono::Duration::from_std(rhs).unwrap_or(chrono::Duration::MAX),
+            monotonic: self.monotonic.and_then(|m| m.checked_sub(rhs)),
+        }
+    }
+}
+
+impl Default for Timestamp {
+    fn default() -> Self {
+        Self::now()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_elapsed_survives_serialization() {
+        let ts = Timestamp::now() - Duration::from_secs(120);
+        assert!(ts.has_elapsed(Duration::from_secs(60)));
+        assert!(!ts.has_elapsed(Duration::from_secs(600)));
+
+        let json = serde_json::to_string(&ts).unwrap();
+        let restored: Timestamp = serde_json::from_str(&json).unwrap();
+        assert!(!restored.has_monotonic());
+        assert_eq!(restored.wall_clock(), ts.wall_clock());
+        assert!(restored.has_elapsed(Duration::from_secs(60)));
+        assert!(!restored.has_elapsed(Duration::from_secs(600)));
+        assert!(restored.remaining(Duration::from_secs(600)) <= Duration::from_secs(480));
+    }
+
+    #[test]
+    fn test_backwards_clock_fails_closed() {
+        // Restored timestamp far in the future: clock was moved backwards
+        let future = Timestamp::from_wall_clock(Utc::now() + chrono::Duration::hours(2));
+        assert!(future.has_elapsed(Duration::from_secs(600)));
+        assert_eq!(future.remaining(Duration::from_secs(600)), Duration::ZERO);
+
+        // Small skew is tolerated
+        let near = Timestamp::from_wall_clock(Utc::now() + chrono::Duration::seconds(10));
+        assert!(!near.has_elapsed(Duration::from_secs(600)));
+        assert!(!near.is_past());
+    }
+}
@@ -0,0 +1,408 @@
+//! Persisted Transfer State
+//!
+//! Every active transfer keeps a manifest in `<data_dir>/transfers/<id>.json`
+//! recording which chunks have gone through (a bitmap), the SHA-256 of each
+//! of them and, for incoming files, the partial file the data is written to.
+//! After a restart `FileTransfer::interrupted_transfers` lists what was left
+//! behind and `FileTransfer::resume_interrupted` picks a transfer up again
+//! once the session with the peer is back, so only missing chunks are sent.
+//!
+//! Senders also record the SHA-256 of the whole file and of every chunk as
+//! `TransferChecksums`; a receiver given them verifies the completed file and
+//! re-requests the chunks that do not match.
+//!
+//! Manifests and partial files untouched for `PARTIAL_TRANSFER_MAX_AGE` are
+//! deleted instead of being offered for resume.
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Directory under the data dir holding manifests and partial files
+pub const TRANSFER_STATE_DIR_NAME: &str = "transfers";
+
+/// Extension of partial incoming files
+pub const PARTIAL_FILE_EXTENSION: &str = "part";
+
+/// Size of the chunks tracked by a manifest
+pub const TRANSFER_CHUNK_SIZE: u64 = 256 * 1024;
+
+/// Interrupted transfers older than this are discarded
+pub const PARTIAL_TRANSFER_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferDirection {
+    Outgoing,
+    Incoming,
+}
+
+/// SHA-256 of a file as the sender read it, hex encoded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TransferChecksums {
+    pub file_hash: String,
+    /// One per `TRANSFER_CHUNK_SIZE` chunk
+    pub chunk_hashes: Vec<String>,
+}
+
+impl TransferChecksums {
+    /// Hash a file in one pass; blocking
+    pub fn compute(path: &Path) -> Result<Self> {
+        use std::io::Read;
+
+        let mut file = std::fs::File::open(path)?;
+        let mut file_hasher = Sha256::new();
+        let mut chunk_hashes = Vec::new();
+        let mut buffer = vec![0u8; TRANSFER_CHUNK_SIZE as usize];
+        loop {
+            let mut len = 0;
+            while len < buffer.len() {
+                match file.read(&mut buffer[len..])? {
+                    0 => break,
+                    n => len += n,
+                }
+            }
+            if len == 0 {
+                break;
+            }
+            file_hasher.update(&buffer[..len]);
+            chunk_hashes.push(hex::encode(Sha256::digest(&buffer[..len])));
+            if len < buffer.len() {
+                break;
+            }
+        }
+        Ok(Self {
+            file_hash: hex::encode(file_hasher.finalize()),
+            chunk_hashes,
+        })
+    }
+}
+
+/// Resumable state of one transfer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferManifest {
+    pub transfer_id: String,
+    pub direction: TransferDirection,
+    /// Device on the other end
+    pub peer_id: String,
+    pub filename: String,
+    pub total_size: u64,
+    pub chunk_size: u64,
+    /// Bit `i` is set once chunk `i` has been transferred
+    chunk_bitmap: Vec<u8>,
+    /// Hex SHA-256 per chunk, empty until the chunk is transferred
+    chunk_hashes: Vec<String>,
+    /// File being sent
+    #[serde(default)]
+    pub source_path: Option<PathBuf>,
+    /// Partial file incoming data is written to
+    #[serde(default)]
+    pub partial_path: Option<PathBuf>,
+    /// Where the completed incoming file is saved
+    #[serde(default)]
+    pub save_path: Option<PathBuf>,
+    /// Sender's checksums the completed file is verified against
+    #[serde(default)]
+    pub expected_checksums: Option<TransferChecksums>,
+    /// Chunks requested again after failing verification
+    #[serde(default)]
+    pub retried_chunks: u64,
+    #[serde(default)]
+    pub verify_attempts: u32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TransferManifest {
+    pub fn new(
+        transfer_id: &str,
+        direction: TransferDirection,
+        peer_id: &str,
+        filename: &str,
+        total_size: u64,
+    ) -> Self {
+        let chunk_count = total_size.div_ceil(TRANSFER_CHUNK_SIZE) as usize;
+        let now = Utc::now();
+        Self {
+            transfer_id: transfer_id.to_string(),
+            direction,
+            peer_id: peer_id.to_string(),
+            filename: filename.to_string(),
+            total_size,
+            chunk_size: TRANSFER_CHUNK_SIZE,
+            chunk_bitmap: vec![0; chunk_count.div_ceil(8)],
+            chunk_hashes: vec![String::new(); chunk_count],
+            source_path: None,
+            partial_path: None,
+            save_path: None,
+            expected_checksums: None,
+            retried_chunks: 0,
+            verify_attempts: 0,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    pub fn chunk_count(&self) -> u64 {
+        self.chunk_hashes.len() as u64
+    }
+
+    /// Byte offset and length of a chunk
+    pub fn chunk_range(&self, index: u64) -> Option<(u64, u64)> {
+        if index >= self.chunk_count() {
+            return None;
+        }
+        let offset = index * self.chunk_size;
+        Some((offset, self.chunk_size.min(self.total_size - offset)))
+    }
+
+    pub fn has_chunk(&self, index: u64) -> bool {
+        self.chunk_bitmap
+            .get((index / 8) as usize)
+            .is_some_and(|byte| byte & (1 << (index % 8)) != 0)
+    }
+
+    /// Hex SHA-256 of a transferred chunk
+    pub fn chunk_hash(&self, index: u64) -> Option<&str> {
+        self.has_chunk(index)
+            .then(|| self.chunk_hashes[index as usize].as_str())
+    }
+
+    /// Record chunk `index` as transferred with `data` as its content
+    pub fn mark_chunk(&mut self, index: u64, data: &[u8]) -> Result<()> {
+        let (_, len) = self
+            .chunk_range(index)
+            .ok_or_else(|| anyhow::anyhow!("Chunk {} out of range", index))?;
+        anyhow::ensure!(
+            data.len() as u64 == len,
+            "Chunk {} is {} bytes, expected {}",
+            index,
+            data.len(),
+            len
+        );
+        self.chunk_hashes[index as usize] = hex::encode(Sha256::digest(data));
+        self.chunk_bitmap[(index / 8) as usize] |= 1 << (index % 8);
+        self.updated_at = Utc::now();
+        Ok(())
+    }
+
+    /// Mark a chunk as missing again, e.g. when it no longer verifies
+    pub fn forget_chunk(&mut self, index: u64) {
+        if let Some(byte) = self.chunk_bitmap.get_mut((index / 8) as usize) {
+            *byte &= !(1 << (index % 8));
+            self.chunk_hashes[index as usize].clear();
+        }
+    }
+
+    pub fn missing_chunks(&self) -> Vec<u64> {
+        (0..self.chunk_count())
+            .filter(|index| !self.has_chunk(*index))
+            .collect()
+    }
+
+    pub fn completed_chunks(&self) -> u64 {
+        self.chunk_bitmap
+            .iter()
+            .map(|byte| byte.count_ones() as u64)
+            .sum()
+    }
+
+    pub fn transferred_bytes(&self) -> u64 {
+        (0..self.chunk_count())
+            .filter(|index| self.has_chunk(*index))
+            .filter_map(|index| self.chunk_range(index))
+            .map(|(_, len)| len)
+            .sum()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.completed_chunks() == self.chunk_count()
+    }
+}
+
+/// Manifests and partial files kept in the data dir
+#[derive(Debug, Clone)]
+pub struct TransferStateStore {
+    dir: PathBuf,
+}
+
+impl TransferStateStore {
+    pub fn new(data_dir: &Path) -> Self {
+        Self {
+            dir: data_dir.join(TRANSFER_STATE_DIR_NAME),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Where incoming data for `transfer_id` is staged until complete
+    pub fn partial_path(&self, transfer_id: &str) -> Result<PathBuf> {
+        self.file_path(transfer_id, PARTIAL_FILE_EXTENSION)
+    }
+
+    pub fn save(&self, manifest: &TransferManifest) -> Result<()> {
+        let path = self.file_path(&manifest.transfer_id, "json")?;
+        std::fs::create_dir_all(&self.dir)?;
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_string(manifest)?)?;
+        std::fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    pub fn load(&self, transfer_id: &str) -> Result<Option<TransferManifest>> {
+        let path = self.file_path(transfer_id, "json")?;
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Ok(Some(serde_json::from_str(&content)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Delete a transfer's manifest and partial file
+    pub fn remove(&self, transfer_id: &str) -> Result<()> {
+        for extension in ["json", PARTIAL_FILE_EXTENSION] {
+            match std::fs::remove_file(self.file_path(transfer_id, extension)?) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Every stored manifest, oldest first
+    ///
+    /// Unreadable manifests are discarded along with their partial file.
+    pub fn load_all(&self) -> Result<Vec<TransferManifest>> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut manifests = Vec::new();
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let parsed = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| Ok(serde_json::from_str::<TransferManifest>(&content)?));
+            match parsed {
+                Ok(manifest) => manifests.push(manifest),
+                Err(e) => {
+                    tracing::warn!(
+                        "Discarding unreadable transfer manifest {}: {}",
+                        path.display(),
+                        e
+                    );
+                    std::fs::remove_file(&path)?;
+                    let _ = std::fs::remove_file(path.with_extension(PARTIAL_FILE_EXTENSION));
+                }
+            }
+        }
+        manifests.sort_by_key(|manifest| manifest.created_at);
+        Ok(manifests)
+    }
+
+    /// Delete manifests not updated within `max_age`, and partial files left
+    /// without a manifest for as long
+    ///
+    /// Returns the ids of the expired transfers.
+    pub fn expire(&self, max_age: Duration) -> Result<Vec<String>> {
+        let max_age = chrono::Duration::from_std(max_age)?;
+        let cutoff = Utc::now() - max_age;
+        let mut expired = Vec::new();
+        for manifest in self.load_all()? {
+            if manifest.updated_at < cutoff {
+                self.remove(&manifest.transfer_id)?;
+                expired.push(manifest.transfer_id);
+            }
+        }
+
+        let Ok(entries) = std::fs::read_dir(&self.dir) else {
+            return Ok(expired);
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some(PARTIAL_FILE_EXTENSION)
+                || path.with_extension("json").exists()
+            {
+                continue;
+            }
+            let modified = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .map(DateTime::<Utc>::from);
+            if modified.is_ok_and(|modified| modified < cutoff) {
+                std::fs::remove_file(&path)?;
+            }
+        }
+        if !expired.is_empty() {
+            tracing::info!("Expired {} interrupted transfers", expired.len());
+        }
+        Ok(expired)
+    }
+
+    fn file_path(&self, transfer_id: &str, extension: &str) -> Result<PathBuf> {
+        // Incoming ids come from the peer; keep them from escaping the directory
+        anyhow::ensure!(
+            !transfer_id.is_empty()
+                && transfer_id
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+            "Invalid transfer id: {}",
+            transfer_id
+        );
+        Ok(self.dir.join(format!("{}.{}", transfer_id, extension)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_tracks_chunks_and_store_expires_stale() {
+        let dir = std::env::temp_dir().join(format!("cec-transfers-{}", uuid::Uuid::new_v4()));
+        let store = TransferStateStore::new(&dir);
+
+        let mut manifest = TransferManifest::new(
+            "t1",
+            TransferDirection::Incoming,
+            "peer",
+            "a.bin",
+            TRANSFER_CHUNK_SIZE * 2 + 10,
+        );
+        assert_eq!(manifest.chunk_count(), 3);
+        assert!(manifest.mark_chunk(2, &[0u8; 11]).is_err());
+        manifest.mark_chunk(2, &[0u8; 10]).unwrap();
+        assert_eq!(manifest.missing_chunks(), vec![0, 1]);
+        assert_eq!(manifest.transferred_bytes(), 10);
+        assert_eq!(manifest.chunk_hash(2).map(str::len), Some(64));
+        manifest.forget_chunk(2);
+        assert_eq!(manifest.completed_chunks(), 0);
+
+        store.save(&manifest).unwrap();
+        std::fs::write(store.partial_path("t1").unwrap(), b"partial").unwrap();
+        std::fs::write(store.dir().join("broken.json"), b"{").unwrap();
+        assert!(store.partial_path("../escape").is_err());
+
+        assert_eq!(store.load_all().unwrap(), vec![manifest.clone()]);
+        assert!(!store.dir().join("broken.json").exists());
+
+        manifest.updated_at = Utc::now() - chrono::Duration::days(8);
+        store.save(&manifest).unwrap();
+        assert_eq!(
+            store.expire(PARTIAL_TRANSFER_MAX_AGE).unwrap(),
+            vec!["t1".to_string()]
+        );
+        assert!(!store.partial_path("t1").unwrap().exists());
+        assert!(store.load("t1").unwrap().is_none());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
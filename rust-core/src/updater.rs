@@ -0,0 +1,415 @@
+//! Update Checks
+//!
+//! Fetches a release manifest describing the latest host and client versions
+//! per channel, verifies its Ed25519 signature against a pinned public key,
+//! and compares the matching release against the running version. Installers
+//! can be downloaded and checked against the manifest's SHA-256 digest and
+//! size; installing them is left to the platform installer.
+//!
+//! The manifest is served as a `SignedManifest` envelope so the signature
+//! covers the exact manifest bytes rather than a re-serialization.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Release channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseChannel {
+    Stable,
+    Beta,
+}
+
+/// Which application a release is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateComponent {
+    Host,
+    Client,
+}
+
+/// One downloadable release
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReleaseInfo {
+    pub channel: ReleaseChannel,
+    pub component: UpdateComponent,
+    /// Platform identifier, as in `std::env::consts::OS`
+    pub platform: String,
+    /// Semantic version
+    pub version: String,
+    pub url: String,
+    /// Hex-encoded SHA-256 of the installer
+    pub sha256: String,
+    pub size: u64,
+    pub release_notes: Option<String>,
+}
+
+/// Release manifest
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateManifest {
+    pub generated_at: DateTime<Utc>,
+    /// Manifests past this time are rejected, so a stale manifest cannot be
+    /// replayed to hide newer (e.g. security) releases
+    pub expires_at: DateTime<Utc>,
+    pub releases: Vec<ReleaseInfo>,
+}
+
+/// Manifest as served: the JSON text and its signature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedManifest {
+    pub manifest: String,
+    /// Hex-encoded Ed25519 signature over the `manifest` bytes
+    pub signature: String,
+}
+
+/// Result of an update check
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateCheckResult {
+    pub current_version: String,
+    /// Newest release for this channel, component and platform
+    pub latest: Option<ReleaseInfo>,
+    pub update_available: bool,
+}
+
+/// Fetches manifests and installers
+pub trait UpdateFetcher: Send + Sync {
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>>;
+}
+
+/// Fetcher backed by reqwest
+#[cfg(feature = "updates")]
+pub struct HttpUpdateFetcher {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "updates")]
+impl HttpUpdateFetcher {
+    pub fn new() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(300))
+            .build()?;
+        Ok(Self { client })
+    }
+}
+
+#[cfg(feature = "updates")]
+impl UpdateFetcher for HttpUpdateFetcher {
+    fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let response = self.client.get(url).send().await?.error_for_status()?;
+            Ok(response.bytes().await?.to_vec())
+        })
+    }
+}
+
+/// Verify a signed manifest and parse it
+pub fn verify_manifest(data: &[u8], public_key: &VerifyingKey) -> Result<UpdateManifest> {
+    let signed: SignedManifest =
+        serde_json::from_slice(data).context("Malformed signed manifest")?;
+    let signature_bytes: [u8; 64] = hex::decode(&signed.signature)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid manifest signature length"))?;
+    public_key
+        .verify(
+            signed.manifest.as_bytes(),
+            &Signature::from_bytes(&signature_bytes),
+        )
+        .map_err(|_| anyhow::anyhow!("Update manifest signature verification failed"))?;
+
+    let manifest: UpdateManifest =
+        serde_json::from_str(&signed.manifest).context("Malformed update manifest")?;
+    if manifest.expires_at < Utc::now() {
+        return Err(anyhow::anyhow!(
+            "Update manifest expired at {}",
+            manifest.expires_at
+        ));
+    }
+    Ok(manifest)
+}
+
+/// Checks for and downloads updates of one component
+pub struct UpdateChecker {
+    public_key: VerifyingKey,
+    channel: ReleaseChannel,
+    component: UpdateComponent,
+    platform: String,
+    current_version: semver::Version,
+    fetcher: Arc<dyn UpdateFetcher>,
+}
+
+impl UpdateChecker {
+    /// Create a checker for the running platform
+    ///
+    /// `public_key` is the hex-encoded Ed25519 release signing key.
+    pub fn new(
+        public_key: &str,
+        channel: ReleaseChannel,
+        component: UpdateComponent,
+        current_version: &str,
+        fetcher: Arc<dyn UpdateFetcher>,
+    ) -> Result<Self> {
+        let key_bytes: [u8; 32] = hex::decode(public_key)?
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Invalid update public key length"))?;
+        Ok(Self {
+            public_key: VerifyingKey::from_bytes(&key_bytes)?,
+            channel,
+            component,
+            platform: std::env::consts::OS.to_string(),
+            current_version: semver::Version::parse(current_version)?,
+            fetcher,
+        })
+    }
+
+    /// Create a checker that fetches over HTTPS
+    #[cfg(feature = "updates")]
+    pub fn with_http(
+        public_key: &str,
+        channel: ReleaseChannel,
+        component: UpdateComponent,
+        current_version: &str,
+    ) -> Result<Self> {
+        Self::new(
+            public_key,
+            channel,
+            component,
+            current_version,
+            Arc::new(HttpUpdateFetcher::new()?),
+        )
+    }
+
+    /// Check against a platform other than the running one
+    pub fn with_platform(mut self, platform: &str) -> Self {
+        self.platform = platform.to_string();
+        self
+    }
+
+    pub fn set_channel(&mut self, channel: ReleaseChannel) {
+        self.channel = channel;
+    }
+
+    /// Fetch and verify the manifest, then compare versions
+    pub async fn check(&self, manifest_url: &str) -> Result<UpdateCheckResult> {
+        let data = self.fetcher.fetch(manifest_url).await?;
+        let manifest = verify_manifest(&data, &self.public_key)?;
+        Ok(self.evaluate(&manifest))
+    }
+
+    fn evaluate(&self, manifest: &UpdateManifest) -> UpdateCheckResult {
+        let latest = manifest
+            .releases
+            .iter()
+            .filter(|r| {
+                r.channel == self.channel
+                    && r.component == self.component
+                    && r.platform == self.platform
+            })
+            .filter_map(|r| match semver::Version::parse(&r.version) {
+                Ok(version) => Some((version, r)),
+                Err(e) => {
+                    tracing::warn!("Skipping release with invalid version {}: {}", r.version, e);
+                    None
+                }
+            })
+            .max_by(|(a, _), (b, _)| a.cmp(b));
+
+        let update_available = latest
+            .as_ref()
+            .is_some_and(|(version, _)| *version > self.current_version);
+        if update_available {
+            tracing::info!(
+                "Update available: {} -> {}",
+                self.current_version,
+                latest
+                    .as_ref()
+                    .map(|(v, _)| v.to_string())
+                    .unwrap_or_default()
+            );
+        }
+
+        UpdateCheckResult {
+            current_version: self.current_version.to_string(),
+            latest: latest.map(|(_, r)| r.clone()),
+            update_available,
+        }
+    }
+
+    /// Download a release's installer into `dest_dir` and verify it
+    ///
+    /// Returns the installer path. Nothing is written unless the size and
+    /// SHA-256 digest match the manifest.
+    pub async fn download(&self, release: &ReleaseInfo, dest_dir: &Path) -> Result<PathBuf> {
+        let data = self.fetcher.fetch(&release.url).await?;
+        if data.len() as u64 != release.size {
+            return Err(anyhow::anyhow!(
+                "Installer size mismatch: expected {} bytes, got {}",
+                release.size,
+                data.len()
+            ));
+        }
+        let digest = hex::encode(Sha256::digest(&data));
+        if !digest.eq_ignore_ascii_case(&release.sha256) {
+            return Err(anyhow::anyhow!("Installer checksum mismatch"));
+        }
+
+        std::fs::create_dir_all(dest_dir)?;
+        let path = dest_dir.join(installer_file_name(release));
+        let tmp_path = path.with_extension("download");
+        std::fs::write(&tmp_path, &data)?;
+        std::fs::rename(&tmp_path, &path)?;
+
+        tracing::info!(
+            "Downloaded update {} to {}",
+            release.version,
+            path.display()
+        );
+        Ok(path)
+    }
+}
+
+/// Local file name for an installer, taken from its URL
+fn installer_file_name(release: &ReleaseInfo) -> String {
+    let name: String = url::Url::parse(&release.url)
+        .ok()
+        .and_then(|url| {
+            url.path_segments()
+                .and_then(|mut segments| segments.next_back().map(str::to_string))
+        })
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+        .collect();
+    if name.trim_matches('.').is_empty() {
+        format!("cec-update-{}", release.version)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::{Signer, SigningKey};
+    use std::collections::HashMap;
+
+    struct MapFetcher(HashMap<String, Vec<u8>>);
+
+    impl UpdateFetcher for MapFetcher {
+        fn fetch<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+            let result = self
+                .0
+                .get(url)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("404: {}", url));
+            Box::pin(async move { result })
+        }
+    }
+
+    const INSTALLER: &[u8] = b"installer bytes";
+
+    fn release(channel: ReleaseChannel, version: &str) -> ReleaseInfo {
+        ReleaseInfo {
+            channel,
+            component: UpdateComponent::Host,
+            platform: "linux".to_string(),
+            version: version.to_string(),
+            url: format!("https://updates.example.com/cec-host-{}.tar.gz", version),
+            sha256: hex::encode(Sha256::digest(INSTALLER)),
+            size: INSTALLER.len() as u64,
+            release_notes: None,
+        }
+    }
+
+    fn signed(key: &SigningKey, manifest: &UpdateManifest) -> Vec<u8> {
+        let text = serde_json::to_string(manifest).unwrap();
+        let signature = hex::encode(key.sign(text.as_bytes()).to_bytes());
+        serde_json::to_vec(&SignedManifest {
+            manifest: text,
+            signature,
+        })
+        .unwrap()
+    }
+
+    fn checker(key: &SigningKey, files: HashMap<String, Vec<u8>>) -> UpdateChecker {
+        UpdateChecker::new(
+            &hex::encode(key.verifying_key().as_bytes()),
+            ReleaseChannel::Stable,
+            UpdateComponent::Host,
+            "1.2.0",
+            Arc::new(MapFetcher(files)),
+        )
+        .unwrap()
+        .with_platform("linux")
+    }
+
+    #[tokio::test]
+    async fn test_check_and_download_per_channel() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let manifest = UpdateManifest {
+            generated_at: Utc::now(),
+            expires_at: Utc::now() + chrono::Duration::days(7),
+            releases: vec![
+                release(ReleaseChannel::Stable, "1.3.0"),
+                release(ReleaseChannel::Stable, "1.2.5"),
+                release(ReleaseChannel::Beta, "2.0.0-beta.1"),
+            ],
+        };
+        let stable = release(ReleaseChannel::Stable, "1.3.0");
+        let mut files = HashMap::new();
+        files.insert(
+            "https://u/manifest.json".to_string(),
+            signed(&key, &manifest),
+        );
+        files.insert(stable.url.clone(), INSTALLER.to_vec());
+        let mut checker = checker(&key, files);
+
+        let result = checker.check("https://u/manifest.json").await.unwrap();
+        assert!(result.update_available);
+        assert_eq!(result.latest.as_ref().unwrap().version, "1.3.0");
+
+        checker.set_channel(ReleaseChannel::Beta);
+        let beta = checker.check("https://u/manifest.json").await.unwrap();
+        assert_eq!(beta.latest.unwrap().version, "2.0.0-beta.1");
+
+        let dir = std::env::temp_dir().join(format!("cec-update-{}", uuid::Uuid::new_v4()));
+        let path = checker.download(&stable, &dir).await.unwrap();
+        assert_eq!(path.file_name().unwrap(), "cec-host-1.3.0.tar.gz");
+        assert_eq!(std::fs::read(&path).unwrap(), INSTALLER);
+
+        let tampered = ReleaseInfo {
+            sha256: hex::encode(Sha256::digest(b"other")),
+            ..stable
+        };
+        assert!(checker.download(&tampered, &dir).await.is_err());
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_forged_or_expired_manifest() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let attacker = SigningKey::from_bytes(&[9u8; 32]);
+        let manifest = UpdateManifest {
+            generated_at: Utc::now(),
+            expires_at: Utc::now() + chrono::Duration::days(7),
+            releases: vec![release(ReleaseChannel::Stable, "9.9.9")],
+        };
+        let expired = UpdateManifest {
+            expires_at: Utc::now() - chrono::Duration::days(1),
+            ..manifest.clone()
+        };
+
+        let mut files = HashMap::new();
+        files.insert("forged".to_string(), signed(&attacker, &manifest));
+        files.insert("expired".to_string(), signed(&key, &expired));
+        let checker = checker(&key, files);
+
+        assert!(checker.check("forged").await.is_err());
+        assert!(checker.check("expired").await.is_err());
+    }
+}
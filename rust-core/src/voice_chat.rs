@@ -0,0 +1,608 @@
+//! Two-Way Voice Chat
+//!
+//! Session audio otherwise flows one way, from the host's system mix. A
+//! `VoiceChat` adds a talkback channel on either end: it captures the local
+//! microphone, negotiates a second Opus track (`VOICE_TRACK_ID`) next to the
+//! session audio, and hands the processed frames to the encoder through
+//! `take_frame_receiver`. Frames of the peer's voice are fed back in with
+//! `receive_remote_frame` so the echo canceller knows what the speakers are
+//! playing.
+//!
+//! Support calls usually run over laptop speakers and built-in microphones,
+//! where the far end hears itself a few tens of milliseconds later. The
+//! `EchoCanceller` is tuned for that: a short adaptive filter, adaptation
+//! frozen during double talk, and residual suppression while only the far end
+//! speaks, which trades full-duplex quality for no audible echo.
+//!
+//! Muting and push-to-talk replace outgoing frames with silence rather than
+//! stopping them, so the track keeps its timing.
+
+use crate::audio_backend::{AudioBackend, AudioLevel};
+use crate::screen_capture::{AudioCaptureOptions, AudioCapturer, AudioFrame};
+use crate::webrtc_engine::WebRTCEngine;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, PoisonError};
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+
+/// Track ID of the voice track, distinct from the session audio track
+pub const VOICE_TRACK_ID: &str = "voice";
+
+/// Far-end audio buffered for the echo canceller before the oldest is dropped
+const MAX_FAR_END_BUFFER: Duration = Duration::from_millis(500);
+
+/// Far-end peak below which the far end counts as silent
+const FAR_END_ACTIVITY_FLOOR: f32 = 64.0;
+
+/// Adds and removes the voice track on a connection
+pub trait VoiceTrackControl: Send + Sync {
+    fn set_voice_track_enabled<'a>(
+        &'a self,
+        connection_id: &'a str,
+        enabled: bool,
+    ) -> BoxFuture<'a, Result<()>>;
+}
+
+impl VoiceTrackControl for WebRTCEngine {
+    fn set_voice_track_enabled<'a>(
+        &'a self,
+        connection_id: &'a str,
+        enabled: bool,
+    ) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            if enabled {
+                self.add_media_track(connection_id, "audio", VOICE_TRACK_ID.to_string())
+                    .await
+            } else {
+                self.remove_media_track(connection_id, VOICE_TRACK_ID).await
+            }
+        })
+    }
+}
+
+/// When the microphone is sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TalkMode {
+    /// Always, unless muted
+    Open,
+    /// Only while the talk key is held
+    PushToTalk,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EchoCancellerConfig {
+    /// Longest echo path the filter models
+    pub tail_length: Duration,
+    /// NLMS step size, 0..=1; larger converges faster but is noisier
+    pub step_size: f32,
+    /// Near end louder than this fraction of the far-end peak is taken as
+    /// double talk (Geigel detector), which freezes adaptation
+    pub double_talk_threshold: f32,
+    /// Gain applied to the residual while only the far end speaks
+    pub residual_gain: f32,
+}
+
+impl Default for EchoCancellerConfig {
+    fn default() -> Self {
+        Self {
+            tail_length: Duration::from_millis(32),
+            step_size: 0.5,
+            double_talk_threshold: 0.5,
+            // About -20 dB
+            residual_gain: 0.1,
+        }
+    }
+}
+
+/// Normalised LMS acoustic echo canceller for mono audio
+#[derive(Debug)]
+pub struct EchoCanceller {
+    config: EchoCancellerConfig,
+    weights: Vec<f32>,
+    /// Far-end history, written twice so `taps` contiguous samples starting
+    /// at `position` are always the newest first
+    history: Vec<f32>,
+    position: usize,
+    /// Sum of squares of the samples in the window; exact in f64, so it
+    /// returns to zero once the far end has been silent for a whole tail
+    history_energy: f64,
+    /// Smoothed echo return loss enhancement
+    erle_db: f32,
+}
+
+impl EchoCanceller {
+    pub fn new(sample_rate: u32, config: EchoCancellerConfig) -> Self {
+        let taps = ((config.tail_length.as_secs_f64() * f64::from(sample_rate)) as usize).max(1);
+        Self {
+            config,
+            weights: vec![0.0; taps],
+            history: vec![0.0; taps * 2],
+            position: 0,
+            history_energy: 0.0,
+            erle_db: 0.0,
+        }
+    }
+
+    /// How much the canceller currently removes, in dB
+    pub fn erle_db(&self) -> f32 {
+        self.erle_db
+    }
+
+    /// Remove the echo of `far` from `near` in place; both mono, same length
+    pub fn process(&mut self, near: &mut [i16], far: &[i16]) {
+        let taps = self.weights.len();
+        let (mut near_energy, mut out_energy) = (0.0f64, 0.0f64);
+        for (index, sample) in near.iter_mut().enumerate() {
+            let x = far.get(index).copied().map_or(0.0, f32::from);
+            let oldest = self.history[self.position + taps - 1];
+            self.position = (self.position + taps - 1) % taps;
+            self.history[self.position] = x;
+            self.history[self.position + taps] = x;
+            self.history_energy += f64::from(x).powi(2) - f64::from(oldest).powi(2);
+            let d = f32::from(*sample);
+            near_energy += f64::from(d * d);
+            if self.history_energy == 0.0 {
+                // Nothing played within the tail, so nothing to cancel
+                out_energy += f64::from(d * d);
+                continue;
+            }
+
+            let window = &self.history[self.position..self.position + taps];
+            let estimate: f32 = window.iter().zip(&self.weights).map(|(x, w)| x * w).sum();
+            let error = d - estimate;
+
+            let far_peak = window.iter().fold(0.0f32, |peak, x| peak.max(x.abs()));
+            let far_active = far_peak > FAR_END_ACTIVITY_FLOOR;
+            let double_talk = d.abs() > self.config.double_talk_threshold * far_peak;
+            if far_active && !double_talk {
+                let step = self.config.step_size * error / (self.history_energy as f32 + 1.0);
+                for (weight, x) in self.weights.iter_mut().zip(window) {
+                    *weight += step * x;
+                }
+            }
+
+            let output = if far_active && !double_talk {
+                error * self.config.residual_gain
+            } else {
+                error
+            };
+            out_energy += f64::from(output * output);
+            *sample = output.clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16;
+        }
+        if near_energy > 0.0 {
+            let erle = (10.0 * (near_energy / out_energy.max(1.0)).log10()) as f32;
+            self.erle_db += (erle - self.erle_db) * 0.1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VoiceChatOptions {
+    /// Microphone capture; mono suits voice
+    pub capture: AudioCaptureOptions,
+    pub talk_mode: TalkMode,
+    pub echo: EchoCancellerConfig,
+}
+
+impl Default for VoiceChatOptions {
+    fn default() -> Self {
+        Self {
+            capture: AudioCaptureOptions {
+                channels: 1,
+                ..AudioCaptureOptions::default()
+            },
+            talk_mode: TalkMode::Open,
+            echo: EchoCancellerConfig::default(),
+        }
+    }
+}
+
+/// Audio flow in one direction
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VoiceDirectionStats {
+    pub frames: u64,
+    pub duration_ms: u64,
+    /// Frames replaced with silence by mute or push-to-talk
+    pub silenced_frames: u64,
+    /// Level of the latest frame
+    pub level: Option<AudioLevel>,
+}
+
+impl VoiceDirectionStats {
+    fn record(&mut self, frame: &AudioFrame, samples: &[i16]) {
+        self.frames += 1;
+        let per_channel = frame.data.len() as u64 / u64::from(frame.channels.max(1));
+        self.duration_ms += per_channel * 1000 / u64::from(frame.sample_rate.max(1));
+        self.level = Some(AudioLevel::measure(samples));
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VoiceChatStats {
+    /// Local microphone, after echo cancellation
+    pub outgoing: VoiceDirectionStats,
+    /// The peer's voice
+    pub incoming: VoiceDirectionStats,
+    pub echo_return_loss_enhancement_db: f32,
+}
+
+/// State shared with the processing task
+struct VoiceState {
+    muted: AtomicBool,
+    push_to_talk: AtomicBool,
+    talk_pressed: AtomicBool,
+    far_end: StdMutex<FarEnd>,
+    stats: StdMutex<VoiceChatStats>,
+}
+
+impl VoiceState {
+    fn transmitting(&self) -> bool {
+        !self.muted.load(Ordering::SeqCst)
+            && (!self.push_to_talk.load(Ordering::SeqCst)
+                || self.talk_pressed.load(Ordering::SeqCst))
+    }
+
+    fn stats(&self) -> std::sync::MutexGuard<'_, VoiceChatStats> {
+        self.stats.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// What the speakers are playing, awaiting the matching microphone frames
+#[derive(Default)]
+struct FarEnd {
+    sample_rate: u32,
+    samples: VecDeque<i16>,
+}
+
+/// Voice channel on one connection
+pub struct VoiceChat {
+    connection_id: String,
+    options: VoiceChatOptions,
+    capturer: Mutex<AudioCapturer>,
+    tracks: Arc<dyn VoiceTrackControl>,
+    state: Arc<VoiceState>,
+    frame_sender: mpsc::UnboundedSender<AudioFrame>,
+    frame_receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<AudioFrame>>>>,
+}
+
+impl VoiceChat {
+    pub fn new(connection_id: String, tracks: Arc<dyn VoiceTrackControl>) -> Self {
+        let (frame_sender, frame_receiver) = mpsc::unbounded_channel();
+        let options = VoiceChatOptions::default();
+        Self {
+            connection_id,
+            state: Arc::new(VoiceState {
+                muted: AtomicBool::new(false),
+                push_to_talk: AtomicBool::new(options.talk_mode == TalkMode::PushToTalk),
+                talk_pressed: AtomicBool::new(false),
+                far_end: StdMutex::new(FarEnd::default()),
+                stats: StdMutex::new(VoiceChatStats::default()),
+            }),
+            options,
+            capturer: Mutex::new(AudioCapturer::new()),
+            tracks,
+            frame_sender,
+            frame_receiver: Arc::new(Mutex::new(Some(frame_receiver))),
+        }
+    }
+
+    pub fn with_options(mut self, options: VoiceChatOptions) -> Self {
+        self.state
+            .push_to_talk
+            .store(options.talk_mode == TalkMode::PushToTalk, Ordering::SeqCst);
+        self.options = options;
+        self
+    }
+
+    /// Capture the microphone through a custom audio backend
+    pub fn with_backend(mut self, backend: Arc<dyn AudioBackend>) -> Self {
+        self.capturer = Mutex::new(AudioCapturer::new().with_backend(backend));
+        self
+    }
+
+    /// Take the outgoing voice frame receiver (can only be taken once)
+    pub async fn take_frame_receiver(&self) -> Option<mpsc::UnboundedReceiver<AudioFrame>> {
+        self.frame_receiver.lock().await.take()
+    }
+
+    /// Microphone to use, or the platform default for `None`
+    pub async fn select_microphone(&self, device_id: Option<String>) -> Result<()> {
+        self.capturer.lock().await.select_device(device_id).await
+    }
+
+    pub async fn is_active(&self) -> bool {
+        self.capturer.lock().await.is_capturing().await
+    }
+
+    /// Open the microphone and add the voice track
+    pub async fn start(&self) -> Result<()> {
+        let mut capturer = self.capturer.lock().await;
+        if capturer.is_capturing().await {
+            return Ok(());
+        }
+        let mut frames = capturer
+            .start_microphone_capture(self.options.capture.clone())
+            .await?;
+        if let Err(e) = self
+            .tracks
+            .set_voice_track_enabled(&self.connection_id, true)
+            .await
+        {
+            capturer.stop_capture().await;
+            return Err(e);
+        }
+
+        let state = Arc::clone(&self.state);
+        let sender = self.frame_sender.clone();
+        let capture = &self.options.capture;
+        let mut canceller = capture
+            .enable_echo_cancellation
+            .then(|| EchoCanceller::new(capture.sample_rate, self.options.echo));
+        tokio::spawn(async move {
+            while let Some(frame) = frames.recv().await {
+                let frame = process_outgoing(&state, canceller.as_mut(), frame);
+                if sender.send(frame).is_err() {
+                    break;
+                }
+            }
+        });
+        tracing::info!("Voice chat started on connection {}", self.connection_id);
+        Ok(())
+    }
+
+    /// Close the microphone and remove the voice track
+    pub async fn stop(&self) -> Result<()> {
+        let mut capturer = self.capturer.lock().await;
+        if !capturer.is_capturing().await {
+            return Ok(());
+        }
+        capturer.stop_capture().await;
+        self.state
+            .far_end
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .samples
+            .clear();
+        tracing::info!("Voice chat stopped on connection {}", self.connection_id);
+        self.tracks
+            .set_voice_track_enabled(&self.connection_id, false)
+            .await
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.state.muted.store(muted, Ordering::SeqCst);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.state.muted.load(Ordering::SeqCst)
+    }
+
+    pub fn set_talk_mode(&self, mode: TalkMode) {
+        self.state
+            .push_to_talk
+            .store(mode == TalkMode::PushToTalk, Ordering::SeqCst);
+    }
+
+    pub fn talk_mode(&self) -> TalkMode {
+        if self.state.push_to_talk.load(Ordering::SeqCst) {
+            TalkMode::PushToTalk
+        } else {
+            TalkMode::Open
+        }
+    }
+
+    /// The push-to-talk key went down (`true`) or up
+    pub fn set_talk_pressed(&self, pressed: bool) {
+        self.state.talk_pressed.store(pressed, Ordering::SeqCst);
+    }
+
+    /// Whether the microphone currently reaches the peer
+    pub fn is_transmitting(&self) -> bool {
+        self.state.transmitting()
+    }
+
+    /// A decoded frame of the peer's voice, as handed to the speakers
+    pub fn receive_remote_frame(&self, frame: &AudioFrame) {
+        let mono = downmix(frame);
+        self.state.stats().incoming.record(frame, &mono);
+
+        let mut far_end = self
+            .state
+            .far_end
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if far_end.sample_rate != frame.sample_rate {
+            far_end.samples.clear();
+            far_end.sample_rate = frame.sample_rate;
+        }
+        far_end.samples.extend(mono);
+        let limit = (MAX_FAR_END_BUFFER.as_millis() as usize) * frame.sample_rate as usize / 1000;
+        let excess = far_end.samples.len().saturating_sub(limit);
+        far_end.samples.drain(..excess);
+    }
+
+    pub fn stats(&self) -> VoiceChatStats {
+        self.state.stats().clone()
+    }
+}
+
+/// Cancel echo, apply mute and push-to-talk, and account one microphone frame
+fn process_outgoing(
+    state: &VoiceState,
+    canceller: Option<&mut EchoCanceller>,
+    frame: AudioFrame,
+) -> AudioFrame {
+    let mut samples = downmix(&frame);
+    let far: Vec<i16> = {
+        let mut far_end = state.far_end.lock().unwrap_or_else(PoisonError::into_inner);
+        if far_end.sample_rate == frame.sample_rate {
+            let available = far_end.samples.len().min(samples.len());
+            far_end.samples.drain(..available).collect()
+        } else {
+            Vec::new()
+        }
+    };
+    let transmitting = state.transmitting();
+
+    let mut stats = state.stats();
+    if let Some(canceller) = canceller {
+        canceller.process(&mut samples, &far);
+        stats.echo_return_loss_enhancement_db = canceller.erle_db();
+    }
+    if !transmitting {
+        samples.fill(0);
+        stats.outgoing.silenced_frames += 1;
+    }
+    stats.outgoing.record(&frame, &samples);
+
+    AudioFrame {
+        channels: 1,
+        data: samples,
+        ..frame
+    }
+}
+
+/// Average interleaved channels into one
+fn downmix(frame: &AudioFrame) -> Vec<i16> {
+    let channels = usize::from(frame.channels.max(1));
+    if channels == 1 {
+        return frame.data.clone();
+    }
+    frame
+        .data
+        .chunks(channels)
+        .map(|samples| {
+            (samples.iter().map(|&s| i32::from(s)).sum::<i32>() / samples.len() as i32) as i16
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_backend::{AudioDeviceInfo, AudioSource};
+
+    #[derive(Default)]
+    struct RecordingTracks {
+        calls: StdMutex<Vec<bool>>,
+    }
+
+    impl VoiceTrackControl for RecordingTracks {
+        fn set_voice_track_enabled<'a>(
+            &'a self,
+            _connection_id: &'a str,
+            enabled: bool,
+        ) -> BoxFuture<'a, Result<()>> {
+            self.calls.lock().unwrap().push(enabled);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    struct Microphone;
+
+    impl AudioSource for Microphone {
+        fn read(&mut self, buffer: &mut [i16]) -> Result<()> {
+            std::thread::sleep(Duration::from_millis(20));
+            buffer.fill(4096);
+            Ok(())
+        }
+    }
+
+    struct MicrophoneBackend;
+
+    impl AudioBackend for MicrophoneBackend {
+        fn devices(&self) -> Result<Vec<AudioDeviceInfo>> {
+            Ok(Vec::new())
+        }
+
+        fn open(
+            &self,
+            _device_id: Option<&str>,
+            _sample_rate: u32,
+            _channels: u8,
+        ) -> Result<Box<dyn AudioSource>> {
+            Ok(Box::new(Microphone))
+        }
+    }
+
+    #[test]
+    fn test_echo_canceller_converges_on_delayed_echo() {
+        let config = EchoCancellerConfig {
+            tail_length: Duration::from_millis(4),
+            ..EchoCancellerConfig::default()
+        };
+        let mut canceller = EchoCanceller::new(16_000, config);
+        // Pseudo-random far end; the room returns it 20 samples later at 40%
+        let mut seed = 12345u32;
+        let far: Vec<i16> = (0..16_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                ((seed >> 16) as i16) / 4
+            })
+            .collect();
+        let mut near: Vec<i16> = (0..far.len())
+            .map(|i| {
+                if i < 20 {
+                    0
+                } else {
+                    (far[i - 20] as f32 * 0.4) as i16
+                }
+            })
+            .collect();
+
+        for (near, far) in near.chunks_mut(320).zip(far.chunks(320)) {
+            canceller.process(near, far);
+        }
+        let tail = &near[near.len() - 320..];
+        let residual = AudioLevel::measure(tail).rms_dbfs;
+        assert!(residual < -50.0, "residual echo at {} dBFS", residual);
+        assert!(canceller.erle_db() > 30.0);
+    }
+
+    #[tokio::test]
+    async fn test_mute_and_push_to_talk_silence_outgoing_frames() {
+        let tracks = Arc::new(RecordingTracks::default());
+        let voice = VoiceChat::new("conn-1".to_string(), tracks.clone())
+            .with_backend(Arc::new(MicrophoneBackend));
+        let mut frames = voice.take_frame_receiver().await.unwrap();
+        voice.start().await.unwrap();
+        assert!(voice.is_active().await);
+        assert!(voice.is_transmitting());
+
+        voice.set_muted(true);
+        assert!(!voice.is_transmitting());
+        voice.set_muted(false);
+        voice.set_talk_mode(TalkMode::PushToTalk);
+        assert!(!voice.is_transmitting());
+        loop {
+            let frame = frames.recv().await.unwrap();
+            assert_eq!(frame.channels, 1);
+            if voice.stats().outgoing.silenced_frames > 0 {
+                assert!(frame.data.iter().all(|&sample| sample == 0));
+                break;
+            }
+        }
+        voice.set_talk_pressed(true);
+        assert!(voice.is_transmitting());
+
+        voice.receive_remote_frame(&AudioFrame {
+            id: 1,
+            timestamp: 0,
+            sample_rate: 48_000,
+            channels: 2,
+            data: vec![1000; 1920],
+        });
+        let incoming = voice.stats().incoming;
+        assert_eq!((incoming.frames, incoming.duration_ms), (1, 20));
+
+        voice.stop().await.unwrap();
+        assert!(!voice.is_active().await);
+        assert_eq!(*tracks.calls.lock().unwrap(), vec![true, false]);
+    }
+}
@@ -0,0 +1,551 @@
+//! Session Event Webhooks
+//!
+//! Optional dispatcher that POSTs JSON payloads to integration endpoints
+//! (ticketing systems, SIEMs) when selected events happen. Each request is
+//! signed with HMAC-SHA256 over `"{timestamp}.{body}"` using the endpoint's
+//! shared secret and sent in the `X-CEC-Signature` header, so receivers can
+//! verify origin and reject replays by timestamp.
+//!
+//! Failed deliveries are retried with exponential backoff. Recent delivery
+//! results are kept for the diagnostics report.
+
+use crate::security::SecurityThreat;
+use crate::session_manager::SessionEvent;
+use anyhow::Result;
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Header carrying the payload signature
+pub const SIGNATURE_HEADER: &str = "X-CEC-Signature";
+/// Header carrying the signing timestamp (Unix seconds)
+pub const TIMESTAMP_HEADER: &str = "X-CEC-Timestamp";
+/// Header carrying the event type
+pub const EVENT_HEADER: &str = "X-CEC-Event";
+
+/// Number of delivery records kept for diagnostics
+const MAX_DELIVERY_HISTORY: usize = 100;
+
+/// Upper bound on the retry delay
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// Event types that can trigger a webhook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventType {
+    SessionCreated,
+    SessionEnded,
+    ThreatDetected,
+    FileTransferred,
+}
+
+/// Webhook endpoint configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// Shared HMAC secret
+    pub secret: String,
+    /// Events delivered to this endpoint
+    pub event_types: Vec<WebhookEventType>,
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry; doubles on each further retry
+    pub initial_backoff_ms: u64,
+}
+
+impl WebhookConfig {
+    pub fn new(url: &str, secret: &str, event_types: Vec<WebhookEventType>) -> Self {
+        Self {
+            url: url.to_string(),
+            secret: secret.to_string(),
+            event_types,
+            max_retries: 5,
+            initial_backoff_ms: 1000,
+        }
+    }
+
+    fn backoff(&self, retry: u32) -> Duration {
+        let delay = self
+            .initial_backoff_ms
+            .saturating_mul(1u64 << retry.min(16));
+        Duration::from_millis(delay).min(MAX_BACKOFF)
+    }
+}
+
+/// JSON body posted to the endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event_id: String,
+    pub event_type: WebhookEventType,
+    /// RFC 3339 time the event occurred
+    pub occurred_at: String,
+    pub session_id: Option<String>,
+    pub data: serde_json::Value,
+}
+
+/// Delivery state of one payload to one endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WebhookDeliveryStatus {
+    Pending,
+    Delivered,
+    Failed,
+}
+
+/// Delivery record kept for diagnostics
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub event_id: String,
+    pub event_type: WebhookEventType,
+    pub url: String,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: u32,
+    pub last_status_code: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+/// Webhook delivery summary included in diagnostics
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebhookDiagnostics {
+    pub endpoints: usize,
+    pub delivered: usize,
+    pub failed: usize,
+    pub pending: usize,
+    pub last_error: Option<String>,
+}
+
+/// Request headers added to each webhook POST
+pub type WebhookHeaders = Vec<(&'static str, String)>;
+
+/// HTTP client used to post payloads; returns the response status code
+pub trait WebhookTransport: Send + Sync {
+    fn post<'a>(
+        &'a self,
+        url: &'a str,
+        headers: WebhookHeaders,
+        body: Vec<u8>,
+    ) -> BoxFuture<'a, Result<u16>>;
+}
+
+/// Transport backed by reqwest
+#[cfg(feature = "webhooks")]
+pub struct HttpWebhookTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(feature = "webhooks")]
+impl HttpWebhookTransport {
+    pub fn new() -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?;
+        Ok(Self { client })
+    }
+}
+
+#[cfg(feature = "webhooks")]
+impl WebhookTransport for HttpWebhookTransport {
+    fn post<'a>(
+        &'a self,
+        url: &'a str,
+        headers: WebhookHeaders,
+        body: Vec<u8>,
+    ) -> BoxFuture<'a, Result<u16>> {
+        Box::pin(async move {
+            let mut request = self
+                .client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .body(body);
+            for (name, value) in headers {
+                request = request.header(name, value);
+            }
+            Ok(request.send().await?.status().as_u16())
+        })
+    }
+}
+
+/// Compute the signature header value for a payload
+pub fn sign_payload(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let key = ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes());
+    let mut context = ring::hmac::Context::with_key(&key);
+    context.update(timestamp.to_string().as_bytes());
+    context.update(b".");
+    context.update(body);
+    format!("sha256={}", hex::encode(context.sign().as_ref()))
+}
+
+/// Whether a failed status code is worth retrying
+fn is_retryable(status: u16) -> bool {
+    status == 408 || status == 429 || status >= 500
+}
+
+/// Posts signed event payloads to configured endpoints
+#[derive(Clone)]
+pub struct WebhookDispatcher {
+    endpoints: Arc<RwLock<Vec<WebhookConfig>>>,
+    transport: Arc<dyn WebhookTransport>,
+    deliveries: Arc<RwLock<VecDeque<WebhookDelivery>>>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(transport: Arc<dyn WebhookTransport>) -> Self {
+        Self {
+            endpoints: Arc::new(RwLock::new(Vec::new())),
+            transport,
+            deliveries: Arc::new(RwLock::new(VecDeque::with_capacity(MAX_DELIVERY_HISTORY))),
+        }
+    }
+
+    /// Create a dispatcher that posts over HTTPS
+    #[cfg(feature = "webhooks")]
+    pub fn with_http() -> Result<Self> {
+        Ok(Self::new(Arc::new(HttpWebhookTransport::new()?)))
+    }
+
+    pub async fn add_endpoint(&self, config: WebhookConfig) -> Result<()> {
+        url::Url::parse(&config.url)
+            .map_err(|e| anyhow::anyhow!("Invalid webhook URL {}: {}", config.url, e))?;
+        tracing::info!("Added webhook endpoint: {}", config.url);
+        self.endpoints.write().await.push(config);
+        Ok(())
+    }
+
+    pub async fn remove_endpoint(&self, url: &str) {
+        self.endpoints.write().await.retain(|e| e.url != url);
+    }
+
+    /// Deliver an event to all subscribed endpoints and wait for the results
+    pub async fn dispatch(
+        &self,
+        event_type: WebhookEventType,
+        session_id: Option<String>,
+        data: serde_json::Value,
+    ) -> Vec<WebhookDelivery> {
+        let endpoints: Vec<WebhookConfig> = self
+            .endpoints
+            .read()
+            .await
+            .iter()
+            .filter(|e| e.event_types.contains(&event_type))
+            .cloned()
+            .collect();
+        if endpoints.is_empty() {
+            return Vec::new();
+        }
+
+        let payload = WebhookPayload {
+            event_id: Uuid::new_v4().to_string(),
+            event_type,
+            occurred_at: chrono::Utc::now().to_rfc3339(),
+            session_id,
+            data,
+        };
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("Failed to serialize webhook payload: {}", e);
+                return Vec::new();
+            }
+        };
+
+        futures::future::join_all(
+            endpoints
+                .iter()
+                .map(|endpoint| self.deliver(endpoint, &payload, &body)),
+        )
+        .await
+    }
+
+    /// Dispatch in the background
+    pub fn notify(
+        &self,
+        event_type: WebhookEventType,
+        session_id: Option<String>,
+        data: serde_json::Value,
+    ) {
+        let dispatcher = self.clone();
+        tokio::spawn(async move {
+            dispatcher.dispatch(event_type, session_id, data).await;
+        });
+    }
+
+    /// Forward session lifecycle events
+    pub fn notify_session_event(&self, event: &SessionEvent) {
+        match event {
+            SessionEvent::Created {
+                session_id,
+                controller_id,
+                controlled_id,
+            } => self.notify(
+                WebhookEventType::SessionCreated,
+                Some(session_id.clone()),
+                serde_json::json!({
+                    "controller_id": controller_id,
+                    "controlled_id": controlled_id,
+                }),
+            ),
+            SessionEvent::Ended { session_id, reason } => self.notify(
+                WebhookEventType::SessionEnded,
+                Some(session_id.clone()),
+                serde_json::json!({ "reason": reason }),
+            ),
+            _ => {}
+        }
+    }
+
+    /// Forward a detected security threat
+    pub fn notify_threat(&self, threat: SecurityThreat, session_id: Option<String>) {
+        self.notify(
+            WebhookEventType::ThreatDetected,
+            session_id,
+            serde_json::json!({ "threat": format!("{:?}", threat) }),
+        );
+    }
+
+    /// Forward a completed file transfer
+    pub fn notify_file_transferred(&self, session_id: Option<String>, filename: &str, size: u64) {
+        self.notify(
+            WebhookEventType::FileTransferred,
+            session_id,
+            serde_json::json!({ "filename": filename, "size": size }),
+        );
+    }
+
+    async fn deliver(
+        &self,
+        endpoint: &WebhookConfig,
+        payload: &WebhookPayload,
+        body: &[u8],
+    ) -> WebhookDelivery {
+        let mut delivery = WebhookDelivery {
+            event_id: payload.event_id.clone(),
+            event_type: payload.event_type,
+            url: endpoint.url.clone(),
+            status: WebhookDeliveryStatus::Pending,
+            attempts: 0,
+            last_status_code: None,
+            last_error: None,
+        };
+        self.record(&delivery).await;
+
+        loop {
+            let timestamp = chrono::Utc::now().timestamp();
+            let headers = vec![
+                (
+                    SIGNATURE_HEADER,
+                    sign_payload(&endpoint.secret, timestamp, body),
+                ),
+                (TIMESTAMP_HEADER, timestamp.to_string()),
+                (
+                    EVENT_HEADER,
+                    serde_json::to_value(payload.event_type)
+                        .ok()
+                        .and_then(|v| v.as_str().map(str::to_string))
+                        .unwrap_or_default(),
+                ),
+            ];
+
+            delivery.attempts += 1;
+            let retryable = match self
+                .transport
+                .post(&endpoint.url, headers, body.to_vec())
+                .await
+            {
+                Ok(status) if (200..300).contains(&status) => {
+                    delivery.status = WebhookDeliveryStatus::Delivered;
+                    delivery.last_status_code = Some(status);
+                    delivery.last_error = None;
+                    break;
+                }
+                Ok(status) => {
+                    delivery.last_status_code = Some(status);
+                    delivery.last_error = Some(format!("HTTP {}", status));
+                    is_retryable(status)
+                }
+                Err(e) => {
+                    delivery.last_error = Some(e.to_string());
+                    true
+                }
+            };
+
+            if !retryable || delivery.attempts > endpoint.max_retries {
+                delivery.status = WebhookDeliveryStatus::Failed;
+                break;
+            }
+
+            let delay = endpoint.backoff(delivery.attempts - 1);
+            tracing::debug!(
+                "Webhook delivery to {} failed ({}), retrying in {:?}",
+                endpoint.url,
+                delivery.last_error.as_deref().unwrap_or_default(),
+                delay
+            );
+            self.record(&delivery).await;
+            tokio::time::sleep(delay).await;
+        }
+
+        if delivery.status == WebhookDeliveryStatus::Failed {
+            tracing::warn!(
+                "Webhook delivery to {} failed after {} attempts: {}",
+                endpoint.url,
+                delivery.attempts,
+                delivery.last_error.as_deref().unwrap_or_default()
+            );
+        }
+        self.record(&delivery).await;
+        delivery
+    }
+
+    async fn record(&self, delivery: &WebhookDelivery) {
+        let mut deliveries = self.deliveries.write().await;
+        if let Some(existing) = deliveries
+            .iter_mut()
+            .find(|d| d.event_id == delivery.event_id && d.url == delivery.url)
+        {
+            *existing = delivery.clone();
+            return;
+        }
+        if deliveries.len() >= MAX_DELIVERY_HISTORY {
+            deliveries.pop_front();
+        }
+        deliveries.push_back(delivery.clone());
+    }
+
+    /// Recent delivery records, oldest first
+    pub async fn get_deliveries(&self) -> Vec<WebhookDelivery> {
+        self.deliveries.read().await.iter().cloned().collect()
+    }
+
+    /// Summary of recent deliveries for the diagnostics report
+    pub async fn get_diagnostics(&self) -> WebhookDiagnostics {
+        let deliveries = self.deliveries.read().await;
+        let count = |status| deliveries.iter().filter(|d| d.status == status).count();
+        WebhookDiagnostics {
+            endpoints: self.endpoints.read().await.len(),
+            delivered: count(WebhookDeliveryStatus::Delivered),
+            failed: count(WebhookDeliveryStatus::Failed),
+            pending: count(WebhookDeliveryStatus::Pending),
+            last_error: deliveries
+                .iter()
+                .rev()
+                .find(|d| d.status == WebhookDeliveryStatus::Failed)
+                .and_then(|d| d.last_error.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Returns queued status codes and records each request
+    struct MockTransport {
+        responses: Mutex<VecDeque<u16>>,
+        requests: Mutex<Vec<(WebhookHeaders, Vec<u8>)>>,
+    }
+
+    impl MockTransport {
+        fn new(responses: Vec<u16>) -> Arc<Self> {
+            Arc::new(Self {
+                responses: Mutex::new(responses.into()),
+                requests: Mutex::new(Vec::new()),
+            })
+        }
+    }
+
+    impl WebhookTransport for MockTransport {
+        fn post<'a>(
+            &'a self,
+            _url: &'a str,
+            headers: WebhookHeaders,
+            body: Vec<u8>,
+        ) -> BoxFuture<'a, Result<u16>> {
+            self.requests.lock().unwrap().push((headers, body));
+            let status = self.responses.lock().unwrap().pop_front().unwrap_or(200);
+            Box::pin(async move { Ok(status) })
+        }
+    }
+
+    fn config(event_types: Vec<WebhookEventType>) -> WebhookConfig {
+        WebhookConfig {
+            initial_backoff_ms: 1,
+            ..WebhookConfig::new("https://hooks.example.com/cec", "s3cret", event_types)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signed_delivery_with_retry() {
+        let transport = MockTransport::new(vec![503, 200]);
+        let dispatcher = WebhookDispatcher::new(transport.clone());
+        dispatcher
+            .add_endpoint(config(vec![WebhookEventType::SessionCreated]))
+            .await
+            .unwrap();
+
+        // Not subscribed
+        assert!(dispatcher
+            .dispatch(
+                WebhookEventType::ThreatDetected,
+                None,
+                serde_json::json!({})
+            )
+            .await
+            .is_empty());
+
+        let results = dispatcher
+            .dispatch(
+                WebhookEventType::SessionCreated,
+                Some("session-1".to_string()),
+                serde_json::json!({ "controller_id": "viewer" }),
+            )
+            .await;
+        assert_eq!(results[0].status, WebhookDeliveryStatus::Delivered);
+        assert_eq!(results[0].attempts, 2);
+
+        let requests = transport.requests.lock().unwrap();
+        let (headers, body) = &requests[1];
+        let header = |name| headers.iter().find(|(n, _)| *n == name).unwrap().1.clone();
+        let timestamp: i64 = header(TIMESTAMP_HEADER).parse().unwrap();
+        assert_eq!(
+            header(SIGNATURE_HEADER),
+            sign_payload("s3cret", timestamp, body)
+        );
+        assert_eq!(header(EVENT_HEADER), "session_created");
+
+        let payload: WebhookPayload = serde_json::from_slice(body).unwrap();
+        assert_eq!(payload.session_id.as_deref(), Some("session-1"));
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_failure_in_diagnostics() {
+        let transport = MockTransport::new(vec![401]);
+        let dispatcher = WebhookDispatcher::new(transport.clone());
+        dispatcher
+            .add_endpoint(config(vec![WebhookEventType::SessionEnded]))
+            .await
+            .unwrap();
+        assert!(dispatcher
+            .add_endpoint(WebhookConfig {
+                url: "not a url".to_string(),
+                ..config(vec![])
+            })
+            .await
+            .is_err());
+
+        let results = dispatcher
+            .dispatch(WebhookEventType::SessionEnded, None, serde_json::json!({}))
+            .await;
+        assert_eq!(results[0].status, WebhookDeliveryStatus::Failed);
+        assert_eq!(results[0].attempts, 1);
+
+        let diagnostics = dispatcher.get_diagnostics().await;
+        assert_eq!(diagnostics.endpoints, 1);
+        assert_eq!(diagnostics.failed, 1);
+        assert_eq!(diagnostics.last_error.as_deref(), Some("HTTP 401"));
+    }
+}
@@ -1,19 +1,51 @@
+use crate::data_compression::{CompressionAlgorithm, CompressionStats, DataChannelCodec};
+use crate::decoder_capabilities::DecoderCodec;
+use crate::metrics::Counter;
+use crate::network::ConnectionType;
+use crate::quic_transport::{DataTransportConfig, DataTransportType};
+use crate::receive_stats::FreezeStats;
+use crate::session_manager::KEYFRAME_REQUEST_MIN_INTERVAL_MS;
+use crate::signaling::SignalingClient;
 use anyhow::Result;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 use tokio::sync::{mpsc, Mutex};
 use uuid::Uuid;
 use webrtc::api::interceptor_registry::register_default_interceptors;
 use webrtc::api::media_engine::MediaEngine;
+use webrtc::api::setting_engine::SettingEngine;
 use webrtc::api::APIBuilder;
+use webrtc::data_channel::data_channel_init::RTCDataChannelInit;
+use webrtc::data_channel::data_channel_state::RTCDataChannelState;
+use webrtc::data_channel::RTCDataChannel;
+use webrtc::ice::candidate::{CandidatePairState, CandidateType};
 use webrtc::ice_transport::ice_candidate::RTCIceCandidate;
+use webrtc::ice_transport::ice_connection_state::RTCIceConnectionState;
 use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::interceptor::registry::Registry;
+use webrtc::media::Sample;
 use webrtc::peer_connection::configuration::RTCConfiguration as WebRTCConfig;
+use webrtc::peer_connection::offer_answer_options::RTCOfferOptions;
 use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState as WebRTCState;
+use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
+use webrtc::peer_connection::signaling_state::RTCSignalingState;
 use webrtc::peer_connection::RTCPeerConnection;
+use webrtc::rtcp::payload_feedbacks::full_intra_request::FullIntraRequest;
+use webrtc::rtcp::payload_feedbacks::picture_loss_indication::PictureLossIndication;
+use webrtc::rtp_transceiver::rtp_codec::{
+    RTCRtpCodecCapability, RTCRtpCodecParameters, RTPCodecType,
+};
+use webrtc::rtp_transceiver::rtp_sender::RTCRtpSender;
+use webrtc::rtp_transceiver::RTCPFeedback;
+use webrtc::stats::{StatsReport, StatsReportType};
+use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RTCConfiguration {
@@ -30,7 +62,7 @@ pub struct IceServer {
     pub credential: Option<String>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum RTCPeerConnectionState {
     New,
     Connecting,
@@ -54,11 +86,413 @@ impl From<WebRTCState> for RTCPeerConnectionState {
     }
 }
 
+/// How a peer resolves colliding offers during renegotiation
+///
+/// When both sides send an offer at once, the polite peer rolls back its own
+/// offer and answers the remote one; the impolite peer ignores the remote
+/// offer and waits for its answer. The polite peer re-offers its change once
+/// the other negotiation completes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NegotiationRole {
+    Polite,
+    Impolite,
+}
+
+impl NegotiationRole {
+    /// Deterministic role both peers agree on: the lower device ID is polite
+    pub fn for_peers(local_id: &str, remote_id: &str) -> Self {
+        if local_id < remote_id {
+            NegotiationRole::Polite
+        } else {
+            NegotiationRole::Impolite
+        }
+    }
+}
+
+/// Renegotiation progress reported through `WebRTCEvent::RenegotiationProgress`
+#[derive(Debug, Clone, PartialEq)]
+pub enum RenegotiationProgress {
+    /// Another negotiation is in flight; the change is offered after it
+    Queued,
+    OfferSent,
+    /// Remote offer collided with ours and was ignored (impolite peer)
+    RemoteOfferIgnored,
+    /// Our offer was withdrawn, for a collision or because it failed
+    RolledBack(String),
+    /// The WebRTC stack refused the rollback; the connection needs an ICE
+    /// restart or a new peer connection to negotiate again safely
+    RollbackFailed(String),
+    Completed,
+}
+
+/// NAT keepalive and consent freshness (RFC 7675) on the selected pair
+///
+/// STUN binding requests go out every `keepalive_interval`, which also
+/// refreshes NAT bindings on idle sessions. A path silent for
+/// `missed_after` is reported with `ConsentEvent::KeepaliveMissed`; after
+/// `consent_timeout` consent has expired and media stops.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConsentConfig {
+    pub keepalive_interval: Duration,
+    pub missed_after: Duration,
+    pub consent_timeout: Duration,
+    /// Offer an ICE restart when consent expires (impolite peer only, so
+    /// both sides do not restart at once)
+    pub auto_ice_restart: bool,
+}
+
+impl Default for ConsentConfig {
+    fn default() -> Self {
+        Self {
+            keepalive_interval: Duration::from_secs(5),
+            missed_after: Duration::from_secs(10),
+            // RFC 7675 section 5.1
+            consent_timeout: Duration::from_secs(30),
+            auto_ice_restart: true,
+        }
+    }
+}
+
+impl ConsentConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.keepalive_interval.is_zero()
+            || self.keepalive_interval >= self.missed_after
+            || self.missed_after >= self.consent_timeout
+        {
+            return Err(anyhow::anyhow!(
+                "Consent timings must satisfy 0 < keepalive_interval < missed_after < consent_timeout"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Media path liveness reported through `WebRTCEvent::Consent`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConsentEvent {
+    /// No consent responses for `ConsentConfig::missed_after`
+    KeepaliveMissed,
+    /// Responses resumed after missed keepalives
+    Restored,
+    /// Consent expired; the path is unusable until ICE restarts
+    Expired,
+    /// An ICE restart offer was sent (or queued behind a negotiation)
+    IceRestartStarted,
+    IceRestartFailed(String),
+}
+
+/// One encoding of a simulcast video source
+///
+/// Each viewer has its own peer connection, so the host encodes every layer
+/// once and forwards the selected one to each subscriber instead of sending
+/// all layers on the wire.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SimulcastLayer {
+    /// Layer name, unique within the source ("h", "m", "l")
+    pub rid: String,
+    /// Resolution divisor relative to the captured frame; at least 1
+    pub scale_resolution_down_by: f64,
+    pub max_bitrate_kbps: u32,
+    pub max_framerate: u32,
+}
+
+impl SimulcastLayer {
+    pub fn new(
+        rid: &str,
+        scale_resolution_down_by: f64,
+        max_bitrate_kbps: u32,
+        max_framerate: u32,
+    ) -> Self {
+        Self {
+            rid: rid.to_string(),
+            scale_resolution_down_by,
+            max_bitrate_kbps,
+            max_framerate,
+        }
+    }
+
+    /// Full, half and quarter resolution, best first
+    pub fn standard() -> Vec<Self> {
+        vec![
+            Self::new("h", 1.0, 4000, 30),
+            Self::new("m", 2.0, 1200, 30),
+            Self::new("l", 4.0, 300, 15),
+        ]
+    }
+
+    /// Check a layer list, which must be ordered best first
+    pub fn validate_layers(layers: &[Self]) -> Result<()> {
+        if layers.is_empty() {
+            return Err(anyhow::anyhow!("Simulcast needs at least one layer"));
+        }
+        for (i, layer) in layers.iter().enumerate() {
+            if layer.rid.is_empty() {
+                return Err(anyhow::anyhow!("Simulcast layer {} has no rid", i));
+            }
+            if layers[..i].iter().any(|other| other.rid == layer.rid) {
+                return Err(anyhow::anyhow!("Duplicate simulcast layer {}", layer.rid));
+            }
+            if layer.scale_resolution_down_by.is_nan() || layer.scale_resolution_down_by < 1.0 {
+                return Err(anyhow::anyhow!(
+                    "Simulcast layer {} must not scale up",
+                    layer.rid
+                ));
+            }
+            if layer.max_bitrate_kbps == 0 || layer.max_framerate == 0 {
+                return Err(anyhow::anyhow!(
+                    "Simulcast layer {} needs a bitrate and frame rate",
+                    layer.rid
+                ));
+            }
+            if i > 0 && layer.max_bitrate_kbps > layers[i - 1].max_bitrate_kbps {
+                return Err(anyhow::anyhow!(
+                    "Simulcast layers must be ordered best first ({} after {})",
+                    layer.rid,
+                    layers[i - 1].rid
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Best layer whose bitrate fits `available_kbps`, else the lowest
+    pub fn for_bandwidth(layers: &[Self], available_kbps: u32) -> Option<&Self> {
+        layers
+            .iter()
+            .find(|layer| layer.max_bitrate_kbps <= available_kbps)
+            .or_else(|| layers.last())
+    }
+}
+
+/// Label of the channel carrying control messages (input, clipboard)
+pub const CONTROL_CHANNEL_LABEL: &str = "control";
+
+/// SCTP stream of the control channel; pre-negotiated so both peers open it
+/// without either having to announce it
+const CONTROL_CHANNEL_ID: u16 = 0;
+
+/// Largest message a data channel carries; the receiving stack closes the
+/// channel on anything bigger, so larger payloads must be chunked
+pub const MAX_DATA_CHANNEL_MESSAGE: usize = u16::MAX as usize;
+
+/// Control messages held while the control channel is not yet open
+pub const MAX_PENDING_CONTROL_MESSAGES: usize = 256;
+
+/// How often `select_data_transport` checks whether a channel has opened
+const DATA_CHANNEL_OPEN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How hard a data channel tries to deliver each message
+///
+/// This WebRTC stack reads a zero limit as "no limit", so limits start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Reliability {
+    /// Retransmit until delivered
+    Reliable,
+    /// Give up after this many retransmissions
+    MaxRetransmits(u16),
+    /// Give up once a message is this many milliseconds old
+    MaxPacketLifeTimeMs(u16),
+}
+
+/// Options for `WebRTCEngine::create_data_channel`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DataChannelConfig {
+    pub label: String,
+    /// Deliver messages in the order they were sent
+    pub ordered: bool,
+    pub reliability: Reliability,
+    /// Sub-protocol announced to the peer
+    pub protocol: String,
+    /// Buffered outgoing bytes at which `BufferedAmountHigh` asks senders to
+    /// hold off
+    pub high_water_mark: usize,
+    /// Buffered outgoing bytes below which `BufferedAmountLow` lets them
+    /// resume
+    pub low_water_mark: usize,
+}
+
+impl DataChannelConfig {
+    /// Ordered and reliable, for file transfer and clipboard
+    pub fn reliable(label: &str) -> Self {
+        Self {
+            label: label.to_string(),
+            ordered: true,
+            reliability: Reliability::Reliable,
+            protocol: String::new(),
+            high_water_mark: 1024 * 1024,
+            low_water_mark: 256 * 1024,
+        }
+    }
+
+    /// Unordered with a single retransmission, for pointer motion and other
+    /// data a newer message supersedes
+    pub fn unreliable(label: &str) -> Self {
+        Self {
+            ordered: false,
+            reliability: Reliability::MaxRetransmits(1),
+            high_water_mark: 64 * 1024,
+            low_water_mark: 16 * 1024,
+            ..Self::reliable(label)
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.label.is_empty() {
+            return Err(anyhow::anyhow!("Data channel label must not be empty"));
+        }
+        if matches!(
+            self.reliability,
+            Reliability::MaxRetransmits(0) | Reliability::MaxPacketLifeTimeMs(0)
+        ) {
+            return Err(anyhow::anyhow!("Data channel delivery limits start at 1"));
+        }
+        if self.low_water_mark >= self.high_water_mark {
+            return Err(anyhow::anyhow!(
+                "Data channel low water mark must be below the high water mark"
+            ));
+        }
+        Ok(())
+    }
+
+    fn init(&self) -> RTCDataChannelInit {
+        let (max_retransmits, max_packet_life_time) = match self.reliability {
+            Reliability::Reliable => (None, None),
+            Reliability::MaxRetransmits(n) => (Some(n), None),
+            Reliability::MaxPacketLifeTimeMs(ms) => (None, Some(ms)),
+        };
+        RTCDataChannelInit {
+            ordered: Some(self.ordered),
+            max_retransmits,
+            max_packet_life_time,
+            protocol: Some(self.protocol.clone()),
+            negotiated: None,
+        }
+    }
+
+    /// Settings of a channel the remote peer opened
+    fn of_remote(channel: &RTCDataChannel) -> Self {
+        let reliability = if channel.max_retransmits() > 0 {
+            Reliability::MaxRetransmits(channel.max_retransmits())
+        } else if channel.max_packet_lifetime() > 0 {
+            Reliability::MaxPacketLifeTimeMs(channel.max_packet_lifetime())
+        } else {
+            Reliability::Reliable
+        };
+        Self {
+            ordered: channel.ordered(),
+            reliability,
+            protocol: channel.protocol().to_string(),
+            ..Self::reliable(channel.label())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataChannelMessage {
+    Binary(Vec<u8>),
+    Text(String),
+}
+
+/// Data channel activity reported through `WebRTCEvent::DataChannel`
+///
+/// Messages on the control channel are reported as
+/// `WebRTCEvent::DataReceived` instead, still encoded for `decode_data`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataChannelEvent {
+    Opened,
+    Closed,
+    Message(DataChannelMessage),
+    /// Buffered outgoing bytes reached the high water mark; hold off until
+    /// `BufferedAmountLow`
+    BufferedAmountHigh(usize),
+    /// The backlog drained below the low water mark
+    BufferedAmountLow,
+}
+
+/// A data channel as reported by `WebRTCEngine::data_channels`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataChannelInfo {
+    pub config: DataChannelConfig,
+    pub open: bool,
+    pub buffered_amount: usize,
+}
+
+/// A data channel of a connection
+struct DataChannelHandle {
+    channel: Arc<RTCDataChannel>,
+    config: DataChannelConfig,
+    /// `BufferedAmountHigh` was reported and the backlog has not drained
+    congested: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for DataChannelHandle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataChannelHandle")
+            .field("config", &self.config)
+            .field("state", &self.channel.ready_state())
+            .finish()
+    }
+}
+
 pub struct WebRTCEngine {
     connections: Arc<Mutex<HashMap<String, ConnectionInfo>>>,
     event_sender: mpsc::UnboundedSender<WebRTCEvent>,
     event_receiver: Arc<Mutex<mpsc::UnboundedReceiver<WebRTCEvent>>>,
     api: webrtc::api::API,
+    negotiator: Negotiator,
+    /// Codec of video tracks added from now on
+    video_codec: Mutex<DecoderCodec>,
+    /// Simulcast layers encoded for each video source, by track ID
+    simulcast: std::sync::RwLock<HashMap<String, Vec<SimulcastLayer>>>,
+    consent: ConsentConfig,
+}
+
+/// Video codecs offered for negotiation, in order of preference after the
+/// configured one
+const NEGOTIATED_VIDEO_CODECS: [DecoderCodec; 4] = [
+    DecoderCodec::AV1,
+    DecoderCodec::VP9,
+    DecoderCodec::H264,
+    DecoderCodec::VP8,
+];
+
+/// Codec parameters matching the media engine's defaults, or `None` for
+/// codecs this WebRTC stack cannot packetize
+fn video_codec_parameters(codec: DecoderCodec) -> Option<RTCRtpCodecParameters> {
+    use webrtc::api::media_engine::{MIME_TYPE_AV1, MIME_TYPE_H264, MIME_TYPE_VP8, MIME_TYPE_VP9};
+
+    let (mime_type, sdp_fmtp_line) = match codec {
+        DecoderCodec::AV1 => (MIME_TYPE_AV1, "profile-id=0"),
+        DecoderCodec::VP9 => (MIME_TYPE_VP9, "profile-id=0"),
+        DecoderCodec::H264 => (
+            MIME_TYPE_H264,
+            "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f",
+        ),
+        DecoderCodec::VP8 => (MIME_TYPE_VP8, ""),
+        DecoderCodec::H265 => return None,
+    };
+    let rtcp_feedback = [
+        ("goog-remb", ""),
+        ("ccm", "fir"),
+        ("nack", ""),
+        ("nack", "pli"),
+    ]
+    .into_iter()
+    .map(|(typ, parameter)| RTCPFeedback {
+        typ: typ.to_string(),
+        parameter: parameter.to_string(),
+    })
+    .collect();
+    Some(RTCRtpCodecParameters {
+        capability: RTCRtpCodecCapability {
+            mime_type: mime_type.to_string(),
+            clock_rate: 90000,
+            channels: 0,
+            sdp_fmtp_line: sdp_fmtp_line.to_string(),
+            rtcp_feedback,
+        },
+        ..Default::default()
+    })
 }
 
 #[derive(Debug)]
@@ -69,6 +503,20 @@ pub enum WebRTCEvent {
     IceCandidateReceived(String, RTCIceCandidate),
     OfferReceived(String, RTCSessionDescription),
     AnswerReceived(String, RTCSessionDescription),
+    /// Local tracks changed; the offer must be sent to the remote peer
+    RenegotiationNeeded(String, RTCSessionDescription),
+    RenegotiationProgress(String, RenegotiationProgress),
+    /// The remote viewer asked for a keyframe (PLI or FIR); forward it to
+    /// `ScreenCapturer::request_keyframe`
+    KeyframeRequested(String),
+    Consent(String, ConsentEvent),
+    /// An ICE restart completed: both sides use fresh credentials and
+    /// candidates on the new path
+    IceRestarted(String),
+    /// Connection ID, channel label and what happened
+    DataChannel(String, String, DataChannelEvent),
+    /// Connection ID, track ID and the simulcast layer it now receives
+    LayerSelected(String, String, String),
 }
 
 #[derive(Debug)]
@@ -77,8 +525,78 @@ pub struct ConnectionInfo {
     id: String,
     peer_connection: Arc<RTCPeerConnection>,
     state: RTCPeerConnectionState,
-    #[allow(dead_code)]
     remote_id: Option<String>,
+    role: NegotiationRole,
+    /// A track change arrived while a negotiation was in flight
+    renegotiation_queued: bool,
+    /// Our pending offer failed and will not be answered
+    offer_withdrawn: bool,
+    /// Outgoing audio track, present while audio capture is allowed
+    audio_sender: Option<Arc<RTCRtpSender>>,
+    /// Outgoing tracks added mid-session, by track ID
+    senders: HashMap<String, Arc<RTCRtpSender>>,
+    /// Sample writers of the outgoing video tracks, by track ID
+    video_tracks: HashMap<String, Arc<TrackLocalStaticSample>>,
+    /// Frames written to each outgoing video track, by track ID
+    frames_encoded: HashMap<String, Arc<Counter>>,
+    /// Simulcast layer each video track forwards, by track ID; the best
+    /// layer when unset
+    layer_selection: HashMap<String, String>,
+    /// Video codec chosen with `switch_video_codec`, overriding the engine's
+    video_codec: Option<DecoderCodec>,
+    /// Receive-side freeze statistics reported by the renderer
+    freeze_stats: FreezeStats,
+    keyframes: Arc<KeyframeCounters>,
+    consent: Arc<ConsentCounters>,
+    /// The next offer restarts ICE
+    ice_restart_pending: bool,
+    /// Our pending offer restarts ICE
+    ice_restart_offered: bool,
+    /// Framing of control data channel messages
+    data_codec: DataChannelCodec,
+    /// Data channels by label, the control channel included
+    data_channels: HashMap<String, DataChannelHandle>,
+    /// Control messages sent before the control channel opened
+    pending_control: VecDeque<Vec<u8>>,
+    /// Transport chosen for the data paths by `select_data_transport`
+    data_transport: Option<DataTransportType>,
+}
+
+/// Consent failures and recoveries on one connection
+#[derive(Debug, Default)]
+struct ConsentCounters {
+    keepalive_failures: Counter,
+    expirations: Counter,
+    ice_restarts: Counter,
+    /// Keepalives are currently missing
+    degraded: AtomicBool,
+}
+
+/// Keyframe requests sent and received on one connection
+#[derive(Debug, Default)]
+struct KeyframeCounters {
+    sent: Counter,
+    received: Counter,
+    /// Received requests not forwarded because they came too fast
+    throttled: Counter,
+    last_forwarded: std::sync::Mutex<Option<Instant>>,
+}
+
+impl KeyframeCounters {
+    /// Count a received request; returns whether it should reach the encoder
+    fn admit(&self) -> bool {
+        self.received.increment();
+        let Ok(mut last_forwarded) = self.last_forwarded.lock() else {
+            return false;
+        };
+        let min_interval = Duration::from_millis(KEYFRAME_REQUEST_MIN_INTERVAL_MS);
+        if last_forwarded.is_some_and(|at| at.elapsed() < min_interval) {
+            self.throttled.increment();
+            return false;
+        }
+        *last_forwarded = Some(Instant::now());
+        true
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -99,8 +617,27 @@ pub struct MediaTrack {
     enabled: bool,
 }
 
+/// Whether closing failed only because the data channels' stream resets
+/// could not be sent over an association the remote peer already closed
+///
+/// `RTCPeerConnection::close` flattens its errors into one line per failed
+/// step, so the steps are matched by message.
+fn is_reset_after_remote_close(error: &webrtc::Error) -> bool {
+    let reset = format!(
+        "data_channels: {}",
+        webrtc::sctp::Error::ErrResetPacketInStateNotExist
+    );
+    error.to_string().lines().all(|line| line == reset)
+}
+
 impl WebRTCEngine {
     pub async fn new() -> Result<Self> {
+        Self::with_consent_config(ConsentConfig::default()).await
+    }
+
+    /// Engine whose connections use `consent` for keepalives and expiry
+    pub async fn with_consent_config(consent: ConsentConfig) -> Result<Self> {
+        consent.validate()?;
         let (event_sender, event_receiver) = mpsc::unbounded_channel();
 
         // Create a MediaEngine object to configure the supported codec
@@ -112,20 +649,62 @@ impl WebRTCEngine {
         let mut registry = Registry::new();
         registry = register_default_interceptors(registry, &mut media_engine)?;
 
+        // The ICE agent fails the pair after disconnected + failed timeouts
+        let mut setting_engine = SettingEngine::default();
+        setting_engine.set_ice_timeouts(
+            Some(consent.missed_after),
+            Some(consent.consent_timeout - consent.missed_after),
+            Some(consent.keepalive_interval),
+        );
+
         // Create the API object with the MediaEngine
         let api = APIBuilder::new()
             .with_media_engine(media_engine)
             .with_interceptor_registry(registry)
+            .with_setting_engine(setting_engine)
             .build();
 
         Ok(Self {
             connections: Arc::new(Mutex::new(HashMap::new())),
+            negotiator: Negotiator {
+                signaling: Arc::default(),
+                events: event_sender.clone(),
+            },
             event_sender,
             event_receiver: Arc::new(Mutex::new(event_receiver)),
             api,
+            video_codec: Mutex::new(DecoderCodec::VP8),
+            simulcast: std::sync::RwLock::default(),
+            consent,
         })
     }
 
+    /// Send video tracks added from now on with `codec`
+    ///
+    /// Pick a codec the viewer can decode (see
+    /// `CaptureOptions::constrained_to`). Fails for codecs the WebRTC stack
+    /// cannot negotiate.
+    pub async fn set_video_codec(&self, codec: DecoderCodec) -> Result<()> {
+        if video_codec_parameters(codec).is_none() {
+            return Err(anyhow::anyhow!(
+                "Video codec {:?} cannot be negotiated over WebRTC",
+                codec
+            ));
+        }
+        *self.video_codec.lock().await = codec;
+        tracing::info!("Video tracks will use {:?}", codec);
+        Ok(())
+    }
+
+    pub async fn video_codec(&self) -> DecoderCodec {
+        *self.video_codec.lock().await
+    }
+
+    /// Send renegotiation offers and answers through `client`
+    pub async fn set_signaling(&self, client: Arc<SignalingClient>) {
+        *self.negotiator.signaling.lock().await = Some(client);
+    }
+
     pub async fn create_peer_connection(&self, config: RTCConfiguration) -> Result<String> {
         let connection_id = Uuid::new_v4().to_string();
 
@@ -180,12 +759,82 @@ impl WebRTCEngine {
             })
         }));
 
+        self.watch_consent(&connection_id, &peer_connection);
+
+        // Channels the remote peer opens
+        let connection_id_clone = connection_id.clone();
+        let event_sender_clone = self.event_sender.clone();
+        let connections = Arc::clone(&self.connections);
+        peer_connection.on_data_channel(Box::new(move |channel| {
+            let connection_id = connection_id_clone.clone();
+            let event_sender = event_sender_clone.clone();
+            let connections = Arc::clone(&connections);
+            Box::pin(async move {
+                let config = DataChannelConfig::of_remote(&channel);
+                tracing::info!(
+                    "Remote opened data channel {} on connection {}",
+                    config.label,
+                    connection_id
+                );
+                let handle = watch_data_channel(
+                    &connection_id,
+                    channel,
+                    config,
+                    &event_sender,
+                    &connections,
+                )
+                .await;
+                if let Some(conn_info) = connections.lock().await.get_mut(&connection_id) {
+                    conn_info
+                        .data_channels
+                        .insert(handle.config.label.clone(), handle);
+                }
+            })
+        }));
+
+        let control_config = DataChannelConfig::reliable(CONTROL_CHANNEL_LABEL);
+        let control = peer_connection
+            .create_data_channel(
+                CONTROL_CHANNEL_LABEL,
+                Some(RTCDataChannelInit {
+                    negotiated: Some(CONTROL_CHANNEL_ID),
+                    ..control_config.init()
+                }),
+            )
+            .await?;
+        let control = watch_data_channel(
+            &connection_id,
+            control,
+            control_config,
+            &self.event_sender,
+            &self.connections,
+        )
+        .await;
+
         // Store connection info
         let connection_info = ConnectionInfo {
             id: connection_id.clone(),
             peer_connection: Arc::clone(&peer_connection),
             state: RTCPeerConnectionState::New,
             remote_id: None,
+            role: NegotiationRole::Polite,
+            renegotiation_queued: false,
+            offer_withdrawn: false,
+            audio_sender: None,
+            senders: HashMap::new(),
+            video_tracks: HashMap::new(),
+            frames_encoded: HashMap::new(),
+            layer_selection: HashMap::new(),
+            video_codec: None,
+            freeze_stats: FreezeStats::default(),
+            keyframes: Arc::default(),
+            consent: Arc::default(),
+            ice_restart_pending: false,
+            ice_restart_offered: false,
+            data_codec: DataChannelCodec::new(None),
+            data_channels: HashMap::from([(CONTROL_CHANNEL_LABEL.to_string(), control)]),
+            pending_control: VecDeque::new(),
+            data_transport: None,
         };
 
         self.connections
@@ -198,10 +847,11 @@ impl WebRTCEngine {
     }
 
     pub async fn establish_connection(&self, connection_id: &str, remote_id: String) -> Result<()> {
-        let connections = self.connections.lock().await;
+        let mut connections = self.connections.lock().await;
         let connection_info = connections
-            .get(connection_id)
+            .get_mut(connection_id)
             .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        connection_info.remote_id = Some(remote_id.clone());
 
         // Create offer
         let offer = connection_info.peer_connection.create_offer(None).await?;
@@ -252,92 +902,749 @@ impl WebRTCEngine {
         Ok(answer)
     }
 
+    /// Record the remote device and derive this side's negotiation role
+    pub async fn set_remote_peer(
+        &self,
+        connection_id: &str,
+        local_id: &str,
+        remote_id: String,
+    ) -> Result<NegotiationRole> {
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        connection_info.role = NegotiationRole::for_peers(local_id, &remote_id);
+        connection_info.remote_id = Some(remote_id);
+        Ok(connection_info.role)
+    }
+
+    pub async fn negotiation_role(&self, connection_id: &str) -> Result<NegotiationRole> {
+        let connections = self.connections.lock().await;
+        let connection_info = connections
+            .get(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        Ok(connection_info.role)
+    }
+
+    pub async fn set_negotiation_role(
+        &self,
+        connection_id: &str,
+        role: NegotiationRole,
+    ) -> Result<()> {
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        connection_info.role = role;
+        Ok(())
+    }
+
+    /// Apply the remote answer; a rejected answer rolls our offer back
+    ///
+    /// A track change queued during the negotiation is offered right after.
     pub async fn handle_remote_answer(
         &self,
         connection_id: &str,
         answer: RTCSessionDescription,
     ) -> Result<()> {
-        let connections = self.connections.lock().await;
+        let mut connections = self.connections.lock().await;
         let connection_info = connections
-            .get(connection_id)
+            .get_mut(connection_id)
             .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
 
-        connection_info
+        if let Err(e) = connection_info
             .peer_connection
             .set_remote_description(answer)
-            .await?;
+            .await
+        {
+            let _ = self
+                .negotiator
+                .rollback_offer(connection_id, connection_info, &e.to_string())
+                .await;
+            return Err(e.into());
+        }
         tracing::info!("Set remote answer for connection {}", connection_id);
+        self.negotiator
+            .emit_progress(connection_id, RenegotiationProgress::Completed);
+        if std::mem::take(&mut connection_info.ice_restart_offered) {
+            self.ice_restarted(connection_id);
+        }
+
+        if std::mem::take(&mut connection_info.renegotiation_queued) {
+            self.negotiator
+                .negotiate(connection_id, connection_info)
+                .await?;
+        }
         Ok(())
     }
 
-    pub async fn add_ice_candidate(
+    /// Answer a remote offer, resolving collisions with our own offer
+    ///
+    /// Returns `None` when the offer collided and this side is impolite. The
+    /// answer is also sent through signaling when it is configured.
+    pub async fn handle_renegotiation_offer(
         &self,
         connection_id: &str,
-        candidate: RTCIceCandidate,
-    ) -> Result<()> {
-        let connections = self.connections.lock().await;
+        offer: RTCSessionDescription,
+    ) -> Result<Option<RTCSessionDescription>> {
+        let mut connections = self.connections.lock().await;
         let connection_info = connections
-            .get(connection_id)
+            .get_mut(connection_id)
             .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
 
-        // Convert RTCIceCandidate to RTCIceCandidateInit
-        let candidate_init = webrtc::ice_transport::ice_candidate::RTCIceCandidateInit {
-            candidate: candidate.to_json()?.candidate,
-            sdp_mid: candidate.to_json()?.sdp_mid,
-            sdp_mline_index: candidate.to_json()?.sdp_mline_index,
-            username_fragment: candidate.to_json()?.username_fragment,
+        let collision =
+            connection_info.peer_connection.signaling_state() != RTCSignalingState::Stable;
+        if collision {
+            if connection_info.role == NegotiationRole::Impolite {
+                tracing::info!("Ignoring colliding offer on connection {}", connection_id);
+                self.negotiator
+                    .emit_progress(connection_id, RenegotiationProgress::RemoteOfferIgnored);
+                return Ok(None);
+            }
+            self.negotiator
+                .rollback_offer(connection_id, connection_info, "offer collision")
+                .await?;
+            connection_info.renegotiation_queued = true;
+        }
+
+        let peer_connection = &connection_info.peer_connection;
+        let remote_ufrag = peer_connection
+            .current_remote_description()
+            .await
+            .and_then(|current| ice_ufrag(&current.sdp).map(str::to_string));
+        let restarts_ice = remote_ufrag.is_some_and(|ufrag| ice_ufrag(&offer.sdp) != Some(&ufrag));
+        peer_connection.set_remote_description(offer).await?;
+        let answer = peer_connection.create_answer(None).await?;
+        peer_connection
+            .set_local_description(answer.clone())
+            .await?;
+        if let (Some(signaling), Some(remote_id)) = (
+            self.negotiator.signaling.lock().await.clone(),
+            &connection_info.remote_id,
+        ) {
+            signaling.send_answer(remote_id, &answer.sdp).await?;
+        }
+        tracing::info!("Answered renegotiation on connection {}", connection_id);
+        self.negotiator
+            .emit_progress(connection_id, RenegotiationProgress::Completed);
+        if restarts_ice {
+            self.ice_restarted(connection_id);
+        }
+
+        if std::mem::take(&mut connection_info.renegotiation_queued) {
+            self.negotiator
+                .negotiate(connection_id, connection_info)
+                .await?;
+        }
+        Ok(Some(answer))
+    }
+
+    /// Add an outgoing track ("audio" or "video") and renegotiate
+    ///
+    /// Video tracks use the codec set with `set_video_codec` (or
+    /// `switch_video_codec` for this connection), listed first in the offer.
+    pub async fn add_media_track(
+        &self,
+        connection_id: &str,
+        kind: &str,
+        track_id: String,
+    ) -> Result<()> {
+        let engine_codec = *self.video_codec.lock().await;
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        if connection_info.senders.contains_key(&track_id) {
+            return Ok(());
+        }
+        let video_codec = connection_info.video_codec.unwrap_or(engine_codec);
+        let capability = match kind {
+            "audio" => RTCRtpCodecCapability {
+                mime_type: webrtc::api::media_engine::MIME_TYPE_OPUS.to_string(),
+                ..Default::default()
+            },
+            "video" => video_codec_parameters(video_codec)
+                .map(|parameters| parameters.capability)
+                .ok_or_else(|| anyhow::anyhow!("Unsupported video codec: {:?}", video_codec))?,
+            _ => return Err(anyhow::anyhow!("Unsupported track kind: {}", kind)),
         };
 
-        connection_info
+        let track = Arc::new(TrackLocalStaticSample::new(
+            capability,
+            track_id.clone(),
+            format!("cec-{}", kind),
+        ));
+        let sender = connection_info
             .peer_connection
-            .add_ice_candidate(candidate_init)
+            .add_track(Arc::clone(&track) as _)
             .await?;
-        tracing::info!("Added ICE candidate for connection {}", connection_id);
+        if kind == "video" {
+            prefer_video_codec(&connection_info.peer_connection, &sender, video_codec).await?;
+            self.watch_keyframe_requests(
+                connection_id,
+                Arc::clone(&sender),
+                Arc::clone(&connection_info.keyframes),
+            );
+            connection_info.video_tracks.insert(track_id.clone(), track);
+            connection_info
+                .frames_encoded
+                .insert(track_id.clone(), Arc::default());
+        }
+        connection_info.senders.insert(track_id.clone(), sender);
+
+        if let Err(e) = self
+            .negotiator
+            .negotiate(connection_id, connection_info)
+            .await
+        {
+            // Keep the track set consistent with what the remote agreed to
+            if let Some(sender) = connection_info.senders.remove(&track_id) {
+                let _ = connection_info.peer_connection.remove_track(&sender).await;
+            }
+            connection_info.video_tracks.remove(&track_id);
+            connection_info.frames_encoded.remove(&track_id);
+            return Err(e);
+        }
         Ok(())
     }
 
-    pub async fn close_connection(&self, connection_id: &str) -> Result<()> {
+    /// Move the connection's outgoing video to `codec` and renegotiate
+    ///
+    /// Each video track is replaced in place, keeping its ID and transceiver,
+    /// so the session carries on without a new connection. Video tracks added
+    /// to this connection later use `codec` too.
+    pub async fn switch_video_codec(&self, connection_id: &str, codec: DecoderCodec) -> Result<()> {
+        let capability = video_codec_parameters(codec)
+            .map(|parameters| parameters.capability)
+            .ok_or_else(|| {
+                anyhow::anyhow!("Video codec {:?} cannot be negotiated over WebRTC", codec)
+            })?;
         let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        if connection_info.video_codec == Some(codec) {
+            return Ok(());
+        }
 
-        if let Some(connection_info) = connections.remove(connection_id) {
-            connection_info.peer_connection.close().await?;
-            tracing::info!("Connection {} closed", connection_id);
+        let mut switched = 0;
+        for (track_id, sender) in &connection_info.senders {
+            let Some(track) = sender.track().await else {
+                continue;
+            };
+            if track.kind() != RTPCodecType::Video {
+                continue;
+            }
+            let replacement = Arc::new(TrackLocalStaticSample::new(
+                capability.clone(),
+                track.id().to_string(),
+                track.stream_id().to_string(),
+            ));
+            sender
+                .replace_track(Some(Arc::clone(&replacement) as _))
+                .await?;
+            prefer_video_codec(&connection_info.peer_connection, sender, codec).await?;
+            connection_info
+                .video_tracks
+                .insert(track_id.clone(), replacement);
+            switched += 1;
         }
+        connection_info.video_codec = Some(codec);
+        if switched == 0 {
+            return Ok(());
+        }
+
+        tracing::info!(
+            "Switched {} video track(s) on connection {} to {:?}, renegotiating",
+            switched,
+            connection_id,
+            codec
+        );
+        self.negotiator
+            .negotiate(connection_id, connection_info)
+            .await
+    }
+
+    /// Encode the video source `track_id` in `layers`, ordered best first
+    ///
+    /// Connections sending the track forward one layer each, chosen with
+    /// `set_subscriber_layer`. A selection naming a layer that no longer
+    /// exists falls back to the best one.
+    pub fn set_simulcast_layers(&self, track_id: &str, layers: Vec<SimulcastLayer>) -> Result<()> {
+        SimulcastLayer::validate_layers(&layers)?;
+        tracing::info!(
+            "Simulcast for {}: {:?}",
+            track_id,
+            layers.iter().map(|l| l.rid.as_str()).collect::<Vec<_>>()
+        );
+        self.simulcast_table().insert(track_id.to_string(), layers);
         Ok(())
     }
 
-    pub async fn get_connection_state(
+    /// Send `track_id` as a single layer again
+    pub fn clear_simulcast_layers(&self, track_id: &str) {
+        self.simulcast_table().remove(track_id);
+    }
+
+    /// Layers the encoder should produce for `track_id`; empty without
+    /// simulcast
+    pub fn simulcast_layers(&self, track_id: &str) -> Vec<SimulcastLayer> {
+        self.simulcast
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .get(track_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Forward layer `rid` of `track_id` to one viewer
+    ///
+    /// The viewer's decoder needs a keyframe of the new layer, so a change
+    /// also emits `KeyframeRequested`.
+    pub async fn set_subscriber_layer(
         &self,
         connection_id: &str,
-    ) -> Option<RTCPeerConnectionState> {
-        let connections = self.connections.lock().await;
-        connections
-            .get(connection_id)
-            .map(|conn| conn.state.clone())
+        track_id: &str,
+        rid: &str,
+    ) -> Result<()> {
+        let layers = self.simulcast_layers(track_id);
+        if !layers.iter().any(|layer| layer.rid == rid) {
+            return Err(anyhow::anyhow!(
+                "No simulcast layer {} for track {}",
+                rid,
+                track_id
+            ));
+        }
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        if !connection_info.video_tracks.contains_key(track_id) {
+            return Err(anyhow::anyhow!(
+                "No video track {} on connection {}",
+                track_id,
+                connection_id
+            ));
+        }
+        let current = selected_layer(&layers, connection_info.layer_selection.get(track_id));
+        connection_info
+            .layer_selection
+            .insert(track_id.to_string(), rid.to_string());
+        if current == Some(rid) {
+            return Ok(());
+        }
+        drop(connections);
+
+        tracing::info!(
+            "Connection {} now receives layer {} of {}",
+            connection_id,
+            rid,
+            track_id
+        );
+        let _ = self.event_sender.send(WebRTCEvent::LayerSelected(
+            connection_id.to_string(),
+            track_id.to_string(),
+            rid.to_string(),
+        ));
+        let _ = self
+            .event_sender
+            .send(WebRTCEvent::KeyframeRequested(connection_id.to_string()));
+        Ok(())
     }
 
-    pub async fn get_connection_stats(&self, connection_id: &str) -> Result<ConnectionStats> {
+    /// Forward the best layer of `track_id` that fits `available_kbps`
+    ///
+    /// Feed it the viewer's bandwidth estimate; returns the chosen rid.
+    pub async fn select_layer_for_bandwidth(
+        &self,
+        connection_id: &str,
+        track_id: &str,
+        available_kbps: u32,
+    ) -> Result<String> {
+        let layers = self.simulcast_layers(track_id);
+        let rid = SimulcastLayer::for_bandwidth(&layers, available_kbps)
+            .map(|layer| layer.rid.clone())
+            .ok_or_else(|| anyhow::anyhow!("Track {} is not simulcast", track_id))?;
+        self.set_subscriber_layer(connection_id, track_id, &rid)
+            .await?;
+        Ok(rid)
+    }
+
+    /// Layer of `track_id` the connection forwards, `None` without simulcast
+    pub async fn subscriber_layer(&self, connection_id: &str, track_id: &str) -> Option<String> {
+        let layers = self.simulcast_layers(track_id);
         let connections = self.connections.lock().await;
-        let connection_info = connections
-            .get(connection_id)
-            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        let selection = connections
+            .get(connection_id)?
+            .layer_selection
+            .get(track_id);
+        selected_layer(&layers, selection).map(str::to_string)
+    }
 
-        let _stats = connection_info.peer_connection.get_stats().await;
-
-        // Convert webrtc stats to our format
-        Ok(ConnectionStats {
-            connection_id: connection_id.to_string(),
-            state: connection_info.state.clone(),
-            bytes_sent: 0,       // TODO: Extract from stats
-            bytes_received: 0,   // TODO: Extract from stats
-            packets_sent: 0,     // TODO: Extract from stats
-            packets_received: 0, // TODO: Extract from stats
-            rtt: 0.0,            // TODO: Extract from stats
-        })
+    /// Send an encoded frame of `track_id` to every connection forwarding
+    /// `layer`
+    ///
+    /// Pass `None` for tracks without simulcast. Returns how many
+    /// connections received the frame.
+    pub async fn write_video_sample(
+        &self,
+        track_id: &str,
+        layer: Option<&str>,
+        sample: &Sample,
+    ) -> Result<usize> {
+        let layers = self.simulcast_layers(track_id);
+        let tracks: Vec<_> = {
+            let connections = self.connections.lock().await;
+            connections
+                .values()
+                .filter(|info| selected_layer(&layers, info.layer_selection.get(track_id)) == layer)
+                .filter_map(|info| {
+                    let track = info.video_tracks.get(track_id)?;
+                    let frames = info.frames_encoded.get(track_id)?;
+                    Some((Arc::clone(track), Arc::clone(frames)))
+                })
+                .collect()
+        };
+        for (track, frames) in &tracks {
+            track.write_sample(sample).await?;
+            frames.increment();
+        }
+        Ok(tracks.len())
     }
 
-    pub async fn get_event_receiver(&self) -> Arc<Mutex<mpsc::UnboundedReceiver<WebRTCEvent>>> {
-        Arc::clone(&self.event_receiver)
+    fn simulcast_table(
+        &self,
+    ) -> std::sync::RwLockWriteGuard<'_, HashMap<String, Vec<SimulcastLayer>>> {
+        self.simulcast
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    /// Read RTCP for an outgoing video track until it is removed, turning
+    /// picture loss reports into `KeyframeRequested` events
+    ///
+    /// The viewer decides when to ask, so requests are limited here to one
+    /// per `KEYFRAME_REQUEST_MIN_INTERVAL_MS`.
+    fn watch_keyframe_requests(
+        &self,
+        connection_id: &str,
+        sender: Arc<RTCRtpSender>,
+        keyframes: Arc<KeyframeCounters>,
+    ) {
+        let connection_id = connection_id.to_string();
+        let event_sender = self.event_sender.clone();
+        tokio::spawn(async move {
+            while let Ok((packets, _)) = sender.read_rtcp().await {
+                let picture_lost = packets.iter().any(|packet| {
+                    let packet = packet.as_any();
+                    packet.is::<PictureLossIndication>() || packet.is::<FullIntraRequest>()
+                });
+                if picture_lost && keyframes.admit() {
+                    let _ =
+                        event_sender.send(WebRTCEvent::KeyframeRequested(connection_id.clone()));
+                }
+            }
+        });
+    }
+
+    /// Ask the remote sender for a keyframe, sending a picture loss
+    /// indication for each incoming video track
+    ///
+    /// Viewer requests are rate limited by `SessionManager::request_keyframe`
+    /// before they get here.
+    pub async fn request_keyframe(&self, connection_id: &str) -> Result<()> {
+        let (peer_connection, keyframes) = {
+            let connections = self.connections.lock().await;
+            let connection_info = connections
+                .get(connection_id)
+                .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+            (
+                Arc::clone(&connection_info.peer_connection),
+                Arc::clone(&connection_info.keyframes),
+            )
+        };
+
+        let mut packets: Vec<Box<dyn webrtc::rtcp::packet::Packet + Send + Sync>> = Vec::new();
+        for receiver in peer_connection.get_receivers().await {
+            for track in receiver.tracks().await {
+                if track.kind() == RTPCodecType::Video {
+                    packets.push(Box::new(PictureLossIndication {
+                        sender_ssrc: 0,
+                        media_ssrc: track.ssrc(),
+                    }));
+                }
+            }
+        }
+        if packets.is_empty() {
+            return Err(anyhow::anyhow!(
+                "No incoming video on connection: {}",
+                connection_id
+            ));
+        }
+        peer_connection.write_rtcp(&packets).await?;
+        keyframes.sent.increment();
+        Ok(())
+    }
+
+    /// Turn ICE connection state changes into consent events, restarting
+    /// ICE when consent expires
+    fn watch_consent(&self, connection_id: &str, peer_connection: &RTCPeerConnection) {
+        let connection_id = connection_id.to_string();
+        let event_sender = self.event_sender.clone();
+        let connections = Arc::clone(&self.connections);
+        let negotiator = self.negotiator.clone();
+        let auto_restart = self.consent.auto_ice_restart;
+
+        peer_connection.on_ice_connection_state_change(Box::new(move |state| {
+            let connection_id = connection_id.clone();
+            let event_sender = event_sender.clone();
+            let connections = Arc::clone(&connections);
+            let negotiator = negotiator.clone();
+
+            Box::pin(async move {
+                let Some(counters) = connections
+                    .lock()
+                    .await
+                    .get(&connection_id)
+                    .map(|c| Arc::clone(&c.consent))
+                else {
+                    return;
+                };
+                let event = match state {
+                    RTCIceConnectionState::Disconnected => {
+                        counters.keepalive_failures.increment();
+                        counters.degraded.store(true, Ordering::SeqCst);
+                        ConsentEvent::KeepaliveMissed
+                    }
+                    RTCIceConnectionState::Connected | RTCIceConnectionState::Completed
+                        if counters.degraded.swap(false, Ordering::SeqCst) =>
+                    {
+                        ConsentEvent::Restored
+                    }
+                    RTCIceConnectionState::Failed => {
+                        counters.expirations.increment();
+                        counters.degraded.store(true, Ordering::SeqCst);
+                        ConsentEvent::Expired
+                    }
+                    _ => return,
+                };
+                tracing::warn!("Consent on connection {}: {:?}", connection_id, event);
+                let expired = event == ConsentEvent::Expired;
+                let _ = event_sender.send(WebRTCEvent::Consent(connection_id.clone(), event));
+
+                if expired && auto_restart {
+                    // Off the ICE agent's callback, which the offer waits on
+                    tokio::spawn(async move {
+                        let mut connections = connections.lock().await;
+                        let Some(connection_info) = connections.get_mut(&connection_id) else {
+                            return;
+                        };
+                        if connection_info.role == NegotiationRole::Polite {
+                            // The impolite peer restarts; we answer its offer
+                            return;
+                        }
+                        let event =
+                            match restart_ice(&negotiator, &connection_id, connection_info).await {
+                                Ok(()) => ConsentEvent::IceRestartStarted,
+                                Err(e) => ConsentEvent::IceRestartFailed(e.to_string()),
+                            };
+                        let _ = event_sender.send(WebRTCEvent::Consent(connection_id, event));
+                    });
+                }
+            })
+        }));
+    }
+
+    /// Restart ICE with a new offer, gathering fresh candidates
+    ///
+    /// Recovers a connection whose path died, e.g. after a network change
+    /// (Wi-Fi to LTE) left it Disconnected or Failed. New credentials are
+    /// offered and candidates gathered for them; `IceRestarted` follows once
+    /// the remote answer is applied. Queued behind a negotiation in flight.
+    pub async fn restart_ice(&self, connection_id: &str) -> Result<()> {
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        let result = restart_ice(&self.negotiator, connection_id, connection_info).await;
+        let event = match &result {
+            Ok(()) => ConsentEvent::IceRestartStarted,
+            Err(e) => ConsentEvent::IceRestartFailed(e.to_string()),
+        };
+        let _ = self
+            .event_sender
+            .send(WebRTCEvent::Consent(connection_id.to_string(), event));
+        result
+    }
+
+    fn ice_restarted(&self, connection_id: &str) {
+        tracing::info!("ICE restarted on connection {}", connection_id);
+        let _ = self
+            .event_sender
+            .send(WebRTCEvent::IceRestarted(connection_id.to_string()));
+    }
+
+    /// Remove a track added with `add_media_track` and renegotiate
+    pub async fn remove_media_track(&self, connection_id: &str, track_id: &str) -> Result<()> {
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        let Some(sender) = connection_info.senders.remove(track_id) else {
+            return Ok(());
+        };
+        connection_info.video_tracks.remove(track_id);
+        connection_info.frames_encoded.remove(track_id);
+        connection_info.layer_selection.remove(track_id);
+        connection_info
+            .peer_connection
+            .remove_track(&sender)
+            .await?;
+        self.negotiator
+            .negotiate(connection_id, connection_info)
+            .await
+    }
+
+    pub async fn add_ice_candidate(
+        &self,
+        connection_id: &str,
+        candidate: RTCIceCandidate,
+    ) -> Result<()> {
+        let connections = self.connections.lock().await;
+        let connection_info = connections
+            .get(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+
+        // Convert RTCIceCandidate to RTCIceCandidateInit
+        let candidate_init = webrtc::ice_transport::ice_candidate::RTCIceCandidateInit {
+            candidate: candidate.to_json()?.candidate,
+            sdp_mid: candidate.to_json()?.sdp_mid,
+            sdp_mline_index: candidate.to_json()?.sdp_mline_index,
+            username_fragment: candidate.to_json()?.username_fragment,
+        };
+
+        connection_info
+            .peer_connection
+            .add_ice_candidate(candidate_init)
+            .await?;
+        tracing::info!("Added ICE candidate for connection {}", connection_id);
+        Ok(())
+    }
+
+    pub async fn close_connection(&self, connection_id: &str) -> Result<()> {
+        // Release the lock first: closing fires the state change handler,
+        // which takes it too
+        let removed = self.connections.lock().await.remove(connection_id);
+
+        if let Some(connection_info) = removed {
+            if let Err(e) = connection_info.peer_connection.close().await {
+                if !is_reset_after_remote_close(&e) {
+                    return Err(e.into());
+                }
+                // The remote peer already tore the association down, so our
+                // data channels cannot send their stream resets
+                tracing::debug!("Connection {} closed after its peer: {}", connection_id, e);
+            }
+            tracing::info!("Connection {} closed", connection_id);
+        }
+        Ok(())
+    }
+
+    pub async fn get_connection_state(
+        &self,
+        connection_id: &str,
+    ) -> Option<RTCPeerConnectionState> {
+        let connections = self.connections.lock().await;
+        connections
+            .get(connection_id)
+            .map(|conn| conn.state.clone())
+    }
+
+    /// Connection totals; see `get_detailed_stats` for the per-track view
+    pub async fn get_connection_stats(&self, connection_id: &str) -> Result<ConnectionStats> {
+        Ok(self.get_detailed_stats(connection_id).await?.summary)
+    }
+
+    /// getStats-style report: per-track inbound and outbound RTP counters,
+    /// RTCP feedback and round trip times, plus the connection totals
+    ///
+    /// Serializes for the dashboard. Encoder and decoder figures come from
+    /// this side: frames written with `write_video_sample` and the
+    /// renderer's `record_freeze_stats`.
+    pub async fn get_detailed_stats(&self, connection_id: &str) -> Result<DetailedStats> {
+        let (peer_connection, summary, frames_encoded) = {
+            let connections = self.connections.lock().await;
+            let connection_info = connections
+                .get(connection_id)
+                .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+            let summary = ConnectionStats {
+                connection_id: connection_id.to_string(),
+                state: connection_info.state.clone(),
+                bytes_sent: 0,
+                bytes_received: 0,
+                packets_sent: 0,
+                packets_received: 0,
+                rtt: 0.0,
+                frames_decoded: connection_info.freeze_stats.frames_decoded,
+                freeze_count: connection_info.freeze_stats.freeze_count,
+                total_freeze_ms: connection_info.freeze_stats.total_freeze_ms,
+                keyframe_requests_sent: connection_info.keyframes.sent.get(),
+                keyframe_requests_received: connection_info.keyframes.received.get(),
+                keyframe_requests_throttled: connection_info.keyframes.throttled.get(),
+                keepalive_failures: connection_info.consent.keepalive_failures.get(),
+                consent_expirations: connection_info.consent.expirations.get(),
+                ice_restarts: connection_info.consent.ice_restarts.get(),
+                data_compression: connection_info.data_codec.stats(),
+            };
+            let frames_encoded: HashMap<String, u64> = connection_info
+                .frames_encoded
+                .iter()
+                .map(|(track_id, frames)| (track_id.clone(), frames.get()))
+                .collect();
+            (
+                Arc::clone(&connection_info.peer_connection),
+                summary,
+                frames_encoded,
+            )
+        };
+
+        // Collected without the lock: the stack's callbacks take it too
+        let report = peer_connection.get_stats().await;
+        Ok(DetailedStats::from_report(
+            summary,
+            &report,
+            &frames_encoded,
+        ))
+    }
+
+    /// Path media currently takes, from the nominated candidate pair
+    ///
+    /// `TurnRelay` when either end is a relay candidate, `StunDirect` through
+    /// a NAT mapping, `Direct` between host candidates; `Unknown` until ICE
+    /// nominates a pair.
+    pub async fn connection_path(&self, connection_id: &str) -> Result<ConnectionType> {
+        let peer_connection = {
+            let connections = self.connections.lock().await;
+            let connection_info = connections
+                .get(connection_id)
+                .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+            Arc::clone(&connection_info.peer_connection)
+        };
+        Ok(selected_path(&peer_connection.get_stats().await))
+    }
+
+    /// Store the renderer's freeze statistics for `get_connection_stats`
+    pub async fn record_freeze_stats(&self, connection_id: &str, stats: FreezeStats) -> Result<()> {
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        connection_info.freeze_stats = stats;
+        Ok(())
+    }
+
+    pub async fn get_event_receiver(&self) -> Arc<Mutex<mpsc::UnboundedReceiver<WebRTCEvent>>> {
+        Arc::clone(&self.event_receiver)
     }
 
     fn convert_config(&self, config: RTCConfiguration) -> Result<WebRTCConfig> {
@@ -383,18 +1690,678 @@ impl WebRTCEngine {
         })
     }
 
-    pub async fn send_data(&self, connection_id: &str, data: Vec<u8>) -> Result<()> {
-        tracing::debug!(
-            "Sending {} bytes to connection {}",
-            data.len(),
+    /// Add or remove the outgoing audio track and renegotiate
+    ///
+    /// Emits `RenegotiationNeeded` with the new offer when the track set
+    /// changed (or queues it behind an in-flight negotiation); does nothing
+    /// if the track is already in the requested state.
+    pub async fn set_audio_track_enabled(&self, connection_id: &str, enabled: bool) -> Result<()> {
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+
+        if enabled == connection_info.audio_sender.is_some() {
+            return Ok(());
+        }
+
+        if enabled {
+            let track = Arc::new(TrackLocalStaticSample::new(
+                RTCRtpCodecCapability {
+                    mime_type: webrtc::api::media_engine::MIME_TYPE_OPUS.to_string(),
+                    ..Default::default()
+                },
+                "audio".to_string(),
+                "cec-audio".to_string(),
+            ));
+            let sender = connection_info.peer_connection.add_track(track).await?;
+            connection_info.audio_sender = Some(sender);
+        } else if let Some(sender) = connection_info.audio_sender.take() {
+            connection_info
+                .peer_connection
+                .remove_track(&sender)
+                .await?;
+        }
+
+        tracing::info!(
+            "Audio track {} for connection {}, renegotiating",
+            if enabled { "added" } else { "removed" },
             connection_id
         );
-        // TODO: Implement data channel sending
+        self.negotiator
+            .negotiate(connection_id, connection_info)
+            .await
+    }
+
+    /// Compress control data channel messages with `algorithm` from now on
+    ///
+    /// Pass the result of `negotiate_compression` once both peers' capabilities
+    /// are known; both ends must switch together.
+    pub async fn set_data_compression(
+        &self,
+        connection_id: &str,
+        algorithm: Option<CompressionAlgorithm>,
+    ) -> Result<()> {
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        connection_info.data_codec = DataChannelCodec::new(algorithm);
+        tracing::info!(
+            "Data channel compression for connection {}: {:?}",
+            connection_id,
+            algorithm
+        );
         Ok(())
     }
+
+    /// Send a control message, held until the control channel opens
+    pub async fn send_data(&self, connection_id: &str, data: Vec<u8>) -> Result<()> {
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        let message = connection_info.data_codec.encode(data);
+        check_message_size(message.len())?;
+        let control = connection_info
+            .data_channels
+            .get(CONTROL_CHANNEL_LABEL)
+            .map(|handle| Arc::clone(&handle.channel));
+
+        match control {
+            // Queued messages go first; the open handler is flushing them
+            Some(channel)
+                if channel.ready_state() == RTCDataChannelState::Open
+                    && connection_info.pending_control.is_empty() =>
+            {
+                tracing::debug!(
+                    "Sending {} bytes to connection {}",
+                    message.len(),
+                    connection_id
+                );
+                channel.send(&Bytes::from(message)).await?;
+            }
+            _ => {
+                if connection_info.pending_control.len() >= MAX_PENDING_CONTROL_MESSAGES {
+                    return Err(anyhow::anyhow!(
+                        "Control channel of connection {} is not open and {} messages are waiting",
+                        connection_id,
+                        MAX_PENDING_CONTROL_MESSAGES
+                    ));
+                }
+                connection_info.pending_control.push_back(message);
+            }
+        }
+        Ok(())
+    }
+
+    /// Open a data channel; the peer learns of it at the next negotiation
+    /// if the connection has none yet, otherwise right away
+    pub async fn create_data_channel(
+        &self,
+        connection_id: &str,
+        config: DataChannelConfig,
+    ) -> Result<()> {
+        config.validate()?;
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        if connection_info.data_channels.contains_key(&config.label) {
+            return Err(anyhow::anyhow!(
+                "Data channel {} already exists on connection {}",
+                config.label,
+                connection_id
+            ));
+        }
+        let channel = connection_info
+            .peer_connection
+            .create_data_channel(&config.label, Some(config.init()))
+            .await?;
+        tracing::info!(
+            "Created data channel {} on connection {} ({:?}, ordered: {})",
+            config.label,
+            connection_id,
+            config.reliability,
+            config.ordered
+        );
+        let handle = watch_data_channel(
+            connection_id,
+            channel,
+            config,
+            &self.event_sender,
+            &self.connections,
+        )
+        .await;
+        connection_info
+            .data_channels
+            .insert(handle.config.label.clone(), handle);
+        Ok(())
+    }
+
+    pub async fn close_data_channel(&self, connection_id: &str, label: &str) -> Result<()> {
+        let handle = {
+            let mut connections = self.connections.lock().await;
+            let connection_info = connections
+                .get_mut(connection_id)
+                .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+            if label == CONTROL_CHANNEL_LABEL {
+                return Err(anyhow::anyhow!(
+                    "The control channel closes with the connection"
+                ));
+            }
+            connection_info.data_channels.remove(label)
+        };
+        match handle {
+            Some(handle) => Ok(handle.channel.close().await?),
+            None => Err(anyhow::anyhow!("Data channel not found: {}", label)),
+        }
+    }
+
+    /// Carry the data paths over a data channel, or over QUIC if it fails
+    ///
+    /// Waits up to `config.data_channel_timeout` for the channel to open and
+    /// otherwise runs `quic_attempt` when the fallback is enabled. The chosen
+    /// transport is kept for the connection, see `data_transport`.
+    pub async fn select_data_transport<Q>(
+        &self,
+        connection_id: &str,
+        label: &str,
+        config: &DataTransportConfig,
+        quic_attempt: Q,
+    ) -> Result<DataTransportType>
+    where
+        Q: Future<Output = Result<()>>,
+    {
+        let transport = crate::quic_transport::select_data_transport(
+            config,
+            self.wait_for_data_channel(connection_id, label),
+            quic_attempt,
+        )
+        .await?;
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        connection_info.data_transport = Some(transport);
+        tracing::info!(
+            "Data paths of connection {} use {}",
+            connection_id,
+            transport
+        );
+        Ok(transport)
+    }
+
+    /// Transport chosen for a connection's data paths, if selected yet
+    pub async fn data_transport(&self, connection_id: &str) -> Option<DataTransportType> {
+        let connections = self.connections.lock().await;
+        connections.get(connection_id)?.data_transport
+    }
+
+    /// Resolve once the channel is open; a channel the peer creates may not
+    /// exist yet
+    async fn wait_for_data_channel(&self, connection_id: &str, label: &str) -> Result<()> {
+        loop {
+            match self.data_channel(connection_id, label).await {
+                Ok((channel, _, _)) => match channel.ready_state() {
+                    RTCDataChannelState::Open => return Ok(()),
+                    RTCDataChannelState::Closing | RTCDataChannelState::Closed => {
+                        return Err(anyhow::anyhow!(
+                            "Data channel {} closed before opening",
+                            label
+                        ))
+                    }
+                    _ => {}
+                },
+                Err(e) if !self.connections.lock().await.contains_key(connection_id) => {
+                    return Err(e)
+                }
+                Err(_) => {}
+            }
+            tokio::time::sleep(DATA_CHANNEL_OPEN_POLL_INTERVAL).await;
+        }
+    }
+
+    pub async fn data_channels(&self, connection_id: &str) -> Result<Vec<DataChannelInfo>> {
+        let channels: Vec<_> = {
+            let connections = self.connections.lock().await;
+            let connection_info = connections
+                .get(connection_id)
+                .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+            connection_info
+                .data_channels
+                .values()
+                .map(|handle| (Arc::clone(&handle.channel), handle.config.clone()))
+                .collect()
+        };
+        let mut infos = Vec::with_capacity(channels.len());
+        for (channel, config) in channels {
+            infos.push(DataChannelInfo {
+                config,
+                open: channel.ready_state() == RTCDataChannelState::Open,
+                buffered_amount: channel.buffered_amount().await,
+            });
+        }
+        infos.sort_by(|a, b| a.config.label.cmp(&b.config.label));
+        Ok(infos)
+    }
+
+    /// Outgoing bytes queued on a channel and not yet sent
+    pub async fn data_channel_buffered_amount(
+        &self,
+        connection_id: &str,
+        label: &str,
+    ) -> Result<usize> {
+        let (channel, _, _) = self.data_channel(connection_id, label).await?;
+        Ok(channel.buffered_amount().await)
+    }
+
+    pub async fn send_binary(&self, connection_id: &str, label: &str, data: &[u8]) -> Result<()> {
+        self.send_on_channel(
+            connection_id,
+            label,
+            DataChannelMessage::Binary(data.to_vec()),
+        )
+        .await
+    }
+
+    pub async fn send_text(&self, connection_id: &str, label: &str, text: &str) -> Result<()> {
+        self.send_on_channel(
+            connection_id,
+            label,
+            DataChannelMessage::Text(text.to_string()),
+        )
+        .await
+    }
+
+    /// Send on an open channel
+    ///
+    /// Crossing the channel's high water mark is reported once with
+    /// `DataChannelEvent::BufferedAmountHigh`; the send itself still
+    /// succeeds, and senders should wait for `BufferedAmountLow`.
+    async fn send_on_channel(
+        &self,
+        connection_id: &str,
+        label: &str,
+        message: DataChannelMessage,
+    ) -> Result<()> {
+        if label == CONTROL_CHANNEL_LABEL {
+            return Err(anyhow::anyhow!("Use send_data for the control channel"));
+        }
+        check_message_size(match &message {
+            DataChannelMessage::Binary(data) => data.len(),
+            DataChannelMessage::Text(text) => text.len(),
+        })?;
+        let (channel, high_water_mark, congested) = self.data_channel(connection_id, label).await?;
+        if channel.ready_state() != RTCDataChannelState::Open {
+            return Err(anyhow::anyhow!("Data channel {} is not open", label));
+        }
+        match message {
+            DataChannelMessage::Binary(data) => channel.send(&Bytes::from(data)).await?,
+            DataChannelMessage::Text(text) => channel.send_text(text).await?,
+        };
+        let buffered = channel.buffered_amount().await;
+        if buffered >= high_water_mark && !congested.swap(true, Ordering::SeqCst) {
+            tracing::debug!(
+                "Data channel {} on connection {} backed up: {} bytes",
+                label,
+                connection_id,
+                buffered
+            );
+            let _ = self.event_sender.send(WebRTCEvent::DataChannel(
+                connection_id.to_string(),
+                label.to_string(),
+                DataChannelEvent::BufferedAmountHigh(buffered),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Channel, high water mark and congestion flag of a data channel
+    async fn data_channel(
+        &self,
+        connection_id: &str,
+        label: &str,
+    ) -> Result<(Arc<RTCDataChannel>, usize, Arc<AtomicBool>)> {
+        let connections = self.connections.lock().await;
+        let connection_info = connections
+            .get(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        let handle = connection_info
+            .data_channels
+            .get(label)
+            .ok_or_else(|| anyhow::anyhow!("Data channel not found: {}", label))?;
+        Ok((
+            Arc::clone(&handle.channel),
+            handle.config.high_water_mark,
+            Arc::clone(&handle.congested),
+        ))
+    }
+
+    /// Payload of a message received on the control data channel
+    pub async fn decode_data(&self, connection_id: &str, message: &[u8]) -> Result<Vec<u8>> {
+        let mut connections = self.connections.lock().await;
+        let connection_info = connections
+            .get_mut(connection_id)
+            .ok_or_else(|| anyhow::anyhow!("Connection not found: {}", connection_id))?;
+        connection_info.data_codec.decode(message)
+    }
 }
 
-#[derive(Debug, Clone)]
+fn check_message_size(len: usize) -> Result<()> {
+    if len > MAX_DATA_CHANNEL_MESSAGE {
+        return Err(anyhow::anyhow!(
+            "Data channel message of {} bytes exceeds the {} byte limit",
+            len,
+            MAX_DATA_CHANNEL_MESSAGE
+        ));
+    }
+    Ok(())
+}
+
+/// Report a channel's lifecycle, messages and drained backlog as events
+///
+/// Opening the control channel flushes the control messages queued before.
+async fn watch_data_channel(
+    connection_id: &str,
+    channel: Arc<RTCDataChannel>,
+    config: DataChannelConfig,
+    events: &mpsc::UnboundedSender<WebRTCEvent>,
+    connections: &Arc<Mutex<HashMap<String, ConnectionInfo>>>,
+) -> DataChannelHandle {
+    let label = config.label.clone();
+    let is_control = label == CONTROL_CHANNEL_LABEL;
+    let congested = Arc::new(AtomicBool::new(false));
+    let event = {
+        let (connection_id, label, events) =
+            (connection_id.to_string(), label.clone(), events.clone());
+        move |event| {
+            let _ = events.send(WebRTCEvent::DataChannel(
+                connection_id.clone(),
+                label.clone(),
+                event,
+            ));
+        }
+    };
+
+    // Handlers hold the channel weakly, as it owns them
+    let weak: Weak<RTCDataChannel> = Arc::downgrade(&channel);
+    let on_open = event.clone();
+    let (open_connection_id, open_connections) =
+        (connection_id.to_string(), Arc::downgrade(connections));
+    channel.on_open(Box::new(move || {
+        Box::pin(async move {
+            on_open(DataChannelEvent::Opened);
+            if !is_control {
+                return;
+            }
+            let (Some(channel), Some(connections)) = (weak.upgrade(), open_connections.upgrade())
+            else {
+                return;
+            };
+            // Sends wait on the lock, so queued messages keep their order
+            let mut connections = connections.lock().await;
+            let Some(conn_info) = connections.get_mut(&open_connection_id) else {
+                return;
+            };
+            while let Some(message) = conn_info.pending_control.pop_front() {
+                if let Err(e) = channel.send(&Bytes::from(message)).await {
+                    tracing::warn!("Failed to flush control message: {}", e);
+                    break;
+                }
+            }
+        })
+    }));
+
+    let on_close = event.clone();
+    channel.on_close(Box::new(move || {
+        on_close(DataChannelEvent::Closed);
+        Box::pin(async {})
+    }));
+
+    let (message_connection_id, message_events, on_message) =
+        (connection_id.to_string(), events.clone(), event.clone());
+    channel.on_message(Box::new(move |message| {
+        if is_control {
+            let _ = message_events.send(WebRTCEvent::DataReceived(
+                message_connection_id.clone(),
+                message.data.to_vec(),
+            ));
+        } else if message.is_string {
+            on_message(DataChannelEvent::Message(DataChannelMessage::Text(
+                String::from_utf8_lossy(&message.data).into_owned(),
+            )));
+        } else {
+            on_message(DataChannelEvent::Message(DataChannelMessage::Binary(
+                message.data.to_vec(),
+            )));
+        }
+        Box::pin(async {})
+    }));
+
+    channel
+        .set_buffered_amount_low_threshold(config.low_water_mark)
+        .await;
+    let drained = Arc::clone(&congested);
+    channel
+        .on_buffered_amount_low(Box::new(move || {
+            if drained.swap(false, Ordering::SeqCst) {
+                event(DataChannelEvent::BufferedAmountLow);
+            }
+            Box::pin(async {})
+        }))
+        .await;
+
+    DataChannelHandle {
+        channel,
+        config,
+        congested,
+    }
+}
+
+/// Offer/answer plumbing shared by the engine and its connection callbacks
+#[derive(Clone)]
+struct Negotiator {
+    /// Carries renegotiation offers and answers when set
+    signaling: Arc<Mutex<Option<Arc<SignalingClient>>>>,
+    events: mpsc::UnboundedSender<WebRTCEvent>,
+}
+
+impl Negotiator {
+    /// Create and send an offer for the current track set
+    ///
+    /// Queued instead if a negotiation is already in flight. On failure the
+    /// local offer is rolled back so the connection stays usable. A pending
+    /// ICE restart is folded into the offer.
+    async fn negotiate(
+        &self,
+        connection_id: &str,
+        connection_info: &mut ConnectionInfo,
+    ) -> Result<()> {
+        let peer_connection = Arc::clone(&connection_info.peer_connection);
+        let can_offer = match peer_connection.signaling_state() {
+            RTCSignalingState::Stable => true,
+            // A new offer replaces one that will never be answered
+            RTCSignalingState::HaveLocalOffer => connection_info.offer_withdrawn,
+            _ => false,
+        };
+        if !can_offer {
+            connection_info.renegotiation_queued = true;
+            self.emit_progress(connection_id, RenegotiationProgress::Queued);
+            return Ok(());
+        }
+
+        let ice_restart = connection_info.ice_restart_pending;
+        let options = ice_restart.then(|| RTCOfferOptions {
+            ice_restart: true,
+            ..Default::default()
+        });
+        let offer = peer_connection.create_offer(options).await?;
+        peer_connection.set_local_description(offer.clone()).await?;
+        connection_info.offer_withdrawn = false;
+        connection_info.ice_restart_pending = false;
+        connection_info.ice_restart_offered = ice_restart;
+
+        if let (Some(signaling), Some(remote_id)) = (
+            self.signaling.lock().await.clone(),
+            &connection_info.remote_id,
+        ) {
+            if let Err(e) = signaling.send_offer(remote_id, &offer.sdp).await {
+                let _ = self
+                    .rollback_offer(connection_id, connection_info, &e.to_string())
+                    .await;
+                return Err(e);
+            }
+        }
+
+        let _ = self.events.send(WebRTCEvent::RenegotiationNeeded(
+            connection_id.to_string(),
+            offer,
+        ));
+        self.emit_progress(connection_id, RenegotiationProgress::OfferSent);
+        Ok(())
+    }
+
+    /// Withdraw our pending local offer
+    ///
+    /// Even when the stack refuses the rollback the offer is marked withdrawn,
+    /// so the next track change can replace it with a fresh offer.
+    async fn rollback_offer(
+        &self,
+        connection_id: &str,
+        connection_info: &mut ConnectionInfo,
+        reason: &str,
+    ) -> Result<()> {
+        let peer_connection = Arc::clone(&connection_info.peer_connection);
+        if peer_connection.signaling_state() != RTCSignalingState::HaveLocalOffer {
+            return Ok(());
+        }
+        connection_info.offer_withdrawn = true;
+        // A withdrawn restart is folded into the next offer
+        if std::mem::take(&mut connection_info.ice_restart_offered) {
+            connection_info.ice_restart_pending = true;
+        }
+        let Some(mut rollback) = peer_connection.pending_local_description().await else {
+            return Ok(());
+        };
+        rollback.sdp_type = RTCSdpType::Rollback;
+        if let Err(e) = peer_connection.set_local_description(rollback).await {
+            tracing::error!("Rollback failed on connection {}: {}", connection_id, e);
+            self.emit_progress(
+                connection_id,
+                RenegotiationProgress::RollbackFailed(e.to_string()),
+            );
+            return Err(e.into());
+        }
+        connection_info.offer_withdrawn = false;
+        tracing::warn!(
+            "Rolled back offer on connection {}: {}",
+            connection_id,
+            reason
+        );
+        self.emit_progress(
+            connection_id,
+            RenegotiationProgress::RolledBack(reason.to_string()),
+        );
+        Ok(())
+    }
+
+    fn emit_progress(&self, connection_id: &str, progress: RenegotiationProgress) {
+        let _ = self.events.send(WebRTCEvent::RenegotiationProgress(
+            connection_id.to_string(),
+            progress,
+        ));
+    }
+}
+
+/// Mark the connection for an ICE restart and offer it
+async fn restart_ice(
+    negotiator: &Negotiator,
+    connection_id: &str,
+    connection_info: &mut ConnectionInfo,
+) -> Result<()> {
+    connection_info.ice_restart_pending = true;
+    connection_info.consent.ice_restarts.increment();
+    tracing::info!("Restarting ICE on connection {}", connection_id);
+    negotiator.negotiate(connection_id, connection_info).await
+}
+
+/// Layer a subscriber forwards: its selection if it still exists, else the
+/// best one; `None` without simulcast
+fn selected_layer<'a>(layers: &'a [SimulcastLayer], selection: Option<&String>) -> Option<&'a str> {
+    selection
+        .and_then(|rid| layers.iter().find(|layer| &layer.rid == rid))
+        .or_else(|| layers.first())
+        .map(|layer| layer.rid.as_str())
+}
+
+/// Path type of the nominated candidate pair in `report`
+fn selected_path(report: &StatsReport) -> ConnectionType {
+    let Some(pair) = report.reports.values().find_map(|stats| match stats {
+        StatsReportType::CandidatePair(pair)
+            if pair.nominated && pair.state == CandidatePairState::Succeeded =>
+        {
+            Some(pair)
+        }
+        _ => None,
+    }) else {
+        return ConnectionType::Unknown;
+    };
+    let candidate_type = |id: &str| match report.reports.get(id) {
+        Some(StatsReportType::LocalCandidate(c) | StatsReportType::RemoteCandidate(c)) => {
+            Some(c.candidate_type)
+        }
+        _ => None,
+    };
+    let (Some(local), Some(remote)) = (
+        candidate_type(&pair.local_candidate_id),
+        candidate_type(&pair.remote_candidate_id),
+    ) else {
+        return ConnectionType::Unknown;
+    };
+    match (local, remote) {
+        (CandidateType::Relay, _) | (_, CandidateType::Relay) => ConnectionType::TurnRelay,
+        (CandidateType::Host, CandidateType::Host) => ConnectionType::Direct,
+        _ => ConnectionType::StunDirect,
+    }
+}
+
+/// ICE username fragment of a session description
+///
+/// Changes exactly when the sender restarted ICE.
+fn ice_ufrag(sdp: &str) -> Option<&str> {
+    sdp.lines()
+        .find_map(|line| line.trim_end().strip_prefix("a=ice-ufrag:"))
+}
+
+/// List `codec` first in the offer for the transceiver carrying `sender`,
+/// keeping the other video codecs for the receive direction
+async fn prefer_video_codec(
+    peer_connection: &RTCPeerConnection,
+    sender: &Arc<RTCRtpSender>,
+    codec: DecoderCodec,
+) -> Result<()> {
+    let preferences: Vec<RTCRtpCodecParameters> = std::iter::once(codec)
+        .chain(NEGOTIATED_VIDEO_CODECS.into_iter().filter(|c| *c != codec))
+        .filter_map(video_codec_parameters)
+        .collect();
+    for transceiver in peer_connection.get_transceivers().await {
+        if transceiver.kind() == RTPCodecType::Video
+            && Arc::ptr_eq(&transceiver.sender().await, sender)
+        {
+            transceiver.set_codec_preferences(preferences).await?;
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Connection totals
+///
+/// Bytes count RTP (headers included) and data channel payloads; packets
+/// count RTP only. The round trip time comes from RTCP reports, zero until
+/// the first one.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ConnectionStats {
     pub connection_id: String,
     pub state: RTCPeerConnectionState,
@@ -403,6 +2370,177 @@ pub struct ConnectionStats {
     pub packets_sent: u64,
     pub packets_received: u64,
     pub rtt: f64, // Round trip time in milliseconds
+    /// Frames the renderer decoded
+    pub frames_decoded: u64,
+    pub freeze_count: u32,
+    pub total_freeze_ms: u64,
+    /// Picture loss indications sent to the remote encoder
+    pub keyframe_requests_sent: u64,
+    /// PLI/FIR received for our outgoing video
+    pub keyframe_requests_received: u64,
+    /// Received requests dropped by the rate limit
+    pub keyframe_requests_throttled: u64,
+    /// Times the path went silent for `ConsentConfig::missed_after`
+    pub keepalive_failures: u64,
+    pub consent_expirations: u64,
+    pub ice_restarts: u64,
+    pub data_compression: CompressionStats,
+}
+
+/// One incoming RTP stream
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InboundRtpStats {
+    pub ssrc: u32,
+    /// "audio" or "video"
+    pub kind: String,
+    pub track_id: String,
+    pub mid: String,
+    pub bytes_received: u64,
+    pub header_bytes_received: u64,
+    pub packets_received: u64,
+    /// Retransmission requests we sent
+    pub nack_count: u64,
+    /// Picture loss indications we sent
+    pub pli_count: u64,
+    pub fir_count: u64,
+    /// From the sender's RTCP reports, once it measured one
+    pub rtt_ms: Option<f64>,
+}
+
+/// One outgoing RTP stream
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OutboundRtpStats {
+    pub ssrc: u32,
+    /// "audio" or "video"
+    pub kind: String,
+    pub track_id: String,
+    pub mid: String,
+    pub bytes_sent: u64,
+    pub header_bytes_sent: u64,
+    pub packets_sent: u64,
+    /// Retransmission requests the receiver sent
+    pub nack_count: u64,
+    /// Picture loss indications the receiver sent
+    pub pli_count: u64,
+    pub fir_count: u64,
+    /// Frames written to the track; zero for audio
+    pub frames_encoded: u64,
+    /// Loss the receiver reported in RTCP receiver reports
+    pub packets_lost: Option<i64>,
+    pub fraction_lost: Option<f64>,
+    /// From RTCP receiver reports, once the receiver sent one
+    pub rtt_ms: Option<f64>,
+}
+
+/// Result of `WebRTCEngine::get_detailed_stats`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DetailedStats {
+    pub collected_at: DateTime<Utc>,
+    pub summary: ConnectionStats,
+    /// Sorted by track ID, then SSRC
+    pub inbound: Vec<InboundRtpStats>,
+    pub outbound: Vec<OutboundRtpStats>,
+}
+
+impl DetailedStats {
+    fn from_report(
+        mut summary: ConnectionStats,
+        report: &StatsReport,
+        frames_encoded: &HashMap<String, u64>,
+    ) -> Self {
+        let mut inbound = Vec::new();
+        let mut outbound = Vec::new();
+        let mut remote_inbound = HashMap::new();
+        let mut remote_outbound = HashMap::new();
+        let (mut data_sent, mut data_received) = (0, 0);
+        for stats in report.reports.values() {
+            match stats {
+                StatsReportType::InboundRTP(s) => inbound.push(InboundRtpStats {
+                    ssrc: s.ssrc,
+                    kind: s.kind.to_string(),
+                    track_id: s.track_identifier.clone(),
+                    mid: s.mid.to_string(),
+                    bytes_received: s.bytes_received,
+                    header_bytes_received: s.header_bytes_received,
+                    packets_received: s.packets_received,
+                    nack_count: s.nack_count,
+                    pli_count: s.pli_count.unwrap_or_default(),
+                    fir_count: s.fir_count.unwrap_or_default(),
+                    rtt_ms: None,
+                }),
+                StatsReportType::OutboundRTP(s) => outbound.push(OutboundRtpStats {
+                    ssrc: s.ssrc,
+                    kind: s.kind.to_string(),
+                    track_id: s.track_identifier.clone(),
+                    mid: s.mid.to_string(),
+                    bytes_sent: s.bytes_sent,
+                    header_bytes_sent: s.header_bytes_sent,
+                    packets_sent: s.packets_sent,
+                    nack_count: s.nack_count,
+                    pli_count: s.pli_count.unwrap_or_default(),
+                    fir_count: s.fir_count.unwrap_or_default(),
+                    frames_encoded: frames_encoded
+                        .get(&s.track_identifier)
+                        .copied()
+                        .unwrap_or_default(),
+                    ..Default::default()
+                }),
+                StatsReportType::RemoteInboundRTP(s) => {
+                    remote_inbound.insert(s.ssrc, s);
+                }
+                StatsReportType::RemoteOutboundRTP(s) => {
+                    remote_outbound.insert(s.ssrc, s);
+                }
+                StatsReportType::DataChannel(s) => {
+                    data_sent += s.bytes_sent as u64;
+                    data_received += s.bytes_received as u64;
+                }
+                _ => {}
+            }
+        }
+
+        for stream in &mut outbound {
+            if let Some(remote) = remote_inbound.get(&stream.ssrc) {
+                stream.packets_lost = Some(remote.packets_lost);
+                stream.fraction_lost = Some(remote.fraction_lost);
+                stream.rtt_ms = remote.round_trip_time.map(|rtt| rtt * 1000.0);
+            }
+        }
+        for stream in &mut inbound {
+            stream.rtt_ms = remote_outbound
+                .get(&stream.ssrc)
+                .and_then(|remote| remote.round_trip_time)
+                .map(|rtt| rtt * 1000.0);
+        }
+        inbound.sort_by(|a, b| (&a.track_id, a.ssrc).cmp(&(&b.track_id, b.ssrc)));
+        outbound.sort_by(|a, b| (&a.track_id, a.ssrc).cmp(&(&b.track_id, b.ssrc)));
+
+        // The ICE agent does not count pair traffic, so sum the streams
+        summary.bytes_sent = data_sent
+            + outbound
+                .iter()
+                .map(|s| s.bytes_sent + s.header_bytes_sent)
+                .sum::<u64>();
+        summary.bytes_received = data_received
+            + inbound
+                .iter()
+                .map(|s| s.bytes_received + s.header_bytes_received)
+                .sum::<u64>();
+        summary.packets_sent = outbound.iter().map(|s| s.packets_sent).sum();
+        summary.packets_received = inbound.iter().map(|s| s.packets_received).sum();
+        summary.rtt = outbound
+            .iter()
+            .find_map(|s| s.rtt_ms)
+            .or_else(|| inbound.iter().find_map(|s| s.rtt_ms))
+            .unwrap_or_default();
+
+        Self {
+            collected_at: Utc::now(),
+            summary,
+            inbound,
+            outbound,
+        }
+    }
 }
 
 // Tests are in a separate file: webrtc_engine_test.rs
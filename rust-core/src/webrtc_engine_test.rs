@@ -8,9 +8,12 @@
 //! proptest + async + WebRTC engine creation causes hangs. Run manually with:
 //! cargo test -- --ignored
 
-use crate::webrtc_engine::{IceServer, RTCConfiguration, RTCPeerConnectionState, WebRTCEngine};
+use crate::webrtc_engine::{
+    IceServer, RTCConfiguration, RTCPeerConnectionState, WebRTCEngine, WebRTCEvent,
+};
 use proptest::prelude::*;
 use std::time::Duration;
+use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
 
 prop_compose! {
     fn arb_ice_server()(
@@ -338,4 +341,881 @@ mod unit_tests {
         engine.close_connection(&id1).await.unwrap();
         engine.close_connection(&id2).await.unwrap();
     }
+
+    async fn drain_events(engine: &WebRTCEngine) -> Vec<WebRTCEvent> {
+        let receiver = engine.get_event_receiver().await;
+        let mut receiver = receiver.lock().await;
+        let mut events = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            events.push(event);
+        }
+        events
+    }
+
+    fn last_offer(events: &[WebRTCEvent]) -> RTCSessionDescription {
+        events
+            .iter()
+            .rev()
+            .find_map(|event| match event {
+                WebRTCEvent::RenegotiationNeeded(_, offer) => Some(offer.clone()),
+                _ => None,
+            })
+            .expect("offer event")
+    }
+
+    /// Track changes during a negotiation are queued; colliding offers are
+    /// ignored by the impolite side
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_renegotiation_queue_and_glare() {
+        use crate::webrtc_engine::{NegotiationRole, RenegotiationProgress};
+
+        let result = tokio::time::timeout(Duration::from_secs(30), async {
+            let config = RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            };
+            let polite = WebRTCEngine::new().await.unwrap();
+            let impolite = WebRTCEngine::new().await.unwrap();
+            let a = polite.create_peer_connection(config.clone()).await.unwrap();
+            let b = impolite.create_peer_connection(config).await.unwrap();
+            assert_eq!(
+                polite
+                    .set_remote_peer(&a, "device-a", "device-b".into())
+                    .await
+                    .unwrap(),
+                NegotiationRole::Polite
+            );
+            assert_eq!(
+                impolite
+                    .set_remote_peer(&b, "device-b", "device-a".into())
+                    .await
+                    .unwrap(),
+                NegotiationRole::Impolite
+            );
+
+            // Second display added while the first offer is unanswered
+            polite
+                .add_media_track(&a, "video", "display-1".into())
+                .await
+                .unwrap();
+            polite
+                .add_media_track(&a, "video", "display-2".into())
+                .await
+                .unwrap();
+            let offer = last_offer(&drain_events(&polite).await);
+            let answer = impolite
+                .handle_renegotiation_offer(&b, offer)
+                .await
+                .unwrap()
+                .expect("no collision, so the offer is answered");
+            polite.handle_remote_answer(&a, answer).await.unwrap();
+
+            let events = drain_events(&polite).await;
+            let progress: Vec<_> = events
+                .iter()
+                .filter_map(|event| match event {
+                    WebRTCEvent::RenegotiationProgress(_, p) => Some(p.clone()),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(
+                progress,
+                [
+                    RenegotiationProgress::Completed,
+                    RenegotiationProgress::OfferSent
+                ]
+            );
+            let queued_offer = last_offer(&events);
+
+            // Impolite side has its own offer out and ignores the collision
+            impolite.set_audio_track_enabled(&b, true).await.unwrap();
+            assert!(impolite
+                .handle_renegotiation_offer(&b, queued_offer)
+                .await
+                .unwrap()
+                .is_none());
+            assert!(drain_events(&impolite).await.iter().any(|e| matches!(
+                e,
+                WebRTCEvent::RenegotiationProgress(_, RenegotiationProgress::RemoteOfferIgnored)
+            )));
+
+            polite.close_connection(&a).await.unwrap();
+            impolite.close_connection(&b).await.unwrap();
+        })
+        .await;
+        assert!(result.is_ok(), "Test timed out after 30 seconds");
+    }
+
+    #[tokio::test]
+    async fn test_av1_video_track_negotiated() {
+        use crate::decoder_capabilities::DecoderCodec;
+
+        let result = tokio::time::timeout(Duration::from_secs(30), async {
+            let config = RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            };
+            let host = WebRTCEngine::new().await.unwrap();
+            let viewer = WebRTCEngine::new().await.unwrap();
+            let a = host.create_peer_connection(config.clone()).await.unwrap();
+            let b = viewer.create_peer_connection(config).await.unwrap();
+            host.set_remote_peer(&a, "device-a", "device-b".into())
+                .await
+                .unwrap();
+            viewer
+                .set_remote_peer(&b, "device-b", "device-a".into())
+                .await
+                .unwrap();
+
+            assert!(host.set_video_codec(DecoderCodec::H265).await.is_err());
+            host.set_video_codec(DecoderCodec::AV1).await.unwrap();
+            host.add_media_track(&a, "video", "display-1".into())
+                .await
+                .unwrap();
+
+            let offer = last_offer(&drain_events(&host).await);
+            let video_line = offer
+                .sdp
+                .lines()
+                .find(|line| line.starts_with("m=video"))
+                .unwrap()
+                .to_string();
+            let first_payload_type = video_line.split(' ').nth(3).unwrap();
+            assert!(offer
+                .sdp
+                .contains(&format!("a=rtpmap:{} AV1/90000", first_payload_type)));
+
+            let answer = viewer
+                .handle_renegotiation_offer(&b, offer)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(answer.sdp.contains("AV1/90000"));
+            host.handle_remote_answer(&a, answer).await.unwrap();
+
+            host.close_connection(&a).await.unwrap();
+            viewer.close_connection(&b).await.unwrap();
+        })
+        .await;
+        assert!(result.is_ok(), "Test timed out after 30 seconds");
+    }
+
+    /// Codec switches and track removal renegotiate on the same connection
+    #[tokio::test]
+    async fn test_switch_video_codec_mid_session() {
+        use crate::decoder_capabilities::DecoderCodec;
+        use crate::webrtc_engine::RenegotiationProgress;
+
+        fn first_video_codec(sdp: &str) -> String {
+            let payload_type = sdp
+                .lines()
+                .find(|line| line.starts_with("m=video"))
+                .and_then(|line| line.split(' ').nth(3))
+                .unwrap()
+                .to_string();
+            let prefix = format!("a=rtpmap:{} ", payload_type);
+            sdp.lines()
+                .find_map(|line| line.strip_prefix(prefix.as_str()))
+                .unwrap()
+                .to_string()
+        }
+
+        let result = tokio::time::timeout(Duration::from_secs(30), async {
+            let config = RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            };
+            let host = WebRTCEngine::new().await.unwrap();
+            let viewer = WebRTCEngine::new().await.unwrap();
+            let a = host.create_peer_connection(config.clone()).await.unwrap();
+            let b = viewer.create_peer_connection(config).await.unwrap();
+            host.set_remote_peer(&a, "device-a", "device-b".into())
+                .await
+                .unwrap();
+            viewer
+                .set_remote_peer(&b, "device-b", "device-a".into())
+                .await
+                .unwrap();
+
+            host.add_media_track(&a, "video", "display-1".into())
+                .await
+                .unwrap();
+            let offer = last_offer(&drain_events(&host).await);
+            assert_eq!(first_video_codec(&offer.sdp), "VP8/90000");
+            let answer = viewer
+                .handle_renegotiation_offer(&b, offer)
+                .await
+                .unwrap()
+                .unwrap();
+            host.handle_remote_answer(&a, answer).await.unwrap();
+            drain_events(&host).await;
+
+            assert!(host
+                .switch_video_codec(&a, DecoderCodec::H265)
+                .await
+                .is_err());
+            host.switch_video_codec(&a, DecoderCodec::VP9)
+                .await
+                .unwrap();
+            let offer = last_offer(&drain_events(&host).await);
+            assert_eq!(first_video_codec(&offer.sdp), "VP9/90000");
+            // Replaced in place: still one video section
+            assert_eq!(offer.sdp.matches("m=video").count(), 1);
+            let answer = viewer
+                .handle_renegotiation_offer(&b, offer)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(answer.sdp.contains("VP9/90000"));
+            host.handle_remote_answer(&a, answer).await.unwrap();
+
+            // Switching to the current codec is a no-op; later tracks follow it
+            host.switch_video_codec(&a, DecoderCodec::VP9)
+                .await
+                .unwrap();
+            assert!(!drain_events(&host)
+                .await
+                .iter()
+                .any(|e| matches!(e, WebRTCEvent::RenegotiationNeeded(..))));
+            host.add_media_track(&a, "video", "display-2".into())
+                .await
+                .unwrap();
+            let offer = last_offer(&drain_events(&host).await);
+            assert_eq!(offer.sdp.matches("VP9/90000").count(), 2);
+            let answer = viewer
+                .handle_renegotiation_offer(&b, offer)
+                .await
+                .unwrap()
+                .unwrap();
+            host.handle_remote_answer(&a, answer).await.unwrap();
+
+            host.remove_media_track(&a, "display-1").await.unwrap();
+            let offer = last_offer(&drain_events(&host).await);
+            let answer = viewer
+                .handle_renegotiation_offer(&b, offer)
+                .await
+                .unwrap()
+                .unwrap();
+            host.handle_remote_answer(&a, answer).await.unwrap();
+            assert!(drain_events(&host).await.iter().any(|e| matches!(
+                e,
+                WebRTCEvent::RenegotiationProgress(_, RenegotiationProgress::Completed)
+            )));
+
+            host.close_connection(&a).await.unwrap();
+            viewer.close_connection(&b).await.unwrap();
+        })
+        .await;
+        assert!(result.is_ok(), "Test timed out after 30 seconds");
+    }
+
+    /// Each viewer forwards its own layer of a simulcast source
+    #[tokio::test]
+    async fn test_simulcast_layer_selection() {
+        use crate::webrtc_engine::SimulcastLayer;
+        use webrtc::media::Sample;
+
+        let layers = SimulcastLayer::standard();
+        assert!(SimulcastLayer::validate_layers(&layers).is_ok());
+        assert!(SimulcastLayer::validate_layers(&[]).is_err());
+        let mut reversed = layers.clone();
+        reversed.reverse();
+        assert!(SimulcastLayer::validate_layers(&reversed).is_err());
+        let mut upscaled = layers.clone();
+        upscaled[0].scale_resolution_down_by = 0.5;
+        assert!(SimulcastLayer::validate_layers(&upscaled).is_err());
+        assert_eq!(
+            SimulcastLayer::for_bandwidth(&layers, 5000).unwrap().rid,
+            "h"
+        );
+        assert_eq!(
+            SimulcastLayer::for_bandwidth(&layers, 1500).unwrap().rid,
+            "m"
+        );
+        assert_eq!(
+            SimulcastLayer::for_bandwidth(&layers, 100).unwrap().rid,
+            "l"
+        );
+
+        let config = RTCConfiguration {
+            ice_servers: vec![],
+            ice_transport_policy: "all".to_string(),
+            bundle_policy: None,
+            rtcp_mux_policy: None,
+        };
+        let engine = WebRTCEngine::new().await.unwrap();
+        let fast = engine.create_peer_connection(config.clone()).await.unwrap();
+        let slow = engine.create_peer_connection(config).await.unwrap();
+        for connection_id in [&fast, &slow] {
+            engine
+                .add_media_track(connection_id, "video", "screen".into())
+                .await
+                .unwrap();
+        }
+        assert!(engine
+            .set_subscriber_layer(&slow, "screen", "l")
+            .await
+            .is_err());
+
+        engine.set_simulcast_layers("screen", layers).unwrap();
+        drain_events(&engine).await;
+        assert_eq!(
+            engine.subscriber_layer(&fast, "screen").await.as_deref(),
+            Some("h")
+        );
+        assert_eq!(
+            engine
+                .select_layer_for_bandwidth(&slow, "screen", 200)
+                .await
+                .unwrap(),
+            "l"
+        );
+        assert_eq!(
+            engine.subscriber_layer(&slow, "screen").await.as_deref(),
+            Some("l")
+        );
+        let events = drain_events(&engine).await;
+        assert!(events.iter().any(|e| matches!(
+            e,
+            WebRTCEvent::LayerSelected(id, track, rid) if id == &slow && track == "screen" && rid == "l"
+        )));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, WebRTCEvent::KeyframeRequested(id) if id == &slow)));
+        assert!(engine
+            .set_subscriber_layer(&slow, "screen", "x")
+            .await
+            .is_err());
+        assert!(engine
+            .set_subscriber_layer(&slow, "camera", "l")
+            .await
+            .is_err());
+
+        let sample = Sample {
+            data: bytes::Bytes::from_static(&[0u8; 16]),
+            duration: Duration::from_millis(33),
+            ..Default::default()
+        };
+        for (layer, receivers) in [(Some("h"), 1), (Some("m"), 0), (Some("l"), 1), (None, 0)] {
+            assert_eq!(
+                engine
+                    .write_video_sample("screen", layer, &sample)
+                    .await
+                    .unwrap(),
+                receivers
+            );
+        }
+
+        // Without simulcast every connection gets the single layer
+        engine.clear_simulcast_layers("screen");
+        assert_eq!(engine.subscriber_layer(&slow, "screen").await, None);
+        assert_eq!(
+            engine
+                .write_video_sample("screen", None, &sample)
+                .await
+                .unwrap(),
+            2
+        );
+
+        engine.close_connection(&fast).await.unwrap();
+        engine.close_connection(&slow).await.unwrap();
+    }
+
+    /// Outgoing video shows up per track once media flows
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_detailed_stats_between_local_peers() {
+        use crate::network::ConnectionType;
+        use crate::webrtc_engine::DetailedStats;
+        use webrtc::media::Sample;
+
+        let result = tokio::time::timeout(Duration::from_secs(30), async {
+            let config = RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            };
+            let host = WebRTCEngine::new().await.unwrap();
+            let viewer = WebRTCEngine::new().await.unwrap();
+            let a = host.create_peer_connection(config.clone()).await.unwrap();
+            let b = viewer.create_peer_connection(config).await.unwrap();
+            assert!(host.get_detailed_stats("missing").await.is_err());
+            assert_eq!(
+                host.connection_path(&a).await.unwrap(),
+                ConnectionType::Unknown
+            );
+
+            host.add_media_track(&a, "video", "screen".into())
+                .await
+                .unwrap();
+            let offer = last_offer(&drain_events(&host).await);
+            let answer = viewer.handle_remote_offer(&b, offer).await.unwrap();
+            host.handle_remote_answer(&a, answer).await.unwrap();
+
+            let sample = Sample {
+                data: bytes::Bytes::from(vec![1u8; 4000]),
+                duration: Duration::from_millis(33),
+                ..Default::default()
+            };
+            let stats = loop {
+                for event in drain_events(&host).await {
+                    if let WebRTCEvent::IceCandidateReceived(_, candidate) = event {
+                        let _ = viewer.add_ice_candidate(&b, candidate).await;
+                    }
+                }
+                for event in drain_events(&viewer).await {
+                    if let WebRTCEvent::IceCandidateReceived(_, candidate) = event {
+                        let _ = host.add_ice_candidate(&a, candidate).await;
+                    }
+                }
+                host.write_video_sample("screen", None, &sample)
+                    .await
+                    .unwrap();
+                let stats = host.get_detailed_stats(&a).await.unwrap();
+                if stats.summary.state == RTCPeerConnectionState::Connected
+                    && stats.outbound.iter().any(|s| s.packets_sent > 0)
+                {
+                    break stats;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            };
+
+            let video = &stats.outbound[0];
+            assert_eq!(
+                (video.kind.as_str(), video.track_id.as_str()),
+                ("video", "screen")
+            );
+            assert!(video.bytes_sent > 0);
+            assert!(video.frames_encoded > 0);
+            assert!(stats.inbound.is_empty());
+            // Host candidates, or peer reflexive ones learnt from early checks
+            assert!(matches!(
+                host.connection_path(&a).await.unwrap(),
+                ConnectionType::Direct | ConnectionType::StunDirect
+            ));
+            assert_eq!(stats.summary.packets_sent, video.packets_sent);
+            assert!(stats.summary.bytes_sent >= video.bytes_sent + video.header_bytes_sent);
+
+            let json = serde_json::to_string(&stats).unwrap();
+            assert_eq!(serde_json::from_str::<DetailedStats>(&json).unwrap(), stats);
+            assert_eq!(
+                host.get_connection_stats(&a).await.unwrap().connection_id,
+                a
+            );
+
+            host.close_connection(&a).await.unwrap();
+            viewer.close_connection(&b).await.unwrap();
+        })
+        .await;
+        assert!(result.is_ok(), "Test timed out after 30 seconds");
+    }
+
+    #[tokio::test]
+    async fn test_keyframe_request_needs_incoming_video() {
+        let engine = WebRTCEngine::new().await.unwrap();
+        let connection_id = engine
+            .create_peer_connection(RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            })
+            .await
+            .unwrap();
+
+        assert!(engine.request_keyframe("missing").await.is_err());
+        let err = engine.request_keyframe(&connection_id).await.unwrap_err();
+        assert!(err.to_string().contains("No incoming video"));
+
+        let stats = engine.get_connection_stats(&connection_id).await.unwrap();
+        assert_eq!(stats.keyframe_requests_sent, 0);
+        assert_eq!(stats.keyframe_requests_received, 0);
+        engine.close_connection(&connection_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_consent_config_and_manual_ice_restart() {
+        use crate::webrtc_engine::{ConsentConfig, ConsentEvent};
+
+        let too_slow = ConsentConfig {
+            keepalive_interval: Duration::from_secs(15),
+            missed_after: Duration::from_secs(10),
+            ..Default::default()
+        };
+        assert!(WebRTCEngine::with_consent_config(too_slow).await.is_err());
+
+        let engine = WebRTCEngine::with_consent_config(ConsentConfig::default())
+            .await
+            .unwrap();
+        let connection_id = engine
+            .create_peer_connection(RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            })
+            .await
+            .unwrap();
+        engine
+            .add_media_track(&connection_id, "video", "screen".to_string())
+            .await
+            .unwrap();
+        let first = last_offer(&drain_events(&engine).await);
+        let answerer = WebRTCEngine::new().await.unwrap();
+        let remote_id = answerer
+            .create_peer_connection(RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            })
+            .await
+            .unwrap();
+        let answer = answerer
+            .handle_remote_offer(&remote_id, first.clone())
+            .await
+            .unwrap();
+        engine
+            .handle_remote_answer(&connection_id, answer)
+            .await
+            .unwrap();
+        drain_events(&engine).await;
+
+        engine.restart_ice(&connection_id).await.unwrap();
+        let events = drain_events(&engine).await;
+        let ufrag = |offer: &RTCSessionDescription| {
+            offer
+                .sdp
+                .lines()
+                .find(|l| l.starts_with("a=ice-ufrag:"))
+                .map(str::to_string)
+        };
+        let restart = last_offer(&events);
+        assert_ne!(ufrag(&restart), ufrag(&first));
+        assert!(events
+            .iter()
+            .any(|e| matches!(e, WebRTCEvent::Consent(_, ConsentEvent::IceRestartStarted))));
+        let ice_restarted = |events: &[WebRTCEvent]| {
+            events
+                .iter()
+                .any(|e| matches!(e, WebRTCEvent::IceRestarted(_)))
+        };
+        assert!(!ice_restarted(&events));
+
+        // Both sides report the restart once the new credentials are agreed
+        let answer = answerer
+            .handle_renegotiation_offer(&remote_id, restart)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(ice_restarted(&drain_events(&answerer).await));
+        engine
+            .handle_remote_answer(&connection_id, answer)
+            .await
+            .unwrap();
+        assert!(ice_restarted(&drain_events(&engine).await));
+
+        // Ordinary renegotiation is not a restart
+        engine
+            .set_audio_track_enabled(&connection_id, true)
+            .await
+            .unwrap();
+        let offer = last_offer(&drain_events(&engine).await);
+        let answer = answerer
+            .handle_renegotiation_offer(&remote_id, offer)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!ice_restarted(&drain_events(&answerer).await));
+        engine
+            .handle_remote_answer(&connection_id, answer)
+            .await
+            .unwrap();
+        assert!(!ice_restarted(&drain_events(&engine).await));
+        let stats = engine.get_connection_stats(&connection_id).await.unwrap();
+        assert_eq!(stats.ice_restarts, 1);
+        assert_eq!(stats.consent_expirations, 0);
+
+        assert!(engine.restart_ice("missing").await.is_err());
+        engine.close_connection(&connection_id).await.unwrap();
+        answerer.close_connection(&remote_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_data_channel_compression_stats() {
+        use crate::data_compression::CompressionAlgorithm;
+
+        let engine = WebRTCEngine::new().await.unwrap();
+        let connection_id = engine
+            .create_peer_connection(RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            })
+            .await
+            .unwrap();
+        let clipboard = "lorem ipsum dolor sit amet ".repeat(100).into_bytes();
+
+        engine
+            .send_data(&connection_id, clipboard.clone())
+            .await
+            .unwrap();
+        let stats = engine.get_connection_stats(&connection_id).await.unwrap();
+        assert_eq!(stats.data_compression.messages_compressed, 0);
+
+        engine
+            .set_data_compression(&connection_id, Some(CompressionAlgorithm::Lz4))
+            .await
+            .unwrap();
+        engine
+            .send_data(&connection_id, clipboard.clone())
+            .await
+            .unwrap();
+        let stats = engine.get_connection_stats(&connection_id).await.unwrap();
+        assert_eq!(stats.data_compression.messages_compressed, 1);
+        assert!(stats.data_compression.ratio() > 10.0);
+
+        assert!(engine.send_data("missing", clipboard).await.is_err());
+        engine.close_connection(&connection_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_data_channel_config_validation() {
+        use crate::webrtc_engine::{DataChannelConfig, Reliability};
+
+        let engine = WebRTCEngine::new().await.unwrap();
+        let connection_id = engine
+            .create_peer_connection(RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            })
+            .await
+            .unwrap();
+
+        let cursor = DataChannelConfig::unreliable("cursor");
+        assert!(!cursor.ordered);
+        engine
+            .create_data_channel(&connection_id, cursor.clone())
+            .await
+            .unwrap();
+        assert!(engine
+            .create_data_channel(&connection_id, cursor)
+            .await
+            .is_err());
+        let zero_retransmits = DataChannelConfig {
+            reliability: Reliability::MaxRetransmits(0),
+            ..DataChannelConfig::unreliable("pointer")
+        };
+        assert!(engine
+            .create_data_channel(&connection_id, zero_retransmits)
+            .await
+            .is_err());
+
+        let channels = engine.data_channels(&connection_id).await.unwrap();
+        let labels: Vec<_> = channels.iter().map(|c| c.config.label.as_str()).collect();
+        assert_eq!(labels, ["control", "cursor"]);
+        assert!(channels.iter().all(|c| !c.open && c.buffered_amount == 0));
+        assert!(engine
+            .send_text(&connection_id, "cursor", "not yet open")
+            .await
+            .is_err());
+        assert!(engine
+            .close_data_channel(&connection_id, "control")
+            .await
+            .is_err());
+        engine
+            .close_data_channel(&connection_id, "cursor")
+            .await
+            .unwrap();
+        engine.close_connection(&connection_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_data_transport_falls_back_when_channel_does_not_open() {
+        use crate::quic_transport::{quic_supported, DataTransportConfig, DataTransportType};
+
+        let engine = WebRTCEngine::new().await.unwrap();
+        let connection_id = engine
+            .create_peer_connection(RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            })
+            .await
+            .unwrap();
+
+        // No peer ever connects, so the control channel never opens
+        let config = DataTransportConfig {
+            data_channel_timeout: Duration::from_millis(100),
+            quic_fallback_enabled: true,
+        };
+        let selected = engine
+            .select_data_transport(&connection_id, "control", &config, async { Ok(()) })
+            .await;
+        if quic_supported() {
+            assert_eq!(selected.unwrap(), DataTransportType::Quic);
+            assert_eq!(
+                engine.data_transport(&connection_id).await,
+                Some(DataTransportType::Quic)
+            );
+        } else {
+            assert!(selected.is_err());
+            assert_eq!(engine.data_transport(&connection_id).await, None);
+        }
+
+        assert!(engine
+            .select_data_transport("missing", "control", &config, async {
+                Err(anyhow::anyhow!("no QUIC either"))
+            })
+            .await
+            .is_err());
+        engine.close_connection(&connection_id).await.unwrap();
+    }
+
+    /// Two local peers open the control channel and a labelled channel,
+    /// exchange text and binary messages and report backpressure
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_data_channels_between_local_peers() {
+        use crate::webrtc_engine::{
+            DataChannelConfig, DataChannelEvent, DataChannelMessage, MAX_DATA_CHANNEL_MESSAGE,
+        };
+
+        let result = tokio::time::timeout(Duration::from_secs(30), async {
+            let config = RTCConfiguration {
+                ice_servers: vec![],
+                ice_transport_policy: "all".to_string(),
+                bundle_policy: None,
+                rtcp_mux_policy: None,
+            };
+            let host = WebRTCEngine::new().await.unwrap();
+            let viewer = WebRTCEngine::new().await.unwrap();
+            let a = host.create_peer_connection(config.clone()).await.unwrap();
+            let b = viewer.create_peer_connection(config).await.unwrap();
+            host.create_data_channel(
+                &a,
+                DataChannelConfig {
+                    high_water_mark: 4 * 1024,
+                    low_water_mark: 1024,
+                    ..DataChannelConfig::reliable("files")
+                },
+            )
+            .await
+            .unwrap();
+            // Held until the control channel opens
+            host.send_data(&a, b"clipboard".to_vec()).await.unwrap();
+
+            host.establish_connection(&a, "viewer".into()).await.unwrap();
+            let offer = drain_events(&host)
+                .await
+                .into_iter()
+                .find_map(|event| match event {
+                    WebRTCEvent::OfferReceived(_, offer) => Some(offer),
+                    _ => None,
+                })
+                .expect("offer event");
+            let answer = viewer.handle_remote_offer(&b, offer).await.unwrap();
+            host.handle_remote_answer(&a, answer).await.unwrap();
+
+            let mut host_opened = false;
+            let mut viewer_events = Vec::new();
+            while !host_opened
+                || !viewer_events.iter().any(|e| {
+                    matches!(e, WebRTCEvent::DataChannel(_, label, DataChannelEvent::Opened) if label == "files")
+                })
+            {
+                for event in drain_events(&host).await {
+                    match event {
+                        WebRTCEvent::IceCandidateReceived(_, candidate) => {
+                            let _ = viewer.add_ice_candidate(&b, candidate).await;
+                        }
+                        WebRTCEvent::DataChannel(_, label, DataChannelEvent::Opened)
+                            if label == "files" =>
+                        {
+                            host_opened = true;
+                        }
+                        _ => {}
+                    }
+                }
+                for event in drain_events(&viewer).await {
+                    match event {
+                        WebRTCEvent::IceCandidateReceived(_, candidate) => {
+                            let _ = host.add_ice_candidate(&a, candidate).await;
+                        }
+                        event => viewer_events.push(event),
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+
+            host.send_text(&a, "files", "manifest").await.unwrap();
+            assert!(host
+                .send_binary(&a, "files", &vec![0u8; MAX_DATA_CHANNEL_MESSAGE + 1])
+                .await
+                .is_err());
+            let chunk = vec![7u8; 16 * 1024];
+            for _ in 0..4 {
+                host.send_binary(&a, "files", &chunk).await.unwrap();
+            }
+
+            let mut host_events = Vec::new();
+            loop {
+                host_events.extend(drain_events(&host).await);
+                viewer_events.extend(drain_events(&viewer).await);
+                let received = viewer_events
+                    .iter()
+                    .filter(|e| {
+                        matches!(e, WebRTCEvent::DataChannel(_, _, DataChannelEvent::Message(DataChannelMessage::Binary(data))) if *data == chunk)
+                    })
+                    .count()
+                    == 4;
+                let drained = host_events.iter().any(|e| {
+                    matches!(e, WebRTCEvent::DataChannel(_, _, DataChannelEvent::BufferedAmountLow))
+                });
+                if received && drained {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+
+            assert!(host_events.iter().any(|e| matches!(
+                e,
+                WebRTCEvent::DataChannel(_, label, DataChannelEvent::BufferedAmountHigh(n))
+                    if label == "files" && *n >= 4 * 1024
+            )));
+            let control = viewer_events
+                .iter()
+                .find_map(|e| match e {
+                    WebRTCEvent::DataReceived(_, message) => Some(message.clone()),
+                    _ => None,
+                })
+                .expect("queued control message delivered");
+            assert_eq!(viewer.decode_data(&b, &control).await.unwrap(), b"clipboard");
+            assert!(viewer_events.iter().any(|e| matches!(
+                e,
+                WebRTCEvent::DataChannel(_, label, DataChannelEvent::Message(DataChannelMessage::Text(text)))
+                    if label == "files" && text == "manifest"
+            )));
+            assert!(viewer
+                .data_channels(&b)
+                .await
+                .unwrap()
+                .iter()
+                .any(|c| c.config.label == "files" && c.open));
+
+            host.close_connection(&a).await.unwrap();
+            viewer.close_connection(&b).await.unwrap();
+        })
+        .await;
+        assert!(result.is_ok(), "Test timed out after 30 seconds");
+    }
 }
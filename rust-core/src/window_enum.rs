@@ -0,0 +1,634 @@
+//! Platform Window Enumeration
+//!
+//! Lists visible top-level application windows for the window picker, so a
+//! host can share a single window through `CaptureTarget::Window`. On X11
+//! (including XWayland) the window manager's `_NET_CLIENT_LIST_STACKING` is
+//! read from the root window; Win32 `EnumWindows` and CoreGraphics
+//! `CGWindowListCopyWindowInfo` are the equivalents elsewhere.
+//!
+//! Results are ordered topmost first. Windows without a title are skipped,
+//! as pickers have nothing to show for them.
+
+use crate::screen_capture::WindowInfo;
+use anyhow::Result;
+
+/// List the visible top-level windows
+///
+/// Returns an empty list when the platform has no window listing; that is
+/// not an error, since full-display capture still works.
+pub fn enumerate_windows() -> Result<Vec<WindowInfo>> {
+    #[cfg(target_os = "linux")]
+    let windows = x11::enumerate()?;
+    #[cfg(target_os = "windows")]
+    let windows = win32::enumerate();
+    #[cfg(target_os = "macos")]
+    let windows = quartz::enumerate()?;
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    let windows: Vec<WindowInfo> = Vec::new();
+
+    Ok(windows
+        .into_iter()
+        .filter(|w| !w.title.trim().is_empty() && w.width > 0 && w.height > 0)
+        .collect())
+}
+
+/// Short name of a running process, e.g. `firefox`
+pub fn process_name(process_id: u32) -> Option<String> {
+    #[cfg(target_os = "linux")]
+    let name = std::fs::read_to_string(format!("/proc/{}/comm", process_id))
+        .ok()
+        .map(|comm| comm.trim_end().to_string());
+    #[cfg(target_os = "windows")]
+    let name = win32::process_name(process_id);
+    #[cfg(target_os = "macos")]
+    let name = quartz::process_name(process_id);
+    #[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+    let name: Option<String> = {
+        let _ = process_id;
+        None
+    };
+
+    name.filter(|name| !name.is_empty())
+}
+
+#[cfg(target_os = "windows")]
+mod win32 {
+    use crate::screen_capture::WindowInfo;
+    use std::ffi::c_void;
+
+    type Hwnd = *mut c_void;
+    type Handle = *mut c_void;
+
+    const GW_OWNER: u32 = 4;
+    const GWL_EXSTYLE: i32 = -20;
+    const WS_EX_TOOLWINDOW: i32 = 0x0000_0080;
+    const DWMWA_CLOAKED: u32 = 14;
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct Rect {
+        left: i32,
+        top: i32,
+        right: i32,
+        bottom: i32,
+    }
+
+    extern "system" {
+        fn EnumWindows(callback: extern "system" fn(Hwnd, isize) -> i32, param: isize) -> i32;
+        fn IsWindowVisible(window: Hwnd) -> i32;
+        fn GetWindow(window: Hwnd, command: u32) -> Hwnd;
+        fn GetWindowLongW(window: Hwnd, index: i32) -> i32;
+        fn GetWindowTextW(window: Hwnd, text: *mut u16, max: i32) -> i32;
+        fn GetWindowRect(window: Hwnd, rect: *mut Rect) -> i32;
+        fn GetWindowThreadProcessId(window: Hwnd, process_id: *mut u32) -> u32;
+        fn DwmGetWindowAttribute(
+            window: Hwnd,
+            attribute: u32,
+            value: *mut c_void,
+            size: u32,
+        ) -> i32;
+        fn OpenProcess(access: u32, inherit: i32, process_id: u32) -> Handle;
+        fn QueryFullProcessImageNameW(
+            process: Handle,
+            flags: u32,
+            name: *mut u16,
+            size: *mut u32,
+        ) -> i32;
+        fn CloseHandle(handle: Handle) -> i32;
+    }
+
+    extern "system" fn collect(window: Hwnd, param: isize) -> i32 {
+        // SAFETY: `param` is the list passed to `EnumWindows` below, alive
+        // for the whole enumeration
+        let windows = unsafe { &mut *(param as *mut Vec<WindowInfo>) };
+        // SAFETY: `window` is a live top-level window handed out by
+        // EnumWindows and every out pointer is valid
+        unsafe {
+            // Owned windows are dialogs and tool palettes of another window
+            if IsWindowVisible(window) == 0
+                || !GetWindow(window, GW_OWNER).is_null()
+                || GetWindowLongW(window, GWL_EXSTYLE) & WS_EX_TOOLWINDOW != 0
+            {
+                return 1;
+            }
+            // Cloaked windows are UWP apps suspended in the background and
+            // windows on other virtual desktops
+            let mut cloaked = 0u32;
+            if DwmGetWindowAttribute(
+                window,
+                DWMWA_CLOAKED,
+                std::ptr::addr_of_mut!(cloaked).cast(),
+                std::mem::size_of::<u32>() as u32,
+            ) == 0
+                && cloaked != 0
+            {
+                return 1;
+            }
+
+            let mut title = [0u16; 512];
+            let length = GetWindowTextW(window, title.as_mut_ptr(), title.len() as i32);
+            let mut rect = Rect::default();
+            if GetWindowRect(window, &mut rect) == 0 {
+                return 1;
+            }
+            let mut process_id = 0u32;
+            GetWindowThreadProcessId(window, &mut process_id);
+
+            windows.push(WindowInfo {
+                window_id: window as u64,
+                title: String::from_utf16_lossy(&title[..length.max(0) as usize]),
+                process_id: (process_id != 0).then_some(process_id),
+                x: rect.left,
+                y: rect.top,
+                width: (rect.right - rect.left).max(0) as u32,
+                height: (rect.bottom - rect.top).max(0) as u32,
+            });
+        }
+        1
+    }
+
+    /// Top-level windows in Z order, topmost first
+    pub(super) fn enumerate() -> Vec<WindowInfo> {
+        let mut windows: Vec<WindowInfo> = Vec::new();
+        // SAFETY: the callback only runs during this call, while `windows`
+        // is borrowed
+        unsafe { EnumWindows(collect, std::ptr::addr_of_mut!(windows) as isize) };
+        windows
+    }
+
+    pub(super) fn process_name(process_id: u32) -> Option<String> {
+        // SAFETY: the handle is checked before use and closed once the name
+        // was copied out; `size` holds the buffer length in characters
+        unsafe {
+            let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, process_id);
+            if process.is_null() {
+                return None;
+            }
+            let mut path = [0u16; 1024];
+            let mut size = path.len() as u32;
+            let ok = QueryFullProcessImageNameW(process, 0, path.as_mut_ptr(), &mut size) != 0;
+            CloseHandle(process);
+            if !ok {
+                return None;
+            }
+            let path = String::from_utf16_lossy(&path[..size as usize]);
+            std::path::Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+        }
+    }
+}
+
+#[cfg(target_os = "macos")]
+mod quartz {
+    use crate::screen_capture::WindowInfo;
+    use anyhow::Result;
+    use std::ffi::{c_char, c_int, c_void, CStr};
+
+    type CfTypeRef = *const c_void;
+    type CfArrayRef = *const c_void;
+    type CfDictionaryRef = *const c_void;
+    type CfStringRef = *const c_void;
+
+    const ON_SCREEN_ONLY: u32 = 1 << 0;
+    const EXCLUDE_DESKTOP_ELEMENTS: u32 = 1 << 4;
+    const NULL_WINDOW_ID: u32 = 0;
+    const CF_NUMBER_SINT64_TYPE: isize = 4;
+    const CF_STRING_ENCODING_UTF8: u32 = 0x0800_0100;
+    const PROC_PIDPATHINFO_MAXSIZE: usize = 4096;
+
+    #[repr(C)]
+    #[derive(Default)]
+    struct CgRect {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    }
+
+    extern "C" {
+        static kCGWindowNumber: CfStringRef;
+        static kCGWindowOwnerPID: CfStringRef;
+        static kCGWindowName: CfStringRef;
+        static kCGWindowLayer: CfStringRef;
+        static kCGWindowBounds: CfStringRef;
+        fn CGWindowListCopyWindowInfo(option: u32, relative_to: u32) -> CfArrayRef;
+        fn CGRectMakeWithDictionaryRepresentation(dict: CfDictionaryRef, rect: *mut CgRect)
+            -> bool;
+        fn CFArrayGetCount(array: CfArrayRef) -> isize;
+        fn CFArrayGetValueAtIndex(array: CfArrayRef, index: isize) -> *const c_void;
+        fn CFDictionaryGetValue(dict: CfDictionaryRef, key: *const c_void) -> *const c_void;
+        fn CFNumberGetValue(number: CfTypeRef, kind: isize, value: *mut c_void) -> bool;
+        fn CFStringGetCString(
+            string: CfStringRef,
+            buffer: *mut c_char,
+            size: isize,
+            encoding: u32,
+        ) -> u8;
+        fn CFRelease(cf: *const c_void);
+        fn proc_name(pid: c_int, buffer: *mut c_void, size: u32) -> c_int;
+    }
+
+    /// Integer entry of a window description
+    ///
+    /// # Safety
+    ///
+    /// `window` must be a live CFDictionary.
+    unsafe fn number(window: CfDictionaryRef, key: CfStringRef) -> Option<i64> {
+        let value = CFDictionaryGetValue(window, key);
+        let mut number = 0i64;
+        (!value.is_null()
+            && CFNumberGetValue(
+                value,
+                CF_NUMBER_SINT64_TYPE,
+                std::ptr::addr_of_mut!(number).cast(),
+            ))
+        .then_some(number)
+    }
+
+    /// String entry of a window description
+    ///
+    /// # Safety
+    ///
+    /// `window` must be a live CFDictionary.
+    unsafe fn string(window: CfDictionaryRef, key: CfStringRef) -> Option<String> {
+        let value = CFDictionaryGetValue(window, key);
+        if value.is_null() {
+            return None;
+        }
+        let mut buffer = [0 as c_char; 1024];
+        (CFStringGetCString(
+            value,
+            buffer.as_mut_ptr(),
+            buffer.len() as isize,
+            CF_STRING_ENCODING_UTF8,
+        ) != 0)
+            .then(|| {
+                CStr::from_ptr(buffer.as_ptr())
+                    .to_string_lossy()
+                    .into_owned()
+            })
+    }
+
+    /// On-screen windows, topmost first
+    ///
+    /// Bounds are in points. Titles are only present once Screen Recording
+    /// has been granted; untitled windows are dropped by the caller.
+    pub(super) fn enumerate() -> Result<Vec<WindowInfo>> {
+        // SAFETY: the array and the dictionaries it owns stay alive until
+        // the CFRelease at the end
+        unsafe {
+            let list = CGWindowListCopyWindowInfo(
+                ON_SCREEN_ONLY | EXCLUDE_DESKTOP_ELEMENTS,
+                NULL_WINDOW_ID,
+            );
+            if list.is_null() {
+                return Err(anyhow::anyhow!("CGWindowListCopyWindowInfo failed"));
+            }
+            let mut windows = Vec::new();
+            for index in 0..CFArrayGetCount(list) {
+                let window = CFArrayGetValueAtIndex(list, index);
+                // Layer 0 holds application windows; menus, the Dock and
+                // overlays sit above it
+                if number(window, kCGWindowLayer) != Some(0) {
+                    continue;
+                }
+                let Some(window_id) = number(window, kCGWindowNumber) else {
+                    continue;
+                };
+                let mut bounds = CgRect::default();
+                let bounds_value = CFDictionaryGetValue(window, kCGWindowBounds);
+                if bounds_value.is_null()
+                    || !CGRectMakeWithDictionaryRepresentation(bounds_value, &mut bounds)
+                {
+                    continue;
+                }
+                windows.push(WindowInfo {
+                    window_id: window_id as u64,
+                    title: string(window, kCGWindowName).unwrap_or_default(),
+                    process_id: number(window, kCGWindowOwnerPID).map(|pid| pid as u32),
+                    x: bounds.x as i32,
+                    y: bounds.y as i32,
+                    width: bounds.width.max(0.0) as u32,
+                    height: bounds.height.max(0.0) as u32,
+                });
+            }
+            CFRelease(list);
+            Ok(windows)
+        }
+    }
+
+    pub(super) fn process_name(process_id: u32) -> Option<String> {
+        let mut buffer = [0u8; PROC_PIDPATHINFO_MAXSIZE];
+        // SAFETY: the buffer size passed matches the buffer
+        let length = unsafe {
+            proc_name(
+                process_id as c_int,
+                buffer.as_mut_ptr().cast(),
+                buffer.len() as u32,
+            )
+        };
+        (length > 0).then(|| String::from_utf8_lossy(&buffer[..length as usize]).into_owned())
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) mod x11 {
+    use crate::capture_backend::{install_error_handler, take_x_error};
+    use crate::screen_capture::WindowInfo;
+    use anyhow::Result;
+    use std::ffi::CStr;
+    use std::os::raw::{c_char, c_int, c_long, c_uchar, c_ulong, c_void};
+
+    type Window = c_ulong;
+    type Atom = c_ulong;
+
+    #[repr(C)]
+    struct Display {
+        _private: [u8; 0],
+    }
+
+    #[repr(C)]
+    struct Visual {
+        _private: [u8; 0],
+    }
+
+    /// Xlib's `XWindowAttributes`
+    #[repr(C)]
+    struct XWindowAttributes {
+        x: c_int,
+        y: c_int,
+        width: c_int,
+        height: c_int,
+        border_width: c_int,
+        depth: c_int,
+        visual: *mut Visual,
+        root: Window,
+        class: c_int,
+        bit_gravity: c_int,
+        win_gravity: c_int,
+        backing_store: c_int,
+        backing_planes: c_ulong,
+        backing_pixel: c_ulong,
+        save_under: c_int,
+        colormap: c_ulong,
+        map_installed: c_int,
+        map_state: c_int,
+        all_event_masks: c_long,
+        your_event_mask: c_long,
+        do_not_propagate_mask: c_long,
+        override_redirect: c_int,
+        screen: *mut c_void,
+    }
+
+    const XA_CARDINAL: Atom = 6;
+    const XA_STRING: Atom = 31;
+    const XA_WINDOW: Atom = 33;
+    const IS_VIEWABLE: c_int = 2;
+    const SUCCESS: c_int = 0;
+    /// Upper bound on property length, in 32-bit units
+    const MAX_PROPERTY_LONGS: c_long = 1 << 16;
+
+    extern "C" {
+        fn XOpenDisplay(name: *const c_char) -> *mut Display;
+        fn XCloseDisplay(display: *mut Display) -> c_int;
+        fn XDefaultRootWindow(display: *mut Display) -> Window;
+        fn XInternAtom(display: *mut Display, name: *const c_char, only_if_exists: c_int) -> Atom;
+        fn XGetWindowProperty(
+            display: *mut Display,
+            window: Window,
+            property: Atom,
+            offset: c_long,
+            length: c_long,
+            delete: c_int,
+            req_type: Atom,
+            actual_type: *mut Atom,
+            actual_format: *mut c_int,
+            nitems: *mut c_ulong,
+            bytes_after: *mut c_ulong,
+            prop: *mut *mut c_uchar,
+        ) -> c_int;
+        fn XGetWindowAttributes(
+            display: *mut Display,
+            window: Window,
+            attributes: *mut XWindowAttributes,
+        ) -> c_int;
+        fn XTranslateCoordinates(
+            display: *mut Display,
+            src: Window,
+            dest: Window,
+            src_x: c_int,
+            src_y: c_int,
+            dest_x: *mut c_int,
+            dest_y: *mut c_int,
+            child: *mut Window,
+        ) -> c_int;
+        fn XSync(display: *mut Display, discard: c_int) -> c_int;
+        fn XFree(data: *mut c_void) -> c_int;
+    }
+
+    /// A property value as returned by the server
+    struct Property {
+        format: c_int,
+        items: usize,
+        data: *mut c_uchar,
+    }
+
+    impl Property {
+        /// Format-32 items, which Xlib hands out as `long`s
+        fn longs(&self) -> &[c_ulong] {
+            if self.format != 32 || self.data.is_null() {
+                return &[];
+            }
+            // SAFETY: Xlib returned `items` longs at `data`
+            unsafe { std::slice::from_raw_parts(self.data as *const c_ulong, self.items) }
+        }
+
+        fn bytes(&self) -> &[u8] {
+            if self.format != 8 || self.data.is_null() {
+                return &[];
+            }
+            // SAFETY: Xlib returned `items` bytes at `data`
+            unsafe { std::slice::from_raw_parts(self.data, self.items) }
+        }
+    }
+
+    impl Drop for Property {
+        fn drop(&mut self) {
+            if !self.data.is_null() {
+                // SAFETY: allocated by XGetWindowProperty
+                unsafe {
+                    XFree(self.data as *mut c_void);
+                }
+            }
+        }
+    }
+
+    struct Connection {
+        display: *mut Display,
+        root: Window,
+    }
+
+    impl Connection {
+        fn open() -> Option<Self> {
+            install_error_handler();
+            // SAFETY: the display is checked for null and closed on drop
+            unsafe {
+                let display = XOpenDisplay(std::ptr::null());
+                if display.is_null() {
+                    return None;
+                }
+                Some(Connection {
+                    display,
+                    root: XDefaultRootWindow(display),
+                })
+            }
+        }
+
+        fn atom(&self, name: &CStr) -> Atom {
+            // SAFETY: the display is open and the name is NUL-terminated
+            unsafe { XInternAtom(self.display, name.as_ptr(), 1) }
+        }
+
+        fn property(&self, window: Window, property: Atom, kind: Atom) -> Option<Property> {
+            if property == 0 {
+                return None;
+            }
+            let mut actual_type = 0;
+            let mut format = 0;
+            let mut items = 0;
+            let mut remaining = 0;
+            let mut data = std::ptr::null_mut();
+            // SAFETY: all out-pointers are valid; the data is owned by the
+            // returned `Property`, which frees it
+            let status = unsafe {
+                XGetWindowProperty(
+                    self.display,
+                    window,
+                    property,
+                    0,
+                    MAX_PROPERTY_LONGS,
+                    0,
+                    kind,
+                    &mut actual_type,
+                    &mut format,
+                    &mut items,
+                    &mut remaining,
+                    &mut data,
+                )
+            };
+            let property = Property {
+                format,
+                items: items as usize,
+                data,
+            };
+            (status == SUCCESS && actual_type != 0 && !take_x_error()).then_some(property)
+        }
+
+        fn title(&self, window: Window) -> Option<String> {
+            let utf8 = self.atom(c"UTF8_STRING");
+            let net_name = self.atom(c"_NET_WM_NAME");
+            let bytes = self
+                .property(window, net_name, utf8)
+                .filter(|p| !p.bytes().is_empty())
+                .or_else(|| self.property(window, self.atom(c"WM_NAME"), XA_STRING))?;
+            Some(String::from_utf8_lossy(bytes.bytes()).into_owned())
+        }
+
+        fn info(&self, window: Window) -> Option<WindowInfo> {
+            let mut attributes = std::mem::MaybeUninit::<XWindowAttributes>::zeroed();
+            // SAFETY: the window may vanish between listing and querying;
+            // the installed handler turns BadWindow into a flag
+            let (attributes, x, y) = unsafe {
+                let ok = XGetWindowAttributes(self.display, window, attributes.as_mut_ptr());
+                XSync(self.display, 0);
+                if ok == 0 || take_x_error() {
+                    return None;
+                }
+                let attributes = attributes.assume_init();
+                let (mut x, mut y, mut child) = (0, 0, 0);
+                XTranslateCoordinates(
+                    self.display,
+                    window,
+                    self.root,
+                    0,
+                    0,
+                    &mut x,
+                    &mut y,
+                    &mut child,
+                );
+                (attributes, x, y)
+            };
+            if attributes.map_state != IS_VIEWABLE {
+                return None;
+            }
+            let process_id = self
+                .property(window, self.atom(c"_NET_WM_PID"), XA_CARDINAL)
+                .and_then(|p| p.longs().first().map(|&pid| pid as u32));
+            Some(WindowInfo {
+                // `c_ulong` is only 32 bits on some targets
+                #[allow(clippy::unnecessary_cast)]
+                window_id: window as u64,
+                title: self.title(window)?,
+                process_id,
+                x,
+                y,
+                width: attributes.width.max(0) as u32,
+                height: attributes.height.max(0) as u32,
+            })
+        }
+    }
+
+    impl Drop for Connection {
+        fn drop(&mut self) {
+            // SAFETY: opened in `enumerate`
+            unsafe {
+                XCloseDisplay(self.display);
+            }
+        }
+    }
+
+    /// Bytes of a string property on the root window
+    pub(crate) fn root_string_property(name: &CStr) -> Option<Vec<u8>> {
+        std::env::var_os("DISPLAY")?;
+        let connection = Connection::open()?;
+        let property = connection.property(connection.root, connection.atom(name), XA_STRING)?;
+        Some(property.bytes().to_vec())
+    }
+
+    pub(super) fn enumerate() -> Result<Vec<WindowInfo>> {
+        if std::env::var_os("DISPLAY").is_none() {
+            // Wayland compositors do not expose other clients' windows; the
+            // ScreenCast portal's own picker covers window sharing there
+            return Ok(Vec::new());
+        }
+        let connection =
+            Connection::open().ok_or_else(|| anyhow::anyhow!("Cannot open X display"))?;
+
+        let list = connection
+            .property(
+                connection.root,
+                connection.atom(c"_NET_CLIENT_LIST_STACKING"),
+                XA_WINDOW,
+            )
+            .or_else(|| {
+                connection.property(
+                    connection.root,
+                    connection.atom(c"_NET_CLIENT_LIST"),
+                    XA_WINDOW,
+                )
+            });
+        let Some(list) = list else {
+            tracing::debug!("Window manager publishes no client list");
+            return Ok(Vec::new());
+        };
+        // Stacking order is bottom to top
+        Ok(list
+            .longs()
+            .iter()
+            .rev()
+            .filter_map(|&window| connection.info(window))
+            .collect())
+    }
+}